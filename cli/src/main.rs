@@ -1,6 +1,13 @@
 use bytes::Bytes;
 use clap::{Parser, ValueHint};
-use shiva::core::{Document, DocumentType};
+use shiva::core::{
+    CsvOptions, CsvParseOptions, Document, DocumentType, FontSource, GenerateOptions, HtmlOptions,
+    HtmlParseOptions, ImageData, ImageDimension, LatexClass, LatexOptions, MarkdownFlavor,
+    MarkdownOptions, MarkdownParseOptions, ParseOptions, PdfCompression, PdfEncryption, PdfOptions,
+    PdfParseOptions, PdfWatermark, WatermarkPosition, XmlMapping, XmlOptions,
+};
+use shiva::detect::detect_format;
+use shiva::ocr::{Ocr, Tesseract};
 use std::path::Path;
 
 #[derive(Parser, Debug)]
@@ -28,34 +35,334 @@ struct Args {
             "Output file (possible formats: {})",
             DocumentType::supported_extensions().join(", ")
         ),
-        value_hint = ValueHint::FilePath
+        value_hint = ValueHint::FilePath,
+        required_unless_present = "stats"
+    )]
+    output_file: Option<String>,
+
+    #[arg(
+        long,
+        help = "Print the differences between the input and output documents instead of converting"
+    )]
+    diff: bool,
+
+    #[arg(
+        long,
+        help = "Print word count, reading time and other statistics of the input document"
+    )]
+    stats: bool,
+
+    #[arg(
+        long,
+        help = "Print the elements and attributes the output format cannot represent"
+    )]
+    report: bool,
+
+    #[arg(
+        long,
+        help = "Detect the format of the input file from its content instead of its extension"
+    )]
+    detect: bool,
+
+    #[arg(
+        long,
+        value_name = "FLAVOR",
+        default_value_t = MarkdownFlavor::default(),
+        help = "Markdown syntax read and written (commonmark, gfm, multimarkdown, pandoc, obsidian)"
+    )]
+    markdown_flavor: MarkdownFlavor,
+
+    #[arg(
+        long,
+        help = "Read the bare http:// and https:// URLs of markdown and text files as links"
+    )]
+    autolink: bool,
+
+    #[arg(
+        long,
+        help = "Read the :sparkles: shortcodes of markdown files as emoji"
+    )]
+    emoji: bool,
+
+    #[arg(long, help = "Write the emoji of generated markdown as :shortcodes:")]
+    emoji_shortcodes: bool,
+
+    #[arg(
+        long,
+        help = "Drop the scripts, frames and javascript: links of untrusted HTML input and of the raw HTML of markdown"
+    )]
+    sanitize: bool,
+
+    #[arg(
+        long,
+        value_name = "PASSWORD",
+        help = "Password of an encrypted PDF input"
+    )]
+    pdf_password: Option<String>,
+
+    #[arg(
+        long,
+        help = "Read the text of scanned PDF pages and of images with the tesseract command"
+    )]
+    ocr: bool,
+
+    #[arg(
+        long,
+        value_name = "LANGUAGES",
+        requires = "ocr",
+        help = "Languages of the text read by OCR, such as eng or eng+deu"
+    )]
+    ocr_language: Option<String>,
+
+    #[arg(
+        long,
+        help = "Write HTML as a fragment, without <html>, <head> and <body>"
+    )]
+    html_fragment: bool,
+
+    #[arg(long, help = "Embed the default stylesheet in the generated HTML page")]
+    html_theme: bool,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        value_hint = ValueHint::FilePath,
+        help = "Embed the stylesheet of a CSS file in the generated HTML page"
+    )]
+    html_css: Option<String>,
+
+    #[arg(
+        long,
+        help = "Write the page size, margins, headers and footers of HTML as print rules"
+    )]
+    html_print: bool,
+
+    #[arg(
+        long,
+        help = "Write email-safe HTML, the images saved next to it are referenced by cid: URLs"
+    )]
+    html_email: bool,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        value_hint = ValueHint::FilePath,
+        help = "Embed a TTF or OTF font in the generated PDF, can be repeated"
     )]
-    output_file: String,
+    pdf_font: Vec<String>,
+
+    #[arg(
+        long,
+        value_name = "FAMILY",
+        help = "Font family of the PDF text, repeated for fallback families in order"
+    )]
+    pdf_font_family: Vec<String>,
+
+    #[arg(
+        long,
+        value_name = "LEVEL",
+        help = "Deepest header level in the bookmarks of the generated PDF, 0 for none"
+    )]
+    pdf_outline_depth: Option<u8>,
+
+    #[arg(
+        long,
+        help = "Leave the first page of the generated PDF without page header and footer"
+    )]
+    pdf_plain_first_page: bool,
+
+    #[arg(
+        long,
+        help = "Generate a tagged PDF/A-2b and PDF/UA-1 for long-term archiving"
+    )]
+    pdf_archival: bool,
+
+    #[arg(
+        long,
+        value_name = "PASSWORD",
+        help = "Encrypt the generated PDF with a password asked to open it"
+    )]
+    pdf_user_password: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "PASSWORD",
+        help = "Encrypt the generated PDF with a password lifting its restrictions"
+    )]
+    pdf_owner_password: Option<String>,
+
+    #[arg(long, help = "Encrypt the generated PDF against printing")]
+    pdf_no_print: bool,
+
+    #[arg(long, help = "Encrypt the generated PDF against copying its content")]
+    pdf_no_copy: bool,
+
+    #[arg(
+        long,
+        value_name = "LEVEL",
+        default_value_t = PdfCompression::default(),
+        help = "Compression of the generated PDF (default, best)"
+    )]
+    pdf_compression: PdfCompression,
+
+    #[arg(
+        long,
+        value_name = "TEXT",
+        help = "Write a text such as DRAFT across every page of the generated PDF"
+    )]
+    pdf_watermark: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "IMAGE",
+        value_hint = ValueHint::FilePath,
+        conflicts_with = "pdf_watermark",
+        help = "Stamp an image file on every page of the generated PDF"
+    )]
+    pdf_stamp: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "OPACITY",
+        help = "Opacity from 0 to 1 of the watermark or stamp"
+    )]
+    pdf_watermark_opacity: Option<f32>,
+
+    #[arg(
+        long,
+        value_name = "POSITION",
+        help = "Place of the watermark or stamp (center, top, bottom, top-left, top-right, bottom-left, bottom-right)"
+    )]
+    pdf_watermark_position: Option<WatermarkPosition>,
+
+    #[arg(
+        long,
+        value_name = "CLASS",
+        default_value_t = LatexClass::default(),
+        help = "Document class of the generated LaTeX (article, report)"
+    )]
+    latex_class: LatexClass,
+
+    #[arg(
+        long,
+        value_name = "CHAR",
+        help = "Separator of the fields of the CSV or TSV input and output (default: a comma for CSV, a tab for TSV)"
+    )]
+    csv_delimiter: Option<char>,
+
+    #[arg(
+        long,
+        value_name = "CHAR",
+        default_value_t = '"',
+        help = "Quote of the fields of the CSV or TSV input and output"
+    )]
+    csv_quote: char,
+
+    #[arg(
+        long,
+        value_name = "INDEX",
+        help = "Write only the table at this index (from 0) of the document to the CSV or TSV output"
+    )]
+    csv_table: Option<usize>,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        value_hint = ValueHint::FilePath,
+        help = "Write the XML output in the schema of a TOML or JSON mapping of the elements to tags"
+    )]
+    xml_mapping: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "DIR",
+        value_hint = ValueHint::DirPath,
+        help = "Also write each page of the SVG output to this directory as page<number>.svg"
+    )]
+    svg_pages: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "NAME=VALUE",
+        help = "Fill a field of the form of the input PDF, written to the output PDF"
+    )]
+    form_value: Vec<String>,
+
+    #[arg(
+        long,
+        help = "Draw the form fields of the input PDF on its pages, no longer editable"
+    )]
+    flatten_form: bool,
 }
 
 fn main() -> anyhow::Result<()> {
     let args = Args::parse();
 
     let input_path = Path::new(&args.input_file);
-    let output_path = Path::new(&args.output_file);
 
     let supported_formats = DocumentType::supported_extensions();
 
-    let input_format = match input_path.extension() {
-        Some(ext) => ext.to_str().ok_or_else(|| {
+    let input_vec = std::fs::read(&args.input_file)?;
+    let input_bytes = Bytes::from(input_vec);
+
+    let input_extension = input_path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .and_then(DocumentType::from_extension);
+
+    // files without a known extension are recognized from their content
+    let input_doc_type = match input_extension {
+        Some(document_type) if !args.detect => document_type,
+        _ => detect_format(&input_bytes).ok_or_else(|| {
             anyhow::anyhow!(
-                "Invalid extension of the input file. Supported formats are: {}",
+                "Cannot detect the format of the input file. Supported formats are: {}",
                 supported_formats.join(", ")
             )
         })?,
-        None => {
-            return Err(anyhow::anyhow!(
-                "The input file has no extension. Supported formats are: {}",
-                supported_formats.join(", ")
-            ))
-        }
     };
 
+    let parse_options = ParseOptions {
+        markdown: MarkdownParseOptions {
+            flavor: args.markdown_flavor,
+            emoji: args.emoji,
+            sanitize: args.sanitize,
+        },
+        html: HtmlParseOptions {
+            sanitize: args.sanitize,
+        },
+        pdf: PdfParseOptions {
+            password: args.pdf_password.clone(),
+        },
+        csv: CsvParseOptions {
+            delimiter: args.csv_delimiter,
+            quote: args.csv_quote,
+        },
+        autolink: args.autolink,
+        ocr: args.ocr.then(|| {
+            Ocr::new(Tesseract {
+                language: args.ocr_language.clone(),
+                ..Default::default()
+            })
+        }),
+    };
+    let mut document = Document::parse_with_options(&input_bytes, input_doc_type, &parse_options)?;
+    for form_value in &args.form_value {
+        let (name, value) = form_value.split_once('=').ok_or_else(|| {
+            anyhow::anyhow!("Invalid form value {}, NAME=VALUE expected", form_value)
+        })?;
+        document.set_form_value(name, value)?;
+    }
+
+    if args.stats {
+        print!("{}", document.statistics());
+        return Ok(());
+    }
+
+    let output_file = args
+        .output_file
+        .ok_or_else(|| anyhow::anyhow!("The output file is missing"))?;
+    let output_path = Path::new(&output_file);
+
     let output_format = match output_path.extension() {
         Some(ext) => ext.to_str().ok_or_else(|| {
             anyhow::anyhow!(
@@ -71,14 +378,6 @@ fn main() -> anyhow::Result<()> {
         }
     };
 
-    let input_doc_type = DocumentType::from_extension(input_format).ok_or_else(|| {
-        anyhow::anyhow!(
-            "Unsupported input file format '{}'. Supported formats are: {}",
-            input_format,
-            supported_formats.join(", ")
-        )
-    })?;
-
     let output_doc_type = DocumentType::from_extension(output_format).ok_or_else(|| {
         anyhow::anyhow!(
             "Unsupported output file format '{}'. Supported formats are: {}",
@@ -87,14 +386,130 @@ fn main() -> anyhow::Result<()> {
         )
     })?;
 
-    let input_vec = std::fs::read(&args.input_file)?;
-    let input_bytes = Bytes::from(input_vec);
-
-    let document = Document::parse(&input_bytes, input_doc_type)?;
+    if args.diff {
+        let other_bytes = Bytes::from(std::fs::read(&output_file)?);
+        let other = Document::parse_with_options(&other_bytes, output_doc_type, &parse_options)?;
+        print!("{}", shiva::diff::diff(&document, &other));
+        return Ok(());
+    }
 
-    let output = document.generate(output_doc_type)?;
+    let watermark = match (&args.pdf_watermark, &args.pdf_stamp) {
+        (Some(text), _) => Some(PdfWatermark::text(text.as_str())),
+        (None, Some(path)) => Some(PdfWatermark::image(ImageData::new(
+            Bytes::from(std::fs::read(path)?),
+            String::new(),
+            String::new(),
+            path.clone(),
+            String::new(),
+            ImageDimension::default(),
+        ))),
+        (None, None) => None,
+    }
+    .map(|mut watermark| {
+        if let Some(opacity) = args.pdf_watermark_opacity {
+            watermark = watermark.with_opacity(opacity);
+        }
+        if let Some(position) = args.pdf_watermark_position {
+            watermark = watermark.with_position(position);
+        }
+        watermark
+    });
 
-    std::fs::write(&args.output_file, output)?;
+    let generate_options = GenerateOptions {
+        markdown: MarkdownOptions {
+            flavor: args.markdown_flavor,
+            emoji_shortcodes: args.emoji_shortcodes,
+            ..Default::default()
+        },
+        html: HtmlOptions {
+            standalone: !args.html_fragment,
+            theme: args.html_theme,
+            print: args.html_print,
+            email: args.html_email,
+            css: args
+                .html_css
+                .as_ref()
+                .map(std::fs::read_to_string)
+                .transpose()?,
+            ..Default::default()
+        },
+        pdf: PdfOptions {
+            fonts: args
+                .pdf_font
+                .iter()
+                .cloned()
+                .map(FontSource::Path)
+                .collect(),
+            font_families: args.pdf_font_family.clone(),
+            outline_depth: args.pdf_outline_depth,
+            first_page_header: args.pdf_plain_first_page.then(Vec::new),
+            first_page_footer: args.pdf_plain_first_page.then(Vec::new),
+            archival: args.pdf_archival,
+            encryption: (args.pdf_user_password.is_some()
+                || args.pdf_owner_password.is_some()
+                || args.pdf_no_print
+                || args.pdf_no_copy)
+                .then(|| PdfEncryption {
+                    user_password: args.pdf_user_password.clone().unwrap_or_default(),
+                    owner_password: args.pdf_owner_password.clone().unwrap_or_default(),
+                    no_print: args.pdf_no_print,
+                    no_copy: args.pdf_no_copy,
+                }),
+            compression: args.pdf_compression,
+            watermark,
+            ..Default::default()
+        },
+        latex: LatexOptions {
+            class: args.latex_class,
+        },
+        csv: CsvOptions {
+            delimiter: args.csv_delimiter,
+            quote: args.csv_quote,
+            table: args.csv_table,
+        },
+        xml: XmlOptions {
+            mapping: args
+                .xml_mapping
+                .as_ref()
+                .map(|path| {
+                    let config = std::fs::read_to_string(path)?;
+                    if path.ends_with(".json") {
+                        XmlMapping::from_json(&config)
+                    } else {
+                        XmlMapping::from_toml(&config)
+                    }
+                })
+                .transpose()?,
+        },
+        ..Default::default()
+    };
+    let fill_form = !args.form_value.is_empty() || args.flatten_form;
+    if fill_form && input_doc_type == DocumentType::PDF && output_doc_type == DocumentType::PDF {
+        // the pages of the input are kept, only its form changes
+        let filled = shiva::pdf::Transformer::fill_form(
+            &input_bytes,
+            document.form_fields(),
+            args.flatten_form,
+        )?;
+        std::fs::write(&output_file, filled)?;
+    } else if let (DocumentType::SVG, Some(dir)) = (output_doc_type, &args.svg_pages) {
+        std::fs::create_dir_all(dir)?;
+        let output = shiva::svg::Transformer::generate_with_saver(
+            &document,
+            &generate_options.pdf,
+            shiva::core::disk_image_saver(dir),
+        )?;
+        std::fs::write(&output_file, output)?;
+    } else {
+        let output = document.generate_with_options(output_doc_type, &generate_options)?;
+        std::fs::write(&output_file, output)?;
+    }
+    if args.report {
+        eprint!(
+            "{}",
+            shiva::report::conversion_report(&document, output_doc_type)
+        );
+    }
 
     Ok(())
 }
@@ -0,0 +1,2824 @@
+//! AsciiDoc source of a document, in the syntax of Asciidoctor
+//!
+//! Headers become section titles, the metadata the document header and images `image::`
+//! macros of files saved next to the source. Asides with an `admonition` class (`admonition
+//! note`) are admonition blocks, other asides sidebars, and raw HTML is passed through.
+//! Sections are at most five levels deep, deeper headers are written at the fifth level.
+//!
+//! Sources are read for the common blocks: sections, paragraphs, admonitions, lists, tables,
+//! listing, literal, example, sidebar, quote, open and passthrough blocks and images, with
+//! the inline formatting, links, cross references, footnotes and attribute references.
+//! Admonitions are read as asides with an `admonition` class, starting with their label so
+//! that the formats without admonitions show it.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write as _;
+
+use bytes::Bytes;
+use log::warn;
+
+use crate::core::{
+    disk_image_loader, disk_image_saver, header_anchor, Band, ContainerKind, Document,
+    DocumentType, Element, FieldKind, ImageData, ImageDimension, ListItem, MediaKind, Metadata,
+    NumberingStyle, TableCell, TableHeader, TableRow, TextStyle, TransformerTrait,
+    TransformerWithImageLoaderSaverTrait,
+};
+use crate::error::image_loader_errors;
+use crate::lightweight::{paragraph, plain_text, text, text_block, Inline};
+
+pub struct Transformer;
+
+/// Admonition styles
+const ADMONITIONS: [&str; 5] = ["NOTE", "TIP", "IMPORTANT", "WARNING", "CAUTION"];
+
+/// Admonition styles of the kinds of other formats, written with their labels as titles
+const ADMONITION_ALIASES: [(&str, &str); 4] = [
+    ("hint", "TIP"),
+    ("attention", "IMPORTANT"),
+    ("danger", "CAUTION"),
+    ("error", "CAUTION"),
+];
+
+/// Deepest section level, below the document title
+const MAX_SECTION_LEVEL: u8 = 5;
+
+/// Deepest list nesting, the items of deeper lists join the list at this depth
+const MAX_LIST_DEPTH: usize = 5;
+
+/// Built-in attributes for characters that would otherwise be read as markup
+const CHARACTER_ATTRIBUTES: &[(&str, &str)] = &[
+    ("empty", ""),
+    ("sp", " "),
+    ("nbsp", "\u{a0}"),
+    ("zwsp", "\u{200b}"),
+    ("wj", "\u{2060}"),
+    ("apos", "'"),
+    ("quot", "\""),
+    ("lsquo", "‘"),
+    ("rsquo", "’"),
+    ("ldquo", "“"),
+    ("rdquo", "”"),
+    ("deg", "°"),
+    ("plus", "+"),
+    ("brvbar", "¦"),
+    ("vbar", "|"),
+    ("amp", "&"),
+    ("lt", "<"),
+    ("gt", ">"),
+    ("startsb", "["),
+    ("endsb", "]"),
+    ("caret", "^"),
+    ("asterisk", "*"),
+    ("tilde", "~"),
+    ("backslash", "\\"),
+    ("backtick", "`"),
+    ("two-colons", "::"),
+    ("two-semicolons", ";;"),
+    ("cpp", "C++"),
+    ("pp", "++"),
+];
+
+/// Schemes of the URLs Asciidoctor turns into links
+const URL_SCHEMES: [&str; 5] = ["https://", "http://", "ftp://", "irc://", "mailto:"];
+
+/// Style, title and blocks of an admonition aside, without its label
+fn admonition_blocks(element: &Element) -> Option<(&'static str, Option<String>, &[Element])> {
+    let (kind, title, blocks) = element.admonition_parts()?;
+    if let Some(style) = ADMONITIONS
+        .iter()
+        .find(|style| style.eq_ignore_ascii_case(kind))
+    {
+        return Some((style, title, blocks));
+    }
+    let (kind, style) = ADMONITION_ALIASES
+        .iter()
+        .find(|(alias, _)| alias.eq_ignore_ascii_case(kind))?;
+    let label = kind[..1].to_uppercase() + &kind[1..];
+    Some((style, title.or(Some(label)), blocks))
+}
+
+/// Id of a section or an anchor, its characters other than ASCII letters, digits, `-`, `_`,
+/// `.` and `:` replaced by `-`
+fn id(anchor: &str) -> String {
+    let id: String = anchor
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || "-_.:".contains(c) {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    // ids start with a letter or `_`
+    if id.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        id
+    } else {
+        format!("_{id}")
+    }
+}
+
+/// Text with the characters that could start inline markup written so that they are not:
+/// with the built-in character attributes, or in a passthrough for `_` and `#`
+fn escape(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut escaped = String::with_capacity(text.len());
+    let is_word = |index: Option<usize>| {
+        index
+            .and_then(|index| chars.get(index))
+            .is_some_and(|c| c.is_alphanumeric())
+    };
+    for (index, &c) in chars.iter().enumerate() {
+        let previous = index.checked_sub(1).and_then(|index| chars.get(index));
+        let next = chars.get(index + 1);
+        // markup in a word is unconstrained, made of two marks
+        let inside_word = is_word(index.checked_sub(1))
+            && is_word(Some(index + 1))
+            && previous != Some(&c)
+            && next != Some(&c);
+        match c {
+            '*' if !inside_word => escaped.push_str("{asterisk}"),
+            '`' if !inside_word => escaped.push_str("{backtick}"),
+            '_' | '#' if !inside_word => {
+                let _ = write!(escaped, "pass:[{c}]");
+            }
+            '^' => escaped.push_str("{caret}"),
+            '~' => escaped.push_str("{tilde}"),
+            '+' => escaped.push_str("{plus}"),
+            '\\' => escaped.push_str("{backslash}"),
+            '{' => escaped.push_str("\\{"),
+            '[' if next == Some(&'[') => escaped.push_str("{startsb}"),
+            '<' if next == Some(&'<') => escaped.push_str("{lt}"),
+            ':' if next == Some(&':') => escaped.push_str("{two-colons}"),
+            ';' if next == Some(&';') => escaped.push_str("{two-semicolons}"),
+            '\n' => escaped.push(' '),
+            '\r' => {}
+            _ => {
+                let rest: String = chars[index..].iter().take(8).collect();
+                if !is_word(index.checked_sub(1))
+                    && URL_SCHEMES.iter().any(|scheme| rest.starts_with(scheme))
+                {
+                    // not a link
+                    escaped.push('\\');
+                }
+                escaped.push(c);
+            }
+        }
+    }
+    escaped
+}
+
+/// Text of an attribute list or a macro target, where `]` ends the list
+fn escape_brackets(text: &str) -> String {
+    text.replace(']', "\\]")
+}
+
+/// Text of an attribute value, quoted when it has commas or quotes
+fn attribute_value(text: &str) -> String {
+    if text.contains([',', '"', '=']) || text.starts_with(' ') || text.ends_with(' ') {
+        format!("\"{}\"", escape_brackets(&text.replace('"', "\\\"")))
+    } else {
+        escape_brackets(text)
+    }
+}
+
+/// Whether a line of text would be read as something else than text at the start of a
+/// paragraph: a section title, a list item, a block title, a delimiter, an attribute entry...
+fn needs_guard(line: &str) -> bool {
+    line.starts_with("//")
+        || line.starts_with(['[', ':', '<', '\''])
+        || (line.starts_with('.') && !line.starts_with(". ") && !line.starts_with(".."))
+        || section_title(line).is_some()
+        || list_item(line).is_some()
+        || description_item(line).is_some()
+        || block_delimiter(line).is_some()
+        || ADMONITIONS
+            .iter()
+            .any(|style| line.starts_with(&format!("{style}: ")))
+        || [
+            "image::",
+            "video::",
+            "audio::",
+            "toc::",
+            "include::",
+            "ifdef::",
+            "ifndef::",
+        ]
+        .iter()
+        .any(|macro_name| line.starts_with(macro_name))
+}
+
+struct Writer<'a, F>
+where
+    F: Fn(&Bytes, &str) -> anyhow::Result<()>,
+{
+    image_saver: &'a F,
+    image_count: usize,
+    link_targets: HashSet<String>,
+    /// Depth of the delimited blocks being written, the delimiters of nested blocks being
+    /// longer
+    block_depth: usize,
+    /// The last block written is a list, a list after it would continue it
+    after_list: bool,
+    /// A formula is written, it needs the `stem` attribute
+    math: bool,
+    /// Deepest header level of a `toc::[]` macro written
+    toc_levels: Option<u8>,
+    adoc: String,
+}
+
+impl<'a, F> Writer<'a, F>
+where
+    F: Fn(&Bytes, &str) -> anyhow::Result<()>,
+{
+    fn block(&mut self, element: &Element) -> anyhow::Result<()> {
+        if matches!(element, Element::List { .. }) && self.after_list {
+            // a comment line ends the list before
+            self.adoc.push_str("//\n\n");
+        }
+        self.after_list = matches!(element, Element::List { .. });
+        if let Some((style, title, blocks)) = admonition_blocks(element) {
+            return self.admonition(style, title.as_deref(), blocks);
+        }
+        match element {
+            Element::Header { level, text } => self.header(*level, text, &header_anchor(text)),
+            Element::Attributed {
+                attributes,
+                element,
+            } => match element.as_ref() {
+                Element::Header { level, text } => {
+                    let anchor = attributes
+                        .get("id")
+                        .cloned()
+                        .unwrap_or_else(|| header_anchor(text));
+                    self.header(*level, text, &anchor);
+                }
+                element => self.block(element)?,
+            },
+            Element::Paragraph { elements, .. } => self.paragraph(elements)?,
+            Element::Table { headers, rows } => self.table(headers, rows)?,
+            Element::List { .. } => {
+                self.list(element, 1)?;
+                self.adoc.push('\n');
+            }
+            Element::Image(image) => {
+                if let Some(target) = self.image_target(image)? {
+                    if !image.title().is_empty() {
+                        let _ = writeln!(self.adoc, ".{}", escape(image.title()));
+                    }
+                    let _ = write!(self.adoc, "image::{target}\n\n");
+                }
+            }
+            Element::CodeBlock { info, code } => {
+                if let Some(language) = element.code_language() {
+                    let _ = writeln!(self.adoc, "[source,{language}]");
+                } else if !info.is_empty() {
+                    self.adoc.push_str("[source]\n");
+                }
+                let delimiter = Self::delimiter('-', code);
+                let _ = write!(self.adoc, "{delimiter}\n{code}\n{delimiter}\n\n");
+            }
+            Element::Math { tex, display: true } => {
+                self.math = true;
+                let delimiter = Self::delimiter('+', tex);
+                let _ = write!(
+                    self.adoc,
+                    "[stem]\n{delimiter}\n{}\n{delimiter}\n\n",
+                    tex.trim()
+                );
+            }
+            Element::TableOfContents { max_level } => {
+                self.toc_levels = Some(*max_level);
+                self.adoc.push_str("toc::[]\n\n");
+            }
+            Element::Media { kind, url, title } => {
+                if !title.is_empty() {
+                    let _ = writeln!(self.adoc, ".{}", escape(title));
+                }
+                match kind {
+                    MediaKind::Video | MediaKind::Audio => {
+                        let _ = write!(self.adoc, "{kind}::{}[]\n\n", escape_brackets(url));
+                    }
+                    MediaKind::Frame => {
+                        if let Some(link) = element.media_link() {
+                            self.paragraph(&[link])?;
+                        }
+                    }
+                }
+            }
+            Element::Container {
+                kind: ContainerKind::Aside,
+                elements,
+            } => {
+                let delimiter = "*".repeat(4 + self.block_depth);
+                let _ = writeln!(self.adoc, "{delimiter}");
+                self.blocks(elements)?;
+                let _ = write!(self.adoc, "{delimiter}\n\n");
+            }
+            Element::Container { elements, .. } => {
+                for element in elements {
+                    self.block(element)?;
+                }
+            }
+            Element::Raw {
+                format: DocumentType::AsciiDoc,
+                content,
+            } => {
+                self.adoc.push_str(content.trim_end());
+                self.adoc.push_str("\n\n");
+            }
+            Element::Raw {
+                format: DocumentType::HTML,
+                content,
+            } => {
+                let delimiter = Self::delimiter('+', content);
+                let _ = write!(
+                    self.adoc,
+                    "{delimiter}\n{}\n{delimiter}\n\n",
+                    content.trim_end()
+                );
+            }
+            Element::Raw { .. } | Element::LineBreak => {}
+            element => self.paragraph(std::slice::from_ref(element))?,
+        }
+        Ok(())
+    }
+
+    /// Blocks of a delimited block, where the nested delimited blocks are longer
+    fn blocks(&mut self, elements: &[Element]) -> anyhow::Result<()> {
+        self.block_depth += 1;
+        self.after_list = false;
+        for element in elements {
+            self.block(element)?;
+        }
+        self.block_depth -= 1;
+        self.after_list = false;
+        if self.adoc.ends_with("\n\n") {
+            self.adoc.pop();
+        }
+        Ok(())
+    }
+
+    /// Delimiter line of a listing or passthrough block, longer than the lines of its content
+    /// made of the same character
+    fn delimiter(character: char, content: &str) -> String {
+        let longest = content
+            .lines()
+            .filter(|line| line.len() >= 4 && line.chars().all(|c| c == character))
+            .map(str::len)
+            .max()
+            .unwrap_or(3);
+        character.to_string().repeat(longest + 1)
+    }
+
+    fn paragraph(&mut self, elements: &[Element]) -> anyhow::Result<()> {
+        let text = self.inline_content(elements)?;
+        let text = text.trim_end_matches([' ', '+', '\n']).trim_start();
+        if text.is_empty() {
+            return Ok(());
+        }
+        if needs_guard(text) {
+            self.adoc.push_str("{empty}");
+        }
+        self.adoc.push_str(text);
+        self.adoc.push_str("\n\n");
+        Ok(())
+    }
+
+    fn inline_content(&mut self, elements: &[Element]) -> anyhow::Result<String> {
+        let mut adoc = String::new();
+        for element in elements {
+            self.inline(element, &mut adoc)?;
+        }
+        Ok(adoc)
+    }
+
+    fn inline(&mut self, element: &Element, adoc: &mut String) -> anyhow::Result<()> {
+        match element {
+            Element::Text { text, .. } => adoc.push_str(&escape(text)),
+            Element::Header { text, .. } => {
+                let _ = write!(adoc, "**{}**", escape(text));
+            }
+            Element::Paragraph { elements, .. } | Element::Container { elements, .. } => {
+                for element in elements {
+                    self.inline(element, adoc)?;
+                }
+            }
+            Element::Styled { style, elements } => {
+                let content = self.inline_content(elements)?;
+                let _ = match style {
+                    TextStyle::Strong => write!(adoc, "**{content}**"),
+                    TextStyle::Emphasis => write!(adoc, "__{content}__"),
+                    TextStyle::Strikethrough => write!(adoc, "[.line-through]##{content}##"),
+                };
+            }
+            Element::Hyperlink { elements, url, .. } => {
+                if let [Element::Image(image)] = elements.as_slice() {
+                    if let Some(target) = self.image_target(image)? {
+                        let target =
+                            target.replacen('[', &format!("[link={},", attribute_value(url)), 1);
+                        let _ = write!(adoc, "image:{}", target.replace(",]", "]"));
+                    }
+                    return Ok(());
+                }
+                let content = escape_brackets(&self.inline_content(elements)?);
+                if let Some(anchor) = url.strip_prefix('#') {
+                    if content.is_empty() {
+                        let _ = write!(adoc, "<<{}>>", id(anchor));
+                    } else {
+                        let _ = write!(adoc, "xref:{}[{content}]", id(anchor));
+                    }
+                } else if URL_SCHEMES.iter().any(|scheme| url.starts_with(scheme))
+                    && !url.contains([' ', '['])
+                {
+                    let _ = write!(adoc, "{url}[{content}]");
+                } else {
+                    let _ = write!(adoc, "link:++{url}++[{content}]");
+                }
+            }
+            Element::InlineCode { text } => {
+                let _ = write!(adoc, "``+{}+``", text.replace('\n', " "));
+            }
+            Element::Math { tex, .. } => {
+                self.math = true;
+                let _ = write!(adoc, "stem:[{}]", escape_brackets(tex.trim()));
+            }
+            Element::CodeBlock { code, .. } => {
+                let lines: Vec<String> = code.lines().map(|line| format!("``+{line}+``")).collect();
+                adoc.push_str(&lines.join(" +\n"));
+            }
+            Element::LineBreak => adoc.push_str(" +\n"),
+            Element::Footnote { elements } => {
+                let content = self.inline_content(elements)?;
+                let content = content.replace(" +\n", " ");
+                let _ = write!(adoc, "footnote:[{}]", escape_brackets(content.trim()));
+            }
+            Element::Field { kind } => match kind {
+                FieldKind::Date => adoc.push_str("{localdate}"),
+                FieldKind::DocumentTitle => adoc.push_str("{doctitle}"),
+                // no pages
+                FieldKind::PageNumber | FieldKind::TotalPages => {}
+            },
+            Element::Bookmark { id: anchor, .. } => {
+                let _ = write!(adoc, "[[{}]]", id(anchor));
+            }
+            Element::Image(image) => {
+                if let Some(target) = self.image_target(image)? {
+                    let _ = write!(adoc, "image:{target}");
+                }
+            }
+            Element::Attributed { element, .. } => self.inline(element, adoc)?,
+            Element::Raw {
+                format: DocumentType::AsciiDoc,
+                content,
+            } => adoc.push_str(content),
+            Element::Raw {
+                format: DocumentType::HTML,
+                content,
+            } => {
+                let _ = write!(adoc, "pass:[{}]", escape_brackets(content));
+            }
+            Element::Media { .. } => {
+                if let Some(link) = element.media_link() {
+                    self.inline(&link, adoc)?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn header(&mut self, level: u8, text: &str, anchor: &str) {
+        if self.link_targets.contains(anchor) {
+            let _ = writeln!(self.adoc, "[[{}]]", id(anchor));
+        }
+        let level = usize::from(level.clamp(1, MAX_SECTION_LEVEL));
+        let _ = write!(self.adoc, "{} {}\n\n", "=".repeat(level + 1), escape(text));
+    }
+
+    fn admonition(
+        &mut self,
+        style: &str,
+        title: Option<&str>,
+        blocks: &[Element],
+    ) -> anyhow::Result<()> {
+        if let Some(title) = title {
+            let _ = writeln!(self.adoc, ".{}", escape(title));
+        }
+        // a single paragraph is written as an admonition paragraph
+        if let [Element::Paragraph { elements, .. }] = blocks {
+            let content = self.inline_content(elements)?;
+            let content = content.trim_end_matches([' ', '+', '\n']).trim_start();
+            let _ = write!(self.adoc, "{style}: {content}\n\n");
+            return Ok(());
+        }
+        let delimiter = "=".repeat(4 + self.block_depth);
+        let _ = writeln!(self.adoc, "[{style}]\n{delimiter}");
+        self.blocks(blocks)?;
+        let _ = write!(self.adoc, "{delimiter}\n\n");
+        Ok(())
+    }
+
+    fn table(&mut self, headers: &[TableHeader], rows: &[TableRow]) -> anyhow::Result<()> {
+        let columns = rows
+            .iter()
+            .map(|row| row.cells.len())
+            .chain(std::iter::once(headers.len()))
+            .max()
+            .unwrap_or_default();
+        if columns == 0 {
+            return Ok(());
+        }
+        let cols = if headers.len() == columns {
+            headers
+                .iter()
+                .map(|header| format!("{:.0}", header.width.max(1.0)))
+                .collect::<Vec<_>>()
+                .join(",")
+        } else {
+            format!("{columns}*")
+        };
+        let options = if headers.is_empty() { "" } else { "%header," };
+        let _ = write!(self.adoc, "[{options}cols=\"{cols}\"]\n|===\n");
+        if !headers.is_empty() {
+            let cells: Vec<&Element> = headers.iter().map(|header| &header.element).collect();
+            self.row(&cells, columns)?;
+            self.adoc.push('\n');
+        }
+        for row in rows {
+            let cells: Vec<&Element> = row.cells.iter().map(|cell| &cell.element).collect();
+            self.row(&cells, columns)?;
+        }
+        self.adoc.push_str("|===\n\n");
+        Ok(())
+    }
+
+    /// Row of a table, on a single line unless a cell has blocks, which are written as
+    /// AsciiDoc cells on their own lines
+    fn row(&mut self, cells: &[&Element], columns: usize) -> anyhow::Result<()> {
+        let blocks = cells
+            .iter()
+            .any(|cell| !cell.is_inline() && !matches!(cell, Element::Paragraph { .. }));
+        for column in 0..columns {
+            let cell = cells.get(column);
+            match cell {
+                Some(cell) if blocks && !cell.is_inline() => {
+                    self.adoc.push_str("a|\n");
+                    let adoc = std::mem::take(&mut self.adoc);
+                    self.blocks(std::slice::from_ref(*cell))?;
+                    let content = std::mem::replace(&mut self.adoc, adoc);
+                    self.adoc.push_str(&content.replace('|', "\\|"));
+                }
+                _ => {
+                    let content = match cell {
+                        Some(cell) => {
+                            let content = self.inline_content(std::slice::from_ref(*cell))?;
+                            content.replace(" +\n", " +\n{empty}").replace('|', "\\|")
+                        }
+                        None => String::new(),
+                    };
+                    let separator = if column == 0 || blocks { "|" } else { " |" };
+                    self.adoc.push_str(separator);
+                    self.adoc.push_str(content.trim());
+                    if blocks {
+                        self.adoc.push('\n');
+                    }
+                }
+            }
+        }
+        if !blocks {
+            self.adoc.push('\n');
+        }
+        Ok(())
+    }
+
+    fn list(&mut self, list: &Element, depth: usize) -> anyhow::Result<()> {
+        let Element::List {
+            elements,
+            numbered,
+            start,
+            numbering,
+        } = list
+        else {
+            return Ok(());
+        };
+        let depth = depth.min(MAX_LIST_DEPTH);
+        let marker = if *numbered { "." } else { "*" }.repeat(depth);
+        if *numbered {
+            let style = match numbering {
+                NumberingStyle::Decimal => None,
+                NumberingStyle::LowerAlpha => Some("loweralpha"),
+                NumberingStyle::UpperAlpha => Some("upperalpha"),
+                NumberingStyle::LowerRoman => Some("lowerroman"),
+                NumberingStyle::UpperRoman => Some("upperroman"),
+            };
+            let mut attributes: Vec<String> = style.into_iter().map(str::to_string).collect();
+            if *start != 1 {
+                attributes.push(format!("start={start}"));
+            }
+            if !attributes.is_empty() {
+                let _ = writeln!(self.adoc, "[{}]", attributes.join(","));
+            }
+        }
+        for (index, item) in elements.iter().enumerate() {
+            match &item.element {
+                Element::List { .. } => {
+                    if index == 0 {
+                        let _ = writeln!(self.adoc, "{marker} {{empty}}");
+                    }
+                    self.list(&item.element, depth + 1)?;
+                }
+                element if element.is_inline() || matches!(element, Element::Paragraph { .. }) => {
+                    let content = self.inline_content(std::slice::from_ref(element))?;
+                    let content = content.trim_end_matches([' ', '+', '\n']).trim_start();
+                    let content = if content.is_empty() {
+                        "{empty}".to_string()
+                    } else {
+                        content.replace(" +\n", " +\n{empty}")
+                    };
+                    let _ = writeln!(self.adoc, "{marker} {content}");
+                }
+                // blocks are attached to the item before
+                element => {
+                    if index == 0 {
+                        let _ = writeln!(self.adoc, "{marker} {{empty}}");
+                    }
+                    self.adoc.push_str("+\n");
+                    let adoc = std::mem::take(&mut self.adoc);
+                    self.block_depth += 1;
+                    self.after_list = false;
+                    self.block(element)?;
+                    self.block_depth -= 1;
+                    let block = std::mem::replace(&mut self.adoc, adoc);
+                    self.adoc.push_str(block.trim_end());
+                    self.adoc.push('\n');
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Target and attributes of the image macro of an image saved as a file
+    fn image_target(&mut self, image: &ImageData) -> anyhow::Result<Option<String>> {
+        if image.bytes().is_empty() {
+            warn!("Image without content left out: {}", image.title());
+            return Ok(None);
+        }
+        self.image_count += 1;
+        let name = format!(
+            "image{}{}",
+            self.image_count,
+            image.image_type().to_extension()
+        );
+        (self.image_saver)(image.bytes(), &name)?;
+        let mut attributes = vec![attribute_value(image.alt())];
+        let size = image.size();
+        if let Some(width) = &size.width {
+            attributes.push(format!("width={}", attribute_value(width)));
+        }
+        if let Some(height) = &size.height {
+            attributes.push(format!("height={}", attribute_value(height)));
+        }
+        while attributes.last().is_some_and(String::is_empty) {
+            attributes.pop();
+        }
+        Ok(Some(format!("{name}[{}]", attributes.join(","))))
+    }
+}
+
+/// Block attributes read before a block: `[style,positional,name=value]`, `[[id]]` and
+/// `.Title` lines
+#[derive(Default)]
+struct BlockAttributes {
+    style: Option<String>,
+    positional: Vec<String>,
+    named: HashMap<String, String>,
+    options: Vec<String>,
+    id: Option<String>,
+    title: Option<String>,
+}
+
+impl BlockAttributes {
+    /// Reads an attribute list, without its brackets
+    fn read(&mut self, list: &str) {
+        for (index, entry) in split_attributes(list).into_iter().enumerate() {
+            match entry.split_once('=') {
+                Some((name, value)) if !name.is_empty() && !name.contains(' ') => {
+                    let value = unquote(value.trim());
+                    if name.trim() == "options" || name.trim() == "opts" {
+                        self.options
+                            .extend(value.split(',').map(|option| option.trim().to_string()));
+                    } else {
+                        self.named.insert(name.trim().to_string(), value);
+                    }
+                }
+                _ => {
+                    let entry = unquote(entry.trim());
+                    if index == 0 {
+                        // style with the shorthands of the id, roles and options
+                        let mut parts = entry.split(['#', '%', '.']).peekable();
+                        let style = parts.next().unwrap_or_default();
+                        if !style.is_empty() {
+                            self.style = Some(style.to_string());
+                        }
+                        let mut rest = &entry[style.len()..];
+                        while let Some(kind) = rest.chars().next() {
+                            let end = rest[1..]
+                                .find(['#', '%', '.'])
+                                .map_or(rest.len(), |end| end + 1);
+                            let value = rest[1..end].to_string();
+                            match kind {
+                                '#' => self.id = Some(value),
+                                '%' => self.options.push(value),
+                                _ => {}
+                            }
+                            rest = &rest[end..];
+                        }
+                    } else {
+                        self.positional.push(entry);
+                    }
+                }
+            }
+        }
+    }
+
+    fn has_option(&self, option: &str) -> bool {
+        self.options.iter().any(|name| name == option)
+    }
+}
+
+/// Entries of an attribute list, split on the commas outside of quotes
+fn split_attributes(list: &str) -> Vec<String> {
+    let mut entries = vec![];
+    let mut entry = String::new();
+    let mut quote = None;
+    let mut chars = list.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if quote.is_some() => entry.extend(chars.next()),
+            '"' | '\''
+                if quote.is_none()
+                    && (entry.trim().is_empty() || entry.trim_end().ends_with('=')) =>
+            {
+                quote = Some(c);
+                entry.push(c);
+            }
+            c if Some(c) == quote => {
+                quote = None;
+                entry.push(c);
+            }
+            ',' if quote.is_none() => entries.push(std::mem::take(&mut entry)),
+            c => entry.push(c),
+        }
+    }
+    if !entry.trim().is_empty() || !entries.is_empty() {
+        entries.push(entry);
+    }
+    entries
+}
+
+fn unquote(value: &str) -> String {
+    let value = value.trim();
+    for quote in ['"', '\''] {
+        if value.len() >= 2 && value.starts_with(quote) && value.ends_with(quote) {
+            return value[1..value.len() - 1].to_string();
+        }
+    }
+    value.to_string()
+}
+
+/// Delimiter of a delimited block: a line of four or more `-`, `.`, `=`, `*`, `_`, `+`, `/`,
+/// a table delimiter or `--` for an open block
+fn block_delimiter(line: &str) -> Option<&str> {
+    let line = line.trim_end();
+    if line == "--" || line == "```" {
+        return Some(line);
+    }
+    if ["|===", ",===", ":===", "!==="]
+        .iter()
+        .any(|table| line.starts_with(table) && line[1..].chars().all(|c| c == '='))
+    {
+        return Some(line);
+    }
+    let first = line.chars().next()?;
+    ("-.=*_+/".contains(first) && line.len() >= 4 && line.chars().all(|c| c == first))
+        .then_some(line)
+}
+
+/// Marker and text of a list item line
+fn list_item(line: &str) -> Option<(String, &str)> {
+    let trimmed = line.trim_start();
+    let (marker, rest) = trimmed.split_once([' ', '\t'])?;
+    let rest = rest.trim_start();
+    if rest.is_empty() {
+        return None;
+    }
+    let is_marker = match marker.chars().next()? {
+        '*' | '.' => {
+            marker.len() <= 5
+                && marker
+                    .chars()
+                    .all(|c| c == marker.chars().next().unwrap_or('*'))
+        }
+        '-' => marker == "-",
+        _ => match marker.strip_suffix(['.', ')']) {
+            Some(number) => {
+                !number.is_empty()
+                    && (number.chars().all(|c| c.is_ascii_digit())
+                        || (number.len() == 1 && number.chars().all(|c| c.is_ascii_alphabetic()))
+                        || (marker.ends_with(')')
+                            && number.chars().all(|c| "ivxlcIVXLC".contains(c))))
+            }
+            None => false,
+        },
+    };
+    is_marker.then(|| (marker.to_string(), rest))
+}
+
+/// Term, marker and description of a description list item line (`CPU:: the processor`)
+fn description_item(line: &str) -> Option<(&str, &str, &str)> {
+    if line.starts_with([' ', '\t', '/', '[', '.', ':']) {
+        return None;
+    }
+    for marker in ["::::", ":::", "::", ";;"] {
+        let mut search = 0;
+        while let Some(found) = line[search..].find(marker) {
+            let index = search + found;
+            let after = &line[index + marker.len()..];
+            let before = &line[..index];
+            let next = after.chars().next();
+            if !before.trim().is_empty()
+                && !before.ends_with([':', ';'])
+                && next.is_none_or(|c| c == ' ' || c == '\t')
+            {
+                return Some((before.trim(), marker, after.trim()));
+            }
+            search = index + marker.len();
+        }
+    }
+    None
+}
+
+/// Kind of list of a marker, the lists of the same kind being at the same depth
+fn marker_kind(marker: &str) -> String {
+    match marker.chars().next() {
+        Some('*' | '.' | '-' | ':' | ';') => marker.to_string(),
+        _ if marker.ends_with(')') => "1)".to_string(),
+        _ => "1.".to_string(),
+    }
+}
+
+/// Numbering style of an explicit number (`a.`, `iv)`) with its value
+fn numbering_of(marker: &str) -> (NumberingStyle, usize) {
+    let number = marker.trim_end_matches(['.', ')']);
+    if let Ok(value) = number.parse() {
+        return (NumberingStyle::Decimal, value);
+    }
+    let roman = |number: &str| -> usize {
+        let digit = |c: char| match c.to_ascii_lowercase() {
+            'i' => 1,
+            'v' => 5,
+            'x' => 10,
+            'l' => 50,
+            _ => 100,
+        };
+        let digits: Vec<usize> = number.chars().map(digit).collect();
+        digits
+            .iter()
+            .enumerate()
+            .map(|(index, &value)| {
+                if digits.get(index + 1).is_some_and(|&next| next > value) {
+                    0usize.wrapping_sub(value)
+                } else {
+                    value
+                }
+            })
+            .fold(0usize, usize::wrapping_add)
+    };
+    let first = number.chars().next().unwrap_or('a');
+    if marker.ends_with(')') {
+        if first.is_ascii_uppercase() {
+            return (NumberingStyle::UpperRoman, roman(number));
+        }
+        return (NumberingStyle::LowerRoman, roman(number));
+    }
+    let value = (first.to_ascii_lowercase() as usize).saturating_sub('a' as usize) + 1;
+    if first.is_ascii_uppercase() {
+        (NumberingStyle::UpperAlpha, value)
+    } else {
+        (NumberingStyle::LowerAlpha, value)
+    }
+}
+
+/// Numbering style of a list style (`loweralpha`)
+fn numbering_style(style: &str) -> Option<NumberingStyle> {
+    match style {
+        "arabic" | "decimal" => Some(NumberingStyle::Decimal),
+        "loweralpha" => Some(NumberingStyle::LowerAlpha),
+        "upperalpha" => Some(NumberingStyle::UpperAlpha),
+        "lowerroman" => Some(NumberingStyle::LowerRoman),
+        "upperroman" => Some(NumberingStyle::UpperRoman),
+        _ => None,
+    }
+}
+
+/// Blocks of a list item or a table cell: consecutive paragraphs are joined by line breaks
+/// and a paragraph of text only is kept as text
+fn join_paragraphs(blocks: Vec<Element>) -> Vec<Element> {
+    let mut joined: Vec<Element> = vec![];
+    for block in blocks {
+        match (joined.last_mut(), block) {
+            (
+                Some(Element::Paragraph {
+                    elements: previous, ..
+                }),
+                Element::Paragraph { elements: next, .. },
+            ) => {
+                previous.push(Element::LineBreak);
+                previous.extend(next);
+            }
+            (_, block) => joined.push(block),
+        }
+    }
+    joined.into_iter().map(text_block).collect()
+}
+
+/// Cell specifier before a `|`: span, duplication, alignments and style (`2+^.^a`)
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct CellSpec {
+    colspan: usize,
+    rowspan: usize,
+    duplicate: usize,
+    style: Option<char>,
+}
+
+impl Default for CellSpec {
+    fn default() -> CellSpec {
+        CellSpec {
+            colspan: 1,
+            rowspan: 1,
+            duplicate: 1,
+            style: None,
+        }
+    }
+}
+
+impl CellSpec {
+    fn parse(spec: &str) -> Option<CellSpec> {
+        let mut result = CellSpec::default();
+        let mut rest = spec;
+        let number = |rest: &mut &str| -> Option<usize> {
+            let end = rest
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(rest.len());
+            let value = rest[..end].parse().ok();
+            *rest = &rest[end..];
+            value
+        };
+        // span: `2+`, `.3+`, `2.3+`, or duplication `3*`
+        let columns = number(&mut rest);
+        let rows = if rest.starts_with('.') && rest[1..].starts_with(|c: char| c.is_ascii_digit()) {
+            rest = &rest[1..];
+            number(&mut rest)
+        } else {
+            None
+        };
+        if let Some(after) = rest.strip_prefix('+') {
+            result.colspan = columns.unwrap_or(1);
+            result.rowspan = rows.unwrap_or(1);
+            rest = after;
+        } else if let (Some(count), Some(after)) = (columns, rest.strip_prefix('*')) {
+            result.duplicate = count;
+            rest = after;
+        } else if columns.is_some() || rows.is_some() {
+            return None;
+        }
+        rest = rest.strip_prefix(['<', '^', '>']).unwrap_or(rest);
+        if let Some(after) = rest.strip_prefix('.') {
+            rest = after.strip_prefix(['<', '^', '>'])?;
+        }
+        if let Some(style) = rest.chars().next() {
+            if rest.len() > 1 || !"adehlmsv".contains(style) {
+                return None;
+            }
+            result.style = Some(style);
+        }
+        Some(result)
+    }
+}
+
+/// Column count, widths and styles of a `cols` attribute (`3*`, `1,2a,1`)
+fn column_specs(cols: &str) -> Vec<(f32, Option<char>)> {
+    let mut columns = vec![];
+    for entry in cols.split([',', ';']) {
+        let entry = entry.trim();
+        let (count, spec) = match entry.split_once('*') {
+            Some((count, spec)) => (count.trim().parse().unwrap_or(1), spec),
+            None => (1, entry),
+        };
+        let width: String = spec.chars().filter(char::is_ascii_digit).collect();
+        let width = width.parse().unwrap_or(1.0);
+        let style = spec.chars().last().filter(|c| "adehlmsv".contains(*c));
+        for _ in 0..count {
+            columns.push((width, style));
+        }
+    }
+    columns
+}
+
+struct Parser<'a, F>
+where
+    F: Fn(&str) -> anyhow::Result<Bytes>,
+{
+    image_loader: &'a F,
+    /// Document attributes set in the header and between the blocks
+    attributes: HashMap<String, String>,
+    metadata: Metadata,
+    /// Ids of the sections, the explicit ones and the generated `_title` ones, with the
+    /// anchors and titles of their headers
+    sections: HashMap<String, (String, String)>,
+}
+
+impl<'a, F> Parser<'a, F>
+where
+    F: Fn(&str) -> anyhow::Result<Bytes>,
+{
+    /// Document header: the title, the author and revision lines and the attribute entries
+    fn header(&mut self, lines: &[&str]) -> anyhow::Result<usize> {
+        let mut index = 0;
+        while index < lines.len() {
+            let line = lines[index].trim_end();
+            let skipped = (line.starts_with("//") && !line.starts_with("////"))
+                || (line.starts_with(':') && self.attribute_entry(line));
+            if !skipped {
+                break;
+            }
+            index += 1;
+        }
+        let Some(title) = lines.get(index).and_then(|line| line.strip_prefix("= ")) else {
+            return Ok(index);
+        };
+        let title = self.inline(title.trim())?;
+        self.metadata.title = Some(plain_text(&title));
+        index += 1;
+        let mut details = 0;
+        while let Some(line) = lines.get(index) {
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            let skipped =
+                line.starts_with("//") || (line.starts_with(':') && self.attribute_entry(line));
+            if skipped {
+                index += 1;
+                continue;
+            }
+            if details == 0 {
+                let authors: Vec<&str> = line
+                    .split(';')
+                    .map(|author| author.split('<').next().unwrap_or_default().trim())
+                    .filter(|author| !author.is_empty())
+                    .collect();
+                self.metadata.author = Some(authors.join(", "));
+                details += 1;
+            } else if details == 1 {
+                // revision: `v1.0, 2024-01-31: remark`
+                let line = line.split(':').next().unwrap_or_default();
+                let date = match line.split_once(',') {
+                    Some((_, date)) => Some(date.trim()),
+                    None if !line.starts_with(['v', 'V']) => Some(line.trim()),
+                    None => None,
+                };
+                self.metadata.date = date.filter(|date| !date.is_empty()).map(str::to_string);
+                details += 1;
+            }
+            index += 1;
+        }
+        Ok(index)
+    }
+
+    /// Sets or unsets a document attribute (`:name: value`, `:name!:`), whether the line is
+    /// an attribute entry
+    fn attribute_entry(&mut self, line: &str) -> bool {
+        let Some((name, value)) = line[1..].split_once(':') else {
+            return false;
+        };
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_alphanumeric() || c == '-' || c == '_' || c == '!')
+        {
+            return false;
+        }
+        let value = self.substitute_attributes(value.trim());
+        let name = name.to_lowercase();
+        if let Some(name) = name.strip_suffix('!').or_else(|| name.strip_prefix('!')) {
+            self.attributes.remove(name);
+            return true;
+        }
+        match name.as_str() {
+            "author" | "authors" => self.metadata.author = Some(value.replace(';', ",")),
+            "revdate" => self.metadata.date = Some(value.clone()),
+            "description" => self.metadata.description = Some(value.clone()),
+            "keywords" => {
+                self.metadata.keywords = value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|keyword| !keyword.is_empty())
+                    .map(str::to_string)
+                    .collect();
+            }
+            _ => {}
+        }
+        self.attributes.insert(name, value);
+        true
+    }
+
+    /// Text with its attribute references replaced by their values, the unknown ones kept
+    fn substitute_attributes(&self, text: &str) -> String {
+        let mut substituted = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(start) = rest.find('{') {
+            let escaped = rest[..start].ends_with('\\');
+            substituted.push_str(&rest[..start - usize::from(escaped)]);
+            rest = &rest[start..];
+            let end = rest.find('}').filter(|end| {
+                *end > 1
+                    && rest[1..*end]
+                        .chars()
+                        .all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+            });
+            match end {
+                Some(end) if !escaped => {
+                    let name = rest[1..end].to_lowercase();
+                    match self.attribute(&name) {
+                        Some(value) => substituted.push_str(&value),
+                        None => substituted.push_str(&rest[..=end]),
+                    }
+                    rest = &rest[end + 1..];
+                }
+                _ => {
+                    substituted.push('{');
+                    rest = &rest[1..];
+                }
+            }
+        }
+        substituted.push_str(rest);
+        substituted
+    }
+
+    fn attribute(&self, name: &str) -> Option<String> {
+        if let Some(value) = self.attributes.get(name) {
+            return Some(value.clone());
+        }
+        if let Some((_, value)) = CHARACTER_ATTRIBUTES
+            .iter()
+            .find(|(attribute, _)| *attribute == name)
+        {
+            return Some(value.to_string());
+        }
+        match name {
+            "doctitle" => self.metadata.title.clone(),
+            "author" => self.metadata.author.clone(),
+            "revdate" => self.metadata.date.clone(),
+            _ => None,
+        }
+    }
+
+    fn blocks(&mut self, lines: &[&str]) -> anyhow::Result<Vec<Element>> {
+        let mut blocks = vec![];
+        let mut attributes = BlockAttributes::default();
+        let mut index = 0;
+        while index < lines.len() {
+            let line = lines[index].trim_end();
+            let trimmed = line.trim();
+            if trimmed.is_empty() {
+                index += 1;
+                continue;
+            }
+            if line.starts_with("//") && block_delimiter(line).is_none() {
+                index += 1;
+                continue;
+            }
+            if line.starts_with(':') && self.attribute_entry(line) {
+                index += 1;
+                continue;
+            }
+            if let Some(anchor) = line
+                .strip_prefix("[[")
+                .and_then(|rest| rest.strip_suffix("]]"))
+            {
+                attributes.id = Some(anchor.split(',').next().unwrap_or_default().to_string());
+                index += 1;
+                continue;
+            }
+            if let Some(list) = line
+                .strip_prefix('[')
+                .and_then(|rest| rest.strip_suffix(']'))
+            {
+                attributes.read(list);
+                index += 1;
+                continue;
+            }
+            if line.starts_with('.')
+                && line[1..].starts_with(|c: char| !c.is_whitespace() && c != '.')
+                && list_item(line).is_none()
+            {
+                attributes.title = Some(line[1..].to_string());
+                index += 1;
+                continue;
+            }
+            let attributes = std::mem::take(&mut attributes);
+            index = self.block(lines, index, attributes, &mut blocks)?;
+        }
+        Ok(blocks)
+    }
+
+    /// Reads the block starting at `index`, the index after it
+    fn block(
+        &mut self,
+        lines: &[&str],
+        index: usize,
+        attributes: BlockAttributes,
+        blocks: &mut Vec<Element>,
+    ) -> anyhow::Result<usize> {
+        let line = lines[index].trim_end();
+        if let Some(delimiter) = block_delimiter(line) {
+            let end = lines[index + 1..]
+                .iter()
+                .position(|line| line.trim_end() == delimiter)
+                .map_or(lines.len(), |end| index + 1 + end);
+            let content = &lines[index + 1..end];
+            self.delimited(delimiter, content, attributes, blocks)?;
+            return Ok((end + 1).min(lines.len()));
+        }
+        if let Some((level, title)) = section_title(line) {
+            self.section(level, title, attributes, blocks)?;
+            return Ok(index + 1);
+        }
+        for (name, kind) in [
+            ("image::", None),
+            ("video::", Some(MediaKind::Video)),
+            ("audio::", Some(MediaKind::Audio)),
+        ] {
+            if let Some(rest) = line.strip_prefix(name) {
+                if let Some((target, list)) =
+                    rest.strip_suffix(']').and_then(|rest| rest.split_once('['))
+                {
+                    let target = self.substitute_attributes(target);
+                    let element = match kind {
+                        None => self.image(&target, list, attributes.title.as_deref())?,
+                        Some(kind) => Some(Element::Media {
+                            kind,
+                            url: target,
+                            title: attributes.title.clone().unwrap_or_default(),
+                        }),
+                    };
+                    blocks.extend(element);
+                    return Ok(index + 1);
+                }
+            }
+        }
+        if line.starts_with("toc::[") {
+            let max_level = self
+                .attributes
+                .get("toclevels")
+                .and_then(|levels| levels.parse().ok())
+                .unwrap_or(2);
+            blocks.push(Element::TableOfContents { max_level });
+            return Ok(index + 1);
+        }
+        // thematic and page breaks, includes and conditionals
+        if ["'''", "---", "***", "<<<"].contains(&line)
+            || ["include::", "ifdef::", "ifndef::", "ifeval::", "endif::"]
+                .iter()
+                .any(|directive| line.starts_with(directive))
+        {
+            if line.starts_with("include::") {
+                warn!("Included file not read: {}", line);
+            }
+            return Ok(index + 1);
+        }
+        if list_item(line).is_some() || description_item(line).is_some() {
+            return self.list(lines, index, attributes, blocks);
+        }
+        // literal paragraph
+        if line.starts_with([' ', '\t']) {
+            let end = paragraph_end(lines, index);
+            let indent = lines[index..end]
+                .iter()
+                .map(|line| line.len() - line.trim_start().len())
+                .min()
+                .unwrap_or_default();
+            let code: Vec<&str> = lines[index..end]
+                .iter()
+                .map(|line| line.get(indent..).unwrap_or_default().trim_end())
+                .collect();
+            blocks.push(Element::CodeBlock {
+                info: String::new(),
+                code: code.join("\n"),
+            });
+            return Ok(end);
+        }
+        let end = paragraph_end(lines, index);
+        let mut content = lines[index..end].join("\n");
+        let mut style = attributes.style.clone();
+        if let Some((label, rest)) = content.split_once(": ") {
+            if ADMONITIONS.contains(&label) {
+                style = Some(label.to_string());
+                content = rest.to_string();
+            }
+        }
+        self.paragraph(&content, style.as_deref(), attributes, blocks)?;
+        Ok(end)
+    }
+
+    fn paragraph(
+        &mut self,
+        content: &str,
+        style: Option<&str>,
+        attributes: BlockAttributes,
+        blocks: &mut Vec<Element>,
+    ) -> anyhow::Result<()> {
+        match style {
+            Some("source" | "listing" | "literal") => {
+                blocks.extend(self.title(&attributes));
+                blocks.push(Element::CodeBlock {
+                    info: source_language(&attributes, &self.attributes),
+                    code: content.to_string(),
+                });
+            }
+            Some("stem" | "latexmath") => blocks.push(Element::Math {
+                tex: content.trim().to_string(),
+                display: true,
+            }),
+            Some(style) if ADMONITIONS.contains(&style) => {
+                let elements = self.inline(content)?;
+                blocks.push(Element::admonition(
+                    &style.to_lowercase(),
+                    attributes.title.as_deref(),
+                    vec![paragraph(elements)],
+                ));
+            }
+            _ => {
+                blocks.extend(self.title(&attributes));
+                let mut elements = self.inline(content)?;
+                if let Some(anchor) = attributes.id {
+                    elements.insert(
+                        0,
+                        Element::Bookmark {
+                            id: anchor,
+                            title: String::new(),
+                        },
+                    );
+                }
+                if style == Some("verse") {
+                    elements = self.inline(&content.replace('\n', " +\n"))?;
+                }
+                if !elements.is_empty() {
+                    blocks.push(paragraph(elements));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Paragraph of a block title, for the blocks without a title of their own
+    fn title(&mut self, attributes: &BlockAttributes) -> Option<Element> {
+        let title = attributes.title.as_deref()?;
+        let elements = self.inline(title).ok()?;
+        Some(paragraph(vec![Element::Styled {
+            style: TextStyle::Strong,
+            elements,
+        }]))
+    }
+
+    fn section(
+        &mut self,
+        level: usize,
+        title: &str,
+        attributes: BlockAttributes,
+        blocks: &mut Vec<Element>,
+    ) -> anyhow::Result<()> {
+        let text = plain_text(&self.inline(title)?);
+        let anchor = header_anchor(&text);
+        let generated = format!(
+            "_{}",
+            text.to_lowercase()
+                .split(|c: char| !c.is_alphanumeric())
+                .filter(|word| !word.is_empty())
+                .collect::<Vec<_>>()
+                .join("_")
+        );
+        self.sections
+            .insert(generated, (anchor.clone(), text.clone()));
+        if let Some(section_id) = attributes.id {
+            self.sections.insert(section_id, (anchor, text.clone()));
+        }
+        blocks.push(Element::Header {
+            level: level.clamp(1, 6) as u8,
+            text,
+        });
+        Ok(())
+    }
+
+    fn delimited(
+        &mut self,
+        delimiter: &str,
+        content: &[&str],
+        attributes: BlockAttributes,
+        blocks: &mut Vec<Element>,
+    ) -> anyhow::Result<()> {
+        let style = attributes.style.as_deref();
+        if let Some(style) = style.filter(|style| ADMONITIONS.contains(style)) {
+            let inner = self.blocks(content)?;
+            blocks.push(Element::admonition(
+                &style.to_lowercase(),
+                attributes.title.as_deref(),
+                inner,
+            ));
+            return Ok(());
+        }
+        match delimiter.chars().next() {
+            Some('-') if delimiter != "--" => {
+                blocks.extend(self.title(&attributes));
+                blocks.push(Element::CodeBlock {
+                    info: source_language(&attributes, &self.attributes),
+                    code: content.join("\n"),
+                });
+            }
+            Some('`') => {
+                blocks.extend(self.title(&attributes));
+                blocks.push(Element::CodeBlock {
+                    info: String::new(),
+                    code: content.join("\n"),
+                });
+            }
+            Some('.') => {
+                blocks.extend(self.title(&attributes));
+                blocks.push(Element::CodeBlock {
+                    info: String::new(),
+                    code: content.join("\n"),
+                });
+            }
+            Some('+') => match style {
+                Some("stem" | "latexmath") => blocks.push(Element::Math {
+                    tex: content.join("\n").trim().to_string(),
+                    display: true,
+                }),
+                Some("asciimath") => blocks.push(Element::CodeBlock {
+                    info: "asciimath".to_string(),
+                    code: content.join("\n"),
+                }),
+                _ => blocks.push(Element::Raw {
+                    format: DocumentType::HTML,
+                    content: content.join("\n"),
+                }),
+            },
+            Some('/') => {}
+            Some('*') => {
+                let mut elements: Vec<Element> = self.title(&attributes).into_iter().collect();
+                elements.extend(self.blocks(content)?);
+                blocks.push(Element::Container {
+                    kind: ContainerKind::Aside,
+                    elements,
+                });
+            }
+            Some('|' | ',' | ':' | '!') => {
+                let table = self.table(delimiter, content, &attributes)?;
+                blocks.extend(self.title(&attributes));
+                blocks.push(table);
+            }
+            _ => {
+                // example, quote and open blocks
+                match style {
+                    Some("source" | "listing" | "literal") => {
+                        blocks.extend(self.title(&attributes));
+                        blocks.push(Element::CodeBlock {
+                            info: source_language(&attributes, &self.attributes),
+                            code: content.join("\n"),
+                        });
+                    }
+                    Some("verse") => {
+                        let content = content.join("\n");
+                        self.paragraph(&content, Some("verse"), attributes, blocks)?;
+                    }
+                    _ => {
+                        blocks.extend(self.title(&attributes));
+                        blocks.extend(self.blocks(content)?);
+                        // attribution of a quote
+                        if style == Some("quote") {
+                            if let Some(author) = attributes.positional.first() {
+                                blocks.push(paragraph(vec![text(format!("— {author}"))]));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn list(
+        &mut self,
+        lines: &[&str],
+        mut index: usize,
+        attributes: BlockAttributes,
+        blocks: &mut Vec<Element>,
+    ) -> anyhow::Result<usize> {
+        /// List being read, with the kind of its markers
+        struct Level {
+            kind: String,
+            list: Element,
+        }
+
+        fn close(stack: &mut Vec<Level>) -> Option<Element> {
+            let level = stack.pop()?;
+            match stack.last_mut() {
+                Some(Level {
+                    list: Element::List { elements, .. },
+                    ..
+                }) => {
+                    elements.push(ListItem {
+                        element: level.list,
+                    });
+                    None
+                }
+                _ => Some(level.list),
+            }
+        }
+
+        let mut stack: Vec<Level> = vec![];
+        let mut pending = Some(attributes);
+        while let Some(line) = lines.get(index).map(|line| line.trim_end()) {
+            // attribute lines of a nested list
+            if let Some(list) = line
+                .strip_prefix('[')
+                .and_then(|rest| rest.strip_suffix(']'))
+                .filter(|_| !line.starts_with("[["))
+            {
+                let next = lines.get(index + 1).copied().unwrap_or_default();
+                if list_item(next).is_some() || description_item(next).is_some() {
+                    let mut attributes = BlockAttributes::default();
+                    attributes.read(list);
+                    pending = Some(attributes);
+                    index += 1;
+                    continue;
+                }
+                break;
+            }
+            if line == "//" {
+                break;
+            }
+            let (kind, item, description) = if let Some((marker, rest)) = list_item(line) {
+                (marker_kind(&marker), (marker, rest.to_string()), None)
+            } else if let Some((term, marker, rest)) = description_item(line) {
+                (
+                    marker.to_string(),
+                    (marker.to_string(), rest.to_string()),
+                    Some(term.to_string()),
+                )
+            } else if line.is_empty() {
+                // the list goes on after blank lines with another item
+                let next = lines[index..]
+                    .iter()
+                    .position(|line| !line.trim().is_empty())
+                    .map(|offset| index + offset);
+                match next {
+                    Some(next)
+                        if list_item(lines[next]).is_some()
+                            || description_item(lines[next]).is_some() =>
+                    {
+                        index = next;
+                        continue;
+                    }
+                    _ => break,
+                }
+            } else {
+                break;
+            };
+
+            match stack.iter().position(|level| level.kind == kind) {
+                Some(position) => {
+                    while stack.len() > position + 1 {
+                        close(&mut stack);
+                    }
+                }
+                None => {
+                    let attributes = pending.take().unwrap_or_default();
+                    let numbered = kind.starts_with('.') || kind.starts_with('1');
+                    let (mut numbering, mut start) = if kind.starts_with('1') {
+                        numbering_of(&item.0)
+                    } else {
+                        (NumberingStyle::Decimal, 1)
+                    };
+                    if let Some(style) = attributes.style.as_deref().and_then(numbering_style) {
+                        numbering = style;
+                    }
+                    if let Some(value) = attributes
+                        .named
+                        .get("start")
+                        .and_then(|start| start.parse().ok())
+                    {
+                        start = value;
+                    }
+                    stack.push(Level {
+                        kind: kind.clone(),
+                        list: Element::List {
+                            elements: vec![],
+                            numbered,
+                            start,
+                            numbering,
+                        },
+                    });
+                }
+            }
+            pending = None;
+
+            // text of the item, up to a blank line, another item or an attached block
+            let mut content = vec![item.1];
+            index += 1;
+            while let Some(line) = lines.get(index).map(|line| line.trim_end()) {
+                if line.is_empty()
+                    || line == "+"
+                    || list_item(line).is_some()
+                    || description_item(line).is_some()
+                    || block_delimiter(line).is_some()
+                    || (line.starts_with('[') && line.ends_with(']'))
+                {
+                    break;
+                }
+                content.push(line.trim_start().to_string());
+                index += 1;
+            }
+            let mut item_blocks = vec![];
+            let mut elements = self.inline(&content.join("\n"))?;
+            if let Some(term) = description {
+                let mut out = Inline::default();
+                out.push(Element::Styled {
+                    style: TextStyle::Strong,
+                    elements: self.inline(&term)?,
+                });
+                if !elements.is_empty() {
+                    out.push_str(" ");
+                }
+                out.extend(elements);
+                elements = out.finish();
+            }
+            item_blocks.push(paragraph(elements));
+            // blocks attached with `+`
+            while lines.get(index).map(|line| line.trim_end()) == Some("+") {
+                index += 1;
+                let start = index;
+                let end = match lines.get(start).and_then(|line| block_delimiter(line)) {
+                    Some(delimiter) => lines[start + 1..]
+                        .iter()
+                        .position(|line| line.trim_end() == delimiter)
+                        .map_or(lines.len(), |end| start + end + 2),
+                    None => {
+                        // block attribute and title lines go with the block
+                        let mut first = start;
+                        while lines.get(first).is_some_and(|line| {
+                            (line.starts_with('[') && line.trim_end().ends_with(']'))
+                                || (line.starts_with('.') && list_item(line).is_none())
+                        }) {
+                            first += 1;
+                        }
+                        match lines.get(first).and_then(|line| block_delimiter(line)) {
+                            Some(delimiter) => lines[first + 1..]
+                                .iter()
+                                .position(|line| line.trim_end() == delimiter)
+                                .map_or(lines.len(), |end| first + end + 2),
+                            None => paragraph_end(lines, first),
+                        }
+                    }
+                };
+                let end = end.min(lines.len());
+                item_blocks.extend(self.blocks(&lines[start..end])?);
+                index = end;
+            }
+            let Some(Level {
+                list: Element::List { elements, .. },
+                ..
+            }) = stack.last_mut()
+            else {
+                break;
+            };
+            elements.extend(
+                join_paragraphs(item_blocks)
+                    .into_iter()
+                    .map(|element| ListItem { element }),
+            );
+        }
+        while !stack.is_empty() {
+            if let Some(list) = close(&mut stack) {
+                blocks.push(list);
+            }
+        }
+        Ok(index)
+    }
+
+    fn table(
+        &mut self,
+        delimiter: &str,
+        content: &[&str],
+        attributes: &BlockAttributes,
+    ) -> anyhow::Result<Element> {
+        let separator = attributes
+            .named
+            .get("separator")
+            .and_then(|separator| separator.chars().next())
+            .unwrap_or(match delimiter.chars().next() {
+                Some(',') => ',',
+                Some(':') => ':',
+                Some('!') => '!',
+                _ => '|',
+            });
+        let mut columns = attributes
+            .named
+            .get("cols")
+            .map(|cols| column_specs(cols))
+            .unwrap_or_default();
+        let first_line = content
+            .iter()
+            .position(|line| !line.trim().is_empty())
+            .unwrap_or_default();
+        let implicit_header = content
+            .get(first_line + 1)
+            .is_some_and(|line| line.trim().is_empty())
+            && content.len() > first_line + 2;
+
+        let mut cells: Vec<(CellSpec, String)> = vec![];
+        if separator == ',' || separator == ':' {
+            // delimited data, a row per line
+            for line in content.iter().filter(|line| !line.trim().is_empty()) {
+                let values: Vec<&str> = line.split(separator).collect();
+                if columns.is_empty() {
+                    columns = vec![(1.0, None); values.len()];
+                }
+                cells.extend(
+                    values
+                        .into_iter()
+                        .map(|value| (CellSpec::default(), unquote(value))),
+                );
+            }
+        } else {
+            let mut first_line_cells = 0;
+            for (number, line) in content.iter().enumerate() {
+                let line = line.trim_end();
+                let mut rest = line;
+                let mut start_of_line = true;
+                loop {
+                    let Some(position) = find_unescaped(rest, separator) else {
+                        if let Some((_, cell)) = cells.last_mut() {
+                            if !start_of_line || !cell.is_empty() {
+                                cell.push('\n');
+                            }
+                            cell.push_str(rest);
+                        }
+                        break;
+                    };
+                    let before = &rest[..position];
+                    // the specifier of the cell is the last word before the separator
+                    let (text, spec) = match before.rfind(char::is_whitespace) {
+                        Some(space) => (&before[..space], &before[space + 1..]),
+                        None => ("", before),
+                    };
+                    let (text, spec) = match CellSpec::parse(spec) {
+                        Some(spec) => (text, spec),
+                        None => (before, CellSpec::default()),
+                    };
+                    if let Some((_, cell)) = cells.last_mut() {
+                        if !start_of_line && !cell.is_empty() {
+                            cell.push(' ');
+                        }
+                        if start_of_line && !text.is_empty() {
+                            cell.push('\n');
+                        }
+                        cell.push_str(text);
+                    }
+                    cells.push((spec, String::new()));
+                    if number == first_line {
+                        first_line_cells += spec.colspan * spec.duplicate;
+                    }
+                    rest = &rest[position + separator.len_utf8()..];
+                    start_of_line = false;
+                }
+            }
+            if columns.is_empty() {
+                columns = vec![(1.0, None); first_line_cells.max(1)];
+            }
+        }
+
+        let header = (attributes.has_option("header") || implicit_header)
+            && !attributes.has_option("noheader");
+        let mut rows: Vec<Vec<Element>> = vec![];
+        let mut row: Vec<Element> = vec![];
+        // rows still covered by the cells above, per column
+        let mut covered = vec![0usize; columns.len()];
+        for (spec, content) in cells {
+            for _ in 0..spec.duplicate {
+                while row.len() < columns.len() && covered[row.len()] > 0 {
+                    covered[row.len()] -= 1;
+                    row.push(text(""));
+                }
+                let style = spec
+                    .style
+                    .or(columns.get(row.len()).and_then(|column| column.1));
+                let element = self.cell(&content, style)?;
+                for offset in 0..spec.colspan {
+                    if let Some(covered) = covered.get_mut(row.len()) {
+                        *covered = spec.rowspan - 1;
+                    }
+                    row.push(if offset == 0 {
+                        element.clone()
+                    } else {
+                        text("")
+                    });
+                }
+                if row.len() >= columns.len() {
+                    rows.push(std::mem::take(&mut row));
+                }
+            }
+        }
+        if !row.is_empty() {
+            row.resize(columns.len(), text(""));
+            rows.push(row);
+        }
+
+        let mut rows = rows.into_iter();
+        let headers = if header {
+            rows.next()
+                .unwrap_or_default()
+                .into_iter()
+                .zip(&columns)
+                .map(|(element, (width, _))| TableHeader {
+                    element,
+                    width: if attributes.named.contains_key("cols") {
+                        *width
+                    } else {
+                        30.
+                    },
+                })
+                .collect()
+        } else {
+            vec![]
+        };
+        Ok(Element::Table {
+            headers,
+            rows: rows
+                .map(|cells| TableRow {
+                    cells: cells
+                        .into_iter()
+                        .map(|element| TableCell { element })
+                        .collect(),
+                })
+                .collect(),
+        })
+    }
+
+    /// Content of a table cell, read as blocks for the AsciiDoc style `a` and as paragraphs
+    /// otherwise
+    fn cell(&mut self, content: &str, style: Option<char>) -> anyhow::Result<Element> {
+        let content = content.trim();
+        let blocks = match style {
+            Some('a') => {
+                let lines: Vec<&str> = content.lines().collect();
+                self.blocks(&lines)?
+            }
+            Some('l') => vec![Element::CodeBlock {
+                info: String::new(),
+                code: content.to_string(),
+            }],
+            _ => {
+                let mut blocks = vec![];
+                for paragraph_text in content.split("\n\n") {
+                    let elements = self.inline(paragraph_text.trim())?;
+                    if !elements.is_empty() {
+                        blocks.push(paragraph(elements));
+                    }
+                }
+                blocks
+            }
+        };
+        let mut blocks = join_paragraphs(blocks);
+        Ok(match blocks.len() {
+            0 => text(""),
+            1 => blocks.remove(0),
+            _ => Element::Container {
+                kind: ContainerKind::Div,
+                elements: blocks,
+            },
+        })
+    }
+
+    /// Image of an image macro, read from the `imagesdir` folder
+    fn image(
+        &mut self,
+        target: &str,
+        attributes: &str,
+        title: Option<&str>,
+    ) -> anyhow::Result<Option<Element>> {
+        let mut list = BlockAttributes::default();
+        list.read(attributes);
+        let alt = list.style.clone().unwrap_or_default();
+        let width = list
+            .named
+            .get("width")
+            .cloned()
+            .or_else(|| list.positional.first().cloned());
+        let height = list
+            .named
+            .get("height")
+            .cloned()
+            .or_else(|| list.positional.get(1).cloned());
+        let path = match self.attributes.get("imagesdir") {
+            Some(folder)
+                if !folder.is_empty() && !target.contains("://") && !target.starts_with('/') =>
+            {
+                format!("{}/{}", folder.trim_end_matches('/'), target)
+            }
+            _ => target.to_string(),
+        };
+        let bytes = (self.image_loader)(&path)?;
+        let mut image = ImageData::new(
+            bytes,
+            title.unwrap_or_default().to_string(),
+            alt,
+            path,
+            String::new(),
+            ImageDimension { width, height },
+        );
+        if let Some(title) = list.named.get("title") {
+            image.set_image_title(title);
+        }
+        let image = Element::Image(image);
+        Ok(Some(match list.named.get("link") {
+            Some(url) => Element::Hyperlink {
+                elements: vec![image],
+                url: url.clone(),
+                alt: String::new(),
+                size: 14,
+            },
+            None => image,
+        }))
+    }
+
+    /// Inline elements of text with AsciiDoc markup
+    fn inline(&mut self, source: &str) -> anyhow::Result<Vec<Element>> {
+        let mut out = Inline::default();
+        // role set before marks (`[.line-through]#`)
+        let mut marked_role: Option<String> = None;
+        let chars: Vec<(usize, char)> = source.char_indices().collect();
+        let at = |position: usize| chars.get(position).map(|(_, c)| *c);
+        let offset = |position: usize| {
+            chars
+                .get(position)
+                .map_or(source.len(), |(index, _)| *index)
+        };
+        let is_word = |position: Option<usize>| {
+            position
+                .and_then(|position| chars.get(position))
+                .is_some_and(|(_, c)| c.is_alphanumeric())
+        };
+        let mut position = 0;
+        while let Some(c) = at(position) {
+            let rest = &source[offset(position)..];
+            let previous = position.checked_sub(1);
+            match c {
+                '\\' => {
+                    let next = at(position + 1);
+                    if next.is_some_and(|c| "*_`#^~+{[]<\\|:".contains(c))
+                        || URL_SCHEMES
+                            .iter()
+                            .any(|scheme| rest[1..].starts_with(scheme))
+                        || rest[1..].starts_with(|c: char| c.is_ascii_alphabetic())
+                            && rest[1..].split_once(':').is_some_and(|(name, after)| {
+                                name.chars().all(|c| c.is_ascii_alphanumeric())
+                                    && after.contains('[')
+                            })
+                    {
+                        if let Some(next) = next {
+                            out.push_char(next);
+                        }
+                        position += 2;
+                    } else {
+                        out.push_char('\\');
+                        position += 1;
+                    }
+                    continue;
+                }
+                '{' => {
+                    if let Some(end) = rest.find('}') {
+                        let name = &rest[1..end];
+                        if !name.is_empty()
+                            && name
+                                .chars()
+                                .all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+                        {
+                            match self.attribute(&name.to_lowercase()) {
+                                Some(value) => out.push_str(&value),
+                                None => out.push_str(&rest[..=end]),
+                            }
+                            position += rest[..=end].chars().count();
+                            continue;
+                        }
+                    }
+                }
+                ' ' if rest.starts_with(" +\n") || rest == " +" => {
+                    out.push(Element::LineBreak);
+                    position += 3;
+                    while at(position).is_some_and(|c| c == ' ') {
+                        position += 1;
+                    }
+                    continue;
+                }
+                '\n' => {
+                    out.push_char(' ');
+                    position += 1;
+                    continue;
+                }
+                '*' | '_' | '`' | '#' => {
+                    let double = at(position + 1) == Some(c);
+                    let marks = if double { 2 } else { 1 };
+                    let closing: String = std::iter::repeat_n(c, marks).collect();
+                    let constrained_start = !double
+                        && !is_word(previous)
+                        && at(position + 1).is_some_and(|c| !c.is_whitespace());
+                    if double || constrained_start {
+                        let start = offset(position + marks);
+                        if let Some(end) = find_closing(source, start, &closing, !double) {
+                            let content = &source[start..end];
+                            let role = marked_role.take();
+                            let element = match c {
+                                '*' => Element::Styled {
+                                    style: TextStyle::Strong,
+                                    elements: self.inline(content)?,
+                                },
+                                '_' => Element::Styled {
+                                    style: TextStyle::Emphasis,
+                                    elements: self.inline(content)?,
+                                },
+                                '`' => Element::InlineCode {
+                                    text: literal_code(content, |content| {
+                                        plain_text(&self.inline(content).unwrap_or_default())
+                                    }),
+                                },
+                                _ => match role.as_deref() {
+                                    Some("line-through" | "strike" | "del") => Element::Styled {
+                                        style: TextStyle::Strikethrough,
+                                        elements: self.inline(content)?,
+                                    },
+                                    _ => {
+                                        let elements = self.inline(content)?;
+                                        out.extend(elements);
+                                        position = source[..end + closing.len()].chars().count();
+                                        continue;
+                                    }
+                                },
+                            };
+                            out.push(element);
+                            position = source[..end + closing.len()].chars().count();
+                            continue;
+                        }
+                    }
+                }
+                '+' => {
+                    // passthroughs
+                    for marks in ["+++", "++", "+"] {
+                        if let Some(after) = rest.strip_prefix(marks) {
+                            if let Some(end) = after.find(marks).filter(|end| *end > 0) {
+                                out.push_str(&after[..end]);
+                                position += (marks.len() * 2) + after[..end].chars().count();
+                                break;
+                            }
+                        }
+                    }
+                    if offset(position) != source.len() - rest.len() {
+                        continue;
+                    }
+                }
+                '^' | '~' => {
+                    // superscript and subscript, written as text
+                    if let Some(end) = rest[1..].find(c) {
+                        let content = &rest[1..end + 1];
+                        if !content.is_empty() && !content.contains(char::is_whitespace) {
+                            let elements = self.inline(content)?;
+                            out.extend(elements);
+                            position += content.chars().count() + 2;
+                            continue;
+                        }
+                    }
+                }
+                '[' => {
+                    if let Some(anchor) = rest
+                        .strip_prefix("[[")
+                        .and_then(|after| after.split_once("]]"))
+                        .map(|(anchor, _)| anchor)
+                        .filter(|anchor| !anchor.contains(char::is_whitespace))
+                    {
+                        let anchor = anchor.trim_start_matches('[');
+                        out.push(Element::Bookmark {
+                            id: anchor.split(',').next().unwrap_or_default().to_string(),
+                            title: String::new(),
+                        });
+                        let length = rest.find("]]").map_or(0, |end| end + 2);
+                        let length = length + usize::from(rest[length..].starts_with(']'));
+                        position += rest[..length].chars().count();
+                        continue;
+                    }
+                    // role of the text in marks: `[.line-through]#text#`
+                    if let Some((role, after)) = rest[1..].split_once(']') {
+                        if after.starts_with('#') && !role.contains(char::is_whitespace) {
+                            marked_role = Some(role.trim_start_matches('.').to_string());
+                            position += role.chars().count() + 2;
+                            continue;
+                        }
+                    }
+                }
+                '<' if rest.starts_with("<<") => {
+                    if let Some(end) = rest.find(">>") {
+                        let reference = &rest[2..end];
+                        let (target, label) = match reference.split_once(',') {
+                            Some((target, label)) => (target.trim(), label.trim()),
+                            None => (reference.trim(), ""),
+                        };
+                        let elements = self.inline(label)?;
+                        out.push(Element::Hyperlink {
+                            elements,
+                            url: format!("#{}", target.rsplit('#').next().unwrap_or(target)),
+                            alt: String::new(),
+                            size: 14,
+                        });
+                        position += rest[..end + 2].chars().count();
+                        continue;
+                    }
+                }
+                c if c.is_ascii_alphabetic() && !is_word(previous) => {
+                    if let Some((element, length)) = self.inline_macro(rest)? {
+                        out.extend(element);
+                        position += rest[..length].chars().count();
+                        continue;
+                    }
+                }
+                _ => {}
+            }
+            out.push_char(c);
+            position += 1;
+        }
+        Ok(out.finish())
+    }
+
+    /// Element of the inline macro or URL at the start of `rest`, with the length read
+    fn inline_macro(&mut self, rest: &str) -> anyhow::Result<Option<(Vec<Element>, usize)>> {
+        if let Some(scheme) = URL_SCHEMES.iter().find(|scheme| rest.starts_with(*scheme)) {
+            let end = rest
+                .find(|c: char| c.is_whitespace() || c == '[' || c == '<' || c == '>')
+                .unwrap_or(rest.len());
+            let mut url = &rest[..end];
+            let mut length = end;
+            let label = if rest[end..].starts_with('[') {
+                let (label, label_length) = bracketed(&rest[end..]);
+                length += label_length;
+                Some(label)
+            } else {
+                // trailing punctuation is not part of the URL
+                url = url.trim_end_matches(['.', ',', ';', ':', '!', '?', ')', '\'', '"']);
+                length = url.len();
+                None
+            };
+            if url.len() <= scheme.len() {
+                return Ok(None);
+            }
+            let elements = match label {
+                Some(label) => self.inline(&label)?,
+                None => vec![text(url.strip_prefix("mailto:").unwrap_or(url))],
+            };
+            return Ok(Some((
+                vec![Element::Hyperlink {
+                    elements,
+                    url: url.to_string(),
+                    alt: String::new(),
+                    size: 14,
+                }],
+                length,
+            )));
+        }
+        let Some((name, after)) = rest.split_once(':') else {
+            return Ok(None);
+        };
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Ok(None);
+        }
+        let Some(bracket) = after.find('[') else {
+            return Ok(None);
+        };
+        let target = &after[..bracket];
+        if target.contains(char::is_whitespace) || after.starts_with(':') {
+            return Ok(None);
+        }
+        let (content, content_length) = bracketed(&after[bracket..]);
+        let length = name.len() + 1 + bracket + content_length;
+        let target = self.substitute_attributes(target);
+        let elements = match name {
+            "footnote" | "footnoteref" => {
+                let content = content
+                    .split_once(',')
+                    .map_or(content.as_str(), |(_, content)| {
+                        if name == "footnoteref" {
+                            content
+                        } else {
+                            ""
+                        }
+                    });
+                let content = if name == "footnote" {
+                    bracketed(&after[bracket..]).0
+                } else {
+                    content.to_string()
+                };
+                vec![Element::Footnote {
+                    elements: self.inline(&content)?,
+                }]
+            }
+            "link" | "mailto" => {
+                let url = target.trim_start_matches("++").trim_end_matches("++");
+                let url = if name == "mailto" {
+                    format!("mailto:{url}")
+                } else {
+                    url.to_string()
+                };
+                let label = content
+                    .split(",")
+                    .next()
+                    .unwrap_or_default()
+                    .trim_end_matches('^');
+                let elements = if label.is_empty() {
+                    vec![text(url.strip_prefix("mailto:").unwrap_or(&url))]
+                } else {
+                    self.inline(label)?
+                };
+                vec![Element::Hyperlink {
+                    elements,
+                    url,
+                    alt: String::new(),
+                    size: 14,
+                }]
+            }
+            "xref" => vec![Element::Hyperlink {
+                elements: self.inline(&content)?,
+                url: format!("#{}", target.rsplit('#').next().unwrap_or(&target)),
+                alt: String::new(),
+                size: 14,
+            }],
+            "anchor" => vec![Element::Bookmark {
+                id: target,
+                title: String::new(),
+            }],
+            "image" => self.image(&target, &content, None)?.into_iter().collect(),
+            "stem" | "latexmath" => vec![Element::Math {
+                tex: content.trim().to_string(),
+                display: false,
+            }],
+            "asciimath" | "kbd" => vec![Element::InlineCode {
+                text: content.trim().to_string(),
+            }],
+            "pass" => vec![text(content)],
+            "btn" => self.inline(&content)?,
+            "menu" => {
+                let mut path = vec![target.to_string()];
+                path.extend(
+                    content
+                        .split('>')
+                        .map(str::trim)
+                        .filter(|item| !item.is_empty())
+                        .map(str::to_string),
+                );
+                vec![text(path.join(" > "))]
+            }
+            "indexterm" | "indexterm2" => {
+                if name == "indexterm2" {
+                    self.inline(&content)?
+                } else {
+                    vec![]
+                }
+            }
+            _ => return Ok(None),
+        };
+        Ok(Some((elements, length)))
+    }
+
+    /// Resolves the links to section ids to the anchors of their headers, and labels the
+    /// cross references without text with the section titles
+    fn resolve_references(&self, elements: &mut [Element]) {
+        for element in elements {
+            if let Element::Hyperlink { elements, url, .. } = element {
+                if let Some(target) = url.strip_prefix('#') {
+                    let section = self.sections.get(target);
+                    if elements.is_empty() {
+                        let label = match section {
+                            Some((_, title)) => title.clone(),
+                            None => format!("[{target}]"),
+                        };
+                        elements.push(text(label));
+                    }
+                    if let Some((anchor, _)) = section {
+                        *url = format!("#{anchor}");
+                    }
+                }
+            }
+            for child in element.children_mut() {
+                self.resolve_references(std::slice::from_mut(child));
+            }
+        }
+    }
+}
+
+/// Index of the end of the paragraph starting at `index`: a blank line or a delimiter
+fn paragraph_end(lines: &[&str], index: usize) -> usize {
+    let mut end = index + 1;
+    while let Some(line) = lines.get(end) {
+        let line = line.trim_end();
+        if line.trim().is_empty() || block_delimiter(line).is_some() {
+            break;
+        }
+        end += 1;
+    }
+    end
+}
+
+/// Level and title of a section title line (`== Title`)
+fn section_title(line: &str) -> Option<(usize, &str)> {
+    let marks = line.chars().take_while(|&c| c == '=' || c == '#').count();
+    let first = line.chars().next()?;
+    if marks == 0 || marks > 6 || line[..marks].chars().any(|c| c != first) {
+        return None;
+    }
+    let title = line[marks..].strip_prefix(' ')?.trim();
+    let title = title.trim_end_matches(first).trim_end();
+    (!title.is_empty()).then_some((marks.saturating_sub(1).max(1), title))
+}
+
+/// Language of a source block: its second positional attribute or `language`, and else the
+/// `source-language` document attribute
+fn source_language(attributes: &BlockAttributes, document: &HashMap<String, String>) -> String {
+    let style = attributes.style.as_deref();
+    attributes
+        .positional
+        .first()
+        .filter(|_| style.is_none_or(|style| style == "source"))
+        .or_else(|| attributes.named.get("language"))
+        .or_else(|| {
+            (style == Some("source"))
+                .then(|| document.get("source-language"))
+                .flatten()
+        })
+        .cloned()
+        .unwrap_or_default()
+}
+
+/// Text of code in backticks, its `+` passthrough taken literally
+fn literal_code(content: &str, text: impl FnOnce(&str) -> String) -> String {
+    match content
+        .strip_prefix('+')
+        .and_then(|content| content.strip_suffix('+'))
+    {
+        Some(literal) => literal.to_string(),
+        None => text(content),
+    }
+}
+
+/// Content of the bracketed text at the start of `text`, its escaped brackets unescaped,
+/// with the length read
+fn bracketed(text: &str) -> (String, usize) {
+    let mut content = String::new();
+    let mut depth = 0;
+    let mut chars = text.char_indices().skip(1);
+    while let Some((index, c)) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some((_, ']')) => content.push(']'),
+                Some((_, next)) => {
+                    content.push('\\');
+                    content.push(next);
+                }
+                None => content.push('\\'),
+            },
+            '[' => {
+                depth += 1;
+                content.push(c);
+            }
+            ']' if depth == 0 => return (content, index + 1),
+            ']' => {
+                depth -= 1;
+                content.push(c);
+            }
+            c => content.push(c),
+        }
+    }
+    (content, text.len())
+}
+
+/// Byte index of the first separator not escaped with a backslash
+fn find_unescaped(text: &str, separator: char) -> Option<usize> {
+    let mut escaped = false;
+    for (index, c) in text.char_indices() {
+        if c == separator && !escaped {
+            return Some(index);
+        }
+        escaped = c == '\\' && !escaped;
+    }
+    None
+}
+
+/// Byte index of the closing marks of formatted text starting at `start`, constrained marks
+/// being followed by a character other than a letter or a digit
+fn find_closing(source: &str, start: usize, closing: &str, constrained: bool) -> Option<usize> {
+    let mut search = start;
+    while let Some(found) = source[search..].find(closing) {
+        let end = search + found;
+        let after = source[end + closing.len()..].chars().next();
+        let before = source[..end].chars().next_back();
+        let valid = end > start
+            && (!constrained
+                || (before.is_some_and(|c| !c.is_whitespace())
+                    && after.is_none_or(|c| !c.is_alphanumeric())));
+        if valid && !source[..end].ends_with('\\') {
+            return Some(end);
+        }
+        search = end + closing.len();
+    }
+    None
+}
+
+impl TransformerTrait for Transformer {
+    /// Document of an AsciiDoc source, its images read from the current directory
+    fn parse(document: &Bytes) -> crate::Result<Document> {
+        Transformer::parse_with_loader(document, disk_image_loader("."))
+    }
+
+    /// AsciiDoc source of the document, its images saved to the current directory
+    fn generate(document: &Document) -> crate::Result<Bytes> {
+        Transformer::generate_with_saver(document, disk_image_saver("."))
+    }
+}
+
+impl TransformerWithImageLoaderSaverTrait for Transformer {
+    /// Document of an AsciiDoc source, its images read with `image_loader` from the targets
+    /// of the image macros under `imagesdir`
+    fn parse_with_loader<F>(document: &Bytes, image_loader: F) -> crate::Result<Document>
+    where
+        F: Fn(&str) -> anyhow::Result<Bytes>,
+    {
+        Transformer::parse_source(document, &image_loader_errors(image_loader))
+            .map_err(crate::Error::parse_error)
+    }
+
+    /// AsciiDoc source of the document, its images saved with `image_saver` under the names
+    /// of their image macros
+    fn generate_with_saver<F>(document: &Document, image_saver: F) -> crate::Result<Bytes>
+    where
+        F: Fn(&Bytes, &str) -> anyhow::Result<()>,
+    {
+        Transformer::generate_with_image_saver(document, image_saver)
+            .map_err(crate::Error::generation_error)
+    }
+}
+
+impl Transformer {
+    fn parse_source<F>(source: &Bytes, image_loader: &F) -> anyhow::Result<Document>
+    where
+        F: Fn(&str) -> anyhow::Result<Bytes>,
+    {
+        let source = std::str::from_utf8(source)?;
+        let source = source.strip_prefix('\u{feff}').unwrap_or(source);
+        let lines: Vec<&str> = source.lines().collect();
+        let mut parser = Parser {
+            image_loader,
+            attributes: HashMap::new(),
+            metadata: Metadata::default(),
+            sections: HashMap::new(),
+        };
+        let start = parser.header(&lines)?;
+        let mut elements = parser.blocks(&lines[start..])?;
+        parser.resolve_references(&mut elements);
+        let mut document = Document::new(elements);
+        document.metadata = parser.metadata;
+        Ok(document)
+    }
+
+    fn generate_with_image_saver<F>(document: &Document, image_saver: F) -> anyhow::Result<Bytes>
+    where
+        F: Fn(&Bytes, &str) -> anyhow::Result<()>,
+    {
+        let document = &document.resolve_fields().without_drawings();
+        let mut writer = Writer {
+            image_saver: &image_saver,
+            image_count: 0,
+            link_targets: document.link_targets(),
+            block_depth: 0,
+            after_list: false,
+            math: false,
+            toc_levels: None,
+            adoc: String::new(),
+        };
+        for band in &document.bands {
+            if !matches!(band, Band::PageHeader(_) | Band::PageFooter(_)) {
+                for element in band.elements() {
+                    writer.block(element)?;
+                }
+            }
+        }
+
+        let metadata = &document.metadata;
+        let mut header = String::new();
+        match &metadata.title {
+            Some(title) => {
+                let _ = writeln!(header, "= {}", escape(title));
+                if let Some(author) = &metadata.author {
+                    let _ = writeln!(header, "{}", author.replace(',', ";"));
+                }
+            }
+            None => {
+                if let Some(author) = &metadata.author {
+                    let _ = writeln!(header, ":author: {author}");
+                }
+            }
+        }
+        if let Some(date) = &metadata.date {
+            let _ = writeln!(header, ":revdate: {date}");
+        }
+        if let Some(description) = &metadata.description {
+            let _ = writeln!(header, ":description: {}", description.replace('\n', " "));
+        }
+        if !metadata.keywords.is_empty() {
+            let _ = writeln!(header, ":keywords: {}", metadata.keywords.join(", "));
+        }
+        if writer.math {
+            header.push_str(":stem: latexmath\n");
+        }
+        if let Some(levels) = writer.toc_levels {
+            let _ = writeln!(header, ":toc: macro\n:toclevels: {levels}");
+        }
+        if !header.is_empty() {
+            header.push('\n');
+        }
+        let adoc = header + writer.adoc.trim_end() + "\n";
+        Ok(Bytes::from(adoc))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+    use crate::core::tests::{init_logger, table_texts};
+    use crate::core::TransformerTrait;
+    use crate::markdown;
+
+    #[test]
+    fn test_generate() -> anyhow::Result<()> {
+        init_logger();
+        let markdown = "# Results *and* costs\n\nSee [the end](#the-end) and \
+            [the site](https://example.com/a_b).[^1]\n\n\
+            1. One\n   - Nested *item*\n2. Two\n\n\
+            | Name | Value |\n|------|-------|\n| a_b | 1 \\| 2 |\n\n\
+            ```rust\nlet x = 2 * 3;\n```\n\n## The end\n\n`a+b` snake_case * 2 **done**\n\n\
+            [^1]: A note.\n";
+        let mut document = markdown::Transformer::parse(&Bytes::from(markdown))?;
+        let png = Bytes::from(std::fs::read("test/data/small.png")?);
+        if let Some(Band::Detail(elements)) = document.bands.last_mut() {
+            elements.push(Element::Image(ImageData::new(
+                png.clone(),
+                "Logo".to_string(),
+                "The logo".to_string(),
+                "png".to_string(),
+                String::new(),
+                ImageDimension {
+                    width: Some("50%".to_string()),
+                    height: None,
+                },
+            )));
+            elements.push(Element::admonition(
+                "warning",
+                None,
+                vec![
+                    paragraph(vec![text("Hot.")]),
+                    Element::CodeBlock {
+                        info: String::new(),
+                        code: "touch".to_string(),
+                    },
+                ],
+            ));
+        }
+        document.metadata.title = Some("Report".to_string());
+        document.metadata.author = Some("Ada, Alan".to_string());
+
+        let saved = RefCell::new(vec![]);
+        let adoc = Transformer::generate_with_saver(&document, |bytes, name| {
+            saved.borrow_mut().push((name.to_string(), bytes.clone()));
+            Ok(())
+        })?;
+        let adoc = std::str::from_utf8(&adoc)?;
+        assert!(adoc.starts_with("= Report\nAda; Alan\n\n"));
+        assert!(adoc.contains("== Results and costs\n\n"));
+        assert!(adoc.contains("[[the-end]]\n=== The end\n\n"));
+        assert!(adoc.contains("See xref:the-end[the end] and https://example.com/a_b[the site]."));
+        assert!(adoc.contains("footnote:[A note.]"));
+        assert!(adoc.contains(". One\n** Nested __item__\n. Two\n"));
+        assert!(
+            adoc.contains("[%header,cols=\"30,30\"]\n|===\n|Name |Value\n\n|a_b |1 \\| 2\n|===\n")
+        );
+        assert!(adoc.contains("[source,rust]\n----\nlet x = 2 * 3;\n----\n"));
+        assert!(adoc.contains("``+a+b+`` snake_case {asterisk} 2 **done**"));
+        assert!(adoc.contains(".Logo\nimage::image1.png[The logo,width=50%]\n"));
+        assert!(adoc.contains("[WARNING]\n====\nHot.\n\n----\ntouch\n----\n====\n"));
+        assert_eq!(
+            saved.borrow().as_slice(),
+            &[("image1.png".to_string(), png)]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse() -> anyhow::Result<()> {
+        init_logger();
+        let source =
+            "= User Guide\nAda Lovelace <ada@example.com>; Alan Turing\nv1.2, 2024-05-01\n\
+            :description: How to use it\n:product: Shiva\n\n\
+            [[start]]\n== Getting *started*\n\n\
+            Install {product} with `cargo install`, see <<usage>> and\n\
+            https://example.com[the site].footnote:[Or build it.] +\nDone.\n\n\
+            NOTE: Needs Rust 1.70.\n\n\
+            [TIP]\n====\nUse the _CLI_.\n====\n\n\
+            * One\n** Nested\n* Two\n+\n----\nshiva a b\n----\n\n\
+            [lowerroman,start=2]\n. First\n. Second\n\n\
+            [#usage]\n== Usage\n\n\
+            .Options\n[cols=\"1,2a\",options=\"header\"]\n|===\n|Name |Meaning\n\n\
+            |a\n|* list\n* cell\n\n2+|Both\n|===\n\n\
+            [source,rust]\n----\nfn main() {}\n----\n\n\
+            .Logo\nimage::small.png[Logo,50]\n\n\
+            CPU:: the processor\n";
+        let requested = RefCell::new(vec![]);
+        let document = Transformer::parse_with_loader(&Bytes::from(source), |path| {
+            requested.borrow_mut().push(path.to_string());
+            Ok(Bytes::from(std::fs::read(format!("test/data/{path}"))?))
+        })?;
+        assert_eq!(document.metadata.title.as_deref(), Some("User Guide"));
+        assert_eq!(
+            document.metadata.author.as_deref(),
+            Some("Ada Lovelace, Alan Turing")
+        );
+        assert_eq!(document.metadata.date.as_deref(), Some("2024-05-01"));
+        assert_eq!(
+            document.metadata.description.as_deref(),
+            Some("How to use it")
+        );
+        assert_eq!(requested.borrow().as_slice(), &["small.png".to_string()]);
+
+        let elements = document.bands[0].elements();
+        assert_eq!(
+            elements[0],
+            Element::Header {
+                level: 1,
+                text: "Getting started".to_string()
+            }
+        );
+        let Element::Paragraph {
+            elements: inline, ..
+        } = &elements[1]
+        else {
+            panic!("paragraph expected: {:?}", elements[1]);
+        };
+        assert_eq!(inline[0], text("Install Shiva with "));
+        assert_eq!(
+            inline[1],
+            Element::InlineCode {
+                text: "cargo install".to_string()
+            }
+        );
+        assert_eq!(
+            inline[3],
+            Element::Hyperlink {
+                elements: vec![text("Usage")],
+                url: "#usage".to_string(),
+                alt: String::new(),
+                size: 14,
+            }
+        );
+        assert!(
+            matches!(&inline[5], Element::Hyperlink { url, .. } if url == "https://example.com")
+        );
+        assert!(matches!(&inline[7], Element::Footnote { .. }));
+        assert_eq!(inline[8], Element::LineBreak);
+        assert_eq!(
+            admonition_blocks(&elements[2]).map(|(style, _, blocks)| (style, blocks.to_vec())),
+            Some(("NOTE", vec![paragraph(vec![text("Needs Rust 1.70.")])]))
+        );
+        assert_eq!(
+            admonition_blocks(&elements[3]).map(|(style, ..)| style),
+            Some("TIP")
+        );
+        let Element::List {
+            elements: items,
+            numbered: false,
+            ..
+        } = &elements[4]
+        else {
+            panic!("list expected: {:?}", elements[4]);
+        };
+        assert_eq!(items.len(), 4);
+        assert!(matches!(&items[1].element, Element::List { elements, .. } if elements.len() == 1));
+        assert_eq!(
+            items[3].element,
+            Element::CodeBlock {
+                info: String::new(),
+                code: "shiva a b".to_string()
+            }
+        );
+        assert!(matches!(
+            &elements[5],
+            Element::List {
+                numbered: true,
+                start: 2,
+                numbering: NumberingStyle::LowerRoman,
+                ..
+            }
+        ));
+        assert!(matches!(&elements[6], Element::Header { level: 1, .. }));
+        assert_eq!(
+            elements[7],
+            paragraph(vec![Element::Styled {
+                style: TextStyle::Strong,
+                elements: vec![text("Options")]
+            }])
+        );
+        let Element::Table { headers, rows } = &elements[8] else {
+            panic!("table expected: {:?}", elements[8]);
+        };
+        assert_eq!(headers.len(), 2);
+        assert_eq!(headers[1].width, 2.);
+        assert_eq!(rows.len(), 2);
+        assert!(matches!(&rows[0].cells[1].element, Element::List { .. }));
+        assert_eq!(rows[1].cells[0].element, text("Both"));
+        assert_eq!(
+            elements[9],
+            Element::CodeBlock {
+                info: "rust".to_string(),
+                code: "fn main() {}".to_string()
+            }
+        );
+        let Element::Image(image) = &elements[10] else {
+            panic!("image expected: {:?}", elements[10]);
+        };
+        assert_eq!(image.title(), "Logo");
+        assert_eq!(image.size().width.as_deref(), Some("50"));
+        assert!(matches!(&elements[11], Element::List { .. }));
+        Ok(())
+    }
+
+    #[test]
+    fn test_round_trip() -> anyhow::Result<()> {
+        init_logger();
+        let source =
+            "= Notes\n\n== Plan\n\nSome *bold* and _odd_ text with a_b, 2 * 3 and {x}.\n\n\
+            [CAUTION]\n====\nFirst.\n\nSecond.\n====\n\n\
+            . One\n.. Two\n";
+        let document = Transformer::parse_with_loader(&Bytes::from(source), |_| Ok(Bytes::new()))?;
+        let generated = Transformer::generate_with_saver(&document, |_, _| Ok(()))?;
+        let reparsed = Transformer::parse_with_loader(&generated, |_| Ok(Bytes::new()))?;
+        assert_eq!(reparsed.bands, document.bands);
+        assert_eq!(reparsed.metadata, document.metadata);
+        Ok(())
+    }
+
+    #[test]
+    fn test_tables() -> anyhow::Result<()> {
+        let source = r#"[cols="1,1,1"]
+|===
+|Item |Note |Count
+
+|a \| b |*1* |2
+2+|total |3
+|===
+
+,===
+x,y
+w,v
+,===
+"#;
+        let document = Transformer::parse(&Bytes::from(source))?;
+        let elements = document.get_all_elements();
+
+        // the blank line after the first row makes it the header, `2+` spans two columns
+        let (headers, rows) = table_texts(elements[0]);
+        assert_eq!(headers, ["Item", "Note", "Count"]);
+        assert_eq!(rows, [vec!["a | b", "1", "2"], vec!["total", "", "3"]]);
+
+        // comma separated data, a row per line
+        let (headers, rows) = table_texts(elements[1]);
+        assert!(headers.is_empty());
+        assert_eq!(rows, [vec!["x", "y"], vec!["w", "v"]]);
+        Ok(())
+    }
+}
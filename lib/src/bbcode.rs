@@ -0,0 +1,901 @@
+//! BBCode, the markup of the posts of forums and of some issue trackers
+//!
+//! Tags are read case-insensitively into a tree, tags left open being closed at the end of
+//! their parent and tags that are not known being text. Headings are `[h1]` to `[h6]` and
+//! `[heading]`, lists `[list]` of `[*]` items (numbered with `[list=1]`, `[list=a]`...),
+//! tables `[table]` of `[tr]` rows of `[th]` and `[td]` cells, code `[code]` and `[pre]`
+//! blocks, quotes `[quote]` blocks read as containers of the `quote` class, their author
+//! closing them, and videos `[youtube]` ids. Text is `[b]` strong, `[i]` emphasis and `[s]`
+//! deleted, links `[url]` and `[email]` or bare URLs and images `[img]` loaded from their
+//! source. Blank lines separate paragraphs and the other new lines are line breaks.
+//! Underlined text, sizes, colors, fonts and alignments are read as their content. BBCode
+//! is parsed only.
+
+use std::collections::HashMap;
+
+use bytes::Bytes;
+
+use crate::core::{
+    disk_image_loader, ContainerKind, Document, Element, ImageData, ImageDimension, ListItem,
+    MediaKind, NumberingStyle, TableCell, TableHeader, TableRow, TextStyle,
+};
+use crate::error::image_loader_errors;
+use crate::lightweight::{paragraph, text, text_block, Inline};
+
+pub struct Transformer;
+
+/// Schemes of the bare URLs of the text
+const URI_SCHEMES: [&str; 3] = ["https://", "http://", "ftp://"];
+
+/// Tags whose content is inline text in another style
+const STYLE_TAGS: [(&str, TextStyle); 7] = [
+    ("b", TextStyle::Strong),
+    ("strong", TextStyle::Strong),
+    ("i", TextStyle::Emphasis),
+    ("em", TextStyle::Emphasis),
+    ("s", TextStyle::Strikethrough),
+    ("strike", TextStyle::Strikethrough),
+    ("del", TextStyle::Strikethrough),
+];
+
+/// Tags read as their content
+const TRANSPARENT_TAGS: [&str; 15] = [
+    "u",
+    "ins",
+    "size",
+    "color",
+    "colour",
+    "font",
+    "sub",
+    "sup",
+    "highlight",
+    "center",
+    "left",
+    "right",
+    "justify",
+    "indent",
+    "spoiler",
+];
+
+/// Tags whose content is text and not tags
+const RAW_TAGS: [&str; 6] = ["code", "pre", "icode", "noparse", "img", "youtube"];
+
+/// Tags of the blocks
+const BLOCK_TAGS: [&str; 17] = [
+    "quote", "code", "pre", "list", "ul", "ol", "table", "h1", "h2", "h3", "h4", "h5", "h6",
+    "heading", "h", "hr", "youtube",
+];
+
+/// Other known tags
+const OTHER_TAGS: [&str; 7] = ["url", "email", "*", "li", "tr", "th", "td"];
+
+/// Container of the blocks of a quote
+fn quote(elements: Vec<Element>) -> Element {
+    Element::Container {
+        kind: ContainerKind::Div,
+        elements,
+    }
+    .with_attributes(HashMap::from([("class".to_string(), "quote".to_string())]))
+}
+
+fn is_known(name: &str) -> bool {
+    STYLE_TAGS.iter().any(|(tag, _)| *tag == name)
+        || TRANSPARENT_TAGS.contains(&name)
+        || RAW_TAGS.contains(&name)
+        || BLOCK_TAGS.contains(&name)
+        || OTHER_TAGS.contains(&name)
+}
+
+/// Node of the tag tree
+#[derive(Debug)]
+enum Node {
+    Text(String),
+    Tag(Tag),
+}
+
+#[derive(Debug, Default)]
+struct Tag {
+    name: String,
+    /// Value of `[name=value]`
+    argument: Option<String>,
+    /// Attributes of `[name key=value]`
+    attributes: HashMap<String, String>,
+    children: Vec<Node>,
+}
+
+/// Value at the start of `source`, in quotes or up to the next space, and the rest after it
+fn value(source: &str) -> (String, &str) {
+    match source.chars().next() {
+        Some(quote @ ('"' | '\'')) => match source[1..].find(quote) {
+            Some(end) => (source[1..end + 1].to_string(), &source[end + 2..]),
+            None => (source[1..].to_string(), ""),
+        },
+        _ => {
+            let end = source.find(char::is_whitespace).unwrap_or(source.len());
+            (source[..end].to_string(), &source[end..])
+        }
+    }
+}
+
+/// Attributes of a tag, `key=value key="value"`
+fn attributes(mut source: &str) -> HashMap<String, String> {
+    let mut attributes = HashMap::new();
+    loop {
+        source = source.trim_start();
+        let Some(equals) = source.find('=') else {
+            return attributes;
+        };
+        let key = source[..equals].trim().to_lowercase();
+        let (value, rest) = value(source[equals + 1..].trim_start());
+        attributes.insert(key, value);
+        source = rest;
+    }
+}
+
+/// Tag of the content of brackets, `name`, `name=value` or `name key=value`, or the name of
+/// a closing tag
+fn tag(content: &str) -> Option<Result<Tag, String>> {
+    if let Some(name) = content.strip_prefix('/') {
+        let name = name.trim().to_lowercase();
+        return is_known(&name).then_some(Err(name));
+    }
+    let end = content
+        .find(|c: char| !c.is_ascii_alphanumeric() && c != '*')
+        .unwrap_or(content.len());
+    let name = content[..end].to_lowercase();
+    if !is_known(&name) {
+        return None;
+    }
+    let rest = &content[end..];
+    let mut tag = Tag {
+        name,
+        ..Tag::default()
+    };
+    if let Some(argument) = rest.strip_prefix('=') {
+        let argument = argument.trim();
+        // a quoted value can be followed by attributes, `[quote="ada" post_id=3]`
+        if argument.starts_with(['"', '\'']) {
+            let (argument, rest) = value(argument);
+            tag.argument = Some(argument);
+            tag.attributes = attributes(rest);
+        } else {
+            tag.argument = Some(argument.to_string());
+        }
+    } else if rest.starts_with(char::is_whitespace) {
+        tag.attributes = attributes(rest);
+    } else if !rest.is_empty() {
+        return None;
+    }
+    Some(Ok(tag))
+}
+
+/// Position of `needle` in `source` from `from`, ignoring the case of ASCII letters
+fn find(source: &str, from: usize, needle: &str) -> Option<usize> {
+    source
+        .get(from..)?
+        .to_ascii_lowercase()
+        .find(needle)
+        .map(|position| from + position)
+}
+
+/// Tag tree of a source
+fn tree(source: &str) -> Vec<Node> {
+    // open tags, the root being the first
+    let mut stack = vec![Tag::default()];
+    let mut text = String::new();
+    let mut index = 0;
+
+    fn push_text(stack: &mut [Tag], text: &mut String) {
+        if !text.is_empty() {
+            let top = stack.last_mut().expect("the root is never closed");
+            top.children.push(Node::Text(std::mem::take(text)));
+        }
+    }
+
+    fn close(stack: &mut Vec<Tag>) {
+        let tag = stack.pop().expect("the root is never closed");
+        let top = stack.last_mut().expect("the root is never closed");
+        top.children.push(Node::Tag(tag));
+    }
+
+    while index < source.len() {
+        let rest = &source[index..];
+        let Some(start) = rest.find('[') else {
+            text.push_str(rest);
+            break;
+        };
+        text.push_str(&rest[..start]);
+        index += start;
+        let Some(end) = source[index..].find(']').map(|end| index + end) else {
+            text.push_str(&source[index..]);
+            break;
+        };
+        match tag(&source[index + 1..end]) {
+            None => {
+                text.push('[');
+                index += 1;
+            }
+            Some(Err(name)) => {
+                let open = stack[1..]
+                    .iter()
+                    .rposition(|tag| tag.name == name || (name == "li" && tag.name == "*"));
+                match open {
+                    Some(open) => {
+                        push_text(&mut stack, &mut text);
+                        while stack.len() > open + 1 {
+                            close(&mut stack);
+                        }
+                    }
+                    None => text.push_str(&source[index..=end]),
+                }
+                index = end + 1;
+            }
+            Some(Ok(mut tag)) => {
+                push_text(&mut stack, &mut text);
+                index = end + 1;
+                // an item ends the item before it
+                if matches!(tag.name.as_str(), "*" | "li") {
+                    if let Some(item) = stack[1..]
+                        .iter()
+                        .rposition(|tag| matches!(tag.name.as_str(), "*" | "li"))
+                        .filter(|item| {
+                            !stack[item + 2..]
+                                .iter()
+                                .any(|tag| matches!(tag.name.as_str(), "list" | "ul" | "ol"))
+                        })
+                    {
+                        while stack.len() > item + 1 {
+                            close(&mut stack);
+                        }
+                    }
+                }
+                let raw = RAW_TAGS.contains(&tag.name.as_str())
+                    || (matches!(tag.name.as_str(), "url" | "email") && tag.argument.is_none());
+                if tag.name == "hr" {
+                    let top = stack.last_mut().expect("the root is never closed");
+                    top.children.push(Node::Tag(tag));
+                } else if raw {
+                    let close = format!("[/{}]", tag.name);
+                    let end = find(source, index, &close).unwrap_or(source.len());
+                    tag.children
+                        .push(Node::Text(source[index..end].to_string()));
+                    index = (end + close.len()).min(source.len());
+                    let top = stack.last_mut().expect("the root is never closed");
+                    top.children.push(Node::Tag(tag));
+                } else {
+                    stack.push(tag);
+                }
+            }
+        }
+    }
+    push_text(&mut stack, &mut text);
+    while stack.len() > 1 {
+        close(&mut stack);
+    }
+    stack.pop().map(|root| root.children).unwrap_or_default()
+}
+
+/// Text of the nodes, without their tags
+fn node_text(nodes: &[Node]) -> String {
+    nodes
+        .iter()
+        .map(|node| match node {
+            Node::Text(text) => text.clone(),
+            Node::Tag(tag) => node_text(&tag.children),
+        })
+        .collect()
+}
+
+/// Inline elements without the line breaks and the spaces around them
+fn trim(mut elements: Vec<Element>) -> Vec<Element> {
+    let blank = |element: &Element| match element {
+        Element::LineBreak => true,
+        Element::Text { text, .. } => text.trim().is_empty(),
+        _ => false,
+    };
+    while elements.first().is_some_and(blank) {
+        elements.remove(0);
+    }
+    while elements.last().is_some_and(blank) {
+        elements.pop();
+    }
+    if let Some(Element::Text { text, .. }) = elements.first_mut() {
+        *text = text.trim_start().to_string();
+    }
+    if let Some(Element::Text { text, .. }) = elements.last_mut() {
+        *text = text.trim_end().to_string();
+    }
+    elements
+}
+
+struct Parser<'a, F>
+where
+    F: Fn(&str) -> anyhow::Result<Bytes>,
+{
+    image_loader: &'a F,
+}
+
+impl<F> Parser<'_, F>
+where
+    F: Fn(&str) -> anyhow::Result<Bytes>,
+{
+    fn blocks(&mut self, nodes: &[Node]) -> anyhow::Result<Vec<Element>> {
+        let mut blocks = vec![];
+        let mut inline = Inline::default();
+        for node in nodes {
+            match node {
+                Node::Text(source) => {
+                    let lines: Vec<&str> = source.split('\n').collect();
+                    for (index, line) in lines.iter().enumerate() {
+                        if index > 0 {
+                            // a blank line ends the paragraph
+                            if line.trim().is_empty() && index + 1 < lines.len() {
+                                finish_paragraph(&mut inline, &mut blocks);
+                                continue;
+                            }
+                            inline.push(Element::LineBreak);
+                        }
+                        inline.extend(bare_links(line));
+                    }
+                }
+                Node::Tag(tag)
+                    if BLOCK_TAGS.contains(&tag.name.as_str())
+                        || (TRANSPARENT_TAGS.contains(&tag.name.as_str())
+                            && contains_block(&tag.children)) =>
+                {
+                    finish_paragraph(&mut inline, &mut blocks);
+                    self.block(tag, &mut blocks)?;
+                }
+                Node::Tag(tag) => inline.extend(self.inline(tag)?),
+            }
+        }
+        finish_paragraph(&mut inline, &mut blocks);
+        Ok(blocks)
+    }
+
+    fn block(&mut self, tag: &Tag, blocks: &mut Vec<Element>) -> anyhow::Result<()> {
+        match tag.name.as_str() {
+            "quote" => {
+                let mut elements = self.blocks(&tag.children)?;
+                let author = tag
+                    .argument
+                    .clone()
+                    .or_else(|| tag.attributes.get("name").cloned())
+                    .or_else(|| tag.attributes.get("author").cloned());
+                if let Some(author) = author.filter(|author| !author.trim().is_empty()) {
+                    elements.push(paragraph(vec![text(format!("— {}", author.trim()))]));
+                }
+                blocks.push(quote(elements));
+            }
+            "code" | "pre" => {
+                let code = node_text(&tag.children);
+                let code = code.trim_start_matches(['\r', '\n']).trim_end();
+                let info = tag
+                    .argument
+                    .clone()
+                    .or_else(|| tag.attributes.get("lang").cloned())
+                    .unwrap_or_default();
+                blocks.push(Element::CodeBlock {
+                    info: info.trim().to_string(),
+                    code: code.to_string(),
+                });
+            }
+            "list" | "ul" | "ol" => blocks.push(self.list(tag)?),
+            "table" => blocks.push(self.table(tag)?),
+            "hr" => {}
+            "youtube" => {
+                let id = node_text(&tag.children);
+                let id = id.trim();
+                let url = if id.contains("://") {
+                    id.to_string()
+                } else {
+                    format!("https://www.youtube.com/watch?v={id}")
+                };
+                blocks.push(Element::Media {
+                    kind: MediaKind::Video,
+                    url,
+                    title: String::new(),
+                });
+            }
+            name if TRANSPARENT_TAGS.contains(&name) => {
+                blocks.extend(self.blocks(&tag.children)?);
+            }
+            name => {
+                let level = match name {
+                    "heading" | "h" => 2,
+                    name => name[1..].parse().unwrap_or(2),
+                };
+                let text = node_text(&tag.children);
+                let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+                if !text.is_empty() {
+                    blocks.push(Element::Header { level, text });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// List of the `[*]` items of a tag, the lists of an item following it
+    fn list(&mut self, tag: &Tag) -> anyhow::Result<Element> {
+        let argument = tag.argument.as_deref().map(str::trim);
+        let numbering = match argument {
+            Some("a") => NumberingStyle::LowerAlpha,
+            Some("A") => NumberingStyle::UpperAlpha,
+            Some("i") => NumberingStyle::LowerRoman,
+            Some("I") => NumberingStyle::UpperRoman,
+            _ => NumberingStyle::Decimal,
+        };
+        let numbered = tag.name == "ol" || argument.is_some_and(|argument| !argument.is_empty());
+        let start = argument
+            .and_then(|argument| argument.parse().ok())
+            .unwrap_or(1);
+        let mut items = vec![];
+        for node in &tag.children {
+            let Node::Tag(item) = node else {
+                continue;
+            };
+            if !matches!(item.name.as_str(), "*" | "li") {
+                continue;
+            }
+            let mut blocks = self.blocks(&item.children)?.into_iter();
+            match blocks.next() {
+                Some(first) => items.push(ListItem {
+                    element: text_block(first),
+                }),
+                None => items.push(ListItem { element: text("") }),
+            }
+            items.extend(blocks.map(|block| ListItem {
+                element: text_block(block),
+            }));
+        }
+        Ok(Element::List {
+            elements: items,
+            numbered,
+            start,
+            numbering,
+        })
+    }
+
+    /// Table of the `[tr]` rows of a tag, a first row of `[th]` cells being the headers
+    fn table(&mut self, tag: &Tag) -> anyhow::Result<Element> {
+        let mut rows: Vec<Vec<(bool, &Tag)>> = vec![];
+        for node in &tag.children {
+            let Node::Tag(row) = node else {
+                continue;
+            };
+            if row.name != "tr" {
+                continue;
+            }
+            let cells = row
+                .children
+                .iter()
+                .filter_map(|node| match node {
+                    Node::Tag(cell) if matches!(cell.name.as_str(), "th" | "td") => {
+                        Some((cell.name == "th", cell))
+                    }
+                    _ => None,
+                })
+                .collect();
+            rows.push(cells);
+        }
+        let mut headers = vec![];
+        if rows
+            .first()
+            .is_some_and(|row| !row.is_empty() && row.iter().all(|(header, _)| *header))
+        {
+            for (_, cell) in rows.remove(0) {
+                headers.push(TableHeader {
+                    element: self.cell(cell)?,
+                    width: 30.,
+                });
+            }
+        }
+        let mut table_rows = vec![];
+        for row in rows {
+            let mut cells = vec![];
+            for (_, cell) in row {
+                cells.push(TableCell {
+                    element: self.cell(cell)?,
+                });
+            }
+            table_rows.push(TableRow { cells });
+        }
+        Ok(Element::Table {
+            headers,
+            rows: table_rows,
+        })
+    }
+
+    /// Element of a cell: its text, its only block or its blocks
+    fn cell(&mut self, cell: &Tag) -> anyhow::Result<Element> {
+        let mut blocks = self.blocks(&cell.children)?;
+        Ok(match blocks.len() {
+            0 => text(""),
+            1 => text_block(blocks.remove(0)),
+            _ => Element::Container {
+                kind: ContainerKind::Div,
+                elements: blocks,
+            },
+        })
+    }
+
+    fn inline(&mut self, tag: &Tag) -> anyhow::Result<Vec<Element>> {
+        let name = tag.name.as_str();
+        if let Some((_, style)) = STYLE_TAGS.iter().find(|(style_tag, _)| *style_tag == name) {
+            return Ok(vec![Element::Styled {
+                style: *style,
+                elements: self.inlines(&tag.children)?,
+            }]);
+        }
+        Ok(match name {
+            "url" | "email" => {
+                let (url, elements) = match &tag.argument {
+                    Some(url) => (url.trim().to_string(), self.inlines(&tag.children)?),
+                    None => {
+                        let url = node_text(&tag.children).trim().to_string();
+                        (url.clone(), vec![text(url)])
+                    }
+                };
+                let url = if name == "email" && !url.starts_with("mailto:") {
+                    format!("mailto:{url}")
+                } else {
+                    url
+                };
+                let elements = if trim(elements.clone()).is_empty() {
+                    vec![text(url.trim_start_matches("mailto:"))]
+                } else {
+                    elements
+                };
+                vec![Element::Hyperlink {
+                    elements,
+                    url,
+                    alt: String::new(),
+                    size: 14,
+                }]
+            }
+            "img" => vec![self.image(tag)?],
+            "icode" | "code" | "pre" => vec![Element::InlineCode {
+                text: node_text(&tag.children),
+            }],
+            "noparse" => vec![text(node_text(&tag.children))],
+            _ => self.inlines(&tag.children)?,
+        })
+    }
+
+    fn inlines(&mut self, nodes: &[Node]) -> anyhow::Result<Vec<Element>> {
+        let mut inline = Inline::default();
+        for node in nodes {
+            match node {
+                Node::Text(source) => {
+                    for (index, line) in source.split('\n').enumerate() {
+                        if index > 0 {
+                            inline.push(Element::LineBreak);
+                        }
+                        inline.extend(bare_links(line));
+                    }
+                }
+                Node::Tag(tag) => inline.extend(self.inline(tag)?),
+            }
+        }
+        Ok(inline.finish())
+    }
+
+    /// Image of its source, its size `[img=200x100]` or `[img width=200 height=100]` and its
+    /// alternative text `[img alt="text"]`
+    fn image(&mut self, tag: &Tag) -> anyhow::Result<Element> {
+        let source = node_text(&tag.children);
+        let source = source.trim();
+        let mut size = ImageDimension {
+            width: tag.attributes.get("width").map(|width| px(width)),
+            height: tag.attributes.get("height").map(|height| px(height)),
+        };
+        if let Some((width, height)) = tag
+            .argument
+            .as_deref()
+            .and_then(|size| size.split_once('x'))
+        {
+            size.width = Some(px(width.trim()));
+            size.height = Some(px(height.trim()));
+        }
+        let alt = tag.attributes.get("alt").cloned().unwrap_or_default();
+        let title = tag.attributes.get("title").cloned().unwrap_or_default();
+        Ok(Element::Image(ImageData::new(
+            (self.image_loader)(source)?,
+            title,
+            alt,
+            source.to_string(),
+            String::new(),
+            size,
+        )))
+    }
+}
+
+/// Length in pixels of a length without unit
+fn px(length: &str) -> String {
+    if !length.is_empty() && length.chars().all(|c| c.is_ascii_digit()) {
+        format!("{length}px")
+    } else {
+        length.to_string()
+    }
+}
+
+/// Whether some of the nodes are blocks
+fn contains_block(nodes: &[Node]) -> bool {
+    nodes.iter().any(|node| match node {
+        Node::Tag(tag) => BLOCK_TAGS.contains(&tag.name.as_str()) || contains_block(&tag.children),
+        Node::Text(_) => false,
+    })
+}
+
+/// Paragraph of the inline elements read so far, added to the blocks when it has content
+fn finish_paragraph(inline: &mut Inline, blocks: &mut Vec<Element>) {
+    let elements = trim(std::mem::take(inline).finish());
+    if !elements.is_empty() {
+        blocks.push(paragraph(elements));
+    }
+}
+
+/// Text of a line with its bare URLs as links
+fn bare_links(line: &str) -> Vec<Element> {
+    let mut elements = vec![];
+    let mut rest = line;
+    loop {
+        let lower = rest.to_ascii_lowercase();
+        let start = URI_SCHEMES
+            .iter()
+            .filter_map(|scheme| {
+                lower.match_indices(scheme).find(|(position, _)| {
+                    rest[..*position]
+                        .chars()
+                        .next_back()
+                        .is_none_or(|c| !c.is_alphanumeric())
+                })
+            })
+            .map(|(position, _)| position)
+            .min();
+        let Some(start) = start else {
+            if !rest.is_empty() {
+                elements.push(text(rest));
+            }
+            return elements;
+        };
+        if start > 0 {
+            elements.push(text(&rest[..start]));
+        }
+        let length = rest[start..]
+            .find(|c: char| c.is_whitespace() || "<>\"".contains(c))
+            .unwrap_or(rest.len() - start);
+        let url =
+            rest[start..start + length].trim_end_matches(['.', ',', ';', ':', '!', '?', ')', '\'']);
+        elements.push(Element::Hyperlink {
+            elements: vec![text(url)],
+            url: url.to_string(),
+            alt: String::new(),
+            size: 14,
+        });
+        rest = &rest[start + url.len()..];
+    }
+}
+
+impl Transformer {
+    /// Document of BBCode, its images loaded from the current directory
+    pub fn parse(document: &Bytes) -> crate::Result<Document> {
+        Transformer::parse_with_loader(document, disk_image_loader("."))
+    }
+
+    /// Document of BBCode, its images loaded with `image_loader` from the sources of the
+    /// `[img]` tags
+    pub fn parse_with_loader<F>(document: &Bytes, image_loader: F) -> crate::Result<Document>
+    where
+        F: Fn(&str) -> anyhow::Result<Bytes>,
+    {
+        Transformer::parse_source(document, &image_loader_errors(image_loader))
+            .map_err(crate::Error::parse_error)
+    }
+
+    fn parse_source<F>(source: &Bytes, image_loader: &F) -> anyhow::Result<Document>
+    where
+        F: Fn(&str) -> anyhow::Result<Bytes>,
+    {
+        let source = std::str::from_utf8(source)?;
+        let source = source.strip_prefix('\u{feff}').unwrap_or(source);
+        let source = source.replace("\r\n", "\n");
+        let mut parser = Parser { image_loader };
+        Ok(Document::new(parser.blocks(&tree(&source))?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+    use crate::core::tests::{init_logger, table_texts};
+    use crate::core::Band;
+
+    #[test]
+    fn test_parse() -> anyhow::Result<()> {
+        init_logger();
+        let source = "[h2]Release [b]notes[/b][/h2]\n\
+            Hello [B]bold [i]both[/i][/b], [s]gone[/s] [u]under[/u] [color=red]red[/color]\n\
+            see [url=https://example.com]the [b]site[/b][/url], [url]https://docs.rs[/url], \
+            [email]ada@example.com[/email] or https://example.org. [unknown]x[/unknown] [/b]\n\n\
+            Next paragraph.\n\
+            [quote=\"Ada\" post_id=3]First [i]quoted[/i].\n\n[quote]Nested[/quote][/quote]\n\
+            [list]\n[*]One\n[*]Two\n[list=a][*]Nested[/list]\n[/list]\n\
+            [list=3][*]Three[*]Four[/list]\n\
+            [code=rust]\nfn main() {\n    let a = [1, 2];\n}\n[/code]\n\
+            [table][tr][th]Name[/th][th]Value[/th][/tr][tr][td]a[/td][td][b]1[/b][/td][/tr][/table]\n\
+            [center][img width=50 alt=\"The logo\"]small.png[/img][/center]\n\
+            [youtube]dQw4w9WgXcQ[/youtube]\n\
+            [i]left open";
+        let requested = RefCell::new(vec![]);
+        let document = Transformer::parse_with_loader(&Bytes::from(source), |path| {
+            requested.borrow_mut().push(path.to_string());
+            Ok(Bytes::from(std::fs::read(format!("test/data/{path}"))?))
+        })?;
+        assert_eq!(requested.borrow().as_slice(), ["small.png"]);
+
+        let Some(Band::Detail(elements)) = document.bands.last() else {
+            panic!("no detail band");
+        };
+        assert_eq!(
+            elements[0],
+            Element::Header {
+                level: 2,
+                text: "Release notes".to_string()
+            }
+        );
+        let Element::Paragraph {
+            elements: inline, ..
+        } = &elements[1]
+        else {
+            panic!("not a paragraph: {:?}", elements[1]);
+        };
+        assert_eq!(inline[0], text("Hello "));
+        assert_eq!(
+            inline[1],
+            Element::Styled {
+                style: TextStyle::Strong,
+                elements: vec![
+                    text("bold "),
+                    Element::Styled {
+                        style: TextStyle::Emphasis,
+                        elements: vec![text("both")],
+                    }
+                ],
+            }
+        );
+        assert_eq!(inline[2], text(", "));
+        assert_eq!(
+            inline[3],
+            Element::Styled {
+                style: TextStyle::Strikethrough,
+                elements: vec![text("gone")],
+            }
+        );
+        assert_eq!(inline[4], text(" under red"));
+        assert_eq!(inline[5], Element::LineBreak);
+        assert!(
+            matches!(&inline[7], Element::Hyperlink { url, elements, .. }
+            if url == "https://example.com" && elements.len() == 2)
+        );
+        assert!(matches!(&inline[9], Element::Hyperlink { url, .. } if url == "https://docs.rs"));
+        assert!(
+            matches!(&inline[11], Element::Hyperlink { url, elements, .. }
+            if url == "mailto:ada@example.com" && elements == &[text("ada@example.com")])
+        );
+        assert!(
+            matches!(&inline[13], Element::Hyperlink { url, .. } if url == "https://example.org")
+        );
+        assert_eq!(inline[14], text(". [unknown]x[/unknown] [/b]"));
+        assert_eq!(elements[2], paragraph(vec![text("Next paragraph.")]));
+        assert_eq!(
+            elements[3],
+            quote(vec![
+                paragraph(vec![
+                    text("First "),
+                    Element::Styled {
+                        style: TextStyle::Emphasis,
+                        elements: vec![text("quoted")],
+                    },
+                    text("."),
+                ]),
+                quote(vec![paragraph(vec![text("Nested")])]),
+                paragraph(vec![text("— Ada")]),
+            ])
+        );
+        let Element::List {
+            elements: items,
+            numbered: false,
+            ..
+        } = &elements[4]
+        else {
+            panic!("not a list: {:?}", elements[4]);
+        };
+        assert_eq!(items[0].element, text("One"));
+        assert_eq!(items[1].element, text("Two"));
+        assert!(matches!(&items[2].element, Element::List {
+            numbered: true,
+            numbering: NumberingStyle::LowerAlpha,
+            elements,
+            ..
+        } if elements[0].element == text("Nested")));
+        assert!(matches!(&elements[5], Element::List {
+            numbered: true,
+            start: 3,
+            elements,
+            ..
+        } if elements.len() == 2 && elements[1].element == text("Four")));
+        assert_eq!(
+            elements[6],
+            Element::CodeBlock {
+                info: "rust".to_string(),
+                code: "fn main() {\n    let a = [1, 2];\n}".to_string()
+            }
+        );
+        let Element::Table { headers, rows } = &elements[7] else {
+            panic!("not a table: {:?}", elements[7]);
+        };
+        assert_eq!(headers[1].element, text("Value"));
+        assert_eq!(
+            rows[0].cells[1].element,
+            paragraph(vec![Element::Styled {
+                style: TextStyle::Strong,
+                elements: vec![text("1")],
+            }])
+        );
+        let Element::Paragraph {
+            elements: inline, ..
+        } = &elements[8]
+        else {
+            panic!("not a paragraph: {:?}", elements[8]);
+        };
+        let Element::Image(image) = &inline[0] else {
+            panic!("not an image: {:?}", inline[0]);
+        };
+        assert_eq!(image.alt(), "The logo");
+        assert_eq!(image.size().width.as_deref(), Some("50px"));
+        assert!(
+            matches!(&elements[9], Element::Media { kind: MediaKind::Video, url, .. }
+            if url == "https://www.youtube.com/watch?v=dQw4w9WgXcQ")
+        );
+        assert_eq!(
+            elements[10],
+            paragraph(vec![Element::Styled {
+                style: TextStyle::Emphasis,
+                elements: vec![text("left open")],
+            }])
+        );
+        assert_eq!(elements.len(), 11);
+        Ok(())
+    }
+
+    #[test]
+    fn test_tables() -> anyhow::Result<()> {
+        let source = "[TABLE]
+[tr][th]Item[/th][th]Price[/th][/tr]
+[tr][td][url=https://example.com]apples[/url][/td][td][b]1[/b][/td][/tr]
+[/TABLE]
+[table][tr][td]x[/td][td][list][*]y[*]z[/list][/td][/tr][/table]
+";
+        let document = Transformer::parse(&Bytes::from(source))?;
+        let elements = document.get_all_elements();
+        assert_eq!(elements.len(), 2);
+
+        // tags in any case, the line breaks between the rows are left out
+        let (headers, rows) = table_texts(elements[0]);
+        assert_eq!(headers, ["Item", "Price"]);
+        assert_eq!(rows, [vec!["apples", "1"]]);
+
+        // a cell holds blocks
+        let (headers, rows) = table_texts(elements[1]);
+        assert!(headers.is_empty());
+        assert_eq!(rows, [vec!["x", "y\nz"]]);
+        let Element::Table { rows, .. } = elements[1] else {
+            unreachable!()
+        };
+        assert!(matches!(rows[0].cells[1].element, Element::List { .. }));
+        Ok(())
+    }
+}
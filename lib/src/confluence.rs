@@ -0,0 +1,560 @@
+//! Confluence storage format of a document, the XHTML body of a page that the Confluence
+//! REST API takes as `body.storage.value`
+//!
+//! Blocks are XHTML: headings, paragraphs, lists and tables with their header row of `th`
+//! cells. Code blocks are `code` macros, admonitions `info`, `tip`, `note` and `warning`
+//! macros and the table of contents a `toc` macro. Images are `ac:image` elements of
+//! attachments saved next to the output, to be uploaded as attachments of the page, links
+//! to headers and bookmarks `ac:link` elements to `anchor` macros, and footnotes numbered
+//! links to a list of the notes at the end of the page. Confluence has no math, formulas
+//! are written as their TeX code. The title of the page is not part of its body and is
+//! left out. Confluence storage format is generated only.
+
+use std::collections::HashSet;
+use std::fmt::Write as _;
+
+use bytes::Bytes;
+use log::warn;
+
+use crate::core::{
+    disk_image_saver, header_anchor, Band, ContainerKind, Document, Element, ImageData,
+    TableHeader, TableRow, TextStyle,
+};
+use crate::util::escape_xml;
+
+pub struct Transformer;
+
+/// CDATA section of a text, its `]]>` split across two sections
+fn cdata(text: &str) -> String {
+    let text: String = text
+        .chars()
+        .filter(|c| !c.is_control() || matches!(c, '\t' | '\n' | '\r'))
+        .collect();
+    format!("<![CDATA[{}]]>", text.replace("]]>", "]]]]><![CDATA[>"))
+}
+
+/// Parameter of a macro
+fn parameter(name: &str, value: &str) -> String {
+    format!(
+        r#"<ac:parameter ac:name="{name}">{}</ac:parameter>"#,
+        escape_xml(value)
+    )
+}
+
+/// `anchor` macro, the target of the `ac:link` elements to its id
+fn anchor(id: &str) -> String {
+    format!(
+        r#"<ac:structured-macro ac:name="anchor" ac:schema-version="1">{}</ac:structured-macro>"#,
+        parameter("", id)
+    )
+}
+
+/// `ac:link` to an anchor of the page with a plain text body
+fn anchor_link(id: &str, text: &str) -> String {
+    format!(
+        r#"<ac:link ac:anchor="{}"><ac:plain-text-link-body>{}</ac:plain-text-link-body></ac:link>"#,
+        escape_xml(id),
+        cdata(text)
+    )
+}
+
+/// Macro of an admonition kind, the kinds without one taking the closest
+fn admonition_macro(kind: &str) -> &'static str {
+    match kind.to_lowercase().as_str() {
+        "tip" | "hint" => "tip",
+        "important" | "attention" | "caution" => "note",
+        "warning" | "danger" | "error" => "warning",
+        _ => "info",
+    }
+}
+
+/// Anchors that links of the document refer to and that are not bookmarks: the anchors
+/// of the headers written before them
+fn header_targets(document: &Document) -> HashSet<String> {
+    let mut targets = document.link_targets();
+    for element in document.get_all_elements() {
+        if let Element::Bookmark { id, .. } = element {
+            targets.remove(id);
+        }
+    }
+    targets
+}
+
+struct Writer<'a, F>
+where
+    F: Fn(&Bytes, &str) -> anyhow::Result<()>,
+{
+    image_saver: &'a F,
+    image_count: usize,
+    /// Anchors of the headers that links refer to
+    header_targets: HashSet<String>,
+    /// Anchors already written, an anchor is written once
+    anchors: HashSet<String>,
+    /// Notes of the footnotes written so far, listed at the end of the page
+    footnotes: Vec<String>,
+    /// Depth of the lists, tables and macros being written, where there are no headings
+    depth: usize,
+}
+
+impl<F> Writer<'_, F>
+where
+    F: Fn(&Bytes, &str) -> anyhow::Result<()>,
+{
+    fn blocks<'e>(
+        &mut self,
+        elements: impl IntoIterator<Item = &'e Element>,
+    ) -> anyhow::Result<String> {
+        let mut parts = vec![];
+        for element in elements {
+            let xhtml = self.block(element)?;
+            if !xhtml.is_empty() {
+                parts.push(xhtml);
+            }
+        }
+        Ok(parts.join("\n"))
+    }
+
+    /// Blocks nested in a list, a table or a macro, where there are no headings
+    fn nested<'e>(
+        &mut self,
+        elements: impl IntoIterator<Item = &'e Element>,
+    ) -> anyhow::Result<String> {
+        self.depth += 1;
+        let xhtml = self.blocks(elements);
+        self.depth -= 1;
+        xhtml
+    }
+
+    /// Anchor macro of an anchor written for the first time, empty otherwise
+    fn anchor(&mut self, id: &str) -> String {
+        if self.anchors.insert(id.to_string()) {
+            anchor(id)
+        } else {
+            String::new()
+        }
+    }
+
+    fn block(&mut self, element: &Element) -> anyhow::Result<String> {
+        if let Some((kind, title, blocks)) = element.admonition_parts() {
+            let title = title
+                .map(|title| parameter("title", title.trim()))
+                .unwrap_or_default();
+            let body = self.nested(blocks)?;
+            return Ok(format!(
+                "<ac:structured-macro ac:name=\"{}\" ac:schema-version=\"1\">{title}\
+                 <ac:rich-text-body>\n{body}\n</ac:rich-text-body></ac:structured-macro>",
+                admonition_macro(kind)
+            ));
+        }
+        Ok(match element {
+            Element::Header { level, text } if self.depth == 0 => {
+                let anchor = header_anchor(text);
+                let anchor = if self.header_targets.contains(&anchor) {
+                    self.anchor(&anchor)
+                } else {
+                    String::new()
+                };
+                let level = (*level).clamp(1, 6);
+                format!("<h{level}>{anchor}{}</h{level}>", escape_xml(text.trim()))
+            }
+            Element::Attributed { element, .. } => self.block(element)?,
+            Element::Paragraph { elements, .. } => self.paragraph(elements)?,
+            Element::Image(image) => match self.image(image)? {
+                Some(image) => format!("<p>{image}</p>"),
+                None => String::new(),
+            },
+            Element::Table { headers, rows } => self.table(headers, rows)?,
+            Element::List { .. } => self.list(element)?,
+            Element::CodeBlock { code, .. } => {
+                let language = element
+                    .code_language()
+                    .map(|language| parameter("language", &language.to_lowercase()))
+                    .unwrap_or_default();
+                format!(
+                    "<ac:structured-macro ac:name=\"code\" ac:schema-version=\"1\">{language}\
+                     <ac:plain-text-body>{}</ac:plain-text-body></ac:structured-macro>",
+                    cdata(code.trim_end_matches('\n'))
+                )
+            }
+            Element::Math { tex, display: true } => format!(
+                "<ac:structured-macro ac:name=\"code\" ac:schema-version=\"1\">{}\
+                 <ac:plain-text-body>{}</ac:plain-text-body></ac:structured-macro>",
+                parameter("language", "latex"),
+                cdata(tex.trim())
+            ),
+            Element::Container {
+                kind: ContainerKind::Aside,
+                elements,
+            } => {
+                let body = self.nested(elements)?;
+                format!(
+                    "<ac:structured-macro ac:name=\"panel\" ac:schema-version=\"1\">\
+                     <ac:rich-text-body>\n{body}\n</ac:rich-text-body></ac:structured-macro>"
+                )
+            }
+            Element::Container { elements, .. } => self.blocks(elements)?,
+            Element::TableOfContents { max_level } => format!(
+                "<ac:structured-macro ac:name=\"toc\" ac:schema-version=\"1\">{}</ac:structured-macro>",
+                parameter("maxLevel", &max_level.clamp(&1, &6).to_string())
+            ),
+            Element::Raw { .. } | Element::Drawing { .. } | Element::LineBreak => String::new(),
+            element => self.paragraph(std::slice::from_ref(element))?,
+        })
+    }
+
+    fn paragraph(&mut self, elements: &[Element]) -> anyhow::Result<String> {
+        let content = self.inlines(elements)?;
+        let content = content.trim();
+        Ok(if content.is_empty() {
+            String::new()
+        } else {
+            format!("<p>{content}</p>")
+        })
+    }
+
+    fn inlines(&mut self, elements: &[Element]) -> anyhow::Result<String> {
+        let mut content = String::new();
+        for element in elements {
+            content.push_str(&self.inline(element)?);
+        }
+        Ok(content)
+    }
+
+    fn inline(&mut self, element: &Element) -> anyhow::Result<String> {
+        Ok(match element {
+            Element::Text { text, .. } => escape_xml(text),
+            Element::Header { text, .. } => format!("<strong>{}</strong>", escape_xml(text.trim())),
+            Element::Paragraph { elements, .. } | Element::Container { elements, .. } => {
+                self.inlines(elements)?
+            }
+            Element::Styled { style, elements } => {
+                let content = self.inlines(elements)?;
+                match style {
+                    TextStyle::Strong => format!("<strong>{content}</strong>"),
+                    TextStyle::Emphasis => format!("<em>{content}</em>"),
+                    TextStyle::Strikethrough => {
+                        format!(r#"<span style="text-decoration: line-through;">{content}</span>"#)
+                    }
+                }
+            }
+            Element::Hyperlink { elements, url, .. } => match url.strip_prefix('#') {
+                Some(id) => {
+                    let text = elements.iter().map(Element::plain_text).collect::<String>();
+                    anchor_link(id, text.trim())
+                }
+                None => {
+                    let content = self.inlines(elements)?;
+                    let content = if content.trim().is_empty() {
+                        escape_xml(url)
+                    } else {
+                        content
+                    };
+                    format!(r#"<a href="{}">{content}</a>"#, escape_xml(url))
+                }
+            },
+            Element::InlineCode { text } => format!("<code>{}</code>", escape_xml(text)),
+            Element::CodeBlock { code, .. } => format!("<code>{}</code>", escape_xml(code.trim())),
+            Element::Math { tex, .. } => format!("<code>{}</code>", escape_xml(tex.trim())),
+            Element::LineBreak => "<br />".to_string(),
+            Element::Footnote { elements } => {
+                let note = self.inlines(elements)?;
+                self.footnotes.push(note.trim().to_string());
+                let number = self.footnotes.len();
+                format!(
+                    "<sup>{}</sup>",
+                    anchor_link(&format!("footnote-{number}"), &number.to_string())
+                )
+            }
+            Element::Bookmark { id, .. } => self.anchor(id),
+            Element::Image(image) => self.image(image)?.unwrap_or_default(),
+            Element::Attributed { element, .. } => self.inline(element)?,
+            Element::Media { .. } => match element.media_link() {
+                Some(link) => self.inline(&link)?,
+                None => String::new(),
+            },
+            Element::List { elements, .. } => {
+                let mut content = String::new();
+                for item in elements {
+                    content.push_str(&self.inline(&item.element)?);
+                    content.push(' ');
+                }
+                content
+            }
+            _ => String::new(),
+        })
+    }
+
+    /// `ac:image` of the saved attachment of an image, with its alternative text, its title
+    /// and its width in pixels
+    fn image(&mut self, image: &ImageData) -> anyhow::Result<Option<String>> {
+        if image.bytes().is_empty() {
+            warn!("Image without content left out: {}", image.title());
+            return Ok(None);
+        }
+        self.image_count += 1;
+        let name = format!(
+            "image{}{}",
+            self.image_count,
+            image.image_type().to_extension()
+        );
+        (self.image_saver)(image.bytes(), &name)?;
+        let mut attributes = String::new();
+        if !image.alt().trim().is_empty() {
+            let _ = write!(
+                attributes,
+                r#" ac:alt="{}""#,
+                escape_xml(image.alt().trim())
+            );
+        }
+        if !image.title().trim().is_empty() {
+            let _ = write!(
+                attributes,
+                r#" ac:title="{}""#,
+                escape_xml(image.title().trim())
+            );
+        }
+        if let Some(width) = &image.size().width {
+            let width = width.trim().trim_end_matches("px");
+            if !width.is_empty() && width.chars().all(|c| c.is_ascii_digit()) {
+                let _ = write!(attributes, r#" ac:width="{width}""#);
+            }
+        }
+        Ok(Some(format!(
+            r#"<ac:image{attributes}><ri:attachment ri:filename="{}" /></ac:image>"#,
+            escape_xml(&name)
+        )))
+    }
+
+    /// Table with its header row of `th` cells
+    fn table(&mut self, headers: &[TableHeader], rows: &[TableRow]) -> anyhow::Result<String> {
+        let mut lines = vec!["<table><tbody>".to_string()];
+        if !headers.is_empty() {
+            let cells: Vec<&Element> = headers.iter().map(|header| &header.element).collect();
+            lines.push(self.row(&cells, "th")?);
+        }
+        for row in rows {
+            let cells: Vec<&Element> = row.cells.iter().map(|cell| &cell.element).collect();
+            lines.push(self.row(&cells, "td")?);
+        }
+        lines.push("</tbody></table>".to_string());
+        Ok(lines.join("\n"))
+    }
+
+    fn row(&mut self, cells: &[&Element], tag: &str) -> anyhow::Result<String> {
+        let mut row = "<tr>".to_string();
+        for cell in cells {
+            let content = match cell {
+                Element::Text { .. } | Element::Paragraph { .. } => {
+                    self.inline(cell)?.trim().to_string()
+                }
+                cell => format!("\n{}\n", self.nested([*cell])?),
+            };
+            let _ = write!(row, "<{tag}>{content}</{tag}>");
+        }
+        row.push_str("</tr>");
+        Ok(row)
+    }
+
+    /// Bulleted or numbered list, the lists, tables and code after an item being nested in
+    /// it
+    fn list(&mut self, list: &Element) -> anyhow::Result<String> {
+        let Element::List {
+            elements,
+            numbered,
+            start,
+            ..
+        } = list
+        else {
+            return Ok(String::new());
+        };
+        let mut items: Vec<Vec<&Element>> = vec![];
+        for item in elements {
+            let element = &item.element;
+            match items.last_mut() {
+                Some(blocks)
+                    if matches!(
+                        element,
+                        Element::List { .. } | Element::CodeBlock { .. } | Element::Table { .. }
+                    ) =>
+                {
+                    blocks.push(element)
+                }
+                _ => items.push(vec![element]),
+            }
+        }
+        if items.is_empty() {
+            return Ok(String::new());
+        }
+        let (open, tag) = match (*numbered, *start) {
+            (true, 1) => ("<ol>".to_string(), "ol"),
+            (true, start) => (format!(r#"<ol start="{start}">"#), "ol"),
+            (false, _) => ("<ul>".to_string(), "ul"),
+        };
+        let mut lines = vec![open];
+        for blocks in items {
+            let mut item = String::new();
+            self.depth += 1;
+            for block in blocks {
+                let xhtml = match block {
+                    Element::List { .. } | Element::CodeBlock { .. } | Element::Table { .. } => {
+                        format!("\n{}", self.block(block)?)
+                    }
+                    element => self.inline(element)?.trim().to_string(),
+                };
+                item.push_str(&xhtml);
+            }
+            self.depth -= 1;
+            lines.push(format!("<li>{item}</li>"));
+        }
+        lines.push(format!("</{tag}>"));
+        Ok(lines.join("\n"))
+    }
+}
+
+impl Transformer {
+    /// Confluence storage format of the document, its images saved to the current directory
+    pub fn generate(document: &Document) -> crate::Result<Bytes> {
+        Transformer::generate_with_saver(document, disk_image_saver("."))
+    }
+
+    /// Confluence storage format of the document, its images saved with `image_saver` under
+    /// the names of the attachments their `ac:image` refers to
+    pub fn generate_with_saver<F>(document: &Document, image_saver: F) -> crate::Result<Bytes>
+    where
+        F: Fn(&Bytes, &str) -> anyhow::Result<()>,
+    {
+        Transformer::generate_with_image_saver(document, image_saver)
+            .map_err(crate::Error::generation_error)
+    }
+
+    fn generate_with_image_saver<F>(document: &Document, image_saver: F) -> anyhow::Result<Bytes>
+    where
+        F: Fn(&Bytes, &str) -> anyhow::Result<()>,
+    {
+        let document = &document.resolve_fields().without_drawings();
+        let mut writer = Writer {
+            image_saver: &image_saver,
+            image_count: 0,
+            header_targets: header_targets(document),
+            anchors: HashSet::new(),
+            footnotes: vec![],
+            depth: 0,
+        };
+        let elements = document
+            .bands
+            .iter()
+            .filter(|band| !matches!(band, Band::PageHeader(_) | Band::PageFooter(_)))
+            .flat_map(|band| band.elements());
+        let mut xhtml = writer.blocks(elements)?;
+        if !writer.footnotes.is_empty() {
+            xhtml.push_str("\n<hr />\n<ol>");
+            for (index, note) in writer.footnotes.iter().enumerate() {
+                let _ = write!(
+                    xhtml,
+                    "\n<li>{}{note}</li>",
+                    anchor(&format!("footnote-{}", index + 1))
+                );
+            }
+            xhtml.push_str("\n</ol>");
+        }
+        Ok(Bytes::from(xhtml.trim().to_string() + "\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+    use crate::core::tests::init_logger;
+    use crate::core::{ImageDimension, TransformerTrait};
+    use crate::markdown;
+
+    #[test]
+    fn test_generate() -> anyhow::Result<()> {
+        init_logger();
+        let markdown = "# Results *and* costs\n\nSee [the end](#the-end) and \
+            [the site](https://example.com/?a=1&b=2).[^1]\n\n\
+            1. One\n   - Nested **item**\n2. Two\n\n\
+            | Name | Value |\n|------|-------|\n| a | <1> |\n\n\
+            ```Rust\nlet end = \"]]>\";\n```\n\n## The end\n\n~~old~~ `a < b`\n\n\
+            [^1]: A note.\n";
+        let mut document = markdown::Transformer::parse(&Bytes::from(markdown))?;
+        let png = Bytes::from(std::fs::read("test/data/small.png")?);
+        if let Some(Band::Detail(elements)) = document.bands.last_mut() {
+            elements.push(Element::Image(ImageData::new(
+                png.clone(),
+                "Logo".to_string(),
+                "The logo".to_string(),
+                "png".to_string(),
+                String::new(),
+                ImageDimension {
+                    width: Some("120px".to_string()),
+                    height: None,
+                },
+            )));
+            elements.push(Element::admonition(
+                "caution",
+                Some("Careful"),
+                vec![Element::Paragraph {
+                    elements: vec![Element::Text {
+                        text: "Hot.".to_string(),
+                        size: 14,
+                    }],
+                    direction: Default::default(),
+                }],
+            ));
+        }
+
+        let saved = RefCell::new(vec![]);
+        let xhtml = Transformer::generate_with_saver(&document, |bytes, name| {
+            saved.borrow_mut().push((name.to_string(), bytes.clone()));
+            Ok(())
+        })?;
+        let xhtml = std::str::from_utf8(&xhtml)?;
+        assert!(xhtml.starts_with("<h1>Results and costs</h1>\n"));
+        assert!(xhtml.contains(
+            "<p>See <ac:link ac:anchor=\"the-end\"><ac:plain-text-link-body><![CDATA[the end]]>\
+             </ac:plain-text-link-body></ac:link> and \
+             <a href=\"https://example.com/?a=1&amp;b=2\">the site</a>.<sup><ac:link \
+             ac:anchor=\"footnote-1\"><ac:plain-text-link-body><![CDATA[1]]>\
+             </ac:plain-text-link-body></ac:link></sup></p>\n"
+        ));
+        assert!(xhtml.contains("<ol>\n<li>One\n<ul>\n<li>Nested <strong>item</strong></li>\n</ul></li>\n<li>Two</li>\n</ol>\n"));
+        assert!(xhtml.contains(
+            "<table><tbody>\n<tr><th>Name</th><th>Value</th></tr>\n\
+             <tr><td>a</td><td>&lt;1&gt;</td></tr>\n</tbody></table>\n"
+        ));
+        assert!(xhtml.contains(
+            "<ac:structured-macro ac:name=\"code\" ac:schema-version=\"1\">\
+             <ac:parameter ac:name=\"language\">rust</ac:parameter><ac:plain-text-body>\
+             <![CDATA[let end = \"]]]]><![CDATA[>\";]]></ac:plain-text-body></ac:structured-macro>\n"
+        ));
+        assert!(xhtml.contains(
+            "<h2><ac:structured-macro ac:name=\"anchor\" ac:schema-version=\"1\">\
+             <ac:parameter ac:name=\"\">the-end</ac:parameter></ac:structured-macro>The end</h2>\n"
+        ));
+        assert!(xhtml.contains(
+            "<p><span style=\"text-decoration: line-through;\">old</span> <code>a &lt; b</code></p>\n"
+        ));
+        assert!(xhtml.contains(
+            "<p><ac:image ac:alt=\"The logo\" ac:title=\"Logo\" ac:width=\"120\">\
+             <ri:attachment ri:filename=\"image1.png\" /></ac:image></p>\n"
+        ));
+        assert!(xhtml.contains(
+            "<ac:structured-macro ac:name=\"note\" ac:schema-version=\"1\">\
+             <ac:parameter ac:name=\"title\">Careful</ac:parameter><ac:rich-text-body>\n\
+             <p>Hot.</p>\n</ac:rich-text-body></ac:structured-macro>\n"
+        ));
+        assert!(xhtml.ends_with(
+            "<hr />\n<ol>\n<li><ac:structured-macro ac:name=\"anchor\" ac:schema-version=\"1\">\
+             <ac:parameter ac:name=\"\">footnote-1</ac:parameter></ac:structured-macro>\
+             A note.</li>\n</ol>\n"
+        ));
+        assert_eq!(
+            saved.borrow().as_slice(),
+            &[("image1.png".to_string(), png)]
+        );
+        Ok(())
+    }
+}
@@ -1,31 +1,77 @@
 use bytes::Bytes;
-use log::info;
+use log::{info, warn};
 #[cfg(feature = "json")]
 use serde::{Deserialize, Serialize};
 use std::str::FromStr;
-use std::{collections::HashMap, fmt::Debug};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt::Debug,
+};
 use strum::{Display, EnumCount, EnumString, IntoStaticStr, VariantArray};
 use thiserror::Error;
 use wasm_bindgen::prelude::wasm_bindgen;
 
+use crate::ocr::Ocr;
+use crate::report::{conversion_report, ConversionReport};
+
+#[cfg(feature = "asciidoc")]
+use crate::asciidoc;
+#[cfg(feature = "bbcode")]
+use crate::bbcode;
+#[cfg(feature = "confluence")]
+use crate::confluence;
 #[cfg(feature = "csv")]
 use crate::csv;
+#[cfg(feature = "docbook")]
+use crate::docbook;
 #[cfg(feature = "docx")]
 use crate::docx;
+#[cfg(feature = "eml")]
+use crate::eml;
+#[cfg(feature = "epub")]
+use crate::epub;
+#[cfg(feature = "fb2")]
+use crate::fb2;
 #[cfg(feature = "html")]
 use crate::html;
+#[cfg(feature = "icalendar")]
+use crate::icalendar;
+#[cfg(feature = "ipynb")]
+use crate::ipynb;
+#[cfg(feature = "jira")]
+use crate::jira;
 #[cfg(feature = "json")]
 use crate::json;
+#[cfg(feature = "latex")]
+use crate::latex;
+#[cfg(feature = "man")]
+use crate::man;
 #[cfg(feature = "markdown")]
 use crate::markdown;
 #[cfg(feature = "ods")]
 use crate::ods;
+#[cfg(feature = "odt")]
+use crate::odt;
+#[cfg(feature = "org")]
+use crate::org;
 #[cfg(feature = "pdf")]
 use crate::pdf;
+#[cfg(feature = "pptx")]
+use crate::pptx;
+#[cfg(feature = "rst")]
+use crate::rst;
 #[cfg(feature = "rtf")]
 use crate::rtf;
+#[cfg(feature = "svg")]
+use crate::svg;
 #[cfg(feature = "text")]
 use crate::text;
+#[cfg(feature = "typst")]
+use crate::typst;
+#[cfg(feature = "vcard")]
+use crate::vcard;
+#[cfg(feature = "wiki")]
+use crate::wiki;
 #[cfg(feature = "xls")]
 use crate::xls;
 #[cfg(feature = "xlsx")]
@@ -33,15 +79,141 @@ use crate::xlsx;
 #[cfg(feature = "xml")]
 use crate::xml;
 
+/// Unit of a [`Length`]
+#[derive(Default, Debug, Clone, Copy, PartialEq, Display, EnumString)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+#[strum(serialize_all = "lowercase")]
+pub enum LengthUnit {
+    #[default]
+    Mm,
+    Cm,
+    In,
+    /// Typographic point, 1/72 inch
+    Pt,
+    /// CSS pixel, 1/96 inch
+    Px,
+}
+
+impl LengthUnit {
+    fn millimeters(&self) -> f32 {
+        match self {
+            LengthUnit::Mm => 1.0,
+            LengthUnit::Cm => 10.0,
+            LengthUnit::In => 25.4,
+            LengthUnit::Pt => 25.4 / 72.0,
+            LengthUnit::Px => 25.4 / 96.0,
+        }
+    }
+}
+
+/// Length with its unit, written and parsed like `210mm` or `8.5in`.
+///
+/// A number without unit is read as millimeters. Lengths are equal when they
+/// measure the same, whatever their units (`1in == 25.4mm`).
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+pub struct Length {
+    pub value: f32,
+    pub unit: LengthUnit,
+}
+
+impl Length {
+    pub fn new(value: f32, unit: LengthUnit) -> Length {
+        Length { value, unit }
+    }
+
+    pub fn mm(value: f32) -> Length {
+        Length::new(value, LengthUnit::Mm)
+    }
+
+    pub fn cm(value: f32) -> Length {
+        Length::new(value, LengthUnit::Cm)
+    }
+
+    pub fn inches(value: f32) -> Length {
+        Length::new(value, LengthUnit::In)
+    }
+
+    pub fn pt(value: f32) -> Length {
+        Length::new(value, LengthUnit::Pt)
+    }
+
+    pub fn px(value: f32) -> Length {
+        Length::new(value, LengthUnit::Px)
+    }
+
+    /// Same length expressed in another unit
+    pub fn to(&self, unit: LengthUnit) -> Length {
+        Length::new(self.to_mm() / unit.millimeters(), unit)
+    }
+
+    pub fn to_mm(&self) -> f32 {
+        self.value * self.unit.millimeters()
+    }
+
+    pub fn to_pt(&self) -> f32 {
+        self.to(LengthUnit::Pt).value
+    }
+
+    pub fn to_inches(&self) -> f32 {
+        self.to(LengthUnit::In).value
+    }
+}
+
+impl PartialEq for Length {
+    fn eq(&self, other: &Self) -> bool {
+        (self.to_mm() - other.to_mm()).abs() < 0.001
+    }
+}
+
+impl std::fmt::Display for Length {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}", self.value, self.unit)
+    }
+}
+
+impl FromStr for Length {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let unit_start = s.find(|c: char| c.is_ascii_alphabetic()).unwrap_or(s.len());
+        let value: f32 = s[..unit_start]
+            .trim()
+            .parse()
+            .map_err(|_| anyhow::anyhow!("Invalid length: {}", s))?;
+        let unit = match &s[unit_start..] {
+            "" => LengthUnit::Mm,
+            unit => LengthUnit::from_str(unit)
+                .map_err(|_| anyhow::anyhow!("Invalid length unit: {}", unit))?,
+        };
+        Ok(Length::new(value, unit))
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 #[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
 pub struct PageDimensions {
-    pub page_width: f32,
-    pub page_height: f32,
-    pub page_margin_top: f32,
-    pub page_margin_bottom: f32,
-    pub page_margin_left: f32,
-    pub page_margin_right: f32,
+    pub page_width: Length,
+    pub page_height: Length,
+    pub page_margin_top: Length,
+    pub page_margin_bottom: Length,
+    pub page_margin_left: Length,
+    pub page_margin_right: Length,
+}
+
+impl PageDimensions {
+    /// Page of the given size with the default 10mm margins
+    pub fn new(page_width: Length, page_height: Length) -> PageDimensions {
+        PageDimensions {
+            page_width,
+            page_height,
+            page_margin_top: Length::mm(10.0),
+            page_margin_bottom: Length::mm(10.0),
+            page_margin_left: Length::mm(10.0),
+            page_margin_right: Length::mm(10.0),
+        }
+    }
 }
 
 #[derive(Default, Debug, Clone, PartialEq)]
@@ -52,60 +224,160 @@ pub enum PageFormat {
     /// Also utilized by formats that does not have a predefined page format(XML, CSV, JSON...)
     #[default]
     A4,
+    /// A5 format (148 x 210 mm)
+    A5,
     Letter,
     Legal,
     Tabloid,
     Custom(PageDimensions),
 }
 
+/// Preset page sizes accepted by [`Document::with_page_size`]
+pub type PageSize = PageFormat;
+
 impl PageFormat {
     pub fn dimensions(&self) -> PageDimensions {
         match self {
-            PageFormat::A4 => PageDimensions {
-                page_width: 210.0,
-                page_height: 297.0,
-                page_margin_top: 10.0,
-                page_margin_bottom: 10.0,
-                page_margin_left: 10.0,
-                page_margin_right: 10.0,
-            },
-            PageFormat::Letter => PageDimensions {
-                page_width: 216.0,
-                page_height: 279.0,
-                page_margin_top: 10.0,
-                page_margin_bottom: 10.0,
-                page_margin_left: 10.0,
-                page_margin_right: 10.0,
-            },
-            PageFormat::Legal => PageDimensions {
-                page_width: 216.0,
-                page_height: 356.0,
-                page_margin_top: 10.0,
-                page_margin_bottom: 10.0,
-                page_margin_left: 10.0,
-                page_margin_right: 10.0,
-            },
-            PageFormat::Tabloid => PageDimensions {
-                page_width: 279.0,
-                page_height: 432.0,
-                page_margin_top: 10.0,
-                page_margin_bottom: 10.0,
-                page_margin_left: 10.0,
-                page_margin_right: 10.0,
-            },
+            PageFormat::A4 => PageDimensions::new(Length::mm(210.0), Length::mm(297.0)),
+            PageFormat::A5 => PageDimensions::new(Length::mm(148.0), Length::mm(210.0)),
+            PageFormat::Letter => PageDimensions::new(Length::inches(8.5), Length::inches(11.0)),
+            PageFormat::Legal => PageDimensions::new(Length::inches(8.5), Length::inches(14.0)),
+            PageFormat::Tabloid => PageDimensions::new(Length::inches(11.0), Length::inches(17.0)),
             PageFormat::Custom(dimensions) => dimensions.clone(),
         }
     }
 }
 
-#[derive(Default, Debug, Clone, PartialEq)]
+#[derive(Default, Debug, Clone, PartialEq, Display, EnumString)]
 #[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+#[strum(serialize_all = "lowercase")]
 pub enum PageOrientation {
     #[default]
     Portrait,
     Landscape,
 }
 
+/// Base direction of a text (left-to-right like Latin, right-to-left like Arabic
+/// and Hebrew).
+///
+/// `Auto` follows the enclosing element (the document for a paragraph), generators
+/// may still detect right-to-left text with [`TextDirection::of_text`].
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Display, EnumString)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+#[strum(serialize_all = "lowercase")]
+pub enum TextDirection {
+    #[default]
+    Auto,
+    Ltr,
+    Rtl,
+}
+
+impl TextDirection {
+    /// Direction of the first strongly directional character of the text,
+    /// `Auto` for texts made of digits, spaces and punctuation only
+    pub fn of_text(text: &str) -> TextDirection {
+        text.chars()
+            .find_map(|c| match c as u32 {
+                // Hebrew, Arabic, Syriac, Thaana, NKo... and their presentation forms
+                0x0590..=0x08FF | 0xFB1D..=0xFDFF | 0xFE70..=0xFEFF | 0x10800..=0x10FFF => {
+                    Some(TextDirection::Rtl)
+                }
+                _ if c.is_alphabetic() => Some(TextDirection::Ltr),
+                _ => None,
+            })
+            .unwrap_or(TextDirection::Auto)
+    }
+
+    /// This direction, or `other` when it is `Auto`
+    pub fn or(self, other: TextDirection) -> TextDirection {
+        match self {
+            TextDirection::Auto => other,
+            direction => direction,
+        }
+    }
+}
+
+/// Part of the document with its own page setup (like a DOCX section).
+///
+/// A section begins at the detail element with index `start` and lasts until
+/// the next section. Elements before the first section use the page setup of
+/// the document.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+pub struct Section {
+    pub start: usize,
+
+    pub page_format: PageFormat,
+
+    pub orientation: PageOrientation,
+}
+
+impl Section {
+    pub fn new(start: usize, page_format: PageFormat, orientation: PageOrientation) -> Section {
+        Section {
+            start,
+            page_format,
+            orientation,
+        }
+    }
+}
+
+/// Descriptive data of the document, like the frontmatter of a markdown file
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "json", serde(default))]
+pub struct Metadata {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub date: Option<String>,
+    pub description: Option<String>,
+    /// Keywords or tags
+    pub keywords: Vec<String>,
+    /// Other entries: nested tables are flattened to dotted keys (`author.email`) and lists are
+    /// joined with commas
+    pub custom: BTreeMap<String, String>,
+}
+
+impl Metadata {
+    pub fn is_empty(&self) -> bool {
+        self == &Metadata::default()
+    }
+}
+
+/// Field of a fillable form, such as the AcroForm fields of a PDF
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize), serde(default))]
+pub struct FormField {
+    /// Full name, the names of the parent fields joined with dots
+    pub name: String,
+    pub kind: FormFieldKind,
+    /// Text or selected option, `Off` or the state of a checked box or radio button
+    pub value: String,
+    /// Options of a choice, states other than `Off` of a check box or radio buttons
+    pub options: Vec<String>,
+    /// Description of the field shown to the user
+    pub label: String,
+    pub read_only: bool,
+    pub required: bool,
+    /// Largest number of characters of a text
+    pub max_length: Option<usize>,
+    /// Page of the field, from 1
+    pub page: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Display, EnumString, VariantArray)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+#[strum(serialize_all = "kebab-case")]
+pub enum FormFieldKind {
+    #[default]
+    Text,
+    CheckBox,
+    RadioButton,
+    ComboBox,
+    ListBox,
+    Signature,
+}
+
 /// Band is a section of a document(Title, PageHeader, ColumnHeader, Detail, ColumnFooter, PageFooter, Summary).
 ///
 /// Each band contains a list of elements (Text, Table, List, Image, Hyperlink...).
@@ -164,9 +436,58 @@ impl Band {
             Band::Custom(_, e) => e,
         }
     }
+
+    pub fn elements_mut(&mut self) -> &mut Vec<Element> {
+        match self {
+            Band::Title(e) => e,
+            Band::PageHeader(e) => e,
+            Band::ColumnHeader(e) => e,
+            Band::Detail(e) => e,
+            Band::ColumnFooter(e) => e,
+            Band::PageFooter(e) => e,
+            Band::Summary(e) => e,
+            Band::Custom(_, e) => e,
+        }
+    }
+
+    /// Same band type, custom bands also need the same name
+    pub fn same_kind(&self, other: &Band) -> bool {
+        match (self, other) {
+            (Band::Custom(name, _), Band::Custom(other_name, _)) => name == other_name,
+            _ => std::mem::discriminant(self) == std::mem::discriminant(other),
+        }
+    }
+
+    /// Empty band of the same type, used as a key (see [`Document::get_elements_by_band`])
+    pub fn empty(&self) -> Band {
+        match self {
+            Band::Title(_) => Band::Title(vec![]),
+            Band::PageHeader(_) => Band::PageHeader(vec![]),
+            Band::ColumnHeader(_) => Band::ColumnHeader(vec![]),
+            Band::Detail(_) => Band::Detail(vec![]),
+            Band::ColumnFooter(_) => Band::ColumnFooter(vec![]),
+            Band::PageFooter(_) => Band::PageFooter(vec![]),
+            Band::Summary(_) => Band::Summary(vec![]),
+            Band::Custom(name, _) => Band::Custom(name.clone(), vec![]),
+        }
+    }
+
+    /// Band type, or the name of a custom band
+    pub fn name(&self) -> &str {
+        match self {
+            Band::Title(_) => "Title",
+            Band::PageHeader(_) => "PageHeader",
+            Band::ColumnHeader(_) => "ColumnHeader",
+            Band::Detail(_) => "Detail",
+            Band::ColumnFooter(_) => "ColumnFooter",
+            Band::PageFooter(_) => "PageFooter",
+            Band::Summary(_) => "Summary",
+            Band::Custom(name, _) => name,
+        }
+    }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 #[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
 pub struct Document {
     /// Bands are the different sections of a document(Title, PageHeader, ColumnHeader, Detail, ColumnFooter, PageFooter, Summary)
@@ -178,6 +499,20 @@ pub struct Document {
 
     /// Page orientation (Portrait, Landscape) Default is Portrait.
     pub orientation: PageOrientation,
+
+    #[cfg_attr(feature = "json", serde(default))]
+    pub sections: Vec<Section>,
+
+    /// Base text direction of the document, paragraphs may override it
+    #[cfg_attr(feature = "json", serde(default))]
+    pub direction: TextDirection,
+
+    #[cfg_attr(feature = "json", serde(default))]
+    pub metadata: Metadata,
+
+    /// Fields of the fillable form of the document
+    #[cfg_attr(feature = "json", serde(default))]
+    pub form: Vec<FormField>,
 }
 
 impl Document {
@@ -186,6 +521,10 @@ impl Document {
             bands: vec![Band::Detail(elements)],
             page_format: PageFormat::default(),
             orientation: PageOrientation::default(),
+            sections: vec![],
+            direction: TextDirection::default(),
+            metadata: Metadata::default(),
+            form: vec![],
         }
     }
 
@@ -203,119 +542,39 @@ impl Document {
             ],
             page_format,
             orientation: PageOrientation::default(),
+            sections: vec![],
+            direction: TextDirection::default(),
+            metadata: Metadata::default(),
+            form: vec![],
         }
     }
 
-    pub fn parse(input_bytes: &Bytes, document_type: DocumentType) -> anyhow::Result<Document> {
-        let document = match document_type {
-            #[cfg(feature = "markdown")]
-            DocumentType::Markdown => markdown::Transformer::parse(input_bytes)?,
-            #[cfg(not(feature = "markdown"))]
-            DocumentType::Markdown => {
-                return Err(anyhow::anyhow!("Markdown feature is not enabled"))
-            }
-            #[cfg(feature = "html")]
-            DocumentType::HTML => html::Transformer::parse(input_bytes)?,
-            #[cfg(not(feature = "html"))]
-            DocumentType::HTML => return Err(anyhow::anyhow!("HTML feature is not enabled")),
-            #[cfg(feature = "text")]
-            DocumentType::Text => text::Transformer::parse(input_bytes)?,
-            #[cfg(not(feature = "text"))]
-            DocumentType::Text => return Err(anyhow::anyhow!("Text feature is not enabled")),
-            #[cfg(feature = "pdf")]
-            DocumentType::PDF => pdf::Transformer::parse(input_bytes)?,
-            #[cfg(not(feature = "pdf"))]
-            DocumentType::PDF => return Err(anyhow::anyhow!("PDF feature is not enabled")),
-            #[cfg(feature = "json")]
-            DocumentType::Json => json::Transformer::parse(input_bytes)?,
-            #[cfg(not(feature = "json"))]
-            DocumentType::Json => return Err(anyhow::anyhow!("Json feature is not enabled")),
-            #[cfg(feature = "csv")]
-            DocumentType::CSV => csv::Transformer::parse(input_bytes)?,
-            #[cfg(not(feature = "csv"))]
-            DocumentType::CSV => return Err(anyhow::anyhow!("CSV feature is not enabled")),
-            #[cfg(feature = "rtf")]
-            DocumentType::RTF => rtf::Transformer::parse(input_bytes)?,
-            #[cfg(not(feature = "rtf"))]
-            DocumentType::RTF => return Err(anyhow::anyhow!("RTF feature is not enabled")),
-            #[cfg(feature = "docx")]
-            DocumentType::DOCX => docx::Transformer::parse(input_bytes)?,
-            #[cfg(not(feature = "docx"))]
-            DocumentType::DOCX => return Err(anyhow::anyhow!("DOCX feature is not enabled")),
-            #[cfg(feature = "xml")]
-            DocumentType::XML => xml::Transformer::parse(input_bytes)?,
-            #[cfg(not(feature = "xml"))]
-            DocumentType::XML => return Err(anyhow::anyhow!("XML feature is not enabled")),
-            #[cfg(feature = "xls")]
-            DocumentType::XLS => xls::Transformer::parse(input_bytes)?,
-            #[cfg(not(feature = "xls"))]
-            DocumentType::XLS => return Err(anyhow::anyhow!("XLS feature is not enabled")),
-            #[cfg(feature = "xlsx")]
-            DocumentType::XLSX => xlsx::Transformer::parse(input_bytes)?,
-            #[cfg(not(feature = "xlsx"))]
-            DocumentType::XLSX => return Err(anyhow::anyhow!("XLSX feature is not enabled")),
-            #[cfg(feature = "ods")]
-            DocumentType::ODS => ods::Transformer::parse(input_bytes)?,
-            #[cfg(not(feature = "ods"))]
-            DocumentType::ODS => return Err(anyhow::anyhow!("ODS feature is not enabled")),
-        };
-        Ok(document)
-    }
-
-    pub fn generate(&self, document_type: DocumentType) -> anyhow::Result<Bytes> {
-        let output = match document_type {
-            #[cfg(feature = "markdown")]
-            DocumentType::Markdown => markdown::Transformer::generate(self)?,
-            #[cfg(not(feature = "markdown"))]
-            DocumentType::Markdown => {
-                return Err(anyhow::anyhow!("Markdown feature is not enabled"))
-            }
-            #[cfg(feature = "html")]
-            DocumentType::HTML => html::Transformer::generate(self)?,
-            #[cfg(not(feature = "html"))]
-            DocumentType::HTML => return Err(anyhow::anyhow!("HTML feature is not enabled")),
-            #[cfg(feature = "text")]
-            DocumentType::Text => text::Transformer::generate(self)?,
-            #[cfg(not(feature = "text"))]
-            DocumentType::Text => return Err(anyhow::anyhow!("Text feature is not enabled")),
-            #[cfg(feature = "pdf")]
-            DocumentType::PDF => pdf::Transformer::generate(self)?,
-            #[cfg(not(feature = "pdf"))]
-            DocumentType::PDF => return Err(anyhow::anyhow!("PDF feature is not enabled")),
-            #[cfg(feature = "json")]
-            DocumentType::Json => json::Transformer::generate(self)?,
-            #[cfg(not(feature = "json"))]
-            DocumentType::Json => return Err(anyhow::anyhow!("Json feature is not enabled")),
-            #[cfg(feature = "csv")]
-            DocumentType::CSV => csv::Transformer::generate(self)?,
-            #[cfg(not(feature = "csv"))]
-            DocumentType::CSV => return Err(anyhow::anyhow!("CSV feature is not enabled")),
-            #[cfg(feature = "rtf")]
-            DocumentType::RTF => rtf::Transformer::generate(self)?,
-            #[cfg(not(feature = "rtf"))]
-            DocumentType::RTF => return Err(anyhow::anyhow!("RTF feature is not enabled")),
-            #[cfg(feature = "docx")]
-            DocumentType::DOCX => docx::Transformer::generate(self)?,
-            #[cfg(not(feature = "docx"))]
-            DocumentType::DOCX => return Err(anyhow::anyhow!("DOCX feature is not enabled")),
-            #[cfg(feature = "xml")]
-            DocumentType::XML => xml::Transformer::generate(self)?,
-            #[cfg(not(feature = "xml"))]
-            DocumentType::XML => return Err(anyhow::anyhow!("XML feature is not enabled")),
-            #[cfg(feature = "xls")]
-            DocumentType::XLS => xls::Transformer::generate(self)?,
-            #[cfg(not(feature = "xls"))]
-            DocumentType::XLS => return Err(anyhow::anyhow!("XLS feature is not enabled")),
-            #[cfg(feature = "xlsx")]
-            DocumentType::XLSX => xlsx::Transformer::generate(self)?,
-            #[cfg(not(feature = "xlsx"))]
-            DocumentType::XLSX => return Err(anyhow::anyhow!("XLSX feature is not enabled")),
-            #[cfg(feature = "ods")]
-            DocumentType::ODS => ods::Transformer::generate(self)?,
-            #[cfg(not(feature = "ods"))]
-            DocumentType::ODS => return Err(anyhow::anyhow!("ODS feature is not enabled")),
-        };
-        Ok(output)
+    /// Parses with the built-in transformers (see [`TransformerRegistry::builtin`])
+    pub fn parse(input_bytes: &Bytes, document_type: DocumentType) -> crate::Result<Document> {
+        TransformerRegistry::builtin().parse(document_type, input_bytes)
+    }
+
+    /// Parses with the built-in transformers and the given options
+    pub fn parse_with_options(
+        input_bytes: &Bytes,
+        document_type: DocumentType,
+        options: &ParseOptions,
+    ) -> crate::Result<Document> {
+        TransformerRegistry::builtin().parse_with_options(document_type, input_bytes, options)
+    }
+
+    /// Generates with the built-in transformers (see [`TransformerRegistry::builtin`])
+    pub fn generate(&self, document_type: DocumentType) -> crate::Result<Bytes> {
+        TransformerRegistry::builtin().generate(document_type, self)
+    }
+
+    /// Generates with the built-in transformers and the given options
+    pub fn generate_with_options(
+        &self,
+        document_type: DocumentType,
+        options: &GenerateOptions,
+    ) -> crate::Result<Bytes> {
+        TransformerRegistry::builtin().generate_with_options(document_type, self, options)
     }
 
     /// Returns all elements from all bands
@@ -328,10 +587,11 @@ impl Document {
     }
 
     /// Returns all elements from a specific band
+    /// Elements of all the bands of the same kind as `band` (custom bands must also have the same name)
     pub fn get_elements_by_band(&self, band: &Band) -> Vec<&Element> {
         let mut elements = Vec::new();
         for b in &self.bands {
-            if b == band {
+            if b.same_kind(band) {
                 elements.extend(b.elements());
             }
         }
@@ -343,6 +603,535 @@ impl Document {
         self.get_elements_by_band(&Band::Title(Vec::new()))
     }
 
+    /// Text of the title band, or of the first top level header when there is no title
+    pub fn get_title_text(&self) -> String {
+        let title: Vec<&str> = self
+            .bands
+            .iter()
+            .filter(|band| matches!(band, Band::Title(_)))
+            .flat_map(|band| band.elements())
+            .filter_map(|element| match element {
+                Element::Text { text, .. } | Element::Header { text, .. } => Some(text.as_str()),
+                _ => None,
+            })
+            .collect();
+        if !title.is_empty() {
+            return title.join(" ");
+        }
+        self.without_containers()
+            .get_all_elements()
+            .into_iter()
+            .find_map(|element| match element {
+                Element::Header { level: 1, text } => Some(text.clone()),
+                _ => None,
+            })
+            .unwrap_or_default()
+    }
+
+    /// Copy of the document with `Date` and `DocumentTitle` fields and cross references
+    /// replaced by text. Page number fields are kept as is.
+    pub fn resolve_fields(&self) -> Document {
+        struct Values {
+            title: String,
+            date: String,
+            numbering: HashMap<(ReferenceKind, String), String>,
+        }
+
+        fn resolve(element: &Element, values: &Values) -> Element {
+            match element {
+                Element::Field {
+                    kind: FieldKind::Date,
+                } => Element::Text {
+                    text: values.date.clone(),
+                    size: 8,
+                },
+                Element::Field {
+                    kind: FieldKind::DocumentTitle,
+                } => Element::Text {
+                    text: values.title.clone(),
+                    size: 8,
+                },
+                Element::Paragraph {
+                    elements,
+                    direction,
+                } => Element::Paragraph {
+                    elements: elements
+                        .iter()
+                        .map(|element| resolve(element, values))
+                        .collect(),
+                    direction: *direction,
+                },
+                Element::Hyperlink {
+                    elements,
+                    url,
+                    alt,
+                    size,
+                } => Element::Hyperlink {
+                    elements: elements
+                        .iter()
+                        .map(|element| resolve(element, values))
+                        .collect(),
+                    url: url.clone(),
+                    alt: alt.clone(),
+                    size: *size,
+                },
+                Element::Footnote { elements } => Element::Footnote {
+                    elements: elements
+                        .iter()
+                        .map(|element| resolve(element, values))
+                        .collect(),
+                },
+                Element::Styled { style, elements } => Element::Styled {
+                    style: *style,
+                    elements: elements
+                        .iter()
+                        .map(|element| resolve(element, values))
+                        .collect(),
+                },
+                Element::Container { kind, elements } => Element::Container {
+                    kind: *kind,
+                    elements: elements
+                        .iter()
+                        .map(|element| resolve(element, values))
+                        .collect(),
+                },
+                Element::List {
+                    elements,
+                    numbered,
+                    start,
+                    numbering,
+                } => Element::List {
+                    elements: elements
+                        .iter()
+                        .map(|item| ListItem {
+                            element: resolve(&item.element, values),
+                        })
+                        .collect(),
+                    numbered: *numbered,
+                    start: *start,
+                    numbering: *numbering,
+                },
+                Element::Table { headers, rows } => Element::Table {
+                    headers: headers
+                        .iter()
+                        .map(|header| TableHeader {
+                            element: resolve(&header.element, values),
+                            width: header.width,
+                        })
+                        .collect(),
+                    rows: rows
+                        .iter()
+                        .map(|row| TableRow {
+                            cells: row
+                                .cells
+                                .iter()
+                                .map(|cell| TableCell {
+                                    element: resolve(&cell.element, values),
+                                })
+                                .collect(),
+                        })
+                        .collect(),
+                },
+                Element::Attributed {
+                    attributes,
+                    element,
+                } => Element::Attributed {
+                    attributes: attributes.clone(),
+                    element: Box::new(resolve(element, values)),
+                },
+                Element::CrossReference { target_id, kind } => {
+                    let number = values
+                        .numbering
+                        .get(&(*kind, target_id.clone()))
+                        .cloned()
+                        .unwrap_or_else(|| {
+                            warn!("Cross reference to unknown {} '{}'", kind, target_id);
+                            "??".to_string()
+                        });
+                    Element::Text {
+                        text: format!("{} {}", kind.label(), number),
+                        size: 8,
+                    }
+                }
+                element => element.clone(),
+            }
+        }
+
+        let values = Values {
+            title: self.get_title_text(),
+            date: today(),
+            numbering: self.numbering(),
+        };
+        let mut document = self.clone();
+        for band in document.bands.iter_mut() {
+            let elements = band.elements_mut();
+            *elements = elements
+                .iter()
+                .map(|element| resolve(element, &values))
+                .collect();
+        }
+        document
+    }
+
+    /// Numbering pass over the document: numbers of the headers ("1.2"), figures and
+    /// tables in the order they appear, keyed by the kind and id of the target.
+    ///
+    /// Headers are identified by their anchor (see [`header_anchor`]), figures by
+    /// the image title, or `figure-N` when there is none, tables by `table-N`.
+    pub fn numbering(&self) -> HashMap<(ReferenceKind, String), String> {
+        #[derive(Default)]
+        struct Counters {
+            headers: Vec<usize>,
+            figures: usize,
+            tables: usize,
+        }
+
+        fn number(
+            element: &Element,
+            counters: &mut Counters,
+            numbering: &mut HashMap<(ReferenceKind, String), String>,
+        ) {
+            match element {
+                Element::Header { level, text } => {
+                    let level = (*level).max(1) as usize;
+                    counters.headers.resize(level, 0);
+                    counters.headers[level - 1] += 1;
+                    let label = counters
+                        .headers
+                        .iter()
+                        .map(|n| n.to_string())
+                        .collect::<Vec<_>>()
+                        .join(".");
+                    numbering.insert((ReferenceKind::Section, header_anchor(text)), label);
+                }
+                Element::Image(image) => {
+                    counters.figures += 1;
+                    let id = if image.title().is_empty() {
+                        format!("figure-{}", counters.figures)
+                    } else {
+                        image.title().to_string()
+                    };
+                    numbering.insert((ReferenceKind::Figure, id), counters.figures.to_string());
+                }
+                Element::Table { headers, rows } => {
+                    counters.tables += 1;
+                    numbering.insert(
+                        (ReferenceKind::Table, format!("table-{}", counters.tables)),
+                        counters.tables.to_string(),
+                    );
+                    for header in headers {
+                        number(&header.element, counters, numbering);
+                    }
+                    for row in rows {
+                        for cell in &row.cells {
+                            number(&cell.element, counters, numbering);
+                        }
+                    }
+                }
+                Element::Paragraph { elements, .. }
+                | Element::Hyperlink { elements, .. }
+                | Element::Footnote { elements }
+                | Element::Styled { elements, .. }
+                | Element::Container { elements, .. } => {
+                    for element in elements {
+                        number(element, counters, numbering);
+                    }
+                }
+                Element::List { elements, .. } => {
+                    for item in elements {
+                        number(&item.element, counters, numbering);
+                    }
+                }
+                Element::Attributed { element, .. } => number(element, counters, numbering),
+                _ => {}
+            }
+        }
+
+        let mut counters = Counters::default();
+        let mut numbering = HashMap::new();
+        for element in self.get_all_elements() {
+            number(element, &mut counters, &mut numbering);
+        }
+        numbering
+    }
+
+    /// Copy of the document where [`Element::Attributed`] elements are replaced by the
+    /// elements they wrap, for generators that do not use the attributes
+    pub fn without_attributes(&self) -> Document {
+        fn strip(element: &Element) -> Element {
+            match element {
+                Element::Attributed { element, .. } => strip(element),
+                Element::Paragraph {
+                    elements,
+                    direction,
+                } => Element::Paragraph {
+                    elements: elements.iter().map(strip).collect(),
+                    direction: *direction,
+                },
+                Element::Hyperlink {
+                    elements,
+                    url,
+                    alt,
+                    size,
+                } => Element::Hyperlink {
+                    elements: elements.iter().map(strip).collect(),
+                    url: url.clone(),
+                    alt: alt.clone(),
+                    size: *size,
+                },
+                Element::Footnote { elements } => Element::Footnote {
+                    elements: elements.iter().map(strip).collect(),
+                },
+                Element::Styled { style, elements } => Element::Styled {
+                    style: *style,
+                    elements: elements.iter().map(strip).collect(),
+                },
+                Element::Container { kind, elements } => Element::Container {
+                    kind: *kind,
+                    elements: elements.iter().map(strip).collect(),
+                },
+                Element::List {
+                    elements,
+                    numbered,
+                    start,
+                    numbering,
+                } => Element::List {
+                    elements: elements
+                        .iter()
+                        .map(|item| ListItem {
+                            element: strip(&item.element),
+                        })
+                        .collect(),
+                    numbered: *numbered,
+                    start: *start,
+                    numbering: *numbering,
+                },
+                Element::Table { headers, rows } => Element::Table {
+                    headers: headers
+                        .iter()
+                        .map(|header| TableHeader {
+                            element: strip(&header.element),
+                            width: header.width,
+                        })
+                        .collect(),
+                    rows: rows
+                        .iter()
+                        .map(|row| TableRow {
+                            cells: row
+                                .cells
+                                .iter()
+                                .map(|cell| TableCell {
+                                    element: strip(&cell.element),
+                                })
+                                .collect(),
+                        })
+                        .collect(),
+                },
+                element => element.clone(),
+            }
+        }
+
+        let mut document = self.clone();
+        for band in document.bands.iter_mut() {
+            let elements = band.elements_mut();
+            *elements = elements.iter().map(strip).collect();
+        }
+        document
+    }
+
+    /// Copy of the document where [`Element::Container`] elements are replaced by the blocks
+    /// they group, for generators without containers
+    pub fn without_containers(&self) -> Document {
+        fn flatten(element: &Element, blocks: &mut Vec<Element>) {
+            match element {
+                Element::Container { elements, .. } => {
+                    for element in elements {
+                        flatten(element, blocks);
+                    }
+                }
+                Element::List {
+                    elements,
+                    numbered,
+                    start,
+                    numbering,
+                } => blocks.push(Element::List {
+                    elements: elements
+                        .iter()
+                        .flat_map(|item| {
+                            let mut items = vec![];
+                            flatten(&item.element, &mut items);
+                            items.into_iter().map(|element| ListItem { element })
+                        })
+                        .collect(),
+                    numbered: *numbered,
+                    start: *start,
+                    numbering: *numbering,
+                }),
+                Element::Attributed {
+                    attributes,
+                    element,
+                } => {
+                    let mut wrapped = vec![];
+                    flatten(element, &mut wrapped);
+                    match <[Element; 1]>::try_from(wrapped) {
+                        Ok([element]) => blocks.push(Element::Attributed {
+                            attributes: attributes.clone(),
+                            element: Box::new(element),
+                        }),
+                        Err(wrapped) => blocks.extend(wrapped),
+                    }
+                }
+                element => blocks.push(element.clone()),
+            }
+        }
+
+        let mut document = self.clone();
+        for band in document.bands.iter_mut() {
+            let elements = band.elements_mut();
+            let mut blocks = vec![];
+            for element in elements.iter() {
+                flatten(element, &mut blocks);
+            }
+            *elements = blocks;
+        }
+        document
+    }
+
+    /// Anchors of the internal links (`#anchor`) of the document, the targets a generator
+    /// writes with an explicit id
+    pub fn link_targets(&self) -> HashSet<String> {
+        fn visit(element: &Element, targets: &mut HashSet<String>) {
+            if let Element::Hyperlink { url, .. } = element {
+                if let Some(anchor) = url.strip_prefix('#') {
+                    targets.insert(anchor.to_string());
+                }
+            }
+            for child in element.children() {
+                visit(child, targets);
+            }
+        }
+
+        let mut targets = HashSet::new();
+        for band in &self.bands {
+            for element in band.elements() {
+                visit(element, &mut targets);
+            }
+        }
+        targets
+    }
+
+    /// Copy of the document where [`Element::Media`] elements are replaced by links to their
+    /// source (see [`Element::media_link`]), for generators without embedded media
+    pub fn without_media(&self) -> Document {
+        fn replace(element: &mut Element) {
+            if let Some(link) = element.media_link() {
+                *element = link;
+            }
+            for child in element.children_mut() {
+                replace(child);
+            }
+        }
+
+        let mut document = self.clone();
+        for band in document.bands.iter_mut() {
+            band.elements_mut().iter_mut().for_each(replace);
+        }
+        document
+    }
+
+    /// Copy of the document where [`Element::Math`] elements are replaced by their TeX source
+    /// (see [`Element::math_code`]), for generators without math. A display formula in the
+    /// text of a paragraph becomes inline code, a code block cannot be in a paragraph
+    pub fn without_math(&self) -> Document {
+        fn replace(element: &mut Element, inline: bool) {
+            match element.math_code() {
+                Some(Element::CodeBlock { code, .. }) if inline => {
+                    *element = Element::InlineCode { text: code }
+                }
+                Some(code) => *element = code,
+                None => {}
+            }
+            let inline = matches!(
+                element,
+                Element::Paragraph { .. } | Element::Styled { .. } | Element::Hyperlink { .. }
+            );
+            for child in element.children_mut() {
+                replace(child, inline);
+            }
+        }
+
+        let mut document = self.clone();
+        for band in document.bands.iter_mut() {
+            band.elements_mut()
+                .iter_mut()
+                .for_each(|element| replace(element, false));
+        }
+        document
+    }
+
+    /// Copy of the document where [`Element::Drawing`] elements are replaced by their image
+    /// (see [`Element::drawing_image`]), for generators without vector graphics
+    pub fn without_drawings(&self) -> Document {
+        fn replace(element: &mut Element) {
+            if let Some(image) = element.drawing_image() {
+                *element = image;
+            }
+            for child in element.children_mut() {
+                replace(child);
+            }
+        }
+
+        let mut document = self.clone();
+        for band in document.bands.iter_mut() {
+            band.elements_mut().iter_mut().for_each(replace);
+        }
+        document
+    }
+
+    /// Copy of the document where the bare `http://` and `https://` URLs of the text are
+    /// links, the text of links and code is left as is
+    pub fn link_urls(&self) -> Document {
+        fn link(element: &mut Element) {
+            match element {
+                Element::Hyperlink { .. } | Element::Header { .. } => {}
+                Element::Text { text, size } => {
+                    let elements = split_urls(text, *size);
+                    if elements.len() > 1 || matches!(elements[..], [Element::Hyperlink { .. }]) {
+                        *element = Element::Paragraph {
+                            elements,
+                            direction: TextDirection::default(),
+                        };
+                    }
+                }
+                Element::Paragraph { elements, .. }
+                | Element::Footnote { elements }
+                | Element::Styled { elements, .. } => {
+                    *elements = std::mem::take(elements)
+                        .into_iter()
+                        .flat_map(|mut element| match &element {
+                            Element::Text { text, size } => split_urls(text, *size),
+                            _ => {
+                                link(&mut element);
+                                vec![element]
+                            }
+                        })
+                        .collect();
+                }
+                element => {
+                    for child in element.children_mut() {
+                        link(child);
+                    }
+                }
+            }
+        }
+
+        let mut document = self.clone();
+        for band in document.bands.iter_mut() {
+            band.elements_mut().iter_mut().for_each(link);
+        }
+        document
+    }
+
     /// Returns all elements from the page header band
     pub fn get_page_header(&self) -> Vec<&Element> {
         self.get_elements_by_band(&Band::PageHeader(Vec::new()))
@@ -383,6 +1172,12 @@ impl Document {
         self.bands.clone()
     }
 
+    /// Sets the page size of the document, like `Document::new(elements).with_page_size(PageSize::Letter)`
+    pub fn with_page_size(mut self, page_size: PageSize) -> Document {
+        self.page_format = page_size;
+        self
+    }
+
     pub fn set_page_format(&mut self, page_format: PageFormat) {
         self.page_format = page_format;
     }
@@ -391,48 +1186,304 @@ impl Document {
         self.orientation = orientation;
     }
 
-    pub fn set_title(&mut self, elements: Vec<Element>) {
-        self.bands.push(Band::Title(elements));
+    pub fn get_sections(&self) -> &Vec<Section> {
+        &self.sections
     }
 
-    pub fn set_page_header(&mut self, elements: Vec<Element>) {
-        self.bands.push(Band::PageHeader(elements));
+    /// Fields of the fillable form, read from PDF forms
+    pub fn form_fields(&self) -> &[FormField] {
+        &self.form
     }
 
-    pub fn set_column_header(&mut self, elements: Vec<Element>) {
-        self.bands.push(Band::ColumnHeader(elements));
+    /// Sets the value of a form field, such as `Off` or the state of a check box. Fails when
+    /// the field is missing or read-only, or the value is not one of the options.
+    pub fn set_form_value(&mut self, name: &str, value: &str) -> crate::Result<()> {
+        let field = self
+            .form
+            .iter_mut()
+            .find(|field| field.name == name)
+            .ok_or_else(|| anyhow::anyhow!("No form field {}", name))?;
+        if field.read_only {
+            return Err(anyhow::anyhow!("Form field {} is read-only", name).into());
+        }
+        let valid = match field.kind {
+            FormFieldKind::CheckBox | FormFieldKind::RadioButton => {
+                value == "Off" || field.options.iter().any(|option| option == value)
+            }
+            // combo boxes may be editable
+            FormFieldKind::ListBox => field.options.iter().any(|option| option == value),
+            FormFieldKind::Text => field
+                .max_length
+                .is_none_or(|max_length| value.chars().count() <= max_length),
+            FormFieldKind::ComboBox => true,
+            FormFieldKind::Signature => false,
+        };
+        if !valid {
+            return Err(anyhow::anyhow!("Invalid value {:?} of form field {}", value, name).into());
+        }
+        field.value = value.to_string();
+        Ok(())
     }
 
-    pub fn set_detail(&mut self, elements: Vec<Element>) {
-        self.bands.push(Band::Detail(elements));
+    /// Adds a section, keeping the sections ordered by their start element.
+    /// A section starting at the same element replaces the existing one.
+    pub fn add_section(&mut self, section: Section) {
+        match self
+            .sections
+            .binary_search_by_key(&section.start, |s| s.start)
+        {
+            Ok(index) => self.sections[index] = section,
+            Err(index) => self.sections.insert(index, section),
+        }
     }
 
-    pub fn set_column_footer(&mut self, elements: Vec<Element>) {
-        self.bands.push(Band::ColumnFooter(elements));
+    /// Page setup in effect for the detail element with the given index
+    pub fn page_setup_at(&self, index: usize) -> (&PageFormat, &PageOrientation) {
+        self.sections
+            .iter()
+            .rev()
+            .find(|section| section.start <= index)
+            .map(|section| (&section.page_format, &section.orientation))
+            .unwrap_or((&self.page_format, &self.orientation))
     }
 
-    pub fn set_page_footer(&mut self, elements: Vec<Element>) {
-        self.bands.push(Band::PageFooter(elements));
-    }
+    /// Splits the document before each header of level `level` or above (e.g. chapters).
+    ///
+    /// Content before the first such header makes a document of its own. Each part keeps the
+    /// page header and footer bands, the title goes to the first part and the summary to the
+    /// last one. Sections are carried over, the page setup of a part is the one in effect
+    /// where it starts.
+    pub fn split_by_headers(&self, level: u8) -> Vec<Document> {
+        let detail = self.get_detail();
+        let mut starts: Vec<usize> = detail
+            .iter()
+            .enumerate()
+            .filter(|(index, element)| {
+                *index > 0 && matches!(element, Element::Header { level: l, .. } if *l <= level)
+            })
+            .map(|(index, _)| index)
+            .collect();
+        starts.insert(0, 0);
+        let ends = starts.iter().skip(1).copied().chain([detail.len()]);
+        let last = starts.len() - 1;
 
-    pub fn set_summary(&mut self, elements: Vec<Element>) {
-        self.bands.push(Band::Summary(elements));
+        starts
+            .iter()
+            .zip(ends)
+            .enumerate()
+            .map(|(part, (&start, end))| {
+                let mut elements = Some(detail[start..end].iter().map(|&e| e.clone()).collect());
+                let bands = self
+                    .bands
+                    .iter()
+                    .filter_map(|band| match band {
+                        Band::Detail(_) => elements.take().map(Band::Detail),
+                        Band::Title(_) if part > 0 => None,
+                        Band::Summary(_) if part < last => None,
+                        _ => Some(band.clone()),
+                    })
+                    .collect();
+                let (page_format, orientation) = self.page_setup_at(start);
+                Document {
+                    bands,
+                    page_format: page_format.clone(),
+                    orientation: orientation.clone(),
+                    sections: self
+                        .sections
+                        .iter()
+                        .filter(|section| section.start > start && section.start < end)
+                        .map(|section| Section {
+                            start: section.start - start,
+                            ..section.clone()
+                        })
+                        .collect(),
+                    direction: self.direction,
+                    metadata: self.metadata.clone(),
+                    form: self.form.clone(),
+                }
+            })
+            .collect()
     }
 
-    pub fn set_custom_band(&mut self, name: String, elements: Vec<Element>) {
-        self.bands.push(Band::Custom(name, elements));
-    }
+    /// Concatenates the detail bands of the documents.
+    ///
+    /// The other bands and the page setup come from the first document, a band missing in
+    /// the first document is taken from the next one having it. Documents with another page
+    /// setup start a new section, and their paragraphs keep their text direction.
+    pub fn concat(documents: &[Document]) -> Document {
+        let Some(first) = documents.first() else {
+            return Document::new(vec![]);
+        };
+        let mut result = first.clone();
+        let position = result
+            .bands
+            .iter()
+            .position(|band| matches!(band, Band::Detail(_)))
+            .unwrap_or(result.bands.len());
+        result.bands.retain(|band| !matches!(band, Band::Detail(_)));
+        let mut detail: Vec<Element> = vec![];
 
-    /// Adds an element to the detail band
-    /// This is useful when you want to add elements to the document without specifying the band
-    /// The detail band is the most common band and is used to display the main content of the document of any type
-    pub fn add_detail(&mut self, element: Element) {
-        for band in &mut self.bands {
-            if let Band::Detail(e) = band {
-                e.push(element.clone())
+        for document in documents {
+            let offset = detail.len();
+            let (page_format, orientation) = document.page_setup_at(0);
+            if offset > 0 && result.page_setup_at(offset) != (page_format, orientation) {
+                result.add_section(Section::new(
+                    offset,
+                    page_format.clone(),
+                    orientation.clone(),
+                ));
             }
-        }
-    }
+            for section in &document.sections {
+                result.add_section(Section {
+                    start: section.start + offset,
+                    ..section.clone()
+                });
+            }
+
+            detail.extend(
+                document
+                    .get_detail()
+                    .into_iter()
+                    .map(|element| match element {
+                        Element::Paragraph {
+                            elements,
+                            direction: TextDirection::Auto,
+                        } if document.direction != result.direction => Element::Paragraph {
+                            elements: elements.clone(),
+                            direction: document.direction,
+                        },
+                        _ => element.clone(),
+                    }),
+            );
+
+            for band in &document.bands {
+                if !matches!(band, Band::Detail(_))
+                    && !result.bands.iter().any(|b| b.same_kind(band))
+                {
+                    result.bands.push(band.clone());
+                }
+            }
+        }
+        result.bands.insert(position, Band::Detail(detail));
+        result
+    }
+
+    /// Builds the table of contents from the document headers up to `max_level`.
+    ///
+    /// The result is a list of links to the header anchors (see [`header_anchor`]), or to
+    /// the `id` attribute of an attributed header, deeper headers go to nested lists.
+    pub fn build_toc(&self, max_level: u8) -> Element {
+        fn toc_list(elements: Vec<ListItem>) -> Element {
+            Element::List {
+                elements,
+                numbered: false,
+                start: 1,
+                numbering: NumberingStyle::default(),
+            }
+        }
+
+        fn header(element: &Element) -> Option<(u8, &String, String)> {
+            match element {
+                Element::Header { level, text } => Some((*level, text, header_anchor(text))),
+                Element::Attributed {
+                    attributes,
+                    element,
+                } => header(element).map(|(level, text, anchor)| {
+                    (level, text, attributes.get("id").cloned().unwrap_or(anchor))
+                }),
+                _ => None,
+            }
+        }
+
+        let document = self.without_containers();
+        let headers: Vec<(u8, &String, String)> = document
+            .get_all_elements()
+            .into_iter()
+            .filter_map(header)
+            .filter(|(level, _, _)| *level <= max_level)
+            .collect();
+        let base_level = headers
+            .iter()
+            .map(|(level, _, _)| *level)
+            .min()
+            .unwrap_or(1);
+
+        let mut stack: Vec<(u8, Vec<ListItem>)> = vec![(base_level, vec![])];
+        for (level, text, anchor) in headers {
+            while level > stack.last().unwrap().0 {
+                let next_level = stack.last().unwrap().0 + 1;
+                stack.push((next_level, vec![]));
+            }
+            while level < stack.last().unwrap().0 {
+                let (_, items) = stack.pop().unwrap();
+                stack.last_mut().unwrap().1.push(ListItem {
+                    element: toc_list(items),
+                });
+            }
+            stack.last_mut().unwrap().1.push(ListItem {
+                element: Element::Hyperlink {
+                    elements: vec![Element::Text {
+                        text: text.clone(),
+                        size: 8,
+                    }],
+                    url: format!("#{}", anchor),
+                    alt: text.clone(),
+                    size: 8,
+                },
+            });
+        }
+        while stack.len() > 1 {
+            let (_, items) = stack.pop().unwrap();
+            stack.last_mut().unwrap().1.push(ListItem {
+                element: toc_list(items),
+            });
+        }
+
+        toc_list(stack.pop().unwrap().1)
+    }
+
+    pub fn set_title(&mut self, elements: Vec<Element>) {
+        self.bands.push(Band::Title(elements));
+    }
+
+    pub fn set_page_header(&mut self, elements: Vec<Element>) {
+        self.bands.push(Band::PageHeader(elements));
+    }
+
+    pub fn set_column_header(&mut self, elements: Vec<Element>) {
+        self.bands.push(Band::ColumnHeader(elements));
+    }
+
+    pub fn set_detail(&mut self, elements: Vec<Element>) {
+        self.bands.push(Band::Detail(elements));
+    }
+
+    pub fn set_column_footer(&mut self, elements: Vec<Element>) {
+        self.bands.push(Band::ColumnFooter(elements));
+    }
+
+    pub fn set_page_footer(&mut self, elements: Vec<Element>) {
+        self.bands.push(Band::PageFooter(elements));
+    }
+
+    pub fn set_summary(&mut self, elements: Vec<Element>) {
+        self.bands.push(Band::Summary(elements));
+    }
+
+    pub fn set_custom_band(&mut self, name: String, elements: Vec<Element>) {
+        self.bands.push(Band::Custom(name, elements));
+    }
+
+    /// Adds an element to the detail band
+    /// This is useful when you want to add elements to the document without specifying the band
+    /// The detail band is the most common band and is used to display the main content of the document of any type
+    pub fn add_detail(&mut self, element: Element) {
+        for band in &mut self.bands {
+            if let Band::Detail(e) = band {
+                e.push(element.clone())
+            }
+        }
+    }
 
     /// Just a wrapper around add_detail
     /// This is useful when you want to add elements to the document without specifying the band
@@ -497,21 +1548,1092 @@ impl Document {
     pub fn remove_all_bands(&mut self) {
         self.bands.clear();
     }
+
+    /// Substitutes `replacement` (which may refer to capture groups, e.g. `$1`) for every match
+    /// of `pattern` in the texts of the document, returns the number of replacements
+    #[cfg(feature = "regex")]
+    pub fn replace_text(&mut self, pattern: &regex::Regex, replacement: &str) -> usize {
+        fn replace(element: &mut Element, pattern: &regex::Regex, replacement: &str) -> usize {
+            match element {
+                Element::Text { text, .. } | Element::Header { text, .. } => {
+                    let count = pattern.find_iter(text).count();
+                    if count > 0 {
+                        *text = pattern.replace_all(text, replacement).into_owned();
+                    }
+                    count
+                }
+                _ => element
+                    .children_mut()
+                    .into_iter()
+                    .map(|child| replace(child, pattern, replacement))
+                    .sum(),
+            }
+        }
+
+        self.bands
+            .iter_mut()
+            .flat_map(|band| band.elements_mut().iter_mut())
+            .map(|element| replace(element, pattern, replacement))
+            .sum()
+    }
+
+    /// Lint pass over the document: broken internal links and cross references, empty headers,
+    /// tables with rows of another width than the header, images without data and header
+    /// levels skipping a level
+    pub fn validate(&self) -> Vec<ValidationWarning> {
+        fn visit<'a>(
+            element: &'a Element,
+            path: ElementPath,
+            elements: &mut Vec<(ElementPath, &'a Element)>,
+        ) {
+            let children = element.children();
+            elements.push((path.clone(), element));
+            for (index, child) in children.into_iter().enumerate() {
+                visit(child, path.child(index), elements);
+            }
+        }
+        let mut elements = vec![];
+        for (band, b) in self.bands.iter().enumerate() {
+            for (index, element) in b.elements().iter().enumerate() {
+                visit(element, ElementPath::new(band, vec![index]), &mut elements);
+            }
+        }
+
+        let numbering = self.numbering();
+        let mut anchors: Vec<String> = vec![];
+        for (_, element) in &elements {
+            match element {
+                Element::Header { text, .. } => anchors.push(header_anchor(text)),
+                Element::Bookmark { id, .. } => anchors.push(id.clone()),
+                Element::Attributed { attributes, .. } => {
+                    anchors.extend(attributes.get("id").cloned())
+                }
+                _ => {}
+            }
+        }
+
+        let mut warnings = vec![];
+        let mut last_level: Option<u8> = None;
+        for (path, element) in elements {
+            match element {
+                Element::Header { level, text } => {
+                    if text.trim().is_empty() {
+                        warnings.push(ValidationWarning::EmptyHeader { path: path.clone() });
+                    }
+                    if let Some(last) = last_level {
+                        if *level > last + 1 {
+                            warnings.push(ValidationWarning::HeaderLevelJump {
+                                path: path.clone(),
+                                from: last,
+                                to: *level,
+                            });
+                        }
+                    }
+                    last_level = Some(*level);
+                }
+                Element::Hyperlink { url, .. } => {
+                    if let Some(anchor) = url.strip_prefix('#') {
+                        if !anchors.iter().any(|a| a == anchor) {
+                            warnings.push(ValidationWarning::BrokenLink {
+                                path,
+                                target: url.clone(),
+                            });
+                        }
+                    }
+                }
+                Element::CrossReference { target_id, kind }
+                    if !numbering.contains_key(&(*kind, target_id.clone())) =>
+                {
+                    warnings.push(ValidationWarning::BrokenLink {
+                        path,
+                        target: target_id.clone(),
+                    });
+                }
+                Element::Table { headers, rows } => {
+                    let expected = if headers.is_empty() {
+                        rows.first().map_or(0, |row| row.cells.len())
+                    } else {
+                        headers.len()
+                    };
+                    for (row, cells) in rows.iter().enumerate() {
+                        if cells.cells.len() != expected {
+                            warnings.push(ValidationWarning::InconsistentTable {
+                                path: path.clone(),
+                                row,
+                                expected,
+                                found: cells.cells.len(),
+                            });
+                        }
+                    }
+                }
+                Element::Image(image) if image.bytes().is_empty() => {
+                    warnings.push(ValidationWarning::MissingImageData { path })
+                }
+                _ => {}
+            }
+        }
+        warnings
+    }
+
+    /// Same as [`Document::generate`], but fails with a [`ValidationError`] when
+    /// [`Document::validate`] returns warnings
+    pub fn generate_validated(&self, document_type: DocumentType) -> crate::Result<Bytes> {
+        let warnings = self.validate();
+        if !warnings.is_empty() {
+            return Err(ValidationError { warnings }.into());
+        }
+        self.generate(document_type)
+    }
+
+    /// Same as [`Document::generate`], with the list of the elements and attributes
+    /// the target format cannot represent
+    pub fn generate_with_report(
+        &self,
+        document_type: DocumentType,
+    ) -> crate::Result<(Bytes, ConversionReport)> {
+        let bytes = self.generate(document_type)?;
+        Ok((bytes, conversion_report(self, document_type)))
+    }
+
+    /// Word, character and sentence counts, number of elements of each type and reading time
+    pub fn statistics(&self) -> Statistics {
+        fn census(element: &Element, elements: &mut BTreeMap<String, usize>) {
+            let kind: &'static str = element.into();
+            *elements.entry(kind.to_string()).or_default() += 1;
+            for child in element.children() {
+                census(child, elements);
+            }
+        }
+
+        let mut statistics = Statistics::default();
+        for element in self.get_all_elements() {
+            census(element, &mut statistics.elements);
+            let text = element.plain_text();
+            statistics.words += text.split_whitespace().count();
+            statistics.characters += text.chars().filter(|c| *c != '\n').count();
+            statistics.characters_without_spaces +=
+                text.chars().filter(|c| !c.is_whitespace()).count();
+            statistics.sentences += text
+                .split(['.', '!', '?', '\n'])
+                .filter(|sentence| sentence.chars().any(char::is_alphanumeric))
+                .count();
+        }
+        statistics.images = statistics
+            .elements
+            .get("Image")
+            .copied()
+            .unwrap_or_default();
+        statistics.reading_time_minutes = statistics.words.div_ceil(WORDS_PER_MINUTE);
+        statistics
+    }
+
+    /// Paths of the innermost elements whose text contains `query`
+    ///
+    /// A match spanning several children (e.g. two texts of a paragraph) returns the parent.
+    pub fn find(&self, query: &str) -> Vec<ElementPath> {
+        fn search(element: &Element, path: ElementPath, query: &str, found: &mut Vec<ElementPath>) {
+            if !element.plain_text().contains(query) {
+                return;
+            }
+            let count = found.len();
+            for (index, child) in element.children().into_iter().enumerate() {
+                search(child, path.child(index), query, found);
+            }
+            if found.len() == count {
+                found.push(path);
+            }
+        }
+
+        let mut found = vec![];
+        for (band, b) in self.bands.iter().enumerate() {
+            for (index, element) in b.elements().iter().enumerate() {
+                search(
+                    element,
+                    ElementPath::new(band, vec![index]),
+                    query,
+                    &mut found,
+                );
+            }
+        }
+        found
+    }
+
+    pub fn get(&self, path: &ElementPath) -> Option<&Element> {
+        let (first, rest) = path.indices.split_first()?;
+        let mut element = self.bands.get(path.band)?.elements().get(*first)?;
+        for index in rest {
+            element = element.children().into_iter().nth(*index)?;
+        }
+        Some(element)
+    }
+
+    pub fn get_mut(&mut self, path: &ElementPath) -> Option<&mut Element> {
+        let (first, rest) = path.indices.split_first()?;
+        let mut element = self
+            .bands
+            .get_mut(path.band)?
+            .elements_mut()
+            .get_mut(*first)?;
+        for index in rest {
+            element = element.children_mut().into_iter().nth(*index)?;
+        }
+        Some(element)
+    }
+}
+
+/// Problem found by [`Document::validate`]
+#[derive(Debug, Clone, PartialEq, Error)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+pub enum ValidationWarning {
+    /// Link to an anchor or cross reference to a target missing from the document
+    #[error("{path}: broken link to {target}")]
+    BrokenLink { path: ElementPath, target: String },
+    #[error("{path}: empty header")]
+    EmptyHeader { path: ElementPath },
+    /// Row with another number of cells than the table header (or the first row)
+    #[error("{path}: row {row} has {found} cells instead of {expected}")]
+    InconsistentTable {
+        path: ElementPath,
+        row: usize,
+        expected: usize,
+        found: usize,
+    },
+    #[error("{path}: image without data")]
+    MissingImageData { path: ElementPath },
+    /// Header more than one level deeper than the previous one
+    #[error("{path}: header level jumps from {from} to {to}")]
+    HeaderLevelJump { path: ElementPath, from: u8, to: u8 },
+}
+
+#[derive(Debug, Error)]
+#[error("Invalid document: {}", warnings.iter().map(|w| w.to_string()).collect::<Vec<_>>().join("; "))]
+pub struct ValidationError {
+    pub warnings: Vec<ValidationWarning>,
+}
+
+/// Average silent reading speed used for [`Statistics::reading_time_minutes`]
+pub const WORDS_PER_MINUTE: usize = 200;
+
+/// Counts returned by [`Document::statistics`]
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+pub struct Statistics {
+    pub words: usize,
+    pub characters: usize,
+    pub characters_without_spaces: usize,
+    /// Sentences, headers and list items
+    pub sentences: usize,
+    /// Number of elements of each type (`Paragraph`, `Text`...), nested elements included
+    pub elements: BTreeMap<String, usize>,
+    pub images: usize,
+    /// Estimated reading time, rounded up
+    pub reading_time_minutes: usize,
+}
+
+impl std::fmt::Display for Statistics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Words: {}", self.words)?;
+        writeln!(f, "Characters: {}", self.characters)?;
+        writeln!(
+            f,
+            "Characters (no spaces): {}",
+            self.characters_without_spaces
+        )?;
+        writeln!(f, "Sentences: {}", self.sentences)?;
+        writeln!(f, "Images: {}", self.images)?;
+        writeln!(f, "Reading time: {} min", self.reading_time_minutes)?;
+        writeln!(f, "Elements:")?;
+        for (kind, count) in &self.elements {
+            writeln!(f, "  {}: {}", kind, count)?;
+        }
+        Ok(())
+    }
+}
+
+/// Address of an element in a [`Document`]: index of the band, index in the band, then
+/// index among the children of each nested element (see [`Element::children`]).
+///
+/// Written as `band/index/child/...`, e.g. `0/3/1` is the second child of the fourth element
+/// of the first band.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+pub struct ElementPath {
+    pub band: usize,
+    pub indices: Vec<usize>,
+}
+
+impl ElementPath {
+    pub fn new(band: usize, indices: Vec<usize>) -> ElementPath {
+        ElementPath { band, indices }
+    }
+
+    /// Path of the child at `index`
+    pub fn child(&self, index: usize) -> ElementPath {
+        let mut indices = self.indices.clone();
+        indices.push(index);
+        ElementPath::new(self.band, indices)
+    }
+
+    /// Path of the parent element, `None` for a top level element
+    pub fn parent(&self) -> Option<ElementPath> {
+        if self.indices.len() < 2 {
+            return None;
+        }
+        let mut indices = self.indices.clone();
+        indices.pop();
+        Some(ElementPath::new(self.band, indices))
+    }
+}
+
+impl std::fmt::Display for ElementPath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.band)?;
+        for index in &self.indices {
+            write!(f, "/{}", index)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for ElementPath {
+    type Err = std::num::ParseIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.trim_matches('/').split('/');
+        let band = parts.next().unwrap_or_default().parse()?;
+        let indices = parts.map(str::parse).collect::<Result<_, _>>()?;
+        Ok(ElementPath::new(band, indices))
+    }
+}
+
+/// Current UTC date in the `YYYY-MM-DD` form
+pub(crate) fn today() -> String {
+    let seconds = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default();
+    // days to civil date, see http://howardhinnant.github.io/date_algorithms.html
+    let days = (seconds / 86_400) as i64 + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// Anchor of a header, built the way GitHub does it: lowercase text with
+/// spaces replaced by dashes and punctuation removed.
+pub fn header_anchor(text: &str) -> String {
+    text.trim()
+        .to_lowercase()
+        .chars()
+        .filter_map(|c| match c {
+            ' ' => Some('-'),
+            c if c.is_alphanumeric() || c == '-' || c == '_' => Some(c),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Label of an admonition kind, `Note` for `note`
+fn admonition_label(kind: &str) -> String {
+    let mut chars = kind.chars();
+    chars
+        .next()
+        .map(|first| {
+            first
+                .to_uppercase()
+                .chain(chars.flat_map(char::to_lowercase))
+                .collect()
+        })
+        .unwrap_or_default()
 }
 
 pub trait TransformerTrait {
-    fn parse(document: &Bytes) -> anyhow::Result<Document>;
-    fn generate(document: &Document) -> anyhow::Result<Bytes>;
+    fn parse(document: &Bytes) -> crate::Result<Document>;
+    fn generate(document: &Document) -> crate::Result<Bytes>;
+
+    /// Same as [`TransformerTrait::parse`], transformers read the options of their format
+    /// and ignore the others
+    fn parse_with_options(document: &Bytes, _options: &ParseOptions) -> crate::Result<Document> {
+        Self::parse(document)
+    }
+
+    /// Same as [`TransformerTrait::generate`], transformers read the options of their
+    /// format and ignore the others
+    fn generate_with_options(
+        document: &Document,
+        _options: &GenerateOptions,
+    ) -> crate::Result<Bytes> {
+        Self::generate(document)
+    }
+}
+
+/// Options of the parsers, the defaults give the same result as [`TransformerTrait::parse`]
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize), serde(default))]
+pub struct ParseOptions {
+    pub markdown: MarkdownParseOptions,
+    pub html: HtmlParseOptions,
+    pub pdf: PdfParseOptions,
+    pub csv: CsvParseOptions,
+    /// Bare `http://` and `https://` URLs of markdown and plain text are read as links
+    pub autolink: bool,
+    /// Engine reading the text of the scanned PDF pages and of the images, which are
+    /// read as images without it
+    #[cfg_attr(feature = "json", serde(skip))]
+    pub ocr: Option<Ocr>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize), serde(default))]
+pub struct MarkdownParseOptions {
+    /// Syntax extensions that are read
+    pub flavor: MarkdownFlavor,
+    /// `:sparkles:` shortcodes of the text read as their emoji
+    pub emoji: bool,
+    /// Active content of the raw HTML is dropped, see [`HtmlParseOptions::sanitize`]
+    pub sanitize: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize), serde(default))]
+pub struct HtmlParseOptions {
+    /// Scripts, embedded frames and objects, `on*` event handlers and `javascript:` URLs
+    /// of untrusted input are dropped
+    pub sanitize: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize), serde(default))]
+pub struct PdfParseOptions {
+    /// User or owner password of an encrypted PDF
+    pub password: Option<String>,
+}
+
+/// Dialect of the CSV and TSV files read
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize), serde(default))]
+pub struct CsvParseOptions {
+    /// Separator of the fields, a comma for CSV and a tab for TSV when not set
+    pub delimiter: Option<char>,
+    /// Quote of the fields holding the delimiter or line breaks
+    pub quote: char,
+}
+
+impl Default for CsvParseOptions {
+    fn default() -> Self {
+        CsvParseOptions {
+            delimiter: None,
+            quote: '"',
+        }
+    }
+}
+
+/// Wrapping of the lines of the generated paragraphs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+pub enum LineWrap {
+    /// One line per paragraph
+    Off,
+    /// Lines wrapped at the given column, the line breaks of the text are replaced by spaces
+    AtWidth(usize),
+    /// Lines broken where the text has line breaks: the lines of plain text and the soft
+    /// breaks of markdown
+    #[default]
+    Preserve,
+}
+
+/// Dialect of markdown, it selects the syntax extensions read by the parser and written by
+/// the generator
+///
+/// - `CommonMark`: the core syntax only
+/// - `Gfm`: GitHub tables, strikethrough, task lists, footnotes and autolinks, with YAML or
+///   TOML frontmatter
+/// - `MultiMarkdown`: tables, footnotes and frontmatter
+/// - `Pandoc`: tables, strikethrough, task lists, footnotes, heading attributes and YAML
+///   frontmatter
+/// - `Obsidian`: the GitHub extensions with `[[Page]]` wiki links and `![[image.png]]`
+///   embeds, written back as markdown links
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Display, EnumString, VariantArray)]
+#[cfg_attr(
+    feature = "json",
+    derive(Serialize, Deserialize),
+    serde(rename_all = "lowercase")
+)]
+#[strum(serialize_all = "lowercase")]
+pub enum MarkdownFlavor {
+    CommonMark,
+    #[default]
+    Gfm,
+    MultiMarkdown,
+    Pandoc,
+    Obsidian,
+}
+
+/// Options of the generators, the defaults give the same output as [`TransformerTrait::generate`]
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize), serde(default))]
+pub struct GenerateOptions {
+    pub markdown: MarkdownOptions,
+    pub html: HtmlOptions,
+    pub pdf: PdfOptions,
+    pub images: ImageOptions,
+    pub latex: LatexOptions,
+    pub csv: CsvOptions,
+    pub xml: XmlOptions,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize), serde(default))]
+pub struct MarkdownOptions {
+    /// Marker of the bullet list items: `-`, `+` or `*`
+    pub bullet: char,
+    /// Wrapping of the paragraph lines
+    pub wrap: LineWrap,
+    /// Images written as `data:` URIs instead of being saved to files
+    pub embed_images: bool,
+    /// Metadata written as a YAML frontmatter block
+    pub frontmatter: bool,
+    /// Syntax extensions that are written
+    pub flavor: MarkdownFlavor,
+    /// Links and images written in the reference style, `[text][1]`, with the link
+    /// definitions at the end of the document
+    pub reference_links: bool,
+    /// Emoji of the text written as their `:shortcode:`
+    pub emoji_shortcodes: bool,
+}
+
+impl Default for MarkdownOptions {
+    fn default() -> Self {
+        MarkdownOptions {
+            bullet: '-',
+            wrap: LineWrap::default(),
+            embed_images: false,
+            frontmatter: false,
+            flavor: MarkdownFlavor::default(),
+            reference_links: false,
+            emoji_shortcodes: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize), serde(default))]
+pub struct HtmlOptions {
+    /// Complete page with `<html>` and `<body>`, otherwise only the content of the body
+    pub standalone: bool,
+    /// Images written as `data:` URIs instead of being saved to files
+    pub embed_images: bool,
+    /// Semantic markup: each header starts a `<section>` and the images with a title are
+    /// `<figure>` elements with a caption
+    pub semantic: bool,
+    /// Default stylesheet embedded in the `<head>` of a standalone page
+    pub theme: bool,
+    /// Stylesheet embedded in the `<head>` of a standalone page, after the default one
+    pub css: Option<String>,
+    /// CSS paged media rules so that printing the page gives the pages of the PDF output:
+    /// page size and margins, page headers and footers in the page margins and a new page
+    /// at each section
+    pub print: bool,
+    /// Email-safe page: the content laid out in tables, its styles inlined on the elements,
+    /// no stylesheet and the images referenced by `cid:` URLs of the names they are saved
+    /// with (unless they are embedded). The theme, stylesheet and print rules are left out.
+    pub email: bool,
+}
+
+impl Default for HtmlOptions {
+    fn default() -> Self {
+        HtmlOptions {
+            standalone: true,
+            embed_images: false,
+            semantic: false,
+            theme: false,
+            css: None,
+            print: false,
+            email: false,
+        }
+    }
+}
+
+/// Processing of the images before generation, applied by
+/// [`TransformerRegistry::generate_with_options`] when the `image` feature is enabled
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize), serde(default))]
+pub struct ImageOptions {
+    /// Resolution of the images of a PDF, larger images are downscaled to this resolution
+    /// at the width of the page (at least 72 so that their size on the page stays the same)
+    pub max_dpi: Option<f32>,
+    /// Largest width and height in pixels of the images, in every format
+    pub max_pixels: Option<u32>,
+    /// Converts BMP and TIFF images to PNG in HTML and markdown, browsers cannot show them
+    pub web_formats: bool,
+    /// Removes the EXIF metadata (camera, location...) of JPEG and PNG images
+    pub strip_metadata: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize), serde(default))]
+pub struct PdfOptions {
+    /// Size of the text in points, 11pt when not set
+    pub font_size: Option<f32>,
+    /// Margin of the four sides of the pages, replaces the margins of the page formats
+    pub margins: Option<Length>,
+    /// Fonts available in addition to the bundled ones, only the glyphs used are embedded
+    pub fonts: Vec<FontSource>,
+    /// Font families of the text by preference, a character missing from them is taken from
+    /// the other fonts. The default font of typst when empty.
+    pub font_families: Vec<String>,
+    /// Deepest header level in the outline (bookmarks) of the PDF, all levels when not set
+    /// and no outline with 0
+    pub outline_depth: Option<u8>,
+    /// Page header of the first page in place of the one of the document, `Some(vec![])`
+    /// leaves the first page without header
+    pub first_page_header: Option<Vec<Element>>,
+    /// Page footer of the first page in place of the one of the document
+    pub first_page_footer: Option<Vec<Element>>,
+    /// Page header of the even (left) pages, the one of the document stays on odd pages
+    pub even_page_header: Option<Vec<Element>>,
+    /// Page footer of the even (left) pages
+    pub even_page_footer: Option<Vec<Element>>,
+    /// PDF/A-2b for long-term archiving, titled for its readers and tagged as PDF/UA-1
+    pub archival: bool,
+    /// Passwords and permissions of an encrypted PDF, not allowed in PDF/A
+    pub encryption: Option<PdfEncryption>,
+    /// Compression of the streams of the PDF
+    pub compression: PdfCompression,
+    /// Text or image drawn on every page, such as DRAFT or a stamp
+    pub watermark: Option<PdfWatermark>,
+}
+
+/// Compression of a generated PDF
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Display, EnumString, VariantArray)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+#[strum(serialize_all = "lowercase")]
+pub enum PdfCompression {
+    /// Identical images written once, the other streams kept as typst writes them
+    #[default]
+    Default,
+    /// Identical streams of any kind written once and the streams recompressed at the
+    /// highest level, slower
+    Best,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize), serde(default))]
+pub struct LatexOptions {
+    /// Document class, which sets the sectioning command of the first header level
+    pub class: LatexClass,
+}
+
+/// Dialect of the generated CSV and TSV files and the tables they hold
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize), serde(default))]
+pub struct CsvOptions {
+    /// Separator of the fields, a comma for CSV and a tab for TSV when not set
+    pub delimiter: Option<char>,
+    /// Quote of the fields holding the delimiter, quotes or line breaks
+    pub quote: char,
+    /// Index from 0 of the only table written, all the tables are written one after the
+    /// other separated by an empty line when not set
+    pub table: Option<usize>,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        CsvOptions {
+            delimiter: None,
+            quote: '"',
+            table: None,
+        }
+    }
+}
+
+/// Schema of the generated XML
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize), serde(default))]
+pub struct XmlOptions {
+    /// Tags the elements are written with, the XML of shiva's own schema being generated
+    /// when not set
+    pub mapping: Option<XmlMapping>,
+}
+
+/// XML schema of its own given by the tags of the elements, so that an in-house schema is
+/// generated without a transformer of its own
+///
+/// ```toml
+/// root = "article"
+/// root_attributes = { xmlns = "http://docbook.org/ns/docbook", version = "5.0" }
+///
+/// [elements]
+/// Header = { name = "title", attributes = { role = "h{level}" } }
+/// Paragraph = "para"
+/// List = "itemizedlist"
+/// NumberedList = "orderedlist"
+/// ListItem = "listitem"
+/// Hyperlink = { name = "link", attributes = { "xlink:href" = "{url}" } }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(
+    any(feature = "json", feature = "xml"),
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct XmlMapping {
+    /// Name of the root element, `document` when empty
+    pub root: String,
+    /// Attributes of the root element, such as its namespaces
+    pub root_attributes: BTreeMap<String, String>,
+    /// Tags by kind of element: the name of the element (`Header`, `Paragraph`, `Image`...),
+    /// of a part of one (`ListItem`, `TableRow`, `TableHeader`, `TableCell`),
+    /// `NumberedList` for the numbered lists and `Strong`, `Emphasis` and `Strikethrough`
+    /// for the styled text. The content of an element without a tag is written in its
+    /// parent, an element without content nor tag is left out.
+    pub elements: BTreeMap<String, XmlTag>,
+}
+
+/// Tag of a kind of element in an [`XmlMapping`], given as its name alone or as a table of
+/// its name and attributes
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(
+    any(feature = "json", feature = "xml"),
+    derive(serde::Serialize, serde::Deserialize),
+    serde(from = "XmlTagConfig")
+)]
+pub struct XmlTag {
+    /// Name of the XML element
+    pub name: String,
+    /// Attributes of the XML element. `{property}` in the name or a value is replaced by a
+    /// property of the element: `level` and `id` of headers, `url` of links and media, `src`,
+    /// `alt`, `title`, `width` and `height` of images, `language` of code blocks, `start`
+    /// and `numbering` of lists, `id` and `title` of bookmarks, `level` of tables of
+    /// contents, `kind` of containers and media, `number` of footnotes and the attributes of
+    /// attributed elements. An attribute left empty is not written.
+    pub attributes: BTreeMap<String, String>,
+}
+
+/// Name alone or table of an [`XmlTag`] in a configuration file
+#[cfg(any(feature = "json", feature = "xml"))]
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+enum XmlTagConfig {
+    Name(String),
+    Tag {
+        name: String,
+        #[serde(default)]
+        attributes: BTreeMap<String, String>,
+    },
+}
+
+#[cfg(any(feature = "json", feature = "xml"))]
+impl From<XmlTagConfig> for XmlTag {
+    fn from(config: XmlTagConfig) -> Self {
+        match config {
+            XmlTagConfig::Name(name) => XmlTag {
+                name,
+                attributes: BTreeMap::new(),
+            },
+            XmlTagConfig::Tag { name, attributes } => XmlTag { name, attributes },
+        }
+    }
+}
+
+/// Document class of a generated LaTeX source
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Display, EnumString, VariantArray)]
+#[cfg_attr(
+    feature = "json",
+    derive(Serialize, Deserialize),
+    serde(rename_all = "lowercase")
+)]
+#[strum(serialize_all = "lowercase")]
+pub enum LatexClass {
+    /// Headers of the first level are sections
+    #[default]
+    Article,
+    /// Headers of the first level are chapters, each starting a page
+    Report,
+}
+
+/// Text watermark or stamp image drawn on every page of a generated PDF
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+pub struct PdfWatermark {
+    pub content: WatermarkContent,
+    /// From 0 (invisible) to 1 (opaque)
+    pub opacity: f32,
+    pub position: WatermarkPosition,
+    /// Distance to the edges of the page when not centered
+    pub margin: Length,
+    /// Counterclockwise rotation in degrees
+    pub angle: f32,
+    /// Size of the text, or width of the image
+    pub size: Length,
+    /// Drawn under the content of the pages instead of over it
+    pub behind: bool,
+}
+
+impl PdfWatermark {
+    /// Gray text across the middle of the pages, rotated by 45 degrees
+    pub fn text(text: impl Into<String>) -> PdfWatermark {
+        PdfWatermark {
+            content: WatermarkContent::Text {
+                text: text.into(),
+                color: "808080".to_string(),
+            },
+            opacity: 0.3,
+            position: WatermarkPosition::Center,
+            margin: Length::mm(15.0),
+            angle: 45.0,
+            size: Length::pt(72.0),
+            behind: false,
+        }
+    }
+
+    /// Stamp image at the top right of the pages
+    pub fn image(image: ImageData) -> PdfWatermark {
+        PdfWatermark {
+            content: WatermarkContent::Image(image),
+            opacity: 1.0,
+            position: WatermarkPosition::TopRight,
+            margin: Length::mm(15.0),
+            angle: 0.0,
+            size: Length::mm(40.0),
+            behind: false,
+        }
+    }
+
+    /// Color of a text watermark, `#rrggbb`, `rgb(r, g, b)` or a basic color name
+    pub fn with_color(mut self, color: &str) -> PdfWatermark {
+        if let (
+            WatermarkContent::Text {
+                color: text_color, ..
+            },
+            Some(color),
+        ) = (&mut self.content, css_color(&color.trim().to_lowercase()))
+        {
+            *text_color = color;
+        }
+        self
+    }
+
+    pub fn with_opacity(mut self, opacity: f32) -> PdfWatermark {
+        self.opacity = opacity.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn with_position(mut self, position: WatermarkPosition) -> PdfWatermark {
+        self.position = position;
+        self
+    }
+
+    pub fn with_margin(mut self, margin: Length) -> PdfWatermark {
+        self.margin = margin;
+        self
+    }
+
+    pub fn with_angle(mut self, angle: f32) -> PdfWatermark {
+        self.angle = angle;
+        self
+    }
+
+    pub fn with_size(mut self, size: Length) -> PdfWatermark {
+        self.size = size;
+        self
+    }
+
+    pub fn behind(mut self) -> PdfWatermark {
+        self.behind = true;
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+pub enum WatermarkContent {
+    /// Text and its color as 6 hexadecimal digits
+    Text {
+        text: String,
+        color: String,
+    },
+    Image(ImageData),
+}
+
+/// Place of a watermark on the pages
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Display, EnumString, VariantArray)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+#[strum(serialize_all = "kebab-case")]
+pub enum WatermarkPosition {
+    #[default]
+    Center,
+    Top,
+    Bottom,
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+/// Encryption of a generated PDF with AES-256
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize), serde(default))]
+pub struct PdfEncryption {
+    /// Password asked to open the document, none when empty
+    pub user_password: String,
+    /// Password lifting the restrictions, a random one when empty
+    pub owner_password: String,
+    /// Printing not allowed without the owner password
+    pub no_print: bool,
+    /// Copying the text and images not allowed without the owner password
+    pub no_copy: bool,
+}
+
+/// TrueType or OpenType font (or font collection) given to the PDF generator
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+pub enum FontSource {
+    Path(String),
+    Bytes(Bytes),
 }
 
 pub trait TransformerWithImageLoaderSaverTrait {
-    fn parse_with_loader<F>(document: &Bytes, image_loader: F) -> anyhow::Result<Document>
+    fn parse_with_loader<F>(document: &Bytes, image_loader: F) -> crate::Result<Document>
     where
         F: Fn(&str) -> anyhow::Result<Bytes>;
-    fn generate_with_saver<F>(document: &Document, image_saver: F) -> anyhow::Result<Bytes>
+    /// Same as [`TransformerWithImageLoaderSaverTrait::parse_with_loader`] with the options
+    /// of the format
+    fn parse_with_loader_and_options<F>(
+        document: &Bytes,
+        image_loader: F,
+        _options: &ParseOptions,
+    ) -> crate::Result<Document>
+    where
+        F: Fn(&str) -> anyhow::Result<Bytes>,
+    {
+        Self::parse_with_loader(document, image_loader)
+    }
+    fn generate_with_saver<F>(document: &Document, image_saver: F) -> crate::Result<Bytes>
     where
         F: Fn(&Bytes, &str) -> anyhow::Result<()>;
 }
+
+/// Asynchronous transformer: the image loader is awaited, so images can be fetched over
+/// the network without blocking the runtime
+#[cfg(feature = "tokio")]
+pub trait AsyncTransformerTrait {
+    fn parse_with_loader<F, Fut>(
+        document: &Bytes,
+        image_loader: F,
+    ) -> impl std::future::Future<Output = crate::Result<Document>> + Send
+    where
+        F: Fn(String) -> Fut + Send + Sync,
+        Fut: std::future::Future<Output = anyhow::Result<Bytes>> + Send;
+    fn parse_with_loader_and_options<F, Fut>(
+        document: &Bytes,
+        image_loader: F,
+        options: &ParseOptions,
+    ) -> impl std::future::Future<Output = crate::Result<Document>> + Send
+    where
+        F: Fn(String) -> Fut + Send + Sync,
+        Fut: std::future::Future<Output = anyhow::Result<Bytes>> + Send;
+    fn generate(
+        document: &Document,
+    ) -> impl std::future::Future<Output = crate::Result<Bytes>> + Send;
+}
+
+/// [`AsyncTransformerTrait`] over a synchronous transformer.
+///
+/// The document is parsed twice on the blocking thread pool: once to collect the image
+/// sources, then with the images awaited from the async loader in between.
+#[cfg(feature = "tokio")]
+pub struct AsyncAdapter<T>(std::marker::PhantomData<T>);
+
+#[cfg(feature = "tokio")]
+impl<T> AsyncTransformerTrait for AsyncAdapter<T>
+where
+    T: TransformerTrait + TransformerWithImageLoaderSaverTrait + 'static,
+{
+    async fn parse_with_loader<F, Fut>(document: &Bytes, image_loader: F) -> crate::Result<Document>
+    where
+        F: Fn(String) -> Fut + Send + Sync,
+        Fut: std::future::Future<Output = anyhow::Result<Bytes>> + Send,
+    {
+        Self::parse_with_loader_and_options(document, image_loader, &ParseOptions::default()).await
+    }
+
+    async fn parse_with_loader_and_options<F, Fut>(
+        document: &Bytes,
+        image_loader: F,
+        options: &ParseOptions,
+    ) -> crate::Result<Document>
+    where
+        F: Fn(String) -> Fut + Send + Sync,
+        Fut: std::future::Future<Output = anyhow::Result<Bytes>> + Send,
+    {
+        let input = document.clone();
+        let parse_options = options.clone();
+        let sources = tokio::task::spawn_blocking(move || {
+            let sources = std::sync::Mutex::new(Vec::<String>::new());
+            T::parse_with_loader_and_options(
+                &input,
+                |source| {
+                    let mut sources = sources.lock().unwrap();
+                    if !sources.iter().any(|s| s == source) {
+                        sources.push(source.to_string());
+                    }
+                    Ok(Bytes::new())
+                },
+                &parse_options,
+            )?;
+            crate::Result::Ok(sources.into_inner().unwrap())
+        })
+        .await
+        .map_err(|error| crate::Error::Other(error.into()))??;
+
+        let mut images = HashMap::new();
+        for source in sources {
+            let bytes = image_loader(source.clone()).await.map_err(|error| {
+                crate::Error::ImageLoadError {
+                    path: source.clone(),
+                    message: format!("{:#}", error),
+                }
+            })?;
+            images.insert(source, bytes);
+        }
+
+        let input = document.clone();
+        let parse_options = options.clone();
+        tokio::task::spawn_blocking(move || {
+            T::parse_with_loader_and_options(
+                &input,
+                |source| {
+                    images
+                        .get(source)
+                        .cloned()
+                        .ok_or_else(|| anyhow::anyhow!("No image: {}", source))
+                },
+                &parse_options,
+            )
+        })
+        .await
+        .map_err(|error| crate::Error::Other(error.into()))?
+    }
+
+    async fn generate(document: &Document) -> crate::Result<Bytes> {
+        let document = document.clone();
+        tokio::task::spawn_blocking(move || T::generate(&document))
+            .await
+            .map_err(|error| crate::Error::Other(error.into()))?
+    }
+}
 #[derive(Error, Debug)]
 pub enum ParserError {
     #[error("Parser error")]
@@ -522,7 +2644,7 @@ pub enum GeneratorError {
     #[error("Generator error")]
     Common,
 }
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, IntoStaticStr)]
 #[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
 pub enum Element {
     Text {
@@ -535,6 +2657,9 @@ pub enum Element {
     },
     Paragraph {
         elements: Vec<Element>,
+        /// Base text direction, `Auto` follows the document
+        #[cfg_attr(feature = "json", serde(default))]
+        direction: TextDirection,
     },
     Table {
         headers: Vec<TableHeader>,
@@ -543,14 +2668,758 @@ pub enum Element {
     List {
         elements: Vec<ListItem>,
         numbered: bool,
+        /// Number of the first item of a numbered list (1 by default)
+        start: usize,
+        /// Marker style of a numbered list (decimal, lower-alpha, roman...)
+        numbering: NumberingStyle,
     },
     Image(ImageData),
-    Hyperlink {
+    /// Hard line break inside a paragraph
+    LineBreak,
+    /// Placeholder expanded by generators from the document headers
+    /// (see [`Document::build_toc`])
+    TableOfContents {
+        max_level: u8,
+    },
+    /// Value filled in when the document is generated, mostly used in page headers and footers
+    Field {
+        kind: FieldKind,
+    },
+    /// Reference to a numbered header, figure or table, rendered like "see Table 3"
+    /// (see [`Document::numbering`] for the target ids)
+    CrossReference {
+        target_id: String,
+        kind: ReferenceKind,
+    },
+    /// Invisible named destination: an outline entry in PDF, an `<a id>` anchor in HTML
+    Bookmark {
+        id: String,
+        title: String,
+    },
+    /// Embedded video, audio or frame kept by its source URL: re-embedded in HTML, written as
+    /// a link by the other formats (see [`Element::media_link`])
+    Media {
+        kind: MediaKind,
+        url: String,
         title: String,
+    },
+    /// Vector drawing such as a chart, its coordinates in points from the top left corner:
+    /// inline SVG in HTML, vector graphics in PDF, an image for the other formats (see
+    /// [`Element::drawing_image`])
+    Drawing {
+        width: f32,
+        height: f32,
+        shapes: Vec<Shape>,
+    },
+    Hyperlink {
+        /// Inline content of the link (text, images...)
+        elements: Vec<Element>,
         url: String,
         alt: String,
         size: u8,
     },
+    /// Code in the text, written in a monospace font
+    InlineCode {
+        text: String,
+    },
+    /// Preformatted block of code, `info` is the info string of a markdown fence
+    /// (`rust,ignore`), its first word being the language, and empty when unknown
+    CodeBlock {
+        info: String,
+        code: String,
+    },
+    /// Formula in TeX notation, the content of math mode (`\frac{a}{b}`), `display` for a
+    /// formula set on its own line rather than in the text
+    Math {
+        tex: String,
+        display: bool,
+    },
+    /// Content in the syntax of another format (HTML embedded in markdown...), written as is
+    /// by the generators of that format and left out by the others
+    Raw {
+        format: DocumentType,
+        content: String,
+    },
+    /// Inline content written with a style, styles combine by nesting
+    Styled {
+        style: TextStyle,
+        elements: Vec<Element>,
+    },
+    /// Note placed where it is referenced: a numbered reference in the text with the content
+    /// at the end of the document (markdown, HTML) or at the bottom of the page (PDF)
+    Footnote {
+        elements: Vec<Element>,
+    },
+    /// Blocks grouped by an HTML5 container (`<article>`, `<section>`, `<nav>`, `<aside>`),
+    /// formats without such containers write the blocks one after the other
+    Container {
+        kind: ContainerKind,
+        elements: Vec<Element>,
+    },
+    /// Element carrying source-format-specific data (CSS classes, `data-*` attributes,
+    /// style names...) so that it survives a round trip.
+    ///
+    /// HTML attributes keep their names, other formats prefix the key with the format
+    /// (`docx:style`). Generators emit the attributes they understand and ignore the rest.
+    Attributed {
+        attributes: HashMap<String, String>,
+        element: Box<Element>,
+    },
+}
+
+impl Element {
+    /// Language of a code block, the first word of its info string
+    pub fn code_language(&self) -> Option<&str> {
+        match self {
+            Element::CodeBlock { info, .. } => info
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .next()
+                .filter(|language| !language.is_empty()),
+            _ => None,
+        }
+    }
+
+    /// Link to the source of a media element, labelled with its title or else its URL
+    pub fn media_link(&self) -> Option<Element> {
+        match self {
+            Element::Media { url, title, .. } => {
+                let text = if title.is_empty() { url } else { title };
+                Some(Element::Hyperlink {
+                    elements: vec![Element::Text {
+                        text: text.clone(),
+                        size: 8,
+                    }],
+                    url: url.clone(),
+                    alt: title.clone(),
+                    size: 8,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// Aside of an admonition (`note`, `warning`...) with an `admonition {kind}` class, its
+    /// title or else the label of its kind first so that the formats without admonitions show
+    /// it
+    pub fn admonition(kind: &str, title: Option<&str>, blocks: Vec<Element>) -> Element {
+        let label = admonition_label(kind);
+        let mut elements = vec![Element::Paragraph {
+            elements: vec![Element::Styled {
+                style: TextStyle::Strong,
+                elements: vec![Element::Text {
+                    text: title.map_or(label, str::to_string),
+                    size: 14,
+                }],
+            }],
+            direction: TextDirection::default(),
+        }];
+        elements.extend(blocks);
+        Element::Attributed {
+            attributes: HashMap::from([(
+                "class".to_string(),
+                format!("admonition {}", kind.to_lowercase()),
+            )]),
+            element: Box::new(Element::Container {
+                kind: ContainerKind::Aside,
+                elements,
+            }),
+        }
+    }
+
+    /// Kind, title and blocks of an admonition (see [`Element::admonition`]), the title being
+    /// `None` when it is the label of the kind
+    pub fn admonition_parts(&self) -> Option<(&str, Option<String>, &[Element])> {
+        let Element::Attributed {
+            attributes,
+            element,
+        } = self
+        else {
+            return None;
+        };
+        let mut classes = attributes.get("class")?.split_whitespace();
+        classes.find(|class| *class == "admonition")?;
+        let kind = classes.next()?;
+        let Element::Container { elements, .. } = element.as_ref() else {
+            return None;
+        };
+        if let Some(Element::Paragraph {
+            elements: first, ..
+        }) = elements.first()
+        {
+            if let [Element::Styled {
+                style: TextStyle::Strong,
+                elements: title,
+            }] = first.as_slice()
+            {
+                let title = title.iter().map(Element::plain_text).collect::<String>();
+                let title = (title != admonition_label(kind)).then_some(title);
+                return Some((kind, title, &elements[1..]));
+            }
+        }
+        Some((kind, None, elements))
+    }
+
+    /// TeX source of a formula as code, a code block for a display formula, for generators
+    /// without math
+    pub fn math_code(&self) -> Option<Element> {
+        match self {
+            Element::Math { tex, display: true } => Some(Element::CodeBlock {
+                info: "latex".to_string(),
+                code: tex.clone(),
+            }),
+            Element::Math { tex, .. } => Some(Element::InlineCode { text: tex.clone() }),
+            _ => None,
+        }
+    }
+
+    /// Image of a drawing, PNG with the `image` feature and SVG without, its size in CSS
+    /// pixels
+    pub fn drawing_image(&self) -> Option<Element> {
+        let Element::Drawing {
+            width,
+            height,
+            shapes,
+        } = self
+        else {
+            return None;
+        };
+        #[cfg(feature = "image")]
+        let (bytes, image_type) = match crate::drawing::png(*width, *height, shapes) {
+            Ok(png) => (png, "png"),
+            Err(error) => {
+                warn!("Cannot rasterize a drawing: {}", error);
+                (
+                    crate::drawing::svg(*width, *height, shapes).into_bytes(),
+                    "svg",
+                )
+            }
+        };
+        #[cfg(not(feature = "image"))]
+        let (bytes, image_type) = (
+            crate::drawing::svg(*width, *height, shapes).into_bytes(),
+            "svg",
+        );
+        let pixels = |points: f32| Some(format!("{}", (points / 0.75).round()));
+        Some(Element::Image(ImageData::new(
+            Bytes::from(bytes),
+            String::new(),
+            String::new(),
+            image_type.to_string(),
+            String::new(),
+            ImageDimension {
+                width: pixels(*width),
+                height: pixels(*height),
+            },
+        )))
+    }
+
+    /// Wraps the element with the given attributes, empty attributes leave it as is
+    pub fn with_attributes(self, attributes: HashMap<String, String>) -> Element {
+        if attributes.is_empty() {
+            self
+        } else {
+            Element::Attributed {
+                attributes,
+                element: Box::new(self),
+            }
+        }
+    }
+
+    /// Whether the element is written in the flow of a paragraph, display math being a
+    /// block of its own
+    pub fn is_inline(&self) -> bool {
+        match self {
+            Element::Text { .. }
+            | Element::Hyperlink { .. }
+            | Element::InlineCode { .. }
+            | Element::Styled { .. }
+            | Element::Footnote { .. }
+            | Element::Field { .. }
+            | Element::Bookmark { .. }
+            | Element::CrossReference { .. }
+            | Element::Math { display: false, .. }
+            | Element::LineBreak => true,
+            Element::Attributed { element, .. } => element.is_inline(),
+            _ => false,
+        }
+    }
+
+    /// Text of the element and of its children, without any formatting
+    pub fn plain_text(&self) -> String {
+        match self {
+            Element::Text { text, .. }
+            | Element::Header { text, .. }
+            | Element::InlineCode { text }
+            | Element::CodeBlock { code: text, .. }
+            | Element::Math { tex: text, .. } => text.clone(),
+            Element::Paragraph { elements, .. }
+            | Element::Hyperlink { elements, .. }
+            | Element::Footnote { elements }
+            | Element::Styled { elements, .. } => elements
+                .iter()
+                .map(|element| element.plain_text())
+                .collect(),
+            Element::List { elements, .. } => elements
+                .iter()
+                .map(|item| item.element.plain_text())
+                .collect::<Vec<_>>()
+                .join("\n"),
+            Element::Container { elements, .. } => elements
+                .iter()
+                .map(Element::plain_text)
+                .collect::<Vec<_>>()
+                .join("\n"),
+            Element::Table { headers, rows } => std::iter::once(
+                headers
+                    .iter()
+                    .map(|header| header.element.plain_text())
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            )
+            .chain(rows.iter().map(|row| {
+                row.cells
+                    .iter()
+                    .map(|cell| cell.element.plain_text())
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            }))
+            .collect::<Vec<_>>()
+            .join("\n"),
+            Element::Image(image) => image.alt().to_string(),
+            Element::Media { url, title, .. } => if title.is_empty() { url } else { title }.clone(),
+            Element::LineBreak => "\n".to_string(),
+            Element::Attributed { element, .. } => element.plain_text(),
+            _ => String::new(),
+        }
+    }
+
+    /// Nested elements: paragraph, link, footnote and container content, list items, table
+    /// headers then cells row by row, and the element wrapped by attributes
+    pub fn children(&self) -> Vec<&Element> {
+        match self {
+            Element::Paragraph { elements, .. }
+            | Element::Hyperlink { elements, .. }
+            | Element::Footnote { elements }
+            | Element::Styled { elements, .. }
+            | Element::Container { elements, .. } => elements.iter().collect(),
+            Element::List { elements, .. } => elements.iter().map(|item| &item.element).collect(),
+            Element::Table { headers, rows } => headers
+                .iter()
+                .map(|header| &header.element)
+                .chain(
+                    rows.iter()
+                        .flat_map(|row| row.cells.iter().map(|cell| &cell.element)),
+                )
+                .collect(),
+            Element::Attributed { element, .. } => vec![element],
+            _ => vec![],
+        }
+    }
+
+    /// Same as [`Element::children`], mutable
+    pub fn children_mut(&mut self) -> Vec<&mut Element> {
+        match self {
+            Element::Paragraph { elements, .. }
+            | Element::Hyperlink { elements, .. }
+            | Element::Footnote { elements }
+            | Element::Styled { elements, .. }
+            | Element::Container { elements, .. } => elements.iter_mut().collect(),
+            Element::List { elements, .. } => {
+                elements.iter_mut().map(|item| &mut item.element).collect()
+            }
+            Element::Table { headers, rows } => headers
+                .iter_mut()
+                .map(|header| &mut header.element)
+                .chain(
+                    rows.iter_mut()
+                        .flat_map(|row| row.cells.iter_mut().map(|cell| &mut cell.element)),
+                )
+                .collect(),
+            Element::Attributed { element, .. } => vec![element],
+            _ => vec![],
+        }
+    }
+}
+
+/// Text split into text and links at its `http://` and `https://` URLs, punctuation ending a
+/// URL is left to the text
+fn split_urls(text: &str, size: u8) -> Vec<Element> {
+    let mut elements = vec![];
+    let mut rest = text;
+    while let Some(start) = ["http://", "https://"]
+        .iter()
+        .filter_map(|scheme| rest.find(scheme))
+        .min()
+    {
+        let length = rest[start..]
+            .find(|c: char| c.is_whitespace() || matches!(c, '<' | '>' | '"'))
+            .unwrap_or(rest.len() - start);
+        let url = rest[start..start + length].trim_end_matches(|c: char| {
+            matches!(c, '.' | ',' | ';' | ':' | '!' | '?' | ')' | '\'')
+        });
+        if url.len() <= "https://".len() {
+            break;
+        }
+        if start > 0 {
+            elements.push(Element::Text {
+                text: rest[..start].to_string(),
+                size,
+            });
+        }
+        elements.push(Element::Hyperlink {
+            elements: vec![Element::Text {
+                text: url.to_string(),
+                size,
+            }],
+            url: url.to_string(),
+            alt: url.to_string(),
+            size,
+        });
+        rest = &rest[start + url.len()..];
+    }
+    if !rest.is_empty() || elements.is_empty() {
+        elements.push(Element::Text {
+            text: rest.to_string(),
+            size,
+        });
+    }
+    elements
+}
+
+/// Kind of the value substituted for [`Element::Field`].
+///
+/// `Date` and `DocumentTitle` are resolved by [`Document::resolve_fields`], page
+/// numbers only make sense for paged outputs and are left to the generators.
+#[derive(Debug, Clone, Copy, PartialEq, Display, EnumString, VariantArray)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+#[strum(serialize_all = "snake_case")]
+pub enum FieldKind {
+    PageNumber,
+    TotalPages,
+    Date,
+    DocumentTitle,
+}
+
+/// Style of [`Element::Styled`], written `*emphasis*`, `**strong**` and
+/// `~~strikethrough~~` in markdown
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Display, EnumString, VariantArray)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+#[strum(serialize_all = "lowercase")]
+pub enum TextStyle {
+    Emphasis,
+    Strong,
+    Strikethrough,
+}
+
+/// Alignment of the text of a block, the CSS `text-align` values
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Display, EnumString, VariantArray)]
+#[strum(serialize_all = "lowercase")]
+pub enum TextAlign {
+    Left,
+    Center,
+    Right,
+    Justify,
+}
+
+/// Formatting read from the `style` attribute of an [`Element::Attributed`], the CSS
+/// properties other formats understand: `color`, `font-weight` and `text-align`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InlineStyle {
+    /// Six uppercase hex digits, `FF0000` for `red` or `#f00`
+    pub color: Option<String>,
+    pub bold: bool,
+    pub align: Option<TextAlign>,
+}
+
+impl InlineStyle {
+    /// Style of the attributes, unknown properties and values are ignored
+    pub fn from_attributes(attributes: &HashMap<String, String>) -> InlineStyle {
+        let mut style = InlineStyle::default();
+        let declarations = attributes.get("style").map(String::as_str).unwrap_or("");
+        for declaration in declarations.split(';') {
+            let Some((property, value)) = declaration.split_once(':') else {
+                continue;
+            };
+            let value = value.trim().to_lowercase();
+            match property.trim().to_lowercase().as_str() {
+                "color" => style.color = css_color(&value),
+                "font-weight" => {
+                    style.bold = match value.as_str() {
+                        "bold" | "bolder" => true,
+                        weight => weight.parse::<u16>().is_ok_and(|weight| weight >= 600),
+                    }
+                }
+                "text-align" => {
+                    style.align = match value.as_str() {
+                        "start" => Some(TextAlign::Left),
+                        "end" => Some(TextAlign::Right),
+                        value => TextAlign::from_str(value).ok(),
+                    }
+                }
+                _ => {}
+            }
+        }
+        style
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == InlineStyle::default()
+    }
+}
+
+/// Hex digits of a CSS color given as `#rgb`, `#rrggbb`, `rgb(r, g, b)` or one of the
+/// basic color names
+fn css_color(value: &str) -> Option<String> {
+    let hex = if let Some(hex) = value.strip_prefix('#') {
+        match hex.len() {
+            3 => hex.chars().flat_map(|c| [c, c]).collect(),
+            6 => hex.to_string(),
+            _ => return None,
+        }
+    } else if let Some(channels) = value
+        .strip_prefix("rgb(")
+        .and_then(|rest| rest.strip_suffix(')'))
+    {
+        let channels: Vec<u8> = channels
+            .split(',')
+            .map(|channel| channel.trim().parse())
+            .collect::<Result<_, _>>()
+            .ok()?;
+        match channels.as_slice() {
+            [r, g, b] => format!("{:02x}{:02x}{:02x}", r, g, b),
+            _ => return None,
+        }
+    } else {
+        match value {
+            "black" => "000000",
+            "white" => "ffffff",
+            "red" => "ff0000",
+            "green" => "008000",
+            "blue" => "0000ff",
+            "yellow" => "ffff00",
+            "orange" => "ffa500",
+            "purple" => "800080",
+            "gray" | "grey" => "808080",
+            _ => return None,
+        }
+        .to_string()
+    };
+    hex.chars()
+        .all(|c| c.is_ascii_hexdigit())
+        .then(|| hex.to_uppercase())
+}
+
+/// Kind of [`Element::Container`], named after its HTML5 element
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Display, EnumString, VariantArray)]
+#[cfg_attr(
+    feature = "json",
+    derive(Serialize, Deserialize),
+    serde(rename_all = "lowercase")
+)]
+#[strum(serialize_all = "lowercase")]
+pub enum ContainerKind {
+    Article,
+    Section,
+    Nav,
+    Aside,
+    /// Blocks grouped without a meaning of their own, such as the content of a table cell
+    Div,
+}
+
+/// Kind of [`Element::Media`], named after its HTML element
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Display, EnumString, VariantArray)]
+#[cfg_attr(
+    feature = "json",
+    derive(Serialize, Deserialize),
+    serde(rename_all = "lowercase")
+)]
+#[strum(serialize_all = "lowercase")]
+pub enum MediaKind {
+    Video,
+    Audio,
+    /// Page embedded with `<iframe>`
+    Frame,
+}
+
+/// Outline of an [`Element::Drawing`], stroked and filled
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+pub struct Shape {
+    pub path: Vec<PathCommand>,
+    /// Six uppercase hex digits of the outline color, not stroked when none
+    pub stroke: Option<String>,
+    /// Width of the outline in points
+    pub stroke_width: f32,
+    /// Six uppercase hex digits of the fill color, not filled when none
+    pub fill: Option<String>,
+}
+
+impl Shape {
+    /// Outline of the path in black, 1 point wide
+    pub fn path(path: Vec<PathCommand>) -> Shape {
+        Shape {
+            path,
+            stroke: Some("000000".to_string()),
+            stroke_width: 1.0,
+            fill: None,
+        }
+    }
+
+    pub fn line(x1: f32, y1: f32, x2: f32, y2: f32) -> Shape {
+        Shape::path(vec![
+            PathCommand::MoveTo { x: x1, y: y1 },
+            PathCommand::LineTo { x: x2, y: y2 },
+        ])
+    }
+
+    pub fn rect(x: f32, y: f32, width: f32, height: f32) -> Shape {
+        Shape::path(vec![
+            PathCommand::MoveTo { x, y },
+            PathCommand::LineTo { x: x + width, y },
+            PathCommand::LineTo {
+                x: x + width,
+                y: y + height,
+            },
+            PathCommand::LineTo { x, y: y + height },
+            PathCommand::Close,
+        ])
+    }
+
+    /// Outline in a CSS color (`#rgb`, `#rrggbb`, `rgb(r, g, b)` or a basic name), not
+    /// stroked when the color is unknown
+    pub fn with_stroke(mut self, color: &str, width: f32) -> Shape {
+        self.stroke = css_color(&color.trim().to_lowercase());
+        self.stroke_width = width;
+        self
+    }
+
+    pub fn without_stroke(mut self) -> Shape {
+        self.stroke = None;
+        self
+    }
+
+    /// Fill in a CSS color, not filled when the color is unknown
+    pub fn with_fill(mut self, color: &str) -> Shape {
+        self.fill = css_color(&color.trim().to_lowercase());
+        self
+    }
+}
+
+/// Segment of the path of a [`Shape`], to absolute coordinates
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+pub enum PathCommand {
+    MoveTo {
+        x: f32,
+        y: f32,
+    },
+    LineTo {
+        x: f32,
+        y: f32,
+    },
+    /// Cubic Bézier curve through two control points
+    CurveTo {
+        x1: f32,
+        y1: f32,
+        x2: f32,
+        y2: f32,
+        x: f32,
+        y: f32,
+    },
+    /// Line back to the start of the subpath
+    Close,
+}
+
+/// Kind of the target of [`Element::CrossReference`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Display, EnumString, VariantArray)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+#[strum(serialize_all = "lowercase")]
+pub enum ReferenceKind {
+    Section,
+    Figure,
+    Table,
+}
+
+impl ReferenceKind {
+    /// Word put before the number of the target
+    pub fn label(&self) -> &'static str {
+        match self {
+            ReferenceKind::Section => "Section",
+            ReferenceKind::Figure => "Figure",
+            ReferenceKind::Table => "Table",
+        }
+    }
+}
+
+/// Marker style of numbered list items.
+///
+/// The string representation matches the CSS `list-style-type` names.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Display, EnumString, VariantArray)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+#[strum(serialize_all = "kebab-case")]
+pub enum NumberingStyle {
+    #[default]
+    Decimal,
+    LowerAlpha,
+    UpperAlpha,
+    LowerRoman,
+    UpperRoman,
+}
+
+impl NumberingStyle {
+    /// Renders the marker for the item with the given (1-based) number
+    pub fn format(&self, number: usize) -> String {
+        match self {
+            NumberingStyle::Decimal => number.to_string(),
+            NumberingStyle::LowerAlpha => alpha_number(number),
+            NumberingStyle::UpperAlpha => alpha_number(number).to_uppercase(),
+            NumberingStyle::LowerRoman => roman_number(number),
+            NumberingStyle::UpperRoman => roman_number(number).to_uppercase(),
+        }
+    }
+}
+
+fn alpha_number(mut number: usize) -> String {
+    if number == 0 {
+        return "0".to_string();
+    }
+    let mut result = Vec::new();
+    while number > 0 {
+        number -= 1;
+        result.push(b'a' + (number % 26) as u8);
+        number /= 26;
+    }
+    result.reverse();
+    String::from_utf8(result).unwrap_or_default()
+}
+
+fn roman_number(mut number: usize) -> String {
+    if number == 0 {
+        return "0".to_string();
+    }
+    const NUMERALS: [(usize, &str); 13] = [
+        (1000, "m"),
+        (900, "cm"),
+        (500, "d"),
+        (400, "cd"),
+        (100, "c"),
+        (90, "xc"),
+        (50, "l"),
+        (40, "xl"),
+        (10, "x"),
+        (9, "ix"),
+        (5, "v"),
+        (4, "iv"),
+        (1, "i"),
+    ];
+    let mut result = String::new();
+    for (value, numeral) in NUMERALS {
+        while number >= value {
+            result.push_str(numeral);
+            number -= value;
+        }
+    }
+    result
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -614,21 +3483,47 @@ impl ImageData {
     }
 
     pub fn set_image_type(&mut self, image_type_str: &str) {
-        let image_type_str = image_type_str
-            .split('.')
-            .last()
-            .unwrap_or("")
-            .trim()
-            .to_lowercase();
+        // data:image/png;base64,... gives its type in the media type
+        let image_type_str = match image_type_str.strip_prefix("data:") {
+            Some(data) => data
+                .split([';', ','])
+                .next()
+                .unwrap_or("")
+                .trim_start_matches("image/")
+                .trim_end_matches("+xml")
+                .to_lowercase(),
+            // the extension of a URL is before its query and fragment
+            None => image_type_str
+                .split(['?', '#'])
+                .next()
+                .unwrap_or("")
+                .split('.')
+                .next_back()
+                .unwrap_or("")
+                .trim()
+                .to_lowercase(),
+        };
+        let image_type_str = match image_type_str.as_str() {
+            "jpg" => "jpeg",
+            "tif" => "tiff",
+            image_type_str => image_type_str,
+        };
 
         if image_type_str.trim().is_empty() {
             self.image_type = ImageType::default();
             return;
         }
 
-        match ImageType::from_str(&image_type_str) {
+        match ImageType::from_str(image_type_str) {
             Ok(image_type) => self.image_type = image_type,
-            Err(_) => panic!("Invalid image type: {}", image_type_str),
+            Err(_) => {
+                warn!(
+                    "Unknown image type {}, read as {}",
+                    image_type_str,
+                    ImageType::default()
+                );
+                self.image_type = ImageType::default();
+            }
         }
     }
 
@@ -693,6 +3588,8 @@ pub enum ImageType {
     Jpeg,
     Gif,
     SVG,
+    Bmp,
+    Tiff,
 }
 
 impl ImageType {
@@ -702,6 +3599,19 @@ impl ImageType {
             ImageType::Jpeg => ".jpeg",
             ImageType::Gif => ".gif",
             ImageType::SVG => ".svg",
+            ImageType::Bmp => ".bmp",
+            ImageType::Tiff => ".tiff",
+        }
+    }
+
+    pub fn mime_type(&self) -> &str {
+        match self {
+            ImageType::Png => "image/png",
+            ImageType::Jpeg => "image/jpeg",
+            ImageType::Gif => "image/gif",
+            ImageType::SVG => "image/svg+xml",
+            ImageType::Bmp => "image/bmp",
+            ImageType::Tiff => "image/tiff",
         }
     }
 }
@@ -724,9 +3634,22 @@ pub struct ImageDimension {
     pub height: Option<String>,
 }
 
+/// Size in pixels given to the images whose size is neither set nor readable from
+/// their bytes, the size a browser gives to a replaced element without one
+#[cfg(any(feature = "odt", feature = "rtf", feature = "pptx"))]
+pub(crate) const DEFAULT_IMAGE_SIZE: (f32, f32) = (300.0, 150.0);
+
+/// Image loader reading the images from the files under `path`, the sources leaving it
+/// through `..` are refused
 pub fn disk_image_loader(path: &str) -> impl Fn(&str) -> anyhow::Result<Bytes> {
     let path = path.to_string();
     let image_loader = move |image: &str| -> anyhow::Result<Bytes> {
+        if std::path::Path::new(image)
+            .components()
+            .any(|component| component == std::path::Component::ParentDir)
+        {
+            anyhow::bail!("Image {} is outside of {}", image, path);
+        }
         let image_path = format!("{}/{}", path, image);
         info!("Loading image: {}", image_path);
         let bytes = std::fs::read(image_path)?;
@@ -745,60 +3668,950 @@ pub fn disk_image_saver(path: &str) -> impl Fn(&Bytes, &str) -> anyhow::Result<(
     image_saver
 }
 
-// Opinion (JohnScience): The variants of this enum should be enabled/disabled based on the features.
-// This way, the user will get compile-time errors if they work. However, this would be a breaking change.
-
-#[wasm_bindgen]
-#[repr(u8)]
-#[derive(
-    Debug, Clone, Copy, PartialEq, Eq, EnumString, Display, VariantArray, IntoStaticStr, EnumCount,
-)]
-#[cfg_attr(feature = "json", derive(Serialize))]
-#[strum(serialize_all = "lowercase")]
-pub enum DocumentType {
-    HTML = 0,
-    Markdown = 1,
-    Text = 2,
-    PDF = 3,
-    Json = 4,
-    CSV = 5,
-    RTF = 6,
-    DOCX = 7,
-    XML = 8,
-    XLS = 9,
-    XLSX = 10,
-    ODS = 11,
+/// Image loader downloading the images, relative sources are resolved against `base_url`.
+/// Requests taking longer than `timeout` and images larger than `max_bytes` fail.
+///
+/// Only public addresses are connected to, the hosts of the redirects included: the
+/// loopback, private, link-local (as `169.254.169.254`) and other special addresses are
+/// refused, so that the sources of an untrusted document do not reach the local network.
+#[cfg(feature = "http")]
+pub fn http_image_loader(
+    base_url: &str,
+    timeout: std::time::Duration,
+    max_bytes: u64,
+) -> impl Fn(&str) -> anyhow::Result<Bytes> {
+    http_loader(base_url, timeout, max_bytes, false)
+}
+
+/// HTTP image loader, connecting to the local network with `allow_private`
+#[cfg(feature = "http")]
+fn http_loader(
+    base_url: &str,
+    timeout: std::time::Duration,
+    max_bytes: u64,
+    allow_private: bool,
+) -> impl Fn(&str) -> anyhow::Result<Bytes> {
+    use std::io::Read;
+
+    let base_url = base_url.to_string();
+    let agent = ureq::AgentBuilder::new().timeout(timeout);
+    // every connection resolves its host here, the ones of the redirects too
+    let agent = if allow_private {
+        agent.build()
+    } else {
+        agent.resolver(public_addresses).build()
+    };
+    move |image: &str| -> anyhow::Result<Bytes> {
+        let url = match url::Url::parse(&base_url) {
+            Ok(base_url) => base_url.join(image)?,
+            Err(_) => url::Url::parse(image)?,
+        };
+        info!("Downloading image: {}", url);
+        let response = agent.request_url("GET", &url).call()?;
+        let mut bytes = vec![];
+        response
+            .into_reader()
+            .take(max_bytes + 1)
+            .read_to_end(&mut bytes)?;
+        if bytes.len() as u64 > max_bytes {
+            return Err(anyhow::anyhow!(
+                "Image {} is larger than {} bytes",
+                url,
+                max_bytes
+            ));
+        }
+        Ok(Bytes::from(bytes))
+    }
+}
+
+/// Addresses of a host and port, an error when one of them is not a public address
+#[cfg(feature = "http")]
+fn public_addresses(netloc: &str) -> std::io::Result<Vec<std::net::SocketAddr>> {
+    use std::net::ToSocketAddrs;
+
+    let addresses: Vec<std::net::SocketAddr> = netloc.to_socket_addrs()?.collect();
+    if let Some(address) = addresses.iter().find(|address| !is_public(address.ip())) {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::PermissionDenied,
+            format!("{} is not a public address", address.ip()),
+        ));
+    }
+    Ok(addresses)
+}
+
+/// Whether an address is reachable on the internet, the addresses of IPv4 embedded in
+/// IPv6 being those of IPv4
+#[cfg(feature = "http")]
+fn is_public(address: std::net::IpAddr) -> bool {
+    use std::net::IpAddr;
+
+    match address {
+        IpAddr::V4(address) => {
+            let [a, b, c, _] = address.octets();
+            !(address.is_unspecified()
+                || address.is_loopback()
+                || address.is_private()
+                || address.is_link_local()
+                || address.is_broadcast()
+                || address.is_documentation()
+                || address.is_multicast()
+                // "this network", shared address space, IETF protocols, benchmarking and
+                // reserved ranges
+                || a == 0
+                || (a == 100 && (64..128).contains(&b))
+                || (a == 192 && b == 0 && c == 0)
+                || (a == 198 && (18..20).contains(&b))
+                || a >= 240)
+        }
+        IpAddr::V6(address) => {
+            if let Some(mapped) = address.to_ipv4_mapped() {
+                return is_public(IpAddr::V4(mapped));
+            }
+            let segments = address.segments();
+            // NAT64 addresses carry an IPv4 address in their last 32 bits
+            if segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0] {
+                let [.., high, low] = segments;
+                let mapped = std::net::Ipv4Addr::from((u32::from(high) << 16) | u32::from(low));
+                return is_public(IpAddr::V4(mapped));
+            }
+            !(address.is_unspecified()
+                || address.is_loopback()
+                || address.is_multicast()
+                // unique local, link-local and documentation addresses
+                || (segments[0] & 0xfe00) == 0xfc00
+                || (segments[0] & 0xffc0) == 0xfe80
+                || (segments[0] == 0x2001 && segments[1] == 0x0db8))
+        }
+    }
+}
+
+/// Image loader decoding the `data:` URIs (base64 or percent-encoded), the other
+/// sources are given to `image_loader`
+#[cfg(feature = "base64")]
+pub fn data_uri_image_loader<F>(image_loader: F) -> impl Fn(&str) -> anyhow::Result<Bytes>
+where
+    F: Fn(&str) -> anyhow::Result<Bytes>,
+{
+    use base64::Engine;
+
+    move |image: &str| {
+        let Some(data) = image.strip_prefix("data:") else {
+            return image_loader(image);
+        };
+        let (media_type, content) = data
+            .split_once(',')
+            .ok_or_else(|| anyhow::anyhow!("Invalid data URI: missing ','"))?;
+        if media_type.ends_with(";base64") {
+            let content: String = content.split_whitespace().collect();
+            Ok(Bytes::from(
+                base64::engine::general_purpose::STANDARD.decode(content)?,
+            ))
+        } else {
+            percent_decode(content)
+        }
+    }
+}
+
+#[cfg(feature = "base64")]
+pub(crate) fn percent_decode(text: &str) -> anyhow::Result<Bytes> {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        if bytes[index] == b'%' {
+            let hex = text
+                .get(index + 1..index + 3)
+                .ok_or_else(|| anyhow::anyhow!("Invalid percent-encoding in data URI"))?;
+            decoded.push(u8::from_str_radix(hex, 16)?);
+            index += 3;
+        } else {
+            decoded.push(bytes[index]);
+            index += 1;
+        }
+    }
+    Ok(Bytes::from(decoded))
+}
+
+/// `data:` URI embedding the image, for single-file HTML and markdown
+#[cfg(feature = "base64")]
+pub fn image_data_uri(image: &ImageData) -> String {
+    use base64::Engine;
+
+    format!(
+        "data:{};base64,{}",
+        image.image_type().mime_type(),
+        base64::engine::general_purpose::STANDARD.encode(image.bytes())
+    )
+}
+
+/// Image loader trying `first`, then `second` when it fails, e.g. the disk then the network
+pub fn fallback_image_loader<F, G>(first: F, second: G) -> impl Fn(&str) -> anyhow::Result<Bytes>
+where
+    F: Fn(&str) -> anyhow::Result<Bytes>,
+    G: Fn(&str) -> anyhow::Result<Bytes>,
+{
+    move |image: &str| {
+        first(image).or_else(|error| {
+            second(image).map_err(|second_error| anyhow::anyhow!("{:#}; {:#}", error, second_error))
+        })
+    }
+}
+
+// Opinion (JohnScience): The variants of this enum should be enabled/disabled based on the features.
+// This way, the user will get compile-time errors if they work. However, this would be a breaking change.
+
+#[wasm_bindgen]
+#[repr(u8)]
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Hash,
+    EnumString,
+    Display,
+    VariantArray,
+    IntoStaticStr,
+    EnumCount,
+)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+#[strum(serialize_all = "lowercase")]
+pub enum DocumentType {
+    HTML = 0,
+    Markdown = 1,
+    Text = 2,
+    PDF = 3,
+    Json = 4,
+    CSV = 5,
+    RTF = 6,
+    DOCX = 7,
+    XML = 8,
+    XLS = 9,
+    XLSX = 10,
+    ODS = 11,
+    /// Standalone image, read but not generated
+    Image = 12,
+    ODT = 13,
+    /// EPUB 3 book
+    EPUB = 14,
+    /// LaTeX source, read for a common subset: sections, lists, tables, images, verbatim,
+    /// emphasis and math
+    LaTeX = 15,
+    /// AsciiDoc source, in the syntax of Asciidoctor
+    AsciiDoc = 16,
+    /// reStructuredText source, as read by docutils and Sphinx
+    RST = 17,
+    /// Org mode source, as written with Emacs
+    Org = 18,
+    /// Tab-separated values, read and written as a table like CSV
+    TSV = 19,
+    /// DocBook 5 XML, generated only
+    DocBook = 20,
+    /// FictionBook 2 e-book, generated only
+    FB2 = 21,
+    /// Jupyter notebook, parsed only
+    Ipynb = 22,
+    /// MediaWiki markup of wiki pages
+    MediaWiki = 23,
+    /// Confluence storage format XHTML of a page body, generated only
+    Confluence = 24,
+    /// Jira wiki markup of issues and comments, parsed only
+    Jira = 25,
+    /// BBCode of forum posts, parsed only
+    BBCode = 26,
+    /// Typst markup of the typesetting system, generated only
+    Typst = 27,
+    /// Man page in roff with the `man` macros, generated only
+    Man = 28,
+    /// Email message (RFC 5322), parsed only
+    Eml = 29,
+    /// vCard contacts, parsed only
+    VCard = 30,
+    /// iCalendar calendar, parsed only
+    ICalendar = 31,
+    /// Pages of the PDF layout as SVG images, generated only
+    SVG = 32,
+    /// PowerPoint presentation of a slide per section, generated only
+    PPTX = 33,
+}
+
+impl DocumentType {
+    pub fn variants() -> &'static [DocumentType] {
+        DocumentType::VARIANTS
+    }
+
+    fn extension_map() -> HashMap<&'static str, DocumentType> {
+        let mut map = HashMap::new();
+        map.insert("html", DocumentType::HTML);
+        map.insert("htm", DocumentType::HTML);
+        map.insert("mht", DocumentType::HTML);
+        map.insert("mhtml", DocumentType::HTML);
+        map.insert("md", DocumentType::Markdown);
+        map.insert("markdown", DocumentType::Markdown);
+        map.insert("txt", DocumentType::Text);
+        map.insert("pdf", DocumentType::PDF);
+        map.insert("json", DocumentType::Json);
+        map.insert("csv", DocumentType::CSV);
+        map.insert("tsv", DocumentType::TSV);
+        map.insert("rtf", DocumentType::RTF);
+        map.insert("docx", DocumentType::DOCX);
+        map.insert("xml", DocumentType::XML);
+        map.insert("xls", DocumentType::XLS);
+        map.insert("xlsx", DocumentType::XLSX);
+        map.insert("ods", DocumentType::ODS);
+        map.insert("odt", DocumentType::ODT);
+        map.insert("epub", DocumentType::EPUB);
+        map.insert("tex", DocumentType::LaTeX);
+        map.insert("latex", DocumentType::LaTeX);
+        map.insert("adoc", DocumentType::AsciiDoc);
+        map.insert("asciidoc", DocumentType::AsciiDoc);
+        map.insert("rst", DocumentType::RST);
+        map.insert("rest", DocumentType::RST);
+        map.insert("org", DocumentType::Org);
+        map.insert("dbk", DocumentType::DocBook);
+        map.insert("docbook", DocumentType::DocBook);
+        map.insert("fb2", DocumentType::FB2);
+        map.insert("ipynb", DocumentType::Ipynb);
+        map.insert("wiki", DocumentType::MediaWiki);
+        map.insert("mediawiki", DocumentType::MediaWiki);
+        map.insert("confluence", DocumentType::Confluence);
+        map.insert("jira", DocumentType::Jira);
+        map.insert("bbcode", DocumentType::BBCode);
+        map.insert("bb", DocumentType::BBCode);
+        map.insert("typ", DocumentType::Typst);
+        map.insert("man", DocumentType::Man);
+        map.insert("1", DocumentType::Man);
+        map.insert("eml", DocumentType::Eml);
+        map.insert("vcf", DocumentType::VCard);
+        map.insert("vcard", DocumentType::VCard);
+        map.insert("ics", DocumentType::ICalendar);
+        map.insert("ical", DocumentType::ICalendar);
+        map.insert("svg", DocumentType::SVG);
+        map.insert("pptx", DocumentType::PPTX);
+        for extension in ["png", "jpg", "jpeg", "gif", "bmp", "tif", "tiff"] {
+            map.insert(extension, DocumentType::Image);
+        }
+        map
+    }
+
+    pub fn from_extension(extension: &str) -> Option<DocumentType> {
+        Self::extension_map().get(extension).cloned()
+    }
+
+    pub fn supported_extensions() -> Vec<&'static str> {
+        Self::extension_map().keys().cloned().collect()
+    }
+}
+
+type ParseFn = Box<dyn Fn(&Bytes, &ParseOptions) -> crate::Result<Document> + Send + Sync>;
+type GenerateFn = Box<dyn Fn(&Document, &GenerateOptions) -> crate::Result<Bytes> + Send + Sync>;
+
+/// Parsers and generators keyed by [`DocumentType`], so that applications dispatch on the
+/// format in one place and can add or replace transformers
+///
+/// ```
+/// use shiva::core::{DocumentType, TransformerRegistry};
+///
+/// let registry = TransformerRegistry::builtin();
+/// let document = registry.parse(DocumentType::Markdown, &"# Title".into())?;
+/// let html = registry.generate(DocumentType::HTML, &document)?;
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+#[derive(Default)]
+pub struct TransformerRegistry {
+    parsers: HashMap<DocumentType, ParseFn>,
+    generators: HashMap<DocumentType, GenerateFn>,
+}
+
+impl TransformerRegistry {
+    /// Empty registry
+    pub fn new() -> TransformerRegistry {
+        TransformerRegistry::default()
+    }
+
+    /// Registry with the transformers of the enabled features
+    pub fn with_builtin() -> TransformerRegistry {
+        let mut registry = TransformerRegistry::new();
+        #[cfg(feature = "html")]
+        registry.register::<html::Transformer>(DocumentType::HTML);
+        #[cfg(feature = "markdown")]
+        registry.register::<markdown::Transformer>(DocumentType::Markdown);
+        #[cfg(feature = "text")]
+        registry.register::<text::Transformer>(DocumentType::Text);
+        #[cfg(feature = "pdf")]
+        registry.register::<pdf::Transformer>(DocumentType::PDF);
+        #[cfg(feature = "json")]
+        registry.register::<json::Transformer>(DocumentType::Json);
+        #[cfg(feature = "csv")]
+        registry.register::<csv::Transformer>(DocumentType::CSV);
+        #[cfg(feature = "csv")]
+        registry.register::<csv::TsvTransformer>(DocumentType::TSV);
+        #[cfg(feature = "rtf")]
+        registry.register::<rtf::Transformer>(DocumentType::RTF);
+        #[cfg(feature = "docx")]
+        registry.register::<docx::Transformer>(DocumentType::DOCX);
+        #[cfg(feature = "xml")]
+        registry.register::<xml::Transformer>(DocumentType::XML);
+        #[cfg(feature = "xls")]
+        registry.register_parser(DocumentType::XLS, xls::Transformer::parse);
+        #[cfg(feature = "xlsx")]
+        registry.register::<xlsx::Transformer>(DocumentType::XLSX);
+        #[cfg(feature = "ods")]
+        registry.register::<ods::Transformer>(DocumentType::ODS);
+        #[cfg(feature = "odt")]
+        registry.register::<odt::Transformer>(DocumentType::ODT);
+        #[cfg(feature = "epub")]
+        registry.register::<epub::Transformer>(DocumentType::EPUB);
+        #[cfg(feature = "latex")]
+        registry.register::<latex::Transformer>(DocumentType::LaTeX);
+        #[cfg(feature = "asciidoc")]
+        registry.register::<asciidoc::Transformer>(DocumentType::AsciiDoc);
+        #[cfg(feature = "rst")]
+        registry.register::<rst::Transformer>(DocumentType::RST);
+        #[cfg(feature = "org")]
+        registry.register::<org::Transformer>(DocumentType::Org);
+        #[cfg(feature = "docbook")]
+        registry.register_generator(DocumentType::DocBook, docbook::Transformer::generate);
+        #[cfg(feature = "fb2")]
+        registry.register_generator(DocumentType::FB2, fb2::Transformer::generate);
+        #[cfg(feature = "ipynb")]
+        registry.parsers.insert(
+            DocumentType::Ipynb,
+            Box::new(ipynb::Transformer::parse_with_options),
+        );
+        #[cfg(feature = "wiki")]
+        registry.register::<wiki::Transformer>(DocumentType::MediaWiki);
+        #[cfg(feature = "confluence")]
+        registry.register_generator(DocumentType::Confluence, confluence::Transformer::generate);
+        #[cfg(feature = "jira")]
+        registry.register_parser(DocumentType::Jira, jira::Transformer::parse);
+        #[cfg(feature = "bbcode")]
+        registry.register_parser(DocumentType::BBCode, bbcode::Transformer::parse);
+        #[cfg(feature = "typst")]
+        registry.register_generator(DocumentType::Typst, typst::Transformer::generate);
+        #[cfg(feature = "man")]
+        registry.register_generator(DocumentType::Man, man::Transformer::generate);
+        #[cfg(feature = "eml")]
+        registry.parsers.insert(
+            DocumentType::Eml,
+            Box::new(eml::Transformer::parse_with_options),
+        );
+        #[cfg(feature = "vcard")]
+        registry.register_parser(DocumentType::VCard, vcard::Transformer::parse);
+        #[cfg(feature = "icalendar")]
+        registry.register_parser(DocumentType::ICalendar, icalendar::Transformer::parse);
+        #[cfg(feature = "svg")]
+        registry.generators.insert(
+            DocumentType::SVG,
+            Box::new(svg::Transformer::generate_with_options),
+        );
+        #[cfg(feature = "pptx")]
+        registry.register_generator(DocumentType::PPTX, pptx::Transformer::generate);
+        #[cfg(feature = "image")]
+        registry
+            .parsers
+            .insert(DocumentType::Image, Box::new(crate::raster::parse));
+        registry
+    }
+
+    /// Shared registry of the built-in transformers
+    pub fn builtin() -> &'static TransformerRegistry {
+        static BUILTIN: std::sync::OnceLock<TransformerRegistry> = std::sync::OnceLock::new();
+        BUILTIN.get_or_init(TransformerRegistry::with_builtin)
+    }
+
+    /// Registers the parser and the generator of a transformer, replacing the existing ones
+    pub fn register<T: TransformerTrait + 'static>(&mut self, document_type: DocumentType) {
+        self.parsers
+            .insert(document_type, Box::new(T::parse_with_options));
+        self.generators
+            .insert(document_type, Box::new(T::generate_with_options));
+    }
+
+    pub fn register_parser<F>(&mut self, document_type: DocumentType, parser: F)
+    where
+        F: Fn(&Bytes) -> crate::Result<Document> + Send + Sync + 'static,
+    {
+        self.parsers.insert(
+            document_type,
+            Box::new(move |input_bytes, _: &ParseOptions| parser(input_bytes)),
+        );
+    }
+
+    pub fn register_generator<F>(&mut self, document_type: DocumentType, generator: F)
+    where
+        F: Fn(&Document) -> crate::Result<Bytes> + Send + Sync + 'static,
+    {
+        self.generators.insert(
+            document_type,
+            Box::new(move |document, _: &GenerateOptions| generator(document)),
+        );
+    }
+
+    pub fn parse(
+        &self,
+        document_type: DocumentType,
+        input_bytes: &Bytes,
+    ) -> crate::Result<Document> {
+        self.parse_with_options(document_type, input_bytes, &ParseOptions::default())
+    }
+
+    pub fn parse_with_options(
+        &self,
+        document_type: DocumentType,
+        input_bytes: &Bytes,
+        options: &ParseOptions,
+    ) -> crate::Result<Document> {
+        let parser = self
+            .parsers
+            .get(&document_type)
+            .ok_or_else(|| crate::Error::UnsupportedFormat(document_type.to_string()))?;
+        parser(input_bytes, options)
+    }
+
+    pub fn generate(
+        &self,
+        document_type: DocumentType,
+        document: &Document,
+    ) -> crate::Result<Bytes> {
+        self.generate_with_options(document_type, document, &GenerateOptions::default())
+    }
+
+    pub fn generate_with_options(
+        &self,
+        document_type: DocumentType,
+        document: &Document,
+        options: &GenerateOptions,
+    ) -> crate::Result<Bytes> {
+        let generator = self
+            .generators
+            .get(&document_type)
+            .ok_or_else(|| crate::Error::UnsupportedFormat(document_type.to_string()))?;
+        #[cfg(feature = "image")]
+        if options.images != ImageOptions::default() {
+            let document =
+                crate::image_pipeline::process_images(document, document_type, &options.images)?;
+            return generator(&document, options);
+        }
+        generator(document, options)
+    }
+
+    pub fn can_parse(&self, document_type: DocumentType) -> bool {
+        self.parsers.contains_key(&document_type)
+    }
+
+    pub fn can_generate(&self, document_type: DocumentType) -> bool {
+        self.generators.contains_key(&document_type)
+    }
+
+    /// Formats with a parser, in the order of [`DocumentType::variants`]
+    pub fn parse_types(&self) -> Vec<DocumentType> {
+        DocumentType::variants()
+            .iter()
+            .copied()
+            .filter(|document_type| self.can_parse(*document_type))
+            .collect()
+    }
+
+    /// Formats with a generator, in the order of [`DocumentType::variants`]
+    pub fn generate_types(&self) -> Vec<DocumentType> {
+        DocumentType::variants()
+            .iter()
+            .copied()
+            .filter(|document_type| self.can_generate(*document_type))
+            .collect()
+    }
+}
+
+/// Size of the texts created by [`DocumentBuilder`]
+const BUILDER_TEXT_SIZE: u8 = 12;
+
+/// Width of the table columns created by [`DocumentBuilder`]
+const BUILDER_COLUMN_WIDTH: f32 = 30.0;
+
+fn builder_text(text: impl Into<String>) -> Element {
+    Element::Text {
+        text: text.into(),
+        size: BUILDER_TEXT_SIZE,
+    }
+}
+
+/// Fluent construction of a [`Document`] without spelling out the element structs
+///
+/// ```
+/// use shiva::core::DocumentBuilder;
+///
+/// let document = DocumentBuilder::new()
+///     .header(1, "Report")
+///     .paragraph(|p| p.text("Total: ").bold("42"))
+///     .list(false, |l| l.item("first").item("second"))
+///     .table(|t| t.header("Name").header("Qty").row(["apples", "3"]))
+///     .build();
+/// assert_eq!(document.get_all_elements().len(), 4);
+/// ```
+#[derive(Debug)]
+pub struct DocumentBuilder {
+    document: Document,
+}
+
+impl Default for DocumentBuilder {
+    fn default() -> Self {
+        DocumentBuilder::new()
+    }
+}
+
+impl DocumentBuilder {
+    pub fn new() -> DocumentBuilder {
+        DocumentBuilder {
+            document: Document::new(vec![]),
+        }
+    }
+
+    /// Appends any element to the detail band
+    pub fn element(mut self, element: Element) -> DocumentBuilder {
+        for band in self.document.bands.iter_mut() {
+            if let Band::Detail(elements) = band {
+                elements.push(element);
+                return self;
+            }
+        }
+        self.document.bands.push(Band::Detail(vec![element]));
+        self
+    }
+
+    pub fn header(self, level: u8, text: impl Into<String>) -> DocumentBuilder {
+        self.element(Element::Header {
+            level,
+            text: text.into(),
+        })
+    }
+
+    pub fn text(self, text: impl Into<String>) -> DocumentBuilder {
+        self.element(builder_text(text))
+    }
+
+    pub fn paragraph(
+        self,
+        build: impl FnOnce(ParagraphBuilder) -> ParagraphBuilder,
+    ) -> DocumentBuilder {
+        let paragraph = build(ParagraphBuilder::default()).build();
+        self.element(paragraph)
+    }
+
+    pub fn list(
+        self,
+        numbered: bool,
+        build: impl FnOnce(ListBuilder) -> ListBuilder,
+    ) -> DocumentBuilder {
+        let list = build(ListBuilder::new(numbered)).build();
+        self.element(list)
+    }
+
+    pub fn table(self, build: impl FnOnce(TableBuilder) -> TableBuilder) -> DocumentBuilder {
+        let table = build(TableBuilder::default()).build();
+        self.element(table)
+    }
+
+    pub fn image(self, image: ImageData) -> DocumentBuilder {
+        self.element(Element::Image(image))
+    }
+
+    pub fn code_block(self, info: impl Into<String>, code: impl Into<String>) -> DocumentBuilder {
+        self.element(Element::CodeBlock {
+            info: info.into(),
+            code: code.into(),
+        })
+    }
+
+    pub fn line_break(self) -> DocumentBuilder {
+        self.element(Element::LineBreak)
+    }
+
+    pub fn table_of_contents(self, max_level: u8) -> DocumentBuilder {
+        self.element(Element::TableOfContents { max_level })
+    }
+
+    pub fn bookmark(self, id: impl Into<String>, title: impl Into<String>) -> DocumentBuilder {
+        self.element(Element::Bookmark {
+            id: id.into(),
+            title: title.into(),
+        })
+    }
+
+    pub fn media(
+        self,
+        kind: MediaKind,
+        url: impl Into<String>,
+        title: impl Into<String>,
+    ) -> DocumentBuilder {
+        self.element(Element::Media {
+            kind,
+            url: url.into(),
+            title: title.into(),
+        })
+    }
+
+    pub fn drawing(self, width: f32, height: f32, shapes: Vec<Shape>) -> DocumentBuilder {
+        self.element(Element::Drawing {
+            width,
+            height,
+            shapes,
+        })
+    }
+
+    /// Title band of the document
+    pub fn title(mut self, text: impl Into<String>) -> DocumentBuilder {
+        self.document.set_title(vec![builder_text(text)]);
+        self
+    }
+
+    /// Page header band, made of the inline elements of the paragraph
+    pub fn page_header(
+        mut self,
+        build: impl FnOnce(ParagraphBuilder) -> ParagraphBuilder,
+    ) -> DocumentBuilder {
+        self.document
+            .set_page_header(build(ParagraphBuilder::default()).elements);
+        self
+    }
+
+    /// Page footer band, made of the inline elements of the paragraph
+    pub fn page_footer(
+        mut self,
+        build: impl FnOnce(ParagraphBuilder) -> ParagraphBuilder,
+    ) -> DocumentBuilder {
+        self.document
+            .set_page_footer(build(ParagraphBuilder::default()).elements);
+        self
+    }
+
+    pub fn page_size(mut self, page_size: PageSize) -> DocumentBuilder {
+        self.document.page_format = page_size;
+        self
+    }
+
+    pub fn orientation(mut self, orientation: PageOrientation) -> DocumentBuilder {
+        self.document.orientation = orientation;
+        self
+    }
+
+    pub fn direction(mut self, direction: TextDirection) -> DocumentBuilder {
+        self.document.direction = direction;
+        self
+    }
+
+    /// Appends a container of the elements added by `build`
+    pub fn container(
+        self,
+        kind: ContainerKind,
+        build: impl FnOnce(DocumentBuilder) -> DocumentBuilder,
+    ) -> DocumentBuilder {
+        let elements = build(DocumentBuilder::new())
+            .build()
+            .get_all_elements()
+            .into_iter()
+            .cloned()
+            .collect();
+        self.element(Element::Container { kind, elements })
+    }
+
+    /// Starts a section with its own page setup before the next element
+    pub fn section(
+        mut self,
+        page_format: PageFormat,
+        orientation: PageOrientation,
+    ) -> DocumentBuilder {
+        let start = self.document.get_detail().len();
+        self.document
+            .add_section(Section::new(start, page_format, orientation));
+        self
+    }
+
+    pub fn build(self) -> Document {
+        self.document
+    }
+}
+
+/// Inline content of a paragraph, see [`DocumentBuilder::paragraph`]
+#[derive(Debug, Default)]
+pub struct ParagraphBuilder {
+    elements: Vec<Element>,
+    direction: TextDirection,
+}
+
+impl ParagraphBuilder {
+    pub fn element(mut self, element: Element) -> ParagraphBuilder {
+        self.elements.push(element);
+        self
+    }
+
+    pub fn text(self, text: impl Into<String>) -> ParagraphBuilder {
+        self.element(builder_text(text))
+    }
+
+    /// Bold text, kept as a CSS style until the model has inline formatting
+    pub fn bold(self, text: impl Into<String>) -> ParagraphBuilder {
+        self.element(builder_text(text).with_attributes(HashMap::from([(
+            "style".to_string(),
+            "font-weight: bold".to_string(),
+        )])))
+    }
+
+    pub fn link(self, url: impl Into<String>, text: impl Into<String>) -> ParagraphBuilder {
+        let url = url.into();
+        self.element(Element::Hyperlink {
+            elements: vec![builder_text(text)],
+            alt: url.clone(),
+            url,
+            size: BUILDER_TEXT_SIZE,
+        })
+    }
+
+    pub fn code(self, text: impl Into<String>) -> ParagraphBuilder {
+        self.element(Element::InlineCode { text: text.into() })
+    }
+
+    pub fn styled(self, style: TextStyle, text: impl Into<String>) -> ParagraphBuilder {
+        self.element(Element::Styled {
+            style,
+            elements: vec![builder_text(text)],
+        })
+    }
+
+    pub fn footnote(self, text: impl Into<String>) -> ParagraphBuilder {
+        self.element(Element::Footnote {
+            elements: vec![builder_text(text)],
+        })
+    }
+
+    pub fn line_break(self) -> ParagraphBuilder {
+        self.element(Element::LineBreak)
+    }
+
+    pub fn field(self, kind: FieldKind) -> ParagraphBuilder {
+        self.element(Element::Field { kind })
+    }
+
+    pub fn cross_reference(
+        self,
+        kind: ReferenceKind,
+        target_id: impl Into<String>,
+    ) -> ParagraphBuilder {
+        self.element(Element::CrossReference {
+            target_id: target_id.into(),
+            kind,
+        })
+    }
+
+    pub fn direction(mut self, direction: TextDirection) -> ParagraphBuilder {
+        self.direction = direction;
+        self
+    }
+
+    fn build(self) -> Element {
+        Element::Paragraph {
+            elements: self.elements,
+            direction: self.direction,
+        }
+    }
+}
+
+/// Items of a list, see [`DocumentBuilder::list`]
+#[derive(Debug)]
+pub struct ListBuilder {
+    elements: Vec<ListItem>,
+    numbered: bool,
+    start: usize,
+    numbering: NumberingStyle,
+}
+
+impl ListBuilder {
+    fn new(numbered: bool) -> ListBuilder {
+        ListBuilder {
+            elements: vec![],
+            numbered,
+            start: 1,
+            numbering: NumberingStyle::default(),
+        }
+    }
+
+    pub fn item(self, text: impl Into<String>) -> ListBuilder {
+        self.item_element(builder_text(text))
+    }
+
+    pub fn item_element(mut self, element: Element) -> ListBuilder {
+        self.elements.push(ListItem { element });
+        self
+    }
+
+    /// Nested list, added as an item following the previous one
+    pub fn sublist(
+        self,
+        numbered: bool,
+        build: impl FnOnce(ListBuilder) -> ListBuilder,
+    ) -> ListBuilder {
+        let list = build(ListBuilder::new(numbered)).build();
+        self.item_element(list)
+    }
+
+    /// Number of the first item of a numbered list
+    pub fn start(mut self, start: usize) -> ListBuilder {
+        self.start = start;
+        self
+    }
+
+    pub fn numbering(mut self, numbering: NumberingStyle) -> ListBuilder {
+        self.numbering = numbering;
+        self
+    }
+
+    fn build(self) -> Element {
+        Element::List {
+            elements: self.elements,
+            numbered: self.numbered,
+            start: self.start,
+            numbering: self.numbering,
+        }
+    }
+}
+
+/// Headers and rows of a table, see [`DocumentBuilder::table`]
+#[derive(Debug, Default)]
+pub struct TableBuilder {
+    headers: Vec<TableHeader>,
+    rows: Vec<TableRow>,
 }
 
-impl DocumentType {
-    pub fn variants() -> &'static [DocumentType] {
-        DocumentType::VARIANTS
+impl TableBuilder {
+    pub fn header(self, text: impl Into<String>) -> TableBuilder {
+        self.header_element(builder_text(text), BUILDER_COLUMN_WIDTH)
     }
 
-    fn extension_map() -> HashMap<&'static str, DocumentType> {
-        let mut map = HashMap::new();
-        map.insert("html", DocumentType::HTML);
-        map.insert("md", DocumentType::Markdown);
-        map.insert("markdown", DocumentType::Markdown);
-        map.insert("txt", DocumentType::Text);
-        map.insert("pdf", DocumentType::PDF);
-        map.insert("json", DocumentType::Json);
-        map.insert("csv", DocumentType::CSV);
-        map.insert("rtf", DocumentType::RTF);
-        map.insert("docx", DocumentType::DOCX);
-        map.insert("xml", DocumentType::XML);
-        map.insert("xls", DocumentType::XLS);
-        map.insert("xlsx", DocumentType::XLSX);
-        map.insert("ods", DocumentType::ODS);
-        map
+    pub fn header_element(mut self, element: Element, width: f32) -> TableBuilder {
+        self.headers.push(TableHeader { element, width });
+        self
     }
 
-    pub fn from_extension(extension: &str) -> Option<DocumentType> {
-        Self::extension_map().get(extension).cloned()
+    pub fn row<S: Into<String>>(self, cells: impl IntoIterator<Item = S>) -> TableBuilder {
+        self.row_elements(cells.into_iter().map(builder_text))
     }
 
-    pub fn supported_extensions() -> Vec<&'static str> {
-        Self::extension_map().keys().cloned().collect()
+    pub fn row_elements(mut self, cells: impl IntoIterator<Item = Element>) -> TableBuilder {
+        self.rows.push(TableRow {
+            cells: cells
+                .into_iter()
+                .map(|element| TableCell { element })
+                .collect(),
+        });
+        self
+    }
+
+    fn build(self) -> Element {
+        Element::Table {
+            headers: self.headers,
+            rows: self.rows,
+        }
     }
 }
 
@@ -819,6 +4632,35 @@ pub mod tests {
         });
     }
 
+    /// Directory of the files written by the tests, outside of the tree so that a test run
+    /// leaves the tracked test data as it is
+    pub fn output_dir() -> String {
+        let dir = std::env::temp_dir().join("shiva-test");
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.to_string_lossy().into_owned()
+    }
+
+    /// Texts of the headers and of the cells row by row of a table
+    pub fn table_texts(table: &Element) -> (Vec<String>, Vec<Vec<String>>) {
+        let Element::Table { headers, rows } = table else {
+            panic!("not a table: {:?}", table);
+        };
+        (
+            headers
+                .iter()
+                .map(|header| header.element.plain_text())
+                .collect(),
+            rows.iter()
+                .map(|row| {
+                    row.cells
+                        .iter()
+                        .map(|cell| cell.element.plain_text())
+                        .collect()
+                })
+                .collect(),
+        )
+    }
+
     const VARIANTS: &[DocumentType] = &[
         DocumentType::HTML,
         DocumentType::Markdown,
@@ -832,6 +4674,28 @@ pub mod tests {
         DocumentType::XLS,
         DocumentType::XLSX,
         DocumentType::ODS,
+        DocumentType::Image,
+        DocumentType::ODT,
+        DocumentType::EPUB,
+        DocumentType::LaTeX,
+        DocumentType::AsciiDoc,
+        DocumentType::RST,
+        DocumentType::Org,
+        DocumentType::TSV,
+        DocumentType::DocBook,
+        DocumentType::FB2,
+        DocumentType::Ipynb,
+        DocumentType::MediaWiki,
+        DocumentType::Confluence,
+        DocumentType::Jira,
+        DocumentType::BBCode,
+        DocumentType::Typst,
+        DocumentType::Man,
+        DocumentType::Eml,
+        DocumentType::VCard,
+        DocumentType::ICalendar,
+        DocumentType::SVG,
+        DocumentType::PPTX,
     ];
 
     #[test]
@@ -911,6 +4775,17 @@ pub mod tests {
         assert_eq!(image.image_type(), &ImageType::Png);
     }
 
+    #[test]
+    fn test_image_type_of_url() {
+        let mut image = ImageData::default();
+        image.set_image_type("https://example.com/photo.JPG?w=640#top");
+        assert_eq!(image.image_type(), &ImageType::Jpeg);
+        image.set_image_type("scan.tif");
+        assert_eq!(image.image_type(), &ImageType::Tiff);
+        image.set_image_type("https://example.com/photo.webp");
+        assert_eq!(image.image_type(), &ImageType::Png);
+    }
+
     #[test]
     fn test_image_type_extension() {
         assert_eq!(ImageType::Png.to_extension(), ".png");
@@ -928,6 +4803,827 @@ pub mod tests {
         assert_eq!("jpeg", ImageType::Jpeg.to_string());
     }
 
+    #[test]
+    fn test_numbering_style() {
+        assert_eq!(NumberingStyle::Decimal.format(3), "3");
+        assert_eq!(NumberingStyle::LowerAlpha.format(1), "a");
+        assert_eq!(NumberingStyle::LowerAlpha.format(27), "aa");
+        assert_eq!(NumberingStyle::UpperAlpha.format(2), "B");
+        assert_eq!(NumberingStyle::LowerRoman.format(4), "iv");
+        assert_eq!(NumberingStyle::UpperRoman.format(1994), "MCMXCIV");
+        assert_eq!(
+            NumberingStyle::LowerAlpha,
+            NumberingStyle::from_str("lower-alpha").unwrap()
+        );
+        assert_eq!("upper-roman", NumberingStyle::UpperRoman.to_string());
+    }
+
+    #[test]
+    fn test_sections() {
+        let mut document = Document::new(vec![]);
+        document.add_section(Section::new(
+            5,
+            PageFormat::Letter,
+            PageOrientation::Landscape,
+        ));
+        document.add_section(Section::new(
+            2,
+            PageFormat::Legal,
+            PageOrientation::Portrait,
+        ));
+
+        assert_eq!(document.get_sections()[0].start, 2);
+        assert_eq!(
+            document.page_setup_at(0),
+            (&PageFormat::A4, &PageOrientation::Portrait)
+        );
+        assert_eq!(
+            document.page_setup_at(3),
+            (&PageFormat::Legal, &PageOrientation::Portrait)
+        );
+        assert_eq!(
+            document.page_setup_at(7),
+            (&PageFormat::Letter, &PageOrientation::Landscape)
+        );
+
+        document.add_section(Section::new(
+            2,
+            PageFormat::Tabloid,
+            PageOrientation::Portrait,
+        ));
+        assert_eq!(document.get_sections().len(), 2);
+        assert_eq!(document.page_setup_at(2).0, &PageFormat::Tabloid);
+    }
+
+    #[test]
+    fn test_inline_style() {
+        let attributes = |style: &str| HashMap::from([("style".to_string(), style.to_string())]);
+        assert_eq!(
+            InlineStyle::from_attributes(&attributes(
+                "Color: #f00; font-weight: 700; text-align: center; margin: 0"
+            )),
+            InlineStyle {
+                color: Some("FF0000".to_string()),
+                bold: true,
+                align: Some(TextAlign::Center),
+            }
+        );
+        assert_eq!(
+            InlineStyle::from_attributes(&attributes("color: rgb(0, 128, 255);font-weight:normal"))
+                .color
+                .as_deref(),
+            Some("0080FF")
+        );
+        assert!(
+            InlineStyle::from_attributes(&attributes("color: tomato; text-align: inherit"))
+                .is_empty()
+        );
+        assert!(InlineStyle::from_attributes(&HashMap::new()).is_empty());
+    }
+
+    #[test]
+    fn test_build_toc() {
+        let header = |level: u8, text: &str| Element::Header {
+            level,
+            text: text.to_string(),
+        };
+        let link = |text: &str, anchor: &str| ListItem {
+            element: Element::Hyperlink {
+                elements: vec![Element::Text {
+                    text: text.to_string(),
+                    size: 8,
+                }],
+                url: format!("#{}", anchor),
+                alt: text.to_string(),
+                size: 8,
+            },
+        };
+        let list = |elements: Vec<ListItem>| Element::List {
+            elements,
+            numbered: false,
+            start: 1,
+            numbering: NumberingStyle::default(),
+        };
+
+        let document = Document::new(vec![
+            header(1, "Introduction"),
+            header(2, "What's new?"),
+            header(3, "Too deep"),
+            header(1, "Usage"),
+        ]);
+
+        assert_eq!(
+            document.build_toc(2),
+            list(vec![
+                link("Introduction", "introduction"),
+                ListItem {
+                    element: list(vec![link("What's new?", "whats-new")]),
+                },
+                link("Usage", "usage"),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_resolve_fields() {
+        let mut document = Document::new(vec![Element::Paragraph {
+            elements: vec![
+                Element::Field {
+                    kind: FieldKind::DocumentTitle,
+                },
+                Element::Field {
+                    kind: FieldKind::PageNumber,
+                },
+            ],
+            direction: TextDirection::default(),
+        }]);
+        document.set_title(vec![Element::Text {
+            text: "Report".to_string(),
+            size: 14,
+        }]);
+        document.set_page_footer(vec![Element::Field {
+            kind: FieldKind::Date,
+        }]);
+
+        let resolved = document.resolve_fields();
+        assert_eq!(
+            resolved.get_detail()[0],
+            &Element::Paragraph {
+                elements: vec![
+                    Element::Text {
+                        text: "Report".to_string(),
+                        size: 8,
+                    },
+                    Element::Field {
+                        kind: FieldKind::PageNumber,
+                    },
+                ],
+                direction: TextDirection::default(),
+            }
+        );
+        match resolved.get_page_footer()[0] {
+            Element::Text { text, .. } => {
+                assert_eq!(text.len(), 10);
+                assert_eq!(&text[4..5], "-");
+            }
+            _ => panic!("Expected Text"),
+        }
+    }
+
+    #[test]
+    fn test_length() -> anyhow::Result<()> {
+        assert_eq!(Length::inches(1.0), Length::mm(25.4));
+        assert_eq!(Length::inches(1.0).to_pt(), 72.0);
+        assert_eq!(Length::px(96.0).to(LengthUnit::In), Length::inches(1.0));
+        assert_eq!(Length::cm(2.0).to(LengthUnit::Mm).value, 20.0);
+        assert_eq!(Length::from_str("8.5in")?, Length::inches(8.5));
+        assert_eq!(Length::from_str("210")?, Length::mm(210.0));
+        assert_eq!(Length::pt(12.0).to_string(), "12pt");
+        assert!(Length::from_str("12furlongs").is_err());
+
+        let document = Document::new(vec![]).with_page_size(PageSize::A5);
+        assert_eq!(
+            document.page_format.dimensions().page_width,
+            Length::mm(148.0)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_text_direction() {
+        assert_eq!(TextDirection::of_text("שלום world"), TextDirection::Rtl);
+        assert_eq!(TextDirection::of_text("123 مرحبا"), TextDirection::Rtl);
+        assert_eq!(TextDirection::of_text("Hello مرحبا"), TextDirection::Ltr);
+        assert_eq!(TextDirection::of_text("42!"), TextDirection::Auto);
+        assert_eq!(
+            TextDirection::Auto.or(TextDirection::Rtl),
+            TextDirection::Rtl
+        );
+        assert_eq!(
+            TextDirection::Ltr.or(TextDirection::Rtl),
+            TextDirection::Ltr
+        );
+    }
+
+    #[test]
+    fn test_document_builder() {
+        let text = |text: &str| Element::Text {
+            text: text.to_string(),
+            size: 12,
+        };
+
+        let document = DocumentBuilder::new()
+            .title("Report")
+            .header(1, "Summary")
+            .paragraph(|p| p.text("Total: ").bold("42"))
+            .list(true, |l| l.item("first").item("second").start(3))
+            .table(|t| t.header("Name").row(["apples"]))
+            .page_footer(|p| p.field(FieldKind::PageNumber))
+            .build();
+
+        let mut expected = Document::new(vec![
+            Element::Header {
+                level: 1,
+                text: "Summary".to_string(),
+            },
+            Element::Paragraph {
+                elements: vec![
+                    text("Total: "),
+                    text("42").with_attributes(HashMap::from([(
+                        "style".to_string(),
+                        "font-weight: bold".to_string(),
+                    )])),
+                ],
+                direction: TextDirection::default(),
+            },
+            Element::List {
+                elements: vec![
+                    ListItem {
+                        element: text("first"),
+                    },
+                    ListItem {
+                        element: text("second"),
+                    },
+                ],
+                numbered: true,
+                start: 3,
+                numbering: NumberingStyle::default(),
+            },
+            Element::Table {
+                headers: vec![TableHeader {
+                    element: text("Name"),
+                    width: 30.0,
+                }],
+                rows: vec![TableRow {
+                    cells: vec![TableCell {
+                        element: text("apples"),
+                    }],
+                }],
+            },
+        ]);
+        expected.set_title(vec![text("Report")]);
+        expected.set_page_footer(vec![Element::Field {
+            kind: FieldKind::PageNumber,
+        }]);
+        assert_eq!(document, expected);
+    }
+
+    #[test]
+    fn test_find() {
+        let text = |text: &str| Element::Text {
+            text: text.to_string(),
+            size: 8,
+        };
+        let mut document = Document::new(vec![
+            Element::Header {
+                level: 1,
+                text: "Prices".to_string(),
+            },
+            Element::Paragraph {
+                elements: vec![text("Apples are "), text("cheap")],
+                direction: TextDirection::default(),
+            },
+            Element::Table {
+                headers: vec![TableHeader {
+                    element: text("Fruit"),
+                    width: 30.0,
+                }],
+                rows: vec![TableRow {
+                    cells: vec![TableCell {
+                        element: text("apples"),
+                    }],
+                }],
+            },
+        ]);
+
+        let found = document.find("pples");
+        assert_eq!(
+            found,
+            vec![
+                ElementPath::new(0, vec![1, 0]),
+                ElementPath::new(0, vec![2, 1])
+            ]
+        );
+        assert_eq!(
+            document.find("are cheap"),
+            vec![ElementPath::new(0, vec![1])]
+        );
+        assert_eq!(found[1].to_string(), "0/2/1");
+        assert_eq!("0/2/1".parse::<ElementPath>(), Ok(found[1].clone()));
+        assert_eq!(found[1].parent(), Some(ElementPath::new(0, vec![2])));
+
+        *document.get_mut(&found[1]).unwrap() = text("pears");
+        assert_eq!(document.get(&found[1]), Some(&text("pears")));
+        assert_eq!(document.get(&ElementPath::new(0, vec![2, 2])), None);
+        assert_eq!(document.find("pples").len(), 1);
+    }
+
+    #[test]
+    #[cfg(feature = "regex")]
+    fn test_replace_text() {
+        let text = |text: &str| Element::Text {
+            text: text.to_string(),
+            size: 8,
+        };
+        let mut document = Document::new(vec![
+            Element::Header {
+                level: 1,
+                text: "Call 555-1234".to_string(),
+            },
+            Element::List {
+                elements: vec![ListItem {
+                    element: Element::Hyperlink {
+                        elements: vec![text("555-9876 or 555-0000")],
+                        url: "tel:555-9876".to_string(),
+                        alt: "phone".to_string(),
+                        size: 8,
+                    },
+                }],
+                numbered: false,
+                start: 1,
+                numbering: NumberingStyle::default(),
+            },
+        ]);
+        document.set_page_footer(vec![text("Office: 555-1111")]);
+
+        let pattern = regex::Regex::new(r"555-(\d{4})").unwrap();
+        assert_eq!(document.replace_text(&pattern, "XXX-$1"), 4);
+        assert_eq!(document.find("555"), vec![]);
+        assert_eq!(document.get_all_elements()[0].plain_text(), "Call XXX-1234");
+        assert_eq!(document.get_page_footer(), vec![&text("Office: XXX-1111")]);
+    }
+
+    #[test]
+    fn test_split_and_concat() {
+        let header = |level: u8, text: &str| Element::Header {
+            level,
+            text: text.to_string(),
+        };
+        let paragraph = |text: &str, direction: TextDirection| Element::Paragraph {
+            elements: vec![Element::Text {
+                text: text.to_string(),
+                size: 8,
+            }],
+            direction,
+        };
+        let mut book = Document::new(vec![
+            paragraph("Preface", TextDirection::Auto),
+            header(1, "Chapter 1"),
+            header(2, "Part 1.1"),
+            header(1, "Chapter 2"),
+            paragraph("Wide", TextDirection::Auto),
+        ]);
+        book.set_page_footer(vec![Element::Field {
+            kind: FieldKind::PageNumber,
+        }]);
+        book.set_summary(vec![paragraph("The end", TextDirection::Auto)]);
+        book.add_section(Section::new(4, PageFormat::A4, PageOrientation::Landscape));
+
+        let chapters = book.split_by_headers(1);
+        assert_eq!(chapters.len(), 3);
+        assert_eq!(
+            chapters[1].get_detail(),
+            vec![&header(1, "Chapter 1"), &header(2, "Part 1.1")]
+        );
+        assert_eq!(chapters[1].get_page_footer(), book.get_page_footer());
+        assert!(chapters[1].get_summary().is_empty());
+        assert_eq!(chapters[2].get_summary(), book.get_summary());
+        assert_eq!(
+            chapters[2].sections,
+            vec![Section::new(1, PageFormat::A4, PageOrientation::Landscape)]
+        );
+        assert_eq!(Document::concat(&chapters), book);
+
+        let mut arabic = Document::new(vec![paragraph("مرحبا", TextDirection::Auto)]);
+        arabic.direction = TextDirection::Rtl;
+        arabic.orientation = PageOrientation::Landscape;
+        let document = Document::concat(&[chapters[0].clone(), arabic]);
+        assert_eq!(
+            document.get_detail(),
+            vec![
+                &paragraph("Preface", TextDirection::Auto),
+                &paragraph("مرحبا", TextDirection::Rtl),
+            ]
+        );
+        assert_eq!(
+            document.sections,
+            vec![Section::new(1, PageFormat::A4, PageOrientation::Landscape)]
+        );
+    }
+
+    #[test]
+    fn test_statistics() {
+        let text = |text: &str| Element::Text {
+            text: text.to_string(),
+            size: 8,
+        };
+        let document = Document::new(vec![
+            Element::Header {
+                level: 1,
+                text: "Fruits".to_string(),
+            },
+            Element::Paragraph {
+                elements: vec![text("Apples are red. "), text("Are pears green?")],
+                direction: TextDirection::default(),
+            },
+            Element::Image(ImageData::default()),
+        ]);
+
+        let statistics = document.statistics();
+        assert_eq!(statistics.words, 7);
+        assert_eq!(statistics.characters, 38);
+        assert_eq!(statistics.characters_without_spaces, 33);
+        assert_eq!(statistics.sentences, 3);
+        assert_eq!(statistics.images, 1);
+        assert_eq!(statistics.reading_time_minutes, 1);
+        assert_eq!(
+            statistics.elements,
+            BTreeMap::from([
+                ("Header".to_string(), 1),
+                ("Image".to_string(), 1),
+                ("Paragraph".to_string(), 1),
+                ("Text".to_string(), 2),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        let header = |level: u8, text: &str| Element::Header {
+            level,
+            text: text.to_string(),
+        };
+        let text = |text: &str| Element::Text {
+            text: text.to_string(),
+            size: 8,
+        };
+        let link = |url: &str| Element::Hyperlink {
+            elements: vec![text("link")],
+            url: url.to_string(),
+            alt: String::new(),
+            size: 8,
+        };
+        let document = Document::new(vec![
+            header(1, "Intro"),
+            header(3, " "),
+            Element::Paragraph {
+                elements: vec![
+                    link("#intro"),
+                    link("#missing"),
+                    link("https://example.com"),
+                ],
+                direction: TextDirection::default(),
+            },
+            Element::CrossReference {
+                target_id: "table-2".to_string(),
+                kind: ReferenceKind::Table,
+            },
+            Element::Table {
+                headers: vec![TableHeader {
+                    element: text("A"),
+                    width: 30.0,
+                }],
+                rows: vec![TableRow {
+                    cells: vec![
+                        TableCell { element: text("1") },
+                        TableCell { element: text("2") },
+                    ],
+                }],
+            },
+            Element::Image(ImageData::default()),
+        ]);
+
+        assert_eq!(
+            document.validate(),
+            vec![
+                ValidationWarning::EmptyHeader {
+                    path: ElementPath::new(0, vec![1])
+                },
+                ValidationWarning::HeaderLevelJump {
+                    path: ElementPath::new(0, vec![1]),
+                    from: 1,
+                    to: 3
+                },
+                ValidationWarning::BrokenLink {
+                    path: ElementPath::new(0, vec![2, 1]),
+                    target: "#missing".to_string()
+                },
+                ValidationWarning::BrokenLink {
+                    path: ElementPath::new(0, vec![3]),
+                    target: "table-2".to_string()
+                },
+                ValidationWarning::InconsistentTable {
+                    path: ElementPath::new(0, vec![4]),
+                    row: 0,
+                    expected: 1,
+                    found: 2
+                },
+                ValidationWarning::MissingImageData {
+                    path: ElementPath::new(0, vec![5])
+                },
+            ]
+        );
+        assert_eq!(
+            document.validate()[2].to_string(),
+            "0/2/1: broken link to #missing"
+        );
+        assert!(Document::new(vec![header(1, "Intro")])
+            .validate()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_transformer_registry() -> anyhow::Result<()> {
+        let mut registry = TransformerRegistry::new();
+        assert!(registry.parse(DocumentType::Text, &Bytes::new()).is_err());
+
+        registry.register_parser(DocumentType::CSV, |bytes| {
+            Ok(Document::new(vec![Element::Text {
+                text: String::from_utf8_lossy(bytes).to_string(),
+                size: 8,
+            }]))
+        });
+        registry.register_generator(DocumentType::Text, |document| {
+            Ok(Bytes::from(document.get_all_elements()[0].plain_text()))
+        });
+        assert_eq!(registry.parse_types(), vec![DocumentType::CSV]);
+        assert_eq!(registry.generate_types(), vec![DocumentType::Text]);
+
+        let document = registry.parse(DocumentType::CSV, &Bytes::from("a,b"))?;
+        assert_eq!(
+            registry.generate(DocumentType::Text, &document)?,
+            Bytes::from("a,b")
+        );
+        assert!(registry.generate(DocumentType::CSV, &document).is_err());
+
+        assert!(TransformerRegistry::builtin().can_parse(DocumentType::Markdown));
+        Ok(())
+    }
+
+    #[cfg(all(feature = "tokio", feature = "markdown"))]
+    #[tokio::test]
+    async fn test_async_adapter() -> anyhow::Result<()> {
+        let input = Bytes::from(
+            "![Logo](https://example.com/logo.png)\n\n![Logo](https://example.com/logo.png)",
+        );
+        let requests = std::sync::atomic::AtomicUsize::new(0);
+        let document =
+            AsyncAdapter::<crate::markdown::Transformer>::parse_with_loader(&input, |source| {
+                requests.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                async move {
+                    assert_eq!(source, "https://example.com/logo.png");
+                    Ok(Bytes::from_static(b"png"))
+                }
+            })
+            .await?;
+        assert_eq!(requests.into_inner(), 1);
+
+        let images: Vec<_> = document
+            .get_all_elements()
+            .into_iter()
+            .flat_map(|element| match element {
+                Element::Image(image) => vec![image.bytes().clone()],
+                _ => element
+                    .children()
+                    .into_iter()
+                    .filter_map(|child| match child {
+                        Element::Image(image) => Some(image.bytes().clone()),
+                        _ => None,
+                    })
+                    .collect(),
+            })
+            .collect();
+        assert_eq!(images, vec![Bytes::from_static(b"png"); 2]);
+
+        let failing =
+            AsyncAdapter::<crate::markdown::Transformer>::parse_with_loader(&input, |_| async {
+                Err(anyhow::anyhow!("offline"))
+            })
+            .await;
+        assert!(failing.is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_disk_image_loader() -> anyhow::Result<()> {
+        let loader = disk_image_loader("test/data");
+        assert_eq!(
+            loader("small.png")?,
+            Bytes::from(std::fs::read("test/data/small.png")?)
+        );
+        for image in ["../Cargo.toml", "images/../../Cargo.toml", "/../Cargo.toml"] {
+            let error = loader(image).unwrap_err();
+            assert!(
+                error.to_string().contains("is outside of"),
+                "{image}: {error}"
+            );
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn test_http_image_loader() -> anyhow::Result<()> {
+        use std::io::{Read, Write};
+
+        // answers each request with an image of `body`
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        let base_url = format!("http://{}/images/", listener.local_addr()?);
+        std::thread::spawn(move || {
+            for stream in listener.incoming().take(2) {
+                let mut stream = stream.unwrap();
+                let mut request = [0; 1024];
+                let length = stream.read(&mut request).unwrap();
+                let request = String::from_utf8_lossy(&request[..length]).to_string();
+                assert!(request.starts_with("GET /images/logo.png "));
+                let body = "0123456789";
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                )
+                .unwrap();
+            }
+        });
+
+        let timeout = std::time::Duration::from_secs(5);
+        let loader = fallback_image_loader(
+            disk_image_loader("test/data"),
+            http_loader(&base_url, timeout, 10, true),
+        );
+        assert_eq!(loader("logo.png")?, Bytes::from("0123456789"));
+        let error = http_loader(&base_url, timeout, 5, true)("logo.png").unwrap_err();
+        assert!(error.to_string().contains("larger than 5 bytes"));
+
+        // the local network is refused before connecting
+        for url in [
+            base_url.as_str(),
+            "http://localhost/logo.png",
+            "http://169.254.169.254/latest/meta-data/",
+            "http://10.0.0.1/logo.png",
+            "http://[::ffff:127.0.0.1]/logo.png",
+        ] {
+            let error = http_image_loader("", timeout, 10)(url).unwrap_err();
+            assert!(
+                format!("{:#}", error).contains("is not a public address"),
+                "{url}: {error:#}"
+            );
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn test_is_public() {
+        for address in [
+            "93.184.216.34",
+            "2606:2800:220:1:248:1893:25c8:1946",
+            "8.8.8.8",
+        ] {
+            assert!(is_public(address.parse().unwrap()), "{address}");
+        }
+        for address in [
+            "127.0.0.1",
+            "0.0.0.0",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "255.255.255.255",
+            "::1",
+            "::",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:192.168.1.1",
+            "64:ff9b::a9fe:a9fe",
+        ] {
+            assert!(!is_public(address.parse().unwrap()), "{address}");
+        }
+    }
+
+    #[test]
+    fn test_without_attributes() {
+        let text = Element::Text {
+            text: "Styled".to_string(),
+            size: 8,
+        };
+        assert_eq!(text.clone().with_attributes(HashMap::new()), text);
+
+        let document = Document::new(vec![Element::Paragraph {
+            elements: vec![text
+                .clone()
+                .with_attributes(HashMap::from([("class".to_string(), "note".to_string())]))],
+            direction: TextDirection::default(),
+        }]);
+        assert_eq!(
+            document.without_attributes().get_all_elements(),
+            vec![&Element::Paragraph {
+                elements: vec![text],
+                direction: TextDirection::default(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_without_media() {
+        let document = DocumentBuilder::new()
+            .media(MediaKind::Video, "https://example.com/clip.mp4", "Clip")
+            .media(MediaKind::Frame, "https://example.com/map", "")
+            .build();
+        let link = |url: &str, text: &str, alt: &str| Element::Hyperlink {
+            elements: vec![Element::Text {
+                text: text.to_string(),
+                size: 8,
+            }],
+            url: url.to_string(),
+            alt: alt.to_string(),
+            size: 8,
+        };
+        assert_eq!(
+            document.without_media().get_all_elements(),
+            vec![
+                &link("https://example.com/clip.mp4", "Clip", "Clip"),
+                &link("https://example.com/map", "https://example.com/map", ""),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_without_drawings() {
+        let document = DocumentBuilder::new()
+            .drawing(
+                60.0,
+                30.0,
+                vec![Shape::rect(0.0, 0.0, 60.0, 30.0).with_fill("blue")],
+            )
+            .build();
+        let elements = document.without_drawings().get_all_elements()[0].clone();
+        let Element::Image(image) = elements else {
+            panic!("image expected, got {:?}", elements);
+        };
+        assert_eq!(image.image_type(), &ImageType::Png);
+        assert_eq!(image.size().width.as_deref(), Some("80"));
+        assert_eq!(image.size().height.as_deref(), Some("40"));
+    }
+
+    #[test]
+    fn test_cross_references() {
+        let table = Element::Table {
+            headers: vec![],
+            rows: vec![],
+        };
+        let document = Document::new(vec![
+            Element::Header {
+                level: 1,
+                text: "Intro".to_string(),
+            },
+            table.clone(),
+            Element::Header {
+                level: 2,
+                text: "Results".to_string(),
+            },
+            table,
+            Element::Paragraph {
+                elements: vec![
+                    Element::CrossReference {
+                        target_id: "table-2".to_string(),
+                        kind: ReferenceKind::Table,
+                    },
+                    Element::CrossReference {
+                        target_id: "results".to_string(),
+                        kind: ReferenceKind::Section,
+                    },
+                    Element::CrossReference {
+                        target_id: "missing".to_string(),
+                        kind: ReferenceKind::Figure,
+                    },
+                ],
+                direction: TextDirection::default(),
+            },
+        ]);
+
+        let numbering = document.numbering();
+        assert_eq!(
+            numbering.get(&(ReferenceKind::Section, "results".to_string())),
+            Some(&"1.1".to_string())
+        );
+
+        let resolved = document.resolve_fields();
+        let texts: Vec<String> = match resolved.get_all_elements()[4] {
+            Element::Paragraph { elements, .. } => elements
+                .iter()
+                .map(|element| match element {
+                    Element::Text { text, .. } => text.clone(),
+                    _ => panic!("Expected Text"),
+                })
+                .collect(),
+            _ => panic!("Expected Paragraph"),
+        };
+        assert_eq!(texts, vec!["Table 2", "Section 1.1", "Figure ??"]);
+    }
+
     #[test]
     fn test_image_alignment() {
         assert_eq!(
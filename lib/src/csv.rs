@@ -1,123 +1,189 @@
+//! CSV and TSV files, read as a single table whose first row is the header and written
+//! one CSV block per table of the document, the blocks separated by an empty line
 use crate::core::{
-    Document,
-    Element::{Table, Text},
-    TableCell, TableHeader, TableRow, TransformerTrait,
+    CsvOptions, CsvParseOptions, Document,
+    Element::{self, Table, Text},
+    GenerateOptions, ParseOptions, TableCell, TableHeader, TableRow, TransformerTrait,
 };
+use anyhow::anyhow;
 use bytes::Bytes;
-use log::info;
+
 pub struct Transformer;
 
-#[allow(unused)]
+/// Tab-separated values, the CSV transformer with a tab as delimiter
+pub struct TsvTransformer;
 
 impl TransformerTrait for Transformer {
-    /// Parses CSV data from a `Bytes` object and converts it into a structured `Document`.
-    /// This function is used to interpret CSV data, typically transforming it into a format
-    /// that can be more easily manipulated within the system.
-    ///
-    /// # Arguments
-    /// * `document` - The CSV data encapsulated in a `Bytes` instance.
-    ///
-    /// # Returns
-    /// A result containing a `Document` if successful, or an `anyhow::Error` in case of failure.
-    fn parse(document: &Bytes) -> anyhow::Result<Document> {
-        // Deserialize the CSV data into a nested Vec structure.
-        let document = serialize_csv(document)?;
-
-        // Check if the document is empty and return an empty `Document` if so.
-        if document.is_empty() {
-            return Ok(Document::new(Vec::new()));
-        }
+    fn parse(document: &Bytes) -> crate::Result<Document> {
+        Transformer::parse_with_options(document, &ParseOptions::default())
+    }
 
-        // Create an iterator over the rows of the CSV data.
-        let mut document_iter = document.iter();
-
-        // Extract the first row, which contains the headers.
-        let headings: &Vec<String> = document_iter.next().unwrap();
-        let mut headers: Vec<TableHeader> = Vec::new();
-
-        // Process each header name to create `TableHeader` elements.
-        for name in headings {
-            headers.push(TableHeader {
-                element: Text {
-                    text: name.clone(),
-                    size: 8, // Default font size
-                },
-                width: 10.0, // Default width, can be adjusted as needed
-            });
-        }
+    fn parse_with_options(document: &Bytes, options: &ParseOptions) -> crate::Result<Document> {
+        parse_bytes(document, &options.csv, b',').map_err(crate::Error::parse_error)
+    }
+
+    fn generate(document: &Document) -> crate::Result<Bytes> {
+        Transformer::generate_with_options(document, &GenerateOptions::default())
+    }
+
+    fn generate_with_options(
+        document: &Document,
+        options: &GenerateOptions,
+    ) -> crate::Result<Bytes> {
+        generate_bytes(document, &options.csv, b',').map_err(crate::Error::generation_error)
+    }
+}
 
-        // Prepare to collect the table rows.
-        let mut rows: Vec<TableRow> = Vec::with_capacity(document.len() - 1);
+impl TransformerTrait for TsvTransformer {
+    fn parse(document: &Bytes) -> crate::Result<Document> {
+        TsvTransformer::parse_with_options(document, &ParseOptions::default())
+    }
 
-        // Process each subsequent row in the CSV data.
-        for lines in document_iter {
-            let mut curr_row: Vec<TableCell> = Vec::with_capacity(headers.len());
+    fn parse_with_options(document: &Bytes, options: &ParseOptions) -> crate::Result<Document> {
+        parse_bytes(document, &options.csv, b'\t').map_err(crate::Error::parse_error)
+    }
+
+    fn generate(document: &Document) -> crate::Result<Bytes> {
+        TsvTransformer::generate_with_options(document, &GenerateOptions::default())
+    }
 
-            // Create a `TableCell` for each cell in the row.
-            for cell in lines {
-                curr_row.push(TableCell {
+    fn generate_with_options(
+        document: &Document,
+        options: &GenerateOptions,
+    ) -> crate::Result<Bytes> {
+        generate_bytes(document, &options.csv, b'\t').map_err(crate::Error::generation_error)
+    }
+}
+
+/// Byte of a delimiter or quote character, the csv reader and writer only take ASCII
+fn ascii(c: char, name: &str) -> anyhow::Result<u8> {
+    u8::try_from(c)
+        .ok()
+        .filter(u8::is_ascii)
+        .ok_or_else(|| anyhow!("The {name} of CSV must be an ASCII character, not {c:?}"))
+}
+
+/// Parses CSV data into a document with a single table, its first row being the headers.
+/// Rows may have fewer or more cells than the headers.
+fn parse_bytes(
+    document: &Bytes,
+    options: &CsvParseOptions,
+    delimiter: u8,
+) -> anyhow::Result<Document> {
+    let delimiter = match options.delimiter {
+        Some(delimiter) => ascii(delimiter, "delimiter")?,
+        None => delimiter,
+    };
+    let data = std::str::from_utf8(document)?;
+    let data = data.strip_prefix('\u{feff}').unwrap_or(data);
+    let document = read_records(data, delimiter, ascii(options.quote, "quote")?)?;
+
+    let mut records = document.into_iter();
+    // an empty file is an empty document
+    let Some(headings) = records.next() else {
+        return Ok(Document::new(Vec::new()));
+    };
+    let headers: Vec<TableHeader> = headings
+        .into_iter()
+        .map(|name| TableHeader {
+            element: Text {
+                text: name,
+                size: 8, // Default font size
+            },
+            width: 10.0, // Default width, can be adjusted as needed
+        })
+        .collect();
+    let rows: Vec<TableRow> = records
+        .map(|record| TableRow {
+            cells: record
+                .into_iter()
+                .map(|cell| TableCell {
                     element: Text {
-                        text: cell.clone(),
-                        size: 8, // Default font size
+                        text: cell,
+                        size: 8,
                     },
-                });
-            }
+                })
+                .collect(),
+        })
+        .collect();
 
-            // Add the completed row to the rows collection.
-            rows.push(TableRow { cells: curr_row });
-        }
+    Ok(Document::new(vec![Table { headers, rows }]))
+}
 
-        // Construct the `Document` with the table created from the CSV data.
-        Ok(Document::new(vec![Table { headers, rows }]))
-    }
-
-    fn generate(document: &Document) -> anyhow::Result<Bytes> {
-        let elements = document.get_all_elements();
-
-        let mut data: Vec<Vec<String>> = Vec::new();
-
-        for element in elements {
-            if let Table { headers, rows } = element {
-                // Create a new vector for the header row
-                let mut header_line = Vec::new();
-                for header in headers {
-                    if let Text { text, size: _ } = &header.element {
-                        header_line.push(text.clone())
-                    }
-                }
-                // Push header row to data
-                data.push(header_line);
-
-                // Iterate over each row
-                for row in rows {
-                    let mut curr_line = Vec::new(); // This must be inside the loop
-                    for cell in &row.cells {
-                        if let Text { text, size: _ } = &cell.element {
-                            curr_line.push(text.clone())
-                        }
-                    }
-                    // Push each row to data
-                    data.push(curr_line);
-                }
-            }
+/// Generates a CSV block for each table of the document, or for the table at
+/// `options.table` (counted from 0, in the order of the document) only
+fn generate_bytes(
+    document: &Document,
+    options: &CsvOptions,
+    delimiter: u8,
+) -> anyhow::Result<Bytes> {
+    let delimiter = match options.delimiter {
+        Some(delimiter) => ascii(delimiter, "delimiter")?,
+        None => delimiter,
+    };
+    let quote = ascii(options.quote, "quote")?;
+
+    let document = &document.resolve_fields();
+    let mut tables = vec![];
+    for element in document.get_all_elements() {
+        collect_tables(element, &mut tables);
+    }
+    if let Some(index) = options.table {
+        let table = tables.get(index).copied().ok_or_else(|| {
+            anyhow!(
+                "There is no table {index} in the document, it has {} (counted from 0)",
+                tables.len()
+            )
+        })?;
+        tables = vec![table];
+    }
+
+    let mut blocks = vec![];
+    for table in tables {
+        let Table { headers, rows } = table else {
+            continue;
+        };
+        let mut data: Vec<Vec<String>> = Vec::with_capacity(rows.len() + 1);
+        if !headers.is_empty() {
+            data.push(
+                headers
+                    .iter()
+                    .map(|header| header.element.plain_text())
+                    .collect(),
+            );
+        }
+        for row in rows {
+            data.push(
+                row.cells
+                    .iter()
+                    .map(|cell| cell.element.plain_text())
+                    .collect(),
+            );
         }
+        blocks.push(write_records(&data, delimiter, quote)?);
+    }
 
-        // Serialize the data into CSV format and convert it to Bytes
-        let csv_bytes = deserialize_csv(&data)?;
+    Ok(Bytes::from(blocks.join("\n")))
+}
 
-        // Return Bytes and an empty HashMap for images or additional data
-        Ok(csv_bytes)
+/// Tables of an element and of its children, in the order of the document
+fn collect_tables<'a>(element: &'a Element, tables: &mut Vec<&'a Element>) {
+    if matches!(element, Table { .. }) {
+        tables.push(element);
+    }
+    for child in element.children() {
+        collect_tables(child, tables);
     }
 }
 
-fn serialize_csv(csv_data: &Bytes) -> anyhow::Result<Vec<Vec<String>>> {
-    // Convert Bytes to a UTF-8 string slice
-    let data_str = std::str::from_utf8(csv_data)?;
-
+fn read_records(data: &str, delimiter: u8, quote: u8) -> anyhow::Result<Vec<Vec<String>>> {
     // Create a CSV reader from a string slice
     let mut rdr = csv::ReaderBuilder::new()
         .has_headers(false) // We consider that we have no headers so that they'll be preserved
-        .from_reader(data_str.as_bytes());
+        .flexible(true)
+        .delimiter(delimiter)
+        .quote(quote)
+        .from_reader(data.as_bytes());
 
     let mut data = Vec::new();
 
@@ -131,9 +197,13 @@ fn serialize_csv(csv_data: &Bytes) -> anyhow::Result<Vec<Vec<String>>> {
     Ok(data)
 }
 
-fn deserialize_csv(data: &Vec<Vec<String>>) -> anyhow::Result<Bytes> {
-    // Create a CSV writer that writes into a string
-    let mut wtr = csv::WriterBuilder::new().from_writer(vec![]);
+fn write_records(data: &[Vec<String>], delimiter: u8, quote: u8) -> anyhow::Result<String> {
+    // Create a CSV writer that writes into a string, the rows of a table may have different lengths
+    let mut wtr = csv::WriterBuilder::new()
+        .flexible(true)
+        .delimiter(delimiter)
+        .quote(quote)
+        .from_writer(vec![]);
 
     // Iterate over the data and write each row to the CSV writer
     for row in data {
@@ -141,18 +211,20 @@ fn deserialize_csv(data: &Vec<Vec<String>>) -> anyhow::Result<Bytes> {
     }
 
     // After writing all data, we consume the writer to get the underlying string
-    let csv_data = String::from_utf8(wtr.into_inner()?)?;
-
-    Ok(bytes::Bytes::from(csv_data))
+    Ok(String::from_utf8(wtr.into_inner()?)?)
 }
 
 #[cfg(test)]
 mod tests {
+    use bytes::Bytes;
     use log::info;
 
-    use crate::core::{tests::init_logger, TransformerTrait};
-    use crate::csv::{self, deserialize_csv, serialize_csv};
-    use crate::markdown;
+    use crate::core::{
+        tests::init_logger, CsvOptions, CsvParseOptions, Document, Element, FieldKind,
+        GenerateOptions, ParseOptions, ReferenceKind, TableCell, TableRow, TransformerTrait,
+    };
+    use crate::csv::{self, read_records, write_records};
+    use crate::{markdown, text};
 
     #[test]
     fn test() -> anyhow::Result<()> {
@@ -180,9 +252,136 @@ mod tests {
             generated_string,
             // this assures that new changes made to test string won't
             // affect the result of tests if the implementation is correct
-            deserialize_csv(&serialize_csv(&bytes::Bytes::from(document))?)?
+            write_records(&read_records(document, b',', b'"')?, b',', b'"')?
         );
 
         Ok(())
     }
+
+    #[test]
+    fn test_tsv_and_options() -> anyhow::Result<()> {
+        init_logger();
+        let parsed =
+            csv::TsvTransformer::parse(&Bytes::from("Name\tCity\nAda\tLondon, UK\nAlan\n"))?;
+        let Some(Element::Table { headers, rows }) = parsed.get_all_elements().first().copied()
+        else {
+            panic!("no table");
+        };
+        assert_eq!(headers[1].element.plain_text(), "City");
+        assert_eq!(rows[0].cells[1].element.plain_text(), "London, UK");
+        assert_eq!(rows[1].cells.len(), 1);
+        assert_eq!(
+            text::Transformer::generate(&parsed)?,
+            "| Name | City       |\n|------|------------|\n| Ada  | London, UK |\n| Alan |            |\n\n"
+        );
+        assert_eq!(
+            csv::TsvTransformer::generate(&parsed)?,
+            "Name\tCity\nAda\tLondon, UK\nAlan\n"
+        );
+
+        let options = ParseOptions {
+            csv: CsvParseOptions {
+                delimiter: Some(';'),
+                quote: '\'',
+            },
+            ..Default::default()
+        };
+        let parsed =
+            csv::Transformer::parse_with_options(&Bytes::from("a;b\n'x;y';2\n"), &options)?;
+        assert_eq!(csv::Transformer::generate(&parsed)?, "a,b\nx;y,2\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_generate_tables() -> anyhow::Result<()> {
+        init_logger();
+        let mut document = csv::Transformer::parse(&Bytes::from("a,b\n1,2\n"))?;
+        let second = csv::Transformer::parse(&Bytes::from("c\n3\n"))?;
+        document = Document::new(
+            document
+                .get_all_elements()
+                .into_iter()
+                .chain(second.get_all_elements())
+                .cloned()
+                .collect(),
+        );
+        assert_eq!(csv::Transformer::generate(&document)?, "a,b\n1,2\n\nc\n3\n");
+
+        let mut options = GenerateOptions {
+            csv: CsvOptions {
+                table: Some(1),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert_eq!(
+            csv::Transformer::generate_with_options(&document, &options)?,
+            "c\n3\n"
+        );
+        options.csv.table = Some(2);
+        assert!(csv::Transformer::generate_with_options(&document, &options).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_generate_fields() -> anyhow::Result<()> {
+        init_logger();
+        let row = |element| TableRow {
+            cells: vec![TableCell { element }],
+        };
+        let document = Document::new(vec![
+            Element::Header {
+                level: 1,
+                text: "Prices".to_string(),
+            },
+            Element::Table {
+                headers: vec![],
+                rows: vec![
+                    row(Element::CrossReference {
+                        target_id: "prices".to_string(),
+                        kind: ReferenceKind::Section,
+                    }),
+                    row(Element::Field {
+                        kind: FieldKind::DocumentTitle,
+                    }),
+                ],
+            },
+        ]);
+        assert_eq!(
+            csv::Transformer::generate(&document)?,
+            "Section 1\nPrices\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_ragged_tables() -> anyhow::Result<()> {
+        init_logger();
+        let parsed = csv::Transformer::parse(&Bytes::from("a,b\n1\n2,3,4\n"))?;
+        assert_eq!(
+            text::Transformer::generate(&parsed)?,
+            "| a | b |   |\n|---|---|---|\n| 1 |   |   |\n| 2 | 3 | 4 |\n\n"
+        );
+        assert_eq!(
+            markdown::Transformer::generate(&parsed)?,
+            "| a | b |  |\n| --- | --- | --- |\n| 1 |  |  |\n| 2 | 3 | 4 |\n"
+        );
+
+        let Some(Element::Table { rows, .. }) = parsed.get_all_elements().first().copied() else {
+            panic!("no table");
+        };
+        let headerless = Document::new(vec![Element::Table {
+            headers: vec![],
+            rows: rows.clone(),
+        }]);
+        assert_eq!(
+            text::Transformer::generate(&headerless)?,
+            "| 1 |   |   |\n| 2 | 3 | 4 |\n\n"
+        );
+        assert_eq!(
+            markdown::Transformer::generate(&headerless)?,
+            "| 1 |  |  |\n| --- | --- | --- |\n| 2 | 3 | 4 |\n"
+        );
+        Ok(())
+    }
 }
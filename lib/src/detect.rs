@@ -0,0 +1,534 @@
+//! Detection of the format of a document from its content, for files whose extension is
+//! missing or cannot be trusted
+//!
+//! Binary formats and images are recognized by their signature, emails and MHTML archives
+//! by their MIME headers.
+//! Text is then checked for RTF, vCard, iCalendar, JSON, markup, LaTeX, TSV, CSV, AsciiDoc,
+//! reStructuredText, Org and markdown in that order, and anything else that is valid UTF-8 is
+//! plain text.
+
+use bytes::Bytes;
+
+use crate::core::DocumentType;
+
+/// Bytes looked at for the signatures that are not at the very start of the file
+const SCAN_LIMIT: usize = 1024;
+
+/// Bytes looked at for the headers of a MIME message, which a mail server can make long
+const HEADER_LIMIT: usize = 64 * 1024;
+
+/// Format of the document, `None` when the content is neither a known binary format nor text
+pub fn detect_format(bytes: &Bytes) -> Option<DocumentType> {
+    detect_binary(bytes).or_else(|| detect_text(bytes))
+}
+
+fn detect_binary(bytes: &[u8]) -> Option<DocumentType> {
+    let head = &bytes[..bytes.len().min(SCAN_LIMIT)];
+    // the header opens the file, after a byte order mark or blank lines at most
+    let start = head.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(head);
+    let start = &start[start
+        .iter()
+        .position(|byte| !byte.is_ascii_whitespace())
+        .unwrap_or(start.len())..];
+    if start.starts_with(b"%PDF-") {
+        return Some(DocumentType::PDF);
+    }
+    if bytes.starts_with(b"PK\x03\x04") {
+        return detect_zip(bytes);
+    }
+    // OLE compound file, XLS is the only one of them that is read
+    if bytes.starts_with(&[0xD0, 0xCF, 0x11, 0xE0, 0xA1, 0xB1, 0x1A, 0xE1]) {
+        return Some(DocumentType::XLS);
+    }
+    let headers = message_headers(&bytes[..bytes.len().min(HEADER_LIMIT)]);
+    if is_email(&headers) {
+        return Some(DocumentType::Eml);
+    }
+    // web page saved with its images as a MIME message, read by the HTML parser
+    if is_mhtml(&headers) {
+        return Some(DocumentType::HTML);
+    }
+    const IMAGE_SIGNATURES: &[&[u8]] = &[
+        b"\x89PNG\r\n\x1A\n",
+        b"\xFF\xD8\xFF",
+        b"GIF87a",
+        b"GIF89a",
+        b"II*\x00",
+        b"MM\x00*",
+    ];
+    // BMP files start with BM and their size, their header is 14 bytes
+    if IMAGE_SIGNATURES
+        .iter()
+        .any(|signature| bytes.starts_with(signature))
+        || (bytes.starts_with(b"BM") && bytes.len() > 14 && bytes[6..10] == [0; 4])
+    {
+        return Some(DocumentType::Image);
+    }
+    None
+}
+
+/// Office documents are ZIP archives told apart by the names of their entries, which are
+/// stored uncompressed in the local headers
+fn detect_zip(bytes: &[u8]) -> Option<DocumentType> {
+    if contains(
+        bytes,
+        b"mimetypeapplication/vnd.oasis.opendocument.spreadsheet",
+    ) {
+        Some(DocumentType::ODS)
+    } else if contains(bytes, b"mimetypeapplication/vnd.oasis.opendocument.text") {
+        Some(DocumentType::ODT)
+    } else if contains(bytes, b"mimetypeapplication/epub+zip") {
+        Some(DocumentType::EPUB)
+    } else if contains(bytes, b"word/document.xml") {
+        Some(DocumentType::DOCX)
+    } else if contains(bytes, b"xl/workbook.xml") {
+        Some(DocumentType::XLSX)
+    } else {
+        None
+    }
+}
+
+fn detect_text(bytes: &[u8]) -> Option<DocumentType> {
+    let bytes = bytes.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(bytes);
+    let text = std::str::from_utf8(bytes).ok()?;
+    if text.contains('\0') {
+        return None;
+    }
+    let trimmed = text.trim();
+
+    if trimmed.starts_with("{\\rtf") {
+        Some(DocumentType::RTF)
+    } else if starts_with_ignore_case(trimmed, "BEGIN:VCARD") {
+        Some(DocumentType::VCard)
+    } else if starts_with_ignore_case(trimmed, "BEGIN:VCALENDAR") {
+        Some(DocumentType::ICalendar)
+    } else if (trimmed.starts_with('{') || trimmed.starts_with('[')) && is_json(trimmed) {
+        // a notebook is a JSON object with its cells and format version
+        if trimmed.contains("\"cells\"") && trimmed.contains("\"nbformat\"") {
+            Some(DocumentType::Ipynb)
+        } else {
+            Some(DocumentType::Json)
+        }
+    } else if trimmed.starts_with('<') {
+        Some(detect_markup(trimmed))
+    } else if text.contains("\\documentclass") || text.contains("\\begin{document}") {
+        Some(DocumentType::LaTeX)
+    } else if is_delimited(trimmed, '\t') {
+        Some(DocumentType::TSV)
+    } else if is_delimited(trimmed, ',') {
+        Some(DocumentType::CSV)
+    } else if is_asciidoc(trimmed) {
+        Some(DocumentType::AsciiDoc)
+    } else if is_rst(trimmed) {
+        Some(DocumentType::RST)
+    } else if is_org(trimmed) {
+        Some(DocumentType::Org)
+    } else if is_markdown(trimmed) {
+        Some(DocumentType::Markdown)
+    } else {
+        Some(DocumentType::Text)
+    }
+}
+
+fn starts_with_ignore_case(text: &str, prefix: &str) -> bool {
+    text.get(..prefix.len())
+        .is_some_and(|start| start.eq_ignore_ascii_case(prefix))
+}
+
+#[cfg(feature = "json")]
+fn is_json(text: &str) -> bool {
+    serde_json::from_str::<serde::de::IgnoredAny>(text).is_ok()
+}
+
+#[cfg(not(feature = "json"))]
+fn is_json(text: &str) -> bool {
+    (text.starts_with('{') && text.ends_with('}')) || (text.starts_with('[') && text.ends_with(']'))
+}
+
+/// Lowercase names and values of the headers the file opens with, up to the first blank
+/// line, none when the file does not open with a header. The line cut at the end of the
+/// bytes looked at is left out.
+fn message_headers(head: &[u8]) -> Vec<(String, String)> {
+    let head = String::from_utf8_lossy(head).to_ascii_lowercase();
+    let mut headers: Vec<(String, String)> = Vec::new();
+    let mut lines = head.split('\n').peekable();
+    while let Some(line) = lines.next() {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        if line.trim().is_empty() {
+            break;
+        }
+        let cut = lines.peek().is_none();
+        if line.starts_with([' ', '\t']) {
+            match headers.last_mut() {
+                Some((_, value)) => {
+                    value.push(' ');
+                    value.push_str(line.trim());
+                }
+                None => return Vec::new(),
+            }
+            continue;
+        }
+        match line.split_once(':') {
+            Some((name, value))
+                if name.starts_with(|c: char| c.is_ascii_alphabetic())
+                    && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') =>
+            {
+                headers.push((name.to_string(), value.trim().to_string()));
+            }
+            _ if cut => break,
+            _ => return Vec::new(),
+        }
+    }
+    headers
+}
+
+fn header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(header, _)| header == name)
+        .map(|(_, value)| value.as_str())
+}
+
+/// RFC 5322 mail headers: a sender with a subject or a date. The web pages saved by a
+/// browser have them too, with the browser as the sender and the address of the page.
+fn is_email(headers: &[(String, String)]) -> bool {
+    header(headers, "from").is_some_and(|from| !from.starts_with("<saved by"))
+        && (header(headers, "subject").is_some() || header(headers, "date").is_some())
+        && header(headers, "snapshot-content-location").is_none()
+}
+
+/// MIME headers with a `multipart/related` content type
+fn is_mhtml(headers: &[(String, String)]) -> bool {
+    header(headers, "content-type").is_some_and(|value| value.starts_with("multipart/related"))
+}
+
+/// HTML when it has a doctype or one of the common HTML tags, XML otherwise
+fn detect_markup(text: &str) -> DocumentType {
+    const HTML_TAGS: &[&str] = &[
+        "<!doctype html",
+        "<html",
+        "<head",
+        "<body",
+        "<div",
+        "<p>",
+        "<p ",
+        "<h1",
+        "<h2",
+        "<h3",
+        "<table",
+        "<ul",
+        "<ol",
+        "<span",
+        "<a ",
+        "<br",
+        "<img",
+    ];
+    let head: String = text
+        .chars()
+        .take(SCAN_LIMIT)
+        .collect::<String>()
+        .to_lowercase();
+    if HTML_TAGS.iter().any(|tag| head.contains(tag)) {
+        DocumentType::HTML
+    } else {
+        DocumentType::XML
+    }
+}
+
+/// Rows a text needs to be read as delimited
+const MIN_ROWS: usize = 3;
+
+/// At least [`MIN_ROWS`] lines with the same number of fields outside quotes, more than one
+fn is_delimited(text: &str, delimiter: char) -> bool {
+    let mut counts = text
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let mut quoted = false;
+            line.chars()
+                .filter(|&c| {
+                    if c == '"' {
+                        quoted = !quoted;
+                    }
+                    c == delimiter && !quoted
+                })
+                .count()
+        });
+    let Some(first) = counts.next() else {
+        return false;
+    };
+    let mut lines = 1;
+    for count in counts {
+        if count != first {
+            return false;
+        }
+        lines += 1;
+    }
+    first > 0 && lines >= MIN_ROWS
+}
+
+fn is_markdown(text: &str) -> bool {
+    let block = text.lines().map(str::trim_start).any(|line| {
+        let hashes = line.chars().take_while(|&c| c == '#').count();
+        let ordered = line.chars().take_while(char::is_ascii_digit).count();
+        (1..=6).contains(&hashes) && line[hashes..].starts_with(' ')
+            || ["- ", "* ", "+ ", "> ", "```", "~~~", "---", "==="]
+                .iter()
+                .any(|marker| line.starts_with(marker))
+            || ordered > 0 && line[ordered..].starts_with(". ")
+    });
+    block || text.contains("](") || text.contains("**") || text.contains("__")
+}
+
+/// A document title followed by attribute entries or sections, or AsciiDoc tables and
+/// listing blocks
+fn is_asciidoc(text: &str) -> bool {
+    let lines: Vec<&str> = text.lines().map(str::trim_end).collect();
+    let titled = lines.first().is_some_and(|line| line.starts_with("= "))
+        && lines[1..].iter().any(|line| {
+            line.starts_with("== ")
+                || line
+                    .strip_prefix(':')
+                    .and_then(|entry| entry.split_once(':'))
+                    .is_some_and(|(name, value)| {
+                        !name.is_empty()
+                            && name.chars().all(|c| c.is_alphanumeric() || c == '-')
+                            && (value.is_empty() || value.starts_with(' '))
+                    })
+        });
+    titled
+        || lines.iter().any(|line| {
+            *line == "|==="
+                || line.starts_with("[source,")
+                || ["NOTE: ", "TIP: ", "IMPORTANT: ", "WARNING: ", "CAUTION: "]
+                    .iter()
+                    .any(|label| line.starts_with(label))
+        })
+}
+
+/// Directives (`.. note::`) or hyperlink targets (`.. _name:`), or a title adorned above and
+/// below
+fn is_rst(text: &str) -> bool {
+    let lines: Vec<&str> = text.lines().map(str::trim_end).collect();
+    let adornment = |line: &str| {
+        line.len() >= 3
+            && line.starts_with(['=', '-', '~', '*', '#'])
+            && line.chars().all(|c| line.starts_with(c))
+    };
+    lines.iter().any(|line| {
+        line.strip_prefix(".. ").is_some_and(|rest| {
+            rest.split_once("::").is_some_and(|(name, _)| {
+                !name.is_empty()
+                    && name
+                        .chars()
+                        .all(|c| c.is_alphanumeric() || c == '-' || c == ':')
+            }) || rest.starts_with('_') && rest.ends_with(':')
+        })
+    }) || lines.windows(3).any(|window| {
+        adornment(window[0]) && window[2] == window[0] && !window[1].trim().is_empty()
+    })
+}
+
+/// Keywords (`#+title:`), blocks (`#+begin_src`) or headlines with a TODO keyword
+fn is_org(text: &str) -> bool {
+    text.lines().any(|line| {
+        let lower = line.trim_start().to_lowercase();
+        lower.starts_with("#+begin_")
+            || lower.strip_prefix("#+").is_some_and(|rest| {
+                rest.split_once(':').is_some_and(|(key, _)| {
+                    !key.is_empty() && key.chars().all(|c| c.is_alphanumeric() || c == '_')
+                })
+            })
+            || line
+                .trim_start_matches('*')
+                .strip_prefix(' ')
+                .is_some_and(|rest| {
+                    line.starts_with('*')
+                        && (rest.starts_with("TODO ") || rest.starts_with("DONE "))
+                })
+    })
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack
+        .windows(needle.len())
+        .any(|window| window == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use crate::core::DocumentType;
+    use crate::detect::*;
+
+    fn detect(bytes: &[u8]) -> Option<DocumentType> {
+        detect_format(&Bytes::copy_from_slice(bytes))
+    }
+
+    #[test]
+    fn test_detect_format() {
+        assert_eq!(
+            detect(b"%PDF-1.7\n%\xE2\xE3\xCF\xD3"),
+            Some(DocumentType::PDF)
+        );
+        assert_eq!(
+            detect(b"PK\x03\x04\x14\x00\x00\x00[Content_Types].xml...PK\x03\x04word/document.xml"),
+            Some(DocumentType::DOCX)
+        );
+        assert_eq!(
+            detect(b"PK\x03\x04\x14\x00\x00\x00xl/workbook.xml"),
+            Some(DocumentType::XLSX)
+        );
+        assert_eq!(
+            detect(b"PK\x03\x04\x0A\x00mimetypeapplication/vnd.oasis.opendocument.spreadsheet"),
+            Some(DocumentType::ODS)
+        );
+        assert_eq!(
+            detect(b"PK\x03\x04\x0A\x00mimetypeapplication/vnd.oasis.opendocument.text"),
+            Some(DocumentType::ODT)
+        );
+        assert_eq!(
+            detect(b"PK\x03\x04\x0A\x00mimetypeapplication/epub+zip"),
+            Some(DocumentType::EPUB)
+        );
+        assert_eq!(detect(b"PK\x03\x04\x14\x00image.png"), None);
+        assert_eq!(
+            detect(b"\xD0\xCF\x11\xE0\xA1\xB1\x1A\xE1\x00\x00"),
+            Some(DocumentType::XLS)
+        );
+        assert_eq!(detect(b"{\\rtf1\\ansi Hello}"), Some(DocumentType::RTF));
+        assert_eq!(
+            detect(b"From: <Saved by Blink>\r\nMIME-Version: 1.0\r\nContent-Type: multipart/related;\r\n\ttype=\"text/html\"\r\n\r\n"),
+            Some(DocumentType::HTML)
+        );
+        assert_eq!(detect(b"\x00\x01\x02\xFF"), None);
+        assert_eq!(
+            detect(b"\x89PNG\r\n\x1A\n\x00\x00\x00\x0DIHDR"),
+            Some(DocumentType::Image)
+        );
+        assert_eq!(
+            detect(b"\xFF\xD8\xFF\xE0\x00\x10JFIF"),
+            Some(DocumentType::Image)
+        );
+    }
+
+    #[test]
+    fn test_detect_email() {
+        // an email with an HTML body and its inline images is not a saved web page
+        assert_eq!(
+            detect(b"From: Ada <ada@example.com>\r\nTo: alan@example.com\r\nSubject: Figures\r\nDate: Tue, 10 Feb 2026 10:00:00 +0000\r\nMIME-Version: 1.0\r\nContent-Type: multipart/related;\r\n\tboundary=\"b1\"; type=\"text/html\"\r\n\r\n--b1\r\nContent-Type: text/html\r\n\r\n<p><img src=\"cid:chart\"></p>\r\n--b1--\r\n"),
+            Some(DocumentType::Eml)
+        );
+        assert_eq!(
+            detect(b"Received: from mail.example.com\n\tby mx.example.org; Tue, 10 Feb 2026\nFrom: ada@example.com\nSubject: Hello\n\nSee you soon.\n"),
+            Some(DocumentType::Eml)
+        );
+        // a web page saved by Blink has the headers of an email
+        assert_eq!(
+            detect(b"From: <Saved by Blink>\r\nSnapshot-Content-Location: https://example.com/\r\nSubject: Example\r\nDate: Tue, 10 Feb 2026 10:00:00 -0000\r\nMIME-Version: 1.0\r\nContent-Type: multipart/related;\r\n\ttype=\"text/html\";\r\n\tboundary=\"----MultipartBoundary--a\"\r\n\r\n"),
+            Some(DocumentType::HTML)
+        );
+        // a note that opens with a sender only is text
+        assert_eq!(
+            detect(b"From: the team\n\nThanks for coming.\n"),
+            Some(DocumentType::Text)
+        );
+    }
+
+    #[test]
+    fn test_detect_text_format() {
+        assert_eq!(
+            detect(b"  {\"bands\": [], \"title\": null}\n"),
+            Some(DocumentType::Json)
+        );
+        assert_eq!(
+            detect(b"{\"cells\": [], \"metadata\": {}, \"nbformat\": 4}"),
+            Some(DocumentType::Ipynb)
+        );
+        assert_eq!(
+            detect(b"BEGIN:VCARD\r\nVERSION:4.0\r\nFN:Jane\r\nEND:VCARD\r\n"),
+            Some(DocumentType::VCard)
+        );
+        assert_eq!(
+            detect(b"begin:vcalendar\nversion:2.0\nend:vcalendar\n"),
+            Some(DocumentType::ICalendar)
+        );
+        assert_eq!(
+            detect(b"\xEF\xBB\xBF<!DOCTYPE html><html><body><p>Hi</p></body></html>"),
+            Some(DocumentType::HTML)
+        );
+        assert_eq!(detect(b"<h1>Title</h1>"), Some(DocumentType::HTML));
+        assert_eq!(
+            detect(b"<?xml version=\"1.0\"?><document><text>Hi</text></document>"),
+            Some(DocumentType::XML)
+        );
+        assert_eq!(
+            detect(b"name,age\n\"Doe, John\",42\nJane,37\n"),
+            Some(DocumentType::CSV)
+        );
+        assert_eq!(
+            detect(b"name\tcity\nAda\tLondon, UK\nAlan\tWilmslow\n"),
+            Some(DocumentType::TSV)
+        );
+        assert_eq!(
+            detect(b"# Title\n\nSome text, with a comma.\n\n1. First\n"),
+            Some(DocumentType::Markdown)
+        );
+        assert_eq!(
+            detect(b"See [the docs](https://example.com)."),
+            Some(DocumentType::Markdown)
+        );
+        assert_eq!(
+            detect(b"% draft\n\\documentclass{article}\n\\begin{document}\nHi\n\\end{document}\n"),
+            Some(DocumentType::LaTeX)
+        );
+        assert_eq!(
+            detect(b"= Guide\n:toc:\n\n== Install\n\nNOTE: Run it once.\n"),
+            Some(DocumentType::AsciiDoc)
+        );
+        assert_eq!(
+            detect(b"=====\nGuide\n=====\n\nInstall\n-------\n\n.. note:: Run it once.\n"),
+            Some(DocumentType::RST)
+        );
+        assert_eq!(
+            detect(b"#+TITLE: Notes\n\n* TODO Call Ada\n- [ ] Agenda\n"),
+            Some(DocumentType::Org)
+        );
+        assert_eq!(
+            detect(b"* Notes\n\n#+begin_src sh\nls\n#+end_src\n"),
+            Some(DocumentType::Org)
+        );
+        // a bracket that is not JSON is not taken for it
+        assert_eq!(
+            detect(b"[draft] Notes of the meeting\nNothing decided."),
+            Some(DocumentType::Text)
+        );
+        assert_eq!(
+            detect(b"Dear John,\nThank you for the letter.\n"),
+            Some(DocumentType::Text)
+        );
+    }
+
+    #[test]
+    fn test_detect_false_positives() {
+        assert_eq!(
+            detect(b"\xEF\xBB\xBF\r\n  %PDF-1.4\n"),
+            Some(DocumentType::PDF)
+        );
+        // the PDF magic is only a header at the start of the file
+        assert_eq!(
+            detect(b"Notes on the %PDF-1.7 header of the files.\n"),
+            Some(DocumentType::Text)
+        );
+        assert_eq!(detect(b"\x00\x01garbage%PDF-1.7\n"), None);
+        // lines with a comma each are prose, not a table
+        assert_eq!(
+            detect(b"Hello, world.\nSee you, soon.\n"),
+            Some(DocumentType::Text)
+        );
+        assert_eq!(detect(b"a,b\n1,2\n3,4,5\n"), Some(DocumentType::Text));
+        assert_eq!(detect(b"one\ntwo\nthree\n"), Some(DocumentType::Text));
+        assert_eq!(detect(b"a,b\n1,2\n3,4\n"), Some(DocumentType::CSV));
+    }
+}
@@ -0,0 +1,404 @@
+//! Structural diff between two versions of a document
+//!
+//! The diff is computed band by band on the top level elements. Each band diff is an edit
+//! script: changes are applied in order and the index of a change is the position in the band
+//! as it is being edited, so [`merge`] replays it with a single cursor.
+
+use std::fmt;
+
+use thiserror::Error;
+
+use crate::core::{Band, Document, Element};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    Added {
+        index: usize,
+        element: Element,
+    },
+    Removed {
+        index: usize,
+        element: Element,
+    },
+    /// Element replaced by another element of the same type
+    Changed {
+        index: usize,
+        old: Element,
+        new: Element,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BandDiff {
+    /// Empty band identifying the edited band (see [`Band::empty`])
+    pub band: Band,
+    pub changes: Vec<Change>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct DocumentDiff {
+    pub bands: Vec<BandDiff>,
+}
+
+impl DocumentDiff {
+    pub fn is_empty(&self) -> bool {
+        self.bands.is_empty()
+    }
+
+    /// Number of changes over all bands
+    pub fn len(&self) -> usize {
+        self.bands.iter().map(|band| band.changes.len()).sum()
+    }
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum MergeError {
+    #[error("Change {index} of band {band} is out of range")]
+    OutOfRange { band: String, index: usize },
+    #[error("Element {index} of band {band} does not match the diff")]
+    Conflict { band: String, index: usize },
+}
+
+/// Changes turning `old` into `new`
+pub fn diff(old: &Document, new: &Document) -> DocumentDiff {
+    let mut keys: Vec<Band> = vec![];
+    for band in old.bands.iter().chain(new.bands.iter()) {
+        if !keys.iter().any(|key| key.same_kind(band)) {
+            keys.push(band.empty());
+        }
+    }
+
+    let bands = keys
+        .into_iter()
+        .filter_map(|band| {
+            let changes = diff_elements(
+                &old.get_elements_by_band(&band),
+                &new.get_elements_by_band(&band),
+            );
+            (!changes.is_empty()).then_some(BandDiff { band, changes })
+        })
+        .collect();
+    DocumentDiff { bands }
+}
+
+/// Applies a diff to a document, failing when the document does not contain
+/// the removed and changed elements of the diff
+pub fn merge(document: &Document, diff: &DocumentDiff) -> Result<Document, MergeError> {
+    let mut document = document.clone();
+    for band_diff in &diff.bands {
+        let position = match document
+            .bands
+            .iter()
+            .position(|band| band.same_kind(&band_diff.band))
+        {
+            Some(position) => position,
+            None => {
+                document.bands.push(band_diff.band.empty());
+                document.bands.len() - 1
+            }
+        };
+        // Bands of the same type are merged, like in Document::get_elements_by_band
+        let mut elements: Vec<Element> = vec![];
+        let mut index = position;
+        while index < document.bands.len() {
+            if document.bands[index].same_kind(&band_diff.band) {
+                elements.append(document.bands[index].elements_mut());
+                if index != position {
+                    document.bands.remove(index);
+                    continue;
+                }
+            }
+            index += 1;
+        }
+
+        let name = band_diff.band.name();
+        for change in &band_diff.changes {
+            match change {
+                Change::Added { index, element } => {
+                    if *index > elements.len() {
+                        return Err(out_of_range(name, *index));
+                    }
+                    elements.insert(*index, element.clone());
+                }
+                Change::Removed { index, element } => {
+                    check(&elements, name, *index, element)?;
+                    elements.remove(*index);
+                }
+                Change::Changed { index, old, new } => {
+                    check(&elements, name, *index, old)?;
+                    elements[*index] = new.clone();
+                }
+            }
+        }
+        *document.bands[position].elements_mut() = elements;
+    }
+    Ok(document)
+}
+
+fn out_of_range(band: &str, index: usize) -> MergeError {
+    MergeError::OutOfRange {
+        band: band.to_string(),
+        index,
+    }
+}
+
+fn check(
+    elements: &[Element],
+    band: &str,
+    index: usize,
+    expected: &Element,
+) -> Result<(), MergeError> {
+    match elements.get(index) {
+        None => Err(out_of_range(band, index)),
+        Some(element) if element != expected => Err(MergeError::Conflict {
+            band: band.to_string(),
+            index,
+        }),
+        Some(_) => Ok(()),
+    }
+}
+
+fn same_type(old: &Element, new: &Element) -> bool {
+    std::mem::discriminant(old) == std::mem::discriminant(new)
+}
+
+/// Edit script from the longest common subsequence of the two element lists,
+/// a removal followed by an addition of the same element type is reported as a change
+fn diff_elements(old: &[&Element], new: &[&Element]) -> Vec<Change> {
+    let (n, m) = (old.len(), new.len());
+    // the common prefix and suffix, most of two versions of a document, are left out
+    let prefix = old
+        .iter()
+        .zip(new)
+        .take_while(|(old, new)| old == new)
+        .count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(old, new)| old == new)
+        .count();
+    let mut common = vec![];
+    common_pairs(
+        &old[prefix..n - suffix],
+        &new[prefix..m - suffix],
+        (prefix, prefix),
+        &mut common,
+    );
+
+    let mut changes = vec![];
+    let mut cursor = prefix;
+    let (mut i, mut j) = (prefix, prefix);
+    for (next_i, next_j) in common.into_iter().chain([(n - suffix, m - suffix)]) {
+        // Run of removed and added elements up to the next common element
+        let (removed, added) = (&old[i..next_i], &new[j..next_j]);
+        for k in 0..removed.len().max(added.len()) {
+            match (removed.get(k), added.get(k)) {
+                (Some(old), Some(new)) if same_type(old, new) => {
+                    changes.push(Change::Changed {
+                        index: cursor,
+                        old: (*old).clone(),
+                        new: (*new).clone(),
+                    });
+                    cursor += 1;
+                }
+                (old, new) => {
+                    if let Some(old) = old {
+                        changes.push(Change::Removed {
+                            index: cursor,
+                            element: (*old).clone(),
+                        });
+                    }
+                    if let Some(new) = new {
+                        changes.push(Change::Added {
+                            index: cursor,
+                            element: (*new).clone(),
+                        });
+                        cursor += 1;
+                    }
+                }
+            }
+        }
+        cursor += 1;
+        (i, j) = (next_i + 1, next_j + 1);
+    }
+    changes
+}
+
+/// Indexes, shifted by `offset`, of the pairs of elements of a longest common subsequence of
+/// the two lists, found in linear space by splitting `old` in halves (Hirschberg)
+fn common_pairs(
+    old: &[&Element],
+    new: &[&Element],
+    offset: (usize, usize),
+    pairs: &mut Vec<(usize, usize)>,
+) {
+    if old.is_empty() || new.is_empty() {
+        return;
+    }
+    if old.len() == 1 {
+        if let Some(j) = new.iter().position(|element| element == &old[0]) {
+            pairs.push((offset.0, offset.1 + j));
+        }
+        return;
+    }
+    let middle = old.len() / 2;
+    let forward = lcs_lengths(old[..middle].iter(), new.iter());
+    let backward = lcs_lengths(old[middle..].iter().rev(), new.iter().rev());
+    // split of `new` with the longest subsequence of both halves, the first one on ties
+    let split = (0..=new.len())
+        .rev()
+        .max_by_key(|&j| forward[j] + backward[new.len() - j])
+        .unwrap_or(0);
+    common_pairs(&old[..middle], &new[..split], offset, pairs);
+    common_pairs(
+        &old[middle..],
+        &new[split..],
+        (offset.0 + middle, offset.1 + split),
+        pairs,
+    );
+}
+
+/// Lengths of the longest common subsequences of `old` and of each prefix of `new`
+fn lcs_lengths<'a>(
+    old: impl Iterator<Item = &'a &'a Element>,
+    new: impl Iterator<Item = &'a &'a Element> + Clone,
+) -> Vec<usize> {
+    let mut lengths = vec![0];
+    lengths.extend(new.clone().map(|_| 0));
+    for old in old {
+        // length of the previous row at the previous prefix
+        let mut diagonal = 0;
+        for (j, new) in new.clone().enumerate() {
+            let above = lengths[j + 1];
+            lengths[j + 1] = if old == new {
+                diagonal + 1
+            } else {
+                above.max(lengths[j])
+            };
+            diagonal = above;
+        }
+    }
+    lengths
+}
+
+fn summary(element: &Element) -> String {
+    let kind: &'static str = element.into();
+    let text = element.plain_text();
+    if text.trim().is_empty() {
+        kind.to_string()
+    } else {
+        format!("{}: {}", kind, text.replace('\n', " "))
+    }
+}
+
+/// Text rendering of the diff, one line per removed (`-`) or added (`+`) element
+impl fmt::Display for DocumentDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for band_diff in &self.bands {
+            writeln!(f, "@@ {} @@", band_diff.band.name())?;
+            for change in &band_diff.changes {
+                match change {
+                    Change::Added { index, element } => {
+                        writeln!(f, "+ [{}] {}", index, summary(element))?
+                    }
+                    Change::Removed { index, element } => {
+                        writeln!(f, "- [{}] {}", index, summary(element))?
+                    }
+                    Change::Changed { index, old, new } => {
+                        writeln!(f, "- [{}] {}", index, summary(old))?;
+                        writeln!(f, "+ [{}] {}", index, summary(new))?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::*;
+    use crate::diff::*;
+
+    fn text(text: &str) -> Element {
+        Element::Text {
+            text: text.to_string(),
+            size: 8,
+        }
+    }
+
+    fn header(text: &str) -> Element {
+        Element::Header {
+            level: 1,
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_diff_and_merge() -> anyhow::Result<()> {
+        let mut old = Document::new(vec![
+            header("Intro"),
+            text("first"),
+            text("second"),
+            text("third"),
+        ]);
+        old.set_page_footer(vec![text("footer")]);
+        let mut new = Document::new(vec![
+            header("Introduction"),
+            text("first"),
+            text("third"),
+            Element::LineBreak,
+        ]);
+        new.set_title(vec![text("Title")]);
+        new.set_page_footer(vec![text("footer")]);
+
+        let diff = diff(&old, &new);
+        assert_eq!(diff.len(), 4);
+        assert_eq!(
+            diff.to_string(),
+            "@@ Detail @@\n\
+             - [0] Header: Intro\n\
+             + [0] Header: Introduction\n\
+             - [2] Text: second\n\
+             + [3] LineBreak\n\
+             @@ Title @@\n\
+             + [0] Text: Title\n"
+        );
+
+        let merged = merge(&old, &diff)?;
+        assert_eq!(merged.get_detail(), new.get_detail());
+        assert_eq!(merged.get_title(), new.get_title());
+        assert_eq!(merged.get_page_footer(), new.get_page_footer());
+        assert!(super::diff(&merged, &new).is_empty());
+
+        assert_eq!(
+            merge(&new, &diff),
+            Err(MergeError::Conflict {
+                band: "Detail".to_string(),
+                index: 0
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_diff_scattered_edits() -> anyhow::Result<()> {
+        let old = Document::new((0..400).map(|i| text(&i.to_string())).collect());
+        let new = Document::new(
+            (0..400)
+                .filter(|i| i % 7 != 3)
+                .flat_map(|i| match i {
+                    i if i % 11 == 5 => vec![Element::LineBreak, text(&i.to_string())],
+                    i if i % 13 == 0 => vec![header(&i.to_string())],
+                    i => vec![text(&i.to_string())],
+                })
+                .collect(),
+        );
+        let diff = diff(&old, &new);
+        // 57 texts removed, 24 texts replaced by a header and 31 line breaks added
+        assert_eq!(diff.len(), 57 + 24 * 2 + 31);
+        assert_eq!(merge(&old, &diff)?.get_detail(), new.get_detail());
+        Ok(())
+    }
+}
@@ -0,0 +1,824 @@
+//! DocBook 5 XML of a document, for the documentation toolchains built on DocBook
+//!
+//! A document with headers is a `book`: the headers of the first level are chapters, the
+//! deeper ones sections nested in them, and the blocks before the first chapter a preface.
+//! A document without headers is an `article`. Code blocks are program listings, images
+//! media objects saved next to the XML (figures when they have a title), tables CALS
+//! informal tables and admonitions their DocBook element. DocBook is generated only.
+
+use std::collections::HashSet;
+use std::fmt::Write as _;
+
+use bytes::Bytes;
+use log::warn;
+
+use crate::core::{
+    disk_image_saver, header_anchor, Band, ContainerKind, Document, DocumentType, Element,
+    ImageData, NumberingStyle, TableHeader, TableRow, TextStyle,
+};
+use crate::util::escape_xml;
+
+pub struct Transformer;
+
+const NAMESPACES: &str =
+    r#"xmlns="http://docbook.org/ns/docbook" xmlns:xlink="http://www.w3.org/1999/xlink""#;
+
+/// Whether an id can be an `xml:id`, which is an XML name without colons
+fn is_xml_id(id: &str) -> bool {
+    let mut chars = id.chars();
+    chars
+        .next()
+        .is_some_and(|first| first.is_alphabetic() || first == '_')
+        && chars.all(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+/// DocBook element of an admonition kind, the kinds without one taking the closest
+fn admonition_element(kind: &str) -> &'static str {
+    match kind.to_lowercase().as_str() {
+        "tip" | "hint" => "tip",
+        "important" | "attention" => "important",
+        "caution" | "error" => "caution",
+        "warning" | "danger" => "warning",
+        _ => "note",
+    }
+}
+
+/// Level and text of a header, and the id of an attributed one
+fn header_parts(element: &Element) -> Option<(u8, &str, Option<&str>)> {
+    match element {
+        Element::Header { level, text } => Some((*level, text, None)),
+        Element::Attributed {
+            attributes,
+            element,
+        } => match element.as_ref() {
+            Element::Header { level, text } => {
+                Some((*level, text, attributes.get("id").map(String::as_str)))
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Ids written in the document: the bookmarks and the anchors of the headers that links
+/// refer to
+fn document_ids(document: &Document) -> HashSet<String> {
+    fn visit(element: &Element, targets: &HashSet<String>, ids: &mut HashSet<String>) {
+        if let Some((_, text, id)) = header_parts(element) {
+            let anchor = id.map_or_else(|| header_anchor(text), str::to_string);
+            if targets.contains(&anchor) {
+                ids.insert(anchor);
+            }
+        }
+        if let Element::Bookmark { id, .. } = element {
+            ids.insert(id.clone());
+        }
+        for child in element.children() {
+            visit(child, targets, ids);
+        }
+    }
+
+    let targets = document.link_targets();
+    let mut ids = HashSet::new();
+    for band in &document.bands {
+        for element in band.elements() {
+            visit(element, &targets, &mut ids);
+        }
+    }
+    ids.retain(|id| is_xml_id(id));
+    ids
+}
+
+/// Open chapter or section: its header level and whether it has content yet
+struct Section {
+    level: u8,
+    filled: bool,
+}
+
+struct Writer<'a, F>
+where
+    F: Fn(&Bytes, &str) -> anyhow::Result<()>,
+{
+    image_saver: &'a F,
+    image_count: usize,
+    /// Ids of the document, the links to them are `linkend` references
+    ids: HashSet<String>,
+    /// Ids already written, an id is written once
+    written_ids: HashSet<String>,
+    lines: Vec<String>,
+}
+
+impl<F> Writer<'_, F>
+where
+    F: Fn(&Bytes, &str) -> anyhow::Result<()>,
+{
+    fn line(&mut self, indent: usize, text: impl AsRef<str>) {
+        self.lines
+            .push(format!("{}{}", "  ".repeat(indent), text.as_ref()));
+    }
+
+    /// ` xml:id="id"` for the first use of an id of the document, empty otherwise
+    fn id_attribute(&mut self, id: &str) -> String {
+        if self.ids.contains(id) && self.written_ids.insert(id.to_string()) {
+            format!(r#" xml:id="{}""#, escape_xml(id))
+        } else {
+            String::new()
+        }
+    }
+
+    /// Chapters and sections of the headers of a book, its blocks before the first header
+    /// in a preface
+    fn book(&mut self, elements: &[&Element], top_level: u8, indent: usize) -> anyhow::Result<()> {
+        let mut sections: Vec<Section> = vec![];
+        let mut preface = false;
+        for element in elements {
+            if let Some((level, text, id)) = header_parts(element) {
+                let level = level.max(top_level);
+                if preface {
+                    self.line(indent, "</preface>");
+                    preface = false;
+                }
+                while sections
+                    .last()
+                    .is_some_and(|section| section.level >= level)
+                {
+                    self.close_section(&mut sections, indent);
+                }
+                if let Some(parent) = sections.last_mut() {
+                    parent.filled = true;
+                }
+                let tag = if sections.is_empty() {
+                    "chapter"
+                } else {
+                    "section"
+                };
+                let anchor = id.map_or_else(|| header_anchor(text), str::to_string);
+                let id = self.id_attribute(&anchor);
+                let depth = indent + sections.len();
+                self.line(depth, format!("<{tag}{id}>"));
+                self.line(
+                    depth + 1,
+                    format!("<title>{}</title>", escape_xml(text.trim())),
+                );
+                sections.push(Section {
+                    level,
+                    filled: false,
+                });
+                continue;
+            }
+            if sections.is_empty() {
+                let opened = !preface;
+                if opened {
+                    self.line(indent, "<preface>");
+                    self.line(indent + 1, "<title>Preface</title>");
+                    preface = true;
+                }
+                let before = self.lines.len();
+                self.block(element, indent + 1)?;
+                if opened && self.lines.len() == before {
+                    // a preface is only written for blocks that are written
+                    self.lines.truncate(before - 2);
+                    preface = false;
+                }
+            } else {
+                let before = self.lines.len();
+                self.block(element, indent + sections.len())?;
+                if let Some(section) = sections.last_mut() {
+                    section.filled |= self.lines.len() > before;
+                }
+            }
+        }
+        if preface {
+            self.line(indent, "</preface>");
+        }
+        while !sections.is_empty() {
+            self.close_section(&mut sections, indent);
+        }
+        Ok(())
+    }
+
+    /// Closes the innermost section, a section cannot be empty
+    fn close_section(&mut self, sections: &mut Vec<Section>, indent: usize) {
+        let Some(section) = sections.pop() else {
+            return;
+        };
+        let depth = indent + sections.len();
+        if !section.filled {
+            self.line(depth + 1, "<para/>");
+        }
+        let tag = if sections.is_empty() {
+            "chapter"
+        } else {
+            "section"
+        };
+        self.line(depth, format!("</{tag}>"));
+    }
+
+    fn blocks<'e>(
+        &mut self,
+        elements: impl IntoIterator<Item = &'e Element>,
+        indent: usize,
+    ) -> anyhow::Result<()> {
+        for element in elements {
+            self.block(element, indent)?;
+        }
+        Ok(())
+    }
+
+    /// Blocks of a list item, a table cell or an admonition, `<para/>` when there are none
+    fn required_blocks<'e>(
+        &mut self,
+        elements: impl IntoIterator<Item = &'e Element>,
+        indent: usize,
+    ) -> anyhow::Result<()> {
+        let before = self.lines.len();
+        self.blocks(elements, indent)?;
+        if self.lines.len() == before {
+            self.line(indent, "<para/>");
+        }
+        Ok(())
+    }
+
+    fn block(&mut self, element: &Element, indent: usize) -> anyhow::Result<()> {
+        if let Some((kind, title, blocks)) = element.admonition_parts() {
+            let tag = admonition_element(kind);
+            self.line(indent, format!("<{tag}>"));
+            if let Some(title) = title {
+                self.line(indent + 1, format!("<title>{}</title>", escape_xml(&title)));
+            }
+            self.required_blocks(blocks, indent + 1)?;
+            self.line(indent, format!("</{tag}>"));
+            return Ok(());
+        }
+        match element {
+            Element::Header { text, .. } => {
+                // headers inside lists, tables and blocks, where there are no sections
+                self.line(
+                    indent,
+                    format!("<bridgehead>{}</bridgehead>", escape_xml(text.trim())),
+                );
+            }
+            Element::Attributed { element, .. } => self.block(element, indent)?,
+            Element::Paragraph { elements, .. } => match elements.as_slice() {
+                [image @ Element::Image(_)] => self.block(image, indent)?,
+                elements => self.paragraph(elements, indent)?,
+            },
+            Element::Image(image) => self.image_block(image, indent)?,
+            Element::Table { headers, rows } => self.table(headers, rows, indent)?,
+            Element::List { .. } => self.list(element, indent)?,
+            Element::CodeBlock { code, .. } => {
+                let language = element
+                    .code_language()
+                    .map(|language| format!(r#" language="{}""#, escape_xml(language)))
+                    .unwrap_or_default();
+                // the text of a listing is verbatim, it is not indented
+                self.lines.push(format!(
+                    "{}<programlisting{language}>{}</programlisting>",
+                    "  ".repeat(indent),
+                    escape_xml(code.trim_end_matches('\n'))
+                ));
+            }
+            Element::Math { tex, display: true } => {
+                self.line(indent, "<informalequation>");
+                self.line(
+                    indent + 1,
+                    format!("<mathphrase>{}</mathphrase>", escape_xml(tex.trim())),
+                );
+                self.line(indent, "</informalequation>");
+            }
+            Element::Container {
+                kind: ContainerKind::Aside,
+                elements,
+            } => {
+                self.line(indent, "<sidebar>");
+                self.required_blocks(elements, indent + 1)?;
+                self.line(indent, "</sidebar>");
+            }
+            Element::Container { elements, .. } => self.blocks(elements, indent)?,
+            Element::Media { .. } => {
+                if let Some(link) = element.media_link() {
+                    self.paragraph(&[link], indent)?;
+                }
+            }
+            Element::Raw {
+                format: DocumentType::DocBook,
+                content,
+            } => self.lines.push(content.trim_end().to_string()),
+            Element::Raw { .. }
+            | Element::TableOfContents { .. }
+            | Element::Drawing { .. }
+            | Element::LineBreak => {}
+            element => self.paragraph(std::slice::from_ref(element), indent)?,
+        }
+        Ok(())
+    }
+
+    /// Paragraph of inline elements, a literal layout keeping its line breaks when it has
+    /// some
+    fn paragraph(&mut self, elements: &[Element], indent: usize) -> anyhow::Result<()> {
+        let mut content = String::new();
+        for element in elements {
+            content.push_str(&self.inline(element)?);
+        }
+        if content.trim().is_empty() {
+            return Ok(());
+        }
+        let tag = if elements
+            .iter()
+            .any(|element| matches!(element, Element::LineBreak))
+        {
+            "literallayout"
+        } else {
+            "para"
+        };
+        self.line(indent, format!("<{tag}>{}</{tag}>", content.trim()));
+        Ok(())
+    }
+
+    fn inline(&mut self, element: &Element) -> anyhow::Result<String> {
+        Ok(match element {
+            Element::Text { text, .. } => escape_xml(text),
+            Element::Header { text, .. } => {
+                format!(r#"<emphasis role="strong">{}</emphasis>"#, escape_xml(text))
+            }
+            Element::Paragraph { elements, .. } | Element::Container { elements, .. } => {
+                self.inlines(elements)?
+            }
+            Element::Styled { style, elements } => {
+                let content = self.inlines(elements)?;
+                match style {
+                    TextStyle::Strong => format!(r#"<emphasis role="strong">{content}</emphasis>"#),
+                    TextStyle::Emphasis => format!("<emphasis>{content}</emphasis>"),
+                    TextStyle::Strikethrough => {
+                        format!(r#"<emphasis role="strikethrough">{content}</emphasis>"#)
+                    }
+                }
+            }
+            Element::Hyperlink { elements, url, .. } => {
+                let content = self.inlines(elements)?;
+                let target = match url.strip_prefix('#') {
+                    Some(anchor) if self.ids.contains(anchor) => {
+                        format!(r#"linkend="{}""#, escape_xml(anchor))
+                    }
+                    _ => format!(r#"xlink:href="{}""#, escape_xml(url)),
+                };
+                if content.trim().is_empty() {
+                    format!("<link {target}/>")
+                } else {
+                    format!("<link {target}>{content}</link>")
+                }
+            }
+            Element::InlineCode { text } => format!("<code>{}</code>", escape_xml(text)),
+            Element::CodeBlock { code, .. } => format!("<code>{}</code>", escape_xml(code.trim())),
+            Element::Math { tex, .. } => format!(
+                "<inlineequation><mathphrase>{}</mathphrase></inlineequation>",
+                escape_xml(tex.trim())
+            ),
+            Element::LineBreak => "\n".to_string(),
+            Element::Footnote { elements } => {
+                let content = self.inlines(elements)?;
+                format!("<footnote><para>{}</para></footnote>", content.trim())
+            }
+            Element::Bookmark { id, .. } => {
+                let id = self.id_attribute(id);
+                if id.is_empty() {
+                    String::new()
+                } else {
+                    format!("<anchor{id}/>")
+                }
+            }
+            Element::Image(image) => match self.save_image(image)? {
+                Some(name) => format!(
+                    "<inlinemediaobject><imageobject><imagedata fileref=\"{}\"/></imageobject>{}</inlinemediaobject>",
+                    escape_xml(&name),
+                    text_object(image)
+                ),
+                None => String::new(),
+            },
+            Element::Attributed { element, .. } => self.inline(element)?,
+            Element::Media { .. } => match element.media_link() {
+                Some(link) => self.inline(&link)?,
+                None => String::new(),
+            },
+            Element::List { elements, .. } => {
+                let mut content = String::new();
+                for item in elements {
+                    content.push_str(&self.inline(&item.element)?);
+                    content.push(' ');
+                }
+                content
+            }
+            _ => String::new(),
+        })
+    }
+
+    fn inlines(&mut self, elements: &[Element]) -> anyhow::Result<String> {
+        let mut content = String::new();
+        for element in elements {
+            content.push_str(&self.inline(element)?);
+        }
+        Ok(content)
+    }
+
+    /// Media object of an image, in a figure when it has a title
+    fn image_block(&mut self, image: &ImageData, indent: usize) -> anyhow::Result<()> {
+        let Some(name) = self.save_image(image)? else {
+            return Ok(());
+        };
+        let title = image.title().trim();
+        let indent = if title.is_empty() {
+            indent
+        } else {
+            self.line(indent, "<figure>");
+            self.line(indent + 1, format!("<title>{}</title>", escape_xml(title)));
+            indent + 1
+        };
+        let size = image.size();
+        let mut attributes = format!(r#"fileref="{}""#, escape_xml(&name));
+        if let Some(width) = &size.width {
+            let _ = write!(attributes, r#" width="{}""#, escape_xml(width.trim()));
+        }
+        if let Some(height) = &size.height {
+            let _ = write!(attributes, r#" depth="{}""#, escape_xml(height.trim()));
+        }
+        self.line(indent, "<mediaobject>");
+        self.line(
+            indent + 1,
+            format!("<imageobject><imagedata {attributes}/></imageobject>"),
+        );
+        let text = text_object(image);
+        if !text.is_empty() {
+            self.line(indent + 1, text);
+        }
+        self.line(indent, "</mediaobject>");
+        if !title.is_empty() {
+            self.line(indent - 1, "</figure>");
+        }
+        Ok(())
+    }
+
+    /// Name of the saved image
+    fn save_image(&mut self, image: &ImageData) -> anyhow::Result<Option<String>> {
+        if image.bytes().is_empty() {
+            warn!("Image without content left out: {}", image.title());
+            return Ok(None);
+        }
+        self.image_count += 1;
+        let name = format!(
+            "image{}{}",
+            self.image_count,
+            image.image_type().to_extension()
+        );
+        (self.image_saver)(image.bytes(), &name)?;
+        Ok(Some(name))
+    }
+
+    /// CALS table without title, its header row in the head
+    fn table(
+        &mut self,
+        headers: &[TableHeader],
+        rows: &[TableRow],
+        indent: usize,
+    ) -> anyhow::Result<()> {
+        let columns = rows
+            .iter()
+            .map(|row| row.cells.len())
+            .chain([headers.len()])
+            .max()
+            .unwrap_or_default();
+        if columns == 0 {
+            return Ok(());
+        }
+        self.line(indent, "<informaltable>");
+        self.line(indent + 1, format!(r#"<tgroup cols="{columns}">"#));
+        if !headers.is_empty() {
+            self.line(indent + 2, "<thead>");
+            let cells: Vec<&Element> = headers.iter().map(|header| &header.element).collect();
+            self.row(&cells, columns, indent + 3)?;
+            self.line(indent + 2, "</thead>");
+        }
+        self.line(indent + 2, "<tbody>");
+        for row in rows {
+            let cells: Vec<&Element> = row.cells.iter().map(|cell| &cell.element).collect();
+            self.row(&cells, columns, indent + 3)?;
+        }
+        if rows.is_empty() {
+            // a body has at least one row
+            self.row(&[], columns, indent + 3)?;
+        }
+        self.line(indent + 2, "</tbody>");
+        self.line(indent + 1, "</tgroup>");
+        self.line(indent, "</informaltable>");
+        Ok(())
+    }
+
+    /// Row of entries, completed with empty ones to the number of columns
+    fn row(&mut self, cells: &[&Element], columns: usize, indent: usize) -> anyhow::Result<()> {
+        self.line(indent, "<row>");
+        for index in 0..columns {
+            match cells.get(index) {
+                Some(cell) if !matches!(cell, Element::Text { .. } | Element::Paragraph { .. }) => {
+                    self.line(indent + 1, "<entry>");
+                    self.required_blocks([*cell], indent + 2)?;
+                    self.line(indent + 1, "</entry>");
+                }
+                Some(cell) => {
+                    let content = self.inline(cell)?;
+                    self.line(indent + 1, format!("<entry>{}</entry>", content.trim()));
+                }
+                None => self.line(indent + 1, "<entry/>"),
+            }
+        }
+        self.line(indent, "</row>");
+        Ok(())
+    }
+
+    /// Itemized or ordered list, the blocks after an item being its own blocks
+    fn list(&mut self, list: &Element, indent: usize) -> anyhow::Result<()> {
+        let Element::List {
+            elements,
+            numbered,
+            start,
+            numbering,
+        } = list
+        else {
+            return Ok(());
+        };
+        let mut items: Vec<Vec<&Element>> = vec![];
+        for item in elements {
+            let element = &item.element;
+            match items.last_mut() {
+                Some(blocks)
+                    if matches!(
+                        element,
+                        Element::List { .. }
+                            | Element::CodeBlock { .. }
+                            | Element::Table { .. }
+                            | Element::Image(_)
+                    ) =>
+                {
+                    blocks.push(element)
+                }
+                _ => items.push(vec![element]),
+            }
+        }
+        if items.is_empty() {
+            return Ok(());
+        }
+        let tag = if *numbered {
+            let numeration = match numbering {
+                NumberingStyle::Decimal => "arabic",
+                NumberingStyle::LowerAlpha => "loweralpha",
+                NumberingStyle::UpperAlpha => "upperalpha",
+                NumberingStyle::LowerRoman => "lowerroman",
+                NumberingStyle::UpperRoman => "upperroman",
+            };
+            let mut tag = format!(r#"orderedlist numeration="{numeration}""#);
+            if *start != 1 {
+                let _ = write!(tag, r#" startingnumber="{start}""#);
+            }
+            self.line(indent, format!("<{tag}>"));
+            "orderedlist"
+        } else {
+            self.line(indent, "<itemizedlist>");
+            "itemizedlist"
+        };
+        for blocks in items {
+            self.line(indent + 1, "<listitem>");
+            for block in blocks {
+                match block {
+                    Element::Text { .. } => {
+                        self.paragraph(std::slice::from_ref(block), indent + 2)?
+                    }
+                    block => self.block(block, indent + 2)?,
+                }
+            }
+            if self
+                .lines
+                .last()
+                .is_some_and(|line| line.ends_with("<listitem>"))
+            {
+                self.line(indent + 2, "<para/>");
+            }
+            self.line(indent + 1, "</listitem>");
+        }
+        self.line(indent, format!("</{tag}>"));
+        Ok(())
+    }
+}
+
+/// Text object of an image with its alternative text, empty without one
+fn text_object(image: &ImageData) -> String {
+    let alt = image.alt().trim();
+    if alt.is_empty() {
+        String::new()
+    } else {
+        format!(
+            "<textobject><phrase>{}</phrase></textobject>",
+            escape_xml(alt)
+        )
+    }
+}
+
+impl Transformer {
+    /// DocBook XML of the document, its images saved to the current directory
+    pub fn generate(document: &Document) -> crate::Result<Bytes> {
+        Transformer::generate_with_saver(document, disk_image_saver("."))
+    }
+
+    /// DocBook XML of the document, its images saved with `image_saver` under the names
+    /// their `imagedata` refers to
+    pub fn generate_with_saver<F>(document: &Document, image_saver: F) -> crate::Result<Bytes>
+    where
+        F: Fn(&Bytes, &str) -> anyhow::Result<()>,
+    {
+        Transformer::generate_with_image_saver(document, image_saver)
+            .map_err(crate::Error::generation_error)
+    }
+
+    fn generate_with_image_saver<F>(document: &Document, image_saver: F) -> anyhow::Result<Bytes>
+    where
+        F: Fn(&Bytes, &str) -> anyhow::Result<()>,
+    {
+        let document = &document.resolve_fields().without_drawings();
+        let mut writer = Writer {
+            image_saver: &image_saver,
+            image_count: 0,
+            ids: document_ids(document),
+            written_ids: HashSet::new(),
+            lines: vec![],
+        };
+        let elements: Vec<&Element> = document
+            .bands
+            .iter()
+            .filter(|band| !matches!(band, Band::PageHeader(_) | Band::PageFooter(_)))
+            .flat_map(|band| band.elements())
+            .collect();
+        let top_level = elements
+            .iter()
+            .filter_map(|element| header_parts(element))
+            .map(|(level, _, _)| level)
+            .min();
+        let root = if top_level.is_some() {
+            "book"
+        } else {
+            "article"
+        };
+
+        writer.line(0, format!(r#"<{root} {NAMESPACES} version="5.0">"#));
+        let metadata = &document.metadata;
+        let mut info = vec![];
+        if let Some(title) = &metadata.title {
+            info.push(format!("<title>{}</title>", escape_xml(title.trim())));
+        }
+        if let Some(author) = &metadata.author {
+            info.push(format!(
+                "<author><personname>{}</personname></author>",
+                escape_xml(author.trim())
+            ));
+        }
+        if let Some(date) = &metadata.date {
+            info.push(format!("<date>{}</date>", escape_xml(date.trim())));
+        }
+        if let Some(description) = &metadata.description {
+            info.push(format!(
+                "<abstract><para>{}</para></abstract>",
+                escape_xml(description.trim())
+            ));
+        }
+        if !metadata.keywords.is_empty() {
+            let keywords: String = metadata
+                .keywords
+                .iter()
+                .map(|keyword| format!("<keyword>{}</keyword>", escape_xml(keyword)))
+                .collect();
+            info.push(format!("<keywordset>{keywords}</keywordset>"));
+        }
+        if !info.is_empty() {
+            writer.line(1, "<info>");
+            for line in info {
+                writer.line(2, line);
+            }
+            writer.line(1, "</info>");
+        }
+        match top_level {
+            Some(top_level) => writer.book(&elements, top_level, 1)?,
+            None => {
+                let before = writer.lines.len();
+                writer.blocks(elements.iter().copied(), 1)?;
+                if writer.lines.len() == before {
+                    // an article has at least one block
+                    writer.line(1, "<para/>");
+                }
+            }
+        }
+        writer.line(0, format!("</{root}>"));
+
+        let xml = format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n{}\n",
+            writer.lines.join("\n")
+        );
+        Ok(Bytes::from(xml))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use bytes::Bytes;
+
+    use crate::core::tests::init_logger;
+    use crate::core::{Band, Element, ImageData, ImageDimension, TransformerTrait};
+    use crate::docbook::Transformer;
+    use crate::markdown;
+
+    #[test]
+    fn test_generate_book() -> anyhow::Result<()> {
+        init_logger();
+        let markdown = "Intro & more.\n\n# Install\n\nSee [usage](#usage) and \
+            [the site](https://example.com).[^1]\n\n## Usage\n\n\
+            1. **Run** it\n   - with `--help`\n2. Done\n\n\
+            | Name | Value |\n|------|-------|\n| a | 1 < 2 |\n\n\
+            ```rust\nfn main() {}\n```\n\n# Empty\n\n[^1]: A note.\n";
+        let mut document = markdown::Transformer::parse(&Bytes::from(markdown))?;
+        document.metadata.title = Some("Guide".to_string());
+        let png = Bytes::from(std::fs::read("test/data/small.png")?);
+        if let Some(Band::Detail(elements)) = document.bands.last_mut() {
+            elements.push(Element::Image(ImageData::new(
+                png.clone(),
+                "Logo".to_string(),
+                "The logo".to_string(),
+                "png".to_string(),
+                String::new(),
+                ImageDimension {
+                    width: Some("50%".to_string()),
+                    height: None,
+                },
+            )));
+            elements.push(Element::admonition(
+                "danger",
+                None,
+                vec![Element::Text {
+                    text: "Hot.".to_string(),
+                    size: 14,
+                }],
+            ));
+        }
+
+        let saved = RefCell::new(vec![]);
+        let xml = Transformer::generate_with_saver(&document, |bytes, name| {
+            saved.borrow_mut().push((name.to_string(), bytes.clone()));
+            Ok(())
+        })?;
+        let xml = std::str::from_utf8(&xml)?;
+        assert!(xml.starts_with(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<book xmlns=\"http://docbook.org/ns/docbook\" \
+             xmlns:xlink=\"http://www.w3.org/1999/xlink\" version=\"5.0\">\n  \
+             <info>\n    <title>Guide</title>\n  </info>\n  \
+             <preface>\n    <title>Preface</title>\n    <para>Intro &amp; more.</para>\n  </preface>\n  \
+             <chapter>\n    <title>Install</title>\n"
+        ));
+        assert!(xml.contains(
+            "<para>See <link linkend=\"usage\">usage</link> and \
+             <link xlink:href=\"https://example.com\">the site</link>.\
+             <footnote><para>A note.</para></footnote></para>"
+        ));
+        assert!(xml.contains("    <section xml:id=\"usage\">\n      <title>Usage</title>\n"));
+        assert!(xml.contains(
+            "<orderedlist numeration=\"arabic\">\n        <listitem>\n          \
+             <para><emphasis role=\"strong\">Run</emphasis> it</para>\n          \
+             <itemizedlist>\n            <listitem>\n              \
+             <para>with <code>--help</code></para>\n"
+        ));
+        assert!(xml.contains("<entry>a</entry>\n              <entry>1 &lt; 2</entry>"));
+        assert!(xml.contains("<programlisting language=\"rust\">fn main() {}</programlisting>"));
+        assert!(xml.contains(
+            "  <chapter>\n    <title>Empty</title>\n    <figure>\n      <title>Logo</title>\n      \
+             <mediaobject>\n        <imageobject><imagedata fileref=\"image1.png\" width=\"50%\"/></imageobject>\n        \
+             <textobject><phrase>The logo</phrase></textobject>\n      </mediaobject>\n    </figure>\n    \
+             <warning>\n      <para>Hot.</para>\n    </warning>\n  </chapter>\n</book>\n"
+        ));
+        assert_eq!(
+            saved.borrow().as_slice(),
+            &[("image1.png".to_string(), png)]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_generate_article() -> anyhow::Result<()> {
+        init_logger();
+        let document = markdown::Transformer::parse(&Bytes::from("One  \ntwo\n\n# \n"))?;
+        let xml = Transformer::generate(&document)?;
+        let xml = std::str::from_utf8(&xml)?;
+        assert!(xml.contains("<book "), "{xml}");
+
+        let document = markdown::Transformer::parse(&Bytes::from("One  \ntwo\n"))?;
+        let xml = Transformer::generate(&document)?;
+        let xml = std::str::from_utf8(&xml)?;
+        assert!(xml
+            .contains("version=\"5.0\">\n  <literallayout>One\ntwo</literallayout>\n</article>\n"));
+        Ok(())
+    }
+}
@@ -1,18 +1,95 @@
 use crate::core::{
-    Document, Element, ImageDimension, ListItem, TableCell, TableRow, TransformerTrait,
+    Document, Element, ImageData, ImageDimension, InlineStyle, ListItem, Metadata, NumberingStyle,
+    TableCell, TableRow, TextAlign, TextStyle, TransformerTrait,
+};
+use crate::util::{
+    block_element, header_bookmark, item_element, RunFormat, BULLETS, HEADING_SIZES,
 };
 
 use bytes::Bytes;
 use docx_rs::{
-    read_docx, AbstractNumbering, Docx, Hyperlink, HyperlinkType, IndentLevel, Level, LevelJc,
-    LevelText, NumberFormat, Numbering, NumberingId, Paragraph, ParagraphStyle, Pic, Run, RunChild,
-    SpecialIndentType, Start, TableRowChild,
+    read_docx, AbstractNumbering, AlignmentType, Bold, Break, BreakType, DocumentChild, Docx,
+    Drawing, DrawingData, Hyperlink, HyperlinkData, HyperlinkType, IndentLevel, InsertChild,
+    Italic, Level, LevelJc, LevelOverride, LevelText, NumberFormat, Numbering, NumberingId,
+    Paragraph, ParagraphChild, Pic, Run, RunChild, RunFonts, RunProperty, SpecialIndentType, Start,
+    Strike, Style, StyleType, TableCellContent, TableRowChild, WidthType,
 };
-use log::{error, info, warn};
+use log::warn;
 use std::io::Cursor;
 
 pub struct Transformer;
 
+//code is written in a monospace font
+fn code_run(text: &str) -> Run {
+    Run::new()
+        .add_text(text)
+        .fonts(RunFonts::new().ascii("Courier New").hi_ansi("Courier New"))
+}
+
+/// Run of the text of a styled element, with the styles of the nested elements
+fn styled_run(element: &Element) -> Run {
+    let mut run = Run::new().add_text(element.plain_text());
+    let mut element = element;
+    while let Element::Styled { style, elements } = element {
+        run = match style {
+            TextStyle::Emphasis => run.italic(),
+            TextStyle::Strong => run.bold(),
+            TextStyle::Strikethrough => run.strike(),
+        };
+        match elements.as_slice() {
+            [nested] => element = nested,
+            _ => break,
+        }
+    }
+    run
+}
+
+/// Applies the `style` attributes of an attributed element, outermost first, to the
+/// paragraphs written for it
+fn apply_inline_style(children: &mut [DocumentChild], attributed: &Element) {
+    let Element::Attributed {
+        attributes,
+        element,
+    } = attributed
+    else {
+        return;
+    };
+    let style = InlineStyle::from_attributes(attributes);
+    for child in children.iter_mut() {
+        let DocumentChild::Paragraph(paragraph) = child else {
+            continue;
+        };
+        if let Some(align) = style.align {
+            paragraph.property = paragraph.property.clone().align(match align {
+                TextAlign::Left => AlignmentType::Left,
+                TextAlign::Center => AlignmentType::Center,
+                TextAlign::Right => AlignmentType::Right,
+                TextAlign::Justify => AlignmentType::Both,
+            });
+        }
+        for paragraph_child in &mut paragraph.children {
+            let ParagraphChild::Run(run) = paragraph_child else {
+                continue;
+            };
+            if let Some(color) = &style.color {
+                run.run_property = run.run_property.clone().color(color);
+            }
+            if style.bold {
+                run.run_property = run.run_property.clone().bold();
+            }
+        }
+    }
+    apply_inline_style(children, element);
+}
+
+/// Element wrapped by the attributes, if any
+fn unwrap_attributes(element: &Element) -> &Element {
+    match element {
+        Element::Attributed { element, .. } => unwrap_attributes(element),
+        element => element,
+    }
+}
+
 //function re_size input picture (if size very big)
 fn re_size_picture(pic: Pic) -> Pic {
     let mut pic = pic;
@@ -43,357 +120,691 @@ fn re_size_picture(pic: Pic) -> Pic {
     pic
 }
 
-//recursive function for processing nested elements in Element::List
-fn detect_element_in_list(doc: &mut Docx, element: &Element, numbered: bool, depth: usize) {
-    match element {
-        Element::Text { text, size } => {
-            let mut paragraph =
-                Paragraph::new().add_run(Run::new().add_text(text).size(*size as usize * 2));
+/// Character style of the text of the links
+const HYPERLINK_STYLE: &str = "Hyperlink";
+
+/// Width of the tables in twentieths of a point, the text width of an A4 page
+const TABLE_WIDTH: usize = 9000;
+
+/// Heading styles, read by Word as the outline of the document, and the style of the links
+fn add_styles(doc: Docx) -> Docx {
+    let mut doc = doc;
+    for (index, size) in HEADING_SIZES.iter().enumerate() {
+        let level = index + 1;
+        doc = doc.add_style(
+            Style::new(format!("Heading{level}"), StyleType::Paragraph)
+                .name(format!("heading {level}"))
+                .based_on("Normal")
+                .next("Normal")
+                .size(*size as usize * 2)
+                .bold()
+                .outline_lvl(index),
+        );
+    }
+    doc.add_style(
+        Style::new(HYPERLINK_STYLE, StyleType::Character)
+            .name("Hyperlink")
+            .color("0563C1")
+            .underline("single"),
+    )
+}
 
-            if numbered {
-                paragraph = paragraph.numbering(NumberingId::new(2), IndentLevel::new(depth));
-            } else {
-                // Add the "-" character at the beginning of the text, taking into account the nesting level
-                let indent = " ".repeat(depth * 4); // 4 spaces for each nesting level
-                let modified_text = format!("{}- {}", indent, text);
-                paragraph = Paragraph::new()
-                    .add_run(Run::new().add_text(modified_text).size(*size as usize * 2));
-            }
-            *doc = doc.clone().add_paragraph(paragraph);
-        }
+/// Adds a numbering of its own to a list, so that its numbers start over, its items being
+/// at the level of its depth
+fn add_list_numbering(
+    doc: &mut Docx,
+    numbered: bool,
+    start: usize,
+    numbering: &NumberingStyle,
+    depth: usize,
+) -> usize {
+    // docx-rs writes a default numbering of id 1
+    let numberings = &mut doc.numberings;
+    let abstract_id = numberings
+        .abstract_nums
+        .iter()
+        .map(|abstract_numbering| abstract_numbering.id)
+        .max()
+        .unwrap_or(1)
+        + 1;
+    let id = numberings
+        .numberings
+        .iter()
+        .map(|numbering| numbering.id)
+        .max()
+        .unwrap_or(1)
+        + 1;
+    let format = match numbering {
+        NumberingStyle::Decimal => "decimal",
+        NumberingStyle::LowerAlpha => "lowerLetter",
+        NumberingStyle::UpperAlpha => "upperLetter",
+        NumberingStyle::LowerRoman => "lowerRoman",
+        NumberingStyle::UpperRoman => "upperRoman",
+    };
+    let mut abstract_numbering = AbstractNumbering::new(abstract_id);
+    for level in 0..9 {
+        let (format, text) = if numbered {
+            (format, format!("%{}.", level + 1))
+        } else {
+            ("bullet", BULLETS[level % BULLETS.len()].to_string())
+        };
+        let start = if level == depth { start } else { 1 };
+        abstract_numbering = abstract_numbering.add_level(
+            Level::new(
+                level,
+                Start::new(start),
+                NumberFormat::new(format),
+                LevelText::new(text),
+                LevelJc::new("left"),
+            )
+            .indent(
+                Some(720 * (level as i32 + 1)),
+                Some(SpecialIndentType::Hanging(360)),
+                None,
+                None,
+            ),
+        );
+    }
+    numberings.abstract_nums.push(abstract_numbering);
+    numberings
+        .numberings
+        .push(Numbering::new(id, abstract_id).add_override(LevelOverride::new(depth).start(start)));
+    id
+}
 
-        Element::Header { level, text } => {
-            let size = match level {
-                1 => 18,
-                2 => 16,
-                _ => 14,
-            };
-            let mut paragraph = Paragraph::new().add_run(Run::new().add_text(text).size(size * 2));
-            if numbered {
-                paragraph = paragraph.numbering(NumberingId::new(3), IndentLevel::new(0));
-            } else {
-                paragraph = paragraph.style("ListBullet");
+/// Writes the items of a list as paragraphs of its numbering, a nested list being
+/// written at the next level
+fn add_list(doc: &mut Docx, list: &Element, depth: usize) {
+    let Element::List {
+        elements,
+        numbered,
+        start,
+        numbering,
+    } = list
+    else {
+        return;
+    };
+    let id = add_list_numbering(doc, *numbered, *start, numbering, depth);
+    for list_item in elements {
+        match &list_item.element {
+            nested @ Element::List { .. } => add_list(doc, nested, depth + 1),
+            element => {
+                let paragraph = add_inline(Paragraph::new(), element)
+                    .numbering(NumberingId::new(id), IndentLevel::new(depth));
+                *doc = std::mem::take(doc).add_paragraph(paragraph);
             }
-            *doc = doc.clone().add_paragraph(paragraph);
         }
+    }
+}
 
-        Element::Hyperlink {
-            title,
-            url,
-            alt: _,
-            size,
-        } => {
-            let mut hyperlink_paragraph =
-                Paragraph::new().add_run(Run::new().add_text(title).size(*size as usize * 2));
-
-            if numbered {
-                hyperlink_paragraph =
-                    hyperlink_paragraph.numbering(NumberingId::new(2), IndentLevel::new(depth));
-            } else {
-                let indent = " ".repeat(depth * 4);
-                let modified_title = format!("{}- {}", indent, title);
-                hyperlink_paragraph = Paragraph::new()
-                    .add_run(Run::new().add_text(modified_title).size(*size as usize * 2));
-            }
-
-            let hyperlink = Hyperlink::new(url, HyperlinkType::External)
-                .add_run(Run::new().add_text(url).size(*size as usize * 2));
+/// Link showing its text, to a bookmark for `#anchor` URLs
+fn hyperlink(url: &str, text: &str, size: u8) -> Hyperlink {
+    let text = if text.is_empty() { url } else { text };
+    let run = Run::new()
+        .add_text(text)
+        .size(size as usize * 2)
+        .style(HYPERLINK_STYLE);
+    match url.strip_prefix('#') {
+        Some(anchor) => Hyperlink::new(anchor, HyperlinkType::Anchor),
+        None => Hyperlink::new(url, HyperlinkType::External),
+    }
+    .add_run(run)
+}
 
-            *doc = doc
-                .clone()
-                .add_paragraph(Paragraph::add_hyperlink(hyperlink_paragraph, hyperlink));
+/// Run of the picture of an image, or of its alt text when its bytes are not a raster image
+fn image_run(image: &ImageData) -> Run {
+    match picture(image) {
+        Ok(pic) => Run::new().add_image(pic),
+        Err(err) => {
+            warn!("Image written as its alt text: {}", err);
+            Run::new().add_text(image.alt())
         }
+    }
+}
 
-        Element::List { elements, numbered } => {
-            for list_item in elements {
-                detect_element_in_list(doc, &list_item.element, *numbered, depth + 1);
-            }
+/// Picture of an image at its size, shrunk to fit the page, the image being decoded here since
+/// docx-rs panics on bytes it cannot decode
+fn picture(image: &ImageData) -> anyhow::Result<Pic> {
+    let bytes = image.bytes();
+    let reader = image::io::Reader::new(Cursor::new(bytes)).with_guessed_format()?;
+    let mut pic = if reader.format() == Some(image::ImageFormat::Png) {
+        let (width, height) = reader.into_dimensions()?;
+        Pic::new_with_dimensions(bytes.to_vec(), width, height)
+    } else {
+        let decoded = reader.decode()?;
+        let mut png = Cursor::new(vec![]);
+        decoded.write_to(&mut png, image::ImageOutputFormat::Png)?;
+        Pic::new_with_dimensions(png.into_inner(), decoded.width(), decoded.height())
+    };
+    if let ImageDimension {
+        width: Some(width),
+        height: Some(height),
+    } = image.size()
+    {
+        let width = width.parse().unwrap_or(0);
+        let height = height.parse().unwrap_or(0);
+        if width > 0 && height > 0 {
+            pic = pic.size(width, height);
         }
+    }
+    Ok(re_size_picture(pic))
+}
 
-        _ => {
-            warn!("unknown element");
+/// Adds the runs of an inline element to a paragraph, blocks being written as their text
+fn add_inline(paragraph: Paragraph, element: &Element) -> Paragraph {
+    match element {
+        Element::Text { text, size } => {
+            paragraph.add_run(Run::new().add_text(text).size(*size as usize * 2))
+        }
+        Element::InlineCode { text } => paragraph.add_run(code_run(text)),
+        Element::Styled { .. } => paragraph.add_run(styled_run(element)),
+        Element::Hyperlink { url, size, .. } => {
+            paragraph.add_hyperlink(hyperlink(url, &element.plain_text(), *size))
+        }
+        Element::LineBreak => paragraph.add_run(Run::new().add_break(BreakType::TextWrapping)),
+        Element::Image(image) => paragraph.add_run(image_run(image)),
+        Element::Paragraph { elements, .. } => elements.iter().fold(paragraph, add_inline),
+        Element::Attributed { element, .. } => add_inline(paragraph, element),
+        element => {
+            let text = element.plain_text();
+            if text.is_empty() {
+                paragraph
+            } else {
+                paragraph.add_run(Run::new().add_text(text))
+            }
         }
     }
 }
 
-impl TransformerTrait for Transformer {
-    fn parse(document: &Bytes) -> anyhow::Result<Document> {
-        fn extract_text(doc_element: &docx_rs::Paragraph) -> String {
-            let mut result = "".to_string();
-            for c in &doc_element.children {
-                match c {
-                    docx_rs::ParagraphChild::Run(run) => {
-                        if run.children.is_empty() {
-                            result.push_str("");
-                            return result;
-                        }
-                        if let RunChild::Text(t) = &run.children[0] {
-                            result.push_str(&t.text);
-                        }
-                    }
-                    _ => {}
-                }
+/// Cell of a table, the text of the header cells in bold
+fn table_cell(element: &Element, header: bool) -> docx_rs::TableCell {
+    let mut paragraph = add_inline(Paragraph::new(), element);
+    if header {
+        for child in &mut paragraph.children {
+            if let ParagraphChild::Run(run) = child {
+                run.run_property = run.run_property.clone().bold();
             }
-
-            return result;
         }
+    }
+    docx_rs::TableCell::new().add_paragraph(paragraph)
+}
 
-        let docx = read_docx(document)?;
-        const HEADING1: &str = "Heading1";
-        const HEADING2: &str = "Heading2";
-        const NORMAL: &str = "Normal";
-        const BODY_TEXT: &str = "BodyText";
-        let mut result: Vec<Element> = vec![];
-
-        let mut is_list_numbered = false;
-
-        let mut current_list: Option<(usize, Vec<ListItem>)> = None;
-
-        for ch in docx.document.children {
-            if let docx_rs::DocumentChild::Paragraph(par) = ch {
-                if let Some(numbering_property) = &par.property.numbering_property {
-                    let num_id = numbering_property
-                        .id
-                        .as_ref()
-                        .expect("No number id in list item")
-                        .id;
-                    if num_id == 3 || num_id == 2 {
-                        let list_text = extract_text(&par);
-
-                        let list_item = ListItem {
-                            element: Element::Text {
-                                text: list_text,
-                                size: 12,
-                            },
-                        };
-
-                        let numbered = numbering_property
-                            .id
-                            .as_ref()
-                            .expect("No number id in list item")
-                            .id
-                            == 3;
-                        let level = numbering_property
-                            .level
-                            .as_ref()
-                            .expect("Expect indent level to be Some")
-                            .val;
-                        if let Some((last_level, ref mut list_items)) = current_list {
-                            if level > last_level {
-                                let nested_list = Element::List {
-                                    elements: vec![list_item],
-                                    numbered,
-                                };
-                                list_items.push(ListItem {
-                                    element: nested_list,
-                                });
-                            } else if level < last_level {
-                                // Finish the current list and start a new one
-                                result.push(Element::List {
-                                    elements: list_items.clone(),
-                                    numbered,
-                                });
-                                current_list = Some((level, vec![list_item]));
-                            } else {
-                                list_items.push(list_item);
-                            }
-                        } else {
-                            current_list = Some((level, vec![list_item]));
-                            is_list_numbered = numbered;
-                        }
-                    } else {
-                        if let Some((_, list_items)) = current_list.take() {
-                            result.push(Element::List {
-                                elements: list_items,
-                                numbered: is_list_numbered,
-                            });
-                        }
-
-                        match &par.property.style {
-                            Some(ParagraphStyle { val }) => match val.as_str() {
-                                HEADING1 => {
-                                    let text = extract_text(&par);
-                                    let element = Element::Header { level: 1, text };
+/// Text with the characters that would be read as markup escaped
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
 
-                                    result.push(element);
-                                }
-                                HEADING2 => {
-                                    let text = extract_text(&par);
-                                    let element = Element::Header { level: 2, text };
+/// docProps/core.xml of the metadata, written here as docx-rs does not set these properties.
+/// The date is written when it is a W3C date (`2024-05-01`, `2024-05-01T10:00:00Z`).
+fn core_properties(metadata: &Metadata) -> Vec<u8> {
+    let mut xml = String::from(
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?><cp:coreProperties xmlns:cp="http://schemas.openxmlformats.org/package/2006/metadata/core-properties" xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:dcterms="http://purl.org/dc/terms/" xmlns:dcmitype="http://purl.org/dc/dcmitype/" xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance">"#,
+    );
+    let keywords = metadata.keywords.join(", ");
+    for (tag, value) in [
+        ("dc:title", metadata.title.as_deref()),
+        ("dc:creator", metadata.author.as_deref()),
+        ("dc:description", metadata.description.as_deref()),
+        (
+            "cp:keywords",
+            Some(keywords.as_str()).filter(|k| !k.is_empty()),
+        ),
+    ] {
+        if let Some(value) = value {
+            xml.push_str(&format!("<{tag}>{}</{tag}>", escape_xml(value)));
+        }
+    }
+    let w3c_date = |date: &&str| {
+        date.get(..4)
+            .is_some_and(|year| year.chars().all(|c| c.is_ascii_digit()))
+            && date
+                .chars()
+                .all(|c| c.is_ascii_digit() || "-:T.Z+".contains(c))
+    };
+    if let Some(date) = metadata.date.as_deref().map(str::trim).filter(w3c_date) {
+        xml.push_str(&format!(
+            r#"<dcterms:created xsi:type="dcterms:W3CDTF">{date}</dcterms:created>"#
+        ));
+    }
+    xml.push_str("</cp:coreProperties>");
+    xml.into_bytes()
+}
 
-                                    result.push(element);
-                                }
+// region:    ---reader
 
-                                BODY_TEXT => {
-                                    let text = extract_text(&par);
-                                    let element = Element::Text { text, size: 16 };
+/// Size of the text read from docx, the sizes of the runs are not read
+const TEXT_SIZE: u8 = 12;
 
-                                    result.push(element);
-                                }
+/// English Metric Units of a pixel at 96 DPI, the unit of the sizes of pictures
+const EMU_PER_PIXEL: u32 = 9525;
 
-                                NORMAL => {
-                                    let text = extract_text(&par);
-                                    let element = Element::Text { text, size: 16 };
+/// List of a numbering of numbering.xml being read, with the level of its items
+struct OpenList {
+    id: usize,
+    level: usize,
+    list: Element,
+}
 
-                                    result.push(element);
-                                }
+/// Reads the body of a docx file with its styles, numberings, images and links
+struct Reader<'a> {
+    docx: &'a Docx,
+}
 
-                                _ => {}
-                            },
-                            _ => {
-                                // TODO: Implement other styles
-                            }
+impl Reader<'_> {
+    fn read(&self) -> Vec<Element> {
+        let mut elements = vec![];
+        let mut lists: Vec<OpenList> = vec![];
+        for child in &self.docx.document.children {
+            match child {
+                DocumentChild::Paragraph(paragraph) => {
+                    let content = self.inline_elements(&paragraph.children);
+                    if let Some(level) = self.heading_level(paragraph) {
+                        close_lists(&mut lists, &mut elements);
+                        let text: String = content.iter().map(Element::plain_text).collect();
+                        if !text.trim().is_empty() {
+                            elements.push(Element::Header { level, text });
                         }
+                    } else if let Some((id, level, list)) = self.list(paragraph) {
+                        add_list_item(&mut lists, &mut elements, id, level, list, content);
+                    } else {
+                        close_lists(&mut lists, &mut elements);
+                        elements.extend(block_element(content));
                     }
-                } else {
-                    if let Some((_, list_items)) = current_list.take() {
-                        result.push(Element::List {
-                            elements: list_items,
-                            numbered: is_list_numbered,
-                        });
-                    }
-                    match &par.property.style {
-                        Some(ParagraphStyle { val }) => match val.as_str() {
-                            HEADING1 => {
-                                let text = extract_text(&par);
-                                let element = Element::Header { level: 1, text };
-
-                                result.push(element);
-                            }
-                            HEADING2 => {
-                                let text = extract_text(&par);
-                                let element = Element::Header { level: 2, text };
-
-                                result.push(element);
-                            }
-
-                            BODY_TEXT => {
-                                let text = extract_text(&par);
-                                let element = Element::Text { text, size: 16 };
+                }
+                DocumentChild::Table(table) => {
+                    close_lists(&mut lists, &mut elements);
+                    elements.push(self.table(table));
+                }
+                _ => {}
+            }
+        }
+        close_lists(&mut lists, &mut elements);
+        elements
+    }
 
-                                result.push(element);
-                            }
+    fn style(&self, style_id: &str) -> Option<&Style> {
+        self.docx
+            .styles
+            .styles
+            .iter()
+            .find(|style| style.style_id == style_id)
+    }
 
-                            NORMAL => {
-                                let text = extract_text(&par);
-                                let element = Element::Text { text, size: 16 };
+    /// Level of a paragraph written with a heading style (`heading 1`, `Title`...) or an
+    /// outline level
+    fn heading_level(&self, paragraph: &Paragraph) -> Option<u8> {
+        let style_id = paragraph.property.style.as_ref().map(|style| &style.val);
+        let style = style_id.and_then(|style_id| self.style(style_id));
+        let outline_level = paragraph
+            .property
+            .outline_lvl
+            .as_ref()
+            .or_else(|| style.and_then(|style| style.paragraph_property.outline_lvl.as_ref()));
+        let level = match style {
+            Some(style) => style
+                .name
+                .get_heading_number()
+                .or_else(|| style.name.starts_with("Title").then_some(1)),
+            // the styles of the file may be missing, `Heading1` is the id of `heading 1`
+            None => style_id
+                .and_then(|style_id| style_id.strip_prefix("Heading"))
+                .and_then(|level| level.parse().ok()),
+        }
+        .or_else(|| {
+            outline_level
+                .filter(|outline_level| outline_level.v < 9)
+                .map(|outline_level| outline_level.v + 1)
+        })?;
+        (1..=9).contains(&level).then_some(level as u8)
+    }
 
-                                result.push(element);
-                            }
+    /// Numbering id, level and empty list of a list item, its marker being the format of
+    /// its level in numbering.xml
+    fn list(&self, paragraph: &Paragraph) -> Option<(usize, usize, Element)> {
+        let numbering_property = paragraph.property.numbering_property.as_ref().or_else(|| {
+            let style = self.style(&paragraph.property.style.as_ref()?.val)?;
+            style.paragraph_property.numbering_property.as_ref()
+        })?;
+        let id = numbering_property.id.as_ref()?.id;
+        let level = numbering_property
+            .level
+            .as_ref()
+            .map_or(0, |level| level.val);
+        let numberings = &self.docx.numberings;
+        let numbering = numberings
+            .numberings
+            .iter()
+            .find(|numbering| numbering.id == id)?;
+        let level_override = numbering
+            .level_overrides
+            .iter()
+            .find(|level_override| level_override.level == level);
+        let format = level_override
+            .and_then(|level_override| level_override.override_level.as_ref())
+            .or_else(|| {
+                numberings
+                    .abstract_nums
+                    .iter()
+                    .find(|abstract_numbering| abstract_numbering.id == numbering.abstract_num_id)?
+                    .levels
+                    .iter()
+                    .find(|abstract_level| abstract_level.level == level)
+            })
+            .map_or("bullet", |abstract_level| {
+                abstract_level.format.val.as_str()
+            });
+        let numbering_style = match format {
+            "lowerLetter" => NumberingStyle::LowerAlpha,
+            "upperLetter" => NumberingStyle::UpperAlpha,
+            "lowerRoman" => NumberingStyle::LowerRoman,
+            "upperRoman" => NumberingStyle::UpperRoman,
+            _ => NumberingStyle::Decimal,
+        };
+        let list = Element::List {
+            elements: vec![],
+            numbered: !matches!(format, "bullet" | "none"),
+            start: level_override
+                .and_then(|level_override| level_override.override_start)
+                .unwrap_or(1),
+            numbering: numbering_style,
+        };
+        Some((id, level, list))
+    }
 
-                            _ => {}
-                        },
-                        _ => {
-                            // TODO: Implement other styles
+    /// Text, styled text, links, images and line breaks of a paragraph, the consecutive
+    /// runs of the same styles being joined
+    fn inline_elements(&self, children: &[ParagraphChild]) -> Vec<Element> {
+        let mut pieces: Vec<(RunFormat, Element)> = vec![];
+        for child in children {
+            match child {
+                ParagraphChild::Run(run) => self.run_pieces(run, &mut pieces),
+                // tracked insertions are part of the text, tracked deletions are not
+                ParagraphChild::Insert(insert) => {
+                    for child in &insert.children {
+                        if let InsertChild::Run(run) = child {
+                            self.run_pieces(run, &mut pieces);
                         }
                     }
                 }
-            } else {
-                if let Some((_, list_items)) = current_list.take() {
-                    result.push(Element::List {
-                        elements: list_items,
-                        numbered: is_list_numbered,
-                    });
+                ParagraphChild::Hyperlink(hyperlink) => {
+                    let elements = self.inline_elements(&hyperlink.children);
+                    let url = match &hyperlink.link {
+                        HyperlinkData::External { rid, .. } => self
+                            .docx
+                            .hyperlinks
+                            .iter()
+                            .find(|(id, ..)| id == rid)
+                            .map(|(_, url, _)| url.clone()),
+                        HyperlinkData::Anchor { anchor } => Some(format!("#{anchor}")),
+                    };
+                    match url {
+                        Some(url) => pieces.push((
+                            RunFormat::default(),
+                            Element::Hyperlink {
+                                elements,
+                                url,
+                                alt: String::new(),
+                                size: TEXT_SIZE,
+                            },
+                        )),
+                        None => pieces.extend(
+                            elements
+                                .into_iter()
+                                .map(|element| (RunFormat::default(), element)),
+                        ),
+                    }
                 }
-                match ch {
-                    docx_rs::DocumentChild::Table(table) => {
-                        let mut rows = vec![];
-                        for row in &table.rows {
-                            let docx_rs::TableChild::TableRow(tr) = row;
-                            let mut cells = TableRow { cells: vec![] };
-
-                            for table_cell in &tr.cells {
-                                let TableRowChild::TableCell(tc) = table_cell;
-                                for ch in &tc.children {
-                                    match ch {
-                                        docx_rs::TableCellContent::Paragraph(par) => {
-                                            let text = extract_text(par);
-                                            cells.cells.push(TableCell {
-                                                element: Element::Text { text, size: 16 },
-                                            });
-                                        }
-                                        _ => {}
-                                    }
-                                }
-                            }
-                            rows.push(cells);
-                        }
+                _ => {}
+            }
+        }
 
-                        result.push(Element::Table {
-                            headers: vec![],
-                            rows,
-                        });
+        let mut elements = vec![];
+        let mut group: Option<(RunFormat, Vec<Element>)> = None;
+        for (format, element) in pieces {
+            match &mut group {
+                Some((group_format, group_elements)) if *group_format == format => {
+                    match (group_elements.last_mut(), element) {
+                        (Some(Element::Text { text, .. }), Element::Text { text: next, .. }) => {
+                            text.push_str(&next)
+                        }
+                        (_, element) => group_elements.push(element),
+                    }
+                }
+                _ => {
+                    if let Some((group_format, group_elements)) =
+                        group.replace((format, vec![element]))
+                    {
+                        elements.extend(group_format.apply(group_elements));
                     }
-                    _ => {}
                 }
             }
         }
-
-        if let Some((_, list_items)) = current_list.take() {
-            result.push(Element::List {
-                elements: list_items,
-                numbered: is_list_numbered,
-            });
+        if let Some((group_format, group_elements)) = group {
+            elements.extend(group_format.apply(group_elements));
         }
+        elements
+    }
 
-        Ok(Document::new(result))
-    }
-
-    fn generate(document: &Document) -> anyhow::Result<Bytes> {
-        let mut doc = Docx::new();
-
-        // region:    ---abstract_numbering
-        let mut abstract_numbering = AbstractNumbering::new(2);
-        for level in 0..=7 {
-            let level_text = match level {
-                0 => "%1",
-                1 => "%1.%2",
-                2 => "%1.%2.%3.",
-                3 => "%1.%2.%3.%4",
-                4 => "%1.%2.%3.%4.%5",
-                5 => "%1.%2.%3.%4.%5.%6",
-                6 => "%1.%2.%3.%4.%5.%6.%7",
-                7 => "%1.%2.%3.%4.%5.%6.%7.%8",
-                _ => "%1.%2.%3.%4.%5.%6.%7.%8.%9",
+    fn run_pieces(&self, run: &Run, pieces: &mut Vec<(RunFormat, Element)>) {
+        let format = self.run_format(&run.run_property);
+        for child in &run.children {
+            let element = match child {
+                RunChild::Text(text) => Element::Text {
+                    text: text.text.clone(),
+                    size: TEXT_SIZE,
+                },
+                RunChild::Tab(_) => Element::Text {
+                    text: "\t".to_string(),
+                    size: TEXT_SIZE,
+                },
+                RunChild::Break(br) if *br == Break::new(BreakType::TextWrapping) => {
+                    Element::LineBreak
+                }
+                RunChild::CarriageReturn(_) => Element::LineBreak,
+                RunChild::Drawing(drawing) => match self.image(drawing) {
+                    Some(image) => image,
+                    None => continue,
+                },
+                _ => continue,
+            };
+            // only the text is styled
+            let format = match element {
+                Element::Text { .. } => format,
+                _ => RunFormat::default(),
             };
+            pieces.push((format, element));
+        }
+    }
 
-            //selecting the offset of a sub-item on the sheet
-            let sub_item_offset = level as i32 + 1;
-            // let sub_item_offset = 1;
-
-            abstract_numbering = abstract_numbering.add_level(
-                Level::new(
-                    level,
-                    Start::new(1),
-                    NumberFormat::new("decimal"),
-                    LevelText::new(level_text),
-                    LevelJc::new("left"),
-                )
-                .indent(
-                    Some(300 * sub_item_offset),
-                    Some(SpecialIndentType::Hanging(320)),
-                    None,
-                    None,
-                ),
-            );
+    /// Styles of a run, set on the run or by its character style
+    fn run_format(&self, property: &RunProperty) -> RunFormat {
+        let style = property
+            .style
+            .as_ref()
+            .and_then(|style| self.style(&style.val))
+            .map(|style| &style.run_property);
+        fn toggle<T: PartialEq>(value: &Option<T>, on: T) -> Option<bool> {
+            value.as_ref().map(|value| *value == on)
         }
-        // endregion: ---abstract_numbering
+        let on = |value: fn(&RunProperty) -> Option<bool>| {
+            value(property)
+                .or_else(|| style.and_then(value))
+                .unwrap_or(false)
+        };
+        RunFormat {
+            strong: on(|property| toggle(&property.bold, Bold::new())),
+            emphasis: on(|property| toggle(&property.italic, Italic::new())),
+            strikethrough: on(|property| toggle(&property.strike, Strike::new())),
+            ..RunFormat::default()
+        }
+    }
+
+    /// Picture of the media folder, its size in pixels
+    fn image(&self, drawing: &Drawing) -> Option<Element> {
+        let Some(DrawingData::Pic(pic)) = &drawing.data else {
+            return None;
+        };
+        let Some((_, path, image, _)) = self.docx.images.iter().find(|(id, ..)| *id == pic.id)
+        else {
+            warn!("No image {} in the media folder", pic.id);
+            return None;
+        };
+        let pixels = |emu: u32| (emu > 0).then(|| (emu / EMU_PER_PIXEL).to_string());
+        Some(Element::Image(ImageData::new(
+            Bytes::from(image.0.clone()),
+            String::new(),
+            String::new(),
+            path.clone(),
+            String::new(),
+            ImageDimension {
+                width: pixels(pic.size.0),
+                height: pixels(pic.size.1),
+            },
+        )))
+    }
+
+    /// Table of the rows, a cell of several paragraphs being one cell with line breaks
+    fn table(&self, table: &docx_rs::Table) -> Element {
+        let rows = table
+            .rows
+            .iter()
+            .map(|docx_rs::TableChild::TableRow(row)| TableRow {
+                cells: row
+                    .cells
+                    .iter()
+                    .map(|TableRowChild::TableCell(cell)| {
+                        let mut content = vec![];
+                        for child in &cell.children {
+                            if let TableCellContent::Paragraph(paragraph) = child {
+                                let elements = self.inline_elements(&paragraph.children);
+                                if elements.is_empty() {
+                                    continue;
+                                }
+                                if !content.is_empty() {
+                                    content.push(Element::LineBreak);
+                                }
+                                content.extend(elements);
+                            }
+                        }
+                        TableCell {
+                            element: item_element(content),
+                        }
+                    })
+                    .collect(),
+            })
+            .collect();
+        Element::Table {
+            headers: vec![],
+            rows,
+        }
+    }
+}
+
+/// Adds an item to the open list of its numbering and level, a deeper level starting a
+/// nested list that follows the item it belongs to
+fn add_list_item(
+    lists: &mut Vec<OpenList>,
+    elements: &mut Vec<Element>,
+    id: usize,
+    level: usize,
+    list: Element,
+    content: Vec<Element>,
+) {
+    while lists
+        .last()
+        .is_some_and(|open| open.level > level || (open.level == level && open.id != id))
+    {
+        close_list(lists, elements);
+    }
+    if lists.last().is_none_or(|open| open.level < level) {
+        lists.push(OpenList { id, level, list });
+    }
+    if let Some(OpenList {
+        list: Element::List { elements, .. },
+        ..
+    }) = lists.last_mut()
+    {
+        elements.push(ListItem {
+            element: item_element(content),
+        });
+    }
+}
+
+/// Ends the innermost open list, an item of its parent list or a block of the document
+fn close_list(lists: &mut Vec<OpenList>, elements: &mut Vec<Element>) {
+    let Some(open) = lists.pop() else {
+        return;
+    };
+    match lists.last_mut() {
+        Some(OpenList {
+            list: Element::List {
+                elements: items, ..
+            },
+            ..
+        }) => items.push(ListItem { element: open.list }),
+        _ => elements.push(open.list),
+    }
+}
+
+fn close_lists(lists: &mut Vec<OpenList>, elements: &mut Vec<Element>) {
+    while !lists.is_empty() {
+        close_list(lists, elements);
+    }
+}
+
+// endregion: ---reader
 
-        doc = doc
-            .add_abstract_numbering(abstract_numbering)
-            .add_numbering(Numbering::new(2, 2));
+impl TransformerTrait for Transformer {
+    fn parse(document: &Bytes) -> crate::Result<Document> {
+        Transformer::parse_bytes(document).map_err(crate::Error::parse_error)
+    }
+
+    fn generate(document: &Document) -> crate::Result<Bytes> {
+        Transformer::generate_bytes(document).map_err(crate::Error::generation_error)
+    }
+}
+
+impl Transformer {
+    fn parse_bytes(document: &Bytes) -> anyhow::Result<Document> {
+        let docx = read_docx(document)?;
+        Ok(Document::new(Reader { docx: &docx }.read()))
+    }
 
+    fn generate_bytes(document: &Document) -> anyhow::Result<Bytes> {
+        // the attributed document gives the inline styles of the elements written from
+        // the stripped one
+        let attributed = &document
+            .resolve_fields()
+            .without_containers()
+            .without_media()
+            .without_math()
+            .without_drawings();
+        let document = &attributed.without_attributes();
+        let mut doc = add_styles(Docx::new());
+        for (key, value) in &document.metadata.custom {
+            doc = doc.custom_property(key, value);
+        }
+
+        let mut bookmark_id = 0;
         // TODO: Consider to refactor this code to use the new #Band Enum (header, footer, etc)
-        for element in &document.get_all_elements() {
+        for (element, attributed) in document
+            .get_all_elements()
+            .into_iter()
+            .zip(attributed.get_all_elements())
+        {
+            let written_from = doc.document.children.len();
             match element {
                 Element::Header { level, text } => {
-                    let size = match level {
-                        1 => 18,
-                        2 => 16,
-                        _ => 14,
-                    };
+                    let (level, anchor) = header_bookmark(*level, text);
+                    bookmark_id += 1;
                     doc = doc.add_paragraph(
-                        Paragraph::new().add_run(Run::new().add_text(text).size(size * 2)),
+                        Paragraph::new()
+                            .style(&format!("Heading{level}"))
+                            .add_bookmark_start(bookmark_id, anchor)
+                            .add_run(Run::new().add_text(text))
+                            .add_bookmark_end(bookmark_id),
                     );
                 }
 
@@ -404,8 +815,12 @@ impl TransformerTrait for Transformer {
                     )
                 }
 
-                Element::Paragraph { elements } => {
-                    for paragraph_element in elements {
+                Element::Paragraph { elements, .. } => {
+                    let attributed_elements = unwrap_attributes(attributed).children();
+                    for (paragraph_element, attributed_element) in
+                        elements.iter().zip(attributed_elements)
+                    {
+                        let element_written_from = doc.document.children.len();
                         match paragraph_element {
                             Element::Text { text, size } => {
                                 doc =
@@ -413,103 +828,140 @@ impl TransformerTrait for Transformer {
                                         Run::new().add_text(text).size(*size as usize * 2),
                                     ));
                             }
+                            Element::InlineCode { text } => {
+                                doc = doc.add_paragraph(Paragraph::new().add_run(code_run(text)));
+                            }
+                            Element::Styled { .. } => {
+                                doc = doc.add_paragraph(
+                                    Paragraph::new().add_run(styled_run(paragraph_element)),
+                                );
+                            }
+                            // every paragraph element is written as its own docx paragraph,
+                            // so the break is already there
+                            Element::LineBreak => {}
                             _ => {
-                                error!("Unknown paragraph element");
+                                doc = doc
+                                    .add_paragraph(add_inline(Paragraph::new(), paragraph_element));
                             }
                         }
+                        apply_inline_style(
+                            &mut doc.document.children[element_written_from..],
+                            attributed_element,
+                        );
                     }
                 }
 
-                Element::List { elements, numbered } => {
-                    for list_item in elements {
-                        detect_element_in_list(&mut doc, &list_item.element, *numbered, 0);
-                    }
+                Element::LineBreak => {
+                    doc = doc.add_paragraph(Paragraph::new());
                 }
 
-                Element::Hyperlink {
-                    title,
-                    url,
-                    alt,
-                    size,
-                } => {
-                    let _ = alt;
-                    let hyperlink = Hyperlink::new(url, HyperlinkType::External)
-                        .add_run(Run::new().add_text(url).size(*size as usize * 2));
-                    let paragraph = Paragraph::new()
-                        .add_run(Run::new().add_text(title).size(*size as usize * 2));
-
-                    doc = doc.add_paragraph(Paragraph::add_hyperlink(paragraph, hyperlink));
+                // page numbers are not supported yet
+                Element::Field { .. } => {}
+
+                // replaced by text in Document::resolve_fields
+                Element::CrossReference { .. } => {}
+
+                // unwrapped in Document::without_attributes
+                Element::Attributed { .. } => {}
+
+                // flattened in Document::without_containers
+                Element::Container { .. } => {}
+                // replaced by a link in Document::without_media
+                Element::Media { .. } => {}
+                // replaced by code in Document::without_math
+                Element::Math { .. } => {}
+                // replaced by an image in Document::without_drawings
+                Element::Drawing { .. } => {}
+
+                Element::InlineCode { text } => {
+                    doc = doc.add_paragraph(Paragraph::new().add_run(code_run(text)));
                 }
 
-                Element::Image(image) => {
-                    let mut pic = Pic::new(&image.bytes());
-
-                    match &image.size() {
-                        &ImageDimension {
-                            width: Some(width),
-                            height: Some(height),
-                        } => {
-                            let width = width.parse().unwrap_or(0);
-                            let height = height.parse().unwrap_or(0);
-                            if width > 0 && height > 0 {
-                                pic = pic.size(width, height);
-                            }
-                        }
-                        _ => {}
+                // content of another format
+                Element::Raw { .. } => {}
+
+                Element::CodeBlock { code, .. } => {
+                    for line in code.lines() {
+                        doc = doc.add_paragraph(Paragraph::new().add_run(code_run(line)));
                     }
+                }
 
-                    pic = re_size_picture(pic);
+                Element::Styled { .. } => {
+                    doc = doc.add_paragraph(Paragraph::new().add_run(styled_run(element)));
+                }
 
-                    let paragraph = Paragraph::new().add_run(Run::new().add_image(pic));
+                // footnotes are not supported yet
+                Element::Footnote { .. } => {}
 
-                    doc = doc.add_paragraph(paragraph);
+                Element::Bookmark { id, .. } => {
+                    bookmark_id += 1;
+                    doc = doc.add_paragraph(
+                        Paragraph::new()
+                            .add_bookmark_start(bookmark_id, id)
+                            .add_bookmark_end(bookmark_id),
+                    );
+                }
+
+                Element::TableOfContents { max_level } => {
+                    add_list(&mut doc, &document.build_toc(*max_level), 0);
+                }
+
+                Element::List { .. } => {
+                    add_list(&mut doc, element, 0);
+                }
+
+                Element::Hyperlink { url, size, .. } => {
+                    doc = doc.add_paragraph(Paragraph::new().add_hyperlink(hyperlink(
+                        url,
+                        &element.plain_text(),
+                        *size,
+                    )));
+                }
+
+                Element::Image(image) => {
+                    doc = doc.add_paragraph(Paragraph::new().add_run(image_run(image)));
                 }
 
                 Element::Table { headers, rows } => {
                     let mut table_rows = Vec::new();
-
                     if !headers.is_empty() {
-                        let mut header_cell: Vec<docx_rs::TableCell> = Vec::new();
-                        for header in headers {
-                            if let Element::Text { text, size } = &header.element {
-                                let cell = docx_rs::TableCell::new().add_paragraph(
-                                    Paragraph::new().add_run(
-                                        Run::new().add_text(text).size(*size as usize * 2),
-                                    ),
-                                );
-                                header_cell.push(cell);
-                            }
-                        }
-                        let header_row = docx_rs::TableRow::new(header_cell);
-                        table_rows.push(header_row)
+                        table_rows.push(docx_rs::TableRow::new(
+                            headers
+                                .iter()
+                                .map(|header| table_cell(&header.element, true))
+                                .collect(),
+                        ));
                     }
-
                     for row in rows {
-                        let mut rows_cell = Vec::new();
-
-                        for cell in &row.cells {
-                            if let Element::Text { text, size } = &cell.element {
-                                let table_cell = docx_rs::TableCell::new().add_paragraph(
-                                    Paragraph::new().add_run(
-                                        Run::new().add_text(text).size(*size as usize * 2),
-                                    ),
-                                );
-                                rows_cell.push(table_cell);
-                            }
-                        }
-                        let table_row = docx_rs::TableRow::new(rows_cell);
-                        table_rows.push(table_row);
+                        table_rows.push(docx_rs::TableRow::new(
+                            row.cells
+                                .iter()
+                                .map(|cell| table_cell(&cell.element, false))
+                                .collect(),
+                        ));
                     }
-                    let table = docx_rs::Table::new(table_rows);
+                    let columns = rows
+                        .iter()
+                        .map(|row| row.cells.len())
+                        .chain([headers.len()])
+                        .max()
+                        .unwrap_or(0)
+                        .max(1);
+                    let table = docx_rs::Table::new(table_rows)
+                        .set_grid(vec![TABLE_WIDTH / columns; columns])
+                        .width(TABLE_WIDTH, WidthType::Dxa);
                     doc = doc.add_table(table);
                 }
             }
+            apply_inline_style(&mut doc.document.children[written_from..], attributed);
         }
 
         let buffer = Vec::new();
         let mut cursor = Cursor::new(buffer);
 
-        doc.build().pack(&mut cursor)?;
+        let mut xml = doc.build();
+        xml.doc_props.core = core_properties(&document.metadata);
+        xml.pack(&mut cursor)?;
         let buffer = cursor.into_inner();
 
         Ok(bytes::Bytes::from(buffer))
@@ -519,11 +971,13 @@ impl TransformerTrait for Transformer {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::core::tests::init_logger;
+    use crate::core::tests::{init_logger, output_dir};
     use crate::core::{disk_image_loader, TransformerWithImageLoaderSaverTrait};
     use crate::{docx, markdown};
     use bytes::Bytes;
+    use docx_rs::Color;
     use log::info;
+    use std::collections::HashMap;
 
     #[test]
     fn test() -> anyhow::Result<()> {
@@ -539,11 +993,240 @@ mod tests {
         let generated_result = docx::Transformer::generate(&parsed)?;
         //write to file
         info!("--->>>{:<12} - start writing document_from_md.docx", "TEST");
-        std::fs::write("test/data/document_from_md.docx", generated_result)?;
+        std::fs::write(
+            format!("{}/document_from_md.docx", output_dir()),
+            generated_result,
+        )?;
+
+        Ok(())
+    }
 
+    #[test]
+    fn test_inline_style() -> anyhow::Result<()> {
+        let style = |style: &str| HashMap::from([("style".to_string(), style.to_string())]);
+        let document = Document::new(vec![Element::Paragraph {
+            elements: vec![
+                Element::Text {
+                    text: "Plain".to_string(),
+                    size: 8,
+                },
+                Element::Text {
+                    text: "Red".to_string(),
+                    size: 8,
+                }
+                .with_attributes(style("color: red; font-weight: bold")),
+            ],
+            direction: Default::default(),
+        }
+        .with_attributes(style("text-align: center"))]);
+        let generated = docx::Transformer::generate(&document)?;
+        let docx = read_docx(&generated)?;
+        let paragraphs: Vec<&Paragraph> = docx
+            .document
+            .children
+            .iter()
+            .filter_map(|child| match child {
+                DocumentChild::Paragraph(paragraph) => Some(paragraph.as_ref()),
+                _ => None,
+            })
+            .collect();
+        let [plain, red] = paragraphs.as_slice() else {
+            panic!("Unexpected paragraphs {:?}", paragraphs);
+        };
+        let run_property = |paragraph: &Paragraph| match &paragraph.children[0] {
+            ParagraphChild::Run(run) => run.run_property.clone(),
+            child => panic!("Unexpected paragraph child {:?}", child),
+        };
+        for paragraph in [plain, red] {
+            assert_eq!(
+                paragraph
+                    .property
+                    .alignment
+                    .as_ref()
+                    .map(|jc| jc.val.as_str()),
+                Some("center")
+            );
+        }
+        assert_eq!(run_property(plain).color, None);
+        assert_eq!(run_property(red).color, Some(Color::new("FF0000")));
+        assert!(run_property(red).bold.is_some());
         Ok(())
     }
 
+    #[test]
+    fn test_generate_elements() -> anyhow::Result<()> {
+        let markdown = "# Report\n\n## Part\n\nSee [the site](https://example.com).\n\n\
+            - One\n  - Nested\n- Two\n\n3. Third\n4. Fourth\n\n\
+            | Name | Value |\n|------|-------|\n| a | **1** |\n";
+        let mut document = markdown::Transformer::parse(&Bytes::from(markdown))?;
+        let png = Bytes::from(std::fs::read("test/data/small.png")?);
+        document.bands = vec![crate::core::Band::Detail(
+            document
+                .get_all_elements()
+                .into_iter()
+                .cloned()
+                .chain([Element::Image(crate::core::ImageData::new(
+                    png.clone(),
+                    String::new(),
+                    String::new(),
+                    "png".to_string(),
+                    String::new(),
+                    ImageDimension::default(),
+                ))])
+                .collect(),
+        )];
+
+        let generated = docx::Transformer::generate(&document)?;
+        let docx = read_docx(&generated)?;
+        let heading = docx
+            .styles
+            .styles
+            .iter()
+            .find(|style| style.style_id == "Heading2")
+            .expect("No Heading2 style");
+        assert_eq!(heading.name.get_heading_number(), Some(2));
+        let formats: Vec<&str> = docx
+            .numberings
+            .abstract_nums
+            .iter()
+            .filter(|abstract_numbering| abstract_numbering.id > 1)
+            .map(|abstract_numbering| abstract_numbering.levels[0].format.val.as_str())
+            .collect();
+        assert_eq!(formats, ["bullet", "bullet", "decimal"]);
+
+        let parsed = docx::Transformer::parse(&generated)?;
+        let elements = parsed.get_all_elements();
+        // the elements of a paragraph are written as paragraphs of their own
+        let [report, part, _, link, _, bullets, numbered, table, image] = elements.as_slice()
+        else {
+            panic!("Unexpected elements {:#?}", elements);
+        };
+        assert_eq!(
+            *report,
+            &Element::Header {
+                level: 1,
+                text: "Report".to_string()
+            }
+        );
+        assert_eq!(
+            *part,
+            &Element::Header {
+                level: 2,
+                text: "Part".to_string()
+            }
+        );
+        let Element::Paragraph { elements, .. } = link else {
+            panic!("Unexpected paragraph {:?}", link);
+        };
+        assert!(elements.iter().any(|element| matches!(
+            element,
+            Element::Hyperlink { url, .. }
+                if url == "https://example.com" && element.plain_text() == "the site"
+        )));
+        let Element::List {
+            elements: items,
+            numbered: false,
+            ..
+        } = bullets
+        else {
+            panic!("Unexpected list {:?}", bullets);
+        };
+        assert_eq!(items.len(), 3);
+        assert!(matches!(
+            &items[1].element,
+            Element::List { elements, .. } if elements[0].element.plain_text() == "Nested"
+        ));
+        let Element::List {
+            elements: items,
+            numbered: true,
+            start: 3,
+            ..
+        } = numbered
+        else {
+            panic!("Unexpected list {:?}", numbered);
+        };
+        assert_eq!(items.len(), 2);
+        let Element::Table { rows, .. } = table else {
+            panic!("Unexpected table {:?}", table);
+        };
+        assert_eq!(rows.len(), 2);
+        assert_eq!(
+            rows[0].cells[0].element,
+            Element::Styled {
+                style: TextStyle::Strong,
+                elements: vec![Element::Text {
+                    text: "Name".to_string(),
+                    size: 12
+                }]
+            }
+        );
+        assert_eq!(rows[1].cells[1].element.plain_text(), "1");
+        let Element::Image(image) = image else {
+            panic!("Unexpected image {:?}", image);
+        };
+        assert_eq!(image.bytes(), &png);
+        Ok(())
+    }
+
+    #[test]
+    fn test_undecodable_image() -> anyhow::Result<()> {
+        let image = |bytes: &'static [u8], alt: &str| {
+            Element::Image(crate::core::ImageData::new(
+                Bytes::from(bytes),
+                String::new(),
+                alt.to_string(),
+                "png".to_string(),
+                String::new(),
+                ImageDimension::default(),
+            ))
+        };
+        let document = Document::new(vec![
+            image(b"not an image", "Broken"),
+            Element::Paragraph {
+                elements: vec![image(
+                    b"<svg xmlns=\"http://www.w3.org/2000/svg\"/>",
+                    "Vector",
+                )],
+                direction: crate::core::TextDirection::default(),
+            },
+        ]);
+        let generated = docx::Transformer::generate(&document)?;
+        let parsed = docx::Transformer::parse(&generated)?;
+        let text: Vec<String> = parsed
+            .get_all_elements()
+            .iter()
+            .map(|element| element.plain_text())
+            .collect();
+        assert_eq!(text, ["Broken", "Vector"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_core_properties() {
+        let metadata = Metadata {
+            title: Some("Q&A".to_string()),
+            author: Some("Ada".to_string()),
+            date: Some("2024-05-01".to_string()),
+            keywords: vec!["one".to_string(), "two".to_string()],
+            ..Default::default()
+        };
+        let xml = String::from_utf8(core_properties(&metadata)).unwrap();
+        assert!(xml.contains("<dc:title>Q&amp;A</dc:title>"));
+        assert!(xml.contains("<dc:creator>Ada</dc:creator>"));
+        assert!(xml.contains("<cp:keywords>one, two</cp:keywords>"));
+        assert!(xml.contains(
+            r#"<dcterms:created xsi:type="dcterms:W3CDTF">2024-05-01</dcterms:created>"#
+        ));
+
+        let metadata = Metadata {
+            date: Some("May 1st".to_string()),
+            ..Default::default()
+        };
+        let xml = String::from_utf8(core_properties(&metadata)).unwrap();
+        assert!(!xml.contains("created"));
+        assert!(xml.ends_with("</cp:coreProperties>"));
+    }
+
     #[test]
     fn test_parse() -> anyhow::Result<()> {
         init_logger();
@@ -554,21 +1237,217 @@ mod tests {
 
         info!("Parsed - {:#?}", parsed);
         let elements = vec![
-            Element::Text {
-                text: "Warszawa, dnia {{DATA}} r. ".to_string(),
-                size: 16,
+            Element::Paragraph {
+                elements: vec![
+                    Element::Text {
+                        text: "Warszawa, dnia ".to_string(),
+                        size: 12,
+                    },
+                    Element::Styled {
+                        style: TextStyle::Strong,
+                        elements: vec![Element::Text {
+                            text: "{{DATA}}".to_string(),
+                            size: 12,
+                        }],
+                    },
+                    Element::Text {
+                        text: " r. ".to_string(),
+                        size: 12,
+                    },
+                ],
+                direction: Default::default(),
             },
             Element::Header {
                 level: 1,
                 text: "Header 1.".to_string(),
             },
-            Element::Text {
-                text: "".to_string(),
-                size: 16,
-            },
         ];
         let expected_result = Document::new(elements);
         assert_eq!(expected_result, parsed);
         Ok(())
     }
+
+    #[test]
+    fn test_parse_elements() -> anyhow::Result<()> {
+        let text = |text: &str| Element::Text {
+            text: text.to_string(),
+            size: 12,
+        };
+        let png = std::fs::read("test/data/small.png")?;
+        let list_numbering = |id: usize, format: &str| {
+            AbstractNumbering::new(id).add_level(Level::new(
+                0,
+                Start::new(1),
+                NumberFormat::new(format),
+                LevelText::new("%1."),
+                LevelJc::new("left"),
+            ))
+        };
+        let docx = Docx::new()
+            .add_style(Style::new("Titre2", StyleType::Paragraph).name("heading 2"))
+            .add_style(Style::new("Accent", StyleType::Character).italic())
+            .add_abstract_numbering(list_numbering(10, "bullet").add_level(Level::new(
+                1,
+                Start::new(1),
+                NumberFormat::new("lowerRoman"),
+                LevelText::new("%2."),
+                LevelJc::new("left"),
+            )))
+            .add_abstract_numbering(list_numbering(11, "decimal"))
+            .add_numbering(Numbering::new(10, 10))
+            .add_numbering(Numbering::new(11, 11).add_override(LevelOverride::new(0).start(3)))
+            .add_paragraph(
+                Paragraph::new()
+                    .style("Heading1")
+                    .add_run(Run::new().add_text("Title")),
+            )
+            .add_paragraph(
+                Paragraph::new()
+                    .style("Titre2")
+                    .add_run(Run::new().add_text("Part")),
+            )
+            .add_paragraph(
+                Paragraph::new()
+                    .add_run(Run::new().add_text("Plain "))
+                    .add_run(Run::new().add_text("bold").bold())
+                    .add_run(Run::new().add_text(" text").bold())
+                    .add_run(Run::new().add_text("both").bold().italic())
+                    .add_run(Run::new().add_text("styled").style("Accent"))
+                    .add_run(Run::new().add_text("gone").strike())
+                    .add_run(Run::new().add_break(BreakType::TextWrapping))
+                    .add_hyperlink(
+                        Hyperlink::new("https://example.com", HyperlinkType::External)
+                            .add_run(Run::new().add_text("link")),
+                    ),
+            )
+            .add_paragraph(Paragraph::new())
+            .add_paragraph(Paragraph::new().add_run(Run::new().add_image(Pic::new(&png))))
+            .add_paragraph(
+                Paragraph::new()
+                    .numbering(NumberingId::new(10), IndentLevel::new(0))
+                    .add_run(Run::new().add_text("One")),
+            )
+            .add_paragraph(
+                Paragraph::new()
+                    .numbering(NumberingId::new(10), IndentLevel::new(1))
+                    .add_run(Run::new().add_text("Nested")),
+            )
+            .add_paragraph(
+                Paragraph::new()
+                    .numbering(NumberingId::new(10), IndentLevel::new(0))
+                    .add_run(Run::new().add_text("Two")),
+            )
+            .add_paragraph(
+                Paragraph::new()
+                    .numbering(NumberingId::new(11), IndentLevel::new(0))
+                    .add_run(Run::new().add_text("Third")),
+            )
+            .add_table(docx_rs::Table::new(vec![docx_rs::TableRow::new(vec![
+                docx_rs::TableCell::new()
+                    .add_paragraph(Paragraph::new().add_run(Run::new().add_text("Cell"))),
+                docx_rs::TableCell::new()
+                    .add_paragraph(Paragraph::new().add_run(Run::new().add_text("Two")))
+                    .add_paragraph(Paragraph::new().add_run(Run::new().add_text("lines"))),
+            ])]));
+        let mut bytes = Cursor::new(Vec::new());
+        docx.build().pack(&mut bytes)?;
+
+        let parsed = docx::Transformer::parse(&Bytes::from(bytes.into_inner()))?;
+        let elements = &parsed.get_all_elements();
+        let [title, part, paragraph, image, bullets, numbered, table] = elements.as_slice() else {
+            panic!("Unexpected elements {:#?}", elements);
+        };
+        assert_eq!(
+            *title,
+            &Element::Header {
+                level: 1,
+                text: "Title".to_string()
+            }
+        );
+        assert_eq!(
+            *part,
+            &Element::Header {
+                level: 2,
+                text: "Part".to_string()
+            }
+        );
+        let styled = |style: TextStyle, elements: Vec<Element>| Element::Styled { style, elements };
+        assert_eq!(
+            *paragraph,
+            &Element::Paragraph {
+                elements: vec![
+                    text("Plain "),
+                    styled(TextStyle::Strong, vec![text("bold text")]),
+                    styled(
+                        TextStyle::Strong,
+                        vec![styled(TextStyle::Emphasis, vec![text("both")])]
+                    ),
+                    styled(TextStyle::Emphasis, vec![text("styled")]),
+                    styled(TextStyle::Strikethrough, vec![text("gone")]),
+                    Element::LineBreak,
+                    Element::Hyperlink {
+                        elements: vec![text("link")],
+                        url: "https://example.com".to_string(),
+                        alt: String::new(),
+                        size: 12,
+                    },
+                ],
+                direction: Default::default(),
+            }
+        );
+        let Element::Image(image) = image else {
+            panic!("Unexpected image {:?}", image);
+        };
+        assert_eq!(image.bytes().as_ref(), png.as_slice());
+        assert_eq!(image.image_type(), &crate::core::ImageType::Png);
+        assert!(image.size().width.is_some());
+
+        let Element::List {
+            elements: items,
+            numbered: false,
+            ..
+        } = bullets
+        else {
+            panic!("Unexpected list {:?}", bullets);
+        };
+        assert_eq!(items.len(), 3);
+        assert_eq!(items[0].element, text("One"));
+        assert_eq!(
+            items[1].element,
+            Element::List {
+                elements: vec![ListItem {
+                    element: text("Nested")
+                }],
+                numbered: true,
+                start: 1,
+                numbering: NumberingStyle::LowerRoman,
+            }
+        );
+        assert_eq!(items[2].element, text("Two"));
+        assert_eq!(
+            *numbered,
+            &Element::List {
+                elements: vec![ListItem {
+                    element: text("Third")
+                }],
+                numbered: true,
+                start: 3,
+                numbering: NumberingStyle::Decimal,
+            }
+        );
+
+        let Element::Table { rows, .. } = table else {
+            panic!("Unexpected table {:?}", table);
+        };
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].cells[0].element, text("Cell"));
+        assert_eq!(
+            rows[0].cells[1].element,
+            Element::Paragraph {
+                elements: vec![text("Two"), Element::LineBreak, text("lines")],
+                direction: Default::default(),
+            }
+        );
+        Ok(())
+    }
 }
@@ -0,0 +1,214 @@
+//! Output of the vector drawings of [`Element::Drawing`](crate::core::Element::Drawing):
+//! SVG markup, the path data of its shapes and, with the `image` feature, PNG images.
+
+use anyhow::{anyhow, bail};
+
+use crate::core::{PathCommand, Shape};
+
+/// Device pixels per point of the PNG images, twice the CSS pixels for sharp screens
+#[cfg(feature = "image")]
+const PIXELS_PER_POINT: f32 = 2.0 / 0.75;
+
+/// SVG document of a drawing, its size in points
+pub fn svg(width: f32, height: f32, shapes: &[Shape]) -> String {
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}pt\" height=\"{height}pt\" viewBox=\"0 0 {width} {height}\">"
+    );
+    for shape in shapes {
+        let color = |color: &Option<String>| match color {
+            Some(color) => format!("#{}", color),
+            None => "none".to_string(),
+        };
+        svg.push_str(&format!(
+            "<path d=\"{}\" fill=\"{}\" stroke=\"{}\"",
+            path_data(&shape.path),
+            color(&shape.fill),
+            color(&shape.stroke)
+        ));
+        if shape.stroke.is_some() {
+            svg.push_str(&format!(" stroke-width=\"{}\"", shape.stroke_width));
+        }
+        svg.push_str("/>");
+    }
+    svg.push_str("</svg>");
+    svg
+}
+
+/// SVG path data of a path, `M 0 0 L 10 0 Z`
+pub fn path_data(path: &[PathCommand]) -> String {
+    path.iter()
+        .map(|command| match command {
+            PathCommand::MoveTo { x, y } => format!("M {} {}", x, y),
+            PathCommand::LineTo { x, y } => format!("L {} {}", x, y),
+            PathCommand::CurveTo {
+                x1,
+                y1,
+                x2,
+                y2,
+                x,
+                y,
+            } => format!("C {} {} {} {} {} {}", x1, y1, x2, y2, x, y),
+            PathCommand::Close => "Z".to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Path of SVG path data with absolute `M`, `L`, `C` and `Z` commands, as written by
+/// [`path_data`]
+pub fn parse_path_data(data: &str) -> anyhow::Result<Vec<PathCommand>> {
+    let mut tokens = data
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|token| !token.is_empty())
+        .peekable();
+    let mut path = Vec::new();
+    while let Some(command) = tokens.next() {
+        let mut numbers = |count: usize| -> anyhow::Result<Vec<f32>> {
+            (0..count)
+                .map(|_| {
+                    let token = tokens
+                        .next()
+                        .ok_or_else(|| anyhow!("Missing coordinate after {}", command))?;
+                    Ok(token.parse::<f32>()?)
+                })
+                .collect()
+        };
+        path.push(match command {
+            "M" => {
+                let values = numbers(2)?;
+                PathCommand::MoveTo {
+                    x: values[0],
+                    y: values[1],
+                }
+            }
+            "L" => {
+                let values = numbers(2)?;
+                PathCommand::LineTo {
+                    x: values[0],
+                    y: values[1],
+                }
+            }
+            "C" => {
+                let values = numbers(6)?;
+                PathCommand::CurveTo {
+                    x1: values[0],
+                    y1: values[1],
+                    x2: values[2],
+                    y2: values[3],
+                    x: values[4],
+                    y: values[5],
+                }
+            }
+            "Z" | "z" => PathCommand::Close,
+            command => bail!("Unsupported path command {}", command),
+        });
+    }
+    Ok(path)
+}
+
+/// PNG image of a drawing, with a transparent background
+#[cfg(feature = "image")]
+pub fn png(width: f32, height: f32, shapes: &[Shape]) -> anyhow::Result<Vec<u8>> {
+    use tiny_skia::{FillRule, Paint, PathBuilder, Pixmap, Stroke, Transform};
+
+    let size = |points: f32| (points * PIXELS_PER_POINT).ceil().max(1.0) as u32;
+    let mut pixmap = Pixmap::new(size(width), size(height))
+        .ok_or_else(|| anyhow!("Invalid drawing size {}x{}", width, height))?;
+    let transform = Transform::from_scale(PIXELS_PER_POINT, PIXELS_PER_POINT);
+    let paint = |color: &str| -> anyhow::Result<Paint> {
+        let rgb = u32::from_str_radix(color, 16)?;
+        let mut paint = Paint::default();
+        paint.set_color_rgba8((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8, 255);
+        paint.anti_alias = true;
+        Ok(paint)
+    };
+    for shape in shapes {
+        let mut builder = PathBuilder::new();
+        for command in &shape.path {
+            match *command {
+                PathCommand::MoveTo { x, y } => builder.move_to(x, y),
+                PathCommand::LineTo { x, y } => builder.line_to(x, y),
+                PathCommand::CurveTo {
+                    x1,
+                    y1,
+                    x2,
+                    y2,
+                    x,
+                    y,
+                } => builder.cubic_to(x1, y1, x2, y2, x, y),
+                PathCommand::Close => builder.close(),
+            }
+        }
+        // a path without segment draws nothing
+        let Some(path) = builder.finish() else {
+            continue;
+        };
+        if let Some(fill) = &shape.fill {
+            pixmap.fill_path(&path, &paint(fill)?, FillRule::Winding, transform, None);
+        }
+        if let Some(stroke) = &shape.stroke {
+            let width = Stroke {
+                width: shape.stroke_width,
+                ..Stroke::default()
+            };
+            pixmap.stroke_path(&path, &paint(stroke)?, &width, transform, None);
+        }
+    }
+    Ok(pixmap.encode_png()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chart() -> Vec<Shape> {
+        vec![
+            Shape::line(0.0, 50.0, 100.0, 50.0),
+            Shape::rect(10.0, 20.0, 20.0, 30.0)
+                .with_fill("#36c")
+                .without_stroke(),
+            Shape::path(vec![
+                PathCommand::MoveTo { x: 40.0, y: 40.0 },
+                PathCommand::CurveTo {
+                    x1: 50.0,
+                    y1: 0.0,
+                    x2: 70.0,
+                    y2: 0.0,
+                    x: 80.0,
+                    y: 40.0,
+                },
+            ])
+            .with_stroke("red", 2.0),
+        ]
+    }
+
+    #[test]
+    fn test_svg() -> anyhow::Result<()> {
+        let shapes = chart();
+        let svg = svg(100.0, 50.0, &shapes);
+        assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"100pt\""));
+        assert!(svg.contains(
+            "<path d=\"M 10 20 L 30 20 L 30 50 L 10 50 Z\" fill=\"#3366CC\" stroke=\"none\"/>"
+        ));
+        assert!(svg.contains("stroke=\"#FF0000\" stroke-width=\"2\""));
+
+        for shape in &shapes {
+            assert_eq!(parse_path_data(&path_data(&shape.path))?, shape.path);
+        }
+        assert!(parse_path_data("M 0 0 Q 1 1 2 2").is_err());
+        Ok(())
+    }
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn test_png() -> anyhow::Result<()> {
+        let png = png(100.0, 50.0, &chart())?;
+        let image = image::load_from_memory(&png)?.to_rgba8();
+        assert_eq!(image.dimensions(), (267, 134));
+        // inside the filled bar
+        let [x, y] = [20.0, 40.0].map(|points: f32| (points * PIXELS_PER_POINT) as u32);
+        assert_eq!(image.get_pixel(x, y).0, [0x33, 0x66, 0xCC, 255]);
+        assert_eq!(image.get_pixel(1, 1).0[3], 0);
+        Ok(())
+    }
+}
@@ -0,0 +1,475 @@
+//! Email messages (RFC 5322 `.eml` files), parsed only
+//!
+//! The subject is the title, `From` the author and `Date` the date of the document, `To` and
+//! `Cc` custom metadata entries, all shown in a header block at its start. The body is the
+//! HTML part of the message, or else its plain text part, read by the HTML and text parsers.
+//! The images of the HTML part are the parts of the message with their `cid:` URL or
+//! `Content-Location`, other sources are given to the image loader. Attachments follow the
+//! body: images as images, other files as a list of their names.
+
+use std::collections::HashMap;
+
+use bytes::Bytes;
+use encoding_rs::{Encoding, UTF_8, WINDOWS_1252};
+use strum::VariantArray;
+
+use crate::core::{
+    data_uri_image_loader, disk_image_loader, Document, Element, ImageData, ImageDimension,
+    ImageType, ListItem, ParseOptions, TextDirection, TransformerTrait,
+};
+use crate::error::image_loader_errors;
+use crate::html::{
+    decode_html, decode_transfer_encoding, header_parameter, mime_part, multipart_parts,
+};
+use crate::{html, text};
+
+pub struct Transformer;
+
+/// Title of the list of the attachments after the body
+const ATTACHMENTS_TITLE: &str = "Attachments";
+
+/// Leaf part of a message, decoded from its transfer encoding
+struct Part {
+    headers: HashMap<String, String>,
+    content: Bytes,
+}
+
+impl Part {
+    /// Lowercase MIME type, without its parameters
+    fn mime_type(&self) -> String {
+        content_type(&self.headers)
+    }
+
+    fn charset(&self) -> Option<&'static Encoding> {
+        header_parameter(self.headers.get("content-type")?, "charset")
+            .and_then(|label| Encoding::for_label(label.as_bytes()))
+    }
+
+    fn is_attachment(&self) -> bool {
+        self.headers
+            .get("content-disposition")
+            .is_some_and(|disposition| disposition.to_ascii_lowercase().starts_with("attachment"))
+    }
+
+    /// File name of the `Content-Disposition` or, in older mailers, of the `Content-Type`
+    fn filename(&self) -> Option<String> {
+        self.headers
+            .get("content-disposition")
+            .and_then(|disposition| header_parameter(disposition, "filename"))
+            .or_else(|| header_parameter(self.headers.get("content-type")?, "name"))
+            .map(|name| decode_words(&name))
+    }
+
+    /// Text of a text part in its charset, Latin-1 or UTF-8 when it has none
+    fn text(&self) -> String {
+        let encoding = self
+            .charset()
+            .unwrap_or(match std::str::from_utf8(&self.content) {
+                Ok(_) => UTF_8,
+                Err(_) => WINDOWS_1252,
+            });
+        encoding.decode(&self.content).0.into_owned()
+    }
+}
+
+/// Parts of a message: the first HTML and text bodies, the resources the HTML body refers
+/// to and the attachments
+#[derive(Default)]
+struct Message {
+    html: Option<Part>,
+    text: Option<Part>,
+    /// Content of the parts by `cid:` URL of their `Content-ID` and by `Content-Location`
+    resources: HashMap<String, Bytes>,
+    attachments: Vec<Part>,
+}
+
+impl Message {
+    /// Adds a part and, for a multipart, the parts it is made of
+    fn add(&mut self, part: &[u8]) -> anyhow::Result<()> {
+        let (headers, body) = mime_part(part);
+        let mime_type = content_type(&headers);
+        if mime_type.starts_with("multipart/") {
+            let boundary = headers
+                .get("content-type")
+                .and_then(|value| header_parameter(value, "boundary"))
+                .ok_or_else(|| anyhow::anyhow!("{} part without boundary", mime_type))?;
+            for part in multipart_parts(body, &boundary) {
+                self.add(part)?;
+            }
+            return Ok(());
+        }
+        let part = Part {
+            content: decode_transfer_encoding(
+                body,
+                headers
+                    .get("content-transfer-encoding")
+                    .map(String::as_str)
+                    .unwrap_or_default(),
+            )?,
+            headers,
+        };
+        if !part.is_attachment() {
+            // the alternatives of a `multipart/alternative` are read in the order they come in
+            if mime_type == "text/html" && self.html.is_none() {
+                self.html = Some(part);
+                return Ok(());
+            }
+            if mime_type == "text/plain" && self.text.is_none() {
+                self.text = Some(part);
+                return Ok(());
+            }
+        }
+        let id = part.headers.get("content-id");
+        if let Some(id) = id {
+            self.resources.insert(
+                format!("cid:{}", id.trim_matches(['<', '>'])),
+                part.content.clone(),
+            );
+        }
+        if let Some(location) = part.headers.get("content-location") {
+            self.resources
+                .insert(location.clone(), part.content.clone());
+        }
+        // inline parts with an ID are the images of the HTML body
+        if part.is_attachment() || id.is_none() {
+            self.attachments.push(part);
+        }
+        Ok(())
+    }
+}
+
+/// Lowercase MIME type of the `Content-Type` of a part, `text/plain` by default
+fn content_type(headers: &HashMap<String, String>) -> String {
+    headers
+        .get("content-type")
+        .and_then(|value| value.split(';').next())
+        .map(|mime_type| mime_type.trim().to_ascii_lowercase())
+        .filter(|mime_type| !mime_type.is_empty())
+        .unwrap_or_else(|| "text/plain".to_string())
+}
+
+/// Header value with its RFC 2047 encoded words (`=?charset?B?...?=` and `=?charset?Q?...?=`)
+/// decoded, the blank space between two encoded words is dropped
+fn decode_words(value: &str) -> String {
+    let mut decoded = String::new();
+    let mut pending_space = String::new();
+    let mut previous_encoded = false;
+    let mut rest = value;
+    while !rest.is_empty() {
+        let space = rest.len() - rest.trim_start().len();
+        if space > 0 {
+            pending_space.push_str(&rest[..space]);
+            rest = &rest[space..];
+            continue;
+        }
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        let (word, next) = rest.split_at(end);
+        match decode_word(word) {
+            Some(text) => {
+                if !previous_encoded {
+                    decoded.push_str(&pending_space);
+                }
+                decoded.push_str(&text);
+                previous_encoded = true;
+            }
+            None => {
+                decoded.push_str(&pending_space);
+                decoded.push_str(word);
+                previous_encoded = false;
+            }
+        }
+        pending_space.clear();
+        rest = next;
+    }
+    decoded
+}
+
+/// Text of an encoded word, `None` when the word is not one
+fn decode_word(word: &str) -> Option<String> {
+    use base64::Engine;
+
+    let inner = word.strip_prefix("=?")?.strip_suffix("?=")?;
+    let mut fields = inner.splitn(3, '?');
+    let charset = fields.next()?;
+    let encoding = fields.next()?;
+    let text = fields.next()?;
+    // RFC 2231 language suffix, `utf-8*en`
+    let charset = charset.split('*').next().unwrap_or(charset);
+    let charset = Encoding::for_label(charset.as_bytes()).unwrap_or(UTF_8);
+    let bytes = match encoding {
+        "B" | "b" => base64::engine::general_purpose::STANDARD
+            .decode(text)
+            .ok()?,
+        "Q" | "q" => {
+            let text = text.replace('_', " ");
+            decode_transfer_encoding(text.as_bytes(), "quoted-printable")
+                .ok()?
+                .to_vec()
+        }
+        _ => return None,
+    };
+    Some(charset.decode(&bytes).0.into_owned())
+}
+
+fn paragraph(elements: Vec<Element>) -> Element {
+    Element::Paragraph {
+        elements,
+        direction: TextDirection::default(),
+    }
+}
+
+fn text_element(text: String) -> Element {
+    Element::Text { text, size: 8 }
+}
+
+impl Transformer {
+    /// Document of the message, the images of its HTML body that are not parts of the
+    /// message loaded from the current directory
+    pub fn parse(document: &Bytes) -> crate::Result<Document> {
+        Transformer::parse_with_options(document, &ParseOptions::default())
+    }
+
+    /// Same as [`Transformer::parse`] with the HTML and text options for the body
+    pub fn parse_with_options(document: &Bytes, options: &ParseOptions) -> crate::Result<Document> {
+        Transformer::parse_with_loader(document, disk_image_loader("."), options)
+    }
+
+    /// Document of the message, the images of its HTML body that are not parts of the
+    /// message loaded with `image_loader`
+    pub fn parse_with_loader<F>(
+        document: &Bytes,
+        image_loader: F,
+        options: &ParseOptions,
+    ) -> crate::Result<Document>
+    where
+        F: Fn(&str) -> anyhow::Result<Bytes>,
+    {
+        Transformer::parse_message(document, image_loader, options)
+            .map_err(crate::Error::parse_error)
+    }
+
+    fn parse_message<F>(
+        document: &Bytes,
+        image_loader: F,
+        options: &ParseOptions,
+    ) -> anyhow::Result<Document>
+    where
+        F: Fn(&str) -> anyhow::Result<Bytes>,
+    {
+        let (headers, _) = mime_part(document);
+        if !headers.contains_key("from") && !headers.contains_key("subject") {
+            anyhow::bail!("Not an email message, it has neither a From nor a Subject header");
+        }
+        let header = |name: &str| {
+            headers
+                .get(name)
+                .map(|value| decode_words(value))
+                .filter(|value| !value.is_empty())
+        };
+        let mut message = Message::default();
+        message.add(document)?;
+
+        let mut elements = vec![];
+        let subject = header("subject");
+        if let Some(subject) = &subject {
+            elements.push(Element::Header {
+                level: 1,
+                text: subject.clone(),
+            });
+        }
+        let mut lines = vec![];
+        for (name, label) in [
+            ("from", "From"),
+            ("to", "To"),
+            ("cc", "Cc"),
+            ("date", "Date"),
+        ] {
+            if let Some(value) = header(name) {
+                if !lines.is_empty() {
+                    lines.push(Element::LineBreak);
+                }
+                lines.push(text_element(format!("{}: {}", label, value)));
+            }
+        }
+        if !lines.is_empty() {
+            elements.push(paragraph(lines));
+        }
+
+        let body = if let Some(part) = &message.html {
+            let resources = &message.resources;
+            let image_loader =
+                image_loader_errors(data_uri_image_loader(|src: &str| {
+                    match resources.get(src) {
+                        Some(bytes) => Ok(bytes.clone()),
+                        None => image_loader(src),
+                    }
+                }));
+            Some(html::Transformer::parse_page(
+                &decode_html(&part.content, part.charset()),
+                image_loader,
+                &options.html,
+            )?)
+        } else if let Some(part) = &message.text {
+            Some(text::Transformer::parse_with_options(
+                &Bytes::from(part.text()),
+                options,
+            )?)
+        } else {
+            None
+        };
+        if let Some(body) = body {
+            elements.extend(body.get_all_elements().into_iter().cloned());
+        }
+
+        if !message.attachments.is_empty() {
+            elements.push(Element::Header {
+                level: 2,
+                text: ATTACHMENTS_TITLE.to_string(),
+            });
+            let mut files = vec![];
+            for (index, part) in message.attachments.iter().enumerate() {
+                let name = part
+                    .filename()
+                    .unwrap_or_else(|| format!("attachment{}", index + 1));
+                let mime_type = part.mime_type();
+                match ImageType::VARIANTS
+                    .iter()
+                    .find(|image_type| image_type.mime_type() == mime_type)
+                {
+                    Some(image_type) => elements.push(Element::Image(ImageData::new(
+                        part.content.clone(),
+                        name.clone(),
+                        name,
+                        image_type.to_string(),
+                        String::new(),
+                        ImageDimension::default(),
+                    ))),
+                    None => files.push(ListItem {
+                        element: text_element(name),
+                    }),
+                }
+            }
+            if !files.is_empty() {
+                elements.push(Element::List {
+                    elements: files,
+                    numbered: false,
+                    start: 1,
+                    numbering: Default::default(),
+                });
+            }
+        }
+
+        let mut document = Document::new(elements);
+        document.metadata.title = subject;
+        document.metadata.author = header("from");
+        document.metadata.date = header("date");
+        for name in ["to", "cc"] {
+            if let Some(value) = header(name) {
+                document.metadata.custom.insert(name.to_string(), value);
+            }
+        }
+        Ok(document)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use crate::core::tests::init_logger;
+    use crate::core::{Element, ParseOptions};
+    use crate::eml::Transformer;
+
+    #[test]
+    fn test_parse() -> anyhow::Result<()> {
+        init_logger();
+        let png = base64::Engine::encode(
+            &base64::engine::general_purpose::STANDARD,
+            std::fs::read("test/data/small.png")?,
+        );
+        let message = format!(
+            "From: =?UTF-8?Q?Ren=C3=A9e?= <renee@example.com>\r\n\
+             To: team@example.com\r\n\
+             Subject: =?UTF-8?B?UmVwb3J0?= for\r\n =?UTF-8?Q?M=C3=A4rz?=\r\n\
+             Date: Mon, 2 Mar 2026 10:00:00 +0100\r\n\
+             MIME-Version: 1.0\r\n\
+             Content-Type: multipart/mixed; boundary=\"mixed\"\r\n\
+             \r\n\
+             --mixed\r\n\
+             Content-Type: multipart/related; boundary=\"related\"\r\n\
+             \r\n\
+             --related\r\n\
+             Content-Type: multipart/alternative; boundary=\"alternative\"\r\n\
+             \r\n\
+             --alternative\r\n\
+             Content-Type: text/plain; charset=utf-8\r\n\
+             \r\n\
+             Sales are up.\r\n\
+             --alternative\r\n\
+             Content-Type: text/html; charset=utf-8\r\n\
+             Content-Transfer-Encoding: quoted-printable\r\n\
+             \r\n\
+             <p>Sales are <b>up</b>.</p><img src=3D\"cid:chart@example\" alt=3D\"chart\">\r\n\
+             --alternative--\r\n\
+             --related\r\n\
+             Content-Type: image/png\r\n\
+             Content-ID: <chart@example>\r\n\
+             Content-Transfer-Encoding: base64\r\n\
+             \r\n\
+             {png}\r\n\
+             --related--\r\n\
+             --mixed\r\n\
+             Content-Type: application/pdf; name=\"figures.pdf\"\r\n\
+             Content-Disposition: attachment; filename=\"figures.pdf\"\r\n\
+             Content-Transfer-Encoding: base64\r\n\
+             \r\n\
+             JVBERi0xLjQK\r\n\
+             --mixed--\r\n"
+        );
+        let document = Transformer::parse_with_loader(
+            &Bytes::from(message),
+            |src| Err(anyhow::anyhow!("{} is not loaded", src)),
+            &ParseOptions::default(),
+        )?;
+        assert_eq!(document.metadata.title.as_deref(), Some("Report for März"));
+        assert_eq!(
+            document.metadata.author.as_deref(),
+            Some("Renée <renee@example.com>")
+        );
+        assert_eq!(
+            document.metadata.custom.get("to").map(String::as_str),
+            Some("team@example.com")
+        );
+
+        let elements = document.get_all_elements();
+        assert!(matches!(
+            elements[0],
+            Element::Header { level: 1, text } if text == "Report for März"
+        ));
+        let text: String = elements
+            .iter()
+            .map(|element| element.plain_text() + "\n")
+            .collect();
+        assert!(text.contains("Date: Mon, 2 Mar 2026 10:00:00 +0100"));
+        assert!(text.contains("Sales are"));
+        // the inline image of the HTML body, not listed with the attachments
+        let images: Vec<_> = elements
+            .iter()
+            .filter_map(|element| match element {
+                Element::Image(image) => Some(image),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(images.len(), 1);
+        assert_eq!(
+            images[0].bytes().as_ref(),
+            std::fs::read("test/data/small.png")?
+        );
+        assert!(matches!(
+            elements.last(),
+            Some(Element::List { elements, .. })
+                if matches!(&elements[0].element, Element::Text { text, .. } if text == "figures.pdf")
+        ));
+        Ok(())
+    }
+}
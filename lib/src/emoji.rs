@@ -0,0 +1,267 @@
+//! GitHub emoji shortcodes (`:sparkles:`), the most common ones of the GitHub list
+//!
+//! [`expand_shortcodes`] replaces the known shortcodes of a text by their emoji and
+//! [`to_shortcodes`] does the reverse, unknown shortcodes are left as they are.
+
+/// Shortcodes and their emoji, the first shortcode of an emoji is the one written back
+const EMOJI: &[(&str, &str)] = &[
+    ("+1", "👍"),
+    ("thumbsup", "👍"),
+    ("-1", "👎"),
+    ("thumbsdown", "👎"),
+    ("100", "💯"),
+    ("alarm_clock", "⏰"),
+    ("angry", "😠"),
+    ("apple", "🍎"),
+    ("arrow_down", "⬇️"),
+    ("arrow_left", "⬅️"),
+    ("arrow_right", "➡️"),
+    ("arrow_up", "⬆️"),
+    ("art", "🎨"),
+    ("balloon", "🎈"),
+    ("bangbang", "‼️"),
+    ("beer", "🍺"),
+    ("bell", "🔔"),
+    ("bike", "🚲"),
+    ("blush", "😊"),
+    ("bomb", "💣"),
+    ("book", "📖"),
+    ("books", "📚"),
+    ("bookmark", "🔖"),
+    ("boom", "💥"),
+    ("bug", "🐛"),
+    ("bulb", "💡"),
+    ("cake", "🍰"),
+    ("calendar", "📆"),
+    ("camera", "📷"),
+    ("car", "🚗"),
+    ("cat", "🐱"),
+    ("chart_with_upwards_trend", "📈"),
+    ("chart_with_downwards_trend", "📉"),
+    ("heavy_check_mark", "✔️"),
+    ("white_check_mark", "✅"),
+    ("clap", "👏"),
+    ("clipboard", "📋"),
+    ("closed_lock_with_key", "🔐"),
+    ("cloud", "☁️"),
+    ("coffee", "☕"),
+    ("computer", "💻"),
+    ("confused", "😕"),
+    ("construction", "🚧"),
+    ("cool", "🆒"),
+    ("cry", "😢"),
+    ("dart", "🎯"),
+    ("dash", "💨"),
+    ("dog", "🐶"),
+    ("dollar", "💵"),
+    ("email", "📧"),
+    ("envelope", "✉️"),
+    ("exclamation", "❗"),
+    ("heavy_exclamation_mark", "❗"),
+    ("eyes", "👀"),
+    ("facepunch", "👊"),
+    ("punch", "👊"),
+    ("file_folder", "📁"),
+    ("fire", "🔥"),
+    ("flags", "🎏"),
+    ("floppy_disk", "💾"),
+    ("gear", "⚙️"),
+    ("gem", "💎"),
+    ("ghost", "👻"),
+    ("gift", "🎁"),
+    ("globe_with_meridians", "🌐"),
+    ("green_heart", "💚"),
+    ("grey_question", "❔"),
+    ("grin", "😁"),
+    ("grinning", "😀"),
+    ("hammer", "🔨"),
+    ("hammer_and_wrench", "🛠️"),
+    ("hand", "✋"),
+    ("raised_hand", "✋"),
+    ("heart", "❤️"),
+    ("heart_eyes", "😍"),
+    ("heavy_minus_sign", "➖"),
+    ("heavy_plus_sign", "➕"),
+    ("hourglass", "⌛"),
+    ("house", "🏠"),
+    ("hugs", "🤗"),
+    ("information_source", "ℹ️"),
+    ("joy", "😂"),
+    ("key", "🔑"),
+    ("kiss", "💋"),
+    ("label", "🏷️"),
+    ("laughing", "😆"),
+    ("satisfied", "😆"),
+    ("link", "🔗"),
+    ("lipstick", "💄"),
+    ("lock", "🔒"),
+    ("loudspeaker", "📢"),
+    ("mag", "🔍"),
+    ("mailbox", "📫"),
+    ("memo", "📝"),
+    ("pencil", "📝"),
+    ("microscope", "🔬"),
+    ("money_with_wings", "💸"),
+    ("moon", "🌔"),
+    ("muscle", "💪"),
+    ("mute", "🔇"),
+    ("new", "🆕"),
+    ("no_entry", "⛔"),
+    ("no_entry_sign", "🚫"),
+    ("ok", "🆗"),
+    ("ok_hand", "👌"),
+    ("package", "📦"),
+    ("page_facing_up", "📄"),
+    ("paperclip", "📎"),
+    ("tada", "🎉"),
+    ("penguin", "🐧"),
+    ("phone", "☎️"),
+    ("telephone", "☎️"),
+    ("pizza", "🍕"),
+    ("point_down", "👇"),
+    ("point_left", "👈"),
+    ("point_right", "👉"),
+    ("point_up", "☝️"),
+    ("pray", "🙏"),
+    ("pushpin", "📌"),
+    ("question", "❓"),
+    ("rainbow", "🌈"),
+    ("raised_hands", "🙌"),
+    ("recycle", "♻️"),
+    ("red_circle", "🔴"),
+    ("relaxed", "☺️"),
+    ("rewind", "⏪"),
+    ("robot", "🤖"),
+    ("rocket", "🚀"),
+    ("rotating_light", "🚨"),
+    ("scream", "😱"),
+    ("see_no_evil", "🙈"),
+    ("shield", "🛡️"),
+    ("skull", "💀"),
+    ("sleeping", "😴"),
+    ("slightly_smiling_face", "🙂"),
+    ("smile", "😄"),
+    ("smiley", "😃"),
+    ("smirk", "😏"),
+    ("snail", "🐌"),
+    ("snake", "🐍"),
+    ("snowflake", "❄️"),
+    ("sob", "😭"),
+    ("sos", "🆘"),
+    ("sparkles", "✨"),
+    ("speech_balloon", "💬"),
+    ("star", "⭐"),
+    ("star2", "🌟"),
+    ("stop_sign", "🛑"),
+    ("sun_with_face", "🌞"),
+    ("sunny", "☀️"),
+    ("sunglasses", "😎"),
+    ("sweat_smile", "😅"),
+    ("thinking", "🤔"),
+    ("thought_balloon", "💭"),
+    ("trophy", "🏆"),
+    ("truck", "🚚"),
+    ("umbrella", "☔"),
+    ("unlock", "🔓"),
+    ("v", "✌️"),
+    ("warning", "⚠️"),
+    ("wave", "👋"),
+    ("white_circle", "⚪"),
+    ("wink", "😉"),
+    ("wrench", "🔧"),
+    ("x", "❌"),
+    ("yellow_heart", "💛"),
+    ("yum", "😋"),
+    ("zap", "⚡"),
+    ("zzz", "💤"),
+];
+
+/// Emoji of a shortcode given without its colons, `sparkles` gives `✨`
+pub fn emoji(shortcode: &str) -> Option<&'static str> {
+    EMOJI
+        .iter()
+        .find(|(name, _)| *name == shortcode)
+        .map(|(_, emoji)| *emoji)
+}
+
+/// Shortcode of an emoji without its colons, an emoji written without its variation
+/// selector (`❤` for `❤️`) has the shortcode of the full emoji
+pub fn shortcode(emoji: &str) -> Option<&'static str> {
+    EMOJI
+        .iter()
+        .find(|(_, e)| *e == emoji || e.strip_suffix('\u{fe0f}') == Some(emoji))
+        .map(|(name, _)| *name)
+}
+
+/// Replaces the known `:shortcode:` of the text by their emoji
+pub fn expand_shortcodes(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find(':') {
+        result.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let name_length = after
+            .find(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '_' | '+' | '-')))
+            .unwrap_or(after.len());
+        match emoji(&after[..name_length]) {
+            Some(emoji) if after[name_length..].starts_with(':') => {
+                result.push_str(emoji);
+                rest = &after[name_length + 1..];
+            }
+            _ => {
+                result.push(':');
+                rest = after;
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+/// Replaces the known emoji of the text by their `:shortcode:`
+pub fn to_shortcodes(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        // the longest emoji first, so that the variation selector is part of the match
+        let found = EMOJI
+            .iter()
+            .flat_map(|(_, emoji)| [*emoji, emoji.trim_end_matches('\u{fe0f}')])
+            .filter(|emoji| rest.starts_with(emoji))
+            .max_by_key(|emoji| emoji.len());
+        match found.and_then(|emoji| Some((emoji, shortcode(emoji)?))) {
+            Some((emoji, name)) => {
+                result.push(':');
+                result.push_str(name);
+                result.push(':');
+                rest = &rest[emoji.len()..];
+            }
+            None => {
+                result.push(c);
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shortcodes() {
+        assert_eq!(
+            expand_shortcodes("Done :sparkles: :+1: at 10:30, :unknown: ::"),
+            "Done ✨ 👍 at 10:30, :unknown: ::"
+        );
+        assert_eq!(
+            to_shortcodes("Done ✨ 👍, ❤️ and ❤"),
+            "Done :sparkles: :+1:, :heart: and :heart:"
+        );
+        assert_eq!(
+            to_shortcodes(&expand_shortcodes(":tada: :warning:")),
+            ":tada: :warning:"
+        );
+    }
+}
@@ -0,0 +1,1463 @@
+//! EPUB 3, the format of e-books
+//!
+//! A book is a ZIP archive starting with its uncompressed `mimetype`. `META-INF/container.xml`
+//! points to the package document, `OEBPS/content.opf`, which lists the metadata, the files of
+//! the book (manifest) and the reading order of its XHTML chapters (spine). The navigation
+//! document, `nav.xhtml`, is the table of contents of the reading systems.
+//! The document is split into a chapter at each level 1 header. Page headers and footers
+//! are left out, e-books have no pages. A book is read back by walking its spine through
+//! the HTML parser.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write as _;
+use std::hash::{Hash, Hasher};
+use std::io::{Cursor, Read, Write};
+
+use anyhow::Context;
+use base64::engine::general_purpose;
+use base64::Engine;
+use bytes::Bytes;
+use log::warn;
+use quick_xml::escape::escape;
+use quick_xml::events::Event;
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::core::{
+    data_uri_image_loader, disk_image_loader, header_anchor, percent_decode, today, Band, Document,
+    DocumentType, Element, ImageData, ImageDimension, ImageType, Metadata, TextDirection,
+    TextStyle, TransformerTrait, TransformerWithImageLoaderSaverTrait,
+};
+use crate::html;
+
+pub struct Transformer;
+
+const MIME_TYPE: &str = "application/epub+zip";
+
+/// Folder of the package document, the chapters and the images in the archive
+const PACKAGE_FOLDER: &str = "OEBPS";
+
+const CONTAINER_XML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+<rootfiles>
+<rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+</rootfiles>
+</container>
+"#;
+
+const STYLE_CSS: &str = "body { margin: 0 5%; line-height: 1.5; }
+h1, h2, h3, h4, h5, h6 { line-height: 1.2; page-break-after: avoid; }
+h1 { page-break-before: always; }
+img { max-width: 100%; }
+figure { margin: 1em 0; text-align: center; }
+figcaption { font-size: 0.9em; font-style: italic; }
+pre { white-space: pre-wrap; font-size: 0.9em; }
+table { border-collapse: collapse; margin: 1em 0; }
+th, td { border: 1px solid #999999; padding: 0.2em 0.5em; }
+aside { font-size: 0.9em; }
+section.cover { text-align: center; }
+section.cover img { max-height: 100%; }
+";
+
+/// Language of the books without a `language` metadata entry
+const DEFAULT_LANGUAGE: &str = "en";
+
+fn chapter_file(chapter: usize) -> String {
+    format!("chapter{}.xhtml", chapter + 1)
+}
+
+/// XHTML content document with the stylesheet of the book
+fn xhtml_document(title: &str, language: &str, direction: &TextDirection, body: &str) -> String {
+    let direction = match direction {
+        TextDirection::Rtl => r#" dir="rtl""#,
+        TextDirection::Ltr => r#" dir="ltr""#,
+        TextDirection::Auto => "",
+    };
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops" xml:lang="{language}" lang="{language}"{direction}>
+<head>
+<title>{}</title>
+<link rel="stylesheet" type="text/css" href="style.css"/>
+</head>
+<body>
+{body}</body>
+</html>
+"#,
+        escape(title)
+    )
+}
+
+/// Size of an image as CSS, bare numbers being pixels
+fn image_style(size: &ImageDimension) -> String {
+    let length = |value: &str| {
+        let value = value.trim();
+        if value.parse::<f32>().is_ok() {
+            format!("{value}px")
+        } else {
+            value.to_string()
+        }
+    };
+    let mut style = vec![];
+    if let Some(width) = size
+        .width
+        .as_deref()
+        .filter(|width| !width.trim().is_empty())
+    {
+        style.push(format!("width: {}", length(width)));
+    }
+    if let Some(height) = size
+        .height
+        .as_deref()
+        .filter(|height| !height.trim().is_empty())
+    {
+        style.push(format!("height: {}", length(height)));
+    }
+    style.join("; ")
+}
+
+/// Raw HTML is written when it is well-formed XML, reading systems reject the chapters
+/// that are not
+fn is_well_formed(html: &str) -> bool {
+    let wrapped = format!("<div>{html}</div>");
+    let mut reader = quick_xml::Reader::from_str(&wrapped);
+    loop {
+        match reader.read_event() {
+            Ok(Event::Eof) => return true,
+            Ok(_) => {}
+            Err(_) => return false,
+        }
+    }
+}
+
+/// Header of the table of contents
+struct NavEntry {
+    level: u8,
+    text: String,
+    href: String,
+}
+
+/// Nested ordered lists of the entries, the entries of a deeper level after an entry being
+/// its children
+fn nav_list(entries: &[NavEntry]) -> String {
+    let mut xhtml = String::from("<ol>\n");
+    let mut index = 0;
+    while index < entries.len() {
+        let entry = &entries[index];
+        let end = entries[index + 1..]
+            .iter()
+            .position(|next| next.level <= entry.level)
+            .map_or(entries.len(), |position| index + 1 + position);
+        let _ = write!(
+            xhtml,
+            r#"<li><a href="{}">{}</a>"#,
+            escape(&entry.href),
+            escape(&entry.text)
+        );
+        if end > index + 1 {
+            xhtml.push('\n');
+            xhtml.push_str(&nav_list(&entries[index + 1..end]));
+        }
+        xhtml.push_str("</li>\n");
+        index = end;
+    }
+    xhtml.push_str("</ol>\n");
+    xhtml
+}
+
+/// Image of the book with its path in the package folder
+struct BookImage {
+    path: String,
+    media_type: String,
+    bytes: Bytes,
+}
+
+/// Writes the chapters as XHTML, collecting the images, the notes and the entries of the
+/// table of contents
+struct Writer<'a> {
+    document: &'a Document,
+    /// Chapter of each header anchor and bookmark, links to them being to the file of
+    /// their chapter
+    anchors: HashMap<String, usize>,
+    chapter: usize,
+    xhtml: String,
+    /// Ids written in the book, repeated header anchors get a number
+    ids: HashSet<String>,
+    /// Notes of the chapter, written at its end, and the number of the notes of the book
+    notes: Vec<String>,
+    note_count: usize,
+    images: Vec<BookImage>,
+    nav: Vec<NavEntry>,
+}
+
+impl<'a> Writer<'a> {
+    fn new(document: &'a Document, chapters: &[Vec<&Element>]) -> Writer<'a> {
+        fn add_anchors(element: &Element, chapter: usize, anchors: &mut HashMap<String, usize>) {
+            match element {
+                Element::Header { text, .. } => {
+                    anchors.entry(header_anchor(text)).or_insert(chapter);
+                }
+                Element::Bookmark { id, .. } => {
+                    anchors.entry(id.clone()).or_insert(chapter);
+                }
+                _ => {}
+            }
+            for child in element.children() {
+                add_anchors(child, chapter, anchors);
+            }
+        }
+
+        let mut anchors = HashMap::new();
+        for (chapter, elements) in chapters.iter().enumerate() {
+            for element in elements {
+                add_anchors(element, chapter, &mut anchors);
+            }
+        }
+        Writer {
+            document,
+            anchors,
+            chapter: 0,
+            xhtml: String::new(),
+            ids: HashSet::new(),
+            notes: vec![],
+            note_count: 0,
+            images: vec![],
+            nav: vec![],
+        }
+    }
+
+    /// Body of a chapter, its notes after its content
+    fn chapter(&mut self, chapter: usize, elements: &[&Element]) -> String {
+        self.chapter = chapter;
+        for element in elements {
+            self.block(element);
+        }
+        if !self.notes.is_empty() {
+            self.xhtml
+                .push_str("<section class=\"notes\" epub:type=\"footnotes\">\n");
+            for note in std::mem::take(&mut self.notes) {
+                self.xhtml.push_str(&note);
+            }
+            self.xhtml.push_str("</section>\n");
+        }
+        std::mem::take(&mut self.xhtml)
+    }
+
+    /// Unique id of an anchor, numbered after its first use
+    fn unique_id(&mut self, anchor: &str) -> String {
+        let mut id = anchor.to_string();
+        let mut number = 1;
+        while self.ids.contains(&id) {
+            id = format!("{anchor}-{number}");
+            number += 1;
+        }
+        self.ids.insert(id.clone());
+        id
+    }
+
+    fn block(&mut self, element: &Element) {
+        match element {
+            Element::Header { level, text } => {
+                let level = (*level).clamp(1, 6);
+                let id = self.unique_id(&header_anchor(text));
+                self.nav.push(NavEntry {
+                    level,
+                    text: text.clone(),
+                    href: format!("{}#{}", chapter_file(self.chapter), id),
+                });
+                let _ = writeln!(
+                    self.xhtml,
+                    r#"<h{level} id="{}">{}</h{level}>"#,
+                    escape(&id),
+                    escape(text)
+                );
+            }
+            Element::Paragraph {
+                elements,
+                direction,
+            } => {
+                let direction = match direction {
+                    TextDirection::Rtl => r#" dir="rtl""#,
+                    TextDirection::Ltr => r#" dir="ltr""#,
+                    TextDirection::Auto => "",
+                };
+                let _ = write!(self.xhtml, "<p{direction}>");
+                for element in elements {
+                    self.inline(element);
+                }
+                self.xhtml.push_str("</p>\n");
+            }
+            Element::List { .. } => self.list(element),
+            Element::Table { headers, rows } => {
+                self.xhtml.push_str("<table>\n");
+                if !headers.is_empty() {
+                    self.xhtml.push_str("<thead>\n<tr>");
+                    for header in headers {
+                        self.xhtml.push_str("<th>");
+                        self.item(&header.element);
+                        self.xhtml.push_str("</th>");
+                    }
+                    self.xhtml.push_str("</tr>\n</thead>\n");
+                }
+                if !rows.is_empty() {
+                    self.xhtml.push_str("<tbody>\n");
+                    for row in rows {
+                        self.xhtml.push_str("<tr>");
+                        for cell in &row.cells {
+                            self.xhtml.push_str("<td>");
+                            self.item(&cell.element);
+                            self.xhtml.push_str("</td>");
+                        }
+                        self.xhtml.push_str("</tr>\n");
+                    }
+                    self.xhtml.push_str("</tbody>\n");
+                }
+                self.xhtml.push_str("</table>\n");
+            }
+            Element::TableOfContents { max_level } => {
+                let toc = self.document.build_toc(*max_level);
+                self.xhtml.push_str("<nav class=\"toc\">\n");
+                self.list(&toc);
+                self.xhtml.push_str("</nav>\n");
+            }
+            Element::CodeBlock { code, .. } => {
+                let class = element
+                    .code_language()
+                    .map(|language| format!(r#" class="language-{}""#, escape(language)))
+                    .unwrap_or_default();
+                let _ = writeln!(
+                    self.xhtml,
+                    "<pre><code{class}>{}</code></pre>",
+                    escape(code.strip_suffix('\n').unwrap_or(code))
+                );
+            }
+            Element::Image(image) if !image.title().is_empty() => {
+                self.xhtml.push_str("<figure>\n");
+                self.image(image);
+                let _ = writeln!(
+                    self.xhtml,
+                    "\n<figcaption>{}</figcaption>\n</figure>",
+                    escape(image.title())
+                );
+            }
+            Element::Image(_) | Element::Bookmark { .. } | Element::Raw { .. } => {
+                self.inline(element);
+                self.xhtml.push('\n');
+            }
+            Element::Text { .. } | Element::Hyperlink { .. } | Element::InlineCode { .. } => {
+                self.xhtml.push_str("<p>");
+                self.inline(element);
+                self.xhtml.push_str("</p>\n");
+            }
+            element => {
+                let mut inline = String::new();
+                std::mem::swap(&mut self.xhtml, &mut inline);
+                self.inline(element);
+                std::mem::swap(&mut self.xhtml, &mut inline);
+                if !inline.is_empty() {
+                    let _ = writeln!(self.xhtml, "<p>{inline}</p>");
+                }
+            }
+        }
+    }
+
+    fn inline(&mut self, element: &Element) {
+        match element {
+            Element::Text { text, .. } => self.xhtml.push_str(&escape(text)),
+            Element::LineBreak => self.xhtml.push_str("<br/>"),
+            Element::InlineCode { text } => {
+                let _ = write!(self.xhtml, "<code>{}</code>", escape(text));
+            }
+            Element::Styled { style, elements } => {
+                let tag = match style {
+                    TextStyle::Emphasis => "em",
+                    TextStyle::Strong => "strong",
+                    TextStyle::Strikethrough => "del",
+                };
+                let _ = write!(self.xhtml, "<{tag}>");
+                for element in elements {
+                    self.inline(element);
+                }
+                let _ = write!(self.xhtml, "</{tag}>");
+            }
+            Element::Hyperlink {
+                elements, url, alt, ..
+            } => {
+                // anchors of the other chapters are in their files
+                let href = match url
+                    .strip_prefix('#')
+                    .map(|anchor| (anchor, self.anchors.get(anchor)))
+                {
+                    Some((anchor, Some(&chapter))) if chapter != self.chapter => {
+                        format!("{}#{}", chapter_file(chapter), anchor)
+                    }
+                    _ => url.clone(),
+                };
+                let _ = write!(self.xhtml, r#"<a href="{}""#, escape(&href));
+                if !alt.is_empty() {
+                    let _ = write!(self.xhtml, r#" title="{}""#, escape(alt));
+                }
+                self.xhtml.push('>');
+                if elements.is_empty() {
+                    self.xhtml.push_str(&escape(url));
+                }
+                for element in elements {
+                    self.inline(element);
+                }
+                self.xhtml.push_str("</a>");
+            }
+            Element::Image(image) => self.image(image),
+            Element::Footnote { elements } => self.footnote(elements),
+            Element::Bookmark { id, .. } => {
+                if !self.ids.contains(id) {
+                    self.ids.insert(id.clone());
+                    let _ = write!(self.xhtml, r#"<a id="{}"></a>"#, escape(id));
+                }
+            }
+            Element::Raw {
+                format: DocumentType::HTML,
+                content,
+            } => {
+                if is_well_formed(content) {
+                    self.xhtml.push_str(content);
+                } else {
+                    warn!("Raw HTML left out, it is not well-formed XHTML");
+                }
+            }
+            // page numbers are not known in reflowable books
+            Element::Field { .. } | Element::Raw { .. } => {}
+            Element::Paragraph { elements, .. } => {
+                for element in elements {
+                    self.inline(element);
+                }
+            }
+            element => self.xhtml.push_str(&escape(&element.plain_text())),
+        }
+    }
+
+    /// Content of a list item or a table cell, the elements of a paragraph without the
+    /// paragraph
+    fn item(&mut self, element: &Element) {
+        match element {
+            Element::List { .. }
+            | Element::Table { .. }
+            | Element::CodeBlock { .. }
+            | Element::Header { .. } => {
+                self.xhtml.push('\n');
+                self.block(element);
+            }
+            element => self.inline(element),
+        }
+    }
+
+    /// List with its nested lists inside the items they follow
+    fn list(&mut self, list: &Element) {
+        let Element::List {
+            elements,
+            numbered,
+            start,
+            numbering,
+        } = list
+        else {
+            return;
+        };
+        let tag = if *numbered { "ol" } else { "ul" };
+        let mut attributes = String::new();
+        if *numbered && *start != 1 {
+            let _ = write!(attributes, r#" start="{start}""#);
+        }
+        if *numbered && *numbering != crate::core::NumberingStyle::Decimal {
+            let marker = numbering.format(1);
+            let _ = write!(attributes, r#" type="{}""#, marker.trim_end_matches('.'));
+        }
+        let _ = writeln!(self.xhtml, "<{tag}{attributes}>");
+        let mut open = false;
+        for item in elements {
+            if let nested @ Element::List { .. } = &item.element {
+                if !open {
+                    self.xhtml.push_str("<li>");
+                }
+                self.xhtml.push('\n');
+                self.list(nested);
+                self.xhtml.push_str("</li>\n");
+                open = false;
+                continue;
+            }
+            if open {
+                self.xhtml.push_str("</li>\n");
+            }
+            self.xhtml.push_str("<li>");
+            self.item(&item.element);
+            open = true;
+        }
+        if open {
+            self.xhtml.push_str("</li>\n");
+        }
+        let _ = writeln!(self.xhtml, "</{tag}>");
+    }
+
+    /// Reference to a note, the note being written at the end of the chapter
+    fn footnote(&mut self, elements: &[Element]) {
+        self.note_count += 1;
+        let number = self.note_count;
+        let _ = write!(
+            self.xhtml,
+            r##"<sup><a epub:type="noteref" id="noteref-{number}" href="#note-{number}">{number}</a></sup>"##
+        );
+        let mut content = String::new();
+        std::mem::swap(&mut self.xhtml, &mut content);
+        let _ = write!(
+            self.xhtml,
+            r##"<aside epub:type="footnote" id="note-{number}">
+<p><a href="#noteref-{number}">{number}.</a> "##
+        );
+        let blocks = elements.iter().position(|element| {
+            matches!(
+                element,
+                Element::Paragraph { .. }
+                    | Element::List { .. }
+                    | Element::Table { .. }
+                    | Element::CodeBlock { .. }
+            )
+        });
+        let (inline, blocks) = elements.split_at(blocks.unwrap_or(elements.len()));
+        for element in inline {
+            self.inline(element);
+        }
+        self.xhtml.push_str("</p>\n");
+        for element in blocks {
+            self.block(element);
+        }
+        self.xhtml.push_str("</aside>\n");
+        std::mem::swap(&mut self.xhtml, &mut content);
+        self.notes.push(content);
+    }
+
+    fn image(&mut self, image: &ImageData) {
+        let Some(path) = self.add_image(image, "image") else {
+            return;
+        };
+        let _ = write!(
+            self.xhtml,
+            r#"<img src="{}" alt="{}""#,
+            escape(&path),
+            escape(image.alt())
+        );
+        if !image.title().is_empty() {
+            let _ = write!(self.xhtml, r#" title="{}""#, escape(image.title()));
+        }
+        let style = image_style(image.size());
+        if !style.is_empty() {
+            let _ = write!(self.xhtml, r#" style="{}""#, escape(&style));
+        }
+        self.xhtml.push_str("/>");
+    }
+
+    /// Adds an image to the book, once for the same content, and gives its path. Images
+    /// of the types that reading systems may not show are converted to PNG.
+    fn add_image(&mut self, image: &ImageData, name: &str) -> Option<String> {
+        if image.bytes().is_empty() {
+            warn!("Image without content left out: {}", image.title());
+            return None;
+        }
+        if let Some(existing) = self
+            .images
+            .iter()
+            .find(|existing| existing.bytes == image.bytes())
+        {
+            return Some(existing.path.clone());
+        }
+        let (image_type, bytes) = match image.image_type() {
+            ImageType::Bmp | ImageType::Tiff => {
+                let converted = image::load_from_memory(image.bytes()).and_then(|decoded| {
+                    let mut png = Cursor::new(Vec::new());
+                    decoded.write_to(&mut png, image::ImageFormat::Png)?;
+                    Ok(png.into_inner())
+                });
+                match converted {
+                    Ok(png) => (ImageType::Png, Bytes::from(png)),
+                    Err(error) => {
+                        warn!("Cannot convert the image {}: {}", image.title(), error);
+                        return None;
+                    }
+                }
+            }
+            image_type => (image_type.clone(), image.bytes().clone()),
+        };
+        let path = format!(
+            "images/{name}{}{}",
+            self.images.len() + 1,
+            image_type.to_extension()
+        );
+        self.images.push(BookImage {
+            path: path.clone(),
+            media_type: image_type.mime_type().to_string(),
+            bytes,
+        });
+        Some(path)
+    }
+}
+
+/// Chapters of the elements, a chapter starting at each level 1 header
+fn chapters(elements: Vec<&Element>) -> Vec<Vec<&Element>> {
+    let mut chapters: Vec<Vec<&Element>> = vec![];
+    for element in elements {
+        match chapters.last_mut() {
+            Some(chapter) if !matches!(element, Element::Header { level: 1, .. }) => {
+                chapter.push(element)
+            }
+            _ => chapters.push(vec![element]),
+        }
+    }
+    if chapters.is_empty() {
+        chapters.push(vec![]);
+    }
+    chapters
+}
+
+/// Identifier of the book, its `identifier` or `isbn` metadata entry or else a UUID derived
+/// from its content, so that generating the same document gives the same book
+fn identifier(document: &Document, chapters: &[String]) -> String {
+    let custom = &document.metadata.custom;
+    if let Some(identifier) = custom.get("identifier").or_else(|| custom.get("isbn")) {
+        return identifier.clone();
+    }
+    let hash = |seed: u64| {
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        seed.hash(&mut hasher);
+        document.metadata.title.hash(&mut hasher);
+        document.metadata.author.hash(&mut hasher);
+        chapters.hash(&mut hasher);
+        hasher.finish()
+    };
+    let (high, low) = (hash(0), hash(1));
+    // version 4 and variant bits of a random UUID
+    format!(
+        "urn:uuid:{:08x}-{:04x}-4{:03x}-{:04x}-{:012x}",
+        high >> 32,
+        (high >> 16) & 0xffff,
+        high & 0xfff,
+        ((low >> 48) & 0x3fff) | 0x8000,
+        low & 0xffff_ffff_ffff
+    )
+}
+
+/// Package document: the metadata, the files and the reading order of the book
+fn package_opf(
+    document: &Document,
+    title: &str,
+    language: &str,
+    identifier: &str,
+    chapters: usize,
+    cover: Option<&BookImage>,
+    images: &[BookImage],
+) -> String {
+    let metadata = &document.metadata;
+    let mut opf = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="3.0" unique-identifier="book-id" xml:lang="{language}">
+<metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+<dc:identifier id="book-id">{}</dc:identifier>
+<dc:title>{}</dc:title>
+<dc:language>{language}</dc:language>
+"#,
+        escape(identifier),
+        escape(title)
+    );
+    if let Some(author) = &metadata.author {
+        let _ = writeln!(opf, "<dc:creator>{}</dc:creator>", escape(author));
+    }
+    if let Some(description) = &metadata.description {
+        let _ = writeln!(
+            opf,
+            "<dc:description>{}</dc:description>",
+            escape(description)
+        );
+    }
+    for keyword in &metadata.keywords {
+        let _ = writeln!(opf, "<dc:subject>{}</dc:subject>", escape(keyword));
+    }
+    // the modification date is required, as a UTC date and time
+    let date = metadata
+        .date
+        .as_deref()
+        .and_then(|date| date.get(..10))
+        .filter(|date| {
+            date.split('-')
+                .map(|part| part.parse::<u16>().is_ok())
+                .eq([true, true, true])
+        });
+    if let Some(date) = date {
+        let _ = writeln!(opf, "<dc:date>{date}</dc:date>");
+    }
+    let _ = writeln!(
+        opf,
+        r#"<meta property="dcterms:modified">{}T00:00:00Z</meta>"#,
+        date.map_or_else(today, str::to_string)
+    );
+    if cover.is_some() {
+        // for the EPUB 2 reading systems
+        opf.push_str("<meta name=\"cover\" content=\"cover-image\"/>\n");
+    }
+    opf.push_str("</metadata>\n<manifest>\n");
+    opf.push_str(concat!(
+        r#"<item id="nav" href="nav.xhtml" media-type="application/xhtml+xml" properties="nav"/>"#,
+        "\n",
+        r#"<item id="style" href="style.css" media-type="text/css"/>"#,
+        "\n"
+    ));
+    if let Some(cover) = cover {
+        let _ = writeln!(
+            opf,
+            r#"<item id="cover" href="cover.xhtml" media-type="application/xhtml+xml"/>
+<item id="cover-image" href="{}" media-type="{}" properties="cover-image"/>"#,
+            escape(&cover.path),
+            cover.media_type
+        );
+    }
+    for chapter in 0..chapters {
+        let _ = writeln!(
+            opf,
+            r#"<item id="chapter{}" href="{}" media-type="application/xhtml+xml"/>"#,
+            chapter + 1,
+            chapter_file(chapter)
+        );
+    }
+    for (index, image) in images.iter().enumerate() {
+        let _ = writeln!(
+            opf,
+            r#"<item id="image{}" href="{}" media-type="{}"/>"#,
+            index + 1,
+            escape(&image.path),
+            image.media_type
+        );
+    }
+    opf.push_str("</manifest>\n");
+    let direction = match document.direction {
+        TextDirection::Rtl => r#" page-progression-direction="rtl""#,
+        _ => "",
+    };
+    let _ = writeln!(opf, "<spine{direction}>");
+    if cover.is_some() {
+        opf.push_str("<itemref idref=\"cover\"/>\n");
+    }
+    for chapter in 0..chapters {
+        let _ = writeln!(opf, r#"<itemref idref="chapter{}"/>"#, chapter + 1);
+    }
+    opf.push_str("</spine>\n</package>\n");
+    opf
+}
+
+/// Item of the manifest of a book being read
+struct ManifestItem {
+    /// Path in the archive
+    path: String,
+    media_type: String,
+    properties: String,
+}
+
+/// Package document of a book being read
+#[derive(Default)]
+struct Package {
+    metadata: Metadata,
+    /// Items of the manifest by id
+    manifest: HashMap<String, ManifestItem>,
+    /// Ids of the items in reading order
+    spine: Vec<String>,
+    direction: TextDirection,
+    /// Id of the cover image of the EPUB 2 books
+    cover: Option<String>,
+}
+
+/// Folder of a path of the archive, empty at its root
+fn folder(path: &str) -> &str {
+    path.rsplit_once('/').map_or("", |(folder, _)| folder)
+}
+
+/// Path in the archive of a link relative to `folder`, without its fragment
+fn archive_path(folder: &str, href: &str) -> String {
+    let href = href.split(['#', '?']).next().unwrap_or_default();
+    let href = percent_decode(href)
+        .map(|bytes| String::from_utf8_lossy(&bytes).into_owned())
+        .unwrap_or_else(|_| href.to_string());
+    let mut segments: Vec<&str> = if href.starts_with('/') {
+        vec![]
+    } else {
+        folder
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .collect()
+    };
+    for segment in href.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            _ => segments.push(segment),
+        }
+    }
+    segments.join("/")
+}
+
+/// Path of the package document named by `META-INF/container.xml`, else of the first one
+/// of the archive
+fn package_path(files: &HashMap<String, Bytes>) -> anyhow::Result<String> {
+    if let Some(container) = files.get("META-INF/container.xml") {
+        let container = String::from_utf8_lossy(container);
+        let mut reader = quick_xml::Reader::from_str(&container);
+        loop {
+            match reader.read_event()? {
+                Event::Start(start) | Event::Empty(start)
+                    if start.local_name().as_ref() == b"rootfile" =>
+                {
+                    if let Some(path) = start.try_get_attribute("full-path")? {
+                        return Ok(path.decode_and_unescape_value(&reader)?.into_owned());
+                    }
+                }
+                Event::Eof => break,
+                _ => {}
+            }
+        }
+    }
+    files
+        .keys()
+        .filter(|name| name.ends_with(".opf"))
+        .min()
+        .cloned()
+        .context("Not an EPUB book: no package document")
+}
+
+/// Metadata, manifest and spine of the package document at `path`. The Dublin Core entries
+/// without a field of [`Metadata`] are kept as custom entries, the creators are joined.
+fn parse_package(xml: &str, path: &str) -> anyhow::Result<Package> {
+    let mut reader = quick_xml::Reader::from_str(xml);
+    let mut package = Package::default();
+    let mut authors = vec![];
+    let mut text = String::new();
+    loop {
+        match reader.read_event()? {
+            Event::Start(start) | Event::Empty(start) => {
+                text.clear();
+                let attribute = |name: &str| -> anyhow::Result<String> {
+                    Ok(match start.try_get_attribute(name)? {
+                        Some(attribute) => {
+                            attribute.decode_and_unescape_value(&reader)?.into_owned()
+                        }
+                        None => String::new(),
+                    })
+                };
+                match start.local_name().as_ref() {
+                    b"item" => {
+                        package.manifest.insert(
+                            attribute("id")?,
+                            ManifestItem {
+                                path: archive_path(folder(path), &attribute("href")?),
+                                media_type: attribute("media-type")?,
+                                properties: attribute("properties")?,
+                            },
+                        );
+                    }
+                    b"itemref" if attribute("linear")? != "no" => {
+                        package.spine.push(attribute("idref")?)
+                    }
+                    b"spine" if attribute("page-progression-direction")? == "rtl" => {
+                        package.direction = TextDirection::Rtl
+                    }
+                    b"meta" if attribute("name")? == "cover" => {
+                        package.cover = Some(attribute("content")?)
+                    }
+                    _ => {}
+                }
+            }
+            Event::Text(content) => text.push_str(&content.unescape()?),
+            Event::CData(data) => text.push_str(&String::from_utf8_lossy(&data)),
+            Event::End(end) => {
+                let value = std::mem::take(&mut text).trim().to_string();
+                if value.is_empty() {
+                    continue;
+                }
+                let metadata = &mut package.metadata;
+                match end.local_name().as_ref() {
+                    b"title" if metadata.title.is_none() => metadata.title = Some(value),
+                    b"creator" => authors.push(value),
+                    b"date" if metadata.date.is_none() => metadata.date = Some(value),
+                    b"description" if metadata.description.is_none() => {
+                        metadata.description = Some(value)
+                    }
+                    b"subject" => metadata.keywords.push(value),
+                    name @ (b"language" | b"identifier" | b"publisher" | b"rights") => {
+                        metadata
+                            .custom
+                            .entry(String::from_utf8_lossy(name).into_owned())
+                            .or_insert(value);
+                    }
+                    _ => {}
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    if !authors.is_empty() {
+        package.metadata.author = Some(authors.join(", "));
+    }
+    Ok(package)
+}
+
+/// Chapter without its `<head>`, whose title would be read as a paragraph
+fn without_head(xhtml: &str) -> String {
+    let start = xhtml
+        .match_indices("<head")
+        .map(|(index, _)| index)
+        .find(|index| {
+            xhtml[index + "<head".len()..].starts_with(|c: char| c == '>' || c.is_whitespace())
+        });
+    match (start, xhtml.find("</head>")) {
+        (Some(start), Some(end)) if start < end => {
+            format!("{}{}", &xhtml[..start], &xhtml[end + "</head>".len()..])
+        }
+        _ => xhtml.to_string(),
+    }
+}
+
+/// Whether the chapter only shows the cover image, in containers or not
+fn is_cover_page(elements: &[&Element], cover: &Bytes) -> bool {
+    match elements {
+        [Element::Image(image)] => image.bytes() == cover,
+        [element] => is_cover_page(&element.children(), cover),
+        _ => false,
+    }
+}
+
+/// Links to a chapter of the book become links to its anchors: the fragment of the link,
+/// else the first header of the chapter
+fn resolve_chapter_link(
+    element: &mut Element,
+    folder: &str,
+    chapters: &HashMap<String, Option<String>>,
+) {
+    if let Element::Hyperlink { url, .. } = element {
+        if !url.starts_with('#') && !url.contains(':') {
+            if let Some(first_header) = chapters.get(&archive_path(folder, url)) {
+                match url.split_once('#') {
+                    Some((_, fragment)) => *url = format!("#{fragment}"),
+                    None => {
+                        if let Some(anchor) = first_header {
+                            *url = format!("#{anchor}");
+                        }
+                    }
+                }
+            }
+        }
+    }
+    for child in element.children_mut() {
+        resolve_chapter_link(child, folder, chapters);
+    }
+}
+
+impl TransformerTrait for Transformer {
+    fn parse(document: &Bytes) -> crate::Result<Document> {
+        Transformer::parse_bytes(document).map_err(crate::Error::parse_error)
+    }
+
+    /// EPUB book of the document, its cover being the image of the `cover` metadata entry
+    /// loaded from the current directory
+    fn generate(document: &Document) -> crate::Result<Bytes> {
+        Transformer::generate_with_loader(document, disk_image_loader("."))
+    }
+}
+
+impl Transformer {
+    /// EPUB book of the document, the image of its `cover` metadata entry, a path or a
+    /// `data:` URI, loaded with `image_loader`
+    pub fn generate_with_loader<F>(document: &Document, image_loader: F) -> crate::Result<Bytes>
+    where
+        F: Fn(&str) -> anyhow::Result<Bytes>,
+    {
+        Transformer::generate_bytes(document, data_uri_image_loader(image_loader))
+            .map_err(crate::Error::generation_error)
+    }
+
+    /// Chapters of the spine read by the HTML parser one after the other, with the images of
+    /// the archive. The cover page is left out, its image being the `cover` metadata entry.
+    fn parse_bytes(document: &Bytes) -> anyhow::Result<Document> {
+        let mut archive = ZipArchive::new(Cursor::new(document))?;
+        let mut files = HashMap::new();
+        for index in 0..archive.len() {
+            let mut file = archive.by_index(index)?;
+            if file.is_dir() {
+                continue;
+            }
+            let mut bytes = Vec::with_capacity(file.size() as usize);
+            file.read_to_end(&mut bytes)?;
+            files.insert(file.name().to_string(), Bytes::from(bytes));
+        }
+        let path = package_path(&files)?;
+        let opf = files
+            .get(&path)
+            .with_context(|| format!("No package document {path} in the book"))?;
+        let package = parse_package(&String::from_utf8_lossy(opf), &path)?;
+
+        let mut metadata = package.metadata;
+        let cover = package
+            .manifest
+            .iter()
+            .find(|(id, item)| {
+                item.properties
+                    .split_whitespace()
+                    .any(|property| property == "cover-image")
+                    || package.cover.as_ref() == Some(*id)
+            })
+            .map(|(_, item)| item);
+        if let Some(cover) = cover {
+            if let Some(bytes) = files.get(&cover.path) {
+                metadata.custom.insert(
+                    "cover".to_string(),
+                    format!(
+                        "data:{};base64,{}",
+                        cover.media_type,
+                        general_purpose::STANDARD.encode(bytes)
+                    ),
+                );
+            }
+        }
+
+        let mut chapters: Vec<(&str, Vec<Element>)> = vec![];
+        for item in package
+            .spine
+            .iter()
+            .filter_map(|id| package.manifest.get(id))
+        {
+            let is_html = matches!(
+                item.media_type.as_str(),
+                "application/xhtml+xml" | "text/html"
+            );
+            if !is_html || item.properties.split_whitespace().any(|p| p == "nav") {
+                continue;
+            }
+            let Some(xhtml) = files.get(&item.path) else {
+                warn!("No chapter {} in the book", item.path);
+                continue;
+            };
+            let image_loader = |src: &str| {
+                let path = archive_path(folder(&item.path), src);
+                files
+                    .get(&path)
+                    .cloned()
+                    .with_context(|| format!("No image {path} in the book"))
+            };
+            let xhtml = without_head(&String::from_utf8_lossy(xhtml));
+            let mut page = html::Transformer::parse_with_loader(&Bytes::from(xhtml), image_loader)?;
+            let elements: Vec<Element> = page
+                .bands
+                .iter_mut()
+                .flat_map(|band| std::mem::take(band.elements_mut()))
+                .collect();
+            let page: Vec<&Element> = elements.iter().collect();
+            let cover_bytes = cover.and_then(|cover| files.get(&cover.path));
+            if !cover_bytes.is_some_and(|cover| is_cover_page(&page, cover)) {
+                chapters.push((&item.path, elements));
+            }
+        }
+
+        let first_headers: HashMap<String, Option<String>> = chapters
+            .iter()
+            .map(|(path, elements)| {
+                let first_header = match elements.first() {
+                    Some(Element::Attributed { element, .. }) => match element.as_ref() {
+                        Element::Header { text, .. } => Some(header_anchor(text)),
+                        _ => None,
+                    },
+                    Some(Element::Header { text, .. }) => Some(header_anchor(text)),
+                    _ => None,
+                };
+                (path.to_string(), first_header)
+            })
+            .collect();
+        let mut elements = vec![];
+        for (path, mut chapter) in chapters {
+            for element in chapter.iter_mut() {
+                resolve_chapter_link(element, folder(path), &first_headers);
+            }
+            elements.extend(chapter);
+        }
+        let mut document = Document::new(elements);
+        document.metadata = metadata;
+        document.direction = package.direction;
+        Ok(document)
+    }
+
+    fn generate_bytes<F>(document: &Document, image_loader: F) -> anyhow::Result<Bytes>
+    where
+        F: Fn(&str) -> anyhow::Result<Bytes>,
+    {
+        let document = &document
+            .resolve_fields()
+            .without_containers()
+            .without_media()
+            .without_math()
+            .without_drawings()
+            .without_attributes();
+        let elements: Vec<&Element> = document
+            .bands
+            .iter()
+            .filter(|band| !matches!(band, Band::PageHeader(_) | Band::PageFooter(_)))
+            .flat_map(|band| band.elements())
+            .collect();
+        let chapters = chapters(elements);
+        let custom = &document.metadata.custom;
+        let language = custom
+            .get("language")
+            .or_else(|| custom.get("lang"))
+            .map_or(DEFAULT_LANGUAGE.to_string(), |language| {
+                escape(language.trim()).into_owned()
+            });
+        let title = document
+            .metadata
+            .title
+            .clone()
+            .unwrap_or_else(|| document.get_title_text());
+        let title = if title.trim().is_empty() {
+            "Untitled".to_string()
+        } else {
+            title
+        };
+
+        let mut writer = Writer::new(document, &chapters);
+        let cover = custom.get("cover").and_then(|source| {
+            let image = image_loader(source)
+                .map_err(|error| warn!("Cannot load the cover {}: {}", source, error))
+                .ok()?;
+            let image = ImageData::new(
+                image,
+                String::new(),
+                String::new(),
+                source.clone(),
+                String::new(),
+                ImageDimension::default(),
+            );
+            writer.add_image(&image, "cover")?;
+            writer.images.pop()
+        });
+        let bodies: Vec<String> = chapters
+            .iter()
+            .enumerate()
+            .map(|(chapter, elements)| writer.chapter(chapter, elements))
+            .collect();
+        if writer.nav.is_empty() {
+            writer.nav.push(NavEntry {
+                level: 1,
+                text: title.clone(),
+                href: chapter_file(0),
+            });
+        }
+
+        let mut nav = format!(
+            "<nav epub:type=\"toc\" id=\"toc\">\n<h1>{}</h1>\n{}</nav>\n",
+            escape(&title),
+            nav_list(&writer.nav)
+        );
+        nav.push_str("<nav epub:type=\"landmarks\" hidden=\"hidden\">\n<ol>\n");
+        if cover.is_some() {
+            nav.push_str("<li><a epub:type=\"cover\" href=\"cover.xhtml\">Cover</a></li>\n");
+        }
+        let _ = write!(
+            nav,
+            "<li><a epub:type=\"bodymatter\" href=\"{}\">{}</a></li>\n</ol>\n</nav>\n",
+            chapter_file(0),
+            escape(&title)
+        );
+
+        let identifier = identifier(document, &bodies);
+        let opf = package_opf(
+            document,
+            &title,
+            &language,
+            &identifier,
+            chapters.len(),
+            cover.as_ref(),
+            &writer.images,
+        );
+
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        // the media type is the first file, uncompressed, for the readers that look for it
+        zip.start_file(
+            "mimetype",
+            FileOptions::default().compression_method(CompressionMethod::Stored),
+        )?;
+        zip.write_all(MIME_TYPE.as_bytes())?;
+        let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+        zip.start_file("META-INF/container.xml", options)?;
+        zip.write_all(CONTAINER_XML.as_bytes())?;
+        let mut files = vec![
+            ("content.opf".to_string(), Bytes::from(opf)),
+            ("style.css".to_string(), Bytes::from(STYLE_CSS)),
+            (
+                "nav.xhtml".to_string(),
+                Bytes::from(xhtml_document(&title, &language, &document.direction, &nav)),
+            ),
+        ];
+        if let Some(cover) = &cover {
+            let body = format!(
+                "<section class=\"cover\" epub:type=\"cover\">\n<img src=\"{}\" alt=\"{}\"/>\n</section>\n",
+                escape(&cover.path),
+                escape(&title)
+            );
+            files.push((
+                "cover.xhtml".to_string(),
+                Bytes::from(xhtml_document(
+                    &title,
+                    &language,
+                    &document.direction,
+                    &body,
+                )),
+            ));
+            files.push((cover.path.clone(), cover.bytes.clone()));
+        }
+        for (chapter, (body, elements)) in bodies.iter().zip(&chapters).enumerate() {
+            let chapter_title = match elements.first() {
+                Some(Element::Header { level: 1, text }) => text.as_str(),
+                _ => &title,
+            };
+            files.push((
+                chapter_file(chapter),
+                Bytes::from(xhtml_document(
+                    chapter_title,
+                    &language,
+                    &document.direction,
+                    body,
+                )),
+            ));
+        }
+        for image in &writer.images {
+            files.push((image.path.clone(), image.bytes.clone()));
+        }
+        for (name, bytes) in files {
+            zip.start_file(format!("{PACKAGE_FOLDER}/{name}"), options)?;
+            zip.write_all(&bytes)?;
+        }
+        Ok(Bytes::from(zip.finish()?.into_inner()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::tests::init_logger;
+    use crate::markdown;
+
+    fn book_files(book: &Bytes) -> anyhow::Result<Vec<(String, String)>> {
+        let mut archive = ZipArchive::new(Cursor::new(book.clone()))?;
+        let mut files = vec![];
+        for index in 0..archive.len() {
+            let mut file = archive.by_index(index)?;
+            let mut content = vec![];
+            file.read_to_end(&mut content)?;
+            files.push((
+                file.name().to_string(),
+                String::from_utf8_lossy(&content).into_owned(),
+            ));
+        }
+        Ok(files)
+    }
+
+    #[test]
+    fn test_generate() -> anyhow::Result<()> {
+        init_logger();
+        let markdown = "Foreword of the book.\n\n\
+            # First chapter\n\nSee [the end](#the-end).[^1]\n\n## Part\n\n\
+            - One\n  - Nested\n- Two\n\n\
+            | Name | Value |\n|------|-------|\n| a & b | <1> |\n\n\
+            # Second chapter\n\n## The end\n\nDone.\n\n\
+            [^1]: A note.\n";
+        let mut document = markdown::Transformer::parse(&Bytes::from(markdown))?;
+        let png = Bytes::from(std::fs::read("test/data/small.png")?);
+        let image = Element::Image(ImageData::new(
+            png.clone(),
+            "Logo".to_string(),
+            "A small picture".to_string(),
+            "png".to_string(),
+            String::new(),
+            ImageDimension {
+                width: Some("40".to_string()),
+                height: None,
+            },
+        ));
+        if let Some(Band::Detail(elements)) = document.bands.last_mut() {
+            elements.push(image.clone());
+            elements.push(image);
+        }
+        document.metadata.title = Some("The Book".to_string());
+        document.metadata.author = Some("Ada".to_string());
+        document.metadata.date = Some("2024-05-01".to_string());
+
+        let book = Transformer::generate(&document)?;
+        let files = book_files(&book)?;
+        assert_eq!(files[0], ("mimetype".to_string(), MIME_TYPE.to_string()));
+        let names: Vec<&str> = files.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "mimetype",
+                "META-INF/container.xml",
+                "OEBPS/content.opf",
+                "OEBPS/style.css",
+                "OEBPS/nav.xhtml",
+                "OEBPS/chapter1.xhtml",
+                "OEBPS/chapter2.xhtml",
+                "OEBPS/chapter3.xhtml",
+                "OEBPS/images/image1.png",
+            ]
+        );
+        for (name, content) in &files {
+            if name.ends_with(".xhtml") || name.ends_with(".opf") || name.ends_with(".xml") {
+                assert!(is_well_formed(content), "{name} is not well-formed");
+            }
+        }
+        let file = |name: &str| {
+            files
+                .iter()
+                .find(|(file, _)| file == name)
+                .map(|(_, content)| content.as_str())
+                .unwrap_or_default()
+        };
+
+        let opf = file("OEBPS/content.opf");
+        assert!(opf.contains("<dc:title>The Book</dc:title>"));
+        assert!(opf.contains("<dc:creator>Ada</dc:creator>"));
+        assert!(opf.contains(r#"<meta property="dcterms:modified">2024-05-01T00:00:00Z</meta>"#));
+        assert!(opf.contains(r#"href="images/image1.png" media-type="image/png""#));
+        assert!(opf.contains(
+            "<spine>\n<itemref idref=\"chapter1\"/>\n<itemref idref=\"chapter2\"/>\n<itemref idref=\"chapter3\"/>\n</spine>"
+        ));
+        assert_eq!(book, Transformer::generate(&document)?);
+
+        let nav = file("OEBPS/nav.xhtml");
+        assert!(nav.contains(
+            r#"<li><a href="chapter2.xhtml#first-chapter">First chapter</a>
+<ol>
+<li><a href="chapter2.xhtml#part">Part</a></li>
+</ol>
+</li>"#
+        ));
+        assert!(nav.contains(r#"<a href="chapter3.xhtml#the-end">The end</a>"#));
+
+        assert!(file("OEBPS/chapter1.xhtml").contains("<p>Foreword of the book.</p>"));
+        let chapter = file("OEBPS/chapter2.xhtml");
+        assert!(chapter.contains(r#"<a href="chapter3.xhtml#the-end">the end</a>"#));
+        assert!(chapter.contains(r##"<a epub:type="noteref" id="noteref-1" href="#note-1">1</a>"##));
+        assert!(chapter.contains(r#"<aside epub:type="footnote" id="note-1">"#));
+        assert!(chapter.contains("<li>One\n<ul>\n<li>Nested</li>\n</ul>\n</li>"));
+        assert!(chapter.contains("<td>a &amp; b</td><td>&lt;1&gt;</td>"));
+        let chapter = file("OEBPS/chapter3.xhtml");
+        assert!(chapter.contains("<title>Second chapter</title>"));
+        assert_eq!(chapter.matches(r#"src="images/image1.png""#).count(), 2);
+        assert!(chapter.contains(r#"title="Logo" style="width: 40px"/>"#));
+        Ok(())
+    }
+
+    #[test]
+    fn test_cover() -> anyhow::Result<()> {
+        let mut document = Document::new(vec![Element::Text {
+            text: "Text".to_string(),
+            size: 12,
+        }]);
+        document
+            .metadata
+            .custom
+            .insert("cover".to_string(), "small.png".to_string());
+        let book = Transformer::generate_with_loader(&document, disk_image_loader("test/data"))?;
+        let files = book_files(&book)?;
+        let opf = &files[2].1;
+        assert!(opf.contains(
+            r#"<item id="cover-image" href="images/cover1.png" media-type="image/png" properties="cover-image"/>"#
+        ));
+        assert!(opf.contains("<itemref idref=\"cover\"/>\n<itemref idref=\"chapter1\"/>"));
+        assert!(files
+            .iter()
+            .any(|(name, content)| name == "OEBPS/cover.xhtml"
+                && content.contains(r#"<img src="images/cover1.png" alt="Untitled"/>"#)));
+
+        document
+            .metadata
+            .custom
+            .insert("cover".to_string(), "missing.png".to_string());
+        let book = Transformer::generate_with_loader(&document, disk_image_loader("test/data"))?;
+        assert!(!book_files(&book)?
+            .iter()
+            .any(|(name, _)| name == "OEBPS/cover.xhtml"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse() -> anyhow::Result<()> {
+        init_logger();
+        let markdown = "# First chapter\n\nSee [the end](#the-end).[^1]\n\n\
+            - One\n  - Nested\n- Two\n\n\
+            | Name | Value |\n|------|-------|\n| a & b | <1> |\n\n\
+            # Second chapter\n\n## The end\n\nDone.\n\n\
+            [^1]: A note.\n";
+        let mut document = markdown::Transformer::parse(&Bytes::from(markdown))?;
+        document.metadata.title = Some("The Book".to_string());
+        document.metadata.author = Some("Ada".to_string());
+        let png = Bytes::from(std::fs::read("test/data/small.png")?);
+        if let Some(Band::Detail(elements)) = document.bands.last_mut() {
+            elements.push(Element::Image(ImageData::new(
+                png.clone(),
+                String::new(),
+                "Logo".to_string(),
+                "png".to_string(),
+                String::new(),
+                ImageDimension::default(),
+            )));
+        }
+        document.metadata.keywords = vec!["fiction".to_string()];
+        document
+            .metadata
+            .custom
+            .insert("cover".to_string(), "small.png".to_string());
+        let book = Transformer::generate_with_loader(&document, disk_image_loader("test/data"))?;
+
+        let parsed = Transformer::parse(&book)?;
+        let elements = parsed.get_all_elements();
+        assert_eq!(
+            elements[0],
+            &Element::Header {
+                text: "First chapter".to_string(),
+                level: 1
+            }
+        );
+        let Element::Paragraph { elements: text, .. } = elements[1] else {
+            panic!("Not a paragraph: {:?}", elements[1]);
+        };
+        assert!(matches!(&text[1], Element::Hyperlink { url, .. } if url == "#the-end"));
+        assert_eq!(
+            text[3],
+            Element::Footnote {
+                elements: vec![Element::Text {
+                    text: "A note.".to_string(),
+                    size: 8
+                }]
+            }
+        );
+        assert!(matches!(elements[2], Element::List { .. }));
+        assert!(matches!(elements[3], Element::Table { .. }));
+        assert_eq!(elements[5].plain_text(), "The end");
+        assert!(matches!(elements[7], Element::Image(image) if image.bytes() == &png));
+        assert_eq!(elements.len(), 8);
+
+        let metadata = &parsed.metadata;
+        assert_eq!(metadata.title.as_deref(), Some("The Book"));
+        assert_eq!(metadata.author.as_deref(), Some("Ada"));
+        assert_eq!(metadata.keywords, ["fiction"]);
+        assert_eq!(metadata.custom["language"], "en");
+        assert!(metadata.custom["cover"].starts_with("data:image/png;base64,"));
+        let book = Transformer::generate(&parsed)?;
+        assert!(book_files(&book)?
+            .iter()
+            .any(|(name, _)| name == "OEBPS/images/cover1.png"));
+        Ok(())
+    }
+}
@@ -0,0 +1,145 @@
+use thiserror::Error;
+
+use crate::core::ValidationError;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Position of a parse error in the input, starting at 1
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl std::fmt::Display for Location {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}, column {}", self.line, self.column)
+    }
+}
+
+/// Errors of the parsers and generators
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("Parse error{}: {message}", location.map(|l| format!(" at {}", l)).unwrap_or_default())]
+    ParseError {
+        message: String,
+        location: Option<Location>,
+    },
+    /// Element the transformer does not know how to handle
+    #[error("Unsupported element: {0}")]
+    UnsupportedElement(String),
+    #[error("Cannot load image {path}: {message}")]
+    ImageLoadError { path: String, message: String },
+    #[error("Generation error: {0}")]
+    GenerationError(String),
+    /// No transformer for the document type (disabled feature or missing registration)
+    #[error("Unsupported format: {0}")]
+    UnsupportedFormat(String),
+    #[error(transparent)]
+    Validation(#[from] ValidationError),
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
+impl Error {
+    /// Error of a parser, keeps the errors of this type and the location of JSON errors
+    pub(crate) fn parse_error(error: anyhow::Error) -> Error {
+        let error = match error.downcast::<Error>() {
+            Ok(error) => return error,
+            Err(error) => error,
+        };
+        #[cfg(feature = "json")]
+        if let Some(json_error) = error.downcast_ref::<serde_json::Error>() {
+            return Error::ParseError {
+                message: json_error.to_string(),
+                location: Some(Location {
+                    line: json_error.line(),
+                    column: json_error.column(),
+                }),
+            };
+        }
+        Error::ParseError {
+            message: format!("{:#}", error),
+            location: None,
+        }
+    }
+
+    /// Error of a generator, keeps the errors of this type
+    pub(crate) fn generation_error(error: anyhow::Error) -> Error {
+        match error.downcast::<Error>() {
+            Ok(error) => error,
+            Err(error) => Error::GenerationError(format!("{:#}", error)),
+        }
+    }
+}
+
+/// Wraps an image loader so that its failures are reported as [`Error::ImageLoadError`]
+#[cfg(any(
+    feature = "markdown",
+    feature = "html",
+    feature = "latex",
+    feature = "asciidoc",
+    feature = "rst",
+    feature = "org",
+    feature = "wiki",
+    feature = "jira",
+    feature = "bbcode"
+))]
+pub(crate) fn image_loader_errors<F>(
+    image_loader: F,
+) -> impl Fn(&str) -> anyhow::Result<bytes::Bytes>
+where
+    F: Fn(&str) -> anyhow::Result<bytes::Bytes>,
+{
+    move |path: &str| {
+        image_loader(path).map_err(|error| {
+            Error::ImageLoadError {
+                path: path.to_string(),
+                message: format!("{:#}", error),
+            }
+            .into()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use crate::core::*;
+    use crate::error::*;
+
+    #[test]
+    #[cfg(feature = "json")]
+    fn test_parse_errors() {
+        let error = crate::json::Transformer::parse(&Bytes::from("{\n  \"bands\": ")).unwrap_err();
+        assert!(matches!(
+            error,
+            Error::ParseError {
+                location: Some(Location { line: 2, .. }),
+                ..
+            }
+        ));
+
+        let document = Document::new(vec![Element::Text {
+            text: "text".to_string(),
+            size: 8,
+        }]);
+        let json = String::from_utf8(document.generate(DocumentType::Json).unwrap().to_vec())
+            .unwrap()
+            .replace("\"Text\"", "\"Unknown\"");
+        let error = crate::json::Transformer::parse(&Bytes::from(json)).unwrap_err();
+        assert!(matches!(error, Error::UnsupportedElement(element) if element == "Unknown"));
+    }
+
+    #[test]
+    #[cfg(feature = "markdown")]
+    fn test_image_load_error() {
+        let error = crate::markdown::Transformer::parse_with_loader(
+            &Bytes::from("![alt](missing.png)"),
+            |_| Err(anyhow::anyhow!("not found")),
+        )
+        .unwrap_err();
+        assert!(matches!(error, Error::ImageLoadError { path, .. } if path == "missing.png"));
+    }
+}
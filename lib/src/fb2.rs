@@ -0,0 +1,743 @@
+//! FictionBook 2 e-books, a single XML file with the images embedded in base64
+//!
+//! Headers become nested sections, the headers of the first level being the top sections of
+//! the body, and the metadata the title info of the book (its `language`, `genre`,
+//! `publisher`, `isbn` and `cover` entries included). FictionBook has no lists, code or math:
+//! list items are paragraphs starting with their marker and code is written line by line.
+//! Footnotes are the sections of a notes body. FictionBook is generated only.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
+use std::fmt::Write as _;
+use std::hash::{Hash, Hasher};
+use std::io::Cursor;
+
+use base64::engine::general_purpose;
+use base64::Engine;
+use bytes::Bytes;
+use log::warn;
+
+use crate::core::{
+    data_uri_image_loader, disk_image_loader, header_anchor, Band, ContainerKind, Document,
+    Element, ImageData, ImageDimension, ImageType, TextStyle,
+};
+use crate::util::escape_xml;
+
+pub struct Transformer;
+
+/// Language of the books without a `language` metadata entry
+const DEFAULT_LANGUAGE: &str = "en";
+
+/// Genre of the books without a `genre` metadata entry, from the genre list of FictionBook
+const DEFAULT_GENRE: &str = "nonfiction";
+
+/// Indentation of a nested list item, FictionBook paragraphs have no margins
+const LIST_INDENT: &str = "\u{a0}\u{a0}\u{a0}\u{a0}";
+
+/// Author of the title info: the first, middle and last names of a name of several words,
+/// the nickname of a single word
+fn author(name: &str) -> String {
+    let words: Vec<&str> = name.split_whitespace().collect();
+    match words.as_slice() {
+        [] => "<author><nickname>Unknown</nickname></author>".to_string(),
+        [nickname] => format!(
+            "<author><nickname>{}</nickname></author>",
+            escape_xml(nickname)
+        ),
+        [first, middle @ .., last] => {
+            let middle = if middle.is_empty() {
+                String::new()
+            } else {
+                format!(
+                    "<middle-name>{}</middle-name>",
+                    escape_xml(&middle.join(" "))
+                )
+            };
+            format!(
+                "<author><first-name>{}</first-name>{middle}<last-name>{}</last-name></author>",
+                escape_xml(first),
+                escape_xml(last)
+            )
+        }
+    }
+}
+
+/// Part of the book between two headers: its title, blocks and subsections
+struct Section<'a> {
+    level: u8,
+    title: Option<&'a str>,
+    blocks: Vec<&'a Element>,
+    sections: Vec<Section<'a>>,
+}
+
+impl<'a> Section<'a> {
+    fn new(level: u8, title: Option<&'a str>) -> Section<'a> {
+        Section {
+            level,
+            title,
+            blocks: vec![],
+            sections: vec![],
+        }
+    }
+}
+
+/// Sections of the headers of the elements, in a root section of level 0 whose blocks are
+/// the ones before the first header
+fn sections<'a>(elements: &[&'a Element]) -> Section<'a> {
+    fn close<'a>(stack: &mut Vec<Section<'a>>) {
+        if let Some(section) = stack.pop() {
+            if let Some(parent) = stack.last_mut() {
+                parent.sections.push(section);
+            }
+        }
+    }
+
+    let mut stack = vec![Section::new(0, None)];
+    for element in elements {
+        let header = match element {
+            Element::Attributed { element, .. } => element.as_ref(),
+            element => element,
+        };
+        if let Element::Header { level, text } = header {
+            let level = (*level).max(1);
+            while stack.len() > 1 && stack.last().is_some_and(|section| section.level >= level) {
+                close(&mut stack);
+            }
+            stack.push(Section::new(level, Some(text)));
+        } else if let Some(section) = stack.last_mut() {
+            section.blocks.push(element);
+        }
+    }
+    while stack.len() > 1 {
+        close(&mut stack);
+    }
+    stack.pop().unwrap_or_else(|| Section::new(0, None))
+}
+
+/// Image embedded in the book
+struct Binary {
+    id: String,
+    content_type: String,
+    bytes: Bytes,
+}
+
+struct Writer {
+    /// Anchors of the headers that links refer to, the ids of their sections
+    targets: HashSet<String>,
+    /// Ids already written, an id is written once
+    written_ids: HashSet<String>,
+    binaries: Vec<Binary>,
+    /// Contents of the footnotes, the sections of the notes body
+    notes: Vec<Vec<String>>,
+    /// Whether the blocks are written in a citation, which takes paragraphs, subtitles and
+    /// tables only
+    in_cite: bool,
+    lines: Vec<String>,
+}
+
+impl Writer {
+    fn line(&mut self, indent: usize, text: impl AsRef<str>) {
+        self.lines
+            .push(format!("{}{}", "  ".repeat(indent), text.as_ref()));
+    }
+
+    /// Section of the book, its blocks before its first subsection in an untitled section
+    /// since a section has either blocks or subsections
+    fn section(&mut self, section: &Section, indent: usize) {
+        let id = section
+            .title
+            .map(header_anchor)
+            .filter(|anchor| self.targets.contains(anchor))
+            .filter(|anchor| self.written_ids.insert(anchor.clone()))
+            .map(|anchor| format!(r#" id="{}""#, escape_xml(&anchor)))
+            .unwrap_or_default();
+        self.line(indent, format!("<section{id}>"));
+        if let Some(title) = section.title {
+            self.line(
+                indent + 1,
+                format!("<title><p>{}</p></title>", escape_xml(title.trim())),
+            );
+        }
+        if section.sections.is_empty() {
+            self.required_blocks(&section.blocks, indent + 1);
+        } else {
+            self.untitled_section(&section.blocks, indent + 1);
+            for subsection in &section.sections {
+                self.section(subsection, indent + 1);
+            }
+        }
+        self.line(indent, "</section>");
+    }
+
+    /// Untitled section of the blocks before a subsection, left out when they write nothing
+    fn untitled_section(&mut self, blocks: &[&Element], indent: usize) {
+        let before = self.lines.len();
+        self.line(indent, "<section>");
+        self.blocks(blocks, indent + 1);
+        if self.lines.len() == before + 1 {
+            self.lines.truncate(before);
+        } else {
+            self.line(indent, "</section>");
+        }
+    }
+
+    fn blocks(&mut self, elements: &[&Element], indent: usize) {
+        for element in elements {
+            self.block(element, indent);
+        }
+    }
+
+    /// Blocks of a section or a citation, an empty line when there are none
+    fn required_blocks(&mut self, elements: &[&Element], indent: usize) {
+        let before = self.lines.len();
+        self.blocks(elements, indent);
+        if self.lines.len() == before {
+            self.line(indent, "<empty-line/>");
+        }
+    }
+
+    fn block(&mut self, element: &Element, indent: usize) {
+        if let Some((_, title, blocks)) = element.admonition_parts() {
+            let blocks: Vec<&Element> = blocks.iter().collect();
+            self.cite(title.as_deref(), &blocks, indent);
+            return;
+        }
+        match element {
+            // headers inside lists, tables and citations, where there are no sections
+            Element::Header { text, .. } => self.line(
+                indent,
+                format!("<subtitle>{}</subtitle>", escape_xml(text.trim())),
+            ),
+            Element::Attributed { element, .. } => self.block(element, indent),
+            Element::Paragraph { elements, .. } => match elements.as_slice() {
+                [image @ Element::Image(_)] => self.block(image, indent),
+                elements => self.paragraphs(elements, "", indent),
+            },
+            Element::Image(image) => {
+                if self.in_cite {
+                    self.paragraphs(std::slice::from_ref(element), "", indent);
+                } else if let Some(id) = self.add_image(image) {
+                    let mut attributes = format!(r##"l:href="#{}""##, escape_xml(&id));
+                    if !image.alt().trim().is_empty() {
+                        let _ = write!(attributes, r#" alt="{}""#, escape_xml(image.alt().trim()));
+                    }
+                    if !image.title().trim().is_empty() {
+                        let _ = write!(
+                            attributes,
+                            r#" title="{}""#,
+                            escape_xml(image.title().trim())
+                        );
+                    }
+                    self.line(indent, format!("<image {attributes}/>"));
+                }
+            }
+            Element::Table { headers, rows } => {
+                self.line(indent, "<table>");
+                if !headers.is_empty() {
+                    let cells: Vec<String> = headers
+                        .iter()
+                        .map(|header| format!("<th>{}</th>", self.inline(&header.element).trim()))
+                        .collect();
+                    self.line(indent + 1, format!("<tr>{}</tr>", cells.concat()));
+                }
+                for row in rows {
+                    let cells: Vec<String> = row
+                        .cells
+                        .iter()
+                        .map(|cell| format!("<td>{}</td>", self.inline(&cell.element).trim()))
+                        .collect();
+                    self.line(indent + 1, format!("<tr>{}</tr>", cells.concat()));
+                }
+                if headers.is_empty() && rows.is_empty() {
+                    self.line(indent + 1, "<tr><td/></tr>");
+                }
+                self.line(indent, "</table>");
+            }
+            Element::List { .. } => self.list(element, 0, indent),
+            Element::CodeBlock { code, .. } => {
+                for line in code.trim_end_matches('\n').lines() {
+                    if line.trim().is_empty() {
+                        self.line(indent, "<empty-line/>");
+                    } else {
+                        // leading spaces would be collapsed by the readers
+                        let content = line.trim_start();
+                        let spaces = "\u{a0}".repeat(line.len() - content.len());
+                        self.line(
+                            indent,
+                            format!("<p><code>{spaces}{}</code></p>", escape_xml(content)),
+                        );
+                    }
+                }
+            }
+            Element::Container {
+                kind: ContainerKind::Aside,
+                elements,
+            } => {
+                let blocks: Vec<&Element> = elements.iter().collect();
+                self.cite(None, &blocks, indent);
+            }
+            Element::Container { elements, .. } => {
+                for element in elements {
+                    self.block(element, indent);
+                }
+            }
+            Element::Raw { .. }
+            | Element::TableOfContents { .. }
+            | Element::Drawing { .. }
+            | Element::Bookmark { .. }
+            | Element::LineBreak => {}
+            element => self.paragraphs(std::slice::from_ref(element), "", indent),
+        }
+    }
+
+    /// Citation of an admonition or an aside, nested ones being written in the enclosing one
+    fn cite(&mut self, title: Option<&str>, blocks: &[&Element], indent: usize) {
+        if self.in_cite {
+            if let Some(title) = title {
+                self.line(
+                    indent,
+                    format!("<subtitle>{}</subtitle>", escape_xml(title.trim())),
+                );
+            }
+            self.blocks(blocks, indent);
+            return;
+        }
+        self.in_cite = true;
+        self.line(indent, "<cite>");
+        if let Some(title) = title {
+            self.line(
+                indent + 1,
+                format!("<subtitle>{}</subtitle>", escape_xml(title.trim())),
+            );
+        }
+        self.required_blocks(blocks, indent + 1);
+        self.line(indent, "</cite>");
+        self.in_cite = false;
+    }
+
+    /// Paragraphs of inline elements, one for each line since a paragraph has no line
+    /// breaks, the first one starting with `prefix`
+    fn paragraphs(&mut self, elements: &[Element], prefix: &str, indent: usize) {
+        let mut content = String::new();
+        for element in elements {
+            content.push_str(&self.inline(element));
+        }
+        let content = content.trim();
+        if content.is_empty() && prefix.is_empty() {
+            return;
+        }
+        for (index, line) in content.split('\n').enumerate() {
+            let prefix = if index == 0 { prefix } else { "" };
+            self.line(indent, format!("<p>{prefix}{}</p>", line.trim()));
+        }
+    }
+
+    /// Items of a list as paragraphs starting with their marker, indented by their depth
+    fn list(&mut self, list: &Element, depth: usize, indent: usize) {
+        let Element::List {
+            elements,
+            numbered,
+            start,
+            numbering,
+        } = list
+        else {
+            return;
+        };
+        let mut number = *start;
+        for item in elements {
+            match &item.element {
+                nested @ Element::List { .. } => self.list(nested, depth + 1, indent),
+                element @ (Element::Table { .. }
+                | Element::CodeBlock { .. }
+                | Element::Image(_)) => self.block(element, indent),
+                element => {
+                    let marker = if *numbered {
+                        format!("{}.", numbering.format(number))
+                    } else {
+                        "•".to_string()
+                    };
+                    number += 1;
+                    let prefix = format!("{}{marker} ", LIST_INDENT.repeat(depth));
+                    let elements = match element {
+                        Element::Paragraph { elements, .. } => elements.as_slice(),
+                        element => std::slice::from_ref(element),
+                    };
+                    self.paragraphs(elements, &prefix, indent);
+                }
+            }
+        }
+    }
+
+    fn inline(&mut self, element: &Element) -> String {
+        match element {
+            Element::Text { text, .. } => escape_xml(text),
+            Element::Header { text, .. } => format!("<strong>{}</strong>", escape_xml(text)),
+            Element::Paragraph { elements, .. } | Element::Container { elements, .. } => {
+                self.inlines(elements)
+            }
+            Element::Styled { style, elements } => {
+                let content = self.inlines(elements);
+                match style {
+                    TextStyle::Strong => format!("<strong>{content}</strong>"),
+                    TextStyle::Emphasis => format!("<emphasis>{content}</emphasis>"),
+                    TextStyle::Strikethrough => format!("<strikethrough>{content}</strikethrough>"),
+                }
+            }
+            Element::Hyperlink { elements, url, .. } => {
+                let content = self.inlines(elements);
+                let content = if content.trim().is_empty() {
+                    escape_xml(url)
+                } else {
+                    content
+                };
+                format!(r#"<a l:href="{}">{content}</a>"#, escape_xml(url))
+            }
+            Element::InlineCode { text } => format!("<code>{}</code>", escape_xml(text)),
+            Element::CodeBlock { code, .. } => format!("<code>{}</code>", escape_xml(code.trim())),
+            Element::LineBreak => "\n".to_string(),
+            Element::Footnote { elements } => {
+                let number = self.notes.len() + 1;
+                // a note is written apart, in the notes body
+                let lines = std::mem::take(&mut self.lines);
+                let in_cite = std::mem::replace(&mut self.in_cite, true);
+                let blocks: Vec<&Element> = elements.iter().collect();
+                self.required_blocks(&blocks, 3);
+                self.in_cite = in_cite;
+                let note = std::mem::replace(&mut self.lines, lines);
+                self.notes.push(note);
+                format!(r##"<a l:href="#note{number}" type="note">[{number}]</a>"##)
+            }
+            Element::Image(image) => match self.add_image(image) {
+                Some(id) => format!(r##"<image l:href="#{}"/>"##, escape_xml(&id)),
+                None => String::new(),
+            },
+            Element::Attributed { element, .. } => self.inline(element),
+            Element::List { elements, .. } => elements
+                .iter()
+                .map(|item| self.inline(&item.element) + " ")
+                .collect(),
+            _ => String::new(),
+        }
+    }
+
+    fn inlines(&mut self, elements: &[Element]) -> String {
+        elements
+            .iter()
+            .map(|element| self.inline(element))
+            .collect()
+    }
+
+    /// Adds an image to the book, once for the same content, and gives its id. The images
+    /// other than PNG and JPEG, which readers may not show, are converted to PNG.
+    fn add_image(&mut self, image: &ImageData) -> Option<String> {
+        if image.bytes().is_empty() {
+            warn!("Image without content left out: {}", image.title());
+            return None;
+        }
+        if let Some(existing) = self
+            .binaries
+            .iter()
+            .find(|existing| existing.bytes == *image.bytes())
+        {
+            return Some(existing.id.clone());
+        }
+        let (image_type, bytes) = match image.image_type() {
+            image_type @ (ImageType::Png | ImageType::Jpeg) => {
+                (image_type.clone(), image.bytes().clone())
+            }
+            _ => {
+                let converted = image::load_from_memory(image.bytes()).and_then(|decoded| {
+                    let mut png = Cursor::new(Vec::new());
+                    decoded.write_to(&mut png, image::ImageFormat::Png)?;
+                    Ok(png.into_inner())
+                });
+                match converted {
+                    Ok(png) => (ImageType::Png, Bytes::from(png)),
+                    Err(error) => {
+                        warn!("Cannot convert the image {}: {}", image.title(), error);
+                        return None;
+                    }
+                }
+            }
+        };
+        let id = format!(
+            "image{}{}",
+            self.binaries.len() + 1,
+            image_type.to_extension()
+        );
+        self.binaries.push(Binary {
+            id: id.clone(),
+            content_type: image_type.mime_type().to_string(),
+            bytes,
+        });
+        Some(id)
+    }
+}
+
+impl Transformer {
+    /// FictionBook of the document, its cover being the image of the `cover` metadata entry
+    /// loaded from the current directory
+    pub fn generate(document: &Document) -> crate::Result<Bytes> {
+        Transformer::generate_with_loader(document, disk_image_loader("."))
+    }
+
+    /// FictionBook of the document, the image of its `cover` metadata entry, a path or a
+    /// `data:` URI, loaded with `image_loader`
+    pub fn generate_with_loader<F>(document: &Document, image_loader: F) -> crate::Result<Bytes>
+    where
+        F: Fn(&str) -> anyhow::Result<Bytes>,
+    {
+        Transformer::generate_bytes(document, data_uri_image_loader(image_loader))
+            .map_err(crate::Error::generation_error)
+    }
+
+    fn generate_bytes<F>(document: &Document, image_loader: F) -> anyhow::Result<Bytes>
+    where
+        F: Fn(&str) -> anyhow::Result<Bytes>,
+    {
+        let document = &document
+            .resolve_fields()
+            .without_media()
+            .without_math()
+            .without_drawings();
+        let elements: Vec<&Element> = document
+            .bands
+            .iter()
+            .filter(|band| !matches!(band, Band::PageHeader(_) | Band::PageFooter(_)))
+            .flat_map(|band| band.elements())
+            .collect();
+        let metadata = &document.metadata;
+        let custom = &metadata.custom;
+        let title = metadata
+            .title
+            .clone()
+            .unwrap_or_else(|| document.get_title_text());
+        let title = if title.trim().is_empty() {
+            "Untitled".to_string()
+        } else {
+            title
+        };
+
+        let mut writer = Writer {
+            targets: document.link_targets(),
+            written_ids: HashSet::new(),
+            binaries: vec![],
+            notes: vec![],
+            in_cite: false,
+            lines: vec![],
+        };
+        let cover = custom.get("cover").and_then(|source| {
+            let image = image_loader(source)
+                .map_err(|error| warn!("Cannot load the cover {}: {}", source, error))
+                .ok()?;
+            writer.add_image(&ImageData::new(
+                image,
+                String::new(),
+                String::new(),
+                source.clone(),
+                String::new(),
+                ImageDimension::default(),
+            ))
+        });
+
+        // the body has sections only, the blocks before the first header are an untitled one
+        let root = sections(&elements);
+        if root.sections.is_empty() {
+            writer.section(&root, 2);
+        } else {
+            writer.untitled_section(&root.blocks, 2);
+        }
+        for section in &root.sections {
+            writer.section(section, 2);
+        }
+        let body = std::mem::take(&mut writer.lines);
+
+        let author = author(metadata.author.as_deref().unwrap_or_default());
+        let date = metadata.date.as_deref().unwrap_or_default().trim();
+        let language = custom
+            .get("language")
+            .or_else(|| custom.get("lang"))
+            .map_or(DEFAULT_LANGUAGE, |language| language.trim());
+        let identifier = custom
+            .get("identifier")
+            .or_else(|| custom.get("isbn"))
+            .cloned()
+            .unwrap_or_else(|| {
+                // the same document gives the same identifier
+                let mut hasher = DefaultHasher::new();
+                title.hash(&mut hasher);
+                body.hash(&mut hasher);
+                format!("shiva-{:016x}", hasher.finish())
+            });
+
+        let mut xml = String::from(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <FictionBook xmlns=\"http://www.gribuser.ru/xml/fictionbook/2.0\" \
+             xmlns:l=\"http://www.w3.org/1999/xlink\">\n  <description>\n    <title-info>\n",
+        );
+        let genre = custom
+            .get("genre")
+            .map_or(DEFAULT_GENRE, |genre| genre.trim());
+        let _ = writeln!(xml, "      <genre>{}</genre>", escape_xml(genre));
+        let _ = writeln!(xml, "      {author}");
+        let _ = writeln!(
+            xml,
+            "      <book-title>{}</book-title>",
+            escape_xml(title.trim())
+        );
+        if let Some(description) = &metadata.description {
+            let _ = writeln!(
+                xml,
+                "      <annotation><p>{}</p></annotation>",
+                escape_xml(description.trim())
+            );
+        }
+        if !metadata.keywords.is_empty() {
+            let _ = writeln!(
+                xml,
+                "      <keywords>{}</keywords>",
+                escape_xml(&metadata.keywords.join(", "))
+            );
+        }
+        if !date.is_empty() {
+            let _ = writeln!(xml, "      <date>{}</date>", escape_xml(date));
+        }
+        if let Some(cover) = &cover {
+            let _ = writeln!(
+                xml,
+                "      <coverpage><image l:href=\"#{}\"/></coverpage>",
+                escape_xml(cover)
+            );
+        }
+        let _ = writeln!(xml, "      <lang>{}</lang>", escape_xml(language));
+        xml.push_str("    </title-info>\n    <document-info>\n");
+        let _ = writeln!(xml, "      {author}");
+        xml.push_str("      <program-used>Shiva</program-used>\n");
+        let _ = writeln!(xml, "      <date>{}</date>", escape_xml(date));
+        let _ = writeln!(xml, "      <id>{}</id>", escape_xml(identifier.trim()));
+        xml.push_str("      <version>1.0</version>\n    </document-info>\n");
+        let publisher = custom.get("publisher");
+        let isbn = custom.get("isbn");
+        if publisher.is_some() || isbn.is_some() {
+            xml.push_str("    <publish-info>\n");
+            if let Some(publisher) = publisher {
+                let _ = writeln!(
+                    xml,
+                    "      <publisher>{}</publisher>",
+                    escape_xml(publisher.trim())
+                );
+            }
+            if let Some(isbn) = isbn {
+                let _ = writeln!(xml, "      <isbn>{}</isbn>", escape_xml(isbn.trim()));
+            }
+            xml.push_str("    </publish-info>\n");
+        }
+        xml.push_str("  </description>\n  <body>\n");
+        let _ = writeln!(
+            xml,
+            "    <title><p>{}</p></title>",
+            escape_xml(title.trim())
+        );
+        for line in body {
+            xml.push_str(&line);
+            xml.push('\n');
+        }
+        xml.push_str("  </body>\n");
+        if !writer.notes.is_empty() {
+            xml.push_str("  <body name=\"notes\">\n    <title><p>Notes</p></title>\n");
+            for (index, note) in writer.notes.iter().enumerate() {
+                let number = index + 1;
+                let _ = writeln!(
+                    xml,
+                    "    <section id=\"note{number}\">\n      <title><p>{number}</p></title>"
+                );
+                for line in note {
+                    xml.push_str(line);
+                    xml.push('\n');
+                }
+                xml.push_str("    </section>\n");
+            }
+            xml.push_str("  </body>\n");
+        }
+        for binary in &writer.binaries {
+            let _ = writeln!(
+                xml,
+                "  <binary id=\"{}\" content-type=\"{}\">{}</binary>",
+                escape_xml(&binary.id),
+                binary.content_type,
+                general_purpose::STANDARD.encode(&binary.bytes)
+            );
+        }
+        xml.push_str("</FictionBook>\n");
+        Ok(Bytes::from(xml))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use crate::core::tests::init_logger;
+    use crate::core::{Band, Element, ImageData, ImageDimension, TransformerTrait};
+    use crate::fb2::Transformer;
+    use crate::markdown;
+
+    #[test]
+    fn test_generate() -> anyhow::Result<()> {
+        init_logger();
+        let markdown = "---\ntitle: Guide\nauthor: Ada King Lovelace\nlanguage: fr\n---\n\n\
+            Intro & more.\n\n# Install\n\nSee [usage](#usage).[^1]\n\n## Usage\n\n\
+            1. Run it\n   - with `--help`\n2. Done\n\n\
+            | Name | Value |\n|------|-------|\n| a | 1 < 2 |\n\n\
+            ```\nfn main() {\n    run();\n}\n```\n\n# Empty\n\n[^1]: A note.\n";
+        let mut document = markdown::Transformer::parse(&Bytes::from(markdown))?;
+        let png = Bytes::from(std::fs::read("test/data/small.png")?);
+        if let Some(Band::Detail(elements)) = document.bands.last_mut() {
+            for _ in 0..2 {
+                elements.push(Element::Image(ImageData::new(
+                    png.clone(),
+                    "Logo".to_string(),
+                    String::new(),
+                    "png".to_string(),
+                    String::new(),
+                    ImageDimension::default(),
+                )));
+            }
+        }
+
+        let xml = Transformer::generate_with_loader(&document, |source| {
+            anyhow::bail!("no image {source}")
+        })?;
+        let xml = std::str::from_utf8(&xml)?;
+        assert!(xml.contains(
+            "      <author><first-name>Ada</first-name><middle-name>King</middle-name>\
+             <last-name>Lovelace</last-name></author>\n      <book-title>Guide</book-title>\n"
+        ));
+        assert!(xml.contains("      <lang>fr</lang>\n"));
+        assert!(xml.contains(
+            "  <body>\n    <title><p>Guide</p></title>\n    <section>\n      \
+             <p>Intro &amp; more.</p>\n    </section>\n    <section>\n      \
+             <title><p>Install</p></title>\n      <section>\n        \
+             <p>See <a l:href=\"#usage\">usage</a>.<a l:href=\"#note1\" type=\"note\">[1]</a></p>\n      \
+             </section>\n      <section id=\"usage\">\n        <title><p>Usage</p></title>\n        \
+             <p>1. Run it</p>\n        <p>\u{a0}\u{a0}\u{a0}\u{a0}• with <code>--help</code></p>\n        \
+             <p>2. Done</p>\n        <table>\n          <tr><th>Name</th><th>Value</th></tr>\n          \
+             <tr><td>a</td><td>1 &lt; 2</td></tr>\n        </table>\n        \
+             <p><code>fn main() {</code></p>\n        <p><code>\u{a0}\u{a0}\u{a0}\u{a0}run();</code></p>\n"
+        ));
+        assert!(xml.contains(
+            "    <section>\n      <title><p>Empty</p></title>\n      \
+             <image l:href=\"#image1.png\" title=\"Logo\"/>\n      \
+             <image l:href=\"#image1.png\" title=\"Logo\"/>\n    </section>\n  </body>\n  \
+             <body name=\"notes\">\n    <title><p>Notes</p></title>\n    \
+             <section id=\"note1\">\n      <title><p>1</p></title>\n      <p>A note.</p>\n    </section>\n"
+        ));
+        assert_eq!(
+            xml.matches("<binary id=\"image1.png\" content-type=\"image/png\">")
+                .count(),
+            1
+        );
+        assert!(xml.ends_with("</binary>\n</FictionBook>\n"));
+        Ok(())
+    }
+}
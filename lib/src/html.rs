@@ -1,42 +1,167 @@
 use crate::core::*;
+use crate::error::image_loader_errors;
 use bytes::Bytes;
 use ego_tree::{iter::Children, NodeRef};
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1252};
+use std::collections::HashMap;
 
-use crate::core::Element::{Header, Hyperlink, Image, List, Paragraph, Table, Text};
-use scraper::{Html, Node};
+use crate::core::Element::{
+    Attributed, Bookmark, CodeBlock, Container, Drawing, Field, Footnote, Header, Hyperlink, Image,
+    InlineCode, LineBreak, List, Math, Media, Paragraph, Raw, Styled, Table, TableOfContents, Text,
+};
+use scraper::{ElementRef, Html, Node};
+use std::str::FromStr;
+use strum::VariantArray;
 
 pub struct Transformer;
 
 impl TransformerTrait for Transformer {
-    fn parse(document: &Bytes) -> anyhow::Result<Document> {
+    fn parse(document: &Bytes) -> crate::Result<Document> {
         Transformer::parse_with_loader(document, disk_image_loader("."))
     }
 
-    fn generate(document: &Document) -> anyhow::Result<Bytes> {
+    fn parse_with_options(document: &Bytes, options: &ParseOptions) -> crate::Result<Document> {
+        Transformer::parse_with_loader_and_options(document, disk_image_loader("."), options)
+    }
+
+    fn generate(document: &Document) -> crate::Result<Bytes> {
         Transformer::generate_with_saver(document, disk_image_saver("."))
     }
+
+    fn generate_with_options(
+        document: &Document,
+        options: &GenerateOptions,
+    ) -> crate::Result<Bytes> {
+        Transformer::generate_with_image_saver(document, disk_image_saver("."), &options.html)
+            .map_err(crate::Error::generation_error)
+    }
 }
 impl TransformerWithImageLoaderSaverTrait for Transformer {
-    fn parse_with_loader<F>(document: &Bytes, image_loader: F) -> anyhow::Result<Document>
+    fn parse_with_loader<F>(document: &Bytes, image_loader: F) -> crate::Result<Document>
+    where
+        F: Fn(&str) -> anyhow::Result<Bytes>,
+    {
+        Transformer::parse_with_loader_and_options(document, image_loader, &ParseOptions::default())
+    }
+
+    fn parse_with_loader_and_options<F>(
+        document: &Bytes,
+        image_loader: F,
+        options: &ParseOptions,
+    ) -> crate::Result<Document>
+    where
+        F: Fn(&str) -> anyhow::Result<Bytes>,
+    {
+        Transformer::parse_with_image_loader(
+            document,
+            image_loader_errors(data_uri_image_loader(image_loader)),
+            &options.html,
+        )
+        .map_err(crate::Error::parse_error)
+    }
+
+    fn generate_with_saver<F>(document: &Document, image_saver: F) -> crate::Result<Bytes>
+    where
+        F: Fn(&Bytes, &str) -> anyhow::Result<()>,
+    {
+        Transformer::generate_with_image_saver(document, image_saver, &HtmlOptions::default())
+            .map_err(crate::Error::generation_error)
+    }
+}
+
+/// Email-safe HTML of a document with its images, to send as a `multipart/related` message
+pub struct HtmlEmail {
+    pub html: Bytes,
+    /// Images by the `Content-ID` they are referenced with in the HTML (`cid:image0.png`)
+    pub attachments: HashMap<String, Bytes>,
+}
+
+impl Transformer {
+    /// Email page of the document, see [`HtmlOptions::email`]. Images are returned as
+    /// attachments, or embedded when [`HtmlOptions::embed_images`] is set.
+    pub fn generate_email(document: &Document, options: &HtmlOptions) -> crate::Result<HtmlEmail> {
+        let attachments = std::cell::RefCell::new(HashMap::new());
+        let html = Transformer::generate_with_image_saver(
+            document,
+            |bytes: &Bytes, name: &str| {
+                attachments
+                    .borrow_mut()
+                    .insert(name.to_string(), bytes.clone());
+                Ok(())
+            },
+            &HtmlOptions {
+                email: true,
+                ..options.clone()
+            },
+        )
+        .map_err(crate::Error::generation_error)?;
+        Ok(HtmlEmail {
+            html,
+            attachments: attachments.into_inner(),
+        })
+    }
+
+    fn parse_with_image_loader<F>(
+        document: &Bytes,
+        image_loader: F,
+        options: &HtmlParseOptions,
+    ) -> anyhow::Result<Document>
     where
         F: Fn(&str) -> anyhow::Result<Bytes>,
     {
-        let html = String::from_utf8(document.to_vec())?;
-        let document = Html::parse_document(&html);
+        if let Some(archive) = Mhtml::parse(document)? {
+            let html = decode_html(&archive.html, archive.charset);
+            let image_loader = |src: &str| match archive.resource(src) {
+                Some(bytes) => Ok(bytes),
+                None => image_loader(src),
+            };
+            return Transformer::parse_page(&html, image_loader, options);
+        }
+        Transformer::parse_page(&decode_html(document, None), image_loader, options)
+    }
+
+    pub(crate) fn parse_page<F>(
+        html: &str,
+        image_loader: F,
+        options: &HtmlParseOptions,
+    ) -> anyhow::Result<Document>
+    where
+        F: Fn(&str) -> anyhow::Result<Bytes>,
+    {
+        let document = Html::parse_document(html);
         let mut elements: Vec<Element> = Vec::new();
 
         let image_loader = ImageLoader {
             function: image_loader,
+            sanitize: options.sanitize,
         };
         parse_html(
             document.root_element().children(),
             &mut elements,
             &image_loader,
         )?;
-        Ok(Document::new(elements))
+        // the direction of <body> wins over the one of <html>
+        let direction = std::iter::once(document.root_element())
+            .chain(
+                document
+                    .root_element()
+                    .children()
+                    .filter_map(ElementRef::wrap),
+            )
+            .filter_map(|element| element.value().attr("dir"))
+            .last()
+            .map(parse_direction)
+            .unwrap_or_default();
+        let mut document = Document::new(elements);
+        document.direction = direction;
+        Ok(document)
     }
 
-    fn generate_with_saver<F>(document: &Document, image_saver: F) -> anyhow::Result<Bytes>
+    fn generate_with_image_saver<F>(
+        document: &Document,
+        image_saver: F,
+        options: &HtmlOptions,
+    ) -> anyhow::Result<Bytes>
     where
         F: Fn(&Bytes, &str) -> anyhow::Result<()>,
     {
@@ -44,102 +169,392 @@ impl TransformerWithImageLoaderSaverTrait for Transformer {
         let mut image_num: i32 = 0;
         let image_saver = ImageSaver {
             function: image_saver,
+            embed: options.embed_images,
+            cid: options.email,
         };
 
-        //TODO: Is this needed? Commented out for now! header_text and footer_text are not read anywhere
-        let mut header_text = String::new();
-        document.get_page_header().iter().for_each(|el| match el {
-            Text { text, size: _ } => {
-                header_text.push_str(text);
-            }
-            _ => {}
-        });
-        let mut footer_text = String::new();
+        let document = &document.resolve_fields();
+        if options.email {
+            return generate_email(document, options.standalone, &mut image_num, &image_saver);
+        }
 
-        document.get_page_footer().iter().for_each(|el| match el {
-            Text { text, size: _ } => {
-                footer_text.push_str(text);
+        if options.standalone {
+            html.push_str(&format!(
+                "<!DOCTYPE html>\n<html{}>\n",
+                direction_attribute(&document.direction)
+            ));
+            // page numbers only exist when printing, so headers and footers with them
+            // are rendered by CSS paged media rules
+            let page_header = document.get_page_header();
+            let page_footer = document.get_page_footer();
+            let has_fields = page_header
+                .iter()
+                .chain(page_footer.iter())
+                .any(|element| matches!(element, Field { .. }));
+            let mut css = String::new();
+            if options.print {
+                css.push_str(&page_rule(
+                    "",
+                    &document.page_format,
+                    &document.orientation,
+                    &page_header,
+                    &page_footer,
+                ));
+                for (index, section) in document.get_sections().iter().enumerate() {
+                    css.push_str(&page_rule(
+                        &format!(" section-{}", index + 1),
+                        &section.page_format,
+                        &section.orientation,
+                        &[],
+                        &[],
+                    ));
+                }
+            } else if has_fields {
+                css.push_str(&format!(
+                    "@page {{\n  @top-center {{ content: {}; }}\n  @bottom-center {{ content: {}; }}\n}}\n",
+                    page_margin_content(&page_header),
+                    page_margin_content(&page_footer)
+                ));
             }
-            _ => {}
-        });
-
-        html.push_str("<!DOCTYPE html>\n<html>\n<body>\n");
-
-        let all_elements: Vec<&Element> = document.get_all_elements();
-
-        for element in &all_elements {
-            match element {
-                Element::Header { level, text } => {
-                    html.push_str(&format!("<h{}>{}</h{}>\n", level, text, level));
+            if options.theme {
+                css.push_str(THEME_CSS);
+            }
+            if let Some(custom) = &options.css {
+                css.push_str(custom);
+                if !custom.ends_with('\n') {
+                    css.push('\n');
                 }
-                Element::Text { text, size: _ } => {
-                    html.push_str(&format!("<p>{}</p>\n", text));
+            }
+            // a page with a stylesheet is meant to be published, it gets a complete head
+            if options.theme || options.css.is_some() {
+                html.push_str("<head>\n<meta charset=\"utf-8\">\n<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n");
+                let title = document.get_title_text();
+                if !title.is_empty() {
+                    html.push_str(&format!("<title>{}</title>\n", escape_html(&title)));
                 }
-                Paragraph { elements } => {
-                    html.push_str("<p>");
-
-                    for child in elements {
-                        html.push_str(&generate_html_for_element(
-                            child,
-                            &mut image_num,
-                            &image_saver,
-                        )?);
-                    }
+                html.push_str(&format!("<style>\n{}</style>\n</head>\n", css));
+            } else if !css.is_empty() {
+                html.push_str(&format!("<head>\n<style>\n{}</style>\n</head>\n", css));
+            }
+            html.push_str("<body>\n");
+        }
 
-                    html.push_str("</p>\n");
+        let all_elements: Vec<&Element> = document.get_all_elements();
+        let blocks = Blocks {
+            document,
+            has_toc: all_elements
+                .iter()
+                .any(|element| matches!(element, TableOfContents { .. })),
+            semantic: options.semantic,
+        };
+        // when printing, the page headers and footers are in the page margins and each
+        // section is on pages of its own
+        let mut sections: Vec<(Option<usize>, Vec<&Element>)> = vec![(None, vec![])];
+        let mut detail_index = 0;
+        for band in &document.bands {
+            if options.print && matches!(band, Band::PageHeader(_) | Band::PageFooter(_)) {
+                continue;
+            }
+            for element in band.elements() {
+                if let Band::Detail(_) = band {
+                    let section = document
+                        .get_sections()
+                        .iter()
+                        .position(|section| section.start == detail_index);
+                    if let (true, Some(section)) = (options.print, section) {
+                        sections.push((Some(section), vec![]));
+                    }
+                    detail_index += 1;
                 }
-                List {
-                    elements: _,
-                    numbered: _,
-                } => {
-                    let list = generate_html_for_element(element, &mut image_num, &image_saver)?;
-
-                    html.push_str(&list);
+                if let Some((_, elements)) = sections.last_mut() {
+                    elements.push(element);
                 }
-                Table { headers, rows } => {
-                    let mut table_html = String::from("<table  border=\"1\">\n");
+            }
+        }
+        for (section, elements) in sections {
+            let section_html = generate_blocks(&elements, &blocks, &mut image_num, &image_saver)?;
+            match section {
+                Some(index) => html.push_str(&format!(
+                    "<div style=\"break-before: page; page: section-{}\">\n{}</div>\n",
+                    index + 1,
+                    section_html
+                )),
+                None => html.push_str(&section_html),
+            }
+        }
 
-                    if !headers.is_empty() {
-                        table_html.push_str("<tr>\n");
+        if options.standalone {
+            html.push_str("</body>\n</html>");
+        }
 
-                        for header in headers {
-                            let header_html = generate_html_for_element(
-                                &header.element,
-                                &mut image_num,
-                                &image_saver,
-                            )?;
+        Ok(Bytes::from(html))
+    }
+}
 
-                            table_html.push_str(&format!("<th>{}</th>\n", header_html));
-                        }
+/// Email page of the document: email clients drop the `<head>` styles and lay out tables
+/// better than CSS, so the content is in a centered column table with inline styles
+fn generate_email(
+    document: &Document,
+    standalone: bool,
+    image_num: &mut i32,
+    image_saver: &ImageSaver<impl Fn(&Bytes, &str) -> anyhow::Result<()>>,
+) -> anyhow::Result<Bytes> {
+    // players, frames and inline SVG are not shown by email clients
+    let document = &document.without_media().without_drawings();
+    let blocks = Blocks {
+        document,
+        has_toc: document
+            .get_all_elements()
+            .iter()
+            .any(|element| matches!(element, TableOfContents { .. })),
+        semantic: false,
+    };
+    let elements: Vec<&Element> = document
+        .bands
+        .iter()
+        .flat_map(|band| band.elements())
+        .collect();
+    let content = inline_styles(&generate_blocks(
+        &elements,
+        &blocks,
+        image_num,
+        image_saver,
+    )?);
 
-                        table_html.push_str("</tr>\n");
-                    }
-                    for row in rows {
-                        table_html.push_str("<tr>\n");
+    let mut html = String::new();
+    if standalone {
+        html.push_str(&format!(
+            "<!DOCTYPE html>\n<html{}>\n<head>\n<meta charset=\"utf-8\">\n<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n",
+            direction_attribute(&document.direction)
+        ));
+        let title = document.get_title_text();
+        if !title.is_empty() {
+            html.push_str(&format!("<title>{}</title>\n", escape_html(&title)));
+        }
+        html.push_str(
+            "</head>\n<body style=\"margin: 0; padding: 0; background-color: #f4f4f4;\">\n",
+        );
+    }
+    html.push_str(&format!(
+        "<table role=\"presentation\" width=\"100%\" cellpadding=\"0\" cellspacing=\"0\" border=\"0\" style=\"background-color: #f4f4f4;\">
+<tr>
+<td align=\"center\" style=\"padding: 16px;\">
+<table role=\"presentation\" width=\"600\" cellpadding=\"0\" cellspacing=\"0\" border=\"0\" style=\"width: 100%; max-width: 600px; background-color: #ffffff;\">
+<tr>
+<td style=\"{}\">
+{}</td>
+</tr>
+</table>
+</td>
+</tr>
+</table>
+",
+        EMAIL_BODY_STYLE, content
+    ));
+    if standalone {
+        html.push_str("</body>\n</html>");
+    }
+    Ok(Bytes::from(html))
+}
 
-                        for cell in &row.cells {
-                            let cell_html = generate_html_for_element(
-                                &cell.element,
-                                &mut image_num,
-                                &image_saver,
-                            )?;
+/// HTML with the style of [`EMAIL_STYLES`] added to the elements of its tags, before their
+/// own `style` so that it wins
+fn inline_styles(html: &str) -> String {
+    let mut styled = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        styled.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let name_length = rest[1..]
+            .find(|c: char| !c.is_ascii_alphanumeric())
+            .unwrap_or(rest.len() - 1);
+        let name = rest[1..1 + name_length].to_ascii_lowercase();
+        let Some((_, style)) = EMAIL_STYLES.iter().find(|(tag, _)| *tag == name) else {
+            styled.push('<');
+            rest = &rest[1..];
+            continue;
+        };
+        let tag_end = rest.find('>').unwrap_or(rest.len());
+        let tag = &rest[..tag_end];
+        match tag.find(" style=\"") {
+            Some(index) => {
+                let index = index + " style=\"".len();
+                styled.push_str(&tag[..index]);
+                styled.push_str(style);
+                styled.push(' ');
+                styled.push_str(&tag[index..]);
+            }
+            None => {
+                styled.push_str(&tag[..1 + name_length]);
+                styled.push_str(&format!(" style=\"{}\"", style));
+                styled.push_str(&tag[1 + name_length..]);
+            }
+        }
+        rest = &rest[tag_end..];
+    }
+    styled.push_str(rest);
+    styled
+}
 
-                            table_html.push_str(&format!("<td>{}</td>\n", cell_html));
-                        }
+/// Style of the cell holding the content of an email
+const EMAIL_BODY_STYLE: &str = "padding: 24px; font-family: Arial, Helvetica, sans-serif; font-size: 16px; line-height: 1.5; color: #222222;";
 
-                        table_html.push_str("</tr>\n");
-                    }
+/// Inline styles of an email by tag, the defaults of [`THEME_CSS`] in pixels and hex colors
+const EMAIL_STYLES: &[(&str, &str)] = &[
+    ("h1", "margin: 24px 0 12px; font-size: 28px; line-height: 1.25;"),
+    ("h2", "margin: 24px 0 12px; font-size: 24px; line-height: 1.25;"),
+    ("h3", "margin: 20px 0 10px; font-size: 20px; line-height: 1.25;"),
+    ("h4", "margin: 16px 0 8px; font-size: 18px; line-height: 1.25;"),
+    ("h5", "margin: 16px 0 8px; font-size: 16px; line-height: 1.25;"),
+    ("h6", "margin: 16px 0 8px; font-size: 14px; line-height: 1.25;"),
+    ("p", "margin: 0 0 16px;"),
+    ("a", "color: #0550ae; text-decoration: underline;"),
+    ("img", "max-width: 100%; height: auto; border: 0;"),
+    ("table", "border-collapse: collapse; margin: 0 0 16px;"),
+    ("th", "border: 1px solid #cccccc; padding: 6px 10px; background-color: #f0f0f0; text-align: left;"),
+    ("td", "border: 1px solid #cccccc; padding: 6px 10px;"),
+    ("ul", "margin: 0 0 16px; padding-left: 24px;"),
+    ("ol", "margin: 0 0 16px; padding-left: 24px;"),
+    ("pre", "margin: 0 0 16px; padding: 12px; background-color: #f5f5f5; white-space: pre-wrap; font-family: Consolas, Menlo, monospace; font-size: 14px;"),
+    ("code", "font-family: Consolas, Menlo, monospace; font-size: 14px;"),
+    ("figure", "margin: 0 0 16px;"),
+    ("figcaption", "font-size: 14px; color: #555555;"),
+];
+
+/// What the blocks of a container are generated with
+struct Blocks<'a> {
+    document: &'a Document,
+    /// Headers get anchors only when there is a table of contents linking to them
+    has_toc: bool,
+    /// Headers start `<section>` elements and titled images are figures
+    semantic: bool,
+}
 
-                    table_html.push_str("</table>\n");
-                    html.push_str(&table_html)
+/// HTML of the blocks of the body or of a container
+fn generate_blocks(
+    elements: &[&Element],
+    blocks: &Blocks,
+    image_num: &mut i32,
+    image_saver: &ImageSaver<impl Fn(&Bytes, &str) -> anyhow::Result<()>>,
+) -> anyhow::Result<String> {
+    let mut html = String::new();
+    // levels of the headers of the open sections
+    let mut sections: Vec<u8> = vec![];
+    for element in elements {
+        if blocks.semantic {
+            if let Some(level) = header_level(element) {
+                while sections.last().is_some_and(|open| *open >= level) {
+                    sections.pop();
+                    html.push_str("</section>\n");
                 }
-                _ => {}
+                html.push_str("<section>\n");
+                sections.push(level);
             }
         }
+        match element {
+            Element::Header { level, text } => {
+                if blocks.has_toc {
+                    html.push_str(&format!(
+                        "<h{} id=\"{}\">{}</h{}>\n",
+                        level,
+                        header_anchor(text),
+                        escape_html(text),
+                        level
+                    ));
+                } else {
+                    html.push_str(&format!("<h{}>{}</h{}>\n", level, escape_html(text), level));
+                }
+            }
+            TableOfContents { max_level } => {
+                let toc = blocks.document.build_toc(*max_level);
+                html.push_str("<nav>\n");
+                html.push_str(&generate_html_for_element(&toc, image_num, image_saver)?);
+                html.push_str("</nav>\n");
+            }
+            Element::Text { text, size: _ } => {
+                html.push_str(&format!("<p>{}</p>\n", escape_html(text)));
+            }
+            Paragraph {
+                elements,
+                direction,
+            } => {
+                html.push_str(&format!("<p{}>", direction_attribute(direction)));
 
-        html.push_str("</body>\n</html>");
+                for child in elements {
+                    html.push_str(&generate_html_for_element(child, image_num, image_saver)?);
+                }
 
-        Ok(Bytes::from(html))
+                html.push_str("</p>\n");
+            }
+            List { .. } => {
+                let list = generate_html_for_element(element, image_num, image_saver)?;
+
+                html.push_str(&list);
+            }
+            Table { .. } => {
+                html.push_str(&generate_html_for_element(element, image_num, image_saver)?);
+            }
+            LineBreak => {
+                html.push_str("<br />\n");
+            }
+            // a link outside a paragraph, such as the link of a media element in an email
+            Hyperlink { .. } | Math { .. } => {
+                html.push_str(&format!(
+                    "<p>{}</p>\n",
+                    generate_html_for_element(element, image_num, image_saver)?
+                ));
+            }
+            Bookmark { .. } | Media { .. } | Drawing { .. } | CodeBlock { .. } | Raw { .. } => {
+                html.push_str(&generate_html_for_element(element, image_num, image_saver)?);
+                html.push('\n');
+            }
+            Attributed {
+                attributes,
+                element,
+            } => {
+                let element_html = match element.as_ref() {
+                    Element::Text { text, size: _ } => format!("<p>{}</p>", text),
+                    Container { .. } => {
+                        generate_blocks(&[element.as_ref()], blocks, image_num, image_saver)?
+                    }
+                    element => generate_html_for_element(element, image_num, image_saver)?,
+                };
+                html.push_str(&add_html_attributes(element_html, attributes));
+                html.push('\n');
+            }
+            Container { kind, elements } => {
+                let children: Vec<&Element> = elements.iter().collect();
+                html.push_str(&format!(
+                    "<{kind}>\n{}</{kind}>\n",
+                    generate_blocks(&children, blocks, image_num, image_saver)?
+                ));
+            }
+            Image(image) if blocks.semantic && !image.title().is_empty() => {
+                html.push_str(&format!(
+                    "<figure>\n{}\n<figcaption>{}</figcaption>\n</figure>\n",
+                    generate_html_for_element(element, image_num, image_saver)?,
+                    image.title()
+                ));
+            }
+            Image(_) => {
+                html.push_str(&generate_html_for_element(element, image_num, image_saver)?);
+                html.push('\n');
+            }
+            _ => {}
+        }
+    }
+    for _ in sections {
+        html.push_str("</section>\n");
+    }
+    Ok(html)
+}
+
+/// Level of a header, also when it carries attributes
+fn header_level(element: &Element) -> Option<u8> {
+    match element {
+        Header { level, .. } => Some(*level),
+        Attributed { element, .. } => header_level(element),
+        _ => None,
     }
 }
 
@@ -148,6 +563,8 @@ where
     F: Fn(&str) -> anyhow::Result<Bytes>,
 {
     pub function: F,
+    /// Active content is dropped while parsing, see [`HtmlParseOptions::sanitize`]
+    pub sanitize: bool,
 }
 
 struct ImageSaver<F>
@@ -155,6 +572,57 @@ where
     F: Fn(&Bytes, &str) -> anyhow::Result<()>,
 {
     pub function: F,
+    /// Images embedded as data URIs instead of being saved
+    pub embed: bool,
+    /// Saved images referenced by `cid:` URLs, as attachments of an email
+    pub cid: bool,
+}
+
+/// List of the `li` children of a `ul` or `ol` element, a list directly inside the list
+/// (as generated for nested lists) is an item of its own
+fn parse_list<F>(
+    node: NodeRef<Node>,
+    element: &scraper::node::Element,
+    image_loader: &ImageLoader<F>,
+) -> anyhow::Result<Element>
+where
+    F: Fn(&str) -> anyhow::Result<Bytes>,
+{
+    let mut list_items: Vec<ListItem> = Vec::new();
+    let numbered = element.name() == "ol";
+    let start = element
+        .attr("start")
+        .and_then(|start| start.trim().parse().ok())
+        .unwrap_or(1);
+    let numbering = element
+        .attr("type")
+        .and_then(numbering_from_html_type)
+        .unwrap_or_default();
+    for list_child in node.children() {
+        if let Node::Element(ref li_element) = list_child.value() {
+            match li_element.name() {
+                "li" => {
+                    let mut item_elements: Vec<Element> = Vec::new();
+                    parse_html(list_child.children(), &mut item_elements, image_loader)?;
+                    list_items.extend(
+                        item_elements
+                            .into_iter()
+                            .map(|element| ListItem { element }),
+                    );
+                }
+                "ul" | "ol" => list_items.push(ListItem {
+                    element: parse_list(list_child, li_element, image_loader)?,
+                }),
+                _ => {}
+            }
+        }
+    }
+    Ok(List {
+        elements: list_items,
+        numbered,
+        start,
+        numbering,
+    })
 }
 
 fn parse_html<F>(
@@ -167,179 +635,1264 @@ where
 {
     for child in children {
         match child.value() {
-            Node::Element(ref element) => match element.name() {
-                "table" => {
-                    let mut headers: Vec<TableHeader> = Vec::new();
-                    let mut rows: Vec<TableRow> = Vec::new();
-                    for table_child in child.children() {
-                        for child in table_child.children() {
-                            match child.value() {
-                                Node::Element(ref table_element) => match table_element.name() {
-                                    "tr" => {
-                                        let mut cells: Vec<TableCell> = Vec::new();
-                                        let mut is_header = false;
-                                        for tr_child in child.children() {
-                                            match tr_child.value() {
-                                                Node::Element(ref tr_element) => {
-                                                    match tr_element.name() {
-                                                        "th" => {
-                                                            is_header = true;
-                                                            let mut header_elements: Vec<Element> =
-                                                                Vec::new();
-                                                            parse_html(
-                                                                tr_child.children(),
-                                                                &mut header_elements,
-                                                                image_loader,
-                                                            )?;
-                                                            headers.extend(
-                                                                header_elements.into_iter().map(
-                                                                    |element| TableHeader {
-                                                                        element,
+            Node::Element(ref element) => {
+                if image_loader.sanitize && ACTIVE_TAGS.contains(&element.name()) {
+                    continue;
+                }
+                let parsed_from = elements.len();
+                match element.name() {
+                    // read at its reference
+                    _ if is_referenced_note(child, element) => {}
+                    "table" => {
+                        let mut headers: Vec<TableHeader> = Vec::new();
+                        let mut rows: Vec<TableRow> = Vec::new();
+                        for table_child in child.children() {
+                            for child in table_child.children() {
+                                match child.value() {
+                                    Node::Element(ref table_element) => {
+                                        match table_element.name() {
+                                            "tr" => {
+                                                let mut cells: Vec<TableCell> = Vec::new();
+                                                let mut is_header = false;
+                                                for tr_child in child.children() {
+                                                    match tr_child.value() {
+                                                        Node::Element(ref tr_element) => {
+                                                            match tr_element.name() {
+                                                                "th" => {
+                                                                    is_header = true;
+                                                                    let mut header_elements: Vec<
+                                                                        Element,
+                                                                    > = Vec::new();
+                                                                    parse_html(
+                                                                        tr_child.children(),
+                                                                        &mut header_elements,
+                                                                        image_loader,
+                                                                    )?;
+                                                                    headers.push(TableHeader {
+                                                                        element: cell_element(
+                                                                            header_elements,
+                                                                        ),
                                                                         width: 10.0,
-                                                                    },
-                                                                ),
-                                                            );
-                                                        }
-                                                        "td" => {
-                                                            let mut cell_elements: Vec<Element> =
-                                                                Vec::new();
-                                                            parse_html(
-                                                                tr_child.children(),
-                                                                &mut cell_elements,
-                                                                image_loader,
-                                                            )?;
-                                                            cells.extend(
-                                                                cell_elements.into_iter().map(
-                                                                    |element| TableCell { element },
-                                                                ),
-                                                            );
+                                                                    });
+                                                                }
+                                                                "td" => {
+                                                                    let mut cell_elements: Vec<
+                                                                        Element,
+                                                                    > = Vec::new();
+                                                                    parse_html(
+                                                                        tr_child.children(),
+                                                                        &mut cell_elements,
+                                                                        image_loader,
+                                                                    )?;
+                                                                    cells.push(TableCell {
+                                                                        element: cell_element(
+                                                                            cell_elements,
+                                                                        ),
+                                                                    });
+                                                                }
+                                                                _ => { /*  */ }
+                                                            }
                                                         }
                                                         _ => { /*  */ }
                                                     }
                                                 }
-                                                _ => { /*  */ }
+                                                if !is_header {
+                                                    rows.push(TableRow { cells });
+                                                }
                                             }
-                                        }
-                                        if !is_header {
-                                            rows.push(TableRow { cells });
+                                            _ => { /*  */ }
                                         }
                                     }
                                     _ => { /*  */ }
-                                },
-                                _ => { /*  */ }
+                                }
                             }
                         }
+                        if !headers.is_empty() || !rows.is_empty() {
+                            elements.push(Table { headers, rows });
+                        }
                     }
-                    if !headers.is_empty() || !rows.is_empty() {
-                        elements.push(Table { headers, rows });
+                    "p" | "title" => {
+                        let mut paragraph_elements: Vec<Element> = Vec::new();
+                        parse_html(child.children(), &mut paragraph_elements, image_loader)?;
+                        elements.push(Paragraph {
+                            elements: paragraph_elements,
+                            direction: element.attr("dir").map(parse_direction).unwrap_or_default(),
+                        });
                     }
-                }
-                "p" | "title" => {
-                    let mut paragraph_elements: Vec<Element> = Vec::new();
-                    parse_html(child.children(), &mut paragraph_elements, image_loader)?;
-                    elements.push(Paragraph {
-                        elements: paragraph_elements,
-                    });
-                }
-                "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
-                    let level = element.name().as_bytes()[1] - b'0';
-                    // Retrieve the deepest text within any nested structure of the same header tag
-                    let mut text = retrieve_deep_text(child, element.name()).trim().to_string();
+                    "br" => {
+                        elements.push(LineBreak);
+                    }
+                    "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                        let level = element.name().as_bytes()[1] - b'0';
+                        // Retrieve the deepest text within any nested structure of the same header tag
+                        let mut text = retrieve_deep_text(child, element.name()).trim().to_string();
 
-                    if text.is_empty() {
+                        if text.is_empty() {
+                            continue;
+                        }
+
+                        if text.contains("\n") {
+                            text = text
+                                .lines()
+                                .map(str::trim)
+                                .filter(|line| !line.is_empty()) // handles multiple consecutive newlines (\n\n)
+                                .collect::<Vec<_>>()
+                                .join(" ");
+                        }
+
+                        elements.push(Header { text, level });
+                    }
+                    "img" => {
+                        let src = image_source(element);
+                        if !(image_loader.sanitize && is_script_url(&src)) {
+                            elements.push(parse_image(element, &src, image_loader)?);
+                        }
+                    }
+                    // the first source of a format that is read wins over the image
+                    "picture" => {
+                        let children: Vec<&scraper::node::Element> = child
+                            .children()
+                            .filter_map(|node| node.value().as_element())
+                            .collect();
+                        if let Some(img) = children.iter().find(|child| child.name() == "img") {
+                            let src = children
+                                .iter()
+                                .filter(|child| child.name() == "source")
+                                .filter(|source| {
+                                    source.attr("type").is_none_or(|mime_type| {
+                                        ImageType::VARIANTS
+                                            .iter()
+                                            .any(|image_type| image_type.mime_type() == mime_type)
+                                    })
+                                })
+                                .find_map(|source| srcset_source(source))
+                                .unwrap_or_else(|| image_source(img));
+                            if !(image_loader.sanitize && is_script_url(&src)) {
+                                elements.push(parse_image(img, &src, image_loader)?);
+                            }
+                        }
+                    }
+                    "ul" | "ol" => {
+                        elements.push(parse_list(child, element, image_loader)?);
+                    }
+                    // the fallback content is kept when the media has no source
+                    "video" | "audio" | "iframe" => {
+                        let kind = match element.name() {
+                            "video" => MediaKind::Video,
+                            "audio" => MediaKind::Audio,
+                            _ => MediaKind::Frame,
+                        };
+                        let url = element
+                            .attr("src")
+                            .or_else(|| {
+                                child
+                                    .children()
+                                    .filter_map(|node| node.value().as_element())
+                                    .filter(|source| source.name() == "source")
+                                    .find_map(|source| source.attr("src"))
+                            })
+                            .unwrap_or_default();
+                        if url.is_empty() {
+                            parse_html(child.children(), elements, image_loader)?;
+                        } else if !(image_loader.sanitize && is_script_url(url)) {
+                            elements.push(Media {
+                                kind,
+                                url: url.to_string(),
+                                title: element
+                                    .attr("title")
+                                    .or_else(|| element.attr("aria-label"))
+                                    .unwrap_or_default()
+                                    .to_string(),
+                            });
+                        }
+                    }
+                    "a" if referenced_note(child).is_some() => {
+                        let mut note_elements: Vec<Element> = Vec::new();
+                        if let Some(note) = referenced_note(child) {
+                            parse_html(note.children(), &mut note_elements, image_loader)?;
+                        }
+                        let backlink = element.id().map(|id| format!("#{id}"));
+                        elements.push(Footnote {
+                            elements: note_content(note_elements, backlink.as_deref()),
+                        });
                         continue;
                     }
-
-                    if text.contains("\n") {
-                        text = text
-                            .lines()
-                            .map(str::trim)
-                            .filter(|line| !line.is_empty()) // handles multiple consecutive newlines (\n\n)
-                            .collect::<Vec<_>>()
-                            .join(" ");
+                    "a" if element.attr("href").is_none() && element.id().is_some() => {
+                        elements.push(Bookmark {
+                            id: element.id().unwrap_or_default().to_string(),
+                            title: element.attr("title").unwrap_or_default().to_string(),
+                        });
+                        parse_html(child.children(), elements, image_loader)?;
                     }
-
-                    elements.push(Header { text, level });
-                }
-                "img" => {
-                    let src = element.attr("src").unwrap_or_default();
-                    let title = element.attr("title").unwrap_or_default();
-                    let alt = element.attr("alt").unwrap_or_default();
-                    let align = element.attr("align").unwrap_or_default();
-                    let width = element.attr("width").and_then(|s| s.parse().ok());
-                    let height = element.attr("height").and_then(|s| s.parse().ok());
-                    let image_bytes = (image_loader.function)(src)?;
-                    elements.push(Image(ImageData::new(
-                        image_bytes,
-                        title.to_string(),
-                        alt.to_string(),
-                        src.to_string(),
-                        align.to_string(),
-                        ImageDimension { width, height },
-                    )));
-                }
-                "ul" | "ol" => {
-                    let mut list_items: Vec<ListItem> = Vec::new();
-                    let numbered = element.name() == "ol";
-                    for list_child in child.children() {
-                        if let Node::Element(ref li_element) = list_child.value() {
-                            if li_element.name() == "li" {
-                                let mut item_elements: Vec<Element> = Vec::new();
-                                parse_html(
-                                    list_child.children(),
-                                    &mut item_elements,
-                                    image_loader,
-                                )?;
-                                list_items.extend(
-                                    item_elements
-                                        .into_iter()
-                                        .map(|element| ListItem { element }),
-                                );
+                    // the text of a script link is kept without the link
+                    "a" if image_loader.sanitize
+                        && is_script_url(element.attr("href").unwrap_or_default()) =>
+                    {
+                        parse_html(child.children(), elements, image_loader)?;
+                    }
+                    "a" => {
+                        let href = element.attr("href").unwrap_or_default().to_string();
+                        let mut link_elements: Vec<Element> = Vec::new();
+                        parse_html(child.children(), &mut link_elements, image_loader)?;
+                        elements.push(Hyperlink {
+                            elements: link_elements,
+                            url: href,
+                            alt: "".to_owned(),
+                            size: 8,
+                        });
+                    }
+                    "pre" => {
+                        let code = ElementRef::wrap(child)
+                            .map(|pre| pre.text().collect::<String>())
+                            .unwrap_or_default();
+                        let info = child
+                            .children()
+                            .filter_map(|node| node.value().as_element())
+                            .find(|code| code.name() == "code")
+                            .and_then(|code| {
+                                code.classes()
+                                    .find_map(|class| class.strip_prefix("language-"))
+                            })
+                            .unwrap_or_default()
+                            .to_string();
+                        elements.push(CodeBlock {
+                            info,
+                            code: code.trim_end_matches('\n').to_string(),
+                        });
+                    }
+                    "code" => {
+                        let text: String = ElementRef::wrap(child)
+                            .map(|code| code.text().collect())
+                            .unwrap_or_default();
+                        elements.push(InlineCode { text });
+                    }
+                    "em" | "i" | "strong" | "b" | "del" | "s" => {
+                        let style = match element.name() {
+                            "em" | "i" => TextStyle::Emphasis,
+                            "strong" | "b" => TextStyle::Strong,
+                            _ => TextStyle::Strikethrough,
+                        };
+                        let mut styled_elements: Vec<Element> = Vec::new();
+                        parse_html(child.children(), &mut styled_elements, image_loader)?;
+                        elements.push(Styled {
+                            style,
+                            elements: styled_elements,
+                        });
+                    }
+                    "article" | "section" | "nav" | "aside" => {
+                        let mut container_elements: Vec<Element> = Vec::new();
+                        parse_html(child.children(), &mut container_elements, image_loader)?;
+                        if !container_elements.is_empty() {
+                            elements.push(Container {
+                                kind: ContainerKind::from_str(element.name())?,
+                                elements: container_elements,
+                            });
+                        }
+                    }
+                    // a figure of one image is the image titled by the caption
+                    "figure" => {
+                        let is_caption = |node: &NodeRef<Node>| {
+                            node.value()
+                                .as_element()
+                                .is_some_and(|element| element.name() == "figcaption")
+                        };
+                        let caption = child
+                            .children()
+                            .find(is_caption)
+                            .and_then(ElementRef::wrap)
+                            .map(|caption| {
+                                caption
+                                    .text()
+                                    .collect::<String>()
+                                    .split_whitespace()
+                                    .collect::<Vec<_>>()
+                                    .join(" ")
+                            })
+                            .unwrap_or_default();
+                        let mut figure_elements: Vec<Element> = Vec::new();
+                        parse_html(child.children(), &mut figure_elements, image_loader)?;
+                        match figure_elements.as_mut_slice() {
+                            [Image(image)] if !caption.is_empty() => {
+                                image.set_image_title(&caption)
+                            }
+                            [Attributed { element, .. }] if !caption.is_empty() => {
+                                if let Image(image) = element.as_mut() {
+                                    image.set_image_title(&caption);
+                                }
                             }
+                            _ if !caption.is_empty() => figure_elements.push(Paragraph {
+                                elements: vec![Text {
+                                    text: caption,
+                                    size: 8,
+                                }],
+                                direction: TextDirection::default(),
+                            }),
+                            _ => {}
                         }
+                        elements.extend(figure_elements);
+                    }
+                    // read by its figure
+                    "figcaption" => {}
+                    "span" if element.attr("class") == Some("footnote") => {
+                        let mut note_elements: Vec<Element> = Vec::new();
+                        parse_html(child.children(), &mut note_elements, image_loader)?;
+                        elements.push(Footnote {
+                            elements: note_elements,
+                        });
+                        // the class is the footnote itself, not an attribute to keep
+                        continue;
+                    }
+                    "span"
+                        if matches!(
+                            element.attr("class"),
+                            Some("math inline" | "math display")
+                        ) =>
+                    {
+                        let display = element.attr("class") == Some("math display");
+                        let text: String = ElementRef::wrap(child)
+                            .map(|span| span.text().collect())
+                            .unwrap_or_default();
+                        let text = text.trim();
+                        let (open, close) = if display {
+                            ("\\[", "\\]")
+                        } else {
+                            ("\\(", "\\)")
+                        };
+                        let tex = text
+                            .strip_prefix(open)
+                            .and_then(|text| text.strip_suffix(close))
+                            .unwrap_or(text);
+                        elements.push(Math {
+                            tex: tex.trim().to_string(),
+                            display,
+                        });
+                        continue;
+                    }
+                    _ => {
+                        parse_html(child.children(), elements, image_loader)?;
                     }
-                    elements.push(List {
-                        elements: list_items,
-                        numbered,
-                    });
                 }
-                "a" => {
-                    let href = element.attr("href").unwrap_or_default().to_string();
-                    let text = child
-                        .children()
-                        .filter_map(|n| {
-                            if let Node::Text(ref text) = n.value() {
-                                Some(text.clone().to_string())
-                            } else {
-                                None
-                            }
-                        })
-                        .collect::<Vec<String>>()
-                        .join("");
-                    elements.push(Hyperlink {
-                        title: text,
-                        url: href,
-                        alt: "".to_owned(),
+                if elements.len() == parsed_from + 1 && ATTRIBUTED_TAGS.contains(&element.name()) {
+                    let attributes = html_attributes(element, image_loader.sanitize);
+                    if let Some(parsed) = elements.pop() {
+                        elements.push(parsed.with_attributes(attributes));
+                    }
+                }
+            }
+            Node::Text(ref text) if is_inline_text(child) => {
+                let text = inline_text(child, text);
+                if !text.is_empty() {
+                    elements.push(Text { text, size: 8 });
+                }
+            }
+            Node::Text(ref text) => {
+                let txt_strings = text.lines().map(str::trim).filter(|p| !p.is_empty());
+                for text_str in txt_strings {
+                    elements.push(Text {
+                        text: text_str.to_owned(),
                         size: 8,
                     });
                 }
-                _ => {
-                    parse_html(child.children(), elements, image_loader)?;
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Whether a node is an element of text, as styled text or a link
+fn is_inline_element(node: NodeRef<Node>) -> bool {
+    node.value()
+        .as_element()
+        .is_some_and(|element| INLINE_TAGS.contains(&element.name()))
+}
+
+/// Whether a text runs along styled text or links, its spaces then separate it from them
+fn is_inline_text(node: NodeRef<Node>) -> bool {
+    node.parent().is_some_and(is_inline_element)
+        || node.prev_sibling().is_some_and(is_inline_element)
+        || node.next_sibling().is_some_and(is_inline_element)
+}
+
+/// Text of an inline text node, its runs of whitespace collapsed to one space as browsers do,
+/// the spaces opening or closing a block or next to a line break or a block dropped
+fn inline_text(node: NodeRef<Node>, text: &str) -> String {
+    let in_block = !node.parent().is_some_and(is_inline_element);
+    let keeps_space = |sibling: Option<NodeRef<Node>>| match sibling {
+        Some(sibling) => sibling.value().is_text() || is_inline_element(sibling),
+        None => !in_block,
+    };
+    let mut collapsed = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if collapsed.is_empty() {
+        // a space between two inline elements, not one between blocks
+        if keeps_space(node.prev_sibling()) && keeps_space(node.next_sibling()) {
+            collapsed.push(' ');
+        }
+        return collapsed;
+    }
+    if text.starts_with(char::is_whitespace) && keeps_space(node.prev_sibling()) {
+        collapsed.insert(0, ' ');
+    }
+    if text.ends_with(char::is_whitespace) && keeps_space(node.next_sibling()) {
+        collapsed.push(' ');
+    }
+    collapsed
+}
+
+/// Whether the element references a note, as the `noteref` links of the EPUB books
+fn is_note_reference(element: &scraper::node::Element) -> bool {
+    element
+        .attr("epub:type")
+        .is_some_and(|types| types.split_whitespace().any(|kind| kind == "noteref"))
+        || element.attr("role") == Some("doc-noteref")
+}
+
+/// Whether the element is a footnote or an endnote of an EPUB book
+fn is_note(element: &scraper::node::Element) -> bool {
+    element.attr("epub:type").is_some_and(|types| {
+        types
+            .split_whitespace()
+            .any(|kind| matches!(kind, "footnote" | "endnote" | "rearnote"))
+    }) || matches!(element.attr("role"), Some("doc-footnote" | "doc-endnote"))
+}
+
+/// Note of the page referenced by the `href` fragment of a note reference
+fn referenced_note(link: NodeRef<Node>) -> Option<NodeRef<Node>> {
+    let element = link.value().as_element()?;
+    if !is_note_reference(element) {
+        return None;
+    }
+    let id = element.attr("href")?.strip_prefix('#')?;
+    link.tree().root().descendants().find(|node| {
+        node.value()
+            .as_element()
+            .is_some_and(|note| is_note(note) && note.id() == Some(id))
+    })
+}
+
+/// Whether the element is a note referenced in the page, read as a footnote at its reference
+fn is_referenced_note(node: NodeRef<Node>, element: &scraper::node::Element) -> bool {
+    let Some(id) = element.id().filter(|_| is_note(element)) else {
+        return false;
+    };
+    node.tree().root().descendants().any(|other| {
+        other.value().as_element().is_some_and(|link| {
+            is_note_reference(link)
+                && link.attr("href").and_then(|href| href.strip_prefix('#')) == Some(id)
+        })
+    })
+}
+
+/// Content of a note read at its reference: the link back to the reference is dropped and
+/// the text of its first paragraph follows the reference
+fn note_content(mut elements: Vec<Element>, backlink: Option<&str>) -> Vec<Element> {
+    let is_backlink = |element: &Element| matches!(element, Hyperlink { url, .. } if backlink == Some(url.as_str()));
+    elements.retain(|element| !is_backlink(element));
+    if let Some(Paragraph {
+        elements: first, ..
+    }) = elements.first_mut()
+    {
+        first.retain(|element| !is_backlink(element));
+        // the space that followed the backlink
+        if let Some(Text { text, .. }) = first.first_mut() {
+            *text = text.trim_start().to_string();
+            if text.is_empty() {
+                first.remove(0);
+            }
+        }
+        let mut content = std::mem::take(first);
+        content.extend(elements.into_iter().skip(1));
+        return content;
+    }
+    elements
+}
+
+/// Text of an HTML page in the encoding of its byte order mark, else of the `charset` it was
+/// sent with, else of its `<meta charset>`, else UTF-8 when it is valid and windows-1252 like
+/// browsers otherwise. Invalid bytes are replaced rather than failing the parse.
+pub(crate) fn decode_html(bytes: &[u8], charset: Option<&'static Encoding>) -> String {
+    let encoding = Encoding::for_bom(bytes)
+        .map(|(encoding, _)| encoding)
+        .or(charset)
+        .or_else(|| meta_charset(bytes))
+        .unwrap_or(match std::str::from_utf8(bytes) {
+            Ok(_) => UTF_8,
+            Err(_) => WINDOWS_1252,
+        });
+    encoding.decode(bytes).0.into_owned()
+}
+
+/// Encoding named by the `charset` of the first 1024 bytes, as in
+/// `<meta charset="iso-8859-1">` or `<meta http-equiv="Content-Type" content="text/html; charset=Shift_JIS">`
+fn meta_charset(bytes: &[u8]) -> Option<&'static Encoding> {
+    let head = String::from_utf8_lossy(&bytes[..bytes.len().min(1024)]).to_lowercase();
+    let label = head
+        .match_indices("charset")
+        .filter(|(index, _)| head[..*index].rfind('<') > head[..*index].rfind('>'))
+        .find_map(|(index, _)| {
+            let value = head[index + "charset".len()..]
+                .trim_start()
+                .strip_prefix('=')?
+                .trim_start()
+                .trim_start_matches(['"', '\'']);
+            let end = value
+                .find(|c: char| c.is_whitespace() || "\"';>/".contains(c))
+                .unwrap_or(value.len());
+            Some(value[..end].to_string())
+        })?;
+    // a page declared in UTF-16 but read as ASCII bytes is UTF-8, as browsers read it
+    match Encoding::for_label(label.as_bytes())? {
+        encoding if encoding == UTF_16LE || encoding == UTF_16BE => Some(UTF_8),
+        encoding => Some(encoding),
+    }
+}
+
+/// Page saved by a browser with its images as a MIME `multipart/related` archive
+/// (`.mht`, `.mhtml`)
+struct Mhtml {
+    html: Bytes,
+    /// `charset` of the page part
+    charset: Option<&'static Encoding>,
+    /// `Content-Location` of the page, the relative sources of its images are resolved against it
+    location: String,
+    /// Content of the other parts by `Content-Location` and by `cid:` URL of their `Content-ID`
+    resources: HashMap<String, Bytes>,
+}
+
+impl Mhtml {
+    /// Archive read from the document, `None` when the document is not a MIME message
+    fn parse(document: &[u8]) -> anyhow::Result<Option<Mhtml>> {
+        // an HTML page starts with a tag, a byte order mark or blank space, never with a header
+        if !document.first().is_some_and(u8::is_ascii_alphabetic) {
+            return Ok(None);
+        }
+        let (headers, body) = mime_part(document);
+        let content_type = headers.get("content-type").cloned().unwrap_or_default();
+        if !content_type
+            .to_ascii_lowercase()
+            .starts_with("multipart/related")
+        {
+            return Ok(None);
+        }
+        let boundary = header_parameter(&content_type, "boundary")
+            .ok_or_else(|| anyhow::anyhow!("MHTML archive without boundary"))?;
+
+        let mut page = None;
+        let mut resources = HashMap::new();
+        for part in multipart_parts(body, &boundary) {
+            let (headers, body) = mime_part(part);
+            let content = decode_transfer_encoding(
+                body,
+                headers
+                    .get("content-transfer-encoding")
+                    .map(String::as_str)
+                    .unwrap_or_default(),
+            )?;
+            let location = headers.get("content-location").cloned().unwrap_or_default();
+            let part_type = headers.get("content-type").cloned().unwrap_or_default();
+            // the first page is the document, the next ones are the pages of its frames
+            if page.is_none() && part_type.to_ascii_lowercase().starts_with("text/html") {
+                let charset = header_parameter(&part_type, "charset")
+                    .and_then(|label| Encoding::for_label(label.as_bytes()));
+                page = Some((content, charset, location));
+                continue;
+            }
+            if let Some(id) = headers.get("content-id") {
+                resources.insert(
+                    format!("cid:{}", id.trim_matches(['<', '>'])),
+                    content.clone(),
+                );
+            }
+            if !location.is_empty() {
+                resources.insert(location, content);
+            }
+        }
+        let (html, charset, location) =
+            page.ok_or_else(|| anyhow::anyhow!("MHTML archive without an HTML page"))?;
+        Ok(Some(Mhtml {
+            html,
+            charset,
+            location,
+            resources,
+        }))
+    }
+
+    /// Content of the part of an image source, as written or resolved against the page
+    fn resource(&self, src: &str) -> Option<Bytes> {
+        self.resources
+            .get(src)
+            .or_else(|| {
+                let url = url::Url::parse(&self.location).ok()?.join(src).ok()?;
+                self.resources.get(url.as_str())
+            })
+            .cloned()
+    }
+}
+
+/// Headers of a MIME part, by lowercase name, and its body
+pub(crate) fn mime_part(part: &[u8]) -> (HashMap<String, String>, &[u8]) {
+    let (head, body) = if part.starts_with(b"\r\n") || part.starts_with(b"\n") {
+        (&part[..0], part.strip_prefix(b"\r\n").unwrap_or(&part[1..]))
+    } else {
+        let crlf = find_bytes(part, b"\r\n\r\n").map(|index| (index, index + 4));
+        let lf = find_bytes(part, b"\n\n").map(|index| (index, index + 2));
+        match crlf.into_iter().chain(lf).min() {
+            Some((end, start)) => (&part[..end], &part[start..]),
+            None => (part, &part[part.len()..]),
+        }
+    };
+    let mut headers: HashMap<String, String> = HashMap::new();
+    let mut last = None;
+    for line in String::from_utf8_lossy(head).lines() {
+        // folded header, continued on the next line
+        if line.starts_with([' ', '\t']) {
+            if let Some(value) = last.as_ref().and_then(|name| headers.get_mut(name)) {
+                value.push(' ');
+                value.push_str(line.trim());
+            }
+        } else if let Some((name, value)) = line.split_once(':') {
+            let name = name.trim().to_ascii_lowercase();
+            headers.insert(name.clone(), value.trim().to_string());
+            last = Some(name);
+        }
+    }
+    (headers, body)
+}
+
+/// Parameter of a header value, as `boundary` in `multipart/related; boundary="..."`
+pub(crate) fn header_parameter(value: &str, name: &str) -> Option<String> {
+    value.split(';').skip(1).find_map(|parameter| {
+        let (key, value) = parameter.split_once('=')?;
+        key.trim()
+            .eq_ignore_ascii_case(name)
+            .then(|| value.trim().trim_matches('"').to_string())
+    })
+}
+
+/// Parts of a multipart body, between the `--boundary` delimiters
+pub(crate) fn multipart_parts<'a>(body: &'a [u8], boundary: &str) -> Vec<&'a [u8]> {
+    let delimiter = format!("--{}", boundary);
+    let delimiter = delimiter.as_bytes();
+    let mut starts = vec![];
+    let mut index = 0;
+    while let Some(found) = find_bytes(&body[index..], delimiter) {
+        starts.push(index + found);
+        index += found + delimiter.len();
+    }
+    starts
+        .windows(2)
+        .map(|window| {
+            let part = &body[window[0] + delimiter.len()..window[1]];
+            let part = part
+                .strip_prefix(b"\r\n")
+                .or_else(|| part.strip_prefix(b"\n"))
+                .unwrap_or(part);
+            part.strip_suffix(b"\r\n")
+                .or_else(|| part.strip_suffix(b"\n"))
+                .unwrap_or(part)
+        })
+        .collect()
+}
+
+fn find_bytes(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// Body of a MIME part decoded from its `Content-Transfer-Encoding`
+pub(crate) fn decode_transfer_encoding(body: &[u8], encoding: &str) -> anyhow::Result<Bytes> {
+    use base64::Engine;
+
+    match encoding.trim().to_ascii_lowercase().as_str() {
+        "base64" => {
+            let content: Vec<u8> = body
+                .iter()
+                .copied()
+                .filter(|byte| !byte.is_ascii_whitespace())
+                .collect();
+            Ok(Bytes::from(
+                base64::engine::general_purpose::STANDARD.decode(content)?,
+            ))
+        }
+        "quoted-printable" => {
+            let mut decoded = Vec::with_capacity(body.len());
+            let mut index = 0;
+            while index < body.len() {
+                let rest = &body[index..];
+                if rest.starts_with(b"=\r\n") {
+                    index += 3;
+                } else if rest.starts_with(b"=\n") {
+                    index += 2;
+                } else if let Some(byte) = rest
+                    .get(1..3)
+                    .filter(|_| rest[0] == b'=')
+                    .and_then(|hex| std::str::from_utf8(hex).ok())
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                {
+                    decoded.push(byte);
+                    index += 3;
+                } else {
+                    decoded.push(rest[0]);
+                    index += 1;
+                }
+            }
+            Ok(Bytes::from(decoded))
+        }
+        // 7bit, 8bit and binary
+        _ => Ok(Bytes::copy_from_slice(body)),
+    }
+}
+
+/// Image of an `<img>` element read from the given source
+fn parse_image<F>(
+    img: &scraper::node::Element,
+    src: &str,
+    image_loader: &ImageLoader<F>,
+) -> anyhow::Result<Element>
+where
+    F: Fn(&str) -> anyhow::Result<Bytes>,
+{
+    let title = img.attr("title").unwrap_or_default();
+    let alt = img.attr("alt").unwrap_or_default();
+    let align = img.attr("align").unwrap_or_default();
+    let width = img.attr("width").and_then(|s| s.parse().ok());
+    let height = img.attr("height").and_then(|s| s.parse().ok());
+    let image_bytes = (image_loader.function)(src)?;
+    Ok(Image(ImageData::new(
+        image_bytes,
+        title.to_string(),
+        alt.to_string(),
+        src.to_string(),
+        align.to_string(),
+        ImageDimension { width, height },
+    )))
+}
+
+/// Attributes of the source of a lazy loaded image, whose `src` is a placeholder
+const LAZY_SOURCE_ATTRIBUTES: [&str; 3] = ["data-src", "data-lazy-src", "data-original"];
+
+/// Source of an `<img>`: the largest candidate of its `srcset`, else its lazy loaded
+/// source, else its `src`
+fn image_source(img: &scraper::node::Element) -> String {
+    srcset_source(img)
+        .or_else(|| {
+            LAZY_SOURCE_ATTRIBUTES
+                .iter()
+                .find_map(|name| img.attr(name))
+                .map(str::to_string)
+        })
+        .unwrap_or_else(|| img.attr("src").unwrap_or_default().to_string())
+}
+
+/// Largest candidate of the `srcset` of an element, the widest (`640w`) or the densest
+/// (`2x`) one
+fn srcset_source(element: &scraper::node::Element) -> Option<String> {
+    element
+        .attr("data-srcset")
+        .or(element.attr("srcset"))?
+        .split(',')
+        .filter_map(|candidate| {
+            let mut parts = candidate.split_whitespace();
+            let url = parts.next()?;
+            let descriptor = parts.next().unwrap_or("1x");
+            let size: f32 = descriptor
+                .strip_suffix(['w', 'x'])
+                .and_then(|size| size.parse().ok())?;
+            Some((url, size))
+        })
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(url, _)| url.to_string())
+}
+
+/// Element of a table cell made of the parsed elements: the inline elements are grouped in
+/// paragraphs, a paragraph of text only is kept as text and several blocks are grouped in
+/// a [`ContainerKind::Div`]
+fn cell_element(elements: Vec<Element>) -> Element {
+    fn is_block(element: &Element) -> bool {
+        match element {
+            Attributed { element, .. } => is_block(element),
+            Paragraph { .. }
+            | Header { .. }
+            | List { .. }
+            | Table { .. }
+            | CodeBlock { .. }
+            | Container { .. }
+            | TableOfContents { .. } => true,
+            _ => false,
+        }
+    }
+
+    let mut blocks: Vec<Element> = vec![];
+    let mut inline: Vec<Element> = vec![];
+    for element in elements {
+        if is_block(&element) {
+            if !inline.is_empty() {
+                blocks.push(Paragraph {
+                    elements: std::mem::take(&mut inline),
+                    direction: TextDirection::default(),
+                });
+            }
+            blocks.push(element);
+        } else {
+            inline.push(element);
+        }
+    }
+    if !inline.is_empty() {
+        blocks.push(Paragraph {
+            elements: inline,
+            direction: TextDirection::default(),
+        });
+    }
+    match <[Element; 1]>::try_from(blocks) {
+        Ok([Paragraph { elements, .. }])
+            if elements
+                .iter()
+                .all(|element| matches!(element, Text { .. })) =>
+        {
+            Text {
+                text: elements
+                    .iter()
+                    .map(|element| element.plain_text())
+                    .collect::<Vec<_>>()
+                    .join(" "),
+                size: 8,
+            }
+        }
+        Ok([Paragraph { mut elements, .. }]) if elements.len() == 1 => elements.remove(0),
+        Ok([block]) => block,
+        Err(blocks) if blocks.is_empty() => Text {
+            text: String::new(),
+            size: 8,
+        },
+        Err(blocks) => Container {
+            kind: ContainerKind::Div,
+            elements: blocks,
+        },
+    }
+}
+
+/// Default stylesheet of a standalone page, readable text in a centered column
+const THEME_CSS: &str = "body {
+  max-width: 48em;
+  margin: 0 auto;
+  padding: 1em 2em;
+  font-family: system-ui, -apple-system, \"Segoe UI\", Roboto, sans-serif;
+  line-height: 1.6;
+  color: #222;
+}
+h1, h2, h3, h4, h5, h6 { line-height: 1.25; margin: 1.5em 0 0.5em; }
+a { color: #0550ae; }
+img { max-width: 100%; height: auto; }
+pre, code { font-family: ui-monospace, Menlo, Consolas, monospace; background: #f5f5f5; }
+pre { padding: 1em; overflow-x: auto; }
+table { border-collapse: collapse; }
+th, td { border: 1px solid #ccc; padding: 0.3em 0.6em; }
+th { background: #f0f0f0; }
+blockquote { border-left: 4px solid #ddd; margin-left: 0; padding-left: 1em; color: #555; }
+";
+
+/// Tags whose classes, styles and data attributes are kept on the parsed element
+const ATTRIBUTED_TAGS: [&str; 23] = [
+    "p", "h1", "h2", "h3", "h4", "h5", "h6", "img", "ul", "ol", "table", "a", "span", "article",
+    "section", "nav", "aside", "em", "i", "strong", "b", "del", "s",
+];
+
+/// Tags of the content that runs in the browser, dropped by a sanitizing parse
+pub(crate) const ACTIVE_TAGS: [&str; 5] = ["script", "iframe", "object", "embed", "applet"];
+
+/// Tags of styled text and links, the text along them keeps its spaces
+const INLINE_TAGS: [&str; 9] = ["a", "em", "i", "strong", "b", "del", "s", "code", "span"];
+
+/// Attributes kept through a round trip, other attributes are mapped to element fields
+/// or dropped
+fn is_kept_attribute(name: &str) -> bool {
+    matches!(name, "class" | "style") || name.starts_with("data-")
+}
+
+/// Kept attributes of the element, the `on*` event handlers never are. A sanitizing parse
+/// also drops the values that run scripts, such as `expression()` styles.
+fn html_attributes(element: &scraper::node::Element, sanitize: bool) -> HashMap<String, String> {
+    element
+        .attrs()
+        .filter(|(name, _)| is_kept_attribute(name))
+        .filter(|(_, value)| !sanitize || !is_script_value(value))
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect()
+}
+
+/// Attribute value that runs a script, such as a `javascript:` URL or an `expression()` style
+pub(crate) fn is_script_value(value: &str) -> bool {
+    let value = value.to_lowercase();
+    value.contains("javascript:") || value.contains("expression(")
+}
+
+/// URL that runs a script when followed, browsers ignore the whitespace and control
+/// characters of the scheme
+pub(crate) fn is_script_url(url: &str) -> bool {
+    let url: String = url
+        .chars()
+        .filter(|c| !c.is_whitespace() && !c.is_control())
+        .collect::<String>()
+        .to_lowercase();
+    ["javascript:", "vbscript:", "data:text/html"]
+        .iter()
+        .any(|scheme| url.starts_with(scheme))
+}
+
+/// Sanitizes the raw HTML of every element of the document with [`sanitize_html`], the raw
+/// HTML of the other formats reaches the generated HTML too
+pub fn sanitize_raw_html(document: &mut Document) {
+    for band in document.bands.iter_mut() {
+        band.elements_mut()
+            .iter_mut()
+            .for_each(sanitize_element_raw_html);
+    }
+}
+
+fn sanitize_element_raw_html(element: &mut Element) {
+    match element {
+        Raw {
+            format: DocumentType::HTML,
+            content,
+        } => *content = sanitize_html(content),
+        element => {
+            for child in element.children_mut() {
+                sanitize_element_raw_html(child);
+            }
+        }
+    }
+}
+
+/// Tags kept by [`sanitize_html`], the other tags are dropped and their text kept
+const SAFE_TAGS: [&str; 65] = [
+    "a",
+    "abbr",
+    "b",
+    "bdi",
+    "bdo",
+    "blockquote",
+    "br",
+    "caption",
+    "center",
+    "cite",
+    "code",
+    "col",
+    "colgroup",
+    "dd",
+    "del",
+    "details",
+    "dfn",
+    "div",
+    "dl",
+    "dt",
+    "em",
+    "figcaption",
+    "figure",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "hr",
+    "i",
+    "img",
+    "ins",
+    "kbd",
+    "li",
+    "mark",
+    "ol",
+    "p",
+    "pre",
+    "q",
+    "rp",
+    "rt",
+    "ruby",
+    "s",
+    "samp",
+    "small",
+    "span",
+    "strike",
+    "strong",
+    "sub",
+    "summary",
+    "sup",
+    "table",
+    "tbody",
+    "td",
+    "tfoot",
+    "th",
+    "thead",
+    "time",
+    "tr",
+    "tt",
+    "u",
+    "ul",
+    "var",
+    "wbr",
+];
+
+/// Attributes kept by [`sanitize_html`] on the safe tags
+const SAFE_ATTRIBUTES: [&str; 25] = [
+    "abbr", "align", "alt", "cite", "class", "colspan", "datetime", "dir", "headers", "height",
+    "href", "id", "lang", "open", "reversed", "rowspan", "scope", "span", "src", "start", "style",
+    "title", "type", "valign", "width",
+];
+
+/// Tags dropped with their content by [`sanitize_html`] besides the active tags, their
+/// content is not text to show
+const HIDDEN_TAGS: [&str; 9] = [
+    "style", "noscript", "template", "title", "textarea", "xmp", "noembed", "noframes", "svg",
+];
+
+/// HTML fragment with only the safe tags and attributes, tokenized as a browser does and
+/// written again. The active and hidden tags are dropped with their content, the other
+/// tags with their attributes only, as are the values that run scripts and the comments.
+/// The end tags of the fragment are kept as they are, a fragment being a single tag of an
+/// element that other fragments close.
+pub(crate) fn sanitize_html(html: &str) -> String {
+    use html5ever::tendril::StrTendril;
+    use html5ever::tokenizer::states::RawKind;
+    use html5ever::tokenizer::{
+        BufferQueue, Tag, TagKind, Token, TokenSink, TokenSinkResult, Tokenizer,
+    };
+
+    #[derive(Default)]
+    struct Sanitizer {
+        html: String,
+        /// Element whose content is dropped and the depth of its nested elements of the
+        /// same name
+        dropped: Option<(String, usize)>,
+    }
+
+    impl Sanitizer {
+        fn tag(&mut self, tag: Tag) -> TokenSinkResult<()> {
+            let name = tag.name.to_string();
+            let start = tag.kind == TagKind::StartTag && !tag.self_closing;
+            if let Some((dropped, depth)) = &mut self.dropped {
+                if *dropped == name && start {
+                    *depth += 1;
+                } else if *dropped == name && tag.kind == TagKind::EndTag {
+                    *depth -= 1;
+                    if *depth == 0 {
+                        self.dropped = None;
+                    }
+                }
+                return TokenSinkResult::Continue;
+            }
+            if ACTIVE_TAGS.contains(&name.as_str()) || HIDDEN_TAGS.contains(&name.as_str()) {
+                // an embed has no content
+                if !start || name == "embed" {
+                    return TokenSinkResult::Continue;
                 }
-            },
-            Node::Text(ref text) => {
-                let txt_strings = text.lines().map(str::trim).filter(|p| !p.is_empty());
-                for text_str in txt_strings {
-                    elements.push(Text {
-                        text: text_str.to_owned(),
-                        size: 8,
-                    });
+                let raw = match name.as_str() {
+                    "script" => Some(RawKind::ScriptData),
+                    "title" | "textarea" => Some(RawKind::Rcdata),
+                    "style" | "iframe" | "xmp" | "noembed" | "noframes" | "noscript" => {
+                        Some(RawKind::Rawtext)
+                    }
+                    _ => None,
+                };
+                self.dropped = Some((name, 1));
+                return raw.map_or(TokenSinkResult::Continue, TokenSinkResult::RawData);
+            }
+            if !SAFE_TAGS.contains(&name.as_str()) {
+                return TokenSinkResult::Continue;
+            }
+            if tag.kind == TagKind::EndTag {
+                self.html.push_str(&format!("</{name}>"));
+                return TokenSinkResult::Continue;
+            }
+            self.html.push('<');
+            self.html.push_str(&name);
+            for attribute in &tag.attrs {
+                let attribute_name = &*attribute.name.local;
+                let value = &*attribute.value;
+                if !SAFE_ATTRIBUTES.contains(&attribute_name)
+                    || is_script_value(value)
+                    || is_script_url(value)
+                {
+                    continue;
                 }
+                self.html.push_str(&format!(
+                    " {attribute_name}=\"{}\"",
+                    value.replace('&', "&amp;").replace('"', "&quot;")
+                ));
             }
-            _ => {}
+            self.html
+                .push_str(if tag.self_closing { "/>" } else { ">" });
+            TokenSinkResult::Continue
         }
     }
-    Ok(())
+
+    impl TokenSink for Sanitizer {
+        type Handle = ();
+
+        fn process_token(&mut self, token: Token, _line_number: u64) -> TokenSinkResult<()> {
+            match token {
+                Token::TagToken(tag) => return self.tag(tag),
+                Token::CharacterTokens(text) if self.dropped.is_none() => self.html.push_str(
+                    &text
+                        .replace('&', "&amp;")
+                        .replace('<', "&lt;")
+                        .replace('>', "&gt;"),
+                ),
+                _ => {}
+            }
+            TokenSinkResult::Continue
+        }
+    }
+
+    let mut tokenizer = Tokenizer::new(Sanitizer::default(), Default::default());
+    let mut input = BufferQueue::default();
+    input.push_back(StrTendril::from(html));
+    let _ = tokenizer.feed(&mut input);
+    tokenizer.end();
+    tokenizer.sink.html
+}
+
+/// Adds the known attributes to the first tag of the generated html, texts are wrapped
+/// in a span. The `id` of a header, read from markdown heading attributes, is its anchor.
+fn add_html_attributes(html: String, attributes: &HashMap<String, String>) -> String {
+    let header = html.starts_with("<h") && html[2..].starts_with(|c: char| c.is_ascii_digit());
+    let mut names: Vec<&String> = attributes
+        .keys()
+        .filter(|name| is_kept_attribute(name) || (header && *name == "id"))
+        .collect();
+    names.sort();
+    let attributes_html: String = names
+        .into_iter()
+        .map(|name| format!(" {}=\"{}\"", name, attributes[name].replace('"', "&quot;")))
+        .collect();
+    if attributes_html.is_empty() {
+        return html;
+    }
+    match html.strip_prefix('<') {
+        Some(rest) if !rest.starts_with('/') => {
+            let tag_end = rest
+                .find(|c: char| c.is_whitespace() || c == '>' || c == '/')
+                .unwrap_or(rest.len());
+            format!(
+                "<{}{}{}",
+                &rest[..tag_end],
+                attributes_html,
+                &rest[tag_end..]
+            )
+        }
+        _ => format!("<span{}>{}</span>", attributes_html, html),
+    }
+}
+
+/// Text with the characters that would be read as markup escaped
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Value of an attribute written between double quotes
+fn escape_attribute(value: &str) -> String {
+    escape_html(value).replace('"', "&quot;")
+}
+
+fn parse_direction(dir: &str) -> TextDirection {
+    TextDirection::from_str(&dir.trim().to_lowercase()).unwrap_or_default()
+}
+
+fn direction_attribute(direction: &TextDirection) -> String {
+    match direction {
+        TextDirection::Auto => String::new(),
+        direction => format!(" dir=\"{}\"", direction),
+    }
+}
+
+/// `@page` rule of the page size and margins of a page format, with the page header and
+/// footer in the margins. A named rule (` section-1`) applies to the pages of a section.
+fn page_rule(
+    name: &str,
+    page_format: &PageFormat,
+    orientation: &PageOrientation,
+    page_header: &[&Element],
+    page_footer: &[&Element],
+) -> String {
+    let dimensions = page_format.dimensions();
+    let (width, height) = match orientation {
+        PageOrientation::Portrait => (dimensions.page_width, dimensions.page_height),
+        PageOrientation::Landscape => (dimensions.page_height, dimensions.page_width),
+    };
+    // CSS lengths in millimeters, rounded so that inches give short values
+    let length = |length: &Length| format!("{}mm", (length.to_mm() * 100.0).round() / 100.0);
+    let mut rule = format!(
+        "@page{} {{\n  size: {} {};\n  margin: {} {} {} {};\n",
+        name,
+        length(&width),
+        length(&height),
+        length(&dimensions.page_margin_top),
+        length(&dimensions.page_margin_right),
+        length(&dimensions.page_margin_bottom),
+        length(&dimensions.page_margin_left)
+    );
+    if !page_header.is_empty() {
+        rule.push_str(&format!(
+            "  @top-center {{ content: {}; }}\n",
+            page_margin_content(page_header)
+        ));
+    }
+    if !page_footer.is_empty() {
+        rule.push_str(&format!(
+            "  @bottom-center {{ content: {}; }}\n",
+            page_margin_content(page_footer)
+        ));
+    }
+    rule.push_str("}\n");
+    rule
+}
+
+/// CSS `content` value of a page margin box
+fn page_margin_content(elements: &[&Element]) -> String {
+    let content: Vec<String> = elements
+        .iter()
+        .filter_map(|element| match element {
+            Text { text, size: _ } => Some(format!(
+                "\"{}\"",
+                text.replace('\\', "\\\\").replace('"', "\\\"")
+            )),
+            Field {
+                kind: FieldKind::PageNumber,
+            } => Some("counter(page)".to_string()),
+            Field {
+                kind: FieldKind::TotalPages,
+            } => Some("counter(pages)".to_string()),
+            _ => None,
+        })
+        .collect();
+    if content.is_empty() {
+        "none".to_string()
+    } else {
+        content.join(" ")
+    }
+}
+
+fn numbering_from_html_type(list_type: &str) -> Option<NumberingStyle> {
+    match list_type {
+        "1" => Some(NumberingStyle::Decimal),
+        "a" => Some(NumberingStyle::LowerAlpha),
+        "A" => Some(NumberingStyle::UpperAlpha),
+        "i" => Some(NumberingStyle::LowerRoman),
+        "I" => Some(NumberingStyle::UpperRoman),
+        _ => None,
+    }
+}
+
+fn html_type_from_numbering(numbering: &NumberingStyle) -> &'static str {
+    match numbering {
+        NumberingStyle::Decimal => "1",
+        NumberingStyle::LowerAlpha => "a",
+        NumberingStyle::UpperAlpha => "A",
+        NumberingStyle::LowerRoman => "i",
+        NumberingStyle::UpperRoman => "I",
+    }
 }
 
 fn generate_html_for_element(
@@ -348,9 +1901,12 @@ fn generate_html_for_element(
     image_saver: &ImageSaver<impl Fn(&Bytes, &str) -> anyhow::Result<()>>,
 ) -> anyhow::Result<String> {
     match element {
-        Text { text, size: _ } => Ok(text.to_string()),
-        Paragraph { elements } => {
-            let mut paragraph_html = String::from("<p>");
+        Text { text, size: _ } => Ok(escape_html(text)),
+        Paragraph {
+            elements,
+            direction,
+        } => {
+            let mut paragraph_html = format!("<p{}>", direction_attribute(direction));
             for child in elements {
                 paragraph_html
                     .push_str(generate_html_for_element(child, image_num, image_saver)?.as_str());
@@ -361,11 +1917,26 @@ fn generate_html_for_element(
         Header { level, text } => Ok(format!(
             "<h{level}>{text}</h{level}>",
             level = level,
-            text = text
+            text = escape_html(text)
         )),
-        List { elements, numbered } => {
+        List {
+            elements,
+            numbered,
+            start,
+            numbering,
+        } => {
             let tag = if *numbered { "ol" } else { "ul" };
-            let mut list_html = format!("<{}>", tag);
+            let mut attributes = String::new();
+            if *numbered && *start != 1 {
+                attributes.push_str(&format!(" start=\"{}\"", start));
+            }
+            if *numbered && *numbering != NumberingStyle::Decimal {
+                attributes.push_str(&format!(
+                    " type=\"{}\"",
+                    html_type_from_numbering(numbering)
+                ));
+            }
+            let mut list_html = format!("<{}{}>", tag, attributes);
             list_html.push('\n');
             for item in elements {
                 let item_html = generate_html_for_element(&item.element, image_num, image_saver)?;
@@ -381,10 +1952,18 @@ fn generate_html_for_element(
             Ok(list_html)
         }
         Image(image) => {
-            let image_path = format!("image{}.png", image_num);
-            // images.insert(image_path.to_string(), bytes.clone());
-            (image_saver.function)(image.bytes(), &image_path)?;
-            *image_num += 1;
+            let image_path = if image_saver.embed {
+                image_data_uri(image)
+            } else {
+                let image_path = format!("image{}.png", image_num);
+                (image_saver.function)(image.bytes(), &image_path)?;
+                *image_num += 1;
+                if image_saver.cid {
+                    format!("cid:{}", image_path)
+                } else {
+                    image_path
+                }
+            };
 
             let align_str = match image.align() {
                 ImageAlignment::None => String::new(),
@@ -402,20 +1981,144 @@ fn generate_html_for_element(
             };
             Ok(format!(
                 "<img src=\"{}\" alt=\"{}\" title=\"{}\"{}{}{} />",
-                image_path,
-                image.alt(),
-                image.title(),
+                escape_attribute(&image_path),
+                escape_attribute(image.alt()),
+                escape_attribute(image.title()),
                 align_str,
                 width_str,
                 height_str
             ))
         }
+        LineBreak => Ok("<br />".to_string()),
+        Attributed {
+            attributes,
+            element,
+        } => Ok(add_html_attributes(
+            generate_html_for_element(element, image_num, image_saver)?,
+            attributes,
+        )),
+        Bookmark { id, title } => Ok(format!(
+            "<a id=\"{}\" title=\"{}\"></a>",
+            escape_attribute(id),
+            escape_attribute(title)
+        )),
+        // the link is shown by browsers that do not play the media
+        Media { kind, url, title } => {
+            let url = escape_attribute(url);
+            let title = escape_attribute(title);
+            Ok(match kind {
+                MediaKind::Frame => format!("<iframe src=\"{url}\" title=\"{title}\"></iframe>"),
+                _ => format!(
+                    "<{kind} controls src=\"{url}\" title=\"{title}\"><a href=\"{url}\">{}</a></{kind}>",
+                    if title.is_empty() { &url } else { &title }
+                ),
+            })
+        }
+        Drawing {
+            width,
+            height,
+            shapes,
+        } => Ok(crate::drawing::svg(*width, *height, shapes)),
+        // page numbers are known only when printing, see the page margin rules
+        Field { kind } => Ok(format!("<span class=\"{}\"></span>", kind)),
         Hyperlink {
-            title, url, alt, ..
-        } => Ok(format!(
-            "<a href=\"{}\" title=\"{}\">{}</a>",
-            url, alt, title
+            elements, url, alt, ..
+        } => {
+            let mut content = String::new();
+            for child in elements {
+                content.push_str(&generate_html_for_element(child, image_num, image_saver)?);
+            }
+            Ok(format!(
+                "<a href=\"{}\" title=\"{}\">{}</a>",
+                escape_attribute(url),
+                escape_attribute(alt),
+                content
+            ))
+        }
+        InlineCode { text } => Ok(format!("<code>{}</code>", escape_html(text))),
+        // delimited for MathJax and KaTeX, like pandoc
+        Math { tex, display: true } => Ok(format!(
+            "<span class=\"math display\">\\[{}\\]</span>",
+            escape_html(tex)
         )),
+        Math { tex, .. } => Ok(format!(
+            "<span class=\"math inline\">\\({}\\)</span>",
+            escape_html(tex)
+        )),
+        Raw {
+            format: DocumentType::HTML,
+            content,
+        } => Ok(content.clone()),
+        CodeBlock { code, .. } => {
+            let class = element
+                .code_language()
+                .map(|language| format!(" class=\"language-{}\"", language))
+                .unwrap_or_default();
+            Ok(format!(
+                "<pre><code{}>{}</code></pre>",
+                class,
+                escape_html(code)
+            ))
+        }
+        Styled { style, elements } => {
+            let tag = match style {
+                TextStyle::Emphasis => "em",
+                TextStyle::Strong => "strong",
+                TextStyle::Strikethrough => "del",
+            };
+            let mut content = String::new();
+            for child in elements {
+                content.push_str(&generate_html_for_element(child, image_num, image_saver)?);
+            }
+            Ok(format!("<{}>{}</{}>", tag, content, tag))
+        }
+        // `float: footnote` on this class moves the note to the bottom of the printed page
+        Footnote { elements } => {
+            let mut content = String::new();
+            for child in elements {
+                content.push_str(&generate_html_for_element(child, image_num, image_saver)?);
+            }
+            Ok(format!("<span class=\"footnote\">{}</span>", content))
+        }
+        Table { headers, rows } => {
+            let mut table_html = String::from("<table  border=\"1\">\n");
+
+            if !headers.is_empty() {
+                table_html.push_str("<tr>\n");
+
+                for header in headers {
+                    let header_html =
+                        generate_html_for_element(&header.element, image_num, image_saver)?;
+
+                    table_html.push_str(&format!("<th>{}</th>\n", header_html));
+                }
+
+                table_html.push_str("</tr>\n");
+            }
+            for row in rows {
+                table_html.push_str("<tr>\n");
+
+                for cell in &row.cells {
+                    let cell_html =
+                        generate_html_for_element(&cell.element, image_num, image_saver)?;
+
+                    table_html.push_str(&format!("<td>{}</td>\n", cell_html));
+                }
+
+                table_html.push_str("</tr>\n");
+            }
+
+            table_html.push_str("</table>\n");
+            Ok(table_html)
+        }
+        Container { kind, elements } => {
+            let mut content = String::new();
+            for child in elements {
+                content.push_str(&generate_html_for_element(child, image_num, image_saver)?);
+                content.push('\n');
+            }
+            Ok(format!("<{kind}>\n{content}</{kind}>"))
+        }
         _ => Ok("".to_string()),
     }
 }
@@ -444,7 +2147,7 @@ fn retrieve_deep_text(node: NodeRef<Node>, tag_name: &str) -> String {
 
 #[cfg(test)]
 mod tests {
-    use crate::core::tests::init_logger;
+    use crate::core::tests::{init_logger, output_dir};
     use crate::core::*;
     use crate::html::*;
     use crate::markdown;
@@ -466,7 +2169,7 @@ mod tests {
             disk_image_loader("test/data"),
         )?;
         info!("{:#?}", document);
-        let result = Transformer::generate_with_saver(&document, disk_image_saver("test/data"))?;
+        let result = Transformer::generate_with_saver(&document, disk_image_saver(&output_dir()))?;
         info!("{}", String::from_utf8(result.to_vec())?);
         Ok(())
     }
@@ -513,8 +2216,840 @@ mod tests {
         )?;
         debug!("{:#?}", document);
         let markdown =
-            markdown::Transformer::generate_with_saver(&document, disk_image_saver("test/data"))?;
+            markdown::Transformer::generate_with_saver(&document, disk_image_saver(&output_dir()))?;
         info!("{}", String::from_utf8(markdown.to_vec())?);
         Ok(())
     }
+
+    #[test]
+    fn test_ordered_list_attributes() -> anyhow::Result<()> {
+        let document_html =
+            r#"<html><body><ol start="4" type="i"><li>Four</li><li>Five</li></ol></body></html>"#;
+        let document = Transformer::parse(&Bytes::from(document_html))?;
+        match &document.get_all_elements()[0] {
+            Element::List {
+                start, numbering, ..
+            } => {
+                assert_eq!(*start, 4);
+                assert_eq!(*numbering, NumberingStyle::LowerRoman);
+            }
+            _ => panic!("Expected List"),
+        }
+
+        let generated = Transformer::generate(&document)?;
+        let generated = String::from_utf8(generated.to_vec())?;
+        assert!(generated.contains(r#"<ol start="4" type="i">"#));
+        Ok(())
+    }
+
+    #[test]
+    fn test_line_break() -> anyhow::Result<()> {
+        let document_html = r#"<html><body><p>First line<br>Second line</p></body></html>"#;
+        let document = Transformer::parse(&Bytes::from(document_html))?;
+        assert_eq!(
+            document.get_all_elements()[0],
+            &Element::Paragraph {
+                elements: vec![
+                    Element::Text {
+                        text: "First line".to_string(),
+                        size: 8,
+                    },
+                    Element::LineBreak,
+                    Element::Text {
+                        text: "Second line".to_string(),
+                        size: 8,
+                    },
+                ],
+                direction: TextDirection::default(),
+            }
+        );
+
+        let generated = Transformer::generate(&document)?;
+        let generated = String::from_utf8(generated.to_vec())?;
+        assert!(generated.contains("<p>First line<br />Second line</p>"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_attributes() -> anyhow::Result<()> {
+        let document_html =
+            r#"<html><body><p class="lead" data-id="7" title="ignored">Hello</p></body></html>"#;
+        let document = Transformer::parse(&Bytes::from(document_html))?;
+        assert_eq!(
+            document.get_all_elements()[0],
+            &Element::Attributed {
+                attributes: HashMap::from([
+                    ("class".to_string(), "lead".to_string()),
+                    ("data-id".to_string(), "7".to_string()),
+                ]),
+                element: Box::new(Element::Paragraph {
+                    elements: vec![Element::Text {
+                        text: "Hello".to_string(),
+                        size: 8,
+                    }],
+                    direction: TextDirection::default(),
+                }),
+            }
+        );
+
+        let generated = Transformer::generate(&document)?;
+        let generated_text = String::from_utf8(generated.to_vec())?;
+        assert!(generated_text.contains(r#"<p class="lead" data-id="7">Hello</p>"#));
+        assert_eq!(Transformer::parse(&generated)?, document);
+        Ok(())
+    }
+
+    #[test]
+    fn test_text_direction() -> anyhow::Result<()> {
+        let document_html =
+            r#"<html><body dir="rtl"><p dir="ltr">Hello</p><p>שלום</p></body></html>"#;
+        let document = Transformer::parse(&Bytes::from(document_html))?;
+        assert_eq!(document.direction, TextDirection::Rtl);
+        let directions: Vec<TextDirection> = document
+            .get_all_elements()
+            .iter()
+            .filter_map(|element| match element {
+                Element::Paragraph { direction, .. } => Some(*direction),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(directions, vec![TextDirection::Ltr, TextDirection::Auto]);
+
+        let generated = Transformer::generate(&document)?;
+        let generated_text = String::from_utf8(generated.to_vec())?;
+        assert!(generated_text.contains("<html dir=\"rtl\">"));
+        assert!(generated_text.contains("<p dir=\"ltr\">Hello</p>"));
+        assert!(generated_text.contains("<p>שלום</p>"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_bookmark() -> anyhow::Result<()> {
+        let document = Document::new(vec![
+            Element::Bookmark {
+                id: "results".to_string(),
+                title: "Results".to_string(),
+            },
+            Element::Text {
+                text: "All green".to_string(),
+                size: 8,
+            },
+        ]);
+        let generated = Transformer::generate(&document)?;
+        let generated_text = String::from_utf8(generated.to_vec())?;
+        assert!(generated_text.contains("<a id=\"results\" title=\"Results\"></a>"));
+
+        let parsed = Transformer::parse(&generated)?;
+        assert_eq!(
+            parsed.get_all_elements()[0],
+            &document.get_all_elements()[0].clone()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_table_of_contents() -> anyhow::Result<()> {
+        let document = Document::new(vec![
+            Element::TableOfContents { max_level: 1 },
+            Element::Header {
+                level: 1,
+                text: "Getting started".to_string(),
+            },
+        ]);
+        let generated = Transformer::generate(&document)?;
+        let generated = String::from_utf8(generated.to_vec())?;
+        assert!(generated.contains(r##"<a href="#getting-started""##));
+        assert!(generated.contains(r#"<h1 id="getting-started">Getting started</h1>"#));
+        Ok(())
+    }
+
+    #[test]
+    fn test_page_fields() -> anyhow::Result<()> {
+        let mut document = Document::new(vec![]);
+        document.set_page_footer(vec![
+            Element::Text {
+                text: "Page ".to_string(),
+                size: 8,
+            },
+            Element::Field {
+                kind: FieldKind::PageNumber,
+            },
+        ]);
+        let generated = Transformer::generate(&document)?;
+        let generated = String::from_utf8(generated.to_vec())?;
+        assert!(generated.contains(r#"@bottom-center { content: "Page " counter(page); }"#));
+        assert!(generated.contains("@top-center { content: none; }"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_semantic_elements() -> anyhow::Result<()> {
+        let html = r##"<html><body>
+<nav><ul><li><a href="#intro">Intro</a></li></ul></nav>
+<article class="post"><h1>Intro</h1><p>Text</p>
+<figure><img src="data:image/gif;base64,R0lGODlh" alt="Pixel" /><figcaption>A  pixel</figcaption></figure>
+<aside><p>Note</p></aside></article>
+</body></html>"##;
+        let document = Transformer::parse(&Bytes::from(html))?;
+        let elements = document.get_all_elements();
+        assert!(matches!(
+            elements[0],
+            Container {
+                kind: ContainerKind::Nav,
+                ..
+            }
+        ));
+        let Attributed { element, .. } = elements[1] else {
+            panic!("attributed article expected, got {:?}", elements[1]);
+        };
+        let Container {
+            kind: ContainerKind::Article,
+            elements: article,
+        } = element.as_ref()
+        else {
+            panic!("article expected, got {:?}", element);
+        };
+        assert!(matches!(&article[2], Image(image) if image.title() == "A pixel"));
+        assert!(matches!(
+            &article[3],
+            Container {
+                kind: ContainerKind::Aside,
+                ..
+            }
+        ));
+        assert_eq!(document.get_title_text(), "Intro");
+
+        let options = GenerateOptions {
+            html: HtmlOptions {
+                standalone: false,
+                embed_images: true,
+                semantic: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let generated = Transformer::generate_with_options(&document, &options)?;
+        let generated = std::str::from_utf8(&generated)?;
+        assert!(generated.starts_with("<nav>\n<ul>"));
+        assert!(generated.contains("<article class=\"post\">\n<section>\n<h1>Intro</h1>"));
+        assert!(generated.contains("<figcaption>A pixel</figcaption>\n</figure>"));
+        assert!(generated.contains("<aside>\n<p>Note</p>\n</aside>\n</section>\n</article>"));
+
+        // the containers are written without the semantic markup too
+        let options = GenerateOptions {
+            html: HtmlOptions {
+                embed_images: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let generated = Transformer::generate_with_options(&document, &options)?;
+        assert_eq!(Transformer::parse(&generated)?, document);
+
+        let text = crate::text::Transformer::generate(&document)?;
+        assert!(std::str::from_utf8(&text)?.contains("Note"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_fragment() -> anyhow::Result<()> {
+        let document = Document::new(vec![Element::Header {
+            level: 1,
+            text: "Title".to_string(),
+        }]);
+        let options = GenerateOptions {
+            html: HtmlOptions {
+                standalone: false,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let generated = Transformer::generate_with_options(&document, &options)?;
+        assert_eq!(generated, "<h1>Title</h1>\n");
+        assert_eq!(
+            Transformer::generate_with_options(&document, &GenerateOptions::default())?,
+            Transformer::generate(&document)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_inline_styles() -> anyhow::Result<()> {
+        let html = r#"<p style="text-align: right">Total <strong class="amount" style="color: #c00">42</strong></p>"#;
+        let document = Transformer::parse(&Bytes::from(html))?;
+        let Attributed {
+            attributes,
+            element,
+        } = document.get_all_elements()[0]
+        else {
+            panic!("Unexpected elements {:?}", document.get_all_elements());
+        };
+        assert_eq!(
+            InlineStyle::from_attributes(attributes).align,
+            Some(TextAlign::Right)
+        );
+        let Attributed { attributes, .. } = element.children()[1] else {
+            panic!("Unexpected paragraph {:?}", element);
+        };
+        assert_eq!(
+            InlineStyle::from_attributes(attributes).color.as_deref(),
+            Some("CC0000")
+        );
+        let generated = Transformer::generate(&document)?;
+        assert!(std::str::from_utf8(&generated)?.contains(
+            r#"<p style="text-align: right">Total <strong class="amount" style="color: #c00">42</strong></p>"#
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_table_cell_blocks() -> anyhow::Result<()> {
+        let html = r#"<table>
+<tr><th>Name</th><th>Notes</th><th>Parts</th></tr>
+<tr><td><p>Shiva</p></td><td><p>Converts</p><ul><li>HTML</li><li>PDF</li></ul></td><td><table><tr><td>Nested</td></tr></table></td></tr>
+<tr><td>Plain <b>bold</b></td><td></td><td>Last</td></tr>
+</table>"#;
+        let document = Transformer::parse(&Bytes::from(html))?;
+        let Table { headers, rows } = document.get_all_elements()[0] else {
+            panic!("Unexpected elements {:?}", document.get_all_elements());
+        };
+        assert_eq!(headers.len(), 3);
+        assert!(rows.iter().all(|row| row.cells.len() == 3));
+        let cells: Vec<&Element> = rows[0].cells.iter().map(|cell| &cell.element).collect();
+        assert_eq!(
+            cells[0],
+            &Text {
+                text: "Shiva".to_string(),
+                size: 8
+            }
+        );
+        let Container {
+            kind: ContainerKind::Div,
+            elements,
+        } = cells[1]
+        else {
+            panic!("Unexpected cell {:?}", cells[1]);
+        };
+        assert!(matches!(
+            elements.as_slice(),
+            [Paragraph { .. }, List { .. }]
+        ));
+        assert!(matches!(cells[2], Table { .. }));
+        assert!(matches!(rows[1].cells[0].element, Paragraph { .. }));
+        assert_eq!(rows[1].cells[1].element.plain_text(), "");
+        assert_eq!(
+            crate::text::Transformer::generate(&document)?,
+            "| Name       | Notes             | Parts  |\n\
+             |------------|-------------------|--------|\n\
+             | Shiva      | Converts HTML PDF | Nested |\n\
+             | Plain bold |                   | Last   |\n\n"
+        );
+
+        let generated = Transformer::generate(&document)?;
+        let generated = std::str::from_utf8(&generated)?;
+        assert!(generated.contains("<td><div>\n<p>Converts</p>\n<ul>"));
+        assert_eq!(
+            Transformer::parse(&Bytes::from(generated.to_string()))?,
+            document
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_inline_spaces() -> anyhow::Result<()> {
+        let html = r#"<p>Hello <b>x</b> and <a href="u">l</a> end.</p>
+<p>
+  <em>Line</em>
+  <s>one</s> two<br> three
+</p>"#;
+        let document = Transformer::parse(&Bytes::from(html))?;
+        let generated = Transformer::generate(&document)?;
+        let generated = std::str::from_utf8(&generated)?;
+        assert!(generated
+            .contains(r#"<p>Hello <strong>x</strong> and <a href="u" title="">l</a> end.</p>"#));
+        assert_eq!(
+            Transformer::parse(&Bytes::from(generated.to_string()))?,
+            document
+        );
+        assert_eq!(
+            crate::markdown::Transformer::generate(&document)?,
+            "Hello **x** and [l](u) end.\n\n*Line* ~~one~~ two\\\nthree\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_sanitize() -> anyhow::Result<()> {
+        let html = r#"<html><body>
+<script>alert(1)</script>
+<p onclick="alert(2)" style="width: expression(alert(3))">Safe <a href=" java&#9;script:alert(4)">click</a> <a href="https://example.com">site</a></p>
+<iframe src="https://example.com/frame"><p>Frame</p></iframe>
+<img src="javascript:alert(5)" alt="x" />
+<p>&lt;script&gt;alert(6)&lt;/script&gt;</p>
+</body></html>"#;
+        let options = ParseOptions {
+            html: HtmlParseOptions { sanitize: true },
+            ..Default::default()
+        };
+        let document = Transformer::parse_with_loader_and_options(
+            &Bytes::from(html),
+            |image| Err(anyhow::anyhow!("Unexpected image {}", image)),
+            &options,
+        )?;
+        let generated = Transformer::generate(&document)?;
+        let generated = std::str::from_utf8(&generated)?;
+        assert!(generated
+            .contains(r#"<p>Safe click <a href="https://example.com" title="">site</a></p>"#));
+        assert!(generated.contains("<p>&lt;script&gt;alert(6)&lt;/script&gt;</p>"));
+        for unsafe_html in [
+            "alert(1)", "alert(2)", "alert(3)", "alert(4)", "Frame", "<img",
+        ] {
+            assert!(!generated.contains(unsafe_html), "{}", generated);
+        }
+
+        // without sanitizing, only the event handlers are dropped
+        let html = "<p><script>alert(1)</script></p>";
+        let document = Transformer::parse(&Bytes::from(html))?;
+        let generated = Transformer::generate(&document)?;
+        assert!(std::str::from_utf8(&generated)?.contains("alert(1)"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_sanitize_raw_html() -> anyhow::Result<()> {
+        let sources = [
+            (
+                DocumentType::RST,
+                ".. raw:: html\n\n   <b>Bold</b><script>alert(1)</script>\n",
+            ),
+            (
+                DocumentType::AsciiDoc,
+                "++++\n<b>Bold</b><script>alert(1)</script>\n++++\n",
+            ),
+            (
+                DocumentType::Org,
+                "#+begin_export html\n<b>Bold</b><script>alert(1)</script>\n#+end_export\n",
+            ),
+        ];
+        for (document_type, source) in sources {
+            let mut document =
+                TransformerRegistry::builtin().parse(document_type, &Bytes::from(source))?;
+            let generated = Transformer::generate(&document)?;
+            assert!(std::str::from_utf8(&generated)?.contains("<script>alert(1)</script>"));
+
+            sanitize_raw_html(&mut document);
+            let generated = Transformer::generate(&document)?;
+            let generated = std::str::from_utf8(&generated)?;
+            assert!(generated.contains("<b>Bold</b>"), "{}", generated);
+            assert!(!generated.contains("alert(1)"), "{}", generated);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_media() -> anyhow::Result<()> {
+        let html = r#"<html><body>
+<video controls><source src="clip.webm" type="video/webm" />Not supported</video>
+<audio src="song.mp3" title="Song"></audio>
+<iframe src="https://example.com/map" title="Map"></iframe>
+<video>No source</video>
+</body></html>"#;
+        let document = Transformer::parse(&Bytes::from(html))?;
+        let media = |kind, url: &str, title: &str| Media {
+            kind,
+            url: url.to_string(),
+            title: title.to_string(),
+        };
+        let expected = [
+            media(MediaKind::Video, "clip.webm", ""),
+            media(MediaKind::Audio, "song.mp3", "Song"),
+            media(MediaKind::Frame, "https://example.com/map", "Map"),
+            Text {
+                text: "No source".to_string(),
+                size: 8,
+            },
+        ];
+        assert_eq!(
+            document.get_all_elements(),
+            expected.iter().collect::<Vec<_>>()
+        );
+
+        let generated = Transformer::generate(&document)?;
+        let generated_text = std::str::from_utf8(&generated)?;
+        assert!(generated_text.contains(
+            r#"<video controls src="clip.webm" title=""><a href="clip.webm">clip.webm</a></video>"#
+        ));
+        assert!(generated_text
+            .contains(r#"<iframe src="https://example.com/map" title="Map"></iframe>"#));
+        assert_eq!(
+            Transformer::parse(&generated)?.get_all_elements()[..3],
+            expected.iter().take(3).collect::<Vec<_>>()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_mhtml() -> anyhow::Result<()> {
+        let mhtml = "From: <Saved by Blink>\r
+Subject: Post\r
+MIME-Version: 1.0\r
+Content-Type: multipart/related;\r
+\ttype=\"text/html\";\r
+\tboundary=\"----MultipartBoundary--abc----\"\r
+\r
+------MultipartBoundary--abc----\r
+Content-Type: text/html\r
+Content-Transfer-Encoding: quoted-printable\r
+Content-Location: https://example.com/blog/post.html\r
+\r
+<html><head><meta charset=3D\"windows-1252\"></head><body><p>Caf=E9 au lait, a long =\r
+line</p><img src=3D\"images/pixel.gif\" alt=3D\"Pixel\"><img src=3D\"cid:logo@example.com\"></body></html>\r
+------MultipartBoundary--abc----\r
+Content-Type: image/gif\r
+Content-Transfer-Encoding: base64\r
+Content-Location: https://example.com/blog/images/pixel.gif\r
+\r
+R0lGODlh\r
+------MultipartBoundary--abc----\r
+Content-Type: image/png\r
+Content-ID: <logo@example.com>\r
+Content-Transfer-Encoding: base64\r
+\r
+iVBORw0K\r
+Ggo=\r
+------MultipartBoundary--abc------\r
+";
+        let document = Transformer::parse_with_loader(&Bytes::from(mhtml), |image| {
+            Err(anyhow::anyhow!("Unexpected image {}", image))
+        })?;
+        let elements = document.get_all_elements();
+        assert_eq!(elements[0].plain_text(), "Café au lait, a long line");
+        let images: Vec<&Bytes> = elements
+            .iter()
+            .filter_map(|element| match element {
+                Image(image) => Some(image.bytes()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            images,
+            vec![
+                &Bytes::from_static(b"GIF89a"),
+                &Bytes::from_static(b"\x89PNG\r\n\x1a\n")
+            ]
+        );
+
+        let error = Transformer::parse(&Bytes::from(
+            "MIME-Version: 1.0\nContent-Type: multipart/related; boundary=b\n\n--b\nContent-Type: image/png\n\npng\n--b--\n",
+        ))
+        .unwrap_err();
+        assert!(
+            error.to_string().contains("without an HTML page"),
+            "{}",
+            error
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_email() -> anyhow::Result<()> {
+        let html = r#"<h1>Welcome</h1>
+<p style="color: #333333">See <a href="https://example.com">the site</a></p>
+<img src="logo.png" alt="Logo" />
+<video src="https://example.com/intro.mp4" title="Intro"></video>"#;
+        let document =
+            Transformer::parse_with_loader(&Bytes::from(html), |_| Ok(Bytes::from_static(b"png")))?;
+        let options = HtmlOptions {
+            theme: true,
+            ..Default::default()
+        };
+        let email = Transformer::generate_email(&document, &options)?;
+        let generated = std::str::from_utf8(&email.html)?;
+        assert!(generated.contains(r#"<table role="presentation" width="600""#));
+        assert!(generated.contains(
+            r#"<h1 style="margin: 24px 0 12px; font-size: 28px; line-height: 1.25;">Welcome</h1>"#
+        ));
+        // the style of the element comes last so that it wins
+        assert!(generated.contains(r#"<p style="margin: 0 0 16px; color: #333333">"#));
+        assert!(generated.contains(
+            r#"<a style="color: #0550ae; text-decoration: underline;" href="https://example.com""#
+        ));
+        assert!(generated.contains(r#"<img style="max-width: 100%; height: auto; border: 0;" src="cid:image0.png" alt="Logo""#));
+        assert!(generated.contains(r#"href="https://example.com/intro.mp4""#));
+        for unsafe_html in ["<style", "<link", "<video"] {
+            assert!(!generated.contains(unsafe_html), "{}", generated);
+        }
+        assert_eq!(
+            email.attachments,
+            HashMap::from([("image0.png".to_string(), Bytes::from_static(b"png"))])
+        );
+
+        let options = HtmlOptions {
+            embed_images: true,
+            ..Default::default()
+        };
+        let email = Transformer::generate_email(&document, &options)?;
+        assert!(std::str::from_utf8(&email.html)?.contains(r#"src="data:image/png;base64,"#));
+        assert!(email.attachments.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_drawing() -> anyhow::Result<()> {
+        let document = DocumentBuilder::new()
+            .paragraph(|paragraph| paragraph.text("Sales"))
+            .drawing(
+                80.0,
+                40.0,
+                vec![Shape::rect(0.0, 10.0, 20.0, 30.0)
+                    .with_fill("green")
+                    .without_stroke()],
+            )
+            .build();
+        let generated = Transformer::generate(&document)?;
+        assert!(std::str::from_utf8(&generated)?.contains(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"80pt\" height=\"40pt\" viewBox=\"0 0 80 40\"><path d=\"M 0 10 L 20 10 L 20 40 L 0 40 Z\" fill=\"#008000\" stroke=\"none\"/></svg>\n"
+        ));
+
+        // email clients get an image
+        let email = Transformer::generate_email(&document, &HtmlOptions::default())?;
+        let generated = std::str::from_utf8(&email.html)?;
+        assert!(!generated.contains("<svg"));
+        assert!(generated.contains(r#"src="cid:image0.png""#));
+        assert!(email.attachments["image0.png"].starts_with(b"\x89PNG"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_stylesheet() -> anyhow::Result<()> {
+        let document = Document::new(vec![Element::Header {
+            level: 1,
+            text: "A & B".to_string(),
+        }]);
+        let options = GenerateOptions {
+            html: HtmlOptions {
+                theme: true,
+                css: Some("h1 { color: red; }".to_string()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let generated = Transformer::generate_with_options(&document, &options)?;
+        let generated = std::str::from_utf8(&generated)?;
+        assert!(generated.contains("<meta charset=\"utf-8\">"));
+        assert!(generated.contains("<title>A &amp; B</title>"));
+        assert!(generated.contains(THEME_CSS));
+        // the custom stylesheet comes last so that it overrides the theme
+        assert!(generated.contains("h1 { color: red; }\n</style>\n</head>\n<body>"));
+        assert!(!Transformer::generate(&document)?.starts_with(b"<head>"));
+
+        let options = GenerateOptions {
+            html: HtmlOptions {
+                standalone: false,
+                theme: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let generated = Transformer::generate_with_options(&document, &options)?;
+        assert!(!std::str::from_utf8(&generated)?.contains("<style>"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_image_sources() -> anyhow::Result<()> {
+        let html = r#"<html><body>
+<img src="small.png" srcset="small.png 320w, large.png 1280w, medium.png 640w" alt="Responsive" />
+<img src="data:image/gif;base64,R0lGODlh" data-src="lazy.jpg" alt="Lazy" />
+<picture>
+<source type="image/webp" srcset="photo.webp" />
+<source srcset="photo.jpg 1x, photo@2x.jpg 2x" />
+<img src="fallback.png" alt="Photo" />
+</picture>
+<img src="plain.png?v=2" />
+</body></html>"#;
+        let loaded = std::cell::RefCell::new(vec![]);
+        let document = Transformer::parse_with_loader(&Bytes::from(html), |image| {
+            loaded.borrow_mut().push(image.to_string());
+            Ok(Bytes::from_static(b"image"))
+        })?;
+        assert_eq!(
+            loaded.into_inner(),
+            ["large.png", "lazy.jpg", "photo@2x.jpg", "plain.png?v=2"]
+        );
+        // the lazy loaded image keeps its data attributes
+        let images: Vec<&ImageData> = document
+            .get_all_elements()
+            .into_iter()
+            .filter_map(|element| match element {
+                Image(image) => Some(image),
+                Attributed { element, .. } => match element.as_ref() {
+                    Image(image) => Some(image),
+                    _ => None,
+                },
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            images
+                .iter()
+                .map(|image| (image.alt(), image.image_type()))
+                .collect::<Vec<_>>(),
+            [
+                ("Responsive", &ImageType::Png),
+                ("Lazy", &ImageType::Jpeg),
+                ("Photo", &ImageType::Jpeg),
+                ("", &ImageType::Png),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_print_rules() -> anyhow::Result<()> {
+        let document = DocumentBuilder::new()
+            .page_size(PageSize::Letter)
+            .page_header(|header| header.text("Report"))
+            .page_footer(|footer| footer.field(FieldKind::PageNumber))
+            .text("Portrait")
+            .section(PageFormat::A4, PageOrientation::Landscape)
+            .text("Landscape")
+            .build();
+        let options = GenerateOptions {
+            html: HtmlOptions {
+                print: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let generated = Transformer::generate_with_options(&document, &options)?;
+        let generated = std::str::from_utf8(&generated)?;
+        assert!(generated.contains(
+            "@page {\n  size: 215.9mm 279.4mm;\n  margin: 10mm 10mm 10mm 10mm;\n  @top-center { content: \"Report\"; }\n  @bottom-center { content: counter(page); }\n}\n"
+        ));
+        assert!(generated.contains("@page section-1 {\n  size: 297mm 210mm;"));
+        assert!(generated.contains(
+            "<body>\n<p>Portrait</p>\n<div style=\"break-before: page; page: section-1\">\n<p>Landscape</p>\n</div>\n</body>"
+        ));
+        // without the print rules the page header is written before the content
+        let generated = Transformer::generate(&document)?;
+        assert!(std::str::from_utf8(&generated)?.contains("<p>Report</p>"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_encodings() -> anyhow::Result<()> {
+        let text = |html: &[u8]| -> anyhow::Result<String> {
+            let document = Transformer::parse(&Bytes::copy_from_slice(html))?;
+            Ok(document
+                .get_all_elements()
+                .iter()
+                .map(|element| element.plain_text())
+                .collect::<Vec<_>>()
+                .join("|"))
+        };
+        assert_eq!(
+            text(b"<html><head><meta charset=\"iso-8859-1\"></head><body><p>Caf\xe9</p></body></html>")?,
+            "Café"
+        );
+        assert_eq!(
+            text(b"<meta http-equiv=\"Content-Type\" content=\"text/html; charset=Shift_JIS\"><p>\x93\xfa\x96\x7b</p>")?,
+            "日本"
+        );
+        // not UTF-8 and without a charset, read as windows-1252
+        assert_eq!(text(b"<p>\x93Quoted\x94</p>")?, "“Quoted”");
+        let utf16: Vec<u8> = [0xFF, 0xFE]
+            .into_iter()
+            .chain("<p>Ünïcode</p>".encode_utf16().flat_map(u16::to_le_bytes))
+            .collect();
+        assert_eq!(text(&utf16)?, "Ünïcode");
+        assert_eq!(text("<p>Ünïcode</p>".as_bytes())?, "Ünïcode");
+
+        // malformed markup is repaired the way browsers do
+        let repaired = text(b"<p>Unclosed <b>bold<p>Next</i></div><table><tr><td>Cell")?;
+        for text in ["Unclosed bold", "Next", "Cell"] {
+            assert!(repaired.contains(text), "{}", repaired);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_data_uri_images() -> anyhow::Result<()> {
+        let html = r#"<html><body><p><img src="data:image/gif;base64,R0lGODlh" alt="Pixel" /><img src="data:image/svg+xml,%3Csvg%2F%3E" /></p></body></html>"#;
+        let document = Transformer::parse_with_loader(&Bytes::from(html), |image| {
+            Err(anyhow::anyhow!("Unexpected image {}", image))
+        })?;
+        let images: Vec<&ImageData> = document.get_all_elements()[0]
+            .children()
+            .into_iter()
+            .filter_map(|element| match element {
+                Image(image) => Some(image),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(images[0].bytes(), &Bytes::from_static(b"GIF89a"));
+        assert_eq!(images[0].image_type(), &ImageType::Gif);
+        assert_eq!(images[1].bytes(), &Bytes::from_static(b"<svg/>"));
+        assert_eq!(images[1].image_type(), &ImageType::SVG);
+
+        let options = GenerateOptions {
+            html: HtmlOptions {
+                embed_images: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let generated = Transformer::generate_with_options(&document, &options)?;
+        let generated = String::from_utf8(generated.to_vec())?;
+        assert!(generated.contains(r#"<img src="data:image/gif;base64,R0lGODlh" alt="Pixel""#));
+        assert!(generated.contains(r#"<img src="data:image/svg+xml;base64,PHN2Zy8+""#));
+        Ok(())
+    }
+
+    #[test]
+    fn test_note_references() -> anyhow::Result<()> {
+        let html = r##"<html><body>
+<p>Text<a epub:type="noteref" id="ref-1" href="#note-1">1</a> and more<a role="doc-noteref" href="#note-2">2</a>.</p>
+<p>Unknown<a epub:type="noteref" href="#missing">3</a></p>
+<section epub:type="footnotes">
+<aside epub:type="footnote" id="note-1"><p><a href="#ref-1">1.</a> First note.</p></aside>
+<aside role="doc-footnote" id="note-2"><p>Second note.</p><p>Again.</p></aside>
+</section>
+</body></html>"##;
+        let document = Transformer::parse(&Bytes::from(html))?;
+        let text = |text: &str| Text {
+            text: text.to_string(),
+            size: 8,
+        };
+        let elements = document.get_all_elements();
+        assert_eq!(elements.len(), 2);
+        assert_eq!(
+            elements[0],
+            &Paragraph {
+                elements: vec![
+                    text("Text"),
+                    Footnote {
+                        elements: vec![text("First note.")]
+                    },
+                    text(" and more"),
+                    Footnote {
+                        elements: vec![
+                            text("Second note."),
+                            Paragraph {
+                                elements: vec![text("Again.")],
+                                direction: TextDirection::default(),
+                            }
+                        ]
+                    },
+                    text("."),
+                ],
+                direction: TextDirection::default(),
+            }
+        );
+        assert!(matches!(
+            &elements[1].children()[1],
+            Hyperlink { url, .. } if url == "#missing"
+        ));
+        Ok(())
+    }
 }
@@ -0,0 +1,474 @@
+//! iCalendar calendars (`.ics`), read as a document with a section per event and task
+//!
+//! The name of the calendar (`X-WR-CALNAME`) is its title and header. Events come in the
+//! order of their start and tasks in the order of their due date, each one a header of its
+//! summary, a table of its fields (time, location, organizer, attendees, status, recurrence
+//! rule...) and its description. Dates are written `2026-03-02 10:00` with their time zone,
+//! the exclusive end of all-day events as the last day they include. Journals, time zones and
+//! alarms are left out. Calendars are parsed only.
+
+use bytes::Bytes;
+
+use crate::core::{Document, Element, TableCell, TableRow, TextDirection};
+use crate::vobject::{self, Component, Property};
+
+pub struct Transformer;
+
+/// Labels of the fields of events and tasks read as their text, in the order of their rows
+const TEXT_FIELDS: [(&str, &str); 5] = [
+    ("LOCATION", "Location"),
+    ("STATUS", "Status"),
+    ("PRIORITY", "Priority"),
+    ("PERCENT-COMPLETE", "Percent complete"),
+    ("RRULE", "Repeats"),
+];
+
+fn text(text: impl Into<String>) -> Element {
+    Element::Text {
+        text: text.into(),
+        size: 8,
+    }
+}
+
+fn row(label: &str, element: Element) -> TableRow {
+    TableRow {
+        cells: vec![
+            TableCell {
+                element: text(label),
+            },
+            TableCell { element },
+        ],
+    }
+}
+
+/// Date of a `DATE` or `DATE-TIME` value, its time `None` for a date
+#[derive(Debug, Clone, PartialEq)]
+struct DateTime {
+    year: u32,
+    month: u32,
+    day: u32,
+    time: Option<(u32, u32)>,
+    /// `UTC` for a time in UTC, the `TZID` of a local time, empty for a floating time
+    zone: String,
+}
+
+impl DateTime {
+    fn parse(property: &Property) -> Option<DateTime> {
+        let value = property.value.trim();
+        let number = |range: std::ops::Range<usize>| value.get(range)?.parse::<u32>().ok();
+        let time = match value.get(8..9) {
+            Some("T") => Some((number(9..11)?, number(11..13)?)),
+            _ => None,
+        };
+        let zone = if value.ends_with(['Z', 'z']) {
+            "UTC".to_string()
+        } else {
+            property.parameter("TZID").unwrap_or_default().to_string()
+        };
+        Some(DateTime {
+            year: number(0..4)?,
+            month: number(4..6)?,
+            day: number(6..8)?,
+            time,
+            zone,
+        })
+    }
+
+    fn date(&self) -> String {
+        format!("{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+
+    /// Time with its zone, `None` for a date
+    fn time(&self) -> Option<String> {
+        let (hour, minute) = self.time?;
+        let time = format!("{:02}:{:02}", hour, minute);
+        Some(if self.zone.is_empty() {
+            time
+        } else {
+            format!("{} {}", time, self.zone)
+        })
+    }
+
+    /// Day before, the last day of an all-day event that ends at this date
+    fn previous_day(&self) -> DateTime {
+        let (year, month, day) = match (self.month, self.day) {
+            (_, 2..) => (self.year, self.month, self.day - 1),
+            (2.., _) => (
+                self.year,
+                self.month - 1,
+                days_in_month(self.year, self.month - 1),
+            ),
+            _ => (self.year - 1, 12, 31),
+        };
+        DateTime {
+            year,
+            month,
+            day,
+            ..self.clone()
+        }
+    }
+}
+
+impl std::fmt::Display for DateTime {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.time() {
+            Some(time) => write!(f, "{} {}", self.date(), time),
+            None => write!(f, "{}", self.date()),
+        }
+    }
+}
+
+fn days_in_month(year: u32, month: u32) -> u32 {
+    match month {
+        4 | 6 | 9 | 11 => 30,
+        2 if year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400)) => {
+            29
+        }
+        2 => 28,
+        _ => 31,
+    }
+}
+
+/// Duration `P1W`, `PT1H30M`, `P1DT12H` written as `1 h 30 min`
+fn duration(value: &str) -> String {
+    let mut parts = vec![];
+    let mut number = String::new();
+    for c in value.trim().trim_start_matches(['+', '-']).chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let unit = match c {
+            'W' => "w",
+            'D' => "d",
+            'H' => "h",
+            'M' => "min",
+            'S' => "s",
+            // `P` and the `T` before the time
+            _ => continue,
+        };
+        parts.push(format!("{} {}", number, unit));
+        number.clear();
+    }
+    parts.join(" ")
+}
+
+/// Time of an event: its start and its end or duration
+fn when(event: &Component) -> Option<String> {
+    let start = DateTime::parse(event.property("DTSTART")?)?;
+    let Some(end) = event.property("DTEND").and_then(DateTime::parse) else {
+        return Some(match event.property("DURATION") {
+            Some(length) => format!("{} ({})", start, duration(&length.value)),
+            None => start.to_string(),
+        });
+    };
+    Some(match (start.time, end.time) {
+        // all-day events end at the start of the day after their last day
+        (None, None) => {
+            let last = end.previous_day();
+            if last.date() <= start.date() {
+                start.date()
+            } else {
+                format!("{} – {}", start.date(), last.date())
+            }
+        }
+        (Some((hour, minute)), Some(_)) if start.date() == end.date() && start.zone == end.zone => {
+            format!(
+                "{} {:02}:{:02} – {}",
+                start.date(),
+                hour,
+                minute,
+                end.time().unwrap_or_default()
+            )
+        }
+        _ => format!("{} – {}", start, end),
+    })
+}
+
+/// Person of an organizer or attendee: its common name or address, linked to its address
+fn person(property: &Property) -> Element {
+    let address = property.value.trim();
+    let email = address
+        .strip_prefix("mailto:")
+        .or_else(|| address.strip_prefix("MAILTO:"))
+        .unwrap_or(address);
+    let mut label = property
+        .parameter("CN")
+        .filter(|name| !name.is_empty())
+        .unwrap_or(email)
+        .to_string();
+    if let Some(status) = property.parameter("PARTSTAT") {
+        label = format!(
+            "{} ({})",
+            label,
+            status.to_ascii_lowercase().replace('-', " ")
+        );
+    }
+    Element::Hyperlink {
+        elements: vec![text(label)],
+        url: address.to_string(),
+        alt: String::new(),
+        size: 14,
+    }
+}
+
+/// Paragraphs of a description, its lines separated by line breaks
+fn description(text: &str) -> Vec<Element> {
+    text.replace("\r\n", "\n")
+        .split("\n\n")
+        .filter(|paragraph| !paragraph.trim().is_empty())
+        .map(|paragraph| {
+            let mut elements = vec![];
+            for line in paragraph.trim().lines() {
+                if !elements.is_empty() {
+                    elements.push(Element::LineBreak);
+                }
+                elements.push(self::text(line.trim_end()));
+            }
+            Element::Paragraph {
+                elements,
+                direction: TextDirection::default(),
+            }
+        })
+        .collect()
+}
+
+/// Header, table of fields and description of an event or a task
+fn section(component: &Component, level: u8) -> Vec<Element> {
+    let is_task = component.name == "VTODO";
+    let summary = component.text("SUMMARY").unwrap_or_else(|| {
+        if is_task {
+            "Untitled task".to_string()
+        } else {
+            "Untitled event".to_string()
+        }
+    });
+    let mut elements = vec![Element::Header {
+        level,
+        text: summary,
+    }];
+
+    let mut rows = vec![];
+    if is_task {
+        if let Some(start) = component.property("DTSTART").and_then(DateTime::parse) {
+            rows.push(row("Start", text(start.to_string())));
+        }
+        if let Some(due) = component.property("DUE").and_then(DateTime::parse) {
+            rows.push(row("Due", text(due.to_string())));
+        }
+        if let Some(completed) = component.property("COMPLETED").and_then(DateTime::parse) {
+            rows.push(row("Completed", text(completed.to_string())));
+        }
+    } else if let Some(when) = when(component) {
+        rows.push(row("When", text(when)));
+    }
+    for (name, label) in TEXT_FIELDS {
+        if let Some(value) = component.text(name) {
+            rows.push(row(label, text(value)));
+        }
+    }
+    if let Some(organizer) = component.property("ORGANIZER") {
+        rows.push(row("Organizer", person(organizer)));
+    }
+    for attendee in component.all("ATTENDEE") {
+        rows.push(row("Attendee", person(attendee)));
+    }
+    let categories: Vec<String> = component
+        .all("CATEGORIES")
+        .flat_map(Property::list)
+        .collect();
+    if !categories.is_empty() {
+        rows.push(row("Categories", text(categories.join(", "))));
+    }
+    if let Some(url) = component.text("URL") {
+        rows.push(row(
+            "Link",
+            Element::Hyperlink {
+                elements: vec![text(url.clone())],
+                url,
+                alt: String::new(),
+                size: 14,
+            },
+        ));
+    }
+    if !rows.is_empty() {
+        elements.push(Element::Table {
+            headers: vec![],
+            rows,
+        });
+    }
+    if let Some(text) = component.text("DESCRIPTION") {
+        elements.extend(description(&text));
+    }
+    elements
+}
+
+/// Components of the kind sorted by the date of the property, the undated ones last
+fn sorted<'a>(calendar: &'a Component, kind: &str, date: &str) -> Vec<&'a Component> {
+    let mut components: Vec<&Component> = calendar
+        .components
+        .iter()
+        .filter(|component| component.name == kind)
+        .collect();
+    components.sort_by_cached_key(|component| {
+        let date = component.property(date).and_then(DateTime::parse);
+        (
+            date.is_none(),
+            date.map(|date| date.to_string()).unwrap_or_default(),
+        )
+    });
+    components
+}
+
+impl Transformer {
+    /// Document of the events and tasks of an iCalendar file
+    pub fn parse(document: &Bytes) -> crate::Result<Document> {
+        Transformer::parse_calendars(document).map_err(crate::Error::parse_error)
+    }
+
+    fn parse_calendars(document: &Bytes) -> anyhow::Result<Document> {
+        let source = std::str::from_utf8(document)?;
+        let source = source.strip_prefix('\u{feff}').unwrap_or(source);
+        let calendars: Vec<Component> = vobject::parse(source)?
+            .into_iter()
+            .filter(|component| component.name == "VCALENDAR")
+            .collect();
+        if calendars.is_empty() {
+            anyhow::bail!("No BEGIN:VCALENDAR calendar in the file");
+        }
+        let mut elements = vec![];
+        let mut title = None;
+        for calendar in &calendars {
+            let name = calendar.text("X-WR-CALNAME");
+            let level = if let Some(name) = &name {
+                elements.push(Element::Header {
+                    level: 1,
+                    text: name.clone(),
+                });
+                2
+            } else {
+                1
+            };
+            if let Some(text) = calendar.text("X-WR-CALDESC") {
+                elements.extend(description(&text));
+            }
+            for event in sorted(calendar, "VEVENT", "DTSTART") {
+                elements.extend(section(event, level));
+            }
+            for task in sorted(calendar, "VTODO", "DUE") {
+                elements.extend(section(task, level));
+            }
+            title = title.or(name);
+        }
+        let mut document = Document::new(elements);
+        document.metadata.title = title;
+        Ok(document)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use crate::core::Element;
+    use crate::icalendar::Transformer;
+
+    #[test]
+    fn test_parse() -> anyhow::Result<()> {
+        let source = "BEGIN:VCALENDAR\r\n\
+             VERSION:2.0\r\n\
+             PRODID:-//Example//Calendar//EN\r\n\
+             X-WR-CALNAME:Team\r\n\
+             BEGIN:VEVENT\r\n\
+             UID:2@example.com\r\n\
+             SUMMARY:Offsite\r\n\
+             DTSTART;VALUE=DATE:20260228\r\n\
+             DTEND;VALUE=DATE:20260302\r\n\
+             END:VEVENT\r\n\
+             BEGIN:VEVENT\r\n\
+             UID:1@example.com\r\n\
+             SUMMARY:Planning\r\n\
+             DTSTART;TZID=Europe/Berlin:20260210T100000\r\n\
+             DTEND;TZID=Europe/Berlin:20260210T113000\r\n\
+             LOCATION:Room 4\\, 2nd floor\r\n\
+             ORGANIZER;CN=Jane Doe:mailto:jane@example.com\r\n\
+             ATTENDEE;CN=John Roe;PARTSTAT=NEEDS-ACTION:mailto:john@example.com\r\n\
+             RRULE:FREQ=WEEKLY;COUNT=4\r\n\
+             DESCRIPTION:Agenda:\\n- budget\\n- hiring\r\n\
+             BEGIN:VALARM\r\n\
+             ACTION:DISPLAY\r\n\
+             DESCRIPTION:Reminder\r\n\
+             TRIGGER:-PT15M\r\n\
+             END:VALARM\r\n\
+             END:VEVENT\r\n\
+             BEGIN:VTODO\r\n\
+             SUMMARY:Book the venue\r\n\
+             DUE:20260215T170000Z\r\n\
+             STATUS:NEEDS-ACTION\r\n\
+             END:VTODO\r\n\
+             END:VCALENDAR\r\n";
+        let document = Transformer::parse(&Bytes::from(source))?;
+        assert_eq!(document.metadata.title.as_deref(), Some("Team"));
+        let elements = document.get_all_elements();
+        let headers: Vec<(u8, &str)> = elements
+            .iter()
+            .filter_map(|element| match element {
+                Element::Header { level, text } => Some((*level, text.as_str())),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            headers,
+            [
+                (1, "Team"),
+                (2, "Planning"),
+                (2, "Offsite"),
+                (2, "Book the venue")
+            ]
+        );
+        let tables: Vec<Vec<[String; 2]>> = elements
+            .iter()
+            .filter_map(|element| match element {
+                Element::Table { rows, .. } => Some(
+                    rows.iter()
+                        .map(|row| {
+                            [
+                                row.cells[0].element.plain_text(),
+                                row.cells[1].element.plain_text(),
+                            ]
+                        })
+                        .collect(),
+                ),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            tables[0],
+            [
+                ["When", "2026-02-10 10:00 – 11:30 Europe/Berlin"],
+                ["Location", "Room 4, 2nd floor"],
+                ["Repeats", "FREQ=WEEKLY;COUNT=4"],
+                ["Organizer", "Jane Doe"],
+                ["Attendee", "John Roe (needs action)"],
+            ]
+            .map(|row| row.map(String::from))
+        );
+        assert_eq!(
+            tables[1],
+            [["When", "2026-02-28 – 2026-03-01"].map(String::from)]
+        );
+        assert_eq!(
+            tables[2],
+            [["Due", "2026-02-15 17:00 UTC"], ["Status", "NEEDS-ACTION"]]
+                .map(|row| row.map(String::from))
+        );
+        assert!(matches!(
+            elements[3],
+            Element::Paragraph { elements, .. } if elements.len() == 5
+        ));
+        assert!(!elements
+            .iter()
+            .any(|element| element.plain_text().contains("Reminder")));
+        Ok(())
+    }
+}
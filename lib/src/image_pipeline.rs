@@ -0,0 +1,289 @@
+//! Processing of the images of a document before generation: downscaling, conversion of the
+//! formats browsers cannot show and removal of the EXIF metadata (see [`ImageOptions`])
+
+use std::io::Cursor;
+
+use bytes::Bytes;
+use image::{imageops::FilterType, ImageFormat, ImageOutputFormat};
+
+use crate::core::{
+    Document, DocumentType, Element, ImageData, ImageOptions, PageDimensions, PageOrientation,
+};
+
+/// Quality of the re-encoded JPEG images
+const JPEG_QUALITY: u8 = 90;
+
+/// Copy of the document with its images processed for `document_type`
+pub fn process_images(
+    document: &Document,
+    document_type: DocumentType,
+    options: &ImageOptions,
+) -> crate::Result<Document> {
+    let pipeline = Pipeline {
+        max_pixels: max_pixels(document, document_type, options),
+        web_formats: options.web_formats
+            && matches!(document_type, DocumentType::HTML | DocumentType::Markdown),
+        strip_metadata: options.strip_metadata,
+    };
+
+    fn visit(element: &mut Element, pipeline: &Pipeline) -> crate::Result<()> {
+        if let Element::Image(image) = element {
+            pipeline.process(image).map_err(|error| {
+                crate::Error::GenerationError(format!(
+                    "Cannot process image {}: {:#}",
+                    image.title(),
+                    error
+                ))
+            })?;
+        }
+        for child in element.children_mut() {
+            visit(child, pipeline)?;
+        }
+        Ok(())
+    }
+
+    let mut document = document.clone();
+    for band in document.bands.iter_mut() {
+        for element in band.elements_mut() {
+            visit(element, &pipeline)?;
+        }
+    }
+    Ok(document)
+}
+
+/// Largest width and height of the images: the `max_pixels` option, and for PDF the
+/// widest page content at `max_dpi`
+fn max_pixels(
+    document: &Document,
+    document_type: DocumentType,
+    options: &ImageOptions,
+) -> Option<u32> {
+    let dpi_limit = match (document_type, options.max_dpi) {
+        (DocumentType::PDF, Some(max_dpi)) => {
+            let width = std::iter::once((&document.page_format, &document.orientation))
+                .chain(
+                    document
+                        .get_sections()
+                        .iter()
+                        .map(|section| (&section.page_format, &section.orientation)),
+                )
+                .map(|(page_format, orientation)| {
+                    let PageDimensions {
+                        page_width,
+                        page_height,
+                        page_margin_left,
+                        page_margin_right,
+                        ..
+                    } = page_format.dimensions();
+                    let width = match orientation {
+                        PageOrientation::Landscape => page_height,
+                        _ => page_width,
+                    };
+                    width.to_inches() - page_margin_left.to_inches() - page_margin_right.to_inches()
+                })
+                .fold(0.0, f32::max);
+            Some((width * max_dpi).round().max(1.0) as u32)
+        }
+        _ => None,
+    };
+    match (options.max_pixels, dpi_limit) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+struct Pipeline {
+    max_pixels: Option<u32>,
+    web_formats: bool,
+    strip_metadata: bool,
+}
+
+impl Pipeline {
+    fn process(&self, image: &mut ImageData) -> anyhow::Result<()> {
+        // SVG and the formats the image crate does not know are left as is
+        let Ok(format) = image::guess_format(image.bytes()) else {
+            return Ok(());
+        };
+        let convert = self.web_formats && matches!(format, ImageFormat::Bmp | ImageFormat::Tiff);
+        let oversized = match self.max_pixels {
+            // animated GIFs would lose their frames
+            Some(max_pixels) if format != ImageFormat::Gif => {
+                let (width, height) =
+                    image::io::Reader::with_format(Cursor::new(image.bytes().as_ref()), format)
+                        .into_dimensions()?;
+                width.max(height) > max_pixels
+            }
+            _ => false,
+        };
+
+        if convert || oversized {
+            let mut decoded = image::load_from_memory_with_format(image.bytes(), format)?;
+            if let (true, Some(max_pixels)) = (oversized, self.max_pixels) {
+                decoded = decoded.resize(max_pixels, max_pixels, FilterType::Lanczos3);
+            }
+            let format = if convert { ImageFormat::Png } else { format };
+            let output_format = match format {
+                ImageFormat::Jpeg => ImageOutputFormat::Jpeg(JPEG_QUALITY),
+                format => ImageOutputFormat::from(format),
+            };
+            // the encoders do not write the metadata back
+            let mut bytes = Cursor::new(vec![]);
+            decoded.write_to(&mut bytes, output_format)?;
+            image.set_image_bytes(Bytes::from(bytes.into_inner()));
+            if convert {
+                image.set_image_type("png");
+            }
+        } else if self.strip_metadata {
+            let stripped = match format {
+                ImageFormat::Jpeg => strip_jpeg_exif(image.bytes()),
+                ImageFormat::Png => strip_png_exif(image.bytes()),
+                _ => None,
+            };
+            if let Some(bytes) = stripped {
+                image.set_image_bytes(Bytes::from(bytes));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// JPEG without its APP1 segments (EXIF and XMP), `None` when the file cannot be read
+fn strip_jpeg_exif(bytes: &[u8]) -> Option<Vec<u8>> {
+    if !bytes.starts_with(&[0xFF, 0xD8]) {
+        return None;
+    }
+    let mut output = bytes[..2].to_vec();
+    let mut position = 2;
+    while position + 4 <= bytes.len() {
+        if bytes[position] != 0xFF {
+            return None;
+        }
+        let marker = bytes[position + 1];
+        // start of scan, the compressed data follows up to the end of the file
+        if marker == 0xDA {
+            output.extend_from_slice(&bytes[position..]);
+            return Some(output);
+        }
+        let length = u16::from_be_bytes([bytes[position + 2], bytes[position + 3]]) as usize;
+        let end = position + 2 + length;
+        if end > bytes.len() {
+            return None;
+        }
+        if marker != 0xE1 {
+            output.extend_from_slice(&bytes[position..end]);
+        }
+        position = end;
+    }
+    None
+}
+
+/// PNG without its `eXIf` chunk, `None` when the file cannot be read
+fn strip_png_exif(bytes: &[u8]) -> Option<Vec<u8>> {
+    const SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+    if !bytes.starts_with(SIGNATURE) {
+        return None;
+    }
+    let mut output = SIGNATURE.to_vec();
+    let mut position = SIGNATURE.len();
+    // chunk: length, type, data, CRC
+    while position + 12 <= bytes.len() {
+        let length = u32::from_be_bytes(bytes[position..position + 4].try_into().ok()?) as usize;
+        let end = position + 12 + length;
+        if end > bytes.len() {
+            return None;
+        }
+        if &bytes[position + 4..position + 8] != b"eXIf" {
+            output.extend_from_slice(&bytes[position..end]);
+        }
+        position = end;
+    }
+    Some(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use bytes::Bytes;
+    use image::{ImageFormat, RgbImage};
+
+    use crate::core::*;
+    use crate::image_pipeline::*;
+
+    fn encode(width: u32, height: u32, format: ImageFormat) -> Bytes {
+        let mut bytes = Cursor::new(vec![]);
+        RgbImage::new(width, height)
+            .write_to(&mut bytes, format)
+            .unwrap();
+        Bytes::from(bytes.into_inner())
+    }
+
+    fn image_document(bytes: Bytes, src: &str) -> Document {
+        Document::new(vec![Element::Paragraph {
+            elements: vec![Element::Image(ImageData::new(
+                bytes,
+                "image".to_string(),
+                "".to_string(),
+                src.to_string(),
+                "".to_string(),
+                ImageDimension::default(),
+            ))],
+            direction: TextDirection::default(),
+        }])
+    }
+
+    fn image(document: &Document) -> &ImageData {
+        match document.get(&"0/0/0".parse().unwrap()) {
+            Some(Element::Image(image)) => image,
+            element => panic!("Not an image: {:?}", element),
+        }
+    }
+
+    #[test]
+    fn test_process_images() -> anyhow::Result<()> {
+        let document = image_document(encode(400, 100, ImageFormat::Bmp), "image.bmp");
+        let options = ImageOptions {
+            max_pixels: Some(200),
+            web_formats: true,
+            ..Default::default()
+        };
+
+        let html = process_images(&document, DocumentType::HTML, &options)?;
+        assert_eq!(image(&html).image_type(), &ImageType::Png);
+        assert_eq!(image::guess_format(image(&html).bytes())?, ImageFormat::Png);
+        assert_eq!(image::load_from_memory(image(&html).bytes())?.width(), 200);
+
+        // PDF shows BMP images, they are only downscaled
+        let pdf = process_images(&document, DocumentType::PDF, &options)?;
+        assert_eq!(image(&pdf).image_type(), &ImageType::Bmp);
+        assert_eq!(image::guess_format(image(&pdf).bytes())?, ImageFormat::Bmp);
+
+        // A4 content width of 190mm at 72 dpi
+        let options = ImageOptions {
+            max_dpi: Some(72.0),
+            ..Default::default()
+        };
+        let document = image_document(encode(1000, 10, ImageFormat::Png), "image.png");
+        let pdf = process_images(&document, DocumentType::PDF, &options)?;
+        assert_eq!(image::load_from_memory(image(&pdf).bytes())?.width(), 539);
+        let html = process_images(&document, DocumentType::HTML, &options)?;
+        assert_eq!(image(&html), image(&document));
+        Ok(())
+    }
+
+    #[test]
+    fn test_strip_metadata() -> anyhow::Result<()> {
+        let jpeg = encode(8, 8, ImageFormat::Jpeg);
+        let exif = [0xFF, 0xE1, 0x00, 0x08, b'E', b'x', b'i', b'f', 0x00, 0x00];
+        let with_exif = Bytes::from([&jpeg[..2], &exif, &jpeg[2..]].concat());
+
+        let document = image_document(with_exif, "image.jpeg");
+        let options = ImageOptions {
+            strip_metadata: true,
+            ..Default::default()
+        };
+        let stripped = process_images(&document, DocumentType::Markdown, &options)?;
+        assert_eq!(image(&stripped).bytes(), &jpeg);
+        Ok(())
+    }
+}
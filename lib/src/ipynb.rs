@@ -0,0 +1,383 @@
+//! Jupyter notebooks (nbformat 4), read cell by cell
+//!
+//! Markdown cells go through the markdown parser, their `attachment:` images taken from the
+//! cell. Code cells are code blocks in the language of the kernel, followed by their outputs:
+//! PNG and JPEG images, markdown, or else plain text in a `text` code block. Raw cells are raw
+//! elements of the format of their MIME type. Notebooks are parsed only.
+
+use base64::engine::general_purpose;
+use base64::Engine;
+use bytes::Bytes;
+use serde_json::{Map, Value};
+
+use crate::core::{
+    disk_image_loader, Band, Document, DocumentType, Element, ImageData, ImageDimension,
+    ParseOptions, TransformerWithImageLoaderSaverTrait,
+};
+use crate::markdown;
+
+pub struct Transformer;
+
+/// Language of the code cells of a notebook without kernel information
+const DEFAULT_LANGUAGE: &str = "python";
+
+/// Info string of the code blocks of text outputs
+const OUTPUT_INFO: &str = "text";
+
+/// Text of a multiline string of a notebook, a string or a list of lines
+fn text(value: Option<&Value>) -> String {
+    match value {
+        Some(Value::String(text)) => text.clone(),
+        Some(Value::Array(lines)) => lines.iter().filter_map(Value::as_str).collect(),
+        _ => String::new(),
+    }
+}
+
+/// Text without the ANSI escape sequences that color the tracebacks
+fn without_ansi_codes(text: &str) -> String {
+    let mut plain = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\u{1b}' {
+            // CSI sequence: `ESC [`, parameters and a final letter
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else {
+            plain.push(c);
+        }
+    }
+    plain
+}
+
+/// Bytes of base64 data of a notebook, which may be split into lines
+fn decode(value: Option<&Value>) -> anyhow::Result<Bytes> {
+    let data: String = text(value).split_whitespace().collect();
+    Ok(Bytes::from(general_purpose::STANDARD.decode(data)?))
+}
+
+fn image(bytes: Bytes, image_type: &str) -> Element {
+    Element::Image(ImageData::new(
+        bytes,
+        String::new(),
+        String::new(),
+        image_type.to_string(),
+        String::new(),
+        ImageDimension::default(),
+    ))
+}
+
+/// Code block of text, `None` for blank text
+fn text_block(text: &str) -> Option<Element> {
+    let code = text.trim_end();
+    (!code.trim().is_empty()).then(|| Element::CodeBlock {
+        info: OUTPUT_INFO.to_string(),
+        code: code.to_string(),
+    })
+}
+
+/// Format of the raw cells of a MIME type, as set by the raw cell format of Jupyter
+fn raw_format(mime_type: &str) -> Option<DocumentType> {
+    match mime_type {
+        "text/html" => Some(DocumentType::HTML),
+        "text/latex" => Some(DocumentType::LaTeX),
+        "text/restructuredtext" => Some(DocumentType::RST),
+        "text/asciidoc" => Some(DocumentType::AsciiDoc),
+        "text/markdown" => Some(DocumentType::Markdown),
+        _ => None,
+    }
+}
+
+impl Transformer {
+    /// Document of the notebook, the images of its markdown cells other than the cell
+    /// attachments loaded from the current directory
+    pub fn parse(document: &Bytes) -> crate::Result<Document> {
+        Transformer::parse_with_options(document, &ParseOptions::default())
+    }
+
+    /// Same as [`Transformer::parse`] with the markdown options for the markdown cells
+    pub fn parse_with_options(document: &Bytes, options: &ParseOptions) -> crate::Result<Document> {
+        Transformer::parse_with_loader(document, disk_image_loader("."), options)
+    }
+
+    /// Document of the notebook, the images of its markdown cells other than the cell
+    /// attachments loaded with `image_loader`
+    pub fn parse_with_loader<F>(
+        document: &Bytes,
+        image_loader: F,
+        options: &ParseOptions,
+    ) -> crate::Result<Document>
+    where
+        F: Fn(&str) -> anyhow::Result<Bytes>,
+    {
+        let notebook: Value = serde_json::from_slice(document)
+            .map_err(|error| crate::Error::parse_error(error.into()))?;
+        let Some(cells) = notebook.get("cells").and_then(Value::as_array) else {
+            return Err(crate::Error::parse_error(anyhow::anyhow!(
+                "The notebook has no cells, only nbformat 4 is read"
+            )));
+        };
+        let metadata = notebook.get("metadata");
+        let language = metadata
+            .and_then(|metadata| {
+                metadata
+                    .pointer("/kernelspec/language")
+                    .or_else(|| metadata.pointer("/language_info/name"))
+            })
+            .and_then(Value::as_str)
+            .unwrap_or(DEFAULT_LANGUAGE);
+
+        let mut elements = vec![];
+        for cell in cells {
+            let source = text(cell.get("source"));
+            match cell.get("cell_type").and_then(Value::as_str) {
+                Some("markdown") => {
+                    let attachments = cell.get("attachments").and_then(Value::as_object);
+                    let cell_loader = |path: &str| match path.strip_prefix("attachment:") {
+                        Some(name) => attachment(attachments, name),
+                        None => image_loader(path),
+                    };
+                    let parsed = markdown::Transformer::parse_with_loader_and_options(
+                        &Bytes::from(source),
+                        cell_loader,
+                        options,
+                    )?;
+                    elements.extend(parsed.bands.into_iter().flat_map(|band| match band {
+                        Band::Title(elements)
+                        | Band::Detail(elements)
+                        | Band::Summary(elements) => elements,
+                        _ => vec![],
+                    }));
+                }
+                Some("code") => {
+                    if !source.trim().is_empty() {
+                        elements.push(Element::CodeBlock {
+                            info: language.to_string(),
+                            code: source.trim_end().to_string(),
+                        });
+                    }
+                    let outputs = cell.get("outputs").and_then(Value::as_array);
+                    for output in outputs.into_iter().flatten() {
+                        elements.extend(Transformer::output(output, options)?);
+                    }
+                }
+                Some("raw") => {
+                    let mime_type = cell
+                        .pointer("/metadata/raw_mimetype")
+                        .or_else(|| cell.pointer("/metadata/format"))
+                        .and_then(Value::as_str)
+                        .unwrap_or_default();
+                    match raw_format(mime_type) {
+                        Some(format) if !source.trim().is_empty() => elements.push(Element::Raw {
+                            format,
+                            content: source,
+                        }),
+                        Some(_) => {}
+                        None => elements.extend(text_block(&source)),
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let mut document = Document::new(elements);
+        if let Some(metadata) = metadata {
+            document.metadata.title = metadata
+                .get("title")
+                .and_then(Value::as_str)
+                .map(str::to_string);
+            let authors: Vec<&str> = metadata
+                .get("authors")
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+                .filter_map(|author| author.get("name").or(Some(author)))
+                .filter_map(Value::as_str)
+                .collect();
+            if !authors.is_empty() {
+                document.metadata.author = Some(authors.join(", "));
+            }
+        }
+        Ok(document)
+    }
+
+    /// Elements of an output of a code cell: its richest representation that is read
+    fn output(output: &Value, options: &ParseOptions) -> crate::Result<Vec<Element>> {
+        let output_type = output.get("output_type").and_then(Value::as_str);
+        Ok(match output_type {
+            Some("stream") => text_block(&text(output.get("text"))).into_iter().collect(),
+            Some("error") => {
+                let traceback = output
+                    .get("traceback")
+                    .and_then(Value::as_array)
+                    .map(|lines| {
+                        lines
+                            .iter()
+                            .filter_map(Value::as_str)
+                            .collect::<Vec<_>>()
+                            .join("\n")
+                    })
+                    .unwrap_or_default();
+                let traceback = if traceback.trim().is_empty() {
+                    format!(
+                        "{}: {}",
+                        text(output.get("ename")),
+                        text(output.get("evalue"))
+                    )
+                } else {
+                    traceback
+                };
+                text_block(&without_ansi_codes(&traceback))
+                    .into_iter()
+                    .collect()
+            }
+            Some("execute_result" | "display_data") => {
+                let Some(data) = output.get("data") else {
+                    return Ok(vec![]);
+                };
+                if let Some(png) = data.get("image/png") {
+                    let bytes = decode(Some(png)).map_err(crate::Error::parse_error)?;
+                    vec![image(bytes, "png")]
+                } else if let Some(jpeg) = data.get("image/jpeg") {
+                    let bytes = decode(Some(jpeg)).map_err(crate::Error::parse_error)?;
+                    vec![image(bytes, "jpeg")]
+                } else if let Some(markdown) = data.get("text/markdown") {
+                    let parsed = markdown::Transformer::parse_with_loader_and_options(
+                        &Bytes::from(text(Some(markdown))),
+                        |path: &str| anyhow::bail!("No image {path} in the notebook"),
+                        options,
+                    )?;
+                    parsed.get_all_elements().into_iter().cloned().collect()
+                } else {
+                    text_block(&text(data.get("text/plain")))
+                        .into_iter()
+                        .collect()
+                }
+            }
+            _ => vec![],
+        })
+    }
+}
+
+/// Image attached to a markdown cell, in the first of its representations
+fn attachment(attachments: Option<&Map<String, Value>>, name: &str) -> anyhow::Result<Bytes> {
+    let bundle = attachments
+        .and_then(|attachments| attachments.get(name))
+        .and_then(Value::as_object)
+        .ok_or_else(|| anyhow::anyhow!("No attachment {name} in the cell"))?;
+    let data = bundle
+        .values()
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("The attachment {name} is empty"))?;
+    decode(Some(data))
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use crate::core::tests::init_logger;
+    use crate::core::{Element, ParseOptions};
+    use crate::ipynb::Transformer;
+
+    #[test]
+    fn test_parse() -> anyhow::Result<()> {
+        init_logger();
+        let png = base64::Engine::encode(
+            &base64::engine::general_purpose::STANDARD,
+            std::fs::read("test/data/small.png")?,
+        );
+        let notebook = serde_json::json!({
+            "nbformat": 4,
+            "nbformat_minor": 5,
+            "metadata": {
+                "kernelspec": {"language": "python", "name": "python3"},
+                "title": "Analysis",
+                "authors": [{"name": "Ada"}, {"name": "Alan"}]
+            },
+            "cells": [
+                {
+                    "cell_type": "markdown",
+                    "metadata": {},
+                    "source": ["# Results\n", "\n", "![plot](attachment:plot.png)"],
+                    "attachments": {"plot.png": {"image/png": png}}
+                },
+                {
+                    "cell_type": "code",
+                    "metadata": {},
+                    "execution_count": 1,
+                    "source": "print(1 + 1)\n1 / 0",
+                    "outputs": [
+                        {"output_type": "stream", "name": "stdout", "text": ["2\n"]},
+                        {
+                            "output_type": "error",
+                            "ename": "ZeroDivisionError",
+                            "evalue": "division by zero",
+                            "traceback": ["\u{1b}[0;31mZeroDivisionError\u{1b}[0m: division by zero"]
+                        },
+                        {
+                            "output_type": "display_data",
+                            "metadata": {},
+                            "data": {"image/png": png, "text/plain": "<Figure>"}
+                        },
+                        {
+                            "output_type": "execute_result",
+                            "execution_count": 1,
+                            "metadata": {},
+                            "data": {"text/plain": ["   a\n", "0  1"]}
+                        }
+                    ]
+                },
+                {
+                    "cell_type": "raw",
+                    "metadata": {"raw_mimetype": "text/html"},
+                    "source": "<hr/>"
+                }
+            ]
+        });
+        let document = Transformer::parse_with_loader(
+            &Bytes::from(serde_json::to_vec(&notebook)?),
+            |path| anyhow::bail!("no file {path}"),
+            &ParseOptions::default(),
+        )?;
+        assert_eq!(document.metadata.title.as_deref(), Some("Analysis"));
+        assert_eq!(document.metadata.author.as_deref(), Some("Ada, Alan"));
+
+        let elements = document.get_all_elements();
+        assert!(matches!(elements[0], Element::Header { level: 1, text } if text == "Results"));
+        let code_blocks: Vec<(&str, &str)> = elements
+            .iter()
+            .filter_map(|element| match element {
+                Element::CodeBlock { info, code } => Some((info.as_str(), code.as_str())),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            code_blocks,
+            [
+                ("python", "print(1 + 1)\n1 / 0"),
+                ("text", "2"),
+                ("text", "ZeroDivisionError: division by zero"),
+                ("text", "   a\n0  1"),
+            ]
+        );
+        let images = elements
+            .iter()
+            .flat_map(|element| match element {
+                Element::Paragraph { elements, .. } => elements.iter().collect(),
+                element => vec![*element],
+            })
+            .filter(|element| matches!(element, Element::Image(image) if !image.bytes().is_empty()))
+            .count();
+        assert_eq!(images, 2);
+        assert!(matches!(
+            elements.last(),
+            Some(Element::Raw { content, .. }) if content == "<hr/>"
+        ));
+
+        assert!(Transformer::parse(&Bytes::from("{\"nbformat\": 4}")).is_err());
+        Ok(())
+    }
+}
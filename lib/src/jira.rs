@@ -0,0 +1,934 @@
+//! Jira wiki markup, the Textile-like markup of the issues and comments of Jira and of the
+//! older Confluence versions
+//!
+//! Headings are `h1.` to `h6.` lines, lists `*`, `#` and `-` lines, tables `||` header and
+//! `|` cell rows, code `{code}` and `{noformat}` blocks and quotes `bq.` lines and
+//! `{quote}` blocks, read as containers of the `quote` class. The `{info}`, `{note}`,
+//! `{tip}` and `{warning}` macros are admonitions and `{panel}` an aside. Text is
+//! `*strong*`, `_emphasis_`, `??citation??` and `-deleted-`, `{{monospaced}}` code, links
+//! `[text|url]`, images `!name!` attachments loaded by name, anchors `{anchor:name}`, the
+//! ids of the headings they are in, and line breaks the new lines of a paragraph.
+//! Inserted, superscript and subscript text, colors and emoticons are read as their text.
+//! Jira wiki markup is parsed only.
+
+use std::collections::HashMap;
+
+use bytes::Bytes;
+
+use crate::core::{
+    disk_image_loader, ContainerKind, Document, Element, ImageData, ImageDimension, ListItem,
+    TableCell, TableHeader, TableRow, TextStyle,
+};
+use crate::error::image_loader_errors;
+use crate::lightweight::{paragraph, text, text_block, Inline};
+
+pub struct Transformer;
+
+/// Schemes of the links and of the bare URLs of the text
+const URI_SCHEMES: [&str; 5] = ["https://", "http://", "ftp://", "mailto:", "file:"];
+
+/// Macros of the admonitions, named after their kind
+const ADMONITIONS: [&str; 4] = ["info", "note", "tip", "warning"];
+
+/// Markers of the text effects and their style, `None` for the effects read as text
+const EFFECTS: [(&str, Option<TextStyle>); 7] = [
+    ("*", Some(TextStyle::Strong)),
+    ("_", Some(TextStyle::Emphasis)),
+    ("??", Some(TextStyle::Emphasis)),
+    ("-", Some(TextStyle::Strikethrough)),
+    ("+", None),
+    ("^", None),
+    ("~", None),
+];
+
+/// Container of the blocks of a quote
+fn quote(elements: Vec<Element>) -> Element {
+    Element::Container {
+        kind: ContainerKind::Div,
+        elements,
+    }
+    .with_attributes(HashMap::from([("class".to_string(), "quote".to_string())]))
+}
+
+/// Name and parameters of the macro starting `line`, `{name:key=value|key=value}`, and the
+/// rest of the line after it
+fn macro_start(line: &str) -> Option<(String, HashMap<String, String>, &str)> {
+    let rest = line.trim_start().strip_prefix('{')?;
+    let end = rest.find('}')?;
+    let (name, parameters) = match rest[..end].split_once(':') {
+        Some((name, parameters)) => (name, parameters),
+        None => (&rest[..end], ""),
+    };
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return None;
+    }
+    let mut map = HashMap::new();
+    for (index, parameter) in parameters.split('|').enumerate() {
+        match parameter.split_once('=') {
+            Some((key, value)) => {
+                map.insert(key.trim().to_string(), value.trim().to_string());
+            }
+            // `{code:java}` names its language without a key
+            None if index == 0 && !parameter.trim().is_empty() => {
+                map.insert(String::new(), parameter.trim().to_string());
+            }
+            None => {}
+        }
+    }
+    Some((name.to_lowercase(), map, &rest[end + 1..]))
+}
+
+/// Level and text of a heading line, `h2. Title`
+fn heading(line: &str) -> Option<(u8, &str)> {
+    let rest = line.trim_start().strip_prefix('h')?;
+    let level = rest.chars().next()?.to_digit(10)?;
+    let text = rest[1..].strip_prefix(". ")?;
+    (1..=6)
+        .contains(&level)
+        .then_some((level as u8, text.trim()))
+}
+
+/// Markers and content of a list line, `** item` or `- item`
+fn list_item(line: &str) -> Option<(&str, &str)> {
+    if let Some(content) = line.strip_prefix("- ") {
+        return Some(("*", content.trim()));
+    }
+    let marker = line.find(|c| c != '*' && c != '#')?;
+    if marker == 0 {
+        return None;
+    }
+    let content = line[marker..].strip_prefix(' ')?;
+    Some((&line[..marker], content.trim()))
+}
+
+/// Cells of a table row: whether they are header cells, and their source
+fn table_row(line: &str) -> Vec<(bool, String)> {
+    let chars: Vec<char> = line.trim().chars().collect();
+    let mut cells = vec![];
+    let mut index = 0;
+    while index < chars.len() {
+        let header = chars[index..].starts_with(&['|', '|']);
+        index += if header { 2 } else { 1 };
+        let start = index;
+        let mut depth = 0usize;
+        while index < chars.len() && !(chars[index] == '|' && depth == 0) {
+            match chars[index] {
+                '[' | '{' => depth += 1,
+                ']' | '}' => depth = depth.saturating_sub(1),
+                '\\' => index += 1,
+                _ => {}
+            }
+            index += 1;
+        }
+        let end = index.min(chars.len());
+        // the marker ending the row is not a cell
+        if index < chars.len() || start < end {
+            cells.push((header, chars[start..end].iter().collect()));
+        }
+    }
+    cells
+}
+
+/// Whether the character at `index` can open a text effect: it is not preceded by a
+/// letter or a digit and not followed by a space
+fn opens_effect(chars: &[char], index: usize, marker: usize) -> bool {
+    (index == 0 || !chars[index - 1].is_alphanumeric())
+        && chars
+            .get(index + marker)
+            .is_some_and(|c| !c.is_whitespace())
+}
+
+/// Index of the marker closing the effect opened before `start`, on the same line
+fn effect_end(chars: &[char], start: usize, marker: &[char]) -> Option<usize> {
+    let mut index = start + 1;
+    while index + marker.len() <= chars.len() {
+        if chars[index] == '\n' {
+            return None;
+        }
+        if chars[index..].starts_with(marker)
+            && !chars[index - 1].is_whitespace()
+            && chars
+                .get(index + marker.len())
+                .is_none_or(|c| !c.is_alphanumeric())
+        {
+            return Some(index);
+        }
+        index += 1;
+    }
+    None
+}
+
+/// Whether `chars` start with `prefix`, ignoring the case of ASCII letters
+fn starts_with(chars: &[char], prefix: &str) -> bool {
+    let mut chars = chars.iter();
+    prefix
+        .chars()
+        .all(|p| chars.next().is_some_and(|c| c.eq_ignore_ascii_case(&p)))
+}
+
+struct Parser<'a, F>
+where
+    F: Fn(&str) -> anyhow::Result<Bytes>,
+{
+    image_loader: &'a F,
+    /// Depth of the nested inline elements, bounded against malicious sources
+    depth: usize,
+}
+
+impl<F> Parser<'_, F>
+where
+    F: Fn(&str) -> anyhow::Result<Bytes>,
+{
+    fn blocks(&mut self, lines: &[&str]) -> anyhow::Result<Vec<Element>> {
+        let mut blocks = vec![];
+        let mut pending: Vec<&str> = vec![];
+        let mut index = 0;
+        while index < lines.len() {
+            let line = lines[index].trim_end();
+            let trimmed = line.trim_start();
+            if trimmed.is_empty() {
+                self.paragraph(&mut pending, &mut blocks)?;
+                index += 1;
+                continue;
+            }
+            let block_macro = macro_start(trimmed).filter(|(name, _, _)| {
+                ["code", "noformat", "quote", "panel", "toc"].contains(&name.as_str())
+                    || ADMONITIONS.contains(&name.as_str())
+            });
+            let starts_block = heading(trimmed).is_some()
+                || trimmed.starts_with("bq. ")
+                || trimmed.starts_with('|')
+                || list_item(trimmed).is_some()
+                || trimmed.starts_with("----")
+                || block_macro.is_some();
+            if !starts_block {
+                pending.push(line);
+                index += 1;
+                continue;
+            }
+            self.paragraph(&mut pending, &mut blocks)?;
+            if let Some((level, title)) = heading(trimmed) {
+                let elements = self.inline(title)?;
+                // an anchor in a heading is its id
+                let attributes = elements
+                    .iter()
+                    .find_map(|element| match element {
+                        Element::Bookmark { id, .. } => Some(id.clone()),
+                        _ => None,
+                    })
+                    .map(|id| HashMap::from([("id".to_string(), id)]))
+                    .unwrap_or_default();
+                let header = Element::Header {
+                    level,
+                    text: elements
+                        .iter()
+                        .map(Element::plain_text)
+                        .collect::<String>()
+                        .trim()
+                        .to_string(),
+                };
+                blocks.push(header.with_attributes(attributes));
+                index += 1;
+            } else if let Some(content) = trimmed.strip_prefix("bq. ") {
+                blocks.push(quote(vec![paragraph(self.inline(content.trim())?)]));
+                index += 1;
+            } else if trimmed.starts_with('|') {
+                let end = lines[index..]
+                    .iter()
+                    .position(|line| !line.trim_start().starts_with('|'))
+                    .map_or(lines.len(), |end| index + end);
+                blocks.push(self.table(&lines[index..end])?);
+                index = end;
+            } else if list_item(trimmed).is_some() {
+                let end = lines[index..]
+                    .iter()
+                    .position(|line| list_item(line.trim()).is_none())
+                    .map_or(lines.len(), |end| index + end);
+                let items: Vec<(&str, &str)> = lines[index..end]
+                    .iter()
+                    .filter_map(|line| list_item(line.trim()))
+                    .collect();
+                blocks.extend(self.list(&items, 0)?);
+                index = end;
+            } else if trimmed.starts_with("----") {
+                index += 1;
+            } else if let Some((name, parameters, _)) = block_macro {
+                index = self.block_macro(lines, index, &name, &parameters, &mut blocks)?;
+            }
+        }
+        self.paragraph(&mut pending, &mut blocks)?;
+        Ok(blocks)
+    }
+
+    /// Paragraph of the lines read so far, their new lines being line breaks
+    fn paragraph(
+        &mut self,
+        lines: &mut Vec<&str>,
+        blocks: &mut Vec<Element>,
+    ) -> anyhow::Result<()> {
+        if lines.is_empty() {
+            return Ok(());
+        }
+        let source = lines.join("\n");
+        lines.clear();
+        let elements = self.inline(source.trim())?;
+        if !elements.is_empty() {
+            blocks.push(paragraph(elements));
+        }
+        Ok(())
+    }
+
+    /// Macro block starting at `index`, its content up to the closing `{name}`, and the
+    /// index after it
+    fn block_macro(
+        &mut self,
+        lines: &[&str],
+        index: usize,
+        name: &str,
+        parameters: &HashMap<String, String>,
+        blocks: &mut Vec<Element>,
+    ) -> anyhow::Result<usize> {
+        if name == "toc" {
+            let max_level = parameters
+                .get("maxLevel")
+                .and_then(|level| level.parse().ok())
+                .unwrap_or(3);
+            blocks.push(Element::TableOfContents { max_level });
+            return Ok(index + 1);
+        }
+        let close = format!("{{{name}}}");
+        let (_, _, first) = macro_start(lines[index]).expect("the line starts with a macro");
+        let mut content = vec![];
+        let mut rest = first.to_string();
+        let mut end = index;
+        loop {
+            if let Some(position) = rest.to_lowercase().find(&close) {
+                content.push(rest[..position].to_string());
+                break;
+            }
+            content.push(rest);
+            end += 1;
+            match lines.get(end) {
+                Some(line) => rest = line.to_string(),
+                None => break,
+            }
+        }
+        if content.first().is_some_and(|line| line.trim().is_empty()) {
+            content.remove(0);
+        }
+        if content.last().is_some_and(|line| line.trim().is_empty()) {
+            content.pop();
+        }
+        let title = parameters.get("title").map(String::as_str);
+        match name {
+            "code" | "noformat" => {
+                let language = parameters
+                    .get("language")
+                    .or_else(|| parameters.get(""))
+                    .filter(|_| name == "code")
+                    .cloned()
+                    .unwrap_or_default();
+                if let Some(title) = title {
+                    blocks.push(paragraph(vec![Element::Styled {
+                        style: TextStyle::Strong,
+                        elements: vec![text(title)],
+                    }]));
+                }
+                blocks.push(Element::CodeBlock {
+                    info: language,
+                    code: content.join("\n"),
+                });
+            }
+            _ => {
+                let content: Vec<&str> = content.iter().map(String::as_str).collect();
+                let inner = self.blocks(&content)?;
+                match name {
+                    "quote" => blocks.push(quote(inner)),
+                    "panel" => {
+                        let mut elements = vec![];
+                        if let Some(title) = title {
+                            elements.push(paragraph(vec![Element::Styled {
+                                style: TextStyle::Strong,
+                                elements: self.inline(title)?,
+                            }]));
+                        }
+                        elements.extend(inner);
+                        blocks.push(Element::Container {
+                            kind: ContainerKind::Aside,
+                            elements,
+                        });
+                    }
+                    kind => blocks.push(Element::admonition(kind, title, inner)),
+                }
+            }
+        }
+        Ok(end + 1)
+    }
+
+    /// Table of the rows, a first row of header cells being the headers
+    fn table(&mut self, lines: &[&str]) -> anyhow::Result<Element> {
+        let mut rows: Vec<Vec<(bool, String)>> = lines.iter().map(|line| table_row(line)).collect();
+        rows.retain(|row| !row.is_empty());
+        let mut headers = vec![];
+        if rows
+            .first()
+            .is_some_and(|row| row.iter().all(|(header, _)| *header))
+        {
+            for (_, source) in rows.remove(0) {
+                headers.push(TableHeader {
+                    element: text_block(paragraph(self.inline(source.trim())?)),
+                    width: 30.,
+                });
+            }
+        }
+        let mut table_rows = vec![];
+        for row in rows {
+            let mut cells = vec![];
+            for (header, source) in row {
+                let mut elements = self.inline(source.trim())?;
+                if header {
+                    elements = vec![Element::Styled {
+                        style: TextStyle::Strong,
+                        elements,
+                    }];
+                }
+                cells.push(TableCell {
+                    element: text_block(paragraph(elements)),
+                });
+            }
+            table_rows.push(TableRow { cells });
+        }
+        Ok(Element::Table {
+            headers,
+            rows: table_rows,
+        })
+    }
+
+    /// Lists of list lines, their markers from `depth` on, a `#` making a numbered list
+    fn list(&mut self, lines: &[(&str, &str)], depth: usize) -> anyhow::Result<Vec<Element>> {
+        let mut blocks = vec![];
+        let mut index = 0;
+        while index < lines.len() {
+            let marker = lines[index].0[depth..].chars().next().unwrap_or('*');
+            let run = lines[index..]
+                .iter()
+                .position(|(prefix, _)| {
+                    prefix.len() <= depth || !prefix[depth..].starts_with(marker)
+                })
+                .map_or(lines.len(), |end| index + end);
+            let group = &lines[index..run];
+            index = run.max(index + 1);
+            let mut items = vec![];
+            let mut position = 0;
+            while position < group.len() {
+                let (prefix, content) = group[position];
+                if prefix.len() <= depth + 1 {
+                    items.push(ListItem {
+                        element: text_block(paragraph(self.inline(content)?)),
+                    });
+                    position += 1;
+                    continue;
+                }
+                let nested_end = group[position..]
+                    .iter()
+                    .position(|(prefix, _)| prefix.len() <= depth + 1)
+                    .map_or(group.len(), |end| position + end);
+                for list in self.list(&group[position..nested_end], depth + 1)? {
+                    items.push(ListItem { element: list });
+                }
+                position = nested_end;
+            }
+            blocks.push(Element::List {
+                elements: items,
+                numbered: marker == '#',
+                start: 1,
+                numbering: Default::default(),
+            });
+        }
+        Ok(blocks)
+    }
+
+    fn inline(&mut self, source: &str) -> anyhow::Result<Vec<Element>> {
+        if self.depth > 16 {
+            return Ok(vec![text(source)]);
+        }
+        self.depth += 1;
+        let elements = self.inline_elements(source);
+        self.depth -= 1;
+        elements
+    }
+
+    fn inline_elements(&mut self, source: &str) -> anyhow::Result<Vec<Element>> {
+        let chars: Vec<char> = source.chars().collect();
+        let mut inline = Inline::default();
+        let mut index = 0;
+        'chars: while index < chars.len() {
+            let c = chars[index];
+            if chars[index..].starts_with(&['\\', '\\']) {
+                inline.push(Element::LineBreak);
+                index += 2;
+                continue;
+            }
+            match c {
+                '\\' if index + 1 < chars.len() => {
+                    inline.push_str(&chars[index + 1].to_string());
+                    index += 2;
+                    continue;
+                }
+                '\n' => {
+                    inline.push(Element::LineBreak);
+                    index += 1;
+                    continue;
+                }
+                '{' if chars.get(index + 1) == Some(&'{') => {
+                    let end = (index + 2..chars.len().saturating_sub(1))
+                        .find(|&end| chars[end] == '}' && chars[end + 1] == '}');
+                    if let Some(end) = end {
+                        let code: String = chars[index + 2..end].iter().collect();
+                        inline.push(Element::InlineCode {
+                            text: code.replace("\\{", "{").replace("\\}", "}"),
+                        });
+                        index = end + 2;
+                        continue;
+                    }
+                }
+                '{' => {
+                    let end = chars[index..].iter().position(|c| *c == '}');
+                    if let Some(end) = end.map(|end| index + end) {
+                        let content: String = chars[index + 1..end].iter().collect();
+                        let (name, parameter) = content.split_once(':').unwrap_or((&content, ""));
+                        match name.to_lowercase().as_str() {
+                            "anchor" => {
+                                inline.push(Element::Bookmark {
+                                    id: parameter.trim().to_string(),
+                                    title: String::new(),
+                                });
+                                index = end + 1;
+                                continue;
+                            }
+                            "color" => {
+                                index = end + 1;
+                                continue;
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+                '[' => {
+                    if let Some(end) = chars[index..].iter().position(|c| *c == ']') {
+                        let content: String = chars[index + 1..index + end].iter().collect();
+                        if let Some(element) = self.link(&content)? {
+                            inline.extend(vec![element]);
+                            index += end + 1;
+                            continue;
+                        }
+                    }
+                }
+                '!' if chars
+                    .get(index + 1)
+                    .is_some_and(|c| !c.is_whitespace() && *c != '!') =>
+                {
+                    let end = chars[index + 1..]
+                        .iter()
+                        .position(|c| *c == '!' || *c == '\n')
+                        .map(|end| index + 1 + end);
+                    if let Some(end) = end.filter(|end| chars[*end] == '!') {
+                        let content: String = chars[index + 1..end].iter().collect();
+                        let (name, options) = content.split_once('|').unwrap_or((&content, ""));
+                        if !name.contains(char::is_whitespace) && name.contains('.') {
+                            inline.push(self.image(name, options)?);
+                            index = end + 1;
+                            continue;
+                        }
+                    }
+                }
+                'h' | 'f' | 'm'
+                    if (index == 0 || !chars[index - 1].is_alphanumeric())
+                        && URI_SCHEMES
+                            .iter()
+                            .take(4)
+                            .any(|scheme| starts_with(&chars[index..], scheme)) =>
+                {
+                    let length = chars[index..]
+                        .iter()
+                        .position(|c| c.is_whitespace() || "[]<>\"{}|".contains(*c))
+                        .unwrap_or(chars.len() - index);
+                    let mut url: String = chars[index..index + length].iter().collect();
+                    while url.ends_with(['.', ',', ';', ':', '!', '?', ')', '\'']) {
+                        url.pop();
+                    }
+                    index += url.chars().count();
+                    inline.push(Element::Hyperlink {
+                        elements: vec![text(url.clone())],
+                        url,
+                        alt: String::new(),
+                        size: 14,
+                    });
+                    continue;
+                }
+                _ => {}
+            }
+            for (marker, style) in EFFECTS {
+                let marker: Vec<char> = marker.chars().collect();
+                if !chars[index..].starts_with(&marker)
+                    || !opens_effect(&chars, index, marker.len())
+                {
+                    continue;
+                }
+                // `--` and `---` are dashes
+                if marker == ['-'] && chars.get(index + 1) == Some(&'-') {
+                    break;
+                }
+                let start = index + marker.len();
+                if let Some(end) = effect_end(&chars, start, &marker) {
+                    let content: String = chars[start..end].iter().collect();
+                    let elements = self.inline(&content)?;
+                    match style {
+                        Some(style) => inline.push(Element::Styled { style, elements }),
+                        None => inline.extend(elements),
+                    }
+                    index = end + marker.len();
+                    continue 'chars;
+                }
+            }
+            match c {
+                '-' if chars[index..].starts_with(&['-', '-', '-']) => {
+                    inline.push_str("—");
+                    index += 3;
+                }
+                '-' if chars[index..].starts_with(&['-', '-']) => {
+                    inline.push_str("–");
+                    index += 2;
+                }
+                c => {
+                    inline.push_str(&c.to_string());
+                    index += 1;
+                }
+            }
+        }
+        Ok(inline.finish())
+    }
+
+    /// Element of the content of a `[...]` link: a link to a URL, an anchor or an
+    /// attachment, the text of a user mention, nothing when it is not a link
+    fn link(&mut self, content: &str) -> anyhow::Result<Option<Element>> {
+        let parts: Vec<&str> = content.split('|').collect();
+        let (label, target) = match parts.as_slice() {
+            [target] => (None, target.trim()),
+            [label, target, ..] => (Some(label.trim()), target.trim()),
+            [] => return Ok(None),
+        };
+        let url = if let Some(user) = target.strip_prefix('~') {
+            return Ok(Some(text(format!("@{}", label.unwrap_or(user)))));
+        } else if let Some(attachment) = target.strip_prefix('^') {
+            attachment.to_string()
+        } else if target.starts_with('#')
+            || URI_SCHEMES
+                .iter()
+                .any(|scheme| target.to_lowercase().starts_with(scheme))
+            || (label.is_some() && !target.is_empty())
+        {
+            target.to_string()
+        } else {
+            return Ok(None);
+        };
+        let elements = match label {
+            Some(label) if !label.is_empty() => self.inline(label)?,
+            _ => vec![text(target.trim_start_matches(['#', '^']))],
+        };
+        Ok(Some(Element::Hyperlink {
+            elements,
+            url,
+            alt: String::new(),
+            size: 14,
+        }))
+    }
+
+    /// Image of an attachment or a URL, `!name|width=300,alt="text"!`
+    fn image(&mut self, name: &str, options: &str) -> anyhow::Result<Element> {
+        let mut alt = String::new();
+        let mut title = String::new();
+        let mut size = ImageDimension::default();
+        for option in options.split(',') {
+            let Some((key, value)) = option.split_once('=') else {
+                continue;
+            };
+            let value = value.trim().trim_matches('"').to_string();
+            match key.trim().to_lowercase().as_str() {
+                "alt" => alt = value,
+                "title" => title = value,
+                "width" => size.width = Some(px(&value)),
+                "height" => size.height = Some(px(&value)),
+                _ => {}
+            }
+        }
+        Ok(Element::Image(ImageData::new(
+            (self.image_loader)(name)?,
+            title,
+            alt,
+            name.to_string(),
+            String::new(),
+            size,
+        )))
+    }
+}
+
+/// Length in pixels of a length without unit
+fn px(length: &str) -> String {
+    if !length.is_empty() && length.chars().all(|c| c.is_ascii_digit()) {
+        format!("{length}px")
+    } else {
+        length.to_string()
+    }
+}
+
+impl Transformer {
+    /// Document of Jira wiki markup, its images loaded from the current directory
+    pub fn parse(document: &Bytes) -> crate::Result<Document> {
+        Transformer::parse_with_loader(document, disk_image_loader("."))
+    }
+
+    /// Document of Jira wiki markup, its images loaded with `image_loader` from the names of
+    /// the attachments or the URLs of the `!image!` tags
+    pub fn parse_with_loader<F>(document: &Bytes, image_loader: F) -> crate::Result<Document>
+    where
+        F: Fn(&str) -> anyhow::Result<Bytes>,
+    {
+        Transformer::parse_source(document, &image_loader_errors(image_loader))
+            .map_err(crate::Error::parse_error)
+    }
+
+    fn parse_source<F>(source: &Bytes, image_loader: &F) -> anyhow::Result<Document>
+    where
+        F: Fn(&str) -> anyhow::Result<Bytes>,
+    {
+        let source = std::str::from_utf8(source)?;
+        let source = source.strip_prefix('\u{feff}').unwrap_or(source);
+        let source = source.replace("\r\n", "\n");
+        let lines: Vec<&str> = source.lines().collect();
+        let mut parser = Parser {
+            image_loader,
+            depth: 0,
+        };
+        Ok(Document::new(parser.blocks(&lines)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+    use crate::core::tests::{init_logger, table_texts};
+    use crate::core::Band;
+
+    #[test]
+    fn test_parse() -> anyhow::Result<()> {
+        init_logger();
+        let source = "h1. Getting *started*\n\
+            Install with {{cargo install}}, see [the docs|https://docs.rs] and [#usage].\n\
+            Ask [~ada] -- *bold _both_* -gone- +inserted+ x*y*z\\\\done.\n\n\
+            * One\n** Nested\n* Two\n# First\n#* Mixed\n\n\
+            ||Name||Value||\n|a|[link|https://example.com/a|b]|\n|b| |\n\n\
+            {code:title=Build|language=bash}\ncargo build\n  --release\n{code}\n\n\
+            {noformat}*not bold*{noformat}\n\n\
+            bq. Quoted _text_.\n\n\
+            {quote}\nLine one\nline two\n{quote}\n\n\
+            {warning:title=Careful}Needs Rust.{warning}\n\n\
+            h2. {anchor:usage}Usage\n\
+            !small.png|width=50,alt=\"The logo\"! See https://example.org.\n";
+        let requested = RefCell::new(vec![]);
+        let document = Transformer::parse_with_loader(&Bytes::from(source), |path| {
+            requested.borrow_mut().push(path.to_string());
+            Ok(Bytes::from(std::fs::read(format!("test/data/{path}"))?))
+        })?;
+        assert_eq!(requested.borrow().as_slice(), ["small.png"]);
+
+        let Some(Band::Detail(elements)) = document.bands.last() else {
+            panic!("no detail band");
+        };
+        assert_eq!(
+            elements[0],
+            Element::Header {
+                level: 1,
+                text: "Getting started".to_string()
+            }
+        );
+        let Element::Paragraph {
+            elements: inline, ..
+        } = &elements[1]
+        else {
+            panic!("not a paragraph: {:?}", elements[1]);
+        };
+        assert_eq!(
+            inline[1],
+            Element::InlineCode {
+                text: "cargo install".to_string()
+            }
+        );
+        assert!(
+            matches!(&inline[3], Element::Hyperlink { url, elements, .. }
+            if url == "https://docs.rs" && elements == &[text("the docs")])
+        );
+        assert!(matches!(&inline[5], Element::Hyperlink { url, .. } if url == "#usage"));
+        assert_eq!(inline[6], text("."));
+        assert_eq!(inline[7], Element::LineBreak);
+        assert_eq!(inline[8], text("Ask @ada – "));
+        assert_eq!(
+            inline[9],
+            Element::Styled {
+                style: TextStyle::Strong,
+                elements: vec![
+                    text("bold "),
+                    Element::Styled {
+                        style: TextStyle::Emphasis,
+                        elements: vec![text("both")],
+                    }
+                ],
+            }
+        );
+        assert_eq!(
+            inline[11],
+            Element::Styled {
+                style: TextStyle::Strikethrough,
+                elements: vec![text("gone")],
+            }
+        );
+        assert_eq!(inline[12], text(" inserted x*y*z"));
+        assert_eq!(inline[13], Element::LineBreak);
+        assert_eq!(inline[14], text("done."));
+
+        let Element::List {
+            elements: items,
+            numbered: false,
+            ..
+        } = &elements[2]
+        else {
+            panic!("not a list: {:?}", elements[2]);
+        };
+        assert_eq!(items[0].element, text("One"));
+        assert!(matches!(&items[1].element, Element::List { elements, .. } if elements.len() == 1));
+        assert_eq!(items[2].element, text("Two"));
+        let Element::List {
+            elements: items,
+            numbered: true,
+            ..
+        } = &elements[3]
+        else {
+            panic!("not a numbered list: {:?}", elements[3]);
+        };
+        assert!(matches!(
+            &items[1].element,
+            Element::List {
+                numbered: false,
+                ..
+            }
+        ));
+
+        let Element::Table { headers, rows } = &elements[4] else {
+            panic!("not a table: {:?}", elements[4]);
+        };
+        assert_eq!(headers[1].element, text("Value"));
+        assert!(
+            matches!(&rows[0].cells[1].element, Element::Paragraph { elements, .. }
+            if matches!(&elements[0], Element::Hyperlink { url, .. } if url == "https://example.com/a"))
+        );
+        assert_eq!(rows[1].cells.len(), 2);
+
+        assert_eq!(
+            elements[5],
+            paragraph(vec![Element::Styled {
+                style: TextStyle::Strong,
+                elements: vec![text("Build")],
+            }])
+        );
+        assert_eq!(
+            elements[6],
+            Element::CodeBlock {
+                info: "bash".to_string(),
+                code: "cargo build\n  --release".to_string()
+            }
+        );
+        assert_eq!(
+            elements[7],
+            Element::CodeBlock {
+                info: String::new(),
+                code: "*not bold*".to_string()
+            }
+        );
+        assert_eq!(
+            elements[8],
+            quote(vec![paragraph(vec![
+                text("Quoted "),
+                Element::Styled {
+                    style: TextStyle::Emphasis,
+                    elements: vec![text("text")],
+                },
+                text("."),
+            ])])
+        );
+        assert_eq!(
+            elements[9],
+            quote(vec![paragraph(vec![
+                text("Line one"),
+                Element::LineBreak,
+                text("line two"),
+            ])])
+        );
+        assert_eq!(
+            elements[10].admonition_parts(),
+            Some((
+                "warning",
+                Some("Careful".to_string()),
+                &[paragraph(vec![text("Needs Rust.")])][..]
+            ))
+        );
+        assert_eq!(
+            elements[11],
+            Element::Header {
+                level: 2,
+                text: "Usage".to_string()
+            }
+            .with_attributes(HashMap::from([("id".to_string(), "usage".to_string())]))
+        );
+        let Element::Paragraph {
+            elements: inline, ..
+        } = &elements[12]
+        else {
+            panic!("not a paragraph: {:?}", elements[12]);
+        };
+        let Element::Image(image) = &inline[0] else {
+            panic!("not an image: {:?}", inline[0]);
+        };
+        assert_eq!(image.alt(), "The logo");
+        assert_eq!(image.size().width.as_deref(), Some("50px"));
+        assert!(
+            matches!(&inline[2], Element::Hyperlink { url, .. } if url == "https://example.org")
+        );
+        assert_eq!(elements.len(), 13);
+        Ok(())
+    }
+
+    #[test]
+    fn test_tables() -> anyhow::Result<()> {
+        let source = r"||Item||Link||
+|a \| b|[site|https://example.com]|
+||Total|3|
+
+|x|y|
+";
+        let document = Transformer::parse(&Bytes::from(source))?;
+        let elements = document.get_all_elements();
+
+        // a first row of `||` cells is the header, the `|` of a link or escaped does not
+        // split a cell and a row starting with a header cell is not a header
+        let (headers, rows) = table_texts(elements[0]);
+        assert_eq!(headers, ["Item", "Link"]);
+        assert_eq!(rows, [vec!["a | b", "site"], vec!["Total", "3"]]);
+
+        let (headers, rows) = table_texts(elements[1]);
+        assert!(headers.is_empty());
+        assert_eq!(rows, [vec!["x", "y"]]);
+        Ok(())
+    }
+}
@@ -1,14 +1,27 @@
 use crate::core::{
-    Document, Element, ImageAlignment, ImageData, ImageDimension, ImageType, ListItem,
-    PageDimensions, PageFormat, TableCell, TableHeader, TableRow, TransformerTrait,
+    ContainerKind, Document, Element, FieldKind, ImageAlignment, ImageData, ImageDimension,
+    ImageType, Length, ListItem, MediaKind, NumberingStyle, PageDimensions, PageFormat,
+    PageOrientation, ReferenceKind, Section, TableCell, TableHeader, TableRow, TextDirection,
+    TextStyle, TransformerTrait,
 };
 use bytes::Bytes;
 use serde_json::Value;
+use std::collections::HashMap;
 use std::str::FromStr;
 pub struct Transformer;
 
 impl TransformerTrait for Transformer {
-    fn parse(document: &Bytes) -> anyhow::Result<Document> {
+    fn parse(document: &Bytes) -> crate::Result<Document> {
+        Transformer::parse_bytes(document).map_err(crate::Error::parse_error)
+    }
+
+    fn generate(document: &Document) -> crate::Result<Bytes> {
+        Transformer::generate_bytes(document).map_err(crate::Error::generation_error)
+    }
+}
+
+impl Transformer {
+    fn parse_bytes(document: &Bytes) -> anyhow::Result<Document> {
         // Преобразуем Bytes в строку
         let data_str = std::str::from_utf8(document)?;
         let json: Value = serde_json::from_str(data_str)?;
@@ -40,8 +53,8 @@ impl TransformerTrait for Transformer {
 
         // Iterate through the mappings and update values if they exist
         for (key, target) in mappings {
-            if let Some(value) = root.get(key).and_then(|v| v.as_f64()) {
-                *target = value as f32;
+            if let Some(value) = root.get(key).and_then(parse_length) {
+                *target = value;
             } else {
                 return Err(anyhow::anyhow!("Missing or invalid '{}'", key));
             }
@@ -69,12 +82,24 @@ impl TransformerTrait for Transformer {
             page_margin_right,
         });
 
-        let document =
+        let mut document =
             Document::new_with_dimensions(page_header, elements, page_footer, page_custom_format);
+        if let Some(sections) = root.get("sections").and_then(|v| v.as_array()) {
+            for section in sections {
+                document.add_section(parse_section(section)?);
+            }
+        }
+        document.direction = parse_direction(root)?;
+        if let Some(metadata) = root.get("metadata") {
+            document.metadata = serde_json::from_value(metadata.clone())?;
+        }
+        if let Some(form) = root.get("form") {
+            document.form = serde_json::from_value(form.clone())?;
+        }
         Ok(document)
     }
 
-    fn generate(document: &Document) -> anyhow::Result<Bytes> {
+    fn generate_bytes(document: &Document) -> anyhow::Result<Bytes> {
         use serde_json::{Map, Value};
         // Helper function to serialize an Element into serde_json::Value
         fn serialize_element(element: &Element) -> Value {
@@ -93,11 +118,20 @@ impl TransformerTrait for Transformer {
                     map.insert("text".to_string(), Value::String(text.clone()));
                     Value::Object(map)
                 }
-                Element::Paragraph { elements } => {
+                Element::Paragraph {
+                    elements,
+                    direction,
+                } => {
                     let elements_json = elements.iter().map(serialize_element).collect();
                     let mut map = Map::new();
                     map.insert("type".to_string(), Value::String("Paragraph".to_string()));
                     map.insert("elements".to_string(), Value::Array(elements_json));
+                    if *direction != TextDirection::Auto {
+                        map.insert(
+                            "direction".to_string(),
+                            Value::String(direction.to_string()),
+                        );
+                    }
                     Value::Object(map)
                 }
                 Element::Table { headers, rows } => {
@@ -136,7 +170,12 @@ impl TransformerTrait for Transformer {
                     map.insert("rows".to_string(), Value::Array(rows_json));
                     Value::Object(map)
                 }
-                Element::List { elements, numbered } => {
+                Element::List {
+                    elements,
+                    numbered,
+                    start,
+                    numbering,
+                } => {
                     let elements_json: Vec<Value> = elements
                         .iter()
                         .map(|item| {
@@ -150,6 +189,11 @@ impl TransformerTrait for Transformer {
                     let mut map = Map::new();
                     map.insert("type".to_string(), Value::String("List".to_string()));
                     map.insert("numbered".to_string(), Value::Bool(*numbered));
+                    map.insert("start".to_string(), Value::from(*start));
+                    map.insert(
+                        "numbering".to_string(),
+                        Value::String(numbering.to_string()),
+                    );
                     map.insert("elements".to_string(), Value::Array(elements_json));
                     Value::Object(map)
                 }
@@ -189,20 +233,152 @@ impl TransformerTrait for Transformer {
                     map.insert("size".to_string(), Value::Object(size_map));
                     Value::Object(map)
                 }
+                Element::LineBreak => {
+                    let mut map = Map::new();
+                    map.insert("type".to_string(), Value::String("LineBreak".to_string()));
+                    Value::Object(map)
+                }
+                Element::Field { kind } => {
+                    let mut map = Map::new();
+                    map.insert("type".to_string(), Value::String("Field".to_string()));
+                    map.insert("kind".to_string(), Value::String(kind.to_string()));
+                    Value::Object(map)
+                }
+                Element::CrossReference { target_id, kind } => {
+                    let mut map = Map::new();
+                    map.insert(
+                        "type".to_string(),
+                        Value::String("CrossReference".to_string()),
+                    );
+                    map.insert("target_id".to_string(), Value::String(target_id.clone()));
+                    map.insert("kind".to_string(), Value::String(kind.to_string()));
+                    Value::Object(map)
+                }
+                Element::Attributed {
+                    attributes,
+                    element,
+                } => {
+                    let mut map = Map::new();
+                    let attributes_json: Map<String, Value> = attributes
+                        .iter()
+                        .map(|(key, value)| (key.clone(), Value::String(value.clone())))
+                        .collect();
+                    map.insert("type".to_string(), Value::String("Attributed".to_string()));
+                    map.insert("attributes".to_string(), Value::Object(attributes_json));
+                    map.insert("element".to_string(), serialize_element(element));
+                    Value::Object(map)
+                }
+                Element::Bookmark { id, title } => {
+                    let mut map = Map::new();
+                    map.insert("type".to_string(), Value::String("Bookmark".to_string()));
+                    map.insert("id".to_string(), Value::String(id.clone()));
+                    map.insert("title".to_string(), Value::String(title.clone()));
+                    Value::Object(map)
+                }
+                Element::Media { kind, url, title } => {
+                    let mut map = Map::new();
+                    map.insert("type".to_string(), Value::String("Media".to_string()));
+                    map.insert("kind".to_string(), Value::String(kind.to_string()));
+                    map.insert("url".to_string(), Value::String(url.clone()));
+                    map.insert("title".to_string(), Value::String(title.clone()));
+                    Value::Object(map)
+                }
+                Element::Drawing {
+                    width,
+                    height,
+                    shapes,
+                } => {
+                    let mut map = Map::new();
+                    map.insert("type".to_string(), Value::String("Drawing".to_string()));
+                    map.insert("width".to_string(), Value::from(*width));
+                    map.insert("height".to_string(), Value::from(*height));
+                    map.insert(
+                        "shapes".to_string(),
+                        serde_json::to_value(shapes).unwrap_or_default(),
+                    );
+                    Value::Object(map)
+                }
+                Element::TableOfContents { max_level } => {
+                    let mut map = Map::new();
+                    map.insert(
+                        "type".to_string(),
+                        Value::String("TableOfContents".to_string()),
+                    );
+                    map.insert("max_level".to_string(), Value::from(*max_level));
+                    Value::Object(map)
+                }
                 Element::Hyperlink {
-                    title,
+                    elements,
                     url,
                     alt,
                     size,
                 } => {
                     let mut map = Map::new();
+                    let elements_json: Vec<Value> =
+                        elements.iter().map(serialize_element).collect();
                     map.insert("type".to_string(), Value::String("Hyperlink".to_string()));
-                    map.insert("title".to_string(), Value::String(title.clone()));
+                    map.insert("elements".to_string(), Value::Array(elements_json));
                     map.insert("url".to_string(), Value::String(url.clone()));
                     map.insert("alt".to_string(), Value::String(alt.clone()));
                     map.insert("size".to_string(), Value::Number((*size).into()));
                     Value::Object(map)
                 }
+                Element::InlineCode { text } => {
+                    let mut map = Map::new();
+                    map.insert("type".to_string(), Value::String("InlineCode".to_string()));
+                    map.insert("text".to_string(), Value::String(text.clone()));
+                    Value::Object(map)
+                }
+                Element::CodeBlock { info, code } => {
+                    let mut map = Map::new();
+                    map.insert("type".to_string(), Value::String("CodeBlock".to_string()));
+                    map.insert("info".to_string(), Value::String(info.clone()));
+                    map.insert("code".to_string(), Value::String(code.clone()));
+                    Value::Object(map)
+                }
+                Element::Math { tex, display } => {
+                    let mut map = Map::new();
+                    map.insert("type".to_string(), Value::String("Math".to_string()));
+                    map.insert("tex".to_string(), Value::String(tex.clone()));
+                    map.insert("display".to_string(), Value::Bool(*display));
+                    Value::Object(map)
+                }
+                Element::Raw { format, content } => {
+                    let mut map = Map::new();
+                    map.insert("type".to_string(), Value::String("Raw".to_string()));
+                    map.insert("format".to_string(), Value::String(format.to_string()));
+                    map.insert("content".to_string(), Value::String(content.clone()));
+                    Value::Object(map)
+                }
+                Element::Styled { style, elements } => {
+                    let mut map = Map::new();
+                    map.insert("type".to_string(), Value::String("Styled".to_string()));
+                    map.insert("style".to_string(), Value::String(style.to_string()));
+                    map.insert(
+                        "elements".to_string(),
+                        Value::Array(elements.iter().map(serialize_element).collect()),
+                    );
+                    Value::Object(map)
+                }
+                Element::Container { kind, elements } => {
+                    let mut map = Map::new();
+                    map.insert("type".to_string(), Value::String("Container".to_string()));
+                    map.insert("kind".to_string(), Value::String(kind.to_string()));
+                    map.insert(
+                        "elements".to_string(),
+                        Value::Array(elements.iter().map(serialize_element).collect()),
+                    );
+                    Value::Object(map)
+                }
+                Element::Footnote { elements } => {
+                    let mut map = Map::new();
+                    map.insert("type".to_string(), Value::String("Footnote".to_string()));
+                    map.insert(
+                        "elements".to_string(),
+                        Value::Array(elements.iter().map(serialize_element).collect()),
+                    );
+                    Value::Object(map)
+                }
             }
         }
 
@@ -216,59 +392,59 @@ impl TransformerTrait for Transformer {
             .map(serialize_element)
             .collect();
         doc_map.insert("elements".to_string(), Value::Array(elements_json));
+        if document.direction != TextDirection::Auto {
+            doc_map.insert(
+                "direction".to_string(),
+                Value::String(document.direction.to_string()),
+            );
+        }
 
         // Serialize page dimensions and indents
         doc_map.insert(
             "page_width".to_string(),
-            Value::Number(
-                serde_json::Number::from_f64(document.page_format.dimensions().page_width as f64)
-                    .unwrap(),
-            ),
+            length_value(&document.page_format.dimensions().page_width),
         );
         doc_map.insert(
             "page_height".to_string(),
-            Value::Number(
-                serde_json::Number::from_f64(document.page_format.dimensions().page_height as f64)
-                    .unwrap(),
-            ),
+            length_value(&document.page_format.dimensions().page_height),
         );
         doc_map.insert(
             "left_page_indent".to_string(),
-            Value::Number(
-                serde_json::Number::from_f64(
-                    document.page_format.dimensions().page_margin_left as f64,
-                )
-                .unwrap(),
-            ),
+            length_value(&document.page_format.dimensions().page_margin_left),
         );
         doc_map.insert(
             "right_page_indent".to_string(),
-            Value::Number(
-                serde_json::Number::from_f64(
-                    document.page_format.dimensions().page_margin_right as f64,
-                )
-                .unwrap(),
-            ),
+            length_value(&document.page_format.dimensions().page_margin_right),
         );
         doc_map.insert(
             "top_page_indent".to_string(),
-            Value::Number(
-                serde_json::Number::from_f64(
-                    document.page_format.dimensions().page_margin_top as f64,
-                )
-                .unwrap(),
-            ),
+            length_value(&document.page_format.dimensions().page_margin_top),
         );
         doc_map.insert(
             "bottom_page_indent".to_string(),
-            Value::Number(
-                serde_json::Number::from_f64(
-                    document.page_format.dimensions().page_margin_bottom as f64,
-                )
-                .unwrap(),
-            ),
+            length_value(&document.page_format.dimensions().page_margin_bottom),
         );
 
+        // Serialize sections
+        if !document.get_sections().is_empty() {
+            let sections_json: Vec<Value> = document
+                .get_sections()
+                .iter()
+                .map(serialize_section)
+                .collect();
+            doc_map.insert("sections".to_string(), Value::Array(sections_json));
+        }
+
+        if !document.metadata.is_empty() {
+            doc_map.insert(
+                "metadata".to_string(),
+                serde_json::to_value(&document.metadata)?,
+            );
+        }
+        if !document.form.is_empty() {
+            doc_map.insert("form".to_string(), serde_json::to_value(&document.form)?);
+        }
+
         // Serialize page headers
         let page_header_json: Vec<Value> = document
             .get_page_header()
@@ -352,7 +528,10 @@ fn parse_element(value: &Value) -> anyhow::Result<Element> {
                     .ok_or_else(|| anyhow::anyhow!("Paragraph missing 'elements' field"))?
                     .clone(),
             )?;
-            Ok(Element::Paragraph { elements })
+            Ok(Element::Paragraph {
+                elements,
+                direction: parse_direction(obj)?,
+            })
         }
         "Table" => {
             let headers = parse_table_headers(
@@ -374,6 +553,17 @@ fn parse_element(value: &Value) -> anyhow::Result<Element> {
                 .ok_or_else(|| {
                     anyhow::anyhow!("List element missing or invalid 'numbered' field")
                 })?;
+            // `start` and `numbering` are optional to keep older documents readable
+            let start = obj
+                .get("start")
+                .and_then(|v| v.as_u64())
+                .map(|v| v as usize)
+                .unwrap_or(1);
+            let numbering = match obj.get("numbering").and_then(|v| v.as_str()) {
+                Some(numbering_str) => NumberingStyle::from_str(numbering_str)
+                    .map_err(|_| anyhow::anyhow!("Invalid numbering: {}", numbering_str))?,
+                None => NumberingStyle::default(),
+            };
             let list_items = obj
                 .get("elements")
                 .ok_or_else(|| anyhow::anyhow!("List element missing 'elements' field"))?;
@@ -388,6 +578,8 @@ fn parse_element(value: &Value) -> anyhow::Result<Element> {
             Ok(Element::List {
                 elements: list_elements,
                 numbered,
+                start,
+                numbering,
             })
         }
         "Image" => {
@@ -439,12 +631,219 @@ fn parse_element(value: &Value) -> anyhow::Result<Element> {
                 size,
             )))
         }
-        "Hyperlink" => {
+        "LineBreak" => Ok(Element::LineBreak),
+        "Field" => {
+            let kind_str = obj
+                .get("kind")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| anyhow::anyhow!("Field element missing 'kind' field"))?;
+            let kind = FieldKind::from_str(kind_str)
+                .map_err(|_| anyhow::anyhow!("Invalid field kind: {}", kind_str))?;
+            Ok(Element::Field { kind })
+        }
+        "CrossReference" => {
+            let target_id = obj
+                .get("target_id")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| {
+                    anyhow::anyhow!("CrossReference element missing 'target_id' field")
+                })?;
+            let kind_str = obj
+                .get("kind")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| anyhow::anyhow!("CrossReference element missing 'kind' field"))?;
+            let kind = ReferenceKind::from_str(kind_str)
+                .map_err(|_| anyhow::anyhow!("Invalid reference kind: {}", kind_str))?;
+            Ok(Element::CrossReference {
+                target_id: target_id.to_string(),
+                kind,
+            })
+        }
+        "Attributed" => {
+            let attributes = obj
+                .get("attributes")
+                .and_then(|v| v.as_object())
+                .ok_or_else(|| anyhow::anyhow!("Attributed element missing 'attributes' field"))?
+                .iter()
+                .map(|(key, value)| {
+                    value
+                        .as_str()
+                        .map(|value| (key.clone(), value.to_string()))
+                        .ok_or_else(|| anyhow::anyhow!("Invalid value of attribute '{}'", key))
+                })
+                .collect::<anyhow::Result<HashMap<String, String>>>()?;
+            let element =
+                parse_element(obj.get("element").ok_or_else(|| {
+                    anyhow::anyhow!("Attributed element missing 'element' field")
+                })?)?;
+            Ok(Element::Attributed {
+                attributes,
+                element: Box::new(element),
+            })
+        }
+        "Bookmark" => {
+            let id = obj
+                .get("id")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| anyhow::anyhow!("Bookmark element missing 'id' field"))?;
+            let title = obj
+                .get("title")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| anyhow::anyhow!("Bookmark element missing 'title' field"))?;
+            Ok(Element::Bookmark {
+                id: id.to_string(),
+                title: title.to_string(),
+            })
+        }
+        "Media" => {
+            let kind_str = obj
+                .get("kind")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| anyhow::anyhow!("Media element missing 'kind' field"))?;
+            let kind = MediaKind::from_str(kind_str)
+                .map_err(|_| anyhow::anyhow!("Invalid media kind: {}", kind_str))?;
+            let url = obj
+                .get("url")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| anyhow::anyhow!("Media element missing 'url' field"))?;
             let title = obj
                 .get("title")
                 .and_then(|v| v.as_str())
-                .ok_or_else(|| anyhow::anyhow!("Hyperlink element missing 'title' field"))?
+                .unwrap_or_default();
+            Ok(Element::Media {
+                kind,
+                url: url.to_string(),
+                title: title.to_string(),
+            })
+        }
+        "Drawing" => {
+            let size = |key: &str| {
+                obj.get(key)
+                    .and_then(|v| v.as_f64())
+                    .ok_or_else(|| anyhow::anyhow!("Drawing element missing '{}' field", key))
+            };
+            let shapes = obj
+                .get("shapes")
+                .cloned()
+                .ok_or_else(|| anyhow::anyhow!("Drawing element missing 'shapes' field"))?;
+            Ok(Element::Drawing {
+                width: size("width")? as f32,
+                height: size("height")? as f32,
+                shapes: serde_json::from_value(shapes)?,
+            })
+        }
+        "TableOfContents" => {
+            let max_level = obj
+                .get("max_level")
+                .and_then(|v| v.as_u64())
+                .ok_or_else(|| {
+                    anyhow::anyhow!("TableOfContents element missing or invalid 'max_level' field")
+                })?;
+            Ok(Element::TableOfContents {
+                max_level: max_level as u8,
+            })
+        }
+        "InlineCode" => {
+            let text = obj
+                .get("text")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| anyhow::anyhow!("InlineCode element missing 'text' field"))?
+                .to_string();
+            Ok(Element::InlineCode { text })
+        }
+        "Raw" => {
+            let format = obj
+                .get("format")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| anyhow::anyhow!("Raw element missing 'format' field"))?
+                .parse()?;
+            let content = obj
+                .get("content")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| anyhow::anyhow!("Raw element missing 'content' field"))?
+                .to_string();
+            Ok(Element::Raw { format, content })
+        }
+        "CodeBlock" => {
+            let info = obj
+                .get("info")
+                .and_then(|v| v.as_str())
+                .unwrap_or_default()
+                .to_string();
+            let code = obj
+                .get("code")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| anyhow::anyhow!("CodeBlock element missing 'code' field"))?
                 .to_string();
+            Ok(Element::CodeBlock { info, code })
+        }
+        "Math" => {
+            let tex = obj
+                .get("tex")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| anyhow::anyhow!("Math element missing 'tex' field"))?
+                .to_string();
+            let display = obj
+                .get("display")
+                .and_then(|v| v.as_bool())
+                .unwrap_or_default();
+            Ok(Element::Math { tex, display })
+        }
+        "Styled" => {
+            let style_str = obj
+                .get("style")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| anyhow::anyhow!("Styled element missing 'style' field"))?;
+            let style = TextStyle::from_str(style_str)
+                .map_err(|_| anyhow::anyhow!("Invalid text style: {}", style_str))?;
+            let elements = obj
+                .get("elements")
+                .ok_or_else(|| anyhow::anyhow!("Styled element missing 'elements' field"))?;
+            Ok(Element::Styled {
+                style,
+                elements: parse_elements(elements)?,
+            })
+        }
+        "Container" => {
+            let kind_str = obj
+                .get("kind")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| anyhow::anyhow!("Container element missing 'kind' field"))?;
+            let kind = ContainerKind::from_str(kind_str)
+                .map_err(|_| anyhow::anyhow!("Invalid container kind: {}", kind_str))?;
+            let elements = obj
+                .get("elements")
+                .ok_or_else(|| anyhow::anyhow!("Container element missing 'elements' field"))?;
+            Ok(Element::Container {
+                kind,
+                elements: parse_elements(elements)?,
+            })
+        }
+        "Footnote" => {
+            let elements = obj
+                .get("elements")
+                .ok_or_else(|| anyhow::anyhow!("Footnote element missing 'elements' field"))?;
+            Ok(Element::Footnote {
+                elements: parse_elements(elements)?,
+            })
+        }
+        "Hyperlink" => {
+            // documents written before links held elements carry a plain 'title'
+            let elements = match (
+                obj.get("elements"),
+                obj.get("title").and_then(|v| v.as_str()),
+            ) {
+                (Some(elements), _) => parse_elements(elements)?,
+                (None, Some(title)) => vec![Element::Text {
+                    text: title.to_string(),
+                    size: 14,
+                }],
+                (None, None) => {
+                    return Err(anyhow::anyhow!(
+                        "Hyperlink element missing 'elements' field"
+                    ))
+                }
+            };
             let url = obj
                 .get("url")
                 .and_then(|v| v.as_str())
@@ -459,13 +858,13 @@ fn parse_element(value: &Value) -> anyhow::Result<Element> {
                 anyhow::anyhow!("Hyperlink element missing or invalid 'size' field")
             })? as u8;
             Ok(Element::Hyperlink {
-                title,
+                elements,
                 url,
                 alt,
                 size,
             })
         }
-        _ => Err(anyhow::anyhow!("Unknown element type: {}", type_str)),
+        _ => Err(crate::Error::UnsupportedElement(type_str.to_string()).into()),
     }
 }
 
@@ -540,6 +939,80 @@ fn parse_list_item(value: &Value) -> anyhow::Result<ListItem> {
     Ok(ListItem { element })
 }
 
+// Optional 'direction' of the document or of a paragraph, auto when missing
+fn parse_direction(obj: &serde_json::Map<String, Value>) -> anyhow::Result<TextDirection> {
+    match obj.get("direction").and_then(|v| v.as_str()) {
+        Some(direction) => TextDirection::from_str(direction)
+            .map_err(|_| anyhow::anyhow!("Invalid direction: {}", direction)),
+        None => Ok(TextDirection::default()),
+    }
+}
+
+// Page lengths are written in millimeters, strings with a unit ("8.5in") are read too
+fn length_value(length: &Length) -> Value {
+    Value::from((length.to_mm() as f64 * 1000.0).round() / 1000.0)
+}
+
+fn parse_length(value: &Value) -> Option<Length> {
+    match value {
+        Value::Number(number) => number.as_f64().map(|mm| Length::mm(mm as f32)),
+        Value::String(length) => Length::from_str(length).ok(),
+        _ => None,
+    }
+}
+
+// Sections use the same page keys as the document root
+fn serialize_section(section: &Section) -> Value {
+    let dimensions = section.page_format.dimensions();
+    let mut map = serde_json::Map::new();
+    map.insert("start".to_string(), Value::from(section.start));
+    map.insert(
+        "orientation".to_string(),
+        Value::String(section.orientation.to_string()),
+    );
+    for (key, value) in [
+        ("page_width", dimensions.page_width),
+        ("page_height", dimensions.page_height),
+        ("top_page_indent", dimensions.page_margin_top),
+        ("bottom_page_indent", dimensions.page_margin_bottom),
+        ("left_page_indent", dimensions.page_margin_left),
+        ("right_page_indent", dimensions.page_margin_right),
+    ] {
+        map.insert(key.to_string(), length_value(&value));
+    }
+    Value::Object(map)
+}
+
+fn parse_section(value: &Value) -> anyhow::Result<Section> {
+    let obj = value
+        .as_object()
+        .ok_or_else(|| anyhow::anyhow!("Section is not an object"))?;
+    let start = obj
+        .get("start")
+        .and_then(|v| v.as_u64())
+        .ok_or_else(|| anyhow::anyhow!("Section missing or invalid 'start' field"))?
+        as usize;
+    let orientation = match obj.get("orientation").and_then(|v| v.as_str()) {
+        Some(orientation_str) => PageOrientation::from_str(orientation_str)
+            .map_err(|_| anyhow::anyhow!("Invalid orientation: {}", orientation_str))?,
+        None => PageOrientation::default(),
+    };
+    let dimension = |key: &str| -> anyhow::Result<Length> {
+        obj.get(key)
+            .and_then(parse_length)
+            .ok_or_else(|| anyhow::anyhow!("Section missing or invalid '{}'", key))
+    };
+    let page_format = PageFormat::Custom(PageDimensions {
+        page_width: dimension("page_width")?,
+        page_height: dimension("page_height")?,
+        page_margin_top: dimension("top_page_indent")?,
+        page_margin_bottom: dimension("bottom_page_indent")?,
+        page_margin_left: dimension("left_page_indent")?,
+        page_margin_right: dimension("right_page_indent")?,
+    });
+    Ok(Section::new(start, page_format, orientation))
+}
+
 #[cfg(test)]
 mod tests {
     use log::{debug, info};
@@ -615,4 +1088,107 @@ blabla2 bla bla blabla bla bla blabla bla bla blabla bla bla bla"#;
         info!("{}", generated_text2);
         Ok(())
     }
+
+    #[test]
+    fn test_attributes() -> anyhow::Result<()> {
+        use crate::core::{Document, Element};
+        use std::collections::HashMap;
+
+        let document = Document::new(vec![Element::Text {
+            text: "Note".to_string(),
+            size: 8,
+        }
+        .with_attributes(HashMap::from([(
+            "docx:style".to_string(),
+            "Quote".to_string(),
+        )]))]);
+
+        let generated = crate::json::Transformer::generate(&document)?;
+        let parsed = crate::json::Transformer::parse(&generated)?;
+        assert_eq!(parsed.get_all_elements(), document.get_all_elements());
+        Ok(())
+    }
+
+    #[test]
+    fn test_form() -> anyhow::Result<()> {
+        use crate::core::{Document, FormField, FormFieldKind};
+
+        let mut document = Document::new(vec![]);
+        document.form = vec![FormField {
+            name: "agree".to_string(),
+            kind: FormFieldKind::CheckBox,
+            value: "Yes".to_string(),
+            options: vec!["Yes".to_string()],
+            page: Some(1),
+            ..Default::default()
+        }];
+
+        let generated = crate::json::Transformer::generate(&document)?;
+        let parsed = crate::json::Transformer::parse(&generated)?;
+        assert_eq!(parsed.form_fields(), document.form_fields());
+        Ok(())
+    }
+
+    #[test]
+    fn test_drawing() -> anyhow::Result<()> {
+        use crate::core::{Document, Element, PathCommand, Shape};
+
+        let document = Document::new(vec![Element::Drawing {
+            width: 120.0,
+            height: 80.5,
+            shapes: vec![
+                Shape::line(0.0, 80.0, 120.0, 80.0),
+                Shape::path(vec![
+                    PathCommand::MoveTo { x: 0.0, y: 80.0 },
+                    PathCommand::CurveTo {
+                        x1: 40.0,
+                        y1: 0.0,
+                        x2: 80.0,
+                        y2: 0.0,
+                        x: 120.0,
+                        y: 80.0,
+                    },
+                    PathCommand::Close,
+                ])
+                .with_fill("#eee"),
+            ],
+        }]);
+
+        let generated = crate::json::Transformer::generate(&document)?;
+        let parsed = crate::json::Transformer::parse(&generated)?;
+        assert_eq!(parsed.get_all_elements(), document.get_all_elements());
+        Ok(())
+    }
+
+    #[test]
+    fn test_sections() -> anyhow::Result<()> {
+        use crate::core::{Document, Element, PageFormat, PageOrientation, Section};
+
+        let mut document = Document::new(vec![
+            Element::Text {
+                text: "Portrait".to_string(),
+                size: 8,
+            },
+            Element::Text {
+                text: "Landscape".to_string(),
+                size: 8,
+            },
+        ]);
+        document.add_section(Section::new(
+            1,
+            PageFormat::Letter,
+            PageOrientation::Landscape,
+        ));
+
+        let generated = crate::json::Transformer::generate(&document)?;
+        let parsed = crate::json::Transformer::parse(&generated)?;
+        let section = &parsed.get_sections()[0];
+        assert_eq!(section.start, 1);
+        assert_eq!(section.orientation, PageOrientation::Landscape);
+        assert_eq!(
+            section.page_format.dimensions(),
+            PageFormat::Letter.dimensions()
+        );
+        Ok(())
+    }
 }
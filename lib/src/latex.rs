@@ -0,0 +1,2770 @@
+//! LaTeX source of a document, compiled by pdfLaTeX, XeLaTeX or LuaLaTeX
+//!
+//! Headers become the sectioning commands of the document class, tables `tabular`
+//! environments, images `\includegraphics` of files saved next to the source and links
+//! `hyperref` links. Labels are set at the header anchors (see [`header_anchor`]) and at the
+//! bookmarks, so that `#anchor` links become internal references. Raw LaTeX is written as is.
+//!
+//! Sources are read for a common subset: the sectioning commands, `itemize`, `enumerate` and
+//! `description` lists, `tabular` tables, `\includegraphics` images in figures, verbatim
+//! environments, emphasis, links, footnotes and references to labels. Math, inline or in
+//! display environments, is kept in TeX notation as [`Element::Math`]; other commands are left
+//! out with their text kept.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write as _;
+
+use bytes::Bytes;
+use log::{debug, warn};
+
+use crate::core::{
+    disk_image_loader, disk_image_saver, header_anchor, Band, ContainerKind, Document,
+    DocumentType, Element, FieldKind, GenerateOptions, ImageData, ImageDimension, ImageType,
+    LatexClass, LatexOptions, Length, LengthUnit, ListItem, Metadata, NumberingStyle,
+    PageOrientation, ReferenceKind, TableCell, TableHeader, TableRow, TextDirection, TextStyle,
+    TransformerTrait, TransformerWithImageLoaderSaverTrait,
+};
+use crate::error::image_loader_errors;
+
+pub struct Transformer;
+
+/// Images at most as large as the text block, when the document gives no size
+const IMAGE_SIZE: &str = r"\makeatletter
+\def\maxwidth{\ifdim\Gin@nat@width>\linewidth\linewidth\else\Gin@nat@width\fi}
+\def\maxheight{\ifdim\Gin@nat@height>\textheight\textheight\else\Gin@nat@height\fi}
+\makeatother
+\setkeys{Gin}{width=\maxwidth,height=\maxheight,keepaspectratio}
+";
+
+/// Deepest list nesting of LaTeX, the items of deeper lists join the list at this depth
+const MAX_LIST_DEPTH: usize = 4;
+
+/// Text with the characters LaTeX reads specially escaped, line breaks being spaces
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str(r"\textbackslash{}"),
+            '{' | '}' | '$' | '&' | '#' | '%' | '_' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '^' => escaped.push_str(r"\textasciicircum{}"),
+            '~' => escaped.push_str(r"\textasciitilde{}"),
+            '<' => escaped.push_str(r"\textless{}"),
+            '>' => escaped.push_str(r"\textgreater{}"),
+            '\u{a0}' => escaped.push('~'),
+            '\n' => escaped.push(' '),
+            '\r' => {}
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// URL as an argument of `\href` and `\url`
+fn escape_url(url: &str) -> String {
+    let mut escaped = String::with_capacity(url.len());
+    for c in url.chars() {
+        if matches!(c, '\\' | '#' | '%' | '{' | '}') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Label of an anchor, its characters other than ASCII letters, digits, `-`, `_`, `.` and
+/// `:` replaced by `-`
+fn label(anchor: &str) -> String {
+    anchor
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || "-_.:".contains(c) {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect()
+}
+
+/// Sectioning command of a header level, `\chapter` being the first level of a report
+fn sectioning(class: LatexClass, level: u8) -> &'static str {
+    let commands = match class {
+        LatexClass::Article => [
+            "section",
+            "subsection",
+            "subsubsection",
+            "paragraph",
+            "subparagraph",
+            "subparagraph",
+        ],
+        LatexClass::Report => [
+            "chapter",
+            "section",
+            "subsection",
+            "subsubsection",
+            "paragraph",
+            "subparagraph",
+        ],
+    };
+    commands[usize::from(level.clamp(1, 6)) - 1]
+}
+
+/// Options of `\includegraphics` for the size of an image: bare numbers are pixels and
+/// percentages are relative to the text block
+fn graphics_options(size: &ImageDimension) -> String {
+    let length = |value: &str, relative_to: &str| -> Option<String> {
+        let value = value.trim();
+        if let Some(percent) = value.strip_suffix('%') {
+            let percent: f32 = percent.trim().parse().ok()?;
+            return Some(format!("{:.2}{relative_to}", percent / 100.0));
+        }
+        let length = match value.parse::<f32>() {
+            Ok(pixels) => Length::px(pixels),
+            Err(_) => value.parse::<Length>().ok()?,
+        };
+        Some(format!("{:.2}pt", length.to_pt()))
+    };
+    let mut options = vec![];
+    if let Some(width) = size
+        .width
+        .as_deref()
+        .and_then(|width| length(width, r"\linewidth"))
+    {
+        options.push(format!("width={width}"));
+    }
+    if let Some(height) = size
+        .height
+        .as_deref()
+        .and_then(|height| length(height, r"\textheight"))
+    {
+        options.push(format!("height={height}"));
+    }
+    options.join(",")
+}
+
+struct Writer<'a, F>
+where
+    F: Fn(&Bytes, &str) -> anyhow::Result<()>,
+{
+    class: LatexClass,
+    image_saver: &'a F,
+    image_count: usize,
+    /// Depth of the list being written
+    list_depth: usize,
+    /// The content is the argument of a command (footnote, table cell), where verbatim
+    /// environments cannot be
+    in_argument: bool,
+    /// A `TotalPages` field is written, it needs the `lastpage` package
+    total_pages: bool,
+    tex: String,
+}
+
+impl<'a, F> Writer<'a, F>
+where
+    F: Fn(&Bytes, &str) -> anyhow::Result<()>,
+{
+    fn block(&mut self, element: &Element) -> anyhow::Result<()> {
+        match element {
+            Element::Header { level, text } => self.header(*level, text, &header_anchor(text)),
+            Element::Attributed {
+                attributes,
+                element,
+            } => match element.as_ref() {
+                Element::Header { level, text } => {
+                    let anchor = attributes
+                        .get("id")
+                        .cloned()
+                        .unwrap_or_else(|| header_anchor(text));
+                    self.header(*level, text, &anchor);
+                }
+                element => self.block(element)?,
+            },
+            Element::Paragraph { elements, .. } => {
+                for element in elements {
+                    self.inline(element)?;
+                }
+                self.tex.push_str("\n\n");
+            }
+            Element::Table { headers, rows } => self.table(headers, rows)?,
+            Element::List { .. } => self.list(element)?,
+            Element::Image(image) => self.figure(image)?,
+            Element::CodeBlock { code, .. } if !self.in_argument => {
+                let code = code.replace(r"\end{verbatim}", r"\end {verbatim}");
+                let _ = write!(
+                    self.tex,
+                    "\\begin{{verbatim}}\n{code}\n\\end{{verbatim}}\n\n"
+                );
+            }
+            Element::TableOfContents { max_level } => {
+                let depth = match self.class {
+                    LatexClass::Article => i32::from(*max_level),
+                    LatexClass::Report => i32::from(*max_level) - 1,
+                };
+                let _ = write!(
+                    self.tex,
+                    "\\setcounter{{tocdepth}}{{{depth}}}\n\\tableofcontents\n\n"
+                );
+            }
+            Element::Math { tex, display: true } => {
+                let _ = write!(self.tex, "\\[\n{}\n\\]\n\n", tex.trim());
+            }
+            Element::LineBreak => self.tex.push_str("\\medskip\n\n"),
+            Element::Container { elements, .. } => {
+                for element in elements {
+                    self.block(element)?;
+                }
+            }
+            Element::Raw {
+                format: DocumentType::LaTeX,
+                content,
+            } => {
+                self.tex.push_str(content.trim_end());
+                self.tex.push_str("\n\n");
+            }
+            Element::Raw { .. } => {}
+            element => {
+                self.inline(element)?;
+                self.tex.push_str("\n\n");
+            }
+        }
+        Ok(())
+    }
+
+    fn inline(&mut self, element: &Element) -> anyhow::Result<()> {
+        match element {
+            Element::Text { text, .. } => self.tex.push_str(&escape(text)),
+            Element::Header { text, .. } => {
+                let _ = write!(self.tex, "\\textbf{{{}}}", escape(text));
+            }
+            Element::Paragraph { elements, .. } | Element::Container { elements, .. } => {
+                for element in elements {
+                    self.inline(element)?;
+                }
+            }
+            Element::Styled { style, elements } => {
+                let command = match style {
+                    TextStyle::Emphasis => "emph",
+                    TextStyle::Strong => "textbf",
+                    TextStyle::Strikethrough => "sout",
+                };
+                let _ = write!(self.tex, "\\{command}{{");
+                for element in elements {
+                    self.inline(element)?;
+                }
+                self.tex.push('}');
+            }
+            Element::Hyperlink { elements, url, .. } => {
+                if elements.is_empty() {
+                    let _ = write!(self.tex, "\\url{{{}}}", escape_url(url));
+                    return Ok(());
+                }
+                match url.strip_prefix('#') {
+                    Some(anchor) => {
+                        let _ = write!(self.tex, "\\hyperref[{}]{{", label(anchor));
+                    }
+                    None => {
+                        let _ = write!(self.tex, "\\href{{{}}}{{", escape_url(url));
+                    }
+                }
+                for element in elements {
+                    self.inline(element)?;
+                }
+                self.tex.push('}');
+            }
+            Element::InlineCode { text } => {
+                let _ = write!(self.tex, "\\texttt{{{}}}", escape(text));
+            }
+            Element::Math { tex, display: true } => {
+                let _ = write!(self.tex, "\\[{}\\]", tex.trim());
+            }
+            Element::Math { tex, .. } => {
+                let _ = write!(self.tex, "\\({}\\)", tex.trim());
+            }
+            Element::CodeBlock { code, .. } => {
+                let lines: Vec<String> = code.lines().map(escape).collect();
+                let _ = write!(self.tex, "\\texttt{{{}}}", lines.join("\\newline "));
+            }
+            Element::LineBreak => self.tex.push_str("\\newline\n"),
+            Element::Footnote { elements } => {
+                let in_argument = std::mem::replace(&mut self.in_argument, true);
+                self.tex.push_str("\\footnote{");
+                self.content(elements)?;
+                self.tex.push('}');
+                self.in_argument = in_argument;
+            }
+            Element::Field { kind } => match kind {
+                FieldKind::PageNumber => self.tex.push_str("\\thepage{}"),
+                FieldKind::TotalPages => {
+                    self.total_pages = true;
+                    self.tex.push_str("\\pageref{LastPage}");
+                }
+                FieldKind::Date => self.tex.push_str("\\today{}"),
+                FieldKind::DocumentTitle => {}
+            },
+            Element::Bookmark { id, .. } => {
+                let _ = write!(self.tex, "\\phantomsection\\label{{{}}}", label(id));
+            }
+            Element::Image(image) => {
+                if let Some(graphics) = self.graphics(image)? {
+                    self.tex.push_str(&graphics);
+                }
+            }
+            Element::Attributed { element, .. } => self.inline(element)?,
+            Element::Raw {
+                format: DocumentType::LaTeX,
+                content,
+            } => self.tex.push_str(content),
+            Element::Table { .. } | Element::List { .. } => self.block(element)?,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Inline elements then blocks, in a footnote or a table cell
+    fn content(&mut self, elements: &[Element]) -> anyhow::Result<()> {
+        let mut after_block = false;
+        for (index, element) in elements.iter().enumerate() {
+            if element.is_inline() && !after_block {
+                self.inline(element)?;
+                continue;
+            }
+            if index > 0 {
+                self.tex.push_str("\\par ");
+            }
+            match element {
+                Element::Paragraph { elements, .. } => {
+                    for element in elements {
+                        self.inline(element)?;
+                    }
+                }
+                element => self.inline(element)?,
+            }
+            after_block = !element.is_inline();
+        }
+        Ok(())
+    }
+
+    fn header(&mut self, level: u8, text: &str, anchor: &str) {
+        let _ = write!(
+            self.tex,
+            "\\{}{{{}}}\\label{{{}}}\n\n",
+            sectioning(self.class, level),
+            escape(text),
+            label(anchor)
+        );
+    }
+
+    fn table(&mut self, headers: &[TableHeader], rows: &[TableRow]) -> anyhow::Result<()> {
+        let columns = rows
+            .iter()
+            .map(|row| row.cells.len())
+            .chain(std::iter::once(headers.len()))
+            .max()
+            .unwrap_or_default();
+        if columns == 0 {
+            return Ok(());
+        }
+        let widths: Vec<f32> = if headers.len() == columns {
+            headers.iter().map(|header| header.width.max(1.0)).collect()
+        } else {
+            vec![1.0; columns]
+        };
+        let total: f32 = widths.iter().sum();
+        // the cell padding takes the rest of the line
+        let spec: String = widths
+            .iter()
+            .map(|width| format!("p{{{:.3}\\linewidth}}|", 0.9 * width / total))
+            .collect();
+        let _ = writeln!(self.tex, "\\noindent\\begin{{tabular}}{{|{spec}}}\n\\hline");
+
+        let in_argument = std::mem::replace(&mut self.in_argument, true);
+        if !headers.is_empty() {
+            let cells: Vec<&Element> = headers.iter().map(|header| &header.element).collect();
+            self.row(&cells, columns, true)?;
+        }
+        for row in rows {
+            let cells: Vec<&Element> = row.cells.iter().map(|cell| &cell.element).collect();
+            self.row(&cells, columns, false)?;
+        }
+        self.in_argument = in_argument;
+        self.tex.push_str("\\end{tabular}\n\n");
+        Ok(())
+    }
+
+    fn row(&mut self, cells: &[&Element], columns: usize, header: bool) -> anyhow::Result<()> {
+        for column in 0..columns {
+            if column > 0 {
+                self.tex.push_str(" & ");
+            }
+            let Some(cell) = cells.get(column) else {
+                continue;
+            };
+            if header {
+                self.tex.push_str("\\textbf{");
+            }
+            self.content(std::slice::from_ref(*cell))?;
+            if header {
+                self.tex.push('}');
+            }
+        }
+        self.tex.push_str(" \\\\\n\\hline\n");
+        Ok(())
+    }
+
+    fn list(&mut self, list: &Element) -> anyhow::Result<()> {
+        let Element::List {
+            elements,
+            numbered,
+            start,
+            numbering,
+        } = list
+        else {
+            return Ok(());
+        };
+        if self.list_depth == MAX_LIST_DEPTH {
+            return self.items(elements);
+        }
+        let environment = if *numbered { "enumerate" } else { "itemize" };
+        let mut options = vec![];
+        if *numbered {
+            match numbering {
+                NumberingStyle::Decimal => {}
+                NumberingStyle::LowerAlpha => options.push(r"label=\alph*.".to_string()),
+                NumberingStyle::UpperAlpha => options.push(r"label=\Alph*.".to_string()),
+                NumberingStyle::LowerRoman => options.push(r"label=\roman*.".to_string()),
+                NumberingStyle::UpperRoman => options.push(r"label=\Roman*.".to_string()),
+            }
+            if *start != 1 {
+                options.push(format!("start={start}"));
+            }
+        }
+        let _ = write!(self.tex, "\\begin{{{environment}}}");
+        if !options.is_empty() {
+            let _ = write!(self.tex, "[{}]", options.join(","));
+        }
+        self.tex.push('\n');
+        self.list_depth += 1;
+        self.items(elements)?;
+        self.list_depth -= 1;
+        let _ = write!(self.tex, "\\end{{{environment}}}\n\n");
+        Ok(())
+    }
+
+    /// Items of a list, a nested list going into the item before it
+    fn items(&mut self, items: &[ListItem]) -> anyhow::Result<()> {
+        for (index, item) in items.iter().enumerate() {
+            match &item.element {
+                Element::List { .. } => {
+                    if index == 0 {
+                        self.tex.push_str("\\item[]\n");
+                    }
+                    self.list(&item.element)?;
+                }
+                element if element.is_inline() || matches!(element, Element::Paragraph { .. }) => {
+                    self.tex.push_str("\\item ");
+                    self.inline(element)?;
+                    self.tex.push('\n');
+                }
+                element => {
+                    self.tex.push_str("\\item ");
+                    self.block(element)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Image alone in a block, a figure when it has a title
+    fn figure(&mut self, image: &ImageData) -> anyhow::Result<()> {
+        let Some(graphics) = self.graphics(image)? else {
+            return Ok(());
+        };
+        if image.title().is_empty() || self.in_argument {
+            let _ = write!(
+                self.tex,
+                "\\begin{{center}}\n{graphics}\n\\end{{center}}\n\n"
+            );
+        } else {
+            let _ = write!(
+                self.tex,
+                "\\begin{{figure}}[htbp]\n\\centering\n{graphics}\n\\caption{{{}}}\n\\end{{figure}}\n\n",
+                escape(image.title())
+            );
+        }
+        Ok(())
+    }
+
+    /// `\includegraphics` of the image saved as a file, the alternative text for the images
+    /// LaTeX cannot include
+    fn graphics(&mut self, image: &ImageData) -> anyhow::Result<Option<String>> {
+        if image.bytes().is_empty() {
+            warn!("Image without content left out: {}", image.title());
+            return Ok(None);
+        }
+        let (image_type, bytes) = match image.image_type() {
+            ImageType::Png | ImageType::Jpeg => (image.image_type().clone(), image.bytes().clone()),
+            ImageType::SVG => {
+                warn!("SVG image written as its text: {}", image.title());
+                let text = if image.alt().is_empty() {
+                    image.title()
+                } else {
+                    image.alt()
+                };
+                return Ok(Some(format!("\\emph{{{}}}", escape(text))));
+            }
+            ImageType::Gif | ImageType::Bmp | ImageType::Tiff => {
+                let converted = image::load_from_memory(image.bytes()).and_then(|decoded| {
+                    let mut png = std::io::Cursor::new(Vec::new());
+                    decoded.write_to(&mut png, image::ImageFormat::Png)?;
+                    Ok(png.into_inner())
+                });
+                match converted {
+                    Ok(png) => (ImageType::Png, Bytes::from(png)),
+                    Err(error) => {
+                        warn!("Cannot convert the image {}: {}", image.title(), error);
+                        return Ok(None);
+                    }
+                }
+            }
+        };
+        self.image_count += 1;
+        let name = format!("image{}{}", self.image_count, image_type.to_extension());
+        (self.image_saver)(&bytes, &name)?;
+        let options = graphics_options(image.size());
+        Ok(Some(if options.is_empty() {
+            format!("\\includegraphics{{{name}}}")
+        } else {
+            format!("\\includegraphics[{options}]{{{name}}}")
+        }))
+    }
+}
+
+/// Sectioning commands from the highest level, the highest one used in a document being its
+/// first header level
+const SECTIONS: [&str; 7] = [
+    "part",
+    "chapter",
+    "section",
+    "subsection",
+    "subsubsection",
+    "paragraph",
+    "subparagraph",
+];
+
+/// Display math environments, with the environment their content is put in to fit in display
+/// math: the alignment environments become their `aligned` forms
+const MATH_ENVIRONMENTS: &[(&str, Option<&str>)] = &[
+    ("equation", None),
+    ("displaymath", None),
+    ("align", Some("aligned")),
+    ("flalign", Some("aligned")),
+    ("eqnarray", Some("aligned")),
+    ("alignat", Some("alignedat")),
+    ("gather", Some("gathered")),
+    ("multline", Some("gathered")),
+];
+
+const VERBATIM_ENVIRONMENTS: &[&str] = &["verbatim", "Verbatim", "lstlisting", "minted"];
+
+const TABLE_ENVIRONMENTS: &[&str] = &["tabular", "tabularx", "tabulary", "longtable"];
+
+/// Commands left out with their number of arguments, their optional arguments included
+const IGNORED: &[(&str, usize)] = &[
+    ("documentclass", 1),
+    ("usepackage", 1),
+    ("RequirePackage", 1),
+    ("newcommand", 2),
+    ("renewcommand", 2),
+    ("providecommand", 2),
+    ("newenvironment", 3),
+    ("renewenvironment", 3),
+    ("newtheorem", 2),
+    ("DeclareMathOperator", 2),
+    ("numberwithin", 2),
+    ("setlength", 2),
+    ("addtolength", 2),
+    ("setcounter", 2),
+    ("addtocounter", 2),
+    ("vspace", 1),
+    ("hspace", 1),
+    ("pagestyle", 1),
+    ("thispagestyle", 1),
+    ("pagenumbering", 1),
+    ("bibliographystyle", 1),
+    ("bibliography", 1),
+    ("addbibresource", 1),
+    ("nocite", 1),
+    ("hypersetup", 1),
+    ("geometry", 1),
+    ("graphicspath", 1),
+    ("definecolor", 3),
+    ("color", 1),
+    ("linespread", 1),
+    ("fontsize", 2),
+    ("thanks", 1),
+    ("index", 1),
+    ("addcontentsline", 3),
+    ("markboth", 2),
+    ("markright", 1),
+    ("footnotetext", 1),
+    ("captionsetup", 1),
+    ("lstset", 1),
+    ("setminted", 1),
+    ("usetikzlibrary", 1),
+    ("includeonly", 1),
+    ("maketitle", 0),
+    ("centering", 0),
+    ("raggedright", 0),
+    ("raggedleft", 0),
+    ("noindent", 0),
+    ("indent", 0),
+    ("newpage", 0),
+    ("clearpage", 0),
+    ("cleardoublepage", 0),
+    ("pagebreak", 0),
+    ("nopagebreak", 0),
+    ("smallskip", 0),
+    ("medskip", 0),
+    ("bigskip", 0),
+    ("hfill", 0),
+    ("vfill", 0),
+    ("normalsize", 0),
+    ("small", 0),
+    ("footnotesize", 0),
+    ("scriptsize", 0),
+    ("tiny", 0),
+    ("large", 0),
+    ("Large", 0),
+    ("LARGE", 0),
+    ("huge", 0),
+    ("Huge", 0),
+    ("normalfont", 0),
+    ("rmfamily", 0),
+    ("sffamily", 0),
+    ("upshape", 0),
+    ("mdseries", 0),
+    ("scshape", 0),
+    ("selectfont", 0),
+    ("protect", 0),
+    ("relax", 0),
+    ("appendix", 0),
+    ("frontmatter", 0),
+    ("mainmatter", 0),
+    ("backmatter", 0),
+    ("listoffigures", 0),
+    ("listoftables", 0),
+    ("printbibliography", 0),
+    ("footnotemark", 0),
+    ("phantomsection", 0),
+    ("allowbreak", 0),
+    ("makeatletter", 0),
+    ("makeatother", 0),
+];
+
+/// Commands replaced by their last argument, the others being options (colors, sizes...)
+const KEPT: &[(&str, usize)] = &[
+    ("textrm", 1),
+    ("textsf", 1),
+    ("textup", 1),
+    ("textmd", 1),
+    ("textsc", 1),
+    ("textnormal", 1),
+    ("underline", 1),
+    ("uline", 1),
+    ("mbox", 1),
+    ("hbox", 1),
+    ("fbox", 1),
+    ("makebox", 1),
+    ("framebox", 1),
+    ("text", 1),
+    ("textcolor", 2),
+    ("colorbox", 2),
+    ("fcolorbox", 3),
+    ("raisebox", 2),
+    ("scalebox", 2),
+    ("resizebox", 3),
+    ("multirow", 3),
+];
+
+/// Text of the symbol commands
+const SYMBOLS: &[(&str, &str)] = &[
+    ("ldots", "…"),
+    ("dots", "…"),
+    ("textellipsis", "…"),
+    ("LaTeX", "LaTeX"),
+    ("LaTeXe", "LaTeX2e"),
+    ("TeX", "TeX"),
+    ("textbackslash", "\\"),
+    ("textasciitilde", "~"),
+    ("textasciicircum", "^"),
+    ("textless", "<"),
+    ("textgreater", ">"),
+    ("textbar", "|"),
+    ("textendash", "–"),
+    ("textemdash", "—"),
+    ("textquoteleft", "‘"),
+    ("textquoteright", "’"),
+    ("textquotedblleft", "“"),
+    ("textquotedblright", "”"),
+    ("textdegree", "°"),
+    ("S", "§"),
+    ("P", "¶"),
+    ("dag", "†"),
+    ("ddag", "‡"),
+    ("copyright", "©"),
+    ("textcopyright", "©"),
+    ("textregistered", "®"),
+    ("texttrademark", "™"),
+    ("pounds", "£"),
+    ("euro", "€"),
+    ("ss", "ß"),
+    ("ae", "æ"),
+    ("AE", "Æ"),
+    ("oe", "œ"),
+    ("OE", "Œ"),
+    ("o", "ø"),
+    ("O", "Ø"),
+    ("aa", "å"),
+    ("AA", "Å"),
+    ("l", "ł"),
+    ("L", "Ł"),
+    ("i", "ı"),
+    ("slash", "/"),
+    ("quad", " "),
+    ("qquad", " "),
+    (" ", " "),
+    (",", " "),
+    (";", " "),
+    (":", " "),
+    ("&", "&"),
+    ("%", "%"),
+    ("$", "$"),
+    ("#", "#"),
+    ("_", "_"),
+    ("{", "{"),
+    ("}", "}"),
+];
+
+/// Accent commands with the letters they have a precomposed character for
+const ACCENTED: &[(&str, &str, &str)] = &[
+    ("'", "aeiouyAEIOUYcnszCNSZ", "áéíóúýÁÉÍÓÚÝćńśźĆŃŚŹ"),
+    ("`", "aeiouAEIOU", "àèìòùÀÈÌÒÙ"),
+    ("^", "aeiouAEIOU", "âêîôûÂÊÎÔÛ"),
+    ("\"", "aeiouyAEIOUY", "äëïöüÿÄËÏÖÜŸ"),
+    ("~", "anoANO", "ãñõÃÑÕ"),
+    ("c", "cCsS", "çÇşŞ"),
+    ("v", "cdenrstzCDENRSTZ", "čďěňřšťžČĎĚŇŘŠŤŽ"),
+    ("H", "ouOU", "őűŐŰ"),
+    ("r", "auAU", "åůÅŮ"),
+];
+
+/// Combining marks of the accent commands, put after the letters without a precomposed
+/// character
+const ACCENTS: &[(&str, char)] = &[
+    ("'", '\u{301}'),
+    ("`", '\u{300}'),
+    ("^", '\u{302}'),
+    ("\"", '\u{308}'),
+    ("~", '\u{303}'),
+    ("=", '\u{304}'),
+    (".", '\u{307}'),
+    ("u", '\u{306}'),
+    ("v", '\u{30c}'),
+    ("H", '\u{30b}'),
+    ("c", '\u{327}'),
+    ("k", '\u{328}'),
+    ("r", '\u{30a}'),
+    ("d", '\u{323}'),
+    ("b", '\u{331}'),
+];
+
+/// Words written before a reference, left out as the reference has its own
+const REFERENCE_WORDS: &[&str] = &[
+    "section", "sections", "sec.", "chapter", "chapters", "figure", "figures", "fig.", "table",
+    "tables", "tab.",
+];
+
+/// Position in a LaTeX source
+struct Cursor<'s> {
+    text: &'s str,
+    position: usize,
+}
+
+impl<'s> Cursor<'s> {
+    fn new(text: &'s str) -> Cursor<'s> {
+        Cursor { text, position: 0 }
+    }
+
+    fn rest(&self) -> &'s str {
+        &self.text[self.position..]
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.position += c.len_utf8();
+        Some(c)
+    }
+
+    fn eat(&mut self, prefix: &str) -> bool {
+        let found = self.rest().starts_with(prefix);
+        if found {
+            self.position += prefix.len();
+        }
+        found
+    }
+
+    /// Skips a comment up to the indentation of the next line, which TeX leaves out too
+    fn skip_comment(&mut self) {
+        match self.rest().find('\n') {
+            Some(end) => self.position += end + 1,
+            None => self.position = self.text.len(),
+        }
+        while matches!(self.peek(), Some(' ' | '\t')) {
+            self.position += 1;
+        }
+    }
+
+    /// Skips spaces, line breaks and comments up to a blank line, which ends a paragraph
+    fn skip_space(&mut self) {
+        loop {
+            match self.peek() {
+                Some(' ' | '\t' | '\r') => self.position += 1,
+                Some('\n') => {
+                    let next_line = self.rest()[1..].trim_start_matches([' ', '\t', '\r']);
+                    if next_line.starts_with('\n') {
+                        return;
+                    }
+                    self.position += 1;
+                }
+                Some('%') => self.skip_comment(),
+                _ => return,
+            }
+        }
+    }
+
+    /// Name of the command after its backslash: letters, or a single other character
+    fn command(&mut self) -> &'s str {
+        let rest = self.rest();
+        let length = match rest.find(|c: char| !c.is_ascii_alphabetic()) {
+            Some(0) => rest.chars().next().map_or(0, char::len_utf8),
+            Some(length) => length,
+            None => rest.len(),
+        };
+        self.position += length;
+        &rest[..length]
+    }
+
+    /// Content of the braced argument that follows, without its braces
+    fn group(&mut self) -> Option<&'s str> {
+        self.delimited('{', '}', true)
+    }
+
+    /// Same as [`Cursor::group`] for the arguments where `%` is not a comment (URLs)
+    fn raw_group(&mut self) -> Option<&'s str> {
+        self.delimited('{', '}', false)
+    }
+
+    /// Content of the optional argument that follows, without its brackets
+    fn optional(&mut self) -> Option<&'s str> {
+        self.delimited('[', ']', true)
+    }
+
+    fn delimited(&mut self, open: char, close: char, comments: bool) -> Option<&'s str> {
+        let before = self.position;
+        self.skip_space();
+        if !self.eat(&open.to_string()) {
+            self.position = before;
+            return None;
+        }
+        let start = self.position;
+        let mut depth = 0;
+        while let Some(c) = self.next() {
+            match c {
+                '\\' => {
+                    self.next();
+                }
+                '%' if comments => {
+                    let end = self.rest().find('\n').unwrap_or(self.rest().len());
+                    self.position += end;
+                }
+                '{' => depth += 1,
+                '}' if depth > 0 => depth -= 1,
+                c if c == close && depth == 0 => {
+                    return Some(&self.text[start..self.position - close.len_utf8()])
+                }
+                _ => {}
+            }
+        }
+        Some(&self.text[start..])
+    }
+
+    /// Argument that follows: braced, or else the next character or command (`\'e`)
+    fn argument(&mut self) -> &'s str {
+        if let Some(group) = self.group() {
+            return group;
+        }
+        self.skip_space();
+        let start = self.position;
+        if self.next() == Some('\\') {
+            self.command();
+        }
+        &self.text[start..self.position]
+    }
+
+    /// Text up to `delimiter`, the cursor after it
+    fn until(&mut self, delimiter: &str) -> &'s str {
+        let start = self.position;
+        while !self.rest().is_empty() {
+            if self.rest().starts_with(delimiter) {
+                let content = &self.text[start..self.position];
+                self.position += delimiter.len();
+                return content;
+            }
+            if self.next() == Some('\\') {
+                self.next();
+            }
+        }
+        &self.text[start..]
+    }
+
+    /// Content of the environment up to its `\end`, nested environments of the same name
+    /// included, the cursor after it
+    fn environment(&mut self, name: &str) -> &'s str {
+        let begin = format!("\\begin{{{name}}}");
+        let end = format!("\\end{{{name}}}");
+        let start = self.position;
+        let mut depth = 0;
+        while !self.rest().is_empty() {
+            if self.rest().starts_with(&end) {
+                if depth == 0 {
+                    let content = &self.text[start..self.position];
+                    self.position += end.len();
+                    return content;
+                }
+                depth -= 1;
+            } else if self.rest().starts_with(&begin) {
+                depth += 1;
+            }
+            self.next();
+        }
+        &self.text[start..]
+    }
+}
+
+/// Parts of `text` split where `separator` matches outside of groups and nested
+/// environments, `separator` giving the length of the separator
+fn split_top_level(text: &str, separator: impl Fn(&str) -> Option<usize>) -> Vec<&str> {
+    let mut parts = vec![];
+    let mut cursor = Cursor::new(text);
+    let mut start = 0;
+    let mut depth = 0usize;
+    let mut environments = 0usize;
+    while let Some(c) = cursor.peek() {
+        if depth == 0 && environments == 0 {
+            if let Some(length) = separator(cursor.rest()) {
+                parts.push(&text[start..cursor.position]);
+                cursor.position += length;
+                start = cursor.position;
+                continue;
+            }
+        }
+        cursor.next();
+        match c {
+            '\\' => match cursor.command() {
+                "begin" => environments += 1,
+                "end" => environments = environments.saturating_sub(1),
+                _ => {}
+            },
+            '%' => {
+                let end = cursor.rest().find('\n').unwrap_or(cursor.rest().len());
+                cursor.position += end;
+            }
+            '{' => depth += 1,
+            '}' => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+    }
+    parts.push(&text[start..]);
+    parts
+}
+
+/// Whether `rest` starts with the command `name`, not with a longer command
+fn starts_with_command(rest: &str, name: &str) -> bool {
+    rest.strip_prefix('\\')
+        .and_then(|rest| rest.strip_prefix(name))
+        .is_some_and(|after| !after.starts_with(|c: char| c.is_ascii_alphabetic()))
+}
+
+/// Letter with an accent, precomposed when there is such a character
+fn accented(accent: &str, letter: &str) -> String {
+    let letter = match letter.trim() {
+        "\\i" => "i",
+        "\\j" => "j",
+        letter => letter,
+    };
+    let mut chars = letter.chars();
+    let (Some(base), None) = (chars.next(), chars.next()) else {
+        return letter.to_string();
+    };
+    for (command, letters, precomposed) in ACCENTED {
+        if *command == accent {
+            if let Some(index) = letters.chars().position(|c| c == base) {
+                if let Some(precomposed) = precomposed.chars().nth(index) {
+                    return precomposed.to_string();
+                }
+            }
+        }
+    }
+    match ACCENTS.iter().find(|(command, _)| *command == accent) {
+        Some((_, mark)) => format!("{base}{mark}"),
+        None => base.to_string(),
+    }
+}
+
+/// Math mode content without the labels and the numbering commands, with its labels
+fn math_content(tex: &str) -> String {
+    let mut content = String::new();
+    let mut cursor = Cursor::new(tex);
+    while let Some(c) = cursor.next() {
+        match c {
+            '%' => {
+                cursor.position -= 1;
+                cursor.skip_comment();
+                content.push('\n');
+            }
+            '\\' => match cursor.command() {
+                "label" => {
+                    cursor.group();
+                }
+                "nonumber" | "notag" => {}
+                name => {
+                    content.push('\\');
+                    content.push_str(name);
+                }
+            },
+            c => content.push(c),
+        }
+    }
+    content.trim().to_string()
+}
+
+/// Size of an image from the options of `\includegraphics`: lengths relative to the text
+/// block as percentages and the other lengths in pixels
+fn graphics_size(options: &str) -> ImageDimension {
+    let length = |value: &str| -> Option<String> {
+        let value = value.trim();
+        for relative in [
+            "\\textwidth",
+            "\\linewidth",
+            "\\columnwidth",
+            "\\textheight",
+        ] {
+            if let Some(factor) = value.strip_suffix(relative) {
+                let factor = factor.trim();
+                let factor: f32 = if factor.is_empty() {
+                    1.0
+                } else {
+                    factor.parse().ok()?
+                };
+                return Some(format!("{}%", (factor * 100.0).round()));
+            }
+        }
+        let length: Length = value.parse().ok()?;
+        Some(format!("{:.0}", length.to(LengthUnit::Px).value))
+    };
+    let mut size = ImageDimension::default();
+    for option in split_top_level(options, |rest| rest.starts_with(',').then_some(1)) {
+        match option.split_once('=') {
+            Some((key, value)) if key.trim() == "width" => size.width = length(value),
+            Some((key, value)) if key.trim() == "height" => size.height = length(value),
+            _ => {}
+        }
+    }
+    size
+}
+
+/// Blocks of a list item or a table cell: consecutive paragraphs are joined by line breaks
+/// and a paragraph of text only is kept as text
+fn join_paragraphs(blocks: Vec<Element>) -> Vec<Element> {
+    let mut joined: Vec<Element> = vec![];
+    for block in blocks {
+        match (joined.last_mut(), block) {
+            (
+                Some(Element::Paragraph {
+                    elements: previous, ..
+                }),
+                Element::Paragraph { elements: next, .. },
+            ) => {
+                previous.push(Element::LineBreak);
+                previous.extend(next);
+            }
+            (_, block) => joined.push(block),
+        }
+    }
+    joined
+        .into_iter()
+        .map(|block| match block {
+            Element::Paragraph { elements, .. }
+                if elements
+                    .iter()
+                    .all(|element| matches!(element, Element::Text { .. })) =>
+            {
+                Element::Text {
+                    text: elements.iter().map(Element::plain_text).collect(),
+                    size: 14,
+                }
+            }
+            block => block,
+        })
+        .collect()
+}
+
+/// Text of inline elements, line breaks being spaces
+fn plain_text(elements: &[Element]) -> String {
+    let text: String = elements
+        .iter()
+        .map(|element| match element {
+            Element::LineBreak => " ".to_string(),
+            element => element.plain_text(),
+        })
+        .collect();
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Blocks read so far and the paragraph being read
+#[derive(Default)]
+struct Output {
+    blocks: Vec<Element>,
+    inline: Vec<Element>,
+    text: String,
+}
+
+impl Output {
+    /// Adds text, whitespace collapsed to single spaces and left out at the start of a
+    /// paragraph
+    fn push_text(&mut self, text: &str) {
+        for c in text.chars() {
+            if c.is_whitespace() && c != '\u{a0}' {
+                let at_start = self.text.is_empty()
+                    && matches!(self.inline.last(), None | Some(Element::LineBreak));
+                if !at_start && !self.text.ends_with(' ') {
+                    self.text.push(' ');
+                }
+            } else {
+                self.text.push(c);
+            }
+        }
+    }
+
+    fn flush_text(&mut self) {
+        if !self.text.is_empty() {
+            self.inline.push(Element::Text {
+                text: std::mem::take(&mut self.text),
+                size: 14,
+            });
+        }
+    }
+
+    fn push_inline(&mut self, element: Element) {
+        self.flush_text();
+        if matches!(element, Element::LineBreak) {
+            if let Some(Element::Text { text, .. }) = self.inline.last_mut() {
+                text.truncate(text.trim_end().len());
+            }
+        }
+        self.inline.push(element);
+    }
+
+    fn extend_inline(&mut self, elements: Vec<Element>) {
+        for element in elements {
+            match element {
+                Element::Text { text, .. } => self.push_text(&text),
+                element => self.push_inline(element),
+            }
+        }
+    }
+
+    fn end_paragraph(&mut self) {
+        self.flush_text();
+        if let Some(Element::Text { text, .. }) = self.inline.last_mut() {
+            text.truncate(text.trim_end().len());
+        }
+        let mut elements = std::mem::take(&mut self.inline);
+        elements
+            .retain(|element| !matches!(element, Element::Text { text, .. } if text.is_empty()));
+        while matches!(elements.last(), Some(Element::LineBreak)) {
+            elements.pop();
+        }
+        if !elements.is_empty() {
+            self.blocks.push(Element::Paragraph {
+                elements,
+                direction: TextDirection::default(),
+            });
+        }
+    }
+
+    fn push_block(&mut self, element: Element) {
+        self.end_paragraph();
+        self.blocks.push(element);
+    }
+
+    fn finish(mut self) -> Vec<Element> {
+        self.end_paragraph();
+        self.blocks
+    }
+}
+
+struct Parser<'a, F>
+where
+    F: Fn(&str) -> anyhow::Result<Bytes>,
+{
+    image_loader: &'a F,
+    /// Index in [`SECTIONS`] of the headers of the first level
+    top_section: usize,
+    /// Folders where the images are looked for, the current one first
+    graphics_paths: Vec<String>,
+    metadata: Metadata,
+    /// Targets of the labels of the headers, figures and tables
+    labels: HashMap<String, (ReferenceKind, String)>,
+    /// Labels set elsewhere, where there is a bookmark
+    bookmarks: HashSet<String>,
+    /// Environments being read, the innermost last
+    environments: Vec<String>,
+    /// Caption of the figure being read
+    caption: Option<String>,
+    /// Labels of the figure or table being read, set on it at its end
+    float_labels: Vec<String>,
+    images: usize,
+    tables: usize,
+}
+
+impl<'a, F> Parser<'a, F>
+where
+    F: Fn(&str) -> anyhow::Result<Bytes>,
+{
+    /// Title, author and date of the preamble, and the folders of the images
+    fn preamble(&mut self, preamble: &str) -> anyhow::Result<()> {
+        let mut cursor = Cursor::new(preamble);
+        while let Some(c) = cursor.next() {
+            match c {
+                '%' => {
+                    cursor.position -= 1;
+                    cursor.skip_comment();
+                }
+                '\\' => match cursor.command() {
+                    name @ ("title" | "author" | "date") => {
+                        cursor.optional();
+                        if let Some(group) = cursor.group() {
+                            self.metadata_entry(name, group)?;
+                        }
+                    }
+                    "graphicspath" => {
+                        if let Some(group) = cursor.group() {
+                            let mut paths = Cursor::new(group);
+                            while let Some(path) = paths.group() {
+                                self.graphics_paths.push(path.trim().to_string());
+                            }
+                        }
+                    }
+                    _ => {}
+                },
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn metadata_entry(&mut self, name: &str, value: &str) -> anyhow::Result<()> {
+        let text = plain_text(&self.inline(value)?);
+        let value = (!text.is_empty()).then_some(text);
+        match name {
+            "title" => self.metadata.title = value,
+            "author" => self.metadata.author = value,
+            _ => self.metadata.date = value,
+        }
+        Ok(())
+    }
+
+    /// Blocks of a part of the source
+    fn parse(&mut self, text: &str) -> anyhow::Result<Vec<Element>> {
+        let mut cursor = Cursor::new(text);
+        let mut out = Output::default();
+        while let Some(c) = cursor.next() {
+            match c {
+                '\\' => self.command(&mut cursor, &mut out)?,
+                '%' => {
+                    cursor.position -= 1;
+                    cursor.skip_comment();
+                }
+                '{' => {
+                    cursor.position -= 1;
+                    let group = cursor.group().unwrap_or_default();
+                    let elements = self.inline(group)?;
+                    out.extend_inline(elements);
+                }
+                '$' => {
+                    if cursor.eat("$") {
+                        let tex = cursor.until("$$");
+                        out.push_block(Element::Math {
+                            tex: math_content(tex),
+                            display: true,
+                        });
+                    } else {
+                        let tex = cursor.until("$");
+                        out.push_inline(Element::Math {
+                            tex: math_content(tex),
+                            display: false,
+                        });
+                    }
+                }
+                '\n' => {
+                    cursor.position -= 1;
+                    cursor.skip_space();
+                    if cursor.peek() == Some('\n') {
+                        cursor.next();
+                        out.end_paragraph();
+                    } else {
+                        out.push_text(" ");
+                    }
+                }
+                '~' => out.push_text("\u{a0}"),
+                '-' if cursor.eat("--") => out.push_text("—"),
+                '-' if cursor.eat("-") => out.push_text("–"),
+                '`' if cursor.eat("`") => out.push_text("“"),
+                '`' => out.push_text("‘"),
+                '\'' if cursor.eat("'") => out.push_text("”"),
+                '\'' => out.push_text("’"),
+                '}' | '&' => {}
+                c => {
+                    let mut buffer = [0; 4];
+                    out.push_text(c.encode_utf8(&mut buffer));
+                }
+            }
+        }
+        Ok(out.finish())
+    }
+
+    /// Inline elements of a part of the source, its paragraphs joined by line breaks
+    fn inline(&mut self, text: &str) -> anyhow::Result<Vec<Element>> {
+        let mut elements = vec![];
+        for block in self.parse(text)? {
+            match block {
+                Element::Paragraph {
+                    elements: paragraph,
+                    ..
+                } => {
+                    if !elements.is_empty() {
+                        elements.push(Element::LineBreak);
+                    }
+                    elements.extend(paragraph);
+                }
+                block => elements.push(block),
+            }
+        }
+        Ok(elements)
+    }
+
+    fn command(&mut self, cursor: &mut Cursor, out: &mut Output) -> anyhow::Result<()> {
+        let name = cursor.command();
+        if name.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            cursor.eat("*");
+        }
+        if let Some(index) = SECTIONS.iter().position(|section| *section == name) {
+            cursor.optional();
+            let text = plain_text(&self.inline(cursor.argument())?);
+            let level = (index.saturating_sub(self.top_section) + 1).min(6) as u8;
+            let anchor = header_anchor(&text);
+            out.push_block(Element::Header { level, text });
+            let before = cursor.position;
+            cursor.skip_space();
+            if cursor.eat("\\label") {
+                let label = cursor.group().unwrap_or_default().trim().to_string();
+                self.labels.insert(label, (ReferenceKind::Section, anchor));
+            } else {
+                cursor.position = before;
+            }
+            return Ok(());
+        }
+        if let Some((_, count)) = IGNORED.iter().find(|(ignored, _)| *ignored == name) {
+            for _ in 0..*count {
+                cursor.optional();
+                cursor.argument();
+            }
+            return Ok(());
+        }
+        if let Some((_, count)) = KEPT.iter().find(|(kept, _)| *kept == name) {
+            let mut argument = "";
+            for _ in 0..*count {
+                cursor.optional();
+                argument = cursor.argument();
+            }
+            let elements = self.inline(argument)?;
+            out.extend_inline(elements);
+            return Ok(());
+        }
+        if ACCENTS.iter().any(|(accent, _)| *accent == name)
+            && (!name.starts_with(|c: char| c.is_ascii_alphabetic())
+                || !cursor.rest().starts_with(|c: char| c.is_ascii_alphabetic()))
+        {
+            let letter = cursor.argument();
+            out.push_text(&accented(name, letter));
+            return Ok(());
+        }
+        if let Some((_, text)) = SYMBOLS.iter().find(|(symbol, _)| *symbol == name) {
+            out.push_text(text);
+            return Ok(());
+        }
+        match name {
+            "begin" => {
+                let environment = cursor.group().unwrap_or_default().trim().to_string();
+                self.environment(&environment, cursor, out)?;
+            }
+            "end" => {
+                cursor.group();
+            }
+            "\\" | "newline" | "linebreak" | "tabularnewline" => {
+                cursor.eat("*");
+                cursor.optional();
+                out.push_inline(Element::LineBreak);
+            }
+            "par" | "item" => out.end_paragraph(),
+            // between the authors of the title
+            "and" => {
+                out.text.truncate(out.text.trim_end().len());
+                out.push_text(", ");
+            }
+            "-" | "/" | "@" | "!" => {}
+            "(" => {
+                let tex = cursor.until("\\)");
+                out.push_inline(Element::Math {
+                    tex: math_content(tex),
+                    display: false,
+                });
+            }
+            "[" => {
+                let tex = cursor.until("\\]");
+                out.push_block(Element::Math {
+                    tex: math_content(tex),
+                    display: true,
+                });
+            }
+            "emph" | "textit" | "textsl" | "textbf" | "sout" | "st" | "xout" => {
+                let style = match name {
+                    "textbf" => TextStyle::Strong,
+                    "sout" | "st" | "xout" => TextStyle::Strikethrough,
+                    _ => TextStyle::Emphasis,
+                };
+                let elements = self.inline(cursor.argument())?;
+                out.push_inline(Element::Styled { style, elements });
+            }
+            // declarations, styling the rest of the group
+            "em" | "it" | "itshape" | "sl" | "slshape" | "bf" | "bfseries" => {
+                let style = match name {
+                    "bf" | "bfseries" => TextStyle::Strong,
+                    _ => TextStyle::Emphasis,
+                };
+                let rest = cursor.rest();
+                cursor.position = cursor.text.len();
+                let elements = self.inline(rest)?;
+                out.push_inline(Element::Styled { style, elements });
+            }
+            "texttt" => {
+                let text = plain_text(&self.inline(cursor.argument())?);
+                out.push_inline(Element::InlineCode { text });
+            }
+            "tt" | "ttfamily" => {
+                let rest = cursor.rest();
+                cursor.position = cursor.text.len();
+                let text = plain_text(&self.inline(rest)?);
+                out.push_inline(Element::InlineCode { text });
+            }
+            "verb" | "lstinline" => {
+                cursor.eat("*");
+                cursor.optional();
+                let text = match cursor.next() {
+                    Some('{') => cursor.until("}"),
+                    Some(delimiter) => {
+                        let mut buffer = [0; 4];
+                        cursor.until(delimiter.encode_utf8(&mut buffer))
+                    }
+                    None => "",
+                };
+                out.push_inline(Element::InlineCode {
+                    text: text.to_string(),
+                });
+            }
+            "mintinline" => {
+                cursor.optional();
+                cursor.group();
+                let text = cursor.raw_group().unwrap_or_default();
+                out.push_inline(Element::InlineCode {
+                    text: text.to_string(),
+                });
+            }
+            "footnote" => {
+                cursor.optional();
+                let elements = self.inline(cursor.argument())?;
+                out.push_inline(Element::Footnote { elements });
+            }
+            "href" => {
+                let url = unescape_url(cursor.raw_group().unwrap_or_default());
+                let elements = self.inline(cursor.argument())?;
+                out.push_inline(Element::Hyperlink {
+                    elements,
+                    url,
+                    alt: String::new(),
+                    size: 14,
+                });
+            }
+            "url" | "nolinkurl" => {
+                let url = unescape_url(cursor.raw_group().unwrap_or_default());
+                out.push_inline(Element::Hyperlink {
+                    elements: vec![Element::Text {
+                        text: url.clone(),
+                        size: 14,
+                    }],
+                    url,
+                    alt: String::new(),
+                    size: 14,
+                });
+            }
+            "hyperref" | "hyperlink" => {
+                let label = match name {
+                    "hyperref" => cursor.optional(),
+                    _ => cursor.group(),
+                };
+                let elements = self.inline(cursor.argument())?;
+                out.push_inline(Element::Hyperlink {
+                    elements,
+                    url: format!("#{}", label.unwrap_or_default().trim()),
+                    alt: String::new(),
+                    size: 14,
+                });
+            }
+            "hypertarget" => {
+                let id = cursor.group().unwrap_or_default().trim().to_string();
+                self.bookmarks.insert(id.clone());
+                out.push_inline(Element::Bookmark {
+                    id,
+                    title: String::new(),
+                });
+                let elements = self.inline(cursor.argument())?;
+                out.extend_inline(elements);
+            }
+            "label" => {
+                let label = cursor.group().unwrap_or_default().trim().to_string();
+                if self.float().is_some() {
+                    self.float_labels.push(label);
+                } else {
+                    self.bookmarks.insert(label.clone());
+                    out.push_inline(Element::Bookmark {
+                        id: label,
+                        title: String::new(),
+                    });
+                }
+            }
+            // resolved once the whole document is read, see Parser::resolve_references
+            "ref" | "autoref" | "cref" | "Cref" | "nameref" | "eqref" | "pageref" => {
+                let label = cursor.group().unwrap_or_default().trim().to_string();
+                out.push_inline(Element::CrossReference {
+                    target_id: label,
+                    kind: ReferenceKind::Section,
+                });
+            }
+            "cite" | "citep" | "citet" | "parencite" | "textcite" | "autocite" => {
+                cursor.optional();
+                cursor.optional();
+                let keys = cursor.group().unwrap_or_default();
+                let keys: Vec<&str> = keys.split(',').map(str::trim).collect();
+                out.push_text(&format!("[{}]", keys.join(", ")));
+            }
+            "caption" => {
+                cursor.optional();
+                let elements = self.inline(cursor.argument())?;
+                if self.float() == Some("figure") {
+                    self.caption = Some(plain_text(&elements));
+                } else {
+                    out.push_block(Element::Paragraph {
+                        elements,
+                        direction: TextDirection::default(),
+                    });
+                }
+            }
+            "includegraphics" => {
+                let options = cursor.optional();
+                let path = cursor.group().unwrap_or_default();
+                if let Some(image) = self.image(path, options)? {
+                    out.push_inline(image);
+                }
+            }
+            "tableofcontents" => out.push_block(Element::TableOfContents { max_level: 3 }),
+            "today" => out.push_inline(Element::Field {
+                kind: FieldKind::Date,
+            }),
+            "thepage" => out.push_inline(Element::Field {
+                kind: FieldKind::PageNumber,
+            }),
+            "title" | "author" | "date" => {
+                cursor.optional();
+                let value = cursor.group().unwrap_or_default();
+                self.metadata_entry(name, value)?;
+            }
+            "input" | "include" => {
+                let file = cursor.group().unwrap_or_default();
+                warn!("Included file not read: {}", file);
+            }
+            "def" => {
+                cursor.next();
+                cursor.command();
+                cursor.until("{");
+                cursor.position -= 1;
+                cursor.group();
+            }
+            // unknown commands are left out, their argument read as text
+            name => {
+                if cursor.peek() == Some('{') {
+                    let elements = self.inline(cursor.group().unwrap_or_default())?;
+                    out.extend_inline(elements);
+                } else {
+                    debug!("Unknown LaTeX command \\{} left out", name);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Kind of the innermost figure or table being read: `figure` or `table`
+    fn float(&self) -> Option<&'static str> {
+        self.environments.iter().rev().find_map(|environment| {
+            match environment.trim_end_matches('*') {
+                "figure" | "wrapfigure" => Some("figure"),
+                "table" | "longtable" => Some("table"),
+                _ => None,
+            }
+        })
+    }
+
+    fn environment(
+        &mut self,
+        name: &str,
+        cursor: &mut Cursor,
+        out: &mut Output,
+    ) -> anyhow::Result<()> {
+        let base = name.trim_end_matches('*');
+        if let Some((_, aligned)) = MATH_ENVIRONMENTS.iter().find(|(math, _)| *math == base) {
+            let columns = if base == "alignat" {
+                cursor.group().map(|columns| format!("{{{columns}}}"))
+            } else {
+                None
+            };
+            let tex = math_content(cursor.environment(name));
+            let tex = match aligned {
+                Some(aligned) => format!(
+                    "\\begin{{{aligned}}}{}\n{tex}\n\\end{{{aligned}}}",
+                    columns.unwrap_or_default()
+                ),
+                None => tex,
+            };
+            out.push_block(Element::Math { tex, display: true });
+            return Ok(());
+        }
+        if base == "math" {
+            let tex = math_content(cursor.environment(name));
+            out.push_inline(Element::Math {
+                tex,
+                display: false,
+            });
+            return Ok(());
+        }
+        if VERBATIM_ENVIRONMENTS.contains(&base) {
+            let mut info = String::new();
+            if cursor.peek() == Some('[') {
+                let options = cursor.optional().unwrap_or_default();
+                for option in options.split(',') {
+                    if let Some(("language", language)) = option
+                        .split_once('=')
+                        .map(|(key, value)| (key.trim(), value.trim()))
+                    {
+                        info = language.to_lowercase();
+                    }
+                }
+            }
+            if base == "minted" {
+                info = cursor.group().unwrap_or_default().trim().to_lowercase();
+            }
+            let content = cursor.environment(name);
+            let content = content.trim_start_matches([' ', '\t', '\r']);
+            let content = content.strip_prefix('\n').unwrap_or(content);
+            out.push_block(Element::CodeBlock {
+                info,
+                code: content.trim_end().to_string(),
+            });
+            return Ok(());
+        }
+        if TABLE_ENVIRONMENTS.contains(&base) {
+            self.environments.push(name.to_string());
+            let blocks = self.table(name, cursor);
+            self.environments.pop();
+            for block in blocks? {
+                out.push_block(block);
+            }
+            return Ok(());
+        }
+        match base {
+            "itemize" | "enumerate" | "description" | "thebibliography" => {
+                let list = self.list(name, cursor)?;
+                out.push_block(list);
+            }
+            "figure" | "wrapfigure" | "table" => {
+                cursor.optional();
+                if base == "wrapfigure" {
+                    cursor.group();
+                    cursor.group();
+                }
+                let content = cursor.environment(name);
+                let blocks = self.float_content(name, content)?;
+                for block in blocks {
+                    out.push_block(block);
+                }
+            }
+            "abstract" => {
+                let blocks = self.parse(cursor.environment(name))?;
+                let text = plain_text(&blocks);
+                self.metadata.description = (!text.is_empty()).then_some(text);
+            }
+            "comment" => {
+                cursor.environment(name);
+            }
+            _ => {
+                if base == "minipage" {
+                    cursor.optional();
+                    cursor.group();
+                } else if base == "multicols" {
+                    cursor.group();
+                }
+                self.environments.push(name.to_string());
+                let blocks = self.parse(cursor.environment(name));
+                self.environments.pop();
+                for block in blocks? {
+                    out.push_block(block);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Blocks of a figure or a table, the caption of a figure being the title of its image
+    fn float_content(&mut self, name: &str, content: &str) -> anyhow::Result<Vec<Element>> {
+        let caption = self.caption.take();
+        let float_labels = std::mem::take(&mut self.float_labels);
+        let (images, tables) = (self.images, self.tables);
+        self.environments.push(name.to_string());
+        let blocks = self.parse(content);
+        self.environments.pop();
+        let mut blocks = blocks?;
+        let figure_caption = std::mem::replace(&mut self.caption, caption);
+        let labels = std::mem::replace(&mut self.float_labels, float_labels);
+
+        let table = (name.starts_with("table") && self.tables > tables)
+            .then(|| (ReferenceKind::Table, format!("table-{}", tables + 1)));
+        let target = table.or_else(|| {
+            let image = first_image(&mut blocks)?;
+            if let Some(caption) = &figure_caption {
+                image.set_image_title(caption);
+            }
+            let id = match &figure_caption {
+                Some(caption) => caption.clone(),
+                None => format!("figure-{}", images + 1),
+            };
+            Some((ReferenceKind::Figure, id))
+        });
+        if let Some(target) = target {
+            for label in labels {
+                self.labels.insert(label, target.clone());
+            }
+        }
+        // a figure of one image is the image
+        if let [Element::Paragraph { elements, .. }] = blocks.as_mut_slice() {
+            if let [Element::Image(_)] = elements.as_slice() {
+                return Ok(std::mem::take(elements));
+            }
+        }
+        if let (Some(caption), None) = (figure_caption, first_image(&mut blocks)) {
+            blocks.push(Element::Paragraph {
+                elements: vec![Element::Text {
+                    text: caption,
+                    size: 14,
+                }],
+                direction: TextDirection::default(),
+            });
+        }
+        Ok(blocks)
+    }
+
+    fn list(&mut self, name: &str, cursor: &mut Cursor) -> anyhow::Result<Element> {
+        let base = name.trim_end_matches('*');
+        let options = cursor.optional().unwrap_or_default();
+        if base == "thebibliography" {
+            cursor.group();
+        }
+        let content = cursor.environment(name);
+        let item = if base == "thebibliography" {
+            "bibitem"
+        } else {
+            "item"
+        };
+        let mut parts = split_top_level(content, |rest| {
+            starts_with_command(rest, item).then_some(item.len() + 1)
+        });
+        let preface = parts.remove(0);
+
+        let mut numbering = NumberingStyle::Decimal;
+        let mut start = 1;
+        let label = options
+            .split(',')
+            .find_map(|option| option.trim().strip_prefix("label="))
+            .unwrap_or(options);
+        for (command, style) in [
+            ("\\alph", NumberingStyle::LowerAlpha),
+            ("\\Alph", NumberingStyle::UpperAlpha),
+            ("\\roman", NumberingStyle::LowerRoman),
+            ("\\Roman", NumberingStyle::UpperRoman),
+        ] {
+            if label.contains(command) {
+                numbering = style;
+            }
+        }
+        // short labels of the enumerate package: a), (i)...
+        if !label.contains('\\') && !label.contains('=') {
+            numbering = match label.trim_matches(|c: char| !c.is_ascii_alphanumeric()) {
+                "a" => NumberingStyle::LowerAlpha,
+                "A" => NumberingStyle::UpperAlpha,
+                "i" => NumberingStyle::LowerRoman,
+                "I" => NumberingStyle::UpperRoman,
+                _ => numbering,
+            };
+        }
+        for option in options.split(',') {
+            if let Some(value) = option.trim().strip_prefix("start=") {
+                start = value.trim().parse().unwrap_or(start);
+            }
+        }
+        let mut preface = Cursor::new(preface);
+        while !preface.rest().is_empty() {
+            if preface.eat("\\setcounter") {
+                let counter = preface.group().unwrap_or_default();
+                let value = preface.group().unwrap_or_default();
+                if counter.starts_with("enum") {
+                    start = value
+                        .trim()
+                        .parse::<usize>()
+                        .map_or(start, |value| value + 1);
+                }
+            } else {
+                preface.next();
+            }
+        }
+
+        self.environments.push(name.to_string());
+        let mut items = vec![];
+        for part in parts {
+            let mut part = Cursor::new(part);
+            let term = part.optional();
+            if base == "thebibliography" {
+                part.group();
+            }
+            let mut blocks = self.parse(part.rest())?;
+            if let Some(term) = term {
+                let mut term = self.inline(term)?;
+                if base == "description" {
+                    term = vec![Element::Styled {
+                        style: TextStyle::Strong,
+                        elements: term,
+                    }];
+                }
+                term.push(Element::Text {
+                    text: " ".to_string(),
+                    size: 14,
+                });
+                match blocks.first_mut() {
+                    Some(Element::Paragraph { elements, .. }) => {
+                        term.append(elements);
+                        *elements = term;
+                    }
+                    _ => blocks.insert(
+                        0,
+                        Element::Paragraph {
+                            elements: term,
+                            direction: TextDirection::default(),
+                        },
+                    ),
+                }
+            }
+            let mut blocks = join_paragraphs(blocks);
+            if blocks.is_empty() {
+                blocks.push(Element::Text {
+                    text: String::new(),
+                    size: 14,
+                });
+            }
+            items.extend(blocks.into_iter().map(|element| ListItem { element }));
+        }
+        self.environments.pop();
+        Ok(Element::List {
+            elements: items,
+            numbered: matches!(base, "enumerate" | "thebibliography"),
+            start,
+            numbering,
+        })
+    }
+
+    /// Table of a tabular environment, with the captions of a long table
+    fn table(&mut self, name: &str, cursor: &mut Cursor) -> anyhow::Result<Vec<Element>> {
+        cursor.optional();
+        if name != "tabular" && name != "longtable" {
+            // width of the table
+            cursor.group();
+        }
+        // column types
+        cursor.group();
+        let content = cursor.environment(name);
+        self.tables += 1;
+
+        let mut blocks = vec![];
+        let mut rows: Vec<(bool, Vec<Element>)> = vec![];
+        let parts = split_top_level(content, |rest| {
+            if rest.starts_with("\\\\") {
+                Some(2)
+            } else if starts_with_command(rest, "tabularnewline") {
+                Some("\\tabularnewline".len())
+            } else {
+                None
+            }
+        });
+        for part in parts {
+            let mut row = Cursor::new(part);
+            row.eat("*");
+            row.optional();
+            let mut rule = false;
+            loop {
+                row.skip_space();
+                while row.peek() == Some('\n') {
+                    row.next();
+                    row.skip_space();
+                }
+                let rest = row.rest();
+                if let Some(command) = [
+                    "hline",
+                    "toprule",
+                    "midrule",
+                    "bottomrule",
+                    "endhead",
+                    "endfirsthead",
+                    "endfoot",
+                    "endlastfoot",
+                ]
+                .into_iter()
+                .find(|command| starts_with_command(rest, command))
+                {
+                    row.position += command.len() + 1;
+                    rule = rule || !command.starts_with("end") || command == "endhead";
+                } else if starts_with_command(rest, "cline")
+                    || starts_with_command(rest, "cmidrule")
+                    || starts_with_command(rest, "hhline")
+                {
+                    row.next();
+                    row.command();
+                    row.delimited('(', ')', false);
+                    row.group();
+                } else {
+                    break;
+                }
+            }
+            let rest = row.rest();
+            if rest.trim().is_empty() {
+                continue;
+            }
+            if starts_with_command(rest.trim_start(), "caption") {
+                blocks.extend(self.parse(rest)?);
+                continue;
+            }
+            let mut cells = vec![];
+            for cell in split_top_level(rest, |rest| rest.starts_with('&').then_some(1)) {
+                let mut source = cell.trim();
+                let mut span = 1;
+                if starts_with_command(source, "multicolumn") {
+                    let mut multicolumn = Cursor::new(source);
+                    multicolumn.next();
+                    multicolumn.command();
+                    span = multicolumn
+                        .group()
+                        .and_then(|columns| columns.trim().parse().ok())
+                        .unwrap_or(1);
+                    multicolumn.group();
+                    source = multicolumn.group().unwrap_or_default();
+                }
+                let mut elements = join_paragraphs(self.parse(source)?);
+                cells.push(match elements.len() {
+                    0 => Element::Text {
+                        text: String::new(),
+                        size: 14,
+                    },
+                    1 => elements.remove(0),
+                    _ => Element::Container {
+                        kind: ContainerKind::Div,
+                        elements,
+                    },
+                });
+                for _ in 1..span {
+                    cells.push(Element::Text {
+                        text: String::new(),
+                        size: 14,
+                    });
+                }
+            }
+            rows.push((rule, cells));
+        }
+
+        // the first row is a header when a rule separates it from the others
+        let has_header = rows.len() > 1 && rows[1].0;
+        let mut rows = rows.into_iter().map(|(_, cells)| cells);
+        let headers = if has_header {
+            rows.next()
+                .unwrap_or_default()
+                .into_iter()
+                .map(|element| TableHeader {
+                    element,
+                    width: 30.,
+                })
+                .collect()
+        } else {
+            vec![]
+        };
+        let rows = rows
+            .map(|cells| TableRow {
+                cells: cells
+                    .into_iter()
+                    .map(|element| TableCell { element })
+                    .collect(),
+            })
+            .collect();
+        blocks.push(Element::Table { headers, rows });
+        Ok(blocks)
+    }
+
+    /// Image of `\includegraphics`, looked for in the graphics paths and, without an
+    /// extension, as PNG or JPEG
+    fn image(&mut self, path: &str, options: Option<&str>) -> anyhow::Result<Option<Element>> {
+        let path = path.trim();
+        let extension = std::path::Path::new(path)
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase());
+        if matches!(extension.as_deref(), Some("pdf" | "eps" | "ps")) {
+            warn!("Image {} left out, PDF and PostScript are not read", path);
+            return Ok(None);
+        }
+        let candidates: Vec<String> = match &extension {
+            Some(_) => vec![path.to_string()],
+            None => ["png", "jpg", "jpeg"]
+                .iter()
+                .map(|extension| format!("{path}.{extension}"))
+                .collect(),
+        };
+        let mut first_error = None;
+        for folder in &self.graphics_paths {
+            for candidate in &candidates {
+                let file = format!("{folder}{candidate}");
+                match (self.image_loader)(&file) {
+                    Ok(bytes) => {
+                        self.images += 1;
+                        let size = options.map(graphics_size).unwrap_or_default();
+                        return Ok(Some(Element::Image(ImageData::new(
+                            bytes,
+                            String::new(),
+                            String::new(),
+                            file,
+                            String::new(),
+                            size,
+                        ))));
+                    }
+                    Err(error) => {
+                        first_error.get_or_insert(error);
+                    }
+                }
+            }
+        }
+        match (extension, first_error) {
+            (Some(_), Some(error)) => Err(error),
+            _ => {
+                warn!("Image {} left out, not found as PNG or JPEG", path);
+                Ok(None)
+            }
+        }
+    }
+
+    /// Replaces the references read as [`Element::CrossReference`] to their label by
+    /// references to the header, figure or table of the label, links to a bookmark or else
+    /// the label in brackets
+    fn resolve_references(&self, elements: &mut [Element]) {
+        for index in 0..elements.len() {
+            let Element::CrossReference { target_id, .. } = &elements[index] else {
+                self.resolve_element(&mut elements[index]);
+                continue;
+            };
+            let label = target_id.clone();
+            elements[index] = match self.labels.get(&label) {
+                Some((kind, target_id)) => {
+                    if let Some(Element::Text { text, .. }) =
+                        index.checked_sub(1).map(|previous| &mut elements[previous])
+                    {
+                        strip_reference_word(text);
+                    }
+                    Element::CrossReference {
+                        target_id: target_id.clone(),
+                        kind: *kind,
+                    }
+                }
+                None if self.bookmarks.contains(&label) => Element::Hyperlink {
+                    elements: vec![Element::Text {
+                        text: label.clone(),
+                        size: 14,
+                    }],
+                    url: format!("#{label}"),
+                    alt: String::new(),
+                    size: 14,
+                },
+                None => Element::Text {
+                    text: format!("[{label}]"),
+                    size: 14,
+                },
+            };
+        }
+    }
+
+    fn resolve_element(&self, element: &mut Element) {
+        match element {
+            Element::Hyperlink { elements, url, .. } => {
+                if let Some((ReferenceKind::Section, anchor)) = url
+                    .strip_prefix('#')
+                    .and_then(|label| self.labels.get(label))
+                {
+                    *url = format!("#{anchor}");
+                }
+                self.resolve_references(elements);
+            }
+            Element::Paragraph { elements, .. }
+            | Element::Styled { elements, .. }
+            | Element::Footnote { elements }
+            | Element::Container { elements, .. } => self.resolve_references(elements),
+            Element::List { elements, .. } => {
+                for item in elements {
+                    self.resolve_references(std::slice::from_mut(&mut item.element));
+                }
+            }
+            Element::Table { headers, rows } => {
+                for header in headers {
+                    self.resolve_references(std::slice::from_mut(&mut header.element));
+                }
+                for cell in rows.iter_mut().flat_map(|row| row.cells.iter_mut()) {
+                    self.resolve_references(std::slice::from_mut(&mut cell.element));
+                }
+            }
+            Element::Attributed { element, .. } => {
+                self.resolve_references(std::slice::from_mut(element.as_mut()))
+            }
+            _ => {}
+        }
+    }
+}
+
+/// First image of the blocks, in a paragraph or alone
+fn first_image(blocks: &mut [Element]) -> Option<&mut ImageData> {
+    blocks.iter_mut().find_map(|block| match block {
+        Element::Image(image) => Some(image),
+        Element::Paragraph { elements, .. } | Element::Container { elements, .. } => {
+            first_image(elements)
+        }
+        _ => None,
+    })
+}
+
+/// Removes the word such as `Table` written before a reference from the end of the text
+fn strip_reference_word(text: &mut String) {
+    let trimmed = text.trim_end_matches([' ', '\u{a0}']);
+    let start = trimmed.rfind(char::is_whitespace).map_or(0, |space| {
+        space + trimmed[space..].chars().next().map_or(1, char::len_utf8)
+    });
+    if REFERENCE_WORDS.contains(&trimmed[start..].to_lowercase().as_str()) {
+        text.truncate(start);
+    }
+}
+
+/// URL of `\href` and `\url`, its escaped characters unescaped
+fn unescape_url(url: &str) -> String {
+    let mut unescaped = String::with_capacity(url.len());
+    let mut chars = url.trim().chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            unescaped.extend(chars.next());
+        } else {
+            unescaped.push(c);
+        }
+    }
+    unescaped
+}
+
+impl TransformerTrait for Transformer {
+    /// Document of a LaTeX source, its images read from the current directory
+    fn parse(document: &Bytes) -> crate::Result<Document> {
+        Transformer::parse_with_loader(document, disk_image_loader("."))
+    }
+
+    /// LaTeX source of the document, its images saved to the current directory
+    fn generate(document: &Document) -> crate::Result<Bytes> {
+        Transformer::generate_with_saver(document, disk_image_saver("."))
+    }
+
+    fn generate_with_options(
+        document: &Document,
+        options: &GenerateOptions,
+    ) -> crate::Result<Bytes> {
+        Transformer::generate_with_image_saver(document, disk_image_saver("."), &options.latex)
+            .map_err(crate::Error::generation_error)
+    }
+}
+
+impl TransformerWithImageLoaderSaverTrait for Transformer {
+    /// Document of a LaTeX source, its images read with `image_loader` from the paths of
+    /// `\includegraphics` and `\graphicspath`
+    fn parse_with_loader<F>(document: &Bytes, image_loader: F) -> crate::Result<Document>
+    where
+        F: Fn(&str) -> anyhow::Result<Bytes>,
+    {
+        Transformer::parse_source(document, &image_loader_errors(image_loader))
+            .map_err(crate::Error::parse_error)
+    }
+
+    /// LaTeX source of the document, its images saved with `image_saver` under the names
+    /// they are included with
+    fn generate_with_saver<F>(document: &Document, image_saver: F) -> crate::Result<Bytes>
+    where
+        F: Fn(&Bytes, &str) -> anyhow::Result<()>,
+    {
+        Transformer::generate_with_image_saver(document, image_saver, &LatexOptions::default())
+            .map_err(crate::Error::generation_error)
+    }
+}
+
+impl Transformer {
+    fn parse_source<F>(source: &Bytes, image_loader: &F) -> anyhow::Result<Document>
+    where
+        F: Fn(&str) -> anyhow::Result<Bytes>,
+    {
+        let source = std::str::from_utf8(source)?;
+        let (preamble, body) = match source.find("\\begin{document}") {
+            Some(start) => {
+                let body = &source[start + "\\begin{document}".len()..];
+                let body = body
+                    .find("\\end{document}")
+                    .map_or(body, |end| &body[..end]);
+                (&source[..start], body)
+            }
+            None => ("", source),
+        };
+        let top_section = SECTIONS
+            .iter()
+            .position(|section| {
+                body.match_indices('\\')
+                    .any(|(index, _)| starts_with_command(&body[index..], section))
+            })
+            .unwrap_or(2);
+        let mut parser = Parser {
+            image_loader,
+            top_section,
+            graphics_paths: vec![String::new()],
+            metadata: Metadata::default(),
+            labels: HashMap::new(),
+            bookmarks: HashSet::new(),
+            environments: vec![],
+            caption: None,
+            float_labels: vec![],
+            images: 0,
+            tables: 0,
+        };
+        parser.preamble(preamble)?;
+        let mut elements = parser.parse(body)?;
+        parser.resolve_references(&mut elements);
+        let mut document = Document::new(elements);
+        document.metadata = parser.metadata;
+        Ok(document)
+    }
+
+    fn generate_with_image_saver<F>(
+        document: &Document,
+        image_saver: F,
+        options: &LatexOptions,
+    ) -> anyhow::Result<Bytes>
+    where
+        F: Fn(&Bytes, &str) -> anyhow::Result<()>,
+    {
+        let document = &document
+            .resolve_fields()
+            .without_containers()
+            .without_media()
+            .without_drawings();
+        let mut writer = Writer {
+            class: options.class,
+            image_saver: &image_saver,
+            image_count: 0,
+            list_depth: 0,
+            in_argument: true,
+            total_pages: false,
+            tex: String::new(),
+        };
+        let mut band = |elements: Vec<&Element>| -> anyhow::Result<String> {
+            let elements: Vec<Element> = elements.into_iter().cloned().collect();
+            writer.content(&elements)?;
+            Ok(std::mem::take(&mut writer.tex))
+        };
+        let header = band(document.get_page_header())?;
+        let footer = band(document.get_page_footer())?;
+        writer.in_argument = false;
+        for band in &document.bands {
+            if !matches!(band, Band::PageHeader(_) | Band::PageFooter(_)) {
+                for element in band.elements() {
+                    writer.block(element)?;
+                }
+            }
+        }
+
+        let metadata = &document.metadata;
+        let mut tex = format!(
+            "\\documentclass{{{}}}\n\\usepackage{{iftex}}\n\\ifPDFTeX\n\\usepackage[utf8]{{inputenc}}\n\\usepackage[T1]{{fontenc}}\n\\else\n\\usepackage{{fontspec}}\n\\fi\n",
+            options.class
+        );
+        let page = document.page_format.dimensions();
+        let (width, height) = match document.orientation {
+            PageOrientation::Portrait => (page.page_width, page.page_height),
+            PageOrientation::Landscape => (page.page_height, page.page_width),
+        };
+        let _ = write!(
+            tex,
+            "\\usepackage[paperwidth={:.1}mm,paperheight={:.1}mm,top={:.1}mm,bottom={:.1}mm,left={:.1}mm,right={:.1}mm",
+            width.to_mm(),
+            height.to_mm(),
+            page.page_margin_top.to_mm(),
+            page.page_margin_bottom.to_mm(),
+            page.page_margin_left.to_mm(),
+            page.page_margin_right.to_mm()
+        );
+        if !header.is_empty() {
+            tex.push_str(",includehead");
+        }
+        if !footer.is_empty() {
+            tex.push_str(",includefoot");
+        }
+        tex.push_str("]{geometry}\n\\usepackage{amsmath,amssymb}\n\\usepackage{graphicx}\n\\usepackage{enumitem}\n\\usepackage[normalem]{ulem}\n\\usepackage{parskip}\n");
+        if writer.total_pages {
+            tex.push_str("\\usepackage{lastpage}\n");
+        }
+        if !header.is_empty() || !footer.is_empty() {
+            let _ = write!(
+                tex,
+                "\\usepackage{{fancyhdr}}\n\\pagestyle{{fancy}}\n\\fancyhf{{}}\n\\renewcommand{{\\headrulewidth}}{{0pt}}\n\\fancyhead[C]{{{header}}}\n\\fancyfoot[C]{{{footer}}}\n"
+            );
+        }
+        tex.push_str(
+            "\\usepackage{hyperref}\n\\hypersetup{colorlinks=true,linkcolor=blue,urlcolor=blue",
+        );
+        if let Some(title) = &metadata.title {
+            let _ = write!(tex, ",pdftitle={{{}}}", escape(title));
+        }
+        if let Some(author) = &metadata.author {
+            let _ = write!(tex, ",pdfauthor={{{}}}", escape(author));
+        }
+        if let Some(description) = &metadata.description {
+            let _ = write!(tex, ",pdfsubject={{{}}}", escape(description));
+        }
+        if !metadata.keywords.is_empty() {
+            let _ = write!(
+                tex,
+                ",pdfkeywords={{{}}}",
+                escape(&metadata.keywords.join(", "))
+            );
+        }
+        tex.push_str("}\n");
+        tex.push_str(IMAGE_SIZE);
+        if let Some(title) = &metadata.title {
+            let _ = write!(
+                tex,
+                "\\title{{{}}}\n\\author{{{}}}\n\\date{{{}}}\n",
+                escape(title),
+                escape(metadata.author.as_deref().unwrap_or_default()),
+                escape(metadata.date.as_deref().unwrap_or_default())
+            );
+        }
+        tex.push_str("\n\\begin{document}\n\n");
+        if metadata.title.is_some() {
+            tex.push_str("\\maketitle\n\n");
+        }
+        tex.push_str(writer.tex.trim_end());
+        tex.push_str("\n\n\\end{document}\n");
+        Ok(Bytes::from(tex))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+    use crate::core::tests::{init_logger, table_texts};
+    use crate::core::TransformerTrait;
+    use crate::markdown;
+
+    #[test]
+    fn test_generate() -> anyhow::Result<()> {
+        init_logger();
+        let markdown = "# Results & costs\n\nSee [the end](#the-end) and \
+            [the site](https://example.com/a_b#c%20d).[^1]\n\n\
+            1. One\n   - Nested *item*\n2. Two\n\n\
+            | Name | Value |\n|------|-------|\n| a_b | 100% |\n\n\
+            ```\nlet x = {1};\n```\n\n## The end\n\n`$HOME` ~ **done**\n\n\
+            [^1]: A note.\n";
+        let mut document = markdown::Transformer::parse(&Bytes::from(markdown))?;
+        let png = Bytes::from(std::fs::read("test/data/small.png")?);
+        if let Some(Band::Detail(elements)) = document.bands.last_mut() {
+            elements.push(Element::Image(ImageData::new(
+                png.clone(),
+                "Logo".to_string(),
+                String::new(),
+                "png".to_string(),
+                String::new(),
+                ImageDimension {
+                    width: Some("50%".to_string()),
+                    height: None,
+                },
+            )));
+        }
+        document.metadata.title = Some("Report #1".to_string());
+        document.metadata.author = Some("Ada".to_string());
+
+        let saved = RefCell::new(vec![]);
+        let tex = Transformer::generate_with_saver(&document, |bytes, name| {
+            saved.borrow_mut().push((name.to_string(), bytes.clone()));
+            Ok(())
+        })?;
+        let tex = std::str::from_utf8(&tex)?;
+        assert!(tex.starts_with("\\documentclass{article}\n"));
+        assert!(tex.contains("\\title{Report \\#1}\n\\author{Ada}\n"));
+        assert!(tex.contains("\\begin{document}\n\n\\maketitle\n\n"));
+        assert!(tex.contains("\\section{Results \\& costs}\\label{results--costs}"));
+        assert!(tex.contains("\\subsection{The end}\\label{the-end}"));
+        assert!(tex.contains("See \\hyperref[the-end]{the end} and"));
+        assert!(tex.contains("\\href{https://example.com/a_b\\#c\\%20d}{the site}"));
+        assert!(tex.contains("\\footnote{A note.}"));
+        assert!(tex.contains(
+            "\\begin{enumerate}\n\\item One\n\\begin{itemize}\n\\item Nested \\emph{item}\n\\end{itemize}\n\n\\item Two\n\\end{enumerate}"
+        ));
+        assert!(tex.contains("\\begin{tabular}{|p{0.450\\linewidth}|p{0.450\\linewidth}|}"));
+        assert!(tex.contains("\\textbf{Name} & \\textbf{Value} \\\\\n\\hline\na\\_b & 100\\% \\\\"));
+        assert!(tex.contains("\\begin{verbatim}\nlet x = {1};\n\\end{verbatim}"));
+        assert!(tex.contains("\\texttt{\\$HOME} \\textasciitilde{} \\textbf{done}"));
+        assert!(tex.contains(
+            "\\begin{figure}[htbp]\n\\centering\n\\includegraphics[width=0.50\\linewidth]{image1.png}\n\\caption{Logo}\n\\end{figure}"
+        ));
+        assert!(tex.ends_with("\\end{document}\n"));
+        assert_eq!(*saved.borrow(), [("image1.png".to_string(), png)]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_report_class() -> anyhow::Result<()> {
+        let mut document = Document::new(vec![
+            Element::TableOfContents { max_level: 2 },
+            Element::Header {
+                level: 1,
+                text: "Introduction".to_string(),
+            },
+            Element::Header {
+                level: 2,
+                text: "Scope".to_string(),
+            },
+        ]);
+        document.bands.push(Band::PageFooter(vec![Element::Field {
+            kind: FieldKind::PageNumber,
+        }]));
+        let options = GenerateOptions {
+            latex: LatexOptions {
+                class: LatexClass::Report,
+            },
+            ..Default::default()
+        };
+        let tex = Transformer::generate_with_options(&document, &options)?;
+        let tex = std::str::from_utf8(&tex)?;
+        assert!(tex.starts_with("\\documentclass{report}\n"));
+        assert!(tex.contains("\\setcounter{tocdepth}{1}\n\\tableofcontents"));
+        assert!(tex.contains("\\chapter{Introduction}\\label{introduction}"));
+        assert!(tex.contains("\\section{Scope}\\label{scope}"));
+        assert!(tex.contains(",includefoot]{geometry}"));
+        assert!(tex.contains("\\fancyfoot[C]{\\thepage{}}"));
+        assert!(!tex.contains("\\maketitle"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse() -> anyhow::Result<()> {
+        init_logger();
+        let source = r"\documentclass{article}
+\usepackage{graphicx}
+\title{On \emph{Things}}
+\author{Ada \and Alan}
+\begin{document}
+\maketitle
+\begin{abstract}
+A short study.
+\end{abstract}
+
+\section{Intro}\label{sec:intro}
+Some \textbf{bold} and \emph{emphasised} text, see \href{https://example.com}{the site}%
+\footnote{A note.} and Table~\ref{tab:data}.
+Inline $a^2 + b^2$ math.
+
+\subsection*{Lists}
+\begin{itemize}
+  \item First
+  \begin{enumerate}[label=\alph*)]
+    \item Inner
+  \end{enumerate}
+  \item Second
+\end{itemize}
+
+\begin{table}
+\caption{Data}\label{tab:data}
+\begin{tabular}{|l|r|}
+\hline
+Name & Value \\
+\hline
+a & 1 \\
+\multicolumn{2}{c}{total} \\
+\hline
+\end{tabular}
+\end{table}
+
+\begin{verbatim}
+let x = {1};
+\end{verbatim}
+
+\begin{align}
+a &= b \label{eq:1} \\
+c &= d \nonumber
+\end{align}
+
+\begin{figure}[h]
+\centering
+\includegraphics[width=0.5\textwidth]{plot}
+\caption{A plot}\label{fig:plot}
+\end{figure}
+See Figure \ref{fig:plot}, section \ref{sec:intro} and \ref{missing}.
+\end{document}
+";
+        let png = Bytes::from(std::fs::read("test/data/small.png")?);
+        let loaded = std::cell::RefCell::new(vec![]);
+        let document = Transformer::parse_with_loader(&Bytes::from(source), |path| {
+            loaded.borrow_mut().push(path.to_string());
+            match path {
+                "plot.png" => Ok(png.clone()),
+                _ => Err(anyhow::anyhow!("not found")),
+            }
+        })?;
+        assert_eq!(*loaded.borrow(), ["plot.png"]);
+        assert_eq!(document.metadata.title.as_deref(), Some("On Things"));
+        assert_eq!(document.metadata.author.as_deref(), Some("Ada, Alan"));
+        assert_eq!(
+            document.metadata.description.as_deref(),
+            Some("A short study.")
+        );
+
+        let text = |text: &str| Element::Text {
+            text: text.to_string(),
+            size: 14,
+        };
+        let elements = document.bands[0].elements();
+        assert_eq!(
+            elements[0],
+            Element::Header {
+                level: 1,
+                text: "Intro".to_string()
+            }
+        );
+        let Element::Paragraph {
+            elements: paragraph,
+            ..
+        } = &elements[1]
+        else {
+            panic!("no paragraph: {:?}", elements[1]);
+        };
+        assert_eq!(
+            paragraph[1],
+            Element::Styled {
+                style: TextStyle::Strong,
+                elements: vec![text("bold")],
+            }
+        );
+        assert!(
+            matches!(&paragraph[5], Element::Hyperlink { url, .. } if url == "https://example.com")
+        );
+        assert_eq!(
+            paragraph[6],
+            Element::Footnote {
+                elements: vec![text("A note.")]
+            }
+        );
+        assert_eq!(paragraph[7], text(" and "));
+        assert_eq!(
+            paragraph[8],
+            Element::CrossReference {
+                target_id: "table-1".to_string(),
+                kind: ReferenceKind::Table,
+            }
+        );
+        assert_eq!(
+            paragraph[10],
+            Element::Math {
+                tex: "a^2 + b^2".to_string(),
+                display: false,
+            }
+        );
+        assert_eq!(
+            elements[2],
+            Element::Header {
+                level: 2,
+                text: "Lists".to_string()
+            }
+        );
+        let Element::List {
+            elements: items,
+            numbered: false,
+            ..
+        } = &elements[3]
+        else {
+            panic!("no list: {:?}", elements[3]);
+        };
+        assert_eq!(items.len(), 3);
+        assert!(matches!(
+            &items[1].element,
+            Element::List {
+                numbered: true,
+                numbering: NumberingStyle::LowerAlpha,
+                ..
+            }
+        ));
+        assert_eq!(
+            elements[4],
+            Element::Paragraph {
+                elements: vec![text("Data")],
+                direction: TextDirection::default(),
+            }
+        );
+        let Element::Table { headers, rows } = &elements[5] else {
+            panic!("no table: {:?}", elements[5]);
+        };
+        assert_eq!(headers[0].element, text("Name"));
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[1].cells[0].element, text("total"));
+        assert_eq!(rows[1].cells.len(), 2);
+        assert_eq!(
+            elements[6],
+            Element::CodeBlock {
+                info: String::new(),
+                code: "let x = {1};".to_string(),
+            }
+        );
+        assert_eq!(
+            elements[7],
+            Element::Math {
+                tex: "\\begin{aligned}\na &= b  \\\\\nc &= d\n\\end{aligned}".to_string(),
+                display: true,
+            }
+        );
+        let Element::Image(image) = &elements[8] else {
+            panic!("no image: {:?}", elements[8]);
+        };
+        assert_eq!(image.title(), "A plot");
+        assert_eq!(image.size().width.as_deref(), Some("50%"));
+        let Element::Paragraph {
+            elements: paragraph,
+            ..
+        } = &elements[9]
+        else {
+            panic!("no paragraph: {:?}", elements[9]);
+        };
+        assert_eq!(
+            paragraph[..],
+            [
+                text("See "),
+                Element::CrossReference {
+                    target_id: "A plot".to_string(),
+                    kind: ReferenceKind::Figure,
+                },
+                text(", "),
+                Element::CrossReference {
+                    target_id: "intro".to_string(),
+                    kind: ReferenceKind::Section,
+                },
+                text(" and "),
+                text("[missing]"),
+                text("."),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_math_to_markdown() -> anyhow::Result<()> {
+        let parsed =
+            Transformer::parse(&Bytes::from("see {$$x^2$$} here\n\nempty \\(\\) formula\n"))?;
+        assert_eq!(
+            markdown::Transformer::generate(&parsed)?,
+            "see `x^2` here\n\nempty  formula\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_tables() -> anyhow::Result<()> {
+        let source = r"\begin{tabular}{ll}
+\toprule
+Company & Share \\
+\midrule
+AT\&T & 50\% \\
+\multicolumn{2}{c}{total} \\
+\bottomrule
+\end{tabular}
+
+\begin{tabular}{ll}
+x & {a \\ b} \tabularnewline
+\end{tabular}
+";
+        let document = Transformer::parse(&Bytes::from(source))?;
+        let elements = document.get_all_elements();
+
+        // the rule after the first row makes it the header, escaped `&` are text
+        let (headers, rows) = table_texts(elements[0]);
+        assert_eq!(headers, ["Company", "Share"]);
+        assert_eq!(rows, [vec!["AT&T", "50%"], vec!["total", ""]]);
+
+        // a `\\` in a group does not end the row
+        let (headers, rows) = table_texts(elements[1]);
+        assert!(headers.is_empty());
+        assert_eq!(rows, [vec!["x", "a\nb"]]);
+        Ok(())
+    }
+}
@@ -4,6 +4,22 @@
 
 pub mod core;
 
+pub mod error;
+pub use error::{Error, Result};
+
+pub mod diff;
+
+pub mod drawing;
+
+pub mod template;
+
+pub mod report;
+
+pub mod detect;
+
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+
 #[cfg(feature = "text")]
 pub mod text;
 
@@ -13,17 +29,46 @@ pub mod typst;
 #[cfg(feature = "markdown")]
 pub mod markdown;
 
+#[cfg(feature = "markdown")]
+pub mod emoji;
+
 #[cfg(feature = "html")]
 pub mod html;
 
 #[cfg(feature = "pdf")]
 pub mod pdf;
 
+#[cfg(feature = "pdf")]
+pub mod pdf_annotations;
+
+#[cfg(feature = "pdf")]
+pub mod pdf_encryption;
+
+#[cfg(feature = "pdf")]
+pub mod pdf_form;
+
+#[cfg(feature = "pdf")]
+pub mod pdf_image;
+
+#[cfg(feature = "pdf")]
+pub mod pdf_layout;
+
+#[cfg(feature = "pdf")]
+pub mod pdf_tags;
+
+#[cfg(feature = "preview")]
+pub mod preview;
+
+#[cfg(feature = "svg")]
+pub mod svg;
+
 #[cfg(feature = "json")]
 pub mod json;
 
 #[cfg(feature = "xml")]
 pub mod xml;
+#[cfg(feature = "xml")]
+pub mod xml_mapping;
 
 #[cfg(feature = "csv")]
 pub mod csv;
@@ -40,5 +85,87 @@ pub mod xlsx;
 #[cfg(feature = "ods")]
 pub mod ods;
 
+#[cfg(feature = "odt")]
+pub mod odt;
+
+#[cfg(feature = "pptx")]
+pub mod pptx;
+
+#[cfg(feature = "epub")]
+pub mod epub;
+
+#[cfg(feature = "latex")]
+pub mod latex;
+
+#[cfg(any(
+    feature = "xml",
+    feature = "docx",
+    feature = "rtf",
+    feature = "odt",
+    feature = "docbook",
+    feature = "fb2",
+    feature = "confluence"
+))]
+mod util;
+
+#[cfg(any(
+    feature = "asciidoc",
+    feature = "rst",
+    feature = "org",
+    feature = "wiki",
+    feature = "jira",
+    feature = "bbcode"
+))]
+mod lightweight;
+
+#[cfg(feature = "asciidoc")]
+pub mod asciidoc;
+
+#[cfg(feature = "rst")]
+pub mod rst;
+
+#[cfg(feature = "org")]
+pub mod org;
+
+#[cfg(feature = "docbook")]
+pub mod docbook;
+
+#[cfg(feature = "fb2")]
+pub mod fb2;
+
+#[cfg(feature = "ipynb")]
+pub mod ipynb;
+
+#[cfg(feature = "wiki")]
+pub mod wiki;
+
+#[cfg(feature = "confluence")]
+pub mod confluence;
+
+#[cfg(feature = "jira")]
+pub mod jira;
+
+#[cfg(feature = "bbcode")]
+pub mod bbcode;
+#[cfg(feature = "eml")]
+pub mod eml;
+#[cfg(feature = "man")]
+pub mod man;
+
+#[cfg(feature = "icalendar")]
+pub mod icalendar;
+#[cfg(feature = "vcard")]
+pub mod vcard;
+#[cfg(any(feature = "vcard", feature = "icalendar"))]
+mod vobject;
+
 #[cfg(feature = "xls")]
 pub mod xls;
+
+#[cfg(feature = "image")]
+pub mod image_pipeline;
+
+pub mod ocr;
+
+#[cfg(feature = "image")]
+pub mod raster;
@@ -0,0 +1,127 @@
+//! Helpers shared by the parsers and writers of the lightweight markup languages: AsciiDoc,
+//! BBCode, Jira wiki markup, Org mode, reStructuredText and MediaWiki
+
+use crate::core::{Element, TextDirection};
+
+pub(crate) fn text(text: impl Into<String>) -> Element {
+    Element::Text {
+        text: text.into(),
+        size: 14,
+    }
+}
+
+pub(crate) fn paragraph(elements: Vec<Element>) -> Element {
+    Element::Paragraph {
+        elements,
+        direction: TextDirection::default(),
+    }
+}
+
+/// Text of a paragraph of text only, the paragraph otherwise
+pub(crate) fn text_block(block: Element) -> Element {
+    match block {
+        Element::Paragraph { elements, .. }
+            if elements
+                .iter()
+                .all(|element| matches!(element, Element::Text { .. })) =>
+        {
+            text(elements.iter().map(Element::plain_text).collect::<String>())
+        }
+        block => block,
+    }
+}
+
+/// Text of inline elements, line breaks being spaces
+pub(crate) fn plain_text(elements: &[Element]) -> String {
+    elements
+        .iter()
+        .map(|element| match element {
+            Element::LineBreak => " ".to_string(),
+            element => element.plain_text(),
+        })
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+/// Line with its tabs expanded to the next multiple of eight columns
+pub(crate) fn expand_tabs(line: &str) -> String {
+    let mut expanded = String::with_capacity(line.len());
+    let mut column = 0;
+    for c in line.chars() {
+        if c == '\t' {
+            let spaces = 8 - column % 8;
+            expanded.push_str(&" ".repeat(spaces));
+            column += spaces;
+        } else {
+            expanded.push(c);
+            column += 1;
+        }
+    }
+    expanded.trim_end().to_string()
+}
+
+pub(crate) fn indentation(line: &str) -> usize {
+    line.len() - line.trim_start_matches(' ').len()
+}
+
+/// Lines without the indentation they share
+pub(crate) fn dedent(lines: &[String]) -> Vec<String> {
+    let indent = lines
+        .iter()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| indentation(line))
+        .min()
+        .unwrap_or_default();
+    dedent_by(lines, indent)
+}
+
+/// Lines without `indent` spaces, the blank lines shorter than it being emptied
+pub(crate) fn dedent_by(lines: &[String], indent: usize) -> Vec<String> {
+    lines
+        .iter()
+        .map(|line| line.get(indent..).unwrap_or(line.trim_start()).to_string())
+        .collect()
+}
+
+/// Inline elements read so far
+#[derive(Default)]
+pub(crate) struct Inline {
+    pub(crate) elements: Vec<Element>,
+    pub(crate) text: String,
+}
+
+impl Inline {
+    pub(crate) fn push_char(&mut self, c: char) {
+        self.text.push(c);
+    }
+
+    pub(crate) fn push_str(&mut self, text: &str) {
+        self.text.push_str(text);
+    }
+
+    pub(crate) fn push(&mut self, element: Element) {
+        self.flush();
+        self.elements.push(element);
+    }
+
+    pub(crate) fn extend(&mut self, elements: Vec<Element>) {
+        for element in elements {
+            match element {
+                Element::Text { text, .. } => self.text.push_str(&text),
+                element => self.push(element),
+            }
+        }
+    }
+
+    pub(crate) fn flush(&mut self) {
+        if !self.text.is_empty() {
+            self.elements.push(text(std::mem::take(&mut self.text)));
+        }
+    }
+
+    pub(crate) fn finish(mut self) -> Vec<Element> {
+        self.flush();
+        self.elements
+    }
+}
@@ -0,0 +1,512 @@
+//! Man page of a document, the roff source of the `man` macros that `man` and `groff -man`
+//! format
+//!
+//! A first header of the form `name(section)`, optionally followed by a dash and a short
+//! description as in `shiva(1) -- document converter`, gives the `.TH` title line and the
+//! NAME section. The title line of the other documents takes their title in section 1.
+//! Headers of the shallowest level left are `.SH` sections, the deeper ones `.SS`
+//! subsections. Paragraphs are `.PP`, list items `.TP` tagged paragraphs with a bullet or
+//! their number, code blocks `.EX` examples and tables `tbl` tables, the page starting with
+//! the `'\" t` line that has `man` run `tbl`. Strong text and code are bold, emphasized
+//! text italic. Links are written as their text followed by their URL and footnotes are
+//! numbered notes of a NOTES section at the end. Man pages have no images, they are left
+//! out. Man pages are generated only.
+
+use bytes::Bytes;
+use log::warn;
+
+use crate::core::{
+    Band, ContainerKind, Document, DocumentType, Element, ListItem, NumberingStyle, TableHeader,
+    TableRow, TextStyle,
+};
+
+pub struct Transformer;
+
+/// Text of a roff line, with the backslashes and minus signs escaped and on one line
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str("\\e"),
+            // a minus sign rather than a hyphen, options are copied from man pages
+            '-' => escaped.push_str("\\-"),
+            '\n' | '\r' | '\t' => escaped.push(' '),
+            c if c.is_control() => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Argument of a request, quoted
+fn argument(text: &str) -> String {
+    format!("\"{}\"", escape(text.trim()).replace('"', "\\(dq"))
+}
+
+/// Text lines of filled text, a line starting with a control character being made text
+/// with a zero-width character. Leading spaces, which break the line, are removed.
+fn text_lines(content: &str) -> String {
+    content
+        .split('\n')
+        .map(|line| match line.trim_start() {
+            ".br" => ".br".to_string(),
+            line if line.starts_with(['.', '\'']) => format!("\\&{line}"),
+            line => line.to_string(),
+        })
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Lines of preformatted text, such as code
+fn literal_lines(text: &str) -> String {
+    text.trim_end_matches('\n')
+        .split('\n')
+        .map(|line| {
+            let line = escape(line.trim_end_matches('\r'));
+            if line.starts_with(['.', '\'']) {
+                format!("\\&{line}")
+            } else {
+                line
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Font escape of the style of the text
+fn font(bold: bool, italic: bool) -> &'static str {
+    match (bold, italic) {
+        (true, true) => "\\f(BI",
+        (true, false) => "\\fB",
+        (false, true) => "\\fI",
+        (false, false) => "\\fR",
+    }
+}
+
+/// Name, section and description of a `name(section) -- description` header
+fn page_title(text: &str) -> Option<(&str, &str, &str)> {
+    let (name, rest) = text.trim().split_once('(')?;
+    let (section, description) = rest.split_once(')')?;
+    let name = name.trim();
+    if name.is_empty()
+        || name.contains(char::is_whitespace)
+        || section.is_empty()
+        || !section.chars().all(|c| c.is_ascii_alphanumeric())
+    {
+        return None;
+    }
+    let description = description
+        .trim_start()
+        .trim_start_matches(['-', '—', '–', ':'])
+        .trim();
+    Some((name, section, description))
+}
+
+struct Writer {
+    /// Level of the headers written as `.SH` sections, the deeper ones being `.SS`
+    section_level: u8,
+    /// Text is being written bold
+    bold: bool,
+    /// Text is being written italic
+    italic: bool,
+    /// Notes of the footnotes written so far, listed at the end of the page
+    footnotes: Vec<String>,
+    /// A table was written, `tbl` is needed
+    tables: bool,
+}
+
+impl Writer {
+    fn blocks<'e>(
+        &mut self,
+        elements: impl IntoIterator<Item = &'e Element>,
+    ) -> anyhow::Result<String> {
+        let mut parts = vec![];
+        for element in elements {
+            let roff = self.block(element)?;
+            if !roff.is_empty() {
+                parts.push(roff);
+            }
+        }
+        Ok(parts.join("\n"))
+    }
+
+    /// Blocks indented relative to the text around
+    fn indented<'e>(
+        &mut self,
+        elements: impl IntoIterator<Item = &'e Element>,
+    ) -> anyhow::Result<String> {
+        let roff = self.blocks(elements)?;
+        Ok(if roff.is_empty() {
+            roff
+        } else {
+            format!(".RS\n{roff}\n.RE")
+        })
+    }
+
+    fn block(&mut self, element: &Element) -> anyhow::Result<String> {
+        if let Some((kind, title, blocks)) = element.admonition_parts() {
+            let title = title.unwrap_or_else(|| {
+                let mut chars = kind.chars();
+                chars
+                    .next()
+                    .map(|first| first.to_uppercase().chain(chars).collect())
+                    .unwrap_or_default()
+            });
+            let body = self.indented(blocks)?;
+            return Ok(format!(".PP\n\\fB{}\\fR\n{body}", escape(title.trim())));
+        }
+        Ok(match element {
+            Element::Header { level, text } if *level <= self.section_level => {
+                format!(".SH {}", argument(text))
+            }
+            Element::Header { text, .. } => format!(".SS {}", argument(text)),
+            Element::Attributed { element, .. } => self.block(element)?,
+            Element::Paragraph { elements, .. } => self.paragraph(elements)?,
+            Element::Table { headers, rows } => self.table(headers, rows)?,
+            Element::List {
+                elements,
+                numbered,
+                start,
+                numbering,
+            } => self.list(elements, *numbered, *start, numbering)?,
+            Element::CodeBlock { code, .. } => {
+                format!(".IP\n.EX\n{}\n.EE", literal_lines(code))
+            }
+            Element::Math { tex, display: true } => {
+                format!(".IP\n.EX\n{}\n.EE", literal_lines(tex.trim()))
+            }
+            Element::Container {
+                kind: ContainerKind::Aside,
+                elements,
+            } => self.indented(elements)?,
+            Element::Container { elements, .. } => self.blocks(elements)?,
+            Element::Raw {
+                format: DocumentType::Man,
+                content,
+            } => content.trim_end().to_string(),
+            Element::Image(image) => {
+                warn!("Image left out of the man page: {}", image.title());
+                String::new()
+            }
+            Element::Raw { .. }
+            | Element::Drawing { .. }
+            | Element::TableOfContents { .. }
+            | Element::LineBreak => String::new(),
+            element => self.paragraph(std::slice::from_ref(element))?,
+        })
+    }
+
+    fn paragraph(&mut self, elements: &[Element]) -> anyhow::Result<String> {
+        let content = text_lines(&self.inlines(elements)?);
+        Ok(if content.is_empty() {
+            String::new()
+        } else {
+            format!(".PP\n{content}")
+        })
+    }
+
+    fn inlines(&mut self, elements: &[Element]) -> anyhow::Result<String> {
+        let mut content = String::new();
+        for element in elements {
+            content.push_str(&self.inline(element)?);
+        }
+        Ok(content)
+    }
+
+    /// Inline elements in a font, the font around being restored after them
+    fn styled(&mut self, bold: bool, italic: bool, elements: &[Element]) -> anyhow::Result<String> {
+        let around = (self.bold, self.italic);
+        (self.bold, self.italic) = (self.bold || bold, self.italic || italic);
+        let content = self.inlines(elements);
+        let open = font(self.bold, self.italic);
+        (self.bold, self.italic) = around;
+        Ok(format!("{open}{}{}", content?, font(around.0, around.1)))
+    }
+
+    fn inline(&mut self, element: &Element) -> anyhow::Result<String> {
+        Ok(match element {
+            Element::Text { text, .. } => escape(text),
+            Element::Header { text, .. } => self.styled(
+                true,
+                false,
+                &[Element::Text {
+                    text: text.trim().to_string(),
+                    size: 0,
+                }],
+            )?,
+            Element::Paragraph { elements, .. } | Element::Container { elements, .. } => {
+                self.inlines(elements)?
+            }
+            Element::Styled { style, elements } => match style {
+                TextStyle::Strong => self.styled(true, false, elements)?,
+                TextStyle::Emphasis => self.styled(false, true, elements)?,
+                TextStyle::Strikethrough => self.inlines(elements)?,
+            },
+            Element::Hyperlink { elements, url, .. } => {
+                let content = self.inlines(elements)?;
+                let address = url.strip_prefix("mailto:").unwrap_or(url);
+                if url.starts_with('#') {
+                    content
+                } else if content.trim().is_empty() || elements_text(elements) == address {
+                    escape(address)
+                } else {
+                    format!("{content} <{}>", escape(address))
+                }
+            }
+            Element::InlineCode { text } => self.styled(
+                true,
+                false,
+                &[Element::Text {
+                    text: text.clone(),
+                    size: 0,
+                }],
+            )?,
+            Element::CodeBlock { code, .. } => escape(code.trim()),
+            Element::Math { tex, .. } => escape(tex.trim()),
+            Element::LineBreak => "\n.br\n".to_string(),
+            Element::Footnote { elements } => {
+                let note = text_lines(&self.inlines(elements)?);
+                self.footnotes.push(note);
+                format!("[{}]", self.footnotes.len())
+            }
+            Element::Attributed { element, .. } => self.inline(element)?,
+            Element::Media { .. } => match element.media_link() {
+                Some(link) => self.inline(&link)?,
+                None => String::new(),
+            },
+            Element::List { elements, .. } => {
+                let mut content = String::new();
+                for item in elements {
+                    content.push_str(&self.inline(&item.element)?);
+                    content.push(' ');
+                }
+                content
+            }
+            _ => String::new(),
+        })
+    }
+
+    /// `tbl` table, bold header row above lines of rules, long cells filled
+    fn table(&mut self, headers: &[TableHeader], rows: &[TableRow]) -> anyhow::Result<String> {
+        let columns = headers
+            .len()
+            .max(rows.iter().map(|row| row.cells.len()).max().unwrap_or(0));
+        if columns == 0 {
+            return Ok(String::new());
+        }
+        self.tables = true;
+        let mut lines = vec![".TS".to_string(), "allbox;".to_string()];
+        if !headers.is_empty() {
+            lines.push(vec!["lb"; columns].join(" "));
+        }
+        lines.push(format!("{}.", vec!["l"; columns].join(" ")));
+        if !headers.is_empty() {
+            let cells: Vec<&Element> = headers.iter().map(|header| &header.element).collect();
+            lines.push(self.row(&cells, columns)?);
+        }
+        for row in rows {
+            let cells: Vec<&Element> = row.cells.iter().map(|cell| &cell.element).collect();
+            lines.push(self.row(&cells, columns)?);
+        }
+        lines.push(".TE".to_string());
+        Ok(lines.join("\n"))
+    }
+
+    /// Cells of a row separated by tabs, a row shorter than the table being completed with
+    /// empty cells
+    fn row(&mut self, cells: &[&Element], columns: usize) -> anyhow::Result<String> {
+        let mut row = vec![];
+        for cell in cells {
+            let content = self.inline(cell)?.replace("\n.br\n", " ");
+            let content = content.trim();
+            // text blocks of long cells are filled to the width of the column
+            if content.chars().count() > 30 {
+                row.push(format!("T{{\n{}\nT}}", text_lines(content)));
+            } else if content.starts_with(['.', '\'']) {
+                row.push(format!("\\&{content}"));
+            } else {
+                row.push(content.to_string());
+            }
+        }
+        row.resize(columns.max(row.len()), String::new());
+        Ok(row.join("\t"))
+    }
+
+    /// `.TP` paragraphs of the items tagged with a bullet or their number, the lists,
+    /// tables and code after an item indented under it
+    fn list(
+        &mut self,
+        elements: &[ListItem],
+        numbered: bool,
+        start: usize,
+        numbering: &NumberingStyle,
+    ) -> anyhow::Result<String> {
+        let mut items: Vec<Vec<&Element>> = vec![];
+        for item in elements {
+            let element = &item.element;
+            match items.last_mut() {
+                Some(blocks)
+                    if matches!(
+                        element,
+                        Element::List { .. } | Element::CodeBlock { .. } | Element::Table { .. }
+                    ) =>
+                {
+                    blocks.push(element)
+                }
+                _ => items.push(vec![element]),
+            }
+        }
+        let mut parts = vec![];
+        for (index, blocks) in items.into_iter().enumerate() {
+            let Some((first, nested)) = blocks.split_first() else {
+                continue;
+            };
+            let tag = if numbered {
+                format!("{}.", numbering.format(start + index))
+            } else {
+                "\\(bu".to_string()
+            };
+            match first {
+                Element::List { .. } | Element::CodeBlock { .. } | Element::Table { .. } => {
+                    parts.push(self.indented(blocks.iter().copied())?);
+                }
+                element => {
+                    let content = text_lines(&self.inline(element)?);
+                    let indent = if numbered { 4 } else { 2 };
+                    parts.push(format!(".TP {indent}\n{tag}\n{content}"));
+                    let nested = self.indented(nested.iter().copied())?;
+                    if !nested.is_empty() {
+                        parts.push(nested);
+                    }
+                }
+            }
+        }
+        Ok(parts.join("\n"))
+    }
+}
+
+/// Plain text of inline elements
+fn elements_text(elements: &[Element]) -> String {
+    elements.iter().map(Element::plain_text).collect()
+}
+
+impl Transformer {
+    pub fn generate(document: &Document) -> crate::Result<Bytes> {
+        Transformer::generate_page(document).map_err(crate::Error::generation_error)
+    }
+
+    fn generate_page(document: &Document) -> anyhow::Result<Bytes> {
+        let document = &document.resolve_fields();
+        let mut elements: Vec<&Element> = document
+            .bands
+            .iter()
+            .filter(|band| !matches!(band, Band::PageHeader(_) | Band::PageFooter(_)))
+            .flat_map(|band| band.elements())
+            .collect();
+
+        let mut lines = vec![];
+        let title = match elements.first().copied() {
+            Some(Element::Header { level: 1, text }) => page_title(text),
+            _ => None,
+        };
+        match title {
+            Some((name, section, description)) => {
+                lines.push(format!(
+                    ".TH {} {}",
+                    argument(&name.to_uppercase()),
+                    argument(section)
+                ));
+                if !description.is_empty() {
+                    lines.push(format!(
+                        ".SH NAME\n{} \\- {}",
+                        escape(name),
+                        escape(description)
+                    ));
+                }
+                elements.remove(0);
+            }
+            None => lines.push(format!(
+                ".TH {} {}",
+                argument(&document.get_title_text().to_uppercase()),
+                argument("1")
+            )),
+        }
+
+        fn min_level(element: &Element) -> u8 {
+            let level = match element {
+                Element::Header { level, .. } => *level,
+                _ => u8::MAX,
+            };
+            element
+                .children()
+                .into_iter()
+                .map(min_level)
+                .fold(level, u8::min)
+        }
+        let section_level = elements.iter().copied().map(min_level).min();
+        let mut writer = Writer {
+            section_level: section_level.unwrap_or(1),
+            bold: false,
+            italic: false,
+            footnotes: vec![],
+            tables: false,
+        };
+        let body = writer.blocks(elements.iter().copied())?;
+        if !body.is_empty() {
+            lines.push(body);
+        }
+        if !writer.footnotes.is_empty() {
+            lines.push(".SH NOTES".to_string());
+            for (index, note) in writer.footnotes.iter().enumerate() {
+                lines.push(format!(".IP [{}] 5\n{note}", index + 1));
+            }
+        }
+        let mut page = lines.join("\n") + "\n";
+        if writer.tables {
+            page.insert_str(0, "'\\\" t\n");
+        }
+        Ok(Bytes::from(page))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::tests::init_logger;
+    use crate::core::TransformerTrait;
+    use crate::markdown;
+
+    #[test]
+    fn test_generate() -> anyhow::Result<()> {
+        init_logger();
+        let markdown = "# shiva(1) -- convert documents\n\n## SYNOPSIS\n\n\
+            `shiva` *input* *output*\n\n## OPTIONS\n\n\
+            - `--format` name: the **output *format***\n- .hidden files\n   1. first\n   2. second\n\n\
+            ## EXAMPLES\n\n```sh\nshiva a.md \\\n  b.pdf\n.dot\n```\n\n\
+            | Name | Description |\n|------|-------------|\n| md | Markdown, the lightweight markup language |\n\n\
+            See [the site](https://example.com) and <https://example.org>.[^1]\n\n\
+            [^1]: A note.\n";
+        let document = markdown::Transformer::parse(&Bytes::from(markdown))?;
+        let roff = Transformer::generate(&document)?;
+        let roff = std::str::from_utf8(&roff)?;
+        assert!(roff.starts_with(
+            "'\\\" t\n.TH \"SHIVA\" \"1\"\n.SH NAME\nshiva \\- convert documents\n.SH \"SYNOPSIS\"\n"
+        ));
+        assert!(roff.contains(".PP\n\\fBshiva\\fR \\fIinput\\fR \\fIoutput\\fR\n"));
+        assert!(roff.contains(
+            ".TP 2\n\\(bu\n\\fB\\-\\-format\\fR name: the \\fBoutput \\f(BIformat\\fB\\fR\n\
+             .TP 2\n\\(bu\n\\&.hidden files\n.RS\n.TP 4\n1.\nfirst\n.TP 4\n2.\nsecond\n.RE\n"
+        ));
+        assert!(roff.contains(".IP\n.EX\nshiva a.md \\e\n  b.pdf\n\\&.dot\n.EE\n"));
+        assert!(roff.contains(
+            ".TS\nallbox;\nlb lb\nl l.\nName\tDescription\n\
+             md\tT{\nMarkdown, the lightweight markup language\nT}\n.TE\n"
+        ));
+        assert!(
+            roff.contains(".PP\nSee the site <https://example.com> and https://example.org.[1]\n")
+        );
+        assert!(roff.ends_with(".SH NOTES\n.IP [1] 5\nA note.\n"));
+        Ok(())
+    }
+}
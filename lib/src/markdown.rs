@@ -1,21 +1,38 @@
-use crate::core::Element::{Header, Hyperlink, List, Table, Text};
+use crate::core::Element::{Hyperlink, Table, Text};
 use crate::core::*;
+use crate::error::image_loader_errors;
 use bytes::Bytes;
 use comrak::arena_tree::Node;
 use comrak::Arena;
-use pulldown_cmark::{Event, HeadingLevel, Options, Parser, Tag, TagEnd, TextMergeStream};
+use pulldown_cmark::{
+    CodeBlockKind, Event, HeadingLevel, MetadataBlockKind, Options, Parser, Tag, TagEnd,
+    TextMergeStream,
+};
 use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
 
 pub struct Transformer;
 
 impl TransformerTrait for Transformer {
-    fn parse(document: &Bytes) -> anyhow::Result<Document> {
+    fn parse(document: &Bytes) -> crate::Result<Document> {
         Transformer::parse_with_loader(document, disk_image_loader("."))
     }
 
-    fn generate(document: &Document) -> anyhow::Result<Bytes> {
+    fn parse_with_options(document: &Bytes, options: &ParseOptions) -> crate::Result<Document> {
+        Transformer::parse_with_loader_and_options(document, disk_image_loader("."), options)
+    }
+
+    fn generate(document: &Document) -> crate::Result<Bytes> {
         Transformer::generate_with_saver(document, disk_image_saver("."))
     }
+
+    fn generate_with_options(
+        document: &Document,
+        options: &GenerateOptions,
+    ) -> crate::Result<Bytes> {
+        Transformer::generate_with_image_saver(document, disk_image_saver("."), &options.markdown)
+            .map_err(crate::Error::generation_error)
+    }
 }
 
 struct ImageSaver<F>
@@ -23,113 +40,299 @@ where
     F: Fn(&Bytes, &str) -> anyhow::Result<()>,
 {
     pub function: F,
+    /// Images embedded as data URIs instead of being saved
+    pub embed: bool,
 }
 impl TransformerWithImageLoaderSaverTrait for Transformer {
-    fn parse_with_loader<F>(document: &Bytes, image_loader: F) -> anyhow::Result<Document>
+    fn parse_with_loader<F>(document: &Bytes, image_loader: F) -> crate::Result<Document>
     where
         F: Fn(&str) -> anyhow::Result<Bytes>,
-        Self: Sized,
     {
-        fn create_element_list(children: Option<Vec<ListItem>>, numbered: bool) -> Element {
-            Element::List {
-                elements: children.unwrap_or(vec![]),
-                numbered,
-            }
-        }
-
-        fn process_element_creation(
-            current_element: &mut Option<Element>,
-            mut new_el: Element,
-            list_depth: &mut i32,
-        ) {
-            match current_element.as_mut() {
-                Some(element) => match element {
-                    Element::List { elements, numbered } => {
-                        let mut list_elements = elements;
-
-                        for _ in 1..*list_depth {
-                            let last_index = list_elements.len() - 1;
-                            if let Element::List {
-                                elements: ref mut inner_els,
-                                ..
-                            } = list_elements[last_index].element
-                            {
-                                list_elements = inner_els;
-                            } else {
-                                panic!("Expected a nested list structure at the specified depth");
-                            }
-                        }
-                        match &new_el {
-                            Element::Hyperlink { .. } | Element::Header { .. } => {
-                                if let Some(ListItem { element }) = list_elements.last() {
-                                    if let Text { .. } = element {
-                                        list_elements.pop();
-                                    }
-                                }
-                            }
+        Transformer::parse_with_loader_and_options(document, image_loader, &ParseOptions::default())
+    }
 
-                            _ => {}
-                        }
+    fn parse_with_loader_and_options<F>(
+        document: &Bytes,
+        image_loader: F,
+        options: &ParseOptions,
+    ) -> crate::Result<Document>
+    where
+        F: Fn(&str) -> anyhow::Result<Bytes>,
+    {
+        Transformer::parse_with_wiki_link_resolver(document, image_loader, wiki_link_url, options)
+    }
 
-                        if matches!(new_el, Element::List { .. }) {
-                            let list_item_children = ListItem {
-                                element: create_element_list(None, *numbered),
-                            };
+    fn generate_with_saver<F>(document: &Document, image_saver: F) -> crate::Result<Bytes>
+    where
+        F: Fn(&Bytes, &str) -> anyhow::Result<()>,
+    {
+        Transformer::generate_with_image_saver(document, image_saver, &MarkdownOptions::default())
+            .map_err(crate::Error::generation_error)
+    }
+}
 
-                            if let Element::List {
-                                ref mut elements, ..
-                            } = new_el
-                            {
-                                let list_item_el = list_elements
-                                    .pop()
-                                    .expect("should have a list item as last element");
-                                elements.push(list_item_el);
-                                elements.push(list_item_children);
-                                *list_depth += 1;
-                            }
-                        }
+impl Transformer {
+    /// Parses the markdown with the wiki links of the [`MarkdownFlavor::Obsidian`] flavor
+    /// resolved by `resolver`
+    ///
+    /// The resolver maps the target of `[[Page Name#Heading]]` or `![[image.png]]` to the URL
+    /// of the link or the image, the URL of an embedded image is then read by the image
+    /// loader. [`wiki_link_url`] is the resolver of the other parse functions.
+    pub fn parse_with_wiki_link_resolver<F, R>(
+        document: &Bytes,
+        image_loader: F,
+        resolver: R,
+        options: &ParseOptions,
+    ) -> crate::Result<Document>
+    where
+        F: Fn(&str) -> anyhow::Result<Bytes>,
+        R: Fn(&str) -> String,
+    {
+        let image_loader = image_loader_errors(data_uri_image_loader(image_loader));
+        let mut document =
+            Transformer::parse_with_image_loader(document, &image_loader, options.markdown.flavor)
+                .map_err(crate::Error::parse_error)?;
+        if options.markdown.emoji {
+            for band in document.bands.iter_mut() {
+                band.elements_mut()
+                    .iter_mut()
+                    .for_each(|element| map_text(element, &crate::emoji::expand_shortcodes));
+            }
+        }
+        if options.markdown.sanitize {
+            crate::html::sanitize_raw_html(&mut document);
+        }
+        let flavor = options.markdown.flavor;
+        for band in document.bands.iter_mut() {
+            for element in band.elements_mut() {
+                if flavor != MarkdownFlavor::CommonMark {
+                    split_text(element, &|text, size| {
+                        split_sized_images(text, size, &image_loader)
+                    })
+                    .map_err(crate::Error::parse_error)?;
+                }
+                if flavor == MarkdownFlavor::Obsidian {
+                    split_text(element, &|text, size| {
+                        split_wiki_links(text, size, &resolver, &image_loader)
+                    })
+                    .map_err(crate::Error::parse_error)?;
+                }
+            }
+        }
+        Ok(if options.autolink {
+            document.link_urls()
+        } else {
+            document
+        })
+    }
 
-                        let li = ListItem { element: new_el };
-                        list_elements.push(li);
+    fn parse_with_image_loader<F>(
+        document: &Bytes,
+        image_loader: F,
+        flavor: MarkdownFlavor,
+    ) -> anyhow::Result<Document>
+    where
+        F: Fn(&str) -> anyhow::Result<Bytes>,
+    {
+        /// Children of the innermost open link or styled span of the paragraph, the paragraph
+        /// elements when none is open
+        fn inline_elements(
+            current_element: &mut Option<Element>,
+            depth: usize,
+        ) -> Option<&mut Vec<Element>> {
+            fn innermost(elements: &mut Vec<Element>, depth: usize) -> Option<&mut Vec<Element>> {
+                if depth == 0 {
+                    return Some(elements);
+                }
+                match elements.last_mut() {
+                    Some(Hyperlink { elements, .. } | Element::Styled { elements, .. }) => {
+                        innermost(elements, depth - 1)
                     }
-                    _ => {}
-                },
-                None => {
-                    *current_element = Some(new_el);
+                    _ => None,
                 }
             }
+            match current_element.as_mut() {
+                Some(Element::Paragraph { elements, .. }) => innermost(elements, depth),
+                _ => None,
+            }
         }
 
         let document_str = std::str::from_utf8(document)?;
         let mut doc_elements: Vec<Element> = Vec::new();
 
-        let mut options = Options::empty();
-        options.insert(Options::ENABLE_TABLES);
-        options.insert(Options::ENABLE_STRIKETHROUGH);
-        options.insert(Options::ENABLE_SMART_PUNCTUATION);
-        options.insert(Options::ENABLE_MATH);
-        options.insert(Options::ENABLE_GFM);
-
-        let parser = Parser::new_ext(document_str, options);
+        let parser = Parser::new_ext(document_str, parser_options(flavor));
         let md_iterator = TextMergeStream::new(parser);
 
         let mut current_element: Option<Element> = None;
-        let mut list_depth = 0;
+        // lists being parsed, the innermost last
+        let mut lists: Vec<OpenList> = Vec::new();
         let mut table_element: Option<(bool, Element)> = None;
+        // a soft break joins the following text to the previous one
+        let mut after_soft_break = false;
+        // links and styled spans open in the paragraph, the inline content goes to the innermost
+        let mut inline_depth = 0;
+        let mut in_link_image = false;
+        // `{#id .class key=value}` of the heading being parsed
+        let mut heading_attributes: HashMap<String, String> = HashMap::new();
+        let mut metadata = Metadata::default();
+        let mut frontmatter: Option<(MetadataBlockKind, String)> = None;
+        // labels of the footnote references in the order of the text, the content of the
+        // definitions, and the label of the current definition with the index of its first
+        // element
+        let mut footnote_labels: VecDeque<String> = VecDeque::new();
+        let mut footnote_definitions: HashMap<String, Vec<Element>> = HashMap::new();
+        let mut definition: Option<(String, usize)> = None;
+        // info string and code of the current code block
+        let mut code_block: Option<(String, String)> = None;
+        // lines of the current HTML block
+        let mut html_block: Option<String> = None;
         for event in md_iterator {
+            // the content of a tight list item is not in a paragraph, it gets one so that
+            // items hold the same inline elements as paragraphs
+            if !lists.is_empty()
+                && current_element.is_none()
+                && table_element.is_none()
+                && code_block.is_none()
+                && html_block.is_none()
+                && is_inline(&event)
+            {
+                current_element = Some(Element::Paragraph {
+                    elements: vec![],
+                    direction: TextDirection::default(),
+                });
+            }
+            // the task marker is kept as the text that starts the item, headers hold plain text
+            // so their code is kept as text
+            let event = match event {
+                Event::TaskListMarker(checked) => {
+                    Event::Text(if checked { "[x] " } else { "[ ] " }.into())
+                }
+                Event::Code(code)
+                    if !matches!(current_element, Some(Element::Paragraph { .. })) =>
+                {
+                    Event::Text(code)
+                }
+                event => event,
+            };
             match event {
+                Event::Start(Tag::MetadataBlock(kind)) => frontmatter = Some((kind, String::new())),
+                Event::Text(text) if frontmatter.is_some() => {
+                    if let Some((_, source)) = frontmatter.as_mut() {
+                        source.push_str(&text);
+                    }
+                }
+                Event::End(TagEnd::MetadataBlock(_)) => {
+                    if let Some((kind, source)) = frontmatter.take() {
+                        metadata = parse_frontmatter(kind, &source)?;
+                    }
+                }
+                Event::Start(Tag::CodeBlock(kind)) => {
+                    end_item_text(&mut current_element, &mut lists);
+                    let info = match kind {
+                        CodeBlockKind::Fenced(info) => info.trim().to_string(),
+                        CodeBlockKind::Indented => String::new(),
+                    };
+                    code_block = Some((info, String::new()));
+                }
+                Event::Text(text) if code_block.is_some() => {
+                    if let Some((_, code)) = code_block.as_mut() {
+                        code.push_str(&text);
+                    }
+                }
+                Event::End(TagEnd::CodeBlock) => {
+                    if let Some((info, code)) = code_block.take() {
+                        let code_block = Element::CodeBlock {
+                            info,
+                            code: code.trim_end_matches('\n').to_string(),
+                        };
+                        push_block(code_block, &mut lists, &mut doc_elements);
+                    }
+                }
+                Event::Start(Tag::HtmlBlock) => {
+                    end_item_text(&mut current_element, &mut lists);
+                    html_block = Some(String::new());
+                }
+                Event::Html(html) => {
+                    if let Some(content) = html_block.as_mut() {
+                        content.push_str(&html);
+                    }
+                }
+                Event::End(TagEnd::HtmlBlock) => {
+                    // the generator separates two lists in a row with an end list comment
+                    if let Some(content) = html_block
+                        .take()
+                        .filter(|content| content.trim() != "<!-- end list -->")
+                    {
+                        let raw = Element::Raw {
+                            format: DocumentType::HTML,
+                            content: content.trim_end_matches('\n').to_string(),
+                        };
+                        push_block(raw, &mut lists, &mut doc_elements);
+                    }
+                }
+                Event::InlineHtml(html) => {
+                    after_soft_break = false;
+                    let raw = Element::Raw {
+                        format: DocumentType::HTML,
+                        content: html.to_string(),
+                    };
+                    if let Some(elements) = inline_elements(&mut current_element, inline_depth) {
+                        elements.push(raw);
+                    }
+                }
+                Event::Start(Tag::FootnoteDefinition(label)) => {
+                    definition = Some((label.to_string(), doc_elements.len()));
+                }
+                Event::End(TagEnd::FootnoteDefinition) => {
+                    if let Some((label, from)) = definition.take() {
+                        let content = doc_elements.drain(from..).collect();
+                        footnote_definitions.insert(label, content);
+                    }
+                }
+                Event::Code(code) => {
+                    after_soft_break = false;
+                    let code = Element::InlineCode {
+                        text: code.to_string(),
+                    };
+                    if let Some(elements) = inline_elements(&mut current_element, inline_depth) {
+                        elements.push(code);
+                    }
+                }
+                Event::FootnoteReference(label) => {
+                    if let Some(elements) = inline_elements(&mut current_element, inline_depth) {
+                        elements.push(Element::Footnote { elements: vec![] });
+                        footnote_labels.push_back(label.to_string());
+                    }
+                }
                 Event::Start(tag) => {
                     match tag {
                         Tag::Paragraph => {
-                            if !matches!(current_element, Some(Element::List { .. })) {
-                                process_element_creation(
-                                    &mut current_element,
-                                    Element::Paragraph { elements: vec![] },
-                                    &mut list_depth,
-                                );
-                            }
+                            end_item_text(&mut current_element, &mut lists);
+                            current_element = Some(Element::Paragraph {
+                                elements: vec![],
+                                direction: TextDirection::default(),
+                            });
                         }
-                        Tag::Heading { level, .. } => {
+                        Tag::Heading {
+                            level,
+                            id,
+                            classes,
+                            attrs,
+                        } => {
+                            heading_attributes = attrs
+                                .into_iter()
+                                .map(|(key, value)| {
+                                    (
+                                        key.to_string(),
+                                        value.map(|value| value.to_string()).unwrap_or_default(),
+                                    )
+                                })
+                                .collect();
+                            heading_attributes
+                                .extend(id.map(|id| ("id".to_string(), id.to_string())));
+                            if !classes.is_empty() {
+                                heading_attributes.insert("class".to_string(), classes.join(" "));
+                            }
                             let level = match level {
                                 HeadingLevel::H1 => 1,
                                 HeadingLevel::H2 => 2,
@@ -138,44 +341,26 @@ impl TransformerWithImageLoaderSaverTrait for Transformer {
                                 HeadingLevel::H5 => 5,
                                 HeadingLevel::H6 => 6,
                             };
-                            process_element_creation(
-                                &mut current_element,
-                                Element::Header {
-                                    level,
-                                    text: "".to_string(),
-                                },
-                                &mut list_depth,
-                            );
-                        }
-                        Tag::List(numbered) => {
-                            let numbered = numbered.is_some();
-
-                            let list_el = List {
-                                elements: vec![],
-                                numbered,
-                            };
-
-                            process_element_creation(
-                                &mut current_element,
-                                list_el,
-                                &mut list_depth,
-                            );
-
-                            list_depth += 1;
-                        }
-                        Tag::Item => {
-                            let list_li = Text {
+                            end_item_text(&mut current_element, &mut lists);
+                            current_element = Some(Element::Header {
+                                level,
                                 text: "".to_string(),
-                                size: 14,
-                            };
-
-                            process_element_creation(
-                                &mut current_element,
-                                list_li,
-                                &mut list_depth,
-                            );
+                            });
+                        }
+                        Tag::List(first_number) => {
+                            end_item_text(&mut current_element, &mut lists);
+                            lists.push(OpenList {
+                                list: Element::List {
+                                    elements: vec![],
+                                    numbered: first_number.is_some(),
+                                    start: first_number.unwrap_or(1) as usize,
+                                    numbering: NumberingStyle::default(),
+                                },
+                                item: vec![],
+                            });
                         }
                         Tag::Table(_) => {
+                            end_item_text(&mut current_element, &mut lists);
                             let table_el = Table {
                                 headers: vec![],
                                 rows: vec![],
@@ -188,6 +373,18 @@ impl TransformerWithImageLoaderSaverTrait for Transformer {
                                 table.0 = true;
                             }
                         }
+                        Tag::TableRow => {
+                            if let Some((_, Table { rows, .. })) = table_element.as_mut() {
+                                rows.push(TableRow { cells: vec![] });
+                            }
+                        }
+                        // the inline content of the cell is parsed as a paragraph
+                        Tag::TableCell => {
+                            current_element = Some(Element::Paragraph {
+                                elements: vec![],
+                                direction: TextDirection::default(),
+                            });
+                        }
                         Tag::Image {
                             dest_url, title, ..
                         } => {
@@ -201,24 +398,54 @@ impl TransformerWithImageLoaderSaverTrait for Transformer {
                                 "".to_string(),
                                 ImageDimension::default(),
                             ));
+                            if inline_depth > 0 {
+                                if let Some(elements) =
+                                    inline_elements(&mut current_element, inline_depth)
+                                {
+                                    elements.push(img_el);
+                                    in_link_image = true;
+                                    continue;
+                                }
+                            }
                             // Before image there is paragraph tag (likely because alt text is in paragraph )
-                            current_element = None;
-                            process_element_creation(&mut current_element, img_el, &mut list_depth);
+                            current_element = Some(img_el);
                         }
                         Tag::Link {
                             dest_url, title, ..
                         } => {
                             let link_element = Hyperlink {
-                                title: title.to_string(),
+                                elements: vec![],
                                 url: dest_url.to_string(),
-                                alt: "alt".to_string(),
+                                alt: title.to_string(),
                                 size: 14,
                             };
-                            process_element_creation(
-                                &mut current_element,
-                                link_element,
-                                &mut list_depth,
-                            );
+                            if let Some(elements) =
+                                inline_elements(&mut current_element, inline_depth)
+                            {
+                                elements.push(link_element);
+                                inline_depth += 1;
+                                continue;
+                            }
+                            if current_element.is_none() {
+                                current_element = Some(link_element);
+                            }
+                        }
+
+                        Tag::Emphasis | Tag::Strong | Tag::Strikethrough => {
+                            let style = match tag {
+                                Tag::Emphasis => TextStyle::Emphasis,
+                                Tag::Strong => TextStyle::Strong,
+                                _ => TextStyle::Strikethrough,
+                            };
+                            if let Some(elements) =
+                                inline_elements(&mut current_element, inline_depth)
+                            {
+                                elements.push(Element::Styled {
+                                    style,
+                                    elements: vec![],
+                                });
+                                inline_depth += 1;
+                            }
                         }
 
                         _rest => {
@@ -227,130 +454,109 @@ impl TransformerWithImageLoaderSaverTrait for Transformer {
                     }
                 }
                 Event::Text(text) => {
+                    let joined = std::mem::take(&mut after_soft_break);
+                    if let Some(elements) = inline_elements(&mut current_element, inline_depth) {
+                        match elements.last_mut() {
+                            Some(Element::Image(image)) if in_link_image => {
+                                image.set_image_alt(&text)
+                            }
+                            Some(Text {
+                                text: last_text, ..
+                            }) if joined => last_text.push_str(&text),
+                            _ => elements.push(Text {
+                                text: text.to_string(),
+                                size: 14,
+                            }),
+                        }
+                        continue;
+                    }
                     if let Some(curr_el) = current_element.as_mut() {
                         match curr_el {
-                            Element::Paragraph { ref mut elements } => {
-                                elements.push(Element::Text {
-                                    text: text.to_string(),
-                                    size: 14,
-                                })
-                            }
                             Element::Header { text: el_text, .. } => {
                                 el_text.push_str(&text);
                             }
-                            Element::List { elements, .. } => {
-                                let mut li_vec_to_insert = elements;
-                                for _ in 1..list_depth {
-                                    let last_index = li_vec_to_insert.len() - 1;
-                                    if let Element::List {
-                                        elements: ref mut inner_els,
-                                        ..
-                                    } = li_vec_to_insert[last_index].element
-                                    {
-                                        li_vec_to_insert = inner_els;
-                                    } else {
-                                        panic!("Expected a nested list structure at the specified depth");
-                                    }
-                                }
-
-                                let li = li_vec_to_insert.last_mut().unwrap();
-
-                                match &mut li.element {
-                                    Text {
-                                        text: element_text, ..
-                                    } => {
-                                        element_text.push_str(&text);
-                                    }
-                                    Hyperlink { title, .. } => {
-                                        *title = text.to_string();
-                                    }
-                                    Header {
-                                        text: header_text, ..
-                                    } => {
-                                        *header_text = text.to_string();
-                                    }
-                                    _ => {}
-                                }
-                            }
                             Element::Image(image) => image.set_image_alt(&text),
-                            Element::Hyperlink { alt, .. } => {
-                                *alt = alt.to_string();
+                            Element::Hyperlink { elements, .. } => {
+                                elements.push(Text {
+                                    text: text.to_string(),
+                                    size: 14,
+                                });
                             }
                             _ => {}
                         }
                     }
-                    match table_element {
-                        Some(ref mut t_el) => {
-                            if let (is_header, Element::Table { headers, rows }) = t_el {
-                                if *is_header {
-                                    headers.push(TableHeader {
-                                        element: Text {
-                                            text: text.to_string(),
-                                            size: 14,
-                                        },
-                                        width: 30.,
-                                    })
-                                } else {
-                                    let last_row = rows.last_mut();
-
-                                    match last_row {
-                                        Some(tr) => {
-                                            if tr.cells.len() == headers.len() {
-                                                rows.push(TableRow {
-                                                    cells: vec![TableCell {
-                                                        element: Text {
-                                                            text: text.to_string(),
-                                                            size: 14,
-                                                        },
-                                                    }],
-                                                });
-                                            } else {
-                                                tr.cells.push(TableCell {
-                                                    element: Text {
-                                                        text: text.to_string(),
-                                                        size: 14,
-                                                    },
-                                                });
-                                            }
-                                        }
-                                        None => {
-                                            rows.push(TableRow {
-                                                cells: vec![TableCell {
-                                                    element: Text {
-                                                        text: text.to_string(),
-                                                        size: 14,
-                                                    },
-                                                }],
-                                            });
-                                        }
-                                    }
-                                }
-                            }
+                }
+                Event::SoftBreak | Event::HardBreak => {
+                    let hard = matches!(event, Event::HardBreak);
+                    if let Some(elements) = inline_elements(&mut current_element, inline_depth) {
+                        if hard {
+                            elements.push(Element::LineBreak);
+                        } else {
+                            push_soft_break(elements);
+                            after_soft_break = true;
                         }
-                        None => {}
+                    } else if let Some(Element::Header { text, .. }) = current_element.as_mut() {
+                        // headers hold plain text, so breaks become spaces
+                        text.push(' ');
                     }
                 }
+                Event::End(TagEnd::Image) if in_link_image => in_link_image = false,
+                Event::End(
+                    TagEnd::Link | TagEnd::Emphasis | TagEnd::Strong | TagEnd::Strikethrough,
+                ) if inline_depth > 0 => inline_depth -= 1,
                 Event::End(tag) => match tag {
-                    TagEnd::Paragraph | TagEnd::Heading(_) | TagEnd::Link | TagEnd::Image => {
-                        if !matches!(current_element, Some(Element::List { .. })) {
-                            let curr_el = current_element.take();
-                            if let Some(curr_el) = curr_el {
-                                match curr_el {
-                                    List { .. } => current_element = Some(curr_el),
-                                    _ => {
-                                        doc_elements.push(curr_el);
-                                    }
-                                }
+                    TagEnd::Paragraph => {
+                        if let Some(element) = current_element.take() {
+                            push_block(element, &mut lists, &mut doc_elements);
+                        }
+                    }
+                    TagEnd::Heading(_) => {
+                        if let Some(element) = current_element.take() {
+                            let attributes = std::mem::take(&mut heading_attributes);
+                            push_block(
+                                element.with_attributes(attributes),
+                                &mut lists,
+                                &mut doc_elements,
+                            );
+                        }
+                    }
+                    // a link or an image outside of a paragraph is a block of its own
+                    TagEnd::Link | TagEnd::Image => {
+                        if matches!(current_element, Some(Hyperlink { .. } | Element::Image(_))) {
+                            if let Some(element) = current_element.take() {
+                                push_block(element, &mut lists, &mut doc_elements);
                             }
                         }
                     }
+                    TagEnd::Item => {
+                        end_item_text(&mut current_element, &mut lists);
+                        if let Some(open) = lists.last_mut() {
+                            open.close_item();
+                        }
+                    }
                     TagEnd::List(_) => {
-                        list_depth -= 2;
-                        if list_depth <= 0 {
-                            list_depth = 0;
-                            let curr_el = current_element.take();
-                            if let Some(curr_el) = curr_el {
-                                doc_elements.push(curr_el);
+                        if let Some(open) = lists.pop() {
+                            push_block(open.list, &mut lists, &mut doc_elements);
+                        }
+                    }
+                    TagEnd::Table => {
+                        if let Some((_, t_el)) = table_element.take() {
+                            push_block(t_el, &mut lists, &mut doc_elements);
+                        }
+                    }
+                    TagEnd::TableCell => {
+                        let element = block_element(current_element.take().unwrap_or(Text {
+                            text: "".to_string(),
+                            size: 14,
+                        }));
+                        if let Some((is_header, Table { headers, rows })) = table_element.as_mut() {
+                            if *is_header {
+                                headers.push(TableHeader {
+                                    element,
+                                    width: 30.,
+                                });
+                            } else if let Some(row) = rows.last_mut() {
+                                row.cells.push(TableCell { element });
                             }
                         }
                     }
@@ -359,11 +565,6 @@ impl TransformerWithImageLoaderSaverTrait for Transformer {
                             *is_header = false;
                         }
                     }
-                    TagEnd::Table => {
-                        if let Some((_, t_el)) = table_element.take() {
-                            doc_elements.push(t_el);
-                        }
-                    }
                     _ => {}
                 },
 
@@ -371,15 +572,38 @@ impl TransformerWithImageLoaderSaverTrait for Transformer {
             }
         }
 
-        Ok(Document::new(doc_elements))
+        for element in doc_elements.iter_mut() {
+            resolve_footnotes(element, &mut footnote_labels, &footnote_definitions);
+        }
+
+        let mut document = Document::new(doc_elements);
+        document.metadata = metadata;
+        Ok(document)
     }
 
-    fn generate_with_saver<F>(document: &Document, image_saver: F) -> anyhow::Result<Bytes>
+    fn generate_with_image_saver<F>(
+        document: &Document,
+        image_saver: F,
+        options: &MarkdownOptions,
+    ) -> anyhow::Result<Bytes>
     where
         F: Fn(&Bytes, &str) -> anyhow::Result<()>,
     {
         use comrak::nodes::LineColumn;
-        use comrak::{format_commonmark, Arena, Options};
+        use comrak::{format_commonmark, Arena, ListStyleType, Options};
+
+        let list_style = match options.bullet {
+            '-' => ListStyleType::Dash,
+            '+' => ListStyleType::Plus,
+            '*' => ListStyleType::Star,
+            bullet => {
+                return Err(crate::Error::GenerationError(format!(
+                    "Unsupported bullet '{}', expected '-', '+' or '*'",
+                    bullet
+                ))
+                .into())
+            }
+        };
         use std::cell::RefCell;
 
         let arena = Arena::new();
@@ -393,27 +617,883 @@ impl TransformerWithImageLoaderSaverTrait for Transformer {
 
         let image_saver = ImageSaver {
             function: &image_saver,
+            embed: options.embed_images,
         };
 
+        let resolved = document
+            .resolve_fields()
+            .without_containers()
+            .without_media()
+            .without_math()
+            .without_drawings();
+        let document = &resolved.without_attributes();
         let all_elements: Vec<&Element> = document.get_all_elements();
 
-        for element in all_elements {
-            let node = element_to_ast_node(&arena, element, &image_num, &image_saver)?;
+        for (element, attributed) in all_elements.into_iter().zip(resolved.get_all_elements()) {
+            let node = match element {
+                Element::TableOfContents { max_level } => element_to_ast_node(
+                    &arena,
+                    &document.build_toc(*max_level),
+                    &image_num,
+                    &image_saver,
+                )?,
+                Element::Raw {
+                    format: DocumentType::HTML | DocumentType::Markdown,
+                    content,
+                } => arena.alloc(Node::new(RefCell::new(Ast::new(
+                    NodeValue::HtmlBlock(NodeHtmlBlock {
+                        block_type: 0,
+                        literal: format!("{}\n", content),
+                    }),
+                    LineColumn { line: 0, column: 0 },
+                )))),
+                _ => element_to_ast_node(
+                    &arena,
+                    &text_to_paragraph(element.clone()),
+                    &image_num,
+                    &image_saver,
+                )?,
+            };
+            if let Element::Attributed { attributes, .. } = attributed {
+                if options.flavor == MarkdownFlavor::Pandoc {
+                    if let Some(attributes) = heading_attributes(attributes) {
+                        if matches!(node.data.borrow().value, NodeValue::Heading(_)) {
+                            node.append(arena.alloc(Node::new(RefCell::new(Ast::new(
+                                NodeValue::HtmlInline(format!(" {}", attributes)),
+                                LineColumn { line: 0, column: 0 },
+                            )))));
+                        }
+                    }
+                }
+            }
             root.append(node);
         }
+        let mut comrak_options = Options::default();
+        set_extensions(&mut comrak_options.extension, options.flavor);
+        if comrak_options.extension.footnotes {
+            move_footnote_definitions(&arena, root);
+        } else {
+            inline_footnotes(&arena, root);
+        }
 
         let mut md = vec![];
 
-        format_commonmark(root, &Options::default(), &mut md)?;
+        comrak_options.render.list_style = list_style;
+        wrap_lines(&arena, root, options.wrap);
+        double_tilde_strikethrough(&arena, root);
+        if options.emoji_shortcodes {
+            for node in root.descendants() {
+                if let NodeValue::Text(text) = &mut node.data.borrow_mut().value {
+                    *text = crate::emoji::to_shortcodes(text);
+                }
+            }
+        }
+        if let LineWrap::AtWidth(width) = options.wrap {
+            comrak_options.render.width = width;
+        }
+        if options.frontmatter && !document.metadata.is_empty() {
+            md.extend_from_slice(b"---\n");
+            md.extend_from_slice(generate_frontmatter(&document.metadata)?.as_bytes());
+            md.extend_from_slice(b"---\n\n");
+        }
+        let definitions = if options.reference_links {
+            reference_links(&arena, root)
+        } else {
+            String::new()
+        };
+        format_commonmark(root, &comrak_options, &mut md)?;
+        if !definitions.is_empty() {
+            md.push(b'\n');
+            md.extend_from_slice(definitions.as_bytes());
+        }
 
         Ok(Bytes::from(md))
     }
 }
 
+/// Soft breaks are kept as newlines in the text, for [`LineWrap::Preserve`]
+fn push_soft_break(elements: &mut Vec<Element>) {
+    match elements.last_mut() {
+        Some(Text { text, .. }) => text.push('\n'),
+        _ => elements.push(Text {
+            text: "\n".to_string(),
+            size: 14,
+        }),
+    }
+}
+
+/// Applies `f` to the text of the element and of its children, code and raw content are
+/// left as they are
+fn map_text(element: &mut Element, f: &impl Fn(&str) -> String) {
+    match element {
+        Text { text, .. } | Element::Header { text, .. } => *text = f(text),
+        element => {
+            for child in element.children_mut() {
+                map_text(child, f);
+            }
+        }
+    }
+}
+
+/// Default resolver of the wiki links: a page name without extension is the markdown file
+/// of the page, `[[Page Name#Heading]]` links to `Page Name.md#Heading`
+pub fn wiki_link_url(target: &str) -> String {
+    let (page, heading) = match target.split_once('#') {
+        Some((page, heading)) => (page, Some(heading)),
+        None => (target, None),
+    };
+    let mut url = page.to_string();
+    if !page.is_empty() && !page.rsplit('/').next().unwrap_or(page).contains('.') {
+        url.push_str(".md");
+    }
+    if let Some(heading) = heading {
+        url.push('#');
+        url.push_str(heading);
+    }
+    url
+}
+
+/// Replaces the text elements outside of links by the elements `split` makes of their text,
+/// the syntax extensions that pulldown-cmark leaves as text
+fn split_text<S>(element: &mut Element, split: &S) -> anyhow::Result<()>
+where
+    S: Fn(&str, u8) -> anyhow::Result<Vec<Element>>,
+{
+    match element {
+        Hyperlink { .. } | Element::Header { .. } => {}
+        Text { text, size } => {
+            let elements = split(text, *size)?;
+            if !matches!(elements[..], [Text { .. }]) {
+                *element = Element::Paragraph {
+                    elements,
+                    direction: TextDirection::default(),
+                };
+            }
+        }
+        Element::Paragraph { elements, .. }
+        | Element::Footnote { elements }
+        | Element::Styled { elements, .. } => {
+            let mut resolved = vec![];
+            for mut element in std::mem::take(elements) {
+                match &element {
+                    Text { text, size } => resolved.extend(split(text, *size)?),
+                    _ => {
+                        split_text(&mut element, split)?;
+                        resolved.push(element);
+                    }
+                }
+            }
+            *elements = resolved;
+        }
+        element => {
+            for child in element.children_mut() {
+                split_text(child, split)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Splits the text at its images with a size, `![alt](image.png =200x100)` or
+/// `![alt](image.png "title" =50%x)`, either dimension can be left out
+fn split_sized_images<F>(text: &str, size: u8, image_loader: &F) -> anyhow::Result<Vec<Element>>
+where
+    F: Fn(&str) -> anyhow::Result<Bytes>,
+{
+    // the quotes of the title are curly with smart punctuation
+    static SIZED_IMAGE: std::sync::OnceLock<regex::Regex> = std::sync::OnceLock::new();
+    let pattern = SIZED_IMAGE.get_or_init(|| {
+        regex::Regex::new(
+            r#"!\[([^\]]*)\]\(\s*<?([^\s<>)]+)>?(?:\s+["“]([^"”]*)["”])?\s+=(\d+(?:\.\d+)?(?:px|%)?)?x(\d+(?:\.\d+)?(?:px|%)?)?\s*\)"#,
+        )
+        .unwrap()
+    });
+    let mut elements = vec![];
+    let mut start = 0;
+    for captures in pattern.captures_iter(text) {
+        let (width, height) = (captures.get(4), captures.get(5));
+        if width.is_none() && height.is_none() {
+            continue;
+        }
+        let image = captures.get(0).unwrap();
+        if image.start() > start {
+            elements.push(Text {
+                text: text[start..image.start()].to_string(),
+                size,
+            });
+        }
+        let url = &captures[2];
+        elements.push(Element::Image(ImageData::new(
+            image_loader(url)?,
+            captures
+                .get(3)
+                .map_or("", |title| title.as_str())
+                .to_string(),
+            captures[1].to_string(),
+            url.to_string(),
+            "".to_string(),
+            ImageDimension {
+                width: width.map(|width| width.as_str().to_string()),
+                height: height.map(|height| height.as_str().to_string()),
+            },
+        )));
+        start = image.end();
+    }
+    if start < text.len() || elements.is_empty() {
+        elements.push(Text {
+            text: text[start..].to_string(),
+            size,
+        });
+    }
+    Ok(elements)
+}
+
+/// Splits the text at its `[[target|label]]` links and `![[image.png]]` embeds, the embed of
+/// a file other than an image is a link
+fn split_wiki_links<R, F>(
+    text: &str,
+    size: u8,
+    resolver: &R,
+    image_loader: &F,
+) -> anyhow::Result<Vec<Element>>
+where
+    R: Fn(&str) -> String,
+    F: Fn(&str) -> anyhow::Result<Bytes>,
+{
+    const IMAGE_EXTENSIONS: [&str; 8] = ["png", "jpg", "jpeg", "gif", "svg", "bmp", "tiff", "webp"];
+    let mut elements = vec![];
+    let mut plain = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("[[") {
+        let Some(length) = rest[start + 2..].find("]]") else {
+            break;
+        };
+        let inner = &rest[start + 2..start + 2 + length];
+        if inner.trim().is_empty() || inner.contains('[') {
+            plain.push_str(&rest[..start + 2]);
+            rest = &rest[start + 2..];
+            continue;
+        }
+        let embed = rest[..start].ends_with('!');
+        plain.push_str(&rest[..if embed { start - 1 } else { start }]);
+        rest = &rest[start + 4 + length..];
+        if !plain.is_empty() {
+            elements.push(Text {
+                text: std::mem::take(&mut plain),
+                size,
+            });
+        }
+        let (target, label) = match inner.split_once('|') {
+            Some((target, label)) => (target.trim(), label.trim()),
+            None => (inner.trim(), inner.trim()),
+        };
+        let url = resolver(target);
+        let image = embed
+            && target.rsplit_once('.').is_some_and(|(_, extension)| {
+                IMAGE_EXTENSIONS.contains(&extension.to_lowercase().as_str())
+            });
+        if image {
+            elements.push(Element::Image(ImageData::new(
+                image_loader(&url)?,
+                "".to_string(),
+                target.to_string(),
+                url,
+                "".to_string(),
+                ImageDimension::default(),
+            )));
+        } else {
+            elements.push(Hyperlink {
+                elements: vec![Text {
+                    text: label.to_string(),
+                    size,
+                }],
+                url,
+                alt: "".to_string(),
+                size,
+            });
+        }
+    }
+    plain.push_str(rest);
+    if !plain.is_empty() || elements.is_empty() {
+        elements.push(Text { text: plain, size });
+    }
+    Ok(elements)
+}
+
+/// Extensions of pulldown-cmark read in the flavor
+fn parser_options(flavor: MarkdownFlavor) -> Options {
+    let frontmatter = Options::ENABLE_YAML_STYLE_METADATA_BLOCKS
+        | Options::ENABLE_PLUSES_DELIMITED_METADATA_BLOCKS;
+    match flavor {
+        MarkdownFlavor::CommonMark => Options::empty(),
+        MarkdownFlavor::Gfm | MarkdownFlavor::Obsidian => {
+            Options::ENABLE_TABLES
+                | Options::ENABLE_STRIKETHROUGH
+                | Options::ENABLE_TASKLISTS
+                | Options::ENABLE_FOOTNOTES
+                | Options::ENABLE_SMART_PUNCTUATION
+                | Options::ENABLE_MATH
+                | Options::ENABLE_GFM
+                | frontmatter
+        }
+        MarkdownFlavor::MultiMarkdown => {
+            Options::ENABLE_TABLES
+                | Options::ENABLE_FOOTNOTES
+                | Options::ENABLE_SMART_PUNCTUATION
+                | Options::ENABLE_MATH
+                | frontmatter
+        }
+        MarkdownFlavor::Pandoc => {
+            Options::ENABLE_TABLES
+                | Options::ENABLE_STRIKETHROUGH
+                | Options::ENABLE_TASKLISTS
+                | Options::ENABLE_FOOTNOTES
+                | Options::ENABLE_SMART_PUNCTUATION
+                | Options::ENABLE_MATH
+                | Options::ENABLE_HEADING_ATTRIBUTES
+                | Options::ENABLE_YAML_STYLE_METADATA_BLOCKS
+        }
+    }
+}
+
+/// Extensions of comrak written in the flavor
+fn set_extensions(extension: &mut comrak::ExtensionOptions, flavor: MarkdownFlavor) {
+    let gfm = matches!(flavor, MarkdownFlavor::Gfm | MarkdownFlavor::Obsidian);
+    let pandoc = flavor == MarkdownFlavor::Pandoc;
+    extension.table = flavor != MarkdownFlavor::CommonMark;
+    extension.footnotes = flavor != MarkdownFlavor::CommonMark;
+    extension.strikethrough = gfm || pandoc;
+    extension.tasklist = gfm || pandoc;
+    extension.autolink = gfm;
+}
+
+/// List being parsed with the blocks of its current item
+struct OpenList {
+    list: Element,
+    item: Vec<Element>,
+}
+
+impl OpenList {
+    /// Adds the blocks of the current item to the list
+    ///
+    /// Consecutive paragraphs make one item, separated by line breaks. A nested list follows
+    /// the item it belongs to as an item of its own, as do the other blocks.
+    fn close_item(&mut self) {
+        let Element::List { elements, .. } = &mut self.list else {
+            return;
+        };
+        let mut blocks: Vec<Element> = vec![];
+        for block in self.item.drain(..) {
+            match (blocks.last_mut(), block) {
+                (
+                    Some(Element::Paragraph {
+                        elements: previous, ..
+                    }),
+                    Element::Paragraph { elements: next, .. },
+                ) => {
+                    previous.push(Element::LineBreak);
+                    previous.extend(next);
+                }
+                (_, block) => blocks.push(block),
+            }
+        }
+        if blocks.is_empty() {
+            blocks.push(Text {
+                text: "".to_string(),
+                size: 14,
+            });
+        }
+        elements.extend(blocks.into_iter().map(|block| ListItem {
+            element: block_element(block),
+        }));
+    }
+}
+
+/// Element of a list item or a table cell: a paragraph of text only is kept as text and a
+/// paragraph of a single link as the link
+fn block_element(block: Element) -> Element {
+    match block {
+        Element::Paragraph { elements, .. }
+            if elements
+                .iter()
+                .all(|element| matches!(element, Text { .. })) =>
+        {
+            Text {
+                text: elements
+                    .iter()
+                    .map(|element| element.plain_text())
+                    .collect(),
+                size: 14,
+            }
+        }
+        Element::Paragraph { mut elements, .. }
+            if matches!(elements.as_slice(), [Hyperlink { .. }]) =>
+        {
+            elements.remove(0)
+        }
+        block => block,
+    }
+}
+
+/// Adds a finished block to the current list item, or to the document outside of lists
+fn push_block(block: Element, lists: &mut [OpenList], doc_elements: &mut Vec<Element>) {
+    match lists.last_mut() {
+        Some(open) => open.item.push(block),
+        None => doc_elements.push(block),
+    }
+}
+
+/// Ends the text of a tight list item before a nested block or the end of the item
+fn end_item_text(current_element: &mut Option<Element>, lists: &mut [OpenList]) {
+    if let Some(open) = lists.last_mut() {
+        if matches!(current_element, Some(Element::Paragraph { .. })) {
+            open.item.extend(current_element.take());
+        }
+    }
+}
+
+/// Events of the inline content of a paragraph
+fn is_inline(event: &Event) -> bool {
+    matches!(
+        event,
+        Event::Text(_)
+            | Event::Code(_)
+            | Event::InlineHtml(_)
+            | Event::FootnoteReference(_)
+            | Event::TaskListMarker(_)
+            | Event::SoftBreak
+            | Event::HardBreak
+            | Event::Start(
+                Tag::Link { .. }
+                    | Tag::Image { .. }
+                    | Tag::Emphasis
+                    | Tag::Strong
+                    | Tag::Strikethrough
+            )
+    )
+}
+
+/// Fills the footnotes of the references, in the order of the text, with the content of their
+/// definitions, a paragraph gives its inline content
+fn resolve_footnotes(
+    element: &mut Element,
+    labels: &mut VecDeque<String>,
+    definitions: &HashMap<String, Vec<Element>>,
+) {
+    if let Element::Footnote { elements } = element {
+        let content = labels
+            .pop_front()
+            .and_then(|label| definitions.get(&label))
+            .cloned()
+            .unwrap_or_default();
+        *elements = match <[Element; 1]>::try_from(content) {
+            Ok([Element::Paragraph { elements, .. }]) => elements,
+            Ok([element]) => vec![element],
+            Err(content) => content,
+        };
+        return;
+    }
+    for child in element.children_mut() {
+        resolve_footnotes(child, labels, definitions);
+    }
+}
+
+/// Metadata of a YAML (`---`) or TOML (`+++`) frontmatter block
+fn parse_frontmatter(kind: MetadataBlockKind, source: &str) -> anyhow::Result<Metadata> {
+    let mut metadata = Metadata::default();
+    match kind {
+        MetadataBlockKind::YamlStyle => {
+            let value: serde_yaml::Value = serde_yaml::from_str(source)?;
+            match value {
+                serde_yaml::Value::Mapping(_) => yaml_entries("", &value, &mut metadata),
+                serde_yaml::Value::Null => {}
+                _ => anyhow::bail!("The YAML frontmatter is not a mapping"),
+            }
+        }
+        MetadataBlockKind::PlusesStyle => {
+            let table: toml::Table = source.parse()?;
+            toml_entries("", &toml::Value::Table(table), &mut metadata);
+        }
+    }
+    Ok(metadata)
+}
+
+fn entry_key(prefix: &str, key: &str) -> String {
+    if prefix.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", prefix, key)
+    }
+}
+
+fn yaml_scalar(value: &serde_yaml::Value) -> Option<String> {
+    match value {
+        serde_yaml::Value::String(text) => Some(text.clone()),
+        serde_yaml::Value::Number(number) => Some(number.to_string()),
+        serde_yaml::Value::Bool(value) => Some(value.to_string()),
+        serde_yaml::Value::Tagged(tagged) => yaml_scalar(&tagged.value),
+        _ => None,
+    }
+}
+
+fn yaml_entries(key: &str, value: &serde_yaml::Value, metadata: &mut Metadata) {
+    match value {
+        serde_yaml::Value::Mapping(mapping) => {
+            for (name, value) in mapping {
+                if let Some(name) = yaml_scalar(name) {
+                    yaml_entries(&entry_key(key, &name), value, metadata);
+                }
+            }
+        }
+        serde_yaml::Value::Sequence(items) => set_metadata(
+            metadata,
+            key,
+            items.iter().filter_map(yaml_scalar).collect(),
+        ),
+        serde_yaml::Value::Tagged(tagged) => yaml_entries(key, &tagged.value, metadata),
+        value => set_metadata(metadata, key, yaml_scalar(value).into_iter().collect()),
+    }
+}
+
+fn toml_scalar(value: &toml::Value) -> Option<String> {
+    match value {
+        toml::Value::String(text) => Some(text.clone()),
+        toml::Value::Integer(value) => Some(value.to_string()),
+        toml::Value::Float(value) => Some(value.to_string()),
+        toml::Value::Boolean(value) => Some(value.to_string()),
+        toml::Value::Datetime(value) => Some(value.to_string()),
+        toml::Value::Array(_) | toml::Value::Table(_) => None,
+    }
+}
+
+fn toml_entries(key: &str, value: &toml::Value, metadata: &mut Metadata) {
+    match value {
+        toml::Value::Table(table) => {
+            for (name, value) in table {
+                toml_entries(&entry_key(key, name), value, metadata);
+            }
+        }
+        toml::Value::Array(items) => set_metadata(
+            metadata,
+            key,
+            items.iter().filter_map(toml_scalar).collect(),
+        ),
+        value => set_metadata(metadata, key, toml_scalar(value).into_iter().collect()),
+    }
+}
+
+fn set_metadata(metadata: &mut Metadata, key: &str, values: Vec<String>) {
+    let field = match key {
+        "title" => &mut metadata.title,
+        "author" => &mut metadata.author,
+        "date" => &mut metadata.date,
+        "description" => &mut metadata.description,
+        "keywords" | "tags" => {
+            metadata.keywords.extend(values);
+            return;
+        }
+        key => {
+            metadata.custom.insert(key.to_string(), values.join(", "));
+            return;
+        }
+    };
+    *field = Some(values.join(", "));
+}
+
+/// YAML frontmatter of the metadata, without the `---` delimiters
+fn generate_frontmatter(metadata: &Metadata) -> anyhow::Result<String> {
+    use serde_yaml::{Mapping, Value};
+
+    let mut mapping = Mapping::new();
+    let fields = [
+        ("title", &metadata.title),
+        ("author", &metadata.author),
+        ("date", &metadata.date),
+        ("description", &metadata.description),
+    ];
+    for (key, value) in fields {
+        if let Some(value) = value {
+            mapping.insert(key.into(), value.as_str().into());
+        }
+    }
+    if !metadata.keywords.is_empty() {
+        mapping.insert(
+            "keywords".into(),
+            Value::Sequence(
+                metadata
+                    .keywords
+                    .iter()
+                    .map(|keyword| keyword.as_str().into())
+                    .collect(),
+            ),
+        );
+    }
+    for (key, value) in &metadata.custom {
+        mapping.insert(key.as_str().into(), value.as_str().into());
+    }
+    Ok(serde_yaml::to_string(&mapping)?)
+}
+
 use comrak::nodes::{
-    Ast, AstNode, LineColumn, NodeHeading, NodeLink, NodeList, NodeTable, NodeValue, TableAlignment,
+    Ast, AstNode, LineColumn, NodeCode, NodeCodeBlock, NodeFootnoteDefinition,
+    NodeFootnoteReference, NodeHeading, NodeHtmlBlock, NodeLink, NodeList, NodeTable, NodeValue,
+    TableAlignment,
 };
 
+/// Numbers the footnote references in the order of the text and moves their content, the
+/// child of the reference node, to definitions at the end of the document
+fn move_footnote_definitions<'a>(arena: &'a Arena<AstNode<'a>>, root: &'a AstNode<'a>) {
+    let references: Vec<&AstNode> = root
+        .descendants()
+        .filter(|node| matches!(node.data.borrow().value, NodeValue::FootnoteReference(_)))
+        .collect();
+    for (index, reference) in references.into_iter().enumerate() {
+        let name = (index + 1).to_string();
+        reference.data.borrow_mut().value = NodeValue::FootnoteReference(NodeFootnoteReference {
+            name: name.clone(),
+            ref_num: 1,
+            ix: index as u32 + 1,
+        });
+        let definition = arena.alloc(Node::new(RefCell::new(Ast::new(
+            NodeValue::FootnoteDefinition(NodeFootnoteDefinition {
+                name,
+                total_references: 1,
+            }),
+            LineColumn { line: 0, column: 0 },
+        ))));
+        while let Some(content) = reference.first_child() {
+            content.detach();
+            definition.append(content);
+        }
+        root.append(definition);
+    }
+}
+
+/// Line breaks of the paragraph texts written as soft breaks for [`LineWrap::Preserve`],
+/// replaced by spaces otherwise and in headers and table cells which are single lines
+fn wrap_lines<'a>(arena: &'a Arena<AstNode<'a>>, root: &'a AstNode<'a>, wrap: LineWrap) {
+    let texts: Vec<&AstNode> = root
+        .descendants()
+        .filter(|node| matches!(&node.data.borrow().value, NodeValue::Text(text) if text.contains('\n')))
+        .collect();
+    for node in texts {
+        let in_paragraph = node
+            .ancestors()
+            .any(|ancestor| matches!(ancestor.data.borrow().value, NodeValue::Paragraph));
+        let NodeValue::Text(text) = node.data.borrow().value.clone() else {
+            continue;
+        };
+        if wrap != LineWrap::Preserve || !in_paragraph {
+            node.data.borrow_mut().value = NodeValue::Text(text.replace('\n', " "));
+            continue;
+        }
+        for (index, line) in text.split('\n').enumerate() {
+            if index > 0 {
+                node.insert_before(arena.alloc(Node::new(RefCell::new(Ast::new(
+                    NodeValue::SoftBreak,
+                    LineColumn { line: 0, column: 0 },
+                )))));
+            }
+            if !line.is_empty() {
+                node.insert_before(arena.alloc(Node::new(RefCell::new(Ast::new(
+                    NodeValue::Text(line.to_string()),
+                    LineColumn { line: 0, column: 0 },
+                )))));
+            }
+        }
+        node.detach();
+    }
+}
+
+/// `{#id .class key=value}` of a heading, the attributes prefixed with another format
+/// (`docx:style`) are left out
+fn heading_attributes(attributes: &HashMap<String, String>) -> Option<String> {
+    let mut parts: Vec<String> = vec![];
+    if let Some(id) = attributes.get("id") {
+        parts.push(format!("#{}", id));
+    }
+    if let Some(classes) = attributes.get("class") {
+        parts.extend(
+            classes
+                .split_whitespace()
+                .map(|class| format!(".{}", class)),
+        );
+    }
+    let mut others: Vec<(&String, &String)> = attributes
+        .iter()
+        .filter(|(key, _)| !matches!(key.as_str(), "id" | "class") && !key.contains(':'))
+        .collect();
+    others.sort();
+    parts.extend(others.into_iter().map(|(key, value)| match value.as_str() {
+        "" => key.clone(),
+        value if value.contains(char::is_whitespace) => format!("{}=\"{}\"", key, value),
+        value => format!("{}={}", key, value),
+    }));
+    if parts.is_empty() {
+        None
+    } else {
+        Some(format!("{{{}}}", parts.join(" ")))
+    }
+}
+
+/// Writes strikethrough with `~~` delimiters, comrak writes a single `~` that only some
+/// parsers read as strikethrough
+fn double_tilde_strikethrough<'a>(arena: &'a Arena<AstNode<'a>>, root: &'a AstNode<'a>) {
+    let raw = || {
+        arena.alloc(Node::new(RefCell::new(Ast::new(
+            NodeValue::HtmlInline("~~".to_string()),
+            LineColumn { line: 0, column: 0 },
+        ))))
+    };
+    let nodes: Vec<&AstNode> = root
+        .descendants()
+        .filter(|node| matches!(node.data.borrow().value, NodeValue::Strikethrough))
+        .collect();
+    for node in nodes {
+        node.insert_before(raw());
+        while let Some(child) = node.first_child() {
+            child.detach();
+            node.insert_before(child);
+        }
+        node.insert_before(raw());
+        node.detach();
+    }
+}
+
+/// Rewrites the links and images as references, `[text][1]`, and returns their definitions,
+/// a link to the same destination with the same title gets the same number
+///
+/// Autolinks, whose text is their URL, are kept as they are
+fn reference_links<'a>(arena: &'a Arena<AstNode<'a>>, root: &'a AstNode<'a>) -> String {
+    let raw = |text: String| {
+        arena.alloc(Node::new(RefCell::new(Ast::new(
+            NodeValue::HtmlInline(text),
+            LineColumn { line: 0, column: 0 },
+        ))))
+    };
+    let links: Vec<(&AstNode, bool, NodeLink)> = root
+        .descendants()
+        .filter_map(|node| match &node.data.borrow().value {
+            NodeValue::Link(link) if !is_autolink(node, link) => Some((node, false, link.clone())),
+            NodeValue::Image(link) => Some((node, true, link.clone())),
+            _ => None,
+        })
+        .collect();
+
+    let mut references: Vec<(String, String)> = vec![];
+    for (node, image, link) in links {
+        let key = (link.url, link.title);
+        let number = match references.iter().position(|reference| *reference == key) {
+            Some(index) => index + 1,
+            None => {
+                references.push(key);
+                references.len()
+            }
+        };
+        node.insert_before(raw(if image { "![" } else { "[" }.to_string()));
+        while let Some(child) = node.first_child() {
+            child.detach();
+            node.insert_before(child);
+        }
+        node.insert_before(raw(format!("][{}]", number)));
+        node.detach();
+    }
+
+    references
+        .iter()
+        .enumerate()
+        .map(|(index, (url, title))| {
+            let url = if url.is_empty() || url.contains(char::is_whitespace) {
+                format!("<{}>", url)
+            } else {
+                url.clone()
+            };
+            if title.is_empty() {
+                format!("[{}]: {}\n", index + 1, url)
+            } else {
+                format!(
+                    "[{}]: {} \"{}\"\n",
+                    index + 1,
+                    url,
+                    title.replace('"', "\\\"")
+                )
+            }
+        })
+        .collect()
+}
+
+/// Link written `<url>` by comrak
+fn is_autolink<'a>(node: &'a AstNode<'a>, link: &NodeLink) -> bool {
+    link.title.is_empty()
+        && node.children().count() == 1
+        && matches!(&node.first_child().map(|child| child.data.borrow().value.clone()),
+            Some(NodeValue::Text(text)) if *text == link.url)
+}
+
+/// Replaces the footnote references by their content in parentheses, for the flavors without
+/// footnotes
+fn inline_footnotes<'a>(arena: &'a Arena<AstNode<'a>>, root: &'a AstNode<'a>) {
+    let text = |text: &str| {
+        arena.alloc(Node::new(RefCell::new(Ast::new(
+            NodeValue::Text(text.to_string()),
+            LineColumn { line: 0, column: 0 },
+        ))))
+    };
+    let references: Vec<&AstNode> = root
+        .descendants()
+        .filter(|node| matches!(node.data.borrow().value, NodeValue::FootnoteReference(_)))
+        .collect();
+    for reference in references {
+        reference.insert_before(text(" ("));
+        for paragraph in reference.children() {
+            while let Some(content) = paragraph.first_child() {
+                content.detach();
+                reference.insert_before(content);
+            }
+        }
+        reference.insert_before(text(")"));
+        reference.detach();
+    }
+}
+
+/// Checkbox of a list item starting with `[ ] ` or `[x] `, with the item without it
+fn task_item(element: &Element) -> Option<(Option<char>, Element)> {
+    let strip = |text: &str| {
+        [(None, "[ ] "), (Some('x'), "[x] "), (Some('x'), "[X] ")]
+            .into_iter()
+            .find_map(|(symbol, marker)| Some((symbol, text.strip_prefix(marker)?.to_string())))
+    };
+    match element {
+        Element::Text { text, .. } => {
+            let (symbol, text) = strip(text)?;
+            Some((symbol, Element::Text { text, size: 14 }))
+        }
+        Element::Paragraph {
+            elements,
+            direction,
+        } => {
+            let Some(Element::Text { text, .. }) = elements.first() else {
+                return None;
+            };
+            let (symbol, text) = strip(text)?;
+            let mut elements = elements.clone();
+            if text.is_empty() {
+                elements.remove(0);
+            } else {
+                elements[0] = Element::Text { text, size: 14 };
+            }
+            Some((
+                symbol,
+                Element::Paragraph {
+                    elements,
+                    direction: *direction,
+                },
+            ))
+        }
+        _ => None,
+    }
+}
+
 fn is_parent_list(list_item: &ListItem) -> bool {
     if let Element::List { elements, .. } = &list_item.element {
         let first = elements.first();
@@ -448,7 +1528,11 @@ fn create_item_node<'a>(arena: &'a Arena<AstNode<'a>>, numbered: bool) -> &'a As
     item_node
 }
 
-fn create_list_node<'a>(arena: &'a Arena<AstNode<'a>>, numbered: bool) -> &'a AstNode<'a> {
+fn create_list_node<'a>(
+    arena: &'a Arena<AstNode<'a>>,
+    numbered: bool,
+    start: usize,
+) -> &'a AstNode<'a> {
     let node = arena.alloc(Node::new(RefCell::new(Ast::new(
         NodeValue::List(NodeList {
             list_type: if numbered {
@@ -456,7 +1540,7 @@ fn create_list_node<'a>(arena: &'a Arena<AstNode<'a>>, numbered: bool) -> &'a As
             } else {
                 comrak::nodes::ListType::Bullet
             },
-            start: 1,
+            start,
             tight: true,
             bullet_char: b'-',
             marker_offset: 0,
@@ -469,17 +1553,89 @@ fn create_list_node<'a>(arena: &'a Arena<AstNode<'a>>, numbered: bool) -> &'a As
     node
 }
 
+/// Puts an inline element found outside of a paragraph, at the top level or in a list item,
+/// in a paragraph of its own, a line break there has nothing to break and leaves an empty paragraph
 fn text_to_paragraph(element: Element) -> Element {
-    if let Element::Text { text, .. } = element {
-        Element::Paragraph {
-            elements: vec![Element::Text {
-                text: text.to_string(),
+    match element {
+        Element::LineBreak => Element::Paragraph {
+            elements: vec![],
+            direction: TextDirection::default(),
+        },
+        Element::Text { .. }
+        | Element::Hyperlink { .. }
+        | Element::Image(_)
+        | Element::InlineCode { .. }
+        | Element::Footnote { .. }
+        | Element::Styled { .. }
+        | Element::Raw { .. } => Element::Paragraph {
+            elements: vec![element],
+            direction: TextDirection::default(),
+        },
+        element => element,
+    }
+}
+
+/// Inline content of the children of a link or a style, which cannot hold blocks: the
+/// paragraphs are unwrapped and the other blocks written as their text, each block on a line
+/// of its own
+fn inline_content(elements: &[Element]) -> Vec<Element> {
+    let mut content = vec![];
+    let mut after_block = false;
+    for element in elements {
+        let inline =
+            element.is_inline() || matches!(element, Element::Image(_) | Element::Raw { .. });
+        if !content.is_empty() && (after_block || !inline) {
+            content.push(Element::LineBreak);
+        }
+        after_block = !inline;
+        match element {
+            element if inline => content.push(element.clone()),
+            Element::Paragraph { elements, .. } => content.extend(inline_content(elements)),
+            block => content.push(Element::Text {
+                text: block.plain_text().replace('\n', " "),
                 size: 14,
-            }],
+            }),
         }
-    } else {
-        element
     }
+    content
+}
+
+/// Appends the content of a table cell, cells hold inline nodes so a paragraph is unwrapped
+fn append_cell_content<'a, F>(
+    arena: &'a Arena<AstNode<'a>>,
+    cell_node: &'a AstNode<'a>,
+    element: &Element,
+    image_num: &RefCell<i32>,
+    image_saver: &ImageSaver<F>,
+) -> anyhow::Result<()>
+where
+    F: Fn(&Bytes, &str) -> anyhow::Result<()>,
+{
+    // a cell holds one line of inline content, the blocks grouped in it are written as
+    // their text
+    let text;
+    let elements = match element {
+        Element::Paragraph { elements, .. } => elements.as_slice(),
+        Element::Container { .. } => {
+            text = Element::Text {
+                text: element.plain_text().replace('\n', " "),
+                size: 14,
+            };
+            std::slice::from_ref(&text)
+        }
+        element => std::slice::from_ref(element),
+    };
+    for element in elements {
+        cell_node.append(element_to_ast_node(arena, element, image_num, image_saver)?);
+    }
+    // a line break would end the row, it is written as HTML
+    for node in cell_node.descendants() {
+        let mut ast = node.data.borrow_mut();
+        if let NodeValue::LineBreak = ast.value {
+            ast.value = NodeValue::HtmlInline("<br>".to_string());
+        }
+    }
+    Ok(())
 }
 
 fn element_to_ast_node<'a, F>(
@@ -517,7 +1673,7 @@ where
             Ok(heading)
         }
 
-        Element::Paragraph { elements } => {
+        Element::Paragraph { elements, .. } => {
             let paragraph = arena.alloc(Node::new(RefCell::new(Ast::new(
                 NodeValue::Paragraph,
                 LineColumn { line: 0, column: 0 },
@@ -530,8 +1686,13 @@ where
             Ok(paragraph)
         }
 
-        Element::List { elements, numbered } => {
-            let list_node = create_list_node(arena, *numbered);
+        Element::List {
+            elements,
+            numbered,
+            start,
+            ..
+        } => {
+            let list_node = create_list_node(arena, *numbered, *start);
             for list_item in elements {
                 let item_node = create_item_node(arena, *numbered);
 
@@ -563,8 +1724,20 @@ where
                             list_node.append(item_node);
                         }
                     }
+                } else if let (Element::List { .. }, Some(previous_item)) =
+                    (&list_item.element, list_node.last_child())
+                {
+                    // a list following an item is nested into that item
+                    let children_node =
+                        element_to_ast_node(arena, &list_item.element, image_num, image_saver)?;
+                    previous_item.append(children_node);
                 } else {
-                    let list_item_element = text_to_paragraph(list_item.element.clone());
+                    let mut list_item_element = list_item.element.clone();
+                    if let Some((symbol, element)) = task_item(&list_item.element) {
+                        item_node.data.borrow_mut().value = NodeValue::TaskItem(symbol);
+                        list_item_element = element;
+                    }
+                    let list_item_element = text_to_paragraph(list_item_element);
 
                     let list_item_content =
                         element_to_ast_node(arena, &list_item_element, image_num, image_saver)?;
@@ -575,12 +1748,25 @@ where
             Ok(list_node)
         }
 
-        Element::Image(image_data) => {
-            *image_num.borrow_mut() += 1;
-            let image_extension = image_data.image_type().to_extension();
-            let image_filename = format!("image{}{}", image_num.borrow(), image_extension);
+        Element::LineBreak => {
+            let node = arena.alloc(Node::new(RefCell::new(Ast::new(
+                NodeValue::LineBreak,
+                LineColumn { line: 0, column: 0 },
+            ))));
+
+            Ok(node)
+        }
 
-            (image_saver.function)(image_data.bytes(), &image_filename)?;
+        Element::Image(image_data) => {
+            let image_filename = if image_saver.embed {
+                image_data_uri(image_data)
+            } else {
+                *image_num.borrow_mut() += 1;
+                let image_extension = image_data.image_type().to_extension();
+                let image_filename = format!("image{}{}", image_num.borrow(), image_extension);
+                (image_saver.function)(image_data.bytes(), &image_filename)?;
+                image_filename
+            };
 
             let image_node = arena.alloc(Node::new(RefCell::new(Ast::new(
                 NodeValue::Image(NodeLink {
@@ -594,25 +1780,45 @@ where
         }
 
         Element::Hyperlink {
-            title, url, alt, ..
+            elements, url, alt, ..
         } => {
             let link_node = arena.alloc(Node::new(RefCell::new(Ast::new(
                 NodeValue::Link(NodeLink {
                     url: url.clone(),
-                    title: alt.clone(),
+                    // a title repeating the destination is dropped, the link of a bare URL
+                    // is written back as an autolink
+                    title: if alt == url {
+                        String::new()
+                    } else {
+                        alt.clone()
+                    },
                 }),
                 LineColumn { line: 0, column: 0 },
             ))));
-            let text_node = arena.alloc(Node::new(RefCell::new(Ast::new(
-                NodeValue::Text(title.clone()),
-                LineColumn { line: 0, column: 0 },
-            ))));
-            link_node.append(text_node);
+            for child_element in &inline_content(elements) {
+                let child_node = element_to_ast_node(arena, child_element, image_num, image_saver)?;
+                link_node.append(child_node);
+            }
             Ok(link_node)
         }
 
         Element::Table { headers, rows } => {
-            let num_columns = headers.len() as u32;
+            // markdown tables need a header row, without one the first row is promoted to it
+            let header_cells: Vec<&Element>;
+            let rows = if headers.is_empty() && !rows.is_empty() {
+                header_cells = rows[0].cells.iter().map(|cell| &cell.element).collect();
+                &rows[1..]
+            } else {
+                header_cells = headers.iter().map(|header| &header.element).collect();
+                &rows[..]
+            };
+            // ragged rows are padded with empty cells up to the widest row
+            let num_columns = rows
+                .iter()
+                .map(|row| row.cells.len())
+                .chain([header_cells.len()])
+                .max()
+                .unwrap_or(0) as u32;
             let num_rows = rows.len() as u32 + 1;
 
             let alignments = vec![TableAlignment::None; num_columns as usize];
@@ -632,16 +1838,20 @@ where
                 NodeValue::TableRow(true), // Indicate header row
                 LineColumn { line: 0, column: 0 },
             ))));
-            for header in headers {
-                let cell_node = arena.alloc(Node::new(RefCell::new(Ast::new(
-                    NodeValue::TableCell,
-                    LineColumn { line: 0, column: 0 },
-                ))));
-                let cell_content =
-                    element_to_ast_node(arena, &header.element, image_num, image_saver)?;
-                cell_node.append(cell_content);
-                header_row_node.append(cell_node);
-            }
+            let append_cells = |row_node: &'a AstNode<'a>, cells: &[&Element]| {
+                for index in 0..num_columns as usize {
+                    let cell_node = arena.alloc(Node::new(RefCell::new(Ast::new(
+                        NodeValue::TableCell,
+                        LineColumn { line: 0, column: 0 },
+                    ))));
+                    if let Some(cell) = cells.get(index) {
+                        append_cell_content(arena, cell_node, cell, image_num, image_saver)?;
+                    }
+                    row_node.append(cell_node);
+                }
+                anyhow::Ok(())
+            };
+            append_cells(header_row_node, &header_cells)?;
             table_node.append(header_row_node);
 
             // Data rows
@@ -650,20 +1860,98 @@ where
                     NodeValue::TableRow(false), // Indicate data row
                     LineColumn { line: 0, column: 0 },
                 ))));
-                for cell in &row.cells {
-                    let cell_node = arena.alloc(Node::new(RefCell::new(Ast::new(
-                        NodeValue::TableCell,
-                        LineColumn { line: 0, column: 0 },
-                    ))));
-                    let cell_content =
-                        element_to_ast_node(arena, &cell.element, image_num, image_saver)?;
-                    cell_node.append(cell_content);
-                    row_node.append(cell_node);
-                }
+                let cells: Vec<&Element> = row.cells.iter().map(|cell| &cell.element).collect();
+                append_cells(row_node, &cells)?;
                 table_node.append(row_node);
             }
 
-            Ok(table_node)
+            Ok(table_node)
+        }
+
+        // an empty code span has nothing to write
+        Element::InlineCode { text } if text.is_empty() => {
+            let node = arena.alloc(Node::new(RefCell::new(Ast::new(
+                NodeValue::Text(String::new()),
+                LineColumn { line: 0, column: 0 },
+            ))));
+            Ok(node)
+        }
+
+        Element::InlineCode { text } => {
+            let node = arena.alloc(Node::new(RefCell::new(Ast::new(
+                NodeValue::Code(NodeCode {
+                    num_backticks: 1,
+                    literal: text.clone(),
+                }),
+                LineColumn { line: 0, column: 0 },
+            ))));
+            Ok(node)
+        }
+
+        Element::CodeBlock { info, code } => {
+            let node = arena.alloc(Node::new(RefCell::new(Ast::new(
+                NodeValue::CodeBlock(NodeCodeBlock {
+                    fenced: true,
+                    fence_char: b'`',
+                    fence_length: 3,
+                    fence_offset: 0,
+                    info: info.clone(),
+                    literal: format!("{}\n", code),
+                }),
+                LineColumn { line: 0, column: 0 },
+            ))));
+            Ok(node)
+        }
+
+        Element::Raw {
+            format: DocumentType::HTML | DocumentType::Markdown,
+            content,
+        } => {
+            let node = arena.alloc(Node::new(RefCell::new(Ast::new(
+                NodeValue::HtmlInline(content.clone()),
+                LineColumn { line: 0, column: 0 },
+            ))));
+            Ok(node)
+        }
+
+        Element::Styled { style, elements } => {
+            let value = match style {
+                TextStyle::Emphasis => NodeValue::Emph,
+                TextStyle::Strong => NodeValue::Strong,
+                TextStyle::Strikethrough => NodeValue::Strikethrough,
+            };
+            let node = arena.alloc(Node::new(RefCell::new(Ast::new(
+                value,
+                LineColumn { line: 0, column: 0 },
+            ))));
+            for child_element in &inline_content(elements) {
+                node.append(element_to_ast_node(
+                    arena,
+                    child_element,
+                    image_num,
+                    image_saver,
+                )?);
+            }
+            Ok(node)
+        }
+
+        // numbered and moved to the end of the document by move_footnote_definitions
+        Element::Footnote { elements } => {
+            let reference = arena.alloc(Node::new(RefCell::new(Ast::new(
+                NodeValue::FootnoteReference(NodeFootnoteReference::default()),
+                LineColumn { line: 0, column: 0 },
+            ))));
+            let content = element_to_ast_node(
+                arena,
+                &Element::Paragraph {
+                    elements: elements.clone(),
+                    direction: TextDirection::default(),
+                },
+                image_num,
+                image_saver,
+            )?;
+            reference.append(content);
+            Ok(reference)
         }
 
         _ => {
@@ -684,7 +1972,8 @@ mod tests {
     use log::info;
     use serde_xml_rs::to_string;
 
-    use crate::core::tests::init_logger;
+    use crate::core::tests::{init_logger, output_dir};
+    use crate::core::Element::{Header, List};
     use crate::core::*;
     use crate::html;
     use crate::markdown::*;
@@ -744,7 +2033,7 @@ blabla2 bla bla blabla bla bla blabla bla bla blabla bla bla bla"#;
         debug!("{:#?}", parsed_document);
         debug!("==========================");
         let generated_result =
-            Transformer::generate_with_saver(&parsed_document, disk_image_saver("test/data"));
+            Transformer::generate_with_saver(&parsed_document, disk_image_saver(&output_dir()));
         assert!(generated_result.is_ok());
         let generated_bytes = generated_result?;
         let generated_text = std::str::from_utf8(&generated_bytes)?;
@@ -854,6 +2143,254 @@ blabla2 bla bla blabla bla bla blabla bla bla blabla bla bla bla"#;
         assert_eq!(parsed, result_doc)
     }
 
+    #[test]
+    fn test_table_inline_content() -> anyhow::Result<()> {
+        let markdown = "| Name | Link |\n| --- | --- |\n| `parse` it | [docs](https://docs.rs) |\n| two words |  |\n";
+        let document = Transformer::parse(&Bytes::from(markdown))?;
+        let Table { headers, rows } = document.get_all_elements()[0] else {
+            panic!("Expected Table");
+        };
+        assert_eq!(headers.len(), 2);
+        assert_eq!(rows.len(), 2);
+        assert_eq!(
+            rows[0].cells[0].element,
+            Element::Paragraph {
+                elements: vec![
+                    Element::InlineCode {
+                        text: "parse".to_string()
+                    },
+                    Text {
+                        text: " it".to_string(),
+                        size: 14
+                    },
+                ],
+                direction: TextDirection::default(),
+            }
+        );
+        assert!(matches!(
+            &rows[0].cells[1].element,
+            Hyperlink { url, .. } if url == "https://docs.rs"
+        ));
+        assert_eq!(rows[1].cells[0].element.plain_text(), "two words");
+        assert_eq!(rows[1].cells[1].element.plain_text(), "");
+
+        let generated = Transformer::generate(&document)?;
+        assert_eq!(std::str::from_utf8(&generated)?, markdown);
+
+        // blocks of a cell, read from HTML, are written on the line of the cell
+        let document = DocumentBuilder::new()
+            .table(|table| {
+                table.header("Notes").row_elements(vec![Element::Container {
+                    kind: ContainerKind::Div,
+                    elements: vec![
+                        Text {
+                            text: "Converts".to_string(),
+                            size: 8,
+                        },
+                        Text {
+                            text: "HTML".to_string(),
+                            size: 8,
+                        },
+                    ],
+                }])
+            })
+            .build();
+        let generated = Transformer::generate(&document)?;
+        assert!(std::str::from_utf8(&generated)?.contains("| Converts HTML |"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_list_start() -> anyhow::Result<()> {
+        let document = r#"
+3. Third
+4. Fourth
+"#;
+        let parsed = Transformer::parse(&document.as_bytes().into())?;
+        match &parsed.get_all_elements()[0] {
+            List {
+                numbered, start, ..
+            } => {
+                assert!(*numbered);
+                assert_eq!(*start, 3);
+            }
+            _ => panic!("Expected List"),
+        }
+
+        let generated = Transformer::generate(&parsed)?;
+        assert!(String::from_utf8(generated.to_vec())?.starts_with("3."));
+        assert_eq!(Transformer::parse(&generated)?, parsed);
+        Ok(())
+    }
+
+    #[test]
+    fn test_top_level_line_break() -> anyhow::Result<()> {
+        let parsed = html::Transformer::parse(&"<p>a</p><br><p>b</p>".as_bytes().into())?;
+        let generated = Transformer::generate(&parsed)?;
+        assert_eq!(generated, "a\n\nb\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_empty_inline_code() -> anyhow::Result<()> {
+        let parsed = html::Transformer::parse(&"<p>a<code></code>b</p>".as_bytes().into())?;
+        let generated = Transformer::generate(&parsed)?;
+        assert_eq!(generated, "ab\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_blocks_in_link() -> anyhow::Result<()> {
+        let parsed = html::Transformer::parse(&r#"<a href="x"><p>a</p></a>"#.as_bytes().into())?;
+        assert_eq!(Transformer::generate(&parsed)?, "[a](x)\n");
+
+        let parsed = html::Transformer::parse(
+            &r#"<a href="https://example.com"><h2>Title</h2><p>a <b>b</b></p>c</a>"#
+                .as_bytes()
+                .into(),
+        )?;
+        let generated = Transformer::generate(&parsed)?;
+        assert_eq!(generated, "[Title\\\na **b**\\\nc](https://example.com)\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_blocks_in_styles() -> anyhow::Result<()> {
+        for (html, markdown) in [
+            ("<strong><p>a</p></strong>", "**a**\n"),
+            ("<em><ul><li>x</li><li>y</li></ul></em>", "*x y*\n"),
+            ("<s><ul><li>x</li></ul></s>", "~~x~~\n"),
+        ] {
+            let parsed = html::Transformer::parse(&html.as_bytes().into())?;
+            assert_eq!(Transformer::generate(&parsed)?, markdown);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_line_break_in_table_cell() -> anyhow::Result<()> {
+        let parsed = html::Transformer::parse(
+            &"<table><tr><td>a<br>b</td><td><b>c<br>d</b></td></tr></table>"
+                .as_bytes()
+                .into(),
+        )?;
+        let generated = Transformer::generate(&parsed)?;
+        assert_eq!(generated, "| a<br>b | **c<br>d** |\n| --- | --- |\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_line_breaks() -> anyhow::Result<()> {
+        let document = "Roses are red,  \nViolets are blue,\nSugar is sweet\n";
+        let parsed = Transformer::parse(&document.as_bytes().into())?;
+        let expected = Document::new(vec![Element::Paragraph {
+            elements: vec![
+                Text {
+                    text: "Roses are red,".to_string(),
+                    size: 14,
+                },
+                Element::LineBreak,
+                Text {
+                    text: "Violets are blue,\nSugar is sweet".to_string(),
+                    size: 14,
+                },
+            ],
+            direction: TextDirection::default(),
+        }]);
+        assert_eq!(parsed, expected);
+
+        let generated = Transformer::generate(&parsed)?;
+        assert_eq!(
+            generated,
+            "Roses are red,\\\nViolets are blue,\nSugar is sweet\n"
+        );
+        assert_eq!(Transformer::parse(&generated)?, parsed);
+
+        let mut options = GenerateOptions::default();
+        options.markdown.wrap = LineWrap::Off;
+        let generated = Transformer::generate_with_options(&parsed, &options)?;
+        assert_eq!(
+            generated,
+            "Roses are red,\\\nViolets are blue, Sugar is sweet\n"
+        );
+        options.markdown.wrap = LineWrap::AtWidth(12);
+        let generated = Transformer::generate_with_options(&parsed, &options)?;
+        assert_eq!(
+            generated,
+            "Roses are\nred,\\\nViolets are\nblue, Sugar\nis sweet\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_link_with_inline_content() -> anyhow::Result<()> {
+        let document = "See [the ![logo](logo.png) site](http://example.com \"Example\") now\n";
+        let parsed = Transformer::parse_with_loader(&document.as_bytes().into(), |_| {
+            Ok(Bytes::from_static(b"png"))
+        })?;
+        let Element::Paragraph { elements, .. } = &parsed.get_all_elements()[0] else {
+            panic!("Expected Paragraph");
+        };
+        let Element::Hyperlink {
+            elements: link_elements,
+            url,
+            alt,
+            ..
+        } = &elements[1]
+        else {
+            panic!("Expected Hyperlink");
+        };
+        assert_eq!(url, "http://example.com");
+        assert_eq!(alt, "Example");
+        assert_eq!(link_elements.len(), 3);
+        assert!(matches!(&link_elements[1], Element::Image(image) if image.alt() == "logo"));
+        assert_eq!(elements[1].plain_text(), "the logo site");
+
+        let generated = Transformer::generate_with_saver(&parsed, |_: &Bytes, _: &str| Ok(()))?;
+        let generated = String::from_utf8(generated.to_vec())?;
+        assert!(generated.contains("[the ![](image1.png) site](http://example.com \"Example\")"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_media_links() -> anyhow::Result<()> {
+        let document = DocumentBuilder::new()
+            .media(MediaKind::Video, "https://example.com/clip.mp4", "Clip")
+            .build();
+        let generated = Transformer::generate(&document)?;
+        assert_eq!(
+            std::str::from_utf8(&generated)?.trim(),
+            "[Clip](https://example.com/clip.mp4 \"Clip\")"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_table_of_contents() -> anyhow::Result<()> {
+        let document = Document::new(vec![
+            Element::TableOfContents { max_level: 2 },
+            Header {
+                level: 1,
+                text: "Getting started".to_string(),
+            },
+            Header {
+                level: 2,
+                text: "Install".to_string(),
+            },
+            Header {
+                level: 3,
+                text: "From sources".to_string(),
+            },
+        ]);
+        let generated = Transformer::generate(&document)?;
+        let generated = String::from_utf8(generated.to_vec())?;
+        assert!(generated.starts_with(
+            "- [Getting started](#getting-started \"Getting started\")\n  - [Install](#install \"Install\")\n"
+        ));
+        assert!(!generated.contains("(#from-sources"));
+        Ok(())
+    }
+
     #[test]
     fn test_html_to_markdown_to_cdm() -> anyhow::Result<()> {
         init_logger();
@@ -895,14 +2432,705 @@ blabla2 bla bla blabla bla bla blabla bla bla blabla bla bla bla"#;
             html::Transformer::parse_with_loader(input, disk_image_loader("test/data"))?;
         info!("{:#?}", doc_from_html);
         let parsed_html_bytes =
-            Transformer::generate_with_saver(&doc_from_html, disk_image_saver("test/data"))?;
+            Transformer::generate_with_saver(&doc_from_html, disk_image_saver(&output_dir()))?;
 
         let doc_from_markdown =
-            Transformer::parse_with_loader(&parsed_html_bytes, disk_image_loader("test/data"));
+            Transformer::parse_with_loader(&parsed_html_bytes, disk_image_loader(&output_dir()));
         info!("{:#?}", doc_from_markdown);
         info!("{}", std::str::from_utf8(&parsed_html_bytes)?);
         assert!(true);
 
         Ok(())
     }
+
+    #[test]
+    fn test_generate_options() -> anyhow::Result<()> {
+        let document = Document::new(vec![
+            Element::Paragraph {
+                elements: vec![Element::Text {
+                    text: "one two three four".to_string(),
+                    size: 8,
+                }],
+                direction: TextDirection::default(),
+            },
+            Element::List {
+                elements: vec![ListItem {
+                    element: Element::Text {
+                        text: "item".to_string(),
+                        size: 8,
+                    },
+                }],
+                numbered: false,
+                start: 1,
+                numbering: NumberingStyle::default(),
+            },
+        ]);
+        let mut options = GenerateOptions::default();
+        options.markdown.bullet = '*';
+        options.markdown.wrap = LineWrap::AtWidth(10);
+        let generated = Transformer::generate_with_options(&document, &options)?;
+        assert_eq!(generated, "one two\nthree four\n\n* item\n");
+
+        options.markdown.bullet = 'x';
+        assert!(matches!(
+            Transformer::generate_with_options(&document, &options),
+            Err(crate::Error::GenerationError(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_embed_images() -> anyhow::Result<()> {
+        let document = Document::new(vec![Element::Paragraph {
+            elements: vec![Element::Image(ImageData::new(
+                Bytes::from_static(b"\x89PNG\r\n\x1a\n"),
+                "Logo".to_string(),
+                "Logo".to_string(),
+                "data:image/png;base64,".to_string(),
+                "".to_string(),
+                ImageDimension::default(),
+            ))],
+            direction: TextDirection::default(),
+        }]);
+        let mut options = GenerateOptions::default();
+        options.markdown.embed_images = true;
+        let generated = Transformer::generate_with_options(&document, &options)?;
+        assert_eq!(
+            generated,
+            "![](data:image/png;base64,iVBORw0KGgo= \"Logo\")\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_frontmatter() -> anyhow::Result<()> {
+        let yaml = r#"---
+title: Release notes
+author:
+  name: Jane Doe
+  email: jane@example.com
+tags: [rust, docs]
+draft: false
+---
+
+# Changes
+"#;
+        let document = Transformer::parse(&Bytes::from(yaml))?;
+        assert_eq!(document.get_all_elements().len(), 1);
+        let metadata = &document.metadata;
+        assert_eq!(metadata.title.as_deref(), Some("Release notes"));
+        assert_eq!(metadata.author, None);
+        assert_eq!(metadata.keywords, vec!["rust", "docs"]);
+        assert_eq!(metadata.custom["author.email"], "jane@example.com");
+        assert_eq!(metadata.custom["draft"], "false");
+
+        let toml = "+++\ntitle = \"Notes\"\ndate = 2024-05-01\n+++\n\nText\n";
+        let document = Transformer::parse(&Bytes::from(toml))?;
+        assert_eq!(document.metadata.title.as_deref(), Some("Notes"));
+        assert_eq!(document.metadata.date.as_deref(), Some("2024-05-01"));
+
+        // written only when asked for
+        let generated = Transformer::generate(&document)?;
+        assert_eq!(generated, "Text\n");
+        let mut options = GenerateOptions::default();
+        options.markdown.frontmatter = true;
+        let generated = Transformer::generate_with_options(&document, &options)?;
+        assert_eq!(
+            generated,
+            "---\ntitle: Notes\ndate: 2024-05-01\n---\n\nText\n"
+        );
+        assert_eq!(Transformer::parse(&generated)?, document);
+        Ok(())
+    }
+
+    #[test]
+    fn test_gfm_extensions() -> anyhow::Result<()> {
+        let markdown = "Shiva converts documents[^1] between formats.\n\n\
+            - [x] Markdown ~~only~~\n\
+            - [ ] PDF\n\n\
+            [^1]: Most of them.\n";
+        let document = Transformer::parse(&Bytes::from(markdown))?;
+        let elements = document.get_all_elements();
+        assert_eq!(elements.len(), 2);
+        match elements[0] {
+            Element::Paragraph { elements, .. } => assert_eq!(
+                elements[1],
+                Element::Footnote {
+                    elements: vec![Text {
+                        text: "Most of them.".to_string(),
+                        size: 14
+                    }]
+                }
+            ),
+            element => panic!("Expected a paragraph: {:?}", element),
+        }
+        assert_eq!(elements[1].plain_text(), "[x] Markdown only\n[ ] PDF");
+        assert!(matches!(
+            elements[1].children()[0].children()[2],
+            Element::Styled {
+                style: TextStyle::Strikethrough,
+                ..
+            }
+        ));
+
+        let generated = Transformer::generate(&document)?;
+        assert_eq!(
+            std::str::from_utf8(&generated)?,
+            "Shiva converts documents[^1] between formats.\n\n\
+            - [x] Markdown ~~only~~\n\
+            - [ ] PDF\n\n\
+            [^1]:\n    Most of them.\n"
+        );
+        assert_eq!(Transformer::parse(&generated)?, document);
+        Ok(())
+    }
+
+    #[test]
+    fn test_flavors() -> anyhow::Result<()> {
+        let markdown = Bytes::from("| a | b |\n|---|---|\n| 1 | 2 |\n");
+        let parse = |flavor| {
+            let options = ParseOptions {
+                markdown: MarkdownParseOptions {
+                    flavor,
+                    ..MarkdownParseOptions::default()
+                },
+                ..ParseOptions::default()
+            };
+            Transformer::parse_with_options(&markdown, &options)
+        };
+        assert!(matches!(
+            parse(MarkdownFlavor::Gfm)?.get_all_elements()[0],
+            Table { .. }
+        ));
+        assert!(matches!(
+            parse(MarkdownFlavor::CommonMark)?.get_all_elements()[0],
+            Element::Paragraph { .. }
+        ));
+
+        let document = DocumentBuilder::new()
+            .paragraph(|p| p.text("Shiva").footnote("A Hindu deity"))
+            .build();
+        let mut options = GenerateOptions::default();
+        options.markdown.flavor = MarkdownFlavor::Pandoc;
+        let generated = Transformer::generate_with_options(&document, &options)?;
+        assert_eq!(generated, "Shiva[^1]\n\n[^1]:\n    A Hindu deity\n");
+        // CommonMark has no footnotes, the note stays in the text
+        options.markdown.flavor = MarkdownFlavor::CommonMark;
+        let generated = Transformer::generate_with_options(&document, &options)?;
+        assert_eq!(generated, "Shiva (A Hindu deity)\n");
+        assert_eq!("multimarkdown".parse(), Ok(MarkdownFlavor::MultiMarkdown));
+        Ok(())
+    }
+
+    #[test]
+    fn test_inline_code() -> anyhow::Result<()> {
+        let markdown = "Call `parse()` or ``a `b` c``.\n\n# The `main` function\n";
+        let document = Transformer::parse(&Bytes::from(markdown))?;
+        let elements = document.get_all_elements();
+        match elements[0] {
+            Element::Paragraph { elements, .. } => {
+                assert_eq!(
+                    elements[1],
+                    Element::InlineCode {
+                        text: "parse()".to_string()
+                    }
+                );
+                assert_eq!(
+                    elements[3],
+                    Element::InlineCode {
+                        text: "a `b` c".to_string()
+                    }
+                );
+            }
+            element => panic!("Expected a paragraph: {:?}", element),
+        }
+        // headers hold plain text
+        assert_eq!(elements[1].plain_text(), "The main function");
+
+        let generated = Transformer::generate(&document)?;
+        assert_eq!(
+            std::str::from_utf8(&generated)?,
+            "Call `parse()` or ``a `b` c``.\n\n# The main function\n"
+        );
+        let html = html::Transformer::generate(&document)?;
+        assert!(std::str::from_utf8(&html)?.contains("<code>parse()</code>"));
+        let parsed = html::Transformer::parse(&html)?;
+        assert_eq!(
+            parsed.get_all_elements()[0].children()[1],
+            &Element::InlineCode {
+                text: "parse()".to_string()
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_code_blocks() -> anyhow::Result<()> {
+        let markdown = "``` rust,ignore\nfn main() {\n    println!(\"<hi>\");\n}\n```\n\n    indented\n    code\n";
+        let document = Transformer::parse(&Bytes::from(markdown))?;
+        let elements = document.get_all_elements();
+        assert_eq!(
+            elements[0],
+            &Element::CodeBlock {
+                info: "rust,ignore".to_string(),
+                code: "fn main() {\n    println!(\"<hi>\");\n}".to_string(),
+            }
+        );
+        assert_eq!(elements[0].code_language(), Some("rust"));
+        assert_eq!(
+            elements[1],
+            &Element::CodeBlock {
+                info: "".to_string(),
+                code: "indented\ncode".to_string(),
+            }
+        );
+
+        let generated = Transformer::generate(&document)?;
+        assert_eq!(std::str::from_utf8(&generated)?, markdown);
+
+        let html = html::Transformer::generate(&document)?;
+        let html_str = std::str::from_utf8(&html)?;
+        assert!(html_str.contains(
+            "<pre><code class=\"language-rust\">fn main() {\n    println!(\"&lt;hi&gt;\");\n}</code></pre>"
+        ));
+        let parsed = html::Transformer::parse(&html)?;
+        assert_eq!(parsed.get_all_elements()[0].code_language(), Some("rust"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_raw_html() -> anyhow::Result<()> {
+        let markdown =
+            "<details>\n<summary>More</summary>\n</details>\n\nPress <kbd>Ctrl</kbd> now\n";
+        let document = Transformer::parse(&Bytes::from(markdown))?;
+        let elements = document.get_all_elements();
+        assert_eq!(
+            elements[0],
+            &Element::Raw {
+                format: DocumentType::HTML,
+                content: "<details>\n<summary>More</summary>\n</details>".to_string(),
+            }
+        );
+        assert_eq!(
+            elements[1].children()[1],
+            &Element::Raw {
+                format: DocumentType::HTML,
+                content: "<kbd>".to_string(),
+            }
+        );
+
+        let generated = Transformer::generate(&document)?;
+        assert_eq!(std::str::from_utf8(&generated)?, markdown);
+
+        let html = html::Transformer::generate(&document)?;
+        let html_str = std::str::from_utf8(&html)?;
+        assert!(html_str.contains("<details>\n<summary>More</summary>\n</details>"));
+        assert!(html_str.contains("<kbd>Ctrl</kbd>"));
+
+        let report = crate::report::conversion_report(&document, DocumentType::PDF);
+        assert_eq!(report.losses.len(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_sanitize_raw_html() -> anyhow::Result<()> {
+        let markdown = "<script>alert(1)</script>\n\n\
+            <div onclick=\"alert(2)\" class=\"box\">\n<iframe src=\"https://example.com\"></iframe>\n</div>\n\n\
+            Click <a href=\" java&#9;script:alert(3)\" title=\"Go\">here</a> \
+            <img src=x onerror=alert(4)><br/>\n";
+        let options = ParseOptions {
+            markdown: MarkdownParseOptions {
+                sanitize: true,
+                ..MarkdownParseOptions::default()
+            },
+            ..Default::default()
+        };
+        let document = Transformer::parse_with_options(&Bytes::from(markdown), &options)?;
+        let html = html::Transformer::generate(&document)?;
+        let html = std::str::from_utf8(&html)?;
+        assert!(html.contains("<div class=\"box\">\n\n</div>"), "{}", html);
+        assert!(html.contains("Click <a title=\"Go\">here</a> <img src=\"x\"><br/>"));
+        for unsafe_html in ["alert(", "<script", "<iframe", "onclick", "onerror"] {
+            assert!(!html.contains(unsafe_html), "{}", html);
+        }
+
+        // a quote left open in a tag does not hide its event handler
+        let unclosed = "<div>\n<img src=x onerror=alert(1) '>\n</div>\n";
+        let document = Transformer::parse_with_options(&Bytes::from(unclosed), &options)?;
+        let html = html::Transformer::generate(&document)?;
+        let html = std::str::from_utf8(&html)?;
+        assert!(html.contains("<img src=\"x\">"), "{}", html);
+        for unsafe_html in ["alert(", "onerror"] {
+            assert!(!html.contains(unsafe_html), "{}", html);
+        }
+
+        // without sanitizing, the raw HTML is written as it is
+        let document = Transformer::parse(&Bytes::from(markdown))?;
+        let html = html::Transformer::generate(&document)?;
+        assert!(std::str::from_utf8(&html)?.contains("<script>alert(1)</script>"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_nested_lists() -> anyhow::Result<()> {
+        let markdown = "- one\n  - two\n    - three\n      - four\n- five\n\n  more about five\n\n  1. six\n  2. seven\n\n  ```sh\n  cargo run\n  ```\n- see [docs](https://docs.rs) and `code`\n";
+        let document = Transformer::parse(&Bytes::from(markdown))?;
+        let elements = document.get_all_elements();
+        assert_eq!(elements.len(), 1);
+        let text = |text: &str| Element::Text {
+            text: text.to_string(),
+            size: 14,
+        };
+        let list = |numbered: bool, items: Vec<Element>| Element::List {
+            elements: items
+                .into_iter()
+                .map(|element| ListItem { element })
+                .collect(),
+            numbered,
+            start: 1,
+            numbering: NumberingStyle::default(),
+        };
+        let nested = list(
+            false,
+            vec![
+                text("two"),
+                list(false, vec![text("three"), list(false, vec![text("four")])]),
+            ],
+        );
+        let Element::List {
+            elements: items, ..
+        } = elements[0]
+        else {
+            panic!("Expected List");
+        };
+        assert_eq!(items[0].element, text("one"));
+        assert_eq!(items[1].element, nested);
+        assert_eq!(
+            items[2].element,
+            Element::Paragraph {
+                elements: vec![text("five"), Element::LineBreak, text("more about five")],
+                direction: TextDirection::default(),
+            }
+        );
+        assert_eq!(
+            items[3].element,
+            list(true, vec![text("six"), text("seven")])
+        );
+        assert_eq!(
+            items[4].element,
+            Element::CodeBlock {
+                info: "sh".to_string(),
+                code: "cargo run".to_string(),
+            }
+        );
+        assert_eq!(items[5].element.children().len(), 4);
+
+        let readme = "# Shiva\n\n## Usage\n\n1. Install:\n\n   ```sh\n   cargo install shiva\n   ```\n\n2. Convert:\n   - to [HTML](https://example.com/html)\n   - to PDF\n     - with `--pdf-engine`\n\n   Then open the file.\n\n- [ ] docs\n- [x] tests\n";
+        let parsed = Transformer::parse(&Bytes::from(readme))?;
+        let generated = Transformer::generate(&parsed)?;
+        let reparsed = Transformer::parse(&generated)?;
+        assert_eq!(parsed.get_all_elements(), reparsed.get_all_elements());
+        Ok(())
+    }
+
+    #[test]
+    fn test_wiki_links() -> anyhow::Result<()> {
+        let markdown = Bytes::from(
+            "See [[Page Name]], [[Other#Part|the part]] and `[[code]]`.\n\n![[picture.png]]\n",
+        );
+        let options = ParseOptions {
+            markdown: MarkdownParseOptions {
+                flavor: MarkdownFlavor::Obsidian,
+                ..MarkdownParseOptions::default()
+            },
+            ..ParseOptions::default()
+        };
+        let loaded = RefCell::new(vec![]);
+        let document = Transformer::parse_with_wiki_link_resolver(
+            &markdown,
+            |url: &str| {
+                loaded.borrow_mut().push(url.to_string());
+                Ok(Bytes::from_static(b"\x89PNG"))
+            },
+            |target: &str| format!("notes/{}", wiki_link_url(target)),
+            &options,
+        )?;
+        assert_eq!(*loaded.borrow(), ["notes/picture.png"]);
+        let elements = document.get_all_elements();
+        let urls: Vec<(&str, String)> = elements[0]
+            .children()
+            .into_iter()
+            .filter_map(|element| match element {
+                Hyperlink { url, .. } => Some((url.as_str(), element.plain_text())),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            urls,
+            [
+                ("notes/Page Name.md", "Page Name".to_string()),
+                ("notes/Other.md#Part", "the part".to_string())
+            ]
+        );
+        assert!(elements[0]
+            .children()
+            .iter()
+            .any(|element| matches!(element, Element::InlineCode { .. })));
+        assert!(matches!(
+            elements[1].children()[..],
+            [Element::Image(image)] if image.alt() == "picture.png"
+        ));
+
+        let gfm = Transformer::parse(&markdown)?;
+        assert!(gfm.get_all_elements()[0]
+            .plain_text()
+            .contains("[[Page Name]]"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_heading_attributes() -> anyhow::Result<()> {
+        let markdown = "# Title {#intro .lead .wide data-x=1}\n\n## Plain\n";
+        let options = ParseOptions {
+            markdown: MarkdownParseOptions {
+                flavor: MarkdownFlavor::Pandoc,
+                ..MarkdownParseOptions::default()
+            },
+            ..ParseOptions::default()
+        };
+        let document = Transformer::parse_with_options(&Bytes::from(markdown), &options)?;
+        let elements = document.get_all_elements();
+        let Element::Attributed {
+            attributes,
+            element,
+        } = elements[0]
+        else {
+            panic!("attributed header expected, got {:?}", elements[0]);
+        };
+        assert_eq!(attributes["id"], "intro");
+        assert_eq!(attributes["class"], "lead wide");
+        assert_eq!(attributes["data-x"], "1");
+        assert_eq!(
+            **element,
+            Header {
+                level: 1,
+                text: "Title".to_string()
+            }
+        );
+        assert!(matches!(elements[1], Header { .. }));
+
+        let generate = |flavor| {
+            let options = MarkdownOptions {
+                flavor,
+                ..MarkdownOptions::default()
+            };
+            Transformer::generate_with_image_saver(&document, |_, _| Ok(()), &options)
+        };
+        assert_eq!(
+            std::str::from_utf8(&generate(MarkdownFlavor::Pandoc)?)?,
+            "# Title {#intro .lead .wide data-x=1}\n\n## Plain\n"
+        );
+        assert_eq!(
+            std::str::from_utf8(&generate(MarkdownFlavor::Gfm)?)?,
+            "# Title\n\n## Plain\n"
+        );
+
+        let html = html::Transformer::generate(&document)?;
+        assert!(std::str::from_utf8(&html)?.contains("id=\"intro\""));
+        let toc = document.build_toc(2);
+        let Element::List { elements, .. } = &toc else {
+            panic!("list expected, got {:?}", toc);
+        };
+        assert!(matches!(
+            &elements[0].element,
+            Hyperlink { url, .. } if url == "#intro"
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_emoji() -> anyhow::Result<()> {
+        let markdown = Bytes::from("# Done :sparkles:\n\nShip it :rocket: at 10:30, `:rocket:`\n");
+        let options = ParseOptions {
+            markdown: MarkdownParseOptions {
+                emoji: true,
+                ..MarkdownParseOptions::default()
+            },
+            ..ParseOptions::default()
+        };
+        let document = Transformer::parse_with_options(&markdown, &options)?;
+        let generated = Transformer::generate(&document)?;
+        assert_eq!(
+            std::str::from_utf8(&generated)?,
+            "# Done ✨\n\nShip it 🚀 at 10:30, `:rocket:`\n"
+        );
+        let options = MarkdownOptions {
+            emoji_shortcodes: true,
+            ..MarkdownOptions::default()
+        };
+        let generated = Transformer::generate_with_image_saver(&document, |_, _| Ok(()), &options)?;
+        assert_eq!(generated, markdown);
+        Ok(())
+    }
+
+    #[test]
+    fn test_sized_images() -> anyhow::Result<()> {
+        let markdown = Bytes::from(
+            "![Logo](logo.png =200x100) and ![Wide](<wide.png> \"title\" =50%x)\n\n`![Code](code.png =1x1)`\n",
+        );
+        let loaded = RefCell::new(vec![]);
+        let document = Transformer::parse_with_loader(&markdown, |url: &str| {
+            loaded.borrow_mut().push(url.to_string());
+            Ok(Bytes::from_static(b"\x89PNG"))
+        })?;
+        assert_eq!(*loaded.borrow(), ["logo.png", "wide.png"]);
+        let elements = document.get_all_elements();
+        let images: Vec<&ImageData> = elements[0]
+            .children()
+            .into_iter()
+            .filter_map(|element| match element {
+                Element::Image(image) => Some(image),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            *images[0].size(),
+            ImageDimension {
+                width: Some("200".to_string()),
+                height: Some("100".to_string()),
+            }
+        );
+        assert_eq!(images[0].alt(), "Logo");
+        assert_eq!(images[1].title(), "title");
+        assert_eq!(
+            *images[1].size(),
+            ImageDimension {
+                width: Some("50%".to_string()),
+                height: None,
+            }
+        );
+        let html =
+            html::Transformer::generate_with_saver(&document, disk_image_saver(&output_dir()))?;
+        assert!(std::str::from_utf8(&html)?.contains(" width=\"200\" height=\"100\""));
+        Ok(())
+    }
+
+    #[test]
+    fn test_autolink() -> anyhow::Result<()> {
+        let markdown = Bytes::from(
+            "Go to https://example.com, not `https://code.org` or [x](https://y.org).\n",
+        );
+        let options = ParseOptions {
+            autolink: true,
+            ..ParseOptions::default()
+        };
+        let document = Transformer::parse_with_options(&markdown, &options)?;
+        let children = document.get_all_elements()[0].children();
+        assert!(matches!(
+            children[1],
+            Element::Hyperlink { url, .. } if url == "https://example.com"
+        ));
+        assert!(matches!(children[2], Element::Text { text, .. } if text == ", not "));
+        assert!(matches!(children[3], Element::InlineCode { .. }));
+        assert_eq!(
+            children
+                .iter()
+                .filter(|element| matches!(element, Element::Hyperlink { .. }))
+                .count(),
+            2
+        );
+        let generated = Transformer::generate(&document)?;
+        assert_eq!(
+            std::str::from_utf8(&generated)?,
+            "Go to <https://example.com>, not `https://code.org` or [x](https://y.org).\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_styled_text() -> anyhow::Result<()> {
+        let markdown = "Some *emphasis*, **strong [link](https://example.com)** and ~~gone `code`~~, ***both***.\n";
+        let document = Transformer::parse(&Bytes::from(markdown))?;
+        let paragraph = document.get_all_elements()[0];
+        let styled = |style: TextStyle, elements: Vec<Element>| Element::Styled { style, elements };
+        let text = |text: &str| Element::Text {
+            text: text.to_string(),
+            size: 14,
+        };
+        let children = paragraph.children();
+        assert_eq!(
+            children[1],
+            &styled(TextStyle::Emphasis, vec![text("emphasis")])
+        );
+        assert_eq!(
+            children[3],
+            &styled(
+                TextStyle::Strong,
+                vec![
+                    text("strong "),
+                    Element::Hyperlink {
+                        elements: vec![text("link")],
+                        url: "https://example.com".to_string(),
+                        alt: "".to_string(),
+                        size: 14,
+                    }
+                ]
+            )
+        );
+        assert_eq!(
+            children[5],
+            &styled(
+                TextStyle::Strikethrough,
+                vec![
+                    text("gone "),
+                    Element::InlineCode {
+                        text: "code".to_string()
+                    }
+                ]
+            )
+        );
+        assert_eq!(
+            children[7],
+            &styled(
+                TextStyle::Emphasis,
+                vec![styled(TextStyle::Strong, vec![text("both")])]
+            )
+        );
+
+        let generated = Transformer::generate(&document)?;
+        assert_eq!(std::str::from_utf8(&generated)?, markdown);
+
+        let html = html::Transformer::generate(&document)?;
+        let html_str = std::str::from_utf8(&html)?;
+        assert!(html_str.contains("<em>emphasis</em>"));
+        assert!(html_str.contains("<del>gone <code>code</code></del>"));
+        let parsed = html::Transformer::parse(&html)?;
+        assert!(matches!(
+            parsed.get_all_elements()[0].children()[1],
+            Element::Styled {
+                style: TextStyle::Emphasis,
+                ..
+            }
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_reference_links() -> anyhow::Result<()> {
+        let markdown =
+            "See [the docs](https://docs.rs/shiva \"API\") and [crates](https://crates.io), \
+            the [API](https://docs.rs/shiva \"API\") or <https://github.com>.\n";
+        let document = Transformer::parse(&Bytes::from(markdown))?;
+        let mut options = GenerateOptions::default();
+        options.markdown.reference_links = true;
+        let generated = Transformer::generate_with_options(&document, &options)?;
+        assert_eq!(
+            std::str::from_utf8(&generated)?,
+            "See [the docs][1] and [crates][2], the [API][1] or <https://github.com>.\n\n\
+            [1]: https://docs.rs/shiva \"API\"\n\
+            [2]: https://crates.io\n"
+        );
+        assert_eq!(Transformer::parse(&generated)?, document);
+        Ok(())
+    }
 }
@@ -0,0 +1,158 @@
+//! Text recognition of scanned PDF pages and of images.
+//!
+//! The parsers call the engine of [`ParseOptions::ocr`](crate::core::ParseOptions::ocr):
+//! any [`OcrEngine`], such as a closure or [`Tesseract`] with the `ocr` feature. The
+//! image is replaced by the paragraphs of its text when some is recognized.
+
+use std::fmt;
+use std::sync::Arc;
+
+use crate::core::Element::{Paragraph, Text};
+use crate::core::{Element, ImageData, TextDirection};
+
+/// Size of the texts recognized, which have no font
+const OCR_TEXT_SIZE: u8 = 12;
+
+/// Recognizes the text of images
+pub trait OcrEngine: Send + Sync {
+    /// Text of the image, its paragraphs separated by blank lines
+    fn recognize(&self, image: &ImageData) -> anyhow::Result<String>;
+}
+
+impl<F> OcrEngine for F
+where
+    F: Fn(&ImageData) -> anyhow::Result<String> + Send + Sync,
+{
+    fn recognize(&self, image: &ImageData) -> anyhow::Result<String> {
+        self(image)
+    }
+}
+
+/// Shared OCR engine of the parse options, equal only to its clones
+#[derive(Clone)]
+pub struct Ocr(Arc<dyn OcrEngine>);
+
+impl Ocr {
+    pub fn new(engine: impl OcrEngine + 'static) -> Ocr {
+        Ocr(Arc::new(engine))
+    }
+
+    /// Paragraphs of the text recognized in the image, none when it has no text
+    pub fn paragraphs(&self, image: &ImageData) -> anyhow::Result<Vec<Element>> {
+        Ok(paragraphs(&self.0.recognize(image)?))
+    }
+}
+
+impl fmt::Debug for Ocr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Ocr")
+    }
+}
+
+impl PartialEq for Ocr {
+    fn eq(&self, other: &Ocr) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+/// Paragraphs of a recognized text, the words hyphenated at the end of a line joined
+fn paragraphs(text: &str) -> Vec<Element> {
+    let mut paragraphs = Vec::new();
+    let mut paragraph = String::new();
+    for line in text.lines().map(str::trim).chain(std::iter::once("")) {
+        if line.is_empty() {
+            if !paragraph.is_empty() {
+                paragraphs.push(Paragraph {
+                    elements: vec![Text {
+                        text: std::mem::take(&mut paragraph),
+                        size: OCR_TEXT_SIZE,
+                    }],
+                    direction: TextDirection::default(),
+                });
+            }
+        } else if paragraph.ends_with('-') && line.starts_with(char::is_lowercase) {
+            paragraph.pop();
+            paragraph.push_str(line);
+        } else {
+            if !paragraph.is_empty() {
+                paragraph.push(' ');
+            }
+            paragraph.push_str(line);
+        }
+    }
+    paragraphs
+}
+
+/// Engine running the `tesseract` command, which must be installed
+#[cfg(feature = "ocr")]
+#[derive(Debug, Clone)]
+pub struct Tesseract {
+    /// Path or name of the command
+    pub command: std::path::PathBuf,
+    /// Languages of the text, such as `eng` or `eng+deu`, tesseract's default when none
+    pub language: Option<String>,
+}
+
+#[cfg(feature = "ocr")]
+impl Default for Tesseract {
+    fn default() -> Tesseract {
+        Tesseract {
+            command: "tesseract".into(),
+            language: None,
+        }
+    }
+}
+
+#[cfg(feature = "ocr")]
+impl OcrEngine for Tesseract {
+    fn recognize(&self, image: &ImageData) -> anyhow::Result<String> {
+        use anyhow::{bail, Context};
+        use std::io::Write;
+        use std::process::{Command, Stdio};
+
+        let mut command = Command::new(&self.command);
+        command.args(["stdin", "stdout"]);
+        if let Some(language) = &self.language {
+            command.args(["-l", language]);
+        }
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("Cannot run {}", self.command.display()))?;
+        // tesseract reads the whole image before writing the text
+        child
+            .stdin
+            .take()
+            .context("No input of tesseract")?
+            .write_all(image.bytes())?;
+        let output = child.wait_with_output()?;
+        if !output.status.success() {
+            bail!(
+                "tesseract failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_paragraphs() {
+        let elements = paragraphs("First line of a hyphen-\nated paragraph\n\n\nSecond one\n");
+        let texts: Vec<String> = elements.iter().map(Element::plain_text).collect();
+        assert_eq!(
+            texts,
+            vec!["First line of a hyphenated paragraph", "Second one"]
+        );
+
+        let ocr = Ocr::new(|_: &ImageData| Ok("Text".to_string()));
+        assert_eq!(ocr, ocr.clone());
+        assert_ne!(ocr, Ocr::new(|_: &ImageData| Ok("Text".to_string())));
+    }
+}
@@ -11,10 +11,17 @@ use std::vec;
 pub struct Transformer;
 
 impl TransformerTrait for Transformer {
-    fn parse(document: &Bytes) -> anyhow::Result<Document>
-    where
-        Self: Sized,
-    {
+    fn parse(document: &Bytes) -> crate::Result<Document> {
+        Transformer::parse_bytes(document).map_err(crate::Error::parse_error)
+    }
+
+    fn generate(document: &Document) -> crate::Result<Bytes> {
+        Transformer::generate_bytes(document).map_err(crate::Error::generation_error)
+    }
+}
+
+impl Transformer {
+    fn parse_bytes(document: &Bytes) -> anyhow::Result<Document> {
         let cursor = Cursor::new(document.clone());
 
         let mut workbook: Ods<Cursor<Bytes>> =
@@ -69,10 +76,8 @@ impl TransformerTrait for Transformer {
         Ok(Document::new(data))
     }
 
-    fn generate(document: &Document) -> anyhow::Result<Bytes>
-    where
-        Self: Sized,
-    {
+    fn generate_bytes(document: &Document) -> anyhow::Result<Bytes> {
+        let document = &document.resolve_fields();
         let mut workbook = WorkBook::new(locale!("en_US"));
         fn generate_element(
             element: &Element,
@@ -121,7 +126,7 @@ impl TransformerTrait for Transformer {
 
 #[cfg(test)]
 mod tests {
-    use crate::core::tests::init_logger;
+    use crate::core::tests::{init_logger, output_dir, table_texts};
     use crate::ods::*;
     use anyhow::Ok;
     use bytes::Bytes;
@@ -157,13 +162,53 @@ mod tests {
         let bytes = Bytes::from(buffer);
         let parsed = Transformer::parse(&bytes)?;
 
-        let generated_data: Result<bytes::Bytes, anyhow::Error> = Transformer::generate(&parsed);
+        let generated_data: crate::Result<bytes::Bytes> = Transformer::generate(&parsed);
 
         let bytes_to_write = generated_data?;
-        std::fs::write("test/data/test_document.ods", bytes_to_write)?;
+        std::fs::write(
+            format!("{}/test_document.ods", output_dir()),
+            bytes_to_write,
+        )?;
 
         info!("Excel file created successfully!");
 
         Ok(())
     }
+
+    #[test]
+    fn test_generate_fields() -> anyhow::Result<()> {
+        init_logger();
+        let row = |element| TableRow {
+            cells: vec![TableCell { element }],
+        };
+        let document = Document::new(vec![
+            Element::Header {
+                level: 1,
+                text: "Prices".to_string(),
+            },
+            Table {
+                headers: vec![],
+                rows: vec![
+                    row(Element::CrossReference {
+                        target_id: "prices".to_string(),
+                        kind: ReferenceKind::Section,
+                    }),
+                    row(Element::Field {
+                        kind: FieldKind::DocumentTitle,
+                    }),
+                ],
+            },
+        ]);
+        let parsed = Transformer::parse(&Transformer::generate(&document)?)?;
+        let table = parsed
+            .get_all_elements()
+            .into_iter()
+            .find(|element| matches!(element, Table { .. }))
+            .expect("no table");
+        // the first row of a sheet is read as the header
+        let (headers, rows) = table_texts(table);
+        assert_eq!(headers, ["Section 1"]);
+        assert_eq!(rows, [vec!["Prices"]]);
+        Ok(())
+    }
 }
@@ -0,0 +1,1644 @@
+//! OpenDocument text (ODT), the format of LibreOffice Writer
+//!
+//! A document is a ZIP archive of XML parts: the body and its automatic styles in
+//! `content.xml`, the named styles and the page in `styles.xml`, the properties in `meta.xml`
+//! and the images in `Pictures/`.
+//! Elements and attributes are matched by their names with the usual namespace prefixes
+//! (`text:p`, `table:table`...), the prefixes all the office suites write.
+
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::io::{Cursor, Read, Write};
+
+use anyhow::Context;
+use bytes::Bytes;
+use log::warn;
+use quick_xml::escape::escape;
+use quick_xml::events::Event;
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::core::{
+    Band, Document, Element, FieldKind, ImageData, ImageDimension, Length, LengthUnit, ListItem,
+    Metadata, NumberingStyle, PageOrientation, TableCell, TableHeader, TableRow, TextStyle,
+    TransformerTrait, DEFAULT_IMAGE_SIZE,
+};
+use crate::util::{
+    block_element, header_bookmark, is_block, item_element, merge_styled, RunFormat, BULLETS,
+    HEADING_SIZES,
+};
+
+pub struct Transformer;
+
+const MIME_TYPE: &str = "application/vnd.oasis.opendocument.text";
+
+/// Namespaces declared on the root of the XML parts
+const NAMESPACES: &str = concat!(
+    r#"xmlns:office="urn:oasis:names:tc:opendocument:xmlns:office:1.0" "#,
+    r#"xmlns:style="urn:oasis:names:tc:opendocument:xmlns:style:1.0" "#,
+    r#"xmlns:text="urn:oasis:names:tc:opendocument:xmlns:text:1.0" "#,
+    r#"xmlns:table="urn:oasis:names:tc:opendocument:xmlns:table:1.0" "#,
+    r#"xmlns:draw="urn:oasis:names:tc:opendocument:xmlns:drawing:1.0" "#,
+    r#"xmlns:fo="urn:oasis:names:tc:opendocument:xmlns:xsl-fo-compatible:1.0" "#,
+    r#"xmlns:svg="urn:oasis:names:tc:opendocument:xmlns:svg-compatible:1.0" "#,
+    r#"xmlns:xlink="http://www.w3.org/1999/xlink" "#,
+    r#"xmlns:dc="http://purl.org/dc/elements/1.1/" "#,
+    r#"xmlns:meta="urn:oasis:names:tc:opendocument:xmlns:meta:1.0" "#,
+    r#"office:version="1.3""#,
+);
+
+// region:    ---writer
+
+const BODY_STYLE: &str = "Text_20_body";
+const LIST_STYLE: &str = "List";
+const CODE_BLOCK_STYLE: &str = "Preformatted_20_Text";
+const TABLE_CONTENTS_STYLE: &str = "Table_20_Contents";
+const TABLE_HEADING_STYLE: &str = "Table_20_Heading";
+const FOOTNOTE_STYLE: &str = "Footnote";
+const CODE_STYLE: &str = "Source_20_Text";
+const LINK_STYLE: &str = "Internet_20_link";
+const BULLET_LIST_STYLE: &str = "List_20_1";
+
+/// Levels of the list styles
+const LIST_LEVELS: usize = 10;
+
+/// Highest height of an image, in pixels (24 cm), images are also shrunk to the width of
+/// the text
+const MAX_IMAGE_HEIGHT: f32 = 907.0;
+
+/// Character style of a text style
+fn text_style_name(style: &TextStyle) -> &'static str {
+    match style {
+        TextStyle::Emphasis => "Emphasis",
+        TextStyle::Strong => "Strong_20_Emphasis",
+        TextStyle::Strikethrough => "Strikethrough",
+    }
+}
+
+/// List style and `style:num-format` of a numbering, the names are those of LibreOffice
+fn numbering_style(numbering: &NumberingStyle) -> (&'static str, &'static str) {
+    match numbering {
+        NumberingStyle::Decimal => ("Numbering_20_123", "1"),
+        NumberingStyle::LowerAlpha => ("Numbering_20_abc", "a"),
+        NumberingStyle::UpperAlpha => ("Numbering_20_ABC", "A"),
+        NumberingStyle::LowerRoman => ("Numbering_20_ivx", "i"),
+        NumberingStyle::UpperRoman => ("Numbering_20_IVX", "I"),
+    }
+}
+
+/// Writes elements as the XML of the body, collecting the images and the automatic styles
+/// of the tables
+struct Writer<'a> {
+    document: &'a Document,
+    xml: String,
+    automatic_styles: String,
+    /// Path in the archive, media type and content of the images
+    images: Vec<(String, String, Bytes)>,
+    tables: usize,
+    notes: usize,
+    indexes: usize,
+    /// Width of the text of the page, in centimeters
+    text_width: f32,
+}
+
+impl<'a> Writer<'a> {
+    fn new(document: &'a Document) -> Writer<'a> {
+        let page = document.page_format.dimensions();
+        let page_width = match document.orientation {
+            PageOrientation::Portrait => page.page_width,
+            PageOrientation::Landscape => page.page_height,
+        };
+        let text_width =
+            page_width.to_mm() - page.page_margin_left.to_mm() - page.page_margin_right.to_mm();
+        Writer {
+            document,
+            xml: String::new(),
+            automatic_styles: String::new(),
+            images: vec![],
+            tables: 0,
+            notes: 0,
+            indexes: 0,
+            text_width: text_width.max(10.0) / 10.0,
+        }
+    }
+
+    fn block(&mut self, element: &Element) {
+        match element {
+            Element::Header { level, text } => {
+                let (level, anchor) = header_bookmark(*level, text);
+                let _ = write!(
+                    self.xml,
+                    r#"<text:h text:style-name="Heading_20_{level}" text:outline-level="{level}"><text:bookmark text:name="{}"/>"#,
+                    escape(&anchor)
+                );
+                self.text(text);
+                self.xml.push_str("</text:h>");
+            }
+            Element::Paragraph { elements, .. } => self.paragraph(BODY_STYLE, elements),
+            Element::List { .. } => self.list(element),
+            Element::TableOfContents { max_level } => {
+                self.indexes += 1;
+                let _ = write!(
+                    self.xml,
+                    r#"<text:table-of-content text:name="Table of Contents{}" text:protected="true"><text:table-of-content-source text:outline-level="{max_level}"/><text:index-body>"#,
+                    self.indexes
+                );
+                self.list(&self.document.build_toc(*max_level));
+                self.xml
+                    .push_str("</text:index-body></text:table-of-content>");
+            }
+            Element::Table { headers, rows } => self.table(headers, rows),
+            Element::CodeBlock { code, .. } => {
+                let _ = write!(self.xml, r#"<text:p text:style-name="{CODE_BLOCK_STYLE}">"#);
+                self.text(code.strip_suffix('\n').unwrap_or(code));
+                self.xml.push_str("</text:p>");
+            }
+            Element::LineBreak => {
+                let _ = write!(self.xml, r#"<text:p text:style-name="{BODY_STYLE}"/>"#);
+            }
+            // content of another format
+            Element::Raw { .. } => {}
+            element => self.paragraph(BODY_STYLE, [element]),
+        }
+    }
+
+    /// Writes the blocks of the elements, the elements between them making paragraphs of
+    /// the given style
+    fn content<'e>(&mut self, elements: impl IntoIterator<Item = &'e Element>, style: &str) {
+        let mut inline: Vec<&Element> = vec![];
+        for element in elements {
+            if !is_block(element) {
+                inline.push(element);
+                continue;
+            }
+            if !inline.is_empty() {
+                self.paragraph(style, std::mem::take(&mut inline));
+            }
+            match element {
+                Element::Paragraph { elements, .. } => self.paragraph(style, elements),
+                element => self.block(element),
+            }
+        }
+        if !inline.is_empty() {
+            self.paragraph(style, inline);
+        }
+    }
+
+    fn paragraph<'e>(&mut self, style: &str, elements: impl IntoIterator<Item = &'e Element>) {
+        let _ = write!(self.xml, r#"<text:p text:style-name="{style}">"#);
+        for element in elements {
+            self.inline(element);
+        }
+        self.xml.push_str("</text:p>");
+    }
+
+    fn inline(&mut self, element: &Element) {
+        match element {
+            Element::Text { text, .. } => self.text(text),
+            Element::LineBreak => self.xml.push_str("<text:line-break/>"),
+            Element::InlineCode { text } => {
+                let _ = write!(self.xml, r#"<text:span text:style-name="{CODE_STYLE}">"#);
+                self.text(text);
+                self.xml.push_str("</text:span>");
+            }
+            Element::Styled { style, elements } => {
+                let _ = write!(
+                    self.xml,
+                    r#"<text:span text:style-name="{}">"#,
+                    text_style_name(style)
+                );
+                for element in elements {
+                    self.inline(element);
+                }
+                self.xml.push_str("</text:span>");
+            }
+            Element::Hyperlink {
+                elements, url, alt, ..
+            } => {
+                let _ = write!(
+                    self.xml,
+                    r#"<text:a xlink:type="simple" xlink:href="{}" text:style-name="{LINK_STYLE}""#,
+                    escape(url)
+                );
+                if !alt.is_empty() {
+                    let _ = write!(self.xml, r#" office:title="{}""#, escape(alt));
+                }
+                self.xml.push('>');
+                if elements.is_empty() {
+                    self.text(url);
+                }
+                for element in elements {
+                    self.inline(element);
+                }
+                self.xml.push_str("</text:a>");
+            }
+            Element::Image(image) => self.image(image),
+            Element::Footnote { elements } => {
+                self.notes += 1;
+                let _ = write!(
+                    self.xml,
+                    r#"<text:note text:id="ftn{0}" text:note-class="footnote"><text:note-citation>{0}</text:note-citation><text:note-body>"#,
+                    self.notes
+                );
+                self.content(elements, FOOTNOTE_STYLE);
+                self.xml.push_str("</text:note-body></text:note>");
+            }
+            Element::Field { kind } => self.xml.push_str(match kind {
+                FieldKind::PageNumber => r#"<text:page-number text:select-page="current"/>"#,
+                FieldKind::TotalPages => "<text:page-count/>",
+                FieldKind::Date => "<text:date/>",
+                FieldKind::DocumentTitle => "<text:title/>",
+            }),
+            Element::Bookmark { id, .. } => {
+                let _ = write!(self.xml, r#"<text:bookmark text:name="{}"/>"#, escape(id));
+            }
+            Element::Paragraph { elements, .. } => {
+                for element in elements {
+                    self.inline(element);
+                }
+            }
+            Element::Attributed { element, .. } => self.inline(element),
+            Element::Raw { .. } => {}
+            element => self.text(&element.plain_text()),
+        }
+    }
+
+    /// Escaped text, with the spaces that would be collapsed, the tabs and the line breaks
+    /// written as elements
+    fn text(&mut self, text: &str) {
+        let mut chars = text.chars().peekable();
+        let mut collapsible = true;
+        while let Some(c) = chars.next() {
+            match c {
+                ' ' => {
+                    let mut count = 1;
+                    while chars.next_if_eq(&' ').is_some() {
+                        count += 1;
+                    }
+                    if !collapsible {
+                        self.xml.push(' ');
+                        count -= 1;
+                    }
+                    match count {
+                        0 => {}
+                        1 => self.xml.push_str("<text:s/>"),
+                        count => {
+                            let _ = write!(self.xml, r#"<text:s text:c="{count}"/>"#);
+                        }
+                    }
+                    collapsible = true;
+                    continue;
+                }
+                '\t' => self.xml.push_str("<text:tab/>"),
+                '\n' => self.xml.push_str("<text:line-break/>"),
+                '&' => self.xml.push_str("&amp;"),
+                '<' => self.xml.push_str("&lt;"),
+                '>' => self.xml.push_str("&gt;"),
+                // not allowed in XML
+                c if c.is_control() => {}
+                c => self.xml.push(c),
+            }
+            collapsible = matches!(c, '\t' | '\n');
+        }
+    }
+
+    /// Image as a character of the text, its file added to the images
+    fn image(&mut self, image: &ImageData) {
+        if image.bytes().is_empty() {
+            warn!("Image without content left out: {}", image.title());
+            return;
+        }
+        let number = self.images.len() + 1;
+        let path = format!(
+            "Pictures/image{number}{}",
+            image.image_type().to_extension()
+        );
+        let (width, height) = self.image_size(image);
+        let _ = write!(
+            self.xml,
+            r#"<draw:frame draw:name="Image{number}" text:anchor-type="as-char" svg:width="{:.4}in" svg:height="{:.4}in"><draw:image xlink:href="{path}" xlink:type="simple" xlink:show="embed" xlink:actuate="onLoad"/>"#,
+            width / 96.0,
+            height / 96.0
+        );
+        if !image.title().is_empty() {
+            let _ = write!(self.xml, "<svg:title>{}</svg:title>", escape(image.title()));
+        }
+        if !image.alt().is_empty() {
+            let _ = write!(self.xml, "<svg:desc>{}</svg:desc>", escape(image.alt()));
+        }
+        self.xml.push_str("</draw:frame>");
+        self.images.push((
+            path,
+            image.image_type().mime_type().to_string(),
+            image.bytes().clone(),
+        ));
+    }
+
+    /// Size of an image in pixels, its own size or else the size of its picture, shrunk to
+    /// fit the page
+    fn image_size(&self, image: &ImageData) -> (f32, f32) {
+        let pixels = |value: &Option<String>| {
+            value
+                .as_deref()
+                .and_then(|value| value.trim().trim_end_matches("px").parse::<f32>().ok())
+                .filter(|value| *value > 0.0)
+        };
+        let size = image.size();
+        let (width, height) = match (pixels(&size.width), pixels(&size.height)) {
+            (Some(width), Some(height)) => (width, height),
+            _ => image::io::Reader::new(Cursor::new(image.bytes()))
+                .with_guessed_format()
+                .ok()
+                .and_then(|reader| reader.into_dimensions().ok())
+                .map(|(width, height)| (width as f32, height as f32))
+                .unwrap_or(DEFAULT_IMAGE_SIZE),
+        };
+        let max_width = Length::cm(self.text_width).to(LengthUnit::Px).value;
+        let scale = (max_width / width).min(MAX_IMAGE_HEIGHT / height).min(1.0);
+        (width * scale, height * scale)
+    }
+
+    /// List with the list style of its numbering, a nested list being an item of its own
+    fn list(&mut self, list: &Element) {
+        let Element::List {
+            elements,
+            numbered,
+            start,
+            numbering,
+        } = list
+        else {
+            return;
+        };
+        let style = if *numbered {
+            numbering_style(numbering).0
+        } else {
+            BULLET_LIST_STYLE
+        };
+        let _ = write!(self.xml, r#"<text:list text:style-name="{style}">"#);
+        for (index, item) in elements.iter().enumerate() {
+            if index == 0 && *numbered && *start != 1 {
+                let _ = write!(self.xml, r#"<text:list-item text:start-value="{start}">"#);
+            } else {
+                self.xml.push_str("<text:list-item>");
+            }
+            match &item.element {
+                nested @ Element::List { .. } => self.list(nested),
+                element => self.content([element], LIST_STYLE),
+            }
+            self.xml.push_str("</text:list-item>");
+        }
+        self.xml.push_str("</text:list>");
+    }
+
+    /// Table of equal columns across the text, the headers in its header row
+    fn table(&mut self, headers: &[TableHeader], rows: &[TableRow]) {
+        self.tables += 1;
+        let name = format!("Table{}", self.tables);
+        let columns = rows
+            .iter()
+            .map(|row| row.cells.len())
+            .chain([headers.len()])
+            .max()
+            .unwrap_or(0)
+            .max(1);
+        let _ = write!(
+            self.automatic_styles,
+            concat!(
+                r#"<style:style style:name="{0}" style:family="table"><style:table-properties style:width="{1:.3}cm" table:align="margins"/></style:style>"#,
+                r#"<style:style style:name="{0}.A" style:family="table-column"><style:table-column-properties style:column-width="{2:.3}cm"/></style:style>"#,
+                r#"<style:style style:name="{0}.A1" style:family="table-cell"><style:table-cell-properties fo:padding="0.1cm" fo:border="0.5pt solid #000000"/></style:style>"#,
+            ),
+            name,
+            self.text_width,
+            self.text_width / columns as f32
+        );
+        let _ = write!(
+            self.xml,
+            r#"<table:table table:name="{name}" table:style-name="{name}"><table:table-column table:style-name="{name}.A" table:number-columns-repeated="{columns}"/>"#
+        );
+        if !headers.is_empty() {
+            self.xml.push_str("<table:table-header-rows>");
+            self.table_row(
+                &name,
+                headers.iter().map(|header| &header.element),
+                columns,
+                TABLE_HEADING_STYLE,
+            );
+            self.xml.push_str("</table:table-header-rows>");
+        }
+        for row in rows {
+            self.table_row(
+                &name,
+                row.cells.iter().map(|cell| &cell.element),
+                columns,
+                TABLE_CONTENTS_STYLE,
+            );
+        }
+        self.xml.push_str("</table:table>");
+    }
+
+    /// Row of the cells, completed with empty cells up to the number of columns
+    fn table_row<'e>(
+        &mut self,
+        table: &str,
+        cells: impl Iterator<Item = &'e Element>,
+        columns: usize,
+        style: &str,
+    ) {
+        self.xml.push_str("<table:table-row>");
+        let mut written = 0;
+        for element in cells {
+            let _ = write!(
+                self.xml,
+                r#"<table:table-cell table:style-name="{table}.A1" office:value-type="string">"#
+            );
+            self.content([element], style);
+            self.xml.push_str("</table:table-cell>");
+            written += 1;
+        }
+        for _ in written..columns {
+            let _ = write!(
+                self.xml,
+                r#"<table:table-cell table:style-name="{table}.A1"/>"#
+            );
+        }
+        self.xml.push_str("</table:table-row>");
+    }
+}
+
+/// Paragraph style of a heading level
+fn heading_style(level: usize, size: u8) -> String {
+    format!(
+        r#"<style:style style:name="Heading_20_{level}" style:display-name="Heading {level}" style:family="paragraph" style:parent-style-name="Heading" style:next-style-name="{BODY_STYLE}" style:default-outline-level="{level}" style:class="text"><style:text-properties fo:font-size="{size}pt" fo:font-weight="bold"/></style:style>"#
+    )
+}
+
+/// List style of a bulleted list, or of a numbered one with its `style:num-format`
+fn list_style(name: &str, num_format: Option<&str>) -> String {
+    let mut xml = format!(
+        r#"<text:list-style style:name="{name}" style:display-name="{}">"#,
+        name.replace("_20_", " ")
+    );
+    for level in 1..=LIST_LEVELS {
+        let (open, close) = match num_format {
+            Some(num_format) => (
+                format!(
+                    r#"<text:list-level-style-number text:level="{level}" style:num-suffix="." style:num-format="{num_format}">"#
+                ),
+                "</text:list-level-style-number>",
+            ),
+            None => (
+                format!(
+                    r#"<text:list-level-style-bullet text:level="{level}" text:bullet-char="{}">"#,
+                    BULLETS[(level - 1) % BULLETS.len()]
+                ),
+                "</text:list-level-style-bullet>",
+            ),
+        };
+        let indent = 0.635 * level as f32;
+        let _ = write!(
+            xml,
+            r#"{open}<style:list-level-properties text:list-level-position-and-space-mode="label-alignment"><style:list-level-label-alignment text:label-followed-by="listtab" text:list-tab-stop-position="{indent:.3}cm" fo:text-indent="-0.635cm" fo:margin-left="{indent:.3}cm"/></style:list-level-properties>{close}"#
+        );
+    }
+    xml.push_str("</text:list-style>");
+    xml
+}
+
+/// styles.xml: the named styles, the page layout and the master page with the page header
+/// and footer
+fn styles_xml(document: &Document, automatic_styles: &str, header: &str, footer: &str) -> String {
+    let mut styles = String::from(concat!(
+        r#"<style:default-style style:family="paragraph"><style:text-properties fo:font-size="12pt"/></style:default-style>"#,
+        r#"<style:style style:name="Standard" style:family="paragraph" style:class="text"/>"#,
+        r#"<style:style style:name="Heading" style:family="paragraph" style:parent-style-name="Standard" style:next-style-name="Text_20_body" style:class="text"><style:paragraph-properties fo:margin-top="0.423cm" fo:margin-bottom="0.212cm" fo:keep-with-next="always"/></style:style>"#,
+        r#"<style:style style:name="Text_20_body" style:display-name="Text body" style:family="paragraph" style:parent-style-name="Standard" style:class="text"><style:paragraph-properties fo:margin-top="0cm" fo:margin-bottom="0.247cm"/></style:style>"#,
+        r#"<style:style style:name="List" style:family="paragraph" style:parent-style-name="Text_20_body" style:class="list"/>"#,
+        r#"<style:style style:name="Preformatted_20_Text" style:display-name="Preformatted Text" style:family="paragraph" style:parent-style-name="Standard" style:class="html"><style:text-properties style:font-name="Liberation Mono" fo:font-size="10pt"/></style:style>"#,
+        r#"<style:style style:name="Table_20_Contents" style:display-name="Table Contents" style:family="paragraph" style:parent-style-name="Standard" style:class="extra"/>"#,
+        r#"<style:style style:name="Table_20_Heading" style:display-name="Table Heading" style:family="paragraph" style:parent-style-name="Table_20_Contents" style:class="extra"><style:paragraph-properties fo:text-align="center"/><style:text-properties fo:font-weight="bold"/></style:style>"#,
+        r#"<style:style style:name="Footnote" style:family="paragraph" style:parent-style-name="Standard" style:class="extra"><style:text-properties fo:font-size="10pt"/></style:style>"#,
+        r#"<style:style style:name="Header" style:family="paragraph" style:parent-style-name="Standard" style:class="extra"/>"#,
+        r#"<style:style style:name="Footer" style:family="paragraph" style:parent-style-name="Standard" style:class="extra"/>"#,
+        r#"<style:style style:name="Emphasis" style:family="text"><style:text-properties fo:font-style="italic"/></style:style>"#,
+        r#"<style:style style:name="Strong_20_Emphasis" style:display-name="Strong Emphasis" style:family="text"><style:text-properties fo:font-weight="bold"/></style:style>"#,
+        r#"<style:style style:name="Strikethrough" style:family="text"><style:text-properties style:text-line-through-style="solid" style:text-line-through-type="single"/></style:style>"#,
+        r#"<style:style style:name="Source_20_Text" style:display-name="Source Text" style:family="text"><style:text-properties style:font-name="Liberation Mono"/></style:style>"#,
+        r##"<style:style style:name="Internet_20_link" style:display-name="Internet link" style:family="text"><style:text-properties fo:color="#000080" style:text-underline-style="solid" style:text-underline-width="auto" style:text-underline-color="font-color"/></style:style>"##,
+    ));
+    for (index, size) in HEADING_SIZES.iter().enumerate() {
+        styles.push_str(&heading_style(index + 1, *size));
+    }
+    styles.push_str(&list_style(BULLET_LIST_STYLE, None));
+    for numbering in [
+        NumberingStyle::Decimal,
+        NumberingStyle::LowerAlpha,
+        NumberingStyle::UpperAlpha,
+        NumberingStyle::LowerRoman,
+        NumberingStyle::UpperRoman,
+    ] {
+        let (name, num_format) = numbering_style(&numbering);
+        styles.push_str(&list_style(name, Some(num_format)));
+    }
+
+    let page = document.page_format.dimensions();
+    let (width, height, orientation) = match document.orientation {
+        PageOrientation::Portrait => (page.page_width, page.page_height, "portrait"),
+        PageOrientation::Landscape => (page.page_height, page.page_width, "landscape"),
+    };
+    let optional = |tag: &str, content: &str| {
+        if content.is_empty() {
+            String::new()
+        } else {
+            format!("<{tag}>{content}</{tag}>")
+        }
+    };
+    format!(
+        concat!(
+            r#"<?xml version="1.0" encoding="UTF-8"?><office:document-styles {}>"#,
+            r#"<office:font-face-decls><style:font-face style:name="Liberation Mono" svg:font-family="'Liberation Mono'" style:font-family-generic="modern" style:font-pitch="fixed"/></office:font-face-decls>"#,
+            r#"<office:styles>{}</office:styles>"#,
+            r#"<office:automatic-styles><style:page-layout style:name="Page"><style:page-layout-properties fo:page-width="{}" fo:page-height="{}" style:print-orientation="{}" fo:margin-top="{}" fo:margin-bottom="{}" fo:margin-left="{}" fo:margin-right="{}"/></style:page-layout>{}</office:automatic-styles>"#,
+            r#"<office:master-styles><style:master-page style:name="Standard" style:page-layout-name="Page">{}{}</style:master-page></office:master-styles>"#,
+            r#"</office:document-styles>"#
+        ),
+        NAMESPACES,
+        styles,
+        width,
+        height,
+        orientation,
+        page.page_margin_top,
+        page.page_margin_bottom,
+        page.page_margin_left,
+        page.page_margin_right,
+        automatic_styles,
+        optional("style:header", header),
+        optional("style:footer", footer),
+    )
+}
+
+/// meta.xml of the metadata, the date is written when it is a W3C date (`2024-05-01`,
+/// `2024-05-01T10:00:00Z`)
+fn meta_xml(metadata: &Metadata) -> String {
+    let mut xml = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?><office:document-meta {NAMESPACES}><office:meta><meta:generator>Shiva</meta:generator>"#
+    );
+    for (tag, value) in [
+        ("dc:title", &metadata.title),
+        ("dc:description", &metadata.description),
+        ("meta:initial-creator", &metadata.author),
+    ] {
+        if let Some(value) = value {
+            let _ = write!(xml, "<{tag}>{}</{tag}>", escape(value));
+        }
+    }
+    for keyword in &metadata.keywords {
+        let _ = write!(xml, "<meta:keyword>{}</meta:keyword>", escape(keyword));
+    }
+    let w3c_date = |date: &&str| {
+        date.get(..4)
+            .is_some_and(|year| year.chars().all(|c| c.is_ascii_digit()))
+            && date
+                .chars()
+                .all(|c| c.is_ascii_digit() || "-:T.Z+".contains(c))
+    };
+    if let Some(date) = metadata.date.as_deref().map(str::trim).filter(w3c_date) {
+        // a date time is expected
+        let time = if date.contains('T') { "" } else { "T00:00:00" };
+        let _ = write!(xml, "<meta:creation-date>{date}{time}</meta:creation-date>");
+    }
+    for (name, value) in &metadata.custom {
+        let _ = write!(
+            xml,
+            r#"<meta:user-defined meta:name="{}">{}</meta:user-defined>"#,
+            escape(name),
+            escape(value)
+        );
+    }
+    xml.push_str("</office:meta></office:document-meta>");
+    xml
+}
+
+fn manifest_xml(images: &[(String, String, Bytes)]) -> String {
+    let mut xml = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?><manifest:manifest xmlns:manifest="urn:oasis:names:tc:opendocument:xmlns:manifest:1.0" manifest:version="1.3"><manifest:file-entry manifest:full-path="/" manifest:version="1.3" manifest:media-type="{MIME_TYPE}"/>"#
+    );
+    for part in ["content.xml", "styles.xml", "meta.xml"] {
+        let _ = write!(
+            xml,
+            r#"<manifest:file-entry manifest:full-path="{part}" manifest:media-type="text/xml"/>"#
+        );
+    }
+    for (path, media_type, _) in images {
+        let _ = write!(
+            xml,
+            r#"<manifest:file-entry manifest:full-path="{path}" manifest:media-type="{media_type}"/>"#
+        );
+    }
+    xml.push_str("</manifest:manifest>");
+    xml
+}
+
+// endregion: ---writer
+
+// region:    ---reader
+
+/// Size of the text read from ODT, the sizes of the spans are not read
+const TEXT_SIZE: u8 = 12;
+
+/// Width of the table headers read from ODT
+const HEADER_WIDTH: f32 = 30.0;
+
+/// Inline elements whose content is not text of the paragraph: comments, marks and change
+/// tracking
+const SKIPPED_INLINE: [&str; 9] = [
+    "office:annotation",
+    "text:bookmark",
+    "text:reference-mark",
+    "text:toc-mark",
+    "text:alphabetical-index-mark",
+    "text:user-index-mark",
+    "text:soft-page-break",
+    "text:change",
+    "draw:",
+];
+
+/// Element of an XML part with its content in document order
+#[derive(Debug, Default)]
+struct Node {
+    name: String,
+    attributes: HashMap<String, String>,
+    children: Vec<Content>,
+}
+
+#[derive(Debug)]
+enum Content {
+    Node(Node),
+    Text(String),
+}
+
+impl Node {
+    /// Root element of an XML part
+    fn parse(xml: &str) -> anyhow::Result<Node> {
+        let mut reader = quick_xml::Reader::from_str(xml);
+        reader.expand_empty_elements(true);
+        let mut stack = vec![Node::default()];
+        loop {
+            match reader.read_event()? {
+                Event::Start(start) => {
+                    let mut node = Node {
+                        name: String::from_utf8_lossy(start.name().as_ref()).into_owned(),
+                        ..Default::default()
+                    };
+                    for attribute in start.attributes() {
+                        let attribute = attribute?;
+                        node.attributes.insert(
+                            String::from_utf8_lossy(attribute.key.as_ref()).into_owned(),
+                            attribute.decode_and_unescape_value(&reader)?.into_owned(),
+                        );
+                    }
+                    stack.push(node);
+                }
+                Event::End(_) => {
+                    let node = stack.pop().context("Unbalanced XML")?;
+                    stack
+                        .last_mut()
+                        .context("Unbalanced XML")?
+                        .children
+                        .push(Content::Node(node));
+                }
+                Event::Text(text) => {
+                    if let Some(node) = stack.last_mut() {
+                        node.children
+                            .push(Content::Text(text.unescape()?.into_owned()));
+                    }
+                }
+                Event::CData(data) => {
+                    if let Some(node) = stack.last_mut() {
+                        node.children.push(Content::Text(
+                            String::from_utf8_lossy(&data.into_inner()).into_owned(),
+                        ));
+                    }
+                }
+                Event::Eof => break,
+                _ => {}
+            }
+        }
+        let document = stack.pop().context("Unbalanced XML")?;
+        document
+            .children
+            .into_iter()
+            .find_map(|child| match child {
+                Content::Node(node) => Some(node),
+                Content::Text(_) => None,
+            })
+            .context("No XML root element")
+    }
+
+    fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes.get(name).map(String::as_str)
+    }
+
+    /// Child elements, without the text between them
+    fn nodes(&self) -> impl Iterator<Item = &Node> {
+        self.children.iter().filter_map(|child| match child {
+            Content::Node(node) => Some(node),
+            Content::Text(_) => None,
+        })
+    }
+
+    fn child(&self, name: &str) -> Option<&Node> {
+        self.nodes().find(|node| node.name == name)
+    }
+
+    /// Text of the element and of its descendants
+    fn text(&self) -> String {
+        let mut text = String::new();
+        for child in &self.children {
+            match child {
+                Content::Node(node) => text.push_str(&node.text()),
+                Content::Text(content) => text.push_str(content),
+            }
+        }
+        text
+    }
+}
+
+/// Sequences of white space read as one space, as in the text of ODF paragraphs
+fn collapse_spaces(text: &str) -> String {
+    let mut collapsed = String::with_capacity(text.len());
+    let mut space = false;
+    for c in text.chars() {
+        if matches!(c, ' ' | '\t' | '\n' | '\r') {
+            if !space {
+                collapsed.push(' ');
+            }
+            space = true;
+        } else {
+            collapsed.push(c);
+            space = false;
+        }
+    }
+    collapsed
+}
+
+/// Properties of a paragraph or text style, unset ones being those of its parent
+#[derive(Debug, Default)]
+struct Style {
+    parent: Option<String>,
+    strong: Option<bool>,
+    emphasis: Option<bool>,
+    strikethrough: Option<bool>,
+    monospace: Option<bool>,
+    outline_level: Option<u8>,
+}
+
+impl Style {
+    fn read(node: &Node) -> Style {
+        let properties = node.child("style:text-properties");
+        let property = |name: &str| properties.and_then(|properties| properties.attribute(name));
+        Style {
+            parent: node
+                .attribute("style:parent-style-name")
+                .map(str::to_string),
+            strong: property("fo:font-weight").map(|weight| {
+                weight == "bold" || weight.parse::<u16>().is_ok_and(|weight| weight >= 600)
+            }),
+            emphasis: property("fo:font-style")
+                .map(|style| style == "italic" || style == "oblique"),
+            strikethrough: property("style:text-line-through-style").map(|style| style != "none"),
+            monospace: property("style:font-name")
+                .or_else(|| property("fo:font-family"))
+                .map(|font| {
+                    let font = font.to_lowercase();
+                    ["mono", "courier", "consolas", "menlo", "monaco"]
+                        .iter()
+                        .any(|name| font.contains(name))
+                }),
+            outline_level: node
+                .attribute("style:default-outline-level")
+                .and_then(|level| level.parse().ok()),
+        }
+    }
+}
+
+/// Reads the body of an ODT file with its styles, list styles and images
+#[derive(Default)]
+struct Reader {
+    files: HashMap<String, Bytes>,
+    /// Styles by family and name
+    styles: HashMap<(String, String), Style>,
+    /// Numbering and start of the levels of the list styles, none for a bulleted level
+    list_styles: HashMap<String, HashMap<usize, Option<(NumberingStyle, usize)>>>,
+}
+
+impl Reader {
+    /// Styles of the named styles and of the automatic styles of a part
+    fn read_styles(&mut self, root: &Node) {
+        for styles in root
+            .nodes()
+            .filter(|node| node.name == "office:styles" || node.name == "office:automatic-styles")
+        {
+            for style in styles.nodes() {
+                let Some(name) = style.attribute("style:name") else {
+                    continue;
+                };
+                match style.name.as_str() {
+                    "style:style" => {
+                        let family = style.attribute("style:family").unwrap_or_default();
+                        self.styles
+                            .insert((family.to_string(), name.to_string()), Style::read(style));
+                    }
+                    "text:list-style" => {
+                        let levels = style
+                            .nodes()
+                            .filter_map(|level| {
+                                let number = level.attribute("text:level")?.parse().ok()?;
+                                let numbering = match level.name.as_str() {
+                                    "text:list-level-style-number" => {
+                                        let numbering = match level.attribute("style:num-format") {
+                                            Some("a") => NumberingStyle::LowerAlpha,
+                                            Some("A") => NumberingStyle::UpperAlpha,
+                                            Some("i") => NumberingStyle::LowerRoman,
+                                            Some("I") => NumberingStyle::UpperRoman,
+                                            _ => NumberingStyle::Decimal,
+                                        };
+                                        let start = level
+                                            .attribute("text:start-value")
+                                            .and_then(|start| start.parse().ok())
+                                            .unwrap_or(1);
+                                        Some((numbering, start))
+                                    }
+                                    _ => None,
+                                };
+                                Some((number, numbering))
+                            })
+                            .collect();
+                        self.list_styles.insert(name.to_string(), levels);
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    /// Style and its ancestors, the style first
+    fn style_chain(&self, family: &str, name: Option<&str>) -> Vec<&Style> {
+        let mut chain = vec![];
+        let mut name = name;
+        // parents are few, the limit stops cycles
+        while let Some(style) = name
+            .filter(|_| chain.len() < 32)
+            .and_then(|name| self.styles.get(&(family.to_string(), name.to_string())))
+        {
+            chain.push(style);
+            name = style.parent.as_deref();
+        }
+        chain
+    }
+
+    /// Format of the text of a style, over the format of its container
+    fn format(&self, family: &str, name: Option<&str>, format: RunFormat) -> RunFormat {
+        let mut format = format;
+        for style in self.style_chain(family, name).into_iter().rev() {
+            format.strong = style.strong.unwrap_or(format.strong);
+            format.emphasis = style.emphasis.unwrap_or(format.emphasis);
+            format.strikethrough = style.strikethrough.unwrap_or(format.strikethrough);
+            format.code = style.monospace.unwrap_or(format.code);
+        }
+        format
+    }
+
+    /// Elements of the blocks of a container (body, section, header, note...)
+    fn blocks(&self, container: &Node, elements: &mut Vec<Element>) {
+        for node in container.nodes() {
+            match node.name.as_str() {
+                "text:h" => {
+                    let style = node.attribute("text:style-name");
+                    let level = node
+                        .attribute("text:outline-level")
+                        .and_then(|level| level.parse().ok())
+                        .or_else(|| {
+                            self.style_chain("paragraph", style)
+                                .iter()
+                                .find_map(|style| style.outline_level)
+                        })
+                        .unwrap_or(1);
+                    let text = Element::Paragraph {
+                        elements: self.inline_elements(node, RunFormat::default()),
+                        direction: Default::default(),
+                    }
+                    .plain_text();
+                    elements.push(Element::Header {
+                        level: level.max(1),
+                        text,
+                    });
+                }
+                "text:p" => {
+                    let format = self.format(
+                        "paragraph",
+                        node.attribute("text:style-name"),
+                        Default::default(),
+                    );
+                    if format.code {
+                        // each line of a code block is often a paragraph of its own
+                        let line = Element::Paragraph {
+                            elements: self.inline_elements(node, RunFormat::default()),
+                            direction: Default::default(),
+                        }
+                        .plain_text();
+                        match elements.last_mut() {
+                            Some(Element::CodeBlock { code, .. }) => {
+                                code.push('\n');
+                                code.push_str(&line);
+                            }
+                            _ => elements.push(Element::CodeBlock {
+                                info: String::new(),
+                                code: line,
+                            }),
+                        }
+                        continue;
+                    }
+                    if let Some(element) = block_element(self.inline_elements(node, format)) {
+                        elements.push(element);
+                    }
+                }
+                "text:list" => elements.push(self.list(node, None, 0)),
+                "table:table" => elements.push(self.table(node)),
+                "text:table-of-content" => {
+                    let max_level = node
+                        .child("text:table-of-content-source")
+                        .and_then(|source| source.attribute("text:outline-level"))
+                        .and_then(|level| level.parse().ok())
+                        .unwrap_or(3);
+                    elements.push(Element::TableOfContents { max_level });
+                }
+                "text:section" => self.blocks(node, elements),
+                _ => {}
+            }
+        }
+    }
+
+    /// Inline elements of a paragraph, the runs of the same format merged
+    fn inline_elements(&self, node: &Node, format: RunFormat) -> Vec<Element> {
+        let mut pieces: Vec<(RunFormat, Element)> = vec![];
+        self.pieces(node, format, &mut pieces);
+
+        let mut elements = vec![];
+        let mut group: Option<(RunFormat, Vec<Element>)> = None;
+        for (format, element) in pieces {
+            match &mut group {
+                Some((group_format, group_elements)) if *group_format == format => {
+                    match (group_elements.last_mut(), element) {
+                        (Some(Element::Text { text, .. }), Element::Text { text: next, .. })
+                        | (
+                            Some(Element::InlineCode { text }),
+                            Element::InlineCode { text: next },
+                        ) => text.push_str(&next),
+                        (_, element) => group_elements.push(element),
+                    }
+                }
+                _ => {
+                    if let Some((group_format, group_elements)) =
+                        group.replace((format, vec![element]))
+                    {
+                        elements.extend(group_format.apply(group_elements));
+                    }
+                }
+            }
+        }
+        if let Some((group_format, group_elements)) = group {
+            elements.extend(group_format.apply(group_elements));
+        }
+        merge_styled(elements)
+    }
+
+    fn pieces(&self, node: &Node, format: RunFormat, pieces: &mut Vec<(RunFormat, Element)>) {
+        let text = |text: String| {
+            if format.code {
+                Element::InlineCode { text }
+            } else {
+                Element::Text {
+                    text,
+                    size: TEXT_SIZE,
+                }
+            }
+        };
+        // code is not styled
+        let text_format = RunFormat {
+            code: false,
+            ..format
+        };
+        for child in &node.children {
+            let node = match child {
+                Content::Text(content) => {
+                    pieces.push((text_format, text(collapse_spaces(content))));
+                    continue;
+                }
+                Content::Node(node) => node,
+            };
+            match node.name.as_str() {
+                "text:span" => self.pieces(
+                    node,
+                    self.format("text", node.attribute("text:style-name"), format),
+                    pieces,
+                ),
+                "text:s" => {
+                    let count = node
+                        .attribute("text:c")
+                        .and_then(|count| count.parse().ok())
+                        .unwrap_or(1);
+                    pieces.push((text_format, text(" ".repeat(count))));
+                }
+                "text:tab" => pieces.push((text_format, text("\t".to_string()))),
+                "text:line-break" => pieces.push((text_format, Element::LineBreak)),
+                "text:a" | "draw:a" => {
+                    let elements = self.inline_elements(node, RunFormat::default());
+                    let Some(url) = node.attribute("xlink:href") else {
+                        pieces.extend(elements.into_iter().map(|element| (text_format, element)));
+                        continue;
+                    };
+                    pieces.push((
+                        text_format,
+                        Element::Hyperlink {
+                            elements,
+                            url: url.to_string(),
+                            alt: node
+                                .attribute("office:title")
+                                .unwrap_or_default()
+                                .to_string(),
+                            size: TEXT_SIZE,
+                        },
+                    ));
+                }
+                "draw:frame" => {
+                    if let Some(image) = self.image(node) {
+                        pieces.push((RunFormat::default(), image));
+                    }
+                }
+                "text:note" => {
+                    if let Some(body) = node.child("text:note-body") {
+                        pieces.push((
+                            RunFormat::default(),
+                            Element::Footnote {
+                                elements: self.note(body),
+                            },
+                        ));
+                    }
+                }
+                "text:page-number" => pieces.push((
+                    RunFormat::default(),
+                    Element::Field {
+                        kind: FieldKind::PageNumber,
+                    },
+                )),
+                "text:page-count" => pieces.push((
+                    RunFormat::default(),
+                    Element::Field {
+                        kind: FieldKind::TotalPages,
+                    },
+                )),
+                name if SKIPPED_INLINE
+                    .iter()
+                    .any(|skipped| name.starts_with(skipped)) => {}
+                // fields, metadata and other marks show their text
+                _ => self.pieces(node, format, pieces),
+            }
+        }
+    }
+
+    /// Content of a note, the elements of its paragraph when it has only one
+    fn note(&self, body: &Node) -> Vec<Element> {
+        let mut elements = vec![];
+        self.blocks(body, &mut elements);
+        match <[Element; 1]>::try_from(elements) {
+            Ok([Element::Paragraph { elements, .. }]) => elements,
+            Ok([element]) => vec![element],
+            Err(elements) => elements,
+        }
+    }
+
+    /// Image of a frame, its size in pixels
+    fn image(&self, frame: &Node) -> Option<Element> {
+        let path = frame.child("draw:image")?.attribute("xlink:href")?;
+        let Some(bytes) = self.files.get(path.trim_start_matches("./")) else {
+            warn!("No image {} in the document", path);
+            return None;
+        };
+        let pixels = |name: &str| {
+            frame
+                .attribute(name)
+                .and_then(|length| length.parse::<Length>().ok())
+                .map(|length| format!("{}", length.to(LengthUnit::Px).value.round()))
+        };
+        let text = |name: &str| frame.child(name).map(Node::text).unwrap_or_default();
+        Some(Element::Image(ImageData::new(
+            bytes.clone(),
+            text("svg:title"),
+            text("svg:desc"),
+            path.to_string(),
+            String::new(),
+            ImageDimension {
+                width: pixels("svg:width"),
+                height: pixels("svg:height"),
+            },
+        )))
+    }
+
+    /// List of the list style of the list or of its parent, numbered when the level of
+    /// the list is numbered
+    fn list(&self, node: &Node, parent_style: Option<&str>, depth: usize) -> Element {
+        let style = node.attribute("text:style-name").or(parent_style);
+        let level = style
+            .and_then(|style| self.list_styles.get(style))
+            .and_then(|levels| levels.get(&(depth + 1)))
+            .copied()
+            .flatten();
+        let mut start = level.map(|(_, start)| start).unwrap_or(1);
+        let mut items = vec![];
+        for (index, item) in node
+            .nodes()
+            .filter(|node| node.name == "text:list-item" || node.name == "text:list-header")
+            .enumerate()
+        {
+            if let Some(value) = item
+                .attribute("text:start-value")
+                .and_then(|value| value.parse().ok())
+                .filter(|_| index == 0)
+            {
+                start = value;
+            }
+            let mut content = vec![];
+            for child in item.nodes() {
+                match child.name.as_str() {
+                    "text:p" | "text:h" => {
+                        let format = self.format(
+                            "paragraph",
+                            child.attribute("text:style-name"),
+                            Default::default(),
+                        );
+                        let elements = self.inline_elements(child, format);
+                        if elements.is_empty() {
+                            continue;
+                        }
+                        if !content.is_empty() {
+                            content.push(Element::LineBreak);
+                        }
+                        content.extend(elements);
+                    }
+                    "text:list" => {
+                        if !content.is_empty() {
+                            items.push(ListItem {
+                                element: item_element(std::mem::take(&mut content)),
+                            });
+                        }
+                        items.push(ListItem {
+                            element: self.list(child, style, depth + 1),
+                        });
+                    }
+                    _ => {}
+                }
+            }
+            if !content.is_empty() {
+                items.push(ListItem {
+                    element: item_element(content),
+                });
+            }
+        }
+        Element::List {
+            elements: items,
+            numbered: level.is_some(),
+            start,
+            numbering: level.map(|(numbering, _)| numbering).unwrap_or_default(),
+        }
+    }
+
+    /// Table of the rows, the rows of its header rows giving the headers. A cell of
+    /// several paragraphs is one cell with line breaks, read without the format of its
+    /// paragraph style as table headings are bold.
+    fn table(&self, node: &Node) -> Element {
+        let cells = |row: &Node| -> Vec<Element> {
+            row.nodes()
+                .filter(|cell| cell.name == "table:table-cell")
+                .map(|cell| {
+                    let mut content = vec![];
+                    for paragraph in cell.nodes().filter(|node| node.name == "text:p") {
+                        let elements = self.inline_elements(paragraph, RunFormat::default());
+                        if elements.is_empty() {
+                            continue;
+                        }
+                        if !content.is_empty() {
+                            content.push(Element::LineBreak);
+                        }
+                        content.extend(elements);
+                    }
+                    item_element(content)
+                })
+                .collect()
+        };
+        fn rows<'n>(node: &'n Node, found: &mut Vec<&'n Node>) {
+            for child in node.nodes() {
+                match child.name.as_str() {
+                    "table:table-row" => found.push(child),
+                    "table:table-rows" | "table:table-row-group" => rows(child, found),
+                    _ => {}
+                }
+            }
+        }
+
+        let mut header_rows = vec![];
+        if let Some(header) = node.child("table:table-header-rows") {
+            rows(header, &mut header_rows);
+        }
+        let headers = header_rows
+            .first()
+            .map(|row| {
+                cells(row)
+                    .into_iter()
+                    .map(|element| TableHeader {
+                        element,
+                        width: HEADER_WIDTH,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        let mut body_rows = vec![];
+        rows(node, &mut body_rows);
+        Element::Table {
+            headers,
+            rows: header_rows
+                .iter()
+                .skip(1)
+                .chain(&body_rows)
+                .map(|row| TableRow {
+                    cells: cells(row)
+                        .into_iter()
+                        .map(|element| TableCell { element })
+                        .collect(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Blocks of the header or footer of the first master page
+    fn page_band(&self, styles: &Node, name: &str) -> Vec<Element> {
+        let mut elements = vec![];
+        if let Some(band) = styles
+            .child("office:master-styles")
+            .and_then(|master_styles| master_styles.child("style:master-page"))
+            .and_then(|master_page| master_page.child(name))
+        {
+            self.blocks(band, &mut elements);
+        }
+        elements
+    }
+}
+
+/// Metadata of meta.xml, the creator when there is no initial creator
+fn read_metadata(meta: &Node) -> Metadata {
+    let mut metadata = Metadata::default();
+    let Some(properties) = meta.child("office:meta") else {
+        return metadata;
+    };
+    let mut creator = None;
+    for node in properties.nodes() {
+        let text = node.text().trim().to_string();
+        match node.name.as_str() {
+            "dc:title" => metadata.title = Some(text),
+            "dc:description" => metadata.description = Some(text),
+            "meta:initial-creator" => metadata.author = Some(text),
+            "dc:creator" => creator = Some(text),
+            "meta:keyword" => metadata.keywords.push(text),
+            "meta:creation-date" => {
+                metadata.date = Some(text.trim_end_matches("T00:00:00").to_string())
+            }
+            "meta:user-defined" => {
+                if let Some(name) = node.attribute("meta:name") {
+                    metadata.custom.insert(name.to_string(), text);
+                }
+            }
+            _ => {}
+        }
+    }
+    if metadata.author.is_none() {
+        metadata.author = creator;
+    }
+    metadata
+}
+
+// endregion: ---reader
+
+impl TransformerTrait for Transformer {
+    fn parse(document: &Bytes) -> crate::Result<Document> {
+        Transformer::parse_bytes(document).map_err(crate::Error::parse_error)
+    }
+
+    fn generate(document: &Document) -> crate::Result<Bytes> {
+        Transformer::generate_bytes(document).map_err(crate::Error::generation_error)
+    }
+}
+
+impl Transformer {
+    fn parse_bytes(document: &Bytes) -> anyhow::Result<Document> {
+        let mut archive = ZipArchive::new(Cursor::new(document))?;
+        let mut reader = Reader::default();
+        for index in 0..archive.len() {
+            let mut file = archive.by_index(index)?;
+            if file.is_dir() {
+                continue;
+            }
+            let mut bytes = Vec::with_capacity(file.size() as usize);
+            file.read_to_end(&mut bytes)?;
+            reader
+                .files
+                .insert(file.name().to_string(), Bytes::from(bytes));
+        }
+        let part = |name: &str| -> anyhow::Result<Option<Node>> {
+            reader
+                .files
+                .get(name)
+                .map(|bytes| Node::parse(std::str::from_utf8(bytes)?))
+                .transpose()
+        };
+        let content = part("content.xml")?.context("Not an ODT document: no content.xml")?;
+        let styles = part("styles.xml")?.unwrap_or_default();
+        let meta = part("meta.xml")?.unwrap_or_default();
+        reader.read_styles(&styles);
+        reader.read_styles(&content);
+
+        let mut elements = vec![];
+        if let Some(text) = content
+            .child("office:body")
+            .and_then(|body| body.child("office:text"))
+        {
+            reader.blocks(text, &mut elements);
+        }
+        let mut document = Document::new(elements);
+        let header = reader.page_band(&styles, "style:header");
+        if !header.is_empty() {
+            document.bands.insert(0, Band::PageHeader(header));
+        }
+        let footer = reader.page_band(&styles, "style:footer");
+        if !footer.is_empty() {
+            document.bands.push(Band::PageFooter(footer));
+        }
+        document.metadata = read_metadata(&meta);
+        Ok(document)
+    }
+
+    fn generate_bytes(document: &Document) -> anyhow::Result<Bytes> {
+        let document = &document
+            .resolve_fields()
+            .without_containers()
+            .without_media()
+            .without_math()
+            .without_drawings()
+            .without_attributes();
+        let mut writer = Writer::new(document);
+        for band in &document.bands {
+            if !matches!(band, Band::PageHeader(_) | Band::PageFooter(_)) {
+                for element in band.elements() {
+                    writer.block(element);
+                }
+            }
+        }
+        let body = std::mem::take(&mut writer.xml);
+        let content_styles = std::mem::take(&mut writer.automatic_styles);
+        // the header and the footer are parts of the master page, in styles.xml
+        writer.content(document.get_page_header(), "Header");
+        let header = std::mem::take(&mut writer.xml);
+        writer.content(document.get_page_footer(), "Footer");
+        let footer = std::mem::take(&mut writer.xml);
+
+        let content = format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?><office:document-content {NAMESPACES}><office:automatic-styles>{content_styles}</office:automatic-styles><office:body><office:text>{body}</office:text></office:body></office:document-content>"#
+        );
+        let styles = styles_xml(document, &writer.automatic_styles, &header, &footer);
+
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        // the media type is the first file, uncompressed, for the readers that look for it
+        zip.start_file(
+            "mimetype",
+            FileOptions::default().compression_method(CompressionMethod::Stored),
+        )?;
+        zip.write_all(MIME_TYPE.as_bytes())?;
+        let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+        for (name, xml) in [
+            ("content.xml", content),
+            ("styles.xml", styles),
+            ("meta.xml", meta_xml(&document.metadata)),
+        ] {
+            zip.start_file(name, options)?;
+            zip.write_all(xml.as_bytes())?;
+        }
+        for (path, _, bytes) in &writer.images {
+            zip.start_file(path.as_str(), options)?;
+            zip.write_all(bytes)?;
+        }
+        zip.start_file("META-INF/manifest.xml", options)?;
+        zip.write_all(manifest_xml(&writer.images).as_bytes())?;
+        Ok(Bytes::from(zip.finish()?.into_inner()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::tests::init_logger;
+    use crate::markdown;
+
+    #[test]
+    fn test_round_trip() -> anyhow::Result<()> {
+        init_logger();
+        let markdown = "# Report\n\n## Part\n\n\
+            Some **bold *and italic*** text, `code` and [the site](https://example.com).[^1]\n\n\
+            - One\n  - Nested\n- Two\n\n3. Third\n4. Fourth\n\n\
+            | Name | Value |\n|------|-------|\n| a | ~~1~~ |\n\n\
+            ```\nfn main() {\n    run();\n}\n```\n\n\
+            [^1]: A note.\n";
+        let mut document = markdown::Transformer::parse(&Bytes::from(markdown))?;
+        let png = Bytes::from(std::fs::read("test/data/small.png")?);
+        document.bands = vec![Band::Detail(
+            document
+                .get_all_elements()
+                .into_iter()
+                .cloned()
+                .chain([Element::Image(ImageData::new(
+                    png.clone(),
+                    "Logo".to_string(),
+                    "A small picture".to_string(),
+                    "png".to_string(),
+                    String::new(),
+                    ImageDimension {
+                        width: Some("40".to_string()),
+                        height: Some("20".to_string()),
+                    },
+                ))])
+                .collect(),
+        )];
+
+        let generated = Transformer::generate(&document)?;
+        assert!(generated[30..].starts_with(b"mimetype"));
+        let parsed = Transformer::parse(&generated)?;
+        let elements = parsed.get_all_elements();
+        let [report, part, paragraph, bullets, numbered, table, code, image] = elements.as_slice()
+        else {
+            panic!("Unexpected elements {:#?}", elements);
+        };
+        assert_eq!(
+            *report,
+            &Element::Header {
+                level: 1,
+                text: "Report".to_string()
+            }
+        );
+        assert!(matches!(part, Element::Header { level: 2, .. }));
+
+        let Element::Paragraph { elements, .. } = paragraph else {
+            panic!("Unexpected paragraph {:?}", paragraph);
+        };
+        assert_eq!(
+            elements[1],
+            Element::Styled {
+                style: TextStyle::Strong,
+                elements: vec![
+                    Element::Text {
+                        text: "bold ".to_string(),
+                        size: TEXT_SIZE
+                    },
+                    Element::Styled {
+                        style: TextStyle::Emphasis,
+                        elements: vec![Element::Text {
+                            text: "and italic".to_string(),
+                            size: TEXT_SIZE
+                        }]
+                    }
+                ]
+            }
+        );
+        assert!(elements.contains(&Element::InlineCode {
+            text: "code".to_string()
+        }));
+        assert!(elements.iter().any(|element| matches!(
+            element,
+            Element::Hyperlink { url, .. }
+                if url == "https://example.com" && element.plain_text() == "the site"
+        )));
+        assert!(elements.iter().any(|element| matches!(
+            element,
+            Element::Footnote { elements } if elements[0].plain_text() == "A note."
+        )));
+
+        let Element::List {
+            elements: items,
+            numbered: false,
+            ..
+        } = bullets
+        else {
+            panic!("Unexpected list {:?}", bullets);
+        };
+        assert_eq!(items.len(), 3);
+        assert!(matches!(
+            &items[1].element,
+            Element::List { elements, numbered: false, .. }
+                if elements[0].element.plain_text() == "Nested"
+        ));
+        assert!(matches!(
+            numbered,
+            Element::List {
+                numbered: true,
+                start: 3,
+                numbering: NumberingStyle::Decimal,
+                ..
+            }
+        ));
+
+        let Element::Table { headers, rows } = table else {
+            panic!("Unexpected table {:?}", table);
+        };
+        assert_eq!(headers.len(), 2);
+        assert_eq!(
+            headers[0].element,
+            Element::Text {
+                text: "Name".to_string(),
+                size: TEXT_SIZE
+            }
+        );
+        assert_eq!(rows.len(), 1);
+        assert!(matches!(
+            &rows[0].cells[1].element,
+            Element::Styled {
+                style: TextStyle::Strikethrough,
+                ..
+            }
+        ));
+
+        assert_eq!(
+            *code,
+            &Element::CodeBlock {
+                info: String::new(),
+                code: "fn main() {\n    run();\n}".to_string()
+            }
+        );
+
+        let Element::Image(image) = image else {
+            panic!("Unexpected image {:?}", image);
+        };
+        assert_eq!(image.bytes(), &png);
+        assert_eq!((image.title(), image.alt()), ("Logo", "A small picture"));
+        assert_eq!(image.size().width.as_deref(), Some("40"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_metadata_and_page_bands() -> anyhow::Result<()> {
+        let mut document = Document::new(vec![Element::Text {
+            text: "Body".to_string(),
+            size: 12,
+        }]);
+        document.bands.push(Band::PageFooter(vec![
+            Element::Text {
+                text: "Page ".to_string(),
+                size: 12,
+            },
+            Element::Field {
+                kind: FieldKind::PageNumber,
+            },
+        ]));
+        document.metadata = Metadata {
+            title: Some("Q&A".to_string()),
+            author: Some("Ada".to_string()),
+            date: Some("2024-05-01".to_string()),
+            description: None,
+            keywords: vec!["one".to_string(), "two".to_string()],
+            custom: [("reviewer".to_string(), "Bob".to_string())].into(),
+        };
+        document.orientation = PageOrientation::Landscape;
+
+        let generated = Transformer::generate(&document)?;
+        let mut archive = ZipArchive::new(Cursor::new(&generated))?;
+        let mut styles = String::new();
+        archive.by_name("styles.xml")?.read_to_string(&mut styles)?;
+        assert!(styles.contains(r#"fo:page-width="297mm" fo:page-height="210mm""#));
+
+        let parsed = Transformer::parse(&generated)?;
+        assert_eq!(parsed.metadata, document.metadata);
+        assert_eq!(
+            parsed.get_page_footer(),
+            vec![&Element::Paragraph {
+                elements: vec![
+                    Element::Text {
+                        text: "Page ".to_string(),
+                        size: TEXT_SIZE
+                    },
+                    Element::Field {
+                        kind: FieldKind::PageNumber
+                    }
+                ],
+                direction: Default::default()
+            }]
+        );
+        assert_eq!(parsed.get_detail().len(), 1);
+        Ok(())
+    }
+}
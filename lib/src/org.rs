@@ -0,0 +1,2377 @@
+//! Org mode source of a document, as written with Emacs
+//!
+//! Headers become headlines, with the TODO keyword, priority and tags of their `org:todo`,
+//! `org:priority` and `org:tags` attributes and a `CUSTOM_ID` property when links refer to
+//! them, and the metadata the `#+title:`, `#+author:`, `#+date:`, `#+description:` and
+//! `#+keywords:` keywords. Code blocks are source blocks, images links to files saved next
+//! to the source with their title as caption, footnotes inline definitions and asides with
+//! an `admonition` class special blocks of their kind. Table cells are written as inline
+//! text, there are no blocks in Org tables, and headers inside lists and blocks as strong
+//! paragraphs. Text that Org would read as markup is escaped with zero width spaces.
+//!
+//! Sources are read for headlines, paragraphs, plain, ordered and description lists,
+//! tables, source, example, quote, verse, export and special blocks, fixed-width lines and
+//! LaTeX math, with the emphasis markers, links, targets, footnotes and macros. Drawers,
+//! planning lines and comments are left out, checkboxes are kept as the text of their
+//! items like task lists in markdown.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write as _;
+
+use bytes::Bytes;
+use log::warn;
+
+use crate::core::{
+    disk_image_loader, disk_image_saver, header_anchor, Band, ContainerKind, Document,
+    DocumentType, Element, ImageData, ImageDimension, ListItem, Metadata, NumberingStyle,
+    TableCell, TableHeader, TableRow, TextStyle, TransformerTrait,
+    TransformerWithImageLoaderSaverTrait,
+};
+use crate::error::image_loader_errors;
+use crate::lightweight::{
+    dedent, dedent_by, expand_tabs, indentation, paragraph, plain_text, text, text_block, Inline,
+};
+
+pub struct Transformer;
+
+/// Zero width space, the escape of Org: a marker after it does not open emphasis and a
+/// marker before it does not close one
+const ZWSP: char = '\u{200b}';
+
+/// Markers of emphasis and verbatim text
+const MARKERS: &str = "*/_+=~";
+
+/// Characters before an opening emphasis marker, besides whitespace
+const EMPHASIS_PRE: &str = "-('\"{";
+
+/// Characters after a closing emphasis marker, besides whitespace
+const EMPHASIS_POST: &str = "-.,;:!?')}[\"\\";
+
+/// Kinds of the special blocks read as admonitions
+const ADMONITIONS: [&str; 10] = [
+    "attention",
+    "caution",
+    "danger",
+    "error",
+    "hint",
+    "important",
+    "note",
+    "tip",
+    "warning",
+    "seealso",
+];
+
+/// Extensions of the linked files shown as images
+const IMAGE_EXTENSIONS: [&str; 8] = ["png", "jpg", "jpeg", "gif", "svg", "webp", "bmp", "tiff"];
+
+/// Schemes of the plain links
+const URI_SCHEMES: [&str; 4] = ["https://", "http://", "ftp://", "mailto:"];
+
+/// Width of text in columns, wide characters counting two and zero width spaces none
+fn width(text: &str) -> usize {
+    text.chars()
+        .map(|c| match c as u32 {
+            0x200b => 0,
+            0..0x1100 => 1,
+            _ => 2,
+        })
+        .sum()
+}
+
+/// Text with zero width spaces around the characters Org would read as markup: emphasis
+/// markers that could open or close emphasis, `[[`, `[fn:`, `<<`, `{{{`, backslashes
+/// before entities, LaTeX and line breaks, and URLs
+fn escape(text: &str) -> String {
+    let chars: Vec<char> = text
+        .chars()
+        .filter(|c| *c != ZWSP && *c != '\r')
+        .map(|c| if c == '\n' { ' ' } else { c })
+        .collect();
+    let mut escaped = String::with_capacity(text.len());
+    for (index, &c) in chars.iter().enumerate() {
+        let previous = index.checked_sub(1).map(|previous| chars[previous]);
+        let next = chars.get(index + 1).copied();
+        let rest = &chars[index + 1..];
+        match c {
+            c if MARKERS.contains(c) || c == '$' => {
+                let opens = previous.is_none_or(|c| c.is_whitespace() || EMPHASIS_PRE.contains(c))
+                    && next.is_some_and(|c| !c.is_whitespace());
+                let closes = previous.is_some_and(|c| !c.is_whitespace())
+                    && next.is_none_or(|c| c.is_whitespace() || EMPHASIS_POST.contains(c));
+                if opens && c != '$' {
+                    escaped.push(ZWSP);
+                }
+                escaped.push(c);
+                if closes {
+                    escaped.push(ZWSP);
+                }
+            }
+            '[' if next == Some('[') || rest.starts_with(&['f', 'n', ':']) => {
+                escaped.push(c);
+                escaped.push(ZWSP);
+            }
+            '<' if next == Some('<') => {
+                escaped.push(c);
+                escaped.push(ZWSP);
+            }
+            '{' if rest.starts_with(&['{', '{']) => {
+                escaped.push(c);
+                escaped.push(ZWSP);
+            }
+            '\\' if next.is_some_and(|c| c.is_alphanumeric() || "()[]\\".contains(c)) => {
+                escaped.push(c);
+                escaped.push(ZWSP);
+            }
+            ':' if rest.starts_with(&['/', '/']) => {
+                escaped.push(c);
+                escaped.push(ZWSP);
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Whether a line would be read as something else than text at the start of a paragraph:
+/// a headline, a list item, a keyword, a table, fixed-width text, a rule...
+fn needs_guard(line: &str) -> bool {
+    line.starts_with(['*', '-', '+', '#', '|', ':'])
+        || line.starts_with("$$")
+        || list_item(line).is_some()
+}
+
+/// Path of a link, its brackets escaped
+fn escape_link(path: &str) -> String {
+    path.replace('\\', "\\\\")
+        .replace('[', "\\[")
+        .replace(']', "\\]")
+}
+
+/// Whether an element is an image, or a link of an image
+fn is_image(element: &Element) -> bool {
+    match element {
+        Element::Image(_) => true,
+        Element::Hyperlink { elements, .. } => matches!(elements.as_slice(), [Element::Image(_)]),
+        _ => false,
+    }
+}
+
+/// Ids of the bookmarks of the document, the targets of the links without `#`
+fn bookmarks(document: &Document) -> HashSet<String> {
+    fn visit(element: &Element, bookmarks: &mut HashSet<String>) {
+        if let Element::Bookmark { id, .. } = element {
+            bookmarks.insert(id.clone());
+        }
+        for child in element.children() {
+            visit(child, bookmarks);
+        }
+    }
+
+    let mut bookmarks = HashSet::new();
+    for band in &document.bands {
+        for element in band.elements() {
+            visit(element, &mut bookmarks);
+        }
+    }
+    bookmarks
+}
+
+/// Source block lines, those that Org would read as headlines or keywords escaped with a
+/// comma
+fn escape_code(code: &str) -> String {
+    code.lines()
+        .map(|line| {
+            let trimmed = line.trim_start();
+            if line.starts_with('*')
+                || trimmed.starts_with("#+")
+                || trimmed.starts_with(",*")
+                || trimmed.starts_with(",#+")
+            {
+                let indent = line.len() - trimmed.len();
+                format!("{},{}", &line[..indent], trimmed)
+            } else {
+                line.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Options of an image link: caption and HTML attributes
+type ImageOptions = Vec<(&'static str, String)>;
+
+struct Writer<'a, F>
+where
+    F: Fn(&Bytes, &str) -> anyhow::Result<()>,
+{
+    image_saver: &'a F,
+    image_count: usize,
+    link_targets: HashSet<String>,
+    bookmarks: HashSet<String>,
+    /// Depth of the lists and blocks being written, where there are no headlines
+    depth: usize,
+}
+
+impl<F> Writer<'_, F>
+where
+    F: Fn(&Bytes, &str) -> anyhow::Result<()>,
+{
+    fn blocks<'e>(
+        &mut self,
+        elements: impl IntoIterator<Item = &'e Element>,
+    ) -> anyhow::Result<String> {
+        let mut parts: Vec<String> = vec![];
+        // whether the last block is a list, and ordered
+        let mut last_list = None;
+        for element in elements {
+            let org = self.block(element)?;
+            if org.is_empty() {
+                continue;
+            }
+            let list = match element {
+                Element::List { numbered, .. } => Some(*numbered),
+                _ => None,
+            };
+            if list.is_some() && list == last_list {
+                // a comment ends the list before, the lists would be one otherwise
+                parts.push("#".to_string());
+            }
+            last_list = list;
+            parts.push(org);
+        }
+        Ok(parts.join("\n\n"))
+    }
+
+    /// Blocks nested in a list or a block
+    fn nested<'e>(
+        &mut self,
+        elements: impl IntoIterator<Item = &'e Element>,
+    ) -> anyhow::Result<String> {
+        self.depth += 1;
+        let org = self.blocks(elements);
+        self.depth -= 1;
+        org
+    }
+
+    fn block(&mut self, element: &Element) -> anyhow::Result<String> {
+        if let Some((kind, title, blocks)) = element.admonition_parts() {
+            let kind = kind.to_lowercase().replace(char::is_whitespace, "-");
+            let content = self.nested(blocks)?;
+            let head = match title {
+                Some(title) => format!("#+begin_{kind} {}", escape(&title)),
+                None => format!("#+begin_{kind}"),
+            };
+            return Ok(format!("{head}\n{content}\n#+end_{kind}").replace("\n\n#+end_", "\n#+end_"));
+        }
+        Ok(match element {
+            Element::Header { level, text } => self.headline(*level, text, &HashMap::new()),
+            Element::Attributed {
+                attributes,
+                element,
+            } => match element.as_ref() {
+                Element::Header { level, text } => self.headline(*level, text, attributes),
+                element => self.block(element)?,
+            },
+            Element::Paragraph { elements, .. } => match elements.as_slice() {
+                [image] if is_image(image) => self.image_block(image)?,
+                elements => self.paragraph(elements)?,
+            },
+            element if is_image(element) => self.image_block(element)?,
+            Element::Table { headers, rows } => self.table(headers, rows)?,
+            Element::List { .. } => self.list(element)?,
+            Element::CodeBlock { code, .. } => {
+                let code = escape_code(code.trim_end_matches('\n'));
+                match element.code_language() {
+                    Some(language) => format!("#+begin_src {language}\n{code}\n#+end_src"),
+                    None => format!("#+begin_example\n{code}\n#+end_example"),
+                }
+            }
+            Element::Math { tex, display: true } => format!("\\[\n{}\n\\]", tex.trim()),
+            Element::TableOfContents { max_level } => format!("#+toc: headlines {max_level}"),
+            Element::Media { .. } => match element.media_link() {
+                Some(link) => self.paragraph(&[link])?,
+                None => String::new(),
+            },
+            Element::Container {
+                kind: ContainerKind::Aside,
+                elements,
+            } => format!("#+begin_aside\n{}\n#+end_aside", self.nested(elements)?),
+            Element::Container { elements, .. } => self.blocks(elements)?,
+            Element::Raw {
+                format: DocumentType::Org,
+                content,
+            } => content.trim_end().to_string(),
+            Element::Raw {
+                format: format @ (DocumentType::HTML | DocumentType::LaTeX),
+                content,
+            } => {
+                let backend = if *format == DocumentType::HTML {
+                    "html"
+                } else {
+                    "latex"
+                };
+                format!(
+                    "#+begin_export {backend}\n{}\n#+end_export",
+                    content.trim_end()
+                )
+            }
+            Element::Raw { .. } | Element::LineBreak | Element::Drawing { .. } => String::new(),
+            element => self.paragraph(std::slice::from_ref(element))?,
+        })
+    }
+
+    /// Headline with the keyword, priority and tags of its attributes, a strong paragraph
+    /// inside lists and blocks
+    fn headline(&mut self, level: u8, text: &str, attributes: &HashMap<String, String>) -> String {
+        let mut title = escape(text.trim());
+        if title.is_empty() {
+            return title;
+        }
+        if self.depth > 0 {
+            let strong = Element::Styled {
+                style: TextStyle::Strong,
+                elements: vec![self::text(text.trim())],
+            };
+            let mut out = InlineText::default();
+            let _ = self.inline(&strong, &mut out);
+            return out.finish().join("\n");
+        }
+        // a first word that is a keyword, or tags at the end, would be read as such
+        if ["TODO", "DONE", "COMMENT"]
+            .iter()
+            .any(|keyword| title.split(' ').next() == Some(*keyword))
+            || title.starts_with("[#")
+        {
+            title.insert(0, ZWSP);
+        }
+        if title.ends_with(':') {
+            title.push(ZWSP);
+        }
+        let mut org = "*".repeat(usize::from(level.max(1)));
+        if let Some(keyword) = attributes.get("org:todo") {
+            let _ = write!(org, " {keyword}");
+        }
+        if let Some(priority) = attributes.get("org:priority") {
+            let _ = write!(org, " [#{priority}]");
+        }
+        let _ = write!(org, " {title}");
+        if let Some(tags) = attributes.get("org:tags").filter(|tags| !tags.is_empty()) {
+            let _ = write!(org, " :{}:", tags.trim_matches(':'));
+        }
+        let anchor = attributes
+            .get("id")
+            .cloned()
+            .unwrap_or_else(|| header_anchor(text));
+        if self.link_targets.contains(&anchor) && !self.bookmarks.contains(&anchor) {
+            let _ = write!(org, "\n:PROPERTIES:\n:CUSTOM_ID: {anchor}\n:END:");
+        }
+        org
+    }
+
+    /// Paragraph of inline elements, its line breaks ending lines with `\\`
+    fn paragraph(&mut self, elements: &[Element]) -> anyhow::Result<String> {
+        let mut out = InlineText::default();
+        for element in elements {
+            self.inline(element, &mut out)?;
+        }
+        let lines: Vec<String> = out
+            .finish()
+            .into_iter()
+            .map(|line| {
+                if needs_guard(&line) {
+                    format!("{ZWSP}{line}")
+                } else {
+                    line
+                }
+            })
+            .collect();
+        Ok(lines.join(" \\\\\n"))
+    }
+
+    fn inline(&mut self, element: &Element, out: &mut InlineText) -> anyhow::Result<()> {
+        match element {
+            Element::Text { text, .. } => out.push(&escape(text)),
+            Element::Header { text, .. } => self.inline(
+                &Element::Styled {
+                    style: TextStyle::Strong,
+                    elements: vec![self::text(text.as_str())],
+                },
+                out,
+            )?,
+            Element::Paragraph { elements, .. } | Element::Container { elements, .. } => {
+                for element in elements {
+                    self.inline(element, out)?;
+                }
+            }
+            Element::Styled { style, elements } => {
+                let marker = match style {
+                    TextStyle::Strong => '*',
+                    TextStyle::Emphasis => '/',
+                    TextStyle::Strikethrough => '+',
+                };
+                let mut inner = InlineText::default();
+                for element in elements {
+                    self.inline(element, &mut inner)?;
+                }
+                let content = inner.finish().join(" ");
+                let trimmed = content.trim();
+                if trimmed.is_empty() {
+                    out.push(&content);
+                } else {
+                    if content.starts_with(char::is_whitespace) {
+                        out.push(" ");
+                    }
+                    out.push(&format!("{marker}{trimmed}{marker}"));
+                    if content.ends_with(char::is_whitespace) {
+                        out.push(" ");
+                    }
+                }
+            }
+            Element::Hyperlink { elements, url, .. } => {
+                let path = match url.strip_prefix('#') {
+                    Some(anchor) if self.bookmarks.contains(anchor) => escape_link(anchor),
+                    _ => escape_link(url),
+                };
+                let mut description = InlineText::default();
+                for element in elements {
+                    match element {
+                        Element::Image(image) => {
+                            if let Some((name, _)) = self.save_image(image)? {
+                                description.push(&format!("file:{name}"));
+                            }
+                        }
+                        element => self.inline(element, &mut description)?,
+                    }
+                }
+                let description = description.finish().join(" ");
+                if description.trim().is_empty() {
+                    out.push(&format!("[[{path}]]"));
+                } else {
+                    out.push(&format!("[[{path}][{}]]", description.trim()));
+                }
+            }
+            Element::InlineCode { text } => {
+                let code = text.replace('\n', " ");
+                let code = code.trim();
+                if !code.is_empty() {
+                    let marker = if code.contains('~') { '=' } else { '~' };
+                    out.push(&format!("{marker}{code}{marker}"));
+                }
+            }
+            Element::CodeBlock { code, .. } => {
+                for (index, line) in code.lines().enumerate() {
+                    if index > 0 {
+                        out.line_break();
+                    }
+                    if !line.trim().is_empty() {
+                        out.push(&format!("={}=", line.trim()));
+                    }
+                }
+            }
+            Element::Math { tex, .. } => out.push(&format!("\\({}\\)", tex.trim())),
+            Element::LineBreak => out.line_break(),
+            Element::Footnote { elements } => {
+                let mut note = InlineText::default();
+                for element in elements {
+                    self.inline(element, &mut note)?;
+                    note.push(" ");
+                }
+                out.push(&format!("[fn::{}]", note.finish().join(" ").trim()));
+            }
+            Element::Bookmark { id, .. } => out.push(&format!("<<{id}>>")),
+            Element::Image(image) => {
+                if let Some((name, _)) = self.save_image(image)? {
+                    out.push(&format!("[[file:{name}]]"));
+                }
+            }
+            Element::Attributed { element, .. } => self.inline(element, out)?,
+            Element::Raw {
+                format: DocumentType::Org,
+                content,
+            } => out.push(content),
+            Element::Media { .. } => {
+                if let Some(link) = element.media_link() {
+                    self.inline(&link, out)?;
+                }
+            }
+            Element::List { elements, .. } => {
+                for item in elements {
+                    self.inline(&item.element, out)?;
+                    out.push(" ");
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Link of an image after its caption and HTML attributes, a link of the image when it
+    /// has a target
+    fn image_block(&mut self, element: &Element) -> anyhow::Result<String> {
+        let (image, url) = match element {
+            Element::Hyperlink { elements, url, .. } => match elements.as_slice() {
+                [Element::Image(image)] => (image, Some(url)),
+                _ => return Ok(String::new()),
+            },
+            Element::Image(image) => (image, None),
+            _ => return Ok(String::new()),
+        };
+        let Some((name, options)) = self.save_image(image)? else {
+            return Ok(String::new());
+        };
+        let mut org = String::new();
+        if !image.title().trim().is_empty() {
+            let _ = writeln!(org, "#+caption: {}", escape(image.title().trim()));
+        }
+        if !options.is_empty() {
+            let attributes: Vec<String> = options
+                .iter()
+                .map(|(name, value)| format!(":{name} {value}"))
+                .collect();
+            let _ = writeln!(org, "#+attr_html: {}", attributes.join(" "));
+        }
+        match url {
+            Some(url) => {
+                let _ = write!(org, "[[{}][file:{name}]]", escape_link(url));
+            }
+            None => {
+                let _ = write!(org, "[[file:{name}]]");
+            }
+        }
+        Ok(org)
+    }
+
+    /// Name of the saved image and its HTML attributes
+    fn save_image(&mut self, image: &ImageData) -> anyhow::Result<Option<(String, ImageOptions)>> {
+        if image.bytes().is_empty() {
+            warn!("Image without content left out: {}", image.title());
+            return Ok(None);
+        }
+        self.image_count += 1;
+        let name = format!(
+            "image{}{}",
+            self.image_count,
+            image.image_type().to_extension()
+        );
+        (self.image_saver)(image.bytes(), &name)?;
+        let mut options = vec![];
+        if !image.alt().trim().is_empty() {
+            options.push(("alt", image.alt().trim().replace('\n', " ")));
+        }
+        let size = image.size();
+        if let Some(width) = &size.width {
+            options.push(("width", width.trim().to_string()));
+        }
+        if let Some(height) = &size.height {
+            options.push(("height", height.trim().to_string()));
+        }
+        Ok(Some((name, options)))
+    }
+
+    /// Table of inline cells, aligned, its header above a rule
+    fn table(&mut self, headers: &[TableHeader], rows: &[TableRow]) -> anyhow::Result<String> {
+        let mut lines: Vec<Vec<String>> = vec![];
+        if !headers.is_empty() {
+            let mut cells = vec![];
+            for header in headers {
+                cells.push(self.cell(&header.element)?);
+            }
+            lines.push(cells);
+        }
+        for row in rows {
+            let mut cells = vec![];
+            for cell in &row.cells {
+                cells.push(self.cell(&cell.element)?);
+            }
+            lines.push(cells);
+        }
+        let columns = lines.iter().map(Vec::len).max().unwrap_or_default();
+        if columns == 0 {
+            return Ok(String::new());
+        }
+        let widths: Vec<usize> = (0..columns)
+            .map(|column| {
+                lines
+                    .iter()
+                    .filter_map(|cells| cells.get(column))
+                    .map(|cell| width(cell))
+                    .max()
+                    .unwrap_or_default()
+                    .max(1)
+            })
+            .collect();
+        let row = |cells: &Vec<String>| {
+            let cells: Vec<String> = widths
+                .iter()
+                .enumerate()
+                .map(|(column, width)| {
+                    let cell = cells.get(column).map_or("", String::as_str);
+                    format!(" {cell}{} ", " ".repeat(width - self::width(cell)))
+                })
+                .collect();
+            format!("|{}|", cells.join("|"))
+        };
+        let mut org: Vec<String> = vec![];
+        for (index, cells) in lines.iter().enumerate() {
+            org.push(row(cells));
+            if index == 0 && !headers.is_empty() {
+                let rule: Vec<String> = widths.iter().map(|width| "-".repeat(width + 2)).collect();
+                org.push(format!("|{}|", rule.join("+")));
+            }
+        }
+        Ok(org.join("\n"))
+    }
+
+    /// Inline text of a table cell, its bars written as the `\vert{}` entity
+    fn cell(&mut self, element: &Element) -> anyhow::Result<String> {
+        let mut out = InlineText::default();
+        self.inline(element, &mut out)?;
+        Ok(out.finish().join(" ").replace('|', "\\vert{}"))
+    }
+
+    /// Plain or ordered list, the blocks after an item being its own blocks
+    fn list(&mut self, list: &Element) -> anyhow::Result<String> {
+        let Element::List {
+            elements,
+            numbered,
+            start,
+            ..
+        } = list
+        else {
+            return Ok(String::new());
+        };
+        let mut items: Vec<Vec<&Element>> = vec![];
+        for item in elements {
+            let element = &item.element;
+            match items.last_mut() {
+                Some(blocks)
+                    if !element.is_inline()
+                        && !matches!(
+                            element,
+                            Element::Paragraph { .. } | Element::Header { .. }
+                        ) =>
+                {
+                    blocks.push(element)
+                }
+                _ => items.push(vec![element]),
+            }
+        }
+        let mut org = vec![];
+        let mut loose = false;
+        for (index, blocks) in items.into_iter().enumerate() {
+            let marker = if *numbered {
+                format!("{}.", start + index)
+            } else {
+                "-".to_string()
+            };
+            let mut body = self.nested(blocks)?;
+            if *numbered && index == 0 && *start != 1 {
+                body.insert_str(0, &format!("[@{start}] "));
+            }
+            loose |= body.contains('\n');
+            let indent = " ".repeat(marker.len() + 1);
+            let mut lines = body.lines();
+            let mut item = match lines.next() {
+                Some(first) => format!("{marker} {first}"),
+                None => marker.clone(),
+            };
+            for line in lines {
+                item.push('\n');
+                if !line.is_empty() {
+                    item.push_str(&indent);
+                    item.push_str(line);
+                }
+            }
+            org.push(item);
+        }
+        Ok(org.join(if loose { "\n\n" } else { "\n" }))
+    }
+}
+
+/// Inline text being written, its lines separated by line breaks
+#[derive(Default)]
+struct InlineText {
+    lines: Vec<String>,
+}
+
+impl InlineText {
+    fn push(&mut self, text: &str) {
+        if self.lines.is_empty() {
+            self.lines.push(String::new());
+        }
+        let last = self.lines.len() - 1;
+        self.lines[last].push_str(text);
+    }
+
+    fn line_break(&mut self) {
+        if self.lines.is_empty() {
+            self.lines.push(String::new());
+        }
+        self.lines.push(String::new());
+    }
+
+    fn finish(self) -> Vec<String> {
+        let mut lines: Vec<String> = self
+            .lines
+            .into_iter()
+            .map(|line| line.trim().to_string())
+            .collect();
+        while lines.last().is_some_and(String::is_empty) {
+            lines.pop();
+        }
+        let leading = lines.iter().take_while(|line| line.is_empty()).count();
+        lines.split_off(leading)
+    }
+}
+
+/// Level and rest of a headline: stars at the start of the line and a space
+fn headline(line: &str) -> Option<(usize, &str)> {
+    let rest = line.trim_start_matches('*');
+    let level = line.len() - rest.len();
+    (level > 0 && (rest.is_empty() || rest.starts_with(' '))).then(|| (level, rest.trim()))
+}
+
+/// Key and value of a keyword line: `#+key: value`
+fn keyword(line: &str) -> Option<(String, &str)> {
+    let rest = line.trim_start().strip_prefix("#+")?;
+    let (key, value) = rest.split_once(':')?;
+    (!key.is_empty() && !key.contains(char::is_whitespace))
+        .then(|| (key.to_lowercase(), value.trim()))
+}
+
+/// Name and parameters of the line starting a block: `#+begin_name parameters`
+fn block_start(line: &str) -> Option<(String, &str)> {
+    let trimmed = line.trim_start();
+    let rest = trimmed
+        .get(..8)
+        .filter(|start| start.eq_ignore_ascii_case("#+begin_"))
+        .map(|_| &trimmed[8..])?;
+    let (name, parameters) = rest.split_once(' ').unwrap_or((rest, ""));
+    (!name.is_empty()).then(|| (name.to_lowercase(), parameters.trim()))
+}
+
+/// Item of a plain or ordered list: indentation, whether ordered, and the byte column of
+/// its text
+fn list_item(line: &str) -> Option<(usize, bool, usize)> {
+    let indent = indentation(line);
+    let rest = &line[indent..];
+    let (ordered, marker) = match rest.chars().next()? {
+        '-' | '+' => (false, 1),
+        '*' if indent > 0 => (false, 1),
+        c if c.is_ascii_digit() => {
+            let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+            if !rest[digits..].starts_with(['.', ')']) {
+                return None;
+            }
+            (true, digits + 1)
+        }
+        _ => return None,
+    };
+    let after = &rest[marker..];
+    if !after.is_empty() && !after.starts_with(' ') {
+        return None;
+    }
+    Some((indent, ordered, line.len() - after.trim_start().len()))
+}
+
+/// Whether a line is a table line, starting with `|`
+fn is_table_line(line: &str) -> bool {
+    line.trim_start().starts_with('|')
+}
+
+/// Label and first line of a footnote definition: `[fn:label] text`
+fn footnote_definition(line: &str) -> Option<(&str, &str)> {
+    let rest = line.strip_prefix("[fn:")?;
+    let (label, text) = rest.split_once(']')?;
+    (!label.is_empty() && !label.contains(char::is_whitespace)).then(|| (label, text.trim()))
+}
+
+/// End of the footnote definition starting at `index`: the next definition, headline, or
+/// two blank lines
+fn footnote_end(lines: &[String], index: usize) -> usize {
+    let mut end = index + 1;
+    while let Some(line) = lines.get(end) {
+        if headline(line).is_some()
+            || footnote_definition(line).is_some()
+            || line.is_empty() && lines.get(end + 1).is_some_and(String::is_empty)
+        {
+            break;
+        }
+        end += 1;
+    }
+    while end > index + 1 && lines[end - 1].is_empty() {
+        end -= 1;
+    }
+    end
+}
+
+/// Whether a line starts an element that ends a paragraph
+fn starts_element(line: &str) -> bool {
+    let trimmed = line.trim_start();
+    trimmed.is_empty()
+        || headline(line).is_some()
+        || list_item(line).is_some()
+        || trimmed.starts_with('|')
+        || trimmed.starts_with("#+")
+        || trimmed == "#"
+        || trimmed.starts_with("# ")
+        || trimmed == ":"
+        || trimmed.starts_with(": ")
+        || footnote_definition(line).is_some()
+        || trimmed.starts_with("\\begin{")
+        || trimmed.starts_with("\\[")
+        || trimmed.starts_with("$$")
+        || is_rule(trimmed)
+}
+
+/// Whether a line is a horizontal rule, five dashes or more
+fn is_rule(line: &str) -> bool {
+    line.len() >= 5 && line.chars().all(|c| c == '-')
+}
+
+/// Whether a link path is a local image
+fn is_image_path(path: &str) -> bool {
+    let path = path.strip_prefix("file:").unwrap_or(path);
+    !path.contains("://")
+        && path.rsplit_once('.').is_some_and(|(_, extension)| {
+            IMAGE_EXTENSIONS.contains(&extension.to_lowercase().as_str())
+        })
+}
+
+/// Text of a heading for its anchor, without markup
+fn plain_title(title: &str) -> String {
+    title
+        .chars()
+        .filter(|c| !MARKERS.contains(*c) && *c != ZWSP)
+        .collect()
+}
+
+/// Name as compared for links: lowercase with its whitespace collapsed
+fn normalize(name: &str) -> String {
+    name.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+/// Index of the marker closing emphasis opened at `index`, the content spanning two lines
+/// at most
+fn emphasis_end(chars: &[char], index: usize, marker: char) -> Option<usize> {
+    let mut newlines = 0;
+    for end in index + 2..chars.len() {
+        if chars[end] == marker
+            && !chars[end - 1].is_whitespace()
+            && chars
+                .get(end + 1)
+                .is_none_or(|c| c.is_whitespace() || EMPHASIS_POST.contains(*c))
+        {
+            return Some(end);
+        }
+        if chars[end] == '\n' {
+            newlines += 1;
+            if newlines > 1 {
+                return None;
+            }
+        }
+    }
+    None
+}
+
+/// Keywords before the next element: caption, name and export attributes
+#[derive(Default)]
+struct Affiliated {
+    caption: Option<String>,
+    /// `#+attr_html:` attributes, `:alt The logo :width 50%`
+    html: HashMap<String, String>,
+}
+
+struct Parser<'a, F>
+where
+    F: Fn(&str) -> anyhow::Result<Bytes>,
+{
+    image_loader: &'a F,
+    metadata: Metadata,
+    /// TODO keywords, `TODO` and `DONE` unless set by `#+todo:`
+    todo_keywords: Vec<String>,
+    /// Anchors of the headlines by normalized title, for `[[*Title]]` links
+    headlines: HashMap<String, String>,
+    /// Normalized names of the targets, `<<name>>`, for links to them
+    targets: HashSet<String>,
+    /// Lines of the footnote definitions by label
+    footnotes: HashMap<String, Vec<String>>,
+    /// Templates of the macros by name, `$1` being their first argument
+    macros: HashMap<String, String>,
+    affiliated: Affiliated,
+    /// Depth of the footnotes and macros being read, that may refer to themselves
+    depth: usize,
+}
+
+impl<F> Parser<'_, F>
+where
+    F: Fn(&str) -> anyhow::Result<Bytes>,
+{
+    /// Reads the TODO keywords, macros, footnote definitions, headlines and targets ahead of
+    /// the references to them
+    fn definitions(&mut self, lines: &[String]) {
+        let mut index = 0;
+        while index < lines.len() {
+            let line = &lines[index];
+            if let Some((key, value)) = keyword(line) {
+                match key.as_str() {
+                    "todo" | "seq_todo" | "typ_todo" => {
+                        self.todo_keywords.extend(
+                            value
+                                .split_whitespace()
+                                .filter(|word| *word != "|")
+                                .map(|word| word.split('(').next().unwrap_or(word).to_string()),
+                        );
+                    }
+                    "macro" => {
+                        if let Some((name, template)) = value.split_once(' ') {
+                            self.macros
+                                .insert(name.to_lowercase(), template.trim().to_string());
+                        }
+                    }
+                    _ => {}
+                }
+            } else if let Some((label, first)) = footnote_definition(line) {
+                let end = footnote_end(lines, index);
+                let mut body = vec![first.to_string()];
+                body.extend(lines[index + 1..end].iter().cloned());
+                self.footnotes.insert(label.to_string(), body);
+                index = end;
+                continue;
+            } else if let Some((_, rest)) = headline(line) {
+                let title = self.headline_parts(rest).2;
+                let custom_id = lines[index + 1..]
+                    .iter()
+                    .take(3)
+                    .take_while(|line| !line.trim().eq_ignore_ascii_case(":end:"))
+                    .find_map(|line| {
+                        let (key, value) = line.trim().strip_prefix(':')?.split_once(':')?;
+                        key.eq_ignore_ascii_case("custom_id")
+                            .then(|| value.trim().to_string())
+                    });
+                let title = plain_title(title);
+                self.headlines.insert(
+                    normalize(&title),
+                    custom_id.unwrap_or_else(|| header_anchor(&title)),
+                );
+            }
+            let mut rest = line.as_str();
+            while let Some(start) = rest.find("<<") {
+                rest = &rest[start + 2..];
+                if let Some(end) = rest.find(">>") {
+                    self.targets
+                        .insert(normalize(rest[..end].trim_start_matches('<')));
+                    rest = &rest[end..];
+                }
+            }
+            index += 1;
+        }
+    }
+
+    /// Keyword, priority and title of the rest of a headline, with its tags
+    fn headline_parts<'l>(
+        &self,
+        rest: &'l str,
+    ) -> (Option<String>, Option<String>, &'l str, Option<String>) {
+        let mut rest = rest;
+        let mut todo = None;
+        if let Some((word, after)) = rest.split_once(' ').or(Some((rest, ""))) {
+            if self.todo_keywords.iter().any(|keyword| keyword == word) {
+                todo = Some(word.to_string());
+                rest = after.trim_start();
+            }
+        }
+        let mut priority = None;
+        if let Some(after) = rest.strip_prefix("[#") {
+            if let Some((value, after)) = after.split_once(']') {
+                priority = Some(value.to_string());
+                rest = after.trim_start();
+            }
+        }
+        let mut tags = None;
+        if let Some((title, last)) = rest.rsplit_once(char::is_whitespace).or(Some(("", rest))) {
+            if last.len() > 2
+                && last.starts_with(':')
+                && last.ends_with(':')
+                && last[1..last.len() - 1]
+                    .chars()
+                    .all(|c| c.is_alphanumeric() || "_@#%:".contains(c))
+            {
+                tags = Some(last.trim_matches(':').to_string());
+                rest = title.trim_end();
+            }
+        }
+        (todo, priority, rest, tags)
+    }
+
+    fn blocks(&mut self, lines: &[String]) -> anyhow::Result<Vec<Element>> {
+        let mut blocks = vec![];
+        let mut index = 0;
+        while index < lines.len() {
+            if lines[index].trim().is_empty() {
+                index += 1;
+                continue;
+            }
+            index = self.block(lines, index, &mut blocks)?;
+        }
+        Ok(blocks)
+    }
+
+    /// Reads the element at `index`, returning the line after it
+    fn block(
+        &mut self,
+        lines: &[String],
+        index: usize,
+        blocks: &mut Vec<Element>,
+    ) -> anyhow::Result<usize> {
+        let line = lines[index].as_str();
+        let trimmed = line.trim_start();
+        if let Some((level, rest)) = headline(line) {
+            return self.headline(lines, index, level, rest, blocks);
+        }
+        if let Some((name, parameters)) = block_start(line) {
+            return self.greater_block(lines, index, &name, parameters, blocks);
+        }
+        if let Some((key, value)) = keyword(line) {
+            self.keyword(&key, value, blocks)?;
+            return Ok(index + 1);
+        }
+        if trimmed == "#" || trimmed.starts_with("# ") || is_rule(trimmed) {
+            return Ok(index + 1);
+        }
+        if footnote_definition(line).is_some() {
+            return Ok(footnote_end(lines, index));
+        }
+        if let Some(end) = drawer_end(lines, index) {
+            return Ok(end);
+        }
+        if trimmed == ":" || trimmed.starts_with(": ") {
+            let end = index
+                + lines[index..]
+                    .iter()
+                    .position(|line| {
+                        let line = line.trim_start();
+                        line != ":" && !line.starts_with(": ")
+                    })
+                    .unwrap_or(lines.len() - index);
+            let code: Vec<&str> = lines[index..end]
+                .iter()
+                .map(|line| {
+                    let line = line.trim_start();
+                    line.strip_prefix(": ").unwrap_or(&line[1..])
+                })
+                .collect();
+            blocks.push(Element::CodeBlock {
+                info: String::new(),
+                code: code.join("\n"),
+            });
+            self.affiliated = Affiliated::default();
+            return Ok(end);
+        }
+        if is_table_line(line) {
+            return self.table(lines, index, blocks);
+        }
+        if let Some((indent, ordered, _)) = list_item(line) {
+            return self.list(lines, index, indent, ordered, blocks);
+        }
+        if let Some(end) = self.math(lines, index, blocks) {
+            return Ok(end);
+        }
+        self.paragraph(lines, index, blocks)
+    }
+
+    fn keyword(&mut self, key: &str, value: &str, blocks: &mut Vec<Element>) -> anyhow::Result<()> {
+        let metadata = &mut self.metadata;
+        match key {
+            "title" => {
+                metadata.title = Some(match metadata.title.take() {
+                    Some(title) => format!("{title} {value}"),
+                    None => value.to_string(),
+                })
+            }
+            "author" => metadata.author = Some(value.to_string()),
+            "date" => metadata.date = Some(value.trim_matches(['<', '>', '[', ']']).to_string()),
+            "description" => metadata.description = Some(value.to_string()),
+            "keywords" => {
+                metadata.keywords = value
+                    .split([',', ' '])
+                    .map(str::trim)
+                    .filter(|keyword| !keyword.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            }
+            "subtitle" | "email" | "language" => {
+                metadata.custom.insert(key.to_string(), value.to_string());
+            }
+            "caption" => self.affiliated.caption = Some(value.to_string()),
+            "attr_html" => {
+                let mut name: Option<&str> = None;
+                let mut words: Vec<&str> = vec![];
+                for word in value.split_whitespace().chain([":"]) {
+                    match word.strip_prefix(':') {
+                        Some(next) => {
+                            if let Some(name) = name {
+                                self.affiliated
+                                    .html
+                                    .insert(name.to_lowercase(), words.join(" "));
+                            }
+                            name = Some(next);
+                            words.clear();
+                        }
+                        None => words.push(word),
+                    }
+                }
+            }
+            "toc" => {
+                let mut words = value.split_whitespace();
+                if words.next() == Some("headlines") {
+                    blocks.push(Element::TableOfContents {
+                        max_level: words
+                            .next()
+                            .and_then(|depth| depth.parse().ok())
+                            .unwrap_or(6),
+                    });
+                }
+            }
+            "html" => blocks.push(Element::Raw {
+                format: DocumentType::HTML,
+                content: value.to_string(),
+            }),
+            "latex" => blocks.push(Element::Raw {
+                format: DocumentType::LaTeX,
+                content: value.to_string(),
+            }),
+            "include" | "setupfile" => warn!("Keyword left out: #+{key}: {value}"),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Headline, after its planning line and with the `CUSTOM_ID` of its properties drawer
+    /// as id
+    fn headline(
+        &mut self,
+        lines: &[String],
+        index: usize,
+        level: usize,
+        rest: &str,
+        blocks: &mut Vec<Element>,
+    ) -> anyhow::Result<usize> {
+        let (todo, priority, title, tags) = self.headline_parts(rest);
+        let text = plain_text(&self.inline(title)?);
+        let mut attributes = HashMap::new();
+        attributes.extend(todo.map(|todo| ("org:todo".to_string(), todo)));
+        attributes.extend(priority.map(|priority| ("org:priority".to_string(), priority)));
+        attributes.extend(tags.map(|tags| ("org:tags".to_string(), tags)));
+        let mut end = index + 1;
+        if lines.get(end).is_some_and(|line| {
+            let line = line.trim_start();
+            ["SCHEDULED:", "DEADLINE:", "CLOSED:"]
+                .iter()
+                .any(|planning| line.starts_with(planning))
+        }) {
+            end += 1;
+        }
+        if let Some(drawer) = drawer_end(lines, end) {
+            if lines[end].trim().eq_ignore_ascii_case(":properties:") {
+                for line in &lines[end + 1..drawer - 1] {
+                    if let Some((key, value)) = line
+                        .trim()
+                        .strip_prefix(':')
+                        .and_then(|line| line.split_once(':'))
+                    {
+                        if key.eq_ignore_ascii_case("custom_id") {
+                            attributes.insert("id".to_string(), value.trim().to_string());
+                        }
+                    }
+                }
+                end = drawer;
+            }
+        }
+        let header = Element::Header {
+            level: level.min(6) as u8,
+            text,
+        };
+        blocks.push(if attributes.is_empty() {
+            header
+        } else {
+            Element::Attributed {
+                attributes,
+                element: Box::new(header),
+            }
+        });
+        Ok(end)
+    }
+
+    /// Block between `#+begin_name` and `#+end_name`
+    fn greater_block(
+        &mut self,
+        lines: &[String],
+        index: usize,
+        name: &str,
+        parameters: &str,
+        blocks: &mut Vec<Element>,
+    ) -> anyhow::Result<usize> {
+        let end_line = format!("#+end_{name}");
+        let Some(length) = lines[index + 1..]
+            .iter()
+            .position(|line| line.trim().eq_ignore_ascii_case(&end_line))
+        else {
+            // not a block without its end, a paragraph
+            return self.paragraph(lines, index, blocks);
+        };
+        let content = &lines[index + 1..index + 1 + length];
+        let end = index + length + 2;
+        let affiliated = std::mem::take(&mut self.affiliated);
+        match name {
+            "src" | "example" => {
+                if let Some(caption) = affiliated.caption {
+                    blocks.push(paragraph(vec![Element::Styled {
+                        style: TextStyle::Strong,
+                        elements: self.inline(&caption)?,
+                    }]));
+                }
+                let code: Vec<String> = dedent(content)
+                    .into_iter()
+                    .map(|line| {
+                        let indent = indentation(&line);
+                        let rest = &line[indent..];
+                        if rest.starts_with(",*") || rest.starts_with(",#+") {
+                            format!("{}{}", &line[..indent], &rest[1..])
+                        } else {
+                            line
+                        }
+                    })
+                    .collect();
+                blocks.push(Element::CodeBlock {
+                    info: if name == "src" {
+                        parameters
+                            .split_whitespace()
+                            .next()
+                            .unwrap_or_default()
+                            .to_string()
+                    } else {
+                        String::new()
+                    },
+                    code: code.join("\n"),
+                });
+            }
+            "quote" | "center" => blocks.extend(self.blocks(content)?),
+            "verse" => {
+                let mut elements = vec![];
+                for (line_index, line) in dedent(content).iter().enumerate() {
+                    if line_index > 0 {
+                        elements.push(Element::LineBreak);
+                    }
+                    elements.extend(self.inline(line)?);
+                }
+                blocks.push(paragraph(elements));
+            }
+            "export" => match parameters.split_whitespace().next().map(str::to_lowercase) {
+                Some(backend) if backend == "html" || backend == "latex" => {
+                    blocks.push(Element::Raw {
+                        format: if backend == "html" {
+                            DocumentType::HTML
+                        } else {
+                            DocumentType::LaTeX
+                        },
+                        content: content.join("\n"),
+                    })
+                }
+                backend => warn!("Export block left out: {}", backend.unwrap_or_default()),
+            },
+            "comment" => {}
+            "aside" => {
+                let elements = self.blocks(content)?;
+                blocks.push(Element::Container {
+                    kind: ContainerKind::Aside,
+                    elements,
+                });
+            }
+            kind if ADMONITIONS.contains(&kind) => {
+                let content = self.blocks(content)?;
+                let title = (!parameters.is_empty()).then_some(parameters);
+                blocks.push(Element::admonition(kind, title, content));
+            }
+            _ => {
+                let elements = self.blocks(content)?;
+                blocks.push(Element::Container {
+                    kind: ContainerKind::Div,
+                    elements,
+                });
+            }
+        }
+        Ok(end)
+    }
+
+    /// Display math: `\[ \]`, `$$ $$` or a LaTeX environment, `None` when the line starts
+    /// none
+    fn math(&mut self, lines: &[String], index: usize, blocks: &mut Vec<Element>) -> Option<usize> {
+        let first = lines[index].trim();
+        let (open, close, environment) = if first.starts_with("\\[") {
+            ("\\[", "\\]".to_string(), false)
+        } else if first.starts_with("$$") {
+            ("$$", "$$".to_string(), false)
+        } else {
+            let name = first.strip_prefix("\\begin{")?.split('}').next()?;
+            ("", format!("\\end{{{name}}}"), true)
+        };
+        let source = lines[index..].join("\n");
+        let source = source.trim_start();
+        let close_at = source[open.len()..].find(&close)? + open.len();
+        let consumed = source[..close_at + close.len()].lines().count();
+        let tex = if environment {
+            let inner = &source[..close_at + close.len()];
+            let name = close
+                .trim_start_matches("\\end{")
+                .trim_end_matches('}')
+                .to_string();
+            if ["equation", "equation*", "displaymath"].contains(&name.as_str()) {
+                inner[inner.find('}')? + 1..inner.len() - close.len()].to_string()
+            } else {
+                inner.to_string()
+            }
+        } else {
+            source[open.len()..close_at].to_string()
+        };
+        blocks.push(Element::Math {
+            tex: tex.trim().to_string(),
+            display: true,
+        });
+        self.affiliated = Affiliated::default();
+        Some(index + consumed)
+    }
+
+    /// Paragraph, an image when it is a single image link
+    fn paragraph(
+        &mut self,
+        lines: &[String],
+        index: usize,
+        blocks: &mut Vec<Element>,
+    ) -> anyhow::Result<usize> {
+        let end = index
+            + 1
+            + lines[index + 1..]
+                .iter()
+                .position(|line| starts_element(line))
+                .unwrap_or(lines.len() - index - 1);
+        let source = lines[index..end]
+            .iter()
+            .map(|line| line.trim())
+            .collect::<Vec<_>>()
+            .join("\n");
+        let affiliated = std::mem::take(&mut self.affiliated);
+        let mut elements = self.inline(&source)?;
+        for element in elements.iter_mut() {
+            let image = match element {
+                Element::Image(image) => image,
+                Element::Hyperlink { elements, .. } => match elements.as_mut_slice() {
+                    [Element::Image(image)] => image,
+                    _ => continue,
+                },
+                _ => continue,
+            };
+            if let Some(caption) = &affiliated.caption {
+                image.set_image_title(caption);
+            }
+            if let Some(alt) = affiliated.html.get("alt") {
+                image.set_image_alt(alt);
+            }
+            let width = affiliated.html.get("width").cloned();
+            let height = affiliated.html.get("height").cloned();
+            if width.is_some() || height.is_some() {
+                image.set_image_size(ImageDimension { width, height });
+            }
+        }
+        match <[Element; 1]>::try_from(elements) {
+            Ok([image]) if is_image(&image) => blocks.push(image),
+            Ok([element]) => blocks.push(paragraph(vec![element])),
+            Err(elements) => {
+                if !elements.is_empty() {
+                    blocks.push(paragraph(elements));
+                }
+            }
+        }
+        Ok(end)
+    }
+
+    /// Table, its header the rows above its first rule when rows follow it
+    fn table(
+        &mut self,
+        lines: &[String],
+        index: usize,
+        blocks: &mut Vec<Element>,
+    ) -> anyhow::Result<usize> {
+        let end = index
+            + lines[index..]
+                .iter()
+                .position(|line| !is_table_line(line))
+                .unwrap_or(lines.len() - index);
+        let mut rows: Vec<Vec<Element>> = vec![];
+        let mut header_rows = None;
+        for line in &lines[index..end] {
+            let line = line.trim();
+            if line.starts_with("|-") {
+                header_rows.get_or_insert(rows.len());
+                continue;
+            }
+            let inner = line.strip_prefix('|').unwrap_or(line);
+            let inner = inner.strip_suffix('|').unwrap_or(inner);
+            let mut cells = vec![];
+            for cell in inner.split('|') {
+                let cell = cell.trim().replace("\\vert{}", "|").replace("\\vert", "|");
+                cells.push(text_block(paragraph(self.inline(&cell)?)));
+            }
+            rows.push(cells);
+        }
+        let affiliated = std::mem::take(&mut self.affiliated);
+        if let Some(caption) = affiliated.caption {
+            blocks.push(paragraph(vec![Element::Styled {
+                style: TextStyle::Strong,
+                elements: self.inline(&caption)?,
+            }]));
+        }
+        let header_rows =
+            header_rows.filter(|rows_above| *rows_above > 0 && *rows_above < rows.len());
+        let mut rows = rows.into_iter();
+        let headers = match header_rows {
+            Some(_) => rows
+                .next()
+                .unwrap_or_default()
+                .into_iter()
+                .map(|element| TableHeader {
+                    element,
+                    width: 30.,
+                })
+                .collect(),
+            None => vec![],
+        };
+        blocks.push(Element::Table {
+            headers,
+            rows: rows
+                .map(|cells| TableRow {
+                    cells: cells
+                        .into_iter()
+                        .map(|element| TableCell { element })
+                        .collect(),
+                })
+                .collect(),
+        });
+        // formulas after the table
+        let mut end = end;
+        while lines
+            .get(end)
+            .is_some_and(|line| line.trim_start().to_lowercase().starts_with("#+tblfm:"))
+        {
+            end += 1;
+        }
+        Ok(end)
+    }
+
+    /// Plain, ordered or description list of the items at `indent`
+    fn list(
+        &mut self,
+        lines: &[String],
+        mut index: usize,
+        indent: usize,
+        ordered: bool,
+        blocks: &mut Vec<Element>,
+    ) -> anyhow::Result<usize> {
+        self.affiliated = Affiliated::default();
+        let mut items = vec![];
+        let mut start = 1;
+        let mut end = index;
+        while let Some((_, _, column)) = lines.get(index).and_then(|line| list_item(line)).filter(
+            |(item_indent, item_ordered, _)| *item_indent == indent && *item_ordered == ordered,
+        ) {
+            // the item ends at a line indented as its bullet or less, or two blank lines
+            let mut item_end = index + 1;
+            while let Some(line) = lines.get(item_end) {
+                if line.is_empty() {
+                    if lines.get(item_end + 1).is_none_or(String::is_empty) {
+                        break;
+                    }
+                } else if indentation(line) <= indent {
+                    break;
+                }
+                item_end += 1;
+            }
+            while item_end > index + 1 && lines[item_end - 1].is_empty() {
+                item_end -= 1;
+            }
+            let mut first = lines[index].get(column..).unwrap_or_default().to_string();
+            if let Some(counter) = first.strip_prefix("[@") {
+                if let Some((value, rest)) = counter.split_once(']') {
+                    if items.is_empty() {
+                        start = value.parse().unwrap_or(1);
+                    }
+                    first = rest.trim_start().to_string();
+                }
+            }
+            // description items: `- term :: description`
+            let term = match first.split_once(" :: ") {
+                Some((term, description)) if !ordered => {
+                    let term = term.to_string();
+                    first = description.to_string();
+                    Some(term)
+                }
+                _ => None,
+            };
+            let following = &lines[index + 1..item_end];
+            let min_indent = following
+                .iter()
+                .filter(|line| !line.is_empty())
+                .map(|line| indentation(line))
+                .min()
+                .map_or(column, |min_indent| min_indent.min(column));
+            let mut body = vec![first];
+            body.extend(dedent_by(following, min_indent));
+            let mut item_blocks = self.blocks(&body)?;
+            if let Some(term) = term {
+                let term = Element::Styled {
+                    style: TextStyle::Strong,
+                    elements: self.inline(&term)?,
+                };
+                match item_blocks.first_mut() {
+                    Some(Element::Paragraph { elements, .. }) => {
+                        elements.splice(0..0, [term, text(" ")]);
+                    }
+                    _ => item_blocks.insert(0, paragraph(vec![term])),
+                }
+            }
+            if item_blocks.is_empty() {
+                item_blocks.push(text(""));
+            }
+            items.extend(item_blocks.into_iter().map(|block| ListItem {
+                element: text_block(block),
+            }));
+            end = item_end;
+            index = item_end;
+            // one blank line between items
+            if lines.get(index).is_some_and(String::is_empty)
+                && lines.get(index + 1).is_some_and(|line| !line.is_empty())
+            {
+                index += 1;
+            }
+        }
+        blocks.push(Element::List {
+            elements: items,
+            numbered: ordered,
+            start,
+            numbering: NumberingStyle::default(),
+        });
+        Ok(end)
+    }
+
+    fn inline(&mut self, source: &str) -> anyhow::Result<Vec<Element>> {
+        let chars: Vec<char> = source.chars().collect();
+        let mut out = Inline::default();
+        let mut index = 0;
+        while index < chars.len() {
+            let c = chars[index];
+            let previous = index.checked_sub(1).map(|previous| chars[previous]);
+            let next = chars.get(index + 1).copied();
+            let rest = &chars[index..];
+            match c {
+                ZWSP => {
+                    index += 1;
+                    continue;
+                }
+                c if MARKERS.contains(c)
+                    && previous.is_none_or(|c| c.is_whitespace() || EMPHASIS_PRE.contains(c))
+                    && next.is_some_and(|c| !c.is_whitespace()) =>
+                {
+                    if let Some(end) = emphasis_end(&chars, index, c) {
+                        let content: String = chars[index + 1..end].iter().collect();
+                        let elements = match c {
+                            '=' | '~' => vec![Element::InlineCode {
+                                text: content.replace(['\n', ZWSP], " "),
+                            }],
+                            '_' => self.inline(&content)?,
+                            c => vec![Element::Styled {
+                                style: match c {
+                                    '*' => TextStyle::Strong,
+                                    '/' => TextStyle::Emphasis,
+                                    _ => TextStyle::Strikethrough,
+                                },
+                                elements: self.inline(&content)?,
+                            }],
+                        };
+                        out.extend(elements);
+                        index = end + 1;
+                        continue;
+                    }
+                }
+                '[' if next == Some('[') => {
+                    if let Some((element, end)) = self.link(&chars, index)? {
+                        out.push(element);
+                        index = end;
+                        continue;
+                    }
+                }
+                '[' if rest.starts_with(&['[', 'f', 'n', ':']) => {
+                    if let Some((element, end)) = self.footnote(&chars, index)? {
+                        out.push(element);
+                        index = end;
+                        continue;
+                    }
+                }
+                '<' if next == Some('<') => {
+                    let start = index + rest.iter().take_while(|c| **c == '<').count();
+                    let close = chars[start..]
+                        .windows(2)
+                        .position(|window| window == ['>', '>'])
+                        .map(|close| start + close);
+                    if let Some(close) = close.filter(|close| *close > start) {
+                        let name: String = chars[start..close].iter().collect();
+                        out.push(Element::Bookmark {
+                            id: header_anchor(&name),
+                            title: String::new(),
+                        });
+                        // radio targets show their text
+                        if start - index == 3 {
+                            out.extend(vec![text(name)]);
+                        }
+                        index = close + chars[close..].iter().take_while(|c| **c == '>').count();
+                        continue;
+                    }
+                }
+                '\\' if next == Some('\\')
+                    && chars[index + 2..]
+                        .iter()
+                        .take_while(|c| **c != '\n')
+                        .all(|c| c.is_whitespace()) =>
+                {
+                    out.push(Element::LineBreak);
+                    index += 2;
+                    while chars.get(index).is_some_and(|c| c.is_whitespace()) {
+                        index += 1;
+                    }
+                    continue;
+                }
+                '\\' if next == Some('(') || next == Some('[') => {
+                    let close = if next == Some('(') { ')' } else { ']' };
+                    let end = (index + 2..chars.len().saturating_sub(1))
+                        .find(|end| chars[*end] == '\\' && chars[end + 1] == close);
+                    if let Some(end) = end {
+                        out.push(Element::Math {
+                            tex: chars[index + 2..end]
+                                .iter()
+                                .collect::<String>()
+                                .trim()
+                                .to_string(),
+                            display: false,
+                        });
+                        index = end + 2;
+                        continue;
+                    }
+                }
+                '$' if !previous.is_some_and(|c| c.is_alphanumeric() || c == '$')
+                    && next.is_some_and(|c| !c.is_whitespace() && !".,;$".contains(c)) =>
+                {
+                    let end = (index + 1..chars.len()).find(|end| {
+                        chars[*end] == '$'
+                            && !chars[end - 1].is_whitespace()
+                            && !".,$".contains(chars[end - 1])
+                            && chars.get(end + 1).is_none_or(|c| {
+                                c.is_whitespace() || c.is_ascii_punctuation() && *c != '$'
+                            })
+                    });
+                    if let Some(end) = end.filter(|end| *end > index + 1 || index + 2 < chars.len())
+                    {
+                        if end > index + 1 || chars[index + 1] != '$' {
+                            out.push(Element::Math {
+                                tex: chars[index + 1..end].iter().collect(),
+                                display: false,
+                            });
+                            index = end + 1;
+                            continue;
+                        }
+                    }
+                }
+                '{' if rest.starts_with(&['{', '{', '{']) => {
+                    let close = chars[index + 3..]
+                        .windows(3)
+                        .position(|window| window == ['}', '}', '}'])
+                        .map(|close| index + 3 + close);
+                    if let Some(close) = close {
+                        let call: String = chars[index + 3..close].iter().collect();
+                        out.extend(self.macro_call(&call)?);
+                        index = close + 3;
+                        continue;
+                    }
+                }
+                '\n' => {
+                    out.push_char(' ');
+                    index += 1;
+                    continue;
+                }
+                's' if rest.starts_with(&['s', 'r', 'c', '_'])
+                    && !previous.is_some_and(char::is_alphanumeric) =>
+                {
+                    // inline source, `src_lang{code}` or `src_lang[options]{code}`
+                    let open = rest.iter().position(|c| *c == '{' || c.is_whitespace());
+                    if let Some(open) = open.filter(|open| rest[*open] == '{') {
+                        if let Some(close) = rest[open..].iter().position(|c| *c == '}') {
+                            out.push(Element::InlineCode {
+                                text: rest[open + 1..open + close].iter().collect(),
+                            });
+                            index += open + close + 1;
+                            continue;
+                        }
+                    }
+                }
+                c if c.is_ascii_alphabetic() && !previous.is_some_and(char::is_alphanumeric) => {
+                    let head: String = rest.iter().take(8).collect();
+                    if URI_SCHEMES.iter().any(|scheme| head.starts_with(scheme)) {
+                        let mut end = rest
+                            .iter()
+                            .position(|c| {
+                                c.is_whitespace() || "<>[]()\"".contains(*c) || *c == ZWSP
+                            })
+                            .map_or(chars.len(), |end| index + end);
+                        while end > index && ".,;:!?'".contains(chars[end - 1]) {
+                            end -= 1;
+                        }
+                        let uri: String = chars[index..end].iter().collect();
+                        out.push(Element::Hyperlink {
+                            elements: vec![text(uri.strip_prefix("mailto:").unwrap_or(&uri))],
+                            url: uri,
+                            alt: String::new(),
+                            size: 14,
+                        });
+                        index = end;
+                        continue;
+                    }
+                }
+                _ => {}
+            }
+            out.push_char(c);
+            index += 1;
+        }
+        Ok(out.finish())
+    }
+
+    /// Link at `index`, `[[path][description]]` or `[[path]]`, and the index after it: an
+    /// image when it is one of an image file without description
+    fn link(&mut self, chars: &[char], index: usize) -> anyhow::Result<Option<(Element, usize)>> {
+        let mut path = String::new();
+        let mut position = index + 2;
+        loop {
+            match chars.get(position) {
+                Some('\\') if matches!(chars.get(position + 1), Some('[' | ']' | '\\')) => {
+                    path.push(chars[position + 1]);
+                    position += 2;
+                }
+                Some(']') => break,
+                Some('[') | None => return Ok(None),
+                Some(c) => {
+                    path.push(*c);
+                    position += 1;
+                }
+            }
+        }
+        let path = path.replace('\n', " ").trim().to_string();
+        let (description, end) = match chars.get(position + 1) {
+            Some(']') => (None, position + 2),
+            Some('[') => {
+                let start = position + 2;
+                let Some(close) = chars[start..]
+                    .windows(2)
+                    .position(|window| window == [']', ']'])
+                else {
+                    return Ok(None);
+                };
+                let description: String = chars[start..start + close].iter().collect();
+                (Some(description), start + close + 2)
+            }
+            _ => return Ok(None),
+        };
+        if description.is_none() && is_image_path(&path) {
+            return Ok(Some((self.image(&path)?, end)));
+        }
+        let url = self.link_url(&path);
+        let elements = match description {
+            Some(description) if is_image_path(description.trim()) => {
+                vec![self.image(description.trim())?]
+            }
+            Some(description) => self.inline(description.trim())?,
+            None => {
+                let label = path.strip_prefix("mailto:").unwrap_or(&path);
+                let label = label.strip_prefix("file:").unwrap_or(label);
+                vec![text(label.trim_start_matches(['#', '*']))]
+            }
+        };
+        Ok(Some((
+            Element::Hyperlink {
+                elements,
+                url,
+                alt: String::new(),
+                size: 14,
+            },
+            end,
+        )))
+    }
+
+    /// URL of a link path: a URL, a file, a custom id (`#id`), a headline (`*Title`), an id
+    /// (`id:`) or the name of a target or headline
+    fn link_url(&self, path: &str) -> String {
+        if let Some(id) = path.strip_prefix('#') {
+            return format!("#{id}");
+        }
+        if let Some(title) = path.strip_prefix('*') {
+            let title = plain_title(title);
+            return format!(
+                "#{}",
+                self.headlines
+                    .get(&normalize(&title))
+                    .cloned()
+                    .unwrap_or_else(|| header_anchor(&title))
+            );
+        }
+        if let Some(id) = path.strip_prefix("id:") {
+            return format!("#{id}");
+        }
+        if let Some(file) = path.strip_prefix("file:") {
+            return file.to_string();
+        }
+        let is_url = path.split_once(':').is_some_and(|(scheme, _)| {
+            !scheme.is_empty()
+                && scheme
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+        });
+        if is_url || path.starts_with(['.', '/', '~']) {
+            return path.to_string();
+        }
+        let name = normalize(path);
+        if self.targets.contains(&name) {
+            return format!("#{}", header_anchor(path));
+        }
+        match self.headlines.get(&name) {
+            Some(anchor) => format!("#{anchor}"),
+            None => {
+                warn!("Unknown link target: {path}");
+                format!("#{}", header_anchor(path))
+            }
+        }
+    }
+
+    /// Image of a linked file
+    fn image(&mut self, path: &str) -> anyhow::Result<Element> {
+        let path = path.strip_prefix("file:").unwrap_or(path);
+        let bytes = (self.image_loader)(path)?;
+        Ok(Element::Image(ImageData::new(
+            bytes,
+            String::new(),
+            String::new(),
+            path.to_string(),
+            String::new(),
+            ImageDimension::default(),
+        )))
+    }
+
+    /// Footnote at `index`: a reference, `[fn:label]`, or an inline definition,
+    /// `[fn::text]` or `[fn:label:text]`, and the index after it
+    fn footnote(
+        &mut self,
+        chars: &[char],
+        index: usize,
+    ) -> anyhow::Result<Option<(Element, usize)>> {
+        let mut depth = 0;
+        let mut close = None;
+        for (position, c) in chars.iter().enumerate().skip(index) {
+            match c {
+                '[' => depth += 1,
+                ']' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        close = Some(position);
+                        break;
+                    }
+                }
+                _ => {}
+            }
+        }
+        let Some(close) = close else {
+            return Ok(None);
+        };
+        let content: String = chars[index + 4..close].iter().collect();
+        if self.depth > 8 {
+            return Ok(None);
+        }
+        self.depth += 1;
+        let elements = match content.split_once(':') {
+            Some((_, definition)) => self.inline(definition.trim()),
+            None => match self.footnotes.get(&content).cloned() {
+                Some(lines) => {
+                    self.blocks(&lines)
+                        .map(|blocks| match <[Element; 1]>::try_from(blocks) {
+                            Ok([Element::Paragraph { elements, .. }]) => elements,
+                            Ok([element]) => vec![element],
+                            Err(blocks) => blocks,
+                        })
+                }
+                None => {
+                    warn!("Footnote without definition: {content}");
+                    Ok(vec![])
+                }
+            },
+        };
+        self.depth -= 1;
+        Ok(Some((
+            Element::Footnote {
+                elements: elements?,
+            },
+            close + 1,
+        )))
+    }
+
+    /// Elements of a macro call, `name(arguments)`: the metadata of `title`, `author`,
+    /// `date` and `email`, or the template of a `#+macro:`
+    fn macro_call(&mut self, call: &str) -> anyhow::Result<Vec<Element>> {
+        let (name, arguments) = match call.split_once('(') {
+            Some((name, arguments)) => (name, arguments.trim_end_matches(')')),
+            None => (call, ""),
+        };
+        let name = name.trim().to_lowercase();
+        let metadata = &self.metadata;
+        let value = match name.as_str() {
+            "title" => metadata.title.clone(),
+            "author" => metadata.author.clone(),
+            "date" => metadata.date.clone(),
+            "email" => metadata.custom.get("email").cloned(),
+            name => self.macros.get(name).map(|template| {
+                let mut expanded = template.clone();
+                for (number, argument) in arguments
+                    .split(',')
+                    .collect::<Vec<_>>()
+                    .into_iter()
+                    .enumerate()
+                    .rev()
+                {
+                    expanded = expanded.replace(&format!("${}", number + 1), argument.trim());
+                }
+                expanded
+            }),
+        };
+        let Some(value) = value.filter(|_| self.depth <= 8) else {
+            return Ok(vec![]);
+        };
+        self.depth += 1;
+        let elements = self.inline(&value);
+        self.depth -= 1;
+        elements
+    }
+}
+
+/// End of the drawer starting at `index`, `:NAME:` to `:END:`
+fn drawer_end(lines: &[String], index: usize) -> Option<usize> {
+    let line = lines.get(index)?.trim();
+    let name = line.strip_prefix(':')?.strip_suffix(':')?;
+    if name.is_empty()
+        || name.eq_ignore_ascii_case("end")
+        || !name
+            .chars()
+            .all(|c| c.is_alphanumeric() || "-_".contains(c))
+    {
+        return None;
+    }
+    let end = lines[index + 1..]
+        .iter()
+        .position(|line| line.trim().eq_ignore_ascii_case(":end:"))?;
+    Some(index + end + 2)
+}
+
+impl TransformerTrait for Transformer {
+    /// Document of an Org source, its images read from the current directory
+    fn parse(document: &Bytes) -> crate::Result<Document> {
+        Transformer::parse_with_loader(document, disk_image_loader("."))
+    }
+
+    /// Org source of the document, its images saved to the current directory
+    fn generate(document: &Document) -> crate::Result<Bytes> {
+        Transformer::generate_with_saver(document, disk_image_saver("."))
+    }
+}
+
+impl TransformerWithImageLoaderSaverTrait for Transformer {
+    /// Document of an Org source, its images read with `image_loader` from the paths of the
+    /// image links
+    fn parse_with_loader<F>(document: &Bytes, image_loader: F) -> crate::Result<Document>
+    where
+        F: Fn(&str) -> anyhow::Result<Bytes>,
+    {
+        Transformer::parse_source(document, &image_loader_errors(image_loader))
+            .map_err(crate::Error::parse_error)
+    }
+
+    /// Org source of the document, its images saved with `image_saver` under the paths of
+    /// their links
+    fn generate_with_saver<F>(document: &Document, image_saver: F) -> crate::Result<Bytes>
+    where
+        F: Fn(&Bytes, &str) -> anyhow::Result<()>,
+    {
+        Transformer::generate_with_image_saver(document, image_saver)
+            .map_err(crate::Error::generation_error)
+    }
+}
+
+impl Transformer {
+    fn parse_source<F>(source: &Bytes, image_loader: &F) -> anyhow::Result<Document>
+    where
+        F: Fn(&str) -> anyhow::Result<Bytes>,
+    {
+        let source = std::str::from_utf8(source)?;
+        let source = source.strip_prefix('\u{feff}').unwrap_or(source);
+        let lines: Vec<String> = source.lines().map(expand_tabs).collect();
+        let mut parser = Parser {
+            image_loader,
+            metadata: Metadata::default(),
+            todo_keywords: vec![],
+            headlines: HashMap::new(),
+            targets: HashSet::new(),
+            footnotes: HashMap::new(),
+            macros: HashMap::new(),
+            affiliated: Affiliated::default(),
+            depth: 0,
+        };
+        parser.definitions(&lines);
+        if parser.todo_keywords.is_empty() {
+            parser.todo_keywords = vec!["TODO".to_string(), "DONE".to_string()];
+        }
+        let elements = parser.blocks(&lines)?;
+        let mut document = Document::new(elements);
+        document.metadata = parser.metadata;
+        Ok(document)
+    }
+
+    fn generate_with_image_saver<F>(document: &Document, image_saver: F) -> anyhow::Result<Bytes>
+    where
+        F: Fn(&Bytes, &str) -> anyhow::Result<()>,
+    {
+        let document = &document.resolve_fields().without_drawings();
+        let mut writer = Writer {
+            image_saver: &image_saver,
+            image_count: 0,
+            link_targets: document.link_targets(),
+            bookmarks: bookmarks(document),
+            depth: 0,
+        };
+        let elements = document
+            .bands
+            .iter()
+            .filter(|band| !matches!(band, Band::PageHeader(_) | Band::PageFooter(_)))
+            .flat_map(|band| band.elements());
+        let body = writer.blocks(elements)?;
+
+        let metadata = &document.metadata;
+        let mut header = String::new();
+        let keywords = [
+            ("title", metadata.title.clone()),
+            ("author", metadata.author.clone()),
+            ("date", metadata.date.clone()),
+            ("description", metadata.description.clone()),
+            (
+                "keywords",
+                Some(metadata.keywords.join(", ")).filter(|keywords| !keywords.is_empty()),
+            ),
+        ];
+        for (key, value) in keywords {
+            if let Some(value) = value {
+                let _ = writeln!(header, "#+{key}: {}", value.replace('\n', " ").trim());
+            }
+        }
+        if !header.is_empty() {
+            header.push('\n');
+        }
+        let org = header + body.trim_end() + "\n";
+        Ok(Bytes::from(org))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+    use crate::core::tests::{init_logger, table_texts};
+    use crate::core::TransformerTrait;
+    use crate::markdown;
+
+    #[test]
+    fn test_generate() -> anyhow::Result<()> {
+        init_logger();
+        let markdown = "# Results *and* costs\n\nSee [the end](#the-end) and \
+            [the site](https://example.com/a_b).[^1]\n\n\
+            1. One\n   - Nested *item*\n2. Two\n\n\
+            | Name | Value |\n|------|-------|\n| a_b | 1 \\| 2 |\n\n\
+            ```rust\nlet x = 2 * 3;\n* not a headline\n```\n\n## The end\n\n\
+            `a+b` snake_case *x* /usr/bin/ **done**\n\n\
+            [^1]: A note.\n";
+        let mut document = markdown::Transformer::parse(&Bytes::from(markdown))?;
+        let png = Bytes::from(std::fs::read("test/data/small.png")?);
+        if let Some(Band::Detail(elements)) = document.bands.last_mut() {
+            elements.push(Element::Image(ImageData::new(
+                png.clone(),
+                "Logo".to_string(),
+                "The logo".to_string(),
+                "png".to_string(),
+                String::new(),
+                ImageDimension {
+                    width: Some("50%".to_string()),
+                    height: None,
+                },
+            )));
+            elements.push(Element::admonition(
+                "warning",
+                None,
+                vec![paragraph(vec![text("Hot.")])],
+            ));
+        }
+        document.metadata.title = Some("Report".to_string());
+        document.metadata.author = Some("Ada, Alan".to_string());
+
+        let saved = RefCell::new(vec![]);
+        let org = Transformer::generate_with_saver(&document, |bytes, name| {
+            saved.borrow_mut().push((name.to_string(), bytes.clone()));
+            Ok(())
+        })?;
+        let org = std::str::from_utf8(&org)?;
+        assert!(org.starts_with("#+title: Report\n#+author: Ada, Alan\n\n"));
+        assert!(org.contains("* Results and costs\n\n"));
+        assert!(org.contains("** The end\n:PROPERTIES:\n:CUSTOM_ID: the-end\n:END:\n\n"));
+        assert!(org.contains(
+            "See [[#the-end][the end]] and [[https://example.com/a_b][the site]].[fn::A note.]\n"
+        ));
+        assert!(org.contains("1. One\n\n   - Nested /item/\n\n2. Two\n"));
+        assert!(org
+            .contains("| Name | Value       |\n|------+-------------|\n| a_b  | 1 \\vert{} 2 |\n"));
+        assert!(org.contains("#+begin_src rust\nlet x = 2 * 3;\n,* not a headline\n#+end_src\n"));
+        assert!(org.contains(&format!(
+            "~a+b~ snake_case /x/ {ZWSP}/usr/bin/{ZWSP} *done*"
+        )));
+        assert!(org.contains(
+            "#+caption: Logo\n#+attr_html: :alt The logo :width 50%\n[[file:image1.png]]\n"
+        ));
+        assert!(org.contains("#+begin_warning\nHot.\n#+end_warning\n"));
+        assert_eq!(
+            saved.borrow().as_slice(),
+            &[("image1.png".to_string(), png)]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse() -> anyhow::Result<()> {
+        init_logger();
+        let source = "#+TITLE: User Guide\n#+AUTHOR: Ada Lovelace\n#+DATE: <2024-05-01 Wed>\n\
+            #+TODO: TODO NEXT | DONE\n\n\
+            * NEXT [#A] Getting *started* :setup:cli:\n\
+            SCHEDULED: <2024-05-02 Thu>\n\
+            :PROPERTIES:\n:CUSTOM_ID: start\n:END:\n\n\
+            Install with ~cargo install~, see [[*Usage][usage]] and\n\
+            [[https://example.com][the site]].[fn:1] Read \\(a^2\\) and =x=.\\\\\n\
+            Done.\n\n\
+            #+begin_note Careful\nNeeds Rust 1.70.\n#+end_note\n\n\
+            - [X] One\n  - Nested\n- Two\n  #+begin_src sh\n  shiva a b\n  #+end_src\n\n\
+            3. [@3] Third\n4. Fourth\n\n\
+            - CPU :: the processor\n\n\
+            * Usage\n\n\
+            #+caption: Options\n\
+            | Name | Meaning  |\n|------+----------|\n| a    | The /a/  |\n#+TBLFM: $1=1\n\n\
+            : shiva --help\n\n\
+            #+CAPTION: Logo\n#+ATTR_HTML: :width 50%\n[[file:small.png]]\n\n\
+            \\begin{equation}\nx = 1\n\\end{equation}\n\n\
+            Back to [[#start][the start]].\n\n\
+            [fn:1] Or build it.\n";
+        let requested = RefCell::new(vec![]);
+        let document = Transformer::parse_with_loader(&Bytes::from(source), |path| {
+            requested.borrow_mut().push(path.to_string());
+            Ok(Bytes::from(std::fs::read(format!("test/data/{path}"))?))
+        })?;
+        assert_eq!(document.metadata.title.as_deref(), Some("User Guide"));
+        assert_eq!(document.metadata.author.as_deref(), Some("Ada Lovelace"));
+        assert_eq!(document.metadata.date.as_deref(), Some("2024-05-01 Wed"));
+        assert_eq!(requested.borrow().as_slice(), ["small.png"]);
+
+        let Some(Band::Detail(elements)) = document.bands.last() else {
+            panic!("no detail band");
+        };
+        let Element::Attributed {
+            attributes,
+            element,
+        } = &elements[0]
+        else {
+            panic!("not attributed: {:?}", elements[0]);
+        };
+        assert_eq!(
+            element.as_ref(),
+            &Element::Header {
+                level: 1,
+                text: "Getting started".to_string()
+            }
+        );
+        assert_eq!(attributes["org:todo"], "NEXT");
+        assert_eq!(attributes["org:priority"], "A");
+        assert_eq!(attributes["org:tags"], "setup:cli");
+        assert_eq!(attributes["id"], "start");
+        let Element::Paragraph {
+            elements: inline, ..
+        } = &elements[1]
+        else {
+            panic!("not a paragraph: {:?}", elements[1]);
+        };
+        assert_eq!(
+            inline[1],
+            Element::InlineCode {
+                text: "cargo install".to_string()
+            }
+        );
+        assert!(matches!(&inline[3], Element::Hyperlink { url, .. } if url == "#usage"));
+        assert!(
+            matches!(&inline[5], Element::Hyperlink { url, .. } if url == "https://example.com")
+        );
+        assert!(
+            matches!(&inline[7], Element::Footnote { elements } if elements == &[text("Or build it.")])
+        );
+        assert!(matches!(&inline[9], Element::Math { tex, display: false } if tex == "a^2"));
+        assert_eq!(
+            inline[11],
+            Element::InlineCode {
+                text: "x".to_string()
+            }
+        );
+        assert_eq!(inline[13], Element::LineBreak);
+        assert_eq!(
+            elements[2].admonition_parts(),
+            Some((
+                "note",
+                Some("Careful".to_string()),
+                &[paragraph(vec![text("Needs Rust 1.70.")])][..]
+            ))
+        );
+        let Element::List {
+            elements: items, ..
+        } = &elements[3]
+        else {
+            panic!("not a list: {:?}", elements[3]);
+        };
+        assert_eq!(items[0].element, text("[X] One"));
+        assert!(matches!(&items[1].element, Element::List { .. }));
+        assert_eq!(items[2].element, text("Two"));
+        assert_eq!(
+            items[3].element,
+            Element::CodeBlock {
+                info: "sh".to_string(),
+                code: "shiva a b".to_string()
+            }
+        );
+        assert!(matches!(
+            &elements[4],
+            Element::List { numbered: true, start: 3, elements, .. } if elements.len() == 2
+        ));
+        let Element::List {
+            elements: items, ..
+        } = &elements[5]
+        else {
+            panic!("not a list: {:?}", elements[5]);
+        };
+        assert!(
+            matches!(&items[0].element, Element::Paragraph { elements, .. }
+            if elements[0] == Element::Styled { style: TextStyle::Strong, elements: vec![text("CPU")] })
+        );
+        assert_eq!(
+            elements[6],
+            Element::Header {
+                level: 1,
+                text: "Usage".to_string()
+            }
+        );
+        assert_eq!(
+            elements[7],
+            paragraph(vec![Element::Styled {
+                style: TextStyle::Strong,
+                elements: vec![text("Options")],
+            }])
+        );
+        let Element::Table { headers, rows } = &elements[8] else {
+            panic!("not a table: {:?}", elements[8]);
+        };
+        assert_eq!(headers[1].element, text("Meaning"));
+        assert!(matches!(
+            &rows[0].cells[1].element,
+            Element::Paragraph { .. }
+        ));
+        assert_eq!(
+            elements[9],
+            Element::CodeBlock {
+                info: String::new(),
+                code: "shiva --help".to_string()
+            }
+        );
+        let Element::Image(image) = &elements[10] else {
+            panic!("not an image: {:?}", elements[10]);
+        };
+        assert_eq!(image.title(), "Logo");
+        assert_eq!(image.size().width.as_deref(), Some("50%"));
+        assert_eq!(
+            elements[11],
+            Element::Math {
+                tex: "x = 1".to_string(),
+                display: true
+            }
+        );
+        assert!(matches!(&elements[12], Element::Paragraph { elements, .. }
+            if matches!(&elements[1], Element::Hyperlink { url, .. } if url == "#start")));
+        assert_eq!(elements.len(), 13);
+        Ok(())
+    }
+
+    #[test]
+    fn test_round_trip() -> anyhow::Result<()> {
+        init_logger();
+        let source = "#+title: Notes\n\n* TODO Write :work:\n\n\
+            Some *bold*, /emphasis/, +gone+, ~code~ and a [[https://example.com][link]].\n\n\
+            ** Lists\n\n- One\n- Two\n\n1. First\n2. Second\n\n\
+            | a | b |\n|---+---|\n| 1 | 2 |\n\n\
+            #+begin_src python\nprint(1)\n#+end_src\n\n\
+            #+begin_warning Beware\nSharp edges.\n#+end_warning\n";
+        let document = Transformer::parse(&Bytes::from(source))?;
+        let generated = Transformer::generate(&document)?;
+        assert_eq!(std::str::from_utf8(&generated)?, source);
+        Ok(())
+    }
+
+    #[test]
+    fn test_tables() -> anyhow::Result<()> {
+        let source = r"#+CAPTION: Prices
+| Item      | Price |
+|-----------+-------|
+| a \vert b | *1*   |
+|-----------+-------|
+#+TBLFM: $2=vsum(@2..@-1)
+
+| x | y |
+|---+---|
+";
+        let document = Transformer::parse(&Bytes::from(source))?;
+        let elements = document.get_all_elements();
+        assert_eq!(elements.len(), 3);
+        assert_eq!(elements[0].plain_text(), "Prices");
+
+        // the rows above the first rule are the header, the formulas are left out
+        let (headers, rows) = table_texts(elements[1]);
+        assert_eq!(headers, ["Item", "Price"]);
+        assert_eq!(rows, [vec!["a | b", "1"]]);
+
+        // a rule under the last row makes no header
+        let (headers, rows) = table_texts(elements[2]);
+        assert!(headers.is_empty());
+        assert_eq!(rows, [vec!["x", "y"]]);
+        Ok(())
+    }
+}
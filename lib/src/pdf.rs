@@ -1,285 +1,363 @@
-use crate::core::Element::{List, Paragraph, Text};
-use crate::core::{Document, Element, ListItem, ParserError, TransformerTrait};
+use crate::core::{
+    Document, FormField, GenerateOptions, ParseOptions, PdfCompression, PdfOptions,
+    TransformerTrait,
+};
 
 use anyhow;
 use bytes::Bytes;
-use log::{debug, warn};
-use lopdf::content::Content;
-use lopdf::{Document as PdfDocument, Object, ObjectId};
-use std::collections::BTreeMap;
-use typst::{eval::Tracer, foundations::Smart};
+use lopdf::xref::XrefType;
+use lopdf::{dictionary, Dictionary, Document as PdfDocument, Object, ObjectId, Stream};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::io::Write;
+use typst::foundations::Smart;
 
 pub struct Transformer;
 impl TransformerTrait for Transformer {
-    fn parse(document: &Bytes) -> anyhow::Result<Document> {
-        let mut elements: Vec<Element> = Vec::new();
-        let pdf_document = PdfDocument::load_mem(document)?;
-        for (_id, page_id) in pdf_document.get_pages() {
-            let objects = pdf_document.get_page_contents(page_id);
-            for object_id in objects {
-                let object = pdf_document.get_object(object_id)?;
-                parse_object(page_id, &pdf_document, object, &mut elements)?;
-            }
-        }
-        Ok(Document::new(elements))
+    fn parse(document: &Bytes) -> crate::Result<Document> {
+        Transformer::parse_bytes(document).map_err(crate::Error::parse_error)
     }
-    fn generate(document: &Document) -> anyhow::Result<Bytes> {
-        let (text, img_map) = crate::typst::generate_document(document)?;
 
-        let world = crate::typst::ShivaWorld::new(text, img_map);
-        let mut tracer = Tracer::default();
+    fn parse_with_options(document: &Bytes, options: &ParseOptions) -> crate::Result<Document> {
+        Transformer::parse_pdf(document, options).map_err(crate::Error::parse_error)
+    }
 
-        let document = typst::compile(&world, &mut tracer).unwrap();
-        let warnings = tracer.warnings();
+    fn generate(document: &Document) -> crate::Result<Bytes> {
+        Transformer::generate_bytes(document, &PdfOptions::default())
+            .map_err(crate::Error::generation_error)
+    }
 
-        if !warnings.is_empty() {
-            // Trowing any warnings if necessary
-            for warn in warnings {
-                warn!("Warning - {}", warn.message);
-            }
+    fn generate_with_options(
+        document: &Document,
+        options: &GenerateOptions,
+    ) -> crate::Result<Bytes> {
+        Transformer::generate_bytes(document, &options.pdf).map_err(crate::Error::generation_error)
+    }
+}
+
+impl Transformer {
+    fn parse_bytes(document: &Bytes) -> anyhow::Result<Document> {
+        Transformer::parse_pdf(document, &ParseOptions::default())
+    }
+
+    fn parse_pdf(document: &Bytes, options: &ParseOptions) -> anyhow::Result<Document> {
+        let mut pdf_document = PdfDocument::load_mem(document)?;
+        if pdf_document.is_encrypted() {
+            let password = options.pdf.password.as_deref().unwrap_or_default();
+            crate::pdf_encryption::decrypt(&mut pdf_document, password)?;
         }
+        let elements = crate::pdf_layout::parse_document(&pdf_document, options.ocr.as_ref())?;
+        let mut document = Document::new(elements);
+        document.form = crate::pdf_form::read(&pdf_document);
+        Ok(document)
+    }
+    /// Fills the form of a PDF with the values of the fields, such as the fields of its
+    /// parsed document once set. A flattened form is drawn on the pages, no longer editable.
+    pub fn fill_form(
+        document: &Bytes,
+        fields: &[FormField],
+        flatten: bool,
+    ) -> crate::Result<Bytes> {
+        Transformer::fill_pdf_form(document, fields, flatten)
+            .map_err(crate::Error::generation_error)
+    }
 
-        // Converting to pdf then to bytes
-        let pdf = typst_pdf::pdf(&document, Smart::Auto, None);
+    fn fill_pdf_form(
+        document: &Bytes,
+        fields: &[FormField],
+        flatten: bool,
+    ) -> anyhow::Result<Bytes> {
+        let mut pdf = PdfDocument::load_mem(document)?;
+        crate::pdf_form::fill(&mut pdf, fields, flatten)?;
+        let mut bytes = Vec::new();
+        pdf.save_to(&mut bytes)?;
+        Ok(Bytes::from(bytes))
+    }
 
-        let bytes = Bytes::from(pdf);
-        Ok(bytes)
+    fn generate_bytes(document: &Document, options: &PdfOptions) -> anyhow::Result<Bytes> {
+        let mut bytes = Vec::new();
+        Transformer::save_pdf(document, options, &mut bytes)?;
+        Ok(Bytes::from(bytes))
     }
-}
 
-fn parse_object(
-    page_id: ObjectId,
-    pdf_document: &PdfDocument,
-    _object: &Object,
-    elements: &mut Vec<Element>,
-) -> anyhow::Result<()> {
-    fn collect_text(
-        text: &mut String,
-        encoding: Option<&str>,
-        operands: &[Object],
-        elements: &mut Vec<Element>,
+    /// Lays out the whole document with typst, which writes its PDF at once: typst has no
+    /// way to write the pages as they are laid out, so the PDF is never written page by
+    /// page. The PDF is loaded again to drop its duplicate images, and to tag, compress
+    /// further or encrypt it. Without any of these it is saved as typst writes it.
+    fn save_pdf(
+        document: &Document,
+        options: &PdfOptions,
+        writer: &mut Vec<u8>,
     ) -> anyhow::Result<()> {
-        for operand in operands.iter() {
-            debug!("2 {:?}", operand);
-            match *operand {
-                Object::String(ref bytes, _) => {
-                    let decoded_text = PdfDocument::decode_text(encoding, bytes);
-                    text.push_str(&decoded_text);
-                    if bytes.len() == 1 && bytes[0] == 1 {
-                        match elements.last() {
-                            None => {
-                                let list_element = List {
-                                    elements: vec![],
-                                    numbered: false,
-                                };
-                                elements.push(list_element);
-                            }
-                            Some(el) => {
-                                match el {
-                                    List { .. } => {
-                                        let old_list = elements.pop().unwrap();
-                                        // let list = old_list.list_as_ref()?;
-                                        if let List {
-                                            elements: list_elements,
-                                            numbered,
-                                        } = old_list
-                                        {
-                                            let mut list_item_elements = list_elements.clone();
-                                            let text_element = Text {
-                                                text: text.clone(),
-                                                size: 8,
-                                            };
-                                            let new_list_item_element = ListItem {
-                                                element: text_element,
-                                            };
-                                            list_item_elements.push(new_list_item_element);
-                                            let new_list = List {
-                                                elements: list_item_elements,
-                                                numbered,
-                                            };
-                                            elements.push(new_list);
-                                            text.clear();
-                                        }
-                                    }
-                                    Paragraph { .. } => {
-                                        let old_paragraph = elements.pop().unwrap();
-                                        // let paragraph = old_paragraph.paragraph_as_ref()?;
-                                        if let Paragraph {
-                                            elements: paragraph_elements,
-                                        } = old_paragraph
-                                        {
-                                            let mut paragraph_elements = paragraph_elements.clone();
-                                            let text_element = Text {
-                                                text: text.clone(),
-                                                size: 8,
-                                            };
-                                            paragraph_elements.push(text_element);
-                                            let new_paragraph = Paragraph {
-                                                elements: paragraph_elements,
-                                            };
-                                            elements.push(new_paragraph);
-                                            text.clear();
-
-                                            let list_element = List {
-                                                elements: vec![],
-                                                numbered: false,
-                                            };
-                                            elements.push(list_element);
-                                        }
-                                    }
-                                    _ => {
-                                        let list_element = List {
-                                            elements: vec![],
-                                            numbered: false,
-                                        };
-                                        elements.push(*Box::new(list_element));
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
-                Object::Array(ref arr) => {
-                    let _ = collect_text(text, encoding, arr, elements);
-                    text.push(' ');
-                }
-                Object::Integer(i) => {
-                    if i < -100 {
-                        text.push(' ');
-                    }
+        if options.archival && options.encryption.is_some() {
+            anyhow::bail!("PDF/A does not allow encryption");
+        }
+        // the layout and the images given to typst are freed before the post-processing,
+        // but for the layout an archival PDF is tagged from
+        let (pdf, tagged) = if options.archival {
+            let (layout, tags) = crate::typst::layout_tagged(document, options)?;
+            (
+                typst_pdf::pdf(&layout, Smart::Auto, None),
+                Some((layout, tags)),
+            )
+        } else {
+            let layout = crate::typst::layout(document, options)?;
+            (typst_pdf::pdf(&layout, Smart::Auto, None), None)
+        };
+        let mut processed = PdfDocument::load_mem(&pdf)?;
+        let best = options.compression == PdfCompression::Best;
+        let deduplicated = deduplicate_streams(&mut processed, best);
+        if !deduplicated && !options.archival && options.encryption.is_none() && !best {
+            writer.extend_from_slice(&pdf);
+            return Ok(());
+        }
+        drop(pdf);
+        if let Some((layout, tags)) = tagged {
+            crate::pdf_tags::tag(&mut processed, &layout, &tags)?;
+        }
+        if best {
+            for object in processed.objects.values_mut() {
+                if let Object::Stream(stream) = object {
+                    recompress(stream);
                 }
-                _ => {}
             }
         }
+        if let Some(encryption) = &options.encryption {
+            crate::pdf_encryption::encrypt(&mut processed, encryption)?;
+        }
+        if options.archival {
+            archival(&mut processed)?;
+            save_binary(&mut processed, writer)?;
+        } else {
+            processed.save_to(writer)?;
+        }
         Ok(())
     }
-    let mut text = String::new();
-
-    let fonts = pdf_document.get_page_fonts(page_id);
-    let encodings = fonts
-        .into_iter()
-        .map(|(name, font)| (name, font.get_font_encoding()))
-        .collect::<BTreeMap<Vec<u8>, &str>>();
-
-    let vec = pdf_document.get_page_content(page_id)?;
-    let content = Content::decode(&vec)?;
-    let mut current_encoding = None;
-    for operation in &content.operations {
-        debug!("1 {:?}", operation.operator);
-        match operation.operator.as_ref() {
-            "Tm" => {
-                let text_element = Text {
-                    text: text.clone(),
-                    size: 8,
-                };
-                match elements.last() {
-                    None => {
-                        let paragraph_element = Paragraph {
-                            elements: vec![text_element],
-                        };
-                        elements.push(paragraph_element);
-                    }
-                    Some(el) => match el {
-                        Paragraph { .. } => {
-                            let old_paragraph = elements.pop().unwrap();
-                            if let Paragraph {
-                                elements: paragraph_elements,
-                            } = old_paragraph
-                            {
-                                let mut paragraph_elements = paragraph_elements.clone();
-                                paragraph_elements.push(text_element);
-                                let new_paragraph = Paragraph {
-                                    elements: paragraph_elements,
-                                };
-                                elements.push(new_paragraph);
-                            }
-                        }
-                        _ => {
-                            elements.push(text_element);
-                        }
-                    },
-                }
-                text.clear();
-            }
-            "Tf" => {
-                let current_font = operation
-                    .operands
-                    .first()
-                    .ok_or(ParserError::Common)?
-                    .as_name()?;
-                current_encoding = encodings.get(current_font).cloned();
-            }
-            "Tj" | "TJ" => {
-                _ = collect_text(&mut text, current_encoding, &operation.operands, elements);
+}
+
+/// Keeps one of each set of identical image XObjects, such as an image shown with different
+/// alternative texts, or of each set of identical streams with `all_streams`. The
+/// references to the others point to the one kept. Returns whether a stream was dropped.
+fn deduplicate_streams(pdf: &mut PdfDocument, all_streams: bool) -> bool {
+    let mut deduplicated = false;
+    // the streams referencing duplicates, such as the images of the same soft masks, are
+    // compared again once these are replaced
+    loop {
+        let mut kept: HashMap<u64, Vec<ObjectId>> = HashMap::new();
+        let mut duplicates = HashMap::new();
+        for (id, object) in &pdf.objects {
+            let Object::Stream(stream) = object else {
+                continue;
+            };
+            if !all_streams
+                && stream
+                    .dict
+                    .get(b"Subtype")
+                    .and_then(Object::as_name_str)
+                    .ok()
+                    != Some("Image")
+            {
+                continue;
             }
-            "ET" => {
-                if !text.ends_with('\n') {
-                    text.push('\n')
+            let mut hasher = DefaultHasher::new();
+            stream.content.hash(&mut hasher);
+            let same_hash = kept.entry(hasher.finish()).or_default();
+            let original = same_hash.iter().find(|other| {
+                matches!(pdf.objects.get(other), Some(Object::Stream(other))
+                    if other.content == stream.content && other.dict == stream.dict)
+            });
+            match original {
+                Some(original) => {
+                    duplicates.insert(*id, *original);
                 }
+                None => same_hash.push(*id),
             }
-            _ => {}
+        }
+        if duplicates.is_empty() {
+            return deduplicated;
+        }
+        deduplicated = true;
+        for id in duplicates.keys() {
+            pdf.objects.remove(id);
+        }
+        for object in pdf.objects.values_mut() {
+            replace_references(object, &duplicates);
         }
     }
+}
 
-    if !text.is_empty() {
-        let text_element = Text {
-            text: text.clone(),
-            size: 8,
-        };
-        match elements.last() {
-            None => {
-                let paragraph_element = Paragraph {
-                    elements: vec![text_element],
-                };
-                elements.push(*Box::new(paragraph_element));
+fn replace_references(object: &mut Object, replacements: &HashMap<ObjectId, ObjectId>) {
+    match object {
+        Object::Reference(id) => {
+            if let Some(replacement) = replacements.get(id) {
+                *id = *replacement;
             }
-            Some(el) => {
-                match el {
-                    Paragraph { .. } => {
-                        let old_paragraph = elements.pop().unwrap();
-                        if let Paragraph {
-                            elements: paragraph_elements,
-                        } = old_paragraph
-                        {
-                            let mut paragraph_elements = paragraph_elements.clone();
-                            paragraph_elements.push(text_element);
-                            let new_paragraph = Paragraph {
-                                elements: paragraph_elements,
-                            };
-                            elements.push(*Box::new(new_paragraph));
-                        }
-                    }
-                    List { .. } => {
-                        let old_list = elements.pop().unwrap();
-                        // let list = old_list.list_as_ref()?;
-                        if let List {
-                            elements: list_elements,
-                            numbered,
-                        } = old_list
-                        {
-                            let mut list_item_elements = list_elements.clone();
-                            let new_list_item_element = ListItem {
-                                element: text_element,
-                            };
-                            list_item_elements.push(new_list_item_element);
-                            let new_list = List {
-                                elements: list_item_elements,
-                                numbered,
-                            };
-                            elements.push(*Box::new(new_list));
-                        }
-                    }
-                    _ => {}
-                }
+        }
+        Object::Array(array) => {
+            for item in array {
+                replace_references(item, replacements);
             }
         }
+        Object::Dictionary(dict) => {
+            for (_, value) in dict.iter_mut() {
+                replace_references(value, replacements);
+            }
+        }
+        Object::Stream(stream) => {
+            for (_, value) in stream.dict.iter_mut() {
+                replace_references(value, replacements);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Compresses a stream at the highest level, left as it is when not smaller. The streams
+/// with other filters than Flate or with predictors are kept, and the metadata stays
+/// readable as PDF/A asks.
+fn recompress(stream: &mut Stream) {
+    if stream.dict.get(b"Type").and_then(Object::as_name_str).ok() == Some("Metadata") {
+        return;
+    }
+    let filters = stream.filters().unwrap_or_default();
+    let content = if filters.is_empty() {
+        stream.content.clone()
+    } else if filters == ["FlateDecode"] && stream.dict.get(b"DecodeParms").is_err() {
+        // lopdf does not decompress the images
+        let mut dict = stream.dict.clone();
+        dict.remove(b"Subtype");
+        match Stream::new(dict, stream.content.clone()).decompressed_content() {
+            Ok(content) => content,
+            Err(_) => return,
+        }
+    } else {
+        return;
+    };
+    let mut compressed = Stream::new(stream.dict.clone(), Vec::new());
+    compressed.set_plain_content(content);
+    if compressed.compress().is_ok() && compressed.content.len() < stream.content.len() {
+        *stream = compressed;
     }
+}
+
+/// XMP description of the PDF/A-2b and PDF/UA-1 conformance. PDF/A knowing no PDF/UA
+/// schema, it is described as an extension.
+const PDFA_IDENTIFICATION: &str = concat!(
+    r#"<rdf:Description rdf:about="" xmlns:pdfaid="http://www.aiim.org/pdfa/ns/id/">"#,
+    "<pdfaid:part>2</pdfaid:part><pdfaid:conformance>B</pdfaid:conformance>",
+    "</rdf:Description>",
+    r#"<rdf:Description rdf:about="" xmlns:pdfuaid="http://www.aiim.org/pdfua/ns/id/">"#,
+    "<pdfuaid:part>1</pdfuaid:part>",
+    "</rdf:Description>",
+    r#"<rdf:Description rdf:about="""#,
+    r#" xmlns:pdfaExtension="http://www.aiim.org/pdfa/ns/extension/""#,
+    r#" xmlns:pdfaSchema="http://www.aiim.org/pdfa/ns/schema#""#,
+    r#" xmlns:pdfaProperty="http://www.aiim.org/pdfa/ns/property#">"#,
+    r#"<pdfaExtension:schemas><rdf:Bag><rdf:li rdf:parseType="Resource">"#,
+    "<pdfaSchema:schema>PDF/UA Universal Accessibility Schema</pdfaSchema:schema>",
+    "<pdfaSchema:namespaceURI>http://www.aiim.org/pdfua/ns/id/</pdfaSchema:namespaceURI>",
+    "<pdfaSchema:prefix>pdfuaid</pdfaSchema:prefix>",
+    r#"<pdfaSchema:property><rdf:Seq><rdf:li rdf:parseType="Resource">"#,
+    "<pdfaProperty:name>part</pdfaProperty:name>",
+    "<pdfaProperty:valueType>Integer</pdfaProperty:valueType>",
+    "<pdfaProperty:category>internal</pdfaProperty:category>",
+    "<pdfaProperty:description>Part of ISO 14289 followed</pdfaProperty:description>",
+    "</rdf:li></rdf:Seq></pdfaSchema:property>",
+    "</rdf:li></rdf:Bag></pdfaExtension:schemas>",
+    "</rdf:Description>"
+);
+
+/// Comment of bytes above 127 following the header line, which PDF/A asks for so that
+/// the file is taken as binary
+const BINARY_COMMENT: &[u8] = b"%\xe2\xe3\xcf\xd3\n";
+
+/// Turns a tagged PDF written by typst into a PDF/A-2b and PDF/UA-1: identified in its
+/// metadata, with sRGB as output intent and its title shown. The fonts are already
+/// embedded by typst.
+fn archival(pdf: &mut PdfDocument) -> anyhow::Result<()> {
+    let catalog_id = pdf.trailer.get(b"Root")?.as_reference()?;
 
+    let metadata_id = pdf
+        .get_dictionary(catalog_id)?
+        .get(b"Metadata")?
+        .as_reference()?;
+    let metadata = pdf.get_object_mut(metadata_id)?.as_stream_mut()?;
+    let xmp = String::from_utf8(metadata.content.clone())?.replacen(
+        "</rdf:RDF>",
+        &format!("{PDFA_IDENTIFICATION}</rdf:RDF>"),
+        1,
+    );
+    metadata.set_content(xmp.into_bytes());
+
+    let profile_id = pdf.add_object(Stream::new(
+        dictionary! { "N" => 3 },
+        typst_assets::icc::S_RGB_V4.to_vec(),
+    ));
+    let output_intent = dictionary! {
+        "Type" => "OutputIntent",
+        "S" => "GTS_PDFA1",
+        "OutputConditionIdentifier" => Object::string_literal("sRGB IEC61966-2.1"),
+        "DestOutputProfile" => profile_id,
+    };
+
+    let catalog = pdf.get_dictionary_mut(catalog_id)?;
+    catalog.set("OutputIntents", vec![output_intent.into()]);
+    let mut preferences = match catalog.get(b"ViewerPreferences") {
+        Ok(Object::Dictionary(preferences)) => preferences.clone(),
+        _ => Dictionary::new(),
+    };
+    preferences.set("DisplayDocTitle", true);
+    catalog.set("ViewerPreferences", preferences);
+    Ok(())
+}
+
+/// Saves the PDF with the binary comment after its header line, which lopdf does not
+/// write: the offsets of its cross-reference table are moved by the length of the comment
+fn save_binary<W: Write>(pdf: &mut PdfDocument, writer: &mut W) -> anyhow::Result<()> {
+    pdf.reference_table.cross_reference_type = XrefType::CrossReferenceTable;
+    let mut bytes = Vec::new();
+    pdf.save_to(&mut bytes)?;
+
+    let shift = BINARY_COMMENT.len();
+    let header_end = bytes
+        .iter()
+        .position(|byte| *byte == b'\n')
+        .map_or(bytes.len(), |end| end + 1);
+    let startxref = bytes
+        .windows(b"startxref".len())
+        .rposition(|window| window == b"startxref")
+        .ok_or_else(|| anyhow::anyhow!("no startxref in the saved PDF"))?;
+    let xref_start: usize = std::str::from_utf8(&bytes[startxref + b"startxref".len()..])?
+        .split_whitespace()
+        .next()
+        .unwrap_or_default()
+        .parse()?;
+
+    writer.write_all(&bytes[..header_end])?;
+    writer.write_all(BINARY_COMMENT)?;
+    writer.write_all(&bytes[header_end..xref_start])?;
+    // the entries in use are `offset generation n`, the offset on ten digits
+    let table = std::str::from_utf8(&bytes[xref_start..startxref])?;
+    for line in table.split_inclusive('\n') {
+        match line
+            .strip_suffix(" n \n")
+            .and_then(|entry| entry.split_once(' '))
+        {
+            Some((offset, generation)) => {
+                let offset: usize = offset.parse()?;
+                writeln!(writer, "{:010} {generation} n ", offset + shift)?;
+            }
+            None => writer.write_all(line.as_bytes())?,
+        }
+    }
+    write!(writer, "startxref\n{}\n%%EOF", xref_start + shift)?;
     Ok(())
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::core::tests::output_dir;
     use crate::core::*;
     use crate::pdf::Transformer;
     use crate::{markdown, pdf};
@@ -298,7 +376,7 @@ mod tests {
         info!("{:?}", parsed_document);
         info!("==========================");
         let generated_result = Transformer::generate(&parsed_document)?;
-        std::fs::write("test/data/generated.pdf", generated_result)?;
+        std::fs::write(format!("{}/generated.pdf", output_dir()), generated_result)?;
         Ok(())
     }
 
@@ -314,7 +392,7 @@ mod tests {
         debug!("{:?}", parsed_document);
         debug!("==========================");
         let generated_result = Transformer::generate(&parsed_document)?;
-        std::fs::write("test/data/generated.pdf", generated_result)?;
+        std::fs::write(format!("{}/generated.pdf", output_dir()), generated_result)?;
         Ok(())
     }
 
@@ -346,7 +424,10 @@ mod tests {
         }]);
         let generated_result = Transformer::generate(&parsed_document);
         assert!(generated_result.is_ok());
-        std::fs::write("test/data/typst.pdf", generated_result.unwrap())?;
+        std::fs::write(
+            format!("{}/typst.pdf", output_dir()),
+            generated_result.unwrap(),
+        )?;
 
         Ok(())
     }
@@ -370,15 +451,22 @@ mod tests {
                         size: 8,
                     },
                 ],
+                direction: TextDirection::default(),
             },
             Hyperlink {
-                title: "Example".to_owned(),
+                elements: vec![Text {
+                    text: "Example".to_owned(),
+                    size: 8,
+                }],
                 url: "https://www.example.com".to_owned(),
                 alt: "Example Site".to_owned(),
                 size: 8,
             },
             Hyperlink {
-                title: "GitHub".to_owned(),
+                elements: vec![Text {
+                    text: "GitHub".to_owned(),
+                    size: 8,
+                }],
                 url: "https://www.github.com".to_owned(),
                 alt: "GitHub".to_owned(),
                 size: 8,
@@ -395,13 +483,79 @@ mod tests {
         assert!(generated_result.is_ok());
 
         std::fs::write(
-            "test/data/generated_hyperlink.pdf",
+            format!("{}/generated_hyperlink.pdf", output_dir()),
             generated_result.unwrap(),
         )?;
 
         Ok(())
     }
 
+    #[test]
+    fn test_internal_links() -> anyhow::Result<()> {
+        let link = |url: &str, text: &str| Element::Paragraph {
+            elements: vec![Element::Hyperlink {
+                elements: vec![Element::Text {
+                    text: text.to_string(),
+                    size: 8,
+                }],
+                url: url.to_string(),
+                alt: String::new(),
+                size: 8,
+            }],
+            direction: TextDirection::default(),
+        };
+        let document = Document::new(vec![
+            link("#results", "Go to results"),
+            link("#appendix a", "Go to appendix"),
+            link("#missing", "Go to missing"),
+            link("https://example.com/?q=\"a\"", "Go to site"),
+            Element::Header {
+                level: 1,
+                text: "Results".to_string(),
+            },
+            Element::Bookmark {
+                id: "appendix a".to_string(),
+                title: "Appendix".to_string(),
+            },
+        ]);
+        let (source, _) = crate::typst::generate_document(&document, &PdfOptions::default())?;
+        assert!(source.contains("= Results <results>"));
+        assert!(source.contains("#link(<results>)[Go to results"));
+        assert!(source.contains("#link(<appendix-a>)["));
+        assert!(source.contains("#[Go to missing"));
+        assert!(source.contains(r#"#link("https://example.com/?q=\"a\"")["#));
+
+        let generated = Transformer::generate(&document)?;
+        let pdf = lopdf::Document::load_mem(&generated)?;
+        let links: Vec<&lopdf::Dictionary> = pdf
+            .get_pages()
+            .values()
+            .filter_map(|page| pdf.get_dictionary(*page).ok()?.get(b"Annots").ok())
+            .filter_map(|annotations| annotations.as_array().ok())
+            .flatten()
+            .filter_map(|annotation| match annotation {
+                lopdf::Object::Reference(id) => pdf.get_dictionary(*id).ok(),
+                annotation => annotation.as_dict().ok(),
+            })
+            .filter(|dict| {
+                dict.get(b"Subtype")
+                    .and_then(|subtype| subtype.as_name())
+                    .is_ok_and(|subtype| subtype == b"Link")
+            })
+            .collect();
+        assert_eq!(links.len(), 3, "{:?}", links);
+        let external = links
+            .iter()
+            .filter(|link| {
+                link.get(b"A")
+                    .and_then(|action| action.as_dict())
+                    .is_ok_and(|action| action.has(b"URI"))
+            })
+            .count();
+        assert_eq!(external, 1);
+        Ok(())
+    }
+
     #[test]
     fn simple_test() {
         let content = std::fs::read("test/data/test.txt").unwrap();
@@ -410,6 +564,1093 @@ mod tests {
         let document = markdown::Transformer::parse(&input_bytes).unwrap();
         let output_bytes = pdf::Transformer::generate(&document).unwrap().to_vec();
 
-        std::fs::write("test/data/test.pdf", output_bytes).unwrap();
+        std::fs::write(format!("{}/test.pdf", output_dir()), output_bytes).unwrap();
+    }
+
+    #[test]
+    fn test_sections() -> anyhow::Result<()> {
+        let mut document = Document::new(vec![
+            Element::Text {
+                text: "Portrait page".to_string(),
+                size: 10,
+            },
+            Element::Text {
+                text: "Landscape page".to_string(),
+                size: 10,
+            },
+        ]);
+        document.add_section(Section::new(1, PageFormat::A4, PageOrientation::Landscape));
+
+        let generated = Transformer::generate(&document)?;
+        let pdf_document = lopdf::Document::load_mem(&generated)?;
+        let pages = pdf_document.get_pages();
+        assert_eq!(pages.len(), 2);
+
+        let media_boxes = pages
+            .values()
+            .map(|page_id| {
+                let page = pdf_document.get_dictionary(*page_id)?;
+                let media_box = page.get(b"MediaBox")?.as_array()?;
+                let size: Vec<f32> = media_box
+                    .iter()
+                    .map(|value| value.as_float().unwrap_or_default())
+                    .collect();
+                Ok((size[2], size[3]))
+            })
+            .collect::<anyhow::Result<Vec<(f32, f32)>>>()?;
+        assert!(media_boxes[0].0 < media_boxes[0].1);
+        assert!(media_boxes[1].0 > media_boxes[1].1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_page_size() -> anyhow::Result<()> {
+        let document = Document::new(vec![Element::Text {
+            text: "Letter page".to_string(),
+            size: 10,
+        }])
+        .with_page_size(PageSize::Letter);
+
+        let generated = Transformer::generate(&document)?;
+        let pdf_document = lopdf::Document::load_mem(&generated)?;
+        let page_id = *pdf_document.get_pages().values().next().unwrap();
+        let media_box = pdf_document
+            .get_dictionary(page_id)?
+            .get(b"MediaBox")?
+            .as_array()?
+            .iter()
+            .map(|value| value.as_float().unwrap_or_default())
+            .collect::<Vec<f32>>();
+        assert!((media_box[2] - 612.0).abs() < 0.1);
+        assert!((media_box[3] - 792.0).abs() < 0.1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_right_to_left() -> anyhow::Result<()> {
+        let document = Document::new(vec![
+            Element::Header {
+                level: 1,
+                text: "مرحبا".to_string(),
+            },
+            Element::Paragraph {
+                elements: vec![Element::Text {
+                    text: "שלום עולם".to_string(),
+                    size: 10,
+                }],
+                direction: TextDirection::Auto,
+            },
+            Element::Paragraph {
+                elements: vec![Element::Text {
+                    text: "Hello".to_string(),
+                    size: 10,
+                }],
+                direction: TextDirection::Auto,
+            },
+        ]);
+
+        let (source, _) = crate::typst::generate_document(&document, &PdfOptions::default())?;
+        assert_eq!(source.matches("#set text(dir: rtl)").count(), 2);
+
+        let generated = Transformer::generate(&document)?;
+        assert!(!generated.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_table_of_contents() -> anyhow::Result<()> {
+        let document = Document::new(vec![
+            Element::TableOfContents { max_level: 2 },
+            Element::Header {
+                level: 1,
+                text: "Chapter".to_string(),
+            },
+            Element::Header {
+                level: 2,
+                text: "Section".to_string(),
+            },
+        ]);
+        let generated = Transformer::generate(&document)?;
+        let pdf_document = lopdf::Document::load_mem(&generated)?;
+
+        // the entries of the outline, title and page number, link to the headers
+        let page = pdf_document.get_dictionary(pdf_document.page_iter().next().unwrap())?;
+        let mut destinations = Vec::new();
+        for annotation in page.get(b"Annots")?.as_array()? {
+            let action = annotation.as_dict()?.get(b"A")?.as_dict()?;
+            destinations.push(String::from_utf8_lossy(action.get(b"D")?.as_str()?).into_owned());
+        }
+        assert_eq!(destinations, ["chapter", "chapter", "section", "section"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_page_number_fields() -> anyhow::Result<()> {
+        let mut document = Document::new(vec![Element::Text {
+            text: "Body".to_string(),
+            size: 10,
+        }]);
+        document.set_page_footer(vec![
+            Element::Text {
+                text: "Page ".to_string(),
+                size: 10,
+            },
+            Element::Field {
+                kind: FieldKind::PageNumber,
+            },
+            Element::Text {
+                text: " of ".to_string(),
+                size: 10,
+            },
+            Element::Field {
+                kind: FieldKind::TotalPages,
+            },
+        ]);
+
+        let (source, _) = crate::typst::generate_document(&document, &PdfOptions::default())?;
+        assert!(source.contains(
+            r##"footer: [#"Page "#context counter(page).display()#" of "#context counter(page).final().first()]"##
+        ));
+        let generated = Transformer::generate(&document)?;
+        assert!(!generated.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_page_layouts() -> anyhow::Result<()> {
+        let mut document = Document::new(vec![Element::Header {
+            level: 1,
+            text: "Report".to_string(),
+        }]);
+        document.set_page_header(vec![Element::Text {
+            text: "Odd".to_string(),
+            size: 10,
+        }]);
+        document.set_page_footer(vec![Element::Field {
+            kind: FieldKind::PageNumber,
+        }]);
+        let options = PdfOptions {
+            first_page_header: Some(vec![]),
+            even_page_header: Some(vec![Element::Field {
+                kind: FieldKind::DocumentTitle,
+            }]),
+            ..Default::default()
+        };
+
+        let (source, _) = crate::typst::generate_document(&document, &options)?;
+        assert!(source.contains(
+            r##"header: context if here().page() == 1 [] else if calc.even(here().page()) [#"Report"] else [#"Odd"],"##
+        ));
+        assert!(source.contains("footer: [#context counter(page).display()],"));
+        let generated = Transformer::generate_with_options(
+            &document,
+            &GenerateOptions {
+                pdf: options,
+                ..Default::default()
+            },
+        )?;
+        assert!(!generated.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_fonts() -> anyhow::Result<()> {
+        let document = Document::new(vec![Element::Text {
+            text: "Привет, мир".to_string(),
+            size: 10,
+        }]);
+        let options = GenerateOptions {
+            pdf: PdfOptions {
+                fonts: vec![FontSource::Path("fonts/PTSans-Regular.ttf".to_string())],
+                font_families: vec!["PT Sans".to_string()],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let generated = Transformer::generate_with_options(&document, &options)?;
+        let pdf = lopdf::Document::load_mem(&generated)?;
+        let fonts: Vec<String> = pdf
+            .objects
+            .values()
+            .filter_map(|object| object.as_dict().ok()?.get(b"BaseFont").ok())
+            .filter_map(|name| Some(String::from_utf8_lossy(name.as_name().ok()?).to_string()))
+            .collect();
+        // subsets are named with a tag, as in ABCDEF+PTSans-Regular
+        assert!(
+            fonts.iter().any(|font| font.ends_with("+PTSans-Regular")),
+            "{:?}",
+            fonts
+        );
+
+        let options = GenerateOptions {
+            pdf: PdfOptions {
+                fonts: vec![FontSource::Bytes(Bytes::from_static(b"not a font"))],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let error = Transformer::generate_with_options(&document, &options).unwrap_err();
+        assert!(error.to_string().contains("Invalid font"), "{}", error);
+        Ok(())
+    }
+
+    #[test]
+    fn test_bookmarks() -> anyhow::Result<()> {
+        let document = Document::new(vec![
+            Element::Bookmark {
+                id: "intro".to_string(),
+                title: "Introduction".to_string(),
+            },
+            Element::Text {
+                text: "Body".to_string(),
+                size: 10,
+            },
+            Element::Bookmark {
+                id: "appendix a".to_string(),
+                title: "Appendix \"A\"".to_string(),
+            },
+        ]);
+        let generated = Transformer::generate(&document)?;
+        let pdf = lopdf::Document::load_mem(&generated)?;
+        let mut titles: Vec<String> = pdf
+            .get_toc()?
+            .toc
+            .into_iter()
+            .map(|entry| entry.title)
+            .collect();
+        titles.sort();
+        assert_eq!(titles, vec!["Appendix \"A\"", "Introduction"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_table_layout() -> anyhow::Result<()> {
+        let text = |text: &str| Element::Text {
+            text: text.to_string(),
+            size: 8,
+        };
+        let rows = (1..=80)
+            .map(|row| TableRow {
+                cells: vec![
+                    TableCell {
+                        element: text(&format!("Row {}", row)),
+                    },
+                    TableCell {
+                        element: text(
+                            &"A long description that does not fit on one line. ".repeat(3),
+                        ),
+                    },
+                ],
+            })
+            .chain(std::iter::once(TableRow {
+                cells: vec![TableCell {
+                    element: text("Short row"),
+                }],
+            }))
+            .collect();
+        let document = Document::new(vec![Element::Table {
+            headers: vec![
+                TableHeader {
+                    element: text("Name"),
+                    width: 1.0,
+                },
+                TableHeader {
+                    element: text("Description"),
+                    width: 3.0,
+                },
+            ],
+            rows,
+        }]);
+        let (source, _) = crate::typst::generate_document(&document, &PdfOptions::default())?;
+        assert!(source.contains("columns: (1fr, 3fr,)"));
+        assert!(source.contains("[Short row],[],"));
+
+        // the header of a table split across pages is repeated on each page
+        assert!(source.contains("table.header(\n[#strong[Name]],"));
+        let generated = Transformer::generate(&document)?;
+        let pdf = lopdf::Document::load_mem(&generated)?;
+        assert!(pdf.get_pages().len() > 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_outline_depth() -> anyhow::Result<()> {
+        let document = Document::new(
+            (1..=3)
+                .map(|level| Element::Header {
+                    level,
+                    text: format!("Level {}", level),
+                })
+                .collect(),
+        );
+        let outline = |outline_depth: Option<u8>| -> anyhow::Result<Vec<String>> {
+            let options = GenerateOptions {
+                pdf: PdfOptions {
+                    outline_depth,
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            let generated = Transformer::generate_with_options(&document, &options)?;
+            let pdf = lopdf::Document::load_mem(&generated)?;
+            let mut titles: Vec<String> = match pdf.get_toc() {
+                Ok(toc) => toc.toc.into_iter().map(|entry| entry.title).collect(),
+                Err(_) => vec![],
+            };
+            titles.sort();
+            Ok(titles)
+        };
+        assert_eq!(outline(None)?, vec!["Level 1", "Level 2", "Level 3"]);
+        assert_eq!(outline(Some(2))?, vec!["Level 1", "Level 2"]);
+        assert!(outline(Some(0))?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_archival() -> anyhow::Result<()> {
+        let document = Document::new(vec![
+            Element::Header {
+                level: 1,
+                text: "Annual report".to_string(),
+            },
+            Element::Text {
+                text: "Body".to_string(),
+                size: 10,
+            },
+        ]);
+        let options = GenerateOptions {
+            pdf: PdfOptions {
+                archival: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let generated = Transformer::generate_with_options(&document, &options)?;
+        assert!(generated.starts_with(b"%PDF-1.7\n%"));
+        assert!(generated[10..14].iter().all(|byte| *byte > 127));
+
+        let pdf = lopdf::Document::load_mem(&generated)?;
+        let catalog = pdf.catalog()?;
+        let metadata = pdf.get_object(catalog.get(b"Metadata")?.as_reference()?)?;
+        let xmp = String::from_utf8(metadata.as_stream()?.content.clone())?;
+        assert!(xmp.contains("<pdfaid:part>2</pdfaid:part>"));
+        assert!(xmp.contains("Annual report"));
+        assert!(xmp.contains("<pdfuaid:part>1</pdfuaid:part>"));
+        let intent = &catalog.get(b"OutputIntents")?.as_array()?[0];
+        assert_eq!(intent.as_dict()?.get(b"S")?.as_name_str()?, "GTS_PDFA1");
+        let mark_info = catalog.get(b"MarkInfo")?.as_dict()?;
+        assert!(mark_info.get(b"Marked")?.as_bool()?);
+        let root = pdf.get_dictionary(catalog.get(b"StructTreeRoot")?.as_reference()?)?;
+        let document = pdf.get_dictionary(root.get(b"K")?.as_reference()?)?;
+        assert_eq!(document.get(b"S")?.as_name_str()?, "Document");
+        let roles = document
+            .get(b"K")?
+            .as_array()?
+            .iter()
+            .map(|kid| {
+                Ok(pdf
+                    .get_dictionary(kid.as_reference()?)?
+                    .get(b"S")?
+                    .as_name_str()?)
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        assert_eq!(roles, vec!["H1", "P"]);
+
+        // the offsets moved by the binary comment are the ones of the objects
+        for (id, entry) in &pdf.reference_table.entries {
+            if let lopdf::xref::XrefEntry::Normal { offset, .. } = entry {
+                let object = format!("{id} 0 obj");
+                assert!(generated[*offset as usize..].starts_with(object.as_bytes()));
+            }
+        }
+
+        let parsed = Transformer::parse(&generated)?;
+        assert!(!parsed.get_all_elements().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_encryption() -> anyhow::Result<()> {
+        let document = Document::new(vec![Element::Text {
+            text: "Confidential".to_string(),
+            size: 10,
+        }]);
+        let options = |encryption: Option<PdfEncryption>| GenerateOptions {
+            pdf: PdfOptions {
+                encryption,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let plain = Transformer::generate(&document)?;
+        let generated = Transformer::generate_with_options(
+            &document,
+            &options(Some(PdfEncryption {
+                user_password: "secret".to_string(),
+                no_print: true,
+                ..Default::default()
+            })),
+        )?;
+
+        let pdf = lopdf::Document::load_mem(&generated)?;
+        let encrypt = pdf.get_encrypted()?;
+        assert_eq!(encrypt.get(b"V")?.as_i64()?, 5);
+        let permissions = encrypt.get(b"P")?.as_i64()?;
+        assert_eq!(permissions & 4, 0);
+        assert_eq!(permissions & 16, 16);
+
+        assert!(Transformer::parse(&generated).is_err());
+        let parse_options = |password: &str| ParseOptions {
+            pdf: PdfParseOptions {
+                password: Some(password.to_string()),
+            },
+            ..Default::default()
+        };
+        assert!(Transformer::parse_with_options(&generated, &parse_options("wrong")).is_err());
+        let parsed = Transformer::parse_with_options(&generated, &parse_options("secret"))?;
+        assert_eq!(parsed, Transformer::parse(&plain)?);
+
+        // only restricted, opened without password
+        let generated = Transformer::generate_with_options(
+            &document,
+            &options(Some(PdfEncryption {
+                owner_password: "owner".to_string(),
+                no_copy: true,
+                ..Default::default()
+            })),
+        )?;
+        assert_eq!(Transformer::parse(&generated)?, Transformer::parse(&plain)?);
+        let parsed = Transformer::parse_with_options(&generated, &parse_options("owner"))?;
+        assert_eq!(parsed, Transformer::parse(&plain)?);
+
+        let archival = GenerateOptions {
+            pdf: PdfOptions {
+                archival: true,
+                encryption: Some(PdfEncryption::default()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert!(Transformer::generate_with_options(&document, &archival).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_layout() -> anyhow::Result<()> {
+        let paragraph = "The quick brown dog jumps over the lazy cat. ".repeat(8);
+        let paragraph = paragraph.trim();
+        let document = Document::new(vec![
+            Element::Header {
+                level: 1,
+                text: "Annual report".to_string(),
+            },
+            Element::Paragraph {
+                elements: vec![Element::Text {
+                    text: paragraph.to_string(),
+                    size: 10,
+                }],
+                direction: TextDirection::default(),
+            },
+            Element::Header {
+                level: 2,
+                text: "Results".to_string(),
+            },
+            Element::List {
+                elements: vec![
+                    ListItem {
+                        element: Element::Text {
+                            text: "Revenue".to_string(),
+                            size: 10,
+                        },
+                    },
+                    ListItem {
+                        element: Element::Text {
+                            text: "Margin".to_string(),
+                            size: 10,
+                        },
+                    },
+                ],
+                numbered: false,
+                start: 1,
+                numbering: NumberingStyle::default(),
+            },
+        ]);
+        let generated = Transformer::generate(&document)?;
+        let parsed = Transformer::parse(&generated)?;
+        let elements = parsed.get_all_elements();
+
+        assert_eq!(
+            elements[0],
+            &Element::Header {
+                level: 1,
+                text: "Annual report".to_string(),
+            }
+        );
+        // the lines of the paragraph are joined back
+        assert!(matches!(
+            elements[1],
+            Element::Paragraph { elements, .. }
+                if matches!(&elements[0], Element::Text { text, .. } if text == paragraph)
+        ));
+        assert_eq!(
+            elements[2],
+            &Element::Header {
+                level: 2,
+                text: "Results".to_string(),
+            }
+        );
+        let Element::List {
+            elements: items, ..
+        } = elements[3]
+        else {
+            panic!("list expected, got {:?}", elements[3]);
+        };
+        let items: Vec<String> = items.iter().map(|item| item.element.plain_text()).collect();
+        assert_eq!(items, vec!["Revenue", "Margin"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_images() -> anyhow::Result<()> {
+        let mut png = Vec::new();
+        let picture = image::RgbaImage::from_fn(40, 20, |x, y| {
+            image::Rgba([
+                x as u8 * 6,
+                y as u8 * 12,
+                200,
+                if x < 20 { 255 } else { 128 },
+            ])
+        });
+        image::DynamicImage::ImageRgba8(picture.clone()).write_to(
+            &mut std::io::Cursor::new(&mut png),
+            image::ImageOutputFormat::Png,
+        )?;
+        let mut jpeg = Vec::new();
+        image::DynamicImage::ImageRgb8(image::RgbImage::from_pixel(
+            16,
+            16,
+            image::Rgb([0, 0, 255]),
+        ))
+        .write_to(
+            &mut std::io::Cursor::new(&mut jpeg),
+            image::ImageOutputFormat::Jpeg(90),
+        )?;
+        let image = |bytes: Vec<u8>, image_type: &str| {
+            Element::Image(ImageData::new(
+                Bytes::from(bytes),
+                "".to_string(),
+                "".to_string(),
+                image_type.to_string(),
+                "".to_string(),
+                ImageDimension {
+                    width: Some("200".to_string()),
+                    height: None,
+                },
+            ))
+        };
+        let document = Document::new(vec![
+            Element::Paragraph {
+                elements: vec![Element::Text {
+                    text: "Before the figures.".to_string(),
+                    size: 10,
+                }],
+                direction: TextDirection::default(),
+            },
+            image(png, "png"),
+            image(jpeg, "jpeg"),
+            Element::Paragraph {
+                elements: vec![Element::Text {
+                    text: "After the figures.".to_string(),
+                    size: 10,
+                }],
+                direction: TextDirection::default(),
+            },
+        ]);
+        let generated = Transformer::generate(&document)?;
+        let parsed = Transformer::parse(&generated)?;
+        let elements = parsed.get_all_elements();
+
+        assert_eq!(elements.len(), 4, "{:?}", elements);
+        let Element::Image(png) = elements[1] else {
+            panic!("image expected, got {:?}", elements[1]);
+        };
+        assert_eq!(png.image_type(), &ImageType::Png);
+        assert_eq!(png.size().width.as_deref(), Some("200"));
+        // the soft mask is the alpha of the image
+        assert_eq!(image::load_from_memory(png.bytes())?.to_rgba8(), picture);
+        let Element::Image(jpeg_image) = elements[2] else {
+            panic!("image expected, got {:?}", elements[2]);
+        };
+        assert_eq!(jpeg_image.image_type(), &ImageType::Jpeg);
+        // typst encodes the JPEG images again
+        let decoded = image::load_from_memory(jpeg_image.bytes())?.to_rgb8();
+        assert_eq!(decoded.dimensions(), (16, 16));
+        assert!(decoded.get_pixel(8, 8)[2] > 240);
+        assert_eq!(elements[3].plain_text(), "After the figures.");
+        Ok(())
+    }
+
+    #[test]
+    fn test_ocr() -> anyhow::Result<()> {
+        let mut png = Vec::new();
+        image::DynamicImage::new_rgb8(60, 80).write_to(
+            &mut std::io::Cursor::new(&mut png),
+            image::ImageOutputFormat::Png,
+        )?;
+        let scan = Document::new(vec![Element::Image(ImageData::new(
+            Bytes::from(png),
+            "".to_string(),
+            "".to_string(),
+            "png".to_string(),
+            "".to_string(),
+            ImageDimension::default(),
+        ))]);
+        let options = ParseOptions {
+            ocr: Some(crate::ocr::Ocr::new(|image: &ImageData| {
+                assert_eq!(image.image_type(), &ImageType::Png);
+                Ok("Scanned\npage\n\nSecond paragraph".to_string())
+            })),
+            ..Default::default()
+        };
+
+        let generated = Transformer::generate(&scan)?;
+        let parsed = Transformer::parse_with_options(&generated, &options)?;
+        let texts: Vec<String> = parsed
+            .get_all_elements()
+            .iter()
+            .map(|element| element.plain_text())
+            .collect();
+        assert_eq!(texts, vec!["Scanned page", "Second paragraph"]);
+        // without OCR the scan stays an image
+        let parsed = Transformer::parse(&generated)?;
+        assert!(matches!(parsed.get_all_elements()[0], Element::Image(_)));
+
+        // the pages with text are not scanned
+        let mut document = scan.clone();
+        document.add_element(Element::Text {
+            text: "Caption".to_string(),
+            size: 10,
+        });
+        let generated = Transformer::generate(&document)?;
+        let parsed = Transformer::parse_with_options(&generated, &options)?;
+        assert!(matches!(parsed.get_all_elements()[0], Element::Image(_)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_table() -> anyhow::Result<()> {
+        let text = |text: &str| Element::Text {
+            text: text.to_string(),
+            size: 10,
+        };
+        let rows = [["Apples", "1.20", "40"], ["Pears", "2.50", "12"]];
+        let document = Document::new(vec![
+            Element::Paragraph {
+                elements: vec![text("Prices of the week.")],
+                direction: TextDirection::default(),
+            },
+            Element::Table {
+                headers: ["Fruit", "Price", "Stock"]
+                    .iter()
+                    .map(|header| TableHeader {
+                        element: text(header),
+                        width: 10.0,
+                    })
+                    .collect(),
+                rows: rows
+                    .iter()
+                    .map(|row| TableRow {
+                        cells: row
+                            .iter()
+                            .map(|cell| TableCell {
+                                element: text(cell),
+                            })
+                            .collect(),
+                    })
+                    .collect(),
+            },
+            Element::Paragraph {
+                elements: vec![text("Prices may change.")],
+                direction: TextDirection::default(),
+            },
+        ]);
+        let generated = Transformer::generate(&document)?;
+        let parsed = Transformer::parse(&generated)?;
+        let elements = parsed.get_all_elements();
+
+        assert_eq!(elements.len(), 3, "{:?}", elements);
+        let Element::Table { headers, rows } = elements[1] else {
+            panic!("table expected, got {:?}", elements[1]);
+        };
+        let headers: Vec<String> = headers
+            .iter()
+            .map(|header| header.element.plain_text())
+            .collect();
+        assert_eq!(headers, vec!["Fruit", "Price", "Stock"]);
+        let rows: Vec<Vec<String>> = rows
+            .iter()
+            .map(|row| {
+                row.cells
+                    .iter()
+                    .map(|cell| cell.element.plain_text())
+                    .collect()
+            })
+            .collect();
+        assert_eq!(
+            rows,
+            vec![vec!["Apples", "1.20", "40"], vec!["Pears", "2.50", "12"]]
+        );
+        assert_eq!(elements[2].plain_text(), "Prices may change.");
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_links() -> anyhow::Result<()> {
+        use lopdf::dictionary;
+
+        let text = |text: &str| Element::Text {
+            text: text.to_string(),
+            size: 11,
+        };
+        let link = |label: &str, url: &str| Element::Hyperlink {
+            elements: vec![text(label)],
+            url: url.to_string(),
+            alt: String::new(),
+            size: 11,
+        };
+        let document = Document::new(vec![
+            Element::Header {
+                level: 1,
+                text: "Introduction".to_string(),
+            },
+            Element::Paragraph {
+                elements: vec![
+                    text("See "),
+                    link("the example site", "https://example.com/page"),
+                    text(" and the "),
+                    link("details", "#details"),
+                    text(" below."),
+                ],
+                direction: TextDirection::default(),
+            },
+            Element::Header {
+                level: 2,
+                text: "Details".to_string(),
+            },
+            Element::Paragraph {
+                elements: vec![text("The end.")],
+                direction: TextDirection::default(),
+            },
+        ]);
+        let generated = Transformer::generate(&document)?;
+
+        // a comment on the paragraph of the links
+        let mut pdf = lopdf::Document::load_mem(&generated)?;
+        let page_id = *pdf.get_pages().get(&1).unwrap();
+        let annots = pdf
+            .get_dictionary(page_id)?
+            .get_deref(b"Annots", &pdf)?
+            .as_array()?
+            .clone();
+        let link_rect = pdf
+            .dereference(&annots[0])?
+            .1
+            .as_dict()?
+            .get(b"Rect")?
+            .as_array()?
+            .iter()
+            .map(|value| value.as_float())
+            .collect::<Result<Vec<f32>, _>>()?;
+        let comment = pdf.add_object(dictionary! {
+            "Type" => "Annot",
+            "Subtype" => "Text",
+            "Rect" => vec![
+                20.into(),
+                (link_rect[1].min(link_rect[3]) - 4.0).into(),
+                40.into(),
+                link_rect[1].max(link_rect[3]).into(),
+            ],
+            "Contents" => lopdf::Object::string_literal("Check this link"),
+            "T" => lopdf::Object::string_literal("Reviewer"),
+        });
+        let mut annots = annots;
+        annots.push(comment.into());
+        pdf.get_dictionary_mut(page_id)?.set("Annots", annots);
+        let mut annotated = Vec::new();
+        pdf.save_to(&mut annotated)?;
+
+        let parsed = Transformer::parse(&Bytes::from(annotated))?;
+        let elements = parsed.get_all_elements();
+        assert_eq!(elements.len(), 4, "{:?}", elements);
+        assert_eq!(
+            elements[0],
+            &Element::Header {
+                level: 1,
+                text: "Introduction".to_string()
+            }
+        );
+        let Element::Paragraph {
+            elements: inline, ..
+        } = elements[1]
+        else {
+            panic!("paragraph expected, got {:?}", elements[1]);
+        };
+        let urls: Vec<&str> = inline
+            .iter()
+            .filter_map(|element| match element {
+                Element::Hyperlink { url, .. } => Some(url.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(urls, vec!["https://example.com/page", "#details"]);
+        let Some(Element::Attributed {
+            attributes,
+            element,
+        }) = inline.last()
+        else {
+            panic!("comment expected, got {:?}", inline);
+        };
+        assert_eq!(attributes["pdf:comment"], "Reviewer");
+        assert_eq!(element.plain_text(), "Check this link");
+        // the outline entry of the header is its anchor, the target of the link
+        assert_eq!(
+            elements[2],
+            &Element::Header {
+                level: 2,
+                text: "Details".to_string()
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_drawing() -> anyhow::Result<()> {
+        let document = DocumentBuilder::new()
+            .drawing(
+                200.0,
+                100.0,
+                vec![
+                    Shape::line(0.0, 100.0, 200.0, 100.0),
+                    Shape::rect(20.0, 40.0, 30.0, 60.0).with_fill("#4472c4"),
+                ],
+            )
+            .build();
+        let generated = Transformer::generate(&document)?;
+        let pdf = lopdf::Document::load_mem(&generated)?;
+
+        // drawn as vector graphics, not as an image
+        let streams: Vec<&lopdf::Stream> = pdf
+            .objects
+            .values()
+            .filter_map(|object| object.as_stream().ok())
+            .collect();
+        assert!(streams.iter().all(|stream| {
+            stream
+                .dict
+                .get(b"Subtype")
+                .and_then(|s| s.as_name_str())
+                .ok()
+                != Some("Image")
+        }));
+        let content: String = streams
+            .iter()
+            .filter_map(|stream| stream.decompressed_content().ok())
+            .map(|content| String::from_utf8_lossy(&content).into_owned())
+            .collect();
+        assert!(content.contains("0 100 m\n200 100 l\nS"), "{}", content);
+        assert!(content.contains("20 40 m\n50 40 l\n50 100 l\n20 100 l\nh\nf"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_compression() -> anyhow::Result<()> {
+        let mut png = Vec::new();
+        let picture = image::RgbImage::from_fn(64, 64, |x, y| image::Rgb([x as u8, y as u8, 90]));
+        image::DynamicImage::ImageRgb8(picture).write_to(
+            &mut std::io::Cursor::new(&mut png),
+            image::ImageOutputFormat::Png,
+        )?;
+        let image = |title: &str, alt: &str| {
+            Element::Image(ImageData::new(
+                Bytes::from(png.clone()),
+                title.to_string(),
+                alt.to_string(),
+                "png".to_string(),
+                "".to_string(),
+                ImageDimension::default(),
+            ))
+        };
+        let mut elements = Vec::new();
+        for page in 0..20 {
+            elements.push(image("logo", "Logo"));
+            elements.push(image(&format!("figure{page}"), "Logo"));
+            elements.push(image("badge", &format!("Badge {page}")));
+            elements.push(Element::Text {
+                text: "Quarterly figures of the report. ".repeat(40),
+                size: 10,
+            });
+        }
+        let document = Document::new(elements);
+        let images = |generated: &Bytes| -> anyhow::Result<usize> {
+            let pdf = lopdf::Document::load_mem(generated)?;
+            Ok(pdf
+                .objects
+                .values()
+                .filter_map(|object| object.as_stream().ok())
+                .filter(|stream| {
+                    stream
+                        .dict
+                        .get(b"Subtype")
+                        .and_then(|s| s.as_name_str())
+                        .ok()
+                        == Some("Image")
+                })
+                .count())
+        };
+
+        // typst writes an image again for each alternative text, the copies are dropped
+        let generated = Transformer::generate(&document)?;
+        assert_eq!(images(&generated)?, 1);
+
+        let options = GenerateOptions {
+            pdf: PdfOptions {
+                compression: PdfCompression::Best,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let best = Transformer::generate_with_options(&document, &options)?;
+        assert_eq!(images(&best)?, 1);
+        assert!(best.len() < generated.len());
+        let parsed = Transformer::parse(&best)?;
+        assert_eq!(parsed.get_all_elements().len(), 80);
+
+        // the images of the same title are not mixed up
+        let mut other = Vec::new();
+        image::DynamicImage::new_rgb8(8, 8).write_to(
+            &mut std::io::Cursor::new(&mut other),
+            image::ImageOutputFormat::Png,
+        )?;
+        let mut document = Document::new(vec![image("chart", "Chart")]);
+        document.add_element(Element::Image(ImageData::new(
+            Bytes::from(other),
+            "chart".to_string(),
+            "Chart".to_string(),
+            "png".to_string(),
+            "".to_string(),
+            ImageDimension::default(),
+        )));
+        assert_eq!(
+            images(&Transformer::generate_with_options(&document, &options)?)?,
+            2
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_watermark() -> anyhow::Result<()> {
+        let document = Document::new(vec![Element::Text {
+            text: "Terms of the agreement. ".repeat(400),
+            size: 10,
+        }]);
+        let options = GenerateOptions {
+            pdf: PdfOptions {
+                watermark: Some(PdfWatermark::text("DRAFT").with_color("red")),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let generated = Transformer::generate_with_options(&document, &options)?;
+        let pdf = lopdf::Document::load_mem(&generated)?;
+        let pages = pdf.get_pages();
+        assert!(pages.len() > 1);
+        for page in pages.values() {
+            // turned by 45 degrees
+            let content = String::from_utf8_lossy(&pdf.get_page_content(*page)?).into_owned();
+            assert!(content.contains("0.70710677 -0.70710677 0.70710677 0.70710677"));
+        }
+        // drawn at the opacity of the watermark
+        assert!(pdf.objects.values().any(|object| {
+            object
+                .as_dict()
+                .and_then(|state| state.get(b"ca")?.as_float())
+                .is_ok_and(|alpha| (alpha - 0.3).abs() < 0.01)
+        }));
+
+        let mut png = Vec::new();
+        image::DynamicImage::ImageRgb8(image::RgbImage::from_pixel(
+            20,
+            10,
+            image::Rgb([200, 0, 0]),
+        ))
+        .write_to(
+            &mut std::io::Cursor::new(&mut png),
+            image::ImageOutputFormat::Png,
+        )?;
+        let stamp = ImageData::new(
+            Bytes::from(png),
+            "stamp".to_string(),
+            "".to_string(),
+            "png".to_string(),
+            "".to_string(),
+            ImageDimension::default(),
+        );
+        let options = GenerateOptions {
+            pdf: PdfOptions {
+                watermark: Some(
+                    PdfWatermark::image(stamp)
+                        .with_opacity(0.5)
+                        .with_position(WatermarkPosition::BottomRight)
+                        .behind(),
+                ),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let generated = Transformer::generate_with_options(&document, &options)?;
+        let pdf = lopdf::Document::load_mem(&generated)?;
+        let images: Vec<&lopdf::Stream> = pdf
+            .objects
+            .values()
+            .filter_map(|object| object.as_stream().ok())
+            .filter(|stream| {
+                stream
+                    .dict
+                    .get(b"Subtype")
+                    .and_then(|s| s.as_name_str())
+                    .ok()
+                    == Some("Image")
+            })
+            .collect();
+        // the same image on every page, its alpha in a soft mask
+        assert_eq!(images.len(), 2);
+        assert!(images.iter().any(|image| image.dict.has(b"SMask")));
+        Ok(())
+    }
+
+    #[test]
+    fn test_generate_options() -> anyhow::Result<()> {
+        let document = Document::new(vec![Element::Text {
+            text: "Hello".to_string(),
+            size: 8,
+        }]);
+        let options = PdfOptions {
+            font_size: Some(14.0),
+            margins: Some(Length::pt(20.0)),
+            ..Default::default()
+        };
+        let (source, _) = crate::typst::generate_document(&document, &options)?;
+        assert!(source.contains("#set text(size: 14pt)"));
+        assert!(source.contains("margin: (top: 20pt, bottom: 20pt, left: 20pt, right: 20pt)"));
+
+        let generated = Transformer::generate_with_options(
+            &document,
+            &GenerateOptions {
+                pdf: options,
+                ..Default::default()
+            },
+        )?;
+        assert!(generated.starts_with(b"%PDF"));
+        Ok(())
     }
 }
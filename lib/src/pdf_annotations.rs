@@ -0,0 +1,386 @@
+//! Links, text annotations and outline of PDF documents, gathered by page.
+//!
+//! The destinations of the outline entries and of the internal links are the targets of
+//! the pages, with an id: the anchor of the outline title, else the name of a named
+//! destination, else the page and height. Internal links point to them by `#id`.
+
+use std::collections::{HashMap, HashSet};
+
+use lopdf::{Dictionary, Document as PdfDocument, Object, ObjectId};
+
+use crate::core::header_anchor;
+
+/// Nesting of the outline and of the name trees followed
+const MAX_DEPTH: usize = 32;
+
+/// Annotations and targets of the pages of a document
+#[derive(Debug, Default)]
+pub struct Annotations {
+    /// URLs of the links, `#id` for the internal ones
+    pub urls: Vec<String>,
+    pub pages: HashMap<ObjectId, PageAnnotations>,
+}
+
+#[derive(Debug, Default)]
+pub struct PageAnnotations {
+    pub links: Vec<Link>,
+    pub comments: Vec<Comment>,
+    pub targets: Vec<Target>,
+}
+
+/// Area of a page linking to one of the URLs
+#[derive(Debug, Clone, Copy)]
+pub struct Link {
+    /// Left, bottom, right and top of the area
+    pub rect: [f32; 4],
+    pub url: usize,
+}
+
+impl Link {
+    pub fn contains(&self, x: f32, y: f32) -> bool {
+        let [x0, y0, x1, y1] = self.rect;
+        // the areas are often drawn tight around the glyphs
+        x >= x0 - 1.0 && x <= x1 + 1.0 && y >= y0 - 1.0 && y <= y1 + 1.0
+    }
+}
+
+/// Note of a text annotation, at the bottom left of its icon, after the line it is on
+#[derive(Debug, Clone)]
+pub struct Comment {
+    pub x: f32,
+    pub y: f32,
+    pub text: String,
+    pub author: String,
+}
+
+/// Destination of an outline entry or of an internal link, at the top of the page when
+/// it has no height
+#[derive(Debug, Clone)]
+pub struct Target {
+    pub x: f32,
+    pub y: Option<f32>,
+    pub id: String,
+    pub title: String,
+}
+
+/// Position on a page of a destination
+struct Place {
+    page: ObjectId,
+    x: f32,
+    y: Option<f32>,
+}
+
+/// Reads the outline and the annotations of the pages
+pub fn read(pdf: &PdfDocument) -> Annotations {
+    let mut reader = Reader {
+        pdf,
+        page_numbers: pdf
+            .get_pages()
+            .into_iter()
+            .map(|(number, id)| (id, number))
+            .collect(),
+        annotations: Annotations::default(),
+        ids: HashSet::new(),
+    };
+    // the outline first, so that its titles give the ids
+    if let Ok(outlines) = pdf
+        .catalog()
+        .and_then(|catalog| catalog.get_deref(b"Outlines", pdf))
+        .and_then(Object::as_dict)
+    {
+        reader.outline(outlines, &mut HashSet::new(), 0);
+    }
+    for page_id in pdf.page_iter() {
+        let Ok(page) = pdf.get_dictionary(page_id) else {
+            continue;
+        };
+        let annots = page
+            .get_deref(b"Annots", pdf)
+            .and_then(Object::as_array)
+            .map(|annots| annots.as_slice())
+            .unwrap_or_default();
+        for annot in annots {
+            if let Ok((_, Object::Dictionary(annot))) = pdf.dereference(annot) {
+                reader.annotation(page_id, annot);
+            }
+        }
+    }
+    reader.annotations
+}
+
+struct Reader<'a> {
+    pdf: &'a PdfDocument,
+    page_numbers: HashMap<ObjectId, u32>,
+    annotations: Annotations,
+    ids: HashSet<String>,
+}
+
+impl<'a> Reader<'a> {
+    fn outline(&mut self, node: &'a Dictionary, visited: &mut HashSet<ObjectId>, depth: usize) {
+        let mut next = node.get(b"First").and_then(Object::as_reference).ok();
+        while let Some(id) = next {
+            if depth >= MAX_DEPTH || !visited.insert(id) {
+                return;
+            }
+            let Ok(item) = self.pdf.get_dictionary(id) else {
+                return;
+            };
+            let title = item
+                .get_deref(b"Title", self.pdf)
+                .and_then(Object::as_str)
+                .map(text_string)
+                .unwrap_or_default();
+            let destination = item.get(b"Dest").ok().or_else(|| self.go_to(item));
+            if let Some((place, name)) = destination.and_then(|dest| self.destination(dest, 0)) {
+                self.target(place, name, title.trim());
+            }
+            self.outline(item, visited, depth + 1);
+            next = item.get(b"Next").and_then(Object::as_reference).ok();
+        }
+    }
+
+    fn annotation(&mut self, page_id: ObjectId, annot: &'a Dictionary) {
+        let Some(rect) = rect(self.pdf, annot) else {
+            return;
+        };
+        let text = |key: &[u8]| {
+            annot
+                .get_deref(key, self.pdf)
+                .and_then(Object::as_str)
+                .map(text_string)
+                .unwrap_or_default()
+        };
+        match annot.get(b"Subtype").and_then(Object::as_name_str) {
+            Ok("Link") => {
+                let action = annot
+                    .get_deref(b"A", self.pdf)
+                    .and_then(Object::as_dict)
+                    .ok();
+                let uri = action
+                    .filter(|action| {
+                        action.get(b"S").and_then(Object::as_name_str).ok() == Some("URI")
+                    })
+                    .and_then(|action| action.get_deref(b"URI", self.pdf).ok())
+                    .and_then(|uri| uri.as_str().ok())
+                    .map(text_string);
+                let url = match uri {
+                    Some(uri) => uri,
+                    None => {
+                        let destination = annot.get(b"Dest").ok().or_else(|| self.go_to(annot));
+                        let Some((place, name)) =
+                            destination.and_then(|dest| self.destination(dest, 0))
+                        else {
+                            return;
+                        };
+                        format!("#{}", self.target(place, name, ""))
+                    }
+                };
+                self.annotations.urls.push(url);
+                let url = self.annotations.urls.len() - 1;
+                self.page(page_id).links.push(Link { rect, url });
+            }
+            Ok("Text" | "FreeText") => {
+                let comment = text(b"Contents");
+                if !comment.trim().is_empty() {
+                    let author = text(b"T");
+                    self.page(page_id).comments.push(Comment {
+                        x: rect[0],
+                        y: rect[1],
+                        text: comment.trim().to_string(),
+                        author,
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn page(&mut self, page_id: ObjectId) -> &mut PageAnnotations {
+        self.annotations.pages.entry(page_id).or_default()
+    }
+
+    /// Destination of the go-to action of an outline entry or a link
+    fn go_to(&self, dictionary: &'a Dictionary) -> Option<&'a Object> {
+        let action = dictionary.get_deref(b"A", self.pdf).ok()?.as_dict().ok()?;
+        if action.get(b"S").and_then(Object::as_name_str).ok()? != "GoTo" {
+            return None;
+        }
+        action.get(b"D").ok()
+    }
+
+    /// Place of a destination, with its name when it is a named destination
+    fn destination(&self, dest: &'a Object, depth: usize) -> Option<(Place, Option<String>)> {
+        if depth >= MAX_DEPTH {
+            return None;
+        }
+        let (_, dest) = self.pdf.dereference(dest).ok()?;
+        match dest {
+            Object::Name(name) => {
+                let dests = self
+                    .pdf
+                    .catalog()
+                    .ok()?
+                    .get_deref(b"Dests", self.pdf)
+                    .ok()?
+                    .as_dict()
+                    .ok()?;
+                let (place, _) = self.destination(dests.get(name).ok()?, depth + 1)?;
+                Some((place, Some(String::from_utf8_lossy(name).into_owned())))
+            }
+            Object::String(name, _) => {
+                let tree = self
+                    .pdf
+                    .catalog()
+                    .ok()?
+                    .get_deref(b"Names", self.pdf)
+                    .ok()?
+                    .as_dict()
+                    .ok()?
+                    .get_deref(b"Dests", self.pdf)
+                    .ok()?
+                    .as_dict()
+                    .ok()?;
+                let value = name_tree(self.pdf, tree, name, 0)?;
+                let (place, _) = self.destination(value, depth + 1)?;
+                Some((place, Some(text_string(name))))
+            }
+            Object::Dictionary(dictionary) => {
+                self.destination(dictionary.get(b"D").ok()?, depth + 1)
+            }
+            Object::Array(array) => {
+                let page = array.first()?.as_reference().ok()?;
+                let number =
+                    |index: usize| array.get(index).and_then(|value| value.as_float().ok());
+                let (x, y) = match array.get(1)?.as_name_str().ok()? {
+                    "XYZ" => (number(2), number(3)),
+                    "FitH" | "FitBH" => (None, number(2)),
+                    "FitR" => (number(2), number(5)),
+                    _ => (None, None),
+                };
+                Some((
+                    Place {
+                        page,
+                        x: x.unwrap_or_default(),
+                        y,
+                    },
+                    None,
+                ))
+            }
+            _ => None,
+        }
+    }
+
+    /// Id of the target at the place, added when the page has none there
+    fn target(&mut self, place: Place, name: Option<String>, title: &str) -> String {
+        let number = self
+            .page_numbers
+            .get(&place.page)
+            .copied()
+            .unwrap_or_default();
+        let same_place = |target: &Target| match (target.y, place.y) {
+            (Some(a), Some(b)) => (a - b).abs() < 2.0,
+            (a, b) => a.is_none() && b.is_none(),
+        };
+        let page = self.annotations.pages.entry(place.page).or_default();
+        if let Some(target) = page.targets.iter_mut().find(|target| same_place(target)) {
+            if target.title.is_empty() {
+                target.title = title.to_string();
+            }
+            return target.id.clone();
+        }
+
+        let anchor = header_anchor(title);
+        let base = if !anchor.is_empty() {
+            anchor
+        } else if let Some(name) = name.filter(|name| !name.is_empty()) {
+            name
+        } else {
+            format!("page-{}-{}", number, place.y.unwrap_or_default().round())
+        };
+        let mut id = base.clone();
+        let mut suffix = 1;
+        while !self.ids.insert(id.clone()) {
+            suffix += 1;
+            id = format!("{}-{}", base, suffix);
+        }
+        let page = self.annotations.pages.entry(place.page).or_default();
+        page.targets.push(Target {
+            x: place.x,
+            y: place.y,
+            id: id.clone(),
+            title: title.to_string(),
+        });
+        id
+    }
+}
+
+/// Value of a key in a name tree
+fn name_tree<'a>(
+    pdf: &'a PdfDocument,
+    node: &'a Dictionary,
+    key: &[u8],
+    depth: usize,
+) -> Option<&'a Object> {
+    if depth >= MAX_DEPTH {
+        return None;
+    }
+    if let Ok(names) = node.get_deref(b"Names", pdf).and_then(Object::as_array) {
+        let found = names
+            .chunks_exact(2)
+            .find(|pair| pair[0].as_str().ok() == Some(key))
+            .map(|pair| &pair[1]);
+        if found.is_some() {
+            return found;
+        }
+    }
+    let kids = node
+        .get_deref(b"Kids", pdf)
+        .and_then(Object::as_array)
+        .ok()?;
+    kids.iter().find_map(|kid| {
+        let kid = pdf.dereference(kid).ok()?.1.as_dict().ok()?;
+        name_tree(pdf, kid, key, depth + 1)
+    })
+}
+
+/// Left, bottom, right and top of the `Rect` of an annotation
+pub(crate) fn rect(pdf: &PdfDocument, annot: &Dictionary) -> Option<[f32; 4]> {
+    let values: Vec<f32> = annot
+        .get_deref(b"Rect", pdf)
+        .and_then(Object::as_array)
+        .ok()?
+        .iter()
+        .map(|value| value.as_float().ok())
+        .collect::<Option<_>>()?;
+    let [x0, y0, x1, y1] = values[..] else {
+        return None;
+    };
+    Some([x0.min(x1), y0.min(y1), x0.max(x1), y0.max(y1)])
+}
+
+/// Text of a PDF text string: UTF-16 or UTF-8 after their byte order mark, else
+/// PDFDocEncoding read as Latin-1
+pub(crate) fn text_string(bytes: &[u8]) -> String {
+    if let Some(utf16) = bytes.strip_prefix(&[0xFE, 0xFF]) {
+        let units: Vec<u16> = utf16
+            .chunks_exact(2)
+            .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+            .collect();
+        String::from_utf16_lossy(&units)
+    } else if let Some(utf8) = bytes.strip_prefix(&[0xEF, 0xBB, 0xBF]) {
+        String::from_utf8_lossy(utf8).into_owned()
+    } else {
+        bytes.iter().map(|byte| *byte as char).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_string() {
+        assert_eq!(text_string(b"Caf\xe9"), "Café");
+        assert_eq!(text_string(b"\xFE\xFF\x00N\x00o\x27\x13"), "No✓");
+    }
+}
@@ -0,0 +1,246 @@
+//! Password encryption of PDF documents with the standard security handler.
+//!
+//! Generated documents are encrypted with AES-256 (revision 6 of ISO 32000-2). Parsed
+//! documents are decrypted from AES-256, the older RC4 schemes are left to lopdf.
+
+use aes::cipher::consts::U16;
+use aes::cipher::{Block, BlockDecrypt, BlockEncrypt, BlockSizeUser, KeyInit};
+use aes::{Aes128, Aes256};
+use anyhow::{anyhow, bail};
+use lopdf::{dictionary, Document as PdfDocument, Object, ObjectId, StringFormat};
+use sha2::{Digest, Sha256, Sha384, Sha512};
+
+use crate::core::PdfEncryption;
+
+/// Permission to print the document, in low and high quality
+const PRINT: i32 = 1 << 2 | 1 << 11;
+/// Permission to copy and extract the text and images
+const COPY: i32 = 1 << 4;
+
+/// Encrypts the strings and streams of the document for the passwords and permissions
+pub fn encrypt(pdf: &mut PdfDocument, encryption: &PdfEncryption) -> anyhow::Result<()> {
+    let file_key: [u8; 32] = random()?;
+    let user_password = password_bytes(&encryption.user_password);
+    // without owner password the permissions could be lifted by anyone
+    let owner_password = if encryption.owner_password.is_empty() {
+        random::<32>()?.to_vec()
+    } else {
+        password_bytes(&encryption.owner_password)
+    };
+
+    let user_salts: [u8; 16] = random()?;
+    let mut user = hash(&user_password, &user_salts[..8], &[])?;
+    user.extend_from_slice(&user_salts);
+    let user_key = hash(&user_password, &user_salts[8..], &[])?;
+    let user_encrypted = cbc::<Aes256>(&user_key, &[0; 16], &file_key, true)?;
+
+    let owner_salts: [u8; 16] = random()?;
+    let mut owner = hash(&owner_password, &owner_salts[..8], &user)?;
+    owner.extend_from_slice(&owner_salts);
+    let owner_key = hash(&owner_password, &owner_salts[8..], &user)?;
+    let owner_encrypted = cbc::<Aes256>(&owner_key, &[0; 16], &file_key, true)?;
+
+    let mut permissions: i32 = -4;
+    if encryption.no_print {
+        permissions &= !PRINT;
+    }
+    if encryption.no_copy {
+        permissions &= !COPY;
+    }
+    let mut perms = [0u8; 16];
+    perms[..4].copy_from_slice(&permissions.to_le_bytes());
+    perms[4..8].fill(0xFF);
+    perms[8..12].copy_from_slice(b"Tadb");
+    perms[12..].copy_from_slice(&random::<4>()?);
+    let perms = cbc::<Aes256>(&file_key, &[0; 16], &perms, true)?;
+
+    let ids: Vec<ObjectId> = pdf.objects.keys().copied().collect();
+    for id in ids {
+        if let Some(object) = pdf.objects.get_mut(&id) {
+            crypt_object(object, &file_key, true)?;
+        }
+    }
+
+    let hex = |bytes: Vec<u8>| Object::String(bytes, StringFormat::Hexadecimal);
+    let encrypt_id = pdf.add_object(dictionary! {
+        "Filter" => "Standard",
+        "V" => 5,
+        "R" => 6,
+        "Length" => 256,
+        "CF" => dictionary! {
+            "StdCF" => dictionary! {
+                "CFM" => "AESV3",
+                "AuthEvent" => "DocOpen",
+                "Length" => 32,
+            },
+        },
+        "StmF" => "StdCF",
+        "StrF" => "StdCF",
+        "O" => hex(owner),
+        "U" => hex(user),
+        "OE" => hex(owner_encrypted),
+        "UE" => hex(user_encrypted),
+        "P" => permissions,
+        "Perms" => hex(perms),
+        "EncryptMetadata" => true,
+    });
+    pdf.trailer.set("Encrypt", encrypt_id);
+    Ok(())
+}
+
+/// Decrypts the document with the user or the owner password, an empty password opens
+/// the documents only protected against printing or copying
+pub fn decrypt(pdf: &mut PdfDocument, password: &str) -> anyhow::Result<()> {
+    let encrypt_id = pdf.trailer.get(b"Encrypt")?.as_reference()?;
+    let encrypt = pdf.get_dictionary(encrypt_id)?;
+    if encrypt.get(b"V")?.as_i64()? != 5 {
+        return pdf
+            .decrypt(password)
+            .map_err(|error| anyhow!("Cannot decrypt the PDF: {}", error));
+    }
+    let value = |key: &[u8], length: usize| -> anyhow::Result<Vec<u8>> {
+        let value = encrypt.get(key)?.as_str()?;
+        if value.len() < length {
+            bail!("Invalid encryption dictionary");
+        }
+        Ok(value.to_vec())
+    };
+    let (user, owner) = (value(b"U", 48)?, value(b"O", 48)?);
+    let password = password_bytes(password);
+    let file_key = if hash(&password, &user[32..40], &[])? == user[..32] {
+        let key = hash(&password, &user[40..48], &[])?;
+        cbc::<Aes256>(&key, &[0; 16], &value(b"UE", 32)?[..32], false)?
+    } else if hash(&password, &owner[32..40], &user[..48])? == owner[..32] {
+        let key = hash(&password, &owner[40..48], &user[..48])?;
+        cbc::<Aes256>(&key, &[0; 16], &value(b"OE", 32)?[..32], false)?
+    } else {
+        bail!("Incorrect password of the encrypted PDF");
+    };
+    let metadata_encrypted = encrypt
+        .get(b"EncryptMetadata")
+        .and_then(Object::as_bool)
+        .unwrap_or(true);
+
+    for (id, object) in pdf.objects.iter_mut() {
+        if *id == encrypt_id || (!metadata_encrypted && object.type_name().ok() == Some("Metadata"))
+        {
+            continue;
+        }
+        crypt_object(object, &file_key, false)?;
+    }
+    pdf.trailer.remove(b"Encrypt");
+    pdf.objects.remove(&encrypt_id);
+    Ok(())
+}
+
+/// Encrypts or decrypts the strings and the stream of an object, the IV is the first block
+fn crypt_object(object: &mut Object, key: &[u8], encrypt: bool) -> anyhow::Result<()> {
+    let crypt = |data: &[u8]| -> anyhow::Result<Vec<u8>> {
+        if encrypt {
+            let iv: [u8; 16] = random()?;
+            let mut padded = data.to_vec();
+            let padding = 16 - data.len() % 16;
+            padded.extend(std::iter::repeat_n(padding as u8, padding));
+            Ok([iv.to_vec(), cbc::<Aes256>(key, &iv, &padded, true)?].concat())
+        } else {
+            if data.len() < 32 || !data.len().is_multiple_of(16) {
+                bail!("Invalid length of encrypted data");
+            }
+            let mut plain = cbc::<Aes256>(key, &data[..16], &data[16..], false)?;
+            let padding = *plain.last().unwrap_or(&0) as usize;
+            if !(1..=16).contains(&padding) {
+                bail!("Invalid padding of encrypted data");
+            }
+            plain.truncate(plain.len() - padding);
+            Ok(plain)
+        }
+    };
+    match object {
+        Object::String(text, _) => *text = crypt(text)?,
+        Object::Array(array) => {
+            for object in array {
+                crypt_object(object, key, encrypt)?;
+            }
+        }
+        Object::Dictionary(dictionary) => {
+            for (_, object) in dictionary.iter_mut() {
+                crypt_object(object, key, encrypt)?;
+            }
+        }
+        Object::Stream(stream) => {
+            for (_, object) in stream.dict.iter_mut() {
+                crypt_object(object, key, encrypt)?;
+            }
+            let content = crypt(&stream.content)?;
+            stream.set_content(content);
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Hash of a password (algorithm 2.B of ISO 32000-2)
+fn hash(password: &[u8], salt: &[u8], user: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let mut k = Sha256::new()
+        .chain_update(password)
+        .chain_update(salt)
+        .chain_update(user)
+        .finalize()
+        .to_vec();
+    let mut round = 0;
+    loop {
+        let k1 = [password, &k, user].concat().repeat(64);
+        let e = cbc::<Aes128>(&k[..16], &k[16..32], &k1, true)?;
+        k = match e[..16].iter().map(|byte| *byte as u32).sum::<u32>() % 3 {
+            0 => Sha256::digest(&e).to_vec(),
+            1 => Sha384::digest(&e).to_vec(),
+            _ => Sha512::digest(&e).to_vec(),
+        };
+        round += 1;
+        if round >= 64 && *e.last().unwrap_or(&0) as u32 <= round - 32 {
+            break;
+        }
+    }
+    k.truncate(32);
+    Ok(k)
+}
+
+/// AES in CBC mode of data made of whole blocks
+fn cbc<C>(key: &[u8], iv: &[u8], data: &[u8], encrypt: bool) -> anyhow::Result<Vec<u8>>
+where
+    C: BlockEncrypt + BlockDecrypt + BlockSizeUser<BlockSize = U16> + KeyInit,
+{
+    let cipher = C::new_from_slice(key).map_err(|_| anyhow!("Invalid encryption key"))?;
+    let mut previous = Block::<C>::clone_from_slice(iv);
+    let mut output = Vec::with_capacity(data.len());
+    for chunk in data.chunks_exact(16) {
+        let mut block = Block::<C>::clone_from_slice(chunk);
+        if encrypt {
+            block.iter_mut().zip(&previous).for_each(|(a, b)| *a ^= b);
+            cipher.encrypt_block(&mut block);
+            previous = block;
+        } else {
+            let input = block;
+            cipher.decrypt_block(&mut block);
+            block.iter_mut().zip(&previous).for_each(|(a, b)| *a ^= b);
+            previous = input;
+        }
+        output.extend_from_slice(&block);
+    }
+    Ok(output)
+}
+
+/// UTF-8 password limited to the 127 bytes read by the PDF readers
+fn password_bytes(password: &str) -> Vec<u8> {
+    let mut end = password.len().min(127);
+    while !password.is_char_boundary(end) {
+        end -= 1;
+    }
+    password.as_bytes()[..end].to_vec()
+}
+
+fn random<const N: usize>() -> anyhow::Result<[u8; N]> {
+    let mut bytes = [0u8; N];
+    getrandom::getrandom(&mut bytes).map_err(|error| anyhow!("No random source: {}", error))?;
+    Ok(bytes)
+}
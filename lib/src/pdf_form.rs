@@ -0,0 +1,924 @@
+//! Fields of the AcroForm of PDF documents: read as [`FormField`]s, filled with new values
+//! and flattened into the content of the pages.
+//!
+//! The appearance of a filled text or choice field is written again in the font, size and
+//! color of its default appearance, on one line or on the lines of a multiline text.
+
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{anyhow, bail};
+use lopdf::{dictionary, Dictionary, Document as PdfDocument, Object, ObjectId, Stream};
+
+use crate::core::{FormField, FormFieldKind};
+use crate::pdf_annotations::{rect, text_string};
+
+/// Nesting of the field tree followed
+const MAX_DEPTH: usize = 32;
+
+// flags of the fields
+const READ_ONLY: i64 = 1;
+const REQUIRED: i64 = 1 << 1;
+const MULTILINE: i64 = 1 << 12;
+const RADIO: i64 = 1 << 15;
+const PUSH_BUTTON: i64 = 1 << 16;
+const COMBO: i64 = 1 << 17;
+
+/// Flag of the hidden annotations
+const HIDDEN: i64 = 1 << 1;
+
+/// Size of the text of the fields with an automatic font size and several lines
+const AUTO_FONT_SIZE: f32 = 12.0;
+
+/// Field of the form with its widget annotations, the field itself when they are merged
+struct Node {
+    field: FormField,
+    id: ObjectId,
+    widgets: Vec<ObjectId>,
+    /// Font, size and color operators of the text
+    appearance: String,
+    /// 0 left aligned, 1 centered, 2 right aligned
+    quadding: i64,
+    multiline: bool,
+}
+
+/// Entries the fields inherit from their parents
+#[derive(Clone, Default)]
+struct Inherited {
+    field_type: Option<String>,
+    flags: i64,
+    value: Option<Object>,
+    appearance: String,
+    quadding: i64,
+    max_length: Option<usize>,
+}
+
+/// Fields of the form of the document, none when it has no form
+pub fn read(pdf: &PdfDocument) -> Vec<FormField> {
+    nodes(pdf).into_iter().map(|node| node.field).collect()
+}
+
+/// Sets the values of the fields given, failing on unknown and read-only fields, then draws
+/// the fields on the pages and removes the form when flattened
+pub fn fill(pdf: &mut PdfDocument, fields: &[FormField], flatten: bool) -> anyhow::Result<()> {
+    if pdf.is_encrypted() {
+        bail!("Cannot fill the form of an encrypted PDF");
+    }
+    let nodes = nodes(pdf);
+    for field in fields {
+        let Some(node) = nodes.iter().find(|node| node.field.name == field.name) else {
+            bail!("No form field {}", field.name);
+        };
+        if field.value == node.field.value {
+            continue;
+        }
+        if node.field.read_only {
+            bail!("Form field {} is read-only", field.name);
+        }
+        match node.field.kind {
+            FormFieldKind::CheckBox | FormFieldKind::RadioButton => {
+                set(
+                    pdf,
+                    node.id,
+                    "V",
+                    Object::Name(field.value.clone().into_bytes()),
+                )?;
+                for widget in &node.widgets {
+                    let state = if states(pdf, *widget).contains(&field.value) {
+                        field.value.as_str()
+                    } else {
+                        "Off"
+                    };
+                    set(pdf, *widget, "AS", Object::Name(state.as_bytes().to_vec()))?;
+                }
+            }
+            FormFieldKind::Text | FormFieldKind::ComboBox | FormFieldKind::ListBox => {
+                set(pdf, node.id, "V", text_object(&field.value))?;
+                // indexes of the selected options
+                pdf.get_dictionary_mut(node.id)?.remove(b"I");
+                let text = option_text(pdf, node.id, &field.value);
+                for widget in &node.widgets {
+                    let top = node.multiline || node.field.kind == FormFieldKind::ListBox;
+                    let appearance = appearance(pdf, *widget, node, &text, top)?;
+                    let appearance = pdf.add_object(appearance);
+                    set(pdf, *widget, "AP", dictionary! { "N" => appearance }.into())?;
+                }
+            }
+            FormFieldKind::Signature => bail!("Cannot fill the signature field {}", field.name),
+        }
+    }
+    if flatten {
+        flatten_form(pdf, &nodes)?;
+    }
+    Ok(())
+}
+
+fn nodes(pdf: &PdfDocument) -> Vec<Node> {
+    let Some(form) = acro_form(pdf) else {
+        return Vec::new();
+    };
+    let mut pages = HashMap::new();
+    for (index, page_id) in pdf.page_iter().enumerate() {
+        let annots = pdf
+            .get_dictionary(page_id)
+            .and_then(|page| page.get_deref(b"Annots", pdf))
+            .and_then(Object::as_array);
+        for annot in annots.into_iter().flatten() {
+            if let Ok(id) = annot.as_reference() {
+                pages.entry(id).or_insert(index + 1);
+            }
+        }
+    }
+    let inherited = Inherited {
+        appearance: string(pdf, form, b"DA"),
+        quadding: form
+            .get_deref(b"Q", pdf)
+            .and_then(Object::as_i64)
+            .unwrap_or_default(),
+        ..Default::default()
+    };
+    let mut reader = Reader {
+        pdf,
+        pages,
+        visited: HashSet::new(),
+        nodes: Vec::new(),
+    };
+    let fields = form
+        .get_deref(b"Fields", pdf)
+        .and_then(Object::as_array)
+        .map(|fields| fields.as_slice())
+        .unwrap_or_default();
+    for field in fields {
+        if let Ok(id) = field.as_reference() {
+            reader.field(id, "", &inherited, 0);
+        }
+    }
+    reader.nodes
+}
+
+struct Reader<'a> {
+    pdf: &'a PdfDocument,
+    /// Pages of the widgets, from 1
+    pages: HashMap<ObjectId, usize>,
+    visited: HashSet<ObjectId>,
+    nodes: Vec<Node>,
+}
+
+impl<'a> Reader<'a> {
+    fn field(&mut self, id: ObjectId, parent: &str, inherited: &Inherited, depth: usize) {
+        if depth >= MAX_DEPTH || !self.visited.insert(id) {
+            return;
+        }
+        let pdf = self.pdf;
+        let Ok(dict) = pdf.get_dictionary(id) else {
+            return;
+        };
+        let name = match dict.get_deref(b"T", pdf).and_then(Object::as_str) {
+            Ok(partial) if parent.is_empty() => text_string(partial),
+            Ok(partial) => format!("{}.{}", parent, text_string(partial)),
+            Err(_) => parent.to_string(),
+        };
+        let mut inherited = inherited.clone();
+        if let Ok(field_type) = dict.get_deref(b"FT", pdf).and_then(Object::as_name_str) {
+            inherited.field_type = Some(field_type.to_string());
+        }
+        if let Ok(flags) = dict.get_deref(b"Ff", pdf).and_then(Object::as_i64) {
+            inherited.flags = flags;
+        }
+        if let Ok((_, value)) = dict.get(b"V").and_then(|value| pdf.dereference(value)) {
+            inherited.value = Some(value.clone());
+        }
+        if dict.has(b"DA") {
+            inherited.appearance = string(pdf, dict, b"DA");
+        }
+        if let Ok(quadding) = dict.get_deref(b"Q", pdf).and_then(Object::as_i64) {
+            inherited.quadding = quadding;
+        }
+        if let Ok(max_length) = dict.get_deref(b"MaxLen", pdf).and_then(Object::as_i64) {
+            inherited.max_length = usize::try_from(max_length).ok();
+        }
+
+        // the kids are fields when named, else the widgets of this field
+        let kids: Vec<ObjectId> = dict
+            .get_deref(b"Kids", pdf)
+            .and_then(Object::as_array)
+            .map(|kids| {
+                kids.iter()
+                    .filter_map(|kid| kid.as_reference().ok())
+                    .collect()
+            })
+            .unwrap_or_default();
+        let (fields, widgets): (Vec<ObjectId>, Vec<ObjectId>) = kids.into_iter().partition(|kid| {
+            pdf.get_dictionary(*kid)
+                .is_ok_and(|kid| kid.has(b"T") || kid.has(b"Kids"))
+        });
+        for field in &fields {
+            self.field(*field, &name, &inherited, depth + 1);
+        }
+        if !fields.is_empty() && widgets.is_empty() {
+            return;
+        }
+        let widgets = if widgets.is_empty() {
+            vec![id]
+        } else {
+            widgets
+        };
+
+        let Some(field_type) = &inherited.field_type else {
+            return;
+        };
+        let flags = inherited.flags;
+        let kind = match field_type.as_str() {
+            "Tx" => FormFieldKind::Text,
+            "Btn" if flags & PUSH_BUTTON != 0 => return,
+            "Btn" if flags & RADIO != 0 => FormFieldKind::RadioButton,
+            "Btn" => FormFieldKind::CheckBox,
+            "Ch" if flags & COMBO != 0 => FormFieldKind::ComboBox,
+            "Ch" => FormFieldKind::ListBox,
+            "Sig" => FormFieldKind::Signature,
+            _ => return,
+        };
+        let button = matches!(kind, FormFieldKind::CheckBox | FormFieldKind::RadioButton);
+        let value = match &inherited.value {
+            Some(Object::Name(name)) => String::from_utf8_lossy(name).into_owned(),
+            Some(Object::String(bytes, _)) => text_string(bytes),
+            // the first option selected of a list box
+            Some(Object::Array(values)) => values
+                .first()
+                .and_then(|value| value.as_str().ok())
+                .map(text_string)
+                .unwrap_or_default(),
+            _ if button => "Off".to_string(),
+            _ => String::new(),
+        };
+        let options = if button {
+            let mut options: Vec<String> = Vec::new();
+            for state in widgets.iter().flat_map(|widget| states(pdf, *widget)) {
+                if !options.contains(&state) {
+                    options.push(state);
+                }
+            }
+            options
+        } else {
+            // export values, before the text shown
+            dict.get_deref(b"Opt", pdf)
+                .and_then(Object::as_array)
+                .map(|options| options.as_slice())
+                .unwrap_or_default()
+                .iter()
+                .filter_map(|option| match pdf.dereference(option).ok()?.1 {
+                    Object::Array(pair) => pair.first()?.as_str().ok().map(text_string),
+                    option => option.as_str().ok().map(text_string),
+                })
+                .collect()
+        };
+
+        self.nodes.push(Node {
+            field: FormField {
+                name,
+                kind,
+                value,
+                options,
+                label: string(pdf, dict, b"TU"),
+                read_only: flags & READ_ONLY != 0,
+                required: flags & REQUIRED != 0,
+                max_length: inherited.max_length,
+                page: widgets
+                    .iter()
+                    .find_map(|widget| self.pages.get(widget))
+                    .copied(),
+            },
+            id,
+            widgets,
+            appearance: inherited.appearance,
+            quadding: inherited.quadding,
+            multiline: flags & MULTILINE != 0,
+        });
+    }
+}
+
+fn acro_form(pdf: &PdfDocument) -> Option<&Dictionary> {
+    pdf.catalog()
+        .and_then(|catalog| catalog.get_deref(b"AcroForm", pdf))
+        .and_then(Object::as_dict)
+        .ok()
+}
+
+/// Text of a string entry, empty when missing
+fn string(pdf: &PdfDocument, dict: &Dictionary, key: &[u8]) -> String {
+    dict.get_deref(key, pdf)
+        .and_then(Object::as_str)
+        .map(text_string)
+        .unwrap_or_default()
+}
+
+/// Appearance states of a check box or radio button widget other than `Off`
+fn states(pdf: &PdfDocument, widget: ObjectId) -> Vec<String> {
+    pdf.get_dictionary(widget)
+        .and_then(|widget| widget.get_deref(b"AP", pdf))
+        .and_then(Object::as_dict)
+        .and_then(|appearances| appearances.get_deref(b"N", pdf))
+        .and_then(Object::as_dict)
+        .map(|states| {
+            states
+                .iter()
+                .map(|(state, _)| String::from_utf8_lossy(state).into_owned())
+                .filter(|state| state != "Off")
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Text shown for the value of a choice, the value itself when it is not an export value
+fn option_text(pdf: &PdfDocument, field: ObjectId, value: &str) -> String {
+    pdf.get_dictionary(field)
+        .and_then(|field| field.get_deref(b"Opt", pdf))
+        .and_then(Object::as_array)
+        .map(|options| options.as_slice())
+        .unwrap_or_default()
+        .iter()
+        .find_map(|option| match pdf.dereference(option).ok()?.1 {
+            Object::Array(pair) if pair.len() == 2 => {
+                let export = text_string(pair[0].as_str().ok()?);
+                let text = pair[1].as_str().ok().map(text_string);
+                text.filter(|_| export == value)
+            }
+            _ => None,
+        })
+        .unwrap_or_else(|| value.to_string())
+}
+
+fn set(pdf: &mut PdfDocument, id: ObjectId, key: &str, value: Object) -> anyhow::Result<()> {
+    pdf.get_dictionary_mut(id)?.set(key, value);
+    Ok(())
+}
+
+/// PDF text string: PDFDocEncoding for Latin-1 texts, else UTF-16 with byte order mark
+pub(crate) fn text_object(text: &str) -> Object {
+    let bytes = if text
+        .chars()
+        .all(|c| (c as u32) < 0x80 || (0xA0..0x100).contains(&(c as u32)))
+    {
+        text.chars().map(|c| c as u8).collect()
+    } else {
+        let mut bytes = vec![0xFE, 0xFF];
+        bytes.extend(text.encode_utf16().flat_map(u16::to_be_bytes));
+        bytes
+    };
+    Object::string_literal(bytes)
+}
+
+/// Normal appearance of a text or choice widget showing the value, its lines from the top
+/// when `top` else one line centered vertically
+fn appearance(
+    pdf: &PdfDocument,
+    widget: ObjectId,
+    node: &Node,
+    value: &str,
+    top: bool,
+) -> anyhow::Result<Stream> {
+    let dict = pdf.get_dictionary(widget)?;
+    let [x0, y0, x1, y1] = rect(pdf, dict).ok_or_else(|| anyhow!("Widget without Rect"))?;
+    let (width, height) = (x1 - x0, y1 - y0);
+    let appearance = match string(pdf, dict, b"DA") {
+        appearance if appearance.is_empty() => node.appearance.clone(),
+        appearance => appearance,
+    };
+    let quadding = dict
+        .get_deref(b"Q", pdf)
+        .and_then(Object::as_i64)
+        .unwrap_or(node.quadding);
+
+    // "/Helv 0 Tf 0 g": the font and size, the other operators set the color
+    let tokens: Vec<&str> = appearance.split_whitespace().collect();
+    let (font, size, color) = match tokens.iter().position(|token| *token == "Tf") {
+        Some(index) if index >= 2 => (
+            tokens[index - 2].trim_start_matches('/').to_string(),
+            tokens[index - 1].parse::<f32>().unwrap_or_default(),
+            [&tokens[..index - 2], &tokens[index + 1..]]
+                .concat()
+                .join(" "),
+        ),
+        _ => ("Helv".to_string(), 0.0, tokens.join(" ")),
+    };
+    let size = match size {
+        size if size > 0.0 => size,
+        _ if top => AUTO_FONT_SIZE,
+        _ => (height * 0.7).clamp(4.0, AUTO_FONT_SIZE),
+    };
+    let font_object = acro_form(pdf)
+        .and_then(|form| form.get_deref(b"DR", pdf).ok())
+        .and_then(|resources| resources.as_dict().ok())
+        .and_then(|resources| resources.get_deref(b"Font", pdf).ok())
+        .and_then(|fonts| fonts.as_dict().ok())
+        .and_then(|fonts| fonts.get(font.as_bytes()).ok())
+        .cloned()
+        .unwrap_or_else(|| {
+            dictionary! {
+                "Type" => "Font",
+                "Subtype" => "Type1",
+                "BaseFont" => "Helvetica",
+                "Encoding" => "WinAnsiEncoding",
+            }
+            .into()
+        });
+    // widths in thousandths of the size, half of it when the font has none
+    let widths = pdf
+        .dereference(&font_object)
+        .ok()
+        .and_then(|(_, font)| font.as_dict().ok());
+    let first_char = widths
+        .and_then(|font| font.get(b"FirstChar").and_then(Object::as_i64).ok())
+        .unwrap_or_default();
+    let widths: Vec<f32> = widths
+        .and_then(|font| {
+            font.get_deref(b"Widths", pdf)
+                .and_then(Object::as_array)
+                .ok()
+        })
+        .map(|widths| {
+            widths
+                .iter()
+                .map(|width| width.as_float().unwrap_or(500.0))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let lines: Vec<String> = if top {
+        value.lines().map(str::to_string).collect()
+    } else {
+        vec![value.replace(['\r', '\n'], " ")]
+    };
+    let mut content = format!(
+        "/Tx BMC\nq\n1 1 {} {} re W n\nBT\n{} /{} {} Tf\n",
+        width - 2.0,
+        height - 2.0,
+        color,
+        font,
+        size
+    );
+    for (index, line) in lines.iter().enumerate() {
+        let bytes: Vec<u8> = line
+            .chars()
+            .map(|c| match c as u32 {
+                code @ (0x20..=0x7E | 0xA0..=0xFF) => code as u8,
+                _ => b'?',
+            })
+            .collect();
+        let line_width: f32 = bytes
+            .iter()
+            .map(|byte| {
+                let index = *byte as i64 - first_char;
+                usize::try_from(index)
+                    .ok()
+                    .and_then(|index| widths.get(index))
+                    .copied()
+                    .unwrap_or(500.0)
+            })
+            .sum::<f32>()
+            * size
+            / 1000.0;
+        let x = match quadding {
+            1 => (width - line_width) / 2.0,
+            2 => width - 2.0 - line_width,
+            _ => 2.0,
+        };
+        let y = if top {
+            height - 2.0 - size * (0.9 + 1.15 * index as f32)
+        } else {
+            (height - size) / 2.0 + size * 0.22
+        };
+        let mut text = Vec::new();
+        for byte in bytes {
+            if matches!(byte, b'(' | b')' | b'\\') {
+                text.push(b'\\');
+            }
+            text.push(byte);
+        }
+        content.push_str(&format!(
+            "1 0 0 1 {} {} Tm\n({}) Tj\n",
+            x,
+            y,
+            // Latin-1 bytes kept as they are
+            text.iter().map(|byte| *byte as char).collect::<String>()
+        ));
+    }
+    content.push_str("ET\nQ\nEMC\n");
+
+    let dict = dictionary! {
+        "Type" => "XObject",
+        "Subtype" => "Form",
+        "BBox" => vec![0.into(), 0.into(), width.into(), height.into()],
+        "Resources" => dictionary! {
+            "Font" => dictionary! { font.as_str() => font_object },
+        },
+    };
+    let bytes: Vec<u8> = content.chars().map(|c| c as u8).collect();
+    Ok(Stream::new(dict, bytes))
+}
+
+/// Draws the visible widgets of the fields with their normal appearance in the content of
+/// their pages, then removes them and the form
+fn flatten_form(pdf: &mut PdfDocument, nodes: &[Node]) -> anyhow::Result<()> {
+    let widgets: HashSet<ObjectId> = nodes
+        .iter()
+        .flat_map(|node| node.widgets.iter().copied())
+        .collect();
+    let page_ids: Vec<ObjectId> = pdf.page_iter().collect();
+    for page_id in page_ids {
+        let page = pdf.get_dictionary(page_id)?;
+        let annots: Vec<Object> = page
+            .get_deref(b"Annots", pdf)
+            .and_then(Object::as_array)
+            .cloned()
+            .unwrap_or_default();
+        let (flattened, annots): (Vec<Object>, Vec<Object>) =
+            annots.into_iter().partition(|annot| {
+                annot
+                    .as_reference()
+                    .is_ok_and(|annot| widgets.contains(&annot))
+            });
+        if flattened.is_empty() {
+            continue;
+        }
+
+        let (direct, inherited) = pdf.get_page_resources(page_id);
+        let mut resources = match (direct, inherited.first()) {
+            (Some(resources), _) => resources.clone(),
+            (None, Some(id)) => pdf.get_dictionary(*id)?.clone(),
+            (None, None) => Dictionary::new(),
+        };
+        let mut xobjects = resources
+            .get_deref(b"XObject", pdf)
+            .and_then(Object::as_dict)
+            .cloned()
+            .unwrap_or_default();
+        let mut contents: Vec<Object> = match page.get(b"Contents") {
+            Ok(Object::Array(contents)) => contents.clone(),
+            Ok(contents) => vec![contents.clone()],
+            Err(_) => Vec::new(),
+        };
+
+        let mut drawing = String::from("Q\n");
+        let mut forms = Vec::new();
+        for widget in flattened
+            .iter()
+            .filter_map(|annot| annot.as_reference().ok())
+        {
+            let dict = pdf.get_dictionary(widget)?;
+            if dict.get(b"F").and_then(Object::as_i64).unwrap_or_default() & HIDDEN != 0 {
+                continue;
+            }
+            let Some(appearance) = normal_appearance(pdf, dict) else {
+                continue;
+            };
+            let Some([x0, y0, x1, y1]) = rect(pdf, dict) else {
+                continue;
+            };
+            let form = pdf.get_object(appearance)?.as_stream()?;
+            let numbers = |key: &[u8]| -> Option<Vec<f32>> {
+                form.dict
+                    .get_deref(key, pdf)
+                    .and_then(Object::as_array)
+                    .ok()?
+                    .iter()
+                    .map(|number| number.as_float().ok())
+                    .collect()
+            };
+            let bbox = numbers(b"BBox").unwrap_or_else(|| vec![0.0, 0.0, x1 - x0, y1 - y0]);
+            let [a, b, c, d, e, f] = numbers(b"Matrix")
+                .and_then(|matrix| matrix.try_into().ok())
+                .unwrap_or([1.0, 0.0, 0.0, 1.0, 0.0, 0.0]);
+            let [bx0, by0, bx1, by1] = bbox[..] else {
+                continue;
+            };
+            // the box of the appearance transformed by its matrix is fit into the rectangle
+            let corners = [(bx0, by0), (bx1, by0), (bx0, by1), (bx1, by1)]
+                .map(|(x, y)| (a * x + c * y + e, b * x + d * y + f));
+            let min_x = corners.iter().map(|(x, _)| *x).fold(f32::MAX, f32::min);
+            let max_x = corners.iter().map(|(x, _)| *x).fold(f32::MIN, f32::max);
+            let min_y = corners.iter().map(|(_, y)| *y).fold(f32::MAX, f32::min);
+            let max_y = corners.iter().map(|(_, y)| *y).fold(f32::MIN, f32::max);
+            if max_x <= min_x || max_y <= min_y {
+                continue;
+            }
+            let scale_x = (x1 - x0) / (max_x - min_x);
+            let scale_y = (y1 - y0) / (max_y - min_y);
+
+            let mut index = xobjects.len() + 1;
+            let mut name = format!("Fm{}", index);
+            while xobjects.has(name.as_bytes()) {
+                index += 1;
+                name = format!("Fm{}", index);
+            }
+            xobjects.set(name.as_str(), appearance);
+            drawing.push_str(&format!(
+                "q {} 0 0 {} {} {} cm /{} Do Q\n",
+                scale_x,
+                scale_y,
+                x0 - min_x * scale_x,
+                y0 - min_y * scale_y,
+                name
+            ));
+            forms.push(appearance);
+        }
+        for form in forms {
+            let dict = &mut pdf.get_object_mut(form)?.as_stream_mut()?.dict;
+            dict.set("Type", "XObject");
+            dict.set("Subtype", "Form");
+        }
+
+        // the content of the page is isolated so that its state does not change the fields
+        let start = pdf.add_object(Stream::new(Dictionary::new(), b"q\n".to_vec()));
+        let end = pdf.add_object(Stream::new(Dictionary::new(), drawing.into_bytes()));
+        contents.insert(0, start.into());
+        contents.push(end.into());
+        resources.set("XObject", xobjects);
+        let page = pdf.get_dictionary_mut(page_id)?;
+        page.set("Resources", resources);
+        page.set("Contents", contents);
+        if annots.is_empty() {
+            page.remove(b"Annots");
+        } else {
+            page.set("Annots", annots);
+        }
+    }
+    let catalog_id = pdf.trailer.get(b"Root")?.as_reference()?;
+    pdf.get_dictionary_mut(catalog_id)?.remove(b"AcroForm");
+    Ok(())
+}
+
+/// Normal appearance stream of a widget, the one of its state for buttons
+fn normal_appearance(pdf: &PdfDocument, widget: &Dictionary) -> Option<ObjectId> {
+    let normal = widget
+        .get_deref(b"AP", pdf)
+        .and_then(Object::as_dict)
+        .ok()?
+        .get(b"N")
+        .ok()?;
+    if let Ok(id) = normal.as_reference() {
+        if pdf.get_object(id).and_then(Object::as_stream).is_ok() {
+            return Some(id);
+        }
+    }
+    let state = widget.get(b"AS").and_then(Object::as_name).ok()?;
+    pdf.dereference(normal)
+        .ok()?
+        .1
+        .as_dict()
+        .ok()?
+        .get(state)
+        .ok()?
+        .as_reference()
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::TransformerTrait;
+    use crate::pdf::Transformer;
+    use bytes::Bytes;
+    use lopdf::content::{Content, Operation};
+
+    /// Page with a text field, a check box, two radio buttons and a combo box
+    fn form_pdf() -> anyhow::Result<Bytes> {
+        let mut pdf = PdfDocument::with_version("1.7");
+        let pages_id = pdf.new_object_id();
+        let page_id = pdf.new_object_id();
+        let font_id = pdf.add_object(dictionary! {
+            "Type" => "Font",
+            "Subtype" => "Type1",
+            "BaseFont" => "Helvetica",
+            "Encoding" => "WinAnsiEncoding",
+        });
+        let content = Content {
+            operations: vec![
+                Operation::new("BT", vec![]),
+                Operation::new("Tf", vec!["F1".into(), 12.into()]),
+                Operation::new("Td", vec![50.into(), 750.into()]),
+                Operation::new("Tj", vec![Object::string_literal("Application")]),
+                Operation::new("ET", vec![]),
+            ],
+        };
+        let content_id = pdf.add_object(Stream::new(dictionary! {}, content.encode()?));
+        let state = |pdf: &mut PdfDocument, fill: &str| {
+            pdf.add_object(Stream::new(
+                dictionary! {
+                    "Type" => "XObject",
+                    "Subtype" => "Form",
+                    "BBox" => vec![0.into(), 0.into(), 12.into(), 12.into()],
+                },
+                format!("{} 0 0 rg 0 0 12 12 re f", fill).into_bytes(),
+            ))
+        };
+        let (yes, off) = (state(&mut pdf, "1"), state(&mut pdf, "0"));
+        let widget = |rect: [i64; 4]| {
+            dictionary! {
+                "Type" => "Annot",
+                "Subtype" => "Widget",
+                "P" => page_id,
+                "Rect" => rect.iter().map(|n| Object::from(*n)).collect::<Vec<_>>(),
+            }
+        };
+
+        let mut name = widget([150, 700, 350, 720]);
+        name.set("FT", "Tx");
+        name.set("T", Object::string_literal("name"));
+        name.set("TU", Object::string_literal("Full name"));
+        name.set("V", Object::string_literal("Ada"));
+        name.set("MaxLen", 20);
+        let name = pdf.add_object(name);
+
+        let mut agree = widget([150, 660, 162, 672]);
+        agree.set("FT", "Btn");
+        agree.set("T", Object::string_literal("agree"));
+        agree.set("V", "Off");
+        agree.set("AS", "Off");
+        agree.set(
+            "AP",
+            dictionary! { "N" => dictionary! { "Yes" => yes, "Off" => off } },
+        );
+        let agree = pdf.add_object(agree);
+
+        let color_id = pdf.new_object_id();
+        let mut radios = Vec::new();
+        for (index, color) in ["Red", "Blue"].into_iter().enumerate() {
+            let mut radio = widget([150 + 30 * index as i64, 620, 162 + 30 * index as i64, 632]);
+            radio.set("Parent", color_id);
+            radio.set("AS", if color == "Red" { color } else { "Off" });
+            radio.set(
+                "AP",
+                dictionary! { "N" => dictionary! { color => yes, "Off" => off } },
+            );
+            radios.push(Object::from(pdf.add_object(radio)));
+        }
+        let color = dictionary! {
+            "FT" => "Btn",
+            "Ff" => RADIO,
+            "T" => Object::string_literal("color"),
+            "V" => "Red",
+            "Kids" => radios.clone(),
+        };
+        pdf.objects.insert(color_id, color.into());
+
+        let address_id = pdf.new_object_id();
+        let mut country = widget([150, 580, 350, 600]);
+        country.set("FT", "Ch");
+        country.set("Ff", COMBO);
+        country.set("T", Object::string_literal("country"));
+        country.set("Parent", address_id);
+        country.set(
+            "Opt",
+            vec![
+                Object::string_literal("France"),
+                vec![
+                    Object::string_literal("de"),
+                    Object::string_literal("Germany"),
+                ]
+                .into(),
+            ],
+        );
+        country.set("V", Object::string_literal("France"));
+        let country = pdf.add_object(country);
+        pdf.objects.insert(
+            address_id,
+            dictionary! { "T" => Object::string_literal("address"), "Kids" => vec![country.into()] }
+                .into(),
+        );
+
+        let mut annots: Vec<Object> = vec![name.into(), agree.into()];
+        annots.extend(radios);
+        annots.push(country.into());
+        pdf.objects.insert(
+            page_id,
+            dictionary! {
+                "Type" => "Page",
+                "Parent" => pages_id,
+                "MediaBox" => vec![0.into(), 0.into(), 612.into(), 792.into()],
+                "Contents" => content_id,
+                "Resources" => dictionary! { "Font" => dictionary! { "F1" => font_id } },
+                "Annots" => annots,
+            }
+            .into(),
+        );
+        pdf.objects.insert(
+            pages_id,
+            dictionary! { "Type" => "Pages", "Kids" => vec![page_id.into()], "Count" => 1 }.into(),
+        );
+        let catalog_id = pdf.add_object(dictionary! {
+            "Type" => "Catalog",
+            "Pages" => pages_id,
+            "AcroForm" => dictionary! {
+                "Fields" => vec![name.into(), agree.into(), color_id.into(), address_id.into()],
+                "DA" => Object::string_literal("/Helv 0 Tf 0 g"),
+                "DR" => dictionary! { "Font" => dictionary! { "Helv" => font_id } },
+            },
+        });
+        pdf.trailer.set("Root", catalog_id);
+        let mut bytes = Vec::new();
+        pdf.save_to(&mut bytes)?;
+        Ok(Bytes::from(bytes))
+    }
+
+    #[test]
+    fn test_read() -> anyhow::Result<()> {
+        let document = Transformer::parse(&form_pdf()?)?;
+        let fields = document.form_fields();
+        let summary: Vec<(&str, FormFieldKind, &str)> = fields
+            .iter()
+            .map(|field| (field.name.as_str(), field.kind, field.value.as_str()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("name", FormFieldKind::Text, "Ada"),
+                ("agree", FormFieldKind::CheckBox, "Off"),
+                ("color", FormFieldKind::RadioButton, "Red"),
+                ("address.country", FormFieldKind::ComboBox, "France"),
+            ]
+        );
+        assert_eq!(fields[0].label, "Full name");
+        assert_eq!(fields[0].max_length, Some(20));
+        assert_eq!(fields[0].page, Some(1));
+        assert_eq!(fields[1].options, vec!["Yes"]);
+        assert_eq!(fields[2].options, vec!["Red", "Blue"]);
+        assert_eq!(fields[3].options, vec!["France", "de"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_fill() -> anyhow::Result<()> {
+        let pdf = form_pdf()?;
+        let mut document = Transformer::parse(&pdf)?;
+        document.set_form_value("name", "Grace (Hopper)")?;
+        document.set_form_value("agree", "Yes")?;
+        document.set_form_value("color", "Blue")?;
+        document.set_form_value("address.country", "de")?;
+        assert!(document.set_form_value("color", "Green").is_err());
+        assert!(document.set_form_value("name", &"x".repeat(21)).is_err());
+        assert!(document.set_form_value("missing", "").is_err());
+
+        let filled = Transformer::fill_form(&pdf, document.form_fields(), false)?;
+        let parsed = Transformer::parse(&filled)?;
+        assert_eq!(parsed.form_fields(), document.form_fields());
+        let filled = PdfDocument::load_mem(&filled)?;
+        let states: Vec<String> = filled
+            .objects
+            .values()
+            .filter_map(|object| object.as_dict().ok())
+            .filter_map(|dict| dict.get(b"AS").and_then(Object::as_name_str).ok())
+            .map(str::to_string)
+            .collect();
+        assert_eq!(states.iter().filter(|state| *state == "Off").count(), 1);
+        assert!(states.contains(&"Yes".to_string()) && states.contains(&"Blue".to_string()));
+        let appearances: Vec<String> = filled
+            .objects
+            .values()
+            .filter_map(|object| object.as_stream().ok())
+            .map(|stream| String::from_utf8_lossy(&stream.content).into_owned())
+            .collect();
+        assert!(appearances
+            .iter()
+            .any(|content| content.contains("/Helv 12 Tf")
+                && content.contains("(Grace \\(Hopper\\)) Tj")));
+        // the text of the option shown, not its export value
+        assert!(appearances
+            .iter()
+            .any(|content| content.contains("(Germany) Tj")));
+
+        let missing = FormField {
+            name: "missing".to_string(),
+            ..Default::default()
+        };
+        assert!(Transformer::fill_form(&pdf, &[missing], false).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_flatten() -> anyhow::Result<()> {
+        let pdf = form_pdf()?;
+        let mut document = Transformer::parse(&pdf)?;
+        document.set_form_value("name", "Grace")?;
+        document.set_form_value("agree", "Yes")?;
+        let flattened = Transformer::fill_form(&pdf, document.form_fields(), true)?;
+
+        let parsed = Transformer::parse(&flattened)?;
+        assert!(parsed.form_fields().is_empty());
+        let text: String = parsed
+            .get_all_elements()
+            .iter()
+            .map(|element| element.plain_text())
+            .collect::<Vec<_>>()
+            .join("\n");
+        assert!(text.contains("Application"), "{}", text);
+        assert!(text.contains("Grace"), "{}", text);
+
+        let flattened = PdfDocument::load_mem(&flattened)?;
+        let page_id = flattened.page_iter().next().unwrap();
+        assert!(!flattened.get_dictionary(page_id)?.has(b"Annots"));
+        let content = String::from_utf8_lossy(&flattened.get_page_content(page_id)?).into_owned();
+        assert!(content.starts_with("q\n"));
+        assert_eq!(content.matches(" Do Q").count(), 4);
+        Ok(())
+    }
+}
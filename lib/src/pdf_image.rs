@@ -0,0 +1,233 @@
+//! Decoding of the image XObjects of PDF documents.
+//!
+//! JPEG images (DCTDecode) are kept as they are, the raster images are converted from
+//! their color space to RGB, with the alpha of their soft mask, and encoded as PNG.
+
+use std::io::Cursor;
+
+use anyhow::{anyhow, bail};
+use bytes::Bytes;
+use image::{DynamicImage, ImageOutputFormat, RgbImage, RgbaImage};
+use lopdf::{Document as PdfDocument, Object, Stream};
+
+use crate::core::{ImageData, ImageDimension};
+
+/// Pixels of the largest image decoded
+const MAX_PIXELS: usize = 100_000_000;
+
+enum ColorSpace {
+    Gray,
+    Rgb,
+    Cmyk,
+    Indexed(Vec<[u8; 3]>),
+}
+
+impl ColorSpace {
+    fn components(&self) -> usize {
+        match self {
+            ColorSpace::Gray | ColorSpace::Indexed(_) => 1,
+            ColorSpace::Rgb => 3,
+            ColorSpace::Cmyk => 4,
+        }
+    }
+
+    /// RGB of the components scaled to 0..=255
+    fn rgb(&self, values: &[u8]) -> [u8; 3] {
+        match (self, values) {
+            (ColorSpace::Gray, [gray, ..]) => [*gray; 3],
+            (ColorSpace::Rgb, [r, g, b, ..]) => [*r, *g, *b],
+            (ColorSpace::Cmyk, [c, m, y, k, ..]) => {
+                let white = |value: u8| ((255 - value as u32) * (255 - *k as u32) / 255) as u8;
+                [white(*c), white(*m), white(*y)]
+            }
+            (ColorSpace::Indexed(palette), [index, ..]) => {
+                palette.get(*index as usize).copied().unwrap_or_default()
+            }
+            _ => [0; 3],
+        }
+    }
+}
+
+/// Image of an image XObject, without size
+pub fn decode(pdf: &PdfDocument, stream: &Stream) -> anyhow::Result<ImageData> {
+    let filters = stream.filters().unwrap_or_default();
+    let (bytes, image_type) = if filters.iter().any(|filter| filter == "DCTDecode") {
+        if filters.len() > 1 {
+            bail!("Unsupported image filters {:?}", filters);
+        }
+        (stream.content.clone(), "jpeg")
+    } else {
+        let (width, height, rgb) = raster(pdf, stream)?;
+        let alpha = stream
+            .dict
+            .get_deref(b"SMask", pdf)
+            .and_then(Object::as_stream)
+            .ok()
+            .and_then(|mask| raster(pdf, mask).ok())
+            .filter(|(mask_width, mask_height, _)| (*mask_width, *mask_height) == (width, height));
+        let image = match alpha {
+            Some((_, _, mask)) => {
+                let rgba = rgb
+                    .chunks_exact(3)
+                    .zip(mask.chunks_exact(3))
+                    .flat_map(|(color, alpha)| [color[0], color[1], color[2], alpha[0]])
+                    .collect();
+                RgbaImage::from_raw(width, height, rgba).map(DynamicImage::ImageRgba8)
+            }
+            None => RgbImage::from_raw(width, height, rgb).map(DynamicImage::ImageRgb8),
+        }
+        .ok_or_else(|| anyhow!("Invalid image size {}x{}", width, height))?;
+        let mut png = Vec::new();
+        image.write_to(&mut Cursor::new(&mut png), ImageOutputFormat::Png)?;
+        (png, "png")
+    };
+    Ok(ImageData::new(
+        Bytes::from(bytes),
+        String::new(),
+        String::new(),
+        image_type.to_string(),
+        String::new(),
+        ImageDimension::default(),
+    ))
+}
+
+/// Width, height and RGB pixels of a raster image
+fn raster(pdf: &PdfDocument, stream: &Stream) -> anyhow::Result<(u32, u32, Vec<u8>)> {
+    let dict = &stream.dict;
+    let number = |key: &[u8]| {
+        dict.get_deref(key, pdf)
+            .and_then(Object::as_i64)
+            .ok()
+            .filter(|value| *value > 0)
+    };
+    let (Some(width), Some(height)) = (number(b"Width"), number(b"Height")) else {
+        bail!("Image without size");
+    };
+    let (width, height) = (width as usize, height as usize);
+    if width.saturating_mul(height) > MAX_PIXELS {
+        bail!("Image of {}x{} pixels too large", width, height);
+    }
+    if dict
+        .get(b"ImageMask")
+        .and_then(Object::as_bool)
+        .unwrap_or(false)
+    {
+        bail!("Stencil masks are painted with the fill color");
+    }
+    let bits = number(b"BitsPerComponent").unwrap_or(8) as usize;
+    if ![1, 2, 4, 8, 16].contains(&bits) {
+        bail!("Unsupported {} bits per component", bits);
+    }
+    let color = color_space(pdf, dict.get(b"ColorSpace").ok())?;
+
+    let data = if stream.filters().map_or(true, |filters| filters.is_empty()) {
+        stream.content.clone()
+    } else {
+        // lopdf leaves the image streams compressed
+        let mut dict = dict.clone();
+        dict.remove(b"Subtype");
+        Stream::new(dict, stream.content.clone())
+            .decompressed_content()
+            .map_err(|error| anyhow!("Cannot decompress the image: {}", error))?
+    };
+    let components = color.components();
+    let row_bytes = (width * components * bits).div_ceil(8);
+    if data.len() < row_bytes * height {
+        bail!("Image data shorter than {}x{} pixels", width, height);
+    }
+    // the Decode array [1 0] inverts the samples
+    let inverted = !matches!(color, ColorSpace::Indexed(_))
+        && dict
+            .get(b"Decode")
+            .and_then(Object::as_array)
+            .ok()
+            .and_then(|decode| decode.first())
+            .and_then(|low| low.as_float().ok())
+            == Some(1.0);
+
+    let max = (1u32 << bits.min(8)) - 1;
+    let mut rgb = Vec::with_capacity(width * height * 3);
+    let mut values = vec![0u8; components];
+    for row in data.chunks_exact(row_bytes).take(height) {
+        let sample = |index: usize| -> u32 {
+            match bits {
+                8 => row[index] as u32,
+                // the high byte is enough for the screen
+                16 => row[index * 2] as u32,
+                _ => {
+                    let bit = index * bits;
+                    (row[bit / 8] as u32 >> (8 - bits - bit % 8)) & max
+                }
+            }
+        };
+        for x in 0..width {
+            for (component, value) in values.iter_mut().enumerate() {
+                let sample = sample(x * components + component);
+                *value = match color {
+                    ColorSpace::Indexed(_) => sample as u8,
+                    _ if inverted => (255 - sample * 255 / max) as u8,
+                    _ => (sample * 255 / max) as u8,
+                };
+            }
+            rgb.extend_from_slice(&color.rgb(&values));
+        }
+    }
+    Ok((width as u32, height as u32, rgb))
+}
+
+fn color_space(pdf: &PdfDocument, object: Option<&Object>) -> anyhow::Result<ColorSpace> {
+    let Some(object) = object else {
+        // the soft masks have no color space
+        return Ok(ColorSpace::Gray);
+    };
+    let (_, object) = pdf.dereference(object)?;
+    let name = |object: Option<&Object>| {
+        object
+            .and_then(|object| object.as_name_str().ok())
+            .unwrap_or_default()
+            .to_string()
+    };
+    let color = match object {
+        Object::Array(array) => match name(array.first()).as_str() {
+            "ICCBased" => {
+                let profile = array
+                    .get(1)
+                    .ok_or_else(|| anyhow!("ICCBased without profile"))?;
+                let (_, profile) = pdf.dereference(profile)?;
+                match profile.as_stream()?.dict.get(b"N").and_then(Object::as_i64) {
+                    Ok(1) => ColorSpace::Gray,
+                    Ok(4) => ColorSpace::Cmyk,
+                    _ => ColorSpace::Rgb,
+                }
+            }
+            "CalGray" => ColorSpace::Gray,
+            "CalRGB" => ColorSpace::Rgb,
+            "Indexed" | "I" => {
+                let base = color_space(pdf, array.get(1))?;
+                let lookup = array
+                    .get(3)
+                    .ok_or_else(|| anyhow!("Indexed without lookup"))?;
+                let lookup = match pdf.dereference(lookup)?.1 {
+                    Object::String(bytes, _) => bytes.clone(),
+                    Object::Stream(stream) => stream
+                        .decompressed_content()
+                        .unwrap_or_else(|_| stream.content.clone()),
+                    _ => bail!("Invalid lookup of an indexed color space"),
+                };
+                let palette = lookup
+                    .chunks_exact(base.components())
+                    .map(|values| base.rgb(values))
+                    .collect();
+                ColorSpace::Indexed(palette)
+            }
+            family => bail!("Unsupported color space {}", family),
+        },
+        object => match name(Some(object)).as_str() {
+            "DeviceGray" | "G" => ColorSpace::Gray,
+            "DeviceRGB" | "RGB" => ColorSpace::Rgb,
+            "DeviceCMYK" | "CMYK" => ColorSpace::Cmyk,
+            family => bail!("Unsupported color space {}", family),
+        },
+    };
+    Ok(color)
+}
@@ -0,0 +1,1829 @@
+//! Layout-aware reading of the text of PDF pages.
+//!
+//! The strings shown by the content streams are placed on the page with the text and
+//! graphics matrices, then gathered in lines by baseline and the lines ordered column by
+//! column. Consecutive lines of the same font size make paragraphs, list items when they
+//! start with a bullet or a number, and headers when their font is larger than the body
+//! text.
+//!
+//! Tables are found before the lines, from the grid of their rules or from rows of
+//! cells aligned in the same columns. The images painted on the pages take their place
+//! among the lines, on the pages without text the OCR engine reads them.
+//!
+//! The text under a link annotation becomes a hyperlink, the text annotations notes of
+//! the paragraphs they are on, and the destinations of the outline and of the internal
+//! links header ids or bookmarks.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use log::warn;
+use lopdf::content::Content;
+use lopdf::{Dictionary, Document as PdfDocument, Object, ObjectId, Stream};
+
+use crate::core::Element::{
+    Attributed, Bookmark, Footnote, Header, Hyperlink, Image, List, Paragraph, Table, Text,
+};
+use crate::core::{
+    header_anchor, Element, ImageData, ImageDimension, ListItem, NumberingStyle, TableCell,
+    TableHeader, TableRow, TextDirection,
+};
+use crate::ocr::Ocr;
+use crate::pdf_annotations::{Comment, Link, PageAnnotations};
+
+type Matrix = [f32; 6];
+
+const IDENTITY: Matrix = [1.0, 0.0, 0.0, 1.0, 0.0, 0.0];
+
+/// Nesting of form XObjects followed
+const MAX_FORM_DEPTH: usize = 8;
+
+/// Gap between two spans of a line, in font sizes, from which they are in two columns
+const COLUMN_GAP: f32 = 2.5;
+
+/// Gap between two cells of a table without rules, in font sizes
+const CELL_GAP: f32 = 1.5;
+
+/// Noncharacters around the text of a link in the lines, `START url SEPARATOR text END`
+/// with the index of its URL
+const LINK_START: char = '\u{FDD0}';
+const LINK_SEPARATOR: char = '\u{FDD1}';
+const LINK_END: char = '\u{FDD2}';
+
+/// Elements of the text and images of all pages, the text of the scanned pages
+/// recognized by `ocr`
+pub fn parse_document(pdf: &PdfDocument, ocr: Option<&Ocr>) -> anyhow::Result<Vec<Element>> {
+    let mut fonts = HashMap::new();
+    let mut images = HashMap::new();
+    let mut blocks = Vec::new();
+    let annotations = crate::pdf_annotations::read(pdf);
+    let no_annotations = PageAnnotations::default();
+    for page_id in pdf.get_pages().into_values() {
+        let resources = page_resources(pdf, page_id);
+        let page = annotations.pages.get(&page_id).unwrap_or(&no_annotations);
+        let mut reader = PageReader {
+            pdf,
+            fonts: &mut fonts,
+            images: &mut images,
+            links: &page.links,
+            spans: Vec::new(),
+            rules: Vec::new(),
+            figures: Vec::new(),
+        };
+        let content = pdf.get_page_content(page_id)?;
+        reader.read(&content, &resources, IDENTITY, 0)?;
+        let mut figures = reader.figures;
+        if let Some(ocr) = ocr.filter(|_| reader.spans.is_empty()) {
+            for figure in &mut figures {
+                if let Some(Inset::Image(image)) = &figure.inset {
+                    let paragraphs = ocr.paragraphs(image)?;
+                    if !paragraphs.is_empty() {
+                        figure.inset = Some(Inset::Recognized(paragraphs));
+                    }
+                }
+            }
+        }
+        let (mut tables, spans) = ruled_tables(reader.spans, &reader.rules);
+        let (aligned, spans) = aligned_tables(spans);
+        tables.extend(aligned);
+        let mut lines = lines(spans, COLUMN_GAP);
+        lines.extend(tables);
+        lines.extend(figures);
+        lines.extend(page.targets.iter().map(|target| Line {
+            x0: target.x,
+            x1: target.x,
+            y: target.y.unwrap_or(f32::MAX),
+            size: 0.0,
+            text: String::new(),
+            inset: Some(Inset::Target {
+                id: target.id.clone(),
+                title: target.title.clone(),
+            }),
+        }));
+        lines.extend(page.comments.iter().map(|comment| Line {
+            x0: comment.x,
+            x1: comment.x,
+            y: comment.y,
+            size: 0.0,
+            text: String::new(),
+            inset: Some(Inset::Comment(comment.clone())),
+        }));
+        blocks.extend(text_blocks(reading_order(lines)));
+    }
+    Ok(elements(blocks, &annotations.urls))
+}
+
+/// Text shown by one string operand, from `x` to `end` on the baseline `y`
+#[derive(Debug, Clone)]
+struct Span {
+    x: f32,
+    end: f32,
+    y: f32,
+    size: f32,
+    text: String,
+}
+
+#[derive(Debug)]
+struct Line {
+    x0: f32,
+    x1: f32,
+    y: f32,
+    size: f32,
+    text: String,
+    /// Table, image or annotation taking the place of one line in the reading order
+    inset: Option<Inset>,
+}
+
+#[derive(Debug)]
+enum Inset {
+    /// Rows of the cells of a table
+    Table(Vec<Vec<String>>),
+    Image(ImageData),
+    /// Paragraphs of the text of a scanned image
+    Recognized(Vec<Element>),
+    /// Destination of the outline or of a link
+    Target {
+        id: String,
+        title: String,
+    },
+    Comment(Comment),
+}
+
+impl Inset {
+    /// Whether it is a point of the page rather than an area
+    fn is_mark(&self) -> bool {
+        matches!(self, Inset::Target { .. } | Inset::Comment(_))
+    }
+}
+
+#[derive(Debug)]
+enum Block {
+    Text {
+        size: f32,
+        lines: Vec<String>,
+        /// Comments on the text
+        notes: Vec<Comment>,
+    },
+    Table {
+        size: f32,
+        rows: Vec<Vec<String>>,
+    },
+    Image(ImageData),
+    Recognized(Vec<Element>),
+    Target {
+        id: String,
+        title: String,
+    },
+    /// Comment outside of the text
+    Comment(Comment),
+}
+
+/// Horizontal or vertical segment drawn on the page
+#[derive(Debug, Clone, Copy)]
+struct Rule {
+    x0: f32,
+    y0: f32,
+    x1: f32,
+    y1: f32,
+}
+
+impl Rule {
+    fn is_horizontal(&self) -> bool {
+        (self.y1 - self.y0).abs() < 0.5 && (self.x1 - self.x0).abs() >= 3.0
+    }
+
+    fn is_vertical(&self) -> bool {
+        (self.x1 - self.x0).abs() < 0.5 && (self.y1 - self.y0).abs() >= 3.0
+    }
+}
+
+/// Font of the shown strings, decoding its codes to text
+struct Font {
+    /// Two bytes codes of the composite (Type0) fonts
+    two_bytes: bool,
+    to_unicode: HashMap<u32, String>,
+    /// Text of the single byte codes of the simple fonts without `ToUnicode`
+    encoding: Vec<String>,
+    /// Widths in thousandths of the font size
+    widths: HashMap<u32, f32>,
+    default_width: f32,
+}
+
+impl Font {
+    fn load(pdf: &PdfDocument, font: &Dictionary) -> Font {
+        let two_bytes = font.get(b"Subtype").and_then(Object::as_name_str).ok() == Some("Type0");
+        let to_unicode = font
+            .get_deref(b"ToUnicode", pdf)
+            .and_then(Object::as_stream)
+            .map(|stream| {
+                cmap(
+                    &stream
+                        .decompressed_content()
+                        .unwrap_or_else(|_| stream.content.clone()),
+                )
+            })
+            .unwrap_or_default();
+        let mut widths = HashMap::new();
+        let mut default_width = 500.0;
+        if two_bytes {
+            let descendant = font
+                .get_deref(b"DescendantFonts", pdf)
+                .and_then(Object::as_array)
+                .ok()
+                .and_then(|fonts| fonts.first())
+                .and_then(|descendant| pdf.dereference(descendant).ok())
+                .and_then(|(_, descendant)| descendant.as_dict().ok());
+            if let Some(descendant) = descendant {
+                default_width = descendant
+                    .get(b"DW")
+                    .and_then(Object::as_float)
+                    .unwrap_or(1000.0);
+                if let Ok(w) = descendant.get_deref(b"W", pdf).and_then(Object::as_array) {
+                    cid_widths(pdf, w, &mut widths);
+                }
+            }
+        } else if let Ok(list) = font.get_deref(b"Widths", pdf).and_then(Object::as_array) {
+            let first = font.get(b"FirstChar").and_then(Object::as_i64).unwrap_or(0);
+            for (index, width) in list.iter().enumerate() {
+                if let Ok((_, width)) = pdf.dereference(width) {
+                    if let Ok(width) = width.as_float() {
+                        widths.insert((first + index as i64) as u32, width);
+                    }
+                }
+            }
+        }
+        let encoding = if two_bytes {
+            vec![]
+        } else {
+            simple_encoding(pdf, font)
+        };
+        Font {
+            two_bytes,
+            to_unicode,
+            encoding,
+            widths,
+            default_width,
+        }
+    }
+
+    fn codes(&self, bytes: &[u8]) -> Vec<u32> {
+        if self.two_bytes {
+            bytes
+                .chunks(2)
+                .map(|pair| pair.iter().fold(0, |code, byte| code << 8 | *byte as u32))
+                .collect()
+        } else {
+            bytes.iter().map(|byte| *byte as u32).collect()
+        }
+    }
+
+    fn text(&self, code: u32) -> &str {
+        match self.to_unicode.get(&code) {
+            Some(text) => text,
+            None => self
+                .encoding
+                .get(code as usize)
+                .map(String::as_str)
+                .unwrap_or_default(),
+        }
+    }
+
+    fn width(&self, code: u32) -> f32 {
+        self.widths
+            .get(&code)
+            .copied()
+            .unwrap_or(self.default_width)
+            / 1000.0
+    }
+}
+
+/// Widths of the `W` array of a CID font: `c [w1 w2 ...]` and `c_first c_last w` entries
+fn cid_widths(pdf: &PdfDocument, w: &[Object], widths: &mut HashMap<u32, f32>) {
+    let mut index = 0;
+    while index + 1 < w.len() {
+        let Ok(first) = w[index].as_i64() else {
+            return;
+        };
+        match pdf.dereference(&w[index + 1]).map(|(_, object)| object) {
+            Ok(Object::Array(list)) => {
+                for (offset, width) in list.iter().enumerate() {
+                    if let Ok(width) = width.as_float() {
+                        widths.insert(first as u32 + offset as u32, width);
+                    }
+                }
+                index += 2;
+            }
+            Ok(last) => {
+                let (Ok(last), Some(Ok(width))) =
+                    (last.as_i64(), w.get(index + 2).map(Object::as_float))
+                else {
+                    return;
+                };
+                for code in first..=last {
+                    widths.insert(code as u32, width);
+                }
+                index += 3;
+            }
+            Err(_) => return,
+        }
+    }
+}
+
+/// Text of the 256 codes of a simple font from its encoding and its `Differences`
+fn simple_encoding(pdf: &PdfDocument, font: &Dictionary) -> Vec<String> {
+    let (base, differences) = match font.get_deref(b"Encoding", pdf) {
+        Ok(Object::Name(name)) => (String::from_utf8_lossy(name).to_string(), None),
+        Ok(Object::Dictionary(encoding)) => (
+            encoding
+                .get(b"BaseEncoding")
+                .and_then(Object::as_name_str)
+                .unwrap_or("StandardEncoding")
+                .to_string(),
+            encoding
+                .get_deref(b"Differences", pdf)
+                .and_then(Object::as_array)
+                .ok(),
+        ),
+        _ => ("StandardEncoding".to_string(), None),
+    };
+    let mut encoding: Vec<String> = (0..=255u8)
+        .map(|code| PdfDocument::decode_text(Some(&base), &[code]))
+        .collect();
+    let mut code = 0;
+    for difference in differences.into_iter().flatten() {
+        match difference {
+            Object::Integer(start) => code = *start as usize,
+            Object::Name(name) => {
+                if let Some(text) = glyph_text(&String::from_utf8_lossy(name)) {
+                    if let Some(entry) = encoding.get_mut(code) {
+                        *entry = text;
+                    }
+                }
+                code += 1;
+            }
+            _ => {}
+        }
+    }
+    encoding
+}
+
+/// Text of a glyph name of the `Differences` of an encoding
+fn glyph_text(name: &str) -> Option<String> {
+    if name.chars().count() == 1 {
+        return Some(name.to_string());
+    }
+    if let Some(hex) = name.strip_prefix("uni") {
+        return u32::from_str_radix(hex.get(..4)?, 16)
+            .ok()
+            .and_then(char::from_u32)
+            .map(String::from);
+    }
+    const DIGITS: [&str; 10] = [
+        "zero", "one", "two", "three", "four", "five", "six", "seven", "eight", "nine",
+    ];
+    if let Some(digit) = DIGITS.iter().position(|digit| *digit == name) {
+        return Some(digit.to_string());
+    }
+    let text = match name {
+        "space" => " ",
+        "period" => ".",
+        "comma" => ",",
+        "colon" => ":",
+        "semicolon" => ";",
+        "hyphen" => "-",
+        "endash" => "–",
+        "emdash" => "—",
+        "quoteleft" => "‘",
+        "quoteright" => "’",
+        "quotedblleft" => "“",
+        "quotedblright" => "”",
+        "quotesingle" => "'",
+        "quotedbl" => "\"",
+        "parenleft" => "(",
+        "parenright" => ")",
+        "exclam" => "!",
+        "question" => "?",
+        "slash" => "/",
+        "bullet" => "•",
+        "fi" => "fi",
+        "fl" => "fl",
+        _ => return None,
+    };
+    Some(text.to_string())
+}
+
+/// Code to text mapping of a `ToUnicode` CMap from its `bfchar` and `bfrange` sections
+fn cmap(data: &[u8]) -> HashMap<u32, String> {
+    #[derive(PartialEq)]
+    enum Token {
+        Hex(Vec<u8>),
+        Word(String),
+        Open,
+        Close,
+    }
+    let mut tokens = Vec::new();
+    let mut index = 0;
+    while index < data.len() {
+        match data[index] {
+            b'<' => {
+                let end = data[index..]
+                    .iter()
+                    .position(|byte| *byte == b'>')
+                    .map_or(data.len(), |end| index + end);
+                let digits: Vec<u8> = data[index + 1..end]
+                    .iter()
+                    .filter(|byte| byte.is_ascii_hexdigit())
+                    .copied()
+                    .collect();
+                let bytes = digits
+                    .chunks(2)
+                    .filter_map(|pair| u8::from_str_radix(&String::from_utf8_lossy(pair), 16).ok())
+                    .collect();
+                tokens.push(Token::Hex(bytes));
+                index = end + 1;
+            }
+            b'[' => {
+                tokens.push(Token::Open);
+                index += 1;
+            }
+            b']' => {
+                tokens.push(Token::Close);
+                index += 1;
+            }
+            byte if byte.is_ascii_whitespace() => index += 1,
+            _ => {
+                let end = data[index..]
+                    .iter()
+                    .position(|byte| byte.is_ascii_whitespace() || b"<[]".contains(byte))
+                    .map_or(data.len(), |end| index + end);
+                tokens.push(Token::Word(
+                    String::from_utf8_lossy(&data[index..end]).to_string(),
+                ));
+                index = end;
+            }
+        }
+    }
+
+    fn code(bytes: &[u8]) -> u32 {
+        bytes.iter().fold(0, |code, byte| code << 8 | *byte as u32)
+    }
+    fn utf16(bytes: &[u8]) -> String {
+        let units: Vec<u16> = bytes
+            .chunks(2)
+            .map(|pair| pair.iter().fold(0, |unit, byte| unit << 8 | *byte as u16))
+            .collect();
+        String::from_utf16_lossy(&units)
+    }
+
+    let mut map = HashMap::new();
+    let mut tokens = tokens.into_iter().peekable();
+    while let Some(token) = tokens.next() {
+        match token {
+            Token::Word(word) if word == "beginbfchar" => {
+                while let (Some(Token::Hex(source)), Some(Token::Hex(target))) =
+                    (tokens.next(), tokens.next())
+                {
+                    map.insert(code(&source), utf16(&target));
+                }
+            }
+            Token::Word(word) if word == "beginbfrange" => {
+                while let (Some(Token::Hex(low)), Some(Token::Hex(high))) =
+                    (tokens.next(), tokens.next())
+                {
+                    let (low, high) = (code(&low), code(&high));
+                    match tokens.next() {
+                        Some(Token::Hex(target)) if !target.is_empty() && high >= low => {
+                            for offset in 0..=(high - low).min(0xFFFF) {
+                                let mut target = target.clone();
+                                let last = target.len() - 1;
+                                target[last] = target[last].wrapping_add(offset as u8);
+                                map.insert(low + offset, utf16(&target));
+                            }
+                        }
+                        Some(Token::Open) => {
+                            let mut offset = 0;
+                            while let Some(Token::Hex(target)) =
+                                tokens.next_if(|t| *t != Token::Close)
+                            {
+                                map.insert(low + offset, utf16(&target));
+                                offset += 1;
+                            }
+                            tokens.next();
+                        }
+                        _ => break,
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    map
+}
+
+/// Resources of a page, its own and the inherited ones
+fn page_resources(pdf: &PdfDocument, page_id: ObjectId) -> Vec<&Dictionary> {
+    let (own, inherited) = pdf.get_page_resources(page_id);
+    own.into_iter()
+        .chain(
+            inherited
+                .into_iter()
+                .filter_map(|id| pdf.get_dictionary(id).ok()),
+        )
+        .collect()
+}
+
+/// Resource of a category (`Font`, `XObject`) in the first resources having it
+fn resource<'a>(
+    pdf: &'a PdfDocument,
+    resources: &[&'a Dictionary],
+    category: &[u8],
+    name: &[u8],
+) -> Option<(Option<ObjectId>, &'a Object)> {
+    resources.iter().find_map(|resources| {
+        let category = resources.get_deref(category, pdf).ok()?.as_dict().ok()?;
+        pdf.dereference(category.get(name).ok()?).ok()
+    })
+}
+
+fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    [
+        a[0] * b[0] + a[1] * b[2],
+        a[0] * b[1] + a[1] * b[3],
+        a[2] * b[0] + a[3] * b[2],
+        a[2] * b[1] + a[3] * b[3],
+        a[4] * b[0] + a[5] * b[2] + b[4],
+        a[4] * b[1] + a[5] * b[3] + b[5],
+    ]
+}
+
+fn matrix(operands: &[Object]) -> Option<Matrix> {
+    let values: Vec<f32> = operands
+        .iter()
+        .filter_map(|operand| operand.as_float().ok())
+        .collect();
+    values.try_into().ok()
+}
+
+/// Text state, saved with the graphics state
+#[derive(Clone, Default)]
+struct TextState {
+    font: Option<Rc<Font>>,
+    size: f32,
+    char_spacing: f32,
+    word_spacing: f32,
+    /// Horizontal scaling, 1 when not set
+    scale: f32,
+    leading: f32,
+    rise: f32,
+}
+
+struct PageReader<'a> {
+    pdf: &'a PdfDocument,
+    fonts: &'a mut HashMap<ObjectId, Rc<Font>>,
+    /// Images decoded by object, none when they cannot be
+    images: &'a mut HashMap<ObjectId, Option<ImageData>>,
+    links: &'a [Link],
+    spans: Vec<Span>,
+    rules: Vec<Rule>,
+    figures: Vec<Line>,
+}
+
+impl<'a> PageReader<'a> {
+    fn read(
+        &mut self,
+        content: &[u8],
+        resources: &[&'a Dictionary],
+        ctm: Matrix,
+        depth: usize,
+    ) -> anyhow::Result<()> {
+        let content = Content::decode(content)?;
+        let mut ctm = ctm;
+        let mut state = TextState {
+            scale: 1.0,
+            ..Default::default()
+        };
+        let mut stack = Vec::new();
+        let mut tm = IDENTITY;
+        let mut tlm = IDENTITY;
+        // segments of the path being built, kept as rules when it is painted
+        let mut path: Vec<Rule> = Vec::new();
+        let (mut start, mut current) = ((0.0, 0.0), (0.0, 0.0));
+        for operation in &content.operations {
+            let operands = &operation.operands;
+            let number = |index: usize| {
+                operands
+                    .get(index)
+                    .and_then(|operand| operand.as_float().ok())
+                    .unwrap_or_default()
+            };
+            match operation.operator.as_str() {
+                "q" => stack.push((ctm, state.clone())),
+                "Q" => {
+                    if let Some((saved_ctm, saved_state)) = stack.pop() {
+                        ctm = saved_ctm;
+                        state = saved_state;
+                    }
+                }
+                "cm" => {
+                    if let Some(m) = matrix(operands) {
+                        ctm = multiply(&m, &ctm);
+                    }
+                }
+                "m" => {
+                    current = point(&ctm, number(0), number(1));
+                    start = current;
+                }
+                "l" => {
+                    let next = point(&ctm, number(0), number(1));
+                    path.push(segment(current, next));
+                    current = next;
+                }
+                "c" | "v" | "y" => {
+                    let last = operands.len().saturating_sub(2);
+                    current = point(&ctm, number(last), number(last + 1));
+                }
+                "h" => {
+                    path.push(segment(current, start));
+                    current = start;
+                }
+                "re" => {
+                    let (x, y, width, height) = (number(0), number(1), number(2), number(3));
+                    let corners = [
+                        point(&ctm, x, y),
+                        point(&ctm, x + width, y),
+                        point(&ctm, x + width, y + height),
+                        point(&ctm, x, y + height),
+                    ];
+                    for index in 0..4 {
+                        path.push(segment(corners[index], corners[(index + 1) % 4]));
+                    }
+                    current = corners[0];
+                    start = current;
+                }
+                "S" | "s" | "f" | "F" | "f*" | "B" | "B*" | "b" | "b*" => {
+                    self.rules.extend(
+                        path.drain(..)
+                            .filter(|rule| rule.is_horizontal() || rule.is_vertical()),
+                    );
+                }
+                "n" => path.clear(),
+                "BT" => {
+                    tm = IDENTITY;
+                    tlm = IDENTITY;
+                }
+                "Tf" => {
+                    state.size = number(1);
+                    state.font = operands
+                        .first()
+                        .and_then(|name| name.as_name().ok())
+                        .and_then(|name| self.font(resources, name));
+                }
+                "Tc" => state.char_spacing = number(0),
+                "Tw" => state.word_spacing = number(0),
+                "Tz" => state.scale = number(0) / 100.0,
+                "TL" => state.leading = number(0),
+                "Ts" => state.rise = number(0),
+                "Td" | "TD" => {
+                    if operation.operator == "TD" {
+                        state.leading = -number(1);
+                    }
+                    tlm = multiply(&[1.0, 0.0, 0.0, 1.0, number(0), number(1)], &tlm);
+                    tm = tlm;
+                }
+                "Tm" => {
+                    if let Some(m) = matrix(operands) {
+                        tlm = m;
+                        tm = m;
+                    }
+                }
+                "T*" | "'" | "\"" => {
+                    if operation.operator == "\"" {
+                        state.word_spacing = number(0);
+                        state.char_spacing = number(1);
+                    }
+                    tlm = multiply(&[1.0, 0.0, 0.0, 1.0, 0.0, -state.leading], &tlm);
+                    tm = tlm;
+                    if let Some(Object::String(bytes, _)) = operands.last() {
+                        self.show(bytes, &state, &mut tm, &ctm);
+                    }
+                }
+                "Tj" => {
+                    if let Some(Object::String(bytes, _)) = operands.first() {
+                        self.show(bytes, &state, &mut tm, &ctm);
+                    }
+                }
+                "TJ" => {
+                    for item in operands
+                        .first()
+                        .and_then(|array| array.as_array().ok())
+                        .into_iter()
+                        .flatten()
+                    {
+                        match item {
+                            Object::String(bytes, _) => self.show(bytes, &state, &mut tm, &ctm),
+                            item => {
+                                if let Ok(adjustment) = item.as_float() {
+                                    let tx = -adjustment / 1000.0 * state.size * state.scale;
+                                    tm = multiply(&[1.0, 0.0, 0.0, 1.0, tx, 0.0], &tm);
+                                }
+                            }
+                        }
+                    }
+                }
+                "Do" if depth < MAX_FORM_DEPTH => {
+                    let name = operands.first().and_then(|name| name.as_name().ok());
+                    let Some((id, xobject)) = name
+                        .and_then(|name| resource(self.pdf, resources, b"XObject", name))
+                        .and_then(|(id, object)| Some((id, object.as_stream().ok()?)))
+                    else {
+                        continue;
+                    };
+                    let subtype = xobject
+                        .dict
+                        .get(b"Subtype")
+                        .and_then(Object::as_name_str)
+                        .ok();
+                    if subtype == Some("Image") {
+                        self.image(id, xobject, &ctm);
+                    } else if subtype == Some("Form") {
+                        let form = xobject;
+                        let form_ctm = form
+                            .dict
+                            .get(b"Matrix")
+                            .and_then(Object::as_array)
+                            .ok()
+                            .and_then(|m| matrix(m))
+                            .unwrap_or(IDENTITY);
+                        let mut form_resources: Vec<&'a Dictionary> = form
+                            .dict
+                            .get_deref(b"Resources", self.pdf)
+                            .and_then(Object::as_dict)
+                            .into_iter()
+                            .collect();
+                        form_resources.extend(resources);
+                        let content = form
+                            .decompressed_content()
+                            .unwrap_or_else(|_| form.content.clone());
+                        self.read(
+                            &content,
+                            &form_resources,
+                            multiply(&form_ctm, &ctm),
+                            depth + 1,
+                        )?;
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds the image painted in the unit square of the graphics matrix
+    fn image(&mut self, id: Option<ObjectId>, stream: &Stream, ctm: &Matrix) {
+        let corners =
+            [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0), (1.0, 1.0)].map(|(x, y)| point(ctm, x, y));
+        let bound = |values: [f32; 4], max: bool| {
+            values
+                .into_iter()
+                .reduce(if max { f32::max } else { f32::min })
+                .unwrap_or_default()
+        };
+        let (xs, ys) = (corners.map(|(x, _)| x), corners.map(|(_, y)| y));
+        let (x0, x1, y0, y1) = (
+            bound(xs, false),
+            bound(xs, true),
+            bound(ys, false),
+            bound(ys, true),
+        );
+        // dots and rules drawn as images
+        if x1 - x0 < 2.0 || y1 - y0 < 2.0 {
+            return;
+        }
+        let decode = || match crate::pdf_image::decode(self.pdf, stream) {
+            Ok(image) => Some(image),
+            Err(error) => {
+                warn!("Cannot read an image of the PDF: {}", error);
+                None
+            }
+        };
+        let image = match id {
+            Some(id) => self.images.entry(id).or_insert_with(decode).clone(),
+            None => decode(),
+        };
+        let Some(mut image) = image else {
+            return;
+        };
+        // in CSS pixels, 96 to the inch
+        let pixels = |points: f32| Some(format!("{}", (points / 0.75).round()));
+        image.set_image_size(ImageDimension {
+            width: pixels(x1 - x0),
+            height: pixels(y1 - y0),
+        });
+        self.figures.push(Line {
+            x0,
+            x1,
+            y: y1,
+            size: 0.0,
+            text: String::new(),
+            inset: Some(Inset::Image(image)),
+        });
+    }
+
+    fn font(&mut self, resources: &[&'a Dictionary], name: &[u8]) -> Option<Rc<Font>> {
+        let (id, font) = resource(self.pdf, resources, b"Font", name)?;
+        let font = font.as_dict().ok()?;
+        match id {
+            Some(id) => Some(
+                self.fonts
+                    .entry(id)
+                    .or_insert_with(|| Rc::new(Font::load(self.pdf, font)))
+                    .clone(),
+            ),
+            None => Some(Rc::new(Font::load(self.pdf, font))),
+        }
+    }
+
+    /// Adds the span of a shown string and moves the text matrix after it
+    fn show(&mut self, bytes: &[u8], state: &TextState, tm: &mut Matrix, ctm: &Matrix) {
+        let Some(font) = &state.font else {
+            return;
+        };
+        let origin =
+            |tm: &Matrix| multiply(&[1.0, 0.0, 0.0, 1.0, 0.0, state.rise], &multiply(tm, ctm));
+        let start = origin(tm);
+        let size = state.size * (start[2] * start[2] + start[3] * start[3]).sqrt();
+        // the string is split where the link under its glyphs changes
+        let (mut piece, mut text, mut link) = (start, String::new(), None);
+        for code in font.codes(bytes) {
+            let before = origin(tm);
+            let mut tx = font.width(code) * state.size + state.char_spacing;
+            if !font.two_bytes && code == 32 {
+                tx += state.word_spacing;
+            }
+            *tm = multiply(&[1.0, 0.0, 0.0, 1.0, tx * state.scale, 0.0], tm);
+            let center = (before[4] + origin(tm)[4]) / 2.0;
+            let glyph_link = self.link_at(center, before[5] + 0.3 * size);
+            if glyph_link != link {
+                self.push_span(&piece, &before, size, std::mem::take(&mut text), link);
+                (piece, link) = (before, glyph_link);
+            }
+            text.push_str(&expand_ligatures(font.text(code)));
+        }
+        self.push_span(&piece, &origin(tm), size, text, link);
+    }
+
+    fn push_span(
+        &mut self,
+        start: &Matrix,
+        end: &Matrix,
+        size: f32,
+        text: String,
+        link: Option<usize>,
+    ) {
+        if text.trim().is_empty() || size <= 0.0 {
+            return;
+        }
+        let text = match link {
+            Some(url) => format!("{LINK_START}{url}{LINK_SEPARATOR}{text}{LINK_END}"),
+            None => text,
+        };
+        self.spans.push(Span {
+            x: start[4].min(end[4]),
+            end: start[4].max(end[4]),
+            y: start[5],
+            size,
+            text,
+        });
+    }
+
+    /// URL of the link at a point of the page
+    fn link_at(&self, x: f32, y: f32) -> Option<usize> {
+        self.links
+            .iter()
+            .find(|link| link.contains(x, y))
+            .map(|link| link.url)
+    }
+}
+
+fn point(ctm: &Matrix, x: f32, y: f32) -> (f32, f32) {
+    let m = multiply(&[1.0, 0.0, 0.0, 1.0, x, y], ctm);
+    (m[4], m[5])
+}
+
+/// Rule between two points, from left to right and bottom to top
+fn segment(a: (f32, f32), b: (f32, f32)) -> Rule {
+    Rule {
+        x0: a.0.min(b.0),
+        y0: a.1.min(b.1),
+        x1: a.0.max(b.0),
+        y1: a.1.max(b.1),
+    }
+}
+
+/// Lines of the spans sharing a baseline, split at the gaps of `gap` font sizes
+fn lines(mut spans: Vec<Span>, gap: f32) -> Vec<Line> {
+    spans.sort_by(|a, b| b.y.total_cmp(&a.y).then(a.x.total_cmp(&b.x)));
+    let mut rows: Vec<Vec<Span>> = Vec::new();
+    for span in spans {
+        match rows.last_mut() {
+            Some(row) if (row[0].y - span.y).abs() <= row[0].size.min(span.size) * 0.5 => {
+                row.push(span)
+            }
+            _ => rows.push(vec![span]),
+        }
+    }
+
+    let mut lines = Vec::new();
+    for mut row in rows {
+        row.sort_by(|a, b| a.x.total_cmp(&b.x));
+        let mut line: Option<Line> = None;
+        let mut sizes: Vec<(f32, usize)> = Vec::new();
+        for span in row {
+            match &mut line {
+                Some(current) if span.x - current.x1 < gap * span.size.max(current.size) => {
+                    let gap = span.x - current.x1;
+                    if gap > 0.15 * span.size
+                        && !current.text.ends_with(' ')
+                        && !span.text.starts_with(' ')
+                    {
+                        current.text.push(' ');
+                    }
+                    current.text.push_str(&span.text);
+                    current.x1 = current.x1.max(span.end);
+                }
+                _ => {
+                    if let Some(mut done) = line.take() {
+                        done.size = most_common(&sizes);
+                        lines.push(done);
+                        sizes.clear();
+                    }
+                    line = Some(Line {
+                        x0: span.x,
+                        x1: span.end,
+                        y: span.y,
+                        size: span.size,
+                        text: span.text.clone(),
+                        inset: None,
+                    });
+                }
+            }
+            sizes.push((span.size, span.text.chars().count()));
+        }
+        if let Some(mut done) = line {
+            done.size = most_common(&sizes);
+            lines.push(done);
+        }
+    }
+    for line in &mut lines {
+        line.text = line.text.split_whitespace().collect::<Vec<_>>().join(" ");
+    }
+    lines
+}
+
+/// Tables drawn as grids of rules, with the spans outside of them
+fn ruled_tables(spans: Vec<Span>, rules: &[Rule]) -> (Vec<Line>, Vec<Span>) {
+    // the rules touching each other make one grid
+    let mut parent: Vec<usize> = (0..rules.len()).collect();
+    fn root(parent: &mut [usize], mut index: usize) -> usize {
+        while parent[index] != index {
+            parent[index] = parent[parent[index]];
+            index = parent[index];
+        }
+        index
+    }
+    for (i, a) in rules.iter().enumerate() {
+        for (j, b) in rules.iter().enumerate().skip(i + 1) {
+            if a.x0 <= b.x1 + 2.0 && b.x0 <= a.x1 + 2.0 && a.y0 <= b.y1 + 2.0 && b.y0 <= a.y1 + 2.0
+            {
+                let (a, b) = (root(&mut parent, i), root(&mut parent, j));
+                parent[a] = b;
+            }
+        }
+    }
+    let mut grids: Vec<Vec<Rule>> = Vec::new();
+    let mut grid_of: HashMap<usize, usize> = HashMap::new();
+    for (index, rule) in rules.iter().enumerate() {
+        let root = root(&mut parent, index);
+        let grid = *grid_of.entry(root).or_insert_with(|| {
+            grids.push(Vec::new());
+            grids.len() - 1
+        });
+        grids[grid].push(*rule);
+    }
+
+    let mut tables = Vec::new();
+    let mut spans = spans;
+    for grid in grids {
+        let xs = positions(
+            grid.iter()
+                .filter(|rule| rule.is_vertical())
+                .map(|rule| rule.x0),
+        );
+        let mut ys = positions(
+            grid.iter()
+                .filter(|rule| rule.is_horizontal())
+                .map(|rule| rule.y0),
+        );
+        ys.reverse();
+        // a frame around a text is not a table
+        if xs.len() < 3 || ys.len() < 2 {
+            continue;
+        }
+        let (left, right, top, bottom) = (xs[0], xs[xs.len() - 1], ys[0], ys[ys.len() - 1]);
+        let (inside, outside): (Vec<Span>, Vec<Span>) = spans.into_iter().partition(|span| {
+            let center = (span.x + span.end) / 2.0;
+            center > left && center < right && span.y > bottom && span.y < top
+        });
+        spans = outside;
+        let mut cells: Vec<Vec<Vec<Span>>> = (1..ys.len())
+            .map(|_| (1..xs.len()).map(|_| Vec::new()).collect())
+            .collect();
+        let mut sizes = Vec::new();
+        for span in inside {
+            let center = (span.x + span.end) / 2.0;
+            let row = ys[1..].iter().take_while(|y| span.y < **y).count();
+            let column = xs[1..].iter().take_while(|x| center > **x).count();
+            sizes.push((span.size, span.text.chars().count()));
+            cells[row.min(ys.len() - 2)][column.min(xs.len() - 2)].push(span);
+        }
+        let rows: Vec<Vec<String>> = cells
+            .into_iter()
+            .map(|row| {
+                row.into_iter()
+                    .map(|cell| {
+                        lines(cell, f32::INFINITY)
+                            .into_iter()
+                            .map(|line| line.text)
+                            .collect::<Vec<_>>()
+                            .join(" ")
+                    })
+                    .collect::<Vec<_>>()
+            })
+            // the rows between double rules
+            .filter(|row| row.iter().any(|cell| !cell.is_empty()))
+            .collect();
+        if rows.is_empty() {
+            continue;
+        }
+        tables.push(Line {
+            x0: left,
+            x1: right,
+            y: top,
+            size: most_common(&sizes),
+            text: String::new(),
+            inset: Some(Inset::Table(rows)),
+        });
+    }
+    (tables, spans)
+}
+
+/// Distinct positions of the rules, the ones less than 2 apart merged
+fn positions(values: impl Iterator<Item = f32>) -> Vec<f32> {
+    let mut values: Vec<f32> = values.collect();
+    values.sort_by(f32::total_cmp);
+    values.dedup_by(|b, a| *b - *a < 2.0);
+    values
+}
+
+/// Tables without rules, rows of cells aligned in the same columns, with the spans
+/// outside of them
+fn aligned_tables(spans: Vec<Span>) -> (Vec<Line>, Vec<Span>) {
+    let mut rows: Vec<Vec<Line>> = Vec::new();
+    for line in lines(spans.clone(), CELL_GAP) {
+        match rows.last_mut() {
+            Some(row) if (row[0].y - line.y).abs() <= row[0].size.min(line.size) * 0.5 => {
+                row.push(line)
+            }
+            _ => rows.push(vec![line]),
+        }
+    }
+
+    // each table with the baseline of its last row
+    let mut tables: Vec<(Line, f32)> = Vec::new();
+    let mut run: Vec<&Vec<Line>> = Vec::new();
+    let mut columns: Vec<(f32, f32)> = Vec::new();
+    for row in &rows {
+        let close = run
+            .last()
+            .is_some_and(|last| last[0].y - row[0].y <= 3.0 * last[0].size);
+        if let Some(fitted) = fit_columns(&columns, row).filter(|_| close) {
+            columns = fitted;
+            run.push(row);
+            continue;
+        }
+        tables.extend(aligned_table(&run, &columns));
+        (run, columns) = match fit_columns(&[], row) {
+            Some(fitted) => (vec![row], fitted),
+            None => (Vec::new(), Vec::new()),
+        };
+    }
+    tables.extend(aligned_table(&run, &columns));
+
+    let spans = spans
+        .into_iter()
+        .filter(|span| {
+            let center = (span.x + span.end) / 2.0;
+            !tables.iter().any(|(table, bottom)| {
+                center > table.x0
+                    && center < table.x1
+                    && span.y <= table.y + 0.5 * table.size
+                    && span.y >= bottom - 0.5 * table.size
+            })
+        })
+        .collect();
+    (tables.into_iter().map(|(table, _)| table).collect(), spans)
+}
+
+/// Columns with the segments of the row added, none when the row is a single segment,
+/// when a segment spans two columns or when two segments fall in one
+fn fit_columns(columns: &[(f32, f32)], row: &[Line]) -> Option<Vec<(f32, f32)>> {
+    if row.len() < 2 {
+        return None;
+    }
+    let mut columns = columns.to_vec();
+    let mut used = vec![false; columns.len()];
+    for line in row {
+        let overlapping: Vec<usize> = (0..columns.len())
+            .filter(|index| line.x0 <= columns[*index].1 && columns[*index].0 <= line.x1)
+            .collect();
+        match overlapping[..] {
+            [] => {
+                let index = columns.partition_point(|column| column.1 < line.x0);
+                columns.insert(index, (line.x0, line.x1));
+                used.insert(index, true);
+            }
+            [index] if !used[index] => {
+                let column = &mut columns[index];
+                *column = (column.0.min(line.x0), column.1.max(line.x1));
+                used[index] = true;
+            }
+            _ => return None,
+        }
+    }
+    // a widened column must not reach the next one
+    columns
+        .windows(2)
+        .all(|pair| pair[0].1 < pair[1].0)
+        .then_some(columns)
+}
+
+/// Table of a run of aligned rows, when they have the short cells of a table rather
+/// than the lines of text columns
+fn aligned_table(run: &[&Vec<Line>], columns: &[(f32, f32)]) -> Option<(Line, f32)> {
+    if run.len() < 2 || columns.len() < 2 || (columns.len() == 2 && run.len() < 3) {
+        return None;
+    }
+    let cells: Vec<&Line> = run.iter().flat_map(|row| row.iter()).collect();
+    let average = cells
+        .iter()
+        .map(|line| strip_links(&line.text).chars().count())
+        .sum::<usize>()
+        / cells.len();
+    if average > 25 {
+        return None;
+    }
+    let rows = run
+        .iter()
+        .map(|row| {
+            let mut texts = vec![String::new(); columns.len()];
+            for line in row.iter() {
+                let index = columns
+                    .iter()
+                    .position(|column| line.x0 <= column.1 && column.0 <= line.x1)
+                    .unwrap_or_default();
+                texts[index] = line.text.clone();
+            }
+            texts
+        })
+        .collect();
+    let table = Line {
+        x0: columns[0].0,
+        x1: columns[columns.len() - 1].1,
+        y: run[0][0].y,
+        size: most_common(
+            &cells
+                .iter()
+                .map(|line| (line.size, line.text.len()))
+                .collect::<Vec<_>>(),
+        ),
+        text: String::new(),
+        inset: Some(Inset::Table(rows)),
+    };
+    Some((table, run[run.len() - 1][0].y))
+}
+
+/// Value of the largest total weight, the values less than 0.5 apart counted together
+/// (the font size of the most characters)
+fn most_common(sizes: &[(f32, usize)]) -> f32 {
+    let mut counts: Vec<(f32, usize)> = Vec::new();
+    for (size, count) in sizes {
+        match counts
+            .iter_mut()
+            .find(|(known, _)| (known - size).abs() < 0.5)
+        {
+            Some((_, total)) => *total += count,
+            None => counts.push((*size, *count)),
+        }
+    }
+    counts
+        .into_iter()
+        .max_by_key(|(_, count)| *count)
+        .map_or(0.0, |(size, _)| size)
+}
+
+/// Lines ordered top to bottom, the columns one after the other between the lines
+/// spanning them
+fn reading_order(mut lines: Vec<Line>) -> Vec<Line> {
+    lines.sort_by(|a, b| b.y.total_cmp(&a.y).then(a.x0.total_cmp(&b.x0)));
+    let (Some(left), Some(right)) = (
+        lines.iter().map(|line| line.x0).min_by(f32::total_cmp),
+        lines.iter().map(|line| line.x1).max_by(f32::total_cmp),
+    ) else {
+        return lines;
+    };
+    let body = most_common(
+        &lines
+            .iter()
+            .map(|line| (line.size, line.text.len()))
+            .collect::<Vec<_>>(),
+    );
+
+    // the narrow lines show the columns, the gaps between them are the gutters
+    let mut narrow: Vec<&Line> = lines
+        .iter()
+        .filter(|line| line.x1 - line.x0 < (right - left) * 0.55)
+        .filter(|line| !line.inset.as_ref().is_some_and(Inset::is_mark))
+        .collect();
+    narrow.sort_by(|a, b| a.x0.total_cmp(&b.x0));
+    let mut columns: Vec<(f32, f32, usize)> = Vec::new();
+    for line in narrow {
+        match columns.last_mut() {
+            Some((_, end, count)) if line.x0 <= *end + body => {
+                *end = end.max(line.x1);
+                *count += 1;
+            }
+            _ => columns.push((line.x0, line.x1, 1)),
+        }
+    }
+    // table cells are narrower than the columns of a page
+    columns.retain(|(start, end, count)| *count >= 3 && end - start >= (right - left) * 0.2);
+    if columns.len() < 2 {
+        return lines;
+    }
+    let gutters: Vec<f32> = columns
+        .windows(2)
+        .map(|pair| (pair[0].1 + pair[1].0) / 2.0)
+        .collect();
+
+    let mut ordered = Vec::with_capacity(lines.len());
+    let mut band: Vec<Vec<Line>> = (0..=gutters.len()).map(|_| Vec::new()).collect();
+    for line in lines {
+        if gutters
+            .iter()
+            .any(|gutter| line.x0 < *gutter && line.x1 > *gutter)
+        {
+            band.iter_mut().for_each(|column| ordered.append(column));
+            ordered.push(line);
+        } else {
+            let column = gutters.iter().filter(|gutter| **gutter < line.x0).count();
+            band[column].push(line);
+        }
+    }
+    band.iter_mut().for_each(|column| ordered.append(column));
+    ordered
+}
+
+/// Blocks of consecutive lines of a font size, separated by the wider spacings, by the
+/// list items and by the tables
+fn text_blocks(lines: Vec<Line>) -> Vec<Block> {
+    // spacing of the lines of a paragraph, the most common on the page
+    let typical = most_common(
+        &lines
+            .windows(2)
+            .filter(|pair| pair[0].inset.is_none() && pair[1].inset.is_none())
+            .filter(|pair| (pair[0].size - pair[1].size).abs() <= 0.5)
+            .map(|pair| (pair[0].y - pair[1].y, 1))
+            .filter(|(gap, _)| *gap > 0.0)
+            .collect::<Vec<_>>(),
+    );
+    let mut blocks: Vec<Block> = Vec::new();
+    let mut previous: Option<(f32, f32, f32)> = None;
+    let mut spacing: Option<f32> = None;
+    for line in lines {
+        if let Some(inset) = line.inset {
+            match (inset, blocks.last_mut()) {
+                // a comment on the text stays in its paragraph
+                (Inset::Comment(comment), Some(Block::Text { notes, .. }))
+                    if previous.is_some() =>
+                {
+                    notes.push(comment);
+                    continue;
+                }
+                (Inset::Comment(comment), _) => blocks.push(Block::Comment(comment)),
+                (Inset::Table(rows), _) => blocks.push(Block::Table {
+                    size: line.size,
+                    rows,
+                }),
+                (Inset::Image(image), _) => blocks.push(Block::Image(image)),
+                (Inset::Recognized(elements), _) => blocks.push(Block::Recognized(elements)),
+                (Inset::Target { id, title }, _) => blocks.push(Block::Target { id, title }),
+            }
+            previous = None;
+            continue;
+        }
+        let starts_block = match previous {
+            None => true,
+            Some((y, size, x0)) => {
+                let gap = y - line.y;
+                (size - line.size).abs() > 0.5
+                    || gap <= 0.0
+                    || gap > 1.8 * size
+                    || spacing.is_some_and(|spacing| gap > spacing * 1.2)
+                    || (typical > 0.0 && gap > typical * 1.2)
+                    || (line.x0 - x0).abs() > 20.0 * size && gap > 0.0
+                    || list_marker(&line.text).is_some()
+            }
+        };
+        if starts_block {
+            spacing = None;
+            blocks.push(Block::Text {
+                size: line.size,
+                lines: vec![],
+                notes: vec![],
+            });
+        } else if let Some((y, _, _)) = previous {
+            spacing.get_or_insert(y - line.y);
+        }
+        previous = Some((line.y, line.size, line.x0));
+        if let Some(Block::Text { lines, .. }) = blocks.last_mut() {
+            lines.push(line.text);
+        }
+    }
+    blocks
+}
+
+/// Letters of the Latin ligatures some fonts map their ligature glyphs to
+fn expand_ligatures(text: &str) -> Cow<'_, str> {
+    if !text.contains(|c| ('\u{FB00}'..='\u{FB06}').contains(&c)) {
+        return Cow::Borrowed(text);
+    }
+    let mut expanded = String::new();
+    for c in text.chars() {
+        match c {
+            '\u{FB00}' => expanded.push_str("ff"),
+            '\u{FB01}' => expanded.push_str("fi"),
+            '\u{FB02}' => expanded.push_str("fl"),
+            '\u{FB03}' => expanded.push_str("ffi"),
+            '\u{FB04}' => expanded.push_str("ffl"),
+            '\u{FB05}' | '\u{FB06}' => expanded.push_str("st"),
+            c => expanded.push(c),
+        }
+    }
+    Cow::Owned(expanded)
+}
+
+/// Bullet or number starting a list item, with whether it is numbered and its number
+fn list_marker(text: &str) -> Option<(usize, bool, usize)> {
+    let (marker, rest) = text.split_once(' ')?;
+    if rest.is_empty() {
+        return None;
+    }
+    if ["•", "◦", "▪", "‣", "–", "-", "*", "·"].contains(&marker) {
+        return Some((marker.len(), false, 1));
+    }
+    let number = marker
+        .strip_suffix('.')
+        .or_else(|| marker.strip_suffix(')'))?;
+    let number = number.parse().ok().filter(|number| *number < 1000)?;
+    Some((marker.len(), true, number))
+}
+
+/// Text of the lines of a block, the words hyphenated at the end of a line joined
+fn block_text(lines: &[String]) -> String {
+    let mut text = String::new();
+    for line in lines {
+        if text.is_empty() {
+            text.push_str(line);
+        } else if text.ends_with('-')
+            && text.chars().rev().nth(1).is_some_and(char::is_alphabetic)
+            && line.starts_with(char::is_lowercase)
+        {
+            text.pop();
+            text.push_str(line);
+        } else {
+            text.push(' ');
+            text.push_str(line);
+        }
+    }
+    text
+}
+
+/// Paragraphs, list, headers, tables and images of the blocks, the headers in a font larger than
+/// the body text, the links to `urls`
+fn elements(blocks: Vec<Block>, urls: &[String]) -> Vec<Element> {
+    let body = most_common(
+        &blocks
+            .iter()
+            .filter_map(|block| match block {
+                Block::Text { size, lines, .. } => {
+                    Some((*size, lines.iter().map(String::len).sum()))
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>(),
+    );
+    let is_header = |size: f32, lines: &[String]| {
+        size >= body * 1.15
+            && lines.len() <= 3
+            && lines.iter().map(String::len).sum::<usize>() < 200
+    };
+    let mut header_sizes: Vec<f32> = Vec::new();
+    for block in &blocks {
+        if let Block::Text { size, lines, .. } = block {
+            if is_header(*size, lines)
+                && !header_sizes
+                    .iter()
+                    .any(|header_size| (header_size - size).abs() < 0.5)
+            {
+                header_sizes.push(*size);
+            }
+        }
+    }
+    header_sizes.sort_by(|a, b| b.total_cmp(a));
+    let note_size = body.round().clamp(1.0, 255.0) as u8;
+
+    let mut elements: Vec<Element> = Vec::new();
+    // the target before a header is its id, the others are bookmarks
+    let mut target: Option<(String, String)> = None;
+    for block in blocks {
+        let (block_size, lines, notes) = match block {
+            Block::Text { size, lines, notes } => (size, lines, notes),
+            Block::Target { id, title } => {
+                if let Some((id, title)) = target.replace((id, title)) {
+                    elements.push(Bookmark { id, title });
+                }
+                continue;
+            }
+            block => {
+                if let Some((id, title)) = target.take() {
+                    elements.push(Bookmark { id, title });
+                }
+                match block {
+                    Block::Table { size, rows } => elements.push(table(size, rows, urls)),
+                    Block::Image(image) => elements.push(Image(image)),
+                    Block::Recognized(recognized) => elements.extend(recognized),
+                    Block::Comment(comment) => elements.push(Paragraph {
+                        elements: vec![note(comment, note_size)],
+                        direction: TextDirection::default(),
+                    }),
+                    Block::Text { .. } | Block::Target { .. } => {}
+                }
+                continue;
+            }
+        };
+        let text = block_text(&lines);
+        let size = block_size.round().clamp(1.0, 255.0) as u8;
+        let notes = notes.into_iter().map(|comment| note(comment, note_size));
+        if is_header(block_size, &lines) {
+            let level = header_sizes
+                .iter()
+                .position(|header_size| (header_size - block_size).abs() < 0.5)
+                .unwrap_or_default()
+                .min(5) as u8
+                + 1;
+            let text = strip_links(&text);
+            let anchor = header_anchor(&text);
+            let header = Header { level, text };
+            elements.push(match target.take() {
+                Some((id, _)) if id != anchor => Attributed {
+                    attributes: HashMap::from([("id".to_string(), id)]),
+                    element: Box::new(header),
+                },
+                _ => header,
+            });
+            let notes: Vec<Element> = notes.collect();
+            if !notes.is_empty() {
+                elements.push(Paragraph {
+                    elements: notes,
+                    direction: TextDirection::default(),
+                });
+            }
+            continue;
+        }
+        if let Some((id, title)) = target.take() {
+            elements.push(Bookmark { id, title });
+        }
+        if let Some((marker_len, numbered, number)) = list_marker(&strip_links(&text)) {
+            // the marker is never linked, the text starts with it
+            let text = text[text.find(' ').unwrap_or(marker_len)..].trim_start();
+            let mut inline = inline(text, size, urls);
+            inline.extend(notes);
+            let item = ListItem {
+                element: match <[Element; 1]>::try_from(inline) {
+                    Ok([element]) => element,
+                    Err(inline) => Paragraph {
+                        elements: inline,
+                        direction: TextDirection::default(),
+                    },
+                },
+            };
+            match elements.last_mut() {
+                Some(List {
+                    elements: items,
+                    numbered: list_numbered,
+                    ..
+                }) if *list_numbered == numbered => items.push(item),
+                _ => elements.push(List {
+                    elements: vec![item],
+                    numbered,
+                    start: number,
+                    numbering: NumberingStyle::default(),
+                }),
+            }
+        } else {
+            let mut inline = inline(&text, size, urls);
+            inline.extend(notes);
+            elements.push(Paragraph {
+                elements: inline,
+                direction: TextDirection::default(),
+            });
+        }
+    }
+    if let Some((id, title)) = target {
+        elements.push(Bookmark { id, title });
+    }
+    elements
+}
+
+/// Table of the rows of cells, the first row its headers
+fn table(size: f32, rows: Vec<Vec<String>>, urls: &[String]) -> Element {
+    let size = size.round().clamp(1.0, 255.0) as u8;
+    let cell = |text: String| match <[Element; 1]>::try_from(inline(&text, size, urls)) {
+        Ok([element]) => element,
+        Err(_) => Text {
+            text: strip_links(&text),
+            size,
+        },
+    };
+    let mut rows = rows.into_iter();
+    let headers = rows
+        .next()
+        .unwrap_or_default()
+        .into_iter()
+        .map(|text| TableHeader {
+            element: cell(text),
+            width: 10.0,
+        })
+        .collect();
+    let rows = rows
+        .map(|row| TableRow {
+            cells: row
+                .into_iter()
+                .map(|text| TableCell {
+                    element: cell(text),
+                })
+                .collect(),
+        })
+        .collect();
+    Table { headers, rows }
+}
+
+/// Footnote of a comment, with its author
+fn note(comment: Comment, size: u8) -> Element {
+    Attributed {
+        attributes: HashMap::from([("pdf:comment".to_string(), comment.author)]),
+        element: Box::new(Footnote {
+            elements: vec![Text {
+                text: comment.text,
+                size,
+            }],
+        }),
+    }
+}
+
+/// Text of a line or block without its link markers
+fn strip_links(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut in_url = false;
+    for c in text.chars() {
+        match c {
+            LINK_START => in_url = true,
+            LINK_SEPARATOR => in_url = false,
+            LINK_END => {}
+            c if !in_url => stripped.push(c),
+            _ => {}
+        }
+    }
+    stripped
+}
+
+/// Texts and hyperlinks of a text with link markers, the links to the same URL separated
+/// by spaces only merged
+fn inline(text: &str, size: u8, urls: &[String]) -> Vec<Element> {
+    let mut segments: Vec<(Option<usize>, String)> = Vec::new();
+    let mut push = |url: Option<usize>, text: &str| match segments.as_mut_slice() {
+        [.., (last, previous)] if *last == url => previous.push_str(text),
+        [.., (before, previous), (None, gap)]
+            if url.is_some() && *before == url && gap.trim().is_empty() =>
+        {
+            previous.push_str(&std::mem::take(gap));
+            previous.push_str(text);
+            segments.pop();
+        }
+        _ => segments.push((url, text.to_string())),
+    };
+    let mut rest = text;
+    while let Some(start) = rest.find(LINK_START) {
+        push(None, &rest[..start]);
+        let marked = &rest[start + LINK_START.len_utf8()..];
+        let (url, marked) = marked.split_once(LINK_SEPARATOR).unwrap_or(("", marked));
+        let (linked, after) = marked.split_once(LINK_END).unwrap_or((marked, ""));
+        push(url.parse().ok().filter(|url| *url < urls.len()), linked);
+        rest = after;
+    }
+    push(None, rest);
+
+    let mut elements = Vec::new();
+    let mut texts = String::new();
+    for (url, text) in segments {
+        let Some(url) = url.filter(|_| !text.trim().is_empty()) else {
+            texts.push_str(&text);
+            continue;
+        };
+        // the spaces around the link text are outside of the link
+        let linked = text.trim();
+        texts.push_str(&text[..text.len() - text.trim_start().len()]);
+        if !texts.is_empty() {
+            elements.push(Text {
+                text: std::mem::take(&mut texts),
+                size,
+            });
+        }
+        elements.push(Hyperlink {
+            elements: vec![Text {
+                text: linked.to_string(),
+                size,
+            }],
+            url: urls[url].clone(),
+            alt: String::new(),
+            size,
+        });
+        texts.push_str(&text[text.trim_end().len()..]);
+    }
+    if !texts.is_empty() || elements.is_empty() {
+        elements.push(Text { text: texts, size });
+    }
+    elements
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(x0: f32, x1: f32, y: f32, text: &str) -> Line {
+        Line {
+            x0,
+            x1,
+            y,
+            size: 10.0,
+            text: text.to_string(),
+            inset: None,
+        }
+    }
+
+    #[test]
+    fn test_cmap() {
+        let map = cmap(
+            b"2 beginbfchar\n<0003> <0020>\n<0004> <00660069>\nendbfchar\n\
+              1 beginbfrange\n<0010> <0012> <0041>\nendbfrange\n\
+              1 beginbfrange\n<0020> <0021> [<0078> <0079>]\nendbfrange",
+        );
+        assert_eq!(map[&3], " ");
+        assert_eq!(map[&4], "fi");
+        assert_eq!(map[&0x12], "C");
+        assert_eq!(map[&0x21], "y");
+    }
+
+    fn cells(table: &Line) -> &Vec<Vec<String>> {
+        match &table.inset {
+            Some(Inset::Table(rows)) => rows,
+            inset => panic!("table expected, got {:?}", inset),
+        }
+    }
+
+    fn span(x: f32, y: f32, text: &str) -> Span {
+        Span {
+            x,
+            end: x + text.len() as f32 * 5.0,
+            y,
+            size: 10.0,
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_ruled_table() {
+        let mut rules = Vec::new();
+        for y in [700.0, 685.0, 670.0] {
+            rules.push(segment((100.0, y), (300.0, y)));
+        }
+        for x in [100.0, 200.0, 300.0] {
+            rules.push(segment((x, 670.0), (x, 700.0)));
+        }
+        let spans = vec![
+            span(105.0, 690.0, "Name"),
+            span(205.0, 690.0, "Value"),
+            span(105.0, 675.0, "Width"),
+            span(205.0, 675.0, "12"),
+            span(105.0, 650.0, "After the table"),
+        ];
+        let (tables, rest) = ruled_tables(spans, &rules);
+        assert_eq!(
+            cells(&tables[0]),
+            &vec![vec!["Name", "Value"], vec!["Width", "12"]]
+        );
+        assert_eq!(rest.len(), 1);
+    }
+
+    #[test]
+    fn test_aligned_table() {
+        let mut spans = vec![span(50.0, 750.0, "A paragraph before the table.")];
+        for (row, cells) in [
+            ["Item", "Count", "Price"],
+            ["Pen", "3", "1.50"],
+            ["Notebook", "12", "4.00"],
+        ]
+        .iter()
+        .enumerate()
+        {
+            let y = 720.0 - row as f32 * 12.0;
+            spans.push(span(50.0, y, cells[0]));
+            spans.push(span(150.0, y, cells[1]));
+            spans.push(span(250.0, y, cells[2]));
+        }
+        let (tables, rest) = aligned_tables(spans);
+        assert_eq!(tables.len(), 1);
+        assert_eq!(cells(&tables[0])[2], vec!["Notebook", "12", "4.00"]);
+        assert_eq!(rest.len(), 1);
+
+        // the lines of two text columns are not a table
+        let spans = (0..4)
+            .flat_map(|row| {
+                let y = 720.0 - row as f32 * 12.0;
+                [
+                    span(50.0, y, "Lines of the left text column"),
+                    span(320.0, y, "Lines of the right text column"),
+                ]
+            })
+            .collect();
+        assert!(aligned_tables(spans).0.is_empty());
+    }
+
+    #[test]
+    fn test_inline() {
+        let urls = vec!["https://example.com".to_string()];
+        let text = "Read \u{FDD0}0\u{FDD1}the\u{FDD2} \u{FDD0}0\u{FDD1}guide \u{FDD2}first.";
+        assert_eq!(strip_links(text), "Read the guide first.");
+        let elements = inline(text, 10, &urls);
+        assert_eq!(elements.len(), 3, "{:?}", elements);
+        assert_eq!(elements[0].plain_text(), "Read ");
+        assert!(matches!(
+            &elements[1],
+            Hyperlink { url, elements, .. }
+                if url == "https://example.com" && elements[0].plain_text() == "the guide"
+        ));
+        assert_eq!(elements[2].plain_text(), " first.");
+    }
+
+    #[test]
+    fn test_reading_order() {
+        let mut lines = vec![line(50.0, 550.0, 800.0, "Title across the columns")];
+        for row in 0..4 {
+            let y = 780.0 - row as f32 * 12.0;
+            lines.push(line(50.0, 280.0, y, &format!("left {}", row)));
+            lines.push(line(320.0, 550.0, y, &format!("right {}", row)));
+        }
+        lines.push(line(50.0, 550.0, 600.0, "Footer across the columns"));
+
+        let texts: Vec<String> = reading_order(lines)
+            .into_iter()
+            .map(|line| line.text)
+            .collect();
+        assert_eq!(
+            texts,
+            vec![
+                "Title across the columns",
+                "left 0",
+                "left 1",
+                "left 2",
+                "left 3",
+                "right 0",
+                "right 1",
+                "right 2",
+                "right 3",
+                "Footer across the columns",
+            ]
+        );
+    }
+}
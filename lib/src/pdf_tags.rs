@@ -0,0 +1,813 @@
+//! Structure tree of a tagged PDF (PDF/UA), built from the tags of the typst source.
+//!
+//! The source marks where each tag opens and closes with invisible metadata (see
+//! [`crate::typst::layout_tagged`]). The markers are found again in the frames of the
+//! layout, walked in the order typst writes the pages: each text and image drawn is then
+//! a marked content sequence of the tag open around it, each link annotation an object of
+//! a `Link` element. Shapes, list markers, table lines and the content of the `Artifact`
+//! tags are artifacts, as are the tags shown again such as the header row of a table on
+//! the next pages.
+
+use std::collections::HashMap;
+
+use lopdf::content::{Content, Operation};
+use lopdf::{dictionary, Dictionary, Document as PdfDocument, Object, ObjectId};
+use typst::foundations::Value;
+use typst::introspection::{Meta, MetadataElem};
+use typst::layout::{Frame, FrameItem};
+
+use crate::pdf_form::text_object;
+use crate::typst::{Tag, TAG_END, TAG_MARKER};
+
+/// Roles grouping other tags, the content drawn in them (list markers, table lines) being
+/// artifacts
+const GROUPING_ROLES: [&str; 2] = ["L", "Table"];
+
+/// What typst draws on a page, in the order of its content stream
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    Text,
+    Shape,
+    Image,
+}
+
+/// Structure element what is drawn belongs to
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Owner {
+    /// Not part of the structure, such as the page headers
+    Artifact,
+    /// Outside of any tag
+    Document,
+    Tag(usize),
+}
+
+/// Kid of a structure element, in reading order
+#[derive(Debug, Clone, Copy)]
+enum Kid {
+    /// Marked content of a page
+    Content {
+        page: usize,
+        mcid: i64,
+    },
+    /// Link annotation of a page, by its index in the annotations of the page
+    Link {
+        page: usize,
+        annotation: usize,
+    },
+    Tag(usize),
+}
+
+/// Text, shape or image drawn, with the role and identifier of its marked content, none
+/// for an artifact
+#[derive(Debug)]
+struct Drawing {
+    kind: Kind,
+    marked: Option<(String, i64)>,
+}
+
+/// Walk through the frames of the pages
+struct Collector<'a> {
+    tags: &'a [Tag],
+    /// Tags already opened, the ones opened again being artifacts
+    seen: Vec<bool>,
+    /// Tags open at this point, none for an artifact
+    open: Vec<Option<usize>>,
+    /// Drawings of each page
+    pages: Vec<Vec<Drawing>>,
+    /// Number of links of the current page
+    links: usize,
+    document: Vec<Kid>,
+    kids: Vec<Vec<Kid>>,
+}
+
+impl Collector<'_> {
+    fn owner(&self) -> Owner {
+        match self.open.last() {
+            None => Owner::Document,
+            Some(None) => Owner::Artifact,
+            Some(Some(index)) => Owner::Tag(*index),
+        }
+    }
+
+    fn kids_mut(&mut self, owner: Owner) -> Option<&mut Vec<Kid>> {
+        match owner {
+            Owner::Artifact => None,
+            Owner::Document => Some(&mut self.document),
+            Owner::Tag(index) => Some(&mut self.kids[index]),
+        }
+    }
+
+    fn page(&mut self, frame: &Frame) {
+        self.pages.push(Vec::new());
+        self.links = 0;
+        self.frame(frame);
+    }
+
+    /// Follows the items as typst writes them in the content stream
+    fn frame(&mut self, frame: &Frame) {
+        for (_, item) in frame.items() {
+            match item {
+                FrameItem::Group(group) => self.frame(&group.frame),
+                FrameItem::Text(_) => self.draw(Kind::Text),
+                FrameItem::Shape(shape, _) => {
+                    // nothing is written for a shape neither filled nor stroked
+                    let stroked = shape
+                        .stroke
+                        .as_ref()
+                        .is_some_and(|stroke| stroke.thickness.to_pt() as f32 > 0.0);
+                    if shape.fill.is_some() || stroked {
+                        self.draw(Kind::Shape);
+                    }
+                }
+                FrameItem::Image(..) => self.draw(Kind::Image),
+                FrameItem::Meta(Meta::Link(_), _) => self.link(),
+                FrameItem::Meta(Meta::Elem(content), _) => {
+                    if let Some(Value::Str(value)) = content
+                        .to_packed::<MetadataElem>()
+                        .map(|metadata| &metadata.value)
+                    {
+                        self.marker(value.as_str());
+                    }
+                }
+                FrameItem::Meta(..) => {}
+            }
+        }
+    }
+
+    fn draw(&mut self, kind: Kind) {
+        let owner = match self.owner() {
+            Owner::Tag(index) if GROUPING_ROLES.contains(&self.tags[index].role.as_str()) => {
+                Owner::Artifact
+            }
+            _ if kind == Kind::Shape => Owner::Artifact,
+            owner => owner,
+        };
+        let page = self.pages.len() - 1;
+        let mcid = self.pages[page]
+            .iter()
+            .filter(|drawing| drawing.marked.is_some())
+            .count() as i64;
+        let role = match owner {
+            Owner::Tag(index) => self.tags[index].role.clone(),
+            _ => "Span".to_string(),
+        };
+        let marked = self.kids_mut(owner).map(|kids| {
+            kids.push(Kid::Content { page, mcid });
+            (role, mcid)
+        });
+        self.pages[page].push(Drawing { kind, marked });
+    }
+
+    fn link(&mut self) {
+        let page = self.pages.len() - 1;
+        let annotation = self.links;
+        self.links += 1;
+        if let Some(kids) = self.kids_mut(self.owner()) {
+            kids.push(Kid::Link { page, annotation });
+        }
+    }
+
+    fn marker(&mut self, value: &str) {
+        if value == TAG_END {
+            self.open.pop();
+            return;
+        }
+        let Some(index) = value
+            .strip_prefix(TAG_MARKER)
+            .and_then(|index| index.parse::<usize>().ok())
+        else {
+            return;
+        };
+        let structure = self.owner() != Owner::Artifact
+            && index < self.tags.len()
+            && !self.seen[index]
+            && self.tags[index].role != "Artifact";
+        if !structure {
+            self.open.push(None);
+            return;
+        }
+        self.seen[index] = true;
+        let parent = match self.tags[index].parent {
+            Some(parent) if self.seen[parent] => Owner::Tag(parent),
+            _ => Owner::Document,
+        };
+        if let Some(kids) = self.kids_mut(parent) {
+            kids.push(Kid::Tag(index));
+        }
+        self.open.push(Some(index));
+    }
+}
+
+/// Tags the PDF typst wrote from the layout: marked content in the pages, structure tree
+/// in the catalog
+pub fn tag(
+    pdf: &mut PdfDocument,
+    layout: &typst::model::Document,
+    tags: &[Tag],
+) -> anyhow::Result<()> {
+    let mut collector = Collector {
+        tags,
+        seen: vec![false; tags.len()],
+        open: Vec::new(),
+        pages: Vec::new(),
+        links: 0,
+        document: Vec::new(),
+        kids: vec![Vec::new(); tags.len()],
+    };
+    for page in &layout.pages {
+        collector.page(&page.frame);
+    }
+
+    let page_ids: Vec<ObjectId> = pdf.get_pages().into_values().collect();
+    if page_ids.len() != collector.pages.len() {
+        anyhow::bail!("the PDF does not have the pages of its layout");
+    }
+    let mut annotations = Vec::new();
+    for (page, drawings) in page_ids.iter().zip(&collector.pages) {
+        mark_content(pdf, *page, drawings)?;
+        annotations.push(annotation_ids(pdf, *page)?);
+    }
+
+    // the tags with nothing drawn are left out, a tag being opened before the ones in it
+    let mut used = vec![false; tags.len()];
+    for index in (0..tags.len()).rev() {
+        used[index] = collector.kids[index].iter().any(|kid| match kid {
+            Kid::Tag(child) => used[*child],
+            _ => true,
+        });
+    }
+
+    let root_id = pdf.new_object_id();
+    let mut builder = Builder {
+        pdf,
+        tags,
+        kids: &collector.kids,
+        used,
+        page_ids: &page_ids,
+        annotations: &annotations,
+        content_parents: collector
+            .pages
+            .iter()
+            .map(|drawings| {
+                let marked = drawings.iter().filter(|d| d.marked.is_some()).count();
+                vec![Object::Null; marked]
+            })
+            .collect(),
+        link_parents: Vec::new(),
+    };
+    let document_id = builder.element("Document", None, &collector.document, root_id)?;
+
+    let mut numbers = Vec::new();
+    for (page, parents) in builder.content_parents.into_iter().enumerate() {
+        numbers.push(Object::Integer(page as i64));
+        numbers.push(Object::Array(parents));
+    }
+    let next_key = page_ids.len() + builder.link_parents.len();
+    for (index, parent) in builder.link_parents.into_iter().enumerate() {
+        numbers.push(Object::Integer((page_ids.len() + index) as i64));
+        numbers.push(parent.into());
+    }
+    pdf.objects.insert(
+        root_id,
+        dictionary! {
+            "Type" => "StructTreeRoot",
+            "K" => document_id,
+            "ParentTree" => dictionary! { "Nums" => numbers },
+            "ParentTreeNextKey" => next_key as i64,
+        }
+        .into(),
+    );
+
+    for (index, page) in page_ids.iter().enumerate() {
+        let page = pdf.get_dictionary_mut(*page)?;
+        page.set("StructParents", index as i64);
+        // the annotations in the order of the structure
+        page.set("Tabs", "S");
+    }
+    let catalog_id = pdf.trailer.get(b"Root")?.as_reference()?;
+    let catalog = pdf.get_dictionary_mut(catalog_id)?;
+    catalog.set("StructTreeRoot", root_id);
+    catalog.set("MarkInfo", dictionary! { "Marked" => true });
+    Ok(())
+}
+
+/// Writer of the structure elements
+struct Builder<'a> {
+    pdf: &'a mut PdfDocument,
+    tags: &'a [Tag],
+    kids: &'a [Vec<Kid>],
+    used: Vec<bool>,
+    page_ids: &'a [ObjectId],
+    annotations: &'a [Vec<ObjectId>],
+    /// Structure element of each marked content of each page
+    content_parents: Vec<Vec<Object>>,
+    /// Structure element of each annotation tagged, in the order of their keys
+    link_parents: Vec<ObjectId>,
+}
+
+impl Builder<'_> {
+    /// Writes the element of a tag, or of the document without tag, with the elements in it
+    fn element(
+        &mut self,
+        role: &str,
+        tag: Option<usize>,
+        kids: &[Kid],
+        parent: ObjectId,
+    ) -> anyhow::Result<ObjectId> {
+        let id = self.pdf.new_object_id();
+        let alt = tag.and_then(|index| self.tags[index].alt.as_deref());
+        let mut objects = Vec::new();
+        // the cells of a table are grouped by row
+        let mut rows: Vec<(usize, ObjectId, Vec<Object>)> = Vec::new();
+        for kid in kids {
+            match *kid {
+                Kid::Content { page, mcid } => {
+                    self.content_parents[page][mcid as usize] = id.into();
+                    objects.push(
+                        dictionary! {
+                            "Type" => "MCR",
+                            "Pg" => self.page_ids[page],
+                            "MCID" => mcid,
+                        }
+                        .into(),
+                    );
+                }
+                Kid::Link { page, annotation } => {
+                    let annotation_id = self.annotations[page]
+                        .get(annotation)
+                        .copied()
+                        .ok_or_else(|| anyhow::anyhow!("a link of the layout has no annotation"))?;
+                    if role == "Link" {
+                        self.annotation(annotation_id, page, id, alt)?;
+                        objects.push(self.object_reference(annotation_id, page).into());
+                    } else {
+                        // a link of its own for the annotations outside of a link tag
+                        let link_id = self.pdf.new_object_id();
+                        self.annotation(annotation_id, page, link_id, None)?;
+                        let reference = self.object_reference(annotation_id, page);
+                        self.pdf.objects.insert(
+                            link_id,
+                            dictionary! {
+                                "Type" => "StructElem",
+                                "S" => "Link",
+                                "P" => id,
+                                "K" => vec![reference.into()],
+                            }
+                            .into(),
+                        );
+                        objects.push(link_id.into());
+                    }
+                }
+                Kid::Tag(child) if self.used[child] => {
+                    let child_tag = &self.tags[child];
+                    match child_tag.row {
+                        Some(row) if role == "Table" => {
+                            if rows.last().map(|(last, ..)| *last) != Some(row) {
+                                let row_id = self.pdf.new_object_id();
+                                objects.push(row_id.into());
+                                rows.push((row, row_id, Vec::new()));
+                            }
+                            let row_id = rows[rows.len() - 1].1;
+                            let role = child_tag.role.clone();
+                            let kids = self.kids;
+                            let cell_id = self.element(&role, Some(child), &kids[child], row_id)?;
+                            rows.last_mut().unwrap().2.push(cell_id.into());
+                        }
+                        _ => {
+                            let role = child_tag.role.clone();
+                            let kids = self.kids;
+                            let child_id = self.element(&role, Some(child), &kids[child], id)?;
+                            objects.push(child_id.into());
+                        }
+                    }
+                }
+                Kid::Tag(_) => {}
+            }
+        }
+        for (_, row_id, cells) in rows {
+            self.pdf.objects.insert(
+                row_id,
+                dictionary! {
+                    "Type" => "StructElem",
+                    "S" => "TR",
+                    "P" => id,
+                    "K" => cells,
+                }
+                .into(),
+            );
+        }
+
+        let mut element = dictionary! {
+            "Type" => "StructElem",
+            "S" => Object::Name(role.as_bytes().to_vec()),
+            "P" => parent,
+            "K" => objects,
+        };
+        if let Some(alt) = alt.filter(|_| role != "Link") {
+            element.set("Alt", text_object(alt));
+        }
+        if role == "TH" {
+            element.set("A", dictionary! { "O" => "Table", "Scope" => "Column" });
+        }
+        self.pdf.objects.insert(id, element.into());
+        Ok(id)
+    }
+
+    fn object_reference(&self, annotation: ObjectId, page: usize) -> Dictionary {
+        dictionary! {
+            "Type" => "OBJR",
+            "Pg" => self.page_ids[page],
+            "Obj" => annotation,
+        }
+    }
+
+    /// Gives the annotation its structure element, and the description PDF/UA asks for: the
+    /// alternative text of the link, else its URL
+    fn annotation(
+        &mut self,
+        annotation: ObjectId,
+        page: usize,
+        parent: ObjectId,
+        alt: Option<&str>,
+    ) -> anyhow::Result<()> {
+        let key = self.page_ids.len() + self.link_parents.len();
+        self.link_parents.push(parent);
+        let dict = self.pdf.get_dictionary_mut(annotation)?;
+        dict.set("StructParent", key as i64);
+        if dict.get(b"Contents").is_err() {
+            let uri = dict
+                .get(b"A")
+                .and_then(Object::as_dict)
+                .and_then(|action| action.get(b"URI"))
+                .and_then(Object::as_str)
+                .map(|uri| String::from_utf8_lossy(uri).into_owned())
+                .ok();
+            let contents = alt
+                .map(str::to_string)
+                .or(uri)
+                .unwrap_or_else(|| format!("Link on page {}", page + 1));
+            dict.set("Contents", text_object(&contents));
+        }
+        Ok(())
+    }
+}
+
+/// Annotations of the page as objects of their own, typst writing them in the page
+fn annotation_ids(pdf: &mut PdfDocument, page: ObjectId) -> anyhow::Result<Vec<ObjectId>> {
+    let annotations = match pdf.get_dictionary(page)?.get(b"Annots") {
+        Ok(Object::Array(annotations)) => annotations.clone(),
+        _ => return Ok(Vec::new()),
+    };
+    let ids: Vec<ObjectId> = annotations
+        .into_iter()
+        .map(|annotation| match annotation {
+            Object::Reference(id) => id,
+            annotation => pdf.add_object(annotation),
+        })
+        .collect();
+    let references: Vec<Object> = ids.iter().map(|id| (*id).into()).collect();
+    pdf.get_dictionary_mut(page)?.set("Annots", references);
+    Ok(ids)
+}
+
+/// Wraps what is drawn on the page in marked content sequences, of a structure element or
+/// of an artifact
+fn mark_content(pdf: &mut PdfDocument, page: ObjectId, drawings: &[Drawing]) -> anyhow::Result<()> {
+    let content = Content::decode(&pdf.get_page_content(page)?)?;
+    let spans = drawn_spans(&content.operations);
+    if spans.len() != drawings.len()
+        || spans
+            .iter()
+            .zip(drawings)
+            .any(|((kind, ..), drawing)| *kind != drawing.kind)
+    {
+        anyhow::bail!("the content of a page does not match its layout");
+    }
+
+    let mut starts = HashMap::new();
+    let mut ends = HashMap::new();
+    for ((_, start, end), drawing) in spans.iter().zip(drawings) {
+        starts.insert(*start, drawing);
+        ends.insert(*end, ());
+    }
+    let mut operations = Vec::with_capacity(content.operations.len() + 2 * drawings.len());
+    for (index, operation) in content.operations.into_iter().enumerate() {
+        if let Some(drawing) = starts.get(&index) {
+            operations.push(match &drawing.marked {
+                Some((role, mcid)) => Operation::new(
+                    "BDC",
+                    vec![
+                        Object::Name(role.as_bytes().to_vec()),
+                        dictionary! { "MCID" => *mcid }.into(),
+                    ],
+                ),
+                None => Operation::new("BMC", vec![Object::Name(b"Artifact".to_vec())]),
+            });
+        }
+        operations.push(operation);
+        if ends.contains_key(&index) {
+            operations.push(Operation::new("EMC", Vec::new()));
+        }
+    }
+    pdf.change_page_content(page, Content { operations }.encode()?)?;
+    Ok(())
+}
+
+/// Kind, first and last operation of what is drawn: a text object, an image or a path
+/// painted. A marked content sequence cannot start in a path, before its painting.
+fn drawn_spans(operations: &[Operation]) -> Vec<(Kind, usize, usize)> {
+    let mut spans = Vec::new();
+    let mut text_start = None;
+    let mut path_start = None;
+    for (index, operation) in operations.iter().enumerate() {
+        match operation.operator.as_str() {
+            "BT" => text_start = Some(index),
+            "ET" => {
+                if let Some(start) = text_start.take() {
+                    spans.push((Kind::Text, start, index));
+                }
+            }
+            "Do" => spans.push((Kind::Image, index, index)),
+            "m" | "l" | "c" | "v" | "y" | "h" | "re" => {
+                path_start.get_or_insert(index);
+            }
+            "S" | "s" | "f" | "F" | "f*" | "B" | "B*" | "b" | "b*" => {
+                spans.push((Kind::Shape, path_start.take().unwrap_or(index), index));
+            }
+            // end of a clipping path
+            "n" => path_start = None,
+            _ => {}
+        }
+    }
+    spans
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use lopdf::content::Operation;
+    use lopdf::Document as PdfDocument;
+
+    use super::{drawn_spans, Kind};
+    use crate::core::*;
+    use crate::pdf::Transformer;
+
+    fn text(text: &str) -> Element {
+        Element::Text {
+            text: text.to_string(),
+            size: 10,
+        }
+    }
+
+    fn archival(first_page_header: Option<Vec<Element>>) -> GenerateOptions {
+        GenerateOptions {
+            pdf: PdfOptions {
+                archival: true,
+                first_page_header,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    /// Structure tree of the PDF from its document element
+    fn tree(pdf: &PdfDocument) -> anyhow::Result<String> {
+        let root = pdf.get_dictionary(pdf.catalog()?.get(b"StructTreeRoot")?.as_reference()?)?;
+        structure(pdf, root.get(b"K")?)
+    }
+
+    /// Roles of the structure element and of the ones in it, `Link` with its annotation
+    fn structure(pdf: &PdfDocument, object: &lopdf::Object) -> anyhow::Result<String> {
+        let element = pdf.get_dictionary(object.as_reference()?)?;
+        let mut kids = Vec::new();
+        for kid in element.get(b"K")?.as_array()? {
+            match kid {
+                lopdf::Object::Reference(_) => kids.push(structure(pdf, kid)?),
+                lopdf::Object::Dictionary(kid) if kid.get(b"Type")?.as_name_str()? == "OBJR" => {
+                    let annotation = pdf.get_dictionary(kid.get(b"Obj")?.as_reference()?)?;
+                    assert!(annotation.get(b"StructParent").is_ok());
+                    kids.push("annotation".to_string());
+                }
+                _ => {}
+            }
+        }
+        let role = element.get(b"S")?.as_name_str()?;
+        Ok(if kids.is_empty() {
+            role.to_string()
+        } else {
+            format!("{role}({})", kids.join(" "))
+        })
+    }
+
+    #[test]
+    fn test_structure() -> anyhow::Result<()> {
+        let item = |element: Element| ListItem { element };
+        let cell = |text: &str| TableCell {
+            element: Element::Text {
+                text: text.to_string(),
+                size: 10,
+            },
+        };
+        let document = Document::new(vec![
+            Element::Header {
+                level: 2,
+                text: "Report".to_string(),
+            },
+            Element::Paragraph {
+                elements: vec![
+                    text("See "),
+                    Element::Hyperlink {
+                        url: "https://example.com".to_string(),
+                        elements: vec![text("the site")],
+                        alt: "Example".to_string(),
+                        size: 10,
+                    },
+                ],
+                direction: TextDirection::default(),
+            },
+            Element::List {
+                elements: vec![
+                    item(text("one")),
+                    item(Element::List {
+                        elements: vec![item(text("nested"))],
+                        numbered: false,
+                        start: 1,
+                        numbering: NumberingStyle::default(),
+                    }),
+                    item(text("two")),
+                ],
+                numbered: false,
+                start: 1,
+                numbering: NumberingStyle::default(),
+            },
+            Element::Table {
+                headers: vec![TableHeader {
+                    element: text("Name"),
+                    width: 10.0,
+                }],
+                rows: vec![
+                    TableRow {
+                        cells: vec![cell("Alice")],
+                    },
+                    TableRow {
+                        cells: vec![cell("Bob")],
+                    },
+                ],
+            },
+            Element::Image(ImageData::new(
+                Bytes::from(std::fs::read("test/data/picture.png")?),
+                "".to_string(),
+                "A picture".to_string(),
+                "png".to_string(),
+                "".to_string(),
+                ImageDimension::default(),
+            )),
+        ]);
+        let generated = Transformer::generate_with_options(&document, &archival(None))?;
+        let pdf = PdfDocument::load_mem(&generated)?;
+        assert_eq!(
+            tree(&pdf)?,
+            "Document(H2 P(Link(annotation)) L(LI(LBody(L(LI(LBody)))) LI(LBody)) \
+            Table(TR(TH) TR(TD) TR(TD)) Figure)"
+        );
+        let figure = pdf
+            .objects
+            .values()
+            .filter_map(|object| object.as_dict().ok())
+            .find(|element| {
+                element.get(b"S").and_then(|role| role.as_name_str()).ok() == Some("Figure")
+            });
+        assert_eq!(figure.unwrap().get(b"Alt")?.as_str()?, b"A picture");
+        Ok(())
+    }
+
+    #[test]
+    fn test_artifacts() -> anyhow::Result<()> {
+        let document = Document::new(vec![
+            Element::Header {
+                level: 1,
+                text: "Report".to_string(),
+            },
+            Element::Drawing {
+                width: 100.0,
+                height: 50.0,
+                shapes: vec![Shape::line(0.0, 0.0, 100.0, 50.0)],
+            },
+        ]);
+        let options = archival(Some(vec![text("Header")]));
+        let generated = Transformer::generate_with_options(&document, &options)?;
+        let pdf = PdfDocument::load_mem(&generated)?;
+        assert_eq!(tree(&pdf)?, "Document(H1)");
+
+        // the page header and the shapes are artifacts, the header text marked content
+        let content =
+            String::from_utf8_lossy(&pdf.get_page_content(pdf.page_iter().next().unwrap())?)
+                .into_owned();
+        assert_eq!(content.matches("/Artifact BMC").count(), 2, "{}", content);
+        assert!(content.contains("/H1 <</MCID 0>> BDC"));
+        assert_eq!(content.matches("BDC").count(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_links() -> anyhow::Result<()> {
+        let link = |url: &str, alt: &str| Element::Hyperlink {
+            url: url.to_string(),
+            elements: vec![text("site")],
+            alt: alt.to_string(),
+            size: 10,
+        };
+        let document = Document::new(vec![Element::Paragraph {
+            elements: vec![
+                link("https://example.com", "Example"),
+                text(" and "),
+                link("https://example.org", ""),
+            ],
+            direction: TextDirection::default(),
+        }]);
+        let generated = Transformer::generate_with_options(&document, &archival(None))?;
+        let pdf = PdfDocument::load_mem(&generated)?;
+        assert_eq!(
+            tree(&pdf)?,
+            "Document(P(Link(annotation) Link(annotation)))"
+        );
+
+        // the description of a link is its alternative text, else its URL
+        let page = pdf.get_dictionary(pdf.page_iter().next().unwrap())?;
+        assert_eq!(page.get(b"Tabs")?.as_name_str()?, "S");
+        let mut contents = Vec::new();
+        for annotation in page.get(b"Annots")?.as_array()? {
+            let annotation = pdf.get_dictionary(annotation.as_reference()?)?;
+            contents
+                .push(String::from_utf8_lossy(annotation.get(b"Contents")?.as_str()?).into_owned());
+        }
+        assert_eq!(contents, ["Example", "https://example.org"]);
+
+        // the parent tree holds the page then the two annotations
+        let root = pdf.get_dictionary(pdf.catalog()?.get(b"StructTreeRoot")?.as_reference()?)?;
+        assert_eq!(root.get(b"ParentTreeNextKey")?.as_i64()?, 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_table_on_pages() -> anyhow::Result<()> {
+        let rows = (0..80)
+            .map(|row| TableRow {
+                cells: vec![TableCell {
+                    element: text(&format!("Row {row}")),
+                }],
+            })
+            .collect();
+        let document = Document::new(vec![Element::Table {
+            headers: vec![TableHeader {
+                element: text("Name"),
+                width: 10.0,
+            }],
+            rows,
+        }]);
+        let generated = Transformer::generate_with_options(&document, &archival(None))?;
+        let pdf = PdfDocument::load_mem(&generated)?;
+        assert!(pdf.get_pages().len() > 1);
+
+        // the header row shown again on the next pages is an artifact
+        let expected = format!("Document(Table(TR(TH){}))", " TR(TD)".repeat(80));
+        assert_eq!(tree(&pdf)?, expected);
+        let second_page = pdf.page_iter().nth(1).unwrap();
+        let content = String::from_utf8_lossy(&pdf.get_page_content(second_page)?).into_owned();
+        assert!(!content.contains("/TH "), "{}", content);
+        Ok(())
+    }
+
+    #[test]
+    fn test_markdown_document() -> anyhow::Result<()> {
+        let markdown = std::fs::read("test/data/document.md")?;
+        let document = crate::markdown::Transformer::parse_with_loader(
+            &Bytes::from(markdown),
+            disk_image_loader("test/data"),
+        )?;
+        let generated = Transformer::generate_with_options(&document, &archival(None))?;
+        let pdf = PdfDocument::load_mem(&generated)?;
+        assert!(tree(&pdf)?.starts_with("Document("));
+        Ok(())
+    }
+
+    #[test]
+    fn test_drawn_spans() {
+        let operation = |operator: &str| Operation::new(operator, Vec::new());
+        let operations: Vec<Operation> = [
+            "q", "BT", "Tj", "ET", "re", "W", "n", "m", "l", "S", "Do", "Q", "re", "f",
+        ]
+        .into_iter()
+        .map(operation)
+        .collect();
+        assert_eq!(
+            drawn_spans(&operations),
+            [
+                (Kind::Text, 1, 3),
+                (Kind::Shape, 7, 9),
+                (Kind::Image, 10, 10),
+                (Kind::Shape, 12, 13),
+            ]
+        );
+    }
+}
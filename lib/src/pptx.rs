@@ -0,0 +1,1104 @@
+//! PowerPoint presentations (PPTX), a slide per section of the document
+//!
+//! Every header of level 1 or 2 starts a slide and is its title, the blocks under it are
+//! laid out one below the other: paragraphs and list items with their bullets in text
+//! boxes, tables and images. Deeper headers are bold paragraphs, footnotes are left out.
+//! The document title, when it has one, is the title of a first slide holding the blocks
+//! before the first header. A slide without blocks is a section slide, its title in the
+//! middle; blocks that do not fit continue on a slide of the same title. The sizes of the
+//! text are estimated from the number of characters. PPTX presentations are generated only.
+
+use std::collections::BTreeSet;
+use std::fmt::Write as _;
+use std::io::{Cursor, Write};
+
+use bytes::Bytes;
+use log::warn;
+use quick_xml::escape::escape;
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::core::{
+    Band, Document, Element, ImageData, ImageType, Metadata, NumberingStyle, TableHeader, TableRow,
+    TextStyle, TransformerTrait, DEFAULT_IMAGE_SIZE,
+};
+
+pub struct Transformer;
+
+/// Namespaces declared on the root of the slides, layouts and masters
+const NAMESPACES: &str = concat!(
+    r#"xmlns:a="http://schemas.openxmlformats.org/drawingml/2006/main" "#,
+    r#"xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships" "#,
+    r#"xmlns:p="http://schemas.openxmlformats.org/presentationml/2006/main""#,
+);
+
+/// Prefix of the types of the relationships between the parts
+const RELATIONSHIPS: &str = "http://schemas.openxmlformats.org/officeDocument/2006/relationships";
+
+/// Prefix of the content types of the presentation parts
+const PRESENTATION_ML: &str = "application/vnd.openxmlformats-officedocument.presentationml";
+
+/// Group of the shapes of a slide, the first element of its shape tree
+const GROUP: &str =
+    r#"<p:nvGrpSpPr><p:cNvPr id="1" name=""/><p:cNvGrpSpPr/><p:nvPr/></p:nvGrpSpPr><p:grpSpPr/>"#;
+
+// sizes in English Metric Units, 914400 to the inch
+
+/// 16:9 slides, 13.33 by 7.5 inches
+const SLIDE_WIDTH: i64 = 12_192_000;
+const SLIDE_HEIGHT: i64 = 6_858_000;
+const MARGIN: i64 = 457_200;
+const TITLE_TOP: i64 = 365_760;
+const TITLE_HEIGHT: i64 = 1_005_840;
+const CONTENT_TOP: i64 = 1_554_480;
+const CONTENT_BOTTOM: i64 = SLIDE_HEIGHT - MARGIN;
+const CONTENT_WIDTH: i64 = SLIDE_WIDTH - 2 * MARGIN;
+/// Space between the blocks of a slide
+const BLOCK_SPACING: i64 = 182_880;
+/// Inner margins of a text box, left and right or top and bottom
+const TEXT_INSETS: i64 = 182_880;
+/// Inner margins of a table cell, left and right or top and bottom
+const CELL_INSETS: i64 = 91_440;
+/// Indentation of a level of a list
+const LIST_INDENT: i64 = 342_900;
+const EMU_PER_POINT: i64 = 12_700;
+const EMU_PER_PIXEL: i64 = 9_525;
+
+// text sizes in points
+
+const TITLE_SIZE: u32 = 32;
+const SECTION_TITLE_SIZE: u32 = 44;
+const TEXT_SIZE: u32 = 20;
+const HEADING_SIZE: u32 = 24;
+const CODE_SIZE: u32 = 16;
+const TABLE_TEXT_SIZE: u32 = 14;
+
+/// Space before a paragraph, in points
+const PARAGRAPH_SPACING: u32 = 6;
+
+/// Bullets of the levels of bulleted lists, repeated past the third level
+const BULLETS: [&str; 3] = ["\u{2022}", "\u{2013}", "\u{25AA}"];
+
+/// `a:buAutoNum` scheme of a numbering
+fn numbering_scheme(numbering: &NumberingStyle) -> &'static str {
+    match numbering {
+        NumberingStyle::Decimal => "arabicPeriod",
+        NumberingStyle::LowerAlpha => "alphaLcPeriod",
+        NumberingStyle::UpperAlpha => "alphaUcPeriod",
+        NumberingStyle::LowerRoman => "romanLcPeriod",
+        NumberingStyle::UpperRoman => "romanUcPeriod",
+    }
+}
+
+/// Estimated height of the lines of text of the size in a box of the width, about half of
+/// the size being the width of a character
+fn text_height(text: &str, size: u32, width: i64) -> i64 {
+    let size = i64::from(size) * EMU_PER_POINT;
+    let per_line = (width / (size / 2)).max(1) as usize;
+    let lines: usize = text
+        .split('\n')
+        .map(|line| line.chars().count().div_ceil(per_line).max(1))
+        .sum();
+    lines as i64 * size * 6 / 5
+}
+
+/// Text for `a:t`, escaped and without the characters not allowed in XML
+fn escape_text(text: &str) -> String {
+    escape(&text.replace(|c: char| c.is_control() && c != '\t', "")).into_owned()
+}
+
+/// `a:off` and `a:ext` of a shape
+fn frame(x: i64, y: i64, width: i64, height: i64) -> String {
+    format!(r#"<a:off x="{x}" y="{y}"/><a:ext cx="{width}" cy="{height}"/>"#)
+}
+
+/// Text box of the paragraphs, `anchor` being the vertical alignment (`t`, `ctr`, `b`)
+fn text_box(id: usize, name: &str, frame: &str, anchor: &str, paragraphs: &str) -> String {
+    format!(
+        r#"<p:sp><p:nvSpPr><p:cNvPr id="{id}" name="{name} {id}"/><p:cNvSpPr txBox="1"/><p:nvPr/></p:nvSpPr><p:spPr><a:xfrm>{frame}</a:xfrm><a:prstGeom prst="rect"><a:avLst/></a:prstGeom><a:noFill/></p:spPr><p:txBody><a:bodyPr wrap="square" rtlCol="0" anchor="{anchor}"><a:normAutofit/></a:bodyPr><a:lstStyle/>{paragraphs}</p:txBody></p:sp>"#
+    )
+}
+
+/// Format of the runs of text
+#[derive(Debug, Clone, Copy)]
+struct RunFormat {
+    size: u32,
+    bold: bool,
+    italic: bool,
+    strikethrough: bool,
+    code: bool,
+}
+
+impl RunFormat {
+    fn new(size: u32) -> RunFormat {
+        RunFormat {
+            size,
+            bold: false,
+            italic: false,
+            strikethrough: false,
+            code: false,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Slide {
+    title: String,
+    /// Shapes of the blocks, below the title
+    shapes: String,
+    /// Types and targets of the relationships, the first one to the layout
+    relationships: Vec<(&'static str, String)>,
+    shape_count: usize,
+}
+
+impl Slide {
+    fn new(title: String) -> Slide {
+        Slide {
+            title,
+            shapes: String::new(),
+            relationships: vec![(
+                "slideLayout",
+                "../slideLayouts/slideLayout1.xml".to_string(),
+            )],
+            shape_count: 0,
+        }
+    }
+
+    /// Id of a new relationship of the slide
+    fn relationship(&mut self, kind: &'static str, target: String) -> String {
+        self.relationships.push((kind, target));
+        format!("rId{}", self.relationships.len())
+    }
+
+    /// Id of a new shape, after the group of the shapes and the title
+    fn shape_id(&mut self) -> usize {
+        self.shape_count += 1;
+        self.shape_count + 2
+    }
+}
+
+/// Lays the blocks out on the slides, collecting the images
+struct Writer<'a> {
+    document: &'a Document,
+    slides: Vec<Slide>,
+    /// Path in the archive, media type and content of the images
+    images: Vec<(String, String, Bytes)>,
+    /// Top of the free space of the current slide
+    top: i64,
+    /// Paragraphs of the text box being filled and their height
+    paragraphs: String,
+    paragraphs_height: i64,
+}
+
+impl<'a> Writer<'a> {
+    fn new(document: &'a Document) -> Writer<'a> {
+        let title = document.metadata.title.clone().unwrap_or_default();
+        Writer {
+            document,
+            slides: vec![Slide::new(title)],
+            images: vec![],
+            top: CONTENT_TOP,
+            paragraphs: String::new(),
+            paragraphs_height: 0,
+        }
+    }
+
+    fn slide(&mut self) -> &mut Slide {
+        self.slides.last_mut().expect("the first slide")
+    }
+
+    fn start_slide(&mut self, title: String) {
+        self.flush_text();
+        self.slides.push(Slide::new(title));
+        self.top = CONTENT_TOP;
+    }
+
+    /// Continues on a slide of the same title
+    fn continue_slide(&mut self) {
+        let title = self.slide().title.clone();
+        self.start_slide(title);
+    }
+
+    /// Whether a block of the height fits below the blocks of the current slide, always the
+    /// case on an empty slide
+    fn fits(&self, height: i64) -> bool {
+        (self.top == CONTENT_TOP && self.paragraphs.is_empty())
+            || self.top + height <= CONTENT_BOTTOM
+    }
+
+    /// Writes the text box of the paragraphs added since the last block
+    fn flush_text(&mut self) {
+        if self.paragraphs.is_empty() {
+            return;
+        }
+        let height = self.paragraphs_height + TEXT_INSETS;
+        let paragraphs = std::mem::take(&mut self.paragraphs);
+        let frame = frame(MARGIN, self.top, CONTENT_WIDTH, height);
+        let slide = self.slide();
+        let id = slide.shape_id();
+        slide
+            .shapes
+            .push_str(&text_box(id, "Text", &frame, "t", &paragraphs));
+        self.top += height + BLOCK_SPACING;
+        self.paragraphs_height = 0;
+    }
+
+    fn block(&mut self, element: &Element) {
+        match element {
+            Element::Header { level, text } if *level <= 2 => self.start_slide(text.clone()),
+            Element::Header { text, .. } => {
+                let format = RunFormat {
+                    bold: true,
+                    ..RunFormat::new(HEADING_SIZE)
+                };
+                let text = Element::Text {
+                    text: text.clone(),
+                    size: 8,
+                };
+                self.paragraph(&[&text], format, 0, "<a:buNone/>");
+            }
+            Element::Paragraph { elements, .. } => {
+                // the images of a paragraph follow its text
+                let (images, inline): (Vec<&Element>, Vec<&Element>) = elements
+                    .iter()
+                    .partition(|element| matches!(element, Element::Image(_)));
+                if inline
+                    .iter()
+                    .any(|element| !element.plain_text().trim().is_empty())
+                {
+                    self.paragraph(&inline, RunFormat::new(TEXT_SIZE), 0, "<a:buNone/>");
+                }
+                for image in images {
+                    self.block(image);
+                }
+            }
+            Element::List { .. } => self.list(element, 0),
+            Element::TableOfContents { max_level } => {
+                let toc = self.document.build_toc(*max_level);
+                self.list(&toc, 0);
+            }
+            Element::Table { headers, rows } => self.table(headers, rows),
+            Element::Image(image) => self.image(image),
+            Element::CodeBlock { code, .. } => self.code_block(code),
+            Element::Container { elements, .. } => {
+                for element in elements {
+                    self.block(element);
+                }
+            }
+            Element::Attributed { element, .. } => self.block(element),
+            Element::LineBreak
+            | Element::Bookmark { .. }
+            | Element::Footnote { .. }
+            | Element::Raw { .. } => {}
+            element => self.paragraph(&[element], RunFormat::new(TEXT_SIZE), 0, "<a:buNone/>"),
+        }
+    }
+
+    /// Adds a paragraph to the text box, `margin` being its left margin and `bullet` its
+    /// bullet properties
+    fn paragraph(&mut self, elements: &[&Element], format: RunFormat, margin: i64, bullet: &str) {
+        let text: String = elements
+            .iter()
+            .map(|element| element.plain_text())
+            .collect();
+        let height = text_height(&text, format.size, CONTENT_WIDTH - TEXT_INSETS - margin)
+            + i64::from(PARAGRAPH_SPACING) * EMU_PER_POINT;
+        if !self.fits(self.paragraphs_height + height + TEXT_INSETS) {
+            self.continue_slide();
+        }
+        let indent = if margin > 0 { -LIST_INDENT } else { 0 };
+        let mut xml = format!(
+            r#"<a:p><a:pPr marL="{margin}" indent="{indent}"><a:spcBef><a:spcPts val="{}"/></a:spcBef>{bullet}</a:pPr>"#,
+            PARAGRAPH_SPACING * 100
+        );
+        for element in elements {
+            self.inline(&mut xml, element, format, None);
+        }
+        xml.push_str("</a:p>");
+        self.paragraphs.push_str(&xml);
+        self.paragraphs_height += height;
+    }
+
+    /// Runs of an inline element, `link` being the relationship of the link around it
+    fn inline(
+        &mut self,
+        xml: &mut String,
+        element: &Element,
+        format: RunFormat,
+        link: Option<&str>,
+    ) {
+        match element {
+            Element::Text { text, .. } => run(xml, text, format, link),
+            Element::LineBreak => xml.push_str("<a:br/>"),
+            Element::InlineCode { text } => {
+                run(
+                    xml,
+                    text,
+                    RunFormat {
+                        code: true,
+                        ..format
+                    },
+                    link,
+                );
+            }
+            Element::Styled { style, elements } => {
+                let format = match style {
+                    TextStyle::Strong => RunFormat {
+                        bold: true,
+                        ..format
+                    },
+                    TextStyle::Emphasis => RunFormat {
+                        italic: true,
+                        ..format
+                    },
+                    TextStyle::Strikethrough => RunFormat {
+                        strikethrough: true,
+                        ..format
+                    },
+                };
+                for element in elements {
+                    self.inline(xml, element, format, link);
+                }
+            }
+            Element::Hyperlink { elements, url, .. } => {
+                // anchors in the document have no slide to go to
+                let relationship = (!url.is_empty() && !url.starts_with('#'))
+                    .then(|| self.slide().relationship("hyperlink", url.clone()));
+                let link = relationship.as_deref().or(link);
+                if elements.is_empty() {
+                    run(xml, url, format, link);
+                }
+                for element in elements {
+                    self.inline(xml, element, format, link);
+                }
+            }
+            Element::Paragraph { elements, .. } | Element::Container { elements, .. } => {
+                for element in elements {
+                    self.inline(xml, element, format, link);
+                }
+            }
+            Element::Attributed { element, .. } => self.inline(xml, element, format, link),
+            Element::Image(_)
+            | Element::Bookmark { .. }
+            | Element::Footnote { .. }
+            | Element::Raw { .. } => {}
+            element => run(xml, &element.plain_text(), format, link),
+        }
+    }
+
+    /// Items of a list as paragraphs with bullets, a nested list being a level deeper
+    fn list(&mut self, list: &Element, depth: usize) {
+        let Element::List {
+            elements,
+            numbered,
+            start,
+            numbering,
+        } = list
+        else {
+            return;
+        };
+        let bullet = if *numbered {
+            format!(
+                r#"<a:buFont typeface="+mj-lt"/><a:buAutoNum type="{}" startAt="{start}"/>"#,
+                numbering_scheme(numbering)
+            )
+        } else {
+            format!(
+                r#"<a:buFont typeface="Arial"/><a:buChar char="{}"/>"#,
+                BULLETS[depth % BULLETS.len()]
+            )
+        };
+        let margin = LIST_INDENT * (depth as i64 + 1);
+        let format = RunFormat::new(TEXT_SIZE);
+        for item in elements {
+            match &item.element {
+                nested @ Element::List { .. } => self.list(nested, depth + 1),
+                Element::Paragraph { elements, .. } => {
+                    let elements: Vec<&Element> = elements.iter().collect();
+                    self.paragraph(&elements, format, margin, &bullet);
+                }
+                element => self.paragraph(&[element], format, margin, &bullet),
+            }
+        }
+    }
+
+    /// Lines of code in a monospace font, long blocks continuing on the next slides
+    fn code_block(&mut self, code: &str) {
+        let code = code.strip_suffix('\n').unwrap_or(code);
+        let format = RunFormat {
+            code: true,
+            ..RunFormat::new(CODE_SIZE)
+        };
+        let line_height = text_height("", CODE_SIZE, CONTENT_WIDTH);
+        let lines_per_slide =
+            ((CONTENT_BOTTOM - CONTENT_TOP - 2 * TEXT_INSETS) / line_height).max(1) as usize;
+        let lines: Vec<&str> = code.lines().collect();
+        for chunk in lines.chunks(lines_per_slide) {
+            let text = Element::Text {
+                text: chunk.join("\n"),
+                size: 8,
+            };
+            self.paragraph(&[&text], format, 0, "<a:buNone/>");
+        }
+    }
+
+    /// Table of equal columns across the slide, the rows that do not fit continuing on the
+    /// next slides below the header row
+    fn table(&mut self, headers: &[TableHeader], rows: &[TableRow]) {
+        self.flush_text();
+        let columns = rows
+            .iter()
+            .map(|row| row.cells.len())
+            .chain([headers.len()])
+            .max()
+            .unwrap_or(0)
+            .max(1);
+        let column_width = CONTENT_WIDTH / columns as i64;
+        let row_height = |cells: &[&Element]| {
+            cells
+                .iter()
+                .map(|cell| {
+                    text_height(
+                        &cell.plain_text(),
+                        TABLE_TEXT_SIZE,
+                        column_width - 2 * CELL_INSETS,
+                    )
+                })
+                .max()
+                .unwrap_or(0)
+                + 2 * CELL_INSETS
+        };
+        let header: Vec<&Element> = headers.iter().map(|header| &header.element).collect();
+        let header_height = if header.is_empty() {
+            0
+        } else {
+            row_height(&header)
+        };
+        let mut chunk: Vec<(Vec<&Element>, i64)> = vec![];
+        for row in rows {
+            let cells: Vec<&Element> = row.cells.iter().map(|cell| &cell.element).collect();
+            let height = row_height(&cells);
+            let chunk_height: i64 = chunk.iter().map(|(_, height)| height).sum();
+            if !self.fits(header_height + chunk_height + height) {
+                if !chunk.is_empty() {
+                    self.write_table(&header, header_height, &chunk, columns);
+                    chunk.clear();
+                }
+                self.continue_slide();
+            }
+            chunk.push((cells, height));
+        }
+        if !chunk.is_empty() || !header.is_empty() {
+            self.write_table(&header, header_height, &chunk, columns);
+        }
+    }
+
+    fn write_table(
+        &mut self,
+        header: &[&Element],
+        header_height: i64,
+        rows: &[(Vec<&Element>, i64)],
+        columns: usize,
+    ) {
+        let column_width = CONTENT_WIDTH / columns as i64;
+        let mut xml = format!(
+            r#"<a:tbl><a:tblPr firstRow="{}"/><a:tblGrid>"#,
+            u8::from(!header.is_empty())
+        );
+        for _ in 0..columns {
+            let _ = write!(xml, r#"<a:gridCol w="{column_width}"/>"#);
+        }
+        xml.push_str("</a:tblGrid>");
+        if !header.is_empty() {
+            self.table_row(&mut xml, header, header_height, columns, true);
+        }
+        for (cells, height) in rows {
+            self.table_row(&mut xml, cells, *height, columns, false);
+        }
+        xml.push_str("</a:tbl>");
+
+        let height = header_height + rows.iter().map(|(_, height)| height).sum::<i64>();
+        let frame = frame(MARGIN, self.top, column_width * columns as i64, height);
+        let slide = self.slide();
+        let id = slide.shape_id();
+        let _ = write!(
+            slide.shapes,
+            r#"<p:graphicFrame><p:nvGraphicFramePr><p:cNvPr id="{id}" name="Table {id}"/><p:cNvGraphicFramePr><a:graphicFrameLocks noGrp="1"/></p:cNvGraphicFramePr><p:nvPr/></p:nvGraphicFramePr><p:xfrm>{frame}</p:xfrm><a:graphic><a:graphicData uri="http://schemas.openxmlformats.org/drawingml/2006/table">{xml}</a:graphicData></a:graphic></p:graphicFrame>"#
+        );
+        self.top += height + BLOCK_SPACING;
+    }
+
+    /// Row of the cells with their borders, completed with empty cells up to the number of
+    /// columns, the header cells in bold on gray
+    fn table_row(
+        &mut self,
+        xml: &mut String,
+        cells: &[&Element],
+        height: i64,
+        columns: usize,
+        header: bool,
+    ) {
+        let format = RunFormat {
+            bold: header,
+            ..RunFormat::new(TABLE_TEXT_SIZE)
+        };
+        let mut properties = String::new();
+        for side in ["lnL", "lnR", "lnT", "lnB"] {
+            let _ = write!(
+                properties,
+                r#"<a:{side} w="12700"><a:solidFill><a:srgbClr val="808080"/></a:solidFill></a:{side}>"#
+            );
+        }
+        if header {
+            properties.push_str(r#"<a:solidFill><a:srgbClr val="D9D9D9"/></a:solidFill>"#);
+        }
+        let _ = write!(xml, r#"<a:tr h="{height}">"#);
+        for index in 0..columns {
+            xml.push_str("<a:tc><a:txBody><a:bodyPr/><a:lstStyle/><a:p>");
+            if let Some(cell) = cells.get(index) {
+                self.inline(xml, cell, format, None);
+            }
+            let _ = write!(
+                xml,
+                r#"</a:p></a:txBody><a:tcPr marL="{CELL_INSETS}" marR="{CELL_INSETS}" marT="{CELL_INSETS}" marB="{CELL_INSETS}">{properties}</a:tcPr></a:tc>"#
+            );
+        }
+        xml.push_str("</a:tr>");
+    }
+
+    /// Image centered across the slide, shrunk to fit the slide
+    fn image(&mut self, image: &ImageData) {
+        if image.bytes().is_empty() {
+            warn!("Image without content left out: {}", image.title());
+            return;
+        }
+        if *image.image_type() == ImageType::SVG {
+            warn!("SVG image left out of the slides: {}", image.title());
+            return;
+        }
+        self.flush_text();
+        let (width, height) = image_size(image);
+        if !self.fits(height) {
+            self.continue_slide();
+        }
+        let number = self.images.len() + 1;
+        let extension = image.image_type().to_extension();
+        self.images.push((
+            format!("ppt/media/image{number}{extension}"),
+            image.image_type().mime_type().to_string(),
+            image.bytes().clone(),
+        ));
+        let frame = frame(
+            MARGIN + (CONTENT_WIDTH - width) / 2,
+            self.top,
+            width,
+            height,
+        );
+        let description = if image.alt().is_empty() {
+            image.title()
+        } else {
+            image.alt()
+        };
+        let slide = self.slide();
+        let id = slide.shape_id();
+        let relationship =
+            slide.relationship("image", format!("../media/image{number}{extension}"));
+        let _ = write!(
+            slide.shapes,
+            r#"<p:pic><p:nvPicPr><p:cNvPr id="{id}" name="Picture {id}" descr="{}"/><p:cNvPicPr><a:picLocks noChangeAspect="1"/></p:cNvPicPr><p:nvPr/></p:nvPicPr><p:blipFill><a:blip r:embed="{relationship}"/><a:stretch><a:fillRect/></a:stretch></p:blipFill><p:spPr><a:xfrm>{frame}</a:xfrm><a:prstGeom prst="rect"><a:avLst/></a:prstGeom></p:spPr></p:pic>"#,
+            escape(description)
+        );
+        self.top += height + BLOCK_SPACING;
+    }
+
+    /// Slides of the document, the first one left out when it has neither title nor blocks
+    fn finish(mut self) -> (Vec<Slide>, Vec<(String, String, Bytes)>) {
+        self.flush_text();
+        if self.slides.len() > 1
+            && self.slides[0].title.is_empty()
+            && self.slides[0].shapes.is_empty()
+        {
+            self.slides.remove(0);
+        }
+        (self.slides, self.images)
+    }
+}
+
+/// Run of text, its line breaks written as `a:br`
+fn run(xml: &mut String, text: &str, format: RunFormat, link: Option<&str>) {
+    for (index, line) in text.split('\n').enumerate() {
+        if index > 0 {
+            xml.push_str("<a:br/>");
+        }
+        if line.is_empty() {
+            continue;
+        }
+        let _ = write!(
+            xml,
+            r#"<a:r><a:rPr lang="en-US" sz="{}""#,
+            format.size * 100
+        );
+        for (on, attribute) in [
+            (format.bold, r#" b="1""#),
+            (format.italic, r#" i="1""#),
+            (format.strikethrough, r#" strike="sngStrike""#),
+        ] {
+            if on {
+                xml.push_str(attribute);
+            }
+        }
+        xml.push_str(r#" dirty="0">"#);
+        if format.code {
+            xml.push_str(r#"<a:latin typeface="Courier New"/>"#);
+        }
+        if let Some(link) = link {
+            let _ = write!(xml, r#"<a:hlinkClick r:id="{link}"/>"#);
+        }
+        let _ = write!(xml, "</a:rPr><a:t>{}</a:t></a:r>", escape_text(line));
+    }
+}
+
+/// Size of an image in EMU, its own size in pixels or else the size of its picture, shrunk
+/// to fit the slide
+fn image_size(image: &ImageData) -> (i64, i64) {
+    let pixels = |value: &Option<String>| {
+        value
+            .as_deref()
+            .and_then(|value| value.trim().trim_end_matches("px").parse::<f32>().ok())
+            .filter(|value| *value > 0.0)
+    };
+    let size = image.size();
+    let (width, height) = match (pixels(&size.width), pixels(&size.height)) {
+        (Some(width), Some(height)) => (width, height),
+        _ => image::io::Reader::new(Cursor::new(image.bytes()))
+            .with_guessed_format()
+            .ok()
+            .and_then(|reader| reader.into_dimensions().ok())
+            .map(|(width, height)| (width as f32, height as f32))
+            .unwrap_or(DEFAULT_IMAGE_SIZE),
+    };
+    let (width, height) = (width * EMU_PER_PIXEL as f32, height * EMU_PER_PIXEL as f32);
+    let scale = (CONTENT_WIDTH as f32 / width)
+        .min((CONTENT_BOTTOM - CONTENT_TOP) as f32 / height)
+        .min(1.0);
+    ((width * scale) as i64, (height * scale) as i64)
+}
+
+/// `.rels` part of the relationships, numbered from `rId1`
+fn relationships_xml(relationships: &[(&str, String)]) -> String {
+    let mut xml = String::from(
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?><Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">"#,
+    );
+    for (index, (kind, target)) in relationships.iter().enumerate() {
+        let _ = write!(
+            xml,
+            r#"<Relationship Id="rId{}" Type="{RELATIONSHIPS}/{kind}" Target="{}""#,
+            index + 1,
+            escape(target)
+        );
+        if *kind == "hyperlink" {
+            xml.push_str(r#" TargetMode="External""#);
+        }
+        xml.push_str("/>");
+    }
+    xml.push_str("</Relationships>");
+    xml
+}
+
+fn slide_xml(slide: &Slide) -> String {
+    let mut shapes = String::new();
+    if !slide.title.is_empty() {
+        // a slide without blocks is a section slide
+        let (top, size, anchor) = if slide.shapes.is_empty() {
+            ((SLIDE_HEIGHT - TITLE_HEIGHT) / 2, SECTION_TITLE_SIZE, "ctr")
+        } else {
+            (TITLE_TOP, TITLE_SIZE, "b")
+        };
+        let title = format!(
+            r#"<a:p><a:r><a:rPr lang="en-US" sz="{}" b="1" dirty="0"/><a:t>{}</a:t></a:r></a:p>"#,
+            size * 100,
+            escape_text(&slide.title)
+        );
+        shapes = text_box(
+            2,
+            "Title",
+            &frame(MARGIN, top, CONTENT_WIDTH, TITLE_HEIGHT),
+            anchor,
+            &title,
+        );
+    }
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?><p:sld {NAMESPACES}><p:cSld><p:spTree>{GROUP}{shapes}{}</p:spTree></p:cSld><p:clrMapOvr><a:masterClrMapping/></p:clrMapOvr></p:sld>"#,
+        slide.shapes
+    )
+}
+
+fn presentation_xml(slides: usize) -> String {
+    let mut xml = format!(
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?><p:presentation {NAMESPACES} saveSubsetFonts="1"><p:sldMasterIdLst><p:sldMasterId id="2147483648" r:id="rId1"/></p:sldMasterIdLst><p:sldIdLst>"#
+    );
+    // the slides follow the master, the theme and the properties in the relationships
+    for index in 0..slides {
+        let _ = write!(
+            xml,
+            r#"<p:sldId id="{}" r:id="rId{}"/>"#,
+            256 + index,
+            5 + index
+        );
+    }
+    let _ = write!(
+        xml,
+        r#"</p:sldIdLst><p:sldSz cx="{SLIDE_WIDTH}" cy="{SLIDE_HEIGHT}"/><p:notesSz cx="6858000" cy="9144000"/></p:presentation>"#
+    );
+    xml
+}
+
+fn slide_master_xml() -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?><p:sldMaster {NAMESPACES}><p:cSld><p:bg><p:bgRef idx="1001"><a:schemeClr val="bg1"/></p:bgRef></p:bg><p:spTree>{GROUP}</p:spTree></p:cSld><p:clrMap bg1="lt1" tx1="dk1" bg2="lt2" tx2="dk2" accent1="accent1" accent2="accent2" accent3="accent3" accent4="accent4" accent5="accent5" accent6="accent6" hlink="hlink" folHlink="folHlink"/><p:sldLayoutIdLst><p:sldLayoutId id="2147483649" r:id="rId1"/></p:sldLayoutIdLst><p:txStyles><p:titleStyle/><p:bodyStyle/><p:otherStyle/></p:txStyles></p:sldMaster>"#
+    )
+}
+
+fn slide_layout_xml() -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?><p:sldLayout {NAMESPACES} type="blank" preserve="1"><p:cSld name="Blank"><p:spTree>{GROUP}</p:spTree></p:cSld><p:clrMapOvr><a:masterClrMapping/></p:clrMapOvr></p:sldLayout>"#
+    )
+}
+
+/// Theme of the Office colors and fonts, its formats plain fills and lines
+fn theme_xml() -> String {
+    let mut colors = String::from(
+        r#"<a:dk1><a:sysClr val="windowText" lastClr="000000"/></a:dk1><a:lt1><a:sysClr val="window" lastClr="FFFFFF"/></a:lt1>"#,
+    );
+    for (name, color) in [
+        ("dk2", "44546A"),
+        ("lt2", "E7E6E6"),
+        ("accent1", "4472C4"),
+        ("accent2", "ED7D31"),
+        ("accent3", "A5A5A5"),
+        ("accent4", "FFC000"),
+        ("accent5", "5B9BD5"),
+        ("accent6", "70AD47"),
+        ("hlink", "0563C1"),
+        ("folHlink", "954F72"),
+    ] {
+        let _ = write!(colors, r#"<a:{name}><a:srgbClr val="{color}"/></a:{name}>"#);
+    }
+    let fill = r#"<a:solidFill><a:schemeClr val="phClr"/></a:solidFill>"#;
+    let fills = fill.repeat(3);
+    let lines: String = [6350, 12700, 19050]
+        .iter()
+        .map(|width| format!(r#"<a:ln w="{width}">{fill}</a:ln>"#))
+        .collect();
+    let effects = "<a:effectStyle><a:effectLst/></a:effectStyle>".repeat(3);
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?><a:theme xmlns:a="http://schemas.openxmlformats.org/drawingml/2006/main" name="Office Theme"><a:themeElements><a:clrScheme name="Office">{colors}</a:clrScheme><a:fontScheme name="Office"><a:majorFont><a:latin typeface="Calibri Light"/><a:ea typeface=""/><a:cs typeface=""/></a:majorFont><a:minorFont><a:latin typeface="Calibri"/><a:ea typeface=""/><a:cs typeface=""/></a:minorFont></a:fontScheme><a:fmtScheme name="Office"><a:fillStyleLst>{fills}</a:fillStyleLst><a:lnStyleLst>{lines}</a:lnStyleLst><a:effectStyleLst>{effects}</a:effectStyleLst><a:bgFillStyleLst>{fills}</a:bgFillStyleLst></a:fmtScheme></a:themeElements></a:theme>"#
+    )
+}
+
+fn content_types_xml(slides: usize, images: &[(String, String, Bytes)]) -> String {
+    let mut xml = String::from(
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?><Types xmlns="http://schemas.openxmlformats.org/package/2006/content-types"><Default Extension="rels" ContentType="application/vnd.openxmlformats-package.relationships+xml"/><Default Extension="xml" ContentType="application/xml"/>"#,
+    );
+    let extensions: BTreeSet<(&str, &str)> = images
+        .iter()
+        .filter_map(|(path, media_type, _)| Some((path.rsplit_once('.')?.1, media_type.as_str())))
+        .collect();
+    for (extension, media_type) in extensions {
+        let _ = write!(
+            xml,
+            r#"<Default Extension="{extension}" ContentType="{media_type}"/>"#
+        );
+    }
+    let mut overrides = vec![
+        (
+            "/ppt/presentation.xml".to_string(),
+            format!("{PRESENTATION_ML}.presentation.main+xml"),
+        ),
+        (
+            "/ppt/slideMasters/slideMaster1.xml".to_string(),
+            format!("{PRESENTATION_ML}.slideMaster+xml"),
+        ),
+        (
+            "/ppt/slideLayouts/slideLayout1.xml".to_string(),
+            format!("{PRESENTATION_ML}.slideLayout+xml"),
+        ),
+        (
+            "/ppt/presProps.xml".to_string(),
+            format!("{PRESENTATION_ML}.presProps+xml"),
+        ),
+        (
+            "/ppt/tableStyles.xml".to_string(),
+            format!("{PRESENTATION_ML}.tableStyles+xml"),
+        ),
+        (
+            "/ppt/theme/theme1.xml".to_string(),
+            "application/vnd.openxmlformats-officedocument.theme+xml".to_string(),
+        ),
+        (
+            "/docProps/core.xml".to_string(),
+            "application/vnd.openxmlformats-package.core-properties+xml".to_string(),
+        ),
+        (
+            "/docProps/app.xml".to_string(),
+            "application/vnd.openxmlformats-officedocument.extended-properties+xml".to_string(),
+        ),
+    ];
+    for number in 1..=slides {
+        overrides.push((
+            format!("/ppt/slides/slide{number}.xml"),
+            format!("{PRESENTATION_ML}.slide+xml"),
+        ));
+    }
+    for (part, content_type) in overrides {
+        let _ = write!(
+            xml,
+            r#"<Override PartName="{part}" ContentType="{content_type}"/>"#
+        );
+    }
+    xml.push_str("</Types>");
+    xml
+}
+
+/// docProps/core.xml of the title, author, description and keywords
+fn core_xml(metadata: &Metadata) -> String {
+    let mut xml = String::from(
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?><cp:coreProperties xmlns:cp="http://schemas.openxmlformats.org/package/2006/metadata/core-properties" xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:dcterms="http://purl.org/dc/terms/" xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance">"#,
+    );
+    let keywords = (!metadata.keywords.is_empty()).then(|| metadata.keywords.join(", "));
+    for (tag, value) in [
+        ("dc:title", &metadata.title),
+        ("dc:creator", &metadata.author),
+        ("dc:description", &metadata.description),
+        ("cp:keywords", &keywords),
+    ] {
+        if let Some(value) = value {
+            let _ = write!(xml, "<{tag}>{}</{tag}>", escape(value));
+        }
+    }
+    xml.push_str("</cp:coreProperties>");
+    xml
+}
+
+impl TransformerTrait for Transformer {
+    fn parse(_document: &Bytes) -> crate::Result<Document> {
+        Err(crate::Error::parse_error(anyhow::anyhow!(
+            "PPTX presentations are generated only"
+        )))
+    }
+
+    fn generate(document: &Document) -> crate::Result<Bytes> {
+        Transformer::generate_bytes(document).map_err(crate::Error::generation_error)
+    }
+}
+
+impl Transformer {
+    fn generate_bytes(document: &Document) -> anyhow::Result<Bytes> {
+        let document = &document
+            .resolve_fields()
+            .without_containers()
+            .without_media()
+            .without_math()
+            .without_drawings()
+            .without_attributes();
+        let mut writer = Writer::new(document);
+        // page headers and footers have no place on slides
+        for band in &document.bands {
+            if !matches!(band, Band::PageHeader(_) | Band::PageFooter(_)) {
+                for element in band.elements() {
+                    writer.block(element);
+                }
+            }
+        }
+        let (slides, images) = writer.finish();
+
+        let mut presentation_relationships = vec![
+            ("slideMaster", "slideMasters/slideMaster1.xml".to_string()),
+            ("theme", "theme/theme1.xml".to_string()),
+            ("presProps", "presProps.xml".to_string()),
+            ("tableStyles", "tableStyles.xml".to_string()),
+        ];
+        for number in 1..=slides.len() {
+            presentation_relationships.push(("slide", format!("slides/slide{number}.xml")));
+        }
+        let mut parts = vec![
+            (
+                "[Content_Types].xml".to_string(),
+                content_types_xml(slides.len(), &images),
+            ),
+            (
+                "_rels/.rels".to_string(),
+                format!(
+                    r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?><Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships"><Relationship Id="rId1" Type="{RELATIONSHIPS}/officeDocument" Target="ppt/presentation.xml"/><Relationship Id="rId2" Type="http://schemas.openxmlformats.org/package/2006/relationships/metadata/core-properties" Target="docProps/core.xml"/><Relationship Id="rId3" Type="{RELATIONSHIPS}/extended-properties" Target="docProps/app.xml"/></Relationships>"#
+                ),
+            ),
+            ("docProps/core.xml".to_string(), core_xml(&document.metadata)),
+            (
+                "docProps/app.xml".to_string(),
+                format!(
+                    r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?><Properties xmlns="http://schemas.openxmlformats.org/officeDocument/2006/extended-properties"><Application>Shiva</Application><Slides>{}</Slides></Properties>"#,
+                    slides.len()
+                ),
+            ),
+            ("ppt/presentation.xml".to_string(), presentation_xml(slides.len())),
+            (
+                "ppt/_rels/presentation.xml.rels".to_string(),
+                relationships_xml(&presentation_relationships),
+            ),
+            (
+                "ppt/presProps.xml".to_string(),
+                format!(
+                    r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?><p:presentationPr {NAMESPACES}/>"#
+                ),
+            ),
+            (
+                "ppt/tableStyles.xml".to_string(),
+                r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?><a:tblStyleLst xmlns:a="http://schemas.openxmlformats.org/drawingml/2006/main" def="{5C22544A-7EE6-4342-B048-85BDC9FD1C3A}"/>"#.to_string(),
+            ),
+            ("ppt/slideMasters/slideMaster1.xml".to_string(), slide_master_xml()),
+            (
+                "ppt/slideMasters/_rels/slideMaster1.xml.rels".to_string(),
+                relationships_xml(&[
+                    ("slideLayout", "../slideLayouts/slideLayout1.xml".to_string()),
+                    ("theme", "../theme/theme1.xml".to_string()),
+                ]),
+            ),
+            ("ppt/slideLayouts/slideLayout1.xml".to_string(), slide_layout_xml()),
+            (
+                "ppt/slideLayouts/_rels/slideLayout1.xml.rels".to_string(),
+                relationships_xml(&[(
+                    "slideMaster",
+                    "../slideMasters/slideMaster1.xml".to_string(),
+                )]),
+            ),
+            ("ppt/theme/theme1.xml".to_string(), theme_xml()),
+        ];
+        for (index, slide) in slides.iter().enumerate() {
+            let number = index + 1;
+            parts.push((format!("ppt/slides/slide{number}.xml"), slide_xml(slide)));
+            parts.push((
+                format!("ppt/slides/_rels/slide{number}.xml.rels"),
+                relationships_xml(&slide.relationships),
+            ));
+        }
+
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+        for (name, xml) in parts {
+            zip.start_file(name, options)?;
+            zip.write_all(xml.as_bytes())?;
+        }
+        for (path, _, bytes) in &images {
+            zip.start_file(path.as_str(), options)?;
+            zip.write_all(bytes)?;
+        }
+        Ok(Bytes::from(zip.finish()?.into_inner()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use zip::ZipArchive;
+
+    use super::*;
+    use crate::core::tests::init_logger;
+    use crate::core::ImageDimension;
+    use crate::markdown;
+
+    fn part(archive: &mut ZipArchive<Cursor<Bytes>>, name: &str) -> anyhow::Result<String> {
+        let mut text = String::new();
+        archive.by_name(name)?.read_to_string(&mut text)?;
+        Ok(text)
+    }
+
+    #[test]
+    fn test_generate() -> anyhow::Result<()> {
+        init_logger();
+        let items: String = (1..=20).map(|item| format!("- Point {item}\n")).collect();
+        let markdown = format!(
+            "# Deck\n\n## Agenda\n\n- First\n  - Nested\n- Second\n\n\
+             ## Numbers\n\n| Name | Value |\n|------|-------|\n| a | 1 |\n\n\
+             Some **bold** text and [a link](https://example.com?a=1&b=2).\n\n\
+             ## Many points\n\n{items}"
+        );
+        let mut document = markdown::Transformer::parse(&Bytes::from(markdown))?;
+        let png = Bytes::from(std::fs::read("test/data/small.png")?);
+        document.bands = vec![Band::Detail(
+            document
+                .get_all_elements()
+                .into_iter()
+                .cloned()
+                .chain([
+                    Element::Header {
+                        level: 2,
+                        text: "Picture".to_string(),
+                    },
+                    Element::Image(ImageData::new(
+                        png,
+                        "Logo".to_string(),
+                        "A small picture".to_string(),
+                        "png".to_string(),
+                        String::new(),
+                        ImageDimension::default(),
+                    )),
+                ])
+                .collect(),
+        )];
+
+        let generated = Transformer::generate(&document)?;
+        let mut archive = ZipArchive::new(Cursor::new(generated))?;
+        let presentation = part(&mut archive, "ppt/presentation.xml")?;
+        let slides = presentation.matches("<p:sldId ").count();
+        let titles: Vec<String> = (1..=slides)
+            .map(|number| {
+                let slide = part(&mut archive, &format!("ppt/slides/slide{number}.xml"))?;
+                let title = slide
+                    .split("<a:t>")
+                    .nth(1)
+                    .and_then(|text| text.split("</a:t>").next())
+                    .unwrap_or_default();
+                Ok(title.to_string())
+            })
+            .collect::<anyhow::Result<_>>()?;
+        // the section slide, the agenda, the numbers, the points on two slides and the picture
+        assert_eq!(
+            titles,
+            [
+                "Deck",
+                "Agenda",
+                "Numbers",
+                "Many points",
+                "Many points",
+                "Picture"
+            ]
+        );
+
+        let deck = part(&mut archive, "ppt/slides/slide1.xml")?;
+        assert!(deck.contains(r#"anchor="ctr""#));
+        let agenda = part(&mut archive, "ppt/slides/slide2.xml")?;
+        assert!(agenda.contains(r#"<a:buChar char="•"/>"#));
+        assert!(agenda.contains(r#"<a:buChar char="–"/>"#));
+
+        let numbers = part(&mut archive, "ppt/slides/slide3.xml")?;
+        assert!(numbers.contains(r#"<a:tblPr firstRow="1"/>"#));
+        assert_eq!(numbers.matches("<a:tr ").count(), 2);
+        assert!(numbers.contains(r#"<a:hlinkClick r:id="rId2"/>"#));
+        let relationships = part(&mut archive, "ppt/slides/_rels/slide3.xml.rels")?;
+        assert!(relationships
+            .contains(r#"Target="https://example.com?a=1&amp;b=2" TargetMode="External""#));
+
+        let points = part(&mut archive, "ppt/slides/slide4.xml")?
+            + &part(&mut archive, "ppt/slides/slide5.xml")?;
+        assert_eq!(points.matches("<a:buChar").count(), 20);
+
+        let relationships = part(&mut archive, "ppt/slides/_rels/slide6.xml.rels")?;
+        assert!(relationships.contains(r#"Target="../media/image1.png""#));
+        assert!(archive.by_name("ppt/media/image1.png").is_ok());
+        let content_types = part(&mut archive, "[Content_Types].xml")?;
+        assert!(content_types.contains(r#"<Default Extension="png" ContentType="image/png"/>"#));
+        assert!(content_types.contains("/ppt/slides/slide6.xml"));
+        Ok(())
+    }
+}
@@ -0,0 +1,163 @@
+//! Rendering of the pages of a document to PNG, the previews of its conversion to PDF.
+
+use anyhow::bail;
+use bytes::Bytes;
+use typst::visualize::Color;
+
+use crate::core::{Document, PdfOptions};
+
+/// Highest resolution of the previews, in dots per inch
+pub const MAX_DPI: f32 = 600.0;
+
+/// Pages to render and their resolution
+#[derive(Debug, Clone, PartialEq)]
+pub struct PreviewOptions {
+    /// Numbers of the pages, starting at 1, all the pages when empty
+    pub pages: Vec<usize>,
+    /// Resolution in dots per inch, up to [`MAX_DPI`]
+    pub dpi: f32,
+}
+
+impl Default for PreviewOptions {
+    fn default() -> Self {
+        PreviewOptions {
+            pages: Vec::new(),
+            dpi: 96.0,
+        }
+    }
+}
+
+/// Rendered page of a document
+#[derive(Debug, Clone, PartialEq)]
+pub struct PagePreview {
+    /// Number of the page, starting at 1
+    pub number: usize,
+    /// Size of the image in pixels
+    pub width: u32,
+    pub height: u32,
+    pub png: Bytes,
+}
+
+/// Previews of the pages of a document
+#[derive(Debug, Clone, PartialEq)]
+pub struct Preview {
+    /// Number of pages of the whole document
+    pub page_count: usize,
+    /// Rendered pages, in the order of the options; pages past the end of the document
+    /// are left out
+    pub pages: Vec<PagePreview>,
+}
+
+/// Lays out the document as its PDF would be, with the PDF options, then renders the pages
+/// of the preview options to PNG
+pub fn render(
+    document: &Document,
+    pdf_options: &PdfOptions,
+    options: &PreviewOptions,
+) -> crate::Result<Preview> {
+    render_pages(document, pdf_options, options).map_err(crate::Error::generation_error)
+}
+
+fn render_pages(
+    document: &Document,
+    pdf_options: &PdfOptions,
+    options: &PreviewOptions,
+) -> anyhow::Result<Preview> {
+    render_selected(document, pdf_options, options.dpi, |page_count| {
+        if options.pages.is_empty() {
+            (1..=page_count).collect()
+        } else {
+            options
+                .pages
+                .iter()
+                .copied()
+                .filter(|&number| (1..=page_count).contains(&number))
+                .collect()
+        }
+    })
+}
+
+/// Renders the pages chosen by `select` from the page count of the laid out document, the
+/// numbers it returns being within the document
+pub(crate) fn render_selected<F>(
+    document: &Document,
+    pdf_options: &PdfOptions,
+    dpi: f32,
+    select: F,
+) -> anyhow::Result<Preview>
+where
+    F: FnOnce(usize) -> Vec<usize>,
+{
+    if !(dpi > 0.0 && dpi <= MAX_DPI) {
+        bail!("Preview resolution must be above 0 and at most {MAX_DPI} DPI");
+    }
+    let layout = crate::typst::layout(document, pdf_options)?;
+    let page_count = layout.pages.len();
+    let numbers = select(page_count);
+
+    let pixel_per_pt = dpi / 72.0;
+    let mut pages = Vec::with_capacity(numbers.len());
+    for number in numbers {
+        let pixmap =
+            typst_render::render(&layout.pages[number - 1].frame, pixel_per_pt, Color::WHITE);
+        pages.push(PagePreview {
+            number,
+            width: pixmap.width(),
+            height: pixmap.height(),
+            png: Bytes::from(pixmap.encode_png()?),
+        });
+    }
+    Ok(Preview { page_count, pages })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{Element, PageFormat, PageOrientation, Section};
+
+    #[test]
+    fn test_render() -> anyhow::Result<()> {
+        let mut document = Document::new(vec![
+            Element::Text {
+                text: "Portrait page".to_string(),
+                size: 10,
+            },
+            Element::Text {
+                text: "Landscape page".to_string(),
+                size: 10,
+            },
+        ]);
+        document.add_section(Section::new(1, PageFormat::A4, PageOrientation::Landscape));
+        let pdf_options = PdfOptions::default();
+
+        let preview = render(&document, &pdf_options, &PreviewOptions::default())?;
+        assert_eq!(preview.page_count, 2);
+        assert_eq!(preview.pages.len(), 2);
+        // an A4 page is 595.28 by 841.89 points, 96 DPI is 4/3 pixels per point
+        let page = &preview.pages[0];
+        assert_eq!((page.width, page.height), (794, 1123));
+        assert!(page.png.starts_with(b"\x89PNG"));
+        let image = image::load_from_memory(&page.png)?;
+        assert_eq!((image.width(), image.height()), (794, 1123));
+
+        let options = PreviewOptions {
+            pages: vec![2, 5],
+            dpi: 36.0,
+        };
+        let preview = render(&document, &pdf_options, &options)?;
+        assert_eq!(preview.page_count, 2);
+        assert_eq!(preview.pages.len(), 1);
+        assert_eq!(preview.pages[0].number, 2);
+        assert_eq!(
+            (preview.pages[0].width, preview.pages[0].height),
+            (421, 298)
+        );
+
+        let options = PreviewOptions {
+            dpi: 0.0,
+            ..Default::default()
+        };
+        assert!(render(&document, &pdf_options, &options).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,276 @@
+//! Reading of standalone images (PNG, JPEG, GIF, BMP and TIFF) as documents of one
+//! image, or of its text with the OCR engine of the parse options, and rendering of the
+//! pages of documents, laid out as in PDF, to PNG or JPEG images.
+
+use std::io::Cursor;
+
+use anyhow::bail;
+use bytes::Bytes;
+use image::{io::Reader as ImageReader, ImageFormat};
+
+#[cfg(feature = "preview")]
+use crate::core::PdfOptions;
+use crate::core::{Document, Element, ImageData, ImageDimension, ParseOptions};
+#[cfg(feature = "preview")]
+use crate::preview;
+
+/// Parses an image, the images of the pages are generated by [`Transformer`]
+pub fn parse(document: &Bytes, options: &ParseOptions) -> crate::Result<Document> {
+    parse_image(document, options).map_err(crate::Error::parse_error)
+}
+
+fn parse_image(document: &Bytes, options: &ParseOptions) -> anyhow::Result<Document> {
+    let reader = ImageReader::new(Cursor::new(document)).with_guessed_format()?;
+    let image_type = match reader.format() {
+        Some(ImageFormat::Png) => "png",
+        Some(ImageFormat::Jpeg) => "jpeg",
+        Some(ImageFormat::Gif) => "gif",
+        Some(ImageFormat::Bmp) => "bmp",
+        Some(ImageFormat::Tiff) => "tiff",
+        Some(format) => bail!("Unsupported image format {:?}", format),
+        None => bail!("Unknown image format"),
+    };
+    let (width, height) = reader.into_dimensions()?;
+    let image = ImageData::new(
+        document.clone(),
+        String::new(),
+        String::new(),
+        image_type.to_string(),
+        String::new(),
+        ImageDimension {
+            width: Some(width.to_string()),
+            height: Some(height.to_string()),
+        },
+    );
+    if let Some(ocr) = &options.ocr {
+        let paragraphs = ocr.paragraphs(&image)?;
+        if !paragraphs.is_empty() {
+            return Ok(Document::new(paragraphs));
+        }
+    }
+    Ok(Document::new(vec![Element::Image(image)]))
+}
+
+/// Format of the images of the pages
+#[cfg(feature = "preview")]
+#[derive(Debug, Clone, Copy, PartialEq, Default, strum::Display, strum::EnumString)]
+#[strum(serialize_all = "lowercase")]
+pub enum RasterFormat {
+    #[default]
+    Png,
+    Jpeg,
+}
+
+/// Pages to render, their resolution and their format
+#[cfg(feature = "preview")]
+#[derive(Debug, Clone, PartialEq)]
+pub struct RasterOptions {
+    /// Resolution in dots per inch, up to [`preview::MAX_DPI`]
+    pub dpi: f32,
+    /// Numbers of the first and last pages, starting at 1, all the pages when `None`
+    pub pages: Option<std::ops::RangeInclusive<usize>>,
+    pub format: RasterFormat,
+    /// Quality of the JPEG images, from 1 to 100
+    pub quality: u8,
+}
+
+#[cfg(feature = "preview")]
+impl Default for RasterOptions {
+    fn default() -> Self {
+        RasterOptions {
+            dpi: 96.0,
+            pages: None,
+            format: RasterFormat::default(),
+            quality: 85,
+        }
+    }
+}
+
+/// Image of a page of a document
+#[cfg(feature = "preview")]
+#[derive(Debug, Clone, PartialEq)]
+pub struct PageImage {
+    /// Number of the page, starting at 1
+    pub number: usize,
+    /// Size of the image in pixels
+    pub width: u32,
+    pub height: u32,
+    pub bytes: Bytes,
+}
+
+/// Images of the pages of documents, as thumbnails and previews
+#[cfg(feature = "preview")]
+pub struct Transformer;
+
+#[cfg(feature = "preview")]
+impl Transformer {
+    /// Lays out the document as its PDF would be, with the PDF options, then renders the
+    /// pages of the range. Pages past the end of the document are left out, a range without
+    /// any page of the document is an error.
+    pub fn generate_with_options(
+        document: &Document,
+        pdf_options: &PdfOptions,
+        options: &RasterOptions,
+    ) -> crate::Result<Vec<PageImage>> {
+        Transformer::generate_images(document, pdf_options, options)
+            .map_err(crate::Error::generation_error)
+    }
+
+    fn generate_images(
+        document: &Document,
+        pdf_options: &PdfOptions,
+        options: &RasterOptions,
+    ) -> anyhow::Result<Vec<PageImage>> {
+        if !(1..=100).contains(&options.quality) {
+            bail!("JPEG quality must be from 1 to 100");
+        }
+        if let Some(range) = &options.pages {
+            if range.is_empty() || *range.start() == 0 {
+                bail!("Page ranges start at page 1 and end after their start");
+            }
+        }
+        // the range is cut to the pages of the document, it may end far past them
+        let preview =
+            preview::render_selected(
+                document,
+                pdf_options,
+                options.dpi,
+                |page_count| match &options.pages {
+                    Some(range) => (*range.start()..=(*range.end()).min(page_count)).collect(),
+                    None => (1..=page_count).collect(),
+                },
+            )?;
+        if preview.pages.is_empty() {
+            bail!(
+                "No page in the range, the document has {} pages",
+                preview.page_count
+            );
+        }
+        preview
+            .pages
+            .into_iter()
+            .map(|page| {
+                let bytes = match options.format {
+                    RasterFormat::Png => page.png,
+                    // the pages are rendered on white, without transparency
+                    RasterFormat::Jpeg => {
+                        let image =
+                            image::load_from_memory_with_format(&page.png, ImageFormat::Png)?;
+                        let mut bytes = Cursor::new(vec![]);
+                        image.to_rgb8().write_to(
+                            &mut bytes,
+                            image::ImageOutputFormat::Jpeg(options.quality),
+                        )?;
+                        Bytes::from(bytes.into_inner())
+                    }
+                };
+                Ok(PageImage {
+                    number: page.number,
+                    width: page.width,
+                    height: page.height,
+                    bytes,
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "preview")]
+    use crate::core::{PageFormat, PageOrientation, Section};
+    use crate::ocr::Ocr;
+
+    #[test]
+    fn test_parse() -> anyhow::Result<()> {
+        let mut png = Vec::new();
+        image::DynamicImage::new_rgb8(30, 10)
+            .write_to(&mut Cursor::new(&mut png), image::ImageOutputFormat::Png)?;
+        let png = Bytes::from(png);
+
+        let document = parse(&png, &ParseOptions::default())?;
+        let Element::Image(image) = document.get_all_elements()[0] else {
+            panic!("image expected");
+        };
+        assert_eq!(image.size().width.as_deref(), Some("30"));
+
+        let options = ParseOptions {
+            ocr: Some(Ocr::new(|image: &ImageData| {
+                assert_eq!(image.image_type().to_string(), "png");
+                Ok("Scanned text\n".to_string())
+            })),
+            ..Default::default()
+        };
+        let document = parse(&png, &options)?;
+        assert_eq!(document.get_all_elements()[0].plain_text(), "Scanned text");
+        Ok(())
+    }
+
+    #[cfg(feature = "preview")]
+    #[test]
+    fn test_generate() -> anyhow::Result<()> {
+        let mut document = Document::new(
+            ["First page", "Second page", "Third page"]
+                .map(|text| Element::Text {
+                    text: text.to_string(),
+                    size: 10,
+                })
+                .to_vec(),
+        );
+        // a section starting at each element begins a new page
+        for start in 1..3 {
+            document.add_section(Section::new(
+                start,
+                PageFormat::A4,
+                PageOrientation::Portrait,
+            ));
+        }
+        let options = RasterOptions {
+            dpi: 36.0,
+            pages: Some(2..=5),
+            ..Default::default()
+        };
+        let pages =
+            Transformer::generate_with_options(&document, &PdfOptions::default(), &options)?;
+        let numbers: Vec<usize> = pages.iter().map(|page| page.number).collect();
+        assert_eq!(numbers, [2, 3]);
+        // an A4 page is 595.28 by 841.89 points, half an inch is 36 points
+        assert_eq!((pages[0].width, pages[0].height), (298, 421));
+        assert!(pages[0].bytes.starts_with(b"\x89PNG"));
+        // a range without end is not collected past the last page
+        let options = RasterOptions {
+            dpi: 36.0,
+            pages: Some(3..=usize::MAX),
+            ..Default::default()
+        };
+        let pages =
+            Transformer::generate_with_options(&document, &PdfOptions::default(), &options)?;
+        assert_eq!(pages.len(), 1);
+
+        let options = RasterOptions {
+            pages: Some(1..=1),
+            format: RasterFormat::Jpeg,
+            ..Default::default()
+        };
+        let pages =
+            Transformer::generate_with_options(&document, &PdfOptions::default(), &options)?;
+        assert_eq!(pages.len(), 1);
+        let image = image::load_from_memory_with_format(&pages[0].bytes, ImageFormat::Jpeg)?;
+        assert_eq!((image.width(), image.height()), (794, 1123));
+
+        for pages in [Some(4..=6), Some(4..=usize::MAX), Some(0..=1)] {
+            let options = RasterOptions {
+                pages,
+                ..Default::default()
+            };
+            assert!(Transformer::generate_with_options(
+                &document,
+                &PdfOptions::default(),
+                &options
+            )
+            .is_err());
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,670 @@
+//! Report of the content lost when a document is generated to a less expressive format
+//!
+//! The report is computed from what each generator writes: element types it skips, the
+//! attributes of [`Element::Attributed`] it drops, the bands it ignores. Losses are reported
+//! on the outermost dropped element only, its children are not listed.
+
+use std::fmt;
+
+use crate::core::{Band, Document, DocumentType, Element, ElementPath};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Loss {
+    /// Element left out of the output, with its children
+    Element {
+        path: ElementPath,
+        element: &'static str,
+    },
+    /// Attributes of an attributed element, the element itself is kept
+    Attributes {
+        path: ElementPath,
+        names: Vec<String>,
+    },
+}
+
+impl Loss {
+    pub fn path(&self) -> &ElementPath {
+        match self {
+            Loss::Element { path, .. } | Loss::Attributes { path, .. } => path,
+        }
+    }
+}
+
+impl fmt::Display for Loss {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Loss::Element { path, element } => write!(f, "{}: {} dropped", path, element),
+            Loss::Attributes { path, names } => {
+                write!(f, "{}: attributes {} dropped", path, names.join(", "))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConversionReport {
+    pub document_type: DocumentType,
+    pub losses: Vec<Loss>,
+}
+
+impl ConversionReport {
+    /// Whether the document was converted without losing anything
+    pub fn is_lossless(&self) -> bool {
+        self.losses.is_empty()
+    }
+}
+
+impl fmt::Display for ConversionReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.losses.is_empty() {
+            return writeln!(f, "Nothing lost in {}", self.document_type);
+        }
+        writeln!(f, "Lost in {}:", self.document_type)?;
+        for loss in &self.losses {
+            writeln!(f, "  {}", loss)?;
+        }
+        Ok(())
+    }
+}
+
+const ALL: &[&str] = &[
+    "Text",
+    "Header",
+    "Paragraph",
+    "Table",
+    "List",
+    "Image",
+    "LineBreak",
+    "TableOfContents",
+    "Field",
+    "CrossReference",
+    "Bookmark",
+    "Hyperlink",
+    "Media",
+    "Drawing",
+    "InlineCode",
+    "Styled",
+    "CodeBlock",
+    "Math",
+    "Footnote",
+    "Raw",
+    "Container",
+    "Attributed",
+];
+
+/// What a generator writes
+struct Support {
+    /// Element types written to the output
+    elements: &'static [&'static str],
+    /// Attributes of attributed elements are written
+    attributes: bool,
+    /// `Date` and `DocumentTitle` fields and cross references are replaced by text
+    /// before generation (see [`Document::resolve_fields`])
+    resolves_fields: bool,
+    /// Only tables of text cells are written (spreadsheets)
+    tables_only: bool,
+    /// Page header, footer and the other bands are left out
+    detail_only: bool,
+    /// Formats of the raw elements written as is, the others are left out
+    raw_formats: &'static [DocumentType],
+}
+
+impl Support {
+    fn of(document_type: DocumentType) -> Support {
+        let support = Support {
+            elements: ALL,
+            attributes: false,
+            resolves_fields: true,
+            tables_only: false,
+            detail_only: false,
+            raw_formats: &[],
+        };
+        match document_type {
+            DocumentType::Json | DocumentType::XML => Support {
+                attributes: true,
+                raw_formats: DocumentType::variants(),
+                ..support
+            },
+            DocumentType::HTML => Support {
+                attributes: true,
+                raw_formats: &[DocumentType::HTML],
+                ..support
+            },
+            DocumentType::ODT => support,
+            // the PDF and its SVG pages are compiled from typst source
+            DocumentType::PDF | DocumentType::Typst | DocumentType::SVG => Support {
+                raw_formats: &[DocumentType::Typst],
+                ..support
+            },
+            // page numbers are left out of the reflowable pages, like the page bands
+            DocumentType::EPUB => Support {
+                elements: &[
+                    "Text",
+                    "Header",
+                    "Paragraph",
+                    "Table",
+                    "List",
+                    "Image",
+                    "LineBreak",
+                    "TableOfContents",
+                    "CrossReference",
+                    "Bookmark",
+                    "Hyperlink",
+                    "Media",
+                    "Drawing",
+                    "InlineCode",
+                    "Styled",
+                    "CodeBlock",
+                    "Math",
+                    "Footnote",
+                    "Raw",
+                    "Container",
+                    "Attributed",
+                ],
+                raw_formats: &[DocumentType::HTML],
+                ..support
+            },
+            DocumentType::DOCX => Support {
+                elements: &[
+                    "Text",
+                    "Header",
+                    "Paragraph",
+                    "Table",
+                    "List",
+                    "Image",
+                    "LineBreak",
+                    "TableOfContents",
+                    "Field",
+                    "CrossReference",
+                    "Bookmark",
+                    "Hyperlink",
+                    "Media",
+                    "Drawing",
+                    "InlineCode",
+                    "Styled",
+                    "CodeBlock",
+                    "Math",
+                    "Container",
+                    "Attributed",
+                ],
+                ..support
+            },
+            // no pages and no anchors
+            DocumentType::Markdown => Support {
+                elements: &[
+                    "Text",
+                    "Header",
+                    "Paragraph",
+                    "Table",
+                    "List",
+                    "Image",
+                    "LineBreak",
+                    "TableOfContents",
+                    "Hyperlink",
+                    "Media",
+                    "Drawing",
+                    "InlineCode",
+                    "Styled",
+                    "CodeBlock",
+                    "Math",
+                    "Footnote",
+                    "Raw",
+                    "Container",
+                    "Attributed",
+                ],
+                raw_formats: &[DocumentType::Markdown, DocumentType::HTML],
+                ..support
+            },
+            // images are referenced but not saved
+            DocumentType::Text => Support {
+                elements: &[
+                    "Text",
+                    "Header",
+                    "Paragraph",
+                    "Table",
+                    "List",
+                    "LineBreak",
+                    "TableOfContents",
+                    "Hyperlink",
+                    "Media",
+                    "InlineCode",
+                    "Styled",
+                    "CodeBlock",
+                    "Math",
+                    "Footnote",
+                    "Raw",
+                    "Container",
+                    "Attributed",
+                ],
+                raw_formats: &[DocumentType::Text],
+                ..support
+            },
+            DocumentType::RTF => Support {
+                raw_formats: &[DocumentType::RTF],
+                ..support
+            },
+            DocumentType::LaTeX => Support {
+                raw_formats: &[DocumentType::LaTeX],
+                ..support
+            },
+            // page numbers have no place in a source without pages
+            DocumentType::AsciiDoc => Support {
+                elements: &[
+                    "Text",
+                    "Header",
+                    "Paragraph",
+                    "Table",
+                    "List",
+                    "Image",
+                    "LineBreak",
+                    "TableOfContents",
+                    "CrossReference",
+                    "Bookmark",
+                    "Hyperlink",
+                    "Media",
+                    "InlineCode",
+                    "Styled",
+                    "CodeBlock",
+                    "Math",
+                    "Footnote",
+                    "Raw",
+                    "Container",
+                    "Attributed",
+                ],
+                detail_only: true,
+                raw_formats: &[DocumentType::AsciiDoc, DocumentType::HTML],
+                ..support
+            },
+            // media are links
+            DocumentType::RST => Support {
+                elements: &[
+                    "Text",
+                    "Header",
+                    "Paragraph",
+                    "Table",
+                    "List",
+                    "Image",
+                    "LineBreak",
+                    "TableOfContents",
+                    "CrossReference",
+                    "Bookmark",
+                    "Hyperlink",
+                    "InlineCode",
+                    "Styled",
+                    "CodeBlock",
+                    "Math",
+                    "Footnote",
+                    "Raw",
+                    "Container",
+                    "Attributed",
+                ],
+                detail_only: true,
+                raw_formats: &[DocumentType::RST, DocumentType::HTML],
+                ..support
+            },
+            // media are links
+            DocumentType::Org => Support {
+                elements: &[
+                    "Text",
+                    "Header",
+                    "Paragraph",
+                    "Table",
+                    "List",
+                    "Image",
+                    "LineBreak",
+                    "TableOfContents",
+                    "Bookmark",
+                    "Hyperlink",
+                    "InlineCode",
+                    "Styled",
+                    "CodeBlock",
+                    "Math",
+                    "Footnote",
+                    "Raw",
+                    "Container",
+                    "Attributed",
+                ],
+                detail_only: true,
+                raw_formats: &[DocumentType::Org, DocumentType::HTML, DocumentType::LaTeX],
+                ..support
+            },
+            // media are links
+            DocumentType::MediaWiki => Support {
+                elements: &[
+                    "Text",
+                    "Header",
+                    "Paragraph",
+                    "Table",
+                    "List",
+                    "Image",
+                    "LineBreak",
+                    "TableOfContents",
+                    "Bookmark",
+                    "Hyperlink",
+                    "InlineCode",
+                    "Styled",
+                    "CodeBlock",
+                    "Math",
+                    "Footnote",
+                    "Raw",
+                    "Container",
+                    "Attributed",
+                ],
+                detail_only: true,
+                raw_formats: &[DocumentType::MediaWiki, DocumentType::HTML],
+                ..support
+            },
+            // media are links, page bands are left out
+            DocumentType::DocBook => Support {
+                elements: &[
+                    "Text",
+                    "Header",
+                    "Paragraph",
+                    "Table",
+                    "List",
+                    "Image",
+                    "LineBreak",
+                    "CrossReference",
+                    "Bookmark",
+                    "Hyperlink",
+                    "InlineCode",
+                    "Styled",
+                    "CodeBlock",
+                    "Math",
+                    "Footnote",
+                    "Raw",
+                    "Container",
+                    "Attributed",
+                ],
+                detail_only: true,
+                raw_formats: &[DocumentType::DocBook],
+                ..support
+            },
+            // media are links, math is TeX code and page bands are left out
+            DocumentType::Confluence => Support {
+                elements: &[
+                    "Text",
+                    "Header",
+                    "Paragraph",
+                    "Table",
+                    "List",
+                    "Image",
+                    "LineBreak",
+                    "TableOfContents",
+                    "Bookmark",
+                    "Hyperlink",
+                    "InlineCode",
+                    "Styled",
+                    "CodeBlock",
+                    "Math",
+                    "Footnote",
+                    "Container",
+                    "Attributed",
+                ],
+                detail_only: true,
+                ..support
+            },
+            // man pages have no images, anchors or page numbers, math is TeX source
+            DocumentType::Man => Support {
+                elements: &[
+                    "Text",
+                    "Header",
+                    "Paragraph",
+                    "Table",
+                    "List",
+                    "LineBreak",
+                    "CrossReference",
+                    "Hyperlink",
+                    "Media",
+                    "InlineCode",
+                    "Styled",
+                    "CodeBlock",
+                    "Math",
+                    "Footnote",
+                    "Raw",
+                    "Container",
+                    "Attributed",
+                ],
+                detail_only: true,
+                raw_formats: &[DocumentType::Man],
+                ..support
+            },
+            // slides have no footnotes or anchors, media are links and math is TeX source
+            DocumentType::PPTX => Support {
+                elements: &[
+                    "Text",
+                    "Header",
+                    "Paragraph",
+                    "Table",
+                    "List",
+                    "Image",
+                    "LineBreak",
+                    "TableOfContents",
+                    "CrossReference",
+                    "Hyperlink",
+                    "Media",
+                    "Drawing",
+                    "InlineCode",
+                    "Styled",
+                    "CodeBlock",
+                    "Math",
+                    "Container",
+                    "Attributed",
+                ],
+                detail_only: true,
+                ..support
+            },
+            // media are links, math is TeX source and page bands are left out
+            DocumentType::FB2 => Support {
+                elements: &[
+                    "Text",
+                    "Header",
+                    "Paragraph",
+                    "Table",
+                    "List",
+                    "Image",
+                    "LineBreak",
+                    "CrossReference",
+                    "Hyperlink",
+                    "InlineCode",
+                    "Styled",
+                    "CodeBlock",
+                    "Footnote",
+                    "Container",
+                    "Attributed",
+                ],
+                detail_only: true,
+                ..support
+            },
+            DocumentType::CSV
+            | DocumentType::TSV
+            | DocumentType::ODS
+            | DocumentType::XLS
+            | DocumentType::XLSX => Support {
+                elements: &["Table", "Text"],
+                tables_only: true,
+                ..support
+            },
+            // images, notebooks, Jira markup, BBCode, emails, contacts and calendars are not
+            // generated
+            DocumentType::Image
+            | DocumentType::Ipynb
+            | DocumentType::Jira
+            | DocumentType::BBCode
+            | DocumentType::Eml
+            | DocumentType::VCard
+            | DocumentType::ICalendar => Support {
+                elements: &[],
+                ..support
+            },
+        }
+    }
+
+    fn check(&self, element: &Element, path: ElementPath, losses: &mut Vec<Loss>) {
+        let kind: &'static str = element.into();
+        let raw_dropped =
+            matches!(element, Element::Raw { format, .. } if !self.raw_formats.contains(format));
+        if !self.elements.contains(&kind) || raw_dropped {
+            losses.push(Loss::Element {
+                path,
+                element: kind,
+            });
+            return;
+        }
+        if let Element::Attributed { attributes, .. } = element {
+            if !self.attributes {
+                let mut names: Vec<String> = attributes.keys().cloned().collect();
+                names.sort();
+                losses.push(Loss::Attributes {
+                    path: path.clone(),
+                    names,
+                });
+            }
+        }
+        for (index, child) in element.children().into_iter().enumerate() {
+            self.check(child, path.child(index), losses);
+        }
+    }
+
+    fn check_table(&self, element: &Element, path: ElementPath, losses: &mut Vec<Loss>) {
+        if !matches!(element, Element::Table { .. }) {
+            losses.push(Loss::Element {
+                path,
+                element: element.into(),
+            });
+            return;
+        }
+        for (index, cell) in element.children().into_iter().enumerate() {
+            if !matches!(cell, Element::Text { .. }) {
+                losses.push(Loss::Element {
+                    path: path.child(index),
+                    element: cell.into(),
+                });
+            }
+        }
+    }
+}
+
+/// Content of the document that a generation to `document_type` leaves out
+pub fn conversion_report(document: &Document, document_type: DocumentType) -> ConversionReport {
+    let support = Support::of(document_type);
+    let resolved;
+    let document = if support.resolves_fields {
+        // resolving maps each element to one element, so the paths are the same
+        resolved = document.resolve_fields();
+        &resolved
+    } else {
+        document
+    };
+
+    let mut losses = vec![];
+    for (band_index, band) in document.bands.iter().enumerate() {
+        for (index, element) in band.elements().iter().enumerate() {
+            let path = ElementPath::new(band_index, vec![index]);
+            if support.detail_only && !matches!(band, Band::Detail(_)) {
+                losses.push(Loss::Element {
+                    path,
+                    element: element.into(),
+                });
+            } else if support.tables_only {
+                support.check_table(element, path, &mut losses);
+            } else {
+                support.check(element, path, &mut losses);
+            }
+        }
+    }
+    ConversionReport {
+        document_type,
+        losses,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::core::*;
+    use crate::report::*;
+
+    fn text(text: &str) -> Element {
+        Element::Text {
+            text: text.to_string(),
+            size: 8,
+        }
+    }
+
+    #[test]
+    fn test_conversion_report() {
+        let mut document = Document::new(vec![
+            Element::Header {
+                level: 1,
+                text: "Title".to_string(),
+            },
+            Element::Paragraph {
+                elements: vec![
+                    text("Page "),
+                    Element::Field {
+                        kind: FieldKind::PageNumber,
+                    },
+                    Element::Field {
+                        kind: FieldKind::Date,
+                    },
+                ],
+                direction: TextDirection::default(),
+            },
+            Element::Attributed {
+                attributes: HashMap::from([
+                    ("id".to_string(), "t".to_string()),
+                    ("class".to_string(), "data".to_string()),
+                ]),
+                element: Box::new(Element::Table {
+                    headers: vec![TableHeader {
+                        element: text("Name"),
+                        width: 30.0,
+                    }],
+                    rows: vec![TableRow {
+                        cells: vec![TableCell {
+                            element: Element::LineBreak,
+                        }],
+                    }],
+                }),
+            },
+        ]);
+        document.set_page_footer(vec![text("footer")]);
+
+        assert!(conversion_report(&document, DocumentType::Json).is_lossless());
+        assert!(conversion_report(&document, DocumentType::DOCX)
+            .losses
+            .iter()
+            .all(|loss| matches!(loss, Loss::Attributes { .. })));
+
+        let report = conversion_report(&document, DocumentType::Markdown);
+        assert_eq!(
+            report.losses,
+            vec![
+                Loss::Element {
+                    path: "0/1/1".parse().unwrap(),
+                    element: "Field",
+                },
+                Loss::Attributes {
+                    path: "0/2".parse().unwrap(),
+                    names: vec!["class".to_string(), "id".to_string()],
+                },
+            ]
+        );
+        assert_eq!(
+            report.to_string(),
+            "Lost in markdown:\n  0/1/1: Field dropped\n  0/2: attributes class, id dropped\n"
+        );
+
+        let report = conversion_report(&document, DocumentType::CSV);
+        assert_eq!(
+            report
+                .losses
+                .iter()
+                .map(|loss| loss.path().to_string())
+                .collect::<Vec<_>>(),
+            vec!["0/0", "0/1", "0/2", "1/0"]
+        );
+    }
+}
@@ -0,0 +1,3247 @@
+//! reStructuredText source of a document, as read by docutils and Sphinx
+//!
+//! Headers become sections, adorned `=`, `-`, `~`, `^`, `"` and `'` by depth, and the
+//! metadata a title adorned above and below with bibliographic fields. Tables are written as
+//! `list-table` directives, code blocks as `code-block` directives, images as `image` and
+//! `figure` directives of files saved next to the source and asides with an `admonition`
+//! class as admonitions. Headers inside lists, tables and directives are rubrics, there are
+//! no sections there, and strikethrough text is written plain.
+//!
+//! Sources are read for sections, paragraphs, bullet, enumerated, definition and field
+//! lists, grid, simple, list and CSV tables, literal, line and doctest blocks, block quotes
+//! and the common directives of docutils and Sphinx, with the inline markup, roles,
+//! hyperlink references, footnotes and substitutions. A title adorned above and below and
+//! alone in its style is the document title. Directives that include other files, like
+//! `toctree` and `include`, are left out.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt::Write as _;
+
+use bytes::Bytes;
+use log::warn;
+
+use crate::core::{
+    disk_image_loader, disk_image_saver, header_anchor, Band, ContainerKind, Document,
+    DocumentType, Element, FieldKind, ImageData, ImageDimension, ListItem, Metadata,
+    NumberingStyle, TableCell, TableHeader, TableRow, TextStyle, TransformerTrait,
+    TransformerWithImageLoaderSaverTrait,
+};
+use crate::error::image_loader_errors;
+use crate::lightweight::{
+    dedent, dedent_by, expand_tabs, indentation, paragraph, plain_text, text, text_block, Inline,
+};
+
+pub struct Transformer;
+
+/// Kinds of the admonition directives
+const ADMONITIONS: [&str; 9] = [
+    "attention",
+    "caution",
+    "danger",
+    "error",
+    "hint",
+    "important",
+    "note",
+    "tip",
+    "warning",
+];
+
+/// Adornments of the sections by depth
+const SECTION_ADORNMENTS: [char; 6] = ['=', '-', '~', '^', '"', '\''];
+
+/// Characters of the section adornments and transitions
+const ADORNMENT_CHARACTERS: &str = "!\"#$%&'()*+,-./:;<=>?@[\\]^_`{|}~";
+
+/// Characters that can come before inline markup, besides whitespace
+const MARKUP_PRECEDERS: &str = "'\"([{<-/:‘“’«¡¿";
+
+/// Characters that can come after inline markup, besides whitespace
+const MARKUP_FOLLOWERS: &str = "'\")]}>-/:.,;!?\\’”»";
+
+/// Roles of literal text, read as inline code
+const LITERAL_ROLES: [&str; 14] = [
+    "code",
+    "literal",
+    "samp",
+    "file",
+    "kbd",
+    "command",
+    "program",
+    "envvar",
+    "option",
+    "makevar",
+    "regexp",
+    "mailheader",
+    "mimetype",
+    "token",
+];
+
+/// Roles of the objects of the Python domain, read as inline code
+const OBJECT_ROLES: [&str; 9] = [
+    "func", "class", "meth", "mod", "attr", "obj", "data", "const", "exc",
+];
+
+/// Directives that include other files or only change the rendering, left out
+const IGNORED_DIRECTIVES: [&str; 16] = [
+    "toctree",
+    "include",
+    "literalinclude",
+    "index",
+    "only",
+    "class",
+    "role",
+    "default-role",
+    "sectnum",
+    "section-numbering",
+    "header",
+    "footer",
+    "target-notes",
+    "tabularcolumns",
+    "currentmodule",
+    "module",
+];
+
+/// Schemes of the standalone URIs read as links
+const URI_SCHEMES: [&str; 4] = ["https://", "http://", "ftp://", "mailto:"];
+
+/// Width of text in columns, wide characters counting two
+fn width(text: &str) -> usize {
+    text.chars()
+        .map(|c| if (c as u32) < 0x1100 { 1 } else { 2 })
+        .sum()
+}
+
+/// Adornment of a title, two characters at least as a single one is not read as an adornment
+fn adornment_line(adornment: char, title: &str) -> String {
+    adornment.to_string().repeat(width(title).max(2))
+}
+
+/// Text with the characters of inline markup escaped, `::` being written `:\:` so that a
+/// paragraph ending with it is not read before a literal block
+fn escape(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut escaped = String::with_capacity(text.len());
+    for (index, &c) in chars.iter().enumerate() {
+        let next = chars.get(index + 1);
+        let word_start = index == 0 || !chars[index - 1].is_alphanumeric();
+        match c {
+            '\\' | '*' | '`' | '|' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            // `name_` is a reference
+            '_' if next.is_none_or(|next| !next.is_alphanumeric()) => escaped.push_str("\\_"),
+            ':' if next == Some(&':') => escaped.push_str(":\\"),
+            '\n' => escaped.push(' '),
+            '\r' => {}
+            c if word_start
+                && URI_SCHEMES.iter().any(|scheme| {
+                    chars[index..].starts_with(&scheme.chars().collect::<Vec<_>>())
+                }) =>
+            {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Text of a reference, without the `<` that would start an embedded URI
+fn escape_reference(text: &str) -> String {
+    escape(text).replace('<', "\\<")
+}
+
+/// Whether a line would be read as something else than text at the start of a paragraph:
+/// a list item, a field, explicit markup, a transition...
+fn needs_guard(line: &str) -> bool {
+    bullet_marker(line).is_some()
+        || enumerator(line).is_some()
+        || field_marker(line).is_some()
+        || line == ".."
+        || line.starts_with(".. ")
+        || line.starts_with("__ ")
+        || line.starts_with(">>>")
+        || line.starts_with(['-', '+', '/'])
+        || !line.starts_with('\\') && adornment(line).is_some()
+}
+
+/// Name of a hyperlink target, quoted when it would end early or be anonymous
+fn target_name(name: &str) -> String {
+    if name.contains(':') || name.starts_with('_') {
+        format!("`{name}`")
+    } else {
+        name.to_string()
+    }
+}
+
+/// Lines indented by `indent` spaces, blank lines left empty
+fn indent(text: &str, indent: usize) -> String {
+    let prefix = " ".repeat(indent);
+    text.lines()
+        .map(|line| {
+            if line.trim().is_empty() {
+                String::new()
+            } else {
+                format!("{prefix}{line}")
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Directive `.. head` with its options and indented content
+fn directive(head: &str, options: &[(&str, String)], content: &str) -> String {
+    let mut rst = format!(".. {head}");
+    for (name, value) in options {
+        let _ = write!(rst, "\n   :{name}: {value}");
+    }
+    if !content.trim().is_empty() {
+        rst.push_str("\n\n");
+        rst.push_str(&indent(content, 3));
+    }
+    rst
+}
+
+/// Whether an image size is a length reStructuredText reads: a number with an optional unit
+fn is_length(value: &str) -> bool {
+    let number = value
+        .trim()
+        .trim_end_matches(|c: char| c.is_ascii_alphabetic() || c == '%');
+    !number.is_empty() && number.parse::<f32>().is_ok()
+}
+
+/// Label of an admonition kind, `Note` for `note`
+fn label(kind: &str) -> String {
+    let mut chars = kind.chars();
+    chars
+        .next()
+        .map(|first| first.to_uppercase().chain(chars).collect())
+        .unwrap_or_default()
+}
+
+/// Roman numeral of a number
+fn roman(mut number: usize) -> String {
+    const NUMERALS: [(usize, &str); 13] = [
+        (1000, "m"),
+        (900, "cm"),
+        (500, "d"),
+        (400, "cd"),
+        (100, "c"),
+        (90, "xc"),
+        (50, "l"),
+        (40, "xl"),
+        (10, "x"),
+        (9, "ix"),
+        (5, "v"),
+        (4, "iv"),
+        (1, "i"),
+    ];
+    let mut numeral = String::new();
+    for (value, symbols) in NUMERALS {
+        while number >= value {
+            numeral.push_str(symbols);
+            number -= value;
+        }
+    }
+    numeral
+}
+
+/// Value of a roman numeral
+fn roman_value(numeral: &str) -> Option<usize> {
+    let values = numeral
+        .chars()
+        .map(|c| match c.to_ascii_lowercase() {
+            'i' => Some(1),
+            'v' => Some(5),
+            'x' => Some(10),
+            'l' => Some(50),
+            'c' => Some(100),
+            'd' => Some(500),
+            'm' => Some(1000),
+            _ => None,
+        })
+        .collect::<Option<Vec<usize>>>()?;
+    let mut value = 0;
+    for (index, &digit) in values.iter().enumerate() {
+        if values.get(index + 1).is_some_and(|&next| next > digit) {
+            value -= digit as isize;
+        } else {
+            value += digit as isize;
+        }
+    }
+    usize::try_from(value).ok().filter(|&value| value > 0)
+}
+
+/// Enumerator of the item `number` of an enumerated list
+fn enumerator_marker(numbering: NumberingStyle, number: usize) -> String {
+    let letter = |base: u8| {
+        u8::try_from(number)
+            .ok()
+            .filter(|number| (1..=26).contains(number))
+            .map(|number| char::from(base + number - 1).to_string())
+    };
+    let enumerator = match numbering {
+        NumberingStyle::LowerAlpha => letter(b'a'),
+        NumberingStyle::UpperAlpha => letter(b'A'),
+        NumberingStyle::LowerRoman if number > 0 => Some(roman(number)),
+        NumberingStyle::UpperRoman if number > 0 => Some(roman(number).to_uppercase()),
+        _ => None,
+    };
+    format!("{}.", enumerator.unwrap_or_else(|| number.to_string()))
+}
+
+/// Whether an element is an image, or a link of an image
+fn is_image(element: &Element) -> bool {
+    match element {
+        Element::Image(_) => true,
+        Element::Hyperlink { elements, .. } => matches!(elements.as_slice(), [Element::Image(_)]),
+        _ => false,
+    }
+}
+
+/// Whether an element is text only, that inline markup can hold
+fn is_plain(element: &Element) -> bool {
+    match element {
+        Element::Text { .. } => true,
+        Element::Styled { elements, .. } => elements.iter().all(is_plain),
+        _ => false,
+    }
+}
+
+/// Inline markup being written, with the escaped spaces (`\ `) that separate it from the
+/// text around it where it would not be recognized
+#[derive(Default)]
+struct InlineText {
+    lines: Vec<String>,
+    after_markup: bool,
+}
+
+impl InlineText {
+    fn line(&mut self) -> &mut String {
+        if self.lines.is_empty() {
+            self.lines.push(String::new());
+        }
+        let last = self.lines.len() - 1;
+        &mut self.lines[last]
+    }
+
+    fn text(&mut self, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        let after_markup = std::mem::replace(&mut self.after_markup, false);
+        let line = self.line();
+        if after_markup
+            && text.starts_with(|c: char| !c.is_whitespace() && !MARKUP_FOLLOWERS.contains(c))
+        {
+            line.push_str("\\ ");
+        }
+        line.push_str(text);
+    }
+
+    fn markup(&mut self, markup: &str) {
+        let line = self.line();
+        if line.ends_with(|c: char| !c.is_whitespace() && !MARKUP_PRECEDERS.contains(c)) {
+            line.push_str("\\ ");
+        }
+        line.push_str(markup);
+        self.after_markup = true;
+    }
+
+    fn line_break(&mut self) {
+        self.line();
+        self.lines.push(String::new());
+        self.after_markup = false;
+    }
+
+    fn finish(self) -> Vec<String> {
+        let mut lines: Vec<String> = self
+            .lines
+            .into_iter()
+            .map(|line| line.trim().to_string())
+            .collect();
+        while lines.last().is_some_and(String::is_empty) {
+            lines.pop();
+        }
+        let leading = lines.iter().take_while(|line| line.is_empty()).count();
+        lines.split_off(leading)
+    }
+}
+
+/// Options of an image directive
+type ImageOptions = Vec<(&'static str, String)>;
+
+struct Writer<'a, F>
+where
+    F: Fn(&Bytes, &str) -> anyhow::Result<()>,
+{
+    image_saver: &'a F,
+    image_count: usize,
+    link_targets: HashSet<String>,
+    /// Levels of the headers of the sections being written, a header being adorned for its
+    /// depth among them so that no section level is skipped
+    sections: Vec<u8>,
+    /// Depth of the lists, tables and directives being written, where headers are rubrics
+    depth: usize,
+    /// Targets of the bookmarks of the block being written, defined before it
+    targets: Vec<String>,
+    /// Footnotes and image substitutions of the block being written, defined after it
+    definitions: Vec<String>,
+}
+
+impl<F> Writer<'_, F>
+where
+    F: Fn(&Bytes, &str) -> anyhow::Result<()>,
+{
+    /// Blocks separated by blank lines, each after the targets and before the definitions of
+    /// its inline elements
+    fn blocks<'e>(
+        &mut self,
+        elements: impl IntoIterator<Item = &'e Element>,
+    ) -> anyhow::Result<String> {
+        let mut parts: Vec<String> = vec![];
+        // whether the last block is a list, and numbered
+        let mut last_list = None;
+        for element in elements {
+            let rst = self.block(element)?;
+            let targets = std::mem::take(&mut self.targets);
+            let definitions = std::mem::take(&mut self.definitions);
+            if !rst.is_empty() {
+                let list = match element {
+                    Element::List { numbered, .. } => Some(*numbered),
+                    _ => None,
+                };
+                if list.is_some() && list == last_list {
+                    // an empty comment ends the list before, the lists would be one otherwise
+                    parts.push("..".to_string());
+                }
+                last_list = list;
+            }
+            parts.extend(
+                targets
+                    .iter()
+                    .map(|target| format!(".. _{}:", target_name(target))),
+            );
+            if !rst.is_empty() {
+                parts.push(rst);
+            }
+            if !definitions.is_empty() {
+                parts.push(definitions.join("\n"));
+            }
+        }
+        Ok(parts.join("\n\n"))
+    }
+
+    /// Blocks nested in a list, a table or a directive
+    fn nested<'e>(
+        &mut self,
+        elements: impl IntoIterator<Item = &'e Element>,
+    ) -> anyhow::Result<String> {
+        self.depth += 1;
+        let rst = self.blocks(elements);
+        self.depth -= 1;
+        rst
+    }
+
+    fn block(&mut self, element: &Element) -> anyhow::Result<String> {
+        if let Some((kind, title, blocks)) = element.admonition_parts() {
+            return self.admonition(kind, title.as_deref(), blocks);
+        }
+        Ok(match element {
+            Element::Header { level, text } => self.header(*level, text, &header_anchor(text)),
+            Element::Attributed {
+                attributes,
+                element,
+            } => match element.as_ref() {
+                Element::Header { level, text } => {
+                    let anchor = attributes
+                        .get("id")
+                        .cloned()
+                        .unwrap_or_else(|| header_anchor(text));
+                    self.header(*level, text, &anchor)
+                }
+                element => self.block(element)?,
+            },
+            Element::Paragraph { elements, .. } => match elements.as_slice() {
+                [image] if is_image(image) => self.image_block(image)?,
+                elements => self.paragraph(elements)?,
+            },
+            element if is_image(element) => self.image_block(element)?,
+            Element::Table { headers, rows } => self.table(headers, rows)?,
+            Element::List { .. } => self.list(element)?,
+            Element::CodeBlock { code, .. } => {
+                let code = code.trim_end();
+                if code.trim().is_empty() {
+                    String::new()
+                } else {
+                    match element.code_language() {
+                        Some(language) => directive(&format!("code-block:: {language}"), &[], code),
+                        None => format!("::\n\n{}", indent(code, 3)),
+                    }
+                }
+            }
+            Element::Math { tex, display: true } => directive("math::", &[], tex.trim()),
+            Element::TableOfContents { max_level } => {
+                directive("contents::", &[("depth", max_level.to_string())], "")
+            }
+            Element::Media { .. } => match element.media_link() {
+                Some(link) => self.paragraph(&[link])?,
+                None => String::new(),
+            },
+            Element::Container {
+                kind: ContainerKind::Aside,
+                elements,
+            } => {
+                let content = self.nested(elements)?;
+                if content.is_empty() {
+                    content
+                } else {
+                    directive("container:: aside", &[], &content)
+                }
+            }
+            Element::Container { elements, .. } => self.blocks(elements)?,
+            Element::Raw {
+                format: DocumentType::RST,
+                content,
+            } => content.trim_end().to_string(),
+            Element::Raw {
+                format: DocumentType::HTML,
+                content,
+            } => directive("raw:: html", &[], content.trim_end()),
+            Element::Raw { .. } | Element::LineBreak | Element::Drawing { .. } => String::new(),
+            element => self.paragraph(std::slice::from_ref(element))?,
+        })
+    }
+
+    fn header(&mut self, level: u8, text: &str, anchor: &str) -> String {
+        let title = escape(text.trim());
+        if title.is_empty() {
+            return title;
+        }
+        if self.depth > 0 {
+            return format!(".. rubric:: {title}");
+        }
+        while self.sections.last().is_some_and(|last| *last >= level) {
+            self.sections.pop();
+        }
+        self.sections.push(level);
+        let adornment = SECTION_ADORNMENTS[(self.sections.len() - 1).min(5)];
+        let mut rst = String::new();
+        if self.link_targets.contains(anchor) {
+            let _ = write!(rst, ".. _{}:\n\n", target_name(anchor));
+        }
+        let _ = write!(rst, "{title}\n{}", adornment_line(adornment, &title));
+        rst
+    }
+
+    /// Paragraph of inline elements, a line block when it has line breaks
+    fn paragraph(&mut self, elements: &[Element]) -> anyhow::Result<String> {
+        let mut out = InlineText::default();
+        for element in elements {
+            self.inline(element, &mut out)?;
+        }
+        Ok(match out.finish().as_slice() {
+            [] => String::new(),
+            [line] if needs_guard(line) => format!("\\{line}"),
+            [line] => line.clone(),
+            lines => lines
+                .iter()
+                .map(|line| {
+                    if line.is_empty() {
+                        "|".to_string()
+                    } else {
+                        format!("| {line}")
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+        })
+    }
+
+    fn inline(&mut self, element: &Element, out: &mut InlineText) -> anyhow::Result<()> {
+        match element {
+            Element::Text { text, .. } => out.text(&escape(text)),
+            Element::Header { text, .. } => styled("**", text, out),
+            Element::Paragraph { elements, .. } | Element::Container { elements, .. } => {
+                for element in elements {
+                    self.inline(element, out)?;
+                }
+            }
+            Element::Styled { style, elements } => {
+                let marks = match style {
+                    TextStyle::Strong => Some("**"),
+                    TextStyle::Emphasis => Some("*"),
+                    TextStyle::Strikethrough => None,
+                };
+                match marks {
+                    Some(marks) if elements.iter().all(is_plain) => {
+                        let text: String = elements.iter().map(Element::plain_text).collect();
+                        styled(marks, &text, out);
+                    }
+                    _ => {
+                        for element in elements {
+                            self.inline(element, out)?;
+                        }
+                    }
+                }
+            }
+            Element::Hyperlink { elements, url, .. } => {
+                if let [Element::Image(image)] = elements.as_slice() {
+                    return self.inline_image(image, Some(url), out);
+                }
+                let label: String = elements.iter().map(Element::plain_text).collect();
+                let (label, target) = match url.strip_prefix('#') {
+                    Some(anchor) => (label.trim(), format!("{anchor}_")),
+                    None => (label.trim(), url.clone()),
+                };
+                let label = if label.is_empty() {
+                    url.strip_prefix('#').unwrap_or(url)
+                } else {
+                    label
+                };
+                out.markup(&format!("`{} <{target}>`__", escape_reference(label)));
+            }
+            Element::InlineCode { text } => {
+                let code = text.replace('\n', " ");
+                if !code.trim().is_empty() {
+                    out.markup(&format!("``{code}``"));
+                }
+            }
+            Element::CodeBlock { code, .. } => {
+                for (index, line) in code.lines().enumerate() {
+                    if index > 0 {
+                        out.line_break();
+                    }
+                    if !line.trim().is_empty() {
+                        out.markup(&format!("``{line}``"));
+                    }
+                }
+            }
+            Element::Math { tex, .. } => out.markup(&format!(":math:`{}`", tex.trim())),
+            Element::LineBreak => out.line_break(),
+            Element::Footnote { elements } => {
+                let mut note = InlineText::default();
+                for element in elements {
+                    self.inline(element, &mut note)?;
+                    note.text(" ");
+                }
+                out.markup("[#]_");
+                self.definitions
+                    .push(format!(".. [#] {}", note.finish().join(" ")));
+            }
+            Element::Bookmark { id, .. } => self.targets.push(id.clone()),
+            Element::Image(image) => self.inline_image(image, None, out)?,
+            Element::Attributed { element, .. } => self.inline(element, out)?,
+            Element::Raw {
+                format: DocumentType::RST,
+                content,
+            } => out.text(content),
+            Element::Media { .. } => {
+                if let Some(link) = element.media_link() {
+                    self.inline(&link, out)?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Image substitution (`|image1|`) defined after the block
+    fn inline_image(
+        &mut self,
+        image: &ImageData,
+        url: Option<&String>,
+        out: &mut InlineText,
+    ) -> anyhow::Result<()> {
+        let Some((name, mut options)) = self.save_image(image)? else {
+            return Ok(());
+        };
+        if let Some(url) = url {
+            options.push(("target", url.clone()));
+        }
+        let substitution = format!("image{}", self.image_count);
+        out.markup(&format!("|{substitution}|"));
+        self.definitions.push(directive(
+            &format!("|{substitution}| image:: {name}"),
+            &options,
+            "",
+        ));
+        Ok(())
+    }
+
+    /// `image` directive, or `figure` with the title as caption
+    fn image_block(&mut self, element: &Element) -> anyhow::Result<String> {
+        let (image, url) = match element {
+            Element::Hyperlink { elements, url, .. } => match elements.as_slice() {
+                [Element::Image(image)] => (image, Some(url)),
+                _ => return Ok(String::new()),
+            },
+            Element::Image(image) => (image, None),
+            _ => return Ok(String::new()),
+        };
+        let Some((name, mut options)) = self.save_image(image)? else {
+            return Ok(String::new());
+        };
+        if let Some(url) = url {
+            options.push(("target", url.clone()));
+        }
+        Ok(if image.title().trim().is_empty() {
+            directive(&format!("image:: {name}"), &options, "")
+        } else {
+            directive(
+                &format!("figure:: {name}"),
+                &options,
+                &escape(image.title().trim()),
+            )
+        })
+    }
+
+    /// Name of the saved image and the options of its directive
+    fn save_image(&mut self, image: &ImageData) -> anyhow::Result<Option<(String, ImageOptions)>> {
+        if image.bytes().is_empty() {
+            warn!("Image without content left out: {}", image.title());
+            return Ok(None);
+        }
+        self.image_count += 1;
+        let name = format!(
+            "image{}{}",
+            self.image_count,
+            image.image_type().to_extension()
+        );
+        (self.image_saver)(image.bytes(), &name)?;
+        let mut options = vec![];
+        if !image.alt().trim().is_empty() {
+            options.push(("alt", image.alt().trim().replace('\n', " ")));
+        }
+        let size = image.size();
+        if let Some(width) = size.width.as_ref().filter(|width| is_length(width)) {
+            options.push(("width", width.trim().to_string()));
+        }
+        if let Some(height) = size.height.as_ref().filter(|height| is_length(height)) {
+            options.push(("height", height.trim().to_string()));
+        }
+        Ok(Some((name, options)))
+    }
+
+    /// Admonition directive of its kind, or a titled `admonition` of the kind as class
+    fn admonition(
+        &mut self,
+        kind: &str,
+        title: Option<&str>,
+        blocks: &[Element],
+    ) -> anyhow::Result<String> {
+        let kind = kind.to_lowercase();
+        let mut content = self.nested(blocks)?;
+        let title = match title {
+            None if ADMONITIONS.contains(&kind.as_str()) => None,
+            title => Some(escape(&title.map_or_else(|| label(&kind), str::to_string))),
+        };
+        if content.is_empty() {
+            // admonitions have content
+            content = title.clone().unwrap_or_else(|| label(&kind));
+        }
+        Ok(match title {
+            None => directive(&format!("{kind}::"), &[], &content),
+            Some(title) => directive(
+                &format!("admonition:: {title}"),
+                &[("class", kind.replace(char::is_whitespace, "-"))],
+                &content,
+            ),
+        })
+    }
+
+    /// `list-table` directive, its header the first row
+    fn table(&mut self, headers: &[TableHeader], rows: &[TableRow]) -> anyhow::Result<String> {
+        let columns = rows
+            .iter()
+            .map(|row| row.cells.len())
+            .chain([headers.len()])
+            .max()
+            .unwrap_or_default();
+        if columns == 0 {
+            return Ok(String::new());
+        }
+        let mut options = vec![];
+        if !headers.is_empty() {
+            options.push(("header-rows", "1".to_string()));
+            if headers.len() == columns {
+                let widths: Vec<String> = headers
+                    .iter()
+                    .map(|header| (header.width.round() as u32).max(1).to_string())
+                    .collect();
+                options.push(("widths", widths.join(" ")));
+            }
+        }
+        let header_row: Vec<&Element> = headers.iter().map(|header| &header.element).collect();
+        let mut all_rows = vec![];
+        if !headers.is_empty() {
+            all_rows.push(header_row);
+        }
+        all_rows.extend(
+            rows.iter()
+                .map(|row| row.cells.iter().map(|cell| &cell.element).collect()),
+        );
+        let mut body = vec![];
+        for row in all_rows {
+            for column in 0..columns {
+                let content = match row.get(column) {
+                    Some(cell) => self.nested([*cell])?,
+                    None => String::new(),
+                };
+                let marker = if column == 0 { "* -" } else { "  -" };
+                let mut lines = content.lines();
+                match lines.next() {
+                    Some(first) => body.push(format!("{marker} {first}")),
+                    None => body.push(marker.to_string()),
+                }
+                body.extend(lines.map(|line| indent(line, 4)));
+            }
+        }
+        Ok(directive("list-table::", &options, &body.join("\n")))
+    }
+
+    /// Bullet or enumerated list, the blocks after an item being its own blocks
+    fn list(&mut self, list: &Element) -> anyhow::Result<String> {
+        let Element::List {
+            elements,
+            numbered,
+            start,
+            numbering,
+        } = list
+        else {
+            return Ok(String::new());
+        };
+        let mut items: Vec<Vec<&Element>> = vec![];
+        for item in elements {
+            let element = &item.element;
+            match items.last_mut() {
+                Some(blocks)
+                    if !element.is_inline()
+                        && !matches!(
+                            element,
+                            Element::Paragraph { .. } | Element::Header { .. }
+                        ) =>
+                {
+                    blocks.push(element)
+                }
+                _ => items.push(vec![element]),
+            }
+        }
+        let mut rst = vec![];
+        let mut loose = false;
+        for (index, blocks) in items.into_iter().enumerate() {
+            let marker = if *numbered {
+                enumerator_marker(*numbering, start + index)
+            } else {
+                "-".to_string()
+            };
+            let body = self.nested(blocks)?;
+            loose |= body.contains('\n');
+            let mut lines = body.lines();
+            let mut item = match lines.next() {
+                Some(first) => format!("{marker} {first}"),
+                None => marker.clone(),
+            };
+            for line in lines {
+                item.push('\n');
+                item.push_str(&indent(line, marker.len() + 1));
+            }
+            rst.push(item);
+        }
+        Ok(rst.join(if loose { "\n\n" } else { "\n" }))
+    }
+}
+
+/// Inline markup of text, the whitespace around it left outside
+fn styled(marks: &str, text: &str, out: &mut InlineText) {
+    let trimmed = text.trim();
+    if trimmed.is_empty() {
+        out.text(&escape(text));
+        return;
+    }
+    if text.starts_with(char::is_whitespace) {
+        out.text(" ");
+    }
+    out.markup(&format!("{marks}{}{marks}", escape(trimmed)));
+    if text.ends_with(char::is_whitespace) {
+        out.text(" ");
+    }
+}
+
+/// Target of a hyperlink reference name
+#[derive(Debug, Clone)]
+enum Target {
+    /// External URI
+    Uri(String),
+    /// Other reference name, `.. _name: other_`
+    Alias(String),
+    /// Anchor of a section or a bookmark of the document
+    Anchor(String),
+}
+
+/// Directive of an explicit markup block: `.. name:: arguments`, its options and content
+#[derive(Debug, Clone)]
+struct Directive {
+    name: String,
+    arguments: String,
+    options: HashMap<String, String>,
+    content: Vec<String>,
+}
+
+impl Directive {
+    /// Directive of an explicit markup block, from its first line after `.. ` and the
+    /// indented lines after it
+    fn read(first: &str, lines: &[String]) -> Option<Directive> {
+        let (name, rest) = first.split_once("::")?;
+        let name = name.trim();
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_alphanumeric() || "-_.:+".contains(c))
+        {
+            return None;
+        }
+        let lines = dedent(lines);
+        let mut arguments = vec![rest.trim().to_string()];
+        let mut index = 0;
+        while index < lines.len()
+            && !lines[index].trim().is_empty()
+            && field_marker(&lines[index]).is_none()
+        {
+            arguments.push(lines[index].trim().to_string());
+            index += 1;
+        }
+        let mut options = HashMap::new();
+        while let Some((option, value)) = lines.get(index).and_then(|line| field_marker(line)) {
+            let mut value = value.trim().to_string();
+            index += 1;
+            while let Some(line) = lines
+                .get(index)
+                .filter(|line| line.starts_with(' ') && !line.trim().is_empty())
+            {
+                value.push(' ');
+                value.push_str(line.trim());
+                index += 1;
+            }
+            options.insert(option.to_lowercase(), value);
+        }
+        while lines.get(index).is_some_and(|line| line.trim().is_empty()) {
+            index += 1;
+        }
+        arguments.retain(|argument| !argument.is_empty());
+        Some(Directive {
+            name: name.to_lowercase(),
+            arguments: arguments.join("\n"),
+            options,
+            content: lines[index.min(lines.len())..].to_vec(),
+        })
+    }
+
+    /// Arguments and content, the content of the directives without arguments starting on
+    /// their first line
+    fn body(&self) -> Vec<String> {
+        let mut body: Vec<String> = self.arguments.lines().map(str::to_string).collect();
+        if !body.is_empty() && !self.content.is_empty() {
+            body.push(String::new());
+        }
+        body.extend(self.content.iter().cloned());
+        body
+    }
+}
+
+/// End of the blank or indented lines from `start`, without the blank lines at the end
+fn indented_end(lines: &[String], start: usize) -> usize {
+    let mut end = start;
+    let mut index = start;
+    while let Some(line) = lines.get(index) {
+        index += 1;
+        if line.trim().is_empty() {
+            continue;
+        }
+        if indentation(line) == 0 {
+            break;
+        }
+        end = index;
+    }
+    end
+}
+
+/// First line from `index` that is not blank
+fn next_line(lines: &[String], mut index: usize) -> usize {
+    while lines.get(index).is_some_and(|line| line.trim().is_empty()) {
+        index += 1;
+    }
+    index
+}
+
+/// Character of a line of one repeated punctuation character, an adornment or a transition
+fn adornment(line: &str) -> Option<char> {
+    let line = line.trim_end();
+    let first = line.chars().next()?;
+    (line.chars().count() >= 2
+        && ADORNMENT_CHARACTERS.contains(first)
+        && line.chars().all(|c| c == first))
+    .then_some(first)
+}
+
+/// Section title at `index`: its text, adornment character, whether it is adorned above
+/// too, and the end of its lines
+fn section_title(lines: &[String], index: usize) -> Option<(String, (char, bool), usize)> {
+    let line = lines.get(index)?;
+    if let Some(over) = adornment(line) {
+        let title = lines.get(index + 1)?;
+        let under = lines.get(index + 2)?;
+        return (!title.trim().is_empty()
+            && adornment(title).is_none()
+            && adornment(under) == Some(over))
+        .then(|| (title.trim().to_string(), (over, true), index + 3));
+    }
+    if line.trim().is_empty() || indentation(line) > 0 {
+        return None;
+    }
+    let under = lines.get(index + 1)?;
+    let character = adornment(under)?;
+    let length = under.chars().count();
+    (length >= width(line) || length >= 4)
+        .then(|| (line.to_string(), (character, false), index + 2))
+}
+
+/// Title of the section after the hyperlink targets at `index`, that they refer to
+fn next_section(lines: &[String], mut index: usize) -> Option<String> {
+    loop {
+        index = next_line(lines, index);
+        let line = lines.get(index)?;
+        if line.starts_with(".. _") && line.ends_with(':') {
+            index = indented_end(lines, index + 1);
+        } else {
+            return section_title(lines, index).map(|(title, _, _)| title);
+        }
+    }
+}
+
+/// Bullet of a bullet list item and the byte column of its text
+fn bullet_marker(line: &str) -> Option<(char, usize)> {
+    let first = line.chars().next()?;
+    if !"*+-•‣⁃".contains(first) {
+        return None;
+    }
+    let rest = &line[first.len_utf8()..];
+    if !rest.is_empty() && !rest.starts_with(' ') {
+        return None;
+    }
+    Some((first, line.len() - rest.trim_start().len()))
+}
+
+/// Enumerator of an enumerated list item: `1.`, `(a)`, `iv)`, `#.`
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Enumerator {
+    numbering: NumberingStyle,
+    value: usize,
+    parenthesized: bool,
+    suffix: char,
+    /// Byte column of the text of the item
+    column: usize,
+}
+
+fn enumerator(line: &str) -> Option<Enumerator> {
+    let (parenthesized, rest) = match line.strip_prefix('(') {
+        Some(rest) => (true, rest),
+        None => (false, line),
+    };
+    let end = rest.find(|c: char| !c.is_ascii_alphanumeric() && c != '#')?;
+    let token = &rest[..end];
+    let suffix = rest[end..].chars().next()?;
+    if token.is_empty() || !(suffix == ')' || suffix == '.' && !parenthesized) {
+        return None;
+    }
+    let after = &rest[end + 1..];
+    if !after.is_empty() && !after.starts_with(' ') {
+        return None;
+    }
+    let is_roman = |numerals: &str| {
+        token.chars().all(|c| numerals.contains(c)) && (token.len() > 1 || token == &numerals[..1])
+    };
+    let (numbering, value) = if token == "#" {
+        (NumberingStyle::Decimal, 1)
+    } else if token.chars().all(|c| c.is_ascii_digit()) {
+        (NumberingStyle::Decimal, token.parse().ok()?)
+    } else if is_roman("ivxlcdm") {
+        (NumberingStyle::LowerRoman, roman_value(token)?)
+    } else if is_roman("IVXLCDM") {
+        (NumberingStyle::UpperRoman, roman_value(token)?)
+    } else {
+        match token.as_bytes() {
+            [letter @ b'a'..=b'z'] => (NumberingStyle::LowerAlpha, usize::from(letter - b'a' + 1)),
+            [letter @ b'A'..=b'Z'] => (NumberingStyle::UpperAlpha, usize::from(letter - b'A' + 1)),
+            _ => return None,
+        }
+    };
+    Some(Enumerator {
+        numbering,
+        value,
+        parenthesized,
+        suffix,
+        column: line.len() - after.trim_start().len(),
+    })
+}
+
+/// Name and text of a field list item, `:name: text`
+fn field_marker(line: &str) -> Option<(&str, &str)> {
+    let rest = line.strip_prefix(':')?;
+    let mut search = 0;
+    while let Some(found) = rest[search..].find(':') {
+        let end = search + found;
+        let after = &rest[end + 1..];
+        if end > 0 && !rest[..end].ends_with('\\') && (after.is_empty() || after.starts_with(' ')) {
+            let name = &rest[..end];
+            return (!name.starts_with(' ') && !name.ends_with(' '))
+                .then(|| (name, after.trim_start()));
+        }
+        search = end + 1;
+    }
+    None
+}
+
+/// Whether a grid table border starts at a line: `+---+---+`
+fn is_grid_border(line: &str) -> bool {
+    line.len() >= 3
+        && (line.starts_with("+-") || line.starts_with("+="))
+        && line.ends_with('+')
+        && line.chars().all(|c| "+-=".contains(c))
+}
+
+/// Whether a line is a simple table border: `=====  =====`
+fn is_simple_border(line: &str) -> bool {
+    line.len() >= 2 && line.starts_with('=') && line.chars().all(|c| c == '=' || c == ' ')
+}
+
+/// End of the simple table at `index`, its bottom border being followed by a blank line
+fn simple_table_end(lines: &[String], index: usize) -> Option<usize> {
+    if !is_simple_border(&lines[index]) {
+        return None;
+    }
+    for (offset, line) in lines[index + 1..].iter().enumerate() {
+        let next = index + offset + 2;
+        if is_simple_border(line) && lines.get(next).is_none_or(|line| line.trim().is_empty()) {
+            return Some(next);
+        }
+        if line.trim().is_empty() {
+            return None;
+        }
+    }
+    None
+}
+
+/// Fields of a line of CSV, quoted with `"`
+fn csv_fields(line: &str, delimiter: char) -> Vec<String> {
+    let mut fields = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            c if c == delimiter && !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+        .into_iter()
+        .map(|field| field.trim().to_string())
+        .collect()
+}
+
+/// Reference name as compared: lowercase with its whitespace collapsed
+fn normalize(name: &str) -> String {
+    name.trim_matches('`')
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+/// Text without its backslash escapes, an escaped whitespace being removed
+fn unescape(chars: &[char]) -> String {
+    let mut text = String::with_capacity(chars.len());
+    let mut index = 0;
+    while index < chars.len() {
+        match (chars[index], chars.get(index + 1)) {
+            ('\\', Some(next)) if next.is_whitespace() => index += 2,
+            ('\\', Some(&next)) => {
+                text.push(next);
+                index += 2;
+            }
+            ('\\', None) => index += 1,
+            ('\n', _) => {
+                text.push(' ');
+                index += 1;
+            }
+            (c, _) => {
+                text.push(c);
+                index += 1;
+            }
+        }
+    }
+    text
+}
+
+fn unescape_str(text: &str) -> String {
+    unescape(&text.chars().collect::<Vec<_>>())
+}
+
+/// Text of a section title for its implicit target, without inline markup
+fn plain_title(title: &str) -> String {
+    unescape_str(&title.replace("**", "").replace(['*', '`'], ""))
+}
+
+/// Label and target of a reference with an embedded URI or alias: `label <target>`
+fn embedded(content: &str) -> Option<(&str, &str)> {
+    let content = content.trim();
+    let inner = content.strip_suffix('>')?;
+    let open = inner.rfind('<')?;
+    let label = &inner[..open];
+    if !label.is_empty() && !label.ends_with(char::is_whitespace) || label.ends_with('\\') {
+        return None;
+    }
+    Some((label.trim(), inner[open + 1..].trim()))
+}
+
+/// Whether inline markup of `marks` characters starts at `index`
+fn starts_markup(chars: &[char], index: usize, marks: usize) -> bool {
+    starts_boundary(index.checked_sub(1).map(|previous| chars[previous]))
+        && chars
+            .get(index + marks)
+            .is_some_and(|next| !next.is_whitespace())
+}
+
+fn starts_boundary(previous: Option<char>) -> bool {
+    previous.is_none_or(|c| c.is_whitespace() || MARKUP_PRECEDERS.contains(c))
+}
+
+fn ends_boundary(next: Option<char>) -> bool {
+    next.is_none_or(|c| c.is_whitespace() || MARKUP_FOLLOWERS.contains(c))
+}
+
+/// Index of the end-string closing inline markup whose content starts at `from`, followed
+/// by whitespace, punctuation or one of `suffixes`
+fn find_end(chars: &[char], from: usize, end: &str, suffixes: &str) -> Option<usize> {
+    let end: Vec<char> = end.chars().collect();
+    let literal = end == ['`', '`'];
+    let mut index = from + 1;
+    while index + end.len() <= chars.len() {
+        let previous = chars[index - 1];
+        if chars[index..index + end.len()] == end[..]
+            && !previous.is_whitespace()
+            && (literal || previous != '\\')
+        {
+            let after = chars.get(index + end.len()).copied();
+            if ends_boundary(after) || after.is_some_and(|c| suffixes.contains(c)) {
+                return Some(index);
+            }
+        }
+        index += 1;
+    }
+    None
+}
+
+/// Role at `index`, `:name:`, and the index after it
+fn role_at(chars: &[char], index: usize) -> Option<(String, usize)> {
+    if chars.get(index) != Some(&':') {
+        return None;
+    }
+    let mut end = index + 1;
+    while end < chars.len() && (chars[end].is_alphanumeric() || "-_.+:".contains(chars[end])) {
+        end += 1;
+    }
+    let close = index + 1 + chars[index + 1..end].iter().rposition(|&c| c == ':')?;
+    (close > index + 1).then(|| {
+        let name: String = chars[index + 1..close].iter().collect();
+        (name.to_lowercase(), close + 1)
+    })
+}
+
+/// Whether a label is one of a footnote or a citation: `1`, `#`, `#name`, `*` or a name
+fn is_footnote_label(label: &str) -> bool {
+    let name = label.strip_prefix('#').unwrap_or(label);
+    label == "*"
+        || label == "#"
+        || !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_alphanumeric() || "-_.".contains(c))
+}
+
+struct Parser<'a, F>
+where
+    F: Fn(&str) -> anyhow::Result<Bytes>,
+{
+    image_loader: &'a F,
+    metadata: Metadata,
+    /// Targets by normalized reference name
+    targets: HashMap<String, Target>,
+    /// Titles of the sections by anchor, the text of the references without one
+    titles: HashMap<String, String>,
+    /// URIs of the anonymous targets not referred to yet
+    anonymous: VecDeque<String>,
+    /// Lines of the footnotes and citations by label, the auto-numbered (`#`) and symbol
+    /// (`*`) ones in order
+    footnotes: HashMap<String, VecDeque<Vec<String>>>,
+    /// Substitution definitions by normalized name
+    substitutions: HashMap<String, Directive>,
+    /// Adornments of the section levels, in the order of their first titles
+    adornments: Vec<(char, bool)>,
+    /// Language of the literal blocks, set by `highlight`
+    language: String,
+    /// Ids of the targets before the next block, bookmarks at its start
+    bookmarks: Vec<String>,
+    /// Depth of the footnotes and substitutions being read, that may refer to themselves
+    depth: usize,
+}
+
+impl<F> Parser<'_, F>
+where
+    F: Fn(&str) -> anyhow::Result<Bytes>,
+{
+    /// Reads the hyperlink targets, footnotes, citations and substitution definitions ahead
+    /// of their references, and counts the titles of each adornment
+    fn definitions(&mut self, lines: &[String]) -> HashMap<(char, bool), usize> {
+        let mut adornments = HashMap::new();
+        let mut index = 0;
+        while index < lines.len() {
+            let line = &lines[index];
+            let trimmed = line.trim_start();
+            if let Some(rest) = trimmed.strip_prefix(".. ") {
+                let end = indented_end(lines, index + 1).max(index + 1);
+                let following = dedent(&lines[index + 1..end]);
+                if let Some(target) = rest.strip_prefix('_') {
+                    self.target_definition(target, &following, lines, end);
+                } else if let Some((label, body)) =
+                    rest.strip_prefix('[').and_then(|rest| rest.split_once(']'))
+                {
+                    let mut body = vec![body.trim().to_string()];
+                    body.extend(following);
+                    let label = normalize(label);
+                    self.footnotes.entry(label).or_default().push_back(body);
+                } else if let Some((name, rest)) =
+                    rest.strip_prefix('|').and_then(|rest| rest.split_once('|'))
+                {
+                    if let Some(directive) = Directive::read(rest.trim(), &lines[index + 1..end]) {
+                        self.substitutions.insert(normalize(name), directive);
+                    }
+                }
+            } else if let Some(uri) = trimmed.strip_prefix("__ ") {
+                self.anonymous.push_back(uri.trim().to_string());
+            } else if let Some((title, adornment, end)) = section_title(lines, index) {
+                *adornments.entry(adornment).or_insert(0) += 1;
+                let title = plain_title(&title);
+                let anchor = header_anchor(&title);
+                self.titles.insert(anchor.clone(), title.clone());
+                self.targets
+                    .entry(normalize(&title))
+                    .or_insert(Target::Anchor(anchor));
+                index = end;
+                continue;
+            }
+            index += 1;
+        }
+        adornments
+    }
+
+    /// Reads a hyperlink target, `name: uri`, `name: other_` or `name:` before a section or
+    /// another block
+    fn target_definition(
+        &mut self,
+        target: &str,
+        following: &[String],
+        lines: &[String],
+        end: usize,
+    ) {
+        let (name, rest) = match target.strip_prefix('`') {
+            Some(quoted) => match quoted.split_once("`:") {
+                Some(split) => split,
+                None => return,
+            },
+            None => match field_marker(&format!(":{target}")) {
+                Some((name, _)) => {
+                    let length = name.len();
+                    (&target[..length], &target[length + 1..])
+                }
+                None => return,
+            },
+        };
+        let uri: String = std::iter::once(rest.trim())
+            .chain(following.iter().map(|line| line.trim()))
+            .collect();
+        if name == "_" {
+            self.anonymous.push_back(uri);
+            return;
+        }
+        let target = if uri.is_empty() {
+            Target::Anchor(match next_section(lines, end) {
+                Some(title) => header_anchor(&plain_title(&title)),
+                None => header_anchor(name),
+            })
+        } else if uri.ends_with('_') && !uri.ends_with("\\_") {
+            Target::Alias(uri[..uri.len() - 1].trim_matches('`').to_string())
+        } else {
+            Target::Uri(unescape_str(&uri))
+        };
+        self.targets.insert(normalize(name), target);
+    }
+
+    /// Link of a reference name
+    fn resolve(&self, name: &str) -> String {
+        let mut key = normalize(name);
+        for _ in 0..8 {
+            match self.targets.get(&key) {
+                Some(Target::Uri(uri)) => return uri.clone(),
+                Some(Target::Anchor(anchor)) => return format!("#{anchor}"),
+                Some(Target::Alias(alias)) => key = normalize(alias),
+                None => break,
+            }
+        }
+        warn!("Unknown reference: {name}");
+        format!("#{}", header_anchor(name))
+    }
+
+    /// Bibliographic fields at the start of the document, returning the line after them
+    fn docinfo(&mut self, lines: &[String], mut index: usize) -> anyhow::Result<usize> {
+        while let Some((name, rest)) = lines.get(index).and_then(|line| field_marker(line)) {
+            let end = indented_end(lines, index + 1);
+            let mut body = vec![rest.to_string()];
+            body.extend(dedent(&lines[index + 1..end]));
+            let value = plain_text(&self.inline(&body.join("\n"))?);
+            let metadata = &mut self.metadata;
+            match name.to_lowercase().as_str() {
+                "author" => metadata.author = Some(value),
+                "authors" => {
+                    metadata.author = Some(
+                        value
+                            .split(';')
+                            .map(str::trim)
+                            .collect::<Vec<_>>()
+                            .join(", "),
+                    )
+                }
+                "date" => metadata.date = Some(value),
+                "description" | "abstract" => metadata.description = Some(value),
+                "keywords" => {
+                    metadata.keywords = value
+                        .split([',', ';'])
+                        .map(|keyword| keyword.trim().to_string())
+                        .filter(|keyword| !keyword.is_empty())
+                        .collect()
+                }
+                name => {
+                    metadata.custom.insert(name.to_string(), value);
+                }
+            }
+            index = next_line(lines, end);
+        }
+        Ok(index)
+    }
+
+    fn blocks(&mut self, lines: &[String]) -> anyhow::Result<Vec<Element>> {
+        let mut blocks = vec![];
+        let mut index = 0;
+        while index < lines.len() {
+            if lines[index].trim().is_empty() {
+                index += 1;
+                continue;
+            }
+            let before = blocks.len();
+            index = self.block(lines, index, &mut blocks)?;
+            if blocks.len() > before && !self.bookmarks.is_empty() {
+                let bookmarks: Vec<Element> = std::mem::take(&mut self.bookmarks)
+                    .into_iter()
+                    .map(|id| Element::Bookmark {
+                        id,
+                        title: String::new(),
+                    })
+                    .collect();
+                match &mut blocks[before] {
+                    Element::Paragraph { elements, .. } => {
+                        elements.splice(0..0, bookmarks);
+                    }
+                    _ => blocks.insert(before, paragraph(bookmarks)),
+                }
+            }
+        }
+        Ok(blocks)
+    }
+
+    /// Reads the block at `index`, returning the line after it
+    fn block(
+        &mut self,
+        lines: &[String],
+        index: usize,
+        blocks: &mut Vec<Element>,
+    ) -> anyhow::Result<usize> {
+        let line = lines[index].as_str();
+        if indentation(line) > 0 {
+            // block quote
+            let end = indented_end(lines, index);
+            blocks.extend(self.blocks(&dedent(&lines[index..end]))?);
+            return Ok(end);
+        }
+        if line == ".." || line.starts_with(".. ") {
+            let end = indented_end(lines, index + 1);
+            self.explicit(
+                line[2..].trim_start(),
+                &lines[index + 1..end],
+                lines,
+                end,
+                blocks,
+            )?;
+            return Ok(end.max(index + 1));
+        }
+        if line.starts_with("__ ") {
+            return Ok(indented_end(lines, index + 1).max(index + 1));
+        }
+        if let Some((title, adornment, end)) = section_title(lines, index) {
+            self.section(&title, adornment, blocks)?;
+            return Ok(end);
+        }
+        if is_grid_border(line) {
+            return self.grid_table(lines, index, blocks);
+        }
+        if let Some(end) = simple_table_end(lines, index) {
+            blocks.push(self.simple_table(&lines[index..end])?);
+            return Ok(end);
+        }
+        if adornment(line).is_some_and(|_| line.chars().count() >= 4) {
+            // transition
+            return Ok(index + 1);
+        }
+        if let Some((bullet, _)) = bullet_marker(line) {
+            return self.bullet_list(lines, index, bullet, blocks);
+        }
+        if let Some(enumerator) = enumerator(line).filter(|_| {
+            lines.get(index + 1).is_none_or(|next| {
+                next.trim().is_empty() || indentation(next) > 0 || enumerator(next).is_some()
+            })
+        }) {
+            return self.enumerated_list(lines, index, enumerator, blocks);
+        }
+        if field_marker(line).is_some() {
+            return self.field_list(lines, index, blocks);
+        }
+        if line == "|" || line.starts_with("| ") {
+            return self.line_block(lines, index, blocks);
+        }
+        if line.starts_with(">>>") {
+            let end = index
+                + lines[index..]
+                    .iter()
+                    .position(|line| line.trim().is_empty())
+                    .unwrap_or(lines.len() - index);
+            blocks.push(Element::CodeBlock {
+                info: "pycon".to_string(),
+                code: lines[index..end].join("\n"),
+            });
+            return Ok(end);
+        }
+        if lines
+            .get(index + 1)
+            .is_some_and(|next| !next.trim().is_empty() && indentation(next) > 0)
+        {
+            return self.definition_list(lines, index, blocks);
+        }
+        self.paragraph(lines, index, blocks)
+    }
+
+    fn section(
+        &mut self,
+        title: &str,
+        adornment: (char, bool),
+        blocks: &mut Vec<Element>,
+    ) -> anyhow::Result<()> {
+        let text = plain_text(&self.inline(title)?);
+        let level = match self.adornments.iter().position(|known| *known == adornment) {
+            Some(position) => position + 1,
+            None => {
+                self.adornments.push(adornment);
+                self.adornments.len()
+            }
+        };
+        self.titles.insert(header_anchor(&text), text.clone());
+        blocks.push(Element::Header {
+            level: level.min(6) as u8,
+            text,
+        });
+        Ok(())
+    }
+
+    /// Paragraph, and the literal block after it when it ends with `::`
+    fn paragraph(
+        &mut self,
+        lines: &[String],
+        index: usize,
+        blocks: &mut Vec<Element>,
+    ) -> anyhow::Result<usize> {
+        let end = index
+            + lines[index..]
+                .iter()
+                .position(|line| line.trim().is_empty())
+                .unwrap_or(lines.len() - index);
+        let source = lines[index..end]
+            .iter()
+            .map(|line| line.trim())
+            .collect::<Vec<_>>()
+            .join("\n");
+        let literal = source.ends_with("::") && !source.ends_with("\\::");
+        let source = match source.strip_suffix("::") {
+            Some(text) if literal && (text.is_empty() || text.ends_with(char::is_whitespace)) => {
+                text.trim_end().to_string()
+            }
+            Some(_) if literal => source[..source.len() - 1].to_string(),
+            _ => source,
+        };
+        if !source.is_empty() {
+            blocks.push(paragraph(self.inline(&source)?));
+        }
+        if !literal {
+            return Ok(end);
+        }
+        let start = next_line(lines, end);
+        let Some(first) = lines.get(start) else {
+            return Ok(end);
+        };
+        if indentation(first) > 0 {
+            let block_end = indented_end(lines, start);
+            blocks.push(Element::CodeBlock {
+                info: self.language.clone(),
+                code: dedent(&lines[start..block_end]).join("\n"),
+            });
+            return Ok(block_end);
+        }
+        // quoted literal block, each line starting with the same punctuation
+        match first.chars().next() {
+            Some(quote) if ADORNMENT_CHARACTERS.contains(quote) => {
+                let block_end = start
+                    + lines[start..]
+                        .iter()
+                        .position(|line| !line.starts_with(quote))
+                        .unwrap_or(lines.len() - start);
+                blocks.push(Element::CodeBlock {
+                    info: self.language.clone(),
+                    code: lines[start..block_end].join("\n"),
+                });
+                Ok(block_end)
+            }
+            _ => Ok(end),
+        }
+    }
+
+    /// Explicit markup block: a directive, a hyperlink target, a footnote, a substitution
+    /// definition or a comment
+    fn explicit(
+        &mut self,
+        first: &str,
+        following: &[String],
+        lines: &[String],
+        end: usize,
+        blocks: &mut Vec<Element>,
+    ) -> anyhow::Result<()> {
+        if let Some(target) = first.strip_prefix('_') {
+            // a target of no URI before a block other than a section is a bookmark of it
+            if let Some((name, rest)) = field_marker(&format!(":{target}")) {
+                let name = name.trim_matches('`');
+                if rest.is_empty()
+                    && following.is_empty()
+                    && name != "_"
+                    && next_section(lines, end).is_none()
+                {
+                    self.bookmarks.push(header_anchor(name));
+                }
+            }
+            return Ok(());
+        }
+        if first.starts_with('[') || first.starts_with('|') {
+            return Ok(());
+        }
+        if let Some(directive) = Directive::read(first, following) {
+            self.directive(directive, blocks)?;
+        }
+        Ok(())
+    }
+
+    fn directive(&mut self, directive: Directive, blocks: &mut Vec<Element>) -> anyhow::Result<()> {
+        let name = directive.name.as_str();
+        let name = name.strip_prefix("rst:").unwrap_or(name);
+        match name {
+            "image" => blocks.extend(self.image(&directive, String::new())?),
+            "figure" => {
+                let caption_end = directive
+                    .content
+                    .iter()
+                    .position(|line| line.trim().is_empty())
+                    .unwrap_or(directive.content.len());
+                let caption =
+                    plain_text(&self.inline(&directive.content[..caption_end].join("\n"))?);
+                blocks.extend(self.image(&directive, caption)?);
+                blocks.extend(self.blocks(&directive.content[caption_end..])?);
+            }
+            "code-block" | "code" | "sourcecode" => {
+                if let Some(caption) = directive.options.get("caption") {
+                    let caption = self.inline(caption)?;
+                    blocks.push(paragraph(vec![Element::Styled {
+                        style: TextStyle::Strong,
+                        elements: caption,
+                    }]));
+                }
+                blocks.push(Element::CodeBlock {
+                    info: directive
+                        .arguments
+                        .split_whitespace()
+                        .next()
+                        .map_or_else(|| self.language.clone(), str::to_string),
+                    code: directive.content.join("\n"),
+                });
+            }
+            "highlight" => self.language = directive.arguments.trim().to_string(),
+            "math" => blocks.push(Element::Math {
+                tex: directive.body().join("\n").trim().to_string(),
+                display: true,
+            }),
+            "raw" => {
+                if directive
+                    .arguments
+                    .split_whitespace()
+                    .any(|format| format == "html")
+                {
+                    blocks.push(Element::Raw {
+                        format: DocumentType::HTML,
+                        content: directive.content.join("\n"),
+                    });
+                } else {
+                    warn!("Raw content left out: {}", directive.arguments);
+                }
+            }
+            "contents" => blocks.push(Element::TableOfContents {
+                max_level: directive
+                    .options
+                    .get("depth")
+                    .and_then(|depth| depth.trim().parse().ok())
+                    .unwrap_or(6),
+            }),
+            "admonition" => {
+                let kind = directive
+                    .options
+                    .get("class")
+                    .and_then(|class| class.split_whitespace().next())
+                    .unwrap_or("note")
+                    .to_string();
+                let title = plain_text(&self.inline(&directive.arguments)?);
+                let content = self.blocks(&directive.content)?;
+                blocks.push(Element::admonition(&kind, Some(&title), content));
+            }
+            kind if ADMONITIONS.contains(&kind) => {
+                let content = self.blocks(&directive.body())?;
+                blocks.push(Element::admonition(kind, None, content));
+            }
+            "seealso" => {
+                let content = self.blocks(&directive.body())?;
+                blocks.push(Element::admonition("seealso", Some("See also"), content));
+            }
+            "versionadded" | "versionchanged" | "deprecated" => {
+                let (version, rest) = directive
+                    .arguments
+                    .split_once(char::is_whitespace)
+                    .unwrap_or((&directive.arguments, ""));
+                let label = match name {
+                    "versionadded" => format!("New in version {version}"),
+                    "versionchanged" => format!("Changed in version {version}"),
+                    _ => format!("Deprecated since version {version}"),
+                };
+                let mut body: Vec<String> = rest.lines().map(str::to_string).collect();
+                if !body.is_empty() && !directive.content.is_empty() {
+                    body.push(String::new());
+                }
+                body.extend(directive.content.iter().cloned());
+                let mut content = self.blocks(&body)?;
+                let label = Element::Styled {
+                    style: TextStyle::Emphasis,
+                    elements: vec![text(label)],
+                };
+                match content.first_mut() {
+                    Some(Element::Paragraph { elements, .. }) => {
+                        elements.splice(0..0, [label, text(": ")]);
+                    }
+                    _ => content.insert(0, paragraph(vec![label])),
+                }
+                blocks.extend(content);
+            }
+            "topic" | "sidebar" => {
+                let mut elements = vec![];
+                if !directive.arguments.is_empty() {
+                    elements.push(paragraph(vec![Element::Styled {
+                        style: TextStyle::Strong,
+                        elements: self.inline(&directive.arguments)?,
+                    }]));
+                }
+                elements.extend(self.blocks(&directive.content)?);
+                blocks.push(Element::Container {
+                    kind: if name == "sidebar" {
+                        ContainerKind::Aside
+                    } else {
+                        ContainerKind::Div
+                    },
+                    elements,
+                });
+            }
+            "rubric" => blocks.push(paragraph(vec![Element::Styled {
+                style: TextStyle::Strong,
+                elements: self.inline(&directive.arguments)?,
+            }])),
+            "container" => {
+                let elements = self.blocks(&directive.content)?;
+                let kind = match directive.arguments.split_whitespace().next() {
+                    Some("aside") => ContainerKind::Aside,
+                    _ => ContainerKind::Div,
+                };
+                blocks.push(Element::Container { kind, elements });
+            }
+            "parsed-literal" => {
+                let mut code = vec![];
+                for line in &directive.content {
+                    code.push(plain_text(&self.inline(line)?));
+                }
+                blocks.push(Element::CodeBlock {
+                    info: String::new(),
+                    code: code.join("\n"),
+                });
+            }
+            "epigraph" | "highlights" | "pull-quote" | "compound" | "glossary" | "centered"
+            | "hlist" => blocks.extend(self.blocks(&directive.body())?),
+            "list-table" => blocks.extend(self.list_table(&directive)?),
+            "csv-table" => blocks.extend(self.csv_table(&directive)?),
+            "table" => {
+                if !directive.arguments.is_empty() {
+                    blocks.push(paragraph(vec![Element::Styled {
+                        style: TextStyle::Strong,
+                        elements: self.inline(&directive.arguments)?,
+                    }]));
+                }
+                blocks.extend(self.blocks(&directive.content)?);
+            }
+            "meta" => {
+                for (name, value) in &directive.options {
+                    match name.as_str() {
+                        "description" => self.metadata.description = Some(value.clone()),
+                        "keywords" => {
+                            self.metadata.keywords = value
+                                .split(',')
+                                .map(|keyword| keyword.trim().to_string())
+                                .filter(|keyword| !keyword.is_empty())
+                                .collect()
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            "title" => self.metadata.title = Some(directive.arguments.clone()),
+            name if IGNORED_DIRECTIVES.contains(&name) => {
+                warn!("Directive left out: {name}");
+            }
+            name => warn!("Unknown directive left out: {name}"),
+        }
+        Ok(())
+    }
+
+    /// Image of an `image` or `figure` directive, a link when it has a target
+    fn image(&mut self, directive: &Directive, title: String) -> anyhow::Result<Option<Element>> {
+        let uri: String = directive.arguments.split_whitespace().collect();
+        if uri.is_empty() {
+            return Ok(None);
+        }
+        let bytes = (self.image_loader)(&uri)?;
+        let options = &directive.options;
+        // the vertical alignments top, middle and bottom have no image alignment
+        let align = match options.get("align").map(String::as_str) {
+            Some(align @ ("left" | "center" | "right")) => align.to_string(),
+            _ => String::new(),
+        };
+        let image = Element::Image(ImageData::new(
+            bytes,
+            title,
+            options.get("alt").cloned().unwrap_or_default(),
+            uri,
+            align,
+            ImageDimension {
+                width: options.get("width").cloned(),
+                height: options.get("height").cloned(),
+            },
+        ));
+        Ok(Some(match options.get("target") {
+            Some(target) => Element::Hyperlink {
+                elements: vec![image],
+                url: match target.strip_suffix('_') {
+                    Some(name) => self.resolve(name.trim_matches('`')),
+                    None => target.clone(),
+                },
+                alt: String::new(),
+                size: 14,
+            },
+            None => image,
+        }))
+    }
+
+    /// Element of the lines of a table cell or a list item
+    fn cell(&mut self, lines: &[String]) -> anyhow::Result<Element> {
+        let mut blocks = self.blocks(lines)?;
+        Ok(match blocks.len() {
+            0 => text(""),
+            1 => text_block(blocks.remove(0)),
+            _ => Element::Container {
+                kind: ContainerKind::Div,
+                elements: blocks,
+            },
+        })
+    }
+
+    /// Items of the blocks of a list item, its blocks after the first being items too
+    fn list_items(&mut self, lines: &[String]) -> anyhow::Result<Vec<ListItem>> {
+        let blocks = self.blocks(lines)?;
+        if blocks.is_empty() {
+            return Ok(vec![ListItem { element: text("") }]);
+        }
+        Ok(blocks
+            .into_iter()
+            .map(|block| ListItem {
+                element: text_block(block),
+            })
+            .collect())
+    }
+
+    fn bullet_list(
+        &mut self,
+        lines: &[String],
+        mut index: usize,
+        bullet: char,
+        blocks: &mut Vec<Element>,
+    ) -> anyhow::Result<usize> {
+        let mut items = vec![];
+        let mut end = index;
+        while let Some((_, column)) = lines
+            .get(index)
+            .and_then(|line| bullet_marker(line))
+            .filter(|(marker, _)| *marker == bullet)
+        {
+            let (body, item_end) = item_body(lines, index, column);
+            items.extend(self.list_items(&body)?);
+            end = item_end;
+            index = next_line(lines, item_end);
+        }
+        blocks.push(Element::List {
+            elements: items,
+            numbered: false,
+            start: 1,
+            numbering: NumberingStyle::default(),
+        });
+        Ok(end)
+    }
+
+    fn enumerated_list(
+        &mut self,
+        lines: &[String],
+        mut index: usize,
+        first: Enumerator,
+        blocks: &mut Vec<Element>,
+    ) -> anyhow::Result<usize> {
+        let mut items = vec![];
+        let mut end = index;
+        while let Some(enumerator) =
+            lines
+                .get(index)
+                .and_then(|line| enumerator(line))
+                .filter(|enumerator| {
+                    enumerator.parenthesized == first.parenthesized
+                        && enumerator.suffix == first.suffix
+                })
+        {
+            let (body, item_end) = item_body(lines, index, enumerator.column);
+            items.extend(self.list_items(&body)?);
+            end = item_end;
+            index = next_line(lines, item_end);
+        }
+        blocks.push(Element::List {
+            elements: items,
+            numbered: true,
+            start: first.value,
+            numbering: first.numbering,
+        });
+        Ok(end)
+    }
+
+    /// Definition list, each term strong at the start of its definition
+    fn definition_list(
+        &mut self,
+        lines: &[String],
+        mut index: usize,
+        blocks: &mut Vec<Element>,
+    ) -> anyhow::Result<usize> {
+        let mut items = vec![];
+        let mut end = index;
+        while let Some(term) = lines.get(index).filter(|line| {
+            !line.trim().is_empty()
+                && indentation(line) == 0
+                && !line.starts_with("..")
+                && bullet_marker(line).is_none()
+                && enumerator(line).is_none()
+                && field_marker(line).is_none()
+                && lines
+                    .get(index + 1)
+                    .is_some_and(|next| !next.trim().is_empty() && indentation(next) > 0)
+        }) {
+            let term = term.split(" : ").next().unwrap_or_default();
+            let term = Element::Styled {
+                style: TextStyle::Strong,
+                elements: self.inline(term)?,
+            };
+            end = indented_end(lines, index + 1);
+            let mut definition = self.blocks(&dedent(&lines[index + 1..end]))?;
+            match definition.first_mut() {
+                Some(Element::Paragraph { elements, .. }) => {
+                    elements.splice(0..0, [term, text(" ")]);
+                }
+                _ => definition.insert(0, paragraph(vec![term])),
+            }
+            items.extend(definition.into_iter().map(|block| ListItem {
+                element: text_block(block),
+            }));
+            index = next_line(lines, end);
+        }
+        blocks.push(Element::List {
+            elements: items,
+            numbered: false,
+            start: 1,
+            numbering: NumberingStyle::default(),
+        });
+        Ok(end)
+    }
+
+    /// Field list, a table of the field names and bodies
+    fn field_list(
+        &mut self,
+        lines: &[String],
+        mut index: usize,
+        blocks: &mut Vec<Element>,
+    ) -> anyhow::Result<usize> {
+        let mut rows = vec![];
+        let mut end = index;
+        while let Some((name, rest)) = lines.get(index).and_then(|line| field_marker(line)) {
+            end = indented_end(lines, index + 1).max(index + 1);
+            let mut body = vec![rest.to_string()];
+            body.extend(dedent(&lines[index + 1..end]));
+            rows.push(TableRow {
+                cells: vec![
+                    TableCell {
+                        element: text(unescape_str(name)),
+                    },
+                    TableCell {
+                        element: self.cell(&body)?,
+                    },
+                ],
+            });
+            index = next_line(lines, end);
+        }
+        blocks.push(Element::Table {
+            headers: vec![],
+            rows,
+        });
+        Ok(end)
+    }
+
+    /// Line block, its lines separated by line breaks
+    fn line_block(
+        &mut self,
+        lines: &[String],
+        mut index: usize,
+        blocks: &mut Vec<Element>,
+    ) -> anyhow::Result<usize> {
+        let mut elements = vec![];
+        while let Some(line) = lines
+            .get(index)
+            .filter(|line| *line == "|" || line.starts_with("| "))
+        {
+            let mut source = line[1..].trim().to_string();
+            index += 1;
+            while let Some(continuation) = lines
+                .get(index)
+                .filter(|line| !line.trim().is_empty() && indentation(line) > 0)
+            {
+                source.push(' ');
+                source.push_str(continuation.trim());
+                index += 1;
+            }
+            if !elements.is_empty() || index > 1 && source.is_empty() {
+                elements.push(Element::LineBreak);
+            }
+            elements.extend(self.inline(&source)?);
+        }
+        blocks.push(paragraph(elements));
+        Ok(index)
+    }
+
+    /// Grid table, its header the rows above a `+===+` border and its cells spanning the
+    /// columns without a `|` between them
+    fn grid_table(
+        &mut self,
+        lines: &[String],
+        index: usize,
+        blocks: &mut Vec<Element>,
+    ) -> anyhow::Result<usize> {
+        let border: Vec<char> = lines[index].chars().collect();
+        let boundaries: Vec<usize> = border
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| **c == '+')
+            .map(|(position, _)| position)
+            .collect();
+        let mut end = index + 1;
+        while lines
+            .get(end)
+            .is_some_and(|line| line.starts_with('+') || line.starts_with('|'))
+        {
+            end += 1;
+        }
+        // cells of each row: first and last boundaries and lines
+        let mut rows: Vec<Vec<(usize, usize, Vec<String>)>> = vec![];
+        let mut header_rows = 0;
+        let mut current: Option<Vec<(usize, usize, Vec<String>)>> = None;
+        for line in &lines[index + 1..end] {
+            let chars: Vec<char> = line.chars().collect();
+            if chars.first() == Some(&'+') {
+                rows.extend(current.take());
+                if chars.contains(&'=') {
+                    header_rows = rows.len();
+                }
+                continue;
+            }
+            let row = current.get_or_insert_with(|| {
+                let mut cells = vec![];
+                let mut first = 0;
+                for (position, &boundary) in boundaries.iter().enumerate().skip(1) {
+                    if chars.get(boundary) == Some(&'|') || position == boundaries.len() - 1 {
+                        cells.push((first, position, vec![]));
+                        first = position;
+                    }
+                }
+                cells
+            });
+            for (first, last, cell) in row.iter_mut() {
+                let from = (boundaries[*first] + 1).min(chars.len());
+                let to = boundaries[*last].min(chars.len());
+                cell.push(
+                    chars[from..to]
+                        .iter()
+                        .collect::<String>()
+                        .trim_end()
+                        .to_string(),
+                );
+            }
+        }
+        rows.extend(current.take());
+
+        let mut headers = vec![];
+        let mut table_rows = vec![];
+        for (row_index, row) in rows.into_iter().enumerate() {
+            let mut cells = vec![];
+            for (first, last, lines) in row {
+                cells.push((
+                    self.cell(&dedent(&lines))?,
+                    boundaries[last] - boundaries[first] - 1,
+                ));
+                // the columns spanned are empty
+                cells.extend((first + 1..last).map(|_| (text(""), 0)));
+            }
+            if row_index == 0 && header_rows > 0 {
+                headers = cells
+                    .into_iter()
+                    .map(|(element, width)| TableHeader {
+                        element,
+                        width: width as f32,
+                    })
+                    .collect();
+            } else {
+                table_rows.push(TableRow {
+                    cells: cells
+                        .into_iter()
+                        .map(|(element, _)| TableCell { element })
+                        .collect(),
+                });
+            }
+        }
+        blocks.push(Element::Table {
+            headers,
+            rows: table_rows,
+        });
+        Ok(end)
+    }
+
+    /// Simple table, its header the rows above a border in the middle and its rows
+    /// continued on the lines with an empty first column
+    fn simple_table(&mut self, lines: &[String]) -> anyhow::Result<Element> {
+        let border: Vec<char> = lines[0].chars().collect();
+        let mut starts = vec![];
+        for (position, c) in border.iter().enumerate() {
+            if *c == '=' && (position == 0 || border[position - 1] == ' ') {
+                starts.push(position);
+            }
+        }
+        let mut rows: Vec<Vec<String>> = vec![];
+        let mut header_rows = 0;
+        for line in &lines[1..lines.len() - 1] {
+            if is_simple_border(line) {
+                header_rows = rows.len();
+                continue;
+            }
+            if line.chars().all(|c| c == '-' || c == ' ') {
+                continue;
+            }
+            let chars: Vec<char> = line.chars().collect();
+            let cells: Vec<String> = starts
+                .iter()
+                .enumerate()
+                .map(|(column, &start)| {
+                    let from = start.min(chars.len());
+                    let to = starts
+                        .get(column + 1)
+                        .map_or(chars.len(), |&next| next.min(chars.len()));
+                    chars[from..to]
+                        .iter()
+                        .collect::<String>()
+                        .trim()
+                        .to_string()
+                })
+                .collect();
+            match rows.last_mut() {
+                Some(row) if cells.first().is_some_and(String::is_empty) => {
+                    for (cell, more) in row.iter_mut().zip(cells) {
+                        if !more.is_empty() {
+                            cell.push('\n');
+                            cell.push_str(&more);
+                        }
+                    }
+                }
+                _ => rows.push(cells),
+            }
+        }
+        let mut headers = vec![];
+        let mut table_rows = vec![];
+        for (row_index, row) in rows.into_iter().enumerate() {
+            let mut cells = vec![];
+            for cell in row {
+                let lines: Vec<String> = cell.lines().map(str::to_string).collect();
+                cells.push(self.cell(&lines)?);
+            }
+            if row_index == 0 && header_rows > 0 {
+                headers = cells
+                    .into_iter()
+                    .enumerate()
+                    .map(|(column, element)| TableHeader {
+                        element,
+                        width: starts.get(column + 1).map_or(border.len(), |next| *next) as f32
+                            - starts[column] as f32,
+                    })
+                    .collect();
+            } else {
+                table_rows.push(TableRow {
+                    cells: cells
+                        .into_iter()
+                        .map(|element| TableCell { element })
+                        .collect(),
+                });
+            }
+        }
+        Ok(Element::Table {
+            headers,
+            rows: table_rows,
+        })
+    }
+
+    /// Table of rows of cells, its title strong before it and its first row the header
+    /// when `header_rows` is set
+    fn table(
+        &mut self,
+        directive: &Directive,
+        rows: Vec<Vec<Element>>,
+    ) -> anyhow::Result<Vec<Element>> {
+        let mut blocks = vec![];
+        if !directive.arguments.is_empty() {
+            blocks.push(paragraph(vec![Element::Styled {
+                style: TextStyle::Strong,
+                elements: self.inline(&directive.arguments)?,
+            }]));
+        }
+        let header_rows = directive
+            .options
+            .get("header-rows")
+            .and_then(|rows| rows.trim().parse::<usize>().ok())
+            .unwrap_or_default();
+        let widths: Vec<f32> = directive
+            .options
+            .get("widths")
+            .map(|widths| {
+                widths
+                    .split([',', ' '])
+                    .filter_map(|width| width.trim().parse().ok())
+                    .collect()
+            })
+            .unwrap_or_default();
+        let mut rows = rows.into_iter();
+        let headers = if header_rows > 0 {
+            rows.next()
+                .unwrap_or_default()
+                .into_iter()
+                .enumerate()
+                .map(|(column, element)| TableHeader {
+                    element,
+                    width: widths.get(column).copied().unwrap_or(30.),
+                })
+                .collect()
+        } else {
+            vec![]
+        };
+        blocks.push(Element::Table {
+            headers,
+            rows: rows
+                .map(|cells| TableRow {
+                    cells: cells
+                        .into_iter()
+                        .map(|element| TableCell { element })
+                        .collect(),
+                })
+                .collect(),
+        });
+        Ok(blocks)
+    }
+
+    /// Table of a `list-table` directive: a bullet list of rows of bullet lists of cells
+    fn list_table(&mut self, directive: &Directive) -> anyhow::Result<Vec<Element>> {
+        let mut rows = vec![];
+        for row in bullet_bodies(&directive.content) {
+            let mut cells = vec![];
+            for cell in bullet_bodies(&row) {
+                cells.push(self.cell(&cell)?);
+            }
+            rows.push(cells);
+        }
+        self.table(directive, rows)
+    }
+
+    /// Table of a `csv-table` directive, its header given by the `header` option or the
+    /// first `header-rows` records
+    fn csv_table(&mut self, directive: &Directive) -> anyhow::Result<Vec<Element>> {
+        let delimiter = match directive.options.get("delim").map(String::as_str) {
+            Some("tab") => '\t',
+            Some("space") => ' ',
+            Some(delimiter) => delimiter.chars().next().unwrap_or(','),
+            None => ',',
+        };
+        let mut records = vec![];
+        if let Some(header) = directive.options.get("header") {
+            records.push(csv_fields(header, delimiter));
+        }
+        records.extend(
+            directive
+                .content
+                .iter()
+                .filter(|line| !line.trim().is_empty())
+                .map(|line| csv_fields(line, delimiter)),
+        );
+        let mut rows = vec![];
+        for record in records {
+            let mut cells = vec![];
+            for field in record {
+                cells.push(text_block(paragraph(self.inline(&field)?)));
+            }
+            rows.push(cells);
+        }
+        let mut directive = directive.clone();
+        if directive.options.contains_key("header") {
+            directive
+                .options
+                .insert("header-rows".to_string(), "1".to_string());
+        }
+        self.table(&directive, rows)
+    }
+
+    fn inline(&mut self, source: &str) -> anyhow::Result<Vec<Element>> {
+        let chars: Vec<char> = source.chars().collect();
+        let mut out = Inline::default();
+        let mut index = 0;
+        while index < chars.len() {
+            let c = chars[index];
+            let previous = index.checked_sub(1).map(|previous| chars[previous]);
+            let next = chars.get(index + 1).copied();
+            match c {
+                '\\' => {
+                    match next {
+                        Some(next) if !next.is_whitespace() => out.push_char(next),
+                        _ => {}
+                    }
+                    index += 2;
+                    continue;
+                }
+                '*' => {
+                    let marks = if next == Some('*') { 2 } else { 1 };
+                    if starts_markup(&chars, index, marks) {
+                        if let Some(end) = find_end(&chars, index + marks, &"*".repeat(marks), "") {
+                            out.push(Element::Styled {
+                                style: if marks == 2 {
+                                    TextStyle::Strong
+                                } else {
+                                    TextStyle::Emphasis
+                                },
+                                elements: vec![text(unescape(&chars[index + marks..end]))],
+                            });
+                            index = end + marks;
+                            continue;
+                        }
+                    }
+                }
+                '`' if next == Some('`') => {
+                    let end = find_end(&chars, index + 2, "``", "")
+                        .filter(|_| starts_markup(&chars, index, 2));
+                    if let Some(end) = end {
+                        let code: String = chars[index + 2..end].iter().collect();
+                        out.push(Element::InlineCode {
+                            text: code.replace('\n', " "),
+                        });
+                        index = end + 2;
+                        continue;
+                    }
+                }
+                '`' if starts_markup(&chars, index, 1) => {
+                    if let Some(end) = find_end(&chars, index + 1, "`", "_") {
+                        let content: String = chars[index + 1..end].iter().collect();
+                        let mut after = end + 1;
+                        if chars.get(after) == Some(&'_') {
+                            let anonymous = chars.get(after + 1) == Some(&'_');
+                            after += if anonymous { 2 } else { 1 };
+                            out.push(self.reference(&content, anonymous));
+                        } else if let Some((role, role_end)) = role_at(&chars, after)
+                            .filter(|(_, role_end)| ends_boundary(chars.get(*role_end).copied()))
+                        {
+                            out.extend(self.role(&role, &content)?);
+                            after = role_end;
+                        } else {
+                            out.extend(self.role("title-reference", &content)?);
+                        }
+                        index = after;
+                        continue;
+                    }
+                }
+                ':' if starts_boundary(previous) => {
+                    if let Some((role, role_end)) =
+                        role_at(&chars, index).filter(|(_, role_end)| {
+                            chars.get(*role_end) == Some(&'`')
+                                && chars.get(role_end + 1) != Some(&'`')
+                        })
+                    {
+                        if let Some(end) = find_end(&chars, role_end + 1, "`", "") {
+                            let content: String = chars[role_end + 1..end].iter().collect();
+                            out.extend(self.role(&role, &content)?);
+                            index = end + 1;
+                            continue;
+                        }
+                    }
+                }
+                '|' if starts_markup(&chars, index, 1) => {
+                    if let Some(end) = find_end(&chars, index + 1, "|", "_") {
+                        let name: String = chars[index + 1..end].iter().collect();
+                        let mut after = end + 1;
+                        let mut elements = self.substitution(&name)?;
+                        if chars.get(after) == Some(&'_') {
+                            let anonymous = chars.get(after + 1) == Some(&'_');
+                            after += if anonymous { 2 } else { 1 };
+                            let url = if anonymous {
+                                self.anonymous.pop_front().unwrap_or_default()
+                            } else {
+                                self.resolve(&name)
+                            };
+                            elements = vec![Element::Hyperlink {
+                                elements,
+                                url,
+                                alt: String::new(),
+                                size: 14,
+                            }];
+                        }
+                        out.extend(elements);
+                        index = after;
+                        continue;
+                    }
+                }
+                '[' if starts_boundary(previous) => {
+                    if let Some(close) = chars[index..].iter().position(|&c| c == ']') {
+                        let close = index + close;
+                        let label: String = chars[index + 1..close].iter().collect();
+                        if chars.get(close + 1) == Some(&'_')
+                            && ends_boundary(chars.get(close + 2).copied())
+                            && is_footnote_label(&label)
+                        {
+                            out.push(self.footnote(&label)?);
+                            index = close + 2;
+                            continue;
+                        }
+                    }
+                }
+                '_' if next == Some('`') && starts_boundary(previous) => {
+                    if let Some(end) = find_end(&chars, index + 2, "`", "") {
+                        let name = unescape(&chars[index + 2..end]);
+                        out.push(Element::Bookmark {
+                            id: header_anchor(&name),
+                            title: String::new(),
+                        });
+                        out.push_str(&name);
+                        index = end + 1;
+                        continue;
+                    }
+                }
+                '\n' => {
+                    out.push_char(' ');
+                    index += 1;
+                    continue;
+                }
+                c if c.is_alphanumeric() && !previous.is_some_and(char::is_alphanumeric) => {
+                    let rest: String = chars[index..chars.len().min(index + 8)].iter().collect();
+                    if starts_boundary(previous)
+                        && URI_SCHEMES.iter().any(|scheme| rest.starts_with(scheme))
+                    {
+                        let mut end = chars[index..]
+                            .iter()
+                            .position(|c| c.is_whitespace() || "<>\"".contains(*c))
+                            .map_or(chars.len(), |end| index + end);
+                        while end > index && ".,;:!?)'".contains(chars[end - 1]) {
+                            end -= 1;
+                        }
+                        let uri: String = chars[index..end].iter().collect();
+                        out.push(Element::Hyperlink {
+                            elements: vec![text(uri.strip_prefix("mailto:").unwrap_or(&uri))],
+                            url: uri,
+                            alt: String::new(),
+                            size: 14,
+                        });
+                        index = end;
+                        continue;
+                    }
+                    // a word, a reference when it ends with `_`
+                    let mut end = index;
+                    while end < chars.len()
+                        && (chars[end].is_alphanumeric()
+                            || "-_.+:".contains(chars[end])
+                                && chars.get(end + 1).is_some_and(|c| c.is_alphanumeric()))
+                    {
+                        end += 1;
+                    }
+                    if chars.get(end) == Some(&'_') && starts_boundary(previous) {
+                        let anonymous = chars.get(end + 1) == Some(&'_');
+                        let after = end + if anonymous { 2 } else { 1 };
+                        if ends_boundary(chars.get(after).copied()) {
+                            let name: String = chars[index..end].iter().collect();
+                            out.push(self.reference(&name, anonymous));
+                            index = after;
+                            continue;
+                        }
+                    }
+                    out.push_str(&chars[index..end].iter().collect::<String>());
+                    index = end;
+                    continue;
+                }
+                _ => {}
+            }
+            out.push_char(c);
+            index += 1;
+        }
+        Ok(out.finish())
+    }
+
+    /// Link of a hyperlink reference: `name_`, `` `text <uri>`_ `` or an anonymous one
+    fn reference(&mut self, content: &str, anonymous: bool) -> Element {
+        let (label, url) = match embedded(content) {
+            Some((label, target)) if target.ends_with('_') && !target.ends_with("\\_") => {
+                let name = target[..target.len() - 1].trim_matches('`');
+                let label = if label.is_empty() { name } else { label };
+                (unescape_str(label), self.resolve(name))
+            }
+            Some((label, target)) => {
+                let uri: String = unescape_str(target).split_whitespace().collect();
+                if !anonymous && !label.is_empty() {
+                    self.targets
+                        .entry(normalize(label))
+                        .or_insert_with(|| Target::Uri(uri.clone()));
+                }
+                let label = if label.is_empty() {
+                    uri.strip_prefix("mailto:").unwrap_or(&uri).to_string()
+                } else {
+                    unescape_str(label)
+                };
+                (label, uri)
+            }
+            None if anonymous => (
+                unescape_str(content),
+                self.anonymous.pop_front().unwrap_or_else(|| {
+                    warn!("Anonymous reference without target: {content}");
+                    String::new()
+                }),
+            ),
+            None => (unescape_str(content), self.resolve(&unescape_str(content))),
+        };
+        Element::Hyperlink {
+            elements: vec![text(label)],
+            url,
+            alt: String::new(),
+            size: 14,
+        }
+    }
+
+    /// Elements of interpreted text with a role: `:role:`text``
+    fn role(&mut self, role: &str, content: &str) -> anyhow::Result<Vec<Element>> {
+        let (domain, name) = role.rsplit_once(':').unwrap_or(("", role));
+        let text_of = |content: &str| unescape_str(content);
+        Ok(match name {
+            "math" => vec![Element::Math {
+                tex: content.trim().to_string(),
+                display: false,
+            }],
+            name if LITERAL_ROLES.contains(&name) => vec![Element::InlineCode {
+                text: text_of(content),
+            }],
+            "strong" => vec![Element::Styled {
+                style: TextStyle::Strong,
+                elements: vec![text(text_of(content))],
+            }],
+            "emphasis" | "title-reference" | "title" | "t" | "dfn" => vec![Element::Styled {
+                style: TextStyle::Emphasis,
+                elements: vec![text(text_of(content))],
+            }],
+            "abbr" => {
+                let content = text_of(content);
+                let abbreviation = content.split(" (").next().unwrap_or_default();
+                vec![text(abbreviation)]
+            }
+            "guilabel" => vec![text(text_of(content).replace('&', ""))],
+            "menuselection" => vec![text(text_of(content).replace("-->", "›"))],
+            "ref" | "numref" | "any" | "doc" | "download" | "term" => {
+                let (label, target) = embedded(content).unwrap_or(("", content.trim()));
+                let target = target.trim_start_matches(['~', '!']);
+                let url = match name {
+                    "doc" | "download" => target.to_string(),
+                    _ => self.resolve(target),
+                };
+                let label = if label.is_empty() {
+                    url.strip_prefix('#')
+                        .and_then(|anchor| self.titles.get(anchor))
+                        .cloned()
+                        .unwrap_or_else(|| text_of(target))
+                } else {
+                    text_of(label)
+                };
+                vec![Element::Hyperlink {
+                    elements: vec![text(label)],
+                    url,
+                    alt: String::new(),
+                    size: 14,
+                }]
+            }
+            "pep" | "rfc" => {
+                let number: String = content.chars().take_while(char::is_ascii_digit).collect();
+                let Ok(value) = number.parse::<usize>() else {
+                    return Ok(vec![text(text_of(content))]);
+                };
+                let (label, url) = if name == "pep" {
+                    (
+                        format!("PEP {value}"),
+                        format!("https://peps.python.org/pep-{value:04}/"),
+                    )
+                } else {
+                    (
+                        format!("RFC {value}"),
+                        format!("https://datatracker.ietf.org/doc/html/rfc{value}"),
+                    )
+                };
+                vec![Element::Hyperlink {
+                    elements: vec![text(label)],
+                    url,
+                    alt: String::new(),
+                    size: 14,
+                }]
+            }
+            name if OBJECT_ROLES.contains(&name) || !domain.is_empty() => {
+                let (label, target) = embedded(content).unwrap_or(("", content.trim()));
+                let code = if !label.is_empty() {
+                    label
+                } else if let Some(target) = target.strip_prefix('~') {
+                    target.rsplit('.').next().unwrap_or(target)
+                } else {
+                    target.trim_start_matches('!')
+                };
+                vec![Element::InlineCode {
+                    text: text_of(code),
+                }]
+            }
+            _ => vec![text(text_of(content))],
+        })
+    }
+
+    /// Elements of a substitution reference: `|name|`
+    fn substitution(&mut self, name: &str) -> anyhow::Result<Vec<Element>> {
+        let Some(definition) = self.substitutions.get(&normalize(name)).cloned() else {
+            warn!("Unknown substitution: {name}");
+            return Ok(vec![text(format!("|{name}|"))]);
+        };
+        if self.depth > 8 {
+            return Ok(vec![text(name)]);
+        }
+        self.depth += 1;
+        let elements = match definition.name.as_str() {
+            "replace" => self.inline(&definition.body().join("\n")),
+            "image" => self.image(&definition, String::new()).map(Vec::from_iter),
+            "unicode" => Ok(vec![text(unicode_text(&definition.arguments))]),
+            "date" => Ok(vec![Element::Field {
+                kind: FieldKind::Date,
+            }]),
+            _ => Ok(vec![text(name)]),
+        };
+        self.depth -= 1;
+        elements
+    }
+
+    /// Footnote of a footnote or citation reference: `[1]_`, `[#]_`, `[#name]_`, `[*]_`,
+    /// `[name]_`
+    fn footnote(&mut self, label: &str) -> anyhow::Result<Element> {
+        let label = normalize(label);
+        let lines = match self.footnotes.get_mut(&label) {
+            Some(notes) if label == "#" || label == "*" => notes.pop_front(),
+            Some(notes) => notes.front().cloned(),
+            None => None,
+        };
+        let Some(lines) = lines.filter(|_| self.depth <= 8) else {
+            warn!("Footnote without content: {label}");
+            return Ok(text(format!("[{label}]")));
+        };
+        self.depth += 1;
+        let content = self.blocks(&lines);
+        self.depth -= 1;
+        let elements = match <[Element; 1]>::try_from(content?) {
+            Ok([Element::Paragraph { elements, .. }]) => elements,
+            Ok([element]) => vec![element],
+            Err(blocks) => blocks,
+        };
+        Ok(Element::Footnote { elements })
+    }
+}
+
+/// Body lines of the items of a bullet list, the lines around them left out
+fn bullet_bodies(lines: &[String]) -> Vec<Vec<String>> {
+    let mut bodies = vec![];
+    let mut index = 0;
+    while index < lines.len() {
+        match bullet_marker(&lines[index]) {
+            Some((_, column)) => {
+                let (body, end) = item_body(lines, index, column);
+                bodies.push(body);
+                index = end.max(index + 1);
+            }
+            None => index += 1,
+        }
+    }
+    bodies
+}
+
+/// Lines of the body of a list item, its text starting at `column` of its first line, and
+/// the end of its lines
+fn item_body(lines: &[String], index: usize, column: usize) -> (Vec<String>, usize) {
+    let end = indented_end(lines, index + 1).max(index + 1);
+    let following = &lines[index + 1..end];
+    let indent = following
+        .iter()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| indentation(line))
+        .min()
+        .map_or(column, |indent| indent.min(column));
+    let mut body = vec![lines[index].get(column..).unwrap_or_default().to_string()];
+    body.extend(dedent_by(following, indent));
+    (body, end)
+}
+
+/// Text of the character codes of a `unicode` substitution: `U+00A9`, `0xA9`, `169` or text
+fn unicode_text(arguments: &str) -> String {
+    let mut result = String::new();
+    for token in arguments.split_whitespace() {
+        if token == ".." {
+            break;
+        }
+        let hex = ["U+", "u+", "0x", "0X", "\\x", "\\u", "x", "u"]
+            .iter()
+            .find_map(|prefix| token.strip_prefix(prefix))
+            .and_then(|digits| u32::from_str_radix(digits, 16).ok());
+        let code = hex.or_else(|| token.parse::<u32>().ok().filter(|_| token.len() > 1));
+        match code.and_then(char::from_u32) {
+            Some(c) => result.push(c),
+            None => result.push_str(token),
+        }
+    }
+    result
+}
+
+impl TransformerTrait for Transformer {
+    /// Document of a reStructuredText source, its images read from the current directory
+    fn parse(document: &Bytes) -> crate::Result<Document> {
+        Transformer::parse_with_loader(document, disk_image_loader("."))
+    }
+
+    /// reStructuredText source of the document, its images saved to the current directory
+    fn generate(document: &Document) -> crate::Result<Bytes> {
+        Transformer::generate_with_saver(document, disk_image_saver("."))
+    }
+}
+
+impl TransformerWithImageLoaderSaverTrait for Transformer {
+    /// Document of a reStructuredText source, its images read with `image_loader` from the
+    /// URIs of the image and figure directives
+    fn parse_with_loader<F>(document: &Bytes, image_loader: F) -> crate::Result<Document>
+    where
+        F: Fn(&str) -> anyhow::Result<Bytes>,
+    {
+        Transformer::parse_source(document, &image_loader_errors(image_loader))
+            .map_err(crate::Error::parse_error)
+    }
+
+    /// reStructuredText source of the document, its images saved with `image_saver` under
+    /// the URIs of their directives
+    fn generate_with_saver<F>(document: &Document, image_saver: F) -> crate::Result<Bytes>
+    where
+        F: Fn(&Bytes, &str) -> anyhow::Result<()>,
+    {
+        Transformer::generate_with_image_saver(document, image_saver)
+            .map_err(crate::Error::generation_error)
+    }
+}
+
+impl Transformer {
+    fn parse_source<F>(source: &Bytes, image_loader: &F) -> anyhow::Result<Document>
+    where
+        F: Fn(&str) -> anyhow::Result<Bytes>,
+    {
+        let source = std::str::from_utf8(source)?;
+        let source = source.strip_prefix('\u{feff}').unwrap_or(source);
+        let lines: Vec<String> = source.lines().map(expand_tabs).collect();
+        let mut parser = Parser {
+            image_loader,
+            metadata: Metadata::default(),
+            targets: HashMap::new(),
+            titles: HashMap::new(),
+            anonymous: VecDeque::new(),
+            footnotes: HashMap::new(),
+            substitutions: HashMap::new(),
+            adornments: vec![],
+            language: String::new(),
+            bookmarks: vec![],
+            depth: 0,
+        };
+        let adornments = parser.definitions(&lines);
+        let mut start = next_line(&lines, 0);
+        if let Some((title, adornment, end)) = section_title(&lines, start) {
+            if adornment.1 && adornments.get(&adornment) == Some(&1) {
+                parser.metadata.title = Some(plain_text(&parser.inline(&title)?));
+                start = next_line(&lines, end);
+            }
+        }
+        if lines
+            .get(start)
+            .is_some_and(|line| field_marker(line).is_some())
+        {
+            start = parser.docinfo(&lines, start)?;
+        }
+        let elements = parser.blocks(&lines[start.min(lines.len())..])?;
+        let mut document = Document::new(elements);
+        document.metadata = parser.metadata;
+        Ok(document)
+    }
+
+    fn generate_with_image_saver<F>(document: &Document, image_saver: F) -> anyhow::Result<Bytes>
+    where
+        F: Fn(&Bytes, &str) -> anyhow::Result<()>,
+    {
+        let document = &document.resolve_fields().without_drawings();
+        let mut writer = Writer {
+            image_saver: &image_saver,
+            image_count: 0,
+            link_targets: document.link_targets(),
+            sections: vec![],
+            depth: 0,
+            targets: vec![],
+            definitions: vec![],
+        };
+        let elements = document
+            .bands
+            .iter()
+            .filter(|band| !matches!(band, Band::PageHeader(_) | Band::PageFooter(_)))
+            .flat_map(|band| band.elements());
+        let body = writer.blocks(elements)?;
+
+        let metadata = &document.metadata;
+        let mut header = String::new();
+        if let Some(title) = metadata.title.as_deref().map(str::trim) {
+            if !title.is_empty() {
+                let title = escape(title);
+                let adornment = adornment_line('=', &title);
+                let _ = write!(header, "{adornment}\n{title}\n{adornment}\n\n");
+            }
+        }
+        let mut fields = vec![];
+        if let Some(author) = &metadata.author {
+            fields.push(format!(":Author: {}", escape(author)));
+        }
+        if let Some(date) = &metadata.date {
+            fields.push(format!(":Date: {}", escape(date)));
+        }
+        if !fields.is_empty() {
+            let _ = write!(header, "{}\n\n", fields.join("\n"));
+        }
+        let mut meta = vec![];
+        if let Some(description) = &metadata.description {
+            meta.push(("description", description.replace('\n', " ")));
+        }
+        if !metadata.keywords.is_empty() {
+            meta.push(("keywords", metadata.keywords.join(", ")));
+        }
+        if !meta.is_empty() {
+            let _ = write!(header, "{}\n\n", directive("meta::", &meta, ""));
+        }
+        let rst = header + body.trim_end() + "\n";
+        Ok(Bytes::from(rst))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+    use crate::core::tests::{init_logger, table_texts};
+    use crate::core::{ImageAlignment, TransformerTrait};
+    use crate::markdown;
+
+    #[test]
+    fn test_generate() -> anyhow::Result<()> {
+        init_logger();
+        let markdown = "# Results *and* costs\n\nSee [the end](#the-end) and \
+            [the site](https://example.com/a_b).[^1]\n\n\
+            1. One\n   - Nested *item*\n2. Two\n\n\
+            | Name | Value |\n|------|-------|\n| a_b | 1 \\| 2 |\n\n\
+            ```rust\nlet x = 2 * 3;\n```\n\n### The end\n\n`a+b` snake_case * 2 **done**\n\n\
+            [^1]: A note.\n";
+        let mut document = markdown::Transformer::parse(&Bytes::from(markdown))?;
+        let png = Bytes::from(std::fs::read("test/data/small.png")?);
+        if let Some(Band::Detail(elements)) = document.bands.last_mut() {
+            elements.push(Element::Image(ImageData::new(
+                png.clone(),
+                "Logo".to_string(),
+                "The logo".to_string(),
+                "png".to_string(),
+                String::new(),
+                ImageDimension {
+                    width: Some("50%".to_string()),
+                    height: None,
+                },
+            )));
+            elements.push(Element::admonition(
+                "warning",
+                None,
+                vec![
+                    paragraph(vec![text("Hot.")]),
+                    Element::CodeBlock {
+                        info: String::new(),
+                        code: "touch".to_string(),
+                    },
+                ],
+            ));
+        }
+        document.metadata.title = Some("Report".to_string());
+        document.metadata.author = Some("Ada, Alan".to_string());
+
+        let saved = RefCell::new(vec![]);
+        let rst = Transformer::generate_with_saver(&document, |bytes, name| {
+            saved.borrow_mut().push((name.to_string(), bytes.clone()));
+            Ok(())
+        })?;
+        let rst = std::str::from_utf8(&rst)?;
+        assert!(rst.starts_with("======\nReport\n======\n\n:Author: Ada, Alan\n\n"));
+        assert!(rst.contains("Results and costs\n=================\n\n"));
+        // sections are not skipped
+        assert!(rst.contains(".. _the-end:\n\nThe end\n-------\n\n"));
+        assert!(rst.contains(
+            "See `the end <the-end_>`__ and `the site <https://example.com/a_b>`__.\\ [#]_\n\n\
+            .. [#] A note.\n"
+        ));
+        assert!(rst.contains("1. One\n\n   - Nested *item*\n\n2. Two\n"));
+        assert!(rst.contains(
+            ".. list-table::\n   :header-rows: 1\n   :widths: 30 30\n\n   \
+            * - Name\n     - Value\n   * - a_b\n     - 1 \\| 2\n"
+        ));
+        assert!(rst.contains(".. code-block:: rust\n\n   let x = 2 * 3;\n"));
+        assert!(rst.contains("``a+b`` snake_case \\* 2 **done**"));
+        assert!(
+            rst.contains(".. figure:: image1.png\n   :alt: The logo\n   :width: 50%\n\n   Logo\n")
+        );
+        assert!(rst.contains(".. warning::\n\n   Hot.\n\n   ::\n\n      touch\n"));
+        assert_eq!(
+            saved.borrow().as_slice(),
+            &[("image1.png".to_string(), png)]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse() -> anyhow::Result<()> {
+        init_logger();
+        let source = "==========\nUser Guide\n==========\n\n\
+            :Author: Ada Lovelace\n:Date: 2024-05-01\n:Version: 1.2\n\n\
+            .. _start:\n\nGetting *started*\n=================\n\n\
+            Install with ``cargo install``, see Usage_ and\n\
+            `the site <https://example.com>`_ [#]_. Read :math:`a^2` and :py:func:`~shiva.parse`.\n\n\
+            .. [#] Or build it.\n\n\
+            .. note:: Needs Rust 1.70.\n\n\
+            - One\n\n  - Nested\n\n- Two::\n\n     shiva a b\n\n\
+            #. First\n#. Second\n\n\
+            Usage\n-----\n\n\
+            :Name: the option\n:Default: none\n\n\
+            .. list-table:: Options\n   :header-rows: 1\n   :widths: 1 2\n\n   \
+            * - Name\n     - Meaning\n   * - a\n     - The *a*\n\n\
+            +------+-------+\n| Cell | Other |\n+======+=======+\n| 1    | 2     |\n+------+-------+\n\n\
+            ===  ===\nA    B\n===  ===\nx    y\n===  ===\n\n\
+            .. code-block:: rust\n\n   fn main() {}\n\n\
+            .. figure:: small.png\n   :width: 50%\n\n   Logo\n\n\
+            CPU\n   the processor\n\n\
+            Back to start_.\n";
+        let requested = RefCell::new(vec![]);
+        let document = Transformer::parse_with_loader(&Bytes::from(source), |path| {
+            requested.borrow_mut().push(path.to_string());
+            Ok(Bytes::from(std::fs::read(format!("test/data/{path}"))?))
+        })?;
+        assert_eq!(document.metadata.title.as_deref(), Some("User Guide"));
+        assert_eq!(document.metadata.author.as_deref(), Some("Ada Lovelace"));
+        assert_eq!(document.metadata.date.as_deref(), Some("2024-05-01"));
+        assert_eq!(
+            document.metadata.custom.get("version").map(String::as_str),
+            Some("1.2")
+        );
+        assert_eq!(requested.borrow().as_slice(), ["small.png"]);
+
+        let Some(Band::Detail(elements)) = document.bands.last() else {
+            panic!("no detail band");
+        };
+        assert_eq!(
+            elements[0],
+            Element::Header {
+                level: 1,
+                text: "Getting started".to_string()
+            }
+        );
+        let Element::Paragraph {
+            elements: inline, ..
+        } = &elements[1]
+        else {
+            panic!("not a paragraph: {:?}", elements[1]);
+        };
+        assert_eq!(
+            inline[1],
+            Element::InlineCode {
+                text: "cargo install".to_string()
+            }
+        );
+        assert!(matches!(&inline[3], Element::Hyperlink { url, .. } if url == "#usage"));
+        assert!(
+            matches!(&inline[5], Element::Hyperlink { url, .. } if url == "https://example.com")
+        );
+        assert!(
+            matches!(&inline[7], Element::Footnote { elements } if elements == &[text("Or build it.")])
+        );
+        assert!(matches!(&inline[9], Element::Math { tex, display: false } if tex == "a^2"));
+        assert_eq!(
+            inline[11],
+            Element::InlineCode {
+                text: "parse".to_string()
+            }
+        );
+        assert_eq!(
+            elements[2].admonition_parts(),
+            Some((
+                "note",
+                None,
+                &[paragraph(vec![text("Needs Rust 1.70.")])][..]
+            ))
+        );
+        let Element::List {
+            elements: items, ..
+        } = &elements[3]
+        else {
+            panic!("not a list: {:?}", elements[3]);
+        };
+        assert_eq!(items[0].element, text("One"));
+        assert!(matches!(&items[1].element, Element::List { .. }));
+        assert_eq!(items[2].element, text("Two:"));
+        assert_eq!(
+            items[3].element,
+            Element::CodeBlock {
+                info: String::new(),
+                code: "shiva a b".to_string()
+            }
+        );
+        assert!(matches!(
+            &elements[4],
+            Element::List { numbered: true, start: 1, elements, .. } if elements.len() == 2
+        ));
+        assert_eq!(
+            elements[5],
+            Element::Header {
+                level: 2,
+                text: "Usage".to_string()
+            }
+        );
+        let Element::Table { headers, rows } = &elements[6] else {
+            panic!("not a table: {:?}", elements[6]);
+        };
+        assert!(headers.is_empty());
+        assert_eq!(rows[1].cells[0].element, text("Default"));
+        assert_eq!(
+            elements[7],
+            paragraph(vec![Element::Styled {
+                style: TextStyle::Strong,
+                elements: vec![text("Options")],
+            }])
+        );
+        let Element::Table { headers, rows } = &elements[8] else {
+            panic!("not a table: {:?}", elements[8]);
+        };
+        assert_eq!(headers[1].width, 2.);
+        assert!(matches!(
+            &rows[0].cells[1].element,
+            Element::Paragraph { .. }
+        ));
+        let Element::Table { headers, rows } = &elements[9] else {
+            panic!("not a table: {:?}", elements[9]);
+        };
+        assert_eq!(headers[0].element, text("Cell"));
+        assert_eq!(rows[0].cells[1].element, text("2"));
+        let Element::Table { headers, rows } = &elements[10] else {
+            panic!("not a table: {:?}", elements[10]);
+        };
+        assert_eq!(headers[1].element, text("B"));
+        assert_eq!(rows[0].cells[0].element, text("x"));
+        assert_eq!(
+            elements[11],
+            Element::CodeBlock {
+                info: "rust".to_string(),
+                code: "fn main() {}".to_string()
+            }
+        );
+        let Element::Image(image) = &elements[12] else {
+            panic!("not an image: {:?}", elements[12]);
+        };
+        assert_eq!(image.title(), "Logo");
+        assert_eq!(image.size().width.as_deref(), Some("50%"));
+        let Element::List {
+            elements: items, ..
+        } = &elements[13]
+        else {
+            panic!("not a list: {:?}", elements[13]);
+        };
+        assert!(
+            matches!(&items[0].element, Element::Paragraph { elements, .. }
+            if elements[0] == Element::Styled { style: TextStyle::Strong, elements: vec![text("CPU")] })
+        );
+        assert!(matches!(&elements[14], Element::Paragraph { elements, .. }
+            if matches!(&elements[1], Element::Hyperlink { url, .. } if url == "#getting-started")));
+        Ok(())
+    }
+
+    #[test]
+    fn test_round_trip() -> anyhow::Result<()> {
+        init_logger();
+        let source = "Title\n=====\n\nSome **bold** and *emphasis*, ``code`` and a \
+            `link <https://example.com>`__.\n\n\
+            Section\n-------\n\n- One\n- Two\n\n3. Three\n4. Four\n\n\
+            | First line\n| Second line\n\n.. math::\n\n   e^{i \\pi} + 1 = 0\n\n\
+            .. admonition:: Beware\n   :class: danger\n\n   Sharp edges.\n";
+        let document = Transformer::parse(&Bytes::from(source))?;
+        let generated = Transformer::generate(&document)?;
+        assert_eq!(std::str::from_utf8(&generated)?, source);
+        Ok(())
+    }
+
+    #[test]
+    fn test_image_align() -> anyhow::Result<()> {
+        let source = ".. image:: a.png\n   :align: top\n\n.. image:: b.png\n   :align: right\n";
+        let document = Transformer::parse_with_loader(&Bytes::from(source), |_| {
+            Ok(Bytes::from(std::fs::read("test/data/small.png")?))
+        })?;
+        let aligns: Vec<&ImageAlignment> = document
+            .get_all_elements()
+            .into_iter()
+            .filter_map(|element| match element {
+                Element::Image(image) => Some(image.align()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(aligns, [&ImageAlignment::default(), &ImageAlignment::Right]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_tables() -> anyhow::Result<()> {
+        let source = r#"+------+-------+
+| Name | Value |
++======+=======+
+| a    | **1** |
+| b    |       |
++------+-------+
+| total        |
++--------------+
+
+=====  =====
+Col    Note
+=====  =====
+x      first
+       line
+y      z
+=====  =====
+
+.. csv-table:: Totals
+   :header: "Key", "Amount"
+
+   "a, b", 1
+"#;
+        let document = Transformer::parse(&Bytes::from(source))?;
+        let elements = document.get_all_elements();
+        assert_eq!(elements.len(), 4);
+
+        // grid table: the `=` border ends the header, a cell without inner border spans
+        let (headers, rows) = table_texts(elements[0]);
+        assert_eq!(headers, ["Name", "Value"]);
+        assert_eq!(rows, [vec!["a b", "1"], vec!["total", ""]]);
+
+        // simple table: a line with an empty first column continues the row
+        let (headers, rows) = table_texts(elements[1]);
+        assert_eq!(headers, ["Col", "Note"]);
+        assert_eq!(rows, [vec!["x", "first line"], vec!["y", "z"]]);
+
+        // CSV table: its title before it, quoted fields keep their delimiters
+        assert_eq!(elements[2].plain_text(), "Totals");
+        let (headers, rows) = table_texts(elements[3]);
+        assert_eq!(headers, ["Key", "Amount"]);
+        assert_eq!(rows, [vec!["a, b", "1"]]);
+        Ok(())
+    }
+}
@@ -1,398 +1,2305 @@
-use crate::core::{Document, Element, TableHeader, TableRow, TransformerTrait};
+//! Rich Text Format (RTF), the interchange format of word processors
+//!
+//! A document is a tree of groups in braces holding control words (`\b`, `\par`...) and
+//! text. The first control word of a group may name a destination: a table of the header
+//! (`\fonttbl`, `\stylesheet`, `\listtable`), a part of the document (`\header`,
+//! `\footnote`, `\field`, `\pict`) or data to be skipped by the readers that do not know it
+//! (`\*\generator`).
+//! Headers are the paragraphs of a heading style or with an outline level, lists the
+//! paragraphs of a list of the list table (`\ls`, `\ilvl`) and tables the rows of
+//! paragraphs in cells (`\intbl`, `\cell`, `\row`).
+
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::io::Cursor;
+
+use anyhow::bail;
 use bytes::Bytes;
-use image::io::Reader as ImageReader;
-use image::GenericImageView;
+use encoding_rs::Encoding;
 use log::warn;
-use std::io::Cursor;
 
-use rtf_parser::lexer::Lexer;
-use rtf_parser::parser::Parser;
+use crate::core::{
+    Band, Document, Element, FieldKind, ImageData, ImageDimension, ImageType, Length, LengthUnit,
+    ListItem, Metadata, NumberingStyle, PageOrientation, TableCell, TableHeader, TableRow,
+    TextDirection, TextStyle, TransformerTrait, DEFAULT_IMAGE_SIZE,
+};
+use crate::util::{
+    block_element, header_bookmark, is_block, item_element, merge_styled, RunFormat, BULLETS,
+    HEADING_SIZES,
+};
 
 pub struct Transformer;
 
-struct ImageSize {
-    output_width: u32,
-    output_height: u32,
+/// Twips (twentieths of a point) in a CSS pixel
+const TWIPS_PER_PIXEL: f32 = 15.0;
+
+// region:    ---writer
+
+/// Numbers of the paragraph styles of the stylesheet, the heading styles being numbered by
+/// their level
+const BODY_STYLE: usize = 0;
+const CODE_BLOCK_STYLE: usize = 7;
+const TABLE_CONTENTS_STYLE: usize = 8;
+const TABLE_HEADING_STYLE: usize = 9;
+const FOOTNOTE_STYLE: usize = 10;
+const HEADER_STYLE: usize = 11;
+const FOOTER_STYLE: usize = 12;
+const LIST_STYLE: usize = 13;
+
+/// Number of the character style of the links
+const LINK_STYLE: usize = 14;
+
+/// Font of the text and monospace font of the code, in the font table
+const FONT_TABLE: &str = r"{\fonttbl{\f0\froman\fprq2\fcharset0 Times New Roman;}{\f1\fmodern\fprq1\fcharset0 Courier New;}}";
+
+/// Black and the blue of the links
+const COLOR_TABLE: &str = r"{\colortbl;\red0\green0\blue0;\red0\green0\blue128;}";
+
+/// Levels of the list definitions
+const LIST_LEVELS: usize = 9;
+
+/// Indent of the first level of lists and of each deeper level, in twips
+const LIST_INDENT: usize = 360;
+
+/// Highest height of an image, in pixels (24 cm), images are also shrunk to the width of
+/// the text
+const MAX_IMAGE_HEIGHT: f32 = 907.0;
+
+/// Name and formatting of a paragraph style, the formatting being repeated on the
+/// paragraphs as RTF readers do not apply the stylesheet
+fn paragraph_style(style: usize) -> (String, String) {
+    let (name, formatting) = match style {
+        1..=6 => {
+            return (
+                format!("heading {style}"),
+                format!(
+                    r"\s{style}\outlinelevel{}\keepn\sb240\sa120\b\fs{}",
+                    style - 1,
+                    HEADING_SIZES[style - 1] as usize * 2
+                ),
+            )
+        }
+        CODE_BLOCK_STYLE => ("Preformatted Text", r"\f1\fs20"),
+        TABLE_CONTENTS_STYLE => ("Table Contents", ""),
+        TABLE_HEADING_STYLE => ("Table Heading", r"\qc\b"),
+        FOOTNOTE_STYLE => ("footnote text", r"\fs20"),
+        HEADER_STYLE => ("header", ""),
+        FOOTER_STYLE => ("footer", ""),
+        LIST_STYLE => ("List Paragraph", r"\sa60"),
+        _ => ("Normal", r"\sa120"),
+    };
+    (name.to_string(), format!(r"\s{style}{formatting}"))
 }
 
-fn re_size_picture(image_bytes: &Bytes) -> ImageSize {
-    //setting the maximum image size
-    let max_width = 9700; // 16.5 cm
-    let max_height = 18000; // 29.7 cm
+fn stylesheet() -> String {
+    let mut rtf = String::from(r"{\stylesheet");
+    for style in (0..=6).chain([
+        CODE_BLOCK_STYLE,
+        TABLE_CONTENTS_STYLE,
+        TABLE_HEADING_STYLE,
+        FOOTNOTE_STYLE,
+        HEADER_STYLE,
+        FOOTER_STYLE,
+        LIST_STYLE,
+    ]) {
+        let (name, formatting) = paragraph_style(style);
+        let _ = write!(rtf, r"{{{formatting}\sbasedon0\snext0 {name};}}");
+    }
+    let _ = write!(rtf, r"{{\*\cs{LINK_STYLE}\ul\cf2 Hyperlink;}}}}");
+    rtf
+}
 
-    let size_img = ImageReader::new(Cursor::new(image_bytes))
-        .with_guessed_format()
-        .expect("the image format is not defined")
-        .decode()
-        .expect("fail decode image");
-    let (width, height) = size_img.dimensions();
+/// `\levelnfc` of a numbering
+fn number_format(numbering: &NumberingStyle) -> u8 {
+    match numbering {
+        NumberingStyle::Decimal => 0,
+        NumberingStyle::UpperRoman => 1,
+        NumberingStyle::LowerRoman => 2,
+        NumberingStyle::UpperAlpha => 3,
+        NumberingStyle::LowerAlpha => 4,
+    }
+}
 
-    //reassigning the dimensions taking into account the coefficients
-    let width = width * 15;
-    let height = height * 15;
+/// Text escaped for RTF, the characters out of ASCII written as Unicode control words with
+/// `?` for the readers that do not read them
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' | '{' | '}' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\t' => escaped.push_str(r"\tab "),
+            '\n' => escaped.push_str(r"\line "),
+            c if c.is_control() => {}
+            c if c.is_ascii() => escaped.push(c),
+            c => {
+                let mut units = [0; 2];
+                for unit in c.encode_utf16(&mut units) {
+                    // the parameter is a signed 16-bit number
+                    let _ = write!(escaped, r"\u{}?", *unit as i16);
+                }
+            }
+        }
+    }
+    escaped
+}
 
-    //scale the image if it exceeds the page size
-    let mut new_width = width;
-    let mut new_height = height;
+/// Writes elements as RTF, collecting the definitions of the lists
+struct Writer<'a> {
+    document: &'a Document,
+    rtf: String,
+    /// Definitions of the list table, the list of a definition having the number of the
+    /// definition in the list override table
+    lists: Vec<String>,
+    /// Width of the text of the page, in twips
+    text_width: f32,
+    /// End of the last table in the RTF, a table right after it being separated by an
+    /// empty paragraph as readers join the rows of tables that follow each other
+    table_end: Option<usize>,
+}
 
-    //scale the image if it exceeds the page size
-    if width > max_width {
-        let ratio = max_width as f32 / width as f32;
-        new_width = (width as f32 * ratio) as u32;
-        new_height = (height as f32 * ratio) as u32;
+impl<'a> Writer<'a> {
+    fn new(document: &'a Document) -> Writer<'a> {
+        let page = document.page_format.dimensions();
+        let page_width = match document.orientation {
+            PageOrientation::Portrait => page.page_width,
+            PageOrientation::Landscape => page.page_height,
+        };
+        let text_width =
+            page_width.to_mm() - page.page_margin_left.to_mm() - page.page_margin_right.to_mm();
+        Writer {
+            document,
+            rtf: String::new(),
+            lists: vec![],
+            text_width: twips(Length::mm(text_width.max(10.0))) as f32,
+            table_end: None,
+        }
     }
-    if new_height > max_height {
-        let ratio = max_height as f32 / new_height as f32;
-        new_width = (new_width as f32 * ratio) as u32;
-        new_height = (new_height as f32 * ratio) as u32;
+
+    fn block(&mut self, element: &Element) {
+        match element {
+            Element::Header { level, text } => {
+                let (level, anchor) = header_bookmark(*level, text);
+                let anchor = escape(&anchor);
+                let _ = write!(
+                    self.rtf,
+                    r"\pard\plain{} {{\*\bkmkstart {anchor}}}{{\*\bkmkend {anchor}}}{}\par",
+                    paragraph_style(level as usize).1,
+                    escape(text)
+                );
+                self.rtf.push('\n');
+            }
+            Element::Paragraph {
+                elements,
+                direction,
+            } => {
+                let mut formatting = paragraph_style(BODY_STYLE).1;
+                match direction {
+                    TextDirection::Rtl => formatting.push_str(r"\rtlpar"),
+                    TextDirection::Ltr => formatting.push_str(r"\ltrpar"),
+                    TextDirection::Auto => {}
+                }
+                self.paragraph(&formatting, elements)
+            }
+            Element::List { .. } => self.list(element, 0),
+            Element::TableOfContents { max_level } => self.table_of_contents(*max_level),
+            Element::Table { headers, rows } => self.table(headers, rows),
+            Element::CodeBlock { code, .. } => {
+                let _ = write!(
+                    self.rtf,
+                    r"\pard\plain{} {}\par",
+                    paragraph_style(CODE_BLOCK_STYLE).1,
+                    escape(code.strip_suffix('\n').unwrap_or(code))
+                );
+                self.rtf.push('\n');
+            }
+            Element::LineBreak => self.paragraph(&paragraph_style(BODY_STYLE).1, []),
+            Element::Raw { .. } => self.inline(element),
+            element => self.paragraph(&paragraph_style(BODY_STYLE).1, [element]),
+        }
     }
 
-    let output_width = new_width;
-    let output_height = new_height;
+    /// Writes the blocks of the elements, the elements between them making paragraphs of
+    /// the given style
+    fn content<'e>(&mut self, elements: impl IntoIterator<Item = &'e Element>, style: usize) {
+        let formatting = paragraph_style(style).1;
+        let mut inline: Vec<&Element> = vec![];
+        for element in elements {
+            if !is_block(element) {
+                inline.push(element);
+                continue;
+            }
+            if !inline.is_empty() {
+                self.paragraph(&formatting, std::mem::take(&mut inline));
+            }
+            match element {
+                Element::Paragraph { elements, .. } => self.paragraph(&formatting, elements),
+                element => self.block(element),
+            }
+        }
+        if !inline.is_empty() {
+            self.paragraph(&formatting, inline);
+        }
+    }
 
-    ImageSize {
-        output_width,
-        output_height,
+    fn paragraph<'e>(&mut self, formatting: &str, elements: impl IntoIterator<Item = &'e Element>) {
+        let _ = write!(self.rtf, r"\pard\plain{formatting} ");
+        for element in elements {
+            self.inline(element);
+        }
+        self.rtf.push_str("\\par\n");
     }
-}
 
-fn detect_element_in_list(
-    rtf_content: &mut String,
-    element: &Element,
-    numbered: bool,
-    parent_indices: &mut Vec<usize>,
-    depth: usize,
-) {
-    match element {
-        Element::Text { text, size } => {
-            let indent = " ".repeat(depth * 4); // 4 пробела для каждого уровня вложенности
-            let modified_text = if numbered {
-                let numbering = parent_indices
-                    .iter()
-                    .map(|i| i.to_string())
-                    .collect::<Vec<String>>()
-                    .join(".");
-                format!("{}{}. {}", indent, numbering, text)
+    fn inline(&mut self, element: &Element) {
+        match element {
+            Element::Text { text, .. } => self.rtf.push_str(&escape(text)),
+            Element::LineBreak => self.rtf.push_str(r"\line "),
+            Element::InlineCode { text } => {
+                let _ = write!(self.rtf, r"{{\f1 {}}}", escape(text));
+            }
+            Element::Styled { style, elements } => {
+                self.rtf.push_str(match style {
+                    TextStyle::Strong => r"{\b ",
+                    TextStyle::Emphasis => r"{\i ",
+                    TextStyle::Strikethrough => r"{\strike ",
+                });
+                for element in elements {
+                    self.inline(element);
+                }
+                self.rtf.push('}');
+            }
+            Element::Hyperlink {
+                elements, url, alt, ..
+            } => {
+                // quotes end the arguments of the field
+                let target = match url.strip_prefix('#') {
+                    Some(anchor) => format!(r#"\\l "{}""#, escape(anchor).replace('"', "%22")),
+                    None => format!(r#""{}""#, escape(url).replace('"', "%22")),
+                };
+                let _ = write!(self.rtf, r"{{\field{{\*\fldinst{{HYPERLINK {target}");
+                if !alt.is_empty() {
+                    let _ = write!(self.rtf, r#" \\o "{}""#, escape(alt).replace('"', "'"));
+                }
+                let _ = write!(self.rtf, r"}}}}{{\fldrslt{{\cs{LINK_STYLE}\ul\cf2 ");
+                if elements.is_empty() {
+                    self.rtf.push_str(&escape(url));
+                }
+                for element in elements {
+                    self.inline(element);
+                }
+                self.rtf.push_str("}}}");
+            }
+            Element::Image(image) => self.image(image),
+            Element::Footnote { elements } => self.footnote(elements),
+            Element::Field { kind } => {
+                let instruction = match kind {
+                    FieldKind::PageNumber => "PAGE",
+                    FieldKind::TotalPages => "NUMPAGES",
+                    FieldKind::Date => "DATE",
+                    FieldKind::DocumentTitle => "TITLE",
+                };
+                let _ = write!(
+                    self.rtf,
+                    r"{{\field{{\*\fldinst {instruction}}}{{\fldrslt }}}}"
+                );
+            }
+            Element::Bookmark { id, .. } => {
+                let id = escape(id);
+                let _ = write!(self.rtf, r"{{\*\bkmkstart {id}}}{{\*\bkmkend {id}}}");
+            }
+            Element::Paragraph { elements, .. } => {
+                for element in elements {
+                    self.inline(element);
+                }
+            }
+            Element::Attributed { element, .. } => self.inline(element),
+            Element::Raw { format, content } => {
+                if *format == crate::core::DocumentType::RTF {
+                    self.rtf.push_str(content);
+                }
+            }
+            element => self.rtf.push_str(&escape(&element.plain_text())),
+        }
+    }
+
+    /// Note with its reference mark, its inline elements written in its first paragraph
+    fn footnote(&mut self, elements: &[Element]) {
+        let inline = elements.iter().position(is_block).unwrap_or(elements.len());
+        let _ = write!(
+            self.rtf,
+            r"{{\super\chftn}}{{\footnote\pard\plain{} {{\super\chftn}} ",
+            paragraph_style(FOOTNOTE_STYLE).1
+        );
+        for element in &elements[..inline] {
+            self.inline(element);
+        }
+        self.rtf.push_str(r"\par ");
+        self.content(&elements[inline..], FOOTNOTE_STYLE);
+        self.rtf.push('}');
+    }
+
+    /// Picture of a PNG or JPEG image, the images of the other types converted to PNG
+    fn image(&mut self, image: &ImageData) {
+        if image.bytes().is_empty() {
+            warn!("Image without content left out: {}", image.title());
+            return;
+        }
+        let decoded = image::load_from_memory(image.bytes());
+        let (blip, bytes) = match (image.image_type(), &decoded) {
+            (ImageType::Png, _) => (r"\pngblip", image.bytes().clone()),
+            (ImageType::Jpeg, _) => (r"\jpegblip", image.bytes().clone()),
+            (_, Ok(decoded)) => {
+                let mut png = Cursor::new(Vec::new());
+                if let Err(error) = decoded.write_to(&mut png, image::ImageFormat::Png) {
+                    warn!("Cannot convert the image {}: {}", image.title(), error);
+                    return;
+                }
+                (r"\pngblip", Bytes::from(png.into_inner()))
+            }
+            (image_type, Err(_)) => {
+                warn!(
+                    "{} image left out, RTF pictures are PNG or JPEG: {}",
+                    image_type,
+                    image.title()
+                );
+                return;
+            }
+        };
+        let (pixel_width, pixel_height) = decoded
+            .map(|decoded| (decoded.width(), decoded.height()))
+            .unwrap_or((DEFAULT_IMAGE_SIZE.0 as u32, DEFAULT_IMAGE_SIZE.1 as u32));
+        let (width, height) = self.image_size(image, (pixel_width, pixel_height));
+        self.rtf.push_str(r"{\pict{\*\picprop");
+        for (name, value) in [("wzName", image.title()), ("wzDescription", image.alt())] {
+            if !value.is_empty() {
+                let _ = write!(self.rtf, r"{{\sp{{\sn {name}}}{{\sv {}}}}}", escape(value));
+            }
+        }
+        let _ = write!(
+            self.rtf,
+            r"}}\picw{pixel_width}\pich{pixel_height}\picwgoal{}\pichgoal{}{blip}",
+            (width * TWIPS_PER_PIXEL).round(),
+            (height * TWIPS_PER_PIXEL).round()
+        );
+        for line in bytes.chunks(64) {
+            self.rtf.push('\n');
+            for byte in line {
+                let _ = write!(self.rtf, "{byte:02x}");
+            }
+        }
+        self.rtf.push('}');
+    }
+
+    /// Size of an image in pixels, its own size or else the size of its picture, shrunk to
+    /// fit the page
+    fn image_size(&self, image: &ImageData, picture: (u32, u32)) -> (f32, f32) {
+        let pixels = |value: &Option<String>| {
+            value
+                .as_deref()
+                .and_then(|value| value.trim().trim_end_matches("px").parse::<f32>().ok())
+                .filter(|value| *value > 0.0)
+        };
+        let size = image.size();
+        let (width, height) = match (pixels(&size.width), pixels(&size.height)) {
+            (Some(width), Some(height)) => (width, height),
+            _ => (picture.0.max(1) as f32, picture.1.max(1) as f32),
+        };
+        let max_width = self.text_width / TWIPS_PER_PIXEL;
+        let scale = (max_width / width).min(MAX_IMAGE_HEIGHT / height).min(1.0);
+        (width * scale, height * scale)
+    }
+
+    /// List at a depth of nesting, with a definition of its own in the list table so that
+    /// its numbering starts again and its nested lists keep their own numbering
+    fn list(&mut self, list: &Element, depth: usize) {
+        let Element::List {
+            elements,
+            numbered,
+            start,
+            numbering,
+        } = list
+        else {
+            return;
+        };
+        let id = self.lists.len() + 1;
+        let mut definition = format!(r"{{\list\listtemplateid{id}\listhybrid");
+        for level in 0..LIST_LEVELS {
+            let (format, text) = if *numbered {
+                // the level text starts with its length, `\'0N` standing for the number of
+                // the level N
+                (
+                    number_format(numbering),
+                    format!(r"\'02\'{level:02x}.;}}{{\levelnumbers\'01;"),
+                )
             } else {
-                format!("{}- {}", indent, text)
+                let bullet = escape(BULLETS[level % BULLETS.len()]);
+                (23, format!(r"\'01{bullet};}}{{\levelnumbers;"))
             };
-            rtf_content.push_str(&format!("{{\\fs{} {}}} ", *size as i32 * 2, modified_text));
-            rtf_content.push_str("\\par ");
+            let _ = write!(
+                definition,
+                r"{{\listlevel\levelnfc{format}\levelnfcn{format}\leveljc0\leveljcn0\levelfollow0\levelstartat{start}{{\leveltext{text}}}\fi-{LIST_INDENT}\li{}}}",
+                LIST_INDENT * (level + 2)
+            );
         }
+        let _ = write!(definition, r"{{\listname ;}}\listid{id}}}");
+        self.lists.push(definition);
 
-        Element::Header { level, text } => {
-            let header_size = 30 + (level);
-            let indent = " ".repeat(depth * 4); // 4 пробела для каждого уровня вложенности
-            let modified_text = if numbered {
-                let numbering = parent_indices
-                    .iter()
-                    .map(|i| i.to_string())
-                    .collect::<Vec<String>>()
-                    .join(".");
-                format!("{}. {} ", numbering, text)
+        // items are written with their marker for the readers without lists
+        let level = depth.min(LIST_LEVELS - 1);
+        let mut number = *start;
+        for item in elements {
+            if let nested @ Element::List { .. } = &item.element {
+                self.list(nested, depth + 1);
+                continue;
+            }
+            let marker = if *numbered {
+                format!("{}.", numbering.format(number))
             } else {
-                format!("{}- {}", indent, text)
+                BULLETS[level % BULLETS.len()].to_string()
             };
-            rtf_content.push_str(&format!(
-                "{{\\fs{}\\b {} \\b0}}\\par ",
-                header_size, modified_text
-            ));
+            number += 1;
+            let _ = write!(
+                self.rtf,
+                r"\pard\plain{}\ls{id}\ilvl{level}\fi-{LIST_INDENT}\li{}{{\listtext\pard\plain {}\tab}}",
+                paragraph_style(LIST_STYLE).1,
+                LIST_INDENT * (level + 2),
+                escape(&marker)
+            );
+            self.inline(&item.element);
+            self.rtf.push_str("\\par\n");
         }
+    }
 
-        Element::Hyperlink { title, url, .. } => {
-            let indent = " ".repeat(depth * 4);
-            let modified_title = if numbered {
-                let numbering = parent_indices
-                    .iter()
-                    .map(|i| i.to_string())
-                    .collect::<Vec<String>>()
-                    .join(".");
-                format!("{}{}. {}", indent, numbering, title)
-            } else {
-                format!("{}- {}", indent, title)
+    /// Table of contents field, its result being the entries of the headers linked to
+    /// their bookmarks
+    fn table_of_contents(&mut self, max_level: u8) {
+        fn entries(writer: &mut Writer, list: &Element, depth: usize) {
+            let Element::List { elements, .. } = list else {
+                return;
             };
-            rtf_content.push_str(&format!(
-                "{{\\field{{\\*\\fldinst HYPERLINK \"{}\" }}{{\\fldrslt {{\\ul\\cf1 {}}}}}}}",
-                url, modified_title
-            ));
-            rtf_content.push_str("\\par ");
+            for item in elements {
+                match &item.element {
+                    nested @ Element::List { .. } => entries(writer, nested, depth + 1),
+                    entry => {
+                        let _ = write!(
+                            writer.rtf,
+                            r"\pard\plain{}\li{} ",
+                            paragraph_style(BODY_STYLE).1,
+                            LIST_INDENT * depth
+                        );
+                        writer.inline(entry);
+                        writer.rtf.push_str("\\par\n");
+                    }
+                }
+            }
         }
 
-        Element::Image(image) => {
-            let image_bytes = image.bytes();
-            let image_size = re_size_picture(image_bytes);
-            let image = image_bytes
-                .iter()
-                .map(|b| format!("{:02X}", b))
-                .collect::<String>();
+        let _ = write!(
+            self.rtf,
+            r#"\pard\plain{} {{\field{{\*\fldinst TOC \\o "1-{max_level}" \\h}}{{\fldrslt "#,
+            paragraph_style(BODY_STYLE).1
+        );
+        let toc = self.document.build_toc(max_level);
+        entries(self, &toc, 0);
+        self.rtf.push_str("}}\\par\n");
+    }
 
-            let indent = " ".repeat(depth * 4);
-            let modified_image_caption = if numbered {
-                let numbering = parent_indices
-                    .iter()
-                    .map(|i| i.to_string())
-                    .collect::<Vec<String>>()
-                    .join(".");
-                format!("{}{}. {}", indent, numbering, "Image")
-            } else {
-                format!("{}- Image", indent)
-            };
+    /// Table of equal columns across the text, the headers in a header row
+    fn table(&mut self, headers: &[TableHeader], rows: &[TableRow]) {
+        let columns = rows
+            .iter()
+            .map(|row| row.cells.len())
+            .chain([headers.len()])
+            .max()
+            .unwrap_or(0)
+            .max(1);
+        if self.table_end == Some(self.rtf.len()) {
+            self.rtf.push_str("\\pard\\plain\\par\n");
+        }
+        if !headers.is_empty() {
+            self.table_row(
+                headers.iter().map(|header| &header.element),
+                columns,
+                TABLE_HEADING_STYLE,
+            );
+        }
+        for row in rows {
+            self.table_row(
+                row.cells.iter().map(|cell| &cell.element),
+                columns,
+                TABLE_CONTENTS_STYLE,
+            );
+        }
+        self.rtf.push_str("\\pard\n");
+        self.table_end = Some(self.rtf.len());
+    }
+
+    /// Row of the cells, completed with empty cells up to the number of columns. Rows of
+    /// the heading style are header rows, repeated on each page.
+    fn table_row<'e>(
+        &mut self,
+        cells: impl Iterator<Item = &'e Element>,
+        columns: usize,
+        style: usize,
+    ) {
+        self.rtf.push_str(r"\trowd\trgaph108\trleft0");
+        if style == TABLE_HEADING_STYLE {
+            self.rtf.push_str(r"\trhdr");
+        }
+        let width = self.text_width / columns as f32;
+        for column in 1..=columns {
+            let _ = write!(
+                self.rtf,
+                r"\clbrdrt\brdrs\brdrw10\clbrdrl\brdrs\brdrw10\clbrdrb\brdrs\brdrw10\clbrdrr\brdrs\brdrw10\cellx{}",
+                (width * column as f32).round()
+            );
+        }
+        self.rtf.push('\n');
+        let formatting = paragraph_style(style).1;
+        let mut written = 0;
+        for element in cells {
+            let _ = write!(self.rtf, r"\pard\plain\intbl{formatting} ");
+            self.inline(element);
+            self.rtf.push_str(r"\cell");
+            self.rtf.push('\n');
+            written += 1;
+        }
+        for _ in written..columns {
+            self.rtf.push_str("\\pard\\plain\\intbl\\cell\n");
+        }
+        self.rtf.push_str("\\row\n");
+    }
+}
 
-            rtf_content.push_str(&format!("{{\\fs24 {}}}\\par ", modified_image_caption));
+/// Length in twips
+fn twips(length: Length) -> i64 {
+    (length.to(LengthUnit::Pt).value * 20.0).round() as i64
+}
 
-            rtf_content.push_str(&format!(
-                "{{{{\\pict\\jpegblip\\picwgoal{}\\pichgoal{} {} }}}}",
-                image_size.output_width, image_size.output_height, image
-            ));
-            rtf_content.push_str("\\par ");
+/// Information group of the metadata and the user properties of the custom metadata, the
+/// date is written when it starts with a `2024-05-01` date
+fn info(metadata: &Metadata) -> String {
+    let mut rtf = String::from(r"{\info");
+    let keywords = metadata.keywords.join(", ");
+    for (destination, value) in [
+        ("title", metadata.title.as_deref()),
+        ("author", metadata.author.as_deref()),
+        ("doccomm", metadata.description.as_deref()),
+        (
+            "keywords",
+            Some(keywords.as_str()).filter(|keywords| !keywords.is_empty()),
+        ),
+    ] {
+        if let Some(value) = value {
+            let _ = write!(rtf, r"{{\{destination} {}}}", escape(value));
         }
+    }
+    let date = metadata.date.as_deref().and_then(|date| {
+        let mut parts = date
+            .get(..10)?
+            .split('-')
+            .map(|part| part.parse::<u16>().ok());
+        Some((parts.next()??, parts.next()??, parts.next()??))
+    });
+    if let Some((year, month, day)) = date {
+        let _ = write!(rtf, r"{{\creatim\yr{year}\mo{month}\dy{day}}}");
+    }
+    rtf.push('}');
+    if !metadata.custom.is_empty() {
+        rtf.push_str(r"{\*\userprops");
+        for (name, value) in &metadata.custom {
+            let _ = write!(
+                rtf,
+                r"{{\propname {}}}\proptype30{{\staticval {}}}",
+                escape(name),
+                escape(value)
+            );
+        }
+        rtf.push('}');
+    }
+    rtf
+}
+
+// endregion: ---writer
+
+// region:    ---reader
+
+/// Size of the text read from RTF, the font sizes are not read
+const TEXT_SIZE: u8 = 12;
+
+/// Width of the table headers read from RTF
+const HEADER_WIDTH: f32 = 30.0;
+
+/// Destinations whose content is not text of the document: tables of the header that are
+/// not read, alternatives of pictures, the markers of list items and annotations
+const SKIPPED_DESTINATIONS: [&str; 20] = [
+    "colortbl",
+    "filetbl",
+    "revtbl",
+    "rsidtbl",
+    "generator",
+    "template",
+    "nonshppict",
+    "listtext",
+    "pntext",
+    "pntxta",
+    "pntxtb",
+    "ftnsep",
+    "ftnsepc",
+    "ftncn",
+    "aftnsep",
+    "aftnsepc",
+    "aftncn",
+    "object",
+    "xe",
+    "tc",
+];
+
+/// Token of an RTF document, a group holding the tokens between its braces
+#[derive(Debug)]
+enum Token {
+    Group(Vec<Token>),
+    /// Control word with its parameter
+    Word(String, Option<i32>),
+    /// Control symbol such as `\~` or `\*`
+    Symbol(u8),
+    /// Text in the code page of the document, a `\'hh` character being a text of one byte
+    Text(Vec<u8>),
+    /// Data of a `\bin` control word
+    Binary(Vec<u8>),
+}
 
-        Element::List { elements, numbered } => {
-            if *numbered {
-                parent_indices.push(0); // Добавляем новый уровень для вложенного списка
+/// Tokens of the groups of an RTF document
+fn tokenize(rtf: &[u8]) -> anyhow::Result<Vec<Token>> {
+    if !rtf.trim_ascii_start().starts_with(br"{\rtf") {
+        bail!("Not an RTF document: no {{\\rtf header");
+    }
+    let mut stack: Vec<Vec<Token>> = vec![vec![]];
+    let mut position = 0;
+    let push_text = |tokens: &mut Vec<Token>, bytes: &[u8]| match tokens.last_mut() {
+        Some(Token::Text(text)) => text.extend_from_slice(bytes),
+        _ => tokens.push(Token::Text(bytes.to_vec())),
+    };
+    while position < rtf.len() {
+        let byte = rtf[position];
+        position += 1;
+        match byte {
+            b'{' => stack.push(vec![]),
+            b'}' => {
+                // unbalanced closing braces are left out
+                if stack.len() > 1 {
+                    let group = stack.pop().unwrap_or_default();
+                    if let Some(parent) = stack.last_mut() {
+                        parent.push(Token::Group(group));
+                    }
+                }
             }
-            for list_item in elements {
-                if *numbered {
-                    *parent_indices.last_mut().unwrap() += 1; // Увеличиваем индекс текущего уровня
+            b'\\' => {
+                let tokens = stack.last_mut().expect("the document is a group");
+                let Some(&next) = rtf.get(position) else {
+                    break;
+                };
+                if !next.is_ascii_alphabetic() {
+                    position += 1;
+                    match next {
+                        b'\'' => {
+                            let hex = rtf.get(position..position + 2).unwrap_or_default();
+                            if let Some(byte) = std::str::from_utf8(hex)
+                                .ok()
+                                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                            {
+                                tokens.push(Token::Text(vec![byte]));
+                                position += 2;
+                            }
+                        }
+                        b'\\' | b'{' | b'}' => push_text(tokens, &[next]),
+                        // an escaped line break is a paragraph break
+                        b'\n' | b'\r' => tokens.push(Token::Word("par".to_string(), None)),
+                        symbol => tokens.push(Token::Symbol(symbol)),
+                    }
+                    continue;
+                }
+                let start = position;
+                while rtf.get(position).is_some_and(u8::is_ascii_alphabetic) {
+                    position += 1;
+                }
+                let name = String::from_utf8_lossy(&rtf[start..position]).into_owned();
+                let number_start = position;
+                if rtf.get(position) == Some(&b'-') {
+                    position += 1;
+                }
+                while rtf.get(position).is_some_and(u8::is_ascii_digit) {
+                    position += 1;
+                }
+                let parameter = std::str::from_utf8(&rtf[number_start..position])
+                    .ok()
+                    .and_then(|number| number.parse::<i64>().ok())
+                    .map(|number| number.clamp(i32::MIN as i64, i32::MAX as i64) as i32);
+                if parameter.is_none() {
+                    position = number_start;
+                }
+                // a space ends the control word and is part of it
+                if rtf.get(position) == Some(&b' ') {
+                    position += 1;
+                }
+                if name == "bin" {
+                    let length = parameter.unwrap_or(0).max(0) as usize;
+                    let end = (position + length).min(rtf.len());
+                    tokens.push(Token::Binary(rtf[position..end].to_vec()));
+                    position = end;
+                } else {
+                    tokens.push(Token::Word(name, parameter));
                 }
-                detect_element_in_list(
-                    rtf_content,
-                    &list_item.element,
-                    *numbered,
-                    parent_indices,
-                    depth + 1,
-                );
             }
-            if *numbered {
-                parent_indices.pop(); // Удаляем уровень после обработки вложенного списка
+            // line breaks of the file are not text
+            b'\n' | b'\r' | 0 => {}
+            byte => push_text(stack.last_mut().expect("the document is a group"), &[byte]),
+        }
+    }
+    // groups left open at the end of the file are closed
+    while stack.len() > 1 {
+        let group = stack.pop().unwrap_or_default();
+        if let Some(parent) = stack.last_mut() {
+            parent.push(Token::Group(group));
+        }
+    }
+    Ok(stack.pop().unwrap_or_default())
+}
+
+/// Destination of a group, its first control word, and whether the readers that do not know
+/// it skip it (`\*`)
+fn destination(group: &[Token]) -> (Option<&str>, bool) {
+    match group {
+        [Token::Symbol(b'*'), Token::Word(name, _), ..] => (Some(name), true),
+        [Token::Word(name, _), ..] => (Some(name), false),
+        _ => (None, false),
+    }
+}
+
+/// Groups of a destination in the group and its descendants, the groups of the destination
+/// not being searched
+fn find_groups<'t>(tokens: &'t [Token], name: &str, found: &mut Vec<&'t [Token]>) {
+    for token in tokens {
+        if let Token::Group(group) = token {
+            if destination(group).0 == Some(name) {
+                found.push(group);
+            } else {
+                find_groups(group, name, found);
             }
         }
+    }
+}
+
+/// Encoding of a Windows code page, Windows-1252 for the unknown ones
+fn code_page_encoding(code_page: i32) -> &'static Encoding {
+    let label = match code_page {
+        874 | 1250..=1258 => format!("windows-{code_page}"),
+        932 => "shift_jis".to_string(),
+        936 => "gbk".to_string(),
+        949 => "euc-kr".to_string(),
+        950 => "big5".to_string(),
+        866 => "ibm866".to_string(),
+        10000 => "macintosh".to_string(),
+        _ => return encoding_rs::WINDOWS_1252,
+    };
+    Encoding::for_label(label.as_bytes()).unwrap_or(encoding_rs::WINDOWS_1252)
+}
+
+/// Code page of a `\fcharset` of the font table
+fn charset_code_page(charset: i32) -> Option<i32> {
+    Some(match charset {
+        0 => 1252,
+        77 => 10000,
+        128 => 932,
+        129 => 949,
+        134 => 936,
+        136 => 950,
+        161 => 1253,
+        162 => 1254,
+        163 => 1258,
+        177 => 1255,
+        178 => 1256,
+        186 => 1257,
+        204 => 1251,
+        222 => 874,
+        238 => 1250,
+        _ => return None,
+    })
+}
+
+/// Font of the font table
+#[derive(Debug, Default)]
+struct Font {
+    monospace: bool,
+    encoding: Option<&'static Encoding>,
+}
+
+/// Properties of a style of the stylesheet
+#[derive(Debug, Default)]
+struct Style {
+    name: String,
+    strong: bool,
+    emphasis: bool,
+    strikethrough: bool,
+    font: Option<i32>,
+    outline_level: Option<u8>,
+}
+
+impl Style {
+    /// Level of the headers of the style, from its outline level or else its name
+    fn level(&self) -> Option<u8> {
+        self.outline_level.or_else(|| {
+            self.name
+                .to_lowercase()
+                .strip_prefix("heading ")
+                .and_then(|level| level.trim().parse().ok())
+        })
+    }
+}
+
+/// Character formatting, inherited by the nested groups
+#[derive(Debug, Clone, Copy)]
+struct CharFormat {
+    strong: bool,
+    emphasis: bool,
+    strikethrough: bool,
+    hidden: bool,
+    font: Option<i32>,
+    /// Characters after a `\u` character for the readers that do not read them
+    unicode_skip: usize,
+}
 
-        _ => {
-            warn!("Unknown element in list: {:?}", element);
+impl Default for CharFormat {
+    fn default() -> Self {
+        CharFormat {
+            strong: false,
+            emphasis: false,
+            strikethrough: false,
+            hidden: false,
+            font: None,
+            unicode_skip: 1,
         }
     }
 }
 
-impl TransformerTrait for Transformer {
-    fn parse(document: &bytes::Bytes) -> anyhow::Result<Document> {
-        let data_str = std::str::from_utf8(document).unwrap();
-        let tokens = Lexer::scan(&data_str).unwrap();
-
-        // keeping the document in a box since it might contain huge data and also
-        // for easy manipulation
-        let mut document: Document = Document::new(vec![]);
-        // initializing header levels
-        let mut level = 1;
-        for styleblock in Parser::new(tokens).parse().unwrap().body.as_slice() {
-            if styleblock.painter.font_size >= 30 && styleblock.painter.bold == true {
-                document.add_element(Element::Header {
-                    level: level,
-                    text: styleblock.text.to_owned(),
+/// Paragraph formatting, set until the next `\pard`
+#[derive(Debug, Clone, Copy, Default)]
+struct ParagraphFormat {
+    style: i32,
+    /// Number of the list override table, 0 for none
+    list: i32,
+    list_level: usize,
+    in_table: bool,
+    outline_level: Option<u8>,
+    direction: TextDirection,
+}
+
+/// List of the list override table being read, with the level of its items
+struct OpenList {
+    id: i32,
+    level: usize,
+    list: Element,
+}
+
+/// Table being read, with its row and cell
+#[derive(Default)]
+struct OpenTable {
+    headers: Vec<TableHeader>,
+    rows: Vec<TableRow>,
+    row: Vec<Element>,
+    cell: Vec<Element>,
+}
+
+/// Blocks of a part of the document (body, header, note...) being read
+#[derive(Default)]
+struct Blocks {
+    elements: Vec<Element>,
+    lists: Vec<OpenList>,
+    table: Option<OpenTable>,
+    /// Inline elements of the paragraph being read
+    pieces: Vec<(RunFormat, Element)>,
+    paragraph: ParagraphFormat,
+    /// The row being read is a header row
+    header_row: bool,
+}
+
+impl Blocks {
+    /// Ends the paragraph being read, a block or an item of the open list or table
+    fn end_paragraph(&mut self, reader: &Reader) {
+        let content = inline_elements(std::mem::take(&mut self.pieces));
+        let format = self.paragraph;
+        if format.in_table {
+            let cell = &mut self.table.get_or_insert_with(Default::default).cell;
+            if !content.is_empty() {
+                if !cell.is_empty() {
+                    cell.push(Element::LineBreak);
+                }
+                cell.extend(content);
+            }
+            return;
+        }
+        self.close_table();
+
+        let style = reader.styles.get(&format.style);
+        if let Some(level) = format
+            .outline_level
+            .or_else(|| style.and_then(Style::level))
+        {
+            let text = Element::Paragraph {
+                elements: content,
+                direction: Default::default(),
+            }
+            .plain_text()
+            .trim()
+            .to_string();
+            if !text.is_empty() {
+                self.close_lists();
+                self.elements.push(Element::Header {
+                    level: level.max(1),
+                    text,
                 });
-                level += 1
-            } else {
-                {
-                    document.add_element(Element::Paragraph {
-                        elements: vec![Element::Text {
-                            text: styleblock.text.to_owned(),
-                            size: styleblock.painter.font_size as u8,
-                        }],
-                    })
+            }
+            return;
+        }
+        if style.is_some_and(|style| reader.is_monospace(style.font)) {
+            self.close_lists();
+            let line: String = content
+                .iter()
+                .map(|element| match element {
+                    Element::InlineCode { text } => text.clone(),
+                    element => element.plain_text(),
+                })
+                .collect();
+            match self.elements.last_mut() {
+                Some(Element::CodeBlock { code, .. }) => {
+                    code.push('\n');
+                    code.push_str(&line);
                 }
+                _ => self.elements.push(Element::CodeBlock {
+                    info: String::new(),
+                    code: line,
+                }),
             }
+            return;
+        }
+        if format.list > 0 && !content.is_empty() {
+            let level = reader.list_level(format.list, format.list_level);
+            let list = Element::List {
+                elements: vec![],
+                numbered: level.is_some(),
+                start: level.map(|(_, start)| start).unwrap_or(1),
+                numbering: level.map(|(numbering, _)| numbering).unwrap_or_default(),
+            };
+            self.add_list_item(format.list, format.list_level, list, content);
+            return;
+        }
+        self.close_lists();
+        if let Some(mut element) = block_element(content) {
+            if let Element::Paragraph { direction, .. } = &mut element {
+                *direction = format.direction;
+            }
+            self.elements.push(element);
         }
-        Ok(document)
     }
 
-    fn generate(document: &Document) -> anyhow::Result<bytes::Bytes> {
-        let mut rtf_content = String::new();
-        let mut parent_indices = Vec::new();
+    /// Ends the cell being read, its last paragraph ending with it
+    fn end_cell(&mut self, reader: &Reader) {
+        self.paragraph.in_table = true;
+        self.end_paragraph(reader);
+        let table = self.table.get_or_insert_with(Default::default);
+        let cell = std::mem::take(&mut table.cell);
+        table.row.push(item_element(cell));
+    }
 
-        rtf_content.push_str("{\\rtf1\\ansi\\deff0"); //the standard title of an RTF document, which indicates that it is an RTF document using ANSI characters and the default font
-        for element in &document.get_all_elements() {
-            match element {
-                Element::Header { level, text } => {
-                    let header_size = 30 + (level);
+    /// Ends the row being read, the first row being the headers when it is a header row.
+    /// A header row after other rows starts another table. Header cells are read without
+    /// their bold.
+    fn end_row(&mut self) {
+        let Some(table) = &mut self.table else {
+            return;
+        };
+        let cells = std::mem::take(&mut table.row);
+        if self.header_row && (!table.headers.is_empty() || !table.rows.is_empty()) {
+            self.close_table();
+        }
+        let table = self.table.get_or_insert_with(Default::default);
+        if self.header_row && table.headers.is_empty() && table.rows.is_empty() {
+            table.headers = cells
+                .into_iter()
+                .map(|element| TableHeader {
+                    element: match element {
+                        Element::Styled {
+                            style: TextStyle::Strong,
+                            elements,
+                        } => item_element(elements),
+                        element => element,
+                    },
+                    width: HEADER_WIDTH,
+                })
+                .collect();
+        } else {
+            table.rows.push(TableRow {
+                cells: cells
+                    .into_iter()
+                    .map(|element| TableCell { element })
+                    .collect(),
+            });
+        }
+    }
 
-                    //formatting the string RTF
-                    rtf_content
-                        .push_str(&format!("{{\\fs{}\\b {} \\b0}}\\par ", header_size, text));
-                }
+    fn close_table(&mut self) {
+        if let Some(table) = self.table.take() {
+            self.close_lists();
+            if !table.headers.is_empty() || !table.rows.is_empty() {
+                self.elements.push(Element::Table {
+                    headers: table.headers,
+                    rows: table.rows,
+                });
+            }
+        }
+    }
+
+    /// Block of its own in the middle of a paragraph, such as a table of contents
+    fn push_block(&mut self, reader: &Reader, element: Element) {
+        if !self.pieces.is_empty() {
+            self.end_paragraph(reader);
+        }
+        self.close_table();
+        self.close_lists();
+        self.elements.push(element);
+    }
+
+    /// Adds an item to the open list of its list override and level, a deeper level
+    /// starting a nested list that follows the item it belongs to
+    fn add_list_item(&mut self, id: i32, level: usize, list: Element, content: Vec<Element>) {
+        while self
+            .lists
+            .last()
+            .is_some_and(|open| open.level > level || (open.level == level && open.id != id))
+        {
+            self.close_list();
+        }
+        if self.lists.last().is_none_or(|open| open.level < level) {
+            self.lists.push(OpenList { id, level, list });
+        }
+        if let Some(OpenList {
+            list: Element::List { elements, .. },
+            ..
+        }) = self.lists.last_mut()
+        {
+            elements.push(ListItem {
+                element: item_element(content),
+            });
+        }
+    }
+
+    /// Ends the innermost open list, an item of its parent list or a block
+    fn close_list(&mut self) {
+        let Some(open) = self.lists.pop() else {
+            return;
+        };
+        match self.lists.last_mut() {
+            Some(OpenList {
+                list: Element::List {
+                    elements: items, ..
+                },
+                ..
+            }) => items.push(ListItem { element: open.list }),
+            _ => self.elements.push(open.list),
+        }
+    }
+
+    fn close_lists(&mut self) {
+        while !self.lists.is_empty() {
+            self.close_list();
+        }
+    }
+
+    /// Blocks of the part, the paragraph not ended by a `\par` included
+    fn finish(mut self, reader: &Reader) -> Vec<Element> {
+        if !self.pieces.is_empty() {
+            self.end_paragraph(reader);
+        }
+        if self
+            .table
+            .as_ref()
+            .is_some_and(|table| !table.row.is_empty())
+        {
+            self.end_row();
+        }
+        self.close_table();
+        self.close_lists();
+        self.elements
+    }
+}
+
+/// Reads an RTF document with the tables of its header
+struct Reader {
+    /// Encoding of the text without a font of its own, from `\ansicpg`
+    encoding: &'static Encoding,
+    default_font: Option<i32>,
+    fonts: HashMap<i32, Font>,
+    /// Paragraph styles by `\s` number
+    styles: HashMap<i32, Style>,
+    /// Character styles by `\cs` number
+    character_styles: HashMap<i32, Style>,
+    /// Numbering and start of the levels of the lists by `\listid`, none for a bulleted
+    /// level
+    lists: HashMap<i32, Vec<Option<(NumberingStyle, usize)>>>,
+    /// `\listid` of the lists of the list override table by `\ls` number
+    list_overrides: HashMap<i32, i32>,
+    metadata: Metadata,
+    header: Vec<Element>,
+    footer: Vec<Element>,
+}
+
+impl Default for Reader {
+    fn default() -> Self {
+        Reader {
+            encoding: encoding_rs::WINDOWS_1252,
+            default_font: None,
+            fonts: HashMap::new(),
+            styles: HashMap::new(),
+            character_styles: HashMap::new(),
+            lists: HashMap::new(),
+            list_overrides: HashMap::new(),
+            metadata: Metadata::default(),
+            header: vec![],
+            footer: vec![],
+        }
+    }
+}
+
+impl Reader {
+    fn is_monospace(&self, font: Option<i32>) -> bool {
+        font.or(self.default_font)
+            .and_then(|font| self.fonts.get(&font))
+            .is_some_and(|font| font.monospace)
+    }
+
+    fn run_format(&self, format: &CharFormat) -> RunFormat {
+        RunFormat {
+            strong: format.strong,
+            emphasis: format.emphasis,
+            strikethrough: format.strikethrough,
+            code: self.is_monospace(format.font),
+        }
+    }
+
+    /// Numbering and start of a level of a list of the list override table, none for a
+    /// bulleted level
+    fn list_level(&self, list: i32, level: usize) -> Option<(NumberingStyle, usize)> {
+        let id = self.list_overrides.get(&list)?;
+        self.lists.get(id)?.get(level).copied().flatten()
+    }
 
-                Element::Text { text, size } => {
-                    rtf_content.push_str(&format!("{{\\fs{} {}}} ", *size as i32 * 2, text));
+    /// Reads a group into the blocks, by its destination
+    fn group(&mut self, group: &[Token], format: CharFormat, blocks: &mut Blocks) {
+        let (name, ignorable) = destination(group);
+        match name.unwrap_or_default() {
+            "fonttbl" => self.font_table(group, &mut None),
+            "stylesheet" => self.stylesheet(group),
+            "listtable" => self.list_table(group),
+            "listoverridetable" => self.list_override_table(group),
+            "info" => self.info(group),
+            "userprops" => self.user_properties(group),
+            "header" | "headerl" | "headerr" | "headerf" | "footer" | "footerl" | "footerr"
+            | "footerf" => {
+                let mut band = Blocks::default();
+                self.content(group, CharFormat::default(), &mut band);
+                let band = band.finish(self);
+                let target = if name.is_some_and(|name| name.starts_with("header")) {
+                    &mut self.header
+                } else {
+                    &mut self.footer
+                };
+                if target.is_empty() {
+                    *target = band;
+                }
+            }
+            "footnote" => {
+                let mut note = Blocks::default();
+                self.content(group, format, &mut note);
+                let elements = note_elements(note.finish(self));
+                blocks
+                    .pieces
+                    .push((RunFormat::default(), Element::Footnote { elements }));
+            }
+            "field" => self.field(group, format, blocks),
+            "pict" => {
+                if let Some(image) = self.picture(group, &HashMap::new()) {
+                    blocks.pieces.push((RunFormat::default(), image));
+                }
+            }
+            // pictures of shapes, with their name and description
+            "shp" | "shppict" => {
+                let mut properties = vec![];
+                find_groups(group, "sp", &mut properties);
+                let properties = properties
+                    .into_iter()
+                    .filter_map(|property| self.shape_property(property))
+                    .collect();
+                let mut pictures = vec![];
+                find_groups(group, "pict", &mut pictures);
+                if let Some(image) = pictures
+                    .into_iter()
+                    .find_map(|picture| self.picture(picture, &properties))
+                {
+                    blocks.pieces.push((RunFormat::default(), image));
+                }
+            }
+            "bkmkstart" => {
+                let id = self.group_text(group).trim().to_string();
+                // bookmarks starting with `_` are hidden ones of the word processors
+                if !id.is_empty() && !id.starts_with('_') {
+                    blocks.pieces.push((
+                        RunFormat::default(),
+                        Element::Bookmark {
+                            id,
+                            title: String::new(),
+                        },
+                    ));
                 }
+            }
+            name if SKIPPED_DESTINATIONS.contains(&name) => {}
+            _ if ignorable => {}
+            _ => self.content(group, format, blocks),
+        }
+    }
 
-                Element::Paragraph { elements } => {
-                    for elem in elements {
-                        if let Element::Text { text, size } = elem {
-                            rtf_content.push_str(&format!("{{\\fs{} {}}}", *size as i32 * 2, text));
-                        }
+    /// Reads the tokens of a group into the blocks, the nested groups by their destination
+    fn content(&mut self, tokens: &[Token], format: CharFormat, blocks: &mut Blocks) {
+        let mut format = format;
+        // bytes of the text are decoded together, as a character may be several bytes
+        let mut text: Vec<u8> = vec![];
+        let mut skip = 0;
+        let mut high_surrogate: Option<u16> = None;
+        for token in tokens {
+            if let Token::Text(bytes) = token {
+                let skipped = skip.min(bytes.len());
+                skip -= skipped;
+                text.extend_from_slice(&bytes[skipped..]);
+                continue;
+            }
+            self.flush_text(&mut text, &format, blocks);
+            let Token::Word(word, parameter) = token else {
+                match token {
+                    Token::Group(group) => self.group(group, format, blocks),
+                    Token::Symbol(b'~') => self.push_text("\u{a0}".to_string(), &format, blocks),
+                    Token::Symbol(b'_') => self.push_text("\u{2011}".to_string(), &format, blocks),
+                    _ => {}
+                }
+                continue;
+            };
+            let on = parameter.is_none_or(|parameter| parameter != 0);
+            let paragraph = &mut blocks.paragraph;
+            match word.as_str() {
+                "ansicpg" => self.encoding = code_page_encoding(parameter.unwrap_or(1252)),
+                "deff" => self.default_font = *parameter,
+                "par" => blocks.end_paragraph(self),
+                "pard" => blocks.paragraph = ParagraphFormat::default(),
+                "s" => paragraph.style = parameter.unwrap_or(0),
+                "ls" => paragraph.list = parameter.unwrap_or(0),
+                "ilvl" => paragraph.list_level = parameter.unwrap_or(0).max(0) as usize,
+                "intbl" => paragraph.in_table = true,
+                // level 9 is the body text
+                "outlinelevel" => {
+                    paragraph.outline_level = parameter
+                        .filter(|level| (0..9).contains(level))
+                        .map(|level| level as u8 + 1)
+                }
+                "rtlpar" => paragraph.direction = TextDirection::Rtl,
+                "cell" => blocks.end_cell(self),
+                "row" => blocks.end_row(),
+                "trowd" => blocks.header_row = false,
+                "trhdr" => blocks.header_row = true,
+                "plain" => {
+                    format = CharFormat {
+                        unicode_skip: format.unicode_skip,
+                        ..Default::default()
                     }
-                    rtf_content.push_str("\\par ");
                 }
-
-                Element::List { elements, numbered } => {
-                    if *numbered {
-                        parent_indices.push(0); // Начинаем с 0 для нового списка
+                "b" => format.strong = on,
+                "i" => format.emphasis = on,
+                "strike" | "striked" => format.strikethrough = on,
+                "v" => format.hidden = on,
+                "f" => format.font = *parameter,
+                "cs" => {
+                    if let Some(style) = parameter.and_then(|id| self.character_styles.get(&id)) {
+                        format.strong |= style.strong;
+                        format.emphasis |= style.emphasis;
+                        format.strikethrough |= style.strikethrough;
+                        format.font = style.font.or(format.font);
                     }
-                    for list_item in elements {
-                        if *numbered {
-                            *parent_indices.last_mut().unwrap() += 1; // Увеличиваем текущий уровень нумерации
+                }
+                "uc" => format.unicode_skip = parameter.unwrap_or(1).max(0) as usize,
+                "u" => {
+                    // the parameter is a signed 16-bit number
+                    let unit = parameter.unwrap_or(0) as u16;
+                    skip = format.unicode_skip;
+                    let character = match (high_surrogate.take(), unit) {
+                        (_, 0xD800..=0xDBFF) => {
+                            high_surrogate = Some(unit);
+                            continue;
                         }
-
-                        // Если элемент является вложенным списком, уменьшаем индекс родительского уровня
-                        if let Element::List { .. } = list_item.element {
-                            if *numbered {
-                                *parent_indices.last_mut().unwrap() -= 1;
-                            }
+                        (Some(high), 0xDC00..=0xDFFF) => {
+                            char::decode_utf16([high, unit]).next().and_then(Result::ok)
                         }
-                        detect_element_in_list(
-                            &mut rtf_content,
-                            &list_item.element,
-                            *numbered,
-                            &mut parent_indices,
-                            0,
-                        );
-                    }
-                    if *numbered {
-                        parent_indices.pop(); // Удаляем уровень после обработки списка
+                        (_, unit) => char::from_u32(unit as u32),
+                    };
+                    if let Some(character) = character {
+                        self.push_text(character.to_string(), &format, blocks);
                     }
                 }
-
-                Element::Hyperlink {
-                    title,
-                    url,
-                    alt: _,
-                    size: _,
-                } => {
-                    rtf_content.push_str(&format!(
-                        "{{\\field{{\\*\\fldinst HYPERLINK \"{}\" }}{{\\fldrslt {{\\ul\\cf1 {}}}}}}}",
-                        url,
-                        title
-                    ));
-                    rtf_content.push_str("\\par ");
+                "line" => blocks
+                    .pieces
+                    .push((RunFormat::default(), Element::LineBreak)),
+                "chpgn" => blocks.pieces.push((
+                    RunFormat::default(),
+                    Element::Field {
+                        kind: FieldKind::PageNumber,
+                    },
+                )),
+                word => {
+                    let text = match word {
+                        "tab" => "\t",
+                        "emdash" => "\u{2014}",
+                        "endash" => "\u{2013}",
+                        "bullet" => "\u{2022}",
+                        "lquote" => "\u{2018}",
+                        "rquote" => "\u{2019}",
+                        "ldblquote" => "\u{201C}",
+                        "rdblquote" => "\u{201D}",
+                        "emspace" | "enspace" | "qmspace" => " ",
+                        _ => continue,
+                    };
+                    self.push_text(text.to_string(), &format, blocks);
                 }
+            }
+        }
+        self.flush_text(&mut text, &format, blocks);
+    }
 
-                Element::Image(image) => {
-                    let image_bytes = image.bytes();
-
-                    let image_size = re_size_picture(image_bytes);
+    /// Adds the bytes of text read so far, decoded in the code page of their font
+    fn flush_text(&self, text: &mut Vec<u8>, format: &CharFormat, blocks: &mut Blocks) {
+        if text.is_empty() {
+            return;
+        }
+        let encoding = format
+            .font
+            .or(self.default_font)
+            .and_then(|font| self.fonts.get(&font))
+            .and_then(|font| font.encoding)
+            .unwrap_or(self.encoding);
+        let decoded = encoding.decode_without_bom_handling(text).0.into_owned();
+        text.clear();
+        self.push_text(decoded, format, blocks);
+    }
 
-                    let image = image_bytes
-                        .iter()
-                        .map(|b| format!("{:02X}", b))
-                        .collect::<String>();
+    fn push_text(&self, text: String, format: &CharFormat, blocks: &mut Blocks) {
+        if format.hidden {
+            return;
+        }
+        let run_format = self.run_format(format);
+        // code is not styled
+        let (run_format, element) = if run_format.code {
+            (
+                RunFormat {
+                    code: false,
+                    ..run_format
+                },
+                Element::InlineCode { text },
+            )
+        } else {
+            (
+                run_format,
+                Element::Text {
+                    text,
+                    size: TEXT_SIZE,
+                },
+            )
+        };
+        blocks.pieces.push((run_format, element));
+    }
 
-                    rtf_content.push_str(&format!(
-                        "{{{{\\pict\\jpegblip\\picwgoal{}\\pichgoal{} {} }}}}",
-                        image_size.output_width, image_size.output_height, image
-                    ));
-                    rtf_content.push_str("\\par ");
+    /// Text of a group and of its nested groups, without the groups to be skipped
+    fn group_text(&self, tokens: &[Token]) -> String {
+        let mut text = String::new();
+        let mut bytes: Vec<u8> = vec![];
+        let mut skip = 0;
+        for token in tokens {
+            match token {
+                Token::Text(content) => {
+                    let skipped = skip.min(content.len());
+                    skip -= skipped;
+                    bytes.extend_from_slice(&content[skipped..]);
+                    continue;
                 }
+                Token::Group(group) if !destination(group).1 => {
+                    text.push_str(&self.group_text(group));
+                }
+                Token::Word(word, parameter) if word == "u" => {
+                    text.push_str(&self.encoding.decode_without_bom_handling(&bytes).0);
+                    bytes.clear();
+                    if let Some(character) = char::from_u32(parameter.unwrap_or(0) as u16 as u32) {
+                        text.push(character);
+                    }
+                    skip = 1;
+                    continue;
+                }
+                Token::Symbol(b'~') => text.push('\u{a0}'),
+                _ => {}
+            }
+        }
+        text.push_str(&self.encoding.decode_without_bom_handling(&bytes).0);
+        text
+    }
 
-                Element::Table { headers, rows } => {
-                    let column_widths = calculate_column_widths(headers, rows);
-                    let mut current_x = 0;
-
-                    rtf_content.push_str("\\trowd\\trgaph108\\trleft-108");
-                    for (_, width) in column_widths.iter().enumerate() {
-                        current_x += width;
-                        rtf_content.push_str("\\clbrdrt\\brdrs\\brdrw10\\clbrdrl\\brdrs\\brdrw10\\clbrdrb\\brdrs\\brdrw10\\clbrdrr\\brdrs\\brdrw10");
-                        rtf_content.push_str(&format!("\\cellx{}", current_x));
+    /// Fonts of the font table, each font in a group or the fonts one after the other
+    fn font_table(&mut self, tokens: &[Token], current: &mut Option<(i32, Font)>) {
+        for token in tokens {
+            match token {
+                Token::Group(group) if !destination(group).1 => {
+                    let mut font = None;
+                    self.font_table(group, &mut font);
+                    if let Some((id, font)) = font {
+                        self.fonts.insert(id, font);
+                    }
+                }
+                Token::Word(word, parameter) => match (word.as_str(), *parameter) {
+                    ("f", Some(id)) => {
+                        if let Some((id, font)) = current.replace((id, Font::default())) {
+                            self.fonts.insert(id, font);
+                        }
+                    }
+                    ("fcharset", Some(charset)) => {
+                        if let Some((_, font)) = current {
+                            font.encoding = charset_code_page(charset).map(code_page_encoding);
+                        }
+                    }
+                    ("fmodern", _) | ("fprq", Some(1)) => {
+                        if let Some((_, font)) = current {
+                            font.monospace = true;
+                        }
                     }
-                    rtf_content.push_str("\\intbl");
-
-                    for header in headers {
-                        if let Element::Text { text, size } = &header.element {
-                            rtf_content.push_str(&format!(
-                                "{{\\fs{} {}}}\\cell",
-                                *size as i32 * 2,
-                                text
-                            ));
+                    _ => {}
+                },
+                Token::Text(name) => {
+                    if let Some((_, font)) = current {
+                        let name = String::from_utf8_lossy(name).to_lowercase();
+                        if ["mono", "courier", "consolas", "menlo", "monaco"]
+                            .iter()
+                            .any(|monospace| name.contains(monospace))
+                        {
+                            font.monospace = true;
                         }
                     }
-                    rtf_content.push_str("\\row");
-
-                    for row in rows {
-                        for cell in &row.cells {
-                            if let Element::Text { text, size } = &cell.element {
-                                rtf_content.push_str(&format!(
-                                    "{{\\fs{} {}}}\\cell",
-                                    *size as i32 * 2,
-                                    text
-                                ));
+                }
+                _ => {}
+            }
+        }
+        if tokens
+            .first()
+            .is_some_and(|token| matches!(token, Token::Word(word, _) if word == "fonttbl"))
+        {
+            if let Some((id, font)) = current.take() {
+                self.fonts.insert(id, font);
+            }
+        }
+    }
+
+    fn stylesheet(&mut self, tokens: &[Token]) {
+        for token in tokens {
+            let Token::Group(group) = token else {
+                continue;
+            };
+            let mut style = Style {
+                name: self
+                    .group_text(group)
+                    .trim()
+                    .trim_end_matches(';')
+                    .trim()
+                    .to_string(),
+                ..Default::default()
+            };
+            let mut number = (false, 0);
+            for token in group {
+                let Token::Word(word, parameter) = token else {
+                    continue;
+                };
+                let on = parameter.is_none_or(|parameter| parameter != 0);
+                match word.as_str() {
+                    "s" => number = (false, parameter.unwrap_or(0)),
+                    "cs" => number = (true, parameter.unwrap_or(0)),
+                    "b" => style.strong = on,
+                    "i" => style.emphasis = on,
+                    "strike" | "striked" => style.strikethrough = on,
+                    "f" => style.font = *parameter,
+                    "outlinelevel" => {
+                        style.outline_level = parameter
+                            .filter(|level| (0..9).contains(level))
+                            .map(|level| level as u8 + 1)
+                    }
+                    _ => {}
+                }
+            }
+            match number {
+                (true, number) => self.character_styles.insert(number, style),
+                (false, number) => self.styles.insert(number, style),
+            };
+        }
+    }
+
+    /// Numberings of the levels of the lists of the list table
+    fn list_table(&mut self, tokens: &[Token]) {
+        let mut lists = vec![];
+        find_groups(tokens, "list", &mut lists);
+        for list in lists {
+            let mut id = None;
+            let mut levels = vec![];
+            for token in list {
+                match token {
+                    Token::Word(word, parameter) if word == "listid" => id = *parameter,
+                    Token::Group(level) if destination(level).0 == Some("listlevel") => {
+                        let mut format = 0;
+                        let mut start = 1;
+                        for token in level {
+                            match token {
+                                Token::Word(word, Some(parameter))
+                                    if word == "levelnfc" || word == "levelnfcn" =>
+                                {
+                                    format = *parameter
+                                }
+                                Token::Word(word, Some(parameter)) if word == "levelstartat" => {
+                                    start = (*parameter).max(0) as usize
+                                }
+                                _ => {}
                             }
                         }
-                        rtf_content.push_str("\\row");
+                        levels.push(
+                            match format {
+                                0 => Some(NumberingStyle::Decimal),
+                                1 => Some(NumberingStyle::UpperRoman),
+                                2 => Some(NumberingStyle::LowerRoman),
+                                3 => Some(NumberingStyle::UpperAlpha),
+                                4 => Some(NumberingStyle::LowerAlpha),
+                                // bullets (23) and levels without a number (255)
+                                _ => None,
+                            }
+                            .map(|numbering| (numbering, start)),
+                        );
                     }
+                    _ => {}
                 }
+            }
+            if let Some(id) = id {
+                self.lists.insert(id, levels);
+            }
+        }
+    }
+
+    fn list_override_table(&mut self, tokens: &[Token]) {
+        let mut overrides = vec![];
+        find_groups(tokens, "listoverride", &mut overrides);
+        for list_override in overrides {
+            let mut id = None;
+            let mut number = None;
+            for token in list_override {
+                match token {
+                    Token::Word(word, parameter) if word == "listid" => id = *parameter,
+                    Token::Word(word, parameter) if word == "ls" => number = *parameter,
+                    _ => {}
+                }
+            }
+            if let (Some(id), Some(number)) = (id, number) {
+                self.list_overrides.insert(number, id);
+            }
+        }
+    }
+
+    /// Metadata of the information group, the subject being the description when there
+    /// are no comments
+    fn info(&mut self, tokens: &[Token]) {
+        let mut subject = None;
+        for token in tokens {
+            let Token::Group(group) = token else {
+                continue;
+            };
+            let text = || Some(self.group_text(group).trim().to_string());
+            match destination(group).0.unwrap_or_default() {
+                "title" => self.metadata.title = text(),
+                "author" => self.metadata.author = text(),
+                "doccomm" => self.metadata.description = text(),
+                "subject" => subject = text(),
+                "keywords" => {
+                    self.metadata.keywords = self
+                        .group_text(group)
+                        .split([',', ';'])
+                        .map(str::trim)
+                        .filter(|keyword| !keyword.is_empty())
+                        .map(str::to_string)
+                        .collect()
+                }
+                "creatim" => {
+                    let part = |name: &str| {
+                        group.iter().find_map(|token| match token {
+                            Token::Word(word, parameter) if word == name => *parameter,
+                            _ => None,
+                        })
+                    };
+                    if let (Some(year), Some(month), Some(day)) =
+                        (part("yr"), part("mo"), part("dy"))
+                    {
+                        if year > 0 {
+                            self.metadata.date = Some(format!("{year:04}-{month:02}-{day:02}"));
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        if self.metadata.description.is_none() {
+            self.metadata.description = subject;
+        }
+    }
 
-                _other_element => {
-                    warn!("Unknown element in list: {:?}", element);
+    /// Custom metadata of the user properties, a name followed by its value
+    fn user_properties(&mut self, tokens: &[Token]) {
+        let mut name = None;
+        for token in tokens {
+            let Token::Group(group) = token else {
+                continue;
+            };
+            match destination(group).0 {
+                Some("propname") => name = Some(self.group_text(group).trim().to_string()),
+                Some("staticval") => {
+                    if let Some(name) = name.take() {
+                        self.metadata
+                            .custom
+                            .insert(name, self.group_text(group).trim().to_string());
+                    }
                 }
+                _ => {}
             }
         }
+    }
 
-        rtf_content.push_str("}");
+    /// Field by its instruction: links, page numbers, a table of contents or else its
+    /// result
+    fn field(&mut self, group: &[Token], format: CharFormat, blocks: &mut Blocks) {
+        let part = |name: &str| {
+            group.iter().find_map(|token| match token {
+                Token::Group(part) if destination(part).0 == Some(name) => Some(part.as_slice()),
+                _ => None,
+            })
+        };
+        let instruction = part("fldinst")
+            .map(|instruction| self.group_text(instruction))
+            .unwrap_or_default();
+        let result = part("fldrslt").unwrap_or_default();
+        let arguments = field_arguments(&instruction);
+        let field = |kind| Element::Field { kind };
+        let element = match arguments.first().map(|name| name.to_uppercase()).as_deref() {
+            Some("HYPERLINK") => {
+                let mut url = String::new();
+                let mut alt = String::new();
+                let mut arguments = arguments.iter().skip(1);
+                while let Some(argument) = arguments.next() {
+                    match argument.as_str() {
+                        r"\l" => {
+                            if let Some(anchor) = arguments.next() {
+                                url = format!("#{anchor}");
+                            }
+                        }
+                        r"\o" => alt = arguments.next().cloned().unwrap_or_default(),
+                        argument if argument.starts_with('\\') => {}
+                        argument if url.is_empty() => url = argument.to_string(),
+                        _ => {}
+                    }
+                }
+                let mut content = Blocks::default();
+                self.content(result, CharFormat::default(), &mut content);
+                Element::Hyperlink {
+                    elements: inline_elements(content.pieces),
+                    url,
+                    alt,
+                    size: TEXT_SIZE,
+                }
+            }
+            Some("PAGE") => field(FieldKind::PageNumber),
+            Some("NUMPAGES") | Some("SECTIONPAGES") => field(FieldKind::TotalPages),
+            Some("DATE") | Some("CREATEDATE") => field(FieldKind::Date),
+            Some("TITLE") => field(FieldKind::DocumentTitle),
+            Some("TOC") => {
+                let max_level = arguments
+                    .iter()
+                    .skip_while(|argument| *argument != r"\o")
+                    .nth(1)
+                    .and_then(|levels| levels.split('-').nth(1))
+                    .and_then(|level| level.trim().parse().ok())
+                    .unwrap_or(3);
+                blocks.push_block(self, Element::TableOfContents { max_level });
+                return;
+            }
+            _ => {
+                self.content(result, format, blocks);
+                return;
+            }
+        };
+        blocks.pieces.push((RunFormat::default(), element));
+    }
 
-        Ok(bytes::Bytes::from(rtf_content.into_bytes()))
+    /// Value of a `wzName` or `wzDescription` shape property, the name and the description
+    /// of a picture
+    fn shape_property(&self, property: &[Token]) -> Option<(String, String)> {
+        let part = |name: &str| {
+            property.iter().find_map(|token| match token {
+                Token::Group(part) if destination(part).0 == Some(name) => Some(part),
+                _ => None,
+            })
+        };
+        let name = self.group_text(part("sn")?).trim().to_string();
+        if name != "wzName" && name != "wzDescription" {
+            return None;
+        }
+        Some((name, self.group_text(part("sv")?).trim().to_string()))
     }
-}
 
-fn calculate_column_widths(headers: &Vec<TableHeader>, rows: &Vec<TableRow>) -> Vec<i32> {
-    let max_width = 9700;
-    let mut column_widths: Vec<i32> = headers.iter().map(|_| 0).collect();
-    let mut column_content_lengths: Vec<usize> = headers.iter().map(|_| 0).collect();
+    /// Image of a PNG or JPEG picture, its size in pixels
+    fn picture(&self, group: &[Token], properties: &HashMap<String, String>) -> Option<Element> {
+        let mut image_type = None;
+        let mut scale = (100.0, 100.0);
+        let mut goal = (None, None);
+        let mut pixels = (None, None);
+        let mut hex = vec![];
+        let mut binary = None;
+        let mut properties = properties.clone();
+        for token in group {
+            match token {
+                Token::Word(word, parameter) => {
+                    let value = parameter.map(|parameter| parameter as f32);
+                    match word.as_str() {
+                        "pngblip" => image_type = Some("png"),
+                        "jpegblip" => image_type = Some("jpeg"),
+                        "emfblip" | "wmetafile" | "macpict" | "pmmetafile" | "dibitmap"
+                        | "wbitmap" => image_type = image_type.or(Some("")),
+                        "picscalex" => scale.0 = value.unwrap_or(100.0),
+                        "picscaley" => scale.1 = value.unwrap_or(100.0),
+                        "picwgoal" => goal.0 = value,
+                        "pichgoal" => goal.1 = value,
+                        "picw" => pixels.0 = value,
+                        "pich" => pixels.1 = value,
+                        _ => {}
+                    }
+                }
+                Token::Text(text) => hex.extend(text.iter().filter(|c| c.is_ascii_hexdigit())),
+                Token::Binary(data) => binary = Some(data.clone()),
+                Token::Group(picture_properties)
+                    if destination(picture_properties).0 == Some("picprop") =>
+                {
+                    let mut found = vec![];
+                    find_groups(picture_properties, "sp", &mut found);
+                    properties.extend(
+                        found
+                            .into_iter()
+                            .filter_map(|property| self.shape_property(property)),
+                    );
+                }
+                _ => {}
+            }
+        }
+        let image_type = image_type.filter(|image_type| !image_type.is_empty());
+        let Some(image_type) = image_type else {
+            warn!("Picture left out, only PNG and JPEG pictures are read");
+            return None;
+        };
+        let bytes = binary.unwrap_or_else(|| {
+            hex.chunks_exact(2)
+                .filter_map(|pair| {
+                    u8::from_str_radix(std::str::from_utf8(pair).unwrap_or_default(), 16).ok()
+                })
+                .collect()
+        });
+        if bytes.is_empty() {
+            return None;
+        }
+        let size = |goal: Option<f32>, scale: f32, pixels: Option<f32>| {
+            goal.map(|goal| goal * scale / 100.0 / TWIPS_PER_PIXEL)
+                .or(pixels)
+                .filter(|size| *size > 0.0)
+                .map(|size| format!("{}", size.round()))
+        };
+        let property = |name: &str| properties.get(name).cloned().unwrap_or_default();
+        Some(Element::Image(ImageData::new(
+            Bytes::from(bytes),
+            property("wzName"),
+            property("wzDescription"),
+            image_type.to_string(),
+            String::new(),
+            ImageDimension {
+                width: size(goal.0, scale.0, pixels.0),
+                height: size(goal.1, scale.1, pixels.1),
+            },
+        )))
+    }
+}
 
-    for (i, header) in headers.iter().enumerate() {
-        if let Element::Text { text, .. } = &header.element {
-            column_content_lengths[i] = text.len().max(column_content_lengths[i]);
+/// Arguments of a field instruction, quoted arguments without their quotes
+fn field_arguments(instruction: &str) -> Vec<String> {
+    let mut arguments = vec![];
+    let mut chars = instruction.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            '"' => {
+                let mut argument = String::new();
+                for c in chars.by_ref() {
+                    if c == '"' {
+                        break;
+                    }
+                    argument.push(c);
+                }
+                arguments.push(argument);
+            }
+            c => {
+                let mut argument = String::from(c);
+                while let Some(c) = chars.next_if(|c| !c.is_whitespace() && *c != '"') {
+                    argument.push(c);
+                }
+                arguments.push(argument);
+            }
         }
     }
+    arguments
+}
 
-    for row in rows {
-        for (i, cell) in row.cells.iter().enumerate() {
-            if let Element::Text { text, .. } = &cell.element {
-                column_content_lengths[i] = text.len().max(column_content_lengths[i]);
+/// Inline elements of a paragraph, the runs of the same format merged
+fn inline_elements(pieces: Vec<(RunFormat, Element)>) -> Vec<Element> {
+    let mut elements = vec![];
+    let mut group: Option<(RunFormat, Vec<Element>)> = None;
+    for (format, element) in pieces {
+        match &mut group {
+            Some((group_format, group_elements)) if *group_format == format => {
+                match (group_elements.last_mut(), element) {
+                    (Some(Element::Text { text, .. }), Element::Text { text: next, .. })
+                    | (Some(Element::InlineCode { text }), Element::InlineCode { text: next }) => {
+                        text.push_str(&next)
+                    }
+                    (_, element) => group_elements.push(element),
+                }
+            }
+            _ => {
+                if let Some((group_format, group_elements)) = group.replace((format, vec![element]))
+                {
+                    elements.extend(group_format.apply(group_elements));
+                }
             }
         }
     }
+    if let Some((group_format, group_elements)) = group {
+        elements.extend(group_format.apply(group_elements));
+    }
+    merge_styled(elements)
+}
+
+/// Content of a note, the elements of its paragraph when it has only one, without the space
+/// after its reference mark
+fn note_elements(elements: Vec<Element>) -> Vec<Element> {
+    let mut elements = match <[Element; 1]>::try_from(elements) {
+        Ok([Element::Paragraph { elements, .. }]) => elements,
+        Ok([element]) => vec![element],
+        Err(elements) => elements,
+    };
+    if let Some(Element::Text { text, .. }) = elements.first_mut() {
+        *text = text.trim_start().to_string();
+    }
+    elements
+}
 
-    let total_length: usize = column_content_lengths.iter().sum();
-    let total_length = total_length as i32;
+// endregion: ---reader
 
-    if total_length == 0 {
-        return column_widths;
+impl TransformerTrait for Transformer {
+    fn parse(document: &Bytes) -> crate::Result<Document> {
+        Transformer::parse_bytes(document).map_err(crate::Error::parse_error)
+    }
+
+    fn generate(document: &Document) -> crate::Result<Bytes> {
+        Transformer::generate_bytes(document).map_err(crate::Error::generation_error)
     }
+}
 
-    for (i, &length) in column_content_lengths.iter().enumerate() {
-        column_widths[i] = (length as f32 / total_length as f32 * max_width as f32) as i32;
+impl Transformer {
+    fn parse_bytes(document: &Bytes) -> anyhow::Result<Document> {
+        let tokens = tokenize(document)?;
+        let mut reader = Reader::default();
+        let mut body = Blocks::default();
+        reader.content(&tokens, CharFormat::default(), &mut body);
+        let mut document = Document::new(body.finish(&reader));
+        if !reader.header.is_empty() {
+            document
+                .bands
+                .insert(0, Band::PageHeader(std::mem::take(&mut reader.header)));
+        }
+        if !reader.footer.is_empty() {
+            document
+                .bands
+                .push(Band::PageFooter(std::mem::take(&mut reader.footer)));
+        }
+        document.metadata = reader.metadata;
+        Ok(document)
     }
 
-    column_widths
+    fn generate_bytes(document: &Document) -> anyhow::Result<Bytes> {
+        let document = &document
+            .resolve_fields()
+            .without_containers()
+            .without_media()
+            .without_math()
+            .without_drawings()
+            .without_attributes();
+        let mut writer = Writer::new(document);
+        writer.content(document.get_page_header(), HEADER_STYLE);
+        let header = std::mem::take(&mut writer.rtf);
+        writer.content(document.get_page_footer(), FOOTER_STYLE);
+        let footer = std::mem::take(&mut writer.rtf);
+        for band in &document.bands {
+            if !matches!(band, Band::PageHeader(_) | Band::PageFooter(_)) {
+                for element in band.elements() {
+                    writer.block(element);
+                }
+            }
+        }
+
+        let mut rtf = format!(
+            "{{\\rtf1\\ansi\\ansicpg1252\\deff0\\uc1\n{FONT_TABLE}\n{COLOR_TABLE}\n{}\n",
+            stylesheet()
+        );
+        if !writer.lists.is_empty() {
+            rtf.push_str(r"{\*\listtable");
+            for list in &writer.lists {
+                rtf.push_str(list);
+            }
+            rtf.push_str(r"}{\*\listoverridetable");
+            for id in 1..=writer.lists.len() {
+                let _ = write!(
+                    rtf,
+                    r"{{\listoverride\listid{id}\listoverridecount0\ls{id}}}"
+                );
+            }
+            rtf.push_str("}\n");
+        }
+        rtf.push_str(&info(&document.metadata));
+
+        let page = document.page_format.dimensions();
+        let (width, height) = match document.orientation {
+            PageOrientation::Portrait => (page.page_width, page.page_height),
+            PageOrientation::Landscape => (page.page_height, page.page_width),
+        };
+        let _ = write!(
+            rtf,
+            "\n\\paperw{}\\paperh{}\\margl{}\\margr{}\\margt{}\\margb{}",
+            twips(width),
+            twips(height),
+            twips(page.page_margin_left),
+            twips(page.page_margin_right),
+            twips(page.page_margin_top),
+            twips(page.page_margin_bottom)
+        );
+        if document.orientation == PageOrientation::Landscape {
+            rtf.push_str(r"\landscape");
+        }
+        rtf.push_str("\\sectd\n");
+        for (destination, band) in [("header", header), ("footer", footer)] {
+            if !band.is_empty() {
+                let _ = write!(rtf, "{{\\{destination}\n{band}}}\n");
+            }
+        }
+        rtf.push_str(&writer.rtf);
+        rtf.push('}');
+        Ok(Bytes::from(rtf))
+    }
 }
 
 #[cfg(test)]
-
 mod tests {
+    use super::*;
+    use crate::core::tests::init_logger;
     use crate::core::{disk_image_loader, TransformerWithImageLoaderSaverTrait};
     use crate::markdown;
-    use bytes::Bytes;
 
-    use super::*;
     #[test]
-    fn test() -> anyhow::Result<()> {
+    fn test_round_trip() -> anyhow::Result<()> {
+        init_logger();
+        let markdown = "# Report\n\n## Part\n\n\
+            Some **bold *and italic*** text, `code`, «quotes» and [the site](https://example.com).[^1]\n\n\
+            - One\n  - Nested\n- Two\n\n3. Third\n4. Fourth\n\n\
+            | Name | Value |\n|------|-------|\n| a | ~~1~~ |\n\n\
+            ```\nfn main() {\n    run();\n}\n```\n\n\
+            [^1]: A note.\n";
+        let mut document = markdown::Transformer::parse(&Bytes::from(markdown))?;
+        let png = Bytes::from(std::fs::read("test/data/small.png")?);
+        document.bands = vec![Band::Detail(
+            document
+                .get_all_elements()
+                .into_iter()
+                .cloned()
+                .chain([Element::Image(ImageData::new(
+                    png.clone(),
+                    "Logo".to_string(),
+                    "A small picture".to_string(),
+                    "png".to_string(),
+                    String::new(),
+                    ImageDimension {
+                        width: Some("40".to_string()),
+                        height: Some("20".to_string()),
+                    },
+                ))])
+                .collect(),
+        )];
+
+        let generated = Transformer::generate(&document)?;
+        assert!(generated.is_ascii());
+        let parsed = Transformer::parse(&generated)?;
+        let elements = parsed.get_all_elements();
+        let [report, part, paragraph, bullets, numbered, table, code, image] = elements.as_slice()
+        else {
+            panic!("Unexpected elements {:#?}", elements);
+        };
+        assert_eq!(
+            *report,
+            &Element::Header {
+                level: 1,
+                text: "Report".to_string()
+            }
+        );
+        assert!(matches!(part, Element::Header { level: 2, .. }));
+
+        let Element::Paragraph { elements, .. } = paragraph else {
+            panic!("Unexpected paragraph {:?}", paragraph);
+        };
+        assert_eq!(
+            elements[1],
+            Element::Styled {
+                style: TextStyle::Strong,
+                elements: vec![
+                    Element::Text {
+                        text: "bold ".to_string(),
+                        size: TEXT_SIZE
+                    },
+                    Element::Styled {
+                        style: TextStyle::Emphasis,
+                        elements: vec![Element::Text {
+                            text: "and italic".to_string(),
+                            size: TEXT_SIZE
+                        }]
+                    }
+                ]
+            }
+        );
+        assert!(elements.contains(&Element::InlineCode {
+            text: "code".to_string()
+        }));
+        assert!(paragraph.plain_text().contains("«quotes»"));
+        assert!(elements.iter().any(|element| matches!(
+            element,
+            Element::Hyperlink { url, .. }
+                if url == "https://example.com" && element.plain_text() == "the site"
+        )));
+        assert!(elements.iter().any(|element| matches!(
+            element,
+            Element::Footnote { elements } if elements[0].plain_text() == "A note."
+        )));
+
+        let Element::List {
+            elements: items,
+            numbered: false,
+            ..
+        } = bullets
+        else {
+            panic!("Unexpected list {:?}", bullets);
+        };
+        assert_eq!(items.len(), 3);
+        assert!(matches!(
+            &items[1].element,
+            Element::List { elements, numbered: false, .. }
+                if elements[0].element.plain_text() == "Nested"
+        ));
+        assert!(matches!(
+            numbered,
+            Element::List {
+                numbered: true,
+                start: 3,
+                numbering: NumberingStyle::Decimal,
+                ..
+            }
+        ));
+
+        let Element::Table { headers, rows } = table else {
+            panic!("Unexpected table {:?}", table);
+        };
+        assert_eq!(headers.len(), 2);
+        assert_eq!(
+            headers[0].element,
+            Element::Text {
+                text: "Name".to_string(),
+                size: TEXT_SIZE
+            }
+        );
+        assert_eq!(rows.len(), 1);
+        assert!(matches!(
+            &rows[0].cells[1].element,
+            Element::Styled {
+                style: TextStyle::Strikethrough,
+                ..
+            }
+        ));
+
+        assert_eq!(
+            *code,
+            &Element::CodeBlock {
+                info: String::new(),
+                code: "fn main() {\n    run();\n}".to_string()
+            }
+        );
+
+        let Element::Image(image) = image else {
+            panic!("Unexpected image {:?}", image);
+        };
+        assert_eq!(image.bytes(), &png);
+        assert_eq!((image.title(), image.alt()), ("Logo", "A small picture"));
+        assert_eq!(image.size().width.as_deref(), Some("40"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_metadata_and_page_bands() -> anyhow::Result<()> {
+        let mut document = Document::new(vec![Element::Text {
+            text: "Body".to_string(),
+            size: 12,
+        }]);
+        document.bands.push(Band::PageFooter(vec![
+            Element::Text {
+                text: "Page ".to_string(),
+                size: 12,
+            },
+            Element::Field {
+                kind: FieldKind::PageNumber,
+            },
+        ]));
+        document.metadata = Metadata {
+            title: Some("Q&A {draft}".to_string()),
+            author: Some("Ada".to_string()),
+            date: Some("2024-05-01".to_string()),
+            description: None,
+            keywords: vec!["one".to_string(), "two".to_string()],
+            custom: [("reviewer".to_string(), "Bob".to_string())].into(),
+        };
+        document.orientation = PageOrientation::Landscape;
+
+        let generated = Transformer::generate(&document)?;
+        let rtf = std::str::from_utf8(&generated)?;
+        assert!(rtf.contains(r"\paperw16838\paperh11906"));
+
+        let parsed = Transformer::parse(&generated)?;
+        assert_eq!(parsed.metadata, document.metadata);
+        assert_eq!(
+            parsed.get_page_footer(),
+            vec![&Element::Paragraph {
+                elements: vec![
+                    Element::Text {
+                        text: "Page ".to_string(),
+                        size: TEXT_SIZE
+                    },
+                    Element::Field {
+                        kind: FieldKind::PageNumber
+                    }
+                ],
+                direction: Default::default()
+            }]
+        );
+        assert_eq!(parsed.get_detail().len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse() -> anyhow::Result<()> {
+        let document = Bytes::from(std::fs::read("test/data/document.rtf")?);
+        let parsed = Transformer::parse(&document)?;
+        let elements = parsed.get_all_elements();
+        assert_eq!(
+            elements[..2],
+            [
+                &Element::Header {
+                    level: 1,
+                    text: "Header 1.".to_string()
+                },
+                &Element::Paragraph {
+                    elements: vec![Element::Text {
+                        text: "Text text".to_string(),
+                        size: TEXT_SIZE
+                    }],
+                    direction: Default::default()
+                }
+            ]
+        );
+        assert!(elements.contains(&&Element::Header {
+            level: 2,
+            text: "Header 1.2.".to_string()
+        }));
+
+        let Some(Element::Table { headers, rows }) = elements
+            .iter()
+            .find(|element| matches!(element, Element::Table { .. }))
+        else {
+            panic!("No table in {:#?}", elements);
+        };
+        assert!(headers.is_empty());
+        assert_eq!(rows.len(), 4);
+        assert_eq!(rows[0].cells[1].element.plain_text(), "TableHeader2");
+
+        let lists: Vec<_> = elements
+            .iter()
+            .filter_map(|element| match element {
+                Element::List {
+                    elements, numbered, ..
+                } => Some((elements.len(), *numbered)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(lists, [(3, false), (3, true)]);
+
+        assert!(elements.iter().any(|element| matches!(
+            element,
+            Element::Paragraph { elements, .. } if matches!(
+                &elements[0],
+                Element::Hyperlink { url, .. } if url == "https://google.com/"
+            )
+        )));
+        assert!(elements
+            .iter()
+            .any(|element| matches!(element, Element::Image(image) if image.bytes().starts_with(b"\x89PNG"))));
+        assert_eq!(parsed.metadata.date.as_deref(), Some("2024-04-29"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_generate_from_markdown() -> anyhow::Result<()> {
         let document = std::fs::read("test/data/document.md")?;
-        let documents_bytes = Bytes::from(document);
         let parsed = markdown::Transformer::parse_with_loader(
-            &documents_bytes,
+            &Bytes::from(document),
             disk_image_loader("test/data"),
         )?;
-        let generated_result = crate::rtf::Transformer::generate(&parsed)?;
-        std::fs::write("test/data/document_from_rtf.rtf", generated_result)?;
-
+        let generated = Transformer::generate(&parsed)?;
+        let headers = |document: &Document| {
+            document
+                .get_all_elements()
+                .into_iter()
+                .filter(|element| matches!(element, Element::Header { .. }))
+                .count()
+        };
+        assert_eq!(headers(&Transformer::parse(&generated)?), headers(&parsed));
         Ok(())
     }
 }
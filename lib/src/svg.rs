@@ -0,0 +1,150 @@
+//! Pages of a document as SVG images, laid out as its PDF would be with the PDF options
+//!
+//! Text is drawn as the outlines of its glyphs, so that the pages look the same in a browser
+//! without the fonts, on the white background of the page. The generated file holds all the
+//! pages one below the other; the single pages are returned by `generate_pages`, or saved
+//! with an explicit image saver as `page1.svg`, `page2.svg`... SVG pages are generated only.
+
+use bytes::Bytes;
+use typst::layout::Abs;
+use typst::visualize::{Color, Paint};
+
+use crate::core::{Document, GenerateOptions, PdfOptions, TransformerTrait};
+
+pub struct Transformer;
+
+/// Space around and between the pages of the generated file, in points
+const PAGE_SPACING: f64 = 10.0;
+
+impl TransformerTrait for Transformer {
+    fn parse(_document: &Bytes) -> crate::Result<Document> {
+        Err(crate::Error::parse_error(anyhow::anyhow!(
+            "SVG pages are generated only"
+        )))
+    }
+
+    fn generate(document: &Document) -> crate::Result<Bytes> {
+        Transformer::generate_with_saver(document, &PdfOptions::default(), |_, _| Ok(()))
+    }
+
+    /// Uses the PDF options, the pages being the ones of the PDF
+    fn generate_with_options(
+        document: &Document,
+        options: &GenerateOptions,
+    ) -> crate::Result<Bytes> {
+        Transformer::generate_with_saver(document, &options.pdf, |_, _| Ok(()))
+    }
+}
+
+impl Transformer {
+    /// SVG image of each page of the document
+    pub fn generate_pages(document: &Document, options: &PdfOptions) -> crate::Result<Vec<Bytes>> {
+        let layout =
+            Transformer::layout(document, options).map_err(crate::Error::generation_error)?;
+        Ok(layout
+            .pages
+            .iter()
+            .map(|page| Bytes::from(typst_svg::svg(&page.frame)))
+            .collect())
+    }
+
+    /// SVG image of all the pages of the document, each page also saved with `page_saver`
+    /// as `page<number>.svg`
+    pub fn generate_with_saver<F>(
+        document: &Document,
+        options: &PdfOptions,
+        page_saver: F,
+    ) -> crate::Result<Bytes>
+    where
+        F: Fn(&Bytes, &str) -> anyhow::Result<()>,
+    {
+        Transformer::generate_bytes(document, options, page_saver)
+            .map_err(crate::Error::generation_error)
+    }
+
+    fn generate_bytes<F>(
+        document: &Document,
+        options: &PdfOptions,
+        page_saver: F,
+    ) -> anyhow::Result<Bytes>
+    where
+        F: Fn(&Bytes, &str) -> anyhow::Result<()>,
+    {
+        let layout = Transformer::layout(document, options)?;
+        for (index, page) in layout.pages.iter().enumerate() {
+            let svg = Bytes::from(typst_svg::svg(&page.frame));
+            page_saver(&svg, &format!("page{}.svg", index + 1))?;
+        }
+        Ok(Bytes::from(typst_svg::svg_merged(
+            &layout,
+            Abs::pt(PAGE_SPACING),
+        )))
+    }
+
+    /// Layout of the PDF, its pages filled with white as the SVG background is transparent
+    fn layout(document: &Document, options: &PdfOptions) -> anyhow::Result<typst::model::Document> {
+        let mut layout = crate::typst::layout(document, options)?;
+        for page in &mut layout.pages {
+            page.frame.fill(Paint::Solid(Color::WHITE));
+        }
+        Ok(layout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+    use crate::core::{Element, PageFormat, PageOrientation, Section};
+
+    #[test]
+    fn test_generate() -> anyhow::Result<()> {
+        let mut document = Document::new(vec![
+            Element::Header {
+                level: 1,
+                text: "Portrait page".to_string(),
+            },
+            Element::Text {
+                text: "Landscape page".to_string(),
+                size: 10,
+            },
+        ]);
+        document.add_section(Section::new(1, PageFormat::A4, PageOrientation::Landscape));
+
+        let saved = RefCell::new(vec![]);
+        let svg = Transformer::generate_with_saver(
+            &document,
+            &PdfOptions::default(),
+            |bytes: &Bytes, name: &str| {
+                saved.borrow_mut().push((name.to_string(), bytes.clone()));
+                Ok(())
+            },
+        )?;
+        let svg = String::from_utf8(svg.to_vec())?;
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.trim_end().ends_with("</svg>"));
+
+        let saved = saved.into_inner();
+        let names: Vec<&str> = saved.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["page1.svg", "page2.svg"]);
+        // an A4 page is 595.28 by 841.89 points, the second one in landscape
+        let first = String::from_utf8(saved[0].1.to_vec())?;
+        assert!(first.contains("viewBox=\"0 0 595.2756 841.8898\""));
+        let second = String::from_utf8(saved[1].1.to_vec())?;
+        assert!(second.contains("viewBox=\"0 0 841.8898 595.2756\""));
+        assert!(first.contains("fill=\"#ffffff\""));
+
+        let pages = Transformer::generate_pages(&document, &PdfOptions::default())?;
+        assert_eq!(pages, [saved[0].1.clone(), saved[1].1.clone()]);
+
+        // the pages are written to the disk only through an explicit saver
+        let _ = std::fs::remove_file("page1.svg");
+        assert_eq!(
+            Transformer::generate(&document)?,
+            Bytes::from(svg.into_bytes())
+        );
+        assert!(!std::path::Path::new("page1.svg").exists());
+        Ok(())
+    }
+}
@@ -0,0 +1,410 @@
+//! Templating: substitution of the `{{name}}` placeholders of a parsed document
+//!
+//! - a text made of a single placeholder is replaced by the value element (a text value keeps
+//!   the size of the template text), a paragraph made of a single placeholder is replaced by a
+//!   block value (table, list, paragraph, header)
+//! - a placeholder inside a longer text (or a header, or a link url) is replaced by the plain
+//!   text of the value
+//! - a table row with `{{items.field}}` placeholders, where `items` is a table value, is
+//!   repeated for each row of `items`, `field` being the text of one of its headers
+//!
+//! Unknown placeholders are left as is.
+
+use std::collections::HashMap;
+
+use crate::core::{Document, Element, ListItem, TableCell, TableHeader, TableRow};
+
+/// Names of the placeholders of the document, in order of appearance
+pub fn variables(document: &Document) -> Vec<String> {
+    fn collect(element: &Element, names: &mut Vec<String>) {
+        let mut add = |text: &str| {
+            for name in placeholders(text) {
+                if !names.contains(&name) {
+                    names.push(name);
+                }
+            }
+        };
+        match element {
+            Element::Text { text, .. } | Element::Header { text, .. } => add(text),
+            Element::Hyperlink { url, .. } => add(url),
+            _ => {}
+        }
+        for child in element.children() {
+            collect(child, names);
+        }
+    }
+
+    let mut names = vec![];
+    for element in document.get_all_elements() {
+        collect(element, &mut names);
+    }
+    names
+}
+
+/// Copy of the document with the placeholders substituted
+pub fn render(document: &Document, values: &HashMap<String, Element>) -> Document {
+    let scope = Scope {
+        values,
+        record: None,
+    };
+    let mut document = document.clone();
+    for band in document.bands.iter_mut() {
+        let elements = band.elements_mut();
+        *elements = elements
+            .iter()
+            .map(|element| scope.render(element))
+            .collect();
+    }
+    document
+}
+
+/// Row of a table value, while a template row is repeated
+struct Record<'a> {
+    name: &'a str,
+    headers: &'a [TableHeader],
+    row: &'a TableRow,
+}
+
+struct Scope<'a> {
+    values: &'a HashMap<String, Element>,
+    record: Option<Record<'a>>,
+}
+
+impl Scope<'_> {
+    fn lookup(&self, name: &str) -> Option<&Element> {
+        if let Some(record) = &self.record {
+            if let Some(field) = name
+                .strip_prefix(record.name)
+                .and_then(|rest| rest.strip_prefix('.'))
+            {
+                let column = record
+                    .headers
+                    .iter()
+                    .position(|header| header.element.plain_text() == field)?;
+                return record.row.cells.get(column).map(|cell| &cell.element);
+            }
+        }
+        self.values.get(name)
+    }
+
+    fn substitute(&self, text: &str) -> String {
+        let mut result = String::new();
+        let mut rest = text;
+        while let Some(start) = rest.find("{{") {
+            let Some(end) = rest[start..].find("}}") else {
+                break;
+            };
+            let placeholder = &rest[start..start + end + 2];
+            result.push_str(&rest[..start]);
+            match self.lookup(placeholder[2..placeholder.len() - 2].trim()) {
+                Some(value) => result.push_str(&value.plain_text()),
+                None => result.push_str(placeholder),
+            }
+            rest = &rest[start + end + 2..];
+        }
+        result.push_str(rest);
+        result
+    }
+
+    fn render(&self, element: &Element) -> Element {
+        match element {
+            Element::Text { text, size } => {
+                match whole_placeholder(text).and_then(|name| self.lookup(name)) {
+                    Some(Element::Text { text, .. }) => Element::Text {
+                        text: text.clone(),
+                        size: *size,
+                    },
+                    Some(value) => value.clone(),
+                    None => Element::Text {
+                        text: self.substitute(text),
+                        size: *size,
+                    },
+                }
+            }
+            Element::Header { level, text } => Element::Header {
+                level: *level,
+                text: self.substitute(text),
+            },
+            Element::Paragraph {
+                elements,
+                direction,
+            } => {
+                if let [Element::Text { text, .. }] = elements.as_slice() {
+                    if let Some(value) = whole_placeholder(text).and_then(|name| self.lookup(name))
+                    {
+                        if is_block(value) {
+                            return value.clone();
+                        }
+                    }
+                }
+                Element::Paragraph {
+                    elements: elements
+                        .iter()
+                        .map(|element| self.render(element))
+                        .collect(),
+                    direction: *direction,
+                }
+            }
+            Element::Hyperlink {
+                elements,
+                url,
+                alt,
+                size,
+            } => Element::Hyperlink {
+                elements: elements
+                    .iter()
+                    .map(|element| self.render(element))
+                    .collect(),
+                url: self.substitute(url),
+                alt: alt.clone(),
+                size: *size,
+            },
+            Element::Footnote { elements } => Element::Footnote {
+                elements: elements
+                    .iter()
+                    .map(|element| self.render(element))
+                    .collect(),
+            },
+            Element::Styled { style, elements } => Element::Styled {
+                style: *style,
+                elements: elements
+                    .iter()
+                    .map(|element| self.render(element))
+                    .collect(),
+            },
+            Element::Container { kind, elements } => Element::Container {
+                kind: *kind,
+                elements: elements
+                    .iter()
+                    .map(|element| self.render(element))
+                    .collect(),
+            },
+            Element::List {
+                elements,
+                numbered,
+                start,
+                numbering,
+            } => Element::List {
+                elements: elements
+                    .iter()
+                    .map(|item| ListItem {
+                        element: self.render(&item.element),
+                    })
+                    .collect(),
+                numbered: *numbered,
+                start: *start,
+                numbering: *numbering,
+            },
+            Element::Table { headers, rows } => Element::Table {
+                headers: headers
+                    .iter()
+                    .map(|header| TableHeader {
+                        element: self.render(&header.element),
+                        width: header.width,
+                    })
+                    .collect(),
+                rows: rows.iter().flat_map(|row| self.render_row(row)).collect(),
+            },
+            Element::Attributed {
+                attributes,
+                element,
+            } => Element::Attributed {
+                attributes: attributes.clone(),
+                element: Box::new(self.render(element)),
+            },
+            _ => element.clone(),
+        }
+    }
+
+    /// Rows rendered from a template row, repeated when it loops over a table value
+    fn render_row(&self, row: &TableRow) -> Vec<TableRow> {
+        let render = |scope: &Scope| TableRow {
+            cells: row
+                .cells
+                .iter()
+                .map(|cell| TableCell {
+                    element: scope.render(&cell.element),
+                })
+                .collect(),
+        };
+
+        let table = row
+            .cells
+            .iter()
+            .flat_map(|cell| placeholders(&cell.element.plain_text()))
+            .find_map(|name| {
+                let (table, _) = name.split_once('.')?;
+                match self.values.get_key_value(table)? {
+                    (name, Element::Table { headers, rows }) => Some((name, headers, rows)),
+                    _ => None,
+                }
+            });
+        match table {
+            Some((name, headers, rows)) => rows
+                .iter()
+                .map(|record| {
+                    render(&Scope {
+                        values: self.values,
+                        record: Some(Record {
+                            name,
+                            headers,
+                            row: record,
+                        }),
+                    })
+                })
+                .collect(),
+            None => vec![render(self)],
+        }
+    }
+}
+
+fn placeholders(text: &str) -> Vec<String> {
+    let mut names = vec![];
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        let Some(end) = rest[start..].find("}}") else {
+            break;
+        };
+        names.push(rest[start + 2..start + end].trim().to_string());
+        rest = &rest[start + end + 2..];
+    }
+    names
+}
+
+/// Name of the placeholder when the text is made of it only
+fn whole_placeholder(text: &str) -> Option<&str> {
+    let name = text.trim().strip_prefix("{{")?.strip_suffix("}}")?;
+    (!name.contains("}}")).then_some(name.trim())
+}
+
+fn is_block(element: &Element) -> bool {
+    matches!(
+        element,
+        Element::Table { .. }
+            | Element::List { .. }
+            | Element::Paragraph { .. }
+            | Element::Header { .. }
+            | Element::TableOfContents { .. }
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::core::*;
+    use crate::template::*;
+
+    fn text(text: &str) -> Element {
+        Element::Text {
+            text: text.to_string(),
+            size: 8,
+        }
+    }
+
+    fn paragraph(elements: Vec<Element>) -> Element {
+        Element::Paragraph {
+            elements,
+            direction: TextDirection::default(),
+        }
+    }
+
+    fn row(cells: Vec<Element>) -> TableRow {
+        TableRow {
+            cells: cells
+                .into_iter()
+                .map(|element| TableCell { element })
+                .collect(),
+        }
+    }
+
+    fn header(text: &str) -> TableHeader {
+        TableHeader {
+            element: self::text(text),
+            width: 30.0,
+        }
+    }
+
+    #[test]
+    fn test_render() {
+        let template = Document::new(vec![
+            Element::Header {
+                level: 1,
+                text: "Invoice {{ number }}".to_string(),
+            },
+            paragraph(vec![text("Dear "), text("{{name}}"), text(", {{unknown}}")]),
+            paragraph(vec![text("{{notes}}")]),
+            Element::Table {
+                headers: vec![header("Item"), header("Qty")],
+                rows: vec![
+                    row(vec![text("{{items.name}}"), text("{{items.qty}} pcs")]),
+                    row(vec![text("Total"), text("{{total}}")]),
+                ],
+            },
+        ]);
+        assert_eq!(
+            variables(&template),
+            vec![
+                "number",
+                "name",
+                "unknown",
+                "notes",
+                "items.name",
+                "items.qty",
+                "total"
+            ]
+        );
+
+        let notes = Element::List {
+            elements: vec![ListItem {
+                element: text("Paid"),
+            }],
+            numbered: false,
+            start: 1,
+            numbering: NumberingStyle::default(),
+        };
+        let values = HashMap::from([
+            ("number".to_string(), text("42")),
+            (
+                "name".to_string(),
+                Element::Text {
+                    text: "Ann".to_string(),
+                    size: 20,
+                },
+            ),
+            ("notes".to_string(), notes.clone()),
+            ("total".to_string(), text("5")),
+            (
+                "items".to_string(),
+                Element::Table {
+                    headers: vec![header("name"), header("qty")],
+                    rows: vec![
+                        row(vec![text("Apple"), text("2")]),
+                        row(vec![text("Pear"), text("3")]),
+                    ],
+                },
+            ),
+        ]);
+
+        let document = render(&template, &values);
+        assert_eq!(
+            document.get_all_elements(),
+            vec![
+                &Element::Header {
+                    level: 1,
+                    text: "Invoice 42".to_string(),
+                },
+                &paragraph(vec![text("Dear "), text("Ann"), text(", {{unknown}}")]),
+                &notes,
+                &Element::Table {
+                    headers: vec![header("Item"), header("Qty")],
+                    rows: vec![
+                        row(vec![text("Apple"), text("2 pcs")]),
+                        row(vec![text("Pear"), text("3 pcs")]),
+                        row(vec![text("Total"), text("5")]),
+                    ],
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,398 @@
+//! Property testing of the transformers: strategies generating random documents and a check
+//! that parsing a generated document gives back the same structure
+//!
+//! ```ignore
+//! use proptest::prelude::*;
+//! use shiva::core::DocumentType;
+//! use shiva::testing::{arb_document, check_round_trip};
+//!
+//! proptest! {
+//!     #[test]
+//!     fn markdown_round_trip(document in arb_document()) {
+//!         check_round_trip(&document, DocumentType::Markdown)
+//!             .map_err(|e| TestCaseError::fail(e.to_string()))?;
+//!     }
+//! }
+//! ```
+
+use proptest::prelude::*;
+use thiserror::Error;
+
+use crate::core::{
+    Document, DocumentType, Element, ListItem, NumberingStyle, TableCell, TableHeader, TableRow,
+    TextDirection, TransformerRegistry,
+};
+
+/// Words of lowercase letters separated by single spaces, so that no format needs escaping
+pub fn arb_text() -> impl Strategy<Value = String> {
+    prop::collection::vec("[a-z]{1,8}", 1..5).prop_map(|words| words.join(" "))
+}
+
+fn text(text: String) -> Element {
+    Element::Text { text, size: 8 }
+}
+
+pub fn arb_header() -> impl Strategy<Value = Element> {
+    (1..=6u8, arb_text()).prop_map(|(level, text)| Element::Header { level, text })
+}
+
+pub fn arb_paragraph() -> impl Strategy<Value = Element> {
+    arb_text().prop_map(|text| Element::Paragraph {
+        elements: vec![self::text(text)],
+        direction: TextDirection::default(),
+    })
+}
+
+/// List of text items, each followed by an optional nested list that is its child, nested
+/// lists are at most `depth` levels deep
+pub fn arb_list(depth: u32) -> impl Strategy<Value = Element> {
+    let nested = if depth > 1 {
+        prop::option::weighted(0.25, arb_list(depth - 1).boxed()).boxed()
+    } else {
+        Just(None).boxed()
+    };
+    (
+        prop::collection::vec((arb_text().prop_map(text), nested), 1..5),
+        any::<bool>(),
+    )
+        .prop_map(|(items, numbered)| Element::List {
+            elements: items
+                .into_iter()
+                .flat_map(|(item, nested)| std::iter::once(item).chain(nested))
+                .map(|element| ListItem { element })
+                .collect(),
+            numbered,
+            start: 1,
+            numbering: NumberingStyle::default(),
+        })
+}
+
+/// Table of text cells with a header row, every row has as many cells as the header
+pub fn arb_table() -> impl Strategy<Value = Element> {
+    (1..4usize, 1..4usize)
+        .prop_flat_map(|(columns, rows)| {
+            (
+                prop::collection::vec(arb_text(), columns),
+                prop::collection::vec(prop::collection::vec(arb_text(), columns), rows),
+            )
+        })
+        .prop_map(|(headers, rows)| Element::Table {
+            headers: headers
+                .into_iter()
+                .map(|header| TableHeader {
+                    element: text(header),
+                    width: 30.0,
+                })
+                .collect(),
+            rows: rows
+                .into_iter()
+                .map(|cells| TableRow {
+                    cells: cells
+                        .into_iter()
+                        .map(|cell| TableCell {
+                            element: text(cell),
+                        })
+                        .collect(),
+                })
+                .collect(),
+        })
+}
+
+pub fn arb_element() -> impl Strategy<Value = Element> {
+    prop_oneof![arb_header(), arb_paragraph(), arb_list(2), arb_table()]
+}
+
+/// Document of headers, paragraphs, lists and tables in the detail band
+pub fn arb_document() -> impl Strategy<Value = Document> {
+    prop::collection::vec(arb_element(), 1..6).prop_map(Document::new)
+}
+
+/// Document of headers, paragraphs, lists and tables whose headers open sections one level
+/// deeper at most, from the first level to `max_level`, for the formats that read the
+/// header levels from the nesting of the sections
+pub fn arb_section_document(max_level: u8) -> impl Strategy<Value = Document> {
+    prop::collection::vec(arb_element(), 1..6).prop_map(move |mut elements| {
+        let mut depth = 0;
+        for element in &mut elements {
+            if let Element::Header { level, .. } = element {
+                *level = (*level).min(depth + 1).min(max_level);
+                depth = *level;
+            }
+        }
+        Document::new(elements)
+    })
+}
+
+/// Document of tables only, for the spreadsheet formats
+pub fn arb_table_document() -> impl Strategy<Value = Document> {
+    prop::collection::vec(arb_table(), 1..3).prop_map(Document::new)
+}
+
+/// Document of tables after headers of distinct short names, for the workbooks of sheets
+/// named after their header
+pub fn arb_sheet_document() -> impl Strategy<Value = Document> {
+    prop::collection::btree_set("[a-z]{1,8}( [a-z]{1,8}){0,2}", 1..3)
+        .prop_flat_map(|names| {
+            let count = names.len();
+            (Just(names), prop::collection::vec(arb_table(), count))
+        })
+        .prop_map(|(names, tables)| {
+            Document::new(
+                names
+                    .into_iter()
+                    .zip(tables)
+                    .flat_map(|(text, table)| [Element::Header { level: 1, text }, table])
+                    .collect(),
+            )
+        })
+}
+
+/// Structure of an element compared by the round trip: the element types, header levels,
+/// list nesting and table shapes, with the text of the leaves
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outline {
+    Header {
+        level: u8,
+        text: String,
+    },
+    Paragraph(String),
+    List {
+        numbered: bool,
+        items: Vec<Outline>,
+    },
+    Table {
+        headers: Vec<String>,
+        rows: Vec<Vec<String>>,
+    },
+    Other(&'static str),
+}
+
+/// Outline of the top level elements of the document
+pub fn outline(document: &Document) -> Vec<Outline> {
+    document
+        .get_all_elements()
+        .into_iter()
+        .map(outline_element)
+        .collect()
+}
+
+fn outline_element(element: &Element) -> Outline {
+    match element {
+        Element::Attributed { element, .. } => outline_element(element),
+        Element::Header { level, text } => Outline::Header {
+            level: *level,
+            text: collapse(text),
+        },
+        Element::Text { text, .. } => Outline::Paragraph(collapse(text)),
+        Element::Paragraph { .. } => Outline::Paragraph(collapse(&element.plain_text())),
+        Element::List {
+            elements, numbered, ..
+        } => Outline::List {
+            numbered: *numbered,
+            items: elements
+                .iter()
+                .flat_map(|item| match &item.element {
+                    // a list of a parent item and its nested list, that the generators
+                    // accept as well as the nested list following its parent item
+                    Element::List { elements, .. } if is_parent_list(elements) => elements
+                        .iter()
+                        .map(|item| outline_element(&item.element))
+                        .collect(),
+                    element => vec![outline_element(element)],
+                })
+                .collect(),
+        },
+        Element::Table { headers, rows } => Outline::Table {
+            headers: headers
+                .iter()
+                .map(|header| collapse(&header.element.plain_text()))
+                .collect(),
+            rows: rows
+                .iter()
+                .map(|row| {
+                    row.cells
+                        .iter()
+                        .map(|cell| collapse(&cell.element.plain_text()))
+                        .collect()
+                })
+                .collect(),
+        },
+        element => Outline::Other(element.into()),
+    }
+}
+
+fn is_parent_list(items: &[ListItem]) -> bool {
+    matches!(
+        items,
+        [
+            ListItem {
+                element: Element::Text { .. }
+            },
+            ListItem {
+                element: Element::List { .. }
+            }
+        ]
+    )
+}
+
+fn collapse(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Documents that a round trip through `document_type` is expected to keep, or why the
+/// format is not round tripped
+///
+/// - HTML, markdown, JSON, ODT, RTF, EPUB, Org and MediaWiki keep headers, paragraphs, lists
+///   and tables
+/// - LaTeX, AsciiDoc and reStructuredText read the header levels from the nesting of the
+///   sections, so the headers open sections one level deeper at most, five levels deep in
+///   LaTeX and AsciiDoc
+/// - DOCX keeps headers, paragraphs and lists, the header row of a table is read as a row
+/// - PDF keeps paragraphs and lists without nesting after each of them, lists that follow
+///   each other are read as one and the other elements are laid out as text
+/// - XML keeps headers and paragraphs, list items and table cells are not read back
+/// - XLSX keeps tables after headers naming their sheet, ODS keeps tables, one per sheet,
+///   CSV and TSV keep a single table
+/// - plain text keeps a single paragraph: paragraphs are merged and the other elements
+///   come back as text
+pub fn round_trip_documents(
+    document_type: DocumentType,
+) -> Result<BoxedStrategy<Document>, &'static str> {
+    fn single(element: impl Strategy<Value = Element> + 'static) -> BoxedStrategy<Document> {
+        element
+            .prop_map(|element| Document::new(vec![element]))
+            .boxed()
+    }
+
+    fn several(element: impl Strategy<Value = Element> + 'static) -> BoxedStrategy<Document> {
+        prop::collection::vec(element, 1..6)
+            .prop_map(Document::new)
+            .boxed()
+    }
+
+    match document_type {
+        DocumentType::HTML
+        | DocumentType::Markdown
+        | DocumentType::Json
+        | DocumentType::ODT
+        | DocumentType::RTF
+        | DocumentType::EPUB
+        | DocumentType::Org
+        | DocumentType::MediaWiki => Ok(arb_document().boxed()),
+        DocumentType::LaTeX | DocumentType::AsciiDoc => Ok(arb_section_document(5).boxed()),
+        DocumentType::RST => Ok(arb_section_document(6).boxed()),
+        DocumentType::DOCX => Ok(several(prop_oneof![
+            arb_header(),
+            arb_paragraph(),
+            arb_list(2)
+        ])),
+        DocumentType::PDF => Ok(prop::collection::vec(
+            (arb_paragraph(), prop::option::of(arb_list(1))),
+            1..4,
+        )
+        .prop_map(|blocks| {
+            Document::new(
+                blocks
+                    .into_iter()
+                    .flat_map(|(paragraph, list)| std::iter::once(paragraph).chain(list))
+                    .collect(),
+            )
+        })
+        .boxed()),
+        DocumentType::XML => Ok(several(prop_oneof![arb_header(), arb_paragraph()])),
+        DocumentType::XLSX => Ok(arb_sheet_document().boxed()),
+        DocumentType::ODS => Ok(arb_table_document().boxed()),
+        DocumentType::CSV | DocumentType::TSV => Ok(single(arb_table())),
+        DocumentType::Text => Ok(single(arb_paragraph())),
+        DocumentType::XLS => Err("XLS is read only"),
+        DocumentType::Image => Err("images are read by OCR and not generated"),
+        DocumentType::Ipynb => Err("notebooks are read only"),
+        DocumentType::Jira => Err("Jira markup is read only"),
+        DocumentType::BBCode => Err("BBCode is read only"),
+        DocumentType::Eml => Err("emails are read only"),
+        DocumentType::VCard => Err("vCards are read only"),
+        DocumentType::ICalendar => Err("iCalendar files are read only"),
+        DocumentType::DocBook => Err("DocBook is written only"),
+        DocumentType::FB2 => Err("FictionBook is written only"),
+        DocumentType::Confluence => Err("Confluence storage format is written only"),
+        DocumentType::Typst => Err("Typst is written only"),
+        DocumentType::Man => Err("man pages are written only"),
+        DocumentType::SVG => Err("SVG pages are written only"),
+        DocumentType::PPTX => Err("PPTX presentations are written only"),
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum RoundTripError {
+    #[error("Cannot generate {0}: {1}")]
+    Generate(DocumentType, crate::Error),
+    #[error("Cannot parse {0}: {1}")]
+    Parse(DocumentType, crate::Error),
+    #[error("Round trip through {document_type} changed the document\nexpected: {expected:?}\nactual:   {actual:?}")]
+    Changed {
+        document_type: DocumentType,
+        expected: Vec<Outline>,
+        actual: Vec<Outline>,
+    },
+}
+
+/// Generates the document to `document_type`, parses it back and compares the outlines
+pub fn check_round_trip(
+    document: &Document,
+    document_type: DocumentType,
+) -> Result<(), RoundTripError> {
+    let registry = TransformerRegistry::builtin();
+    let bytes = registry
+        .generate(document_type, document)
+        .map_err(|e| RoundTripError::Generate(document_type, e))?;
+    let parsed = registry
+        .parse(document_type, &bytes)
+        .map_err(|e| RoundTripError::Parse(document_type, e))?;
+    let expected = outline(document);
+    let actual = outline(&parsed);
+    if expected == actual {
+        Ok(())
+    } else {
+        Err(RoundTripError::Changed {
+            document_type,
+            expected,
+            actual,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::test_runner::{Config, TestRunner};
+
+    use crate::core::{DocumentType, TransformerRegistry};
+    use crate::testing::*;
+
+    #[test]
+    fn test_round_trip() {
+        let registry = TransformerRegistry::builtin();
+        for &document_type in DocumentType::variants() {
+            let both = registry.can_parse(document_type) && registry.can_generate(document_type);
+            let documents = match round_trip_documents(document_type) {
+                Ok(documents) if both => documents,
+                Ok(_) => continue,
+                Err(reason) => {
+                    assert!(!both, "{document_type} is not round tripped: {reason}");
+                    continue;
+                }
+            };
+            let mut runner = TestRunner::new(Config {
+                cases: 64,
+                failure_persistence: None,
+                ..Config::default()
+            });
+            let result = runner.run(&documents, |document| {
+                check_round_trip(&document, document_type)
+                    .map_err(|e| TestCaseError::fail(e.to_string()))
+            });
+            if let Err(error) = result {
+                panic!("{}", error);
+            }
+        }
+    }
+}
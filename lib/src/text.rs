@@ -6,10 +6,26 @@ use std::collections::HashMap;
 
 pub struct Transformer;
 impl TransformerTrait for Transformer {
-    fn parse(document: &Bytes) -> anyhow::Result<Document>
-    where
-        Self: Sized,
-    {
+    fn parse(document: &Bytes) -> crate::Result<Document> {
+        Transformer::parse_bytes(document).map_err(crate::Error::parse_error)
+    }
+
+    fn parse_with_options(document: &Bytes, options: &ParseOptions) -> crate::Result<Document> {
+        let document = Transformer::parse(document)?;
+        Ok(if options.autolink {
+            document.link_urls()
+        } else {
+            document
+        })
+    }
+
+    fn generate(document: &Document) -> crate::Result<Bytes> {
+        Transformer::generate_bytes(document).map_err(crate::Error::generation_error)
+    }
+}
+
+impl Transformer {
+    fn parse_bytes(document: &Bytes) -> anyhow::Result<Document> {
         let mut elements: Vec<Element> = vec![];
         let document: &str = std::str::from_utf8(document.as_ref())?;
         let lines = document.lines();
@@ -27,14 +43,20 @@ impl TransformerTrait for Transformer {
             });
             i += 1;
         }
-        let new_paragraph = Paragraph { elements };
+        let new_paragraph = Paragraph {
+            elements,
+            direction: TextDirection::default(),
+        };
         Ok(Document::new(vec![new_paragraph]))
     }
 
-    fn generate(document: &Document) -> anyhow::Result<Bytes>
-    where
-        Self: Sized,
-    {
+    fn generate_bytes(document: &Document) -> anyhow::Result<Bytes> {
+        let document = &document
+            .resolve_fields()
+            .without_attributes()
+            .without_containers()
+            .without_media()
+            .without_math();
         let mut images: HashMap<String, Bytes> = HashMap::new();
         let mut image_num: i32 = 0;
 
@@ -44,7 +66,7 @@ impl TransformerTrait for Transformer {
             markdown: &mut String,
             list_depth: usize,
             list_counters: &mut Vec<usize>,
-            list_types: &mut Vec<bool>,
+            list_types: &mut Vec<Option<NumberingStyle>>,
             images: &mut HashMap<String, Bytes>,
             image_num: &mut i32,
         ) -> anyhow::Result<()> {
@@ -53,16 +75,16 @@ impl TransformerTrait for Transformer {
                 markdown: &mut String,
                 list_depth: usize,
                 list_counters: &mut Vec<usize>,
-                list_types: &mut Vec<bool>,
+                list_types: &mut Vec<Option<NumberingStyle>>,
                 images: &mut HashMap<String, Bytes>,
                 image_num: &mut i32,
             ) -> anyhow::Result<()> {
-                let prefix = if *list_types.last().unwrap() {
+                let prefix = if let Some(numbering) = list_types.last().unwrap() {
                     let counter = list_counters.last_mut().unwrap();
                     if let Element::Text { .. } = element.element {
                         *counter += 1;
                     }
-                    format!("{}. ", counter)
+                    format!("{}. ", numbering.format(*counter))
                 } else {
                     "- ".to_string()
                 };
@@ -92,7 +114,7 @@ impl TransformerTrait for Transformer {
                     markdown.push('\n');
                     markdown.push('\n');
                 }
-                Element::Paragraph { elements } => {
+                Element::Paragraph { elements, .. } => {
                     for child in elements {
                         generate_element(
                             child,
@@ -107,9 +129,14 @@ impl TransformerTrait for Transformer {
                     markdown.push('\n');
                     markdown.push('\n');
                 }
-                Element::List { elements, numbered } => {
-                    list_counters.push(0);
-                    list_types.push(*numbered);
+                Element::List {
+                    elements,
+                    numbered,
+                    start,
+                    numbering,
+                } => {
+                    list_counters.push(start.saturating_sub(1));
+                    list_types.push(numbered.then_some(*numbering));
                     for item in elements {
                         generate_list_item(
                             item,
@@ -134,9 +161,61 @@ impl TransformerTrait for Transformer {
                         markdown.push(' ');
                     }
                 }
-                Element::Hyperlink {
-                    title, url, alt, ..
-                } => {
+                Element::LineBreak => {
+                    markdown.push('\n');
+                }
+                // expanded from the document headers before generation
+                Element::TableOfContents { .. } => {}
+                // plain text has no pages
+                Element::Field { .. } => {}
+                // replaced by text in Document::resolve_fields
+                Element::CrossReference { .. } => {}
+                // nothing to link to in plain text
+                Element::Bookmark { .. } => {}
+                // unwrapped in Document::without_attributes
+                Element::Attributed { .. } => {}
+                // flattened in Document::without_containers
+                Element::Container { .. } => {}
+                // replaced by a link in Document::without_media
+                Element::Media { .. } => {}
+                // replaced by code in Document::without_math
+                Element::Math { .. } => {}
+                // a drawing has no text
+                Element::Drawing { .. } => {}
+                Element::InlineCode { text } => {
+                    markdown.push_str(text);
+                    markdown.push(' ');
+                }
+                Element::Raw {
+                    format: DocumentType::Text,
+                    content,
+                } => markdown.push_str(content),
+                // content of another format
+                Element::Raw { .. } => {}
+                Element::CodeBlock { code, .. } => {
+                    markdown.push_str(code);
+                    markdown.push('\n');
+                }
+                // plain text has no styles
+                Element::Styled { elements, .. } => {
+                    for child in elements {
+                        generate_element(
+                            child,
+                            markdown,
+                            list_depth,
+                            list_counters,
+                            list_types,
+                            images,
+                            image_num,
+                        )?;
+                    }
+                }
+                // inline in brackets where it is referenced
+                Element::Footnote { .. } => {
+                    markdown.push_str(&format!("[{}] ", element.plain_text().trim()));
+                }
+                Element::Hyperlink { url, alt, .. } => {
+                    let title = element.plain_text();
                     if url == alt {
                         markdown.push_str(&url.to_string());
                     } else {
@@ -155,53 +234,63 @@ impl TransformerTrait for Transformer {
                     *image_num += 1;
                 }
                 Table { headers, rows } => {
-                    let mut max_lengths: Vec<usize> = Vec::new();
-
-                    for header in headers {
-                        if let Element::Text { text, size: _ } = header.element.clone() {
-                            max_lengths.push(text.len());
-                        }
+                    fn texts<'a>(cells: impl Iterator<Item = &'a Element>) -> Vec<String> {
+                        // the blocks of a cell are put on its single line
+                        cells
+                            .map(|cell| {
+                                let text = cell.plain_text();
+                                let lines: Vec<&str> = text
+                                    .lines()
+                                    .map(str::trim)
+                                    .filter(|line| !line.is_empty())
+                                    .collect();
+                                lines.join(" ")
+                            })
+                            .collect()
                     }
-                    for row in rows {
-                        for (cell_index, cell) in row.cells.iter().enumerate() {
-                            if let Element::Text { text, size: _ } = cell.element.clone() {
-                                if cell_index < max_lengths.len() {
-                                    max_lengths[cell_index] =
-                                        max_lengths[cell_index].max(text.len());
-                                }
-                            }
+                    let header_texts = texts(headers.iter().map(|header| &header.element));
+                    let row_texts: Vec<Vec<String>> = rows
+                        .iter()
+                        .map(|row| texts(row.cells.iter().map(|cell| &cell.element)))
+                        .collect();
+
+                    // rows may be ragged or the header missing, the widest row sets the columns
+                    let columns = row_texts
+                        .iter()
+                        .map(Vec::len)
+                        .chain([header_texts.len()])
+                        .max()
+                        .unwrap_or(0);
+                    let mut max_lengths = vec![0; columns];
+                    for texts in row_texts.iter().chain([&header_texts]) {
+                        for (index, text) in texts.iter().enumerate() {
+                            max_lengths[index] = max_lengths[index].max(text.len());
                         }
                     }
 
-                    for (index, header) in headers.iter().enumerate() {
-                        if let Element::Text { text, size: _ } = header.element.clone() {
-                            let padding = max_lengths[index] - text.len();
+                    let push_row = |markdown: &mut String, texts: &[String]| {
+                        for (index, max_length) in max_lengths.iter().enumerate() {
+                            let text = texts.get(index).map(String::as_str).unwrap_or("");
                             markdown.push_str("| ");
-                            markdown.push_str(text.as_str());
-                            markdown.push_str(&" ".repeat(padding));
+                            markdown.push_str(text);
+                            markdown.push_str(&" ".repeat(max_length.saturating_sub(text.len())));
                             markdown.push(' ');
                         }
-                    }
-                    markdown.push_str("|\n");
-
-                    for max_length in &max_lengths {
-                        markdown.push('|');
-                        markdown.push_str(&"-".repeat(*max_length + 2));
-                    }
-                    markdown.push_str("|\n");
+                        markdown.push_str("|\n");
+                    };
 
-                    for row in rows {
-                        for (cell_index, cell) in row.cells.iter().enumerate() {
-                            if let Element::Text { text, size: _ } = cell.element.clone() {
-                                let padding = max_lengths[cell_index] - text.len();
-                                markdown.push_str("| ");
-                                markdown.push_str(text.as_str());
-                                markdown.push_str(&" ".repeat(padding));
-                                markdown.push(' ');
-                            }
+                    if !header_texts.is_empty() {
+                        push_row(markdown, &header_texts);
+                        for max_length in &max_lengths {
+                            markdown.push('|');
+                            markdown.push_str(&"-".repeat(*max_length + 2));
                         }
                         markdown.push_str("|\n");
                     }
+
+                    for texts in &row_texts {
+                        push_row(markdown, texts);
+                    }
                     markdown.push('\n');
                 }
             }
@@ -209,10 +298,18 @@ impl TransformerTrait for Transformer {
         }
 
         let mut list_counters: Vec<usize> = Vec::new();
-        let mut list_types: Vec<bool> = Vec::new();
+        let mut list_types: Vec<Option<NumberingStyle>> = Vec::new();
 
         for band in &document.bands {
             for element in &document.get_elements_by_band(band) {
+                let toc;
+                let element = match element {
+                    Element::TableOfContents { max_level } => {
+                        toc = toc_titles(document.build_toc(*max_level));
+                        &toc
+                    }
+                    _ => element,
+                };
                 generate_element(
                     element,
                     &mut markdown,
@@ -229,6 +326,34 @@ impl TransformerTrait for Transformer {
     }
 }
 
+// Links to anchors mean nothing in plain text, so the table of contents
+// lists only the header titles
+fn toc_titles(element: Element) -> Element {
+    match element {
+        Element::List {
+            elements,
+            numbered,
+            start,
+            numbering,
+        } => Element::List {
+            elements: elements
+                .into_iter()
+                .map(|item| ListItem {
+                    element: toc_titles(item.element),
+                })
+                .collect(),
+            numbered,
+            start,
+            numbering,
+        },
+        Element::Hyperlink { elements, size, .. } => Element::Text {
+            text: elements.iter().map(Element::plain_text).collect(),
+            size,
+        },
+        element => element,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use log::{debug, info};
@@ -237,6 +362,7 @@ mod tests {
     use crate::core::Element::Header;
     use crate::core::*;
     use crate::text::*;
+    use bytes::Bytes;
 
     #[test]
     fn test() -> anyhow::Result<()> {
@@ -289,4 +415,87 @@ Second header
         info!("{}", generated_text);
         Ok(())
     }
+
+    #[test]
+    fn test_autolink() -> anyhow::Result<()> {
+        let text = Bytes::from("See https://example.com/a?b=1. or http://x.org\n");
+        let options = ParseOptions {
+            autolink: true,
+            ..ParseOptions::default()
+        };
+        let document = Transformer::parse_with_options(&text, &options)?;
+        let links: Vec<&str> = document
+            .get_all_elements()
+            .into_iter()
+            .flat_map(|element| element.children())
+            .filter_map(|element| match element {
+                Element::Hyperlink { url, .. } => Some(url.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(links, ["https://example.com/a?b=1", "http://x.org"]);
+        let plain = Transformer::parse_with_options(&text, &ParseOptions::default())?;
+        assert_eq!(plain, Transformer::parse(&text)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_table_cells() -> anyhow::Result<()> {
+        let html = "<table><tr><th>Name</th><th>Note</th></tr>\
+            <tr><td><b>Ada</b></td><td><i>first programmer</i></td></tr></table>";
+        let parsed = crate::html::Transformer::parse(&Bytes::from(html))?;
+        assert_eq!(
+            Transformer::generate(&parsed)?,
+            "| Name | Note             |\n|------|------------------|\n| Ada  | first programmer |\n\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_table_to_text() -> anyhow::Result<()> {
+        let text = |text: &str| Element::Text {
+            text: text.to_string(),
+            size: 14,
+        };
+        let row = |cells: &[&str]| TableRow {
+            cells: cells
+                .iter()
+                .map(|cell| TableCell {
+                    element: text(cell),
+                })
+                .collect(),
+        };
+        let mut document = Document::new(vec![]);
+        document.add_element(Element::Table {
+            headers: ["Name", "Value"]
+                .map(|header| TableHeader {
+                    element: text(header),
+                    width: 30.0,
+                })
+                .to_vec(),
+            rows: vec![TableRow {
+                cells: vec![
+                    TableCell { element: text("a") },
+                    TableCell {
+                        element: Element::Paragraph {
+                            elements: vec![Element::Styled {
+                                style: TextStyle::Strong,
+                                elements: vec![text("1")],
+                            }],
+                            direction: Default::default(),
+                        },
+                    },
+                ],
+            }],
+        });
+        document.add_element(Element::Table {
+            headers: vec![],
+            rows: vec![row(&["x", "y", "z"]), row(&["w"])],
+        });
+        assert_eq!(
+            Transformer::generate(&document)?,
+            "| Name | Value |\n|------|-------|\n| a    | 1     |\n\n| x | y | z |\n| w |   |   |\n\n"
+        );
+        Ok(())
+    }
 }
@@ -1,16 +1,30 @@
-use crate::core::Element::{Header, Hyperlink, Image, List, Paragraph, Table, Text};
+use crate::core::Element::{
+    Attributed, Bookmark, CodeBlock, CrossReference, Field, Footnote, Header, Hyperlink, Image,
+    InlineCode, LineBreak, List, Paragraph, Raw, Styled, Table, TableOfContents, Text,
+};
 
-use crate::core::{Document, Element, ListItem, TableHeader, TableRow, TransformerTrait};
+use crate::core::{
+    disk_image_saver, header_anchor, Band, Document, DocumentType, Element, FieldKind, FontSource,
+    GenerateOptions, ImageData, Length, ListItem, NumberingStyle, PageDimensions, PageFormat,
+    PageOrientation, PdfOptions, PdfWatermark, TableHeader, TableRow, TextDirection, TextStyle,
+    TransformerTrait, WatermarkContent, WatermarkPosition,
+};
 use anyhow;
 use bytes::Bytes;
 use comemo::Prehashed;
 use log::warn;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
 use std::path::Path;
-use std::{collections::HashMap, io::Cursor};
+use std::{
+    collections::{HashMap, HashSet},
+    io::Cursor,
+};
 use time::{OffsetDateTime, UtcOffset};
 
 use typst::{
     diag::{FileError, FileResult},
+    eval::Tracer,
     foundations::Datetime,
     syntax::{FileId, Source},
     text::{Font, FontBook},
@@ -96,6 +110,25 @@ impl ShivaWorld {
             img_map,
         }
     }
+
+    /// World with the given fonts added to the bundled ones
+    pub fn with_fonts(mut self, fonts: &[FontSource]) -> anyhow::Result<Self> {
+        for source in fonts {
+            let (bytes, name) = match source {
+                FontSource::Path(path) => (std::fs::read(path)?, path.as_str()),
+                FontSource::Bytes(bytes) => (bytes.to_vec(), "bytes"),
+            };
+            let buffer = typst::foundations::Bytes::from(bytes);
+            let face_count = ttf_parser::fonts_in_collection(&buffer).unwrap_or(1);
+            for face in 0..face_count {
+                let font = Font::new(buffer.clone(), face)
+                    .ok_or_else(|| anyhow::anyhow!("Invalid font: {} (face {})", name, face))?;
+                self.fonts.push(font);
+            }
+        }
+        self.book = Prehashed::new(FontBook::from_fonts(&self.fonts));
+        Ok(self)
+    }
 }
 
 #[cfg(target_arch = "wasm32")]
@@ -180,27 +213,736 @@ impl World for ShivaWorld {
 pub struct Transformer;
 
 impl TransformerTrait for Transformer {
-    #[allow(unused)]
-    fn parse(document: &bytes::Bytes) -> anyhow::Result<Document> {
-        todo!()
+    /// Typst is written only, its source is not read back
+    fn parse(_document: &bytes::Bytes) -> crate::Result<Document> {
+        Err(crate::Error::UnsupportedFormat(
+            DocumentType::Typst.to_string(),
+        ))
+    }
+
+    fn generate(document: &Document) -> crate::Result<bytes::Bytes> {
+        Transformer::generate_with_saver(document, disk_image_saver("."))
+    }
+
+    /// Uses the PDF options, the typst source being the one compiled to PDF
+    fn generate_with_options(
+        document: &Document,
+        options: &GenerateOptions,
+    ) -> crate::Result<bytes::Bytes> {
+        Transformer::generate_bytes(document, &options.pdf, disk_image_saver("."))
+            .map_err(crate::Error::generation_error)
+    }
+}
+
+impl Transformer {
+    /// Typst source of the document, its images saved with `image_saver` under the file
+    /// names the source refers to
+    pub fn generate_with_saver<F>(document: &Document, image_saver: F) -> crate::Result<Bytes>
+    where
+        F: Fn(&Bytes, &str) -> anyhow::Result<()>,
+    {
+        Transformer::generate_bytes(document, &PdfOptions::default(), image_saver)
+            .map_err(crate::Error::generation_error)
+    }
+
+    fn generate_bytes<F>(
+        document: &Document,
+        options: &PdfOptions,
+        image_saver: F,
+    ) -> anyhow::Result<bytes::Bytes>
+    where
+        F: Fn(&Bytes, &str) -> anyhow::Result<()>,
+    {
+        let (text, img_map, _) = generate_source(document, options, true, false)?;
+        for (file_name, bytes) in img_map {
+            image_saver(&Bytes::from(bytes.to_vec()), &file_name)?;
+        }
+        Ok(Bytes::from(text))
+    }
+}
+
+/// Typst code of a field, date and title are expected to be resolved before
+fn field_content(kind: &FieldKind) -> String {
+    match kind {
+        FieldKind::PageNumber => "#context counter(page).display()".to_string(),
+        FieldKind::TotalPages => "#context counter(page).final().first()".to_string(),
+        FieldKind::Date => "#datetime.today().display()".to_string(),
+        FieldKind::DocumentTitle => String::new(),
+    }
+}
+
+/// Content block of a page header or footer made of texts and fields
+fn band_content(elements: &[&Element], title: &str) -> String {
+    let mut content = String::from("[");
+    for element in elements {
+        match element {
+            Text { text, size: _ } => content.push_str(&format!("#{}", string_literal(text))),
+            // the bands of the options are not resolved with the document
+            Field {
+                kind: FieldKind::DocumentTitle,
+            } => content.push_str(&format!("#{}", string_literal(title))),
+            Field { kind } => content.push_str(&field_content(kind)),
+            _ => {}
+        }
+    }
+    content.push(']');
+    content
+}
+
+/// Page header or footer switching to the band of the first page and of the even pages
+/// when given
+fn page_band(default: String, first: Option<String>, even: Option<String>) -> String {
+    if first.is_none() && even.is_none() {
+        return default;
+    }
+    let mut band = String::from("context ");
+    if let Some(first) = first {
+        band.push_str(&format!("if here().page() == 1 {first} else "));
+    }
+    if let Some(even) = even {
+        band.push_str(&format!("if calc.even(here().page()) {even} else "));
+    }
+    band.push_str(&default);
+    band
+}
+
+/// Typst string literal holding the given text, on one line
+fn string_literal(text: &str) -> String {
+    format!(
+        "\"{}\"",
+        text.replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('\n', "\\n")
+    )
+}
+
+/// Typst markup showing the text as is, the characters starting markup being escaped
+fn markup_escape(text: &str) -> String {
+    let mut markup = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    // nothing but spaces before on the line, where lists and headings start
+    let mut line_start = true;
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' | '*' | '_' | '#' | '$' | '@' | '<' | '>' | '[' | ']' | '`' | '~' => {
+                markup.push('\\');
+                markup.push(c);
+            }
+            // comments
+            '/' if matches!(chars.peek(), Some('/' | '*')) => markup.push_str("\\/"),
+            '=' | '-' | '+' if line_start => {
+                markup.push('\\');
+                markup.push(c);
+            }
+            // numbered list items
+            c if line_start && c.is_ascii_digit() => {
+                markup.push(c);
+                while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+                    markup.push(digit);
+                }
+                if chars.peek() == Some(&'.') {
+                    markup.push('\\');
+                }
+            }
+            c => markup.push(c),
+        }
+        line_start = c == '\n' || (line_start && c.is_whitespace());
+    }
+    markup
+}
+
+/// Names of the TeX math commands that typst calls otherwise, typst sharing the name of
+/// the other symbols and functions
+const MATH_COMMANDS: &[(&str, &str)] = &[
+    ("approx", "approx"),
+    ("cap", "sect"),
+    ("cdot", "dot.op"),
+    ("cdots", "dots.c"),
+    ("circ", "compose"),
+    ("cup", "union"),
+    ("ddots", "dots.down"),
+    ("emptyset", "nothing"),
+    ("ge", ">="),
+    ("geq", ">="),
+    ("gets", "<-"),
+    ("iint", "integral.double"),
+    ("infty", "infinity"),
+    ("int", "integral"),
+    ("langle", "angle.l"),
+    ("ldots", "dots"),
+    ("le", "<="),
+    ("leftarrow", "<-"),
+    ("leftrightarrow", "<->"),
+    ("leq", "<="),
+    ("mapsto", "|->"),
+    ("mid", "divides"),
+    ("mp", "minus.plus"),
+    ("ne", "!="),
+    ("neq", "!="),
+    ("notin", "in.not"),
+    ("oint", "integral.cont"),
+    ("partial", "diff"),
+    ("pm", "plus.minus"),
+    ("prod", "product"),
+    ("propto", "prop"),
+    ("qquad", "wide"),
+    ("rangle", "angle.r"),
+    ("Rightarrow", "=>"),
+    ("rightarrow", "->"),
+    ("setminus", "without"),
+    ("sim", "tilde.op"),
+    ("subseteq", "subset.eq"),
+    ("supseteq", "supset.eq"),
+    ("to", "->"),
+    ("varepsilon", "epsilon.alt"),
+    ("varphi", "phi.alt"),
+    ("vartheta", "theta.alt"),
+    ("vdots", "dots.v"),
+];
+
+/// Typst math of TeX math, the common commands being translated and the others kept by
+/// their name
+fn typst_math(tex: &str) -> String {
+    let chars: Vec<char> = tex.chars().collect();
+    let mut index = 0;
+    math_group(&chars, &mut index).trim().to_string()
+}
+
+/// Appends a piece of math, spaced from the letters and digits before so that typst does
+/// not read them as one name
+fn push_math(math: &mut String, piece: &str) {
+    let (Some(last), Some(first)) = (math.chars().last(), piece.chars().next()) else {
+        math.push_str(piece);
+        return;
+    };
+    if last.is_alphanumeric()
+        && first.is_alphanumeric()
+        && !(last.is_ascii_digit() && first.is_ascii_digit())
+    {
+        math.push(' ');
+    }
+    math.push_str(piece);
+}
+
+/// Math up to the end of the TeX group the index is in, the closing brace skipped
+fn math_group(chars: &[char], index: &mut usize) -> String {
+    let mut math = String::new();
+    while let Some(&c) = chars.get(*index) {
+        *index += 1;
+        match c {
+            '}' => break,
+            '{' => {
+                let group = math_group(chars, index);
+                push_math(&mut math, &group);
+            }
+            '^' | '_' => {
+                math.push(c);
+                let argument = math_argument(chars, index);
+                if argument.chars().count() == 1 {
+                    math.push_str(&argument);
+                } else {
+                    math.push_str(&format!("({argument})"));
+                }
+            }
+            '\\' => {
+                let command = math_command(chars, index);
+                push_math(&mut math, &command);
+            }
+            // a fraction and a string in typst
+            '/' | '"' => {
+                math.push('\\');
+                math.push(c);
+            }
+            c if c.is_whitespace() => {
+                if !math.ends_with(' ') {
+                    math.push(' ');
+                }
+            }
+            c => push_math(&mut math, &c.to_string()),
+        }
+    }
+    math
+}
+
+/// Math of the argument of a TeX command: a group, a command or a character
+fn math_argument(chars: &[char], index: &mut usize) -> String {
+    while chars.get(*index).is_some_and(|c| c.is_whitespace()) {
+        *index += 1;
+    }
+    let Some(&c) = chars.get(*index) else {
+        return String::new();
+    };
+    *index += 1;
+    match c {
+        '{' => math_group(chars, index).trim().to_string(),
+        '\\' => math_command(chars, index),
+        c => c.to_string(),
+    }
+}
+
+/// Text of the argument of a TeX command, `\text{...}` and the like
+fn text_argument(chars: &[char], index: &mut usize) -> String {
+    while chars.get(*index).is_some_and(|c| c.is_whitespace()) {
+        *index += 1;
+    }
+    if chars.get(*index) != Some(&'{') {
+        return String::new();
+    }
+    let mut text = String::new();
+    let mut depth = 0;
+    for &c in &chars[*index + 1..] {
+        *index += 1;
+        match c {
+            '{' => depth += 1,
+            '}' if depth == 0 => break,
+            '}' => depth -= 1,
+            _ => {}
+        }
+        text.push(c);
+    }
+    *index += 1;
+    text
+}
+
+/// Math of the TeX command after the backslash at the index
+fn math_command(chars: &[char], index: &mut usize) -> String {
+    let start = *index;
+    while chars.get(*index).is_some_and(|c| c.is_ascii_alphabetic()) {
+        *index += 1;
+    }
+    if *index == start {
+        let Some(&c) = chars.get(*index) else {
+            return String::new();
+        };
+        *index += 1;
+        return match c {
+            ',' => " thin ".to_string(),
+            ':' | '>' => " med ".to_string(),
+            ';' => " thick ".to_string(),
+            '!' => String::new(),
+            '\\' => " \\\n".to_string(),
+            c if c.is_whitespace() => " ".to_string(),
+            c => format!("\\{c}"),
+        };
+    }
+    let name: String = chars[start..*index].iter().collect();
+    let function = |name: &str, chars: &[char], index: &mut usize| {
+        format!("{name}({})", math_argument(chars, index))
+    };
+    match name.as_str() {
+        "frac" | "dfrac" | "tfrac" => {
+            let numerator = math_argument(chars, index);
+            let denominator = math_argument(chars, index);
+            format!("frac({numerator}, {denominator})")
+        }
+        "sqrt" if chars.get(*index) == Some(&'[') => {
+            let end = chars[*index..]
+                .iter()
+                .position(|&c| c == ']')
+                .map_or(chars.len(), |end| *index + end);
+            let degree: String = chars[*index + 1..end].iter().collect();
+            *index = (end + 1).min(chars.len());
+            format!(
+                "root({}, {})",
+                typst_math(&degree),
+                math_argument(chars, index)
+            )
+        }
+        "text" | "textrm" | "mbox" => string_literal(&text_argument(chars, index)),
+        "operatorname" => format!("op({})", string_literal(&text_argument(chars, index))),
+        "mathbf" | "boldsymbol" => function("bold", chars, index),
+        "mathit" => function("italic", chars, index),
+        "mathrm" => function("upright", chars, index),
+        "mathbb" => function("bb", chars, index),
+        "mathcal" => function("cal", chars, index),
+        "mathfrak" => function("frak", chars, index),
+        "mathsf" => function("sans", chars, index),
+        "mathtt" => function("mono", chars, index),
+        "bar" | "overline" => function("overline", chars, index),
+        "underline" => function("underline", chars, index),
+        "hat" | "widehat" => function("hat", chars, index),
+        "tilde" | "widetilde" => function("tilde", chars, index),
+        "vec" => function("arrow", chars, index),
+        "dot" => function("dot", chars, index),
+        "ddot" => function("dot.double", chars, index),
+        // typst sizes the delimiters itself
+        "left" | "right" | "big" | "Big" | "bigg" | "Bigg" => {
+            if chars.get(*index) == Some(&'.') {
+                *index += 1;
+            }
+            String::new()
+        }
+        // environments are left to their content, their rows and columns written alike
+        "begin" | "end" => {
+            text_argument(chars, index);
+            String::new()
+        }
+        name => MATH_COMMANDS
+            .iter()
+            .find(|(command, _)| *command == name)
+            .map_or(name, |(_, typst)| typst)
+            .to_string(),
+    }
+}
+
+/// Typst label of an anchor, the characters not allowed in labels are replaced by `-`
+fn label_name(anchor: &str) -> String {
+    anchor
+        .chars()
+        .map(|c| match c {
+            c if c.is_alphanumeric() || "-_.:".contains(c) => c,
+            _ => '-',
+        })
+        .collect()
+}
+
+/// Hidden heading making a PDF outline entry, labelled so that it can be linked to
+fn bookmark_content(id: &str, title: &str) -> String {
+    format!(
+        "#place(hide[#heading(level: 1, outlined: false, bookmarked: true)[#{}] <{}>])",
+        string_literal(title),
+        label_name(id)
+    )
+}
+
+/// Labels of the headers and bookmarks that internal links can go to, a label given to
+/// several elements is left out as typst cannot link to it
+fn link_labels(document: &Document) -> HashSet<String> {
+    fn visit(element: &Element, counts: &mut HashMap<String, usize>) {
+        let label = match element {
+            Header { text, .. } => Some(label_name(&header_anchor(text))),
+            Bookmark { id, .. } => Some(label_name(id)),
+            _ => None,
+        };
+        if let Some(label) = label.filter(|label| !label.is_empty()) {
+            *counts.entry(label).or_default() += 1;
+        }
+        for child in element.children() {
+            visit(child, counts);
+        }
+    }
+    let mut counts = HashMap::new();
+    for element in document.get_all_elements() {
+        visit(element, &mut counts);
+    }
+    counts
+        .into_iter()
+        .filter(|(_, count)| *count == 1)
+        .map(|(label, _)| label)
+        .collect()
+}
+
+/// Typst set rule for the direction of a text, `None` when it follows the document
+fn direction_rule(direction: TextDirection, text: &str) -> Option<String> {
+    let direction = match direction {
+        // right-to-left text is detected, left-to-right text follows the document
+        TextDirection::Auto if TextDirection::of_text(text) == TextDirection::Rtl => {
+            TextDirection::Rtl
+        }
+        direction => direction,
+    };
+    match direction {
+        TextDirection::Auto => None,
+        direction => Some(format!("#set text(dir: {direction})\n")),
+    }
+}
+
+/// Typst page set rule for the given page format, `margins` replaces its margins
+fn page_setup(
+    page_format: &PageFormat,
+    orientation: &PageOrientation,
+    margins: Option<Length>,
+) -> String {
+    let PageDimensions {
+        page_width,
+        page_height,
+        page_margin_top,
+        page_margin_bottom,
+        page_margin_left,
+        page_margin_right,
+    } = page_format.dimensions();
+    let [page_margin_top, page_margin_bottom, page_margin_left, page_margin_right] = match margins {
+        Some(margin) => [margin; 4],
+        None => [
+            page_margin_top,
+            page_margin_bottom,
+            page_margin_left,
+            page_margin_right,
+        ],
+    };
+    let flipped = *orientation == PageOrientation::Landscape;
+    // typst has no pixels, every length is given in points
+    let [width, height, top, bottom, left, right] = [
+        page_width,
+        page_height,
+        page_margin_top,
+        page_margin_bottom,
+        page_margin_left,
+        page_margin_right,
+    ]
+    .map(|length| length.to_pt());
+
+    format!(
+        "#set page(width: {width}pt, height: {height}pt, flipped: {flipped}, margin: (top: {top}pt, bottom: {bottom}pt, left: {left}pt, right: {right}pt))\n"
+    )
+}
+
+/// Typst page set rule drawing the watermark on every page, the image added to the files
+fn watermark_rule(watermark: &PdfWatermark, generation: &mut Generation) -> String {
+    let content = match &watermark.content {
+        WatermarkContent::Text { text, color } => format!(
+            "text(size: {}pt, fill: rgb(\"#{}\").transparentize({}%), {})",
+            watermark.size.to_pt(),
+            color.trim_start_matches('#'),
+            (1.0 - watermark.opacity.clamp(0.0, 1.0)) * 100.0,
+            string_literal(text)
+        ),
+        WatermarkContent::Image(image) => {
+            let path = match translucent(image, watermark.opacity) {
+                Ok(Some(png)) => generation.add_file("png", &png),
+                result => {
+                    if let Err(error) = result {
+                        warn!("Watermark drawn opaque: {}", error);
+                    }
+                    generation.add_file(&image.image_type().to_string(), image.bytes())
+                }
+            };
+            format!("image(\"{path}\", width: {}pt)", watermark.size.to_pt())
+        }
+    };
+    let margin = watermark.margin.to_pt();
+    let (alignment, dx, dy) = match watermark.position {
+        WatermarkPosition::Center => ("center + horizon", 0.0, 0.0),
+        WatermarkPosition::Top => ("center + top", 0.0, margin),
+        WatermarkPosition::Bottom => ("center + bottom", 0.0, -margin),
+        WatermarkPosition::TopLeft => ("left + top", margin, margin),
+        WatermarkPosition::TopRight => ("right + top", -margin, margin),
+        WatermarkPosition::BottomLeft => ("left + bottom", margin, -margin),
+        WatermarkPosition::BottomRight => ("right + bottom", -margin, -margin),
+    };
+    let layer = if watermark.behind {
+        "background"
+    } else {
+        "foreground"
+    };
+    // typst turns clockwise, the rotated size is the one placed in the corners
+    let mark = format!(
+        "place({alignment}, dx: {dx}pt, dy: {dy}pt, rotate({}deg, reflow: true, {content}))",
+        -watermark.angle
+    );
+    // not part of the structure of the document
+    let open = generation.open_tag(Tag::new("Artifact"));
+    if open.is_empty() {
+        format!("#set page({layer}: {mark})\n")
+    } else {
+        format!(
+            "#set page({layer}: [{open}#{mark}{}])\n",
+            generation.close_tag()
+        )
+    }
+}
+
+/// PNG of a stamp image with its alpha scaled by the opacity, none when opaque
+fn translucent(image: &ImageData, opacity: f32) -> anyhow::Result<Option<Vec<u8>>> {
+    if opacity >= 1.0 {
+        return Ok(None);
+    }
+    #[cfg(feature = "image")]
+    if image.image_type() != &crate::core::ImageType::SVG {
+        let mut rgba = image::load_from_memory(image.bytes())?.to_rgba8();
+        for pixel in rgba.pixels_mut() {
+            pixel[3] = (pixel[3] as f32 * opacity.max(0.0)).round() as u8;
+        }
+        let mut png = Vec::new();
+        image::DynamicImage::ImageRgba8(rgba)
+            .write_to(&mut Cursor::new(&mut png), image::ImageOutputFormat::Png)?;
+        return Ok(Some(png));
+    }
+    anyhow::bail!("no opacity for {} images", image.image_type())
+}
+
+/// Structure element of a tagged PDF opened by a marker of the typst source, the markers
+/// being invisible metadata found again in the laid out frames (see [`crate::pdf_tags`])
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tag {
+    /// Standard structure type (`P`, `H1`, `L`, `TD`...), `Artifact` for the content that
+    /// is not part of the document such as page headers
+    pub role: String,
+    /// Alternative text of a figure
+    pub alt: Option<String>,
+    /// Row of a table cell, the cells of a row being grouped in a `TR`
+    pub row: Option<usize>,
+    /// Index of the tag it is in, none for the tags of the document
+    pub parent: Option<usize>,
+}
+
+impl Tag {
+    fn new(role: &str) -> Tag {
+        Tag {
+            role: role.to_string(),
+            alt: None,
+            row: None,
+            parent: None,
+        }
+    }
+}
+
+/// Prefix of the values of the markers, followed by the index of their tag
+pub const TAG_MARKER: &str = "shiva-tag:";
+/// Value of the marker closing the last tag opened
+pub const TAG_END: &str = "shiva-tag:end";
+
+/// State of the generation shared by the elements
+struct Generation {
+    /// Typst source of its own rather than the one compiled to PDF: with typst math and
+    /// figures
+    standalone: bool,
+    /// Images by the file name they are referenced with
+    img_map: HashMap<String, typst::foundations::Bytes>,
+    /// Labels that internal links can go to, see [`link_labels`]
+    labels: HashSet<String>,
+    /// Tags of the structure of a tagged PDF, none when it is not tagged
+    tags: Option<Vec<Tag>>,
+    /// Indexes of the tags opened and not yet closed
+    open_tags: Vec<usize>,
+    /// Whether the element processed is the one of a list item
+    item_start: bool,
+}
+
+impl Generation {
+    /// Marker opening a tag around the content that follows, nothing when not tagged
+    fn open_tag(&mut self, tag: Tag) -> String {
+        match &mut self.tags {
+            Some(tags) => {
+                tags.push(Tag {
+                    parent: self.open_tags.last().copied(),
+                    ..tag
+                });
+                self.open_tags.push(tags.len() - 1);
+                format!("#metadata(\"{TAG_MARKER}{}\")", tags.len() - 1)
+            }
+            None => String::new(),
+        }
+    }
+
+    /// Marker closing the last tag opened, nothing when not tagged
+    fn close_tag(&mut self) -> String {
+        match self.open_tags.pop() {
+            Some(_) => format!("#metadata(\"{TAG_END}\")"),
+            None => String::new(),
+        }
+    }
+
+    /// Marker of a tag opened on a line of its own before a block
+    fn open_block(&mut self, tag: Tag) -> String {
+        let open = self.open_tag(tag);
+        if open.is_empty() {
+            open
+        } else {
+            open + "\n"
+        }
+    }
+
+    /// Marker closing a tag on a line of its own after a block
+    fn close_block(&mut self) -> String {
+        let close = self.close_tag();
+        if close.is_empty() {
+            close
+        } else {
+            close + "\n"
+        }
+    }
+
+    /// File name of an image, the same for the same bytes so that typst embeds each
+    /// image once however often it is shown
+    fn add_file(&mut self, extension: &str, bytes: &[u8]) -> String {
+        let mut hasher = DefaultHasher::new();
+        bytes.hash(&mut hasher);
+        let hash = hasher.finish();
+        let mut key = format!("image-{hash:016x}.{extension}");
+        let mut suffix = 1;
+        // different bytes of the same hash
+        while let Some(file) = self.img_map.get(&key) {
+            if file.as_slice() == bytes {
+                return key;
+            }
+            suffix += 1;
+            key = format!("image-{hash:016x}-{suffix}.{extension}");
+        }
+        self.img_map
+            .insert(key.clone(), typst::foundations::Bytes::from(bytes.to_vec()));
+        key
     }
+}
+
+/// Lays out the pages of the document with typst, the layout given to the PDF and to the
+/// previews of its pages
+pub fn layout(document: &Document, options: &PdfOptions) -> anyhow::Result<typst::model::Document> {
+    let (text, img_map, _) = generate_source(document, options, false, false)?;
+    compile(text, img_map, options)
+}
 
-    fn generate(document: &Document) -> anyhow::Result<bytes::Bytes> {
-        let (text, _) = generate_document(document)?;
-        let bytes = Bytes::from(text);
-        Ok(bytes)
+/// Layout of the document with the markers of the tags of its structure, for a tagged PDF
+/// (see [`crate::pdf_tags`]). The markers are invisible, the pages are the same as the ones
+/// of [`layout`].
+pub fn layout_tagged(
+    document: &Document,
+    options: &PdfOptions,
+) -> anyhow::Result<(typst::model::Document, Vec<Tag>)> {
+    let (text, img_map, tags) = generate_source(document, options, false, true)?;
+    Ok((compile(text, img_map, options)?, tags))
+}
+
+fn compile(
+    text: TypstString,
+    img_map: HashMap<String, typst::foundations::Bytes>,
+    options: &PdfOptions,
+) -> anyhow::Result<typst::model::Document> {
+    let world = ShivaWorld::new(text, img_map).with_fonts(&options.fonts)?;
+    let mut tracer = Tracer::default();
+
+    let layout = typst::compile(&world, &mut tracer).map_err(|errors| {
+        let messages: Vec<_> = errors.iter().map(|error| error.message.as_str()).collect();
+        anyhow::anyhow!("Layout error: {}", messages.join(", "))
+    })?;
+    for warning in tracer.warnings() {
+        warn!("Warning - {}", warning.message);
     }
+    Ok(layout)
 }
 
 /// Converts Document into a typst::model::Document
 pub fn generate_document(
     document: &Document,
+    options: &PdfOptions,
 ) -> anyhow::Result<(TypstString, HashMap<String, typst::foundations::Bytes>)> {
+    let (source, img_map, _) = generate_source(document, options, false, false)?;
+    Ok((source, img_map))
+}
+
+/// Typst source of the document, the images it refers to by file name and the tags of its
+/// structure when `tagged`. The source compiled to PDF keeps the math as TeX code, typst
+/// failing on the commands it does not know, and shows the images without caption.
+fn generate_source(
+    document: &Document,
+    options: &PdfOptions,
+    standalone: bool,
+    tagged: bool,
+) -> anyhow::Result<(
+    TypstString,
+    HashMap<String, typst::foundations::Bytes>,
+    Vec<Tag>,
+)> {
     // Array of methods to process Document object into a typst string repr
     fn process_header(source: &mut TypstString, level: usize, text: &str) -> anyhow::Result<()> {
         let header_depth = "=".repeat(level);
-        let header_text = format!("{header_depth} {text}");
+        let header_text = format!("{header_depth} {}", markup_escape(text));
         source.push_str(&header_text);
+        // the label of the anchor of the header, the target of internal links
+        let label = label_name(&header_anchor(text));
+        if !label.is_empty() {
+            source.push_str(&format!(" <{label}>"));
+        }
         source.push('\n');
 
         Ok(())
@@ -212,141 +954,323 @@ pub fn generate_document(
         text: &str,
         is_bold: bool,
     ) -> anyhow::Result<()> {
+        let text = markup_escape(text);
         if is_bold {
             let bold_text = format!("*{text}*");
             source.push_str(&bold_text);
         } else {
-            source.push_str(text);
+            source.push_str(&text);
         }
 
         Ok(())
     }
 
-    fn process_link(source: &mut TypstString, url: &str) -> anyhow::Result<()> {
-        let link = format!("#link(\"{url}\")");
+    /// Start of a link to an URL, or to a label for `#anchor` URLs. An anchor without a
+    /// label to go to is not linked.
+    fn process_link(
+        source: &mut TypstString,
+        labels: &HashSet<String>,
+        url: &str,
+    ) -> anyhow::Result<bool> {
+        let target = match url.strip_prefix('#') {
+            Some(anchor) => {
+                let label = label_name(anchor);
+                if !labels.contains(&label) {
+                    warn!("No header or bookmark to link to: {}", url);
+                    return Ok(false);
+                }
+                format!("<{label}>")
+            }
+            None => string_literal(url),
+        };
+        source.push_str(&format!("#link({target})"));
 
-        source.push_str(&link);
+        Ok(true)
+    }
 
-        Ok(())
+    /// Opens the tag of a list before the line breaks ending the source: a list with no
+    /// blank line before it is laid out closer to what precedes, which the marker must not
+    /// change
+    fn open_list(source: &mut TypstString, generation: &mut Generation) {
+        let open = generation.open_tag(Tag::new("L"));
+        if open.is_empty() {
+            return;
+        }
+        let end = source.trim_end_matches('\n').len();
+        source.insert_str(end, &open);
+        if end + open.len() == source.len() {
+            source.push('\n');
+        }
     }
 
+    /// Table filling the width of the page, the header widths being the proportions of the
+    /// columns. Long cells wrap and a table taller than the page goes on with its header
+    /// repeated on the next page.
     fn process_table(
         source: &mut TypstString,
-        headers: &Vec<TableHeader>,
-        rows: &Vec<TableRow>,
+        generation: &mut Generation,
+        headers: &[TableHeader],
+        rows: &[TableRow],
     ) -> anyhow::Result<()> {
-        let mut headers_text = TypstString::new();
-
-        for header in headers {
-            match &header.element {
-                Text { text, size } => {
-                    headers_text.push('[');
-                    process_text(&mut headers_text, *size, text, true)?;
-                    headers_text.push(']');
-                    headers_text.push(',');
-                }
-                _ => {
-                    warn!(
-                        "Should implement element for processing in inside table header - {:?}",
-                        header.element
-                    );
+        fn cell_content(generation: &mut Generation, element: &Element) -> anyhow::Result<String> {
+            let mut content = TypstString::new();
+            match element {
+                // blocks of an HTML cell
+                Element::Container { elements, .. } => {
+                    for element in elements {
+                        process_element(&mut content, generation, element)?;
+                    }
                 }
+                element => process_element(&mut content, generation, element)?,
             }
+            Ok(content)
         }
 
-        let mut cells_text = TypstString::new();
-
-        for row in rows {
+        let column_count = headers
+            .len()
+            .max(rows.iter().map(|row| row.cells.len()).max().unwrap_or(0));
+        if column_count == 0 {
+            return Ok(());
+        }
+        let columns: Vec<String> = (0..column_count)
+            .map(|index| match headers.get(index) {
+                Some(header) if header.width > 0.0 => format!("{}fr", header.width),
+                _ => "1fr".to_string(),
+            })
+            .collect();
+
+        let mut table_text = generation.open_block(Tag::new("Table"));
+        table_text.push_str(&format!(
+            "#table(\ncolumns: ({},),\nstroke: 0.5pt,\ninset: 5pt,\n",
+            columns.join(", ")
+        ));
+        if !headers.is_empty() {
+            table_text.push_str("table.header(\n");
+            for header in headers {
+                let open = generation.open_tag(Tag {
+                    row: Some(0),
+                    ..Tag::new("TH")
+                });
+                let content = cell_content(generation, &header.element)?;
+                table_text.push_str(&format!(
+                    "[{open}#strong[{}]{}],\n",
+                    content.trim_end(),
+                    generation.close_tag()
+                ));
+            }
+            table_text.push_str("),\n");
+        }
+        for (index, row) in rows.iter().enumerate() {
             for cell in &row.cells {
-                match &cell.element {
-                    Text { text, size } => {
-                        cells_text.push('[');
-                        process_text(&mut cells_text, *size, text, false)?;
-                        cells_text.push(']');
-                        cells_text.push(',');
-                    }
-                    _ => {
-                        warn!(
-                            "Should implement element for processing in inside cell - {:?}",
-                            cell.element
-                        );
-                    }
-                }
+                let open = generation.open_tag(Tag {
+                    row: Some(index + 1),
+                    ..Tag::new("TD")
+                });
+                let content = cell_content(generation, &cell.element)?;
+                table_text.push_str(&format!(
+                    "[{open}{}{}],",
+                    content.trim_end(),
+                    generation.close_tag()
+                ));
             }
-
-            cells_text.push('\n');
+            // a short row is completed so that the next row starts on a new line
+            for _ in row.cells.len()..column_count {
+                table_text.push_str("[],");
+            }
+            table_text.push('\n');
         }
+        table_text.push_str(")\n");
 
-        let columns = headers.len();
-        let table_text = format!(
-            r#"
-        #table(
-            columns:{columns},
-            {headers_text}
-            {cells_text}
-        )
-        "#
-        );
-
+        table_text.push_str(&generation.close_block());
         source.push_str(&table_text);
         Ok(())
     }
 
+    /// Items of a list, the lines of an item indented under its marker and the nested
+    /// lists under the item before
     fn process_list(
         source: &mut TypstString,
-        img_map: &mut HashMap<String, typst::foundations::Bytes>,
+        generation: &mut Generation,
         list: &Vec<ListItem>,
         numbered: bool,
         depth: usize,
     ) -> anyhow::Result<()> {
-        source.push_str(&" ".repeat(depth));
-        for el in list {
-            if let List { elements, numbered } = &el.element {
-                process_list(source, img_map, elements, *numbered, depth + 1)?;
+        let indent = "  ".repeat(depth);
+        for (index, el) in list.iter().enumerate() {
+            if let List {
+                elements,
+                numbered,
+                start,
+                numbering,
+            } = &el.element
+            {
+                // a nested list is tagged in the item before it, opened at the end of the
+                // line of the item and closed with the item after the list
+                let in_item = index > 0 && !matches!(list[index - 1].element, List { .. });
+                if !in_item {
+                    open_list(source, generation);
+                }
+                process_numbered_list(
+                    source,
+                    generation,
+                    elements,
+                    *numbered,
+                    *start,
+                    numbering,
+                    depth + 1,
+                )?;
+                let mut close = generation.close_tag();
+                if in_item {
+                    close.push_str(&generation.close_tag());
+                    close.push_str(&generation.close_tag());
+                }
+                if !close.is_empty() {
+                    source.push_str(&format!("{indent}  {close}\n"));
+                }
             } else {
-                if numbered {
-                    source.push_str("+ ")
-                } else {
-                    source.push_str("- ")
+                let open =
+                    generation.open_tag(Tag::new("LI")) + &generation.open_tag(Tag::new("LBody"));
+                let mut item = TypstString::new();
+                generation.item_start = true;
+                process_element(&mut item, generation, &el.element)?;
+                let marker = if numbered { "+" } else { "-" };
+                let lines: Vec<&str> = item.trim().lines().collect();
+                let close = match list.get(index + 1) {
+                    Some(ListItem {
+                        element: List { .. },
+                    }) => generation.open_tag(Tag::new("L")),
+                    _ => generation.close_tag() + &generation.close_tag(),
                 };
-
-                process_element(source, img_map, &el.element)?;
+                source.push_str(&format!(
+                    "{indent}{marker} {open}{}{close}\n",
+                    lines.join(&format!("\n{indent}  "))
+                ));
             }
         }
 
         Ok(())
     }
 
+    fn process_numbered_list(
+        source: &mut TypstString,
+        generation: &mut Generation,
+        list: &Vec<ListItem>,
+        numbered: bool,
+        start: usize,
+        numbering: &NumberingStyle,
+        depth: usize,
+    ) -> anyhow::Result<()> {
+        if !numbered || (start == 1 && *numbering == NumberingStyle::Decimal) {
+            return process_list(source, generation, list, numbered, depth);
+        }
+
+        let pattern = match numbering {
+            NumberingStyle::Decimal => "1.",
+            NumberingStyle::LowerAlpha => "a.",
+            NumberingStyle::UpperAlpha => "A.",
+            NumberingStyle::LowerRoman => "i.",
+            NumberingStyle::UpperRoman => "I.",
+        };
+        // scope the set rule so it does not leak into the following lists
+        let indent = "  ".repeat(depth);
+        source.push_str(&format!(
+            "{indent}#[\n{indent}#set enum(numbering: \"{pattern}\", start: {start})\n"
+        ));
+        process_list(source, generation, list, numbered, depth)?;
+        source.push_str(&format!("{indent}]\n"));
+
+        Ok(())
+    }
+
+    /// Image, in a figure captioned with its title in a typst source of its own
     fn process_image(
         source: &mut TypstString,
-        bytes: &Bytes,
-        title: &str,
-        alt: &str,
-        image_type: &str,
+        generation: &mut Generation,
+        image: &ImageData,
     ) -> anyhow::Result<()> {
-        if !bytes.is_empty() {
-            let image_text = format!(
-                "
-            #image(\"{title}{image_type}\", alt: \"{alt}\")
-            "
-            );
-            source.push_str(&image_text);
-        }
-        // need to think how to implement using raw bytes
+        if image.bytes().is_empty() {
+            return Ok(());
+        }
+        let path = generation.add_file(&image.image_type().to_string(), image.bytes());
+        let size = image.size();
+        let dimensions: String = [("width", &size.width), ("height", &size.height)]
+            .into_iter()
+            .filter_map(|(name, value)| {
+                let length = image_length(value.as_deref()?)?;
+                Some(format!(", {name}: {length}"))
+            })
+            .collect();
+        let image_text = format!(
+            "image({}, alt: {}{dimensions})",
+            string_literal(&path),
+            string_literal(image.alt())
+        );
+        if generation.standalone && !image.title().is_empty() {
+            source.push_str(&format!(
+                "#figure({image_text}, caption: [{}])\n",
+                markup_escape(image.title())
+            ));
+        } else {
+            // an image without alternative text is taken as decoration
+            let open = generation.open_tag(Tag {
+                alt: Some(image.alt().to_string()),
+                ..Tag::new(if image.alt().is_empty() {
+                    "Artifact"
+                } else {
+                    "Figure"
+                })
+            });
+            source.push_str(&format!("{open}#{image_text}{}\n", generation.close_tag()));
+        }
         Ok(())
     }
 
     fn process_element(
         source: &mut TypstString,
-        img_map: &mut HashMap<String, typst::foundations::Bytes>,
+        generation: &mut Generation,
         element: &Element,
     ) -> anyhow::Result<()> {
+        let item_start = std::mem::take(&mut generation.item_start);
         match element {
-            Header { level, text } => process_header(source, *level as usize, text),
-            Paragraph { elements } => {
+            Header { level, text } => {
+                let tag = Tag::new(&format!("H{}", (*level).clamp(1, 6)));
+                // the header of a list item is on the line of the item, where the marker
+                // cannot go on a line of its own
+                if item_start {
+                    source.push_str(&generation.open_tag(tag));
+                } else {
+                    source.push_str(&generation.open_block(tag));
+                }
+                match direction_rule(TextDirection::Auto, text) {
+                    Some(rule) => {
+                        source.push_str(&format!("#[\n{rule}"));
+                        process_header(source, *level as usize, text)?;
+                        source.push_str("]\n");
+                    }
+                    None => process_header(source, *level as usize, text)?,
+                }
+                source.push_str(&generation.close_block());
+                Ok(())
+            }
+            Paragraph {
+                elements,
+                direction,
+            } => {
+                source.push_str(&generation.open_tag(Tag::new("P")));
+                // scope the set rule so it does not leak into the following elements
+                let rule = direction_rule(*direction, &element.plain_text());
+                if let Some(rule) = &rule {
+                    source.push_str(&format!("#[\n{rule}"));
+                }
                 for paragraph_element in elements {
-                    process_element(source, img_map, paragraph_element)?;
+                    process_element(source, generation, paragraph_element)?;
                 }
+                if rule.is_some() {
+                    source.push_str("]\n");
+                }
+                source.push_str(&generation.close_block());
+                // a blank line ends the paragraph
+                source.push('\n');
 
                 Ok(())
             }
@@ -356,83 +1280,341 @@ pub fn generate_document(
 
                 Ok(())
             }
-            List { elements, numbered } => {
-                process_list(source, img_map, elements, *numbered, 0)?;
+            List {
+                elements,
+                numbered,
+                start,
+                numbering,
+            } => {
+                open_list(source, generation);
+                process_numbered_list(
+                    source, generation, elements, *numbered, *start, numbering, 0,
+                )?;
+                source.push_str(&generation.close_block());
+                Ok(())
+            }
+            LineBreak => {
+                source.push_str("#linebreak()\n");
+
+                Ok(())
+            }
+            Field { kind } => {
+                source.push_str(&field_content(kind));
+                source.push('\n');
+
+                Ok(())
+            }
+            // replaced by text in Document::resolve_fields
+            CrossReference { .. } => Ok(()),
+            // unwrapped in Document::without_attributes
+            Attributed { .. } => Ok(()),
+            // flattened in Document::without_containers
+            Element::Container { .. } => Ok(()),
+            // replaced by a link in Document::without_media
+            Element::Media { .. } => Ok(()),
+            // TeX code in the source compiled to PDF, see Document::without_math
+            Element::Math { tex, display } => {
+                let math = typst_math(tex);
+                if *display {
+                    source.push_str(&format!("$ {math} $\n"));
+                } else {
+                    source.push_str(&format!("${math}$\n"));
+                }
+
+                Ok(())
+            }
+            Element::Drawing {
+                width,
+                height,
+                shapes,
+            } => {
+                // typst draws SVG images as vector graphics
+                let svg = crate::drawing::svg(*width, *height, shapes);
+                let key = generation.add_file("svg", svg.as_bytes());
+                // a drawing has no alternative text, it is taken as decoration
+                let open = generation.open_tag(Tag::new("Artifact"));
+                source.push_str(&format!(
+                    "{open}#image(\"{key}\", width: {width}pt, height: {height}pt){}\n",
+                    generation.close_tag()
+                ));
+
+                Ok(())
+            }
+            Bookmark { id, title } => {
+                source.push_str(&bookmark_content(id, title));
+                source.push('\n');
+
+                Ok(())
+            }
+            TableOfContents { max_level } => {
+                // typst builds the outline from the headings, entries are clickable. Its
+                // entries are not told apart, the outline is a division of their links.
+                source.push_str(&generation.open_block(Tag::new("Div")));
+                source.push_str(&format!("#outline(depth: {max_level})\n"));
+                source.push_str(&generation.close_block());
+
                 Ok(())
             }
             Hyperlink {
-                url,
-                title: _,
-                alt: _,
-                size: _,
+                url, elements, alt, ..
             } => {
-                process_link(source, url)?;
+                let mut link = TypstString::new();
+                let linked = process_link(&mut link, &generation.labels, url)?;
+                // the annotations of the link are given to its tag
+                if linked {
+                    source.push_str(&generation.open_tag(Tag {
+                        alt: Some(if alt.is_empty() { url } else { alt }.to_string()),
+                        ..Tag::new("Link")
+                    }));
+                }
+                source.push_str(&link);
+                if !elements.is_empty() {
+                    source.push_str(if linked { "[" } else { "#[" });
+                    for link_element in elements {
+                        process_element(source, generation, link_element)?;
+                    }
+                    source.push(']');
+                }
+                if linked {
+                    source.push_str(&generation.close_tag());
+                }
                 source.push('\n');
 
                 Ok(())
             }
-            Table { headers, rows } => {
-                process_table(source, headers, rows)?;
+            InlineCode { text } => {
+                source.push_str(&format!("#raw({})\n", string_literal(text)));
+
                 Ok(())
             }
-            Image(image) => {
-                let key = format!("{}{}", image.title(), image.image_type());
-                img_map.insert(key, typst::foundations::Bytes::from(image.bytes().to_vec()));
-                process_image(
-                    source,
-                    image.bytes(),
-                    image.title(),
-                    image.alt(),
-                    &image.image_type().to_string(),
-                )?;
+            Raw {
+                format: DocumentType::Typst,
+                content,
+            } => {
+                source.push_str(content.trim_end());
                 source.push('\n');
+
+                Ok(())
+            }
+            // content of another format
+            Raw { .. } => Ok(()),
+            CodeBlock { code, .. } => {
+                let lang = element
+                    .code_language()
+                    .map(|language| format!(", lang: {}", string_literal(language)))
+                    .unwrap_or_default();
+                source.push_str(&generation.open_block(Tag::new("Code")));
+                source.push_str(&format!(
+                    "#raw(block: true{}, {})\n",
+                    lang,
+                    string_literal(code)
+                ));
+                source.push_str(&generation.close_block());
+
+                Ok(())
+            }
+            Styled { style, elements } => {
+                let function = match style {
+                    TextStyle::Emphasis => "emph",
+                    TextStyle::Strong => "strong",
+                    TextStyle::Strikethrough => "strike",
+                };
+                source.push_str(&format!("#{}[", function));
+                for styled_element in elements {
+                    process_element(source, generation, styled_element)?;
+                }
+                source.push_str("]\n");
+
+                Ok(())
+            }
+            Footnote { elements } => {
+                source.push_str("#footnote[");
+                source.push_str(&generation.open_tag(Tag::new("Note")));
+                for note_element in elements {
+                    process_element(source, generation, note_element)?;
+                }
+                source.push_str(&generation.close_tag());
+                source.push_str("]\n");
+
                 Ok(())
-            } // _ => {
-              //     warn!("Should implement element - {:?}", element);
-              //     Ok(())
-              // }
+            }
+            Table { headers, rows } => {
+                process_table(source, generation, headers, rows)?;
+                Ok(())
+            }
+            Image(image) => process_image(source, generation, image), // _ => {
+                                                                      //     warn!("Should implement element - {:?}", element);
+                                                                      //     Ok(())
+                                                                      // }
         }
     }
 
+    let document = document
+        .resolve_fields()
+        .without_attributes()
+        .without_containers()
+        .without_media();
+    let document = &if standalone {
+        document
+    } else {
+        document.without_math()
+    };
     // String to build off of
     let mut source = TypstString::new();
-    // Mapping of connections between elements
-    let mut img_map: HashMap<String, typst::foundations::Bytes> = HashMap::new();
-
-    // Converting both headers and footers into a string repr of them in Typst
-    let mut header_text = String::new();
-    document.get_page_header().iter().for_each(|el| match el {
-        Text { text, size: _ } => {
-            header_text.push_str(text);
-        }
-        _ => {}
-    });
-    let mut footer_text = String::new();
-    document.get_page_footer().iter().for_each(|el| match el {
-        Text { text, size: _ } => {
-            footer_text.push_str(text);
+    let mut generation = Generation {
+        standalone,
+        img_map: HashMap::new(),
+        labels: link_labels(document),
+        tags: tagged.then(Vec::new),
+        open_tags: Vec::new(),
+        item_start: false,
+    };
+
+    // Converting both headers and footers into a content repr of them in Typst
+    let title = document.get_title_text();
+    let option_band = |elements: &Option<Vec<Element>>| {
+        elements
+            .as_ref()
+            .map(|elements| band_content(&elements.iter().collect::<Vec<_>>(), &title))
+    };
+    let header_text = page_band(
+        band_content(&document.get_page_header(), &title),
+        option_band(&options.first_page_header),
+        option_band(&options.even_page_header),
+    );
+    let footer_text = page_band(
+        band_content(&document.get_page_footer(), &title),
+        option_band(&options.first_page_footer),
+        option_band(&options.even_page_footer),
+    );
+    // the page headers and footers are not part of the structure of the document
+    let artifact = |band: String, generation: &mut Generation| {
+        let open = generation.open_tag(Tag::new("Artifact"));
+        if open.is_empty() {
+            band
+        } else {
+            format!("[{open}#({band}){}]", generation.close_tag())
         }
-        _ => {}
-    });
+    };
+    let header_text = artifact(header_text, &mut generation);
+    let footer_text = artifact(footer_text, &mut generation);
     let footer_header_text = format!(
         "#set page(
-        header: \"{header_text}\",
-        footer: \"{footer_text}\",
+        header: {header_text},
+        footer: {footer_text},
     )\n"
     );
 
     // Converting Document repr to one of typst string
+    if options.archival && !title.is_empty() {
+        source.push_str(&format!(
+            "#set document(title: {})\n",
+            string_literal(&title)
+        ));
+    }
     source.push_str(&footer_header_text);
-    for element in &document.get_all_elements() {
-        process_element(&mut source, &mut img_map, element)?;
+    if let Some(watermark) = &options.watermark {
+        source.push_str(&watermark_rule(watermark, &mut generation));
+    }
+    source.push_str(&page_setup(
+        &document.page_format,
+        &document.orientation,
+        options.margins,
+    ));
+    if let Some(font_size) = options.font_size {
+        source.push_str(&format!("#set text(size: {font_size}pt)\n"));
+    }
+    // the headers are the outline entries, deeper headers are left out of it
+    if let Some(depth) = options.outline_depth {
+        if depth == 0 {
+            source.push_str("#set heading(bookmarked: false)\n");
+        } else {
+            for level in depth + 1..=6 {
+                source.push_str(&format!(
+                    "#show heading.where(level: {level}): set heading(bookmarked: false)\n"
+                ));
+            }
+        }
+    }
+    if !options.font_families.is_empty() {
+        let families: String = options
+            .font_families
+            .iter()
+            .map(|family| format!("{},", string_literal(family)))
+            .collect();
+        source.push_str(&format!("#set text(font: ({families}))\n"));
+    }
+    if let Some(rule) = direction_rule(document.direction, "") {
+        source.push_str(&rule);
+    }
+    let mut detail_index = 0;
+    for band in &document.bands {
+        for element in band.elements() {
+            if let Band::Detail(_) = band {
+                // a page set rule in the middle of the flow starts a new page
+                if let Some(section) = document
+                    .get_sections()
+                    .iter()
+                    .find(|section| section.start == detail_index)
+                {
+                    source.push_str(&page_setup(
+                        &section.page_format,
+                        &section.orientation,
+                        options.margins,
+                    ));
+                }
+                detail_index += 1;
+            }
+            // the text outside of a paragraph is a paragraph of the structure, like the
+            // typst markup written in the flow
+            let inline = element.is_inline()
+                || matches!(
+                    element,
+                    Raw {
+                        format: DocumentType::Typst,
+                        ..
+                    }
+                );
+            if inline {
+                source.push_str(&generation.open_tag(Tag::new("P")));
+                process_element(&mut source, &mut generation, element)?;
+                source.push_str(&generation.close_tag());
+            } else {
+                process_element(&mut source, &mut generation, element)?;
+            }
+        }
     }
 
-    Ok((source, img_map))
+    Ok((
+        source,
+        generation.img_map,
+        generation.tags.unwrap_or_default(),
+    ))
+}
+
+/// Typst length of an image width or height: a number of CSS pixels (`200` or `200px`), a
+/// percentage of the available width, or a length with a typst unit
+fn image_length(value: &str) -> Option<String> {
+    let value = value.trim();
+    let number_end = value
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(value.len());
+    let number: f64 = value[..number_end].parse().ok()?;
+    match &value[number_end..] {
+        // 96 CSS pixels to the inch
+        "" | "px" => Some(format!("{}pt", number * 0.75)),
+        unit @ ("%" | "pt" | "mm" | "cm" | "in" | "em") => Some(format!("{}{}", number, unit)),
+        _ => None,
+    }
 }
 
 #[cfg(test)]
 mod test {
-    use crate::core::{disk_image_loader, TransformerWithImageLoaderSaverTrait};
+    use crate::core::tests::output_dir;
+    use crate::core::{
+        disk_image_loader, ImageData, ImageDimension, TableCell, TableRow,
+        TransformerWithImageLoaderSaverTrait,
+    };
     use crate::markdown;
     use bytes::Bytes;
 
@@ -445,8 +1627,11 @@ mod test {
             &documents_bytes,
             disk_image_loader("test/data"),
         )?;
-        let generated_result = crate::typst::Transformer::generate(&parsed)?;
-        std::fs::write("test/data/document_from_md.typ", generated_result)?;
+        let generated_result = Transformer::generate_with_saver(&parsed, |_, _| Ok(()))?;
+        std::fs::write(
+            format!("{}/document_from_md.typ", output_dir()),
+            generated_result,
+        )?;
 
         Ok(())
     }
@@ -456,9 +1641,227 @@ mod test {
         let document = std::fs::read("test/data/document.xml")?;
         let documents_bytes = Bytes::from(document);
         let parsed = crate::xml::Transformer::parse(&documents_bytes)?;
-        let generated_result = crate::typst::Transformer::generate(&parsed)?;
-        std::fs::write("test/data/document_from_xml.typ", generated_result)?;
+        let generated_result = Transformer::generate_with_saver(&parsed, |_, _| Ok(()))?;
+        std::fs::write(
+            format!("{}/document_from_xml.typ", output_dir()),
+            generated_result,
+        )?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_generate_source() -> anyhow::Result<()> {
+        let image = std::fs::read("test/data/image0.png")?;
+        let document = Document::new(vec![
+            Header {
+                level: 1,
+                text: "Results".to_string(),
+            },
+            Paragraph {
+                elements: vec![
+                    Text {
+                        text: "Cost of #1 is 5$ * 2, see ".to_string(),
+                        size: 10,
+                    },
+                    Element::Math {
+                        tex: r"\frac{a}{b} \cdot xy".to_string(),
+                        display: false,
+                    },
+                ],
+                direction: TextDirection::Auto,
+            },
+            Element::Math {
+                tex: r"\sum_{i=1}^{n} \alpha_i \le \sqrt[3]{\text{max}}".to_string(),
+                display: true,
+            },
+            List {
+                elements: vec![
+                    ListItem {
+                        element: Text {
+                            text: "- first".to_string(),
+                            size: 10,
+                        },
+                    },
+                    ListItem {
+                        element: List {
+                            elements: vec![ListItem {
+                                element: Text {
+                                    text: "nested".to_string(),
+                                    size: 10,
+                                },
+                            }],
+                            numbered: true,
+                            start: 3,
+                            numbering: NumberingStyle::LowerAlpha,
+                        },
+                    },
+                ],
+                numbered: false,
+                start: 1,
+                numbering: NumberingStyle::Decimal,
+            },
+            Image(ImageData::new(
+                Bytes::from(image),
+                "Chart".to_string(),
+                "Sales chart".to_string(),
+                "png".to_string(),
+                "".to_string(),
+                ImageDimension::default(),
+            )),
+            Raw {
+                format: DocumentType::Typst,
+                content: "#lorem(5)".to_string(),
+            },
+        ]);
+
+        let saved = std::cell::RefCell::new(vec![]);
+        let generated = Transformer::generate_with_saver(&document, |_, name| {
+            saved.borrow_mut().push(name.to_string());
+            Ok(())
+        })?;
+        let source = String::from_utf8(generated.to_vec())?;
+        assert!(source.contains("= Results <results>"));
+        assert!(source.contains(r"Cost of \#1 is 5\$ \* 2, see"));
+        assert!(source.contains("$frac(a, b) dot.op x y$"));
+        assert!(source.contains("$ sum_(i=1)^n alpha_i <= root(3, \"max\") $"));
+        assert!(source.contains(
+            "- \\- first\n  #[\n  #set enum(numbering: \"a.\", start: 3)\n  + nested\n  ]"
+        ));
+        let name = &saved.borrow()[0];
+        assert!(source.contains(&format!(
+            "#figure(image(\"{name}\", alt: \"Sales chart\"), caption: [Chart])"
+        )));
+        assert!(source.contains("#lorem(5)"));
+
+        // the source compiles, the PDF keeping the math as code
+        let (_, img_map, _) = generate_source(&document, &PdfOptions::default(), true, false)?;
+        let world = ShivaWorld::new(source, img_map);
+        let layout = typst::compile(&world, &mut Tracer::default());
+        assert!(layout.is_ok(), "{:?}", layout.err());
+        let (pdf_source, _) = generate_document(&document, &PdfOptions::default())?;
+        assert!(pdf_source.contains("#raw(\"\\\\frac{a}{b} \\\\cdot xy\")"));
+        Ok(())
+    }
 
+    #[test]
+    fn test_tagged_layout() -> anyhow::Result<()> {
+        use typst::layout::{Frame, FrameItem, Point};
+
+        /// Texts and images drawn, with their place on the page to the thousandth of a point
+        fn drawn(frame: &Frame, origin: Point, items: &mut Vec<String>) {
+            for (position, item) in frame.items() {
+                let position = origin + *position;
+                let place = format!("{:.3} {:.3}", position.x.to_pt(), position.y.to_pt());
+                match item {
+                    FrameItem::Group(group) => drawn(&group.frame, position, items),
+                    FrameItem::Text(text) => items.push(format!("{} {place}", text.text)),
+                    FrameItem::Image(..) => items.push(format!("image {place}")),
+                    _ => {}
+                }
+            }
+        }
+        let pages = |layout: &typst::model::Document| {
+            layout
+                .pages
+                .iter()
+                .map(|page| {
+                    let mut items = Vec::new();
+                    drawn(&page.frame, Point::zero(), &mut items);
+                    items
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let markdown = std::fs::read("test/data/document.md")?;
+        let mut document = markdown::Transformer::parse_with_loader(
+            &Bytes::from(markdown),
+            disk_image_loader("test/data"),
+        )?;
+        let text = |text: &str| Text {
+            text: text.to_string(),
+            size: 10,
+        };
+        for element in [
+            TableOfContents { max_level: 2 },
+            Paragraph {
+                elements: vec![
+                    text("Note"),
+                    Footnote {
+                        elements: vec![text("in the footer")],
+                    },
+                ],
+                direction: TextDirection::Auto,
+            },
+            List {
+                elements: vec![
+                    ListItem {
+                        element: text("item"),
+                    },
+                    ListItem {
+                        element: List {
+                            elements: vec![ListItem {
+                                element: text("nested"),
+                            }],
+                            numbered: true,
+                            start: 2,
+                            numbering: NumberingStyle::LowerRoman,
+                        },
+                    },
+                ],
+                numbered: false,
+                start: 1,
+                numbering: NumberingStyle::Decimal,
+            },
+            text("after the list"),
+            Table {
+                headers: vec![],
+                rows: vec![TableRow {
+                    cells: vec![TableCell {
+                        element: Header {
+                            level: 3,
+                            text: "In a cell".to_string(),
+                        },
+                    }],
+                }],
+            },
+        ] {
+            document.add_element(element);
+        }
+        let options = PdfOptions {
+            first_page_header: Some(vec![text("Header")]),
+            watermark: Some(crate::core::PdfWatermark::text("Draft")),
+            ..Default::default()
+        };
+
+        // the markers of the tags change nothing to the pages
+        let (tagged, tags) = layout_tagged(&document, &options)?;
+        assert!(tags.iter().any(|tag| tag.role == "Table"));
+        assert_eq!(pages(&tagged), pages(&layout(&document, &options)?));
+        Ok(())
+    }
+
+    #[test]
+    fn test_image_size() -> anyhow::Result<()> {
+        assert_eq!(image_length("200").as_deref(), Some("150pt"));
+        assert_eq!(image_length(" 40px").as_deref(), Some("30pt"));
+        assert_eq!(image_length("50%").as_deref(), Some("50%"));
+        assert_eq!(image_length("2.5cm").as_deref(), Some("2.5cm"));
+        assert_eq!(image_length("auto"), None);
+
+        let document = Document::new(vec![Element::Image(ImageData::new(
+            Bytes::from_static(b"image"),
+            "picture".to_string(),
+            "".to_string(),
+            "png".to_string(),
+            "".to_string(),
+            ImageDimension {
+                width: Some("200".to_string()),
+                height: None,
+            },
+        ))]);
+        let (source, _) = generate_document(&document, &PdfOptions::default())?;
+        assert!(source.contains("alt: \"\", width: 150pt)"));
         Ok(())
     }
 }
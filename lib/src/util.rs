@@ -0,0 +1,168 @@
+//! Helpers shared by the readers and writers of the word processor formats and by the XML
+//! writers
+
+#[cfg(any(feature = "docx", feature = "odt", feature = "rtf"))]
+use crate::core::{header_anchor, Element, TextStyle};
+
+/// Text of an XML element or attribute, without the control characters that XML does not
+/// allow
+#[cfg(any(
+    feature = "xml",
+    feature = "docbook",
+    feature = "fb2",
+    feature = "confluence"
+))]
+pub(crate) fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            c if c.is_control() => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Size of the text read from the word processor formats
+#[cfg(any(feature = "docx", feature = "odt", feature = "rtf"))]
+const TEXT_SIZE: u8 = 12;
+
+/// Sizes of the Heading 1 to Heading 6 styles of the word processor formats, in points
+#[cfg(any(feature = "docx", feature = "odt", feature = "rtf"))]
+pub(crate) const HEADING_SIZES: [u8; 6] = [18, 16, 14, 13, 12, 11];
+
+/// Bullets of the levels of bulleted lists, repeated past the third level
+#[cfg(any(feature = "docx", feature = "odt", feature = "rtf"))]
+pub(crate) const BULLETS: [&str; 3] = ["\u{2022}", "\u{25E6}", "\u{25AA}"];
+
+/// Level of the heading style of a header and the name of its bookmark, the target of
+/// `#anchor` links
+#[cfg(any(feature = "docx", feature = "odt", feature = "rtf"))]
+pub(crate) fn header_bookmark(level: u8, text: &str) -> (u8, String) {
+    (
+        level.clamp(1, HEADING_SIZES.len() as u8),
+        header_anchor(text),
+    )
+}
+
+/// Styles of a run kept by the document, monospace text being inline code
+#[cfg(any(feature = "docx", feature = "odt", feature = "rtf"))]
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub(crate) struct RunFormat {
+    pub(crate) strong: bool,
+    pub(crate) emphasis: bool,
+    pub(crate) strikethrough: bool,
+    pub(crate) code: bool,
+}
+
+#[cfg(any(feature = "docx", feature = "odt", feature = "rtf"))]
+impl RunFormat {
+    /// Styled elements of the inline elements, strong outermost
+    pub(crate) fn apply(self, elements: Vec<Element>) -> Vec<Element> {
+        let mut elements = elements;
+        for (on, style) in [
+            (self.strikethrough, TextStyle::Strikethrough),
+            (self.emphasis, TextStyle::Emphasis),
+            (self.strong, TextStyle::Strong),
+        ] {
+            if on {
+                elements = vec![Element::Styled { style, elements }];
+            }
+        }
+        elements
+    }
+}
+
+/// Blocks written as blocks of their own, the other elements are written in paragraphs
+#[cfg(any(feature = "odt", feature = "rtf"))]
+pub(crate) fn is_block(element: &Element) -> bool {
+    matches!(
+        element,
+        Element::Header { .. }
+            | Element::Paragraph { .. }
+            | Element::Table { .. }
+            | Element::List { .. }
+            | Element::TableOfContents { .. }
+            | Element::CodeBlock { .. }
+    )
+}
+
+/// Elements with the styled elements that follow each other in the same style merged, so
+/// that runs of nested styles give nested styled elements
+#[cfg(any(feature = "odt", feature = "rtf"))]
+pub(crate) fn merge_styled(elements: Vec<Element>) -> Vec<Element> {
+    let mut merged: Vec<Element> = vec![];
+    for element in elements {
+        match (merged.last_mut(), element) {
+            (
+                Some(Element::Styled {
+                    style,
+                    elements: previous,
+                }),
+                Element::Styled {
+                    style: next_style,
+                    elements: next,
+                },
+            ) if *style == next_style => {
+                previous.extend(next);
+                *previous = merge_styled(std::mem::take(previous));
+            }
+            (Some(Element::Text { text, .. }), Element::Text { text: next, .. }) => {
+                text.push_str(&next)
+            }
+            (_, element) => merged.push(element),
+        }
+    }
+    merged
+}
+
+/// Block of a paragraph, none for an empty one and the image itself for a paragraph of an
+/// image
+#[cfg(any(feature = "docx", feature = "odt", feature = "rtf"))]
+pub(crate) fn block_element(content: Vec<Element>) -> Option<Element> {
+    let empty = content.iter().all(|element| match element {
+        Element::Text { text, .. } => text.trim().is_empty(),
+        Element::LineBreak => true,
+        _ => false,
+    });
+    if empty {
+        return None;
+    }
+    match <[Element; 1]>::try_from(content) {
+        Ok([image @ Element::Image(_)]) => Some(image),
+        Ok([element]) => Some(Element::Paragraph {
+            elements: vec![element],
+            direction: Default::default(),
+        }),
+        Err(elements) => Some(Element::Paragraph {
+            elements,
+            direction: Default::default(),
+        }),
+    }
+}
+
+/// Element of a list item or of a table cell, text when the content is only text
+#[cfg(any(feature = "docx", feature = "odt", feature = "rtf"))]
+pub(crate) fn item_element(content: Vec<Element>) -> Element {
+    if content
+        .iter()
+        .all(|element| matches!(element, Element::Text { .. }))
+    {
+        return Element::Text {
+            text: content.iter().map(Element::plain_text).collect(),
+            size: TEXT_SIZE,
+        };
+    }
+    match <[Element; 1]>::try_from(content) {
+        Ok([element]) => element,
+        Err(elements) => Element::Paragraph {
+            elements,
+            direction: Default::default(),
+        },
+    }
+}
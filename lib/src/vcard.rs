@@ -0,0 +1,290 @@
+//! vCard contacts (`.vcf`, versions 2.1 to 4.0), read as a document with a section per card
+//!
+//! The formatted name of a card is its header, followed by its embedded photo and a table of
+//! its fields: the structured name, organization, phone numbers, emails, addresses, websites,
+//! dates, categories and note, labeled with their types. Other properties, such as `UID`,
+//! `REV` and the `X-` extensions, are left out. A file of a single card takes its name as
+//! title. vCards are parsed only.
+
+use base64::Engine;
+use bytes::Bytes;
+
+use crate::core::{
+    data_uri_image_loader, Document, Element, ImageData, ImageDimension, TableCell, TableRow,
+};
+use crate::vobject::{self, Component, Property};
+
+pub struct Transformer;
+
+/// Labels of the properties of the table, in the order of their rows
+const FIELDS: [(&str, &str); 15] = [
+    ("N", "Name"),
+    ("NICKNAME", "Nickname"),
+    ("ORG", "Organization"),
+    ("TITLE", "Title"),
+    ("ROLE", "Role"),
+    ("TEL", "Phone"),
+    ("EMAIL", "Email"),
+    ("IMPP", "Messaging"),
+    ("ADR", "Address"),
+    ("URL", "Website"),
+    ("BDAY", "Birthday"),
+    ("ANNIVERSARY", "Anniversary"),
+    ("GENDER", "Gender"),
+    ("CATEGORIES", "Categories"),
+    ("NOTE", "Note"),
+];
+
+fn text(text: impl Into<String>) -> Element {
+    Element::Text {
+        text: text.into(),
+        size: 8,
+    }
+}
+
+fn link(label: String, url: String) -> Element {
+    Element::Hyperlink {
+        elements: vec![text(label)],
+        url,
+        alt: String::new(),
+        size: 14,
+    }
+}
+
+/// Non-empty components joined with the separator
+fn join(components: &[String], separator: &str) -> String {
+    components
+        .iter()
+        .map(|component| component.trim())
+        .filter(|component| !component.is_empty())
+        .collect::<Vec<_>>()
+        .join(separator)
+}
+
+/// Name of a card: its formatted name or else its structured name
+fn card_name(card: &Component) -> Option<String> {
+    card.text("FN")
+        .or_else(|| card.property("N").map(structured_name))
+        .filter(|name| !name.is_empty())
+}
+
+/// Structured name `family;given;additional;prefixes;suffixes` in reading order
+fn structured_name(property: &Property) -> String {
+    let mut components = property.components();
+    components.resize(5, String::new());
+    join(
+        &[
+            components[3].clone(),
+            components[1].clone(),
+            components[2].clone(),
+            components[0].clone(),
+            components[4].clone(),
+        ],
+        " ",
+    )
+}
+
+/// Value cell of a property
+fn value(property: &Property) -> Option<Element> {
+    let element = match property.name.as_str() {
+        "N" => text(structured_name(property)),
+        "ORG" => text(join(&property.components(), ", ")),
+        // post office box, extended address, street, locality, region, postal code, country
+        "ADR" => text(join(&property.components(), ", ")),
+        "CATEGORIES" | "NICKNAME" => text(property.list().join(", ")),
+        "EMAIL" => {
+            let email = property.text();
+            link(email.clone(), format!("mailto:{}", email.trim()))
+        }
+        "URL" => {
+            let url = property.text();
+            link(url.clone(), url)
+        }
+        "TEL" => {
+            // vCard 4 numbers are `tel:` URIs
+            let url = property.text();
+            let number = url.strip_prefix("tel:").unwrap_or(&url).to_string();
+            text(number)
+        }
+        _ => text(property.text()),
+    };
+    (!element.plain_text().trim().is_empty()).then_some(element)
+}
+
+/// Image of an embedded photo: a `data:` URI or base64 data with `ENCODING=b`
+fn photo(card: &Component) -> Option<Element> {
+    let property = card.property("PHOTO")?;
+    let value: String = property.value.split_whitespace().collect();
+    let encoding = property.parameter("ENCODING").unwrap_or_default();
+    let (bytes, image_type) = if value.starts_with("data:") {
+        let loader = data_uri_image_loader(|_: &str| anyhow::bail!("not embedded"));
+        (loader(&value).ok()?, value.clone())
+    } else if encoding.eq_ignore_ascii_case("b") || encoding.eq_ignore_ascii_case("base64") {
+        let bytes = base64::engine::general_purpose::STANDARD
+            .decode(&value)
+            .ok()?;
+        let image_type = property
+            .types()
+            .first()
+            .cloned()
+            .unwrap_or_else(|| "jpeg".to_string());
+        (Bytes::from(bytes), image_type)
+    } else {
+        // photos given by URL are not loaded
+        return None;
+    };
+    let name = card_name(card).unwrap_or_default();
+    Some(Element::Image(ImageData::new(
+        bytes,
+        String::new(),
+        name,
+        image_type,
+        String::new(),
+        ImageDimension::default(),
+    )))
+}
+
+/// Header, photo and table of fields of a card
+fn card_elements(card: &Component) -> Vec<Element> {
+    let mut elements = vec![];
+    if let Some(name) = card_name(card) {
+        elements.push(Element::Header {
+            level: 1,
+            text: name,
+        });
+    }
+    elements.extend(photo(card));
+    let mut rows = vec![];
+    for (name, label) in FIELDS {
+        for property in card.all(name) {
+            let Some(value) = value(property) else {
+                continue;
+            };
+            // every email address is an internet one
+            let types: Vec<String> = property
+                .types()
+                .into_iter()
+                .filter(|kind| kind != "internet")
+                .collect();
+            let label = if types.is_empty() {
+                label.to_string()
+            } else {
+                format!("{} ({})", label, types.join(", "))
+            };
+            rows.push(TableRow {
+                cells: vec![
+                    TableCell {
+                        element: text(label),
+                    },
+                    TableCell { element: value },
+                ],
+            });
+        }
+    }
+    if !rows.is_empty() {
+        elements.push(Element::Table {
+            headers: vec![],
+            rows,
+        });
+    }
+    elements
+}
+
+impl Transformer {
+    /// Document of the cards of a vCard file
+    pub fn parse(document: &Bytes) -> crate::Result<Document> {
+        Transformer::parse_cards(document).map_err(crate::Error::parse_error)
+    }
+
+    fn parse_cards(document: &Bytes) -> anyhow::Result<Document> {
+        let source = std::str::from_utf8(document)?;
+        let source = source.strip_prefix('\u{feff}').unwrap_or(source);
+        let cards: Vec<Component> = vobject::parse(source)?
+            .into_iter()
+            .filter(|component| component.name == "VCARD")
+            .collect();
+        if cards.is_empty() {
+            anyhow::bail!("No BEGIN:VCARD card in the file");
+        }
+        let mut document = Document::new(cards.iter().flat_map(card_elements).collect());
+        if let [card] = cards.as_slice() {
+            document.metadata.title = card_name(card);
+        }
+        Ok(document)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use crate::core::Element;
+    use crate::vcard::Transformer;
+
+    #[test]
+    fn test_parse() -> anyhow::Result<()> {
+        let png = base64::Engine::encode(
+            &base64::engine::general_purpose::STANDARD,
+            std::fs::read("test/data/small.png")?,
+        );
+        let source = format!(
+            "BEGIN:VCARD\r\n\
+             VERSION:3.0\r\n\
+             FN:Dr. Jane Doe\r\n\
+             N:Doe;Jane;;Dr.;\r\n\
+             ORG:Example Corp;Research\r\n\
+             TEL;TYPE=WORK,VOICE:+1 555 0100\r\n\
+             EMAIL;TYPE=INTERNET:jane@example.com\r\n\
+             ADR;TYPE=WORK:;;1 Main St.;Springfield;;12345;USA\r\n\
+             NOTE:Met at the conference\\, 2025\r\n\
+             PHOTO;ENCODING=b;TYPE=PNG:{png}\r\n\
+             X-CUSTOM:left out\r\n\
+             END:VCARD\r\n\
+             BEGIN:VCARD\r\n\
+             VERSION:4.0\r\n\
+             FN:John Roe\r\n\
+             TEL;VALUE=uri;TYPE=cell:tel:+1-555-0199\r\n\
+             END:VCARD\r\n"
+        );
+        let document = Transformer::parse(&Bytes::from(source))?;
+        assert_eq!(document.metadata.title, None);
+        let elements = document.get_all_elements();
+        assert!(matches!(
+            elements[0],
+            Element::Header { level: 1, text } if text == "Dr. Jane Doe"
+        ));
+        assert!(matches!(elements[1], Element::Image(_)));
+        let Element::Table { rows, .. } = elements[2] else {
+            panic!("no table of fields: {:?}", elements[2]);
+        };
+        let cells: Vec<Vec<String>> = rows
+            .iter()
+            .map(|row| {
+                row.cells
+                    .iter()
+                    .map(|cell| cell.element.plain_text())
+                    .collect()
+            })
+            .collect();
+        assert_eq!(
+            cells,
+            [
+                ["Name", "Dr. Jane Doe"],
+                ["Organization", "Example Corp, Research"],
+                ["Phone (work, voice)", "+1 555 0100"],
+                ["Email", "jane@example.com"],
+                ["Address (work)", "1 Main St., Springfield, 12345, USA"],
+                ["Note", "Met at the conference, 2025"],
+            ]
+        );
+        assert!(matches!(
+            elements[3],
+            Element::Header { text, .. } if text == "John Roe"
+        ));
+        assert!(matches!(
+            elements[4],
+            Element::Table { rows, .. } if rows[0].cells[1].element.plain_text() == "+1-555-0199"
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,270 @@
+//! Content lines and components of vCard (RFC 6350) and iCalendar (RFC 5545) files
+//!
+//! Lines folded with a leading space or tab are unfolded, and the `BEGIN:` and `END:` lines
+//! nest the components, as the events of a calendar and their alarms.
+
+/// Property of a component: `group.NAME;PARAMETER=value:value`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Property {
+    /// Uppercase name, without its group
+    pub name: String,
+    /// Parameters by uppercase name, the values of a list parameter joined with commas
+    pub parameters: Vec<(String, String)>,
+    /// Value as written, with its escapes
+    pub value: String,
+}
+
+impl Property {
+    /// Value of a parameter, without quotes
+    pub fn parameter(&self, name: &str) -> Option<&str> {
+        self.parameters
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Values of the `TYPE` parameters, lowercase, as `work` and `voice` for a phone number
+    pub fn types(&self) -> Vec<String> {
+        self.parameters
+            .iter()
+            .filter(|(key, _)| key == "TYPE")
+            .flat_map(|(_, value)| value.split(','))
+            .map(|value| value.trim().to_ascii_lowercase())
+            .filter(|value| !value.is_empty() && value != "pref")
+            .collect()
+    }
+
+    /// Text value with its escapes replaced
+    pub fn text(&self) -> String {
+        unescape(&self.value)
+    }
+
+    /// Components of a structured value separated by semicolons, as the parts of an address
+    pub fn components(&self) -> Vec<String> {
+        split_unescaped(&self.value, ';')
+            .iter()
+            .map(|component| unescape(component))
+            .collect()
+    }
+
+    /// Values of a list value separated by commas, as categories
+    pub fn list(&self) -> Vec<String> {
+        split_unescaped(&self.value, ',')
+            .iter()
+            .map(|value| unescape(value))
+            .filter(|value| !value.is_empty())
+            .collect()
+    }
+}
+
+/// Component between a `BEGIN:` and its `END:` line
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Component {
+    /// Uppercase name, as `VCARD` or `VEVENT`
+    pub name: String,
+    pub properties: Vec<Property>,
+    pub components: Vec<Component>,
+}
+
+impl Component {
+    /// First property of the name
+    pub fn property(&self, name: &str) -> Option<&Property> {
+        self.properties
+            .iter()
+            .find(|property| property.name == name)
+    }
+
+    /// Text of the first property of the name, `None` when it is missing or blank
+    pub fn text(&self, name: &str) -> Option<String> {
+        self.property(name)
+            .map(Property::text)
+            .filter(|text| !text.trim().is_empty())
+    }
+
+    /// Properties of the name
+    pub fn all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Property> {
+        self.properties
+            .iter()
+            .filter(move |property| property.name == name)
+    }
+}
+
+/// Components at the top of the file, as the cards of a vCard file
+pub fn parse(text: &str) -> anyhow::Result<Vec<Component>> {
+    let mut stack: Vec<Component> = vec![];
+    let mut components = vec![];
+    for line in unfold(text) {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let Some(property) = parse_line(&line) else {
+            anyhow::bail!("Invalid content line: {}", line);
+        };
+        match property.name.as_str() {
+            "BEGIN" => stack.push(Component {
+                name: property.value.trim().to_ascii_uppercase(),
+                ..Component::default()
+            }),
+            "END" => {
+                let name = property.value.trim().to_ascii_uppercase();
+                let component = stack
+                    .pop()
+                    .filter(|component| component.name == name)
+                    .ok_or_else(|| anyhow::anyhow!("END:{} without its BEGIN", name))?;
+                match stack.last_mut() {
+                    Some(parent) => parent.components.push(component),
+                    None => components.push(component),
+                }
+            }
+            _ => match stack.last_mut() {
+                Some(component) => component.properties.push(property),
+                None => anyhow::bail!("Property {} outside of a component", property.name),
+            },
+        }
+    }
+    if let Some(component) = stack.last() {
+        anyhow::bail!("BEGIN:{} without its END", component.name);
+    }
+    Ok(components)
+}
+
+/// Lines with their folded continuations joined
+fn unfold(text: &str) -> Vec<String> {
+    let mut lines: Vec<String> = vec![];
+    for line in text.lines() {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(continuation), Some(last)) => last.push_str(continuation),
+            _ => lines.push(line.to_string()),
+        }
+    }
+    lines
+}
+
+/// Property of a content line, `None` when the line has no value
+fn parse_line(line: &str) -> Option<Property> {
+    // the value starts at the first colon outside of a quoted parameter value
+    let mut quoted = false;
+    let colon = line.char_indices().find_map(|(index, c)| match c {
+        '"' => {
+            quoted = !quoted;
+            None
+        }
+        ':' if !quoted => Some(index),
+        _ => None,
+    })?;
+    let (head, value) = (&line[..colon], &line[colon + 1..]);
+    let mut parts = split_quoted(head, ';').into_iter();
+    let name = parts.next()?;
+    let name = name.rsplit('.').next().unwrap_or(name).trim();
+    if name.is_empty() {
+        return None;
+    }
+    let parameters = parts
+        .map(|parameter| match parameter.split_once('=') {
+            Some((key, value)) => (
+                key.trim().to_ascii_uppercase(),
+                split_quoted(value, ',')
+                    .iter()
+                    .map(|value| value.trim_matches('"'))
+                    .collect::<Vec<_>>()
+                    .join(","),
+            ),
+            // vCard 2.1 types without a name, `TEL;WORK;VOICE:`
+            None => ("TYPE".to_string(), parameter.to_string()),
+        })
+        .collect();
+    Some(Property {
+        name: name.to_ascii_uppercase(),
+        parameters,
+        value: value.to_string(),
+    })
+}
+
+/// Parts of the text separated by the delimiter outside of quotes
+fn split_quoted(text: &str, delimiter: char) -> Vec<&str> {
+    let mut parts = vec![];
+    let mut quoted = false;
+    let mut start = 0;
+    for (index, c) in text.char_indices() {
+        if c == '"' {
+            quoted = !quoted;
+        } else if c == delimiter && !quoted {
+            parts.push(&text[start..index]);
+            start = index + c.len_utf8();
+        }
+    }
+    parts.push(&text[start..]);
+    parts
+}
+
+/// Parts of a value separated by the delimiter when it is not escaped with a backslash
+fn split_unescaped(value: &str, delimiter: char) -> Vec<&str> {
+    let mut parts = vec![];
+    let mut escaped = false;
+    let mut start = 0;
+    for (index, c) in value.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == delimiter {
+            parts.push(&value[start..index]);
+            start = index + c.len_utf8();
+        }
+    }
+    parts.push(&value[start..]);
+    parts
+}
+
+/// Text value with `\n`, `\,`, `\;` and `\\` replaced by the characters they stand for
+fn unescape(value: &str) -> String {
+    let mut text = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            text.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n' | 'N') => text.push('\n'),
+            Some(c) => text.push(c),
+            None => text.push('\\'),
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::vobject::parse;
+
+    #[test]
+    fn test_parse() -> anyhow::Result<()> {
+        let components = parse(
+            "BEGIN:VCALENDAR\r\n\
+             BEGIN:VEVENT\r\n\
+             SUMMARY:Review\\, part 2\r\n\
+             ATTENDEE;CN=\"Doe, Jane\";ROLE=CHAIR:mailto:jane\r\n \
+             @example.com\r\n\
+             BEGIN:VALARM\r\n\
+             DESCRIPTION:Reminder\r\n\
+             END:VALARM\r\n\
+             END:VEVENT\r\n\
+             END:VCALENDAR\r\n",
+        )?;
+        assert_eq!(components.len(), 1);
+        let event = &components[0].components[0];
+        assert_eq!(event.name, "VEVENT");
+        assert_eq!(event.text("SUMMARY").as_deref(), Some("Review, part 2"));
+        let attendee = event.property("ATTENDEE").unwrap();
+        assert_eq!(attendee.parameter("CN"), Some("Doe, Jane"));
+        assert_eq!(attendee.value, "mailto:jane@example.com");
+        assert_eq!(event.text("DESCRIPTION"), None);
+        assert_eq!(
+            event.components[0].text("DESCRIPTION").as_deref(),
+            Some("Reminder")
+        );
+        assert!(parse("BEGIN:VCARD\nFN:Jane\n").is_err());
+        Ok(())
+    }
+}
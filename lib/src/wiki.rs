@@ -0,0 +1,1838 @@
+//! MediaWiki markup of a document, the source of the pages of Wikipedia and the other wikis
+//!
+//! Headers are `=` headings, emphasis `''` and `'''` quotes, links `[[page]]` wiki links or
+//! `[url text]` external links, images `[[File:...]]` links to files saved next to the source
+//! with their title as caption, tables wikitables and lists `*` and `#` lines. Code blocks
+//! are `syntaxhighlight` blocks, math `<math>` tags, footnotes `<ref>` tags listed by
+//! `<references />` and asides with an `admonition` class `<div>`s of their kind. MediaWiki
+//! lists have no start number and no blocks in their items: numbered lists start at 1 and
+//! the tables of list items are left out. Text MediaWiki would read as markup is written in
+//! `<nowiki>` tags.
+//!
+//! Pages are read for the same markup, with preformatted lines, `<pre>` and `<source>`
+//! blocks, definition lists, the HTML tags of formatting, `<span id>` anchors and
+//! `__TOC__`. Templates on their own lines are kept as raw MediaWiki and inline templates as
+//! their text; comments, categories and the other magic words are left out.
+
+use std::collections::HashMap;
+
+use bytes::Bytes;
+use log::warn;
+
+use crate::core::{
+    disk_image_loader, disk_image_saver, header_anchor, Band, ContainerKind, Document,
+    DocumentType, Element, ImageData, ImageDimension, ListItem, TableCell, TableHeader, TableRow,
+    TextStyle, TransformerTrait, TransformerWithImageLoaderSaverTrait,
+};
+use crate::error::image_loader_errors;
+use crate::lightweight::{paragraph, plain_text, text, text_block, Inline};
+
+pub struct Transformer;
+
+/// Schemes of external links
+const URI_SCHEMES: [&str; 4] = ["https://", "http://", "ftp://", "mailto:"];
+
+/// Namespaces of the links that embed a file
+const FILE_NAMESPACES: [&str; 2] = ["file:", "image:"];
+
+/// Options of a `[[File:...]]` link that are not its caption
+const FILE_OPTIONS: [&str; 10] = [
+    "thumb",
+    "thumbnail",
+    "frame",
+    "frameless",
+    "border",
+    "left",
+    "right",
+    "center",
+    "none",
+    "upright",
+];
+
+/// Tags whose content is inline text in another style
+const STYLE_TAGS: [(&str, TextStyle); 7] = [
+    ("b", TextStyle::Strong),
+    ("strong", TextStyle::Strong),
+    ("i", TextStyle::Emphasis),
+    ("em", TextStyle::Emphasis),
+    ("s", TextStyle::Strikethrough),
+    ("del", TextStyle::Strikethrough),
+    ("strike", TextStyle::Strikethrough),
+];
+
+/// Tags whose content is read as code
+const CODE_TAGS: [&str; 4] = ["code", "tt", "kbd", "samp"];
+
+/// Tags written around their content without a style of their own
+const TRANSPARENT_TAGS: [&str; 8] = ["span", "u", "ins", "small", "big", "sup", "sub", "font"];
+
+/// Text with the entities of `&` and `<`, in a `<nowiki>` tag when MediaWiki would read
+/// some of it as markup: quotes, brackets, braces, pipes, signatures and magic words
+fn escape(text: &str) -> String {
+    let text = text
+        .replace('\r', "")
+        .replace('\n', " ")
+        .replace('&', "&amp;")
+        .replace('<', "&lt;");
+    let markup = ["''", "[", "]", "{{", "}}", "|", "~~~", "__", "://"]
+        .iter()
+        .any(|markup| text.contains(markup))
+        || text.starts_with('\'')
+        || text.ends_with('\'');
+    if markup {
+        format!("<nowiki>{text}</nowiki>")
+    } else {
+        text
+    }
+}
+
+/// Value of an attribute of a tag, in double quotes
+fn escape_attribute(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+}
+
+/// Text of the entities of `&`, `<` and `>`, read back by [`decode_entities`]
+fn escape_code(code: &str) -> String {
+    code.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Text with its character references and the common named entities replaced
+fn decode_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let entity = rest
+            .find(';')
+            .filter(|end| *end <= 10)
+            .and_then(|end| Some((entity_char(&rest[1..end])?, end)));
+        match entity {
+            Some((c, end)) => {
+                decoded.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+fn entity_char(name: &str) -> Option<char> {
+    if let Some(number) = name.strip_prefix('#') {
+        let code = match number.strip_prefix(['x', 'X']) {
+            Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+            None => number.parse().ok()?,
+        };
+        return char::from_u32(code);
+    }
+    Some(match name {
+        "amp" => '&',
+        "lt" => '<',
+        "gt" => '>',
+        "quot" => '"',
+        "apos" => '\'',
+        "nbsp" => '\u{a0}',
+        "ndash" => '–',
+        "mdash" => '—',
+        "hellip" => '…',
+        "copy" => '©',
+        _ => return None,
+    })
+}
+
+/// Value of an attribute in the attributes of a tag, `name="value"`, `name='value'` or
+/// `name=value`
+fn attribute(attributes: &str, name: &str) -> Option<String> {
+    let lower = attributes.to_lowercase();
+    let mut offset = 0;
+    while let Some(found) = lower[offset..].find(name) {
+        let start = offset + found;
+        offset = start + name.len();
+        let before = lower[..start].chars().next_back();
+        if before.is_some_and(|c| !c.is_whitespace()) {
+            continue;
+        }
+        let rest = attributes[offset..].trim_start();
+        let Some(value) = rest.strip_prefix('=') else {
+            continue;
+        };
+        let value = value.trim_start();
+        let value = match value.chars().next() {
+            Some(quote @ ('"' | '\'')) => value[1..].split(quote).next().unwrap_or_default(),
+            _ => value
+                .split(|c: char| c.is_whitespace() || c == '/' || c == '>')
+                .next()
+                .unwrap_or_default(),
+        };
+        return Some(decode_entities(value));
+    }
+    None
+}
+
+/// Source without its `<!-- -->` comments
+fn without_comments(source: &str) -> String {
+    let mut result = String::with_capacity(source.len());
+    let mut rest = source;
+    while let Some(start) = rest.find("<!--") {
+        result.push_str(&rest[..start]);
+        match rest[start..].find("-->") {
+            Some(end) => rest = &rest[start + end + 3..],
+            None => {
+                rest = "";
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+/// Parts of `text` between the separators outside of links, templates and `<nowiki>`
+fn split_top_level<'t>(text: &'t str, separator: &str) -> Vec<&'t str> {
+    let mut parts = vec![];
+    let mut depth = 0usize;
+    let mut start = 0;
+    let mut index = 0;
+    while index < text.len() {
+        let rest = &text[index..];
+        if rest.starts_with("<nowiki>") {
+            index += rest.find("</nowiki>").map_or(rest.len(), |end| end + 9);
+            continue;
+        }
+        if rest.starts_with("[[") || rest.starts_with("{{") {
+            depth += 1;
+            index += 2;
+        } else if (rest.starts_with("]]") || rest.starts_with("}}")) && depth > 0 {
+            depth -= 1;
+            index += 2;
+        } else if depth == 0 && rest.starts_with(separator) {
+            parts.push(&text[start..index]);
+            index += separator.len();
+            start = index;
+        } else {
+            index += 1;
+            while index < text.len() && !text.is_char_boundary(index) {
+                index += 1;
+            }
+        }
+    }
+    parts.push(&text[start..]);
+    parts
+}
+
+/// Level and text of a heading line, `== Title ==`
+fn heading(line: &str) -> Option<(u8, &str)> {
+    let line = line.trim();
+    let opening = line.chars().take_while(|c| *c == '=').count();
+    let closing = line.chars().rev().take_while(|c| *c == '=').count();
+    if opening == 0 || closing == 0 || line.len() <= opening + closing {
+        return None;
+    }
+    let level = opening.min(closing).min(6);
+    let text = line[level..line.len() - level].trim();
+    (!text.is_empty()).then_some((level as u8, text))
+}
+
+/// Index of the `]]` closing the link opened at `index`, links nesting in captions
+fn link_end(chars: &[char], index: usize) -> Option<usize> {
+    let mut depth = 0;
+    let mut position = index;
+    while position + 1 < chars.len() {
+        match (chars[position], chars[position + 1]) {
+            ('[', '[') => {
+                depth += 1;
+                position += 2;
+            }
+            (']', ']') => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(position);
+                }
+                position += 2;
+            }
+            _ => position += 1,
+        }
+    }
+    None
+}
+
+/// Index of the `}}` closing the template opened at `index`
+fn template_end(chars: &[char], index: usize) -> Option<usize> {
+    let mut depth = 0;
+    let mut position = index;
+    while position + 1 < chars.len() {
+        match (chars[position], chars[position + 1]) {
+            ('{', '{') => {
+                depth += 1;
+                position += 2;
+            }
+            ('}', '}') => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(position);
+                }
+                position += 2;
+            }
+            _ => position += 1,
+        }
+    }
+    None
+}
+
+/// Position of `needle` in `chars` from `from`, ignoring the case of ASCII letters
+fn find(chars: &[char], from: usize, needle: &str) -> Option<usize> {
+    let needle: Vec<char> = needle.chars().collect();
+    (from..chars.len().saturating_sub(needle.len() - 1)).find(|&position| {
+        chars[position..position + needle.len()]
+            .iter()
+            .zip(&needle)
+            .all(|(c, n)| c.eq_ignore_ascii_case(n))
+    })
+}
+
+/// Whether `chars` start with `prefix`, ignoring the case of ASCII letters
+fn starts_with(chars: &[char], prefix: &str) -> bool {
+    let mut chars = chars.iter();
+    prefix
+        .chars()
+        .all(|p| chars.next().is_some_and(|c| c.eq_ignore_ascii_case(&p)))
+}
+
+struct Writer<'a, F>
+where
+    F: Fn(&Bytes, &str) -> anyhow::Result<()>,
+{
+    image_saver: &'a F,
+    image_count: usize,
+    /// Text of the headers by their anchor, the targets of `[[#Header]]` links
+    headers: HashMap<String, String>,
+    /// Whether a footnote was written, its `<references />` ending the page
+    footnotes: bool,
+    /// Depth of the lists and blocks being written, where there are no headings
+    depth: usize,
+}
+
+impl<F> Writer<'_, F>
+where
+    F: Fn(&Bytes, &str) -> anyhow::Result<()>,
+{
+    fn blocks<'e>(
+        &mut self,
+        elements: impl IntoIterator<Item = &'e Element>,
+    ) -> anyhow::Result<String> {
+        let mut parts = vec![];
+        for element in elements {
+            let wiki = self.block(element)?;
+            if !wiki.is_empty() {
+                parts.push(wiki);
+            }
+        }
+        Ok(parts.join("\n\n"))
+    }
+
+    /// Blocks nested in a block, where there are no headings
+    fn nested<'e>(
+        &mut self,
+        elements: impl IntoIterator<Item = &'e Element>,
+    ) -> anyhow::Result<String> {
+        self.depth += 1;
+        let wiki = self.blocks(elements);
+        self.depth -= 1;
+        wiki
+    }
+
+    fn block(&mut self, element: &Element) -> anyhow::Result<String> {
+        if let Some((kind, title, blocks)) = element.admonition_parts() {
+            let title = title
+                .map(|title| format!(r#" title="{}""#, escape_attribute(&title)))
+                .unwrap_or_default();
+            let content = self.nested(blocks)?;
+            return Ok(format!(
+                "<div class=\"admonition {}\"{title}>\n{content}\n</div>",
+                escape_attribute(&kind.to_lowercase().replace(char::is_whitespace, "-"))
+            ));
+        }
+        Ok(match element {
+            Element::Header { level, text } if self.depth == 0 => {
+                let marks = "=".repeat(usize::from((*level).clamp(1, 6)));
+                let mut title = escape(text.trim());
+                if title.starts_with('=') || title.ends_with('=') {
+                    title = format!("<nowiki>{title}</nowiki>");
+                }
+                format!("{marks} {title} {marks}")
+            }
+            Element::Attributed { element, .. } => self.block(element)?,
+            Element::Paragraph { elements, .. } => match elements.as_slice() {
+                [image @ Element::Image(_)] => self.block(image)?,
+                elements => self.paragraph(elements)?,
+            },
+            Element::Image(image) => match self.save_image(image)? {
+                Some(name) => self.file_link(&name, image),
+                None => String::new(),
+            },
+            Element::Table { headers, rows } => self.table(headers, rows)?,
+            Element::List { .. } => self.list(element, "")?,
+            Element::CodeBlock { code, .. } => {
+                let code = code.trim_end_matches('\n');
+                match element.code_language() {
+                    Some(language) => format!(
+                        "<syntaxhighlight lang=\"{}\">\n{code}\n</syntaxhighlight>",
+                        escape_attribute(language)
+                    ),
+                    None => format!("<pre>\n{}\n</pre>", escape_code(code)),
+                }
+            }
+            Element::Math { tex, display: true } => {
+                format!("<math display=\"block\">{}</math>", escape_code(tex.trim()))
+            }
+            Element::Container { elements, .. } => self.blocks(elements)?,
+            Element::Raw {
+                format: DocumentType::MediaWiki | DocumentType::HTML,
+                content,
+            } => content.trim_end().to_string(),
+            Element::TableOfContents { .. } => "__TOC__".to_string(),
+            Element::Raw { .. } | Element::Drawing { .. } | Element::LineBreak => String::new(),
+            element => self.paragraph(std::slice::from_ref(element))?,
+        })
+    }
+
+    /// Paragraph of inline elements, a marker that would start a list, a heading or
+    /// preformatted text at its start being escaped
+    fn paragraph(&mut self, elements: &[Element]) -> anyhow::Result<String> {
+        let content = self.inlines(elements)?;
+        let content = content.trim();
+        if content.is_empty() {
+            return Ok(String::new());
+        }
+        Ok(if content.starts_with(['*', '#', ':', ';', '=', '-']) {
+            format!("<nowiki/>{content}")
+        } else {
+            content.to_string()
+        })
+    }
+
+    fn inlines(&mut self, elements: &[Element]) -> anyhow::Result<String> {
+        let mut wiki = String::new();
+        for element in elements {
+            wiki.push_str(&self.inline(element)?);
+        }
+        Ok(wiki)
+    }
+
+    fn inline(&mut self, element: &Element) -> anyhow::Result<String> {
+        Ok(match element {
+            Element::Text { text, .. } => escape(text),
+            Element::Header { text, .. } => format!("'''{}'''", escape(text.trim())),
+            Element::Paragraph { elements, .. } | Element::Container { elements, .. } => {
+                self.inlines(elements)?
+            }
+            Element::Styled { style, elements } => {
+                let content = self.inlines(elements)?;
+                if content.trim().is_empty() {
+                    return Ok(content);
+                }
+                match style {
+                    TextStyle::Strong => format!("'''{content}'''"),
+                    TextStyle::Emphasis => format!("''{content}''"),
+                    TextStyle::Strikethrough => format!("<s>{content}</s>"),
+                }
+            }
+            Element::Hyperlink { elements, url, .. } => {
+                let content = self.inlines(elements)?;
+                self.link(url, content.trim())
+            }
+            Element::InlineCode { text } => format!("<code>{}</code>", escape(text)),
+            Element::CodeBlock { code, .. } => format!("<code>{}</code>", escape(code.trim())),
+            Element::Math { tex, .. } => format!("<math>{}</math>", escape_code(tex.trim())),
+            Element::LineBreak => "<br />".to_string(),
+            Element::Footnote { elements } => {
+                self.footnotes = true;
+                format!("<ref>{}</ref>", self.inlines(elements)?.trim())
+            }
+            Element::Bookmark { id, .. } => {
+                format!("<span id=\"{}\"></span>", escape_attribute(id))
+            }
+            Element::Image(image) => match self.save_image(image)? {
+                Some(name) => self.file_link(&name, image),
+                None => String::new(),
+            },
+            Element::Attributed { element, .. } => self.inline(element)?,
+            Element::Media { .. } => match element.media_link() {
+                Some(link) => self.inline(&link)?,
+                None => String::new(),
+            },
+            Element::List { elements, .. } => {
+                let mut wiki = String::new();
+                for item in elements {
+                    wiki.push_str(&self.inline(&item.element)?);
+                    wiki.push(' ');
+                }
+                wiki
+            }
+            _ => String::new(),
+        })
+    }
+
+    /// Wiki link of a page or a header of the page, external link of a URL
+    fn link(&self, url: &str, content: &str) -> String {
+        if let Some(anchor) = url.strip_prefix('#') {
+            let target = self
+                .headers
+                .get(anchor)
+                .cloned()
+                .unwrap_or_else(|| anchor.to_string());
+            return if content.is_empty() {
+                format!("[[#{target}]]")
+            } else {
+                format!("[[#{target}|{content}]]")
+            };
+        }
+        if URI_SCHEMES.iter().any(|scheme| url.starts_with(scheme)) || url.contains("://") {
+            let url = url
+                .replace(' ', "%20")
+                .replace('[', "%5B")
+                .replace(']', "%5D");
+            return if content.is_empty() {
+                format!("[{url}]")
+            } else {
+                format!("[{url} {content}]")
+            };
+        }
+        if content.is_empty() || content == url {
+            format!("[[{url}]]")
+        } else {
+            format!("[[{url}|{content}]]")
+        }
+    }
+
+    /// `[[File:...]]` link of a saved image: a thumbnail with its title as caption, its
+    /// width in pixels and its alternative text
+    fn file_link(&self, name: &str, image: &ImageData) -> String {
+        let mut options = vec![format!("File:{name}")];
+        let title = image.title().trim();
+        if !title.is_empty() {
+            options.push("thumb".to_string());
+        }
+        if let Some(width) = &image.size().width {
+            let width = width.trim().trim_end_matches("px");
+            if !width.is_empty() && width.chars().all(|c| c.is_ascii_digit()) {
+                options.push(format!("{width}px"));
+            }
+        }
+        if !image.alt().trim().is_empty() {
+            options.push(format!("alt={}", escape(image.alt().trim())));
+        }
+        if !title.is_empty() {
+            options.push(escape(title));
+        }
+        format!("[[{}]]", options.join("|"))
+    }
+
+    /// Name of the saved image
+    fn save_image(&mut self, image: &ImageData) -> anyhow::Result<Option<String>> {
+        if image.bytes().is_empty() {
+            warn!("Image without content left out: {}", image.title());
+            return Ok(None);
+        }
+        self.image_count += 1;
+        let name = format!(
+            "image{}{}",
+            self.image_count,
+            image.image_type().to_extension()
+        );
+        (self.image_saver)(image.bytes(), &name)?;
+        Ok(Some(name))
+    }
+
+    /// Wikitable, a cell on each line
+    fn table(&mut self, headers: &[TableHeader], rows: &[TableRow]) -> anyhow::Result<String> {
+        let mut lines = vec!["{| class=\"wikitable\"".to_string()];
+        if !headers.is_empty() {
+            for header in headers {
+                lines.push(
+                    format!("! {}", self.cell(&header.element)?)
+                        .trim_end()
+                        .to_string(),
+                );
+            }
+        }
+        for (index, row) in rows.iter().enumerate() {
+            if index > 0 || !headers.is_empty() {
+                lines.push("|-".to_string());
+            }
+            for cell in &row.cells {
+                lines.push(
+                    format!("| {}", self.cell(&cell.element)?)
+                        .trim_end()
+                        .to_string(),
+                );
+            }
+        }
+        lines.push("|}".to_string());
+        Ok(lines.join("\n"))
+    }
+
+    /// Content of a cell, blocks starting on the line after the cell marker
+    fn cell(&mut self, element: &Element) -> anyhow::Result<String> {
+        match element {
+            Element::List { .. } | Element::Table { .. } | Element::CodeBlock { .. } => {
+                Ok(format!("\n{}", self.nested([element])?))
+            }
+            element => Ok(self.inline(element)?.trim().to_string()),
+        }
+    }
+
+    /// Lines of a list, the markers of the enclosing lists in `prefix`
+    fn list(&mut self, list: &Element, prefix: &str) -> anyhow::Result<String> {
+        let Element::List {
+            elements, numbered, ..
+        } = list
+        else {
+            return Ok(String::new());
+        };
+        let prefix = format!("{prefix}{}", if *numbered { '#' } else { '*' });
+        let mut lines = vec![];
+        for item in elements {
+            match &item.element {
+                nested @ Element::List { .. } => {
+                    let nested = self.list(nested, &prefix)?;
+                    if !nested.is_empty() {
+                        lines.push(nested);
+                    }
+                }
+                Element::Table { .. } => warn!("Table of a list item left out"),
+                element => {
+                    self.depth += 1;
+                    let content = self.inline(element);
+                    self.depth -= 1;
+                    lines.push(
+                        format!("{prefix} {}", content?.trim())
+                            .trim_end()
+                            .to_string(),
+                    );
+                }
+            }
+        }
+        Ok(lines.join("\n"))
+    }
+}
+
+struct Parser<'a, F>
+where
+    F: Fn(&str) -> anyhow::Result<Bytes>,
+{
+    image_loader: &'a F,
+    /// Contents of the named references, `<ref name="x" />` repeating the one of its name
+    references: HashMap<String, Vec<Element>>,
+    /// Depth of the nested inline elements, bounded against malicious sources
+    depth: usize,
+}
+
+impl<F> Parser<'_, F>
+where
+    F: Fn(&str) -> anyhow::Result<Bytes>,
+{
+    fn blocks(&mut self, lines: &[&str]) -> anyhow::Result<Vec<Element>> {
+        let mut blocks = vec![];
+        let mut paragraph: Vec<&str> = vec![];
+        let mut index = 0;
+        while index < lines.len() {
+            let line = lines[index].trim_end();
+            let trimmed = line.trim_start();
+            let lower = trimmed.to_lowercase();
+            if trimmed.is_empty() {
+                self.paragraph(&mut paragraph, &mut blocks)?;
+                index += 1;
+                continue;
+            }
+            let starts_block = heading(line).is_some()
+                || trimmed.starts_with("{|")
+                || line.starts_with(['*', '#', ':', ';'])
+                || line.starts_with("----")
+                || line.starts_with(' ')
+                || (lower.starts_with("__") && lower.ends_with("__"))
+                || ["<syntaxhighlight", "<source", "<pre", "<div", "<references"]
+                    .iter()
+                    .any(|tag| lower.starts_with(tag))
+                || (trimmed.starts_with("{{") && template_line_end(lines, index).is_some());
+            if !starts_block {
+                paragraph.push(line);
+                index += 1;
+                continue;
+            }
+            self.paragraph(&mut paragraph, &mut blocks)?;
+            if let Some((level, title)) = heading(line) {
+                blocks.push(Element::Header {
+                    level,
+                    text: plain_text(&self.inline(title)?),
+                });
+                index += 1;
+            } else if trimmed.starts_with("{|") {
+                index = self.table(lines, index, &mut blocks)?;
+            } else if line.starts_with(['*', '#', ':', ';']) {
+                let end = lines[index..]
+                    .iter()
+                    .position(|line| !line.starts_with(['*', '#', ':', ';']))
+                    .map_or(lines.len(), |end| index + end);
+                let items: Vec<(&str, &str)> = lines[index..end]
+                    .iter()
+                    .map(|line| {
+                        let marker = line.find(|c| !"*#:;".contains(c)).unwrap_or(line.len());
+                        (&line[..marker], line[marker..].trim())
+                    })
+                    .collect();
+                blocks.extend(self.list(&items, 0)?);
+                index = end;
+            } else if line.starts_with("----") {
+                index += 1;
+            } else if line.starts_with(' ') {
+                let end = lines[index..]
+                    .iter()
+                    .position(|line| !line.starts_with(' ') || line.trim().is_empty())
+                    .map_or(lines.len(), |end| index + end);
+                let code: Vec<String> = lines[index..end]
+                    .iter()
+                    .map(|line| decode_entities(&line[1..]))
+                    .collect();
+                blocks.push(Element::CodeBlock {
+                    info: String::new(),
+                    code: code.join("\n"),
+                });
+                index = end;
+            } else if lower.starts_with("__") {
+                if lower == "__toc__" || lower == "__forcetoc__" {
+                    blocks.push(Element::TableOfContents { max_level: 3 });
+                }
+                index += 1;
+            } else if lower.starts_with("<div") {
+                index = self.div(lines, index, &mut blocks)?;
+            } else if lower.starts_with("<references") {
+                index = if lower.ends_with("/>") {
+                    index + 1
+                } else {
+                    lines[index..]
+                        .iter()
+                        .position(|line| line.to_lowercase().contains("</references>"))
+                        .map_or(lines.len(), |end| index + end + 1)
+                };
+            } else if lower.starts_with('<') {
+                index = self.code(lines, index, &mut blocks);
+            } else {
+                let end = template_line_end(lines, index).unwrap_or(index);
+                blocks.push(Element::Raw {
+                    format: DocumentType::MediaWiki,
+                    content: lines[index..=end].join("\n"),
+                });
+                index = end + 1;
+            }
+        }
+        self.paragraph(&mut paragraph, &mut blocks)?;
+        Ok(blocks)
+    }
+
+    /// Paragraph of the lines read so far, a display formula when it is one
+    fn paragraph(
+        &mut self,
+        lines: &mut Vec<&str>,
+        blocks: &mut Vec<Element>,
+    ) -> anyhow::Result<()> {
+        if lines.is_empty() {
+            return Ok(());
+        }
+        let source = lines.join("\n");
+        lines.clear();
+        let elements = self.inline(&source)?;
+        match elements.as_slice() {
+            [] => {}
+            [Element::Math { display: true, .. }] => blocks.extend(elements),
+            [Element::Text { text, .. }] if text.trim().is_empty() => {}
+            _ => blocks.push(paragraph(elements)),
+        }
+        Ok(())
+    }
+
+    /// `<syntaxhighlight>`, `<source>` or `<pre>` block at `index` and the index after it
+    fn code(&mut self, lines: &[&str], index: usize, blocks: &mut Vec<Element>) -> usize {
+        let line = lines[index].trim();
+        let Some(open_end) = line.find('>') else {
+            return index + 1;
+        };
+        let name: String = line[1..]
+            .chars()
+            .take_while(|c| c.is_ascii_alphabetic())
+            .collect::<String>()
+            .to_lowercase();
+        let attributes = &line[1 + name.len()..open_end];
+        let language = attribute(attributes, "lang").unwrap_or_default();
+        let close = format!("</{name}>");
+        let mut content = vec![];
+        let mut first = line[open_end + 1..].to_string();
+        let mut end = index;
+        loop {
+            if let Some(position) = first.to_lowercase().find(&close) {
+                content.push(first[..position].to_string());
+                break;
+            }
+            content.push(first);
+            end += 1;
+            match lines.get(end) {
+                Some(line) => first = line.to_string(),
+                None => break,
+            }
+        }
+        if content.first().is_some_and(|line| line.trim().is_empty()) {
+            content.remove(0);
+        }
+        let code = content.join("\n");
+        let code = code.trim_end_matches(['\n', ' ']);
+        let code = if name == "pre" {
+            decode_entities(code)
+        } else {
+            code.to_string()
+        };
+        blocks.push(Element::CodeBlock {
+            info: language,
+            code,
+        });
+        end + 1
+    }
+
+    /// `<div>` block at `index`, an admonition of its `admonition` class or its blocks, and
+    /// the index after it
+    fn div(
+        &mut self,
+        lines: &[&str],
+        index: usize,
+        blocks: &mut Vec<Element>,
+    ) -> anyhow::Result<usize> {
+        let line = lines[index].trim();
+        let Some(open_end) = line.find('>') else {
+            return Ok(index + 1);
+        };
+        let attributes = &line[4..open_end];
+        let mut depth = 0;
+        let mut content = vec![];
+        let mut end = index;
+        let mut rest = &line[open_end + 1..];
+        'lines: loop {
+            let lower = rest.to_lowercase();
+            let mut position = 0;
+            while position < lower.len() {
+                let tail = &lower[position..];
+                if tail.starts_with("<div") {
+                    depth += 1;
+                    position += 4;
+                } else if tail.starts_with("</div>") {
+                    if depth == 0 {
+                        content.push(&rest[..position]);
+                        break 'lines;
+                    }
+                    depth -= 1;
+                    position += 6;
+                } else {
+                    position += tail.chars().next().map_or(1, char::len_utf8);
+                }
+            }
+            content.push(rest);
+            end += 1;
+            match lines.get(end) {
+                Some(line) => rest = line,
+                None => break,
+            }
+        }
+        let inner = self.blocks(&content)?;
+        let class = attribute(attributes, "class").unwrap_or_default();
+        let mut classes = class.split_whitespace();
+        if classes.any(|class| class == "admonition") {
+            let kind = class
+                .split_whitespace()
+                .find(|class| *class != "admonition")
+                .unwrap_or("note");
+            let title = attribute(attributes, "title");
+            blocks.push(Element::admonition(kind, title.as_deref(), inner));
+        } else {
+            blocks.extend(inner);
+        }
+        Ok(end + 1)
+    }
+
+    /// Wikitable at `index` and the index after it, a first row of header cells being the
+    /// headers
+    fn table(
+        &mut self,
+        lines: &[&str],
+        index: usize,
+        blocks: &mut Vec<Element>,
+    ) -> anyhow::Result<usize> {
+        // cells of the rows: whether they are header cells, and their source
+        let mut rows: Vec<Vec<(bool, String)>> = vec![vec![]];
+        let mut depth = 0;
+        let mut end = index + 1;
+        while let Some(line) = lines.get(end) {
+            let trimmed = line.trim();
+            end += 1;
+            if depth > 0 {
+                if trimmed.starts_with("|}") {
+                    depth -= 1;
+                } else if trimmed.starts_with("{|") {
+                    depth += 1;
+                }
+                append_to_cell(&mut rows, line);
+                continue;
+            }
+            if trimmed.starts_with("|}") {
+                break;
+            } else if trimmed.starts_with("{|") {
+                depth += 1;
+                append_to_cell(&mut rows, line);
+            } else if trimmed.starts_with("|+") {
+                // the caption is left out
+            } else if trimmed.starts_with("|-") {
+                rows.push(vec![]);
+            } else if let Some(cells) = trimmed.strip_prefix('!') {
+                let row = rows.last_mut().expect("rows are never empty");
+                for cell in split_top_level(cells, "!!")
+                    .into_iter()
+                    .flat_map(|cell| split_top_level(cell, "||"))
+                {
+                    row.push((true, cell_content(cell).to_string()));
+                }
+            } else if let Some(cells) = trimmed.strip_prefix('|') {
+                let row = rows.last_mut().expect("rows are never empty");
+                for cell in split_top_level(cells, "||") {
+                    row.push((false, cell_content(cell).to_string()));
+                }
+            } else {
+                append_to_cell(&mut rows, line);
+            }
+        }
+        rows.retain(|row| !row.is_empty());
+        let header_row = rows
+            .first()
+            .is_some_and(|row| row.iter().all(|(header, _)| *header));
+        let mut headers = vec![];
+        if header_row {
+            for (_, source) in rows.remove(0) {
+                headers.push(TableHeader {
+                    element: self.cell(&source)?,
+                    width: 30.,
+                });
+            }
+        }
+        let mut table_rows = vec![];
+        for row in rows {
+            let mut cells = vec![];
+            for (_, source) in row {
+                cells.push(TableCell {
+                    element: self.cell(&source)?,
+                });
+            }
+            table_rows.push(TableRow { cells });
+        }
+        blocks.push(Element::Table {
+            headers,
+            rows: table_rows,
+        });
+        Ok(end)
+    }
+
+    /// Element of the source of a cell: its text, its only block or its blocks
+    fn cell(&mut self, source: &str) -> anyhow::Result<Element> {
+        let lines: Vec<&str> = source.lines().collect();
+        let mut blocks = self.blocks(&lines)?;
+        Ok(match blocks.len() {
+            0 => text(""),
+            1 => text_block(blocks.remove(0)),
+            _ => Element::Container {
+                kind: ContainerKind::Div,
+                elements: blocks,
+            },
+        })
+    }
+
+    /// Blocks of list lines, their markers from `depth` on: lists of the `*` and `#` lines,
+    /// paragraphs of the `;` terms and `:` definitions outside of lists
+    fn list(&mut self, lines: &[(&str, &str)], depth: usize) -> anyhow::Result<Vec<Element>> {
+        let mut blocks = vec![];
+        let mut index = 0;
+        while index < lines.len() {
+            let marker = lines[index].0[depth..].chars().next().unwrap_or(':');
+            let run = lines[index..]
+                .iter()
+                .position(|(prefix, _)| !prefix[depth..].starts_with(marker))
+                .map_or(lines.len(), |end| index + end);
+            let group = &lines[index..run];
+            index = run;
+            if marker == ':' || marker == ';' {
+                for (prefix, content) in group {
+                    if prefix.len() > depth + 1 && !prefix[depth + 1..].starts_with([':', ';']) {
+                        blocks.extend(self.list(&[(prefix, content)], depth + 1)?);
+                        continue;
+                    }
+                    let mut elements = self.inline(content)?;
+                    if marker == ';' {
+                        // a term and its definition on the same line, `;term : definition`
+                        let (term, definition) = match content.split_once(" : ") {
+                            Some((term, definition)) => (term, Some(definition)),
+                            None => (*content, None),
+                        };
+                        elements = vec![Element::Styled {
+                            style: TextStyle::Strong,
+                            elements: self.inline(term)?,
+                        }];
+                        if let Some(definition) = definition {
+                            blocks.push(paragraph(elements));
+                            elements = self.inline(definition)?;
+                        }
+                    }
+                    if !elements.is_empty() {
+                        blocks.push(paragraph(elements));
+                    }
+                }
+                continue;
+            }
+            let mut items: Vec<ListItem> = vec![];
+            let mut position = 0;
+            while position < group.len() {
+                let (prefix, content) = group[position];
+                if prefix.len() == depth + 1 {
+                    items.push(ListItem {
+                        element: text_block(paragraph(self.inline(content)?)),
+                    });
+                    position += 1;
+                    continue;
+                }
+                let nested_end = group[position..]
+                    .iter()
+                    .position(|(prefix, _)| prefix.len() == depth + 1)
+                    .map_or(group.len(), |end| position + end);
+                for block in self.list(&group[position..nested_end], depth + 1)? {
+                    match (block, items.last_mut()) {
+                        // definitions continue the item before them
+                        (Element::Paragraph { elements, .. }, Some(item))
+                            if !matches!(item.element, Element::List { .. }) =>
+                        {
+                            let mut item_elements =
+                                match std::mem::replace(&mut item.element, text("")) {
+                                    Element::Paragraph { elements, .. } => elements,
+                                    element => vec![element],
+                                };
+                            item_elements.push(Element::LineBreak);
+                            item_elements.extend(elements);
+                            item.element = paragraph(item_elements);
+                        }
+                        (block, _) => items.push(ListItem { element: block }),
+                    }
+                }
+                position = nested_end;
+            }
+            blocks.push(Element::List {
+                elements: items,
+                numbered: marker == '#',
+                start: 1,
+                numbering: Default::default(),
+            });
+        }
+        Ok(blocks)
+    }
+
+    fn inline(&mut self, source: &str) -> anyhow::Result<Vec<Element>> {
+        if self.depth > 16 {
+            return Ok(vec![text(decode_entities(source))]);
+        }
+        self.depth += 1;
+        let elements = self.inline_elements(source);
+        self.depth -= 1;
+        elements
+    }
+
+    fn inline_elements(&mut self, source: &str) -> anyhow::Result<Vec<Element>> {
+        let chars: Vec<char> = source.chars().collect();
+        let mut inline = Inline::default();
+        let mut index = 0;
+        while index < chars.len() {
+            let c = chars[index];
+            match c {
+                '\'' if chars.get(index + 1) == Some(&'\'') => {
+                    let run = chars[index..].iter().take_while(|c| **c == '\'').count();
+                    let (literal, marker) = match run {
+                        2 => (0, 2),
+                        3 => (0, 3),
+                        4 => (1, 3),
+                        run => (run - 5, 5),
+                    };
+                    inline.push_str(&"'".repeat(literal));
+                    let start = index + literal + marker;
+                    match quote_end(&chars, start, marker) {
+                        Some(end) => {
+                            let content: String = chars[start..end].iter().collect();
+                            let elements = self.inline(&content)?;
+                            inline.push(match marker {
+                                2 => Element::Styled {
+                                    style: TextStyle::Emphasis,
+                                    elements,
+                                },
+                                3 => Element::Styled {
+                                    style: TextStyle::Strong,
+                                    elements,
+                                },
+                                _ => Element::Styled {
+                                    style: TextStyle::Strong,
+                                    elements: vec![Element::Styled {
+                                        style: TextStyle::Emphasis,
+                                        elements,
+                                    }],
+                                },
+                            });
+                            index = end + marker;
+                        }
+                        None => {
+                            inline.push_str(&"'".repeat(marker));
+                            index = start;
+                        }
+                    }
+                }
+                '[' if chars.get(index + 1) == Some(&'[') => match link_end(&chars, index) {
+                    Some(end) => {
+                        let content: String = chars[index + 2..end].iter().collect();
+                        // letters right after a link are part of its text, `[[dog]]s`
+                        let trail: String = chars[end + 2..]
+                            .iter()
+                            .take_while(|c| c.is_alphabetic())
+                            .collect();
+                        index = end + 2 + trail.chars().count();
+                        if let Some(element) = self.wiki_link(&content, &trail)? {
+                            inline.push(element);
+                        }
+                    }
+                    None => {
+                        inline.push_str("[[");
+                        index += 2;
+                    }
+                },
+                '[' if URI_SCHEMES
+                    .iter()
+                    .chain(&["//"])
+                    .any(|scheme| starts_with(&chars[index + 1..], scheme)) =>
+                {
+                    match chars[index..].iter().position(|c| *c == ']' || *c == '\n') {
+                        Some(end) if chars[index + end] == ']' => {
+                            let content: String = chars[index + 1..index + end].iter().collect();
+                            let (url, label) = match content.split_once(' ') {
+                                Some((url, label)) => (url, label.trim()),
+                                None => (content.as_str(), ""),
+                            };
+                            let elements = if label.is_empty() {
+                                vec![text(url)]
+                            } else {
+                                self.inline(label)?
+                            };
+                            inline.push(Element::Hyperlink {
+                                elements,
+                                url: url.to_string(),
+                                alt: String::new(),
+                                size: 14,
+                            });
+                            index += end + 1;
+                        }
+                        _ => {
+                            inline.push_str("[");
+                            index += 1;
+                        }
+                    }
+                }
+                '{' if chars.get(index + 1) == Some(&'{') => match template_end(&chars, index) {
+                    Some(end) => {
+                        let template: String = chars[index..end + 2].iter().collect();
+                        inline.push_str(&template);
+                        index = end + 2;
+                    }
+                    None => {
+                        inline.push_str("{{");
+                        index += 2;
+                    }
+                },
+                '<' => index = self.tag(&chars, index, &mut inline)?,
+                '&' => {
+                    let end = chars[index..]
+                        .iter()
+                        .take(10)
+                        .position(|c| *c == ';')
+                        .map(|end| index + end);
+                    match end.and_then(|end| {
+                        entity_char(&chars[index + 1..end].iter().collect::<String>())
+                            .map(|c| (c, end))
+                    }) {
+                        Some((c, end)) => {
+                            inline.push_str(&c.to_string());
+                            index = end + 1;
+                        }
+                        None => {
+                            inline.push_str("&");
+                            index += 1;
+                        }
+                    }
+                }
+                'h' | 'f' | 'm'
+                    if (index == 0 || !chars[index - 1].is_alphanumeric())
+                        && URI_SCHEMES
+                            .iter()
+                            .take(3)
+                            .any(|scheme| starts_with(&chars[index..], scheme)) =>
+                {
+                    let length = chars[index..]
+                        .iter()
+                        .position(|c| c.is_whitespace() || "[]<>\"{}|".contains(*c))
+                        .unwrap_or(chars.len() - index);
+                    let mut url: String = chars[index..index + length].iter().collect();
+                    while url.ends_with(['.', ',', ';', ':', '!', '?', ')', '\'']) {
+                        url.pop();
+                    }
+                    index += url.chars().count();
+                    inline.push(Element::Hyperlink {
+                        elements: vec![text(url.clone())],
+                        url,
+                        alt: String::new(),
+                        size: 14,
+                    });
+                }
+                '\n' => {
+                    inline.push_str(" ");
+                    index += 1;
+                }
+                c => {
+                    inline.push_str(&c.to_string());
+                    index += 1;
+                }
+            }
+        }
+        Ok(inline.finish())
+    }
+
+    /// Element of a wiki link: a link to a page or to a header of the page, an image of a
+    /// file, nothing for a category
+    fn wiki_link(&mut self, content: &str, trail: &str) -> anyhow::Result<Option<Element>> {
+        let parts = split_top_level(content, "|");
+        let target = parts[0].trim();
+        let lower = target.to_lowercase();
+        if lower.starts_with("category:") {
+            return Ok(None);
+        }
+        if let Some(namespace) = FILE_NAMESPACES
+            .iter()
+            .find(|namespace| lower.starts_with(*namespace))
+        {
+            return self.file(&target[namespace.len()..], &parts[1..]).map(Some);
+        }
+        let target = target.strip_prefix(':').unwrap_or(target);
+        let url = match target.strip_prefix('#') {
+            Some(header) => format!("#{}", header_anchor(&decode_entities(header))),
+            None => target.to_string(),
+        };
+        let mut elements = match parts.get(1..) {
+            Some(label) if !label.is_empty() && !label.join("|").trim().is_empty() => {
+                self.inline(label.join("|").trim())?
+            }
+            _ => vec![text(target.trim_start_matches('#'))],
+        };
+        if !trail.is_empty() {
+            elements.push(text(trail));
+        }
+        Ok(Some(Element::Hyperlink {
+            elements,
+            url,
+            alt: String::new(),
+            size: 14,
+        }))
+    }
+
+    /// Image of a `[[File:...]]` link: its width in pixels, alternative text and caption
+    fn file(&mut self, name: &str, options: &[&str]) -> anyhow::Result<Element> {
+        let name = name.trim();
+        let mut alt = String::new();
+        let mut size = ImageDimension::default();
+        let mut caption = None;
+        for option in options {
+            let option = option.trim();
+            if let Some(text) = option.strip_prefix("alt=") {
+                alt = plain_text(&self.inline(text)?);
+            } else if let Some(width) = option
+                .strip_suffix("px")
+                .filter(|width| !width.is_empty() && width.chars().all(|c| c.is_ascii_digit()))
+            {
+                size.width = Some(format!("{width}px"));
+            } else if !FILE_OPTIONS.contains(&option) && !option.contains('=') {
+                caption = Some(option);
+            }
+        }
+        let title = match caption {
+            Some(caption) => plain_text(&self.inline(caption)?),
+            None => String::new(),
+        };
+        Ok(Element::Image(ImageData::new(
+            (self.image_loader)(name)?,
+            title,
+            alt,
+            name.to_string(),
+            String::new(),
+            size,
+        )))
+    }
+
+    /// Tag at `index`: its element added to `inline`, and the index after it. Tags that are
+    /// not read are written as text.
+    fn tag(&mut self, chars: &[char], index: usize, inline: &mut Inline) -> anyhow::Result<usize> {
+        let Some(open_end) = chars[index..].iter().position(|c| *c == '>') else {
+            inline.push_str("<");
+            return Ok(index + 1);
+        };
+        let open_end = index + open_end;
+        let tag: String = chars[index + 1..open_end].iter().collect();
+        let self_closing = tag.ends_with('/');
+        let tag = tag.trim_end_matches('/');
+        let name: String = tag
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric())
+            .collect::<String>()
+            .to_lowercase();
+        let attributes = &tag[name.len()..];
+        if name.is_empty() {
+            inline.push_str("<");
+            return Ok(index + 1);
+        }
+        if name == "br" {
+            inline.push(Element::LineBreak);
+            return Ok(open_end + 1);
+        }
+        if self_closing {
+            match name.as_str() {
+                "ref" => {
+                    let name = attribute(attributes, "name").unwrap_or_default();
+                    match self.references.get(&name) {
+                        Some(elements) => inline.push(Element::Footnote {
+                            elements: elements.clone(),
+                        }),
+                        None => warn!("Reference without content: {name}"),
+                    }
+                }
+                "span" => {
+                    if let Some(id) = attribute(attributes, "id") {
+                        inline.push(Element::Bookmark {
+                            id,
+                            title: String::new(),
+                        });
+                    }
+                }
+                _ => {}
+            }
+            return Ok(open_end + 1);
+        }
+        let Some(close) = find(chars, open_end + 1, &format!("</{name}>")) else {
+            inline.push_str("<");
+            return Ok(index + 1);
+        };
+        let content: String = chars[open_end + 1..close].iter().collect();
+        let end = close + name.len() + 3;
+        match name.as_str() {
+            "nowiki" => inline.push_str(&decode_entities(&content)),
+            "math" => inline.push(Element::Math {
+                tex: decode_entities(content.trim()),
+                display: attribute(attributes, "display").as_deref() == Some("block"),
+            }),
+            "ref" => {
+                let elements = self.inline(content.trim())?;
+                if let Some(name) = attribute(attributes, "name") {
+                    self.references.insert(name, elements.clone());
+                }
+                inline.push(Element::Footnote { elements });
+            }
+            "span" if attribute(attributes, "id").is_some() => {
+                inline.push(Element::Bookmark {
+                    id: attribute(attributes, "id").unwrap_or_default(),
+                    title: String::new(),
+                });
+                inline.extend(self.inline(&content)?);
+            }
+            "syntaxhighlight" | "source" | "pre" => inline.push(Element::InlineCode {
+                text: decode_entities(&content),
+            }),
+            name if CODE_TAGS.contains(&name) => inline.push(Element::InlineCode {
+                text: plain_text(&self.inline(&content)?),
+            }),
+            name if TRANSPARENT_TAGS.contains(&name) => inline.extend(self.inline(&content)?),
+            name => match STYLE_TAGS.iter().find(|(tag, _)| *tag == name) {
+                Some((_, style)) => inline.push(Element::Styled {
+                    style: *style,
+                    elements: self.inline(&content)?,
+                }),
+                None => {
+                    inline.push_str("<");
+                    return Ok(index + 1);
+                }
+            },
+        }
+        Ok(end)
+    }
+}
+
+/// Index of the quotes closing emphasis of `marker` quotes opened before `start`, on the
+/// same line
+fn quote_end(chars: &[char], start: usize, marker: usize) -> Option<usize> {
+    let mut index = start;
+    while index < chars.len() && chars[index] != '\n' {
+        if chars[index] == '\'' {
+            let run = chars[index..].iter().take_while(|c| **c == '\'').count();
+            if run >= marker && index > start {
+                // the closing quotes are the last ones of a longer run
+                return Some(index + run - marker);
+            }
+            index += run;
+        } else {
+            index += 1;
+        }
+    }
+    None
+}
+
+/// Index of the last line of the template starting the line at `index`, when the template
+/// is the whole of its lines
+fn template_line_end(lines: &[&str], index: usize) -> Option<usize> {
+    let mut depth = 0i32;
+    for (offset, line) in lines[index..].iter().enumerate() {
+        depth += line.matches("{{").count() as i32;
+        depth -= line.matches("}}").count() as i32;
+        if depth <= 0 {
+            return line.trim_end().ends_with("}}").then_some(index + offset);
+        }
+    }
+    None
+}
+
+/// Source of a cell without its attributes, `style="..." | content`
+fn cell_content(cell: &str) -> &str {
+    let parts = split_top_level(cell, "|");
+    match parts.as_slice() {
+        [attributes, ..] if parts.len() > 1 && attributes.contains('=') => {
+            let offset = attributes.len() + 1;
+            cell[offset..].trim()
+        }
+        _ => cell.trim(),
+    }
+}
+
+/// Appends a line to the last cell, the lines of a cell after its marker line
+fn append_to_cell(rows: &mut [Vec<(bool, String)>], line: &str) {
+    if let Some((_, content)) = rows.last_mut().and_then(|row| row.last_mut()) {
+        content.push('\n');
+        content.push_str(line);
+    }
+}
+
+impl TransformerTrait for Transformer {
+    /// Document of a MediaWiki page, its images read from the current directory
+    fn parse(document: &Bytes) -> crate::Result<Document> {
+        Transformer::parse_with_loader(document, disk_image_loader("."))
+    }
+
+    /// MediaWiki page of the document, its images saved to the current directory
+    fn generate(document: &Document) -> crate::Result<Bytes> {
+        Transformer::generate_with_saver(document, disk_image_saver("."))
+    }
+}
+
+impl TransformerWithImageLoaderSaverTrait for Transformer {
+    /// Document of a MediaWiki page, its images read with `image_loader` from the names of
+    /// the `[[File:...]]` links
+    fn parse_with_loader<F>(document: &Bytes, image_loader: F) -> crate::Result<Document>
+    where
+        F: Fn(&str) -> anyhow::Result<Bytes>,
+    {
+        Transformer::parse_source(document, &image_loader_errors(image_loader))
+            .map_err(crate::Error::parse_error)
+    }
+
+    /// MediaWiki page of the document, its images saved with `image_saver` under the names
+    /// of their `[[File:...]]` links
+    fn generate_with_saver<F>(document: &Document, image_saver: F) -> crate::Result<Bytes>
+    where
+        F: Fn(&Bytes, &str) -> anyhow::Result<()>,
+    {
+        Transformer::generate_with_image_saver(document, image_saver)
+            .map_err(crate::Error::generation_error)
+    }
+}
+
+impl Transformer {
+    fn parse_source<F>(source: &Bytes, image_loader: &F) -> anyhow::Result<Document>
+    where
+        F: Fn(&str) -> anyhow::Result<Bytes>,
+    {
+        let source = std::str::from_utf8(source)?;
+        let source = source.strip_prefix('\u{feff}').unwrap_or(source);
+        let source = without_comments(&source.replace("\r\n", "\n"));
+        let lines: Vec<&str> = source.lines().collect();
+        let mut parser = Parser {
+            image_loader,
+            references: HashMap::new(),
+            depth: 0,
+        };
+        Ok(Document::new(parser.blocks(&lines)?))
+    }
+
+    fn generate_with_image_saver<F>(document: &Document, image_saver: F) -> anyhow::Result<Bytes>
+    where
+        F: Fn(&Bytes, &str) -> anyhow::Result<()>,
+    {
+        let document = &document.resolve_fields().without_drawings();
+        let mut headers = HashMap::new();
+        for element in document.get_all_elements() {
+            if let Element::Header { text, .. } = element {
+                headers
+                    .entry(header_anchor(text))
+                    .or_insert_with(|| text.trim().to_string());
+            }
+        }
+        let mut writer = Writer {
+            image_saver: &image_saver,
+            image_count: 0,
+            headers,
+            footnotes: false,
+            depth: 0,
+        };
+        let elements = document
+            .bands
+            .iter()
+            .filter(|band| !matches!(band, Band::PageHeader(_) | Band::PageFooter(_)))
+            .flat_map(|band| band.elements());
+        let mut wiki = writer.blocks(elements)?;
+        if writer.footnotes {
+            wiki.push_str("\n\n<references />");
+        }
+        Ok(Bytes::from(wiki.trim().to_string() + "\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+    use crate::core::tests::{init_logger, table_texts};
+    use crate::core::TransformerTrait;
+    use crate::markdown;
+
+    #[test]
+    fn test_generate() -> anyhow::Result<()> {
+        init_logger();
+        let markdown = "# Results *and* costs\n\nSee [the end](#the-end), \
+            [the site](https://example.com/a) and [home](Main_Page).[^1]\n\n\
+            1. One\n   - Nested **item**\n2. Two\n\n\
+            | Name | Value |\n|------|-------|\n| a | 1 \\| 2 |\n\n\
+            ```rust\nlet x = 2 * 3;\n```\n\n```\n<b> & </b>\n```\n\n## The end\n\n\
+            [^1]: A note.\n";
+        let mut document = markdown::Transformer::parse(&Bytes::from(markdown))?;
+        let png = Bytes::from(std::fs::read("test/data/small.png")?);
+        if let Some(Band::Detail(elements)) = document.bands.last_mut() {
+            elements.push(paragraph(vec![
+                Element::InlineCode {
+                    text: "a+b".to_string(),
+                },
+                text(" a ''quoted'' [word] "),
+                Element::Styled {
+                    style: TextStyle::Strikethrough,
+                    elements: vec![text("old")],
+                },
+                text(" "),
+                Element::Math {
+                    tex: "x^2".to_string(),
+                    display: false,
+                },
+            ]));
+            elements.push(paragraph(vec![text("* starts like a list")]));
+            elements.push(Element::Image(ImageData::new(
+                png.clone(),
+                "Logo".to_string(),
+                "The logo".to_string(),
+                "png".to_string(),
+                String::new(),
+                ImageDimension {
+                    width: Some("120px".to_string()),
+                    height: None,
+                },
+            )));
+            elements.push(Element::admonition(
+                "warning",
+                Some("Careful"),
+                vec![paragraph(vec![text("Hot.")])],
+            ));
+        }
+
+        let saved = RefCell::new(vec![]);
+        let wiki = Transformer::generate_with_saver(&document, |bytes, name| {
+            saved.borrow_mut().push((name.to_string(), bytes.clone()));
+            Ok(())
+        })?;
+        let wiki = std::str::from_utf8(&wiki)?;
+        assert!(wiki.starts_with("= Results and costs =\n\n"));
+        assert!(wiki.contains(
+            "See [[#The end|the end]], [https://example.com/a the site] and \
+             [[Main_Page|home]].<ref>A note.</ref>\n"
+        ));
+        assert!(wiki.contains("# One\n#* Nested '''item'''\n# Two\n"));
+        assert!(wiki.contains(
+            "{| class=\"wikitable\"\n! Name\n! Value\n|-\n| a\n| <nowiki>1 | 2</nowiki>\n|}\n"
+        ));
+        assert!(
+            wiki.contains("<syntaxhighlight lang=\"rust\">\nlet x = 2 * 3;\n</syntaxhighlight>\n")
+        );
+        assert!(wiki.contains("<pre>\n&lt;b&gt; &amp; &lt;/b&gt;\n</pre>\n"));
+        assert!(wiki.contains("== The end ==\n"));
+        assert!(wiki.contains(
+            "<code>a+b</code><nowiki> a ''quoted'' [word] </nowiki><s>old</s> <math>x^2</math>\n"
+        ));
+        assert!(wiki.contains("<nowiki/>* starts like a list\n"));
+        assert!(wiki.contains("[[File:image1.png|thumb|120px|alt=The logo|Logo]]\n"));
+        assert!(
+            wiki.contains("<div class=\"admonition warning\" title=\"Careful\">\nHot.\n</div>\n")
+        );
+        assert!(wiki.ends_with("\n\n<references />\n"));
+        assert_eq!(
+            saved.borrow().as_slice(),
+            &[("image1.png".to_string(), png)]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse() -> anyhow::Result<()> {
+        init_logger();
+        let source = "{{Infobox software\n| name = Shiva\n}}\n\
+            __TOC__\n\
+            == Getting '''started''' ==\n\
+            Install with <code>cargo install</code>, see [[#Usage|usage]], [[Rust]]s and\n\
+            [https://example.com the site].<ref name=\"b\">Or build it.</ref> \
+            Again<ref name=\"b\" /> &amp; '''''both''''' <!-- hidden -->{{citation needed}}\n\n\
+            <div class=\"admonition note\" title=\"Careful\">\nNeeds Rust 1.70.\n</div>\n\
+            * One\n** Nested\n* Two\n\n# First\n#: more\n# Second\n\n\
+            ; CPU : the processor\n\n\
+            == Usage ==\n\
+            {| class=\"wikitable\"\n|+ Options\n! Name !! Meaning\n|-\n\
+            | a || style=\"color: red\" | The ''a''\n|-\n| b\n| [[Rust|the language]]\n|}\n\
+            \x20shiva --help\n\x20shiva a &lt;b&gt;\n\n\
+            [[File:small.png|thumb|50px|alt=A logo|The ''logo'']]\n\n\
+            <math display=\"block\">x = 1</math>\n\n\
+            See https://example.org.<br />Done.\n\n\
+            [[Category:Tools]]\n\n<references />\n";
+        let requested = RefCell::new(vec![]);
+        let document = Transformer::parse_with_loader(&Bytes::from(source), |path| {
+            requested.borrow_mut().push(path.to_string());
+            Ok(Bytes::from(std::fs::read(format!("test/data/{path}"))?))
+        })?;
+        assert_eq!(requested.borrow().as_slice(), ["small.png"]);
+
+        let Some(Band::Detail(elements)) = document.bands.last() else {
+            panic!("no detail band");
+        };
+        assert_eq!(
+            elements[0],
+            Element::Raw {
+                format: DocumentType::MediaWiki,
+                content: "{{Infobox software\n| name = Shiva\n}}".to_string()
+            }
+        );
+        assert_eq!(elements[1], Element::TableOfContents { max_level: 3 });
+        assert_eq!(
+            elements[2],
+            Element::Header {
+                level: 2,
+                text: "Getting started".to_string()
+            }
+        );
+        let Element::Paragraph {
+            elements: inline, ..
+        } = &elements[3]
+        else {
+            panic!("not a paragraph: {:?}", elements[3]);
+        };
+        assert_eq!(
+            inline[1],
+            Element::InlineCode {
+                text: "cargo install".to_string()
+            }
+        );
+        assert!(matches!(&inline[3], Element::Hyperlink { url, .. } if url == "#usage"));
+        assert!(
+            matches!(&inline[5], Element::Hyperlink { url, elements, .. }
+            if url == "Rust" && elements == &[text("Rust"), text("s")])
+        );
+        assert!(
+            matches!(&inline[7], Element::Hyperlink { url, .. } if url == "https://example.com")
+        );
+        assert_eq!(
+            inline[9],
+            Element::Footnote {
+                elements: vec![text("Or build it.")]
+            }
+        );
+        assert_eq!(inline[10], text(" Again"));
+        assert_eq!(inline[11], inline[9]);
+        assert_eq!(inline[12], text(" & "));
+        assert_eq!(
+            inline[13],
+            Element::Styled {
+                style: TextStyle::Strong,
+                elements: vec![Element::Styled {
+                    style: TextStyle::Emphasis,
+                    elements: vec![text("both")],
+                }],
+            }
+        );
+        assert_eq!(inline[14], text(" {{citation needed}}"));
+        assert_eq!(
+            elements[4].admonition_parts(),
+            Some((
+                "note",
+                Some("Careful".to_string()),
+                &[paragraph(vec![text("Needs Rust 1.70.")])][..]
+            ))
+        );
+        let Element::List {
+            elements: items, ..
+        } = &elements[5]
+        else {
+            panic!("not a list: {:?}", elements[5]);
+        };
+        assert_eq!(items[0].element, text("One"));
+        assert!(matches!(&items[1].element, Element::List { elements, .. } if elements.len() == 1));
+        assert_eq!(items[2].element, text("Two"));
+        let Element::List {
+            elements: items,
+            numbered: true,
+            ..
+        } = &elements[6]
+        else {
+            panic!("not a numbered list: {:?}", elements[6]);
+        };
+        assert_eq!(
+            items[0].element,
+            paragraph(vec![text("First"), Element::LineBreak, text("more")])
+        );
+        assert_eq!(items.len(), 2);
+        assert_eq!(
+            elements[7],
+            paragraph(vec![Element::Styled {
+                style: TextStyle::Strong,
+                elements: vec![text("CPU")],
+            }])
+        );
+        assert_eq!(elements[8], paragraph(vec![text("the processor")]));
+        let Element::Table { headers, rows } = &elements[10] else {
+            panic!("not a table: {:?}", elements[10]);
+        };
+        assert_eq!(headers[1].element, text("Meaning"));
+        assert_eq!(
+            rows[0].cells[1].element,
+            paragraph(vec![
+                text("The "),
+                Element::Styled {
+                    style: TextStyle::Emphasis,
+                    elements: vec![text("a")],
+                }
+            ])
+        );
+        assert!(
+            matches!(&rows[1].cells[1].element, Element::Paragraph { elements, .. }
+            if matches!(&elements[0], Element::Hyperlink { url, .. } if url == "Rust"))
+        );
+        assert_eq!(
+            elements[11],
+            Element::CodeBlock {
+                info: String::new(),
+                code: "shiva --help\nshiva a <b>".to_string()
+            }
+        );
+        let Element::Paragraph {
+            elements: inline, ..
+        } = &elements[12]
+        else {
+            panic!("not a paragraph: {:?}", elements[12]);
+        };
+        let Element::Image(image) = &inline[0] else {
+            panic!("not an image: {:?}", inline[0]);
+        };
+        assert_eq!(image.title(), "The logo");
+        assert_eq!(image.alt(), "A logo");
+        assert_eq!(image.size().width.as_deref(), Some("50px"));
+        assert_eq!(
+            elements[13],
+            Element::Math {
+                tex: "x = 1".to_string(),
+                display: true
+            }
+        );
+        assert!(matches!(&elements[14], Element::Paragraph { elements, .. }
+            if matches!(&elements[1], Element::Hyperlink { url, .. } if url == "https://example.org")
+                && elements[3] == Element::LineBreak));
+        assert_eq!(elements.len(), 15);
+        Ok(())
+    }
+
+    #[test]
+    fn test_round_trip() -> anyhow::Result<()> {
+        init_logger();
+        let source = "= Notes =\n\n\
+            Some '''bold''', ''emphasis'', <s>gone</s>, <code>code</code> and a \
+            [https://example.com link].\n\n\
+            == Lists ==\n\n* One\n* Two\n** Nested\n\n# First\n# Second\n\n\
+            {| class=\"wikitable\"\n! a\n! b\n|-\n| 1\n| 2\n|}\n\n\
+            <syntaxhighlight lang=\"python\">\nprint(1)\n</syntaxhighlight>\n\n\
+            See [[#Lists|the lists]] and [[Main Page]].\n";
+        let document = Transformer::parse(&Bytes::from(source))?;
+        let generated = Transformer::generate(&document)?;
+        assert_eq!(std::str::from_utf8(&generated)?, source);
+        Ok(())
+    }
+
+    #[test]
+    fn test_tables() -> anyhow::Result<()> {
+        let source = r#"{| class="wikitable"
+|+ Prices
+! Item !! Price
+|-
+| style="color: red" | [[Apple|apples]] || '''1'''
+|-
+| pears
+| 2
+|}
+
+{|
+! Total
+| 3
+|}
+"#;
+        let document = Transformer::parse(&Bytes::from(source))?;
+        let elements = document.get_all_elements();
+        assert_eq!(elements.len(), 2);
+
+        // a first row of `!` cells is the header, the cell attributes and the caption are
+        // left out and the `|` of a link does not split its cell
+        let (headers, rows) = table_texts(elements[0]);
+        assert_eq!(headers, ["Item", "Price"]);
+        assert_eq!(rows, [vec!["apples", "1"], vec!["pears", "2"]]);
+
+        // a row mixing `!` and `|` cells is not a header
+        let (headers, rows) = table_texts(elements[1]);
+        assert!(headers.is_empty());
+        assert_eq!(rows, [vec!["Total", "3"]]);
+        Ok(())
+    }
+}
@@ -8,14 +8,23 @@ use std::io::Cursor;
 pub struct Transformer;
 
 impl TransformerTrait for Transformer {
-    fn parse(document: &Bytes) -> anyhow::Result<Document>
-    where
-        Self: Sized,
-    {
+    fn parse(document: &Bytes) -> crate::Result<Document> {
+        Transformer::parse_bytes(document).map_err(crate::Error::parse_error)
+    }
+
+    /// XLS is read only, the workbooks are written as XLSX
+    fn generate(_document: &Document) -> crate::Result<Bytes> {
+        Err(crate::Error::UnsupportedFormat(
+            DocumentType::XLS.to_string(),
+        ))
+    }
+}
+
+impl Transformer {
+    fn parse_bytes(document: &Bytes) -> anyhow::Result<Document> {
         let cursor = Cursor::new(document.clone());
 
-        let mut workbook: Xls<Cursor<Bytes>> =
-            open_workbook_from_rs(cursor).expect("Cannot open xls file from bytes");
+        let mut workbook: Xls<Cursor<Bytes>> = open_workbook_from_rs(cursor)?;
 
         let mut data: Vec<Element> = Vec::new();
 
@@ -65,13 +74,6 @@ impl TransformerTrait for Transformer {
 
         Ok(Document::new(data))
     }
-
-    fn generate(_document: &Document) -> anyhow::Result<Bytes>
-    where
-        Self: Sized,
-    {
-        todo!()
-    }
 }
 
 #[cfg(test)]
@@ -107,4 +109,19 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_read_only() -> anyhow::Result<()> {
+        let document = Transformer::parse(&Bytes::from(std::fs::read("test/data/document.xls")?))?;
+        assert!(matches!(
+            Transformer::generate(&document),
+            Err(crate::Error::UnsupportedFormat(_))
+        ));
+        assert!(matches!(
+            TransformerRegistry::builtin().generate(DocumentType::XLS, &document),
+            Err(crate::Error::UnsupportedFormat(_))
+        ));
+        assert!(Transformer::parse(&Bytes::from("not a workbook")).is_err());
+        Ok(())
+    }
 }
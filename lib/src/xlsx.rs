@@ -1,61 +1,148 @@
-use crate::core::Element::{Table, Text};
+//! XLSX workbooks: each sheet is read as a header of its name and a table whose first row
+//! is the header row, and each table of a document is written to a sheet named after the
+//! header before it
+use crate::core::Element::{Header, Table, Text};
 use crate::core::*;
 use bytes::Bytes;
 use calamine::{open_workbook_from_rs, Reader, Xlsx};
 use log::error;
 use rust_xlsxwriter::*;
+use std::collections::HashSet;
 use std::io::Cursor;
 pub struct Transformer;
 
 impl TransformerTrait for Transformer {
-    fn parse(document: &Bytes) -> anyhow::Result<Document>
-    where
-        Self: Sized,
-    {
+    fn parse(document: &Bytes) -> crate::Result<Document> {
+        Transformer::parse_bytes(document).map_err(crate::Error::parse_error)
+    }
+
+    fn generate(document: &Document) -> crate::Result<Bytes> {
+        Transformer::generate_bytes(document).map_err(crate::Error::generation_error)
+    }
+}
+
+/// Longest name of a sheet in Excel
+const MAX_SHEET_NAME: usize = 31;
+
+/// Name of a sheet from the text of a header, without the characters that Excel does not
+/// allow and at most 31 characters long
+fn sheet_name(text: &str) -> String {
+    let name: String = text
+        .chars()
+        .filter(|c| !matches!(c, '*' | '?' | ':' | '[' | ']' | '\\' | '/') && !c.is_control())
+        .collect();
+    let name: String = name
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .chars()
+        .take(MAX_SHEET_NAME)
+        .collect();
+    name.trim_matches(|c: char| c == '\'' || c.is_whitespace())
+        .to_string()
+}
+
+/// Name not yet taken by a sheet, Excel compares them without case. The name is numbered
+/// `Name (2)`, `Name (3)`... when it is taken and `Sheet{n}` when it is empty.
+fn unique_sheet_name(name: String, index: usize, taken: &mut HashSet<String>) -> String {
+    let name = if name.is_empty() {
+        format!("Sheet{index}")
+    } else {
+        name
+    };
+    let mut unique = name.clone();
+    let mut number = 2;
+    // Excel reserves History for its own sheet
+    while taken.contains(&unique.to_lowercase()) || unique.eq_ignore_ascii_case("history") {
+        let suffix = format!(" ({number})");
+        let base: String = name.chars().take(MAX_SHEET_NAME - suffix.len()).collect();
+        unique = format!("{}{suffix}", base.trim_end());
+        number += 1;
+    }
+    taken.insert(unique.to_lowercase());
+    unique
+}
+
+/// Tables of an element and of its children with the text of the header before each of them
+fn collect_tables<'a>(
+    element: &'a Element,
+    header: &mut Option<String>,
+    tables: &mut Vec<(Option<String>, &'a Element)>,
+) {
+    match element {
+        Header { text, .. } => *header = Some(text.clone()),
+        Table { .. } => tables.push((header.clone(), element)),
+        _ => {}
+    }
+    for child in element.children() {
+        collect_tables(child, header, tables);
+    }
+}
+
+/// Writes the text of a cell, as a number when it is written the same way as one so that
+/// formulas and sorting work on it
+fn write_cell(
+    worksheet: &mut Worksheet,
+    row: u32,
+    column: u16,
+    text: &str,
+    format: &Format,
+) -> Result<(), XlsxError> {
+    match text.parse::<f64>() {
+        Ok(number) if number.is_finite() && number.to_string() == text => {
+            worksheet.write_number_with_format(row, column, number, format)?;
+        }
+        _ => {
+            worksheet.write_string_with_format(row, column, text, format)?;
+        }
+    }
+    Ok(())
+}
+
+impl Transformer {
+    fn parse_bytes(document: &Bytes) -> anyhow::Result<Document> {
         let cursor = Cursor::new(document.clone());
 
-        let mut workbook: Xlsx<Cursor<Bytes>> =
-            open_workbook_from_rs(cursor).expect("Cannot open xlsx file from bytes");
+        let mut workbook: Xlsx<Cursor<Bytes>> = open_workbook_from_rs(cursor)?;
 
         let mut data: Vec<Element> = Vec::new();
 
         for sheet_name in workbook.sheet_names().clone() {
             match workbook.worksheet_range(&sheet_name) {
                 Ok(range) => {
-                    let mut table_rows: Vec<TableRow> = Vec::new();
-                    let mut table_headers: Vec<TableHeader> = Vec::new();
-                    let mut is_first_row = true;
-
-                    for row in range.rows() {
-                        if is_first_row {
-                            table_headers = row
-                                .iter()
-                                .map(|header| TableHeader {
-                                    element: Text {
-                                        text: header.to_string(),
-                                        size: 8,
-                                    },
-                                    width: 10.0,
-                                })
-                                .collect();
-                            is_first_row = false;
-                        } else {
-                            let cells = row
+                    data.push(Header {
+                        level: 1,
+                        text: sheet_name.clone(),
+                    });
+                    let mut rows = range.rows();
+                    // an empty sheet is its header only
+                    let Some(first_row) = rows.next() else {
+                        continue;
+                    };
+                    let headers = first_row
+                        .iter()
+                        .map(|header| TableHeader {
+                            element: Text {
+                                text: header.to_string(),
+                                size: 8,
+                            },
+                            width: 10.0,
+                        })
+                        .collect();
+                    let rows = rows
+                        .map(|row| TableRow {
+                            cells: row
                                 .iter()
-                                .map(|header| TableCell {
+                                .map(|cell| TableCell {
                                     element: Text {
-                                        text: header.to_string(),
+                                        text: cell.to_string(),
                                         size: 8,
                                     },
                                 })
-                                .collect();
-                            table_rows.push(TableRow { cells });
-                        }
-                    }
-                    data.push(Table {
-                        headers: table_headers.clone(),
-                        rows: table_rows.clone(),
-                    });
+                                .collect(),
+                        })
+                        .collect();
+                    data.push(Table { headers, rows });
                 }
                 Err(err) => {
                     error!("Error reading sheet {}: {}", sheet_name, err);
@@ -66,38 +153,48 @@ impl TransformerTrait for Transformer {
         Ok(Document::new(data))
     }
 
-    fn generate(document: &Document) -> anyhow::Result<Bytes>
-    where
-        Self: Sized,
-    {
+    fn generate_bytes(document: &Document) -> anyhow::Result<Bytes> {
+        let document = &document.resolve_fields();
         let mut workbook = Workbook::new();
-        fn generate_element(element: &Element, workbook: &mut Workbook) -> anyhow::Result<()> {
-            if let Table { headers, rows } = element {
-                let worksheet = workbook.add_worksheet();
-                let mut row_index = 1;
-                let mut col_index = 0;
-                for header in headers {
-                    if let Text { text, .. } = header.element.clone() {
-                        worksheet.write_string(0, col_index, text)?;
-                        col_index += 1;
-                    }
-                }
+        let header_format = Format::new().set_bold();
+        let cell_format = Format::new();
 
-                for row in rows {
-                    let mut col_index = 0;
-                    for cell in row.cells.iter() {
-                        if let Text { text, .. } = cell.element.clone() {
-                            worksheet.write_string(row_index, col_index, text)?;
-                            col_index += 1;
-                        }
-                    }
-                    row_index += 1;
+        let mut header = None;
+        let mut tables = vec![];
+        for element in document.get_all_elements() {
+            collect_tables(element, &mut header, &mut tables);
+        }
+
+        let mut taken = HashSet::new();
+        for (index, (header, table)) in tables.into_iter().enumerate() {
+            let Table { headers, rows } = table else {
+                continue;
+            };
+            let name = unique_sheet_name(
+                header.as_deref().map(sheet_name).unwrap_or_default(),
+                index + 1,
+                &mut taken,
+            );
+            let worksheet = workbook.add_worksheet();
+            worksheet.set_name(name)?;
+
+            let mut row_index = 0;
+            if !headers.is_empty() {
+                for (col_index, header) in headers.iter().enumerate() {
+                    let text = header.element.plain_text();
+                    write_cell(worksheet, 0, col_index as u16, &text, &header_format)?;
                 }
+                worksheet.set_freeze_panes(1, 0)?;
+                row_index = 1;
             }
-            Ok(())
-        }
-        for element in &document.get_detail() {
-            generate_element(element, &mut workbook)?;
+            for row in rows {
+                for (col_index, cell) in row.cells.iter().enumerate() {
+                    let text = cell.element.plain_text();
+                    write_cell(worksheet, row_index, col_index as u16, &text, &cell_format)?;
+                }
+                row_index += 1;
+            }
+            worksheet.autofit();
         }
         let xlsx_data = workbook.save_to_buffer()?;
         Ok(Bytes::from(xlsx_data))
@@ -106,7 +203,7 @@ impl TransformerTrait for Transformer {
 
 #[cfg(test)]
 mod tests {
-    use crate::core::tests::init_logger;
+    use crate::core::tests::{init_logger, output_dir, table_texts};
     use crate::xlsx::*;
     use anyhow::Ok;
     use bytes::Bytes;
@@ -126,6 +223,9 @@ mod tests {
         let parsed = Transformer::parse(&bytes)?;
 
         debug!("Parsed document: {:?}", parsed);
+        let elements = parsed.get_all_elements();
+        assert!(matches!(elements[0], Header { level: 1, .. }));
+        assert!(matches!(elements[1], Table { .. }));
 
         Ok(())
     }
@@ -141,13 +241,107 @@ mod tests {
         let bytes = Bytes::from(buffer);
         let parsed = Transformer::parse(&bytes)?;
 
-        let generated_data: Result<bytes::Bytes, anyhow::Error> = Transformer::generate(&parsed);
+        let generated_data: crate::Result<bytes::Bytes> = Transformer::generate(&parsed);
 
         let bytes_to_write = generated_data?;
-        std::fs::write("test/data/test_document.xlsx", bytes_to_write)?;
+        std::fs::write(
+            format!("{}/test_document.xlsx", output_dir()),
+            bytes_to_write,
+        )?;
 
         info!("Excel file created successfully!");
 
         Ok(())
     }
+
+    #[test]
+    fn test_sheets_of_tables() -> anyhow::Result<()> {
+        init_logger();
+        let table = |header: &str, cell: &str| Table {
+            headers: vec![TableHeader {
+                element: Text {
+                    text: header.to_string(),
+                    size: 8,
+                },
+                width: 10.0,
+            }],
+            rows: vec![TableRow {
+                cells: vec![TableCell {
+                    element: Text {
+                        text: cell.to_string(),
+                        size: 8,
+                    },
+                }],
+            }],
+        };
+        let document = Document::new(vec![
+            table("Note", "first"),
+            Header {
+                level: 2,
+                text: "Sales: Q1/Q2".to_string(),
+            },
+            table("Total", "12.5"),
+            Element::Container {
+                kind: ContainerKind::Div,
+                elements: vec![table("Total", "007")],
+            },
+        ]);
+        let parsed = Transformer::parse(&Transformer::generate(&document)?)?;
+        let names: Vec<String> = parsed
+            .get_all_elements()
+            .into_iter()
+            .filter_map(|element| match element {
+                Header { text, .. } => Some(text.clone()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(names, ["Sheet1", "Sales Q1Q2", "Sales Q1Q2 (2)"]);
+        let cells: Vec<String> = parsed
+            .get_all_elements()
+            .into_iter()
+            .filter_map(|element| match element {
+                Table { rows, .. } => Some(rows[0].cells[0].element.plain_text()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(cells, ["first", "12.5", "007"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_generate_fields() -> anyhow::Result<()> {
+        init_logger();
+        let row = |element| TableRow {
+            cells: vec![TableCell { element }],
+        };
+        let document = Document::new(vec![
+            Header {
+                level: 1,
+                text: "Prices".to_string(),
+            },
+            Table {
+                headers: vec![],
+                rows: vec![
+                    row(Element::CrossReference {
+                        target_id: "prices".to_string(),
+                        kind: ReferenceKind::Section,
+                    }),
+                    row(Element::Field {
+                        kind: FieldKind::DocumentTitle,
+                    }),
+                ],
+            },
+        ]);
+        let parsed = Transformer::parse(&Transformer::generate(&document)?)?;
+        let table = parsed
+            .get_all_elements()
+            .into_iter()
+            .find(|element| matches!(element, Table { .. }))
+            .expect("no table");
+        // the first row of a sheet is read as the header
+        let (headers, rows) = table_texts(table);
+        assert_eq!(headers, ["Section 1"]);
+        assert_eq!(rows, [vec!["Prices"]]);
+        Ok(())
+    }
 }
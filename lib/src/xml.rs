@@ -5,11 +5,14 @@ use quick_xml::{
     events::{BytesDecl, BytesEnd, BytesStart, BytesText, Event},
     Reader, Writer,
 };
-use std::str::from_utf8;
+use std::collections::HashMap;
+use std::str::{from_utf8, FromStr};
 
 use crate::core::{
-    Document, Element, ImageAlignment, ImageData, ImageDimension, ImageType, ListItem,
-    PageDimensions, PageFormat, TableCell, TableHeader, TableRow, TransformerTrait,
+    disk_image_saver, ContainerKind, Document, DocumentType, Element, FieldKind, GenerateOptions,
+    ImageAlignment, ImageData, ImageDimension, ImageType, ListItem, MediaKind, NumberingStyle,
+    PageDimensions, PageFormat, ReferenceKind, Shape, TableCell, TableHeader, TableRow,
+    TextDirection, TextStyle, TransformerTrait,
 };
 
 use serde::{Deserialize, Serialize};
@@ -94,7 +97,30 @@ impl Node {
 pub struct Transformer;
 
 impl TransformerTrait for Transformer {
-    fn parse(document: &Bytes) -> Result<Document> {
+    fn parse(document: &Bytes) -> crate::Result<Document> {
+        Transformer::parse_bytes(document).map_err(crate::Error::parse_error)
+    }
+
+    fn generate(document: &Document) -> crate::Result<Bytes> {
+        Transformer::generate_bytes(document).map_err(crate::Error::generation_error)
+    }
+
+    /// Written in the schema of the mapping of the options when there is one, its images
+    /// saved to the current directory
+    fn generate_with_options(
+        document: &Document,
+        options: &GenerateOptions,
+    ) -> crate::Result<Bytes> {
+        match &options.xml.mapping {
+            Some(mapping) => crate::xml_mapping::generate(document, mapping, disk_image_saver("."))
+                .map_err(crate::Error::generation_error),
+            None => Transformer::generate(document),
+        }
+    }
+}
+
+impl Transformer {
+    fn parse_bytes(document: &Bytes) -> Result<Document> {
         let xml_data = from_utf8(&document)?;
         let mut reader = Reader::from_str(xml_data);
         reader.trim_text(true);
@@ -125,12 +151,25 @@ impl TransformerTrait for Transformer {
                 match element.name.as_str() {
                     "Paragraph" => {
                         let sub_elements = parse_element(element)?;
+                        let mut direction = TextDirection::default();
+                        for child in element.children.iter() {
+                            if child.name == "direction" {
+                                if let Some(value) = &child.text {
+                                    direction = TextDirection::from_str(value)?;
+                                } else {
+                                    error!("Error: No value");
+                                }
+                            }
+                        }
                         elements.push(Element::Paragraph {
                             elements: sub_elements,
+                            direction,
                         });
                     }
                     "List" => {
                         let mut numbered = false;
+                        let mut start = 1;
+                        let mut numbering = NumberingStyle::default();
                         let mut sub_elements: Vec<ListItem> = vec![];
                         for child in element.children.iter() {
                             match child.name.as_str() {
@@ -144,12 +183,28 @@ impl TransformerTrait for Transformer {
                                         error!("Error: No value");
                                     }
                                 }
+                                "start" => {
+                                    if let Some(value) = &child.text {
+                                        start = value.parse()?;
+                                    } else {
+                                        error!("Error: No value");
+                                    }
+                                }
+                                "numbering" => {
+                                    if let Some(value) = &child.text {
+                                        numbering = NumberingStyle::from_str(value)?;
+                                    } else {
+                                        error!("Error: No value");
+                                    }
+                                }
                                 _ => {}
                             }
                         }
                         elements.push(Element::List {
                             elements: sub_elements,
                             numbered: numbered,
+                            start,
+                            numbering,
                         });
                     }
                     "elements" => {
@@ -253,10 +308,281 @@ impl TransformerTrait for Transformer {
                             ImageDimension { width, height },
                         )));
                     }
+                    "LineBreak" => {
+                        elements.push(Element::LineBreak);
+                    }
+                    "Field" => {
+                        for child in element.children.iter() {
+                            if child.name == "kind" {
+                                if let Some(value) = &child.text {
+                                    elements.push(Element::Field {
+                                        kind: FieldKind::from_str(value)?,
+                                    });
+                                } else {
+                                    error!("Error: No value");
+                                }
+                            }
+                        }
+                    }
+                    "CrossReference" => {
+                        let mut target_id = "_";
+                        let mut kind = ReferenceKind::Section;
+                        for child in element.children.iter() {
+                            match child.name.as_str() {
+                                "target_id" => {
+                                    if let Some(value) = &child.text {
+                                        target_id = value;
+                                    } else {
+                                        error!("Error: No value");
+                                    }
+                                }
+                                "kind" => {
+                                    if let Some(value) = &child.text {
+                                        kind = ReferenceKind::from_str(value)?;
+                                    } else {
+                                        error!("Error: No value");
+                                    }
+                                }
+                                _ => {}
+                            }
+                        }
+                        elements.push(Element::CrossReference {
+                            target_id: target_id.to_string(),
+                            kind,
+                        });
+                    }
+                    "Attributed" => {
+                        let mut attributes = HashMap::new();
+                        let mut inner = None;
+                        for child in element.children.iter() {
+                            match child.name.as_str() {
+                                "attribute" => {
+                                    let key = child.attributes.iter().find(|a| a.key == "key");
+                                    let value = child.attributes.iter().find(|a| a.key == "value");
+                                    if let (Some(key), Some(value)) = (key, value) {
+                                        attributes.insert(key.value.clone(), value.value.clone());
+                                    } else {
+                                        error!("Error: No value");
+                                    }
+                                }
+                                "element" => {
+                                    inner = parse_element(child)?.into_iter().next();
+                                }
+                                _ => {}
+                            }
+                        }
+                        if let Some(inner) = inner {
+                            elements.push(inner.with_attributes(attributes));
+                        } else {
+                            error!("Error: No element");
+                        }
+                    }
+                    "Bookmark" => {
+                        let mut id = "_";
+                        let mut title = "_";
+                        for child in element.children.iter() {
+                            match child.name.as_str() {
+                                "id" => {
+                                    if let Some(value) = &child.text {
+                                        id = value;
+                                    } else {
+                                        error!("Error: No value");
+                                    }
+                                }
+                                "title" => {
+                                    if let Some(value) = &child.text {
+                                        title = value;
+                                    } else {
+                                        error!("Error: No value");
+                                    }
+                                }
+                                _ => {}
+                            }
+                        }
+                        elements.push(Element::Bookmark {
+                            id: id.to_string(),
+                            title: title.to_string(),
+                        });
+                    }
+                    "Media" => {
+                        let mut kind = MediaKind::Video;
+                        let mut url = "";
+                        let mut title = "";
+                        for child in element.children.iter() {
+                            match child.name.as_str() {
+                                "kind" => {
+                                    kind = MediaKind::from_str(
+                                        child.text.as_deref().unwrap_or_default(),
+                                    )?
+                                }
+                                "url" => url = child.text.as_deref().unwrap_or_default(),
+                                "title" => title = child.text.as_deref().unwrap_or_default(),
+                                _ => {}
+                            }
+                        }
+                        elements.push(Element::Media {
+                            kind,
+                            url: url.to_string(),
+                            title: title.to_string(),
+                        });
+                    }
+                    "Drawing" => {
+                        let (mut width, mut height) = (0.0, 0.0);
+                        let mut shapes = Vec::new();
+                        for child in element.children.iter() {
+                            let text = child.text.as_deref().unwrap_or_default();
+                            match child.name.as_str() {
+                                "width" => width = text.parse()?,
+                                "height" => height = text.parse()?,
+                                "Shape" => {
+                                    let mut shape = Shape::path(Vec::new()).without_stroke();
+                                    for field in child.children.iter() {
+                                        let text = field.text.as_deref().unwrap_or_default();
+                                        match field.name.as_str() {
+                                            "path" => {
+                                                shape.path = crate::drawing::parse_path_data(text)?
+                                            }
+                                            "stroke" => shape.stroke = Some(text.to_string()),
+                                            "stroke_width" => shape.stroke_width = text.parse()?,
+                                            "fill" => shape.fill = Some(text.to_string()),
+                                            _ => {}
+                                        }
+                                    }
+                                    shapes.push(shape);
+                                }
+                                _ => {}
+                            }
+                        }
+                        elements.push(Element::Drawing {
+                            width,
+                            height,
+                            shapes,
+                        });
+                    }
+                    "TableOfContents" => {
+                        let mut max_level = 3;
+                        for child in element.children.iter() {
+                            if child.name == "max_level" {
+                                if let Some(value) = &child.text {
+                                    max_level = value.parse()?;
+                                } else {
+                                    error!("Error: No value");
+                                }
+                            }
+                        }
+                        elements.push(Element::TableOfContents { max_level });
+                    }
+                    "InlineCode" => {
+                        let mut text = "";
+                        for child in element.children.iter() {
+                            if child.name == "text" {
+                                text = child.text.as_deref().unwrap_or_default();
+                            }
+                        }
+                        elements.push(Element::InlineCode {
+                            text: text.to_string(),
+                        });
+                    }
+                    "Raw" => {
+                        let mut format = DocumentType::HTML;
+                        let mut content = "";
+                        for child in element.children.iter() {
+                            match child.name.as_str() {
+                                "format" => {
+                                    format = child.text.as_deref().unwrap_or_default().parse()?
+                                }
+                                "content" => content = child.text.as_deref().unwrap_or_default(),
+                                _ => {}
+                            }
+                        }
+                        elements.push(Element::Raw {
+                            format,
+                            content: content.to_string(),
+                        });
+                    }
+                    "CodeBlock" => {
+                        let mut info = "";
+                        let mut code = "";
+                        for child in element.children.iter() {
+                            match child.name.as_str() {
+                                "info" => info = child.text.as_deref().unwrap_or_default(),
+                                "code" => code = child.text.as_deref().unwrap_or_default(),
+                                _ => {}
+                            }
+                        }
+                        elements.push(Element::CodeBlock {
+                            info: info.to_string(),
+                            code: code.to_string(),
+                        });
+                    }
+                    "Math" => {
+                        let mut tex = "";
+                        let mut display = false;
+                        for child in element.children.iter() {
+                            match child.name.as_str() {
+                                "tex" => tex = child.text.as_deref().unwrap_or_default(),
+                                "display" => display = child.text.as_deref() == Some("true"),
+                                _ => {}
+                            }
+                        }
+                        elements.push(Element::Math {
+                            tex: tex.to_string(),
+                            display,
+                        });
+                    }
+                    "Styled" => {
+                        let mut style = TextStyle::Emphasis;
+                        let mut styled_elements = vec![];
+                        for child in element.children.iter() {
+                            match child.name.as_str() {
+                                "style" => {
+                                    style = TextStyle::from_str(
+                                        child.text.as_deref().unwrap_or_default(),
+                                    )?
+                                }
+                                "elements" => styled_elements = parse_element(child)?,
+                                _ => {}
+                            }
+                        }
+                        elements.push(Element::Styled {
+                            style,
+                            elements: styled_elements,
+                        });
+                    }
+                    "Container" => {
+                        let mut kind = ContainerKind::Section;
+                        let mut container_elements = vec![];
+                        for child in element.children.iter() {
+                            match child.name.as_str() {
+                                "kind" => {
+                                    kind = ContainerKind::from_str(
+                                        child.text.as_deref().unwrap_or_default(),
+                                    )?
+                                }
+                                "elements" => container_elements = parse_element(child)?,
+                                _ => {}
+                            }
+                        }
+                        elements.push(Element::Container {
+                            kind,
+                            elements: container_elements,
+                        });
+                    }
+                    "Footnote" => {
+                        let mut note_elements = vec![];
+                        for child in element.children.iter() {
+                            if child.name == "elements" {
+                                note_elements = parse_element(child)?;
+                            }
+                        }
+                        elements.push(Element::Footnote {
+                            elements: note_elements,
+                        });
+                    }
                     "Hyperlink" => {
                         let mut url = "_";
                         let mut alt = "_";
-                        let mut title = "_";
+                        let mut link_elements = vec![];
                         let mut size = 10;
                         for child in element.children.iter() {
                             match child.name.as_str() {
@@ -281,9 +607,15 @@ impl TransformerTrait for Transformer {
                                         error!("Error: No value");
                                     }
                                 }
+                                "elements" => {
+                                    link_elements = parse_element(child)?;
+                                }
                                 "title" => {
                                     if let Some(value) = &child.text {
-                                        title = value;
+                                        link_elements.push(Element::Text {
+                                            text: value.to_string(),
+                                            size: 14,
+                                        });
                                     } else {
                                         error!("Error: No value");
                                     }
@@ -292,7 +624,7 @@ impl TransformerTrait for Transformer {
                             }
                         }
                         elements.push(Element::Hyperlink {
-                            title: title.to_string(),
+                            elements: link_elements,
                             url: url.to_string(),
                             alt: alt.to_string(),
                             size: size,
@@ -575,6 +907,8 @@ impl TransformerTrait for Transformer {
                                 }
                                 "List" => {
                                     let mut numbered = false;
+                                    let mut start = 1;
+                                    let mut numbering = NumberingStyle::default();
                                     let mut sub_elements: Vec<ListItem> = vec![];
                                     for sub_child in child.children.iter() {
                                         match sub_child.name.as_str() {
@@ -588,6 +922,20 @@ impl TransformerTrait for Transformer {
                                                     error!("Error: No value");
                                                 }
                                             }
+                                            "start" => {
+                                                if let Some(value) = &sub_child.text {
+                                                    start = value.parse()?;
+                                                } else {
+                                                    error!("Error: No value");
+                                                }
+                                            }
+                                            "numbering" => {
+                                                if let Some(value) = &sub_child.text {
+                                                    numbering = NumberingStyle::from_str(value)?;
+                                                } else {
+                                                    error!("Error: No value");
+                                                }
+                                            }
                                             _ => {}
                                         }
                                     }
@@ -595,6 +943,8 @@ impl TransformerTrait for Transformer {
                                         element: Element::List {
                                             elements: sub_elements,
                                             numbered: numbered,
+                                            start,
+                                            numbering,
                                         },
                                     });
                                 }
@@ -727,7 +1077,7 @@ impl TransformerTrait for Transformer {
         Ok(document)
     }
 
-    fn generate(document: &Document) -> Result<Bytes> {
+    fn generate_bytes(document: &Document) -> Result<Bytes> {
         let mut buffer: Vec<u8> = Vec::new();
         let mut writer = Writer::new(&mut buffer);
         writer.write_event(Event::Decl(BytesDecl::from_start(
@@ -748,13 +1098,21 @@ impl TransformerTrait for Transformer {
                     writer.write_event(Event::End(BytesEnd::new("level")))?;
                     writer.write_event(Event::End(BytesEnd::new("Header")))?;
                 }
-                Element::Paragraph { elements } => {
+                Element::Paragraph {
+                    elements,
+                    direction,
+                } => {
                     writer.write_event(Event::Start(BytesStart::new("Paragraph")))?;
                     writer.write_event(Event::Start(BytesStart::new("elements")))?;
                     for sub_element in elements {
                         serialize_element(sub_element, writer)?;
                     }
                     writer.write_event(Event::End(BytesEnd::new("elements")))?;
+                    if *direction != TextDirection::Auto {
+                        writer.write_event(Event::Start(BytesStart::new("direction")))?;
+                        writer.write_event(Event::Text(BytesText::new(&direction.to_string())))?;
+                        writer.write_event(Event::End(BytesEnd::new("direction")))?;
+                    }
                     writer.write_event(Event::End(BytesEnd::new("Paragraph")))?;
                 }
                 Element::Text { text, size } => {
@@ -789,8 +1147,110 @@ impl TransformerTrait for Transformer {
                     writer.write_event(Event::End(BytesEnd::new("image_type")))?;
                     writer.write_event(Event::End(BytesEnd::new("Image")))?;
                 }
+                Element::LineBreak => {
+                    writer.write_event(Event::Start(BytesStart::new("LineBreak")))?;
+                    writer.write_event(Event::End(BytesEnd::new("LineBreak")))?;
+                }
+                Element::Field { kind } => {
+                    writer.write_event(Event::Start(BytesStart::new("Field")))?;
+                    writer.write_event(Event::Start(BytesStart::new("kind")))?;
+                    writer.write_event(Event::Text(BytesText::new(&kind.to_string())))?;
+                    writer.write_event(Event::End(BytesEnd::new("kind")))?;
+                    writer.write_event(Event::End(BytesEnd::new("Field")))?;
+                }
+                Element::CrossReference { target_id, kind } => {
+                    writer.write_event(Event::Start(BytesStart::new("CrossReference")))?;
+                    writer.write_event(Event::Start(BytesStart::new("target_id")))?;
+                    writer.write_event(Event::Text(BytesText::new(target_id)))?;
+                    writer.write_event(Event::End(BytesEnd::new("target_id")))?;
+                    writer.write_event(Event::Start(BytesStart::new("kind")))?;
+                    writer.write_event(Event::Text(BytesText::new(&kind.to_string())))?;
+                    writer.write_event(Event::End(BytesEnd::new("kind")))?;
+                    writer.write_event(Event::End(BytesEnd::new("CrossReference")))?;
+                }
+                Element::Attributed {
+                    attributes,
+                    element,
+                } => {
+                    writer.write_event(Event::Start(BytesStart::new("Attributed")))?;
+                    for (key, value) in attributes {
+                        writer.write_event(Event::Start(
+                            BytesStart::new("attribute").with_attributes([
+                                ("key", key.as_str()),
+                                ("value", value.as_str()),
+                            ]),
+                        ))?;
+                        writer.write_event(Event::End(BytesEnd::new("attribute")))?;
+                    }
+                    writer.write_event(Event::Start(BytesStart::new("element")))?;
+                    serialize_element(element, writer)?;
+                    writer.write_event(Event::End(BytesEnd::new("element")))?;
+                    writer.write_event(Event::End(BytesEnd::new("Attributed")))?;
+                }
+                Element::Bookmark { id, title } => {
+                    writer.write_event(Event::Start(BytesStart::new("Bookmark")))?;
+                    writer.write_event(Event::Start(BytesStart::new("id")))?;
+                    writer.write_event(Event::Text(BytesText::new(id)))?;
+                    writer.write_event(Event::End(BytesEnd::new("id")))?;
+                    writer.write_event(Event::Start(BytesStart::new("title")))?;
+                    writer.write_event(Event::Text(BytesText::new(title)))?;
+                    writer.write_event(Event::End(BytesEnd::new("title")))?;
+                    writer.write_event(Event::End(BytesEnd::new("Bookmark")))?;
+                }
+                Element::Media { kind, url, title } => {
+                    writer.write_event(Event::Start(BytesStart::new("Media")))?;
+                    writer.write_event(Event::Start(BytesStart::new("kind")))?;
+                    writer.write_event(Event::Text(BytesText::new(&kind.to_string())))?;
+                    writer.write_event(Event::End(BytesEnd::new("kind")))?;
+                    writer.write_event(Event::Start(BytesStart::new("url")))?;
+                    writer.write_event(Event::Text(BytesText::new(url)))?;
+                    writer.write_event(Event::End(BytesEnd::new("url")))?;
+                    writer.write_event(Event::Start(BytesStart::new("title")))?;
+                    writer.write_event(Event::Text(BytesText::new(title)))?;
+                    writer.write_event(Event::End(BytesEnd::new("title")))?;
+                    writer.write_event(Event::End(BytesEnd::new("Media")))?;
+                }
+                Element::Drawing {
+                    width,
+                    height,
+                    shapes,
+                } => {
+                    fn field(
+                        writer: &mut Writer<&mut Vec<u8>>,
+                        name: &str,
+                        value: &str,
+                    ) -> Result<()> {
+                        writer.write_event(Event::Start(BytesStart::new(name)))?;
+                        writer.write_event(Event::Text(BytesText::new(value)))?;
+                        writer.write_event(Event::End(BytesEnd::new(name)))?;
+                        Ok(())
+                    }
+                    writer.write_event(Event::Start(BytesStart::new("Drawing")))?;
+                    field(writer, "width", &width.to_string())?;
+                    field(writer, "height", &height.to_string())?;
+                    for shape in shapes {
+                        writer.write_event(Event::Start(BytesStart::new("Shape")))?;
+                        field(writer, "path", &crate::drawing::path_data(&shape.path))?;
+                        if let Some(stroke) = &shape.stroke {
+                            field(writer, "stroke", stroke)?;
+                        }
+                        field(writer, "stroke_width", &shape.stroke_width.to_string())?;
+                        if let Some(fill) = &shape.fill {
+                            field(writer, "fill", fill)?;
+                        }
+                        writer.write_event(Event::End(BytesEnd::new("Shape")))?;
+                    }
+                    writer.write_event(Event::End(BytesEnd::new("Drawing")))?;
+                }
+                Element::TableOfContents { max_level } => {
+                    writer.write_event(Event::Start(BytesStart::new("TableOfContents")))?;
+                    writer.write_event(Event::Start(BytesStart::new("max_level")))?;
+                    writer.write_event(Event::Text(BytesText::new(&max_level.to_string())))?;
+                    writer.write_event(Event::End(BytesEnd::new("max_level")))?;
+                    writer.write_event(Event::End(BytesEnd::new("TableOfContents")))?;
+                }
                 Element::Hyperlink {
-                    title,
+                    elements,
                     url,
                     alt,
                     size,
@@ -799,9 +1259,11 @@ impl TransformerTrait for Transformer {
                     writer.write_event(Event::Start(BytesStart::new("url")))?;
                     writer.write_event(Event::Text(BytesText::new(url)))?;
                     writer.write_event(Event::End(BytesEnd::new("url")))?;
-                    writer.write_event(Event::Start(BytesStart::new("title")))?;
-                    writer.write_event(Event::Text(BytesText::new(title)))?;
-                    writer.write_event(Event::End(BytesEnd::new("title")))?;
+                    writer.write_event(Event::Start(BytesStart::new("elements")))?;
+                    for sub_element in elements {
+                        serialize_element(sub_element, writer)?;
+                    }
+                    writer.write_event(Event::End(BytesEnd::new("elements")))?;
                     writer.write_event(Event::Start(BytesStart::new("alt")))?;
                     writer.write_event(Event::Text(BytesText::new(alt)))?;
                     writer.write_event(Event::End(BytesEnd::new("alt")))?;
@@ -810,7 +1272,82 @@ impl TransformerTrait for Transformer {
                     writer.write_event(Event::End(BytesEnd::new("size")))?;
                     writer.write_event(Event::End(BytesEnd::new("Hyperlink")))?;
                 }
-                Element::List { elements, numbered } => {
+                Element::InlineCode { text } => {
+                    writer.write_event(Event::Start(BytesStart::new("InlineCode")))?;
+                    writer.write_event(Event::Start(BytesStart::new("text")))?;
+                    writer.write_event(Event::Text(BytesText::new(text)))?;
+                    writer.write_event(Event::End(BytesEnd::new("text")))?;
+                    writer.write_event(Event::End(BytesEnd::new("InlineCode")))?;
+                }
+                Element::Raw { format, content } => {
+                    writer.write_event(Event::Start(BytesStart::new("Raw")))?;
+                    writer.write_event(Event::Start(BytesStart::new("format")))?;
+                    writer.write_event(Event::Text(BytesText::new(&format.to_string())))?;
+                    writer.write_event(Event::End(BytesEnd::new("format")))?;
+                    writer.write_event(Event::Start(BytesStart::new("content")))?;
+                    writer.write_event(Event::Text(BytesText::new(content)))?;
+                    writer.write_event(Event::End(BytesEnd::new("content")))?;
+                    writer.write_event(Event::End(BytesEnd::new("Raw")))?;
+                }
+                Element::CodeBlock { info, code } => {
+                    writer.write_event(Event::Start(BytesStart::new("CodeBlock")))?;
+                    writer.write_event(Event::Start(BytesStart::new("info")))?;
+                    writer.write_event(Event::Text(BytesText::new(info)))?;
+                    writer.write_event(Event::End(BytesEnd::new("info")))?;
+                    writer.write_event(Event::Start(BytesStart::new("code")))?;
+                    writer.write_event(Event::Text(BytesText::new(code)))?;
+                    writer.write_event(Event::End(BytesEnd::new("code")))?;
+                    writer.write_event(Event::End(BytesEnd::new("CodeBlock")))?;
+                }
+                Element::Math { tex, display } => {
+                    writer.write_event(Event::Start(BytesStart::new("Math")))?;
+                    writer.write_event(Event::Start(BytesStart::new("tex")))?;
+                    writer.write_event(Event::Text(BytesText::new(tex)))?;
+                    writer.write_event(Event::End(BytesEnd::new("tex")))?;
+                    writer.write_event(Event::Start(BytesStart::new("display")))?;
+                    writer.write_event(Event::Text(BytesText::new(&display.to_string())))?;
+                    writer.write_event(Event::End(BytesEnd::new("display")))?;
+                    writer.write_event(Event::End(BytesEnd::new("Math")))?;
+                }
+                Element::Styled { style, elements } => {
+                    writer.write_event(Event::Start(BytesStart::new("Styled")))?;
+                    writer.write_event(Event::Start(BytesStart::new("style")))?;
+                    writer.write_event(Event::Text(BytesText::new(&style.to_string())))?;
+                    writer.write_event(Event::End(BytesEnd::new("style")))?;
+                    writer.write_event(Event::Start(BytesStart::new("elements")))?;
+                    for sub_element in elements {
+                        serialize_element(sub_element, writer)?;
+                    }
+                    writer.write_event(Event::End(BytesEnd::new("elements")))?;
+                    writer.write_event(Event::End(BytesEnd::new("Styled")))?;
+                }
+                Element::Container { kind, elements } => {
+                    writer.write_event(Event::Start(BytesStart::new("Container")))?;
+                    writer.write_event(Event::Start(BytesStart::new("kind")))?;
+                    writer.write_event(Event::Text(BytesText::new(&kind.to_string())))?;
+                    writer.write_event(Event::End(BytesEnd::new("kind")))?;
+                    writer.write_event(Event::Start(BytesStart::new("elements")))?;
+                    for sub_element in elements {
+                        serialize_element(sub_element, writer)?;
+                    }
+                    writer.write_event(Event::End(BytesEnd::new("elements")))?;
+                    writer.write_event(Event::End(BytesEnd::new("Container")))?;
+                }
+                Element::Footnote { elements } => {
+                    writer.write_event(Event::Start(BytesStart::new("Footnote")))?;
+                    writer.write_event(Event::Start(BytesStart::new("elements")))?;
+                    for sub_element in elements {
+                        serialize_element(sub_element, writer)?;
+                    }
+                    writer.write_event(Event::End(BytesEnd::new("elements")))?;
+                    writer.write_event(Event::End(BytesEnd::new("Footnote")))?;
+                }
+                Element::List {
+                    elements,
+                    numbered,
+                    start,
+                    numbering,
+                } => {
                     writer.write_event(Event::Start(BytesStart::new("List")))?;
                     writer.write_event(Event::Start(BytesStart::new("elements")))?;
                     for sub_element in elements {
@@ -820,6 +1357,12 @@ impl TransformerTrait for Transformer {
                     writer.write_event(Event::Start(BytesStart::new("numbered")))?;
                     writer.write_event(Event::Text(BytesText::new(&numbered.to_string())))?;
                     writer.write_event(Event::End(BytesEnd::new("numbered")))?;
+                    writer.write_event(Event::Start(BytesStart::new("start")))?;
+                    writer.write_event(Event::Text(BytesText::new(&start.to_string())))?;
+                    writer.write_event(Event::End(BytesEnd::new("start")))?;
+                    writer.write_event(Event::Start(BytesStart::new("numbering")))?;
+                    writer.write_event(Event::Text(BytesText::new(&numbering.to_string())))?;
+                    writer.write_event(Event::End(BytesEnd::new("numbering")))?;
                     writer.write_event(Event::End(BytesEnd::new("List")))?;
                 }
                 Element::Table { headers, rows } => {
@@ -1004,7 +1547,8 @@ impl TransformerTrait for Transformer {
 
 #[cfg(test)]
 mod tests {
-    use crate::core::tests::init_logger;
+    use crate::core::tests::{init_logger, output_dir};
+    use crate::core::{disk_image_saver, TransformerWithImageLoaderSaverTrait};
     use crate::markdown;
     use crate::xml::*;
     use bytes::Bytes;
@@ -1023,8 +1567,9 @@ mod tests {
         let bytes = Bytes::from(buffer);
         let parsed = Transformer::parse(&bytes)?;
         debug!("{:#?}", parsed);
-        let generated = markdown::Transformer::generate(&parsed)?;
-        let mut file = File::create("test/data/generated.md")?;
+        let generated =
+            markdown::Transformer::generate_with_saver(&parsed, disk_image_saver(&output_dir()))?;
+        let mut file = File::create(format!("{}/generated.md", output_dir()))?;
         file.write_all(&generated)?;
         Ok(())
     }
@@ -1042,9 +1587,27 @@ mod tests {
         let generated = Transformer::generate(&parsed)?;
         debug!("{:#?}", generated);
         // write to file
-        let mut file = File::create("test/data/generated.xml")?;
+        let mut file = File::create(format!("{}/generated.xml", output_dir()))?;
         file.write_all(&generated)?;
 
         Ok(())
     }
+
+    #[test]
+    fn test_drawing() -> anyhow::Result<()> {
+        use crate::core::{Document, Element, Shape};
+
+        let document = Document::new(vec![Element::Drawing {
+            width: 100.0,
+            height: 50.0,
+            shapes: vec![
+                Shape::rect(10.0, 10.0, 20.0, 40.0).with_fill("#36c"),
+                Shape::line(0.0, 50.0, 100.0, 50.0).with_stroke("gray", 0.5),
+            ],
+        }]);
+        let generated = Transformer::generate(&document)?;
+        let parsed = Transformer::parse(&generated)?;
+        assert_eq!(parsed.get_all_elements(), document.get_all_elements());
+        Ok(())
+    }
 }
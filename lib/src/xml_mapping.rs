@@ -0,0 +1,461 @@
+//! XML of a document in a schema given by an [`XmlMapping`] of the elements to tags, read
+//! from a TOML or JSON configuration
+//!
+//! Each element is written with the tag of its kind, its content inside: the text of text,
+//! headers and code, the items of lists, the rows of tables and the inline elements of
+//! paragraphs, links, styled text and footnotes. The lists, tables and code after a list
+//! item are written in the item. Images are saved next to the output under the name of
+//! their `src` property. Raw XML elements are written as is.
+
+use std::collections::BTreeMap;
+
+use anyhow::bail;
+use bytes::Bytes;
+use log::warn;
+
+use crate::core::{
+    header_anchor, Band, Document, DocumentType, Element, ListItem, TextStyle, XmlMapping, XmlTag,
+};
+use crate::util::escape_xml;
+
+impl XmlMapping {
+    /// Mapping of a TOML configuration
+    pub fn from_toml(config: &str) -> anyhow::Result<XmlMapping> {
+        Ok(toml::from_str(config)?)
+    }
+
+    /// Mapping of a JSON configuration
+    pub fn from_json(config: &str) -> anyhow::Result<XmlMapping> {
+        Ok(serde_json::from_str(config)?)
+    }
+}
+
+/// Name of an XML element or attribute: a letter or `_` followed by letters, digits and
+/// `_.-:`
+fn is_xml_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_alphabetic() || c == '_' || c == ':')
+        && chars.all(|c| c.is_alphanumeric() || "_.-:".contains(c))
+}
+
+/// Text with its `{property}` placeholders replaced, the unknown properties by nothing
+fn substitute(template: &str, properties: &BTreeMap<String, String>) -> String {
+    let mut text = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let Some(end) = rest[start..].find('}') else {
+            break;
+        };
+        text.push_str(&rest[..start]);
+        let name = &rest[start + 1..start + end];
+        if let Some(value) = properties.get(name) {
+            text.push_str(value);
+        }
+        rest = &rest[start + end + 1..];
+    }
+    text.push_str(rest);
+    text
+}
+
+struct Writer<'a, F>
+where
+    F: Fn(&Bytes, &str) -> anyhow::Result<()>,
+{
+    mapping: &'a XmlMapping,
+    image_saver: &'a F,
+    image_count: usize,
+    footnote_count: usize,
+}
+
+impl<F> Writer<'_, F>
+where
+    F: Fn(&Bytes, &str) -> anyhow::Result<()>,
+{
+    /// Content in the tag of the kind, as is without a tag
+    fn tagged(
+        &self,
+        kind: &str,
+        properties: &BTreeMap<String, String>,
+        content: String,
+    ) -> anyhow::Result<String> {
+        let Some(XmlTag { name, attributes }) = self.mapping.elements.get(kind) else {
+            return Ok(content);
+        };
+        let name = substitute(name, properties);
+        if !is_xml_name(&name) {
+            bail!("Invalid XML element name {:?} of {}", name, kind);
+        }
+        let mut tag = format!("<{name}");
+        for (attribute, value) in attributes {
+            if !is_xml_name(attribute) {
+                bail!("Invalid XML attribute name {:?} of {}", attribute, kind);
+            }
+            let value = substitute(value, properties);
+            if !value.is_empty() {
+                tag.push_str(&format!(" {attribute}=\"{}\"", escape_xml(&value)));
+            }
+        }
+        Ok(if content.is_empty() {
+            format!("{tag}/>")
+        } else {
+            format!("{tag}>{content}</{name}>")
+        })
+    }
+
+    fn elements<'e>(
+        &mut self,
+        elements: impl IntoIterator<Item = &'e Element>,
+        separator: &str,
+    ) -> anyhow::Result<String> {
+        let mut parts = vec![];
+        for element in elements {
+            let xml = self.element(element, &BTreeMap::new())?;
+            if !xml.is_empty() {
+                parts.push(xml);
+            }
+        }
+        Ok(parts.join(separator))
+    }
+
+    /// XML of an element, `inherited` holding the attributes of the attributed element it is
+    /// in
+    fn element(
+        &mut self,
+        element: &Element,
+        inherited: &BTreeMap<String, String>,
+    ) -> anyhow::Result<String> {
+        let mut properties = inherited.clone();
+        let mut property = |name: &str, value: String| {
+            properties.entry(name.to_string()).or_insert(value);
+        };
+        let (kind, content) = match element {
+            Element::Text { text, .. } => ("Text", escape_xml(text)),
+            Element::Header { level, text } => {
+                property("level", level.to_string());
+                property("id", header_anchor(text));
+                ("Header", escape_xml(text.trim()))
+            }
+            Element::Paragraph { elements, .. } => ("Paragraph", self.elements(elements, "")?),
+            Element::List {
+                elements,
+                numbered,
+                start,
+                numbering,
+            } => {
+                property("start", start.to_string());
+                property("numbering", numbering.to_string());
+                let kind = if *numbered && self.mapping.elements.contains_key("NumberedList") {
+                    "NumberedList"
+                } else {
+                    "List"
+                };
+                (kind, self.list(elements)?)
+            }
+            Element::Table { headers, rows } => {
+                let mut lines = vec![];
+                if !headers.is_empty() {
+                    let kind = if self.mapping.elements.contains_key("TableHeader") {
+                        "TableHeader"
+                    } else {
+                        "TableCell"
+                    };
+                    let mut cells = String::new();
+                    for header in headers {
+                        let content = self.element(&header.element, &BTreeMap::new())?;
+                        cells.push_str(&self.tagged(kind, &BTreeMap::new(), content)?);
+                    }
+                    lines.push(self.tagged("TableRow", &BTreeMap::new(), cells)?);
+                }
+                for row in rows {
+                    let mut cells = String::new();
+                    for cell in &row.cells {
+                        let content = self.element(&cell.element, &BTreeMap::new())?;
+                        cells.push_str(&self.tagged("TableCell", &BTreeMap::new(), content)?);
+                    }
+                    lines.push(self.tagged("TableRow", &BTreeMap::new(), cells)?);
+                }
+                ("Table", lines.join("\n"))
+            }
+            Element::Image(image) => {
+                if image.bytes().is_empty() {
+                    warn!("Image without content left out: {}", image.title());
+                    return Ok(String::new());
+                }
+                self.image_count += 1;
+                let src = format!(
+                    "image{}{}",
+                    self.image_count,
+                    image.image_type().to_extension()
+                );
+                (self.image_saver)(image.bytes(), &src)?;
+                property("src", src);
+                property("alt", image.alt().to_string());
+                property("title", image.title().to_string());
+                property("width", image.size().width.clone().unwrap_or_default());
+                property("height", image.size().height.clone().unwrap_or_default());
+                ("Image", String::new())
+            }
+            Element::LineBreak => ("LineBreak", String::new()),
+            Element::TableOfContents { max_level } => {
+                property("level", max_level.to_string());
+                ("TableOfContents", String::new())
+            }
+            Element::Bookmark { id, title } => {
+                property("id", id.clone());
+                property("title", title.clone());
+                ("Bookmark", String::new())
+            }
+            Element::Hyperlink {
+                elements, url, alt, ..
+            } => {
+                property("url", url.clone());
+                property("alt", alt.clone());
+                let content = self.elements(elements, "")?;
+                let content = if content.is_empty() {
+                    escape_xml(url)
+                } else {
+                    content
+                };
+                ("Hyperlink", content)
+            }
+            Element::Media { kind, url, title } => {
+                if !self.mapping.elements.contains_key("Media") {
+                    return match element.media_link() {
+                        Some(link) => self.element(&link, inherited),
+                        None => Ok(String::new()),
+                    };
+                }
+                property("kind", kind.to_string());
+                property("url", url.clone());
+                property("title", title.clone());
+                ("Media", String::new())
+            }
+            Element::InlineCode { text } => ("InlineCode", escape_xml(text)),
+            Element::CodeBlock { code, .. } => {
+                if let Some(language) = element.code_language() {
+                    property("language", language.to_string());
+                }
+                ("CodeBlock", escape_xml(code.trim_end_matches('\n')))
+            }
+            Element::Math { tex, .. } => ("Math", escape_xml(tex.trim())),
+            Element::Raw {
+                format: DocumentType::XML,
+                content,
+            } => return Ok(content.trim().to_string()),
+            Element::Styled { style, elements } => {
+                let kind = match style {
+                    TextStyle::Strong => "Strong",
+                    TextStyle::Emphasis => "Emphasis",
+                    TextStyle::Strikethrough => "Strikethrough",
+                };
+                (kind, self.elements(elements, "")?)
+            }
+            Element::Footnote { elements } => {
+                self.footnote_count += 1;
+                property("number", self.footnote_count.to_string());
+                ("Footnote", self.elements(elements, "")?)
+            }
+            Element::Container { kind, elements } => {
+                property("kind", kind.to_string());
+                ("Container", self.elements(elements, "\n")?)
+            }
+            Element::Attributed {
+                attributes,
+                element,
+            } => {
+                for (name, value) in attributes {
+                    property(name, value.clone());
+                }
+                return self.element(element, &properties);
+            }
+            // page numbers, the other fields being resolved, and content of other formats
+            Element::Field { .. }
+            | Element::CrossReference { .. }
+            | Element::Drawing { .. }
+            | Element::Raw { .. } => return Ok(String::new()),
+        };
+        self.tagged(kind, &properties, content)
+    }
+
+    /// Items of a list, the lists, tables and code after an item being written in it
+    fn list(&mut self, items: &[ListItem]) -> anyhow::Result<String> {
+        let mut groups: Vec<Vec<&Element>> = vec![];
+        for item in items {
+            let element = &item.element;
+            match groups.last_mut() {
+                Some(blocks)
+                    if matches!(
+                        element,
+                        Element::List { .. } | Element::CodeBlock { .. } | Element::Table { .. }
+                    ) =>
+                {
+                    blocks.push(element)
+                }
+                _ => groups.push(vec![element]),
+            }
+        }
+        let mut lines = vec![];
+        for blocks in groups {
+            let content = self.elements(blocks, "\n")?;
+            lines.push(self.tagged("ListItem", &BTreeMap::new(), content)?);
+        }
+        Ok(lines.join("\n"))
+    }
+}
+
+/// XML of the document in the schema of the mapping, its images saved with `image_saver`
+pub fn generate<F>(
+    document: &Document,
+    mapping: &XmlMapping,
+    image_saver: F,
+) -> anyhow::Result<Bytes>
+where
+    F: Fn(&Bytes, &str) -> anyhow::Result<()>,
+{
+    let document = &document.resolve_fields().without_drawings();
+    let root = if mapping.root.is_empty() {
+        "document"
+    } else {
+        mapping.root.as_str()
+    };
+    if !is_xml_name(root) {
+        bail!("Invalid XML root element name {:?}", root);
+    }
+    let mut writer = Writer {
+        mapping,
+        image_saver: &image_saver,
+        image_count: 0,
+        footnote_count: 0,
+    };
+    let elements = document
+        .bands
+        .iter()
+        .filter(|band| !matches!(band, Band::PageHeader(_) | Band::PageFooter(_)))
+        .flat_map(|band| band.elements());
+    let body = writer.elements(elements, "\n")?;
+
+    let mut xml = format!("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<{root}");
+    for (attribute, value) in &mapping.root_attributes {
+        if !is_xml_name(attribute) {
+            bail!("Invalid XML attribute name {:?} of the root", attribute);
+        }
+        xml.push_str(&format!(" {attribute}=\"{}\"", escape_xml(value)));
+    }
+    xml.push_str(&format!(">\n{body}\n</{root}>\n"));
+    Ok(Bytes::from(xml))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+    use crate::core::{GenerateOptions, ImageData, ImageDimension, TransformerTrait, XmlOptions};
+    use crate::markdown;
+
+    const DOCBOOK: &str = r#"
+root = "article"
+root_attributes = { xmlns = "http://docbook.org/ns/docbook", version = "5.0" }
+
+[elements]
+Header = { name = "title", attributes = { "xml:id" = "{id}", role = "h{level}" } }
+Paragraph = "para"
+List = "itemizedlist"
+NumberedList = { name = "orderedlist", attributes = { startingnumber = "{start}" } }
+ListItem = "listitem"
+Table = "informaltable"
+TableRow = "tr"
+TableHeader = "th"
+TableCell = "td"
+Strong = { name = "emphasis", attributes = { role = "strong" } }
+Emphasis = "emphasis"
+Hyperlink = { name = "link", attributes = { "xlink:href" = "{url}" } }
+CodeBlock = { name = "programlisting", attributes = { language = "{language}" } }
+Image = { name = "imagedata", attributes = { fileref = "{src}", width = "{width}" } }
+"#;
+
+    #[test]
+    fn test_generate() -> anyhow::Result<()> {
+        let mapping = XmlMapping::from_toml(DOCBOOK)?;
+        assert_eq!(
+            mapping.elements.get("Paragraph"),
+            Some(&XmlTag {
+                name: "para".to_string(),
+                attributes: BTreeMap::new(),
+            })
+        );
+        let json = XmlMapping::from_json(&serde_json::to_string(&mapping)?)?;
+        assert_eq!(json, mapping);
+
+        let markdown = "# Results & costs\n\nSee **the [site](https://example.com/?a=1&b=2)** \
+            and *more* <b>raw</b>.\n\n\
+            1. One\n   - Nested\n2. Two\n\n\
+            | Name | Value |\n|------|-------|\n| a | <1> |\n\n\
+            ```rust\nlet a = 1 < 2;\n```\n";
+        let mut document = markdown::Transformer::parse(&Bytes::from(markdown))?;
+        let png = Bytes::from(std::fs::read("test/data/small.png")?);
+        if let Some(Band::Detail(elements)) = document.bands.last_mut() {
+            elements.push(Element::Image(ImageData::new(
+                png,
+                "Logo".to_string(),
+                "The logo".to_string(),
+                "png".to_string(),
+                String::new(),
+                ImageDimension {
+                    width: Some("120px".to_string()),
+                    height: None,
+                },
+            )));
+        }
+
+        let saved = RefCell::new(vec![]);
+        let xml = generate(&document, &mapping, |_, name| {
+            saved.borrow_mut().push(name.to_string());
+            Ok(())
+        })?;
+        let xml = std::str::from_utf8(&xml)?;
+        assert!(xml.starts_with(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <article version=\"5.0\" xmlns=\"http://docbook.org/ns/docbook\">\n\
+             <title role=\"h1\" xml:id=\"results--costs\">Results &amp; costs</title>\n"
+        ));
+        assert!(xml.contains(
+            "<para>See <emphasis role=\"strong\">the <link xlink:href=\
+             \"https://example.com/?a=1&amp;b=2\">site</link></emphasis> and \
+             <emphasis>more</emphasis> raw.</para>\n"
+        ));
+        assert!(xml.contains(
+            "<orderedlist startingnumber=\"1\"><listitem>One\n<itemizedlist>\
+             <listitem>Nested</listitem></itemizedlist></listitem>\n\
+             <listitem>Two</listitem></orderedlist>\n"
+        ));
+        assert!(xml.contains(
+            "<informaltable><tr><th>Name</th><th>Value</th></tr>\n\
+             <tr><td>a</td><td>&lt;1&gt;</td></tr></informaltable>\n"
+        ));
+        assert!(
+            xml.contains("<programlisting language=\"rust\">let a = 1 &lt; 2;</programlisting>\n")
+        );
+        assert!(xml.ends_with("<imagedata fileref=\"image1.png\" width=\"120px\"/>\n</article>\n"));
+        assert_eq!(*saved.borrow(), vec!["image1.png".to_string()]);
+
+        // the names of the mapping are checked
+        let options = GenerateOptions {
+            xml: XmlOptions {
+                mapping: Some(XmlMapping {
+                    root: "1st".to_string(),
+                    ..Default::default()
+                }),
+            },
+            ..Default::default()
+        };
+        assert!(crate::xml::Transformer::generate_with_options(&document, &options).is_err());
+        // without a mapping the XML of shiva's own schema is generated
+        let text = markdown::Transformer::parse(&Bytes::from(markdown))?;
+        let own = crate::xml::Transformer::generate_with_options(&text, &Default::default())?;
+        assert_eq!(own, crate::xml::Transformer::generate(&text)?);
+        Ok(())
+    }
+}
@@ -1,49 +0,0 @@
-#set page(
-        header: "",
-        footer: "",
-    )
-= First header
-Paragraph  bla bla bla blabla bla bla blabla bla bla blabla bla bla blabla bla bla blabla bla bla blabla bla bla blabla bla bla blabla bla bla
-blabla bla bla blabla bla bla blabla bla bla blabla bla bla bla
-
-            #image("Picture title1png", alt: "Picture alt1")
-            
-+ List item 1
-+ List item 2
- + List item 3
-  + List item secode level 1
-+ List item secode level 2
- + List item 4
-  + List item secode level 3
-+ List item secode level 4
- + List item 5
-  + List item secode level 5
- - List item one
-  - List item two
- - List item three
-  - List item four
-   - List item five
-    - List item zzz
- - List item six
-  - List item seven
-+ = List item 1
-+ == List item 2
- - === List item 3
-  + List item secode level 1
-+ #link("https://link-url-here.org")
-
-            #image("Picture title1png", alt: "Picture alt1")
-            
-== Second header
-
-        #table(
-            columns:8,
-            [*Column 1*],[*Column 2*],[*Column 3*],[*Column 4*],[*Column 5*],[*Column 6*],[*Column 7*],[*Column 8*],
-            [Data 1],[Data 2],[Data 3],[Data 4],[Data 5],[Data 6],[Data 7],[Data 8],
-[Data 9],[Data 10],[Data 11],[Data 12],[Data 13],[Data 14],[Data 15],[Data 16],
-[Data 17],[Data 18],[Data 19],[Data 20],[Data 21],[Data 22],[Data 23],[Data 24],
-
-        )
-        Paragraph2  bla bla bla blabla bla bla blabla bla bla blabla bla bla blabla bla bla blabla bla bla blabla bla bla blabla bla bla blabla bla bla
-blabla2 bla bla blabla bla bla blabla bla bla blabla bla bla bla
-- #link("https://link-url-here.org")
@@ -13,19 +13,50 @@ pub enum Error {
     FailBytes,             //binary conversion error
     UnsupportedFormat,     //unsupported format
     FailParseDocument,     //document parsing error
+    UnsupportedElement,    //the document contains an element the transformer cannot handle
+    FailLoadImage,         //an image referenced by the document cannot be loaded
     FailConvertFile,       //file conversion error
     FailHeader,            //error creating the header of the converted file
     NoFilesToConvertInZip, //there are no files to convert in the zip archive
+    InvalidPreviewOptions, //the page or the resolution of a preview is invalid
+    PageNotFound,          //the document has no page of the preview
 }
 
 impl IntoResponse for Error {
     fn into_response(self) -> Response {
         info!("-->> {:<12} - {self:?}", "INTO_RES");
 
-        let mut response = StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        let status = match self {
+            Error::ExtensionMissing
+            | Error::FailBytes
+            | Error::NoFilesToConvertInZip
+            | Error::InvalidPreviewOptions => StatusCode::BAD_REQUEST,
+            Error::PageNotFound => StatusCode::NOT_FOUND,
+            Error::UnsupportedFormat => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            Error::FailParseDocument | Error::UnsupportedElement => {
+                StatusCode::UNPROCESSABLE_ENTITY
+            }
+            Error::FailLoadImage => StatusCode::BAD_GATEWAY,
+            Error::FailConvertFile | Error::FailHeader => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        let mut response = status.into_response();
 
         response.extensions_mut().insert(self);
 
         response
     }
 }
+
+impl From<shiva::Error> for Error {
+    fn from(error: shiva::Error) -> Self {
+        match error {
+            shiva::Error::ParseError { .. } => Error::FailParseDocument,
+            shiva::Error::UnsupportedElement(_) => Error::UnsupportedElement,
+            shiva::Error::ImageLoadError { .. } => Error::FailLoadImage,
+            shiva::Error::UnsupportedFormat(_) => Error::UnsupportedFormat,
+            shiva::Error::GenerationError(_)
+            | shiva::Error::Validation(_)
+            | shiva::Error::Other(_) => Error::FailConvertFile,
+        }
+    }
+}
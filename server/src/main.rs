@@ -1,10 +1,12 @@
 pub use self::error::Result;
-use crate::web::routes_files::handler_convert_file;
+use crate::web::routes_files::{
+    enable_remote_images, handler_convert_file, handler_preview, handler_statistics,
+};
 use axum::extract::DefaultBodyLimit;
 use axum::response::{Html, IntoResponse, Response};
 use axum::routing::{get, post};
 use axum::{middleware, Router};
-use clap::{Arg, Command};
+use clap::{Arg, ArgAction, Command};
 use env_logger::Env;
 use log::info;
 use tokio::net::TcpListener;
@@ -39,11 +41,20 @@ async fn main() -> Result<()> {
                 .require_equals(true)
                 .default_value("8080"),
         )
+        .arg(
+            Arg::new("remote-images")
+                .long("remote-images")
+                .help("Downloads the images of uploaded documents from public addresses")
+                .action(ArgAction::SetTrue),
+        )
         .get_matches();
 
     // Extracting argument values
     let host = matches.get_one::<String>("host").unwrap();
     let port = matches.get_one::<String>("port").unwrap();
+    if matches.get_flag("remote-images") {
+        enable_remote_images();
+    }
 
     let route_test = Router::new().route("/test_server", get(handler_answer_server));
 
@@ -52,9 +63,21 @@ async fn main() -> Result<()> {
         .layer(DefaultBodyLimit::disable())
         .layer(RequestBodyLimitLayer::new(100 * 1024 * 1024)); //file size limit - 100 Mb
 
+    let route_statistics = Router::new()
+        .route("/statistics", post(handler_statistics))
+        .layer(DefaultBodyLimit::disable())
+        .layer(RequestBodyLimitLayer::new(100 * 1024 * 1024));
+
+    let route_preview = Router::new()
+        .route("/preview", post(handler_preview))
+        .layer(DefaultBodyLimit::disable())
+        .layer(RequestBodyLimitLayer::new(100 * 1024 * 1024));
+
     let routes_all = Router::new()
         .merge(route_test)
         .merge(route_input_file)
+        .merge(route_statistics)
+        .merge(route_preview)
         .layer(middleware::map_response(main_response_mapper));
 
     // region:    ---Start Server
@@ -352,6 +375,137 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_handler_convert_file_status() -> Result<(), Box<dyn std::error::Error>> {
+        //the errors of the conversion keep their status: a malformed document cannot be
+        //processed and an unknown output format is not supported
+        let cases = vec![
+            (
+                "test_file.json",
+                "{ not json",
+                "md",
+                reqwest::StatusCode::UNPROCESSABLE_ENTITY,
+            ),
+            (
+                "test_file.md",
+                "# Title",
+                "unknown",
+                reqwest::StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            ),
+        ];
+
+        for (file_name, file_data, output_format, status) in cases {
+            let client = reqwest::Client::new();
+
+            let part = multipart::Part::bytes(file_data.as_bytes().to_vec())
+                .file_name(file_name.to_string());
+            let form = multipart::Form::new().part("file", part);
+
+            let response = client
+                .post(format!("http://localhost:8080/transform/{}", output_format))
+                .multipart(form)
+                .send()
+                .await?;
+
+            assert_eq!(response.status(), status);
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_handler_convert_file_raw_html() -> Result<(), Box<dyn std::error::Error>> {
+        //the raw HTML blocks of every input format are sanitized, not only those of markdown
+        let cases = vec![
+            (
+                "test_file.rst",
+                ".. raw:: html\n\n   <b>Bold</b><script>alert(1)</script>\n",
+            ),
+            (
+                "test_file.adoc",
+                "++++\n<b>Bold</b><script>alert(1)</script>\n++++\n",
+            ),
+            (
+                "test_file.org",
+                "#+begin_export html\n<b>Bold</b><script>alert(1)</script>\n#+end_export\n",
+            ),
+        ];
+
+        for (file_name, file_data) in cases {
+            let client = reqwest::Client::new();
+
+            let part = multipart::Part::bytes(file_data.as_bytes().to_vec())
+                .file_name(file_name.to_string());
+            let form = multipart::Form::new().part("file", part);
+
+            let response = client
+                .post("http://localhost:8080/transform/html")
+                .multipart(form)
+                .send()
+                .await?;
+
+            assert_eq!(response.status(), reqwest::StatusCode::OK);
+            let html = response.text().await?;
+            assert!(html.contains("<b>Bold</b>"), "{}", html);
+            assert!(!html.contains("alert(1)"), "{}", html);
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_handler_convert_file_images() -> Result<(), Box<dyn std::error::Error>> {
+        let client = reqwest::Client::new();
+
+        //the images of every input format are never read from the disk of the server
+        let cases = vec![
+            ("test_file.rst", ".. image:: ../Cargo.toml\n"),
+            ("test_file.adoc", "image::../Cargo.toml[]\n"),
+            ("test_file.org", "[[file:../image.png]]\n"),
+            ("test_file.tex", "\\includegraphics{../Cargo.toml}\n"),
+        ];
+        for (file_name, file_data) in cases {
+            let part = multipart::Part::bytes(file_data.as_bytes().to_vec())
+                .file_name(file_name.to_string());
+            let form = multipart::Form::new().part("file", part);
+
+            let response = client
+                .post("http://localhost:8080/transform/json")
+                .multipart(form)
+                .send()
+                .await?;
+
+            //remote images are disabled by default
+            assert_eq!(
+                response.status(),
+                reqwest::StatusCode::BAD_GATEWAY,
+                "{}",
+                file_name
+            );
+        }
+
+        //the images saved by the generators come back with the document in a ZIP archive
+        let png = "iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAYAAAAfFcSJAAAADUlEQVR42mNkYPhfDwAChwGA60e6kgAAAABJRU5ErkJggg==";
+        let file_data = format!("# Title\n\n![dot](data:image/png;base64,{})\n", png);
+        let part = multipart::Part::bytes(file_data.into_bytes()).file_name("test_file.md");
+        let form = multipart::Form::new().part("file", part);
+
+        let response = client
+            .post("http://localhost:8080/transform/tex")
+            .multipart(form)
+            .send()
+            .await?;
+
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        let archive = zip::ZipArchive::new(Cursor::new(response.bytes().await?))?;
+        let mut file_names: Vec<&str> = archive.file_names().collect();
+        file_names.sort();
+        assert_eq!(file_names, vec!["document.tex", "image1.png"]);
+        assert!(!std::path::Path::new("image1.png").exists());
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_upload_zip() -> Result<(), Box<dyn std::error::Error>> {
         info!("start test_upload_zip");
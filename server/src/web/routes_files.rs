@@ -1,14 +1,72 @@
 use crate::error::{Error, Result};
 use axum::body::Bytes;
 use axum::extract::multipart::Field;
-use axum::extract::{Multipart, Path};
+use axum::extract::{Multipart, Path, Query};
 use axum::response::{IntoResponse, Response};
+use axum::Json;
 use futures_util::StreamExt;
 use log::{debug, error, info};
-use serde::Serialize;
-use shiva::core::{Document, TransformerTrait, TransformerWithImageLoaderSaverTrait};
+use serde::{Deserialize, Serialize};
+use shiva::core::{
+    http_image_loader, AsyncAdapter, AsyncTransformerTrait, CsvOptions, CsvParseOptions, Document,
+    DocumentType, GenerateOptions, HtmlOptions, HtmlParseOptions, ImageOptions, MarkdownFlavor,
+    MarkdownOptions, MarkdownParseOptions, ParseOptions, Statistics, TransformerRegistry,
+    TransformerWithImageLoaderSaverTrait,
+};
+use shiva::detect::detect_format;
+use shiva::image_pipeline::process_images;
+use shiva::preview::{PreviewOptions, MAX_DPI};
+use shiva::raster::{RasterFormat, RasterOptions};
 use std::collections::HashMap;
 use std::io::{Cursor, Read};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+//query parameters of the conversion, `?flavor=commonmark` reads and writes markdown
+//without the GitHub extensions, `?autolink=true` reads the bare URLs of markdown and text
+//as links, `?emoji=true` reads the `:sparkles:` shortcodes of markdown as emoji,
+//`?fragment=true` writes HTML without its page, `?theme=true` styles the HTML page,
+//`?print=true` adds the print rules of the pages, `?email=true` writes email-safe HTML,
+//`?delimiter=;` sets the separator of CSV and TSV, `?table=1` writes the second table
+//only to CSV and TSV and `?page=2&dpi=150` renders the second page at 150 DPI to PNG and
+//JPEG (the first page at 96 DPI by default)
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ConvertQuery {
+    flavor: MarkdownFlavor,
+    autolink: bool,
+    emoji: bool,
+    fragment: bool,
+    theme: bool,
+    print: bool,
+    email: bool,
+    delimiter: Option<char>,
+    table: Option<usize>,
+    page: Option<usize>,
+    dpi: Option<f32>,
+}
+
+impl ConvertQuery {
+    //uploads are untrusted, the scripts of HTML input and of the raw HTML of every format
+    //never reach the response
+    fn parse_options(&self) -> ParseOptions {
+        ParseOptions {
+            markdown: MarkdownParseOptions {
+                flavor: self.flavor,
+                emoji: self.emoji,
+                sanitize: true,
+            },
+            html: HtmlParseOptions { sanitize: true },
+            csv: CsvParseOptions {
+                delimiter: self.delimiter,
+                ..Default::default()
+            },
+            autolink: self.autolink,
+            ..Default::default()
+        }
+    }
+}
 
 #[derive(Debug, Clone, Serialize)]
 struct UploadFileInfo {
@@ -52,6 +110,7 @@ impl IntoResponse for DownloadFile {
 
 pub async fn handler_convert_file(
     Path(output_format): Path<String>,
+    Query(query): Query<ConvertQuery>,
     multipart: Multipart,
 ) -> Result<impl IntoResponse> {
     match upload_file(multipart).await {
@@ -66,9 +125,9 @@ pub async fn handler_convert_file(
                     upload_file_info.upload_file_extension,
                     upload_file_info.upload_file_data,
                     output_format,
+                    &query,
                 )
-                .await
-                .map_err(|_| Error::FailConvertFile)?;
+                .await?;
 
                 Ok(build_response_file)
             }
@@ -81,9 +140,9 @@ pub async fn handler_convert_file(
                     upload_file_zip.file_extension,
                     upload_file_zip.images,
                     output_format,
+                    &query,
                 )
-                .await
-                .map_err(|_| Error::FailConvertFile)?;
+                .await?;
 
                 Ok(build_response_file)
             }
@@ -92,12 +151,106 @@ pub async fn handler_convert_file(
     }
 }
 
+pub async fn handler_statistics(
+    Query(query): Query<ConvertQuery>,
+    multipart: Multipart,
+) -> Result<Json<Statistics>> {
+    let document = upload_document(multipart, &query, "handler_statistics").await?;
+
+    Ok(Json(document.statistics()))
+}
+
+//query parameters of the previews, `?page=2` renders the second page (the first one by
+//default) and `?dpi=150` its resolution, 96 DPI by default
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct PreviewQuery {
+    page: usize,
+    dpi: f32,
+}
+
+impl Default for PreviewQuery {
+    fn default() -> Self {
+        PreviewQuery { page: 1, dpi: 96.0 }
+    }
+}
+
+//the page of the uploaded file as it is laid out in PDF, a PNG image, the
+//`X-Page-Count` header gives the number of pages to preview
+pub async fn handler_preview(
+    Query(query): Query<ConvertQuery>,
+    Query(preview_query): Query<PreviewQuery>,
+    multipart: Multipart,
+) -> Result<Response> {
+    use axum::http::{header, HeaderValue};
+
+    if preview_query.page == 0 || !(preview_query.dpi > 0.0 && preview_query.dpi <= MAX_DPI) {
+        return Err(Error::InvalidPreviewOptions);
+    }
+    let document = upload_document(multipart, &query, "handler_preview").await?;
+
+    let options = PreviewOptions {
+        pages: vec![preview_query.page],
+        dpi: preview_query.dpi,
+    };
+    let preview = shiva::preview::render(&document, &generate_options(&query).pdf, &options)
+        .map_err(|e| {
+            error!("Preview error: {}", e);
+            Error::from(e)
+        })?;
+    let page = preview
+        .pages
+        .into_iter()
+        .next()
+        .ok_or(Error::PageNotFound)?;
+
+    let mut res = page.png.into_response();
+    res.headers_mut()
+        .insert(header::CONTENT_TYPE, HeaderValue::from_static("image/png"));
+    res.headers_mut()
+        .insert("X-Page-Count", HeaderValue::from(preview.page_count));
+
+    Ok(res)
+}
+
+//parses the uploaded file or archive
+async fn upload_document(
+    multipart: Multipart,
+    query: &ConvertQuery,
+    handler: &str,
+) -> Result<Document> {
+    match upload_file(multipart).await? {
+        StructUploadFile::UploadFile(upload_file_info) => {
+            info!(
+                "-->> {:<12} - {handler} input_extension_{}",
+                "HANDLER", upload_file_info.upload_file_extension
+            );
+            parse_document(
+                &upload_file_info.upload_file_extension,
+                &upload_file_info.upload_file_data,
+                &query.parse_options(),
+            )
+            .await
+        }
+        StructUploadFile::UploadZip(upload_file_zip) => {
+            info!("-->> {:<12} - {handler} input ZIP archive", "HANDLER");
+            parse_document_zip(
+                &upload_file_zip.file_extension,
+                &upload_file_zip.file_data,
+                upload_file_zip.images,
+                &query.parse_options(),
+            )
+        }
+    }
+}
+
 async fn convert_file_zip(
     file_name: String,
     input_file_data_bytes: Bytes,
     file_extension: String,
     images: HashMap<String, Bytes>,
     output_format: String,
+    query: &ConvertQuery,
 ) -> Result<DownloadFile> {
     /*
     info!("upload file name: {}", file_name);
@@ -109,34 +262,14 @@ async fn convert_file_zip(
     }
      */
 
-    let document = match file_extension.as_str() {
-        "md" => Document::from(
-            shiva::markdown::Transformer::parse_with_loader(
-                &input_file_data_bytes,
-                memory_image_loader(images),
-            )
-            .unwrap(),
-        ),
-        "html" | "htm" => Document::from(
-            shiva::html::Transformer::parse_with_loader(
-                &input_file_data_bytes,
-                memory_image_loader(images),
-            )
-            .unwrap(),
-        ),
-        _ => return Err(Error::FailParseDocument),
-    };
+    let document = parse_document_zip(
+        &file_extension,
+        &input_file_data_bytes,
+        images,
+        &query.parse_options(),
+    )?;
 
-    let output_bytes = match output_format.as_str() {
-        "md" => shiva::markdown::Transformer::generate(&document).unwrap(),
-        "html" | "htm" => shiva::html::Transformer::generate(&document).unwrap(),
-        "txt" => shiva::text::Transformer::generate(&document).unwrap(),
-        "pdf" => shiva::pdf::Transformer::generate(&document).unwrap(),
-        "json" => shiva::json::Transformer::generate(&document).unwrap(),
-        "rtf" => shiva::rtf::Transformer::generate(&document).unwrap(),
-        "docx" => shiva::docx::Transformer::generate(&document).unwrap(),
-        _ => return Err(Error::FailConvertFile),
-    };
+    let output_bytes = generate_document(&output_format, &document, query)?;
 
     Ok(DownloadFile {
         file_name,
@@ -144,6 +277,30 @@ async fn convert_file_zip(
     })
 }
 
+fn parse_document_zip(
+    file_extension: &str,
+    input_file_data_bytes: &Bytes,
+    images: HashMap<String, Bytes>,
+    parse_options: &ParseOptions,
+) -> Result<Document> {
+    let document = match file_extension {
+        "md" => shiva::markdown::Transformer::parse_with_loader_and_options(
+            input_file_data_bytes,
+            memory_image_loader(images),
+            parse_options,
+        )?,
+        "html" | "htm" | "mht" | "mhtml" => {
+            shiva::html::Transformer::parse_with_loader_and_options(
+                input_file_data_bytes,
+                memory_image_loader(images),
+                parse_options,
+            )?
+        }
+        _ => return Err(Error::FailParseDocument),
+    };
+    Ok(document)
+}
+
 fn memory_image_loader(images: HashMap<String, Bytes>) -> impl Fn(&str) -> anyhow::Result<Bytes> {
     let image_loader = move |image: &str| -> anyhow::Result<Bytes> {
         let bytes = images
@@ -158,7 +315,7 @@ fn memory_image_loader(images: HashMap<String, Bytes>) -> impl Fn(&str) -> anyho
 //checking the supported formats in the archive
 fn supported_extensions_in_archive(file_extension: &str) -> bool {
     match file_extension {
-        "md" | "html" | "htm" | "png" => true,
+        "md" | "html" | "htm" | "mht" | "mhtml" | "png" => true,
 
         _ => false,
     }
@@ -226,7 +383,7 @@ async fn unpacking(mut field: Field<'_>) -> Result<StructUploadFile> {
                 let mut file_data_buf = Vec::new();
                 file.read_to_end(&mut file_data_buf).unwrap();
                 match ext.as_str() {
-                    "html" | "htm" | "md" => {
+                    "html" | "htm" | "mht" | "mhtml" | "md" => {
                         file_name = file_name_in_archive;
                         file_data = Some(Bytes::from(file_data_buf));
                         file_extension = file_extension_in_archive.clone();
@@ -289,31 +446,18 @@ async fn upload_file(mut multipart: Multipart) -> Result<StructUploadFile> {
                 .filter(|ext| !ext.trim().is_empty())
                 .map(String::from);
 
-            //matching the file extension
-            if let Some(ref ext) = file_extension {
-                match ext.as_str() {
-                    "zip" => {
-                        return unpacking(field).await; //if _zip, start unpacking
-                    }
-
-                    _ => {
-                        //if not zip, check the supported extension
-                        if supported_format(ext).await {
-                            file_data = field.bytes().await.unwrap();
-                        } else {
-                            return Err(Error::UnsupportedFormat);
-                        }
-                    }
-                }
-            } else {
-                return Err(Error::ExtensionMissing);
+            //matching the file extension, the format of files without a known one is detected
+            //from their content when they are parsed
+            if file_extension.as_deref() == Some("zip") {
+                return unpacking(field).await; //if _zip, start unpacking
             }
+            file_data = field.bytes().await.map_err(|_| Error::FailBytes)?;
         }
     }
 
     //writing the received data to variables
     let file_name = file_name.unwrap_or("Shiva_convert".to_string());
-    let file_extension = file_extension.ok_or("File extension not found").unwrap();
+    let file_extension = file_extension.unwrap_or_default();
     let file_data = file_data;
 
     //creating the uploadFile Info structure
@@ -329,34 +473,20 @@ async fn convert_file(
     file_extension: String,
     input_file_data_bytes: Bytes,
     output_format: String,
+    query: &ConvertQuery,
 ) -> Result<DownloadFile> {
     debug!("upload file name: {}", file_name);
     debug!("upload file format: {}", file_extension);
     debug!("download file format: {}", output_format);
 
-    let document = match file_extension.as_str() {
-        "md" => {
-            Document::from(shiva::markdown::Transformer::parse(&input_file_data_bytes).unwrap())
-        }
-        "html" | "htm" => {
-            Document::from(shiva::html::Transformer::parse(&input_file_data_bytes).unwrap())
-        }
-        "txt" => Document::from(shiva::text::Transformer::parse(&input_file_data_bytes).unwrap()),
-        "pdf" => Document::from(shiva::pdf::Transformer::parse(&input_file_data_bytes).unwrap()),
-        "json" => Document::from(shiva::json::Transformer::parse(&input_file_data_bytes).unwrap()),
-        _ => return Err(Error::FailParseDocument),
-    };
+    let document = parse_document(
+        &file_extension,
+        &input_file_data_bytes,
+        &query.parse_options(),
+    )
+    .await?;
 
-    let output_bytes = match output_format.as_str() {
-        "md" => shiva::markdown::Transformer::generate(&document).unwrap(),
-        "html" | "htm" => shiva::html::Transformer::generate(&document).unwrap(),
-        "txt" => shiva::text::Transformer::generate(&document).unwrap(),
-        "pdf" => shiva::pdf::Transformer::generate(&document).unwrap(),
-        "json" => shiva::json::Transformer::generate(&document).unwrap(),
-        "rtf" => shiva::rtf::Transformer::generate(&document).unwrap(),
-        "docx" => shiva::docx::Transformer::generate(&document).unwrap(),
-        _ => return Err(Error::FailConvertFile),
-    };
+    let output_bytes = generate_document(&output_format, &document, query)?;
 
     Ok(DownloadFile {
         file_name,
@@ -364,10 +494,321 @@ async fn convert_file(
     })
 }
 
-async fn supported_format(file_extension: &str) -> bool {
-    match file_extension {
-        "md" | "html" | "htm" | "txt" | "pdf" | "json" => true,
+async fn parse_document(
+    file_extension: &str,
+    input_file_data_bytes: &Bytes,
+    parse_options: &ParseOptions,
+) -> Result<Document> {
+    let document_type = DocumentType::from_extension(file_extension)
+        .or_else(|| detect_format(input_file_data_bytes))
+        .filter(|&document_type| TransformerRegistry::builtin().can_parse(document_type))
+        .ok_or(Error::UnsupportedFormat)?;
+    let document = match document_type {
+        DocumentType::Markdown => {
+            AsyncAdapter::<shiva::markdown::Transformer>::parse_with_loader_and_options(
+                input_file_data_bytes,
+                remote_image_loader,
+                parse_options,
+            )
+            .await
+        }
+        DocumentType::HTML => {
+            AsyncAdapter::<shiva::html::Transformer>::parse_with_loader_and_options(
+                input_file_data_bytes,
+                remote_image_loader,
+                parse_options,
+            )
+            .await
+        }
+        DocumentType::LaTeX => {
+            AsyncAdapter::<shiva::latex::Transformer>::parse_with_loader_and_options(
+                input_file_data_bytes,
+                remote_image_loader,
+                parse_options,
+            )
+            .await
+        }
+        DocumentType::AsciiDoc => {
+            AsyncAdapter::<shiva::asciidoc::Transformer>::parse_with_loader_and_options(
+                input_file_data_bytes,
+                remote_image_loader,
+                parse_options,
+            )
+            .await
+        }
+        DocumentType::RST => {
+            AsyncAdapter::<shiva::rst::Transformer>::parse_with_loader_and_options(
+                input_file_data_bytes,
+                remote_image_loader,
+                parse_options,
+            )
+            .await
+        }
+        DocumentType::Org => {
+            AsyncAdapter::<shiva::org::Transformer>::parse_with_loader_and_options(
+                input_file_data_bytes,
+                remote_image_loader,
+                parse_options,
+            )
+            .await
+        }
+        DocumentType::MediaWiki => {
+            AsyncAdapter::<shiva::wiki::Transformer>::parse_with_loader_and_options(
+                input_file_data_bytes,
+                remote_image_loader,
+                parse_options,
+            )
+            .await
+        }
+        //the parsers without an async adapter load the images on the blocking thread pool
+        DocumentType::BBCode | DocumentType::Jira | DocumentType::Eml | DocumentType::Ipynb => {
+            let input = input_file_data_bytes.clone();
+            let parse_options = parse_options.clone();
+            tokio::task::spawn_blocking(move || match document_type {
+                DocumentType::BBCode => {
+                    shiva::bbcode::Transformer::parse_with_loader(&input, blocking_image_loader)
+                }
+                DocumentType::Jira => {
+                    shiva::jira::Transformer::parse_with_loader(&input, blocking_image_loader)
+                }
+                DocumentType::Eml => shiva::eml::Transformer::parse_with_loader(
+                    &input,
+                    blocking_image_loader,
+                    &parse_options,
+                ),
+                _ => shiva::ipynb::Transformer::parse_with_loader(
+                    &input,
+                    blocking_image_loader,
+                    &parse_options,
+                ),
+            })
+            .await
+            .map_err(|e| {
+                error!("Parse error: {}", e);
+                Error::FailParseDocument
+            })?
+        }
+        //the other formats read no image from the disk
+        _ => TransformerRegistry::builtin().parse_with_options(
+            document_type,
+            input_file_data_bytes,
+            parse_options,
+        ),
+    };
+    let mut document = document.map_err(|e| {
+        error!("Parse error: {}", e);
+        Error::from(e)
+    })?;
+    //the raw HTML blocks of RST, AsciiDoc, Org and the other formats reach the HTML output
+    //as well as those of markdown
+    shiva::html::sanitize_raw_html(&mut document);
+    Ok(document)
+}
 
-        _ => false,
+const IMAGE_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_IMAGE_BYTES: u64 = 10 * 1024 * 1024;
+
+//the images of uploaded documents are downloaded only with `--remote-images`
+static REMOTE_IMAGES: AtomicBool = AtomicBool::new(false);
+
+pub fn enable_remote_images() {
+    REMOTE_IMAGES.store(true, Ordering::Relaxed);
+}
+
+//images are downloaded from public addresses, never read from the disk of the server: the
+//sources of an uploaded document are untrusted
+async fn remote_image_loader(image: String) -> anyhow::Result<Bytes> {
+    tokio::task::spawn_blocking(move || blocking_image_loader(&image)).await?
+}
+
+fn blocking_image_loader(image: &str) -> anyhow::Result<Bytes> {
+    if !REMOTE_IMAGES.load(Ordering::Relaxed) {
+        anyhow::bail!("Remote images are disabled, start the server with --remote-images");
     }
+    let image_loader = http_image_loader("", IMAGE_TIMEOUT, MAX_IMAGE_BYTES);
+    image_loader(image)
+}
+
+//images are downscaled and stripped of their metadata to keep the responses small,
+//HTML and markdown embed them so that the response is a single file, markdown keeps the
+//frontmatter of the input
+fn generate_options(query: &ConvertQuery) -> GenerateOptions {
+    GenerateOptions {
+        markdown: MarkdownOptions {
+            embed_images: true,
+            frontmatter: true,
+            flavor: query.flavor,
+            ..Default::default()
+        },
+        html: HtmlOptions {
+            standalone: !query.fragment,
+            embed_images: true,
+            theme: query.theme,
+            print: query.print,
+            email: query.email,
+            ..Default::default()
+        },
+        csv: CsvOptions {
+            delimiter: query.delimiter,
+            table: query.table,
+            ..Default::default()
+        },
+        images: ImageOptions {
+            max_dpi: Some(150.0),
+            max_pixels: Some(2048),
+            web_formats: true,
+            strip_metadata: true,
+        },
+        ..Default::default()
+    }
+}
+
+fn generate_document(
+    output_format: &str,
+    document: &Document,
+    query: &ConvertQuery,
+) -> Result<Bytes> {
+    let document_type =
+        DocumentType::from_extension(output_format).ok_or(Error::UnsupportedFormat)?;
+    if document_type == DocumentType::Image {
+        return generate_page_image(output_format, document, query);
+    }
+    let options = generate_options(query);
+    //the generators of these formats save the images to files next to the document: the
+    //images are kept in memory and returned with the document in a ZIP archive, never
+    //written to the disk of the server
+    let images = Mutex::new(Vec::new());
+    let image_saver = |bytes: &Bytes, image: &str| -> anyhow::Result<()> {
+        images
+            .lock()
+            .unwrap()
+            .push((image.to_string(), bytes.clone()));
+        Ok(())
+    };
+    //the covers of EPUB and FB2 are read from `data:` URIs only
+    let cover_loader = |image: &str| -> anyhow::Result<Bytes> {
+        anyhow::bail!(
+            "The cover {} is not read from the disk of the server",
+            image
+        )
+    };
+    let generated = match document_type {
+        DocumentType::LaTeX
+        | DocumentType::AsciiDoc
+        | DocumentType::RST
+        | DocumentType::Org
+        | DocumentType::MediaWiki
+        | DocumentType::Confluence
+        | DocumentType::DocBook
+        | DocumentType::Typst
+        | DocumentType::EPUB
+        | DocumentType::FB2 => {
+            process_images(document, document_type, &options.images).and_then(|document| {
+                match document_type {
+                    DocumentType::LaTeX => {
+                        shiva::latex::Transformer::generate_with_saver(&document, image_saver)
+                    }
+                    DocumentType::AsciiDoc => {
+                        shiva::asciidoc::Transformer::generate_with_saver(&document, image_saver)
+                    }
+                    DocumentType::RST => {
+                        shiva::rst::Transformer::generate_with_saver(&document, image_saver)
+                    }
+                    DocumentType::Org => {
+                        shiva::org::Transformer::generate_with_saver(&document, image_saver)
+                    }
+                    DocumentType::MediaWiki => {
+                        shiva::wiki::Transformer::generate_with_saver(&document, image_saver)
+                    }
+                    DocumentType::Confluence => {
+                        shiva::confluence::Transformer::generate_with_saver(&document, image_saver)
+                    }
+                    DocumentType::DocBook => {
+                        shiva::docbook::Transformer::generate_with_saver(&document, image_saver)
+                    }
+                    DocumentType::Typst => {
+                        shiva::typst::Transformer::generate_with_saver(&document, image_saver)
+                    }
+                    DocumentType::EPUB => {
+                        shiva::epub::Transformer::generate_with_loader(&document, cover_loader)
+                    }
+                    _ => shiva::fb2::Transformer::generate_with_loader(&document, cover_loader),
+                }
+            })
+        }
+        _ => {
+            TransformerRegistry::builtin().generate_with_options(document_type, document, &options)
+        }
+    }
+    .map_err(|e| {
+        error!("Generate error: {}", e);
+        Error::from(e)
+    })?;
+
+    let images = images.into_inner().unwrap();
+    if images.is_empty() {
+        return Ok(generated);
+    }
+    archive_document(output_format, generated, images)
+}
+
+//ZIP archive of the generated document and of its images, in the layout of the uploaded
+//archives
+fn archive_document(
+    output_format: &str,
+    generated: Bytes,
+    images: Vec<(String, Bytes)>,
+) -> Result<Bytes> {
+    use std::io::Write;
+
+    let mut archive = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    let files = std::iter::once((format!("document.{output_format}"), generated)).chain(images);
+    for (file_name, bytes) in files {
+        archive
+            .start_file(file_name, zip::write::FileOptions::default())
+            .and_then(|()| archive.write_all(&bytes).map_err(Into::into))
+            .map_err(|e| {
+                error!("Archive error: {}", e);
+                Error::FailConvertFile
+            })?;
+    }
+    let archive = archive.finish().map_err(|e| {
+        error!("Archive error: {}", e);
+        Error::FailConvertFile
+    })?;
+    Ok(Bytes::from(archive.into_inner()))
+}
+
+//image of a page as it is laid out in PDF, for thumbnails
+fn generate_page_image(
+    output_format: &str,
+    document: &Document,
+    query: &ConvertQuery,
+) -> Result<Bytes> {
+    let format = match output_format {
+        "png" => RasterFormat::Png,
+        "jpg" | "jpeg" => RasterFormat::Jpeg,
+        _ => return Err(Error::UnsupportedFormat),
+    };
+    let page = query.page.unwrap_or(1);
+    let options = RasterOptions {
+        dpi: query.dpi.unwrap_or(RasterOptions::default().dpi),
+        pages: Some(page..=page),
+        format,
+        ..Default::default()
+    };
+    let pages = shiva::raster::Transformer::generate_with_options(
+        document,
+        &generate_options(query).pdf,
+        &options,
+    )
+    .map_err(|e| {
+        error!("Generate error: {}", e);
+        Error::from(e)
+    })?;
+    pages
+        .into_iter()
+        .next()
+        .map(|page| page.bytes)
+        .ok_or(Error::PageNotFound)
 }
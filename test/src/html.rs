@@ -4,7 +4,7 @@ mod tests {
     use shiva::core::Element::{Header, Hyperlink, Image, List, Paragraph, Table, Text};
     use shiva::core::{
         Document, Element, ImageAlignment, ImageData, ImageDimension, ImageType, ListItem,
-        TableCell, TableHeader, TableRow, TransformerTrait,
+        NumberingStyle, TableCell, TableHeader, TableRow, TextDirection, TransformerTrait,
     };
     use shiva::html::Transformer;
 
@@ -107,7 +107,7 @@ mod tests {
         assert_eq!(parsed.get_all_elements().len(), 6);
         let elements: Vec<&Element> = parsed.get_all_elements();
         match &elements[0] {
-            Paragraph { elements } => match &elements[0] {
+            Paragraph { elements, .. } => match &elements[0] {
                 Text { text, size: _ } => {
                     assert_eq!(text, "First Paragraph");
                 }
@@ -138,6 +138,7 @@ mod tests {
                     size: 8,
                 }]
                 .to_vec(),
+                direction: TextDirection::default(),
             },
             Paragraph {
                 elements: [Text {
@@ -145,6 +146,7 @@ mod tests {
                     size: 8,
                 }]
                 .to_vec(),
+                direction: TextDirection::default(),
             },
             Paragraph {
                 elements: [Text {
@@ -152,6 +154,7 @@ mod tests {
                     size: 8,
                 }]
                 .to_vec(),
+                direction: TextDirection::default(),
             },
             Paragraph {
                 elements: [Text {
@@ -159,6 +162,7 @@ mod tests {
                     size: 8,
                 }]
                 .to_vec(),
+                direction: TextDirection::default(),
             },
             Paragraph {
                 elements: [Text {
@@ -166,6 +170,7 @@ mod tests {
                     size: 8,
                 }]
                 .to_vec(),
+                direction: TextDirection::default(),
             },
             Paragraph {
                 elements: [Text {
@@ -173,6 +178,7 @@ mod tests {
                     size: 8,
                 }]
                 .to_vec(),
+                direction: TextDirection::default(),
             },
         ]
         .to_vec();
@@ -209,10 +215,7 @@ mod tests {
         let parsed: Document = Transformer::parse(&html_document.as_bytes().into())?;
         let elements: Vec<&Element> = parsed.get_all_elements();
         match &elements[0] {
-            List {
-                elements,
-                numbered: _,
-            } => match &elements[0] {
+            List { elements, .. } => match &elements[0] {
                 ListItem { element } => match element {
                     Text { text, size: _ } => {
                         assert_eq!(text, "List item 1");
@@ -252,6 +255,8 @@ mod tests {
                     }
                 }],
                 numbered: true,
+                start: 1,
+                numbering: NumberingStyle::default(),
             },
             List {
                 elements: vec![{
@@ -265,6 +270,8 @@ mod tests {
                     }
                 }],
                 numbered: false,
+                start: 1,
+                numbering: NumberingStyle::default(),
             },
         ]
         .to_vec();
@@ -447,14 +454,13 @@ mod tests {
 </body>
 </html>"#;
 
-        let parsed: Result<Document, anyhow::Error> =
-            Transformer::parse(&html_document.as_bytes().into());
+        let parsed: shiva::Result<Document> = Transformer::parse(&html_document.as_bytes().into());
         assert!(parsed.is_ok());
         assert!(parsed.is_ok());
         let parsed: Document = parsed?;
         let elements: Vec<&Element> = parsed.get_all_elements();
         match &elements[0] {
-            Paragraph { elements } => match &elements[0] {
+            Paragraph { elements, .. } => match &elements[0] {
                 Image(image) => {
                     assert_eq!(image.title(), "Picture title2");
                 }
@@ -501,6 +507,7 @@ mod tests {
                     }
                 },
             ],
+            direction: TextDirection::default(),
         }]
         .to_vec();
         let html_document: Document = Document::new(elements);
@@ -522,21 +529,15 @@ mod tests {
 </body>
 </html>"#;
 
-        let parsed: Result<Document, anyhow::Error> =
-            Transformer::parse(&html_document.as_bytes().into());
+        let parsed: shiva::Result<Document> = Transformer::parse(&html_document.as_bytes().into());
         assert!(parsed.is_ok());
         assert!(parsed.is_ok());
         let parsed: Document = parsed?;
         let elements: Vec<&Element> = parsed.get_all_elements();
 
         match &elements[0] {
-            Hyperlink {
-                title,
-                alt: _,
-                url,
-                size: _,
-            } => {
-                assert_eq!(title, "http://example.com");
+            link @ Hyperlink { url, .. } => {
+                assert_eq!(link.plain_text(), "http://example.com");
                 assert_eq!(url, "http://example.com");
             }
 
@@ -557,7 +558,10 @@ mod tests {
         let elements = [Paragraph {
             elements: [
                 Hyperlink {
-                    title: "http://example.com".to_string(),
+                    elements: vec![Text {
+                        size: 8,
+                        text: "http://example.com".to_string(),
+                    }],
                     url: "http://example.com".to_string(),
                     size: 8,
                     alt: "http://example.com".to_string(),
@@ -567,19 +571,26 @@ mod tests {
                     text: "  ".to_string(),
                 },
                 Hyperlink {
-                    title: "Example".to_string(),
+                    elements: vec![Text {
+                        size: 8,
+                        text: "Example".to_string(),
+                    }],
                     url: "http://example.com".to_string(),
                     size: 8,
                     alt: "Example".to_string(),
                 },
                 Hyperlink {
-                    title: "Example".to_string(),
+                    elements: vec![Text {
+                        size: 8,
+                        text: "Example".to_string(),
+                    }],
                     url: "http://example.com".to_string(),
                     size: 8,
                     alt: "Example tooltip".to_string(),
                 },
             ]
             .to_vec(),
+            direction: TextDirection::default(),
         }]
         .to_vec();
         let html_document: Document = Document::new(elements);
@@ -601,8 +612,7 @@ mod tests {
 </body>
 </html>"#;
 
-        let parsed: Result<Document, anyhow::Error> =
-            Transformer::parse(&html_str.as_bytes().into());
+        let parsed: shiva::Result<Document> = Transformer::parse(&html_str.as_bytes().into());
         assert!(parsed.is_ok());
         let mut parsed: Document = parsed?;
 
@@ -634,8 +644,7 @@ mod tests {
 </body>
 </html>"#;
 
-        let parsed: Result<Document, anyhow::Error> =
-            Transformer::parse(&html_str.as_bytes().into());
+        let parsed: shiva::Result<Document> = Transformer::parse(&html_str.as_bytes().into());
         assert!(parsed.is_ok());
         let mut parsed: Document = parsed?;
 
@@ -666,8 +675,7 @@ mod tests {
 </body>
 </html>"#;
 
-        let parsed: Result<Document, anyhow::Error> =
-            Transformer::parse(&html_str.as_bytes().into());
+        let parsed: shiva::Result<Document> = Transformer::parse(&html_str.as_bytes().into());
         assert!(parsed.is_ok());
         let mut parsed: Document = parsed?;
 
@@ -699,8 +707,7 @@ mod tests {
 </body>
 </html>"#;
 
-        let parsed: Result<Document, anyhow::Error> =
-            Transformer::parse(&html_str.as_bytes().into());
+        let parsed: shiva::Result<Document> = Transformer::parse(&html_str.as_bytes().into());
         assert!(parsed.is_ok());
         let mut parsed: Document = parsed?;
 